@@ -36,19 +36,22 @@ use flate2::{read::GzDecoder, Compression, GzBuilder};
 use md5::{Digest, Md5};
 use std::num::NonZeroUsize;
 use std::{
+    any::Any,
+    collections::BTreeSet,
     convert::TryInto,
     ffi::CStr,
     fmt::{Display, Error as FmtError, Formatter},
     io::{self, Read, SeekFrom, Write},
-    path::PathBuf,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
     result::Result as StdResult,
     slice,
-    sync::Mutex,
+    sync::{Mutex, PoisonError},
 };
 use tectonic_errors::prelude::*;
 use tectonic_io_base::{
     digest::DigestData, normalize_tex_path, InputFeatures, InputHandle, IoProvider, OpenResult,
-    OutputHandle,
+    OutputHandle, SearchLayer,
 };
 use tectonic_status_base::{tt_error, tt_warning, MessageKind, StatusBackend};
 
@@ -113,6 +116,20 @@ impl Display for SystemRequestError {
     }
 }
 
+/// The outcome of a request by an engine to read an environment variable.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EnvReadOutcome {
+    /// The variable was allowed to be read, and had a value.
+    Read,
+
+    /// The variable was allowed to be read, but was not set, or its value
+    /// was not valid Unicode.
+    NotSet,
+
+    /// The security settings did not allow the variable to be read.
+    Forbidden,
+}
+
 /// The DriverHooks trait allows engines to interact with the higher-level code
 /// that is driving the TeX processing.
 ///
@@ -149,6 +166,16 @@ pub trait DriverHooks {
     ) {
     }
 
+    /// This function is called when the engine asks to read an environment
+    /// variable, whether or not the security settings allowed it. The value
+    /// itself is not passed along, since it might be sensitive.
+    fn event_env_read(&mut self, _name: &str, _outcome: EnvReadOutcome) {}
+
+    /// This function is called when the engine has searched for an input file
+    /// of a particular format, whether or not it was found. Drivers can use
+    /// it to learn which names were tried, and which one was opened.
+    fn event_input_searched(&mut self, _search: &InputSearch<'_>) {}
+
     /// The engine is requesting a "shell escape" evaluation.
     ///
     /// If the driver wishes to implement this request, it should run the
@@ -194,18 +221,57 @@ impl<T: IoProvider> DriverHooks for MinimalDriver<T> {
 // Function defined in the C support code:
 extern "C" {
     fn _ttbc_get_error_message() -> *const libc::c_char;
+    fn _ttbc_get_error_kind() -> libc::c_int;
 }
 
 lazy_static::lazy_static! {
     static ref ENGINE_LOCK: Mutex<u8> = Mutex::new(0u8);
 }
 
+/// The general category of a fatal error in FFI engine code.
+///
+/// This lets callers distinguish between problems with a document, which are
+/// usually reported as [`EngineAbortKind::General`], and problems with the
+/// environment that the engine is running in.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum EngineAbortKind {
+    /// A general fatal error, such as one caused by invalid input.
+    General,
+
+    /// A memory allocation failed.
+    OutOfMemory,
+
+    /// A file that the engine required could not be opened.
+    FileOpen,
+
+    /// An internal error, such as a bug in the engine or the bridge code.
+    Internal,
+
+    /// A format file could be opened but not loaded, because it was damaged
+    /// or written by a different version of the engine.
+    BadFormat,
+}
+
+impl EngineAbortKind {
+    /// Convert the value of a C `ttbc_abort_kind`.
+    fn from_c(kind: libc::c_int) -> Self {
+        match kind {
+            1 => EngineAbortKind::OutOfMemory,
+            2 => EngineAbortKind::FileOpen,
+            3 => EngineAbortKind::Internal,
+            4 => EngineAbortKind::BadFormat,
+            _ => EngineAbortKind::General,
+        }
+    }
+}
+
 /// An error type indicating the the FFI code aborted.
 ///
 /// FFI bridge callbacks should return this type, which will then be filled in
 /// with error text extracted from the global FFI bridge framework.
 #[derive(Debug)]
 pub struct EngineAbortedError {
+    kind: EngineAbortKind,
     message: String,
 }
 
@@ -217,6 +283,7 @@ impl EngineAbortedError {
     /// from the global FFI bridge framework.
     pub fn new_abort_indicator() -> Self {
         EngineAbortedError {
+            kind: EngineAbortKind::General,
             message: "[failed to extract detailed error message]".to_owned(),
         }
     }
@@ -228,7 +295,29 @@ impl EngineAbortedError {
         let message = unsafe { CStr::from_ptr(ptr) }
             .to_string_lossy()
             .into_owned();
-        EngineAbortedError { message }
+        // SAFETY: This is always safe to call
+        let kind = EngineAbortKind::from_c(unsafe { _ttbc_get_error_kind() });
+        EngineAbortedError { kind, message }
+    }
+
+    fn new_from_panic(payload: Box<dyn Any + Send>) -> Self {
+        let detail = if let Some(s) = payload.downcast_ref::<&str>() {
+            s
+        } else if let Some(s) = payload.downcast_ref::<String>() {
+            s.as_str()
+        } else {
+            "[no details available]"
+        };
+
+        EngineAbortedError {
+            kind: EngineAbortKind::Internal,
+            message: format!("the engine panicked: {detail}"),
+        }
+    }
+
+    /// Get the general category of the error.
+    pub fn kind(&self) -> EngineAbortKind {
+        self.kind
     }
 }
 
@@ -301,18 +390,31 @@ impl<'a> CoreBridgeLauncher<'a> {
     /// and `ttbc_global_engine_exit` according to the pattern described in
     /// `tectonic_bridge_core.h`. If an abort is detected, the callback function
     /// should return `Err(EngineAbortedError::new_abort_indicator())`.
+    ///
+    /// If the callback panics in its own Rust code, the panic is caught and
+    /// reported as an [`EngineAbortedError`] of kind
+    /// [`EngineAbortKind::Internal`], so that later invocations in the same
+    /// process can proceed. This can't help with panics in the bridge
+    /// functions called by the C code: a panic can't unwind through C frames,
+    /// so one of those aborts the process before it gets here.
     pub fn with_global_lock<F, T>(&mut self, callback: F) -> Result<T>
     where
         F: FnOnce(&mut CoreBridgeState<'_>) -> Result<T>,
     {
-        let _guard = ENGINE_LOCK.lock().unwrap();
+        // The lock doesn't protect any Rust data, so if a previous holder
+        // panicked, there's nothing to recover.
+        let _guard = ENGINE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
         let mut state = CoreBridgeState::new(
             self.security.clone(),
             self.hooks,
             self.status,
             self.filesystem_emulation_settings.clone(),
         );
-        let result = callback(&mut state);
+
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| callback(&mut state))) {
+            Ok(r) => r,
+            Err(payload) => return Err(EngineAbortedError::new_from_panic(payload).into()),
+        };
 
         if let Err(ref e) = result {
             if e.downcast_ref::<EngineAbortedError>().is_some() {
@@ -324,6 +426,40 @@ impl<'a> CoreBridgeLauncher<'a> {
     }
 }
 
+/// A record of where an input file was looked for, to explain why it couldn't
+/// be found. See [`CoreBridgeState::trace_input_search`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct InputSearchTrace {
+    /// The names that were tried, in order: the name as requested, followed
+    /// by the name with each of the file format's extensions added.
+    pub names: Vec<String>,
+
+    /// The places that were searched for each name, in order.
+    pub layers: Vec<SearchLayer>,
+}
+
+/// A search made by an engine for an input file, as reported to
+/// [`DriverHooks::event_input_searched`].
+#[derive(Clone, Copy, Debug)]
+pub struct InputSearch<'a> {
+    /// The name that the engine asked for.
+    pub requested: &'a str,
+
+    /// The format of the file, which determines the extensions that were
+    /// tried.
+    pub format: FileFormat,
+
+    /// The names that were tried, in order. If the file was found, the last
+    /// of these is the one that was opened.
+    pub tried: &'a [String],
+
+    /// The name under which the file was found, if it was.
+    pub resolved: Option<&'a str>,
+
+    /// The path of the file on the filesystem, if it was found there.
+    pub path: Option<&'a Path>,
+}
+
 /// The CoreBridgeState structure is a handle to Rust state that can be used by
 /// C/C++ engine code to perform basic I/O functions.
 ///
@@ -377,12 +513,16 @@ impl<'a> CoreBridgeState<'a> {
         }
     }
 
+    /// Open an input, trying the extensions of its format if needed. Each
+    /// name that is tried is added to *tried*.
     fn input_open_name_format(
         &mut self,
         name: &str,
         format: FileFormat,
+        tried: &mut Vec<String>,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
         let io = self.hooks.io();
+        tried.push(name.to_owned());
 
         if let FileFormat::Format = format {
             match io.input_open_format(name, self.status) {
@@ -404,6 +544,7 @@ impl<'a> CoreBridgeState<'a> {
 
         for e in format.extensions() {
             let ext = format!("{name}.{e}");
+            tried.push(ext.clone());
 
             if let FileFormat::Format = format {
                 match io.input_open_format(&ext, self.status) {
@@ -427,8 +568,9 @@ impl<'a> CoreBridgeState<'a> {
         name: &str,
         format: FileFormat,
         is_gz: bool,
+        tried: &mut Vec<String>,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
-        let base = self.input_open_name_format(name, format);
+        let base = self.input_open_name_format(name, format, tried);
 
         if !is_gz {
             return base;
@@ -454,7 +596,7 @@ impl<'a> CoreBridgeState<'a> {
         // truncated SHA256 digest as the MD5 ... but it seems like a better
         // idea to just go and read the file.
 
-        let mut ih = match self.input_open_name_format(&name, FileFormat::Tex) {
+        let mut ih = match self.input_open_name_format(&name, FileFormat::Tex, &mut Vec::new()) {
             OpenResult::Ok((ih, _path)) => ih,
             OpenResult::NotAvailable => {
                 // We could issue a warning here, but the standard LaTeX
@@ -505,6 +647,24 @@ impl<'a> CoreBridgeState<'a> {
         error_occurred
     }
 
+    /// Check a handle passed in from C code.
+    ///
+    /// A null handle indicates a bug in the engine, but we report it rather
+    /// than panicking: a panic can't unwind out of an `extern "C"` function,
+    /// so it would abort the whole process, and [`CoreBridgeLauncher::with_global_lock`]
+    /// can't catch it.
+    fn check_handle<T>(&mut self, handle: Option<T>, operation: &str) -> Option<T> {
+        if handle.is_none() {
+            tt_error!(
+                self.status,
+                "serious internal bug: {}() called with a null handle",
+                operation
+            );
+        }
+
+        handle
+    }
+
     /// Get a mutable reference to an [`OutputHandle`] associated with an [`OutputId`]
     pub fn get_output(&mut self, id: OutputId) -> &mut OutputHandle {
         self.output_handles[id.idx()].as_mut().unwrap()
@@ -601,7 +761,23 @@ impl<'a> CoreBridgeState<'a> {
     pub fn input_open(&mut self, name: &str, format: FileFormat, is_gz: bool) -> Option<InputId> {
         let name = normalize_tex_path(name);
 
-        let (ih, path) = match self.input_open_name_format_gz(&name, format, is_gz) {
+        let mut tried = Vec::new();
+        let r = self.input_open_name_format_gz(&name, format, is_gz, &mut tried);
+
+        let (resolved, path) = match r {
+            OpenResult::Ok((_, ref path)) => (tried.last().map(|n| n.as_str()), path.as_deref()),
+            _ => (None, None),
+        };
+
+        self.hooks.event_input_searched(&InputSearch {
+            requested: &name,
+            format,
+            tried: &tried,
+            resolved,
+            path,
+        });
+
+        let (ih, path) = match r {
             OpenResult::Ok(tup) => tup,
             OpenResult::NotAvailable => {
                 return None;
@@ -617,6 +793,22 @@ impl<'a> CoreBridgeState<'a> {
         InputId::new(self.input_handles.len())
     }
 
+    /// Trace the search done by [`Self::input_open`] for an input file.
+    ///
+    /// Engines can use this to explain why a file couldn't be found. It
+    /// doesn't open anything, and it isn't cheap, so it should only be used
+    /// after an open has failed.
+    pub fn trace_input_search(&mut self, name: &str, format: FileFormat) -> InputSearchTrace {
+        let name = normalize_tex_path(name);
+        let mut names = vec![name.to_string()];
+        names.extend(format.extensions().iter().map(|e| format!("{name}.{e}")));
+
+        InputSearchTrace {
+            names,
+            layers: self.hooks.io().describe_search(self.status),
+        }
+    }
+
     fn input_open_primary(&mut self) -> Option<InputId> {
         let io = self.hooks.io();
 
@@ -706,6 +898,25 @@ impl<'a> CoreBridgeState<'a> {
         rv
     }
 
+    fn get_env(&mut self, name: &str) -> Option<String> {
+        let (outcome, value) = if self.security.allow_env_var(name) {
+            match std::env::var(name) {
+                Ok(v) => (EnvReadOutcome::Read, Some(v)),
+                Err(_) => (EnvReadOutcome::NotSet, None),
+            }
+        } else {
+            tt_warning!(
+                self.status,
+                "forbidden to read environment variable `{}`; it must be explicitly allowed",
+                name
+            );
+            (EnvReadOutcome::Forbidden, None)
+        };
+
+        self.hooks.event_env_read(name, outcome);
+        value
+    }
+
     fn shell_escape(&mut self, command: &str) -> bool {
         if self.security.allow_shell_escape() {
             match self.hooks.sysrq_shell_escape(command, self.status) {
@@ -756,6 +967,9 @@ pub struct SecuritySettings {
     /// there should always be a hard "disable everything known to be risky"
     /// option that supersedes everything else.
     disable_insecures: bool,
+
+    /// The environment variables that engines may read.
+    allowed_env_vars: BTreeSet<String>,
 }
 
 /// Different high-level security stances that can be adopted when creating
@@ -799,7 +1013,21 @@ impl SecuritySettings {
             }
         };
 
-        SecuritySettings { disable_insecures }
+        SecuritySettings {
+            disable_insecures,
+            allowed_env_vars: BTreeSet::new(),
+        }
+    }
+
+    /// Allow engines to read the named environment variable.
+    ///
+    /// By default, documents can't read any environment variables. Allowing
+    /// specific ones lets them embed things like build numbers without
+    /// resorting to shell-escape. Like other potentially insecure features,
+    /// this has no effect if known-insecure features are disabled.
+    pub fn with_allowed_env_var<S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.allowed_env_vars.insert(name.into());
+        self
     }
 
     /// Query whether the shell-escape TeX engine feature is allowed to be used.
@@ -811,6 +1039,22 @@ impl SecuritySettings {
     pub fn allow_extra_search_paths(&self) -> bool {
         !self.disable_insecures
     }
+
+    /// Query whether external programs may be run to post-process outputs.
+    pub fn allow_external_post_processors(&self) -> bool {
+        !self.disable_insecures
+    }
+
+    /// Query whether external programs may be run to generate input files.
+    pub fn allow_external_generators(&self) -> bool {
+        !self.disable_insecures
+    }
+
+    /// Query whether engines are allowed to read the named environment
+    /// variable.
+    pub fn allow_env_var(&self, name: &str) -> bool {
+        !self.disable_insecures && self.allowed_env_vars.contains(name)
+    }
 }
 
 impl Default for SecuritySettings {
@@ -943,7 +1187,11 @@ pub extern "C" fn ttbc_output_putc(
 ) -> libc::c_int {
     let rc = c as u8;
 
-    if es.output_write(handle.expect("valid handle"), &[rc]) {
+    let Some(handle) = es.check_handle(handle, "putc") else {
+        return libc::EOF;
+    };
+
+    if es.output_write(handle, &[rc]) {
         libc::EOF
     } else {
         c
@@ -966,7 +1214,11 @@ pub unsafe extern "C" fn ttbc_output_write(
 
     // NOTE: we use f.write_all() so partial writes are not gonna be a thing.
 
-    if es.output_write(handle.expect("valid handle"), rdata) {
+    let Some(handle) = es.check_handle(handle, "write") else {
+        return 0;
+    };
+
+    if es.output_write(handle, rdata) {
         0
     } else {
         len
@@ -979,7 +1231,10 @@ pub extern "C" fn ttbc_output_flush(
     es: &mut CoreBridgeState,
     handle: Option<OutputId>,
 ) -> libc::c_int {
-    libc::c_int::from(es.output_flush(handle.expect("valid handle")))
+    match es.check_handle(handle, "flush") {
+        Some(handle) => libc::c_int::from(es.output_flush(handle)),
+        None => 1,
+    }
 }
 
 /// Close a Tectonic output file.
@@ -1067,19 +1322,60 @@ pub unsafe extern "C" fn ttbc_get_last_input_abspath(
     }
 }
 
+/// Read an environment variable, if the security settings allow it.
+///
+/// If the variable may be read and has a value, this function will fill in the
+/// caller's buffer with its UTF-8 value, including a terminating NUL, if the
+/// buffer is big enough. It returns 0 if the value is unavailable, or the
+/// number of bytes needed to hold it (including the NUL) otherwise. If this is
+/// larger than *len*, nothing is written and the caller should try again with
+/// a bigger buffer.
+///
+/// # Safety
+///
+/// This function is unsafe because it dereferences raw C pointers.
+#[no_mangle]
+pub unsafe extern "C" fn ttbc_get_env(
+    es: &mut CoreBridgeState,
+    name: *const libc::c_char,
+    buffer: *mut u8,
+    len: libc::size_t,
+) -> libc::size_t {
+    let rname = CStr::from_ptr(name).to_string_lossy();
+
+    let value = match es.get_env(&rname) {
+        Some(v) => v,
+        None => return 0,
+    };
+
+    let n = value.len();
+    if n < len {
+        std::ptr::copy(value.as_ptr(), buffer, n);
+        *buffer.add(n) = b'\0';
+    }
+
+    n + 1
+}
+
 /// Get the size of a Tectonic input file.
 #[no_mangle]
 pub extern "C" fn ttbc_input_get_size(
     es: &mut CoreBridgeState,
     handle: Option<InputId>,
 ) -> libc::size_t {
-    es.input_get_size(handle.expect("valid handle"))
+    match es.check_handle(handle, "get_size") {
+        Some(handle) => es.input_get_size(handle),
+        None => 0,
+    }
 }
 
 /// Get the modification time of a Tectonic input file.
 #[no_mangle]
 pub extern "C" fn ttbc_input_get_mtime(es: &mut CoreBridgeState, handle: Option<InputId>) -> i64 {
-    es.input_get_mtime(handle.expect("valid handle"))
+    match es.check_handle(handle, "get_mtime") {
+        Some(handle) => es.input_get_mtime(handle),
+        None => 0,
+    }
 }
 
 /// Seek in a Tectonic input stream.
@@ -1110,7 +1406,12 @@ pub unsafe extern "C" fn ttbc_input_seek(
         }
     };
 
-    match es.input_seek(handle.expect("valid handle"), rwhence) {
+    let Some(handle) = es.check_handle(handle, "seek") else {
+        *internal_error = 1;
+        return 0;
+    };
+
+    match es.input_seek(handle, rwhence) {
         Ok(pos) => pos as libc::size_t,
         Err(e) => {
             // TODO: Handle the error better. Report the error properly to the caller?
@@ -1129,7 +1430,11 @@ pub extern "C" fn ttbc_input_getc(
     // If we couldn't fill the whole (1-byte) buffer, that's boring old EOF.
     // No need to complain. Fun match statement here.
 
-    match es.input_getc(handle.expect("valid handle")) {
+    let Some(handle) = es.check_handle(handle, "getc") else {
+        return libc::EOF;
+    };
+
+    match es.input_getc(handle) {
         Ok(b) => libc::c_int::from(b),
         Err(e) => {
             if let Some(ioe) = e.downcast_ref::<io::Error>() {
@@ -1151,7 +1456,11 @@ pub extern "C" fn ttbc_input_ungetc(
     handle: Option<InputId>,
     ch: libc::c_int,
 ) -> libc::c_int {
-    match es.input_ungetc(handle.expect("valid handle"), ch as u8) {
+    let Some(handle) = es.check_handle(handle, "ungetc") else {
+        return -1;
+    };
+
+    match es.input_ungetc(handle, ch as u8) {
         Ok(_) => 0,
         Err(e) => {
             tt_warning!(es.status, "ungetc() failed"; e);
@@ -1177,7 +1486,11 @@ pub unsafe extern "C" fn ttbc_input_read(
 ) -> libc::ssize_t {
     let rdata = slice::from_raw_parts_mut(data, len);
 
-    match es.input_read(handle.expect("valid handle"), rdata) {
+    let Some(handle) = es.check_handle(handle, "read") else {
+        return -1;
+    };
+
+    match es.input_read(handle, rdata) {
         Ok(_) => len as isize,
         Err(e) => {
             tt_warning!(es.status, "{}-byte read failed", len; e);
@@ -1203,7 +1516,11 @@ pub unsafe extern "C" fn ttbc_input_read_partial(
 ) -> libc::ssize_t {
     let rdata = slice::from_raw_parts_mut(data, len);
 
-    match es.input_read_partial(handle.expect("valid handle"), rdata) {
+    let Some(handle) = es.check_handle(handle, "read_partial") else {
+        return -1;
+    };
+
+    match es.input_read_partial(handle, rdata) {
         Ok(size) => size as isize,
         Err(e) => {
             tt_warning!(es.status, "{}-byte read failed", len; e);
@@ -1413,3 +1730,55 @@ impl FileFormat {
 /// Does our resulting executable link correctly?
 #[test]
 fn linkage() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tectonic_io_base::stack::IoStack;
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[allow(improper_ctypes)] // for CoreBridgeState
+    extern "C" {
+        fn _ttbc_test_abort(api: &mut CoreBridgeState, kind: libc::c_int) -> libc::c_int;
+    }
+
+    /// Run the C test helper with the given kind of abort, returning the
+    /// kind of the error that it was reported as.
+    fn abort_kind(kind: libc::c_int) -> Option<EngineAbortKind> {
+        let mut hooks = MinimalDriver::new(IoStack::new(Vec::new()));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let result = launcher.with_global_lock(|state| {
+            // SAFETY: the helper follows the enter/exit protocol itself.
+            let aborted = unsafe { _ttbc_test_abort(state, kind) } != 0;
+
+            // Report an abort regardless, like an engine that fails without
+            // calling an abort function.
+            if aborted || kind == -2 {
+                Err(EngineAbortedError::new_abort_indicator().into())
+            } else {
+                Ok(())
+            }
+        });
+
+        result
+            .err()
+            .map(|e| e.downcast_ref::<EngineAbortedError>().unwrap().kind())
+    }
+
+    #[test]
+    fn abort_kinds() {
+        assert_eq!(abort_kind(-1), Some(EngineAbortKind::General));
+        assert_eq!(abort_kind(0), Some(EngineAbortKind::General));
+        assert_eq!(abort_kind(1), Some(EngineAbortKind::OutOfMemory));
+        assert_eq!(abort_kind(2), Some(EngineAbortKind::FileOpen));
+        assert_eq!(abort_kind(3), Some(EngineAbortKind::Internal));
+    }
+
+    #[test]
+    fn abort_kind_is_reset() {
+        assert_eq!(abort_kind(1), Some(EngineAbortKind::OutOfMemory));
+        assert_eq!(abort_kind(-2), Some(EngineAbortKind::General));
+    }
+}
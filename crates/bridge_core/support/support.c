@@ -20,8 +20,9 @@ xcalloc(size_t nelem, size_t elsize)
     void *new_mem = calloc(nelem ? nelem : 1, elsize ? elsize : 1);
 
     if (new_mem == NULL)
-        _tt_abort("xcalloc request for %lu elements of size %lu failed",
-                  (unsigned long) nelem, (unsigned long) elsize);
+        _tt_abort_with_kind(TTBC_ABORT_OUT_OF_MEMORY,
+                            "xcalloc request for %lu elements of size %lu failed",
+                            (unsigned long) nelem, (unsigned long) elsize);
 
     return new_mem;
 }
@@ -33,7 +34,8 @@ xmalloc(size_t size)
     void *new_mem = malloc(size ? size : 1);
 
     if (new_mem == NULL)
-        _tt_abort("xmalloc request for %lu bytes failed", (unsigned long) size);
+        _tt_abort_with_kind(TTBC_ABORT_OUT_OF_MEMORY,
+                            "xmalloc request for %lu bytes failed", (unsigned long) size);
 
     return new_mem;
 }
@@ -49,7 +51,8 @@ xrealloc(void *old_ptr, size_t size)
     } else {
         new_mem = realloc(old_ptr, size ? size : 1);
         if (new_mem == NULL)
-            _tt_abort("xrealloc() to %lu bytes failed", (unsigned long) size);
+            _tt_abort_with_kind(TTBC_ABORT_OUT_OF_MEMORY,
+                                "xrealloc() to %lu bytes failed", (unsigned long) size);
     }
 
     return new_mem;
@@ -89,6 +92,16 @@ ttstub_diag_printf(ttbc_diagnostic_t *diag, const char *format, ...)
 
 static ttbc_state_t *tectonic_global_bridge_core = NULL;
 static jmp_buf jump_buffer;
+static ttbc_abort_kind abort_kind = TTBC_ABORT_GENERAL;
+
+
+NORETURN PRINTF_FUNC(2,0) static void
+vabort(ttbc_abort_kind kind, const char *format, va_list ap)
+{
+    vsnprintf(format_buf, BUF_SIZE, format, ap);
+    abort_kind = kind;
+    longjmp(jump_buffer, 1);
+}
 
 
 NORETURN PRINTF_FUNC(1,2) int
@@ -97,9 +110,17 @@ _tt_abort(const char *format, ...)
     va_list ap;
 
     va_start(ap, format);
-    vsnprintf(format_buf, BUF_SIZE, format, ap);
-    va_end(ap);
-    longjmp(jump_buffer, 1);
+    vabort(TTBC_ABORT_GENERAL, format, ap);
+}
+
+
+NORETURN PRINTF_FUNC(2,3) int
+_tt_abort_with_kind(ttbc_abort_kind kind, const char *format, ...)
+{
+    va_list ap;
+
+    va_start(ap, format);
+    vabort(kind, format, ap);
 }
 
 
@@ -113,10 +134,21 @@ _ttbc_get_error_message(void)
 }
 
 
+/* Likewise, for the kind of the abort. */
+int
+_ttbc_get_error_kind(void)
+{
+    return (int) abort_kind;
+}
+
+
 jmp_buf *
 ttbc_global_engine_enter(ttbc_state_t *api)
 {
     tectonic_global_bridge_core = api;
+    /* An engine can fail without calling an abort function, so don't let it
+     * report the kind of an abort from a previous run. */
+    abort_kind = TTBC_ABORT_GENERAL;
     return &jump_buffer;
 }
 
@@ -128,6 +160,28 @@ ttbc_global_engine_exit(void)
 }
 
 
+/* This is used by the Rust test suite to exercise aborts without an engine.
+ * A `kind` of -1 aborts with _tt_abort(), -2 doesn't abort at all, and
+ * anything else aborts with that kind. Returns nonzero if there was an
+ * abort. */
+int
+_ttbc_test_abort(ttbc_state_t *api, int kind)
+{
+    if (setjmp(*ttbc_global_engine_enter(api))) {
+        ttbc_global_engine_exit();
+        return 1;
+    }
+
+    if (kind == -1)
+        _tt_abort("test abort");
+    if (kind != -2)
+        _tt_abort_with_kind((ttbc_abort_kind) kind, "test abort of kind %d", kind);
+
+    ttbc_global_engine_exit();
+    return 0;
+}
+
+
 PRINTF_FUNC(1,2) void
 ttstub_issue_warning(const char *format, ...)
 {
@@ -244,6 +298,12 @@ ttstub_get_last_input_abspath(char *buffer, size_t len)
     return ttbc_get_last_input_abspath(tectonic_global_bridge_core, (uint8_t *) buffer, len);
 }
 
+size_t
+ttstub_get_env(char const *name, char *buffer, size_t len)
+{
+    return ttbc_get_env(tectonic_global_bridge_core, name, (uint8_t *) buffer, len);
+}
+
 size_t
 ttstub_input_get_size(rust_input_handle_t handle)
 {
@@ -272,7 +332,7 @@ ttstub_input_seek(rust_input_handle_t handle, ssize_t offset, int whence)
 
     if (internal_error) {
         // Nonzero indicates a serious internal error.
-        longjmp(jump_buffer, 1);
+        _tt_abort_with_kind(TTBC_ABORT_INTERNAL, "internal error while seeking in an input file");
     }
 
     return rv;
@@ -310,7 +370,7 @@ ttstub_input_close(rust_input_handle_t handle)
 {
     if (ttbc_input_close(tectonic_global_bridge_core, handle)) {
         // Nonzero return value indicates a serious internal error.
-        longjmp(jump_buffer, 1);
+        _tt_abort_with_kind(TTBC_ABORT_INTERNAL, "internal error while closing an input file");
     }
 
     return 0;
@@ -221,7 +221,22 @@ PRINTF_FUNC(2,0) void ttstub_diag_vprintf(ttbc_diagnostic_t *diag, const char *f
 jmp_buf *ttbc_global_engine_enter(ttbc_state_t *api);
 void ttbc_global_engine_exit(void);
 
+/* Fatal errors are reported by calling one of the abort functions, which
+ * longjmp() back to the engine entry point. The "kind" of the abort is passed
+ * along to the Rust layer so that library users can distinguish problems with
+ * the input from problems with the environment. The values must be kept in sync
+ * with `EngineAbortKind` in the Rust code. */
+
+typedef enum {
+    TTBC_ABORT_GENERAL = 0,
+    TTBC_ABORT_OUT_OF_MEMORY = 1,
+    TTBC_ABORT_FILE_OPEN = 2,
+    TTBC_ABORT_INTERNAL = 3,
+    TTBC_ABORT_BAD_FORMAT = 4,
+} ttbc_abort_kind;
+
 NORETURN PRINTF_FUNC(1,2) int _tt_abort(const char *format, ...);
+NORETURN PRINTF_FUNC(2,3) int _tt_abort_with_kind(ttbc_abort_kind kind, const char *format, ...);
 
 PRINTF_FUNC(1,2) void ttstub_issue_warning(const char *format, ...);
 PRINTF_FUNC(1,2) void ttstub_issue_error(const char *format, ...);
@@ -239,6 +254,7 @@ int ttstub_output_close(rust_output_handle_t handle);
 rust_input_handle_t ttstub_input_open(char const *path, ttbc_file_format format, int is_gz);
 rust_input_handle_t ttstub_input_open_primary(void);
 ssize_t ttstub_get_last_input_abspath(char *buffer, size_t len);
+size_t ttstub_get_env(char const *name, char *buffer, size_t len);
 size_t ttstub_input_get_size(rust_input_handle_t handle);
 time_t ttstub_input_get_mtime(rust_input_handle_t handle);
 size_t ttstub_input_seek(rust_input_handle_t handle, ssize_t offset, int whence);
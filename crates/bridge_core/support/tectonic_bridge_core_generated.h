@@ -252,6 +252,22 @@ Option_InputId ttbc_input_open_primary(ttbc_state_t *es);
  */
 ssize_t ttbc_get_last_input_abspath(ttbc_state_t *es, uint8_t *buffer, size_t len);
 
+/**
+ * Read an environment variable, if the security settings allow it.
+ *
+ * If the variable may be read and has a value, this function will fill in the
+ * caller's buffer with its UTF-8 value, including a terminating NUL, if the
+ * buffer is big enough. It returns 0 if the value is unavailable, or the
+ * number of bytes needed to hold it (including the NUL) otherwise. If this is
+ * larger than *len*, nothing is written and the caller should try again with
+ * a bigger buffer.
+ *
+ * # Safety
+ *
+ * This function is unsafe because it dereferences raw C pointers.
+ */
+size_t ttbc_get_env(ttbc_state_t *es, const char *name, uint8_t *buffer, size_t len);
+
 /**
  * Get the size of a Tectonic input file.
  */
@@ -9,6 +9,7 @@
 
 use crate::{Bundle, CachableBundle, FileIndex, FileInfo};
 use std::{
+    collections::HashSet,
     fs::{self, File},
     io::{self, BufReader, Read, Write},
     path::{Path, PathBuf},
@@ -21,7 +22,7 @@ use tectonic_io_base::{
     digest::{self, DigestData},
     InputHandle, InputOrigin, IoProvider, OpenResult,
 };
-use tectonic_status_base::StatusBackend;
+use tectonic_status_base::{tt_warning, StatusBackend};
 
 /// A convenience method to provide a better error message when writing to a created file.
 fn file_create_write<P, F, E>(path: P, write_fn: F) -> Result<()>
@@ -92,6 +93,10 @@ pub struct BundleCache<'this, T> {
 
     // The hash of the bundle we're caching.
     bundle_hash: DigestData,
+
+    /// Cached files that have been checked against the digests recorded in
+    /// the bundle index, so that they're only hashed once per session.
+    verified: HashSet<PathBuf>,
 }
 
 impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
@@ -136,7 +141,12 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
             }
         };
 
-        let live_hash = bundle.get_digest();
+        // In cache-only mode, the saved digest is trusted as it is, since
+        // checking it against the live one would mean touching the network.
+        let live_hash = match saved_hash {
+            Some(_) if only_cached => Err(anyhow!("only cached files may be used")),
+            _ => bundle.get_digest(),
+        };
 
         // Check remote bundle digest
         let bundle_hash: DigestData = match (saved_hash, live_hash) {
@@ -171,6 +181,7 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
             bundle,
             cache_root,
             bundle_hash,
+            verified: HashSet::new(),
         };
 
         // Right now, files are stored in
@@ -190,9 +201,14 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
 
     /// Build a cache path for the given bundle file
     fn get_file_path(&self, info: &T::InfoType) -> PathBuf {
+        self.get_path_for(info.path())
+    }
+
+    /// Build a cache path for the bundle file with the given path
+    fn get_path_for(&self, path: &str) -> PathBuf {
         let mut out = self.cache_root.clone();
         out.push(format!("data/{}", self.bundle_hash));
-        out.push(info.path());
+        out.push(path);
         out
     }
 
@@ -328,6 +344,75 @@ impl<'this, T: FileIndex<'this>> BundleCache<'this, T> {
 
         OpenResult::Ok(target)
     }
+
+    /// Check a file in the cache against the digest recorded for it in the
+    /// bundle index, if there is one.
+    ///
+    /// A file that was already cached and doesn't match is assumed to have
+    /// been damaged, so it's removed and fetched again, once. A file that
+    /// doesn't match right after being fetched is an error.
+    fn verify_file(
+        &mut self,
+        path: PathBuf,
+        info: T::InfoType,
+        fetched: bool,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<PathBuf> {
+        let Some(expected) = info.hash().map(|h| h.to_owned()) else {
+            return OpenResult::Ok(path);
+        };
+
+        if self.verified.contains(&path) {
+            return OpenResult::Ok(path);
+        }
+
+        match file_digest(&path) {
+            Ok(d) if d.eq_ignore_ascii_case(&expected) => {
+                self.verified.insert(path.clone());
+                return OpenResult::Ok(path);
+            }
+            Ok(_) => {}
+            Err(e) => return OpenResult::Err(e),
+        }
+
+        if fetched {
+            return OpenResult::Err(anyhow!(
+                "bundle file `{}` doesn't match the digest recorded in the bundle index",
+                info.path()
+            ));
+        }
+
+        tt_warning!(
+            status,
+            "cached bundle file `{}` is corrupted; removing it and fetching it again",
+            path.display()
+        );
+
+        if let Err(e) = fs::remove_file(&path) {
+            return OpenResult::Err(e.into());
+        }
+
+        let path = match self.fetch_file(info.clone(), status) {
+            OpenResult::Ok(p) => p,
+            OpenResult::NotAvailable => {
+                return OpenResult::Err(anyhow!(
+                    "cached bundle file `{}` was corrupted and couldn't be fetched again",
+                    info.path()
+                ));
+            }
+            OpenResult::Err(e) => return OpenResult::Err(e),
+        };
+
+        self.verify_file(path, info, true, status)
+    }
+}
+
+/// Compute the SHA256 digest of a file, as hexadecimal text.
+fn file_digest(path: &Path) -> Result<String> {
+    let mut f = File::open(path).with_context(|| format!("while opening {path:?} in cache"))?;
+    let mut hasher = digest::create();
+    io::copy(&mut f, &mut hasher).with_context(|| format!("while hashing {path:?} in cache"))?;
+    Ok(digest::bytes_to_hex(&digest::Digest::finalize(hasher)))
 }
 
 impl<'this, T: FileIndex<'this>> IoProvider for BundleCache<'this, T> {
@@ -336,17 +421,23 @@ impl<'this, T: FileIndex<'this>> IoProvider for BundleCache<'this, T> {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
-        let path = match self.get_fileinfo(name) {
+        let (path, info, fetched) = match self.get_fileinfo(name) {
             OpenResult::NotAvailable => return OpenResult::NotAvailable,
             OpenResult::Err(e) => return OpenResult::Err(e),
-            OpenResult::Ok((true, f)) => self.get_file_path(&f),
-            OpenResult::Ok((false, f)) => match self.fetch_file(f, status) {
-                OpenResult::Ok(p) => p,
+            OpenResult::Ok((true, f)) => (self.get_file_path(&f), f, false),
+            OpenResult::Ok((false, f)) => match self.fetch_file(f.clone(), status) {
+                OpenResult::Ok(p) => (p, f, true),
                 OpenResult::NotAvailable => return OpenResult::NotAvailable,
                 OpenResult::Err(e) => return OpenResult::Err(e),
             },
         };
 
+        let path = match self.verify_file(path, info, fetched, status) {
+            OpenResult::Ok(p) => p,
+            OpenResult::NotAvailable => return OpenResult::NotAvailable,
+            OpenResult::Err(e) => return OpenResult::Err(e),
+        };
+
         let f = match File::open(path) {
             Ok(f) => f,
             Err(e) => return OpenResult::Err(e.into()),
@@ -368,4 +459,167 @@ impl<'this, T: FileIndex<'this>> Bundle for BundleCache<'this, T> {
     fn all_files(&self) -> Vec<String> {
         self.bundle.all_files()
     }
+
+    fn cached_files(&mut self) -> Result<Vec<(String, PathBuf)>> {
+        self.ensure_index()?;
+
+        let mut files: Vec<_> = self
+            .bundle
+            .all_files()
+            .into_iter()
+            .filter_map(|path| {
+                let local = self.get_path_for(&path);
+                local.is_file().then_some((path, local))
+            })
+            .collect();
+
+        files.sort();
+        Ok(files)
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        Some(self.cache_root.join(format!("data/{}", self.bundle_hash)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ttb::{TTBFileIndex, TTBFileInfo};
+    use std::{cell::Cell, io::Cursor, rc::Rc};
+    use tectonic_status_base::NoopStatusBackend;
+
+    const GOOD: &str = "\\bye\n";
+
+    /// A bundle serving one file from memory, counting how many times it's
+    /// fetched. Its index records the digest of `GOOD`, whatever it serves.
+    struct MemoryBundle {
+        content: &'static str,
+        fetches: Rc<Cell<usize>>,
+        index: TTBFileIndex,
+    }
+
+    impl MemoryBundle {
+        fn new(content: &'static str, fetches: &Rc<Cell<usize>>) -> Box<Self> {
+            Box::new(MemoryBundle {
+                content,
+                fetches: fetches.clone(),
+                index: TTBFileIndex::default(),
+            })
+        }
+    }
+
+    impl IoProvider for MemoryBundle {}
+
+    impl Bundle for MemoryBundle {
+        fn get_digest(&mut self) -> Result<DigestData> {
+            Ok(DigestData::zeros())
+        }
+
+        fn all_files(&self) -> Vec<String> {
+            vec!["plain.tex".to_owned()]
+        }
+    }
+
+    impl CachableBundle<'_, TTBFileIndex> for MemoryBundle {
+        fn initialize_index(&mut self, source: &mut dyn Read) -> Result<()> {
+            self.index.initialize(source)
+        }
+
+        fn get_index_reader(&mut self) -> Result<Box<dyn Read>> {
+            let mut hasher = digest::create();
+            io::copy(&mut GOOD.as_bytes(), &mut hasher)?;
+            let hash = digest::bytes_to_hex(&digest::Digest::finalize(hasher));
+            let index = format!(
+                "[DEFAULTSEARCH]\nMAIN\n[SEARCH:MAIN]\n/\n[FILELIST]\n0 0 {} {hash} plain.tex\n",
+                GOOD.len()
+            );
+            Ok(Box::new(Cursor::new(index)))
+        }
+
+        fn index(&mut self) -> &mut TTBFileIndex {
+            &mut self.index
+        }
+
+        fn open_fileinfo(
+            &mut self,
+            info: &TTBFileInfo,
+            _status: &mut dyn StatusBackend,
+        ) -> OpenResult<InputHandle> {
+            self.fetches.set(self.fetches.get() + 1);
+            OpenResult::Ok(InputHandle::new_read_only(
+                info.name.clone(),
+                Cursor::new(self.content.as_bytes().to_vec()),
+                InputOrigin::Other,
+            ))
+        }
+
+        fn search(&mut self, name: &str) -> Option<TTBFileInfo> {
+            self.index.search(name)
+        }
+
+        fn get_location(&mut self) -> String {
+            "memory".to_owned()
+        }
+    }
+
+    fn read(cache: &mut BundleCache<'_, TTBFileIndex>) -> Result<String> {
+        match cache.input_open_name("plain.tex", &mut NoopStatusBackend::default()) {
+            OpenResult::Ok(mut h) => {
+                let mut text = String::new();
+                h.read_to_string(&mut text)?;
+                Ok(text)
+            }
+            OpenResult::NotAvailable => bail!("plain.tex is not available"),
+            OpenResult::Err(e) => Err(e),
+        }
+    }
+
+    #[test]
+    fn refetch_corrupted_file() {
+        let root = tempfile::tempdir().unwrap();
+        let fetches = Rc::new(Cell::new(0));
+
+        let mut cache = BundleCache::new(
+            MemoryBundle::new(GOOD, &fetches),
+            false,
+            Some(root.path().to_owned()),
+        )
+        .unwrap();
+        assert_eq!(read(&mut cache).unwrap(), GOOD);
+        assert_eq!(fetches.get(), 1);
+
+        let cached = cache.cache_dir().unwrap().join("plain.tex");
+        fs::write(&cached, "garbage\n").unwrap();
+
+        // Files are only checked once per session, so start another.
+        let mut cache = BundleCache::new(
+            MemoryBundle::new(GOOD, &fetches),
+            false,
+            Some(root.path().to_owned()),
+        )
+        .unwrap();
+        assert_eq!(read(&mut cache).unwrap(), GOOD);
+        assert_eq!(fetches.get(), 2);
+        assert_eq!(fs::read_to_string(&cached).unwrap(), GOOD);
+
+        // Once it's good again, it's not fetched any more.
+        assert_eq!(read(&mut cache).unwrap(), GOOD);
+        assert_eq!(fetches.get(), 2);
+    }
+
+    #[test]
+    fn corrupted_fetch_is_an_error() {
+        let root = tempfile::tempdir().unwrap();
+        let fetches = Rc::new(Cell::new(0));
+
+        let mut cache = BundleCache::new(
+            MemoryBundle::new("garbage\n", &fetches),
+            false,
+            Some(root.path().to_owned()),
+        )
+        .unwrap();
+        assert!(read(&mut cache).is_err());
+        assert_eq!(fetches.get(), 1);
+    }
 }
@@ -15,7 +15,10 @@
 //! resource, the index file merely contains a byte offset and length that are
 //! then used to construct an HTTP Range request to obtain the file as needed.
 
-use crate::{Bundle, CachableBundle, FileIndex, FileInfo, NET_RETRY_ATTEMPTS, NET_RETRY_SLEEP_MS};
+use crate::{
+    read_with_progress, Bundle, CachableBundle, FileIndex, FileInfo, NET_RETRY_ATTEMPTS,
+    NET_RETRY_SLEEP_MS,
+};
 use flate2::read::GzDecoder;
 use std::{
     collections::HashMap,
@@ -27,7 +30,7 @@ use std::{
 use tectonic_errors::prelude::*;
 use tectonic_geturl::{DefaultBackend, DefaultRangeReader, GetUrlBackend, RangeReader};
 use tectonic_io_base::{digest, InputHandle, InputOrigin, IoProvider, OpenResult};
-use tectonic_status_base::{tt_note, tt_warning, NoopStatusBackend, StatusBackend};
+use tectonic_status_base::{tt_note, tt_warning, DownloadEvent, NoopStatusBackend, StatusBackend};
 
 /// The internal file-information struct used by the [`ItarBundle`].
 #[derive(Clone, Debug)]
@@ -230,10 +233,19 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
 
         let mut v = Vec::with_capacity(info.length);
         tt_note!(status, "downloading {}", info.name);
+        status.report_download(DownloadEvent::Start {
+            name: &info.name,
+            total: Some(info.length as u64),
+            source: &self.url,
+        });
 
         // Edge case for zero-sized reads
         // (these cause errors on some web hosts)
         if info.length == 0 {
+            status.report_download(DownloadEvent::Finish {
+                name: &info.name,
+                bytes: 0,
+            });
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
                 Cursor::new(v),
@@ -260,7 +272,13 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
                 }
             };
 
-            match stream.read_to_end(&mut v) {
+            match read_with_progress(
+                &mut stream,
+                &mut v,
+                &info.name,
+                Some(info.length as u64),
+                status,
+            ) {
                 Ok(_) => {}
                 Err(e) => {
                     tt_warning!(status,
@@ -272,6 +290,10 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
                 }
             };
 
+            status.report_download(DownloadEvent::Finish {
+                name: &info.name,
+                bytes: v.len() as u64,
+            });
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
                 Cursor::new(v),
@@ -279,6 +301,7 @@ impl CachableBundle<'_, ItarFileIndex> for ItarBundle {
             ));
         }
 
+        status.report_download(DownloadEvent::Fail { name: &info.name });
         OpenResult::Err(anyhow!(
             "failed to download \"{}\"; please check your network connection.",
             info.name
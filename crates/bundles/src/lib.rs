@@ -18,7 +18,7 @@
 use std::{fmt::Debug, io::Read, path::PathBuf};
 use tectonic_errors::{prelude::bail, Result};
 use tectonic_io_base::{digest::DigestData, InputHandle, IoProvider, OpenResult};
-use tectonic_status_base::StatusBackend;
+use tectonic_status_base::{DownloadEvent, StatusBackend};
 
 pub mod cache;
 pub mod dir;
@@ -44,6 +44,44 @@ const TECTONIC_BUNDLE_PREFIX_DEFAULT: &str = "https://relay.fullyjustified.net";
 const NET_RETRY_ATTEMPTS: usize = 3;
 const NET_RETRY_SLEEP_MS: u64 = 500;
 
+// How much data network bundles should receive
+// between reports of download progress.
+const NET_PROGRESS_INTERVAL: u64 = 64 * 1024;
+
+/// Read a file that's being downloaded into `buf`, replacing any data from
+/// earlier attempts and reporting progress to the status backend as it
+/// arrives.
+fn read_with_progress(
+    reader: &mut dyn Read,
+    buf: &mut Vec<u8>,
+    name: &str,
+    total: Option<u64>,
+    status: &mut dyn StatusBackend,
+) -> std::io::Result<()> {
+    let mut chunk = [0u8; 8192];
+    let mut last_report = 0;
+    buf.clear();
+
+    loop {
+        let n = match reader.read(&mut chunk) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+
+        buf.extend_from_slice(&chunk[..n]);
+        let bytes = buf.len() as u64;
+
+        if bytes - last_report >= NET_PROGRESS_INTERVAL {
+            status.report_download(DownloadEvent::Progress { name, bytes, total });
+            last_report = bytes;
+        }
+    }
+
+    Ok(())
+}
+
 /// Uniquely identifies a file in a bundle.
 pub trait FileInfo: Clone + Debug {
     /// Return a path to this file, relative to the bundle.
@@ -51,6 +89,12 @@ pub trait FileInfo: Clone + Debug {
 
     /// Return the name of this file
     fn name(&self) -> &str;
+
+    /// Return the SHA256 digest of this file's contents, as hexadecimal
+    /// text, if the bundle records one.
+    fn hash(&self) -> Option<&str> {
+        None
+    }
 }
 
 /// Keeps track of
@@ -107,6 +151,22 @@ pub trait Bundle: IoProvider {
     /// Iterate over all file paths in this bundle.
     /// This is used for the `bundle search` command
     fn all_files(&self) -> Vec<String>;
+
+    /// Get the files of this bundle that are stored in a local cache.
+    ///
+    /// Returns pairs of the paths of the files within the bundle and on the
+    /// local filesystem. This never touches the network, and is used to export
+    /// a cache for use on another machine. Bundles that aren't caches return
+    /// an error.
+    fn cached_files(&mut self) -> Result<Vec<(String, PathBuf)>> {
+        bail!("this bundle is not backed by a local cache")
+    }
+
+    /// Get the local directory in which this bundle's files are cached, if
+    /// it's backed by a cache. This never touches the network.
+    fn cache_dir(&self) -> Option<PathBuf> {
+        None
+    }
 }
 
 impl<B: Bundle + ?Sized> Bundle for Box<B> {
@@ -117,6 +177,14 @@ impl<B: Bundle + ?Sized> Bundle for Box<B> {
     fn all_files(&self) -> Vec<String> {
         (**self).all_files()
     }
+
+    fn cached_files(&mut self) -> Result<Vec<(String, PathBuf)>> {
+        (**self).cached_files()
+    }
+
+    fn cache_dir(&self) -> Option<PathBuf> {
+        (**self).cache_dir()
+    }
 }
 
 /// A bundle that may be cached.
@@ -284,6 +352,14 @@ pub fn get_fallback_bundle_url(format_version: u32) -> String {
         return bundle_locked.to_owned();
     }
 
+    // Version 34 only added Tectonic-specific primitives, so it uses the same
+    // support files as version 33.
+    let format_version = if format_version == 34 {
+        33
+    } else {
+        format_version
+    };
+
     // Format version 32 (TeXLive 2021) was when we introduced versioning to the
     // URL.
     if format_version < 32 {
@@ -9,6 +9,7 @@
 //! Instead, wrap it in a [`crate::BundleCache`] for filesystem-backed caching.
 
 use crate::{
+    read_with_progress,
     ttb::{TTBFileIndex, TTBFileInfo, TTBv1Header},
     Bundle, CachableBundle, FileIndex, FileInfo, NET_RETRY_ATTEMPTS, NET_RETRY_SLEEP_MS,
 };
@@ -22,7 +23,7 @@ use std::{
 use tectonic_errors::prelude::*;
 use tectonic_geturl::{DefaultBackend, DefaultRangeReader, GetUrlBackend, RangeReader};
 use tectonic_io_base::{InputHandle, InputOrigin, IoProvider, OpenResult};
-use tectonic_status_base::{tt_note, tt_warning, StatusBackend};
+use tectonic_status_base::{tt_note, tt_warning, DownloadEvent, StatusBackend};
 
 /// Read a [`TTBFileInfo`] from this bundle.
 /// We assume that `fileinfo` points to a valid file in this bundle.
@@ -169,10 +170,19 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
     ) -> OpenResult<InputHandle> {
         let mut v: Vec<u8> = Vec::with_capacity(info.real_len as usize);
         tt_note!(status, "downloading {}", info.name);
+        status.report_download(DownloadEvent::Start {
+            name: &info.name,
+            total: Some(info.real_len as u64),
+            source: &self.url,
+        });
 
         // Edge case for zero-sized reads
         // (these cause errors on some web hosts)
         if info.gzip_len == 0 {
+            status.report_download(DownloadEvent::Finish {
+                name: &info.name,
+                bytes: 0,
+            });
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
                 Cursor::new(v),
@@ -194,7 +204,13 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
                 }
             };
 
-            match reader.read_to_end(&mut v) {
+            match read_with_progress(
+                &mut reader,
+                &mut v,
+                &info.name,
+                Some(info.real_len as u64),
+                status,
+            ) {
                 Ok(_) => {}
                 Err(e) => {
                     tt_warning!(status,
@@ -206,6 +222,10 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
                 }
             };
 
+            status.report_download(DownloadEvent::Finish {
+                name: &info.name,
+                bytes: v.len() as u64,
+            });
             return OpenResult::Ok(InputHandle::new_read_only(
                 info.name.to_owned(),
                 Cursor::new(v),
@@ -213,6 +233,7 @@ impl CachableBundle<'_, TTBFileIndex> for TTBNetBundle<TTBFileIndex> {
             ));
         }
 
+        status.report_download(DownloadEvent::Fail { name: &info.name });
         OpenResult::Err(anyhow!(
             "failed to download \"{}\"; please check your network connection.",
             info.name
@@ -0,0 +1,113 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Configuration for building workspaces on continuous-integration services.
+//!
+//! The configuration is generated from templates kept alongside this module,
+//! so that it follows changes in how documents are built. Each pipeline caches
+//! the files fetched from the bundle, keyed on the `Tectonic.toml` files that
+//! pin it, builds the workspace with `SOURCE_DATE_EPOCH` set to the time of the
+//! commit, so that rebuilding a commit gives the same outputs, and keeps the
+//! build directories of the documents as artifacts.
+
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+
+use crate::workspace::Workspace;
+
+/// The command that the pipelines run to build the workspace.
+const BUILD_COMMAND: &str = "tectonic -X build";
+
+/// A continuous-integration service that configuration can be generated for.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CiService {
+    /// GitHub Actions.
+    GitHub,
+
+    /// GitLab CI/CD.
+    GitLab,
+}
+
+impl CiService {
+    /// Get the path of the service's configuration file, relative to the root
+    /// of the repository.
+    pub fn config_path(&self) -> &'static str {
+        match self {
+            CiService::GitHub => ".github/workflows/tectonic.yml",
+            CiService::GitLab => ".gitlab-ci.yml",
+        }
+    }
+
+    /// Render the configuration that builds *workspace* on this service,
+    /// assuming that the root of the workspace is the root of the repository.
+    pub fn render(&self, workspace: &Workspace) -> String {
+        let (template, item_prefix) = match self {
+            CiService::GitHub => (include_str!("ci/github.yml"), "            "),
+            CiService::GitLab => (include_str!("ci/gitlab.yml"), "      - "),
+        };
+
+        let artifact_paths: Vec<_> = workspace
+            .documents()
+            .iter()
+            .map(|doc| {
+                let dir = doc.build_dir();
+                let dir = dir.strip_prefix(workspace.root_dir()).unwrap_or(dir);
+                let dir: Vec<_> = dir
+                    .components()
+                    .map(|c| c.as_os_str().to_string_lossy())
+                    .collect();
+                format!("{item_prefix}{}/", dir.join("/"))
+            })
+            .collect();
+
+        template
+            .replace("@BUILD_COMMAND@", BUILD_COMMAND)
+            .replace("@ARTIFACT_PATHS@", &artifact_paths.join("\n"))
+    }
+}
+
+impl FromStr for CiService {
+    type Err = Error;
+
+    /// Parse `github` or `gitlab`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "github" => Ok(CiService::GitHub),
+            "gitlab" => Ok(CiService::GitLab),
+            _ => bail!("unrecognized CI service `{s}`; expected `github` or `gitlab`"),
+        }
+    }
+}
+
+impl fmt::Display for CiService {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            CiService::GitHub => "github",
+            CiService::GitLab => "gitlab",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn render() {
+        let ws = Workspace::load(
+            PathBuf::from("/ws"),
+            "[doc]\nname = \"a\"\nbundle = \"na\"\n[[output]]\nname = \"o\"\ntype = \"pdf\"\n",
+        )
+        .unwrap();
+
+        let github = CiService::GitHub.render(&ws);
+        assert!(github.contains("\n          tectonic -X build\n"));
+        assert!(github.ends_with("          path: |\n            build/\n"));
+
+        let gitlab = CiService::GitLab.render(&ws);
+        assert!(gitlab.contains("\n    - tectonic -X build\n"));
+        assert!(gitlab.ends_with("    paths:\n      - build/\n"));
+        assert!(!gitlab.contains('@'));
+    }
+}
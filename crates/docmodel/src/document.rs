@@ -3,16 +3,15 @@
 
 //! A single Tectonic document.
 //!
-//! Every document is part of a [`crate::workspace::Workspace`]. At the moment
-//! workspaces can only contain a single document each, but in the future it
-//! might become possible for one workspace to contain multiple documents.
+//! Every document is part of a [`crate::workspace::Workspace`], which may
+//! contain several documents.
 //!
 //! This crate, on its own, does not provide document-processing capabilities.
 //! The main `tectonic` crate provides extension traits that set up document
 //! processing, in the `tectonic::docmodel` module.
 
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     env, fs,
     io::{Read, Write},
     path::{Component, Path, PathBuf},
@@ -80,6 +79,14 @@ pub struct Document {
     /// different settings (e.g., PDF with A4 paper and PDF with US Letter
     /// paper).
     pub outputs: HashMap<String, OutputProfile>,
+
+    /// Input files whose contents are produced by generators before each
+    /// build, in the order that they're declared.
+    pub generators: Vec<GeneratedInput>,
+
+    /// The names of other documents in the workspace that must be built
+    /// before this one, such as ones whose outputs this document includes.
+    pub depends_on: Vec<String>,
 }
 
 impl Document {
@@ -101,6 +108,8 @@ impl Document {
         let mut outputs = HashMap::new();
 
         for toml_output in &doc.outputs {
+            toml_output.validate()?;
+
             let output: OutputProfile = toml_output.into();
 
             if outputs.insert(output.name.clone(), output).is_some() {
@@ -115,6 +124,22 @@ impl Document {
             bail!("TOML specification must define at least one output");
         }
 
+        let mut generators: Vec<GeneratedInput> = Vec::new();
+
+        for toml_generator in &doc.generators {
+            toml_generator.validate()?;
+            let generator: GeneratedInput = toml_generator.into();
+
+            if generators.iter().any(|g| g.name == generator.name) {
+                bail!(
+                    "duplicated generated input name `{}` in TOML specification",
+                    &generator.name
+                );
+            }
+
+            generators.push(generator);
+        }
+
         Ok(Document {
             src_dir: src_dir.into(),
             build_dir: build_dir.into(),
@@ -123,6 +148,8 @@ impl Document {
             extra_paths: doc.doc.extra_paths.unwrap_or_default(),
             metadata: doc.doc.metadata,
             outputs,
+            generators,
+            depends_on: doc.doc.depends_on.unwrap_or_default(),
         })
     }
 
@@ -145,14 +172,27 @@ impl Document {
             Some(self.extra_paths.clone())
         };
 
+        let depends_on = if self.depends_on.is_empty() {
+            None
+        } else {
+            Some(self.depends_on.clone())
+        };
+
         let doc = syntax::TomlDocument {
             doc: syntax::TomlDocSection {
                 name: self.name.clone(),
                 bundle: self.bundle_loc.clone(),
                 extra_paths,
                 metadata: None,
+                depends_on,
             },
             outputs,
+            generators: self
+                .generators
+                .iter()
+                .map(syntax::TomlGeneratedInput::from)
+                .collect(),
+            workspace: None,
         };
 
         let toml_text = toml::to_string_pretty(&doc)?;
@@ -256,6 +296,212 @@ pub struct OutputProfile {
     ///
     /// Default is false.
     pub synctex: bool,
+
+    /// Overrides for the parameters of OpenType math fonts.
+    ///
+    /// By default, the parameters provided by the fonts are used unchanged.
+    pub math_font: MathFontOverrides,
+
+    /// The legacy 8-bit encoding, such as `latin1`, of any input files that
+    /// aren't valid UTF-8. If set, such files are transcoded into UTF-8 before
+    /// the engine sees them.
+    pub legacy_input_encoding: Option<String>,
+
+    /// Whether input files encoded as UTF-16 should be transcoded into UTF-8.
+    ///
+    /// Default is false, in which case such files are rejected.
+    pub transcode_utf16: bool,
+
+    /// Whether a report of accessibility gaps in the outputs should be
+    /// written. This only has an effect for HTML outputs.
+    ///
+    /// Default is false.
+    pub accessibility_report: bool,
+
+    /// Whether the outputs should describe themselves for previews on social
+    /// media, using the `title`, `description`, `url`, `site_name`,
+    /// `twitter_site`, `image`, and `image_alt` keys of the document's
+    /// metadata. This only has an effect for HTML outputs.
+    ///
+    /// Default is false, unless `social_preview` is set.
+    pub social_metadata: bool,
+
+    /// How to render the image shown in previews on social media. This only
+    /// has an effect for HTML outputs.
+    ///
+    /// Default is none.
+    pub social_preview: Option<SocialPreview>,
+
+    /// How math is rendered. This only has an effect for HTML outputs.
+    ///
+    /// Default is [`MathRendering::Glyphs`].
+    pub math_rendering: MathRendering,
+
+    /// What happens to the section numbers in headings. This only has an
+    /// effect for HTML outputs.
+    ///
+    /// Default is [`HeadingNumbers::Keep`].
+    pub heading_numbers: HeadingNumbers,
+
+    /// The deepest heading level, from 1 to 6, listed in the tables of
+    /// contents generated for the templates. This only has an effect for HTML
+    /// outputs.
+    ///
+    /// Default is none, meaning that all headings are listed.
+    pub toc_depth: Option<u8>,
+
+    /// Post-processing steps applied to the outputs of this profile, in
+    /// order.
+    ///
+    /// Default is empty.
+    pub postprocess: Vec<PostProcessStep>,
+
+    /// The largest size, in bytes, that the output of this profile may have.
+    /// For HTML outputs, this is the total size of the files emitted. Builds
+    /// whose outputs are larger fail, with a breakdown of what takes up the
+    /// space.
+    ///
+    /// Default is none, meaning that outputs may be of any size.
+    pub max_size: Option<u64>,
+}
+
+/// How to render the image shown in previews of an HTML output on social
+/// media.
+///
+/// The image is rendered from the PDF output of another profile of the same
+/// document, which should therefore be built first.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SocialPreview {
+    /// The name of the PDF output profile to render the image from.
+    pub from: String,
+
+    /// The page to render, counting from 1.
+    ///
+    /// Default is 1.
+    pub page: u32,
+
+    /// The region of the page to render, as the horizontal and vertical
+    /// distances of its top left corner from that of the page, and its width
+    /// and height, all in PostScript points.
+    ///
+    /// Default is none, meaning the whole page.
+    pub region: Option<[f64; 4]>,
+
+    /// The width of the image in pixels.
+    ///
+    /// Default is 1200.
+    pub width: u32,
+}
+
+/// A step of post-processing applied to the outputs of a build.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct PostProcessStep {
+    /// The post-processor to run.
+    pub processor: PostProcessor,
+
+    /// The extensions of the files that the step applies to, such as `pdf`.
+    ///
+    /// If empty, the post-processor's defaults are used.
+    pub extensions: Vec<String>,
+
+    /// The settings passed to the post-processor. Values that aren't strings
+    /// in `Tectonic.toml` are given in their TOML syntax.
+    pub config: BTreeMap<String, String>,
+}
+
+/// A post-processor run in a [`PostProcessStep`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum PostProcessor {
+    /// One of the post-processors built into Tectonic, by name.
+    Builtin(String),
+
+    /// An external program, given as its name or path followed by its
+    /// arguments. This is never empty.
+    Command(Vec<String>),
+}
+
+/// An input file whose contents are produced by a generator before each
+/// build.
+///
+/// Documents read such files as `\input{<name>}`, or as `\input{|<name>}`,
+/// which replaces the piped input from external commands that other TeX
+/// engines offer with shell-escape.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GeneratedInput {
+    /// The name of the file.
+    pub name: String,
+
+    /// The generator that produces its contents.
+    pub generator: Generator,
+
+    /// The paths of the files that its contents are computed from, relative
+    /// to the document's root directory. The output of an external generator
+    /// is cached until their contents change.
+    ///
+    /// Default is empty, meaning that the generator is run on every build.
+    pub inputs: Vec<String>,
+
+    /// The settings passed to the generator. Values that aren't strings in
+    /// `Tectonic.toml` are given in their TOML syntax.
+    pub config: BTreeMap<String, String>,
+}
+
+/// A generator that produces a [`GeneratedInput`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Generator {
+    /// One of the generators built into Tectonic, by name.
+    Builtin(String),
+
+    /// An external program, given as its name or path followed by its
+    /// arguments, whose standard output becomes the contents of the input.
+    /// This is never empty.
+    Command(Vec<String>),
+}
+
+/// Overrides for parameters read from OpenType math fonts.
+///
+/// These let a document work around fonts whose parameters produce poor
+/// results, such as misaligned fraction bars or too-small scripts, without
+/// patching the font files.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MathFontOverrides {
+    /// If set, replaces the font's `ScriptPercentScaleDown` constant.
+    pub script_percent_scale_down: Option<u8>,
+
+    /// If set, replaces the font's `ScriptScriptPercentScaleDown` constant.
+    pub script_script_percent_scale_down: Option<u8>,
+
+    /// An adjustment added to the font's `AxisHeight` constant, in thousandths
+    /// of an em.
+    pub axis_height_adjust: i32,
+}
+
+/// How math is rendered in HTML outputs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MathRendering {
+    /// Draw math as positioned glyphs, like the rest of the text.
+    #[default]
+    Glyphs,
+
+    /// Emit the TeX source of math, for KaTeX to render in the browser.
+    Katex,
+
+    /// Emit the TeX source of math, for MathJax to render in the browser.
+    MathJax,
+}
+
+/// What happens to the section numbers typeset in headings of HTML outputs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum HeadingNumbers {
+    /// Keep the numbers as typeset.
+    #[default]
+    Keep,
+
+    /// Leave the numbers out.
+    Strip,
+
+    /// Leave the numbers out and number the headings with CSS counters.
+    CssCounters,
 }
 
 /// The output target type of a document build.
@@ -330,6 +576,8 @@ impl Document {
             extra_paths,
             outputs: crate::document::default_outputs(),
             metadata: None,
+            generators: Vec::new(),
+            depends_on: Vec::new(),
         })
     }
 }
@@ -349,6 +597,17 @@ pub(crate) fn default_outputs() -> HashMap<String, OutputProfile> {
             shell_escape: false,
             shell_escape_cwd: None,
             synctex: false,
+            math_font: MathFontOverrides::default(),
+            legacy_input_encoding: None,
+            transcode_utf16: false,
+            accessibility_report: false,
+            social_metadata: false,
+            social_preview: None,
+            math_rendering: MathRendering::Glyphs,
+            heading_numbers: HeadingNumbers::Keep,
+            toc_depth: None,
+            postprocess: Vec::new(),
+            max_size: None,
         },
     );
     outputs
@@ -418,6 +677,34 @@ mod tests {
         assert!(doc.outputs.get("o").unwrap().shell_escape);
     }
 
+    #[test]
+    fn math_font_overrides() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [output.math_font]
+        script_percent_scale_down = 75
+        axis_height_adjust = -20
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert_eq!(
+            doc.outputs.get("o").unwrap().math_font,
+            MathFontOverrides {
+                script_percent_scale_down: Some(75),
+                script_script_percent_scale_down: None,
+                axis_height_adjust: -20,
+            }
+        );
+    }
+
     #[test]
     fn synctex_default_false() {
         const TOML: &str = r#"
@@ -450,4 +737,265 @@ mod tests {
         let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
         assert!(doc.outputs.get("o").unwrap().synctex);
     }
+
+    #[test]
+    fn accessibility_report_set_true() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+        accessibility_report = true
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        assert!(doc.outputs.get("o").unwrap().accessibility_report);
+    }
+
+    #[test]
+    fn social_preview() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "web"
+        type = "html"
+
+        [output.social_preview]
+        from = "pdf"
+        region = [72, 72, 468, 234]
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let web = doc.outputs.get("web").unwrap();
+        assert!(web.social_metadata);
+        assert_eq!(
+            web.social_preview,
+            Some(SocialPreview {
+                from: "pdf".to_owned(),
+                page: 1,
+                region: Some([72., 72., 468., 234.]),
+                width: 1200,
+            })
+        );
+
+        let toml = crate::syntax::TomlOutputProfile::from(web);
+        assert_eq!(toml.social_metadata, None);
+        assert_eq!(toml.social_preview.unwrap().page, None);
+    }
+
+    #[test]
+    fn math_rendering() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+        math_rendering = "katex"
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let o = doc.outputs.get("o").unwrap();
+        assert_eq!(o.math_rendering, MathRendering::Katex);
+
+        let toml = crate::syntax::TomlOutputProfile::from(o);
+        assert_eq!(
+            toml.math_rendering,
+            Some(crate::syntax::TomlMathRendering::Katex)
+        );
+    }
+
+    #[test]
+    fn headings() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "html"
+        heading_numbers = "css-counters"
+        toc_depth = 3
+        "#;
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let o = doc.outputs.get("o").unwrap();
+        assert_eq!(o.heading_numbers, HeadingNumbers::CssCounters);
+        assert_eq!(o.toc_depth, Some(3));
+
+        let toml = crate::syntax::TomlOutputProfile::from(o);
+        assert_eq!(
+            toml.heading_numbers,
+            Some(crate::syntax::TomlHeadingNumbers::CssCounters)
+        );
+        assert_eq!(toml.toc_depth, Some(3));
+
+        let bad = TOML.replace("toc_depth = 3", "toc_depth = 7");
+        let mut c = Cursor::new(bad.as_bytes());
+        assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+    }
+
+    #[test]
+    fn postprocess_steps() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[output.postprocess]]
+        builtin = "pdf-compress"
+        config = { level = 6 }
+
+        [[output.postprocess]]
+        command = ["./sign.sh", "--key", "k"]
+        extensions = ["pdf", "log"]
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+        let steps = &doc.outputs.get("o").unwrap().postprocess;
+
+        assert_eq!(
+            steps[0],
+            PostProcessStep {
+                processor: PostProcessor::Builtin("pdf-compress".to_owned()),
+                extensions: Vec::new(),
+                config: [("level".to_owned(), "6".to_owned())].into_iter().collect(),
+            }
+        );
+        assert_eq!(
+            steps[1].processor,
+            PostProcessor::Command(vec!["./sign.sh".into(), "--key".into(), "k".into()])
+        );
+        assert_eq!(steps[1].extensions, ["pdf", "log"]);
+    }
+
+    #[test]
+    fn postprocess_needs_one_processor() {
+        for step in [
+            r#"builtin = "html-minify"
+            command = ["x"]"#,
+            "command = []",
+            "extensions = [\"pdf\"]",
+        ] {
+            let toml = format!(
+                "[doc]\nname = \"test\"\nbundle = \"na\"\n\n\
+                 [[output]]\nname = \"o\"\ntype = \"pdf\"\n\n\
+                 [[output.postprocess]]\n{step}\n"
+            );
+
+            let mut c = Cursor::new(toml.as_bytes());
+            assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+        }
+    }
+
+    #[test]
+    fn max_sizes() {
+        for (size, expected) in [
+            ("2048", Some(2048)),
+            ("\"10 MB\"", Some(10_000_000)),
+            ("\"1.5MiB\"", Some(1_572_864)),
+            ("\"300 kB\"", Some(300_000)),
+            ("\"10 parsecs\"", None),
+            ("\"MB\"", None),
+        ] {
+            let toml = format!(
+                "[doc]\nname = \"test\"\nbundle = \"na\"\n\n\
+                 [[output]]\nname = \"o\"\ntype = \"pdf\"\nmax_size = {size}\n"
+            );
+
+            let mut c = Cursor::new(toml.as_bytes());
+            let doc = Document::new_from_toml(".", ".", &mut c);
+            assert_eq!(
+                doc.ok().map(|d| d.outputs.get("o").unwrap().max_size),
+                expected.map(Some),
+                "{size}"
+            );
+        }
+    }
+
+    #[test]
+    fn generated_inputs() {
+        const TOML: &str = r#"
+        [doc]
+        name = "test"
+        bundle = "na"
+
+        [[output]]
+        name = "o"
+        type = "pdf"
+
+        [[generator]]
+        name = "gitinfo.tex"
+        builtin = "git-info"
+        config = { prefix = "doc" }
+
+        [[generator]]
+        name = "table.tex"
+        command = ["python3", "scripts/table.py"]
+        inputs = ["data/table.csv"]
+        "#;
+
+        let mut c = Cursor::new(TOML.as_bytes());
+        let doc = Document::new_from_toml(".", ".", &mut c).unwrap();
+
+        assert_eq!(
+            doc.generators,
+            [
+                GeneratedInput {
+                    name: "gitinfo.tex".to_owned(),
+                    generator: Generator::Builtin("git-info".to_owned()),
+                    inputs: Vec::new(),
+                    config: [("prefix".to_owned(), "doc".to_owned())]
+                        .into_iter()
+                        .collect(),
+                },
+                GeneratedInput {
+                    name: "table.tex".to_owned(),
+                    generator: Generator::Command(vec![
+                        "python3".into(),
+                        "scripts/table.py".into()
+                    ]),
+                    inputs: vec!["data/table.csv".to_owned()],
+                    config: BTreeMap::new(),
+                },
+            ]
+        );
+
+        let toml = crate::syntax::TomlGeneratedInput::from(&doc.generators[1]);
+        assert_eq!(toml.builtin, None);
+        assert_eq!(toml.config, None);
+    }
+
+    #[test]
+    fn generated_inputs_are_checked() {
+        for generator in [
+            "name = \"a\"\nbuiltin = \"date\"\ncommand = [\"x\"]",
+            "name = \"a\"\ncommand = []",
+            "name = \"|a\"\nbuiltin = \"date\"",
+            "name = \"a\"\nbuiltin = \"date\"\n\n[[generator]]\nname = \"a\"\nbuiltin = \"date\"",
+        ] {
+            let toml = format!(
+                "[doc]\nname = \"test\"\nbundle = \"na\"\n\n\
+                 [[output]]\nname = \"o\"\ntype = \"pdf\"\n\n\
+                 [[generator]]\n{generator}\n"
+            );
+
+            let mut c = Cursor::new(toml.as_bytes());
+            assert!(Document::new_from_toml(".", ".", &mut c).is_err());
+        }
+    }
 }
@@ -16,6 +16,7 @@
 //! `Tectonic.toml` file. There is also [`workspace::WorkspaceCreator`] for
 //! creating new workspaces from scratch.
 
+pub mod ci;
 pub mod document;
 mod syntax;
 pub mod workspace;
@@ -6,12 +6,15 @@
 //!
 //! This module is only used by [`crate::document::Document`]
 
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf};
+use tectonic_errors::prelude::*;
 
 use crate::document::{
-    BuildTargetType, InputFile, OutputProfile, DEFAULT_INDEX_FILE, DEFAULT_POSTAMBLE_FILE,
-    DEFAULT_PREAMBLE_FILE,
+    BuildTargetType, GeneratedInput, Generator, HeadingNumbers, InputFile, MathFontOverrides,
+    MathRendering, OutputProfile, PostProcessStep, PostProcessor, SocialPreview,
+    DEFAULT_INDEX_FILE, DEFAULT_POSTAMBLE_FILE, DEFAULT_PREAMBLE_FILE,
 };
+use crate::workspace::SiteConfig;
 use serde::{Deserialize, Serialize, Serializer};
 
 // This file is an exercise in Rust type conversion.
@@ -27,6 +30,14 @@ pub struct TomlDocument {
 
     #[serde(rename = "output")]
     pub outputs: Vec<TomlOutputProfile>,
+
+    #[serde(default, rename = "generator", skip_serializing_if = "Vec::is_empty")]
+    pub generators: Vec<TomlGeneratedInput>,
+
+    // The workspace table is handled by `TomlWorkspaceFile`, but a file that
+    // defines a document can have one too.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub workspace: Option<TomlWorkspaceSection>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -36,6 +47,60 @@ pub struct TomlDocSection {
     pub bundle: String,
     pub metadata: Option<toml::Value>,
     pub extra_paths: Option<Vec<PathBuf>>,
+    pub depends_on: Option<Vec<String>>,
+}
+
+/// The parts of a `Tectonic.toml` file that say whether it defines a
+/// document, a workspace of several documents, or both. The rest of the file
+/// is checked when the document is loaded.
+#[derive(Debug, Deserialize)]
+pub struct TomlWorkspaceFile {
+    pub doc: Option<toml::Value>,
+    pub workspace: Option<TomlWorkspaceSection>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlWorkspaceSection {
+    pub members: Vec<PathBuf>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub site: Option<TomlSite>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlSite {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub sitemap: Option<bool>,
+    pub feed: Option<bool>,
+    pub index: Option<bool>,
+    pub index_template: Option<PathBuf>,
+}
+
+impl TomlSite {
+    pub fn validate(&self) -> Result<()> {
+        let needs_url = self.sitemap.unwrap_or(true) || self.feed.unwrap_or(true);
+        ensure!(
+            self.url.is_some() || !needs_url,
+            "the `[workspace.site]` table must set `url` unless `sitemap` and `feed` are both false"
+        );
+        Ok(())
+    }
+}
+
+impl From<&TomlSite> for SiteConfig {
+    fn from(val: &TomlSite) -> SiteConfig {
+        SiteConfig {
+            title: val.title.clone(),
+            url: val.url.clone(),
+            sitemap: val.sitemap.unwrap_or(true),
+            feed: val.feed.unwrap_or(true),
+            index: val.index.unwrap_or(true),
+            index_template: val.index_template.clone(),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -102,6 +167,17 @@ pub struct TomlOutputProfile {
     pub shell_escape: Option<bool>,
     pub shell_escape_cwd: Option<String>,
     pub synctex: Option<bool>,
+    pub math_font: Option<TomlMathFontOverrides>,
+    pub legacy_input_encoding: Option<String>,
+    pub transcode_utf16: Option<bool>,
+    pub accessibility_report: Option<bool>,
+    pub social_metadata: Option<bool>,
+    pub social_preview: Option<TomlSocialPreview>,
+    pub math_rendering: Option<TomlMathRendering>,
+    pub heading_numbers: Option<TomlHeadingNumbers>,
+    pub toc_depth: Option<u8>,
+    pub postprocess: Option<Vec<TomlPostProcessStep>>,
+    pub max_size: Option<TomlSize>,
 
     // We cannot handle these two input variants with an enum.
     // The ideal solution requires #[serde(flatten)],
@@ -119,6 +195,33 @@ pub struct TomlOutputProfile {
     pub postamble_file: Option<String>,
 }
 
+impl TomlOutputProfile {
+    /// Check the settings that serde can't check by itself.
+    pub fn validate(&self) -> Result<()> {
+        for step in self.postprocess.iter().flatten() {
+            step.validate(&self.name)?;
+        }
+
+        if let Some(size) = &self.max_size {
+            atry!(
+                size.to_bytes();
+                ["output `{}` has an invalid `max_size`", self.name]
+            );
+        }
+
+        if let Some(depth) = self.toc_depth {
+            ensure!(
+                (1..=6).contains(&depth),
+                "output `{}` has `toc_depth` {} but it must be between 1 and 6",
+                self.name,
+                depth
+            );
+        }
+
+        Ok(())
+    }
+}
+
 impl From<&TomlOutputProfile> for OutputProfile {
     fn from(val: &TomlOutputProfile) -> OutputProfile {
         let shell_escape_default = val.shell_escape_cwd.is_some();
@@ -167,6 +270,17 @@ impl From<&TomlOutputProfile> for OutputProfile {
             shell_escape: val.shell_escape.unwrap_or(shell_escape_default),
             shell_escape_cwd: val.shell_escape_cwd.clone(),
             synctex: val.synctex.unwrap_or(synctex_default),
+            math_font: val.math_font.as_ref().map(|m| m.into()).unwrap_or_default(),
+            legacy_input_encoding: val.legacy_input_encoding.clone(),
+            transcode_utf16: val.transcode_utf16.unwrap_or(false),
+            accessibility_report: val.accessibility_report.unwrap_or(false),
+            social_metadata: val.social_metadata.unwrap_or(val.social_preview.is_some()),
+            social_preview: val.social_preview.as_ref().map(|p| p.into()),
+            math_rendering: val.math_rendering.map(|m| m.into()).unwrap_or_default(),
+            heading_numbers: val.heading_numbers.map(|h| h.into()).unwrap_or_default(),
+            toc_depth: val.toc_depth,
+            postprocess: val.postprocess.iter().flatten().map(|s| s.into()).collect(),
+            max_size: val.max_size.as_ref().and_then(|s| s.to_bytes().ok()),
         }
     }
 }
@@ -185,6 +299,46 @@ impl From<&OutputProfile> for TomlOutputProfile {
         let shell_escape = if !rt.shell_escape { None } else { Some(true) };
         let shell_escape_cwd = rt.shell_escape_cwd.clone();
         let synctex = if !rt.synctex { None } else { Some(true) };
+        let transcode_utf16 = if !rt.transcode_utf16 {
+            None
+        } else {
+            Some(true)
+        };
+        let accessibility_report = if !rt.accessibility_report {
+            None
+        } else {
+            Some(true)
+        };
+        let social_metadata = if rt.social_metadata == rt.social_preview.is_some() {
+            None
+        } else {
+            Some(rt.social_metadata)
+        };
+        let math_rendering = if rt.math_rendering == MathRendering::default() {
+            None
+        } else {
+            Some((&rt.math_rendering).into())
+        };
+        let heading_numbers = if rt.heading_numbers == HeadingNumbers::default() {
+            None
+        } else {
+            Some((&rt.heading_numbers).into())
+        };
+        let postprocess = if rt.postprocess.is_empty() {
+            None
+        } else {
+            Some(
+                rt.postprocess
+                    .iter()
+                    .map(TomlPostProcessStep::from)
+                    .collect(),
+            )
+        };
+        let math_font = if rt.math_font == MathFontOverrides::default() {
+            None
+        } else {
+            Some((&rt.math_font).into())
+        };
 
         TomlOutputProfile {
             name: rt.name.clone(),
@@ -194,6 +348,17 @@ impl From<&OutputProfile> for TomlOutputProfile {
             shell_escape,
             shell_escape_cwd,
             synctex,
+            math_font,
+            legacy_input_encoding: rt.legacy_input_encoding.clone(),
+            transcode_utf16,
+            accessibility_report,
+            social_metadata,
+            social_preview: rt.social_preview.as_ref().map(|p| p.into()),
+            math_rendering,
+            heading_numbers,
+            toc_depth: rt.toc_depth,
+            postprocess,
+            max_size: rt.max_size.map(TomlSize::Bytes),
             preamble_file: None,
             index_file: None,
             postamble_file: None,
@@ -201,6 +366,340 @@ impl From<&OutputProfile> for TomlOutputProfile {
     }
 }
 
+/// A size, given either as a number of bytes or as a string with a unit,
+/// such as `"10 MB"`.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(untagged)]
+pub enum TomlSize {
+    Bytes(u64),
+    Text(String),
+}
+
+impl TomlSize {
+    /// Get the number of bytes. Units such as `kB` and `MB` are powers of
+    /// 1000, and units such as `KiB` and `MiB` are powers of 1024.
+    pub fn to_bytes(&self) -> Result<u64> {
+        let text = match self {
+            TomlSize::Bytes(n) => return Ok(*n),
+            TomlSize::Text(t) => t.trim(),
+        };
+
+        let split = text
+            .find(|c: char| !c.is_ascii_digit() && c != '.')
+            .unwrap_or(text.len());
+        let (number, unit) = text.split_at(split);
+
+        let scale: u64 = match unit.trim().to_ascii_lowercase().as_str() {
+            "" | "b" => 1,
+            "kb" => 1_000,
+            "mb" => 1_000_000,
+            "gb" => 1_000_000_000,
+            "kib" => 1 << 10,
+            "mib" => 1 << 20,
+            "gib" => 1 << 30,
+            _ => bail!("unrecognized unit in the size `{}`", text),
+        };
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| anyhow!("expected a number in the size `{}`", text))?;
+        Ok((number * scale as f64).round() as u64)
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlPostProcessStep {
+    pub builtin: Option<String>,
+    pub command: Option<Vec<String>>,
+    pub extensions: Option<Vec<String>>,
+    pub config: Option<toml::Table>,
+}
+
+impl TomlPostProcessStep {
+    /// Check that the step names exactly one post-processor, since serde
+    /// can't express that without giving up `deny_unknown_fields`.
+    pub fn validate(&self, output_name: &str) -> Result<()> {
+        match (&self.builtin, &self.command) {
+            (Some(_), None) => Ok(()),
+            (None, Some(c)) if !c.is_empty() => Ok(()),
+            (None, Some(_)) => bail!(
+                "post-processing step of output `{}` has an empty `command`",
+                output_name
+            ),
+            _ => bail!(
+                "post-processing step of output `{}` must have exactly one of `builtin` and `command`",
+                output_name
+            ),
+        }
+    }
+}
+
+impl From<&TomlPostProcessStep> for PostProcessStep {
+    fn from(val: &TomlPostProcessStep) -> PostProcessStep {
+        let processor = match &val.builtin {
+            Some(name) => PostProcessor::Builtin(name.clone()),
+            None => PostProcessor::Command(val.command.clone().unwrap_or_default()),
+        };
+
+        PostProcessStep {
+            processor,
+            extensions: val.extensions.clone().unwrap_or_default(),
+            config: config_from_toml(val.config.as_ref()),
+        }
+    }
+}
+
+impl From<&PostProcessStep> for TomlPostProcessStep {
+    fn from(rt: &PostProcessStep) -> Self {
+        let (builtin, command) = match &rt.processor {
+            PostProcessor::Builtin(name) => (Some(name.clone()), None),
+            PostProcessor::Command(argv) => (None, Some(argv.clone())),
+        };
+
+        let extensions = if rt.extensions.is_empty() {
+            None
+        } else {
+            Some(rt.extensions.clone())
+        };
+
+        TomlPostProcessStep {
+            builtin,
+            command,
+            extensions,
+            config: config_to_toml(&rt.config),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlGeneratedInput {
+    pub name: String,
+    pub builtin: Option<String>,
+    pub command: Option<Vec<String>>,
+    pub inputs: Option<Vec<String>>,
+    pub config: Option<toml::Table>,
+}
+
+impl TomlGeneratedInput {
+    /// Check that the input has a usable name and exactly one generator.
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            !self.name.is_empty() && !self.name.starts_with('|'),
+            "generated input name `{}` must be nonempty and must not start with `|`",
+            self.name
+        );
+
+        match (&self.builtin, &self.command) {
+            (Some(_), None) => Ok(()),
+            (None, Some(c)) if !c.is_empty() => Ok(()),
+            (None, Some(_)) => bail!("generated input `{}` has an empty `command`", self.name),
+            _ => bail!(
+                "generated input `{}` must have exactly one of `builtin` and `command`",
+                self.name
+            ),
+        }
+    }
+}
+
+impl From<&TomlGeneratedInput> for GeneratedInput {
+    fn from(val: &TomlGeneratedInput) -> GeneratedInput {
+        let generator = match &val.builtin {
+            Some(name) => Generator::Builtin(name.clone()),
+            None => Generator::Command(val.command.clone().unwrap_or_default()),
+        };
+
+        GeneratedInput {
+            name: val.name.clone(),
+            generator,
+            inputs: val.inputs.clone().unwrap_or_default(),
+            config: config_from_toml(val.config.as_ref()),
+        }
+    }
+}
+
+impl From<&GeneratedInput> for TomlGeneratedInput {
+    fn from(rt: &GeneratedInput) -> Self {
+        let (builtin, command) = match &rt.generator {
+            Generator::Builtin(name) => (Some(name.clone()), None),
+            Generator::Command(argv) => (None, Some(argv.clone())),
+        };
+
+        let inputs = if rt.inputs.is_empty() {
+            None
+        } else {
+            Some(rt.inputs.clone())
+        };
+
+        TomlGeneratedInput {
+            name: rt.name.clone(),
+            builtin,
+            command,
+            inputs,
+            config: config_to_toml(&rt.config),
+        }
+    }
+}
+
+/// Convert the settings of a post-processor or generator. Values that aren't
+/// strings are kept in their TOML syntax.
+fn config_from_toml(config: Option<&toml::Table>) -> BTreeMap<String, String> {
+    config
+        .into_iter()
+        .flatten()
+        .map(|(k, v)| {
+            let v = match v {
+                toml::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            (k.clone(), v)
+        })
+        .collect()
+}
+
+fn config_to_toml(config: &BTreeMap<String, String>) -> Option<toml::Table> {
+    if config.is_empty() {
+        None
+    } else {
+        Some(
+            config
+                .iter()
+                .map(|(k, v)| (k.clone(), toml::Value::String(v.clone())))
+                .collect(),
+        )
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlSocialPreview {
+    pub from: String,
+    pub page: Option<u32>,
+    pub region: Option<[f64; 4]>,
+    pub width: Option<u32>,
+}
+
+impl From<&TomlSocialPreview> for SocialPreview {
+    fn from(val: &TomlSocialPreview) -> SocialPreview {
+        SocialPreview {
+            from: val.from.clone(),
+            page: val.page.unwrap_or(1),
+            region: val.region,
+            width: val.width.unwrap_or(1200),
+        }
+    }
+}
+
+impl From<&SocialPreview> for TomlSocialPreview {
+    fn from(rt: &SocialPreview) -> Self {
+        TomlSocialPreview {
+            from: rt.from.clone(),
+            page: (rt.page != 1).then_some(rt.page),
+            region: rt.region,
+            width: (rt.width != 1200).then_some(rt.width),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TomlMathFontOverrides {
+    pub script_percent_scale_down: Option<u8>,
+    pub script_script_percent_scale_down: Option<u8>,
+    pub axis_height_adjust: Option<i32>,
+}
+
+impl From<&TomlMathFontOverrides> for MathFontOverrides {
+    fn from(val: &TomlMathFontOverrides) -> MathFontOverrides {
+        MathFontOverrides {
+            script_percent_scale_down: val.script_percent_scale_down,
+            script_script_percent_scale_down: val.script_script_percent_scale_down,
+            axis_height_adjust: val.axis_height_adjust.unwrap_or(0),
+        }
+    }
+}
+
+impl From<&MathFontOverrides> for TomlMathFontOverrides {
+    fn from(rt: &MathFontOverrides) -> Self {
+        let axis_height_adjust = if rt.axis_height_adjust == 0 {
+            None
+        } else {
+            Some(rt.axis_height_adjust)
+        };
+
+        TomlMathFontOverrides {
+            script_percent_scale_down: rt.script_percent_scale_down,
+            script_script_percent_scale_down: rt.script_script_percent_scale_down,
+            axis_height_adjust,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TomlMathRendering {
+    #[serde(rename = "glyphs")]
+    Glyphs,
+
+    #[serde(rename = "katex")]
+    Katex,
+
+    #[serde(rename = "mathjax")]
+    MathJax,
+}
+
+impl From<TomlMathRendering> for MathRendering {
+    fn from(val: TomlMathRendering) -> MathRendering {
+        match val {
+            TomlMathRendering::Glyphs => MathRendering::Glyphs,
+            TomlMathRendering::Katex => MathRendering::Katex,
+            TomlMathRendering::MathJax => MathRendering::MathJax,
+        }
+    }
+}
+
+impl From<&MathRendering> for TomlMathRendering {
+    fn from(s: &MathRendering) -> Self {
+        match s {
+            MathRendering::Glyphs => TomlMathRendering::Glyphs,
+            MathRendering::Katex => TomlMathRendering::Katex,
+            MathRendering::MathJax => TomlMathRendering::MathJax,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum TomlHeadingNumbers {
+    #[serde(rename = "keep")]
+    Keep,
+
+    #[serde(rename = "strip")]
+    Strip,
+
+    #[serde(rename = "css-counters")]
+    CssCounters,
+}
+
+impl From<TomlHeadingNumbers> for HeadingNumbers {
+    fn from(val: TomlHeadingNumbers) -> HeadingNumbers {
+        match val {
+            TomlHeadingNumbers::Keep => HeadingNumbers::Keep,
+            TomlHeadingNumbers::Strip => HeadingNumbers::Strip,
+            TomlHeadingNumbers::CssCounters => HeadingNumbers::CssCounters,
+        }
+    }
+}
+
+impl From<&HeadingNumbers> for TomlHeadingNumbers {
+    fn from(s: &HeadingNumbers) -> Self {
+        match s {
+            HeadingNumbers::Keep => TomlHeadingNumbers::Keep,
+            HeadingNumbers::Strip => TomlHeadingNumbers::Strip,
+            HeadingNumbers::CssCounters => TomlHeadingNumbers::CssCounters,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum TomlBuildTargetType {
     #[serde(rename = "html")]
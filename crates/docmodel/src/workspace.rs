@@ -3,62 +3,153 @@
 
 //! A Tectonic document-build workspace.
 //!
-//! For the time being, this is just a thin wrapper to provide access to a
-//! `Document` instance. This API exists to future-proof a bit for a potential
-//! world where one workspace can contain multiple documents.
+//! A workspace is defined by a `Tectonic.toml` file. Usually that file
+//! defines a single document, but it can also list other directories holding
+//! documents of their own in a `[workspace]` table, so that they can be built
+//! together:
+//!
+//! ```toml
+//! [workspace]
+//! members = ["thesis", "slides"]
+//! ```
+//!
+//! Such a file may define a document of its own as well, or nothing else.
+//! Documents can say that other documents of the workspace must be built
+//! before them with the `doc.depends_on` key.
+//!
+//! A `[workspace.site]` table says that the HTML outputs of the documents
+//! are published together as a site; see [`SiteConfig`].
 
 use std::{
+    collections::HashSet,
     env,
     error::Error,
     fmt, fs,
     io::{self, Write},
-    path::PathBuf,
+    path::{Path, PathBuf},
 };
 use tectonic_errors::prelude::*;
 
-use crate::document::Document;
+use crate::{ci::CiService, document::Document, syntax};
 
 /// A Tectonic workspace.
 ///
-/// For the time being, a Workspace is just a thin wrapper to provide access to
-/// a `Document` instance. In the future, it might become possible for one
-/// workspace to contain multiple documents.
+/// A workspace contains one or more documents, which can be built together.
 ///
 /// In most cases, you will want to create a [`Workspace`] by opening an
 /// existing one using [`Workspace::open_from_environment`].
 #[derive(Debug)]
 pub struct Workspace {
     /// The root directory of the workspace.
-    #[allow(dead_code)] // We expect to use this eventually.
     root_dir: PathBuf,
 
-    /// This workspace's document. In the future, there might be more than one.
-    doc: Document,
+    /// This workspace's documents. There is always at least one. If the root
+    /// `Tectonic.toml` file defines a document, it comes first, followed by
+    /// the workspace members in the order that they're listed.
+    docs: Vec<Document>,
+
+    /// The settings of the site made of the documents' HTML outputs, if
+    /// there is one.
+    site: Option<SiteConfig>,
+}
+
+/// The settings of the site that the HTML outputs of a workspace's documents
+/// make up, from the `[workspace.site]` table.
+///
+/// After all of the documents of the workspace are built, a sitemap, an Atom
+/// feed of the recently changed documents, and a landing page linking to
+/// them all are written to the `build` directory at the root of the
+/// workspace. The documents' pages are located relative to the root of the
+/// workspace, which is published at [`Self::url`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SiteConfig {
+    /// The title of the site.
+    pub title: Option<String>,
+
+    /// The URL at which the root directory of the workspace is published.
+    /// It's needed for the sitemap and feed, which use absolute URLs.
+    pub url: Option<String>,
+
+    /// Whether to write a sitemap.
+    pub sitemap: bool,
+
+    /// Whether to write an Atom feed.
+    pub feed: bool,
+
+    /// Whether to write a landing page.
+    pub index: bool,
+
+    /// The Tera template of the landing page, relative to the root of the
+    /// workspace, to use instead of the default one.
+    pub index_template: Option<PathBuf>,
 }
 
 impl Workspace {
+    /// Get the root directory of the workspace, which contains its
+    /// `Tectonic.toml` file.
+    pub fn root_dir(&self) -> &Path {
+        &self.root_dir
+    }
+
     /// Get the first document in the workspace.
     ///
-    /// Right now, workspaces in fact only include one document. That may change
-    /// in the future.
+    /// This is the document defined by the workspace's own `Tectonic.toml`
+    /// file if there is one, and otherwise its first member.
     pub fn first_document(&self) -> &Document {
-        &self.doc
+        &self.docs[0]
     }
 
     /// Get the first document in the workspace, mutably.
     ///
-    /// Right now, workspaces in fact only include one document. That may change
-    /// in the future.
+    /// See [`Self::first_document`] for which document this is.
     pub fn first_document_mut(&mut self) -> &mut Document {
-        &mut self.doc
+        &mut self.docs[0]
+    }
+
+    /// Get all of the documents in the workspace.
+    pub fn documents(&self) -> &[Document] {
+        &self.docs
+    }
+
+    /// Get the document with the given name, if there is one.
+    pub fn document(&self, name: &str) -> Option<&Document> {
+        self.docs.iter().find(|d| d.name == name)
+    }
+
+    /// Get the settings of the site made of the documents' HTML outputs, if
+    /// the workspace defines one.
+    pub fn site(&self) -> Option<&SiteConfig> {
+        self.site.as_ref()
+    }
+
+    /// Write the configuration that builds this workspace on a
+    /// continuous-integration service, and return the path of the file.
+    ///
+    /// The file is written at [`CiService::config_path`] in the root
+    /// directory of the workspace. An existing file isn't overwritten.
+    pub fn write_ci_config(&self, service: CiService) -> Result<PathBuf> {
+        let path = self.root_dir.join(service.config_path());
+
+        if let Some(dir) = path.parent() {
+            atry!(
+                fs::create_dir_all(dir);
+                ["couldn\'t create directory `{}`", dir.display()]
+            );
+        }
+
+        let mut f = atry!(
+            fs::File::options().write(true).create_new(true).open(&path);
+            ["couldn\'t create `{}`", path.display()]
+        );
+        f.write_all(service.render(self).as_bytes())?;
+        Ok(path)
     }
 
     /// Open up a workspace based on the current process environment.
     ///
     /// This function searches the current directory and its parents for a
-    /// `Tectonic.toml` file. Because workspaces can currently only contain a
-    /// single document, the search stops when the first such file is found. If
-    /// no such file is found, an error downcastable into
+    /// `Tectonic.toml` file, and opens the workspace that the first such file
+    /// defines. If no such file is found, an error downcastable into
     /// [`NoWorkspaceFoundError`] is returned.
     pub fn open_from_environment() -> Result<Self> {
         let initial_dir = env::current_dir()?;
@@ -69,8 +160,8 @@ impl Workspace {
         while root_dir.pop() {
             root_dir.push("Tectonic.toml");
 
-            let mut doc_file = match fs::File::open(&root_dir) {
-                Ok(f) => f,
+            let toml_text = match fs::read_to_string(&root_dir) {
+                Ok(t) => t,
                 Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
                     root_dir.pop(); // remove "Tectonic.toml"
                     continue; // this will pop up one directory and try again
@@ -79,15 +170,113 @@ impl Workspace {
             };
 
             root_dir.pop();
-            let mut doc_build_dir = root_dir.clone();
-            doc_build_dir.push("build");
-            let doc = Document::new_from_toml(root_dir.clone(), doc_build_dir, &mut doc_file)?;
-
-            return Ok(Workspace { root_dir, doc });
+            return Self::load(root_dir, &toml_text);
         }
 
         Err(NoWorkspaceFoundError { initial_dir }.into())
     }
+
+    /// Load the workspace defined by the `Tectonic.toml` file in *root_dir*,
+    /// whose contents are *toml_text*.
+    pub(crate) fn load(root_dir: PathBuf, toml_text: &str) -> Result<Self> {
+        let file: syntax::TomlWorkspaceFile = toml::from_str(toml_text)?;
+        let mut docs = Vec::new();
+
+        let site = match file.workspace.as_ref().and_then(|w| w.site.as_ref()) {
+            Some(site) => {
+                site.validate()?;
+                Some(site.into())
+            }
+            None => None,
+        };
+
+        if file.doc.is_some() || file.workspace.is_none() {
+            docs.push(Document::new_from_toml(
+                root_dir.clone(),
+                root_dir.join("build"),
+                &mut toml_text.as_bytes(),
+            )?);
+        }
+
+        for member in file.workspace.map(|w| w.members).unwrap_or_default() {
+            let src_dir = root_dir.join(&member);
+            let toml_path = src_dir.join("Tectonic.toml");
+
+            let member_text = atry!(
+                fs::read_to_string(&toml_path);
+                ["couldn\'t read workspace member `{}`", toml_path.display()]
+            );
+
+            let member_file: syntax::TomlWorkspaceFile = atry!(
+                toml::from_str(&member_text);
+                ["couldn\'t load workspace member `{}`", toml_path.display()]
+            );
+
+            ensure!(
+                member_file.workspace.is_none(),
+                "workspace member `{}` defines a workspace of its own, but workspaces can\'t be nested",
+                toml_path.display()
+            );
+
+            let doc = atry!(
+                Document::new_from_toml(
+                    src_dir.clone(),
+                    src_dir.join("build"),
+                    &mut member_text.as_bytes(),
+                );
+                ["couldn\'t load workspace member `{}`", toml_path.display()]
+            );
+
+            docs.push(doc);
+        }
+
+        ensure!(
+            !docs.is_empty(),
+            "the workspace in `{}` doesn\'t contain any documents",
+            root_dir.display()
+        );
+        check_documents(&docs)?;
+
+        Ok(Workspace {
+            root_dir,
+            docs,
+            site,
+        })
+    }
+}
+
+/// Check that the documents of a workspace have distinct names, and that they
+/// only depend on other documents of the workspace.
+///
+/// Cycles of dependencies aren't detected here, but by the build scheduler.
+fn check_documents(docs: &[Document]) -> Result<()> {
+    let mut names = HashSet::new();
+
+    for doc in docs {
+        ensure!(
+            names.insert(doc.name.as_str()),
+            "the workspace has more than one document named `{}`",
+            doc.name
+        );
+    }
+
+    for doc in docs {
+        for dep in &doc.depends_on {
+            ensure!(
+                *dep != doc.name,
+                "document `{}` can\'t depend on itself",
+                doc.name
+            );
+            ensure!(
+                names.contains(dep.as_str()),
+                "document `{}` depends on `{}`, which isn\'t in the workspace",
+                doc.name,
+                dep
+            );
+        }
+    }
+
+    Ok(())
 }
 
 /// An error for when the environment does not seem to contain a Tectonic
@@ -114,6 +303,9 @@ impl Error for NoWorkspaceFoundError {}
 pub struct WorkspaceCreator {
     /// The root directory of the workspace to be created.
     pub(crate) root_dir: PathBuf,
+
+    /// The continuous-integration services to write configuration for.
+    ci_services: Vec<CiService>,
 }
 
 impl WorkspaceCreator {
@@ -121,9 +313,17 @@ impl WorkspaceCreator {
     pub fn new<P: Into<PathBuf>>(root_dir: P) -> Self {
         WorkspaceCreator {
             root_dir: root_dir.into(),
+            ci_services: Vec::new(),
         }
     }
 
+    /// Also write configuration that builds the new workspace on a
+    /// continuous-integration service. See [`Workspace::write_ci_config`].
+    pub fn ci_service(&mut self, service: CiService) -> &mut Self {
+        self.ci_services.push(service);
+        self
+    }
+
     /// Consume this object and attempt to create the new workspace.
     pub fn create(self, bundle_loc: String, extra_paths: Vec<PathBuf>) -> Result<Workspace> {
         let doc = Document::create_for(&self, bundle_loc, extra_paths)?;
@@ -172,11 +372,105 @@ impl WorkspaceCreator {
             tex_dir.pop();
         }
 
+        let ws = Workspace {
+            root_dir: self.root_dir,
+            docs: vec![doc],
+            site: None,
+        };
+
+        for service in self.ci_services {
+            ws.write_ci_config(service)?;
+        }
+
         // All done.
 
-        Ok(Workspace {
-            root_dir: self.root_dir,
-            doc,
-        })
+        Ok(ws)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn doc(name: &str, depends_on: &str) -> Document {
+        let toml = format!(
+            r#"
+            [doc]
+            name = "{name}"
+            bundle = "na"
+            depends_on = [{depends_on}]
+
+            [[output]]
+            name = "o"
+            type = "pdf"
+            "#
+        );
+        Document::new_from_toml(".", "build", &mut toml.as_bytes()).unwrap()
+    }
+
+    #[test]
+    fn document_checks() {
+        let docs = [doc("a", ""), doc("b", r#""a""#)];
+        assert_eq!(docs[1].depends_on, ["a"]);
+        check_documents(&docs).unwrap();
+
+        let err = check_documents(&[doc("a", ""), doc("a", "")]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the workspace has more than one document named `a`"
+        );
+
+        let err = check_documents(&[doc("a", r#""c""#)]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "document `a` depends on `c`, which isn't in the workspace"
+        );
+
+        let err = check_documents(&[doc("a", r#""a""#)]).unwrap_err();
+        assert_eq!(err.to_string(), "document `a` can't depend on itself");
+    }
+
+    #[test]
+    fn workspace_files() {
+        // A file that only defines a workspace has no document of its own.
+        let err = Workspace::load(PathBuf::from("/nonexistent"), "[workspace]\nmembers = []\n")
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the workspace in `/nonexistent` doesn't contain any documents"
+        );
+
+        // Unknown keys are still rejected in files that define documents.
+        assert!(Workspace::load(PathBuf::from("."), "[doc]\nname = 1\n").is_err());
+        assert!(Workspace::load(PathBuf::from("."), "[workspace]\nmember = []\n").is_err());
+    }
+
+    #[test]
+    fn site_settings() {
+        let err = Workspace::load(
+            PathBuf::from("/nonexistent"),
+            "[workspace]\nmembers = []\n[workspace.site]\ntitle = \"Papers\"\n",
+        )
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the `[workspace.site]` table must set `url` unless `sitemap` and `feed` are both false"
+        );
+
+        let site: syntax::TomlSite =
+            toml::from_str("sitemap = false\nfeed = false\nindex_template = \"index.tera\"\n")
+                .unwrap();
+        site.validate().unwrap();
+        assert_eq!(
+            SiteConfig::from(&site),
+            SiteConfig {
+                title: None,
+                url: None,
+                sitemap: false,
+                feed: false,
+                index: true,
+                index_template: Some("index.tera".into()),
+            }
+        );
     }
 }
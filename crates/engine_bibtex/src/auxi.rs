@@ -4,21 +4,24 @@ use crate::{
     char_info::LexClass,
     cite::CiteInfo,
     exec::print_bst_name,
+    hardened::internal_error,
     hash::{HashData, HashExtra},
     log::{
         aux_end1_err_print, aux_end2_err_print, aux_err_illegal_another_print,
         aux_err_no_right_brace_print, aux_err_print, aux_err_stuff_after_right_brace_print,
         aux_err_white_space_in_argument_print, hash_cite_confusion, log_pr_aux_name,
         log_pr_bst_name, print_a_pool_str, print_a_token, print_aux_name, print_bib_name,
-        print_confusion, print_overflow, AuxTy,
+        print_confusion, print_overflow, print_search_trace, AuxTy,
     },
     peekable::PeekableInput,
     pool::{StrNumber, StringPool},
     scan::Scan,
+    structured::{self, DatabaseFormat},
     Bibtex, BibtexError, File, GlobalItems, StrIlk,
 };
 use std::ffi::CString;
 use tectonic_bridge_core::FileFormat;
+use tectonic_io_base::digest::{self, Digest, DigestData};
 
 const AUX_STACK_SIZE: usize = 20;
 
@@ -28,6 +31,7 @@ pub(crate) enum AuxCommand {
     Style,
     Citation,
     Input,
+    RefContext,
 }
 
 pub(crate) struct AuxData {
@@ -115,12 +119,51 @@ fn aux_bib_data_command(
 
         let name = pool.get_str(hash.text(res.loc));
         let fname = CString::new(name).unwrap();
-        let bib_in = PeekableInput::open(ctx, &fname, FileFormat::Bib);
+        let structured_format =
+            DatabaseFormat::of_name(fname.as_bytes()).filter(|_| ctx.config.structured_databases);
+
+        let bib_in = match structured_format {
+            None => PeekableInput::open(ctx, &fname, FileFormat::Bib),
+            Some(format) => match PeekableInput::read_all(ctx, &fname, FileFormat::Bib) {
+                Err(e) => Err(e),
+                Ok(data) => match structured::convert(format, &data) {
+                    Ok(converted) => {
+                        for warning in &converted.warnings {
+                            ctx.diagnostics.locate(
+                                fname.to_string_lossy().into_owned(),
+                                None,
+                                None,
+                            );
+                            ctx.write_logs(&format!(
+                                "Warning--{}: {}\n",
+                                fname.to_string_lossy(),
+                                warning
+                            ));
+                            ctx.mark_warning();
+                        }
+
+                        Ok(PeekableInput::from_memory(converted.text))
+                    }
+
+                    Err(message) => {
+                        ctx.write_logs(&format!(
+                            "I couldn't convert database file {}: {}\n",
+                            fname.to_string_lossy(),
+                            message
+                        ));
+                        aux_err_print(ctx, buffers, aux, pool)?;
+                        return Ok(());
+                    }
+                },
+            },
+        };
+
         match bib_in {
             Err(_) => {
                 ctx.write_logs("I couldn't open database file ");
                 print_bib_name(ctx, pool, hash.text(res.loc))?;
                 aux_err_print(ctx, buffers, aux, pool)?;
+                print_search_trace(ctx, &fname, FileFormat::Bib);
                 return Ok(());
             }
             Ok(file) => {
@@ -150,6 +193,11 @@ fn aux_bib_style_command(
     }
     ctx.bst_seen = true;
 
+    // The style isn't run when writing data for biblatex.
+    if ctx.config.biblatex_output {
+        return Ok(());
+    }
+
     buffers.set_offset(BufTy::Base, 2, buffers.offset(BufTy::Base, 2) + 1);
     let init = buffers.init(BufTy::Base);
     if !Scan::new()
@@ -192,6 +240,7 @@ fn aux_bib_style_command(
             print_bst_name(ctx, pool, hash.text(res.loc))?;
             ctx.bst = None;
             aux_err_print(ctx, buffers, aux, pool)?;
+            print_search_trace(ctx, &fname, FileFormat::Bst);
             return Ok(());
         }
         Ok(file) => {
@@ -203,9 +252,22 @@ fn aux_bib_style_command(
         }
     }
 
+    // The code cache is keyed by the digest of the whole style, so read it
+    // through once first.
+    if ctx.code_cache.is_some() {
+        ctx.bst_digest = PeekableInput::read_all(ctx, &fname, FileFormat::Bst)
+            .ok()
+            .map(|text| {
+                let mut dc = digest::create();
+                dc.update(&text);
+                DigestData::from(dc).to_string()
+            });
+    }
+
     if ctx.config.verbose {
         ctx.write_logs("The style file: ");
         print_bst_name(ctx, pool, ctx.bst.as_ref().unwrap().name)?;
+        ctx.diagnostics.clear_text();
     } else {
         ctx.write_log_file("The style file: ");
         log_pr_bst_name(ctx, pool)?;
@@ -279,7 +341,10 @@ fn aux_citation_command(
         let lc_res = hash.lookup_str_insert(ctx, pool, lc_cite, HashExtra::LcCite(0))?;
         if lc_res.exists {
             let HashExtra::LcCite(cite_loc) = hash.node(lc_res.loc).extra else {
-                panic!("LcCite lookup didn't have LcCite extra");
+                return Err(internal_error(
+                    ctx,
+                    "LcCite lookup didn't have LcCite extra",
+                ));
             };
 
             let cite = &buffers.buffer(BufTy::Base)
@@ -287,7 +352,10 @@ fn aux_citation_command(
             let uc_res = hash.lookup_str(pool, cite, StrIlk::Cite);
             if !uc_res.exists {
                 let HashExtra::Cite(cite) = hash.node(cite_loc).extra else {
-                    panic!("LcCite location didn't have a Cite extra");
+                    return Err(internal_error(
+                        ctx,
+                        "LcCite location didn't have a Cite extra",
+                    ));
                 };
 
                 ctx.write_logs("Case mismatch error between cite keys ");
@@ -321,6 +389,19 @@ fn aux_citation_command(
     Ok(())
 }
 
+/// Record the reference context that biblatex asks for, which names the
+/// sorting scheme of the entries.
+fn aux_refcontext_command(ctx: &mut Bibtex<'_, '_>, buffers: &mut GlobalBuffer) {
+    buffers.set_offset(BufTy::Base, 2, buffers.offset(BufTy::Base, 2) + 1);
+
+    let init = buffers.init(BufTy::Base);
+    if Scan::new().chars(b"}").scan_till(buffers, init) {
+        let context = &buffers.buffer(BufTy::Base)
+            [buffers.offset(BufTy::Base, 1)..buffers.offset(BufTy::Base, 2)];
+        ctx.biblatex_refcontext = Some(String::from_utf8_lossy(context).into_owned());
+    }
+}
+
 fn aux_input_command(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &mut GlobalBuffer,
@@ -354,8 +435,7 @@ fn aux_input_command(
     if aux.ptr() == AUX_STACK_SIZE {
         print_a_token(ctx, buffers);
         ctx.write_logs(": ");
-        print_overflow(ctx);
-        ctx.write_logs(&format!("auxiliary file depth {AUX_STACK_SIZE}\n"));
+        print_overflow(ctx, &format!("auxiliary file depth {AUX_STACK_SIZE}"));
         return Err(BibtexError::Fatal);
     }
 
@@ -404,6 +484,7 @@ fn aux_input_command(
 
     ctx.write_logs(&format!("A level-{} auxiliary file: ", aux.ptr() - 1));
     log_pr_aux_name(ctx, aux, pool)?;
+    ctx.diagnostics.clear_text();
 
     Ok(())
 }
@@ -426,7 +507,10 @@ pub(crate) fn get_aux_command_and_process(
 
     if res.exists {
         let HashExtra::AuxCommand(cmd) = globals.hash.node(res.loc).extra else {
-            panic!("AuxCommand lookup didn't have AuxCommand extra");
+            return Err(internal_error(
+                ctx,
+                "AuxCommand lookup didn't have AuxCommand extra",
+            ));
         };
 
         match cmd {
@@ -460,6 +544,10 @@ pub(crate) fn get_aux_command_and_process(
                 globals.pool,
                 globals.hash,
             ),
+            AuxCommand::RefContext => {
+                aux_refcontext_command(ctx, globals.buffers);
+                Ok(())
+            }
         }?
     }
     Ok(())
@@ -503,6 +591,11 @@ pub(crate) fn last_check_for_aux_errors(
         aux_end2_err_print(ctx, pool, last_aux)?;
     }
 
+    // No style is needed when writing data for biblatex.
+    if ctx.config.biblatex_output {
+        return Ok(());
+    }
+
     if !ctx.bst_seen {
         aux_end1_err_print(ctx);
         ctx.write_logs("\\bibstyle command");
@@ -0,0 +1,753 @@
+//! A structured representation of the `.bbl` output.
+//!
+//! Classic BibTeX streams whatever the style writes straight into the `.bbl`
+//! file, breaking long lines as it goes. Consumers other than LaTeX, such as
+//! HTML bibliographies or citation previews, would rather see what the style
+//! wrote without having to re-parse that text. This module provides an
+//! intermediate representation that records each `write$` and `newline$`
+//! along with where it came from, and that can be rendered back into the
+//! exact text of the classic output.
+//!
+//! The same representation lets the engine reorder the formatted entries after
+//! the style has run, for documents whose style doesn't sort the bibliography
+//! the way they need; see [`EntryOrder`].
+
+use crate::{
+    char_info::LexClass,
+    names::{format_nth_name, num_names},
+    pool::{MAX_PRINT_LINE, MIN_PRINT_LINE},
+    srcmap::EntryLocation,
+};
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+
+/// The style command that produced a block of output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BblCommand {
+    /// An `EXECUTE` command, which runs a function once.
+    Execute,
+
+    /// An `ITERATE` command, which runs a function once for each entry.
+    Iterate,
+
+    /// A `REVERSE` command, which runs a function once for each entry, in
+    /// reverse order.
+    Reverse,
+}
+
+/// One piece of output written by the style.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BblChunk {
+    /// Text written by the `write$` builtin.
+    Text {
+        /// The text that was written. BibTeX operates on bytes; for sensible
+        /// inputs, this will be UTF-8.
+        text: Vec<u8>,
+
+        /// The name of the innermost style function that called `write$`.
+        function: String,
+    },
+
+    /// A line ending requested by the `newline$` builtin.
+    Newline,
+}
+
+/// The output produced by a single invocation of a style function.
+///
+/// For `ITERATE` and `REVERSE` commands there is one block per entry, so that
+/// each bibliography item can be picked out individually.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BblBlock {
+    /// The command that invoked the function.
+    pub command: BblCommand,
+
+    /// The name of the function that the command invoked.
+    pub function: String,
+
+    /// The citation key of the entry being processed, if the command
+    /// iterates over entries.
+    pub cite_key: Option<String>,
+
+    /// The `sort.key$` of the entry being processed, as the style last set
+    /// it before the function was invoked, if the command iterates over
+    /// entries. This is the key that `SORT` ordered the entry by, unless the
+    /// style changed it afterwards.
+    pub sort_key: Option<String>,
+
+    /// The label that the style computed for the entry being processed,
+    /// which is the value of its `label` entry variable, if the command
+    /// iterates over entries and the style declares one. Styles such as
+    /// `alpha` write this as the optional argument of `\bibitem`, including
+    /// any suffix such as the `b` of `Knu84b`; numeric styles such as `plain`
+    /// don't compute labels.
+    pub label: Option<String>,
+
+    /// The output written while processing this block, in order.
+    pub chunks: Vec<BblChunk>,
+}
+
+impl BblBlock {
+    /// Get the text of this block with its TeX markup stripped, as a rough
+    /// plain-text rendering of a bibliography entry.
+    ///
+    /// The `\bibitem` command and its arguments are dropped, as are other
+    /// control words, braces, and math shifts. Ties and line breaks become
+    /// spaces, runs of whitespace are collapsed, and runs of hyphens become en
+    /// and em dashes. This is meant for previews such as citation popovers,
+    /// not for faithful typesetting.
+    pub fn plain_text(&self) -> String {
+        let mut raw = String::new();
+
+        for chunk in &self.chunks {
+            match chunk {
+                BblChunk::Text { text, .. } => raw.push_str(&String::from_utf8_lossy(text)),
+                BblChunk::Newline => raw.push(' '),
+            }
+        }
+
+        let mut out = String::with_capacity(raw.len());
+        let mut chars = raw.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some(c) if c.is_ascii_alphabetic() => {
+                        let mut word = String::from(c);
+
+                        while let Some(c) = chars.next_if(char::is_ascii_alphabetic) {
+                            word.push(c);
+                        }
+
+                        match word.as_str() {
+                            "bibitem" => {
+                                while chars.next_if_eq(&' ').is_some() {}
+
+                                if chars.peek() == Some(&'[') {
+                                    skip_group(&mut chars, '[', ']');
+                                }
+
+                                if chars.peek() == Some(&'{') {
+                                    skip_group(&mut chars, '{', '}');
+                                }
+                            }
+                            "TeX" | "LaTeX" | "BibTeX" => out.push_str(&word),
+                            _ => {}
+                        }
+                    }
+                    Some(c @ ('&' | '%' | '$' | '#' | '_' | '{' | '}')) => out.push(c),
+                    Some(' ') => out.push(' '),
+                    // Accents and other control symbols.
+                    _ => {}
+                },
+                '{' | '}' | '$' => {}
+                '~' => out.push(' '),
+                '-' => {
+                    let mut n = 1;
+
+                    while chars.next_if_eq(&'-').is_some() {
+                        n += 1;
+                    }
+
+                    match n {
+                        2 => out.push('–'),
+                        3 => out.push('—'),
+                        n => out.push_str(&"-".repeat(n)),
+                    }
+                }
+                c => out.push(c),
+            }
+        }
+
+        out.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// Skip a balanced group delimited by `open` and `close`, starting at the
+/// opening delimiter.
+fn skip_group(chars: &mut std::iter::Peekable<std::str::Chars<'_>>, open: char, close: char) {
+    let mut depth = 0;
+
+    for c in chars.by_ref() {
+        if c == open {
+            depth += 1;
+        } else if c == close {
+            depth -= 1;
+
+            if depth == 0 {
+                break;
+            }
+        }
+    }
+}
+
+/// The structured output of a BibTeX run.
+///
+/// Blocks that didn't write anything are omitted.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bbl {
+    blocks: Vec<BblBlock>,
+}
+
+impl Bbl {
+    /// Get the blocks of output, in the order in which they were written.
+    pub fn blocks(&self) -> &[BblBlock] {
+        &self.blocks
+    }
+
+    /// Render the output into the text of a classic `.bbl` file.
+    ///
+    /// The result is byte-for-byte identical to the file written by the
+    /// engine.
+    pub fn render(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut lines = LineBreaker::default();
+        let mut sink = |line: &[u8]| out.extend_from_slice(line);
+
+        for chunk in self.blocks.iter().flat_map(|b| &b.chunks) {
+            match chunk {
+                BblChunk::Text { text, .. } => lines.write(text, &mut sink),
+                BblChunk::Newline => lines.newline(&mut sink),
+            }
+        }
+
+        out
+    }
+}
+
+/// An order in which to put the entries of the `.bbl` output, overriding the
+/// order chosen by the style.
+///
+/// Reordering happens after the style has run, so it works with any style,
+/// but it can only move whole entries around. Labels that the style has
+/// already computed, such as those of `alpha`-like styles, are kept as they
+/// are. Entries are compared using the values of their fields, so only fields
+/// that the style declares in its `ENTRY` command are available. Entries that
+/// compare equal keep the order chosen by the style.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum EntryOrder {
+    /// Keep the order chosen by the style. This is the default.
+    #[default]
+    Style,
+
+    /// The order in which the entries were first cited. Entries that are
+    /// only included because they were cross-referenced come last.
+    Citation,
+
+    /// By the last names of the authors (or, if there are none, the editors),
+    /// then by year, then by title.
+    AuthorYear,
+
+    /// By the value of the named field, which is matched case-insensitively.
+    /// Entries without the field come last.
+    Field(String),
+}
+
+impl FromStr for EntryOrder {
+    type Err = Error;
+
+    /// Parse an order from one of `style`, `citation`, `author-year`, or
+    /// `field:<name>`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "style" => Ok(EntryOrder::Style),
+            "citation" => Ok(EntryOrder::Citation),
+            "author-year" => Ok(EntryOrder::AuthorYear),
+            _ => match s.strip_prefix("field:") {
+                Some(name) if !name.is_empty() => Ok(EntryOrder::Field(name.to_owned())),
+                _ => bail!(
+                    "unrecognized entry order `{s}`; expected `style`, `citation`, \
+                     `author-year`, or `field:<name>`"
+                ),
+            },
+        }
+    }
+}
+
+impl fmt::Display for EntryOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EntryOrder::Style => f.write_str("style"),
+            EntryOrder::Citation => f.write_str("citation"),
+            EntryOrder::AuthorYear => f.write_str("author-year"),
+            EntryOrder::Field(name) => write!(f, "field:{name}"),
+        }
+    }
+}
+
+/// The key by which the block of an entry is sorted.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum SortKey {
+    /// The position of the entry in an existing order.
+    Rank(usize),
+
+    /// Normalized field values, compared in turn. Each is paired with a flag
+    /// that is set if the value is missing, so that missing values sort after
+    /// all others.
+    Values(Vec<(bool, Vec<u8>)>),
+}
+
+impl SortKey {
+    pub(crate) fn values(values: impl IntoIterator<Item = Option<Vec<u8>>>) -> Self {
+        SortKey::Values(
+            values
+                .into_iter()
+                .map(|v| (v.is_none(), v.unwrap_or_default()))
+                .collect(),
+        )
+    }
+}
+
+/// Normalize text for sorting, somewhat like the `purify$` builtin: control
+/// sequences and punctuation are dropped, runs of whitespace, hyphens, and
+/// ties become single spaces, and ASCII letters are lowercased. Non-ASCII
+/// bytes are kept as they are.
+pub(crate) fn sort_text(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+    let mut i = 0;
+
+    while i < text.len() {
+        let c = text[i];
+        i += 1;
+
+        if c == b'\\' {
+            while i < text.len() && text[i].is_ascii_alphabetic() {
+                i += 1;
+            }
+        } else if c.is_ascii_alphanumeric() || !c.is_ascii() {
+            out.push(c.to_ascii_lowercase());
+        } else if (is_white(c) || c == b'-' || c == b'~') && out.last().is_some_and(|&l| l != b' ')
+        {
+            out.push(b' ');
+        }
+    }
+
+    if out.last() == Some(&b' ') {
+        out.pop();
+    }
+
+    out
+}
+
+/// How the last names of [`last_names()`] are formatted.
+const LAST_NAME_SPEC: &[u8] = b"{vv{ } }{ll{ }}";
+
+/// Get the normalized last names from a list of names joined by `and`, as in
+/// an `author` field, separated by spaces. See [`last_names()`].
+pub(crate) fn last_names_sort_text(names: &[u8]) -> Vec<u8> {
+    last_names(names).join(&b' ')
+}
+
+/// Get the normalized last names from a list of names joined by `and`, as in
+/// an `author` field. Names are parsed as the `format.name$` builtin does,
+/// and each one's "von" and last parts are kept, as in the
+/// `sort.format.names` function of the standard styles. Names that normalize
+/// to nothing are skipped.
+pub(crate) fn last_names(names: &[u8]) -> Vec<Vec<u8>> {
+    // Problems with the names are for the style to report.
+    let mut problems = Vec::new();
+
+    (1..=num_names(names, &mut problems))
+        .map(|n| sort_text(&format_nth_name(names, n, LAST_NAME_SPEC, &mut problems)))
+        .filter(|last| !last.is_empty())
+        .collect()
+}
+
+/// The information needed to reorder the block of an entry.
+struct EntryInfo {
+    key: SortKey,
+    location: Option<EntryLocation>,
+}
+
+/// Accumulates a [`Bbl`] while the engine runs.
+#[derive(Default)]
+pub(crate) struct BblRecorder {
+    /// The blocks, each with its sort information if the output is being
+    /// reordered and the block belongs to an entry.
+    blocks: Vec<(BblBlock, Option<EntryInfo>)>,
+}
+
+impl BblRecorder {
+    pub(crate) fn start_block(
+        &mut self,
+        command: BblCommand,
+        function: String,
+        cite_key: Option<String>,
+    ) {
+        self.drop_empty_block();
+        self.blocks.push((
+            BblBlock {
+                command,
+                function,
+                cite_key,
+                sort_key: None,
+                label: None,
+                chunks: Vec::new(),
+            },
+            None,
+        ));
+    }
+
+    /// Set how the most recently started block is sorted, if the output is
+    /// being reordered.
+    pub(crate) fn set_entry(&mut self, key: SortKey, location: Option<EntryLocation>) {
+        if let Some((_, entry)) = self.blocks.last_mut() {
+            *entry = Some(EntryInfo { key, location });
+        }
+    }
+
+    /// Set the sort key and label that the style computed for the entry of
+    /// the most recently started block.
+    pub(crate) fn set_entry_values(&mut self, sort_key: String, label: Option<String>) {
+        if let Some((block, _)) = self.blocks.last_mut() {
+            block.sort_key = Some(sort_key);
+            block.label = label;
+        }
+    }
+
+    /// Record a `write$`. If *function* is `None`, the builtin was invoked
+    /// directly by the command, rather than from within a style function.
+    pub(crate) fn write(&mut self, text: &[u8], function: Option<String>) {
+        if let Some((block, _)) = self.blocks.last_mut() {
+            let function = function.unwrap_or_else(|| block.function.clone());
+            block.chunks.push(BblChunk::Text {
+                text: text.to_owned(),
+                function,
+            });
+        }
+    }
+
+    pub(crate) fn newline(&mut self) {
+        if let Some((block, _)) = self.blocks.last_mut() {
+            block.chunks.push(BblChunk::Newline);
+        }
+    }
+
+    fn drop_empty_block(&mut self) {
+        if self.blocks.last().is_some_and(|(b, _)| b.chunks.is_empty()) {
+            self.blocks.pop();
+        }
+    }
+
+    /// Sort the blocks that belong to entries by their keys.
+    ///
+    /// Only runs of consecutive entry blocks that were produced by the same
+    /// command and function are sorted, so that the output of different
+    /// commands is never interleaved.
+    pub(crate) fn reorder(&mut self) {
+        self.drop_empty_block();
+
+        let mut start = 0;
+
+        while start < self.blocks.len() {
+            let (first, entry) = &self.blocks[start];
+            let mut end = start + 1;
+
+            if entry.is_some() {
+                while self.blocks.get(end).is_some_and(|(b, e)| {
+                    e.is_some() && b.command == first.command && b.function == first.function
+                }) {
+                    end += 1;
+                }
+
+                self.blocks[start..end].sort_by(|(_, a), (_, b)| {
+                    a.as_ref().map(|e| &e.key).cmp(&b.as_ref().map(|e| &e.key))
+                });
+            }
+
+            start = end;
+        }
+    }
+
+    /// Iterate over the recorded blocks, along with the source locations of
+    /// those that belong to entries, if they were recorded.
+    pub(crate) fn blocks(&self) -> impl Iterator<Item = (&BblBlock, Option<&EntryLocation>)> {
+        self.blocks
+            .iter()
+            .map(|(block, entry)| (block, entry.as_ref().and_then(|e| e.location.as_ref())))
+    }
+
+    pub(crate) fn finish(mut self) -> Bbl {
+        self.drop_empty_block();
+
+        Bbl {
+            blocks: self.blocks.into_iter().map(|(block, _)| block).collect(),
+        }
+    }
+}
+
+fn is_white(c: u8) -> bool {
+    LexClass::of(c) == LexClass::Whitespace
+}
+
+/// The classic BibTeX line-breaking algorithm.
+///
+/// Text is accumulated into a line until a newline is requested. Lines longer
+/// than [`MAX_PRINT_LINE`] are broken at whitespace, with continuation lines
+/// indented by two spaces. Completed lines, including their line endings, are
+/// handed to a sink. As in the original, trailing whitespace is trimmed, lines
+/// consisting only of whitespace are dropped, and any partial line left at the
+/// end of the run is never written.
+#[derive(Default)]
+pub(crate) struct LineBreaker {
+    line: Vec<u8>,
+}
+
+impl LineBreaker {
+    pub(crate) fn write(&mut self, text: &[u8], sink: &mut impl FnMut(&[u8])) {
+        self.line.extend_from_slice(text);
+
+        // Lines are broken off the front of the buffer without moving what's
+        // left, which is only moved once at the end, so that breaking a long
+        // string up takes time in proportion to its length.
+        let mut start = 0;
+        let mut unbreakable_tail = false;
+        while self.line.len() - start > MAX_PRINT_LINE && !unbreakable_tail {
+            let line = &self.line[start..];
+            let end_ptr = line.len();
+            let mut out_offset = MAX_PRINT_LINE;
+            let mut break_pt_found = false;
+
+            while !is_white(line[out_offset]) && out_offset >= MIN_PRINT_LINE {
+                out_offset -= 1;
+            }
+
+            if out_offset == MIN_PRINT_LINE - 1 {
+                out_offset = MAX_PRINT_LINE + 1;
+                while out_offset < end_ptr && !is_white(line[out_offset]) {
+                    out_offset += 1;
+                }
+
+                if out_offset == end_ptr {
+                    unbreakable_tail = true;
+                } else {
+                    break_pt_found = true;
+                    while out_offset + 1 < end_ptr && is_white(line[out_offset + 1]) {
+                        out_offset += 1;
+                    }
+                }
+            } else {
+                break_pt_found = true;
+            }
+
+            if break_pt_found {
+                let break_pt = start + out_offset;
+                Self::break_line(&mut self.line[start..=break_pt], sink);
+                // The indentation of the continuation line is written over
+                // the end of the one just finished.
+                start = break_pt - 1;
+                self.line[start..start + 2].copy_from_slice(b"  ");
+            }
+        }
+
+        self.line.drain(..start);
+    }
+
+    /// Hand *line* to the sink without its last byte, the whitespace that it
+    /// was broken at, and without trailing whitespace, ending it in place.
+    fn break_line(line: &mut [u8], sink: &mut impl FnMut(&[u8])) {
+        let mut end = line.len() - 1;
+        while end > 0 && is_white(line[end - 1]) {
+            end -= 1;
+        }
+
+        if end > 0 {
+            line[end] = b'\n';
+            sink(&line[..=end]);
+        }
+    }
+
+    pub(crate) fn newline(&mut self, sink: &mut impl FnMut(&[u8])) {
+        if !self.line.is_empty() {
+            while self.line.last().is_some_and(|&c| is_white(c)) {
+                self.line.pop();
+            }
+
+            if self.line.is_empty() {
+                return;
+            }
+        }
+
+        self.line.push(b'\n');
+        sink(&self.line);
+        self.line.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn break_lines(ops: &[Option<&[u8]>]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut lines = LineBreaker::default();
+        let mut sink = |line: &[u8]| out.extend_from_slice(line);
+
+        for op in ops {
+            match op {
+                Some(text) => lines.write(text, &mut sink),
+                None => lines.newline(&mut sink),
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_short_lines() {
+        assert_eq!(
+            break_lines(&[Some(b"\\begin{thebibliography}{1}"), None, None]),
+            b"\\begin{thebibliography}{1}\n\n"
+        );
+        assert_eq!(break_lines(&[Some(b"trailing  \t"), None]), b"trailing\n");
+        assert_eq!(break_lines(&[Some(b"   "), None]), b"");
+        assert_eq!(break_lines(&[Some(b"unterminated")]), b"");
+    }
+
+    #[test]
+    fn test_long_lines() {
+        let words = "word ".repeat(20);
+        let out = break_lines(&[Some(words.trim_end().as_bytes()), None]);
+        let expected = format!(
+            "{}\n  {}\n",
+            "word ".repeat(16).trim_end(),
+            "word ".repeat(4).trim_end()
+        );
+        assert_eq!(out, expected.as_bytes());
+
+        let unbreakable = "x".repeat(100);
+        let out = break_lines(&[Some(unbreakable.as_bytes()), None]);
+        assert_eq!(out, format!("{unbreakable}\n").as_bytes());
+
+        // Breaks after an unbreakable stretch, and many breaks in one write.
+        let text = format!("{unbreakable}   {}", "word ".repeat(1000));
+        let out = break_lines(&[Some(text.trim_end().as_bytes()), None]);
+        let mut expected = format!("{unbreakable}\n");
+        for _ in 0..1000 / 15 {
+            expected += &format!("  {}\n", "word ".repeat(15).trim_end());
+        }
+        expected += &format!("  {}\n", "word ".repeat(1000 % 15).trim_end());
+        assert_eq!(String::from_utf8(out).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut rec = BblRecorder::default();
+        rec.start_block(BblCommand::Execute, "begin.bib".into(), None);
+        rec.write(b"\\begin{thebibliography}{1}", None);
+        rec.newline();
+        rec.start_block(BblCommand::Execute, "init.state.consts".into(), None);
+        rec.start_block(BblCommand::Iterate, "call.type$".into(), Some("key".into()));
+        rec.set_entry_values("knuth  donald".into(), Some("Knu84".into()));
+        rec.write(b"\\bibitem{key}", Some("output.bibitem".into()));
+        rec.newline();
+        rec.start_block(BblCommand::Execute, "end.bib".into(), None);
+        let bbl = rec.finish();
+
+        assert_eq!(bbl.blocks().len(), 2);
+        assert_eq!(bbl.blocks()[1].cite_key.as_deref(), Some("key"));
+        assert_eq!(bbl.blocks()[1].sort_key.as_deref(), Some("knuth  donald"));
+        assert_eq!(bbl.blocks()[1].label.as_deref(), Some("Knu84"));
+        assert_eq!(bbl.blocks()[0].sort_key, None);
+        assert_eq!(
+            bbl.blocks()[1].chunks[0],
+            BblChunk::Text {
+                text: b"\\bibitem{key}".to_vec(),
+                function: "output.bibitem".into(),
+            }
+        );
+        assert_eq!(
+            bbl.render(),
+            b"\\begin{thebibliography}{1}\n\\bibitem{key}\n"
+        );
+    }
+
+    #[test]
+    fn test_plain_text() {
+        let text = |t: &[u8]| BblChunk::Text {
+            text: t.to_vec(),
+            function: "output".into(),
+        };
+
+        let block = BblBlock {
+            command: BblCommand::Iterate,
+            function: "call.type$".into(),
+            cite_key: Some("knuth84".into()),
+            sort_key: Some("knuth  donald e    1984    texbook".into()),
+            label: Some("Knu84".into()),
+            chunks: vec![
+                text(b"\\bibitem[Kn{\\o}84]{knuth84}"),
+                BblChunk::Newline,
+                text(b"Donald~E. Knuth."),
+                BblChunk::Newline,
+                text(b"\\newblock {\\em The {\\TeX}book}."),
+                BblChunk::Newline,
+                text(b"\\newblock Addison-Wesley, 1984, pp. 1--10, \\& 100\\%."),
+                BblChunk::Newline,
+            ],
+        };
+
+        assert_eq!(
+            block.plain_text(),
+            "Donald E. Knuth. The TeXbook. Addison-Wesley, 1984, pp. 1–10, & 100%."
+        );
+    }
+
+    #[test]
+    fn test_entry_order() {
+        for s in ["style", "citation", "author-year", "field:date"] {
+            assert_eq!(s.parse::<EntryOrder>().unwrap().to_string(), s);
+        }
+
+        assert!("field:".parse::<EntryOrder>().is_err());
+        assert!("year".parse::<EntryOrder>().is_err());
+    }
+
+    #[test]
+    fn test_sort_text() {
+        assert_eq!(sort_text(b"The {\\TeX}book"), b"the book");
+        assert_eq!(sort_text(b"{\\\"A}ndersson"), b"andersson");
+        assert_eq!(sort_text(b"  Jean-Paul~Sartre. "), b"jean paul sartre");
+        assert_eq!(
+            last_names_sort_text(b"Donald E. Knuth and Bibby, Duane AND {Barnes and Noble}"),
+            b"knuth bibby barnes and noble"
+        );
+        assert_eq!(
+            last_names(b"Ludwig van Beethoven and de la Fontaine, Jean and Smith, Jr., John"),
+            [&b"van beethoven"[..], b"de la fontaine", b"smith"]
+        );
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut rec = BblRecorder::default();
+        rec.start_block(BblCommand::Execute, "begin.bib".into(), None);
+        rec.write(b"begin", None);
+        rec.newline();
+
+        for (key, rank) in [("b", 2), ("c", 3), ("a", 1)] {
+            rec.start_block(BblCommand::Iterate, "call.type$".into(), Some(key.into()));
+            rec.set_entry(SortKey::Rank(rank), None);
+            rec.write(key.as_bytes(), None);
+            rec.newline();
+        }
+
+        // A separate command isn't mixed in with the previous one.
+        rec.start_block(BblCommand::Iterate, "other".into(), Some("0".into()));
+        rec.set_entry(SortKey::Rank(0), None);
+        rec.write(b"0", None);
+        rec.newline();
+
+        rec.start_block(BblCommand::Execute, "end.bib".into(), None);
+        rec.write(b"end", None);
+        rec.newline();
+
+        rec.reorder();
+        assert_eq!(rec.finish().render(), b"begin\na\nb\nc\n0\nend\n");
+
+        let missing_last = [
+            SortKey::values([None]),
+            SortKey::values([Some(b"z".to_vec())]),
+        ];
+        assert!(missing_last[1] < missing_last[0]);
+    }
+}
@@ -0,0 +1,931 @@
+//! Reading `.bib` files without running the engine.
+//!
+//! [`BibReader`] splits the text of a `.bib` file into its commands, in the
+//! order that they appear: entries, `@string` macros, `@preamble`s, and
+//! `@comment`s, each with the [`Span`] of the input that it came from. It
+//! follows the same lexical rules as the engine, but doesn't expand macros,
+//! look anything up, or store anything: the items borrow from the input, and
+//! are only parsed when the iterator gets to them, so tools that just need to
+//! pick a few entries out of a large database can skip the rest cheaply.
+//!
+//! As in BibTeX, any text outside of commands is ignored, and after a syntax
+//! error the reader reports it and carries on from the next `@`.
+//!
+//! Tools that want the whole file can instead [`parse`] it into a
+//! [`Bibliography`], which owns its text, so that it can be kept after the
+//! input is gone.
+//!
+//! ```
+//! use tectonic_engine_bibtex::bib::{BibReader, ValuePart};
+//!
+//! let bib = br#"@article{knuth84, title = "Literate " # prog, year = 1984}"#;
+//! let entry = BibReader::new(bib).entries().next().unwrap().unwrap();
+//! assert_eq!(entry.key, b"knuth84");
+//! assert_eq!(entry.fields[0].value.parts[1], ValuePart::Macro(b"prog"));
+//! ```
+
+use std::{error, fmt};
+
+use crate::char_info::{IdClass, LexClass};
+
+/// A range of the input.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Span {
+    /// The byte offset of the start of the range.
+    pub start: usize,
+
+    /// The byte offset just past the end of the range.
+    pub end: usize,
+
+    /// The line on which the range starts, counting from 1.
+    pub line: usize,
+}
+
+/// A command read from a `.bib` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Item<'a> {
+    /// A database entry, such as `@article{...}`.
+    Entry(Entry<'a>),
+
+    /// A macro definition, made with `@string`.
+    Macro(Macro<'a>),
+
+    /// Text for the start of the `.bbl` file, given with `@preamble`.
+    Preamble(Preamble<'a>),
+
+    /// An `@comment`.
+    Comment(Comment<'a>),
+}
+
+impl Item<'_> {
+    /// The span of the whole command, from its `@` on.
+    pub fn span(&self) -> Span {
+        match self {
+            Item::Entry(e) => e.span,
+            Item::Macro(m) => m.span,
+            Item::Preamble(p) => p.span,
+            Item::Comment(c) => c.span,
+        }
+    }
+}
+
+/// A database entry.
+///
+/// The entry type and field names are as written; BibTeX treats them without
+/// regard to case.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Entry<'a> {
+    /// The type of the entry, such as `article`.
+    pub entry_type: &'a [u8],
+
+    /// The cite key of the entry.
+    pub key: &'a [u8],
+
+    /// The fields of the entry, in the order that they appear.
+    pub fields: Vec<Field<'a>>,
+
+    /// The span of the whole entry.
+    pub span: Span,
+}
+
+/// A field of an entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Field<'a> {
+    /// The name of the field.
+    pub name: &'a [u8],
+
+    /// The value of the field.
+    pub value: Value<'a>,
+
+    /// The span of the field, from its name to the end of its value.
+    pub span: Span,
+}
+
+/// A macro definition.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Macro<'a> {
+    /// The name of the macro.
+    pub name: &'a [u8],
+
+    /// The value of the macro.
+    pub value: Value<'a>,
+
+    /// The span of the whole command.
+    pub span: Span,
+}
+
+/// A preamble.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Preamble<'a> {
+    /// The text of the preamble.
+    pub value: Value<'a>,
+
+    /// The span of the whole command.
+    pub span: Span,
+}
+
+/// A comment.
+///
+/// BibTeX doesn't look for the end of an `@comment`; it just goes on to the
+/// next `@`. So the text of the comment is everything up to the next `@` or
+/// the end of the input, whether or not it's delimited.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Comment<'a> {
+    /// The text after the word `comment`.
+    pub text: &'a [u8],
+
+    /// The span of the whole command.
+    pub span: Span,
+}
+
+/// The value of a field, macro, or preamble: a list of parts that are
+/// concatenated with `#`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Value<'a> {
+    /// The parts of the value.
+    pub parts: Vec<ValuePart<'a>>,
+
+    /// The span of the value, from the start of its first part to the end of
+    /// its last.
+    pub span: Span,
+}
+
+/// A part of a [`Value`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValuePart<'a> {
+    /// A literal string, delimited by braces or double quotes. This is the
+    /// text between the delimiters, as written: BibTeX collapses runs of
+    /// whitespace in it to single spaces.
+    Literal(&'a [u8]),
+
+    /// A number, written without delimiters.
+    Number(&'a [u8]),
+
+    /// The name of a macro, to be replaced by its value.
+    Macro(&'a [u8]),
+}
+
+/// A syntax error in a `.bib` file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SyntaxError {
+    /// A description of the problem.
+    pub message: String,
+
+    /// Where the problem was found.
+    pub span: Span,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.span.line, self.message)
+    }
+}
+
+impl error::Error for SyntaxError {}
+
+/// The parsed contents of a `.bib` file, owning their text.
+///
+/// This is made by [`parse`]. Unlike the items of a [`BibReader`], it can
+/// outlive the input.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Bibliography {
+    /// The entries, in the order that they appear.
+    pub entries: Vec<OwnedEntry>,
+
+    /// The `@string` macro definitions, in the order that they appear.
+    pub macros: Vec<OwnedMacro>,
+
+    /// The values of the `@preamble`s, in the order that they appear.
+    pub preambles: Vec<OwnedValue>,
+
+    /// The `@comment`s, in the order that they appear. BibTeX ignores them,
+    /// but they're kept so that tools that rewrite a file can preserve them.
+    /// Their spans place them among the other items.
+    pub comments: Vec<OwnedComment>,
+
+    /// The syntax errors found. The commands that they were found in are
+    /// left out.
+    pub errors: Vec<SyntaxError>,
+}
+
+impl Bibliography {
+    /// Look up the entry with cite key *key*. Like BibTeX, this ignores case,
+    /// and if several entries have the key, it's the first.
+    pub fn entry(&self, key: &[u8]) -> Option<&OwnedEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.key.eq_ignore_ascii_case(key))
+    }
+
+    /// Look up the macro named *name*, ignoring case. If it's defined more
+    /// than once, this is the last definition.
+    pub fn macro_value(&self, name: &[u8]) -> Option<&OwnedValue> {
+        self.macros
+            .iter()
+            .rev()
+            .find(|m| m.name.eq_ignore_ascii_case(name))
+            .map(|m| &m.value)
+    }
+}
+
+/// Parse *input*, which is the text of a `.bib` file, into a
+/// [`Bibliography`].
+///
+/// This reads the whole input with a [`BibReader`], collecting the items and
+/// the syntax errors.
+///
+/// ```
+/// use tectonic_engine_bibtex::bib::{parse, OwnedValuePart};
+///
+/// let bib = parse(b"@string{tug = {TUGboat}} @article{k, journal = tug}");
+/// let journal = &bib.entry(b"K").unwrap().fields[0].value;
+/// assert_eq!(journal.parts, [OwnedValuePart::Macro(b"tug".to_vec())]);
+/// assert!(bib.macro_value(b"tug").is_some());
+/// ```
+pub fn parse(input: &[u8]) -> Bibliography {
+    let mut bib = Bibliography::default();
+
+    for item in BibReader::new(input) {
+        match item {
+            Ok(Item::Entry(e)) => bib.entries.push(e.into_owned()),
+            Ok(Item::Macro(m)) => bib.macros.push(m.into_owned()),
+            Ok(Item::Preamble(p)) => bib.preambles.push(p.value.into_owned()),
+            Ok(Item::Comment(c)) => bib.comments.push(c.into_owned()),
+            Err(e) => bib.errors.push(e),
+        }
+    }
+
+    bib
+}
+
+/// A database entry, owning its text. This is an owned [`Entry`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedEntry {
+    /// The type of the entry, such as `article`.
+    pub entry_type: Vec<u8>,
+
+    /// The cite key of the entry.
+    pub key: Vec<u8>,
+
+    /// The fields of the entry, in the order that they appear.
+    pub fields: Vec<OwnedField>,
+
+    /// The span of the whole entry.
+    pub span: Span,
+}
+
+impl OwnedEntry {
+    /// Look up the field named *name*, ignoring case. If there are several,
+    /// this is the first, which is the one that BibTeX uses.
+    pub fn field(&self, name: &[u8]) -> Option<&OwnedValue> {
+        self.fields
+            .iter()
+            .find(|f| f.name.eq_ignore_ascii_case(name))
+            .map(|f| &f.value)
+    }
+}
+
+/// A field of an entry, owning its text. This is an owned [`Field`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedField {
+    /// The name of the field.
+    pub name: Vec<u8>,
+
+    /// The value of the field.
+    pub value: OwnedValue,
+
+    /// The span of the field, from its name to the end of its value.
+    pub span: Span,
+}
+
+/// A macro definition, owning its text. This is an owned [`Macro`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedMacro {
+    /// The name of the macro.
+    pub name: Vec<u8>,
+
+    /// The value of the macro.
+    pub value: OwnedValue,
+
+    /// The span of the whole command.
+    pub span: Span,
+}
+
+/// A comment, owning its text. This is an owned [`Comment`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedComment {
+    /// The text after the word `comment`.
+    pub text: Vec<u8>,
+
+    /// The span of the whole command.
+    pub span: Span,
+}
+
+/// A value, owning its text. This is an owned [`Value`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct OwnedValue {
+    /// The parts of the value.
+    pub parts: Vec<OwnedValuePart>,
+
+    /// The span of the value.
+    pub span: Span,
+}
+
+/// A part of an [`OwnedValue`]. This is an owned [`ValuePart`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum OwnedValuePart {
+    /// A literal string, as written between its delimiters.
+    Literal(Vec<u8>),
+
+    /// A number, written without delimiters.
+    Number(Vec<u8>),
+
+    /// The name of a macro, to be replaced by its value.
+    Macro(Vec<u8>),
+}
+
+impl Entry<'_> {
+    /// Copy the text of the entry, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedEntry {
+        OwnedEntry {
+            entry_type: self.entry_type.to_vec(),
+            key: self.key.to_vec(),
+            fields: self.fields.into_iter().map(Field::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl Field<'_> {
+    /// Copy the text of the field, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedField {
+        OwnedField {
+            name: self.name.to_vec(),
+            value: self.value.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl Macro<'_> {
+    /// Copy the text of the macro, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedMacro {
+        OwnedMacro {
+            name: self.name.to_vec(),
+            value: self.value.into_owned(),
+            span: self.span,
+        }
+    }
+}
+
+impl Comment<'_> {
+    /// Copy the text of the comment, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedComment {
+        OwnedComment {
+            text: self.text.to_vec(),
+            span: self.span,
+        }
+    }
+}
+
+impl Value<'_> {
+    /// Copy the text of the value, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedValue {
+        OwnedValue {
+            parts: self.parts.into_iter().map(ValuePart::into_owned).collect(),
+            span: self.span,
+        }
+    }
+}
+
+impl ValuePart<'_> {
+    /// Copy the text of the part, so that it no longer borrows the input.
+    pub fn into_owned(self) -> OwnedValuePart {
+        match self {
+            ValuePart::Literal(t) => OwnedValuePart::Literal(t.to_vec()),
+            ValuePart::Number(t) => OwnedValuePart::Number(t.to_vec()),
+            ValuePart::Macro(t) => OwnedValuePart::Macro(t.to_vec()),
+        }
+    }
+}
+
+/// A reader of the commands in the text of a `.bib` file.
+///
+/// This is an iterator over the [`Item`]s in the input. Syntax errors are
+/// returned in line with the items, and reading continues after them.
+#[derive(Clone, Debug)]
+pub struct BibReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+
+    /// The line of `counted`, which is as far as newlines have been counted.
+    line: usize,
+    counted: usize,
+}
+
+type ReadResult<T> = Result<T, SyntaxError>;
+
+impl<'a> BibReader<'a> {
+    /// Read the commands in *input*, which is the text of a `.bib` file.
+    pub fn new(input: &'a [u8]) -> Self {
+        BibReader {
+            input,
+            pos: 0,
+            line: 1,
+            counted: 0,
+        }
+    }
+
+    /// Read the commands in *input*, which is the part of a `.bib` file that
+    /// starts on line *line*. Lines are counted from there, but byte offsets
+    /// from the start of *input*.
+    pub(crate) fn starting_at_line(input: &'a [u8], line: usize) -> Self {
+        BibReader {
+            line,
+            ..BibReader::new(input)
+        }
+    }
+
+    /// Only read the entries, skipping the other commands.
+    pub fn entries(self) -> impl Iterator<Item = ReadResult<Entry<'a>>> {
+        self.filter_map(|item| match item {
+            Ok(Item::Entry(e)) => Some(Ok(e)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+
+    /// Get the line of the byte at *pos*. Lines are counted as the reader
+    /// goes, so *pos* mustn't be before any position already asked about.
+    fn line_at(&mut self, pos: usize) -> usize {
+        self.line += self.input[self.counted..pos]
+            .iter()
+            .filter(|&&c| c == b'\n')
+            .count();
+        self.counted = pos;
+        self.line
+    }
+
+    fn error(&mut self, message: impl Into<String>) -> SyntaxError {
+        SyntaxError {
+            message: message.into(),
+            span: Span {
+                start: self.pos,
+                end: (self.pos + 1).min(self.input.len()),
+                line: self.line_at(self.pos),
+            },
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    /// Skip whitespace, which must be followed by something.
+    fn skip_white(&mut self) -> ReadResult<()> {
+        while let Some(c) = self.peek() {
+            if !is_white(c) {
+                return Ok(());
+            }
+
+            self.pos += 1;
+        }
+
+        Err(self.error("the file ended in the middle of a command"))
+    }
+
+    fn expect(&mut self, c: u8) -> ReadResult<()> {
+        if self.peek() != Some(c) {
+            return Err(self.error(format!("expected \"{}\"", c as char)));
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+
+    /// Read an identifier, which must be followed by whitespace or one of
+    /// *delims*.
+    fn identifier(&mut self, delims: &[u8], what: &str) -> ReadResult<&'a [u8]> {
+        let start = self.pos;
+        let len = identifier_len(&self.input[start..]);
+
+        if len == 0 {
+            return Err(self.error(format!("missing {what}")));
+        }
+
+        self.pos += len;
+
+        match self.peek() {
+            Some(c) if !is_white(c) && !delims.contains(&c) => {
+                Err(self.error(format!("\"{}\" immediately follows {what}", c as char)))
+            }
+            _ => Ok(&self.input[start..self.pos]),
+        }
+    }
+
+    /// Read an opening delimiter, returning the matching closing one.
+    fn open(&mut self) -> ReadResult<u8> {
+        let close = match self.peek() {
+            Some(b'{') => b'}',
+            Some(b'(') => b')',
+            _ => return Err(self.error("expected \"{\" or \"(\"")),
+        };
+
+        self.pos += 1;
+        Ok(close)
+    }
+
+    /// Read the command at the current position, which is an `@`.
+    fn command(&mut self) -> ReadResult<Item<'a>> {
+        let start = self.pos;
+        let line = self.line_at(start);
+        let span = |end| Span { start, end, line };
+        self.pos += 1;
+        self.skip_white()?;
+        let command = self.identifier(b"{(", "an entry type")?;
+
+        if command.eq_ignore_ascii_case(b"comment") {
+            let text_start = self.pos;
+            self.pos = find_at(self.input, self.pos);
+
+            return Ok(Item::Comment(Comment {
+                text: &self.input[text_start..self.pos],
+                span: span(self.pos),
+            }));
+        }
+
+        self.skip_white()?;
+        let close = self.open()?;
+        self.skip_white()?;
+
+        if command.eq_ignore_ascii_case(b"preamble") {
+            let value = self.value(close)?;
+            self.expect(close)?;
+
+            Ok(Item::Preamble(Preamble {
+                value,
+                span: span(self.pos),
+            }))
+        } else if command.eq_ignore_ascii_case(b"string") {
+            let name = self.identifier(b"=", "a string name")?;
+            self.skip_white()?;
+            self.expect(b'=')?;
+            self.skip_white()?;
+            let value = self.value(close)?;
+            self.expect(close)?;
+
+            Ok(Item::Macro(Macro {
+                name,
+                value,
+                span: span(self.pos),
+            }))
+        } else {
+            self.entry(command, close, span).map(Item::Entry)
+        }
+    }
+
+    /// Read the body of an entry, from just after its opening delimiter.
+    /// *span* gives the span of the entry from its end.
+    fn entry(
+        &mut self,
+        entry_type: &'a [u8],
+        close: u8,
+        span: impl Fn(usize) -> Span,
+    ) -> ReadResult<Entry<'a>> {
+        // With parentheses, the key may contain a right brace.
+        let key_start = self.pos;
+
+        while let Some(c) = self.peek() {
+            if is_white(c) || c == b',' || (c == b'}' && close == b'}') {
+                break;
+            }
+
+            self.pos += 1;
+        }
+
+        let key = &self.input[key_start..self.pos];
+        let mut fields = Vec::new();
+        self.skip_white()?;
+
+        while self.peek() != Some(close) {
+            if self.peek() != Some(b',') {
+                return Err(self.error(format!("expected \",\" or \"{}\"", close as char)));
+            }
+
+            self.pos += 1;
+            self.skip_white()?;
+
+            if self.peek() == Some(close) {
+                break;
+            }
+
+            let field_start = self.pos;
+            let field_line = self.line_at(field_start);
+            let name = self.identifier(b"=", "a field name")?;
+            self.skip_white()?;
+            self.expect(b'=')?;
+            self.skip_white()?;
+            let value = self.value(close)?;
+
+            fields.push(Field {
+                name,
+                span: Span {
+                    start: field_start,
+                    end: value.span.end,
+                    line: field_line,
+                },
+                value,
+            });
+        }
+
+        self.pos += 1;
+
+        Ok(Entry {
+            entry_type,
+            key,
+            fields,
+            span: span(self.pos),
+        })
+    }
+
+    /// Read a value, and the whitespace after it.
+    fn value(&mut self, close: u8) -> ReadResult<Value<'a>> {
+        let start = self.pos;
+        let line = self.line_at(start);
+        let mut parts = Vec::new();
+
+        let end = loop {
+            parts.push(self.value_part(close)?);
+            let end = self.pos;
+            self.skip_white()?;
+
+            if self.peek() != Some(b'#') {
+                break end;
+            }
+
+            self.pos += 1;
+            self.skip_white()?;
+        };
+
+        Ok(Value {
+            parts,
+            span: Span { start, end, line },
+        })
+    }
+
+    fn value_part(&mut self, close: u8) -> ReadResult<ValuePart<'a>> {
+        match self.peek() {
+            Some(b'{') => self.literal(b'}'),
+            Some(b'"') => self.literal(b'"'),
+
+            Some(b'0'..=b'9') => {
+                let start = self.pos;
+
+                while let Some(b'0'..=b'9') = self.peek() {
+                    self.pos += 1;
+                }
+
+                Ok(ValuePart::Number(&self.input[start..self.pos]))
+            }
+
+            _ => self
+                .identifier(&[b',', b'#', close], "a field part")
+                .map(ValuePart::Macro),
+        }
+    }
+
+    /// Read a literal string, starting at its opening delimiter, which is
+    /// closed by *delim* outside of any braces.
+    fn literal(&mut self, delim: u8) -> ReadResult<ValuePart<'a>> {
+        self.pos += 1;
+        let start = self.pos;
+        let mut depth = 0usize;
+
+        loop {
+            match self.peek() {
+                None => {
+                    return Err(self.error("the file ended in the middle of a string"));
+                }
+
+                Some(c) if c == delim && depth == 0 => break,
+                Some(b'{') => depth += 1,
+
+                Some(b'}') => {
+                    if depth == 0 {
+                        return Err(self.error("unbalanced braces"));
+                    }
+
+                    depth -= 1;
+                }
+
+                Some(_) => (),
+            }
+
+            self.pos += 1;
+        }
+
+        let text = &self.input[start..self.pos];
+        self.pos += 1;
+        Ok(ValuePart::Literal(text))
+    }
+}
+
+impl<'a> Iterator for BibReader<'a> {
+    type Item = ReadResult<Item<'a>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pos = find_at(self.input, self.pos);
+
+        if self.pos == self.input.len() {
+            return None;
+        }
+
+        // On errors, the position is left where the problem was found, so
+        // that the next command is looked for from there, as BibTeX does.
+        Some(self.command())
+    }
+}
+
+fn is_white(c: u8) -> bool {
+    c == b'\n' || LexClass::of(c) == LexClass::Whitespace
+}
+
+/// Find the next `@` at or after *pos*, or the end of the input.
+fn find_at(input: &[u8], pos: usize) -> usize {
+    input[pos..]
+        .iter()
+        .position(|&c| c == b'@')
+        .map_or(input.len(), |n| pos + n)
+}
+
+/// The length of the identifier at the start of *text*, which is zero if it
+/// doesn't start with one. Identifiers can't start with digits.
+pub(crate) fn identifier_len(text: &[u8]) -> usize {
+    match text.first() {
+        Some(&c) if LexClass::of(c) != LexClass::Numeric => text
+            .iter()
+            .take_while(|&&c| IdClass::of(c) == IdClass::LegalIdChar)
+            .count(),
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_commands() {
+        let bib = b"Some junk.\n\
+            @String{ jgg = \"J. Geophys.\" }\n\
+            @PREAMBLE( \"\\newcommand{\\noop}[1]{}\" )\n\
+            @comment{ ignored }\n\
+            @article(knuth:84},\n  Title = {The {\\TeX}book},\n  journal = jgg # \" Res.\",\n  year = 1984,\n)\n";
+
+        let items: Vec<_> = BibReader::new(bib).map(Result::unwrap).collect();
+        assert_eq!(items.len(), 4);
+
+        let Item::Macro(m) = &items[0] else {
+            panic!("expected a macro: {:?}", items[0]);
+        };
+        assert_eq!(m.name, b"jgg");
+        assert_eq!(m.value.parts, [ValuePart::Literal(b"J. Geophys.")]);
+        assert_eq!(m.span.line, 2);
+
+        let Item::Preamble(p) = &items[1] else {
+            panic!("expected a preamble: {:?}", items[1]);
+        };
+        assert_eq!(
+            p.value.parts,
+            [ValuePart::Literal(b"\\newcommand{\\noop}[1]{}")]
+        );
+
+        let Item::Comment(c) = &items[2] else {
+            panic!("expected a comment: {:?}", items[2]);
+        };
+        assert_eq!(c.text, b"{ ignored }\n");
+
+        let Item::Entry(e) = &items[3] else {
+            panic!("expected an entry: {:?}", items[3]);
+        };
+        assert_eq!(e.entry_type, b"article");
+        assert_eq!(e.key, b"knuth:84}");
+        assert_eq!(e.span.line, 5);
+        assert!(bib[e.span.start..e.span.end].starts_with(b"@article(knuth"));
+        assert_eq!(e.span.end, bib.len() - 1);
+
+        let names: Vec<_> = e.fields.iter().map(|f| f.name).collect();
+        assert_eq!(names, [&b"Title"[..], b"journal", b"year"]);
+        assert_eq!(
+            e.fields[0].value.parts,
+            [ValuePart::Literal(b"The {\\TeX}book")]
+        );
+        assert_eq!(
+            e.fields[1].value.parts,
+            [ValuePart::Macro(b"jgg"), ValuePart::Literal(b" Res.")]
+        );
+        assert_eq!(e.fields[1].span.line, 7);
+        assert_eq!(
+            &bib[e.fields[1].value.span.start..e.fields[1].value.span.end],
+            b"jgg # \" Res.\""
+        );
+        assert_eq!(e.fields[2].value.parts, [ValuePart::Number(b"1984")]);
+    }
+
+    #[test]
+    fn recover_from_errors() {
+        let bib = b"@book{a, title = {Unbalanced}}}\n\
+            @book{b, title = \"x\" year = 1}\n\
+            @book{c, 2title = {x}}\n\
+            @misc{d, note = \"}\"}\n\
+            @misc{e}\n\
+            @misc{f, title = {truncated";
+
+        let results: Vec<_> = BibReader::new(bib).collect();
+        let keys: Vec<_> = results
+            .iter()
+            .filter_map(|r| r.as_ref().ok())
+            .map(|item| match item {
+                Item::Entry(e) => e.key,
+                _ => panic!("unexpected item: {item:?}"),
+            })
+            .collect();
+        assert_eq!(keys, [&b"a"[..], b"e"]);
+
+        let errors: Vec<_> = results
+            .iter()
+            .filter_map(|r| r.as_ref().err())
+            .map(|e| (e.span.line, e.message.as_str()))
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                (2, "expected \",\" or \"}\""),
+                (3, "missing a field name"),
+                (4, "unbalanced braces"),
+                (6, "the file ended in the middle of a string"),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_owned() {
+        let bib = {
+            let text = b"@STRING{ms = {Microsoft}}\n\
+                @preamble{\"\\def\\x{}\"}\n\
+                @comment{ @misc{junk} }\n\
+                @book{Gates:99, Publisher = ms # { Press}, year = 1999, year = 2000}\n\
+                @book{bad, title = {x}\n\
+                @string{ms = {MS}}\n"
+                .to_vec();
+            parse(&text)
+        };
+
+        assert_eq!(bib.preambles.len(), 1);
+        assert_eq!(
+            bib.preambles[0].parts,
+            [OwnedValuePart::Literal(b"\\def\\x{}".to_vec())]
+        );
+        assert_eq!(bib.macros.len(), 2);
+        assert_eq!(
+            bib.macro_value(b"MS").unwrap().parts,
+            [OwnedValuePart::Literal(b"MS".to_vec())]
+        );
+
+        assert_eq!(bib.comments.len(), 1);
+        assert_eq!(bib.comments[0].text, b"{ ");
+        assert_eq!(bib.comments[0].span.line, 3);
+
+        // The comment ends at the next `@`, so its "entry" is read.
+        let keys: Vec<_> = bib.entries.iter().map(|e| e.key.as_slice()).collect();
+        assert_eq!(keys, [&b"junk"[..], b"Gates:99"]);
+
+        let entry = bib.entry(b"gates:99").unwrap();
+        assert_eq!(entry.entry_type, b"book");
+        assert_eq!(entry.span.line, 4);
+        assert_eq!(
+            entry.field(b"publisher").unwrap().parts,
+            [
+                OwnedValuePart::Macro(b"ms".to_vec()),
+                OwnedValuePart::Literal(b" Press".to_vec()),
+            ]
+        );
+        assert_eq!(
+            entry.field(b"year").unwrap().parts,
+            [OwnedValuePart::Number(b"1999".to_vec())]
+        );
+        assert!(bib.entry(b"bad").is_none());
+
+        assert_eq!(bib.errors.len(), 1);
+        assert_eq!(bib.errors[0].span.line, 6);
+    }
+
+    #[test]
+    fn identifiers() {
+        assert_eq!(identifier_len(b"article{"), 7);
+        assert_eq!(identifier_len(b"a-b.c:d e"), 7);
+        assert_eq!(identifier_len(b"2nd"), 0);
+        assert_eq!(identifier_len(b"{x"), 0);
+        assert_eq!(identifier_len(b""), 0);
+    }
+}
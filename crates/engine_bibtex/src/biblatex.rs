@@ -0,0 +1,1686 @@
+//! Writing bibliography data for biblatex.
+//!
+//! If enabled with
+//! [`BibtexEngine::biblatex_output()`](crate::BibtexEngine::biblatex_output),
+//! the engine doesn't run a style. Instead, it reads the cited entries from
+//! the databases named in the `.aux` file and writes them to the `.bbl` file
+//! in the data format that biblatex reads from the output of biber: a record
+//! for each entry, with its names, lists, and fields, inside a data list
+//! sorted as the document's reference context asks. This lets biblatex
+//! documents be processed without an external program. Every entry of the
+//! databases is read and stored, unless
+//! [`BibtexEngine::low_memory()`](crate::BibtexEngine::low_memory) is enabled,
+//! in which case only the entries that are needed are.
+//!
+//! Only the commonly used parts of biber's processing are reproduced. Entry
+//! types and fields are mapped from their BibTeX names to biblatex's, dates
+//! are split into their parts, fields are inherited from `@xdata` entries, and
+//! one level of `crossref` inheritance is resolved. The entries are sorted by
+//! the `none`, `nty`, `nyt`, `ynt`, or `ydnt` scheme named in the reference
+//! context, falling back to `nty`, and the hashes, `sortinit`, and
+//! `extradate` fields that the standard styles use are computed. Fields that
+//! only some styles need, such as `labelalpha` and the `uniquename`
+//! information, are not, and options given in the document are ignored.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt::Write as _,
+    io::Write as _,
+};
+
+use crate::{
+    bbl::sort_text,
+    bib::{BibReader, Item, Span, Value, ValuePart},
+    char_info::LexClass,
+    cite::{Citation, CitationReport, EntryLocation, RepeatedEntry},
+    collate::Locale,
+    exec::ControlSeq,
+    log::{
+        bad_xdata_reference_warning, cited_xdata_warning, has_database_extension,
+        print_missing_entry, xdata_cycle_warning,
+    },
+    peekable::PeekableInput,
+    Bibtex, BibtexError, GlobalItems,
+};
+
+/// The reference context used if the `.aux` file doesn't name one. This is
+/// biblatex's default.
+const DEFAULT_REFCONTEXT: &str = "nty/global//global/global";
+
+/// The key of the entry that biblatex cites to pass its options to its own
+/// BibTeX style, which isn't needed here.
+const CONTROL_KEY: &[u8] = b"biblatex-control";
+
+/// Fields holding lists of names.
+const NAME_FIELDS: &[&str] = &[
+    "afterword",
+    "annotator",
+    "author",
+    "bookauthor",
+    "commentator",
+    "editor",
+    "editora",
+    "editorb",
+    "editorc",
+    "foreword",
+    "holder",
+    "introduction",
+    "shortauthor",
+    "shorteditor",
+    "translator",
+];
+
+/// Fields holding lists of literals.
+const LIST_FIELDS: &[&str] = &[
+    "institution",
+    "language",
+    "location",
+    "organization",
+    "origlocation",
+    "origpublisher",
+    "publisher",
+];
+
+/// Fields whose values are written verbatim.
+const VERBATIM_FIELDS: &[&str] = &["doi", "eprint", "file", "url"];
+
+/// Fields holding the keys of other entries.
+const KEY_FIELDS: &[&str] = &["crossref", "xref"];
+
+/// Entry types that other entries are contained in, whose titles become the
+/// `booktitle` of the entries that inherit from them.
+const CONTAINER_TYPES: &[&str] = &[
+    "book",
+    "collection",
+    "mvbook",
+    "mvcollection",
+    "mvproceedings",
+    "proceedings",
+    "reference",
+];
+
+/// Entry types contained in other entries.
+const CONTAINED_TYPES: &[&str] = &[
+    "bookinbook",
+    "inbook",
+    "incollection",
+    "inproceedings",
+    "inreference",
+    "suppbook",
+    "suppcollection",
+];
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+/// The names that BibTeX entry types and fields have in biblatex's data model,
+/// along with the `type` field implied by an entry type.
+fn alias_type(entry_type: &str) -> (&str, Option<&'static str>) {
+    match entry_type {
+        "conference" => ("inproceedings", None),
+        "electronic" | "www" => ("online", None),
+        "mastersthesis" => ("thesis", Some("mathesis")),
+        "phdthesis" => ("thesis", Some("phdthesis")),
+        "techreport" => ("report", Some("techreport")),
+        t => (t, None),
+    }
+}
+
+fn alias_field(name: &str) -> &str {
+    match name {
+        "address" => "location",
+        "annote" => "annotation",
+        "archiveprefix" => "eprinttype",
+        "journal" => "journaltitle",
+        "key" => "sortkey",
+        "pdf" => "file",
+        "primaryclass" => "eprintclass",
+        "school" => "institution",
+        n => n,
+    }
+}
+
+/// An entry as read from a database.
+struct DbEntry {
+    key: String,
+    entry_type: String,
+    fields: Vec<(String, Vec<u8>)>,
+    location: EntryLocation,
+}
+
+/// Where the first pass of a low-memory read found an entry.
+struct IndexedEntry {
+    key: String,
+    /// The number of the database file containing the entry, from 0.
+    file: usize,
+    span: Span,
+    /// How many macros had been defined before the entry.
+    macros: usize,
+    /// The lowercase keys of the entries named in its `crossref` and `xdata`
+    /// fields.
+    parents: Vec<String>,
+}
+
+/// The entries of the databases as found by the first pass of a low-memory
+/// read, which doesn't store their fields, and the macros in the order that
+/// they're defined.
+#[derive(Default)]
+struct DbIndex {
+    entries: Vec<IndexedEntry>,
+    /// The indices of the entries, by lowercase key.
+    by_key: HashMap<String, usize>,
+    macros: Vec<(Vec<u8>, Vec<u8>)>,
+    /// The number of the database file being read.
+    file: usize,
+    /// The names of the database files read so far.
+    file_names: Vec<String>,
+}
+
+impl DbIndex {
+    /// Get which of the entries are needed for the cited keys *cited*: the
+    /// entries themselves, and those that they inherit from, directly or
+    /// indirectly.
+    fn needed(&self, cited: impl IntoIterator<Item = String>) -> Vec<bool> {
+        let mut needed = vec![false; self.entries.len()];
+        let mut queue: Vec<usize> = cited
+            .into_iter()
+            .filter_map(|key| self.by_key.get(&key).copied())
+            .collect();
+
+        while let Some(i) = queue.pop() {
+            if !needed[i] {
+                needed[i] = true;
+                let parents = self.entries[i].parents.iter();
+                queue.extend(parents.filter_map(|key| self.by_key.get(key).copied()));
+            }
+        }
+
+        needed
+    }
+}
+
+/// The entries, macros, and preambles of the databases.
+#[derive(Default)]
+struct Database {
+    entries: Vec<DbEntry>,
+    /// The indices of the entries, by lowercase key.
+    index: HashMap<String, usize>,
+    macros: HashMap<Vec<u8>, Vec<u8>>,
+    preamble: Vec<u8>,
+    /// The byte offset in its file of the text being read.
+    origin: usize,
+}
+
+impl Database {
+    fn new() -> Self {
+        let mut db = Database::default();
+
+        for (num, month) in MONTHS.iter().enumerate() {
+            db.macros.insert(
+                month.as_bytes().to_vec(),
+                (num + 1).to_string().into_bytes(),
+            );
+        }
+
+        db
+    }
+
+    fn find(&self, key: &[u8]) -> Option<&DbEntry> {
+        let key = String::from_utf8_lossy(key).to_lowercase();
+        self.index.get(&key).map(|&i| &self.entries[i])
+    }
+
+    /// Tell the diagnostic recorder that the problem being reported is at
+    /// *span* of the text being read from database file *file*.
+    fn locate(&self, ctx: &mut Bibtex<'_, '_>, file: &str, span: Span) {
+        ctx.diagnostics.locate(
+            file.to_owned(),
+            Some(span.line as u32),
+            Some(self.origin + span.start..self.origin + span.end),
+        );
+    }
+
+    /// Read the text of a database file, reporting problems in the log. If
+    /// *index* is given, the entries are added to it instead of being stored.
+    fn read(
+        &mut self,
+        ctx: &mut Bibtex<'_, '_>,
+        file: &str,
+        reader: BibReader<'_>,
+        mut index: Option<&mut DbIndex>,
+    ) {
+        for item in reader {
+            let item = match item {
+                Ok(item) => item,
+                Err(e) => {
+                    self.locate(ctx, file, e.span);
+                    ctx.write_logs(&e.message);
+                    ctx.diagnostics.end_message();
+                    ctx.write_logs(&format!(
+                        "---line {} of file {}\nI'm skipping whatever remains of this command or entry\n",
+                        e.span.line, file
+                    ));
+                    ctx.mark_error();
+                    continue;
+                }
+            };
+
+            match item {
+                Item::Entry(e) => {
+                    let key = String::from_utf8_lossy(e.key).into_owned();
+                    let lc_key = key.to_lowercase();
+                    let repeated = match &index {
+                        Some(index) => index.by_key.get(&lc_key).map(|&i| {
+                            let first = &index.entries[i];
+                            EntryLocation {
+                                file: index.file_names[first.file].clone(),
+                                line: first.span.line as u32,
+                            }
+                        }),
+                        None => self
+                            .index
+                            .get(&lc_key)
+                            .map(|&i| self.entries[i].location.clone()),
+                    };
+
+                    if let Some(first) = repeated {
+                        ctx.repeated_entries.push(RepeatedEntry {
+                            cite_key: key,
+                            location: EntryLocation {
+                                file: file.to_owned(),
+                                line: e.span.line as u32,
+                            },
+                            first: Some(first),
+                        });
+
+                        self.locate(ctx, file, e.span);
+                        ctx.diagnostics.set_cite_key(e.key);
+                        ctx.write_logs("Repeated entry");
+                        ctx.diagnostics.end_message();
+                        ctx.write_logs(&format!(
+                            "---line {} of file {}\nI'm skipping whatever remains of this entry\n",
+                            e.span.line, file
+                        ));
+                        ctx.mark_error();
+                        continue;
+                    }
+
+                    if let Some(index) = &mut index {
+                        let mut parents = Vec::new();
+                        let mut seen = HashSet::new();
+
+                        for field in &e.fields {
+                            let name = String::from_utf8_lossy(field.name).to_lowercase();
+
+                            if (name == "crossref" || name == "xdata") && seen.insert(name) {
+                                let value = self.expand_with(&field.value, |_| ());
+                                let keys = value.split(|&c| c == b',').map(trim_white);
+                                parents.extend(
+                                    keys.filter(|k| !k.is_empty())
+                                        .map(|k| String::from_utf8_lossy(k).to_lowercase()),
+                                );
+                            }
+                        }
+
+                        index.by_key.insert(key.to_lowercase(), index.entries.len());
+                        index.entries.push(IndexedEntry {
+                            key,
+                            file: index.file,
+                            span: e.span,
+                            macros: index.macros.len(),
+                            parents,
+                        });
+                        continue;
+                    }
+
+                    let mut fields: Vec<(String, Vec<u8>)> = Vec::new();
+
+                    for field in &e.fields {
+                        let name = String::from_utf8_lossy(field.name).to_lowercase();
+
+                        if fields.iter().any(|(n, _)| *n == name) {
+                            self.locate(ctx, file, field.span);
+                            ctx.diagnostics.set_cite_key(e.key);
+                            ctx.write_logs(&format!(
+                                "Warning--I'm ignoring {key}'s extra \"{name}\" field\n"
+                            ));
+                            ctx.diagnostics.end_message();
+                            ctx.write_logs(&format!(
+                                "--line {} of file {}\n",
+                                field.span.line, file
+                            ));
+                            ctx.mark_warning();
+                            continue;
+                        }
+
+                        let value = self.expand(ctx, &field.value, file);
+                        fields.push((name, value));
+                    }
+
+                    self.index.insert(key.to_lowercase(), self.entries.len());
+                    self.entries.push(DbEntry {
+                        key,
+                        entry_type: String::from_utf8_lossy(e.entry_type).to_lowercase(),
+                        fields,
+                        location: EntryLocation {
+                            file: file.to_owned(),
+                            line: e.span.line as u32,
+                        },
+                    });
+                }
+
+                Item::Macro(m) => {
+                    let value = self.expand(ctx, &m.value, file);
+
+                    if let Some(index) = &mut index {
+                        index
+                            .macros
+                            .push((m.name.to_ascii_lowercase(), value.clone()));
+                    }
+
+                    self.macros.insert(m.name.to_ascii_lowercase(), value);
+                }
+
+                Item::Preamble(p) => {
+                    let value = self.expand(ctx, &p.value, file);
+                    self.preamble.extend_from_slice(&value);
+                }
+
+                Item::Comment(_) => (),
+            }
+        }
+    }
+
+    /// Get the text of a value, expanding macros and collapsing whitespace.
+    fn expand(&self, ctx: &mut Bibtex<'_, '_>, value: &Value<'_>, file: &str) -> Vec<u8> {
+        self.expand_with(value, |name| {
+            self.locate(ctx, file, value.span);
+            ctx.write_logs(&format!(
+                "Warning--string name \"{}\" is undefined\n",
+                String::from_utf8_lossy(name)
+            ));
+            ctx.diagnostics.end_message();
+            ctx.write_logs(&format!("--line {} of file {}\n", value.span.line, file));
+            ctx.mark_warning();
+        })
+    }
+
+    /// Get the text of a value, calling *undefined* with the name of each
+    /// macro that isn't defined.
+    fn expand_with(&self, value: &Value<'_>, mut undefined: impl FnMut(&[u8])) -> Vec<u8> {
+        let mut text = Vec::new();
+
+        for part in &value.parts {
+            match part {
+                ValuePart::Literal(t) | ValuePart::Number(t) => text.extend_from_slice(t),
+
+                ValuePart::Macro(name) => match self.macros.get(&name.to_ascii_lowercase()) {
+                    Some(v) => text.extend_from_slice(v),
+                    None => undefined(name),
+                },
+            }
+        }
+
+        collapse_white(&text)
+    }
+
+    /// Get the record of an entry, with the fields that it inherits from
+    /// `@xdata` entries and through its `crossref` field.
+    fn record(&self, ctx: &mut Bibtex<'_, '_>, entry: &DbEntry) -> Record {
+        let mut record = self.xdata_record(ctx, entry, &mut Vec::new());
+
+        let Some(parent_key) = record.fields.get("crossref").cloned() else {
+            return record;
+        };
+
+        let Some(parent) = self.find(&parent_key) else {
+            ctx.diagnostics.set_cite_key(entry.key.as_bytes());
+            ctx.write_logs(&format!(
+                "Warning--A bad cross reference---entry \"{}\"\nrefers to entry \"{}\", which doesn't exist\n",
+                entry.key,
+                String::from_utf8_lossy(&parent_key)
+            ));
+            ctx.mark_warning();
+            return record;
+        };
+
+        let parent = self.xdata_record(ctx, parent, &mut Vec::new());
+        let titles_move = CONTAINER_TYPES.contains(&parent.entry_type.as_str())
+            && CONTAINED_TYPES.contains(&record.entry_type.as_str());
+
+        for (name, value) in parent.fields {
+            let name = match name.as_str() {
+                "crossref" | "xref" | "ids" | "sortkey" => continue,
+                "title" | "subtitle" | "titleaddon" if titles_move => format!("book{name}"),
+                _ => name,
+            };
+
+            record.fields.entry(name).or_insert(value);
+        }
+
+        record
+    }
+
+    /// Get the record of an entry, with the fields that it inherits from the
+    /// `@xdata` entries named in its `xdata` field, and from those that they
+    /// name in turn. *path* holds the keys of the entries whose records are
+    /// being built, which inherit from this one.
+    fn xdata_record(
+        &self,
+        ctx: &mut Bibtex<'_, '_>,
+        entry: &DbEntry,
+        path: &mut Vec<String>,
+    ) -> Record {
+        let mut record = Record::new(entry);
+
+        let Some(value) = record.fields.remove("xdata") else {
+            return record;
+        };
+
+        path.push(entry.key.clone());
+
+        for key in value.split(|&c| c == b',').map(trim_white) {
+            if key.is_empty() {
+                continue;
+            }
+
+            let Some(parent) = self.find(key) else {
+                bad_xdata_reference_warning(ctx, entry.key.as_bytes(), key, "which doesn't exist");
+                continue;
+            };
+
+            if parent.entry_type != "xdata" {
+                bad_xdata_reference_warning(
+                    ctx,
+                    entry.key.as_bytes(),
+                    key,
+                    "which isn't an @xdata entry",
+                );
+                continue;
+            }
+
+            if let Some(start) = path
+                .iter()
+                .position(|k| k.eq_ignore_ascii_case(&parent.key))
+            {
+                let cycle: Vec<&[u8]> = path[start..].iter().map(|k| k.as_bytes()).collect();
+                xdata_cycle_warning(ctx, &cycle);
+                continue;
+            }
+
+            for (name, value) in self.xdata_record(ctx, parent, path).fields {
+                record.fields.entry(name).or_insert(value);
+            }
+        }
+
+        path.pop();
+        record
+    }
+}
+
+/// An entry as it's written, with its type and fields named as in biblatex.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Record {
+    key: String,
+    entry_type: String,
+    fields: BTreeMap<String, Vec<u8>>,
+}
+
+impl Record {
+    fn new(entry: &DbEntry) -> Self {
+        let (entry_type, implied_type) = alias_type(&entry.entry_type);
+        let mut fields = BTreeMap::new();
+
+        for (name, value) in &entry.fields {
+            fields
+                .entry(alias_field(name).to_owned())
+                .or_insert_with(|| value.clone());
+        }
+
+        if let Some(t) = implied_type {
+            fields
+                .entry("type".to_owned())
+                .or_insert_with(|| t.as_bytes().to_vec());
+        }
+
+        convert_dates(&mut fields);
+
+        Record {
+            key: entry.key.clone(),
+            entry_type: entry_type.to_owned(),
+            fields,
+        }
+    }
+
+    /// The field whose names label the entry.
+    fn label_name(&self) -> Option<&'static str> {
+        ["author", "editor", "translator"]
+            .into_iter()
+            .find(|f| self.fields.contains_key(*f))
+    }
+
+    fn names(&self, field: &str) -> (Vec<Name<'_>>, bool) {
+        let Some(value) = self.fields.get(field) else {
+            return (Vec::new(), false);
+        };
+
+        let (items, more) = split_list(value);
+        (items.into_iter().filter_map(Name::parse).collect(), more)
+    }
+}
+
+/// Replace a `date` field with its parts, and give the month as a number.
+fn convert_dates(fields: &mut BTreeMap<String, Vec<u8>>) {
+    if let Some(date) = fields.get("date") {
+        let text = String::from_utf8_lossy(date).into_owned();
+        let mut ends = text.splitn(2, '/');
+        let start = ends.next().and_then(parse_date);
+        let end = ends.next().map(|e| (e.is_empty(), parse_date(e)));
+
+        if let Some(start) = start {
+            if end.is_none_or(|(open, end)| open || end.is_some()) {
+                fields.remove("date");
+                fields.remove("month");
+                insert_date(fields, "", start);
+
+                match end {
+                    Some((true, _)) => {
+                        fields.insert("endyear".to_owned(), Vec::new());
+                    }
+                    Some((false, Some(end))) => insert_date(fields, "end", end),
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    if let Some(month) = fields.get("month") {
+        let text = String::from_utf8_lossy(month).to_lowercase();
+        let number = text
+            .parse::<u32>()
+            .ok()
+            .filter(|m| (1..=12).contains(m))
+            .or_else(|| {
+                MONTHS
+                    .iter()
+                    .position(|m| text.len() >= 3 && text.starts_with(m))
+                    .map(|i| i as u32 + 1)
+            });
+
+        if let Some(number) = number {
+            fields.insert("month".to_owned(), number.to_string().into_bytes());
+        }
+    }
+}
+
+fn insert_date(fields: &mut BTreeMap<String, Vec<u8>>, prefix: &str, (y, m, d): Date) {
+    fields.insert(format!("{prefix}year"), y.to_string().into_bytes());
+
+    if let Some(m) = m {
+        fields.insert(format!("{prefix}month"), m.to_string().into_bytes());
+    }
+
+    if let Some(d) = d {
+        fields.insert(format!("{prefix}day"), d.to_string().into_bytes());
+    }
+}
+
+pub(crate) type Date = (i32, Option<u32>, Option<u32>);
+
+/// Parse an ISO 8601 date of the forms `YYYY`, `YYYY-MM`, and `YYYY-MM-DD`.
+pub(crate) fn parse_date(text: &str) -> Option<Date> {
+    let mut parts = text.trim().split('-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next().map(str::parse).transpose().ok()?;
+    let day = parts.next().map(str::parse).transpose().ok()?;
+
+    if parts.next().is_some()
+        || month.is_some_and(|m| !(1..=12).contains(&m))
+        || day.is_some_and(|d| !(1..=31).contains(&d))
+    {
+        return None;
+    }
+
+    Some((year, month, day))
+}
+
+/// A name, split into its parts as BibTeX does. Each part is a list of
+/// words.
+#[derive(Debug, Default, Eq, PartialEq)]
+struct Name<'a> {
+    given: Vec<&'a [u8]>,
+    prefix: Vec<&'a [u8]>,
+    family: Vec<&'a [u8]>,
+    suffix: Vec<&'a [u8]>,
+}
+
+impl<'a> Name<'a> {
+    /// Parse a name written as `First von Last`, `von Last, First`, or
+    /// `von Last, Jr, First`.
+    fn parse(text: &'a [u8]) -> Option<Self> {
+        let parts: Vec<Vec<&[u8]>> = split_top_level(text, |c| c == b',')
+            .into_iter()
+            .map(|p| split_top_level(p, is_white))
+            .collect();
+
+        let words = parts.first().filter(|w| !w.is_empty())?;
+        let n = words.len();
+        let mut name = Name::default();
+
+        if parts.len() == 1 {
+            // The von part runs from the first lowercase word to the last
+            // one, but never includes the last word.
+            match (0..n - 1).find(|&i| starts_lowercase(words[i])) {
+                Some(start) => {
+                    let end = (start..n - 1)
+                        .rev()
+                        .find(|&i| starts_lowercase(words[i]))
+                        .unwrap_or(start)
+                        + 1;
+                    name.given = words[..start].to_vec();
+                    name.prefix = words[start..end].to_vec();
+                    name.family = words[end..].to_vec();
+                }
+                None => {
+                    name.given = words[..n - 1].to_vec();
+                    name.family = words[n - 1..].to_vec();
+                }
+            }
+        } else {
+            let end = if starts_lowercase(words[0]) {
+                (0..n - 1)
+                    .rev()
+                    .find(|&i| starts_lowercase(words[i]))
+                    .map_or(0, |i| i + 1)
+            } else {
+                0
+            };
+
+            name.prefix = words[..end].to_vec();
+            name.family = words[end..].to_vec();
+
+            if parts.len() == 2 {
+                name.given = parts[1].clone();
+            } else {
+                name.suffix = parts[1].clone();
+                name.given = parts[2..].concat();
+            }
+        }
+
+        Some(name)
+    }
+
+    fn parts(&self) -> [(&'static str, &[&'a [u8]]); 4] {
+        [
+            ("family", &self.family),
+            ("given", &self.given),
+            ("prefix", &self.prefix),
+            ("suffix", &self.suffix),
+        ]
+    }
+
+    /// The full text of the name, for hashing.
+    fn full(&self) -> Vec<u8> {
+        let mut text = Vec::new();
+
+        for (_, words) in self.parts() {
+            text.extend_from_slice(&words.join(&b' '));
+            text.push(b'\0');
+        }
+
+        text
+    }
+
+    /// The text by which the name sorts.
+    fn sort_text(&self) -> Vec<u8> {
+        let mut text = [&self.family[..], &self.given, &self.prefix, &self.suffix]
+            .concat()
+            .join(&b' ');
+        text.push(b' ');
+        sort_text(&text)
+    }
+}
+
+/// Split *text* at the bytes matching *sep* outside of braces, dropping
+/// empty pieces.
+fn split_top_level(text: &[u8], sep: impl Fn(u8) -> bool) -> Vec<&[u8]> {
+    let mut pieces = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (i, &c) in text.iter().enumerate() {
+        match c {
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            c if depth == 0 && sep(c) => {
+                pieces.push(&text[start..i]);
+                start = i + 1;
+            }
+            _ => (),
+        }
+    }
+
+    pieces.push(&text[start..]);
+    pieces
+        .into_iter()
+        .map(trim_white)
+        .filter(|p| !p.is_empty())
+        .collect()
+}
+
+/// Split a list of names or literals at each `and` outside of braces. Also
+/// returns whether the list ends with `others`, meaning that it's truncated.
+fn split_list(text: &[u8]) -> (Vec<&[u8]>, bool) {
+    let words = split_top_level(text, is_white);
+    let mut items = Vec::new();
+    let mut first: Option<&[u8]> = None;
+    let mut last: &[u8] = &[];
+
+    let mut push = |first: Option<&[u8]>, last: &[u8]| {
+        if let Some(first) = first {
+            // Both are slices of *text*, so the item is the span between them.
+            let start = first.as_ptr() as usize - text.as_ptr() as usize;
+            let end = last.as_ptr() as usize - text.as_ptr() as usize + last.len();
+            items.push(&text[start..end]);
+        }
+    };
+
+    for word in words {
+        if word.eq_ignore_ascii_case(b"and") {
+            push(first.take(), last);
+        } else {
+            first.get_or_insert(word);
+            last = word;
+        }
+    }
+
+    push(first, last);
+
+    let more = items
+        .last()
+        .is_some_and(|i| i.eq_ignore_ascii_case(b"others"));
+
+    if more {
+        items.pop();
+    }
+
+    (items, more)
+}
+
+/// Whether a word of a name starts with a lowercase letter, which makes it
+/// part of the von part. As in BibTeX, letters inside braces don't count,
+/// except in special characters such as `{\"o}`.
+fn starts_lowercase(word: &[u8]) -> bool {
+    let mut i = 0;
+    let mut depth = 0usize;
+
+    while let Some(&c) = word.get(i) {
+        match c {
+            b'{' if depth == 0 && word.get(i + 1) == Some(&b'\\') => {
+                let name_len = word[i + 2..]
+                    .iter()
+                    .take_while(|c| c.is_ascii_alphabetic())
+                    .count();
+                let name = &word[i + 2..i + 2 + name_len];
+
+                if let Some(seq) = ControlSeq::of_name(name) {
+                    return seq.is_lower();
+                }
+
+                let letter = word[i + 2 + name_len..]
+                    .iter()
+                    .take_while(|&&c| c != b'}')
+                    .find(|c| c.is_ascii_alphabetic());
+
+                if let Some(letter) = letter {
+                    return letter.is_ascii_lowercase();
+                }
+
+                depth += 1;
+            }
+            b'{' => depth += 1,
+            b'}' => depth = depth.saturating_sub(1),
+            c if depth == 0 && c.is_ascii_alphabetic() => return c.is_ascii_lowercase(),
+            c if depth == 0 && c >= 0x80 => {
+                let rest = String::from_utf8_lossy(&word[i..]);
+                return rest.chars().next().is_some_and(char::is_lowercase);
+            }
+            _ => (),
+        }
+
+        i += 1;
+    }
+
+    false
+}
+
+/// The initials of the words of a name part, in biblatex's markup.
+fn initials(words: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for (i, word) in words.iter().enumerate() {
+        if i > 0 {
+            out.extend_from_slice(b"\\bibinitdelim ");
+        }
+
+        for (j, piece) in split_top_level(word, |c| c == b'-').iter().enumerate() {
+            if j > 0 {
+                out.extend_from_slice(b"\\bibinithyphendelim ");
+            }
+
+            out.extend_from_slice(initial(piece));
+            out.extend_from_slice(b"\\bibinitperiod");
+        }
+    }
+
+    out
+}
+
+/// The initial of a word: its first character, or its first group if it
+/// starts with a brace.
+fn initial(word: &[u8]) -> &[u8] {
+    if word.first() == Some(&b'{') {
+        let mut depth = 0;
+
+        for (i, &c) in word.iter().enumerate() {
+            match c {
+                b'{' => depth += 1,
+                b'}' => {
+                    depth -= 1;
+
+                    if depth == 0 {
+                        return &word[..=i];
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        return word;
+    }
+
+    let len = String::from_utf8_lossy(word)
+        .chars()
+        .next()
+        .map_or(0, char::len_utf8)
+        .min(word.len());
+    &word[..len]
+}
+
+fn is_white(c: u8) -> bool {
+    c == b'\n' || c == b'~' || LexClass::of(c) == LexClass::Whitespace
+}
+
+fn trim_white(text: &[u8]) -> &[u8] {
+    let start = text
+        .iter()
+        .position(|&c| !is_white(c))
+        .unwrap_or(text.len());
+    let end = text
+        .iter()
+        .rposition(|&c| !is_white(c))
+        .map_or(start, |i| i + 1);
+    &text[start..end]
+}
+
+/// Collapse runs of whitespace into single spaces, and trim the ends, as
+/// BibTeX does with field values.
+fn collapse_white(text: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(text.len());
+
+    for &c in text {
+        if c == b'\n' || LexClass::of(c) == LexClass::Whitespace {
+            if out.last().is_some_and(|&l| l != b' ') {
+                out.push(b' ');
+            }
+        } else {
+            out.push(c);
+        }
+    }
+
+    if out.last() == Some(&b' ') {
+        out.pop();
+    }
+
+    out
+}
+
+/// A stable hash of some text, for the hash fields that biblatex uses to
+/// compare names.
+fn hash(text: &[u8]) -> String {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+
+    for &c in text {
+        h ^= u64::from(c);
+        h = h.wrapping_mul(0x0100_0000_01b3);
+    }
+
+    format!("{h:016x}")
+}
+
+/// A part of the key by which an entry sorts.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+enum SortPart {
+    Ascending(Vec<u8>),
+    Descending(Reverse<Vec<u8>>),
+}
+
+/// The text by which the names of a record sort, or its title if it has
+/// none.
+fn sort_name(record: &Record) -> Vec<u8> {
+    if let Some(field) = record.label_name() {
+        let (names, _) = record.names(field);
+        let mut text = Vec::new();
+
+        for name in names {
+            text.extend_from_slice(&name.sort_text());
+            text.push(b' ');
+        }
+
+        return text;
+    }
+
+    sort_title(record)
+}
+
+fn sort_title(record: &Record) -> Vec<u8> {
+    record
+        .fields
+        .get("title")
+        .map(|t| sort_text(t))
+        .unwrap_or_default()
+}
+
+fn sort_year(record: &Record) -> Vec<u8> {
+    record
+        .fields
+        .get("year")
+        .map(|t| sort_text(t))
+        .unwrap_or_default()
+}
+
+/// Sort the records as the sorting scheme at the start of *refcontext*
+/// asks. Records that compare equal keep their order.
+fn sort_records(records: &mut [Record], refcontext: &str, collation: Option<Locale>) {
+    let scheme = refcontext.split('/').next().unwrap_or_default();
+
+    if scheme == "none" {
+        return;
+    }
+
+    let collate = |text: Vec<u8>| match collation {
+        Some(locale) => locale.sort_key(&text),
+        None => text,
+    };
+
+    records.sort_by_cached_key(|r| {
+        if let Some(key) = r.fields.get("sortkey") {
+            return vec![SortPart::Ascending(collate(sort_text(key)))];
+        }
+
+        let name = SortPart::Ascending(collate(sort_name(r)));
+        let title = SortPart::Ascending(collate(sort_title(r)));
+        let year = sort_year(r);
+
+        match scheme {
+            "nyt" => vec![name, SortPart::Ascending(year), title],
+            "ynt" => vec![SortPart::Ascending(year), name, title],
+            "ydnt" => vec![SortPart::Descending(Reverse(year)), name, title],
+            _ => vec![name, title, SortPart::Ascending(year)],
+        }
+    });
+}
+
+/// The hashes of the names in a list.
+fn names_hash(names: &[Name<'_>]) -> String {
+    let text: Vec<u8> = names.iter().flat_map(Name::full).collect();
+    hash(&text)
+}
+
+/// Write the `.bbl` text for some records, which are in their sorted order,
+/// along with the preamble and the keys of missing entries.
+fn render(records: &[Record], preamble: &[u8], refcontext: &str, missing: &[String]) -> Vec<u8> {
+    // The entries with the same labelling names and year are told apart by
+    // their `extradate`, numbered in order.
+    let label_of = |r: &Record| {
+        let names = match r.label_name() {
+            Some(f) => names_hash(&r.names(f).0),
+            None => hash(r.fields.get("title").map_or(&[][..], |t| t)),
+        };
+        (names, r.fields.get("year").cloned().unwrap_or_default())
+    };
+
+    let mut counts: HashMap<_, usize> = HashMap::new();
+
+    for r in records {
+        *counts.entry(label_of(r)).or_default() += 1;
+    }
+
+    let mut seen: HashMap<_, usize> = HashMap::new();
+    let mut out = String::new();
+
+    out.push_str(
+        "% $ biblatex auxiliary file $\n\
+         % $ biblatex bbl format version 3.2 $\n\
+         % Do not modify the above lines!\n\
+         %\n\
+         % This is an auxiliary file used by the 'biblatex' package.\n\
+         % This file may safely be deleted. It will be recreated by\n\
+         % Tectonic as required.\n\
+         %\n\
+         \\begingroup\n\
+         \\makeatletter\n\
+         \\@ifundefined{ver@biblatex.sty}\n\
+         \x20 {\\@latex@error\n\
+         \x20    {Missing 'biblatex' package}\n\
+         \x20    {The bibliography requires the 'biblatex' package.}\n\
+         \x20     \\aftergroup\\endinput}\n\
+         \x20 {}\n\
+         \\endgroup\n\n",
+    );
+
+    if !preamble.is_empty() {
+        let _ = write!(
+            out,
+            "\\preamble{{%\n{}%\n}}\n\n",
+            String::from_utf8_lossy(preamble)
+        );
+    }
+
+    let _ = writeln!(out, "\n\\refsection{{0}}");
+    let _ = writeln!(out, "  \\datalist[entry]{{{refcontext}}}");
+
+    for r in records {
+        let label = label_of(r);
+        let extradate = (counts[&label] > 1).then(|| {
+            let n = seen.entry(label).or_default();
+            *n += 1;
+            *n
+        });
+
+        render_record(&mut out, r, extradate);
+    }
+
+    out.push_str("  \\enddatalist\n");
+
+    for key in missing {
+        let _ = writeln!(out, "  \\missing{{{key}}}");
+    }
+
+    out.push_str("\\endrefsection\n\\endinput\n\n");
+    out.into_bytes()
+}
+
+fn render_record(out: &mut String, r: &Record, extradate: Option<usize>) {
+    let text = |v: &[u8]| String::from_utf8_lossy(v).into_owned();
+    let mut fields: BTreeMap<&str, String> = BTreeMap::new();
+    let mut strings: BTreeMap<String, String> = BTreeMap::new();
+    let mut flags = Vec::new();
+
+    let _ = writeln!(out, "    \\entry{{{}}}{{{}}}{{}}", r.key, r.entry_type);
+
+    for &field in NAME_FIELDS {
+        let (names, more) = r.names(field);
+
+        if names.is_empty() {
+            continue;
+        }
+
+        let _ = writeln!(out, "      \\name{{{field}}}{{{}}}{{}}{{%", names.len());
+
+        for name in &names {
+            let _ = writeln!(out, "        {{{{hash={}}}{{%", hash(&name.full()));
+            let parts: Vec<_> = name
+                .parts()
+                .into_iter()
+                .filter(|(_, words)| !words.is_empty())
+                .collect();
+
+            for (i, (part, words)) in parts.iter().enumerate() {
+                let _ = writeln!(out, "           {part}={{{}}},", text(&words.join(&b' ')));
+                let end = if i + 1 == parts.len() { "}}%" } else { "," };
+                let _ = writeln!(
+                    out,
+                    "           {part}i={{{}}}{end}",
+                    text(&initials(words))
+                );
+            }
+        }
+
+        out.push_str("      }\n");
+
+        let h = names_hash(&names);
+        strings.insert(format!("{field}bibnamehash"), h.clone());
+        strings.insert(format!("{field}namehash"), h.clone());
+        strings.insert(format!("{field}fullhash"), h);
+
+        if more {
+            flags.push(format!("more{field}"));
+        }
+    }
+
+    for &field in LIST_FIELDS {
+        let Some(value) = r.fields.get(field) else {
+            continue;
+        };
+
+        let (items, more) = split_list(value);
+        let _ = writeln!(out, "      \\list{{{field}}}{{{}}}{{%", items.len());
+
+        for item in items {
+            let _ = writeln!(out, "        {{{}}}%", text(item));
+        }
+
+        out.push_str("      }\n");
+
+        if more {
+            flags.push(format!("more{field}"));
+        }
+    }
+
+    if let Some(field) = r.label_name() {
+        let (names, more) = r.names(field);
+        let h = names_hash(&names);
+        strings.insert("bibnamehash".to_owned(), h.clone());
+        strings.insert("namehash".to_owned(), h.clone());
+        strings.insert("fullhash".to_owned(), h);
+        fields.insert("labelnamesource", field.to_owned());
+
+        if more {
+            flags.push("morelabelname".to_owned());
+        }
+    }
+
+    for &field in KEY_FIELDS {
+        if let Some(value) = r.fields.get(field) {
+            strings.insert(field.to_owned(), text(value));
+        }
+    }
+
+    for (name, value) in &strings {
+        let _ = writeln!(out, "      \\strng{{{name}}}{{{value}}}");
+    }
+
+    let sortinit = sort_name(r)
+        .into_iter()
+        .find(u8::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase() as char)
+        .unwrap_or('0')
+        .to_string();
+    fields.insert("sortinithash", hash(sortinit.as_bytes()));
+    fields.insert("sortinit", sortinit);
+
+    if r.fields.contains_key("title") {
+        fields.insert("labeltitlesource", "title".to_owned());
+    }
+
+    if let Some(year) = r.fields.get("year") {
+        fields.insert("labeldatesource", String::new());
+        fields.insert("labelyear", text(year));
+
+        if let Some(month) = r.fields.get("month") {
+            fields.insert("labelmonth", text(month));
+        }
+
+        if let Some(n) = extradate {
+            fields.insert("extradate", n.to_string());
+            fields.insert("extradatescope", "labelyear".to_owned());
+        }
+    }
+
+    for (name, value) in &r.fields {
+        let name = name.as_str();
+
+        if NAME_FIELDS.contains(&name)
+            || LIST_FIELDS.contains(&name)
+            || VERBATIM_FIELDS.contains(&name)
+            || KEY_FIELDS.contains(&name)
+            || name == "keywords"
+        {
+            continue;
+        }
+
+        let value = if name == "pages" {
+            text(&page_ranges(value))
+        } else {
+            text(value)
+        };
+
+        fields.insert(name, value);
+    }
+
+    for flag in flags {
+        let _ = writeln!(out, "      \\true{{{flag}}}");
+    }
+
+    for (name, value) in &fields {
+        let _ = writeln!(out, "      \\field{{{name}}}{{{value}}}");
+    }
+
+    if let Some(pages) = r.fields.get("pages") {
+        let _ = writeln!(out, "      \\range{{pages}}{{{}}}", page_count(pages));
+    }
+
+    for &field in VERBATIM_FIELDS {
+        if let Some(value) = r.fields.get(field) {
+            let _ = writeln!(out, "      \\verb{{{field}}}");
+            let _ = writeln!(out, "      \\verb {}", text(value));
+            out.push_str("      \\endverb\n");
+        }
+    }
+
+    if let Some(keywords) = r.fields.get("keywords") {
+        let words: Vec<_> = text(keywords)
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(str::to_owned)
+            .collect();
+        let _ = writeln!(out, "      \\keyw{{{}}}", words.join(","));
+    }
+
+    out.push_str("    \\endentry\n");
+}
+
+/// Mark up page ranges, such as `1--10, 15`, with biblatex's range dashes
+/// and separators.
+fn page_ranges(pages: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    for (i, range) in pages.split(|&c| c == b',').enumerate() {
+        if i > 0 {
+            out.extend_from_slice(b"\\bibrangessep ");
+        }
+
+        let mut ends = split_top_level(range, |c| c == b'-').into_iter();
+
+        if let Some(start) = ends.next() {
+            out.extend_from_slice(start);
+        }
+
+        if let Some(end) = ends.last() {
+            out.extend_from_slice(b"\\bibrangedash ");
+            out.extend_from_slice(end);
+        }
+    }
+
+    out
+}
+
+/// The number of pages in some page ranges, or -1 if they aren't numeric.
+fn page_count(pages: &[u8]) -> i64 {
+    let mut count = 0;
+
+    for range in pages.split(|&c| c == b',') {
+        let ends: Vec<_> = split_top_level(range, |c| c == b'-')
+            .into_iter()
+            .map(|e| String::from_utf8_lossy(e).parse::<i64>().ok())
+            .collect();
+
+        count += match ends[..] {
+            [Some(_)] => 1,
+            [Some(start), Some(end)] if end >= start => end - start + 1,
+            _ => return -1,
+        };
+    }
+
+    count
+}
+
+/// Get the name of the next database file, and note in the log that it's
+/// being read.
+fn next_database(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    num: usize,
+) -> (String, PeekableInput) {
+    let file = globals.bibs.pop_file();
+    let raw_name = globals.pool.get_str(file.name);
+    let mut name = String::from_utf8_lossy(raw_name).into_owned();
+
+    if !has_database_extension(ctx, raw_name) {
+        name.push_str(".bib");
+    }
+
+    ctx.write_log_file(&format!("Database file #{num}: {name}\n"));
+    (name, file.file)
+}
+
+/// Read all of the entries of the databases.
+fn read_all(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<Database, BibtexError> {
+    let mut db = Database::new();
+
+    for num in 1.. {
+        if globals.bibs.len() == 0 {
+            break;
+        }
+
+        let (name, file) = next_database(ctx, globals, num);
+        let text = file.read_to_end(ctx)?;
+        db.read(ctx, &name, BibReader::new(&text), None);
+    }
+
+    ctx.database_keys = db.entries.iter().map(|e| e.key.clone()).collect();
+    Ok(db)
+}
+
+/// Read the cited entries of the databases, and those that they inherit
+/// from, without storing the fields of the others. The databases are read
+/// twice: first to index their entries, and then to read the text of just
+/// those that are needed.
+fn read_cited(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<Database, BibtexError> {
+    let mut first = Database::new();
+    let mut index = DbIndex::default();
+    let mut files = Vec::new();
+
+    for num in 1.. {
+        if globals.bibs.len() == 0 {
+            break;
+        }
+
+        let (name, mut file) = next_database(ctx, globals, num);
+        let text = file.read_rest(ctx)?;
+        index.file = files.len();
+        index.file_names.push(name.clone());
+        first.read(ctx, &name, BibReader::new(&text), Some(&mut index));
+        file.rewind(ctx)?;
+        files.push((name, file));
+    }
+
+    ctx.database_keys = index.entries.iter().map(|e| e.key.clone()).collect();
+
+    let needed = if ctx.all_entries {
+        vec![true; index.entries.len()]
+    } else {
+        index.needed((0..globals.cites.num_cites()).map(|cite| {
+            let key = globals.pool.get_str(globals.cites.get_cite(cite));
+            String::from_utf8_lossy(key).to_lowercase()
+        }))
+    };
+
+    let mut db = Database::new();
+    db.preamble = first.preamble;
+    let mut defined = 0;
+    let mut entries = index
+        .entries
+        .iter()
+        .zip(needed)
+        .filter_map(|(entry, needed)| needed.then_some(entry))
+        .peekable();
+
+    for (num, (name, mut file)) in files.into_iter().enumerate() {
+        while let Some(entry) = entries.next_if(|e| e.file == num) {
+            for (macro_name, value) in &index.macros[defined..entry.macros] {
+                db.macros.insert(macro_name.clone(), value.clone());
+            }
+            defined = entry.macros;
+
+            let text = file.read_range(ctx, entry.span.start..entry.span.end)?;
+            let reader = BibReader::starting_at_line(&text, entry.span.line);
+            db.origin = entry.span.start;
+            db.read(ctx, &name, reader, None);
+        }
+
+        file.close(ctx)?;
+    }
+
+    db.origin = 0;
+    Ok(db)
+}
+
+/// Write the cited entries to the `.bbl` file in biblatex's format, instead of
+/// running the style. The `.aux` file must have been read.
+pub(crate) fn write_biblatex_bbl(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    let db = if ctx.config.low_memory {
+        read_cited(ctx, globals)?
+    } else {
+        read_all(ctx, globals)?
+    };
+
+    let mut selected = Vec::new();
+    let mut seen = HashSet::new();
+    let mut missing = Vec::new();
+    let mut citations = Vec::new();
+
+    for cite in 0..globals.cites.num_cites() {
+        let key = globals.cites.get_cite(cite);
+        let key_text = globals.pool.get_str(key);
+
+        if key_text.eq_ignore_ascii_case(CONTROL_KEY) {
+            continue;
+        }
+
+        let entry = db.find(key_text);
+        citations.push(Citation {
+            cite_key: String::from_utf8_lossy(key_text).into_owned(),
+            entry: entry.map(|e| e.location.clone()),
+        });
+
+        match entry {
+            Some(entry) if entry.entry_type == "xdata" => cited_xdata_warning(ctx, key_text),
+            Some(entry) => {
+                if seen.insert(entry.key.to_lowercase()) {
+                    selected.push(entry);
+                }
+            }
+            None => {
+                missing.push(String::from_utf8_lossy(key_text).into_owned());
+                print_missing_entry(ctx, globals.pool, key)?;
+            }
+        }
+    }
+
+    if ctx.all_entries {
+        for entry in &db.entries {
+            if seen.insert(entry.key.to_lowercase()) {
+                selected.push(entry);
+            }
+        }
+    }
+
+    ctx.citation_report = Some(CitationReport {
+        generated: ctx.now(),
+        run_id: ctx.run_id(),
+        citations,
+        all_entries: ctx.all_entries,
+        repeated_entries: std::mem::take(&mut ctx.repeated_entries),
+    });
+
+    let mut records: Vec<_> = selected
+        .into_iter()
+        .filter(|e| e.entry_type != "control" && e.entry_type != "xdata")
+        .map(|e| db.record(ctx, e))
+        .collect();
+
+    let refcontext = ctx
+        .biblatex_refcontext
+        .clone()
+        .unwrap_or_else(|| DEFAULT_REFCONTEXT.to_owned());
+    sort_records(&mut records, &refcontext, ctx.config.collation);
+
+    let text = render(&records, &db.preamble, &refcontext, &missing);
+
+    if let Some(bbl) = ctx.bbl_file {
+        ctx.engine
+            .get_output(bbl)
+            .write_all(&text)
+            .map_err(|_| BibtexError::Fatal)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(entry_type: &str, key: &str, fields: &[(&str, &str)]) -> Record {
+        Record::new(&DbEntry {
+            key: key.to_owned(),
+            entry_type: entry_type.to_owned(),
+            fields: fields
+                .iter()
+                .map(|(n, v)| (n.to_string(), v.as_bytes().to_vec()))
+                .collect(),
+            location: EntryLocation {
+                file: "refs".to_owned(),
+                line: 1,
+            },
+        })
+    }
+
+    fn parse(name: &str) -> [String; 4] {
+        let name = Name::parse(name.as_bytes()).unwrap();
+        name.parts()
+            .map(|(_, words)| String::from_utf8(words.join(&b' ')).unwrap())
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(parse("Donald E. Knuth"), ["Knuth", "Donald E.", "", ""]);
+        assert_eq!(
+            parse("Ludwig van Beethoven"),
+            ["Beethoven", "Ludwig", "van", ""]
+        );
+        assert_eq!(
+            parse("van der Waals, Johannes"),
+            ["Waals", "Johannes", "van der", ""]
+        );
+        assert_eq!(parse("Ford, Jr., Henry"), ["Ford", "Henry", "", "Jr."]);
+        assert_eq!(
+            parse("{Barnes and Noble}"),
+            ["{Barnes and Noble}", "", "", ""]
+        );
+        assert_eq!(
+            parse("Jean {de la} Fontaine"),
+            ["Fontaine", "Jean {de la}", "", ""]
+        );
+        assert_eq!(parse("{\\'e}mile Zola"), ["Zola", "", "{\\'e}mile", ""]);
+
+        let (items, more) = split_list(b"A. Smith and {Jones and Sons} AND others");
+        assert_eq!(items, [&b"A. Smith"[..], b"{Jones and Sons}"]);
+        assert!(more);
+
+        assert_eq!(
+            initials(&[b"Jean-Paul", b"{\\\"O}rs"]),
+            b"J\\bibinitperiod\\bibinithyphendelim P\\bibinitperiod\\bibinitdelim {\\\"O}\\bibinitperiod"
+        );
+    }
+
+    #[test]
+    fn records() {
+        let r = record(
+            "phdthesis",
+            "t",
+            &[
+                ("school", "MIT"),
+                ("date", "2020-03/2021"),
+                ("journal", "J"),
+            ],
+        );
+        assert_eq!(r.entry_type, "thesis");
+        let fields: Vec<_> = r
+            .fields
+            .iter()
+            .map(|(n, v)| (n.as_str(), String::from_utf8_lossy(v).into_owned()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("endyear", "2021"),
+                ("institution", "MIT"),
+                ("journaltitle", "J"),
+                ("month", "3"),
+                ("type", "phdthesis"),
+                ("year", "2020"),
+            ]
+            .map(|(n, v)| (n, v.to_owned()))
+        );
+
+        let r = record("article", "a", &[("month", "September"), ("year", "1999")]);
+        assert_eq!(r.fields["month"], b"9");
+
+        assert_eq!(
+            page_ranges(b"1--10, 15"),
+            b"1\\bibrangedash 10\\bibrangessep 15"
+        );
+        assert_eq!(page_count(b"1--10, 15"), 11);
+        assert_eq!(page_count(b"iv--x"), -1);
+    }
+
+    #[test]
+    fn sorting_and_rendering() {
+        let mut records = vec![
+            record(
+                "book",
+                "b",
+                &[
+                    ("author", "Knuth, Donald"),
+                    ("title", "B"),
+                    ("year", "1984"),
+                ],
+            ),
+            record(
+                "book",
+                "a",
+                &[("author", "Donald Knuth"), ("title", "A"), ("year", "1984")],
+            ),
+            record("misc", "c", &[("title", "Anonymous")]),
+        ];
+
+        sort_records(&mut records, "none/global//global/global", None);
+        let keys: Vec<_> = records.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["b", "a", "c"]);
+
+        sort_records(&mut records, DEFAULT_REFCONTEXT, None);
+        let keys: Vec<_> = records.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["c", "a", "b"]);
+
+        let text = render(
+            &records,
+            b"\\def\\x{}",
+            DEFAULT_REFCONTEXT,
+            &["z".to_owned()],
+        );
+        let text = String::from_utf8(text).unwrap();
+        let h = names_hash(&records[1].names("author").0);
+
+        assert!(text.starts_with("% $ biblatex auxiliary file $\n"));
+        assert!(text.contains("\\preamble{%\n\\def\\x{}%\n}\n"));
+        assert!(text.contains("  \\datalist[entry]{nty/global//global/global}\n    \\entry{c}"));
+        assert!(text.contains(&format!(
+            "    \\entry{{a}}{{book}}{{}}\n\
+             \x20     \\name{{author}}{{1}}{{}}{{%\n\
+             \x20       {{{{hash={h}}}{{%\n\
+             \x20          family={{Knuth}},\n\
+             \x20          familyi={{K\\bibinitperiod}},\n\
+             \x20          given={{Donald}},\n\
+             \x20          giveni={{D\\bibinitperiod}}}}}}%\n\
+             \x20     }}\n\
+             \x20     \\strng{{authorbibnamehash}}{{{h}}}\n"
+        )));
+        assert!(text.contains(
+            "      \\field{extradate}{1}\n\
+             \x20     \\field{extradatescope}{labelyear}\n\
+             \x20     \\field{labeldatesource}{}\n\
+             \x20     \\field{labelnamesource}{author}\n\
+             \x20     \\field{labeltitlesource}{title}\n\
+             \x20     \\field{labelyear}{1984}\n\
+             \x20     \\field{sortinit}{K}\n"
+        ));
+        assert!(text.ends_with(
+            "    \\endentry\n  \\enddatalist\n  \\missing{z}\n\\endrefsection\n\\endinput\n\n"
+        ));
+    }
+}
@@ -1,7 +1,8 @@
 use crate::{
     buffer::{BufTy, GlobalBuffer},
     char_info::LexClass,
-    cite::add_database_cite,
+    cite::{add_database_cite, CiteInfo, EntrySource, RepeatedEntry},
+    hardened::internal_error,
     hash::{BstFn, HashData, HashExtra},
     log::{
         bib_equals_sign_print, bib_err_print, bib_id_print, bib_one_of_two_print, bib_warn_print,
@@ -11,6 +12,7 @@ use crate::{
     peekable::input_ln,
     pool::{StrNumber, StringPool},
     scan::{scan_and_store_the_field_value_and_eat_white, scan_identifier, Scan, ScanRes},
+    schema::RecordedEntry,
     BibNumber, Bibtex, BibtexError, File, GlobalItems, HashPointer, StrIlk,
 };
 
@@ -154,6 +156,11 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         return Err(BibtexError::Fatal);
     }
 
+    let entry_source = EntrySource {
+        file: globals.bibs.top_file().name,
+        line: globals.bibs.top_file().line,
+    };
+
     globals
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
@@ -198,7 +205,10 @@ pub(crate) fn get_bib_command_or_entry_and_process(
 
     if res.exists {
         let HashExtra::BibCommand(cmd) = globals.hash.node(res.loc).extra else {
-            panic!("BibCommand lookup didn't have BibCommand extra");
+            return Err(internal_error(
+                ctx,
+                "BibCommand lookup didn't have BibCommand extra",
+            ));
         };
 
         bib_command = Some(cmd);
@@ -440,6 +450,14 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     let range = globals.buffers.offset(BufTy::Base, 1)..globals.buffers.offset(BufTy::Base, 2);
     let bst_fn = &mut globals.buffers.buffer_mut(BufTy::Base)[range];
     let bst_res = globals.hash.lookup_str(globals.pool, bst_fn, StrIlk::BstFn);
+    // `@xdata` entries are stored whether or not they're cited, since they
+    // supply fields to the entries that name them in their `xdata` fields,
+    // which may come before or after them.
+    let is_xdata = bst_fn == b"xdata";
+    let entry_type = ctx
+        .config
+        .lint_entries
+        .then(|| String::from_utf8_lossy(bst_fn).into_owned());
 
     let type_exists = if bst_res.exists {
         matches!(
@@ -510,10 +528,21 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         .copy_within(BufTy::Base, BufTy::Ex, start, start, end - start);
 
     let range = globals.buffers.offset(BufTy::Base, 1)..globals.buffers.offset(BufTy::Base, 2);
+    let key = &globals.buffers.buffer(BufTy::Base)[range.clone()];
+    let key_text = String::from_utf8_lossy(key);
+
+    if !ctx.config.low_memory || admit_key(ctx, globals.cites, globals.pool, &key_text) {
+        ctx.database_keys.push(key_text.into_owned());
+    }
+
+    let lint_key = entry_type
+        .is_some()
+        .then(|| String::from_utf8_lossy(key).to_ascii_lowercase());
+
     let lc_cite = &mut globals.buffers.buffer_mut(BufTy::Ex)[range];
     lc_cite.make_ascii_lowercase();
 
-    let lc_res = if ctx.all_entries {
+    let lc_res = if ctx.all_entries || is_xdata {
         globals
             .hash
             .lookup_str_insert(ctx, globals.pool, lc_cite, HashExtra::LcCite(0))?
@@ -529,10 +558,16 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     let mut inner = || {
         if lc_res.exists {
             let HashExtra::LcCite(cite_loc) = globals.hash.node(lc_res.loc).extra else {
-                panic!("LcCite lookup didn't have LcCite extra");
+                return Some(Err(internal_error(
+                    ctx,
+                    "LcCite lookup didn't have LcCite extra",
+                )));
             };
             let HashExtra::Cite(cite) = globals.hash.node(cite_loc).extra else {
-                panic!("LcCite location didn't have Cite extra");
+                return Some(Err(internal_error(
+                    ctx,
+                    "LcCite location didn't have Cite extra",
+                )));
             };
 
             globals.cites.set_entry_ptr(cite);
@@ -598,6 +633,19 @@ pub(crate) fn get_bib_command_or_entry_and_process(
                 return Some(Err(BibtexError::Fatal));
             }
 
+            let range =
+                globals.buffers.offset(BufTy::Base, 1)..globals.buffers.offset(BufTy::Base, 2);
+            let repeated = RepeatedEntry {
+                cite_key: String::from_utf8_lossy(&globals.buffers.buffer(BufTy::Base)[range])
+                    .into_owned(),
+                location: entry_source.location(ctx, globals.pool),
+                first: globals
+                    .cites
+                    .source(entry_ptr)
+                    .map(|source| source.location(ctx, globals.pool)),
+            };
+            ctx.repeated_entries.push(repeated);
+
             ctx.write_logs("Repeated entry");
             return Some(bib_err_print(
                 ctx,
@@ -614,12 +662,15 @@ pub(crate) fn get_bib_command_or_entry_and_process(
         return ret;
     }
 
-    let store_entry = if ctx.all_entries {
+    let store_entry = if ctx.all_entries || (is_xdata && !res.exists) {
         if res.exists {
             if globals.cites.entry_ptr() >= globals.cites.all_marker() {
                 globals.cites.set_exists(globals.cites.entry_ptr(), true);
                 let HashExtra::LcCite(cite_loc) = globals.hash.node(lc_res.loc).extra else {
-                    panic!("LcCite lookup didn't have LcCite extra");
+                    return Err(internal_error(
+                        ctx,
+                        "LcCite lookup didn't have LcCite extra",
+                    ));
                 };
                 globals.cites.set_entry_ptr(globals.cites.ptr());
                 let num = add_database_cite(
@@ -660,6 +711,21 @@ pub(crate) fn get_bib_command_or_entry_and_process(
     };
 
     if store_entry {
+        globals
+            .cites
+            .set_source(globals.cites.entry_ptr(), entry_source);
+        globals.cites.set_xdata(globals.cites.entry_ptr(), is_xdata);
+
+        if let (Some(entry_type), Some(key)) = (entry_type, &lint_key) {
+            ctx.recorded_entries.insert(
+                key.clone(),
+                RecordedEntry {
+                    entry_type,
+                    fields: Vec::new(),
+                },
+            );
+        }
+
         if type_exists {
             globals
                 .cites
@@ -668,10 +734,14 @@ pub(crate) fn get_bib_command_or_entry_and_process(
             globals
                 .cites
                 .set_type(globals.cites.entry_ptr(), HashData::undefined());
-            ctx.write_logs("Warning--entry type for \"");
-            print_a_token(ctx, globals.buffers);
-            ctx.write_logs("\" isn't style-file defined\n");
-            bib_warn_print(ctx, globals.pool, globals.bibs)?;
+
+            // Styles needn't define `@xdata` entries, which are never output.
+            if !is_xdata {
+                ctx.write_logs("Warning--entry type for \"");
+                print_a_token(ctx, globals.buffers);
+                ctx.write_logs("\" isn't style-file defined\n");
+                bib_warn_print(ctx, globals.pool, globals.bibs)?;
+            }
         }
     }
 
@@ -744,6 +814,17 @@ pub(crate) fn get_bib_command_or_entry_and_process(
             let bst_fn = &mut globals.buffers.buffer_mut(BufTy::Base)[range];
             bst_fn.make_ascii_lowercase();
 
+            // Record all of the fields for linting, since those that the
+            // style doesn't declare are dropped.
+            if let Some(entry) = lint_key
+                .as_ref()
+                .and_then(|key| ctx.recorded_entries.get_mut(key))
+            {
+                entry
+                    .fields
+                    .push(String::from_utf8_lossy(bst_fn).into_owned());
+            }
+
             let res = globals.hash.lookup_str(globals.pool, bst_fn, StrIlk::BstFn);
 
             *field_name_loc = res.loc;
@@ -811,3 +892,26 @@ pub(crate) fn get_bib_command_or_entry_and_process(
 
     Ok(())
 }
+
+/// Whether to remember the key of a database entry in low-memory mode, which
+/// is the case if it could be suggested for one of the keys that have been
+/// cited so far.
+///
+/// The keys cited in the `.aux` file are followed by those of the entries
+/// that are cross-referenced as the databases are read, unless every entry is
+/// cited, in which case the entries after the `.aux` file's citations are the
+/// databases' own.
+fn admit_key(ctx: &mut Bibtex<'_, '_>, cites: &CiteInfo, pool: &StringPool, key: &str) -> bool {
+    let n_cited = if ctx.all_entries {
+        cites.old_num_cites()
+    } else {
+        cites.ptr()
+    };
+
+    for idx in ctx.key_filter.n_added()..n_cited {
+        let cited = String::from_utf8_lossy(pool.get_str(cites.get_cite(idx)));
+        ctx.key_filter.add(&cited);
+    }
+
+    ctx.key_filter.admits(key)
+}
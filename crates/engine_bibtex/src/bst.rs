@@ -1,21 +1,34 @@
 use crate::{
+    bbl::BblCommand,
     bibs::get_bib_command_or_entry_and_process,
     buffer::{BufTy, GlobalBuffer},
-    char_info::LexClass,
-    cite::find_cite_locs_for_this_cite_key,
+    char_info::{CharInfo, LexClass},
+    cite::{crossref_chain, find_cite_locs_for_this_cite_key, report_citations, CitationReport},
+    codecache::scan_or_reuse_fn_def,
+    dups::find_duplicates,
+    effects::{lint_command, lint_function},
     exec::{check_command_execution, execute_fn, ExecCtx},
+    hardened::internal_error,
     hash::{BstFn, HashData, HashExtra},
     log::{
         already_seen_function_print, bad_cross_reference_print,
         bst_err_print_and_look_for_blank_line, bst_id_print, bst_left_brace_print,
-        bst_right_brace_print, bst_warn_print, cite_key_disappeared_confusion, eat_bst_print,
-        hash_cite_confusion, log_pr_bib_name, nonexistent_cross_reference_error, print_a_token,
-        print_bib_name, print_confusion, print_fn_class, print_missing_entry,
+        bst_right_brace_print, bst_warn_print, cite_key_disappeared_confusion, cited_xdata_warning,
+        crossref_cycle_error, eat_bst_print, hash_cite_confusion, log_pr_bib_name,
+        nonexistent_cross_reference_error, print_a_token, print_bib_name, print_confusion,
+        print_fn_class, print_missing_entry,
     },
+    metrics::Phase,
     pool::{StrNumber, StringPool},
-    scan::{eat_bst_white_space, scan_fn_def, scan_identifier, Scan, ScanRes},
-    Bibtex, BibtexError, GlobalItems, HashPointer, StrIlk,
+    scan::{eat_bst_white_space, scan_identifier, Scan, ScanRes},
+    schema::lint_entries,
+    session::StyleCommand,
+    sorting::sort_by_scheme,
+    validate::check_fields,
+    xdata::resolve_xdata,
+    Bibtex, BibtexConfig, BibtexError, GlobalItems, HashPointer, StrIlk,
 };
+use std::time::Instant;
 
 macro_rules! eat_bst_white {
     ($ctx:ident, $globals:ident, $name:literal) => {
@@ -106,13 +119,18 @@ fn bst_entry_command(
             bst_fn,
             HashExtra::BstFn(BstFn::Field(globals.other.num_fields())),
         )?;
-        if res.exists {
+        // Like `crossref`, `xdata` is predefined, but styles written for
+        // biblatex data may declare it too.
+        if !res.exists {
+            globals.other.set_num_fields(globals.other.num_fields() + 1);
+        } else if !matches!(
+            globals.hash.node(res.loc).extra,
+            HashExtra::BstFn(BstFn::Field(f)) if f == globals.other.xdata_num()
+        ) {
             already_seen_function_print(ctx, globals.buffers, globals.pool, globals.hash, res.loc)?;
             return Ok(());
         }
 
-        globals.other.set_num_fields(globals.other.num_fields() + 1);
-
         eat_bst_white!(ctx, globals, "entry");
     }
 
@@ -232,11 +250,37 @@ fn bst_execute_command(
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
 
+    if ctx.config.lint_style {
+        lint_command(ctx, globals, StyleCommand::Execute, fn_loc);
+    }
+
+    if capture_command(ctx, globals, StyleCommand::Execute, fn_loc) {
+        return Ok(());
+    }
+
+    run_execute(ctx, globals, fn_loc)
+}
+
+/// Run the function at *fn_loc* once, as `EXECUTE` does.
+pub(crate) fn run_execute(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
     // TODO: Associated method on ExecCtx
     ctx.lit_stack.clear();
-    ctx.checkpoint = globals.pool.checkpoint();
+    globals.pool.promote_scratch();
 
     ctx.mess_with_entries = false;
+    ctx.start_command_fn(
+        globals.pool,
+        globals.hash,
+        globals.entries,
+        globals.other,
+        globals.cites,
+        BblCommand::Execute,
+        fn_loc,
+    );
 
     execute_fn(ctx, globals, fn_loc)?;
     check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
@@ -285,7 +329,12 @@ fn bst_function_command(
     globals
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
-    scan_fn_def(ctx, globals, res.loc, res.loc)?;
+    scan_or_reuse_fn_def(ctx, globals, res.loc)?;
+
+    if ctx.config.lint_style {
+        lint_function(ctx, globals, res.loc);
+    }
+
     Ok(())
 }
 
@@ -362,8 +411,26 @@ fn bst_iterate_command(
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
 
+    if ctx.config.lint_style {
+        lint_command(ctx, globals, StyleCommand::Iterate, fn_loc);
+    }
+
+    if capture_command(ctx, globals, StyleCommand::Iterate, fn_loc) {
+        return Ok(());
+    }
+
+    run_iterate(ctx, globals, fn_loc)
+}
+
+/// Run the function at *fn_loc* for each entry, in the current order, as
+/// `ITERATE` does.
+pub(crate) fn run_iterate(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
     ctx.lit_stack.clear();
-    ctx.checkpoint = globals.pool.checkpoint();
+    globals.pool.promote_scratch();
 
     ctx.mess_with_entries = true;
 
@@ -372,6 +439,15 @@ fn bst_iterate_command(
         globals
             .cites
             .set_ptr(globals.cites.info(sort_cite_ptr).to_raw_dangerous());
+        ctx.start_command_fn(
+            globals.pool,
+            globals.hash,
+            globals.entries,
+            globals.other,
+            globals.cites,
+            BblCommand::Iterate,
+            fn_loc,
+        );
         execute_fn(ctx, globals, fn_loc)?;
         check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
         sort_cite_ptr += 1;
@@ -479,6 +555,19 @@ fn bst_read_command(
         return Ok(());
     }
 
+    if let Some(commands) = &mut ctx.captured_commands {
+        commands.push(StyleCommand::Read);
+        return Ok(());
+    }
+
+    run_read(ctx, globals)
+}
+
+/// Read the database files, as `READ` does.
+pub(crate) fn run_read(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<(), BibtexError> {
     let start = globals.buffers.offset(BufTy::Base, 2);
     let to = globals.buffers.init(BufTy::Base);
     let sv_range = start..to;
@@ -503,6 +592,9 @@ fn bst_read_command(
         globals.cites.set_info(idx, StrNumber::invalid());
     }
     globals.cites.set_old_num_cites(globals.cites.num_cites());
+    let cited: Vec<StrNumber> = (0..globals.cites.old_num_cites())
+        .map(|idx| globals.cites.get_cite(idx))
+        .collect();
 
     if ctx.all_entries {
         for idx in 0..globals.cites.old_num_cites() {
@@ -521,6 +613,7 @@ fn bst_read_command(
         if ctx.config.verbose {
             ctx.write_logs(&format!("Database file #{}: ", idx + 1));
             print_bib_name(ctx, globals.pool, file.name)?;
+            ctx.diagnostics.clear_text();
         } else {
             ctx.write_log_file(&format!("Database file #{}: ", idx + 1));
             log_pr_bib_name(ctx, globals.pool, file.name)?;
@@ -545,6 +638,13 @@ fn bst_read_command(
 
     ctx.reading_completed = true;
     globals.cites.set_num_cites(globals.cites.ptr());
+    ctx.citation_report = Some(CitationReport {
+        generated: ctx.now(),
+        run_id: ctx.run_id(),
+        citations: report_citations(ctx, globals.pool, globals.hash, globals.cites, &cited),
+        all_entries: ctx.all_entries,
+        repeated_entries: std::mem::take(&mut ctx.repeated_entries),
+    });
 
     let cites = match globals.cites.num_cites() {
         0 => 0,
@@ -558,6 +658,49 @@ fn bst_read_command(
         return Err(BibtexError::Fatal);
     }
 
+    if !ctx.config.field_checks.is_empty() {
+        check_fields(ctx, globals)?;
+    }
+
+    resolve_xdata(ctx, globals)?;
+
+    if ctx.config.lint_entries {
+        lint_entries(ctx, globals)?;
+    }
+
+    if ctx.config.find_duplicates {
+        find_duplicates(ctx, globals)?;
+    }
+
+    // Classic BibTeX doesn't notice cross reference cycles at all. Report each
+    // one once, starting from its earliest entry.
+    for cite_ptr in 0..globals.cites.num_cites() {
+        let chain = crossref_chain(
+            globals.pool,
+            globals.hash,
+            globals.other,
+            cite_ptr,
+            usize::MAX,
+        );
+        if chain.loops_back_to == Some(cite_ptr) && chain.ancestors.iter().all(|&c| c > cite_ptr) {
+            crossref_cycle_error(ctx, globals.pool, globals.cites, cite_ptr, &chain.ancestors)?;
+        }
+    }
+
+    // With the classic depth of one, an entry inherits the fields of its
+    // parent as they stand when it's processed, which may or may not include
+    // the parent's own inherited fields. For deeper chains, we instead inherit
+    // from each ancestor's own fields in turn, so we need to save them before
+    // modifying anything.
+    let max_depth = ctx.config.max_crossref_depth.max(1) as usize;
+    let own_fields: Vec<StrNumber> = if max_depth > 1 {
+        (0..globals.cites.num_cites() * globals.other.num_fields())
+            .map(|idx| globals.other.field(idx))
+            .collect()
+    } else {
+        Vec::new()
+    };
+
     for cite_ptr in 0..globals.cites.num_cites() {
         let field_ptr = cite_ptr * globals.other.num_fields() + globals.other.crossref_num();
         if !globals.other.field(field_ptr).is_invalid() {
@@ -569,25 +712,50 @@ fn bst_read_command(
 
             if find.lc_found {
                 let HashExtra::LcCite(cite_loc) = globals.hash.node(find.lc_cite_loc).extra else {
-                    panic!("LcCite lookup didn't have LcCite extra");
+                    return Err(internal_error(
+                        ctx,
+                        "LcCite lookup didn't have LcCite extra",
+                    ));
                 };
                 globals
                     .other
                     .set_field(field_ptr, globals.hash.text(cite_loc));
 
                 let field_start = cite_ptr * globals.other.num_fields();
-                let HashExtra::Cite(cite) = globals.hash.node(cite_loc).extra else {
-                    panic!("LcCite location didn't have Cite extra");
-                };
-                let mut parent =
-                    cite * globals.other.num_fields() + globals.other.pre_defined_fields();
-                for idx in (field_start + globals.other.pre_defined_fields())
-                    ..(field_start + globals.other.num_fields())
-                {
-                    if globals.other.field(idx).is_invalid() {
-                        globals.other.set_field(idx, globals.other.field(parent));
+                let child_fields = (field_start + globals.other.pre_defined_fields())
+                    ..(field_start + globals.other.num_fields());
+
+                if max_depth > 1 {
+                    let chain = crossref_chain(
+                        globals.pool,
+                        globals.hash,
+                        globals.other,
+                        cite_ptr,
+                        max_depth,
+                    );
+                    for ancestor in chain.ancestors {
+                        let parent_start = ancestor * globals.other.num_fields()
+                            + globals.other.pre_defined_fields();
+                        for (idx, parent) in child_fields.clone().zip(parent_start..) {
+                            if globals.other.field(idx).is_invalid() {
+                                globals.other.set_field(idx, own_fields[parent]);
+                            }
+                        }
+                    }
+                } else {
+                    let HashExtra::Cite(cite) = globals.hash.node(cite_loc).extra else {
+                        return Err(internal_error(
+                            ctx,
+                            "LcCite location didn't have Cite extra",
+                        ));
+                    };
+                    let parent_start =
+                        cite * globals.other.num_fields() + globals.other.pre_defined_fields();
+                    for (idx, parent) in child_fields.zip(parent_start..) {
+                        if globals.other.field(idx).is_invalid() {
+                            globals.other.set_field(idx, globals.other.field(parent));
+                        }
                     }
-                    parent += 1;
                 }
             }
         }
@@ -618,7 +786,10 @@ fn bst_read_command(
                 globals.other.set_field(field_ptr, StrNumber::invalid());
             } else {
                 let HashExtra::LcCite(cite_loc) = globals.hash.node(find.lc_cite_loc).extra else {
-                    panic!("LcCite lookup didn't have LcCite extra");
+                    return Err(internal_error(
+                        ctx,
+                        "LcCite lookup didn't have LcCite extra",
+                    ));
                 };
                 if find.cite_loc != cite_loc {
                     hash_cite_confusion(ctx);
@@ -626,7 +797,7 @@ fn bst_read_command(
                 }
 
                 let HashExtra::Cite(cite) = globals.hash.node(find.cite_loc).extra else {
-                    panic!("Cite lookup didn't have Cite extra");
+                    return Err(internal_error(ctx, "Cite lookup didn't have Cite extra"));
                 };
                 let cite_parent_ptr = cite;
                 if globals.cites.get_type(cite_parent_ptr) == 0 {
@@ -640,19 +811,47 @@ fn bst_read_command(
                     )?;
                     globals.other.set_field(field_ptr, StrNumber::invalid());
                 } else {
-                    let field_parent_ptr =
-                        cite_parent_ptr * globals.other.num_fields() + globals.other.crossref_num();
-                    if !globals.other.field(field_parent_ptr).is_invalid() {
-                        ctx.write_logs("Warning--you've nested cross references");
-                        bad_cross_reference_print(
-                            ctx,
+                    // Warn if the chain goes deeper than we follow it. With the
+                    // classic depth of one, that's whenever the parent has a
+                    // cross reference of its own.
+                    let deepest = if max_depth > 1 {
+                        let chain = crossref_chain(
                             globals.pool,
-                            globals.cites,
+                            globals.hash,
+                            globals.other,
                             cite_ptr,
-                            globals.cites.get_cite(cite_parent_ptr),
-                        )?;
-                        ctx.write_logs("\", which also refers to something\n");
-                        ctx.mark_warning();
+                            max_depth,
+                        );
+                        if chain.ancestors.len() == max_depth {
+                            chain.ancestors.last().copied()
+                        } else {
+                            None
+                        }
+                    } else {
+                        Some(cite_parent_ptr)
+                    };
+
+                    if let Some(deepest) = deepest {
+                        let field_deepest_ptr =
+                            deepest * globals.other.num_fields() + globals.other.crossref_num();
+                        if !globals.other.field(field_deepest_ptr).is_invalid() {
+                            if max_depth > 1 {
+                                ctx.write_logs(&format!(
+                                    "Warning--you've nested cross references more than {max_depth} deep"
+                                ));
+                            } else {
+                                ctx.write_logs("Warning--you've nested cross references");
+                            }
+                            bad_cross_reference_print(
+                                ctx,
+                                globals.pool,
+                                globals.cites,
+                                cite_ptr,
+                                globals.cites.get_cite(deepest),
+                            )?;
+                            ctx.write_logs("\", which also refers to something\n");
+                            ctx.mark_warning();
+                        }
                     }
                     if !ctx.all_entries
                         && cite_parent_ptr >= globals.cites.old_num_cites()
@@ -669,6 +868,11 @@ fn bst_read_command(
     for cite_ptr in 0..globals.cites.num_cites() {
         if globals.cites.get_type(cite_ptr) == 0 {
             print_missing_entry(ctx, globals.pool, globals.cites.get_cite(cite_ptr))?;
+        } else if globals.cites.is_xdata(cite_ptr) {
+            if !ctx.all_entries && cite_ptr < globals.cites.old_num_cites() {
+                let key = globals.pool.get_str(globals.cites.get_cite(cite_ptr));
+                cited_xdata_warning(ctx, key);
+            }
         } else if ctx.all_entries
             || cite_ptr < globals.cites.old_num_cites()
             || globals.cites.info(cite_ptr).to_raw_dangerous() >= ctx.config.min_crossrefs as usize
@@ -686,6 +890,10 @@ fn bst_read_command(
                 globals
                     .cites
                     .set_type(ctx.cite_xptr, globals.cites.get_type(cite_ptr));
+                globals.cites.set_xdata(ctx.cite_xptr, false);
+                if let Some(source) = globals.cites.source(cite_ptr) {
+                    globals.cites.set_source(ctx.cite_xptr, source);
+                }
 
                 let find = find_cite_locs_for_this_cite_key(
                     globals.pool,
@@ -698,7 +906,10 @@ fn bst_read_command(
                 }
 
                 let HashExtra::LcCite(cite_loc) = globals.hash.node(find.lc_cite_loc).extra else {
-                    panic!("LcCite lookup didn't have LcCite extra");
+                    return Err(internal_error(
+                        ctx,
+                        "LcCite lookup didn't have LcCite extra",
+                    ));
                 };
                 if !find.cite_found || find.cite_loc != cite_loc {
                     hash_cite_confusion(ctx);
@@ -788,8 +999,26 @@ fn bst_reverse_command(
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.offset(BufTy::Base, 2) + 1);
 
+    if ctx.config.lint_style {
+        lint_command(ctx, globals, StyleCommand::Reverse, fn_loc);
+    }
+
+    if capture_command(ctx, globals, StyleCommand::Reverse, fn_loc) {
+        return Ok(());
+    }
+
+    run_reverse(ctx, globals, fn_loc)
+}
+
+/// Run the function at *fn_loc* for each entry, in reverse order, as
+/// `REVERSE` does.
+pub(crate) fn run_reverse(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
     ctx.lit_stack.clear();
-    ctx.checkpoint = globals.pool.checkpoint();
+    globals.pool.promote_scratch();
 
     ctx.mess_with_entries = true;
 
@@ -797,6 +1026,15 @@ fn bst_reverse_command(
         globals
             .cites
             .set_ptr(globals.cites.info(idx).to_raw_dangerous());
+        ctx.start_command_fn(
+            globals.pool,
+            globals.hash,
+            globals.entries,
+            globals.other,
+            globals.cites,
+            BblCommand::Reverse,
+            fn_loc,
+        );
         execute_fn(ctx, globals, fn_loc)?;
         check_command_execution(ctx, globals.pool, globals.hash, globals.cites)?;
     }
@@ -814,15 +1052,43 @@ fn bst_sort_command(
         return Ok(());
     }
 
-    if globals.cites.num_cites() > 1 {
-        globals
-            .cites
-            .sort_info(globals.entries, 0..=globals.cites.num_cites() - 1);
+    if let Some(commands) = &mut ctx.captured_commands {
+        commands.push(StyleCommand::Sort);
+        return Ok(());
     }
 
+    run_sort(&ctx.config, &ctx.char_info, globals);
     Ok(())
 }
 
+/// Sort the entries as `SORT` does: by the keys of the configured sort
+/// scheme if there is one, and otherwise by their `sort.key$`, in the order
+/// of the collation if one is set, or else that of the character tables.
+pub(crate) fn run_sort(config: &BibtexConfig, chars: &CharInfo, globals: &mut GlobalItems<'_>) {
+    if globals.cites.num_cites() <= 1 {
+        return;
+    }
+
+    match &config.sort_scheme {
+        Some(scheme) => sort_by_scheme(
+            scheme,
+            &config.transliteration,
+            config.collation,
+            chars,
+            globals.pool,
+            globals.hash,
+            globals.other,
+            globals.cites,
+        ),
+        None => globals.cites.sort_info(
+            globals.entries,
+            0..=globals.cites.num_cites() - 1,
+            config.collation,
+            chars,
+        ),
+    }
+}
+
 fn bst_strings_command(
     ctx: &mut ExecCtx<'_, '_, '_>,
     globals: &mut GlobalItems<'_>,
@@ -871,6 +1137,23 @@ fn bst_strings_command(
     Ok(())
 }
 
+/// If the commands of the style are being captured for a custom driver, record
+/// a command that runs the function at *fn_loc* rather than running it.
+fn capture_command(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &GlobalItems<'_>,
+    command: fn(String) -> StyleCommand,
+    fn_loc: HashPointer,
+) -> bool {
+    let Some(commands) = &mut ctx.captured_commands else {
+        return false;
+    };
+
+    let name = globals.pool.get_str(globals.hash.text(fn_loc));
+    commands.push(command(String::from_utf8_lossy(name).into_owned()));
+    true
+}
+
 fn bad_argument_token(
     ctx: &mut Bibtex<'_, '_>,
     fn_out: Option<&mut HashPointer>,
@@ -893,7 +1176,8 @@ fn bad_argument_token(
         ctx.write_logs(" is an unknown function");
         bst_err_print_and_look_for_blank_line(ctx, buffers, pool)?;
         Ok(true)
-    } else if let HashExtra::BstFn(BstFn::Builtin(_) | BstFn::Wizard(_)) = hash.node(res.loc).extra
+    } else if let HashExtra::BstFn(BstFn::Builtin(_) | BstFn::Custom(_) | BstFn::Wizard(_)) =
+        hash.node(res.loc).extra
     {
         Ok(false)
     } else {
@@ -937,19 +1221,28 @@ pub(crate) fn get_bst_command_and_process(
     }
 
     let HashExtra::BstCommand(cmd) = globals.hash.node(res.loc).extra else {
-        panic!("BstCommand lookup didn't have BstCommand extra");
+        return Err(internal_error(
+            ctx,
+            "BstCommand lookup didn't have BstCommand extra",
+        ));
     };
 
-    match cmd {
-        BstCommand::Entry => bst_entry_command(ctx, globals),
-        BstCommand::Execute => bst_execute_command(ctx, globals),
-        BstCommand::Function => bst_function_command(ctx, globals),
-        BstCommand::Integers => bst_integers_command(ctx, globals),
-        BstCommand::Iterate => bst_iterate_command(ctx, globals),
-        BstCommand::Macro => bst_macro_command(ctx, globals),
-        BstCommand::Read => bst_read_command(ctx, globals),
-        BstCommand::Reverse => bst_reverse_command(ctx, globals),
-        BstCommand::Sort => bst_sort_command(ctx, globals),
-        BstCommand::Strings => bst_strings_command(ctx, globals),
+    let started = Instant::now();
+    let (phase, res) = match cmd {
+        BstCommand::Entry => (Phase::Style, bst_entry_command(ctx, globals)),
+        BstCommand::Execute => (Phase::Execute, bst_execute_command(ctx, globals)),
+        BstCommand::Function => (Phase::Style, bst_function_command(ctx, globals)),
+        BstCommand::Integers => (Phase::Style, bst_integers_command(ctx, globals)),
+        BstCommand::Iterate => (Phase::Execute, bst_iterate_command(ctx, globals)),
+        BstCommand::Macro => (Phase::Style, bst_macro_command(ctx, globals)),
+        BstCommand::Read => (Phase::Read, bst_read_command(ctx, globals)),
+        BstCommand::Reverse => (Phase::Execute, bst_reverse_command(ctx, globals)),
+        BstCommand::Sort => (Phase::Sort, bst_sort_command(ctx, globals)),
+        BstCommand::Strings => (Phase::Style, bst_strings_command(ctx, globals)),
+    };
+    // Commands captured for a driver are timed when the driver runs them.
+    if phase == Phase::Style || ctx.captured_commands.is_none() {
+        ctx.run_metrics.add_time(phase, started.elapsed());
     }
+    res
 }
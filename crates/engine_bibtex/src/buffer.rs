@@ -32,8 +32,6 @@ pub(crate) struct GlobalBuffer {
     sv_buffer: Buffer<ASCIICode, 0>,
     ex_buf: Buffer<ASCIICode, 1>,
     out_buf: Buffer<ASCIICode, 0>,
-    name_sep_char: Buffer<ASCIICode, 0>,
-    name_tok: Vec<BufPointer>,
 }
 
 impl GlobalBuffer {
@@ -45,8 +43,6 @@ impl GlobalBuffer {
             sv_buffer: Buffer::new(buf_len),
             ex_buf: Buffer::new(buf_len),
             out_buf: Buffer::new(buf_len),
-            name_sep_char: Buffer::new(buf_len),
-            name_tok: vec![0; buf_len + 1],
         }
     }
 
@@ -60,7 +56,6 @@ impl GlobalBuffer {
             BufTy::Sv => self.sv_buffer.ptr.as_mut_ptr(),
             BufTy::Ex => self.ex_buf.ptr.as_mut_ptr(),
             BufTy::Out => self.out_buf.ptr.as_mut_ptr(),
-            BufTy::NameSep => self.name_sep_char.ptr.as_mut_ptr(),
         }
     }
 
@@ -70,7 +65,6 @@ impl GlobalBuffer {
             BufTy::Sv => &self.sv_buffer.ptr,
             BufTy::Ex => &self.ex_buf.ptr,
             BufTy::Out => &self.out_buf.ptr,
-            BufTy::NameSep => &self.name_sep_char.ptr,
         }
     }
 
@@ -80,7 +74,6 @@ impl GlobalBuffer {
             BufTy::Sv => &mut self.sv_buffer.ptr,
             BufTy::Ex => &mut self.ex_buf.ptr,
             BufTy::Out => &mut self.out_buf.ptr,
-            BufTy::NameSep => &mut self.name_sep_char.ptr,
         }
     }
 
@@ -130,25 +123,17 @@ impl GlobalBuffer {
         match ty {
             BufTy::Base => self.buffer.offset[offset - 1] = val,
             BufTy::Ex => self.ex_buf.offset[offset - 1] = val,
-            BufTy::Sv | BufTy::Out | BufTy::NameSep => {
+            BufTy::Sv | BufTy::Out => {
                 unreachable!("Buffer {:?} has no offsets", ty)
             }
         }
     }
 
-    pub fn name_tok(&self, pos: BufPointer) -> BufPointer {
-        self.name_tok[pos]
-    }
-
-    pub fn set_name_tok(&mut self, pos: BufPointer, val: BufPointer) {
-        self.name_tok[pos] = val;
-    }
-
     // pub fn incr_offset(&mut self, ty: BufTy, offset: usize) {
     //     match ty {
     //         BufTy::Base => self.buffer.offset[offset - 1] += 1,
     //         BufTy::Ex => self.ex_buf.offset[offset - 1] += 1,
-    //         BufTy::Sv | BufTy::Out | BufTy::NameSep => {
+    //         BufTy::Sv | BufTy::Out => {
     //             unreachable!("Buffer {:?} has no offsets", ty)
     //         }
     //     }
@@ -158,7 +143,7 @@ impl GlobalBuffer {
         match ty {
             BufTy::Base => self.buffer.offset[offset - 1],
             BufTy::Ex => self.ex_buf.offset[offset - 1],
-            BufTy::Sv | BufTy::Out | BufTy::NameSep => {
+            BufTy::Sv | BufTy::Out => {
                 unreachable!("Buffer {:?} has no offsets", ty)
             }
         }
@@ -170,7 +155,6 @@ impl GlobalBuffer {
             BufTy::Sv => self.sv_buffer.init,
             BufTy::Ex => self.ex_buf.init,
             BufTy::Out => self.out_buf.init,
-            BufTy::NameSep => self.name_sep_char.init,
         }
     }
 
@@ -180,7 +164,6 @@ impl GlobalBuffer {
             BufTy::Sv => self.sv_buffer.init = val,
             BufTy::Ex => self.ex_buf.init = val,
             BufTy::Out => self.out_buf.init = val,
-            BufTy::NameSep => self.name_sep_char.init = val,
         }
     }
 
@@ -190,8 +173,6 @@ impl GlobalBuffer {
         self.sv_buffer.grow(BUF_SIZE);
         self.ex_buf.grow(BUF_SIZE);
         self.out_buf.grow(BUF_SIZE);
-        self.name_sep_char.grow(BUF_SIZE);
-        self.name_tok.resize(self.name_tok.len() + BUF_SIZE, 0);
         self.buf_len = new_len;
     }
 }
@@ -202,5 +183,4 @@ pub(crate) enum BufTy {
     Sv,
     Ex,
     Out,
-    NameSep,
 }
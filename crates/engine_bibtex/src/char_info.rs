@@ -1,4 +1,5 @@
 use crate::ASCIICode;
+use tectonic_errors::prelude::*;
 
 macro_rules! const_for {
     ($start:literal..$end:literal => $arr:ident[..] = $expr:expr) => {
@@ -196,3 +197,339 @@ pub static CHAR_WIDTH: [i64; 256] = {
 
     char_width
 };
+
+/// The character tables of a run, which a bibtex8-style `.csf` file can
+/// customize.
+///
+/// By default, these are the tables of classic BibTeX: the letters are the
+/// ASCII letters and all bytes from 128 up, only the ASCII letters change
+/// case, and strings sort by their bytes. A `.csf` file, as read by
+/// [`CharInfo::from_csf`], can add letters and case mappings for the bytes
+/// of an 8-bit codepage, which `change.case$` and `purify$` then use, and
+/// define the order in which `SORT` compares the bytes of sort keys. The
+/// whitespace and separator classes can't be changed.
+#[derive(Clone, Debug)]
+pub(crate) struct CharInfo {
+    lex_class: [LexClass; 256],
+    lower: [u8; 256],
+    upper: [u8; 256],
+
+    /// The weight of each byte in comparisons of sort keys, if the order has
+    /// been customized.
+    order: Option<[u16; 256]>,
+}
+
+impl Default for CharInfo {
+    fn default() -> Self {
+        CharInfo {
+            lex_class: LEX_CLASS,
+            lower: std::array::from_fn(|c| (c as u8).to_ascii_lowercase()),
+            upper: std::array::from_fn(|c| (c as u8).to_ascii_uppercase()),
+            order: None,
+        }
+    }
+}
+
+impl CharInfo {
+    /// Read the tables from the text of a `.csf` file.
+    ///
+    /// The file consists of sections, each written as `\name{...}`, and `%`
+    /// starts a comment that runs to the end of the line. Characters are
+    /// written as themselves or, in the TeX manner, as `^^` followed by two
+    /// lowercase hex digits. The sections are:
+    ///
+    /// - `\lowupcase{...}`, whose lines list pairs of a lowercase letter and
+    ///   its uppercase counterpart;
+    /// - `\lowercase{...}`, whose lines list pairs of a character and what it
+    ///   lowercases to;
+    /// - `\uppercase{...}`, whose lines list pairs of a character and what it
+    ///   uppercases to; and
+    /// - `\order{...}`, whose lines list the characters in the order in which
+    ///   they sort. The characters on a line sort equally, and may be
+    ///   separated by `_`. A range like `a-z`, on a line of its own, stands
+    ///   for the characters from `a` to `z`, which sort in that order.
+    ///   Characters that aren't listed sort after those that are, by their
+    ///   bytes.
+    ///
+    /// The characters named in the case sections become letters.
+    pub fn from_csf(text: &[u8]) -> Result<CharInfo> {
+        let mut info = CharInfo::default();
+        let mut rest = text;
+        let mut line = 1;
+
+        loop {
+            let skipped = skip_csf_space(rest);
+            line += count_lines(&rest[..rest.len() - skipped.len()]);
+            rest = skipped;
+
+            if rest.is_empty() {
+                break;
+            }
+
+            let Some(after) = rest.strip_prefix(b"\\") else {
+                bail!("line {line}: expected a section such as `\\order{{`");
+            };
+
+            let name_len = after
+                .iter()
+                .position(|c| !c.is_ascii_alphabetic())
+                .unwrap_or(after.len());
+            let name = String::from_utf8_lossy(&after[..name_len]).into_owned();
+
+            let Some(body) = after[name_len..].strip_prefix(b"{") else {
+                bail!("line {line}: expected `{{` after `\\{name}`");
+            };
+            let Some(end) = body.iter().position(|&c| c == b'}') else {
+                bail!("line {line}: the `\\{name}` section isn't closed with `}}`");
+            };
+
+            let lines: Vec<Vec<&[u8]>> = body[..end]
+                .split(|&c| c == b'\n')
+                .map(|l| {
+                    let l = l.split(|&c| c == b'%').next().unwrap_or_default();
+                    l.split(|c| c.is_ascii_whitespace())
+                        .filter(|t| !t.is_empty())
+                        .collect()
+                })
+                .collect();
+
+            match name.as_str() {
+                "lowupcase" | "lowercase" | "uppercase" => {
+                    for (n, tokens) in lines.iter().enumerate() {
+                        let line = line + n;
+                        ensure!(
+                            tokens.len() % 2 == 0,
+                            "line {line}: characters in `\\{name}` must come in pairs"
+                        );
+
+                        for pair in tokens.chunks(2) {
+                            let from = csf_char(pair[0], line)?;
+                            let to = csf_char(pair[1], line)?;
+
+                            match name.as_str() {
+                                "lowupcase" => {
+                                    info.upper[from as usize] = to;
+                                    info.lower[to as usize] = from;
+                                }
+                                "lowercase" => info.lower[from as usize] = to,
+                                _ => info.upper[from as usize] = to,
+                            }
+
+                            info.lex_class[from as usize] = LexClass::Alpha;
+                            info.lex_class[to as usize] = LexClass::Alpha;
+                        }
+                    }
+                }
+
+                "order" => {
+                    let mut weights = [0u16; 256];
+                    let mut next = 1;
+
+                    for (n, tokens) in lines.iter().enumerate() {
+                        let line = line + n;
+                        let tokens: Vec<&[u8]> =
+                            tokens.iter().copied().filter(|t| *t != b"_").collect();
+
+                        if let [token] = tokens[..] {
+                            if let Some((first, last)) = csf_range(token, line)? {
+                                ensure!(
+                                    first <= last,
+                                    "line {line}: the range in `\\order` runs backwards"
+                                );
+
+                                for c in first..=last {
+                                    weights[c as usize] = next;
+                                    next += 1;
+                                }
+
+                                continue;
+                            }
+                        }
+
+                        for token in &tokens {
+                            ensure!(
+                                csf_range(token, line)?.is_none(),
+                                "line {line}: a range in `\\order` must be on a line of its own"
+                            );
+                            weights[csf_char(token, line)? as usize] = next;
+                        }
+
+                        if !tokens.is_empty() {
+                            next += 1;
+                        }
+                    }
+
+                    for (c, w) in weights.iter_mut().enumerate() {
+                        if *w == 0 {
+                            *w = next + c as u16;
+                        }
+                    }
+
+                    info.order = Some(weights);
+                }
+
+                _ => bail!("line {line}: unknown section `\\{name}`"),
+            }
+
+            line += count_lines(&body[..end]);
+            rest = &body[end + 1..];
+        }
+
+        Ok(info)
+    }
+
+    /// Get the `LexClass` of a character.
+    pub fn lex_class(&self, c: ASCIICode) -> LexClass {
+        self.lex_class[c as usize]
+    }
+
+    pub fn make_lowercase(&self, text: &mut [u8]) {
+        for c in text {
+            *c = self.lower[*c as usize];
+        }
+    }
+
+    pub fn make_uppercase(&self, text: &mut [u8]) {
+        for c in text {
+            *c = self.upper[*c as usize];
+        }
+    }
+
+    /// Whether the sort order has been customized.
+    pub fn has_order(&self) -> bool {
+        self.order.is_some()
+    }
+
+    /// Compute the key by which *text* sorts in the customized order.
+    /// Comparing the keys of two texts byte by byte compares the texts.
+    pub fn sort_key(&self, text: &[u8]) -> Vec<u8> {
+        match &self.order {
+            Some(weights) => text
+                .iter()
+                .flat_map(|&c| weights[c as usize].to_be_bytes())
+                .collect(),
+            None => text.to_vec(),
+        }
+    }
+}
+
+/// Skip whitespace and comments in a `.csf` file.
+fn skip_csf_space(mut text: &[u8]) -> &[u8] {
+    loop {
+        text = text.trim_ascii_start();
+
+        match text.strip_prefix(b"%") {
+            Some(comment) => {
+                let end = comment
+                    .iter()
+                    .position(|&c| c == b'\n')
+                    .unwrap_or(comment.len());
+                text = &comment[end..];
+            }
+            None => return text,
+        }
+    }
+}
+
+fn count_lines(text: &[u8]) -> usize {
+    text.iter().filter(|&&c| c == b'\n').count()
+}
+
+/// Parse a character of a `.csf` file.
+fn csf_char(token: &[u8], line: usize) -> Result<u8> {
+    match token {
+        [c] => Ok(*c),
+        [b'^', b'^', hi, lo] => {
+            let hex = |d: u8| (d as char).to_digit(16).filter(|_| !d.is_ascii_uppercase());
+
+            match (hex(*hi), hex(*lo)) {
+                (Some(hi), Some(lo)) => Ok((hi * 16 + lo) as u8),
+                _ => bail!(
+                    "line {line}: `{}` isn't a character",
+                    String::from_utf8_lossy(token)
+                ),
+            }
+        }
+        _ => bail!(
+            "line {line}: `{}` isn't a character",
+            String::from_utf8_lossy(token)
+        ),
+    }
+}
+
+/// Parse a range of characters like `a-z` in a `.csf` file, if *token* is
+/// one.
+fn csf_range(token: &[u8], line: usize) -> Result<Option<(u8, u8)>> {
+    if token.len() < 3 {
+        return Ok(None);
+    }
+
+    let split = if token.starts_with(b"^^") { 4 } else { 1 };
+
+    match token.get(split..) {
+        Some([b'-', last @ ..]) if !last.is_empty() => Ok(Some((
+            csf_char(&token[..split], line)?,
+            csf_char(last, line)?,
+        ))),
+        _ => Ok(None),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSF: &[u8] = br"% A Latin-1 fragment.
+\lowupcase{
+  ^^e4 ^^c4   % a-umlaut
+}
+\lowercase{ ^^df ^^df }
+\order{
+  0-9
+  a _ A ^^e4 ^^c4
+  b B
+}
+";
+
+    #[test]
+    fn read_csf() {
+        let info = CharInfo::from_csf(CSF).unwrap();
+
+        let mut text = b"\xe4b\xc4".to_vec();
+        info.make_uppercase(&mut text);
+        assert_eq!(text, b"\xc4B\xc4");
+        info.make_lowercase(&mut text);
+        assert_eq!(text, b"\xe4b\xe4");
+        assert_eq!(info.lex_class(0xdf), LexClass::Alpha);
+
+        let key = |s: &[u8]| info.sort_key(s);
+        assert!(key(b"9") < key(b"a"));
+        assert_eq!(key(b"\xe4"), key(b"A"));
+        assert!(key(b"\xc4z") < key(b"b"));
+        assert!(key(b"b") < key(b"c"));
+        assert!(key(b"c") < key(b"d"));
+    }
+
+    #[test]
+    fn bad_csf() {
+        let err = |text: &[u8]| CharInfo::from_csf(text).unwrap_err().to_string();
+
+        assert_eq!(
+            err(b"\\lowupcase{\n a \n}\n\\order{}"),
+            "line 2: characters in `\\lowupcase` must come in pairs"
+        );
+        assert_eq!(
+            err(b"\n\n\\codepage{ }"),
+            "line 3: unknown section `\\codepage`"
+        );
+        assert_eq!(
+            err(b"\\order{\n a z-a \n}"),
+            "line 2: a range in `\\order` must be on a line of its own"
+        );
+        assert_eq!(err(b"\\order{ ^^zz }"), "line 1: `^^zz` isn't a character");
+        assert_eq!(
+            err(b"\\order{ a"),
+            "line 1: the `\\order` section isn't closed with `}`"
+        );
+    }
+}
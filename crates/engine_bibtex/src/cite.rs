@@ -1,19 +1,124 @@
 use crate::{
+    char_info::CharInfo,
+    collate::Locale,
     entries::EntryData,
     hash::{HashData, HashExtra},
+    log::has_database_extension,
     other::OtherData,
     pool::{StrNumber, StringPool},
-    CiteNumber, FindCiteLocs, HashPointer, StrIlk,
+    Bibtex, CiteNumber, FindCiteLocs, HashPointer, StrIlk,
 };
-use std::{cmp::Ordering, ops::IndexMut};
+use std::{cmp::Ordering, ops::IndexMut, time::SystemTime};
 
 pub(crate) const MAX_CITES: usize = 750;
 
+/// Where an entry was defined: the name of its database file, as given in the
+/// `.aux` file, and the line where the entry starts.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct EntrySource {
+    pub file: StrNumber,
+    pub line: u32,
+}
+
+impl EntrySource {
+    pub(crate) fn location(&self, ctx: &Bibtex<'_, '_>, pool: &StringPool) -> EntryLocation {
+        let name = pool.get_str(self.file);
+        let mut file = String::from_utf8_lossy(name).into_owned();
+
+        if !has_database_extension(ctx, name) {
+            file.push_str(".bib");
+        }
+
+        EntryLocation {
+            file,
+            line: self.line,
+        }
+    }
+}
+
+/// Where a database entry was defined.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntryLocation {
+    /// The name of the database file, as given in the `.aux` file, with the
+    /// `.bib` extension added if it was left out.
+    pub file: String,
+
+    /// The line of the file where the entry starts.
+    pub line: u32,
+}
+
+/// A key cited by the document, as returned in a [`CitationReport`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Citation {
+    /// The cite key, as it was cited.
+    pub cite_key: String,
+
+    /// Where the database entry that satisfied the citation was defined, or
+    /// `None` if no database had an entry for the key.
+    pub entry: Option<EntryLocation>,
+}
+
+/// A database entry that was ignored because an earlier entry had the same
+/// key, ignoring case.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RepeatedEntry {
+    /// The key of the entry, as it was written.
+    pub cite_key: String,
+
+    /// Where the ignored entry was defined.
+    pub location: EntryLocation,
+
+    /// Where the entry that was used instead was defined, if it's known. It
+    /// isn't known in low-memory mode with biblatex output.
+    pub first: Option<EntryLocation>,
+}
+
+/// The citations of a run, once the databases have been read, as returned by
+/// [`BibtexEngine::take_citation_report`].
+///
+/// [`BibtexEngine::take_citation_report`]: crate::BibtexEngine::take_citation_report
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CitationReport {
+    /// When the report was made. This is the [build
+    /// date](crate::BibtexEngine::build_date) if one was set, so that runs
+    /// with the same inputs give the same report.
+    pub generated: SystemTime,
+
+    /// An identifier for the run, from the [id
+    /// source](crate::BibtexEngine::id_source) if one was set. Otherwise
+    /// it's random, and the same for runs with the same [RNG
+    /// seed](crate::BibtexEngine::rng_seed).
+    pub run_id: String,
+
+    /// The keys cited by the document, in the order in which they were first
+    /// cited. Each key appears once. A `\nocite{*}` doesn't appear here; see
+    /// [`Self::all_entries`].
+    pub citations: Vec<Citation>,
+
+    /// Whether the document cited all of the entries with `\nocite{*}`.
+    pub all_entries: bool,
+
+    /// The entries that were ignored because their keys were repeated, in
+    /// the order in which they were read.
+    pub repeated_entries: Vec<RepeatedEntry>,
+}
+
+impl CitationReport {
+    /// Iterate over the citations that no database entry satisfied.
+    pub fn unresolved(&self) -> impl Iterator<Item = &Citation> {
+        self.citations.iter().filter(|c| c.entry.is_none())
+    }
+}
+
 pub(crate) struct CiteInfo {
     cite_list: Vec<StrNumber>,
     cite_info: Vec<StrNumber>,
     type_list: Vec<HashPointer>,
     entry_exists: Vec<bool>,
+    sources: Vec<Option<EntrySource>>,
+    /// Whether each entry is an `@xdata` entry, which only exists to supply
+    /// fields to other entries.
+    xdata: Vec<bool>,
     cite_ptr: CiteNumber,
 
     entry_cite_ptr: CiteNumber,
@@ -29,6 +134,8 @@ impl CiteInfo {
             cite_info: vec![StrNumber::invalid(); MAX_CITES + 1],
             type_list: vec![0; MAX_CITES + 1],
             entry_exists: vec![false; MAX_CITES + 1],
+            sources: vec![None; MAX_CITES + 1],
+            xdata: vec![false; MAX_CITES + 1],
             cite_ptr: 0,
             entry_cite_ptr: 0,
             num_cites: 0,
@@ -45,6 +152,19 @@ impl CiteInfo {
         self.type_list.resize(self.type_list.len() + MAX_CITES, 0);
         self.entry_exists
             .resize(self.entry_exists.len() + MAX_CITES, false);
+        self.sources.resize(self.sources.len() + MAX_CITES, None);
+        self.xdata.resize(self.xdata.len() + MAX_CITES, false);
+    }
+
+    /// Make room for at least *cites* cites.
+    pub fn reserve(&mut self, cites: usize) {
+        let len = self.cite_list.len().max(cites + 1);
+        self.cite_list.resize(len, StrNumber::invalid());
+        self.cite_info.resize(len, StrNumber::invalid());
+        self.type_list.resize(len, 0);
+        self.entry_exists.resize(len, false);
+        self.sources.resize(len, None);
+        self.xdata.resize(len, false);
     }
 
     pub fn get_cite(&self, offset: usize) -> StrNumber {
@@ -79,6 +199,22 @@ impl CiteInfo {
         self.entry_exists[offset] = exists;
     }
 
+    pub fn source(&self, offset: usize) -> Option<EntrySource> {
+        self.sources[offset]
+    }
+
+    pub fn set_source(&mut self, offset: usize, source: EntrySource) {
+        self.sources[offset] = Some(source);
+    }
+
+    pub fn is_xdata(&self, offset: usize) -> bool {
+        self.xdata[offset]
+    }
+
+    pub fn set_xdata(&mut self, offset: usize, xdata: bool) {
+        self.xdata[offset] = xdata;
+    }
+
     pub fn ptr(&self) -> CiteNumber {
         self.cite_ptr
     }
@@ -123,23 +259,46 @@ impl CiteInfo {
         self.all_marker = val;
     }
 
-    pub fn sort_info<I>(&mut self, entries: &EntryData, r: I)
-    where
+    pub fn sort_info<I>(
+        &mut self,
+        entries: &EntryData,
+        r: I,
+        collation: Option<Locale>,
+        chars: &CharInfo,
+    ) where
         Vec<StrNumber>: IndexMut<I, Output = [StrNumber]>,
     {
-        self.cite_info[r]
-            .sort_by(|a, b| less_than(entries, a.to_raw_dangerous(), b.to_raw_dangerous()))
+        match collation {
+            None if chars.has_order() => self.cite_info[r].sort_by_cached_key(|c| {
+                let key = sort_key(entries, c.to_raw_dangerous());
+                (chars.sort_key(key), key.to_vec())
+            }),
+
+            None => self.cite_info[r]
+                .sort_by(|a, b| less_than(entries, a.to_raw_dangerous(), b.to_raw_dangerous())),
+
+            // Keys that collate the same still have a predictable order.
+            Some(locale) => self.cite_info[r].sort_by_cached_key(|c| {
+                let key = sort_key(entries, c.to_raw_dangerous());
+                (locale.sort_key(key), key.to_vec())
+            }),
+        }
+    }
+
+    /// Sort the entries by keys computed from their cite numbers. Entries
+    /// with equal keys keep their order.
+    pub fn sort_info_by_key<K: Ord>(&mut self, mut key: impl FnMut(CiteNumber) -> K) {
+        self.cite_info[..self.num_cites].sort_by_key(|c| key(c.to_raw_dangerous()));
     }
 }
 
 fn less_than(entries: &EntryData, arg1: CiteNumber, arg2: CiteNumber) -> Ordering {
-    let ptr1 = arg1 * entries.num_ent_strs() + entries.sort_key_num();
-    let ptr2 = arg2 * entries.num_ent_strs() + entries.sort_key_num();
-
-    let str1 = entries.strs(ptr1);
-    let str2 = entries.strs(ptr2);
+    Ord::cmp(sort_key(entries, arg1), sort_key(entries, arg2))
+}
 
-    Ord::cmp(str1, str2)
+/// Get the `sort.key$` of an entry.
+fn sort_key(entries: &EntryData, cite: CiteNumber) -> &[u8] {
+    entries.strs(cite * entries.num_ent_strs() + entries.sort_key_num())
 }
 
 pub(crate) fn add_database_cite(
@@ -178,3 +337,113 @@ pub(crate) fn find_cite_locs_for_this_cite_key(
         lc_found: lc_cite_hash.exists,
     }
 }
+
+/// The entries reached by following `crossref` fields from an entry.
+pub(crate) struct CrossrefChain {
+    /// The entries that were referred to, directly or indirectly, in order.
+    /// This doesn't include the starting entry.
+    pub ancestors: Vec<CiteNumber>,
+
+    /// If the chain was cut short because it referred back to an entry that
+    /// was already in it, that entry.
+    pub loops_back_to: Option<CiteNumber>,
+}
+
+/// Find the cite whose key matches *key*, ignoring case.
+pub(crate) fn find_cite(pool: &StringPool, hash: &HashData, key: StrNumber) -> Option<CiteNumber> {
+    let find = find_cite_locs_for_this_cite_key(pool, hash, key);
+    if !find.lc_found {
+        return None;
+    }
+
+    let HashExtra::LcCite(cite_loc) = hash.node(find.lc_cite_loc).extra else {
+        return None;
+    };
+    match hash.node(cite_loc).extra {
+        HashExtra::Cite(cite) => Some(cite),
+        _ => None,
+    }
+}
+
+fn crossref_parent(
+    pool: &StringPool,
+    hash: &HashData,
+    other: &OtherData,
+    cite_ptr: CiteNumber,
+) -> Option<CiteNumber> {
+    let field = other.field(cite_ptr * other.num_fields() + other.crossref_num());
+    if field.is_invalid() {
+        return None;
+    }
+
+    find_cite(pool, hash, field)
+}
+
+/// Build the citations of a [`CitationReport`] once the databases have been
+/// read, before the cites are compacted. *cited* gives the keys cited in the
+/// `.aux` file.
+pub(crate) fn report_citations(
+    ctx: &Bibtex<'_, '_>,
+    pool: &StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+    cited: &[StrNumber],
+) -> Vec<Citation> {
+    cited
+        .iter()
+        .map(|&key| {
+            let key_text = pool.get_str(key);
+
+            // When all entries are read, the cites of missing keys can be
+            // reused by other entries, so check that the cite still has the
+            // key.
+            let entry = find_cite(pool, hash, key)
+                .filter(|&cite| {
+                    cites.get_type(cite) != 0
+                        && pool
+                            .get_str(cites.get_cite(cite))
+                            .eq_ignore_ascii_case(key_text)
+                })
+                .and_then(|cite| cites.source(cite))
+                .map(|source| source.location(ctx, pool));
+
+            Citation {
+                cite_key: String::from_utf8_lossy(key_text).into_owned(),
+                entry,
+            }
+        })
+        .collect()
+}
+
+/// Follow the `crossref` fields starting at *cite_ptr*, for at most
+/// *max_depth* levels. References to entries that don't exist end the chain.
+pub(crate) fn crossref_chain(
+    pool: &StringPool,
+    hash: &HashData,
+    other: &OtherData,
+    cite_ptr: CiteNumber,
+    max_depth: usize,
+) -> CrossrefChain {
+    let mut ancestors = Vec::new();
+    let mut loops_back_to = None;
+    let mut cur = cite_ptr;
+
+    while ancestors.len() < max_depth {
+        let Some(parent) = crossref_parent(pool, hash, other, cur) else {
+            break;
+        };
+
+        if parent == cite_ptr || ancestors.contains(&parent) {
+            loops_back_to = Some(parent);
+            break;
+        }
+
+        ancestors.push(parent);
+        cur = parent;
+    }
+
+    CrossrefChain {
+        ancestors,
+        loops_back_to,
+    }
+}
@@ -0,0 +1,606 @@
+//! A cache of the code of the functions that styles define, which can be
+//! saved to a file and loaded back for later runs.
+//!
+//! Where a function ends up in the hash table depends on the run -- the
+//! functions defined after `READ` are put in after the cite keys -- so the
+//! cache doesn't keep locations. Instead, it keeps the tokens of each
+//! function's body, with the names of the functions that it calls, keyed by
+//! the digest of the style file and by where in it the body starts. When the
+//! style defines a function that's in the cache, the body is linked by
+//! looking up those names, and its text is skipped rather than scanned.
+//!
+//! A definition is only cached if it was scanned without any problems, and
+//! only reused if every name that it calls is still defined and isn't the
+//! function itself, so the outcome is the same either way. The hash table is
+//! added to in the same order as when scanning, so that the rest of the run
+//! can't tell the difference.
+
+use crate::{
+    buffer::BufTy,
+    char_info::LexClass,
+    hash::{BstFn, HashData, HashExtra},
+    log::print_confusion,
+    peekable::input_ln,
+    scan::{define_wiz_fn, scan_fn_def, QUOTE_NEXT_FN},
+    Bibtex, BibtexError, BufPointer, FnDefLoc, GlobalItems, HashPointer, StrIlk,
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    io::{Read, Write},
+};
+use tectonic_errors::prelude::*;
+
+const MAGIC: &[u8; 8] = b"TTBSTC01";
+
+/// Deeper nesting of `{...}` blocks than this is taken to mean that a saved
+/// cache is corrupt.
+const MAX_BLOCK_DEPTH: usize = 1000;
+
+/// Counts of how well the code cache worked out.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CodeCacheStats {
+    /// The number of function definitions whose code was reused.
+    pub hits: u64,
+
+    /// The number of function definitions that had to be scanned.
+    pub misses: u64,
+}
+
+/// A cache of the code of the functions defined by styles, given to
+/// [`crate::BibtexEngine::code_cache`].
+///
+/// The cache can be saved with [`save()`](Self::save) and loaded with
+/// [`load()`](Self::load), so that it carries over between processes. The
+/// format of the file is private, and changes with the version of the
+/// engine; files written by other versions are ignored.
+#[derive(Debug, Default)]
+pub struct BstCodeCache {
+    /// The definitions of each style, keyed by its digest and then by the
+    /// line and offset at which each body starts.
+    styles: BTreeMap<String, BTreeMap<(u32, BufPointer), CachedFn>>,
+    stats: CodeCacheStats,
+}
+
+/// A function definition in the cache.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct CachedFn {
+    /// The offset in the line on which the body ends just after its closing
+    /// brace.
+    end_offset: BufPointer,
+    /// Where the line on which the body ends starts in the style's bytes.
+    end_pos: usize,
+    body: Body,
+}
+
+/// The body of a function, as its tokens and the lines on which they were
+/// written.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Body {
+    tokens: Vec<(Token, u32)>,
+    /// The line with the closing brace.
+    end_line: u32,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Token {
+    /// An integer literal, with its text and value.
+    Int(Vec<u8>, i64),
+    /// A string literal.
+    Str(Vec<u8>),
+    /// A function quoted with `'`.
+    Quote(Vec<u8>),
+    /// A `{...}` block, which defines an implicit function.
+    Block(Body),
+    /// A function call.
+    Call(Vec<u8>),
+}
+
+impl BstCodeCache {
+    /// Create an empty cache.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The number of function definitions in the cache.
+    pub fn len(&self) -> usize {
+        self.styles.values().map(BTreeMap::len).sum()
+    }
+
+    /// Whether the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How often the cache has been used since it was created or loaded.
+    pub fn stats(&self) -> CodeCacheStats {
+        self.stats
+    }
+
+    /// Save the cache. The definitions are written in a fixed order, so that
+    /// the same cache contents always give the same file.
+    ///
+    /// Returns the number of function definitions that were saved.
+    pub fn save<W: Write>(&self, mut dest: W) -> Result<usize> {
+        atry!(
+            dest.write_all(&self.encode());
+            ["failed to write the style code cache"]
+        );
+        Ok(self.len())
+    }
+
+    /// Load a cache saved by [`save()`](Self::save).
+    ///
+    /// If the file was saved by a different version of the engine, it's
+    /// ignored and `None` is returned.
+    pub fn load<R: Read>(mut src: R) -> Result<Option<Self>> {
+        let mut data = Vec::new();
+        atry!(
+            src.read_to_end(&mut data);
+            ["failed to read the style code cache"]
+        );
+        Self::decode(&data)
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(MAGIC);
+        data.extend_from_slice(&(self.styles.len() as u32).to_le_bytes());
+
+        for (digest, fns) in &self.styles {
+            put_bytes(&mut data, digest.as_bytes());
+            data.extend_from_slice(&(fns.len() as u32).to_le_bytes());
+
+            for (&(line, offset), func) in fns {
+                data.extend_from_slice(&line.to_le_bytes());
+                data.extend_from_slice(&(offset as u64).to_le_bytes());
+                data.extend_from_slice(&(func.end_offset as u64).to_le_bytes());
+                data.extend_from_slice(&(func.end_pos as u64).to_le_bytes());
+                put_body(&mut data, &func.body);
+            }
+        }
+
+        data
+    }
+
+    fn decode(mut data: &[u8]) -> Result<Option<Self>> {
+        if data.len() < MAGIC.len() || &data[..MAGIC.len()] != MAGIC {
+            return Ok(None);
+        }
+
+        data = &data[MAGIC.len()..];
+        let mut cache = BstCodeCache::default();
+
+        for _ in 0..take_u32(&mut data)? {
+            let digest = String::from_utf8(take_bytes(&mut data)?.to_vec())
+                .map_err(|_| anyhow!("the style code cache is corrupt"))?;
+            let mut fns = BTreeMap::new();
+
+            for _ in 0..take_u32(&mut data)? {
+                let line = take_u32(&mut data)?;
+                let offset = take_u64(&mut data)? as BufPointer;
+                let end_offset = take_u64(&mut data)? as BufPointer;
+                let end_pos = take_u64(&mut data)? as usize;
+                let body = take_body(&mut data, 0)?;
+                fns.insert(
+                    (line, offset),
+                    CachedFn {
+                        end_offset,
+                        end_pos,
+                        body,
+                    },
+                );
+            }
+
+            cache.styles.insert(digest, fns);
+        }
+
+        ensure!(data.is_empty(), "the style code cache has trailing data");
+        Ok(Some(cache))
+    }
+}
+
+fn put_bytes(data: &mut Vec<u8>, bytes: &[u8]) {
+    data.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+    data.extend_from_slice(bytes);
+}
+
+fn put_body(data: &mut Vec<u8>, body: &Body) {
+    data.extend_from_slice(&body.end_line.to_le_bytes());
+    data.extend_from_slice(&(body.tokens.len() as u32).to_le_bytes());
+
+    for (token, line) in &body.tokens {
+        data.extend_from_slice(&line.to_le_bytes());
+
+        match token {
+            Token::Int(text, value) => {
+                data.push(0);
+                put_bytes(data, text);
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            Token::Str(text) => {
+                data.push(1);
+                put_bytes(data, text);
+            }
+            Token::Quote(name) => {
+                data.push(2);
+                put_bytes(data, name);
+            }
+            Token::Block(body) => {
+                data.push(3);
+                put_body(data, body);
+            }
+            Token::Call(name) => {
+                data.push(4);
+                put_bytes(data, name);
+            }
+        }
+    }
+}
+
+fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+    ensure!(data.len() >= n, "the style code cache is truncated");
+    let (head, tail) = data.split_at(n);
+    *data = tail;
+    Ok(head)
+}
+
+fn take_u32(data: &mut &[u8]) -> Result<u32> {
+    Ok(u32::from_le_bytes(take(data, 4)?.try_into().unwrap()))
+}
+
+fn take_u64(data: &mut &[u8]) -> Result<u64> {
+    Ok(u64::from_le_bytes(take(data, 8)?.try_into().unwrap()))
+}
+
+fn take_bytes<'a>(data: &mut &'a [u8]) -> Result<&'a [u8]> {
+    let n = take_u32(data)? as usize;
+    take(data, n)
+}
+
+fn take_body(data: &mut &[u8], depth: usize) -> Result<Body> {
+    ensure!(
+        depth < MAX_BLOCK_DEPTH,
+        "the style code cache nests blocks too deeply"
+    );
+
+    let end_line = take_u32(data)?;
+    let n = take_u32(data)?;
+    let mut tokens = Vec::new();
+
+    for _ in 0..n {
+        let line = take_u32(data)?;
+
+        let token = match take(data, 1)?[0] {
+            0 => {
+                let text = take_bytes(data)?.to_vec();
+                Token::Int(text, take_u64(data)? as i64)
+            }
+            1 => Token::Str(take_bytes(data)?.to_vec()),
+            2 => Token::Quote(take_bytes(data)?.to_vec()),
+            3 => Token::Block(take_body(data, depth + 1)?),
+            4 => Token::Call(take_bytes(data)?.to_vec()),
+            tag => bail!("the style code cache has a token of unknown type {}", tag),
+        };
+
+        tokens.push((token, line));
+    }
+
+    Ok(Body { tokens, end_line })
+}
+
+/// Scan the body of the function at *fn_loc*, which starts at the current
+/// position in the style, or reuse its code from the cache if it's there. If
+/// the function is scanned, its code is added to the cache.
+pub(crate) fn scan_or_reuse_fn_def(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
+    let (Some(cache), Some(digest)) = (&ctx.code_cache, &ctx.bst_digest) else {
+        return scan_fn_def(ctx, globals, fn_loc, fn_loc);
+    };
+
+    let start = (
+        ctx.bst.as_ref().unwrap().line,
+        globals.buffers.offset(BufTy::Base, 2),
+    );
+
+    let mut names = Vec::new();
+    let cached = cache
+        .styles
+        .get(digest)
+        .and_then(|fns| fns.get(&start))
+        .filter(|func| resolve(globals, &func.body, fn_loc, &mut names));
+
+    if cached.is_some() {
+        // Take the cache out of the context while the code is linked, so that
+        // it doesn't need to be copied.
+        let mut cache = ctx.code_cache.take().unwrap();
+        let func = &cache.styles[ctx.bst_digest.as_deref().unwrap()][&start];
+        let res = reuse(ctx, globals, fn_loc, func, &mut names.into_iter());
+        cache.stats.hits += 1;
+        ctx.code_cache = Some(cache);
+        return res;
+    }
+
+    let first_wiz = globals.other.wiz_func_len();
+    let problems = ctx.n_errors + ctx.n_warnings;
+
+    scan_fn_def(ctx, globals, fn_loc, fn_loc)?;
+
+    let digest = ctx.bst_digest.clone().unwrap();
+    let cache = ctx.code_cache.as_mut().unwrap();
+    cache.stats.misses += 1;
+
+    // A definition with problems would give different messages if it were
+    // reused, and text after its end on the same line might be shown in
+    // later messages, which would differ as scanning lowercases the names
+    // that it calls in place.
+    if ctx.n_errors + ctx.n_warnings != problems || !rest_of_line_is_blank(globals) {
+        return Ok(());
+    }
+
+    let HashExtra::BstFn(BstFn::Wizard(wiz)) = globals.hash.node(fn_loc).extra else {
+        return Ok(());
+    };
+
+    let Some(end_pos) = ctx.bst.as_ref().unwrap().file.line_span() else {
+        return Ok(());
+    };
+
+    if let Some(body) = unlink(globals, wiz, first_wiz, &mut HashSet::new()) {
+        let end_offset = globals.buffers.offset(BufTy::Base, 2);
+        cache.styles.entry(digest).or_default().insert(
+            start,
+            CachedFn {
+                end_offset,
+                end_pos: end_pos.start,
+                body,
+            },
+        );
+    }
+
+    Ok(())
+}
+
+fn rest_of_line_is_blank(globals: &GlobalItems<'_>) -> bool {
+    let buffers = &globals.buffers;
+    buffers.buffer(BufTy::Base)[buffers.offset(BufTy::Base, 2)..buffers.init(BufTy::Base)]
+        .iter()
+        .find(|&&c| LexClass::of(c) != LexClass::Whitespace)
+        .is_none_or(|&c| c == b'%')
+}
+
+/// Turn the body of the wizard function at *wiz* back into tokens. Blocks are
+/// recognized as the implicit functions defined since *first_wiz* when they
+/// first appear. Returns `None` if the body quotes such a function again,
+/// which can't be linked by name before it's been defined.
+fn unlink(
+    globals: &GlobalItems<'_>,
+    mut wiz: FnDefLoc,
+    first_wiz: FnDefLoc,
+    blocks: &mut HashSet<HashPointer>,
+) -> Option<Body> {
+    let name = |loc: HashPointer| globals.pool.get_str(globals.hash.text(loc)).to_vec();
+    let mut tokens = Vec::new();
+
+    loop {
+        let loc = globals.other.wiz_function(wiz);
+        let line = globals.other.wiz_line(wiz);
+
+        if loc == HashData::end_of_def() {
+            return Some(Body {
+                tokens,
+                end_line: line,
+            });
+        }
+
+        let token = if loc == QUOTE_NEXT_FN {
+            wiz += 1;
+            let quoted = globals.other.wiz_function(wiz);
+
+            match globals.hash.node(quoted).extra {
+                HashExtra::BstFn(BstFn::Wizard(inner)) if inner >= first_wiz => {
+                    if !blocks.insert(quoted) {
+                        return None;
+                    }
+                    Token::Block(unlink(globals, inner, first_wiz, blocks)?)
+                }
+                _ => Token::Quote(name(quoted)),
+            }
+        } else {
+            match globals.hash.node(loc).extra {
+                HashExtra::Integer(value) => Token::Int(name(loc), value),
+                HashExtra::Text => Token::Str(name(loc)),
+                _ => Token::Call(name(loc)),
+            }
+        };
+
+        tokens.push((token, line));
+        wiz += 1;
+    }
+}
+
+/// Look up the functions that *body* calls or quotes by name, in order,
+/// appending them to *names*. Returns whether they're all defined, as they
+/// must be for scanning it to succeed.
+fn resolve(
+    globals: &GlobalItems<'_>,
+    body: &Body,
+    fn_loc: HashPointer,
+    names: &mut Vec<HashPointer>,
+) -> bool {
+    body.tokens.iter().all(|(token, _)| match token {
+        Token::Int(..) | Token::Str(_) => true,
+        Token::Block(body) => resolve(globals, body, fn_loc, names),
+        Token::Quote(name) | Token::Call(name) => {
+            let res = globals.hash.lookup_str(globals.pool, name, StrIlk::BstFn);
+            names.push(res.loc);
+            res.exists && res.loc != fn_loc
+        }
+    })
+}
+
+/// Define the function at *fn_loc* with the cached code, and move past its
+/// text in the style. *names* are the functions it refers to, as found by
+/// [`resolve`].
+fn reuse(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+    func: &CachedFn,
+    names: &mut impl Iterator<Item = HashPointer>,
+) -> Result<(), BibtexError> {
+    link(ctx, globals, fn_loc, &func.body, names)?;
+
+    // The style is the one that the definition was cached from, so the lines
+    // before the one that it ends on can be skipped without reading them.
+    let mut bst = ctx.bst.take().unwrap();
+
+    if bst.line < func.body.end_line {
+        let skipped = bst.file.skip_to(ctx, func.end_pos);
+
+        if skipped.is_err() || !input_ln(ctx.engine, &mut bst.file, globals.buffers) {
+            ctx.bst = Some(bst);
+            print_confusion(ctx);
+            return Err(BibtexError::Fatal);
+        }
+        bst.line = func.body.end_line;
+    }
+
+    ctx.bst = Some(bst);
+
+    globals.buffers.set_offset(BufTy::Base, 2, func.end_offset);
+    Ok(())
+}
+
+/// Define the function at *fn_loc* with the given body, adding literals and
+/// implicit functions to the hash table as scanning it would.
+fn link(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    fn_loc: HashPointer,
+    body: &Body,
+    names: &mut impl Iterator<Item = HashPointer>,
+) -> Result<(), BibtexError> {
+    let mut single_function = Vec::with_capacity(body.tokens.len() + 1);
+
+    for (token, line) in &body.tokens {
+        match token {
+            Token::Int(text, value) => {
+                let res = globals.hash.lookup_str_insert(
+                    ctx,
+                    globals.pool,
+                    text,
+                    HashExtra::Integer(*value),
+                )?;
+                single_function.push((res.loc, *line));
+            }
+            Token::Str(text) => {
+                let res =
+                    globals
+                        .hash
+                        .lookup_str_insert(ctx, globals.pool, text, HashExtra::Text)?;
+                single_function.push((res.loc, *line));
+            }
+            Token::Quote(_) => {
+                single_function.push((QUOTE_NEXT_FN, *line));
+                single_function.push((names.next().unwrap(), *line));
+            }
+            Token::Block(inner) => {
+                let str = format!("'{}", ctx.impl_fn_num);
+                let res = globals.hash.lookup_str_insert(
+                    ctx,
+                    globals.pool,
+                    str.as_bytes(),
+                    HashExtra::BstFn(BstFn::Wizard(0)),
+                )?;
+
+                if res.exists {
+                    ctx.write_logs("Already encountered implicit function");
+                    print_confusion(ctx);
+                    return Err(BibtexError::Fatal);
+                }
+                ctx.impl_fn_num += 1;
+
+                single_function.push((QUOTE_NEXT_FN, *line));
+                single_function.push((res.loc, *line));
+
+                link(ctx, globals, res.loc, inner, names)?;
+            }
+            Token::Call(_) => {
+                single_function.push((names.next().unwrap(), *line));
+            }
+        }
+    }
+
+    single_function.push((HashData::end_of_def(), body.end_line));
+    define_wiz_fn(globals, fn_loc, single_function);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> BstCodeCache {
+        let body = Body {
+            tokens: vec![
+                (Token::Int(b"1".to_vec(), 1), 3),
+                (Token::Str(b"text".to_vec()), 3),
+                (
+                    Token::Block(Body {
+                        tokens: vec![(Token::Call(b"write$".to_vec()), 4)],
+                        end_line: 4,
+                    }),
+                    4,
+                ),
+                (Token::Quote(b"skip$".to_vec()), 5),
+                (Token::Call(b"if$".to_vec()), 5),
+            ],
+            end_line: 6,
+        };
+
+        let mut fns = BTreeMap::new();
+        fns.insert(
+            (2, 17),
+            CachedFn {
+                end_offset: 1,
+                end_pos: 90,
+                body,
+            },
+        );
+
+        let mut cache = BstCodeCache::new();
+        cache.styles.insert("0123abcd".to_owned(), fns);
+        cache
+    }
+
+    #[test]
+    fn round_trip() {
+        let cache = sample();
+        let mut data = Vec::new();
+        assert_eq!(cache.save(&mut data).unwrap(), 1);
+
+        let loaded = BstCodeCache::load(&data[..]).unwrap().unwrap();
+        assert_eq!(loaded.styles, cache.styles);
+        assert_eq!(loaded.encode(), data);
+    }
+
+    #[test]
+    fn other_versions_ignored() {
+        let mut data = sample().encode();
+        data[7] = b'0';
+        assert!(BstCodeCache::load(&data[..]).unwrap().is_none());
+    }
+
+    #[test]
+    fn corrupt() {
+        let data = sample().encode();
+        assert!(BstCodeCache::load(&data[..data.len() - 1]).is_err());
+
+        let mut extended = data.clone();
+        extended.push(0);
+        assert!(BstCodeCache::load(&extended[..]).is_err());
+    }
+}
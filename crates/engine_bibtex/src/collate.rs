@@ -0,0 +1,400 @@
+//! Locale-aware collation of sort keys.
+//!
+//! Classic BibTeX sorts entries by the bytes of their sort keys. That's fine
+//! for English, but letters with accents, which the classic styles leave as
+//! UTF-8, all sort after `z`, and languages that put some letters in places
+//! of their own, such as Swedish with `å`, `ä`, and `ö` after `z`, can't be
+//! accommodated. A [`Locale`] compares sort keys the way that a reader of a
+//! language expects instead.
+//!
+//! The comparison roughly follows the Unicode Collation Algorithm, with the
+//! tailorings of the Unicode CLDR for each language. Texts are compared by
+//! their letters first, ignoring accents and case, then by their accents,
+//! and then by their case, with lowercase first. Whitespace sorts before
+//! punctuation, which sorts before digits, which sort before letters, and
+//! characters of other scripts sort after Latin letters, in the order of
+//! their code points.
+
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// A language whose conventions are used to sort entries.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Locale {
+    /// The language-neutral order, which suits English, French, Italian,
+    /// Dutch, and many other languages. Accented letters sort with their
+    /// base letters, and ligatures such as `æ` and `ß` as the letters that
+    /// they join.
+    Root,
+
+    /// German, in the dictionary order: `ä`, `ö`, and `ü` sort as `a`, `o`,
+    /// and `u`. This is the same as the root order.
+    German,
+
+    /// German, in the phone book order: `ä`, `ö`, and `ü` sort as `ae`,
+    /// `oe`, and `ue`.
+    GermanPhonebook,
+
+    /// Swedish and Finnish: `å`, `ä`, and `ö` are letters of their own after
+    /// `z`, and `æ` and `ø` sort with `ä` and `ö`.
+    Swedish,
+
+    /// Danish and Norwegian: `æ`, `ø`, and `å` are letters of their own after
+    /// `z`, `ä` and `ö` sort with `æ` and `ø`, and `aa` sorts with `å`.
+    Danish,
+
+    /// Spanish: `ñ` is a letter of its own after `n`.
+    Spanish,
+
+    /// Czech: `č`, `ř`, `š`, and `ž` are letters of their own after their
+    /// base letters, and `ch` is one after `h`.
+    Czech,
+
+    /// Polish: `ą`, `ć`, `ę`, `ł`, `ń`, `ó`, `ś`, `ź`, and `ż` are letters
+    /// of their own after their base letters.
+    Polish,
+}
+
+impl FromStr for Locale {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s.to_ascii_lowercase().replace('_', "-").as_str() {
+            "root" | "und" | "en" => Locale::Root,
+            "de" => Locale::German,
+            "de-phonebook" => Locale::GermanPhonebook,
+            "sv" | "fi" => Locale::Swedish,
+            "da" | "nb" | "nn" | "no" => Locale::Danish,
+            "es" => Locale::Spanish,
+            "cs" => Locale::Czech,
+            "pl" => Locale::Polish,
+            _ => bail!(
+                "unknown locale `{}`; expected one of `root`, `en`, `de`, `de-phonebook`, `sv`, \
+                 `fi`, `da`, `nb`, `nn`, `no`, `es`, `cs`, or `pl`",
+                s
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Locale {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Locale::Root => "root",
+            Locale::German => "de",
+            Locale::GermanPhonebook => "de-phonebook",
+            Locale::Swedish => "sv",
+            Locale::Danish => "da",
+            Locale::Spanish => "es",
+            Locale::Czech => "cs",
+            Locale::Polish => "pl",
+        })
+    }
+}
+
+/// How a tailored letter, or sequence of letters, is weighted.
+#[derive(Clone, Copy, Debug)]
+enum Weight {
+    /// Sort as a letter some steps after a base letter, with a secondary
+    /// weight to tell apart letters that are in the same place. Zero steps
+    /// means with the base letter.
+    After(char, u32, u32),
+
+    /// Sort as a sequence of base letters, with a secondary weight.
+    Expand(&'static str, u32),
+}
+
+use Weight::{After, Expand};
+
+/// Letters that don't decompose into base letters and accents, but which
+/// sort with base letters in the root order.
+const ROOT: &[(&str, Weight)] = &[
+    ("ß", Expand("ss", 2)),
+    ("æ", Expand("ae", 2)),
+    ("œ", Expand("oe", 2)),
+    ("þ", Expand("th", 2)),
+    ("ð", After('d', 0, 2)),
+    ("đ", After('d', 0, 3)),
+    ("ı", After('i', 0, 2)),
+    ("ł", After('l', 0, 2)),
+    ("ø", After('o', 0, 2)),
+];
+
+const GERMAN_PHONEBOOK: &[(&str, Weight)] = &[
+    ("ä", Expand("ae", 3)),
+    ("ö", Expand("oe", 3)),
+    ("ü", Expand("ue", 3)),
+];
+
+const SWEDISH: &[(&str, Weight)] = &[
+    ("å", After('z', 1, 1)),
+    ("ä", After('z', 2, 1)),
+    ("æ", After('z', 2, 2)),
+    ("ö", After('z', 3, 1)),
+    ("ø", After('z', 3, 2)),
+    ("ü", After('y', 0, 2)),
+];
+
+const DANISH: &[(&str, Weight)] = &[
+    ("aa", After('z', 3, 2)),
+    ("æ", After('z', 1, 1)),
+    ("ä", After('z', 1, 2)),
+    ("ø", After('z', 2, 1)),
+    ("ö", After('z', 2, 2)),
+    ("å", After('z', 3, 1)),
+];
+
+const SPANISH: &[(&str, Weight)] = &[("ñ", After('n', 1, 1))];
+
+const CZECH: &[(&str, Weight)] = &[
+    ("ch", After('h', 1, 1)),
+    ("č", After('c', 1, 1)),
+    ("ř", After('r', 1, 1)),
+    ("š", After('s', 1, 1)),
+    ("ž", After('z', 1, 1)),
+];
+
+const POLISH: &[(&str, Weight)] = &[
+    ("ą", After('a', 1, 1)),
+    ("ć", After('c', 1, 1)),
+    ("ę", After('e', 1, 1)),
+    ("ł", After('l', 1, 1)),
+    ("ń", After('n', 1, 1)),
+    ("ó", After('o', 1, 1)),
+    ("ś", After('s', 1, 1)),
+    ("ź", After('z', 1, 1)),
+    ("ż", After('z', 2, 1)),
+];
+
+// Primary weights. Each base letter leaves room after it for the letters
+// that tailorings put there.
+const SPACE: u32 = 0x100;
+const PUNCTUATION: u32 = 0x200;
+const DIGIT: u32 = 0x400;
+const LETTER: u32 = 0x1000;
+const OTHER: u32 = 0x10000;
+const INVALID: u32 = 0x200000;
+
+// Secondary and tertiary weights.
+const NO_ACCENT: u32 = 1;
+const ACCENT: u32 = 0x10;
+const LOWER: u32 = 1;
+const UPPER: u32 = 2;
+
+fn letter(c: char) -> u32 {
+    LETTER + (c as u32 - 'a' as u32) * 16
+}
+
+/// A collation element: primary, secondary, and tertiary weights. Zero
+/// weights are ignored at their level.
+type Element = (u32, u32, u32);
+
+impl Locale {
+    fn tailoring(&self) -> &'static [(&'static str, Weight)] {
+        match self {
+            Locale::Root | Locale::German => &[],
+            Locale::GermanPhonebook => GERMAN_PHONEBOOK,
+            Locale::Swedish => SWEDISH,
+            Locale::Danish => DANISH,
+            Locale::Spanish => SPANISH,
+            Locale::Czech => CZECH,
+            Locale::Polish => POLISH,
+        }
+    }
+
+    /// Compute the collation key of some text. Comparing the keys of two
+    /// texts byte by byte compares the texts in this locale's order. Bytes
+    /// that aren't valid UTF-8 sort after everything else.
+    pub(crate) fn sort_key(&self, text: &[u8]) -> Vec<u8> {
+        let mut elements = Vec::with_capacity(text.len());
+
+        for chunk in text.utf8_chunks() {
+            let chars: Vec<char> = chunk.valid().nfc().collect();
+            let mut i = 0;
+
+            while i < chars.len() {
+                i += self.push_elements(&chars[i..], &mut elements);
+            }
+
+            for b in chunk.invalid() {
+                elements.push((INVALID + *b as u32, NO_ACCENT, LOWER));
+            }
+        }
+
+        let mut key = Vec::with_capacity(elements.len() * 12 + 8);
+
+        for level in 0..3 {
+            if level > 0 {
+                key.extend_from_slice(&0u32.to_be_bytes());
+            }
+
+            for e in &elements {
+                let w = [e.0, e.1, e.2][level];
+
+                if w != 0 {
+                    key.extend_from_slice(&w.to_be_bytes());
+                }
+            }
+        }
+
+        key
+    }
+
+    /// Push the collation elements of the start of *chars*, returning the
+    /// number of characters consumed.
+    fn push_elements(&self, chars: &[char], elements: &mut Vec<Element>) -> usize {
+        let case = if chars[0].is_uppercase() {
+            UPPER
+        } else {
+            LOWER
+        };
+
+        for (pattern, weight) in self.tailoring().iter().chain(ROOT) {
+            let n = pattern.chars().count();
+
+            if chars.len() < n
+                || !chars[..n]
+                    .iter()
+                    .flat_map(|c| c.to_lowercase())
+                    .eq(pattern.chars())
+            {
+                continue;
+            }
+
+            match *weight {
+                After(base, steps, secondary) => {
+                    elements.push((letter(base) + steps, secondary, case));
+                }
+
+                Expand(letters, secondary) => {
+                    for c in letters.chars() {
+                        elements.push((letter(c), secondary, case));
+                    }
+                }
+            }
+
+            return n;
+        }
+
+        // Otherwise, split the character into a base character and accents.
+
+        let mut parts = chars[0].nfd();
+        let base = parts.next().unwrap();
+        let primary = if base.is_whitespace() {
+            SPACE
+        } else if base.is_ascii_digit() {
+            DIGIT + (base as u32 - '0' as u32)
+        } else if base.is_ascii_alphabetic() {
+            letter(base.to_ascii_lowercase())
+        } else if is_combining_mark(base) {
+            0
+        } else if base.is_ascii() {
+            PUNCTUATION + base as u32
+        } else {
+            OTHER + base.to_lowercase().next().unwrap_or(base) as u32
+        };
+
+        if primary == 0 {
+            elements.push((0, ACCENT + base as u32, 0));
+        } else {
+            elements.push((primary, NO_ACCENT, case));
+        }
+
+        for mark in parts {
+            elements.push((0, ACCENT + mark as u32, 0));
+        }
+
+        1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sorted(locale: Locale, words: &[&str]) -> Vec<String> {
+        let mut words: Vec<String> = words.iter().map(|w| w.to_string()).collect();
+        words.sort_by_cached_key(|w| locale.sort_key(w.as_bytes()));
+        words
+    }
+
+    #[test]
+    fn root() {
+        assert_eq!(
+            sorted(
+                Locale::Root,
+                &["zebra", "résumé", "Resume", "resume", "Øre", "orb", "10 a", "apple", "a b"]
+            ),
+            ["10 a", "a b", "apple", "orb", "Øre", "resume", "Resume", "résumé", "zebra"]
+        );
+
+        assert_eq!(
+            sorted(Locale::German, &["Muster", "Müller", "Mumford"]),
+            ["Müller", "Mumford", "Muster"]
+        );
+
+        // Other scripts sort after Latin letters, and invalid UTF-8 after
+        // everything.
+        let mut keys = [b"\xff".to_vec(), "жук".into(), b"zz".to_vec()];
+        keys.sort_by_cached_key(|k| Locale::Root.sort_key(k));
+        assert_eq!(keys, [b"zz".to_vec(), "жук".into(), b"\xff".to_vec()]);
+    }
+
+    #[test]
+    fn tailorings() {
+        assert_eq!(
+            sorted(
+                Locale::GermanPhonebook,
+                &["Müller", "Mukherjee", "Mueller", "Mudd"]
+            ),
+            ["Mudd", "Mueller", "Müller", "Mukherjee"]
+        );
+        assert_eq!(
+            sorted(
+                Locale::Swedish,
+                &["Östberg", "Zetterberg", "Åberg", "Adams", "Ängström"]
+            ),
+            ["Adams", "Zetterberg", "Åberg", "Ängström", "Östberg"]
+        );
+        assert_eq!(
+            sorted(
+                Locale::Danish,
+                &["Aagaard", "Zahle", "Ærø", "Øster", "Andersen"]
+            ),
+            ["Andersen", "Zahle", "Ærø", "Øster", "Aagaard"]
+        );
+        assert_eq!(
+            sorted(Locale::Spanish, &["oro", "ñandú", "nube"]),
+            ["nube", "ñandú", "oro"]
+        );
+        assert_eq!(
+            sorted(Locale::Czech, &["ir", "Chata", "hrad", "cena", "čas"]),
+            ["cena", "čas", "hrad", "Chata", "ir"]
+        );
+        assert_eq!(
+            sorted(
+                Locale::Polish,
+                &["mama", "łąka", "lody", "źle", "żaba", "zero"]
+            ),
+            ["lody", "łąka", "mama", "zero", "źle", "żaba"]
+        );
+
+        // Decomposed letters are composed before they're looked up.
+        assert_eq!(
+            sorted(Locale::Swedish, &["a\u{30a}r", "zon"]),
+            ["zon", "a\u{30a}r"]
+        );
+    }
+
+    #[test]
+    fn parse() {
+        assert_eq!("sv".parse::<Locale>().unwrap(), Locale::Swedish);
+        assert_eq!(
+            "de_PHONEBOOK".parse::<Locale>().unwrap(),
+            Locale::GermanPhonebook
+        );
+        assert_eq!(Locale::Danish.to_string(), "da");
+        assert!("klingon".parse::<Locale>().is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Builtin functions supplied by embedding code.
+//!
+//! Styles can only call the builtins that the engine knows about, which makes
+//! it hard to try out new ones, such as a `url.cleanup$`, without changing the
+//! interpreter itself. Embedding code can register its own builtins by name,
+//! which styles then call like any other. They work on the literal stack
+//! through a [`BuiltinStack`], which only lets them push and pop integers and
+//! strings, so that they can't break the interpreter's invariants.
+//!
+//! Problems with the stack, such as popping a value of the wrong type, are
+//! reported like those of the standard builtins once the builtin returns, and
+//! so are the errors that it returns itself.
+
+use crate::{
+    cite::CiteInfo,
+    exec::{bst_ex_warn_print, ExecCtx, ExecVal},
+    hash::HashData,
+    log::print_confusion,
+    pool::StringPool,
+    trace::{stack_value, StackValue},
+    BibtexError,
+};
+use std::fmt::{Debug, Error as FmtError, Formatter};
+use tectonic_errors::prelude::*;
+
+/// The type of a builtin supplied by embedding code.
+type BuiltinFn = Box<dyn FnMut(&mut BuiltinStack<'_>) -> Result<()> + Send>;
+
+/// A builtin registered with
+/// [`BibtexEngine::register_builtin()`](crate::BibtexEngine::register_builtin).
+pub(crate) struct CustomBuiltin {
+    pub name: String,
+    pub func: BuiltinFn,
+}
+
+impl CustomBuiltin {
+    pub fn new(name: &str, func: BuiltinFn) -> CustomBuiltin {
+        CustomBuiltin {
+            name: name.to_ascii_lowercase(),
+            func,
+        }
+    }
+}
+
+impl Debug for CustomBuiltin {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.debug_struct("CustomBuiltin")
+            .field("name", &self.name)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What went wrong while a builtin used the stack.
+enum Problem {
+    /// A message in the style of the interpreter's own, which is reported as
+    /// an error in the style.
+    Style(String),
+    /// The string pool is in a state that it shouldn't be.
+    Confusion(&'static str),
+}
+
+/// The literal stack of the style interpreter, as seen by a builtin supplied
+/// by embedding code.
+///
+/// Values are popped from the top of the stack and pushed onto it, as the
+/// standard builtins do. Popping from an empty stack, or popping a value of
+/// the wrong type with [`pop_int()`](Self::pop_int) or
+/// [`pop_str()`](Self::pop_str), is an error in the style, which is reported
+/// once the builtin returns; only the first such problem is reported.
+pub struct BuiltinStack<'a> {
+    stack: &'a mut Vec<ExecVal>,
+    pool: &'a mut StringPool,
+    hash: &'a HashData,
+    cite_key: Option<String>,
+    problem: Option<Problem>,
+}
+
+impl BuiltinStack<'_> {
+    /// The number of values on the stack.
+    pub fn len(&self) -> usize {
+        self.stack.len()
+    }
+
+    /// Whether the stack is empty.
+    pub fn is_empty(&self) -> bool {
+        self.stack.is_empty()
+    }
+
+    /// The cite key of the entry being processed, if the builtin was called
+    /// on behalf of an `ITERATE` or `REVERSE` command.
+    pub fn cite_key(&self) -> Option<&str> {
+        self.cite_key.as_deref()
+    }
+
+    /// Pop the value on the top of the stack. This returns `None`, and
+    /// reports an error, if the stack is empty.
+    pub fn pop(&mut self) -> Option<StackValue> {
+        let Some(val) = self.stack.pop() else {
+            self.report(Problem::Style(
+                "You can't pop an empty literal stack".to_owned(),
+            ));
+            return None;
+        };
+
+        let value = stack_value(self.pool, self.hash, &val);
+
+        // Strings made while running the style are freed as they're popped,
+        // as `pop_stack` does for the standard builtins.
+        if let ExecVal::String(str) = val {
+            if !self.pool.discard_scratch(str) {
+                self.report(Problem::Confusion("Nontop top of string stack"));
+            }
+        }
+
+        value
+    }
+
+    /// Pop an integer from the top of the stack. This returns `None`, and
+    /// reports an error, if the stack is empty or its top isn't an integer.
+    pub fn pop_int(&mut self) -> Option<i64> {
+        match self.pop()? {
+            StackValue::Integer(i) => Some(i),
+            other => {
+                self.report(Problem::Style(wrong_type(&other, "an integer")));
+                None
+            }
+        }
+    }
+
+    /// Pop a string from the top of the stack. This returns `None`, and
+    /// reports an error, if the stack is empty or its top isn't a string.
+    /// Bytes that aren't valid UTF-8 are replaced.
+    pub fn pop_str(&mut self) -> Option<String> {
+        match self.pop()? {
+            StackValue::String(s) => Some(s),
+            other => {
+                self.report(Problem::Style(wrong_type(&other, "a string")));
+                None
+            }
+        }
+    }
+
+    /// Push an integer onto the stack.
+    pub fn push_int(&mut self, value: i64) {
+        self.stack.push(ExecVal::Integer(value));
+    }
+
+    /// Push a string onto the stack.
+    pub fn push_str(&mut self, value: &str) {
+        let str = self.pool.add_string(value.as_bytes());
+        self.stack.push(ExecVal::String(str));
+    }
+
+    fn report(&mut self, problem: Problem) {
+        if self.problem.is_none() {
+            self.problem = Some(problem);
+        }
+    }
+}
+
+/// The message for a popped value that isn't of the *expected* type, worded
+/// like those of the standard builtins.
+fn wrong_type(value: &StackValue, expected: &str) -> String {
+    let literal = match value {
+        StackValue::Integer(i) => format!("{i} is an integer literal"),
+        StackValue::String(s) => format!("\"{s}\" is a string literal"),
+        StackValue::Function(name) => format!("`{name}` is a function literal"),
+        StackValue::Missing(field) => format!("`{field}` is a missing field"),
+    };
+
+    format!("{literal}, not {expected},")
+}
+
+/// Run the registered builtin with the given index.
+pub(crate) fn execute_custom(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    pool: &mut StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+    index: usize,
+) -> Result<(), BibtexError> {
+    // Builtins can't call back into the interpreter, so they can be taken out
+    // of the context while one of them runs.
+    let mut builtins = std::mem::take(&mut ctx.custom_builtins);
+    let mut lit_stack = std::mem::take(&mut ctx.lit_stack);
+
+    let cite_key = ctx
+        .mess_with_entries
+        .then(|| String::from_utf8_lossy(pool.get_str(cites.get_cite(cites.ptr()))).into_owned());
+
+    let mut stack = BuiltinStack {
+        stack: &mut lit_stack,
+        pool,
+        hash,
+        cite_key,
+        problem: None,
+    };
+
+    let res = (builtins[index].func)(&mut stack);
+    let problem = stack.problem.take();
+
+    ctx.lit_stack = lit_stack;
+    ctx.custom_builtins = builtins;
+
+    match problem {
+        Some(Problem::Confusion(msg)) => {
+            ctx.write_logs(msg);
+            print_confusion(ctx);
+            return Err(BibtexError::Fatal);
+        }
+        Some(Problem::Style(msg)) => {
+            ctx.write_logs(&msg);
+            bst_ex_warn_print(ctx, pool, cites)?;
+        }
+        None => {}
+    }
+
+    if let Err(e) = res {
+        let name = ctx.custom_builtins[index].name.clone();
+        ctx.write_logs(&format!("{name} failed: {e}"));
+        bst_ex_warn_print(ctx, pool, cites)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrong_type_messages() {
+        assert_eq!(
+            wrong_type(&StackValue::Integer(3), "a string"),
+            "3 is an integer literal, not a string,"
+        );
+        assert_eq!(
+            wrong_type(&StackValue::String("abc".to_owned()), "an integer"),
+            "\"abc\" is a string literal, not an integer,"
+        );
+        assert_eq!(
+            wrong_type(&StackValue::Missing("url".to_owned()), "a string"),
+            "`url` is a missing field, not a string,"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+//! Structured diagnostics.
+//!
+//! BibTeX reports problems as free text, which is written to the terminal and
+//! the log as it's produced, often in several pieces, and which ends with a
+//! description of where the problem is. So that tools such as editors can
+//! show problems next to the input that caused them, the engine also records
+//! each problem as a [`Diagnostic`].
+//!
+//! The recorder sees all of the text written to the terminal. The code that
+//! reports a problem tells it what the location is and, if a description of
+//! the location follows that of the problem, where the latter ends. The
+//! message is complete when the warning or error is counted. Messages are
+//! left as classic BibTeX writes them, apart from the `Warning--` prefix.
+
+use crate::srcmap::EntryLocation;
+use std::ops::Range;
+
+/// How serious a [`Diagnostic`] is.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    /// A warning, which doesn't prevent the output from being used.
+    Warning,
+
+    /// An error, after which the output is probably wrong.
+    Error,
+
+    /// An error that stopped the engine.
+    Fatal,
+}
+
+/// A problem reported during a run.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    /// How serious the problem is.
+    pub severity: Severity,
+
+    /// A description of the problem.
+    pub message: String,
+
+    /// The name of the `.aux`, `.bib`, or `.bst` file in which the problem
+    /// was found, if it's in a file.
+    pub file: Option<String>,
+
+    /// The line of the file where the problem was found, counting from 1.
+    pub line: Option<u32>,
+
+    /// The bytes of the file where the problem was found. When the engine
+    /// knows just the line, this covers the whole of it, and when it knows
+    /// where on the line it was reading, this runs from there to the end of
+    /// the line. This is `None` for files that the engine converted to
+    /// `.bib` syntax before reading them.
+    pub span: Option<Range<usize>>,
+
+    /// The cite key of the entry that the problem concerns, if any.
+    pub cite_key: Option<String>,
+}
+
+/// Collects [`Diagnostic`]s from the text of messages.
+#[derive(Debug, Default)]
+pub(crate) struct DiagnosticRecorder {
+    /// The text written to the terminal since the last message ended.
+    text: Vec<u8>,
+    /// Where in `text` the description of the problem ends, if the location
+    /// has been printed.
+    message_end: Option<usize>,
+    file: Option<String>,
+    line: Option<u32>,
+    span: Option<Range<usize>>,
+    cite_key: Option<String>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl DiagnosticRecorder {
+    pub fn write(&mut self, text: &[u8]) {
+        self.text.extend_from_slice(text);
+    }
+
+    /// Forget the text written so far, which isn't part of a message.
+    pub fn clear_text(&mut self) {
+        self.text.clear();
+    }
+
+    /// Note that the description of the current problem is complete, and
+    /// that what follows it says where the problem is.
+    pub fn end_message(&mut self) {
+        self.message_end.get_or_insert(self.text.len());
+    }
+
+    /// Note the location of the current problem: a file, and optionally the
+    /// line of it and the span of that line.
+    pub fn locate(&mut self, file: String, line: Option<u32>, span: Option<Range<usize>>) {
+        self.file = Some(file);
+        self.line = line;
+        self.span = span;
+    }
+
+    /// Narrow the span of the current problem to start at *column* of its
+    /// line.
+    pub fn point_at(&mut self, column: usize) {
+        if let Some(span) = &mut self.span {
+            span.start = (span.start + column).min(span.end);
+        }
+    }
+
+    /// Note that the current problem concerns the given entry, as a whole.
+    pub fn locate_entry(&mut self, entry: EntryLocation) {
+        self.locate(entry.file, Some(entry.line), None);
+        self.cite_key = Some(entry.cite_key);
+    }
+
+    pub fn set_cite_key(&mut self, key: &[u8]) {
+        self.cite_key = Some(String::from_utf8_lossy(key).into_owned());
+    }
+
+    /// Record the current problem, now that its message is complete.
+    pub fn finish(&mut self, severity: Severity) {
+        let end = self.message_end.take().unwrap_or(self.text.len());
+        let text = String::from_utf8_lossy(&self.text[..end]);
+        let text = text.trim();
+        let message = text
+            .strip_prefix("Warning--")
+            .unwrap_or(text)
+            .trim_end_matches('-');
+
+        self.diagnostics.push(Diagnostic {
+            severity,
+            message: message.to_owned(),
+            file: self.file.take(),
+            line: self.line.take(),
+            span: self.span.take(),
+            cite_key: self.cite_key.take(),
+        });
+        self.text.clear();
+    }
+
+    pub fn take(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn messages() {
+        let mut rec = DiagnosticRecorder::default();
+
+        rec.write(b"The style file: plain.bst\n");
+        rec.clear_text();
+        rec.write(b"I was expecting a `,' or a `}'-");
+        rec.end_message();
+        rec.locate("refs.bib".to_owned(), Some(3), Some(40..60));
+        rec.write(b"--line 3 of file refs.bib\n : @book{knuth\n");
+        rec.point_at(12);
+        rec.finish(Severity::Error);
+
+        rec.write(b"Warning--I didn't find a database entry for \"x\"\n");
+        rec.set_cite_key(b"x");
+        rec.finish(Severity::Warning);
+
+        assert_eq!(
+            rec.take(),
+            [
+                Diagnostic {
+                    severity: Severity::Error,
+                    message: "I was expecting a `,' or a `}'".to_owned(),
+                    file: Some("refs.bib".to_owned()),
+                    line: Some(3),
+                    span: Some(52..60),
+                    cite_key: None,
+                },
+                Diagnostic {
+                    severity: Severity::Warning,
+                    message: "I didn't find a database entry for \"x\"".to_owned(),
+                    file: None,
+                    line: None,
+                    span: None,
+                    cite_key: Some("x".to_owned()),
+                },
+            ]
+        );
+    }
+}
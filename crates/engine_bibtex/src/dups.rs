@@ -0,0 +1,166 @@
+//! Detection of probable duplicate entries.
+//!
+//! Large `.bib` files that are shared and merged by several people tend to
+//! collect the same work more than once, under different cite keys and with
+//! small differences in how it's written: `{\"U}ber` in one copy and `Über`
+//! in another, or the title in a different case. When duplicate detection is
+//! enabled, the entries that have been read are compared once the database
+//! files have been read, before any cross references are resolved. Two
+//! entries are taken to be duplicates if they have the same title and their
+//! first authors have the same last name, ignoring case, accents, braces,
+//! control sequences, and punctuation. Each entry that duplicates an earlier
+//! one is written to the log as a warning and recorded as a
+//! [`DuplicateEntry`].
+//!
+//! As with everything else that BibTeX does with fields, only the fields that
+//! the style declares in its `ENTRY` command can be compared, and entries
+//! without a title or an author aren't compared at all.
+
+use crate::{
+    bbl::{last_names, sort_text},
+    exec::{entry_location, field_value},
+    Bibtex, BibtexError, GlobalItems,
+};
+use std::{collections::HashMap, fmt};
+use unicode_normalization::{char::is_combining_mark, UnicodeNormalization};
+
+/// An entry that seems to duplicate one that was read before it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DuplicateEntry {
+    /// The cite key of the entry.
+    pub cite_key: String,
+
+    /// The name of the `.bib` file containing the entry.
+    pub file: String,
+
+    /// The line of the `.bib` file where the entry starts.
+    pub line: u32,
+
+    /// The cite key of the earlier entry that this one duplicates.
+    pub original_key: String,
+
+    /// The name of the `.bib` file containing the earlier entry.
+    pub original_file: String,
+
+    /// The line of the `.bib` file where the earlier entry starts.
+    pub original_line: u32,
+}
+
+impl fmt::Display for DuplicateEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} seems to duplicate {}, at {}:{}",
+            self.file,
+            self.line,
+            self.cite_key,
+            self.original_key,
+            self.original_file,
+            self.original_line
+        )
+    }
+}
+
+/// Normalize text for comparison: as for sorting, and then with accents
+/// removed and all letters lowercased.
+fn fold(text: &[u8]) -> String {
+    String::from_utf8_lossy(&sort_text(text))
+        .nfd()
+        .filter(|c| !is_combining_mark(*c))
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The text by which an entry is compared with others, if it has the fields
+/// that are compared.
+fn duplicate_key(title: &[u8], author: &[u8]) -> Option<(String, String)> {
+    let title = fold(title);
+    let author = fold(last_names(author).first()?);
+
+    (!title.is_empty() && !author.is_empty()).then_some((title, author))
+}
+
+/// Report the entries that have been read that seem to duplicate earlier
+/// ones.
+pub(crate) fn find_duplicates(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    let mut seen = HashMap::new();
+
+    for cite in 0..globals.cites.num_cites() {
+        let Some(location) = entry_location(globals.pool, globals.cites, cite) else {
+            continue;
+        };
+
+        let field =
+            |name: &[u8]| field_value(globals.pool, globals.hash, globals.other, cite, name);
+
+        let Some(key) = field(b"title")
+            .zip(field(b"author"))
+            .and_then(|(title, author)| duplicate_key(title, author))
+        else {
+            continue;
+        };
+
+        let Some(original) = seen.get(&key) else {
+            seen.insert(key, location);
+            continue;
+        };
+
+        let dup = DuplicateEntry {
+            cite_key: location.cite_key.clone(),
+            file: location.file.clone(),
+            line: location.line,
+            original_key: original.cite_key.clone(),
+            original_file: original.file.clone(),
+            original_line: original.line,
+        };
+
+        ctx.diagnostics.locate_entry(location);
+        ctx.write_logs(&format!(
+            "Warning--{} seems to duplicate {}, with the same title and first author\n",
+            dup.cite_key, dup.original_key
+        ));
+        ctx.diagnostics.end_message();
+        ctx.write_logs(&format!("--line {} of file {}\n", dup.line, dup.file));
+        ctx.mark_warning();
+        ctx.duplicate_entries.push(dup);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(title: &str, author: &str) -> Option<(String, String)> {
+        duplicate_key(title.as_bytes(), author.as_bytes())
+    }
+
+    #[test]
+    fn duplicate_keys() {
+        assert_eq!(
+            key(
+                "{\\\"U}ber die {Relativit\\\"at}",
+                "Albert {\\\"E}instein and M. Grossmann"
+            ),
+            key("Über die Relativität", "Éinstein, A.")
+        );
+        assert_eq!(
+            key("The {TeX}book", "Knuth, Donald E."),
+            Some(("the texbook".to_owned(), "knuth".to_owned()))
+        );
+        assert_ne!(
+            key("The TeXbook", "Donald E. Knuth"),
+            key("The METAFONTbook", "Donald E. Knuth")
+        );
+        assert_ne!(
+            key("Surreal Numbers", "Donald E. Knuth and Someone Else"),
+            key("Surreal Numbers", "Someone Else and Donald E. Knuth")
+        );
+        assert_eq!(key("Anonymous", ""), None);
+        assert_eq!(key("", "Knuth"), None);
+    }
+}
@@ -0,0 +1,701 @@
+//! Static analysis of the stack effects of style functions.
+//!
+//! A style's functions communicate through the literal stack, and nothing
+//! checks that they agree about it until the style runs, when a function that
+//! pops a value nobody pushed, or gets a string where it wanted an integer,
+//! produces a confusing error for some entry or other. When style linting is
+//! enabled, each function is analyzed as soon as it has been defined: the
+//! types of the values that it pushes are followed through its body, and
+//! those given to the builtins and to the style's other functions are
+//! compared with what they expect. The functions run by `EXECUTE`, `ITERATE`,
+//! and `REVERSE` commands are then checked to leave the stack as they found
+//! it. Each problem is written to the log as a warning and recorded as a
+//! [`StyleLint`].
+//!
+//! Whether a style is correct can depend on the data that it's run on, so
+//! the analysis only reports problems that it's sure of. Once the depth of
+//! the stack can't be known, as after `call.type$`, `stack$`, a custom
+//! builtin, or an `if$` whose branches push different numbers of values, the
+//! rest of the function is only checked for literals of the wrong type.
+
+use crate::{
+    hash::{BstBuiltin, BstFn, HashData, HashExtra},
+    other::OtherData,
+    pool::StringPool,
+    scan::QUOTE_NEXT_FN,
+    session::StyleCommand,
+    Bibtex, GlobalItems, HashPointer,
+};
+use std::{
+    collections::HashMap,
+    fmt::{Display, Error as FmtError, Formatter},
+};
+
+/// The type of a value on the literal stack, as far as linting is concerned.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ValueType {
+    /// An integer.
+    Integer,
+
+    /// A string.
+    String,
+
+    /// A function.
+    Function,
+}
+
+impl Display for ValueType {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            ValueType::Integer => write!(f, "an integer"),
+            ValueType::String => write!(f, "a string"),
+            ValueType::Function => write!(f, "a function"),
+        }
+    }
+}
+
+/// The kind of problem found by analyzing the stack effects of a style.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StyleProblem {
+    /// A function is given a value of the wrong type.
+    WrongType {
+        /// The name of the function given the value.
+        callee: String,
+        /// The type that it needs.
+        expected: ValueType,
+        /// The type that it's given.
+        found: ValueType,
+    },
+
+    /// The condition of a `while$` doesn't leave exactly one integer on the
+    /// stack.
+    BadWhileCondition,
+
+    /// The body of a `while$` changes the depth of the stack, so that each
+    /// iteration leaves more or fewer values on it.
+    UnbalancedWhileBody,
+
+    /// A function pops more values than it pushes, when the stack is empty
+    /// because a command is running it. This holds the number of values that
+    /// are missing.
+    Underflow {
+        /// The command that runs the function.
+        command: StyleCommand,
+        /// The number of values missing.
+        values: usize,
+    },
+
+    /// A function leaves values on the stack when a command runs it.
+    LeftOnStack {
+        /// The command that runs the function.
+        command: StyleCommand,
+        /// The number of values left.
+        values: usize,
+    },
+}
+
+impl Display for StyleProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        let plural = |n: &usize| if *n == 1 { "" } else { "s" };
+
+        match self {
+            StyleProblem::WrongType {
+                callee,
+                expected,
+                found,
+            } => write!(f, "{found} given to {callee} where {expected} is needed"),
+            StyleProblem::BadWhileCondition => {
+                write!(f, "a while$ condition that doesn't leave one integer")
+            }
+            StyleProblem::UnbalancedWhileBody => {
+                write!(f, "a while$ body that changes the depth of the stack")
+            }
+            StyleProblem::Underflow { command, values } => write!(
+                f,
+                "a stack underflow of {values} value{} when run by {command}",
+                plural(values)
+            ),
+            StyleProblem::LeftOnStack { command, values } => write!(
+                f,
+                "{values} value{} left on the stack when run by {command}",
+                plural(values)
+            ),
+        }
+    }
+}
+
+/// A problem found by analyzing the stack effects of a style.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StyleLint {
+    /// The name of the function with the problem.
+    pub function: String,
+
+    /// The name of the `.bst` file.
+    pub file: String,
+
+    /// The line of the `.bst` file with the problem. For problems with how a
+    /// command uses a function, this is the line of the command.
+    pub line: u32,
+
+    /// The problem.
+    pub problem: StyleProblem,
+}
+
+impl Display for StyleLint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        write!(
+            f,
+            "{}:{}: {} in function {}",
+            self.file, self.line, self.problem, self.function
+        )
+    }
+}
+
+/// What the analysis knows about a value on the stack.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Ty {
+    Int,
+    Str,
+    /// A function, and which one if it's known.
+    Fn(Option<HashPointer>),
+    Any,
+}
+
+impl Ty {
+    fn value_type(self) -> Option<ValueType> {
+        match self {
+            Ty::Int => Some(ValueType::Integer),
+            Ty::Str => Some(ValueType::String),
+            Ty::Fn(_) => Some(ValueType::Function),
+            Ty::Any => None,
+        }
+    }
+
+    /// The type of a value that may be of either type.
+    fn join(self, other: Ty) -> Ty {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (Ty::Fn(_), Ty::Fn(_)) => Ty::Fn(None),
+            _ => Ty::Any,
+        }
+    }
+
+    /// The type of a value that must satisfy both requirements.
+    fn meet(self, other: Ty) -> Ty {
+        match (self, other) {
+            (Ty::Any, t) | (t, Ty::Any) => t,
+            (a, b) => a.join(b),
+        }
+    }
+}
+
+/// The stack effect of a function.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Effect {
+    /// The values that the function pops from beneath those that it has
+    /// pushed, in the order popped.
+    inputs: Vec<Ty>,
+    /// The values that the function leaves, from the bottom up.
+    outputs: Vec<Ty>,
+}
+
+impl Effect {
+    /// Make this effect pop as many values as *other*. The values that it
+    /// didn't pop are left beneath those that it pushes.
+    fn pad_inputs(&mut self, other: &Effect) {
+        while self.inputs.len() < other.inputs.len() {
+            let ty = other.inputs[self.inputs.len()];
+            self.inputs.push(ty);
+            self.outputs.insert(0, ty);
+        }
+    }
+}
+
+/// The state of the analysis of a function body.
+#[derive(Clone, Debug, Default)]
+struct State {
+    effect: Effect,
+    /// Whether the depth of the stack is no longer known.
+    open: bool,
+}
+
+impl State {
+    fn push(&mut self, ty: Ty) {
+        self.effect.outputs.push(ty);
+    }
+
+    fn pop(&mut self, expected: Ty) -> Ty {
+        self.effect.outputs.pop().unwrap_or_else(|| {
+            if !self.open {
+                self.effect.inputs.push(expected);
+            }
+            expected
+        })
+    }
+
+    /// Forget the contents of the stack, whose depth is no longer known.
+    fn lose_track(&mut self) {
+        self.effect.outputs.clear();
+        self.open = true;
+    }
+
+    /// The state after either of two alternatives.
+    fn merge(self, other: State) -> State {
+        let mut merged = State::default();
+
+        if self.open || other.open {
+            merged.open = true;
+            return merged;
+        }
+
+        let (mut a, mut b) = (self.effect, other.effect);
+
+        a.pad_inputs(&b);
+        b.pad_inputs(&a);
+
+        if a.outputs.len() != b.outputs.len() {
+            merged.open = true;
+            return merged;
+        }
+
+        merged.effect.inputs = a
+            .inputs
+            .iter()
+            .zip(&b.inputs)
+            .map(|(x, y)| x.meet(*y))
+            .collect();
+        merged.effect.outputs = a
+            .outputs
+            .iter()
+            .zip(&b.outputs)
+            .map(|(x, y)| x.join(*y))
+            .collect();
+        merged
+    }
+}
+
+struct Analyzer<'a> {
+    hash: &'a HashData,
+    pool: &'a StringPool,
+    other: &'a OtherData,
+    effects: &'a mut HashMap<HashPointer, Option<Effect>>,
+    /// The functions being analyzed, so that recursion can be stopped.
+    active: Vec<HashPointer>,
+    problems: Vec<(u32, StyleProblem)>,
+}
+
+impl Analyzer<'_> {
+    fn name(&self, loc: HashPointer) -> String {
+        String::from_utf8_lossy(self.pool.get_str(self.hash.text(loc))).into_owned()
+    }
+
+    /// The stack effect of one of the style's own functions, or `None` if it
+    /// can't be known.
+    fn effect_of(&mut self, loc: HashPointer) -> Option<Effect> {
+        if let Some(effect) = self.effects.get(&loc) {
+            return effect.clone();
+        }
+
+        let HashExtra::BstFn(BstFn::Wizard(mut ptr)) = self.hash.node(loc).extra else {
+            return None;
+        };
+
+        if self.active.contains(&loc) {
+            return None;
+        }
+
+        self.active.push(loc);
+        let mut state = State::default();
+        let mut cur_fn = self.other.wiz_function(ptr);
+
+        while cur_fn != HashData::end_of_def() {
+            if cur_fn == QUOTE_NEXT_FN {
+                ptr += 1;
+                state.push(Ty::Fn(Some(self.other.wiz_function(ptr))));
+            } else {
+                self.call(&mut state, cur_fn, self.other.wiz_line(ptr));
+            }
+            ptr += 1;
+            cur_fn = self.other.wiz_function(ptr);
+        }
+
+        self.active.pop();
+        let effect = (!state.open).then_some(state.effect);
+        self.effects.insert(loc, effect.clone());
+        effect
+    }
+
+    /// The stack effect of calling any function by itself.
+    fn standalone(&mut self, loc: HashPointer, line: u32) -> Option<Effect> {
+        let mut state = State::default();
+        self.call(&mut state, loc, line);
+        (!state.open).then_some(state.effect)
+    }
+
+    /// Pop a value that *callee* needs to be of the *expected* type.
+    fn take(&mut self, state: &mut State, expected: Ty, callee: HashPointer, line: u32) -> Ty {
+        let found = state.pop(expected);
+
+        if let (Some(expected), Some(found)) = (expected.value_type(), found.value_type()) {
+            if expected != found {
+                self.problems.push((
+                    line,
+                    StyleProblem::WrongType {
+                        callee: self.name(callee),
+                        expected,
+                        found,
+                    },
+                ));
+            }
+        }
+
+        found
+    }
+
+    fn apply(&mut self, state: &mut State, effect: &Effect, callee: HashPointer, line: u32) {
+        for ty in &effect.inputs {
+            self.take(state, *ty, callee, line);
+        }
+        state.effect.outputs.extend(&effect.outputs);
+    }
+
+    fn call(&mut self, state: &mut State, loc: HashPointer, line: u32) {
+        let bst_fn = match self.hash.node(loc).extra {
+            HashExtra::Text => return state.push(Ty::Str),
+            HashExtra::Integer(_) => return state.push(Ty::Int),
+            HashExtra::BstFn(bst_fn) => bst_fn,
+            _ => return state.lose_track(),
+        };
+
+        match bst_fn {
+            BstFn::Builtin(builtin) => self.call_builtin(state, loc, builtin, line),
+            BstFn::Custom(_) => state.lose_track(),
+            BstFn::Wizard(_) => match self.effect_of(loc) {
+                Some(effect) => self.apply(state, &effect, loc, line),
+                None => state.lose_track(),
+            },
+            BstFn::Field(_) => state.push(Ty::Any),
+            BstFn::IntEntry(_) | BstFn::IntGlbl(_) => state.push(Ty::Int),
+            BstFn::StrEntry(_) | BstFn::StrGlbl(_) => state.push(Ty::Str),
+        }
+    }
+
+    fn call_builtin(
+        &mut self,
+        state: &mut State,
+        loc: HashPointer,
+        builtin: BstBuiltin,
+        line: u32,
+    ) {
+        macro_rules! pop {
+            ($ty:expr) => {
+                self.take(state, $ty, loc, line)
+            };
+        }
+
+        let result = match builtin {
+            BstBuiltin::Gt | BstBuiltin::Lt | BstBuiltin::Plus | BstBuiltin::Minus => {
+                pop!(Ty::Int);
+                pop!(Ty::Int);
+                Some(Ty::Int)
+            }
+            BstBuiltin::Eq => {
+                let a = pop!(Ty::Any);
+                let b = pop!(Ty::Any);
+                if let (Some(a @ (ValueType::Integer | ValueType::String)), Some(b)) =
+                    (a.value_type(), b.value_type())
+                {
+                    if a != b {
+                        self.problems.push((
+                            line,
+                            StyleProblem::WrongType {
+                                callee: self.name(loc),
+                                expected: a,
+                                found: b,
+                            },
+                        ));
+                    }
+                }
+                Some(Ty::Int)
+            }
+            BstBuiltin::Concat => {
+                pop!(Ty::Str);
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::Set => {
+                let value = match pop!(Ty::Fn(None)) {
+                    Ty::Fn(Some(var)) => match self.hash.node(var).extra {
+                        HashExtra::BstFn(BstFn::IntEntry(_) | BstFn::IntGlbl(_)) => Ty::Int,
+                        HashExtra::BstFn(BstFn::StrEntry(_) | BstFn::StrGlbl(_)) => Ty::Str,
+                        _ => Ty::Any,
+                    },
+                    _ => Ty::Any,
+                };
+                pop!(value);
+                None
+            }
+            BstBuiltin::AddPeriod | BstBuiltin::Purify => {
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::ChangeCase => {
+                pop!(Ty::Str);
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::ChrToInt | BstBuiltin::NumNames => {
+                pop!(Ty::Str);
+                Some(Ty::Int)
+            }
+            BstBuiltin::Cite | BstBuiltin::Preamble | BstBuiltin::Quote | BstBuiltin::Type => {
+                Some(Ty::Str)
+            }
+            BstBuiltin::Duplicate => {
+                let ty = pop!(Ty::Any);
+                state.push(ty);
+                Some(ty)
+            }
+            BstBuiltin::Swap => {
+                let a = pop!(Ty::Any);
+                let b = pop!(Ty::Any);
+                state.push(a);
+                Some(b)
+            }
+            BstBuiltin::Empty | BstBuiltin::Missing => {
+                pop!(Ty::Any);
+                Some(Ty::Int)
+            }
+            BstBuiltin::FormatName => {
+                pop!(Ty::Str);
+                pop!(Ty::Int);
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::IntToChr | BstBuiltin::IntToStr => {
+                pop!(Ty::Int);
+                Some(Ty::Str)
+            }
+            BstBuiltin::Newline | BstBuiltin::Skip => None,
+            BstBuiltin::OutputFile | BstBuiltin::Warning | BstBuiltin::Write => {
+                pop!(Ty::Str);
+                None
+            }
+            BstBuiltin::Pop | BstBuiltin::Top => {
+                pop!(Ty::Any);
+                None
+            }
+            BstBuiltin::Substring => {
+                pop!(Ty::Int);
+                pop!(Ty::Int);
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::TextLength | BstBuiltin::Width => {
+                pop!(Ty::Str);
+                Some(Ty::Int)
+            }
+            BstBuiltin::TextPrefix => {
+                pop!(Ty::Int);
+                pop!(Ty::Str);
+                Some(Ty::Str)
+            }
+            BstBuiltin::CallType | BstBuiltin::Stack => {
+                state.lose_track();
+                None
+            }
+            BstBuiltin::If => {
+                let else_fn = pop!(Ty::Fn(None));
+                let then_fn = pop!(Ty::Fn(None));
+                pop!(Ty::Int);
+
+                let (Ty::Fn(Some(then_fn)), Ty::Fn(Some(else_fn))) = (then_fn, else_fn) else {
+                    state.lose_track();
+                    return;
+                };
+
+                let mut then_state = state.clone();
+                self.call(&mut then_state, then_fn, line);
+                let mut else_state = state.clone();
+                self.call(&mut else_state, else_fn, line);
+                *state = then_state.merge(else_state);
+                None
+            }
+            BstBuiltin::While => {
+                let body = pop!(Ty::Fn(None));
+                let cond = pop!(Ty::Fn(None));
+                self.call_while(state, loc, cond, body, line);
+                None
+            }
+        };
+
+        if let Some(ty) = result {
+            state.push(ty);
+        }
+    }
+
+    fn call_while(&mut self, state: &mut State, loc: HashPointer, cond: Ty, body: Ty, line: u32) {
+        let (Ty::Fn(Some(cond)), Ty::Fn(Some(body))) = (cond, body) else {
+            return state.lose_track();
+        };
+
+        let (Some(cond), Some(body)) = (self.standalone(cond, line), self.standalone(body, line))
+        else {
+            return state.lose_track();
+        };
+
+        let leaves_int = cond.outputs.len() == cond.inputs.len() + 1
+            && matches!(cond.outputs.last(), Some(Ty::Int | Ty::Any));
+
+        if !leaves_int {
+            self.problems.push((line, StyleProblem::BadWhileCondition));
+            return state.lose_track();
+        }
+
+        if body.outputs.len() != body.inputs.len() {
+            self.problems
+                .push((line, StyleProblem::UnbalancedWhileBody));
+            return state.lose_track();
+        }
+
+        // The loop may stop before or after running the body.
+        self.apply(state, &cond, loc, line);
+        state.pop(Ty::Int);
+        let mut after_body = state.clone();
+        self.apply(&mut after_body, &body, loc, line);
+        self.apply(&mut after_body, &cond, loc, line);
+        after_body.pop(Ty::Int);
+        *state = std::mem::take(state).merge(after_body);
+    }
+}
+
+fn report(
+    ctx: &mut Bibtex<'_, '_>,
+    pool: &StringPool,
+    function: &str,
+    line: u32,
+    problem: StyleProblem,
+) {
+    let name = pool.get_str(ctx.bst.as_ref().unwrap().name);
+    let lint = StyleLint {
+        function: function.to_owned(),
+        file: format!("{}.bst", String::from_utf8_lossy(name)),
+        line,
+        problem,
+    };
+
+    ctx.diagnostics.locate(lint.file.clone(), Some(line), None);
+    ctx.write_logs(&format!(
+        "Warning--{} in function {}\n",
+        lint.problem, lint.function
+    ));
+    ctx.diagnostics.end_message();
+    ctx.write_logs(&format!("--line {} of file {}\n", lint.line, lint.file));
+    ctx.mark_warning();
+    ctx.style_lints.push(lint);
+}
+
+fn analyze<T>(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+    fn_loc: HashPointer,
+    f: impl FnOnce(&mut Analyzer<'_>) -> T,
+) -> (String, T, Vec<(u32, StyleProblem)>) {
+    let mut effects = std::mem::take(&mut ctx.stack_effects);
+    let mut analyzer = Analyzer {
+        hash: globals.hash,
+        pool: globals.pool,
+        other: globals.other,
+        effects: &mut effects,
+        active: Vec::new(),
+        problems: Vec::new(),
+    };
+
+    let result = f(&mut analyzer);
+    let name = analyzer.name(fn_loc);
+    let problems = analyzer.problems;
+    ctx.stack_effects = effects;
+    (name, result, problems)
+}
+
+/// Analyze a function that the style has just defined, reporting the
+/// problems found in its body, including those of the `{...}` blocks in it.
+pub(crate) fn lint_function(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+    fn_loc: HashPointer,
+) {
+    let (name, _, problems) = analyze(ctx, globals, fn_loc, |a| a.effect_of(fn_loc));
+
+    for (line, problem) in problems {
+        report(ctx, globals.pool, &name, line, problem);
+    }
+}
+
+/// Check that the function at *fn_loc* leaves the stack as it finds it, when
+/// it's run by a command at the current line of the style.
+pub(crate) fn lint_command(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+    command: fn(String) -> StyleCommand,
+    fn_loc: HashPointer,
+) {
+    let line = ctx.bst.as_ref().unwrap().line;
+    let (name, effect, mut problems) =
+        analyze(ctx, globals, fn_loc, |a| a.standalone(fn_loc, line));
+
+    if let Some(effect) = effect {
+        if !effect.inputs.is_empty() {
+            problems.push((
+                line,
+                StyleProblem::Underflow {
+                    command: command(name.clone()),
+                    values: effect.inputs.len(),
+                },
+            ));
+        }
+
+        if !effect.outputs.is_empty() {
+            problems.push((
+                line,
+                StyleProblem::LeftOnStack {
+                    command: command(name.clone()),
+                    values: effect.outputs.len(),
+                },
+            ));
+        }
+    }
+
+    for (line, problem) in problems {
+        report(ctx, globals.pool, &name, line, problem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let state = |inputs: &[Ty], outputs: &[Ty]| State {
+            effect: Effect {
+                inputs: inputs.to_vec(),
+                outputs: outputs.to_vec(),
+            },
+            open: false,
+        };
+
+        // `{ ", " * } 'skip$ if$` leaves a string either way.
+        let merged = state(&[Ty::Str], &[Ty::Str]).merge(state(&[], &[]));
+        assert!(!merged.open);
+        assert_eq!(merged.effect.inputs, [Ty::Str]);
+        assert_eq!(merged.effect.outputs, [Ty::Str]);
+
+        let merged = state(&[], &[Ty::Int]).merge(state(&[], &[Ty::Str]));
+        assert_eq!(merged.effect.outputs, [Ty::Any]);
+
+        let merged = state(&[], &[Ty::Str]).merge(state(&[], &[]));
+        assert!(merged.open);
+        assert!(merged.effect.outputs.is_empty());
+    }
+}
@@ -1,5 +1,9 @@
 use crate::{cite::CiteInfo, ASCIICode};
 
+/// The longest entry string that classic BibTeX can hold. Longer strings are
+/// only truncated to this if [`BibtexEngine::truncate_strings()`] is enabled.
+///
+/// [`BibtexEngine::truncate_strings()`]: crate::BibtexEngine::truncate_strings
 pub(crate) const ENT_STR_SIZE: usize = 250;
 
 pub(crate) struct EntryData {
@@ -7,7 +11,7 @@ pub(crate) struct EntryData {
     num_entry_strs: usize,
     sort_key_num: usize,
     entry_ints: Option<Vec<i64>>,
-    entry_strs: Option<Vec<ASCIICode>>,
+    entry_strs: Option<Vec<Vec<ASCIICode>>>,
 }
 
 impl EntryData {
@@ -29,23 +33,14 @@ impl EntryData {
         self.entry_ints.as_mut().unwrap()[pos] = val;
     }
 
-    pub fn strs(&self, start: usize) -> &[ASCIICode] {
-        let start = start * (ENT_STR_SIZE + 1);
-        let strs = self.entry_strs.as_ref().unwrap();
-        let end_pos = strs[start..start + ENT_STR_SIZE + 1]
-            .iter()
-            .position(|c| *c == 127)
-            .unwrap_or(ENT_STR_SIZE + 1);
-
-        &strs[start..start + end_pos]
+    pub fn strs(&self, pos: usize) -> &[ASCIICode] {
+        &self.entry_strs.as_ref().unwrap()[pos]
     }
 
     pub fn set_str(&mut self, pos: usize, val: &[ASCIICode]) {
-        assert!(val.len() <= ENT_STR_SIZE);
-        let pos = pos * (ENT_STR_SIZE + 1);
-        let strs = self.entry_strs.as_mut().unwrap();
-        strs[pos..pos + val.len()].copy_from_slice(val);
-        strs[pos + val.len()] = 127;
+        let str = &mut self.entry_strs.as_mut().unwrap()[pos];
+        str.clear();
+        str.extend_from_slice(val);
     }
 
     pub fn num_ent_ints(&self) -> usize {
@@ -76,11 +71,8 @@ impl EntryData {
         let num_cites = cites.num_cites();
         self.entry_ints = Some(vec![0; (self.num_entry_ints + 1) * (num_cites + 1) + 1]);
         self.entry_strs = Some(vec![
-            127;
-            (self.num_entry_strs + 1)
-                * (num_cites + 1)
-                * (ENT_STR_SIZE + 1)
-                + 1
+            Vec::new();
+            (self.num_entry_strs + 1) * (num_cites + 1) + 1
         ]);
     }
 }
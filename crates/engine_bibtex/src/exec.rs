@@ -1,25 +1,35 @@
 use crate::{
+    bbl::{last_names_sort_text, sort_text, BblCommand, EntryOrder, SortKey},
     bibs::BibData,
-    buffer::{BufTy, GlobalBuffer},
-    char_info::{LexClass, CHAR_WIDTH},
+    char_info::{CharInfo, LexClass},
     cite::CiteInfo,
+    collate::Locale,
+    custom::execute_custom,
     entries::{EntryData, ENT_STR_SIZE},
     global::{GlobalData, GLOB_STR_SIZE},
+    hardened::internal_error,
     hash::{BstBuiltin, BstFn, HashData, HashExtra},
+    limits::step_limit_exceeded,
     log::{
         brace_lvl_one_letters_complaint, braces_unbalanced_complaint,
         bst_1print_string_size_exceeded, bst_2print_string_size_exceeded,
-        bst_cant_mess_with_entries_print, output_bbl_line, print_a_pool_str, print_confusion,
-        print_fn_class,
+        bst_cant_mess_with_entries_print, locate_line, output_bbl_line, print_a_pool_str,
+        print_confusion, print_fn_class,
     },
-    pool::{Checkpoint, StrNumber, StringPool, MAX_PRINT_LINE, MIN_PRINT_LINE},
-    scan::{
-        check_brace_level, decr_brace_level, enough_text_chars, name_scan_for_and,
-        von_name_ends_and_last_name_starts_stuff, von_token_found, QUOTE_NEXT_FN,
-    },
-    ASCIICode, Bibtex, BibtexError, BufPointer, GlobalItems, HashPointer, StrIlk,
+    names::{format_nth_name, num_names, NameProblem},
+    other::OtherData,
+    pool::{StrNumber, StringPool},
+    scan::{check_brace_level, decr_brace_level, QUOTE_NEXT_FN},
+    srcmap::EntryLocation,
+    trace::{trace_call, Tracer},
+    translit::Transliteration,
+    ASCIICode, Bibtex, BibtexError, CiteNumber, GlobalItems, HashPointer, StrIlk,
+};
+use std::{
+    io::Write,
+    ops::{Deref, DerefMut, Index, Range},
+    rc::Rc,
 };
-use std::ops::{Deref, DerefMut, Index};
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum ControlSeq {
@@ -36,6 +46,147 @@ pub(crate) enum ControlSeq {
     LowerL,
     UpperL,
     LowerSS,
+    UpperSS,
+    LowerDH,
+    UpperDH,
+    LowerTH,
+    UpperTH,
+    LowerNG,
+    UpperNG,
+    LowerDJ,
+    UpperDJ,
+    LowerIJ,
+    UpperIJ,
+}
+
+impl ControlSeq {
+    /// The control sequences for special letters that BibTeX knows, with
+    /// their names.
+    pub(crate) const ALL: [(&'static [u8], ControlSeq); 24] = [
+        (b"i", ControlSeq::LowerI),
+        (b"j", ControlSeq::LowerJ),
+        (b"oe", ControlSeq::LowerOE),
+        (b"OE", ControlSeq::UpperOE),
+        (b"ae", ControlSeq::LowerAE),
+        (b"AE", ControlSeq::UpperAE),
+        (b"aa", ControlSeq::LowerAA),
+        (b"AA", ControlSeq::UpperAA),
+        (b"o", ControlSeq::LowerO),
+        (b"O", ControlSeq::UpperO),
+        (b"l", ControlSeq::LowerL),
+        (b"L", ControlSeq::UpperL),
+        (b"ss", ControlSeq::LowerSS),
+        (b"SS", ControlSeq::UpperSS),
+        (b"dh", ControlSeq::LowerDH),
+        (b"DH", ControlSeq::UpperDH),
+        (b"th", ControlSeq::LowerTH),
+        (b"TH", ControlSeq::UpperTH),
+        (b"ng", ControlSeq::LowerNG),
+        (b"NG", ControlSeq::UpperNG),
+        (b"dj", ControlSeq::LowerDJ),
+        (b"DJ", ControlSeq::UpperDJ),
+        (b"ij", ControlSeq::LowerIJ),
+        (b"IJ", ControlSeq::UpperIJ),
+    ];
+
+    /// Get the special letter written as the control sequence *name*, such
+    /// as `oe` for `\oe`.
+    pub(crate) fn of_name(name: &[u8]) -> Option<ControlSeq> {
+        ControlSeq::ALL
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, seq)| *seq)
+    }
+
+    /// Whether the letter is lowercase. Each lowercase letter but `\i` and
+    /// `\j` has an uppercase one, and the other way round.
+    pub(crate) fn is_lower(self) -> bool {
+        match self {
+            ControlSeq::LowerI
+            | ControlSeq::LowerJ
+            | ControlSeq::LowerAA
+            | ControlSeq::LowerAE
+            | ControlSeq::LowerOE
+            | ControlSeq::LowerO
+            | ControlSeq::LowerL
+            | ControlSeq::LowerSS
+            | ControlSeq::LowerDH
+            | ControlSeq::LowerTH
+            | ControlSeq::LowerNG
+            | ControlSeq::LowerDJ
+            | ControlSeq::LowerIJ => true,
+            ControlSeq::UpperAA
+            | ControlSeq::UpperAE
+            | ControlSeq::UpperOE
+            | ControlSeq::UpperO
+            | ControlSeq::UpperL
+            | ControlSeq::UpperSS
+            | ControlSeq::UpperDH
+            | ControlSeq::UpperTH
+            | ControlSeq::UpperNG
+            | ControlSeq::UpperDJ
+            | ControlSeq::UpperIJ => false,
+        }
+    }
+
+    /// Whether `purify$` keeps the first two letters of the name of the
+    /// control sequence, rather than just the first, as for `\ae`.
+    fn purifies_to_two_letters(self) -> bool {
+        !matches!(
+            self,
+            ControlSeq::LowerI
+                | ControlSeq::LowerJ
+                | ControlSeq::LowerAA
+                | ControlSeq::UpperAA
+                | ControlSeq::LowerO
+                | ControlSeq::UpperO
+                | ControlSeq::LowerL
+                | ControlSeq::UpperL
+        )
+    }
+
+    /// The character that the control sequence stands for.
+    pub(crate) fn char(self) -> char {
+        match self {
+            ControlSeq::LowerI => 'ı',
+            ControlSeq::LowerJ => 'ȷ',
+            ControlSeq::LowerAA => 'å',
+            ControlSeq::UpperAA => 'Å',
+            ControlSeq::LowerAE => 'æ',
+            ControlSeq::UpperAE => 'Æ',
+            ControlSeq::LowerOE => 'œ',
+            ControlSeq::UpperOE => 'Œ',
+            ControlSeq::LowerO => 'ø',
+            ControlSeq::UpperO => 'Ø',
+            ControlSeq::LowerL => 'ł',
+            ControlSeq::UpperL => 'Ł',
+            ControlSeq::LowerSS => 'ß',
+            ControlSeq::UpperSS => 'ẞ',
+            ControlSeq::LowerDH => 'ð',
+            ControlSeq::UpperDH => 'Ð',
+            ControlSeq::LowerTH => 'þ',
+            ControlSeq::UpperTH => 'Þ',
+            ControlSeq::LowerNG => 'ŋ',
+            ControlSeq::UpperNG => 'Ŋ',
+            ControlSeq::LowerDJ => 'đ',
+            ControlSeq::UpperDJ => 'Đ',
+            ControlSeq::LowerIJ => 'ĳ',
+            ControlSeq::UpperIJ => 'Ĳ',
+        }
+    }
+
+    /// The width of the character in cmr10, if BibTeX gives it one of its
+    /// own. The others are as wide as the first letter of their names.
+    pub(crate) fn cmr10_width(self) -> Option<i64> {
+        match self {
+            ControlSeq::LowerSS => Some(500),
+            ControlSeq::LowerAE => Some(722),
+            ControlSeq::LowerOE => Some(778),
+            ControlSeq::UpperAE => Some(903),
+            ControlSeq::UpperOE => Some(1014),
+            _ => None,
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -68,23 +219,193 @@ impl ExecVal {
     }
 }
 
+/// Where the entry with the given cite number was defined, for the source map.
+pub(crate) fn entry_location(
+    pool: &StringPool,
+    cites: &CiteInfo,
+    cite: CiteNumber,
+) -> Option<EntryLocation> {
+    let source = cites.source(cite)?;
+    let mut file = String::from_utf8_lossy(pool.get_str(source.file)).into_owned();
+
+    if !file.ends_with(".bib") {
+        file.push_str(".bib");
+    }
+
+    Some(EntryLocation {
+        cite_key: String::from_utf8_lossy(pool.get_str(cites.get_cite(cite))).into_owned(),
+        file,
+        line: source.line,
+    })
+}
+
+/// Get the value of a field of an entry, if the style declared the field and
+/// the entry has it.
+pub(crate) fn field_value<'p>(
+    pool: &'p StringPool,
+    hash: &HashData,
+    other: &OtherData,
+    cite: CiteNumber,
+    name: &[u8],
+) -> Option<&'p [u8]> {
+    let res = hash.lookup_str(pool, name, StrIlk::BstFn);
+
+    if !res.exists {
+        return None;
+    }
+
+    let HashExtra::BstFn(BstFn::Field(field)) = hash.node(res.loc).extra else {
+        return None;
+    };
+
+    let value = other.field(cite * other.num_fields() + field);
+    (!value.is_invalid()).then(|| pool.get_str(value))
+}
+
+/// Get the value of the string entry variable *name* of the entry *cite*, if
+/// the style declares one.
+fn entry_str_value<'e>(
+    pool: &StringPool,
+    hash: &HashData,
+    entries: &'e EntryData,
+    cite: CiteNumber,
+    name: &[u8],
+) -> Option<&'e [u8]> {
+    let res = hash.lookup_str(pool, name, StrIlk::BstFn);
+
+    if !res.exists {
+        return None;
+    }
+
+    let HashExtra::BstFn(BstFn::StrEntry(entry)) = hash.node(res.loc).extra else {
+        return None;
+    };
+
+    Some(entries.strs(cite * entries.num_ent_strs() + entry))
+}
+
+fn entry_sort_key(
+    order: &EntryOrder,
+    translit: &Transliteration,
+    collation: Option<Locale>,
+    pool: &StringPool,
+    hash: &HashData,
+    other: &OtherData,
+    cite: CiteNumber,
+) -> SortKey {
+    let field = |name: &[u8]| field_value(pool, hash, other, cite, name).map(|v| translit.apply(v));
+    let collate = |text: Vec<u8>| match collation {
+        Some(locale) => locale.sort_key(&text),
+        None => text,
+    };
+
+    match order {
+        // Cites are numbered in the order in which they were first seen, and
+        // the style's sorting only permutes the list that `ITERATE` follows.
+        EntryOrder::Style | EntryOrder::Citation => SortKey::Rank(cite),
+        EntryOrder::AuthorYear => SortKey::values([
+            field(b"author")
+                .or_else(|| field(b"editor"))
+                .map(|v| collate(last_names_sort_text(&v))),
+            field(b"year").map(|v| collate(sort_text(&v))),
+            field(b"title").map(|v| collate(sort_text(&v))),
+        ]),
+        EntryOrder::Field(name) => SortKey::values([
+            field(name.to_ascii_lowercase().as_bytes()).map(|v| collate(sort_text(&v)))
+        ]),
+    }
+}
+
 pub(crate) struct ExecCtx<'a, 'bib, 'cbs> {
     glbl_ctx: &'a mut Bibtex<'bib, 'cbs>,
     pub default: HashPointer,
     pub(crate) lit_stack: Vec<ExecVal>,
     pub mess_with_entries: bool,
-    /// Pointer to the current top of the string pool, used to optimize certain string operations
-    pub checkpoint: Checkpoint,
+    /// The wizard-defined functions currently being executed, innermost last
+    pub(crate) wiz_fns: Vec<HashPointer>,
+    /// The state of tracing, if the execution of the style is traced or has
+    /// breakpoints
+    pub(crate) trace: Option<Tracer>,
+    /// The number of functions that the style may still call, if its steps
+    /// are limited
+    pub(crate) steps_left: Option<u64>,
 }
 
 impl<'a, 'bib, 'cbs> ExecCtx<'a, 'bib, 'cbs> {
     pub(crate) fn new(glbl_ctx: &'a mut Bibtex<'bib, 'cbs>) -> ExecCtx<'a, 'bib, 'cbs> {
+        let trace = Tracer::new(glbl_ctx.config.trace_bst, &glbl_ctx.config.bst_breakpoints);
+        let steps_left = glbl_ctx.config.step_limit;
+
         ExecCtx {
             glbl_ctx,
             default: 0,
             lit_stack: Vec::new(),
             mess_with_entries: false,
-            checkpoint: Checkpoint::default(),
+            wiz_fns: Vec::new(),
+            trace,
+            steps_left,
+        }
+    }
+
+    /// Prepare to run the function for one invocation of an `EXECUTE`, `ITERATE`, or `REVERSE`
+    /// command. For the latter two, the current cite pointer must already be set.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn start_command_fn(
+        &mut self,
+        pool: &StringPool,
+        hash: &HashData,
+        entries: &EntryData,
+        other: &OtherData,
+        cites: &CiteInfo,
+        command: BblCommand,
+        fn_loc: HashPointer,
+    ) {
+        self.wiz_fns.clear();
+
+        if let Some(trace) = &mut self.trace {
+            trace.line = self.glbl_ctx.bst.as_ref().map_or(0, |bst| bst.line);
+        }
+
+        let reordering = self.glbl_ctx.config.reordering();
+
+        if let Some(recorder) = &mut self.glbl_ctx.bbl_recorder {
+            let function = String::from_utf8_lossy(pool.get_str(hash.text(fn_loc))).into_owned();
+            let cite_key = (command != BblCommand::Execute).then(|| {
+                String::from_utf8_lossy(pool.get_str(cites.get_cite(cites.ptr()))).into_owned()
+            });
+            recorder.start_block(command, function, cite_key);
+
+            if command != BblCommand::Execute {
+                let cite = cites.ptr();
+                let sort_key = entries.strs(cite * entries.num_ent_strs() + entries.sort_key_num());
+                let label = entry_str_value(pool, hash, entries, cite, b"label");
+                recorder.set_entry_values(
+                    String::from_utf8_lossy(sort_key).into_owned(),
+                    label.map(|l| String::from_utf8_lossy(l).into_owned()),
+                );
+            }
+
+            if reordering && command != BblCommand::Execute {
+                let key = entry_sort_key(
+                    &self.glbl_ctx.config.entry_order,
+                    &self.glbl_ctx.config.transliteration,
+                    self.glbl_ctx.config.collation,
+                    pool,
+                    hash,
+                    other,
+                    cites.ptr(),
+                );
+                recorder.set_entry(key, entry_location(pool, cites, cites.ptr()));
+            }
+        }
+
+        if let Some(map) = &mut self.glbl_ctx.source_map {
+            let entry = if command == BblCommand::Execute {
+                None
+            } else {
+                entry_location(pool, cites, cites.ptr())
+            };
+            map.set_entry(entry);
         }
     }
 
@@ -99,7 +420,7 @@ impl<'a, 'bib, 'cbs> ExecCtx<'a, 'bib, 'cbs> {
     ) -> Result<ExecVal, BibtexError> {
         if let Some(pop) = self.lit_stack.pop() {
             if let ExecVal::String(str) = pop {
-                if self.checkpoint.is_before(str) && !pool.remove_last_str(str) {
+                if !pool.discard_scratch(str) {
                     self.write_logs("Nontop top of string stack");
                     print_confusion(self);
                     return Err(BibtexError::Fatal);
@@ -222,7 +543,10 @@ pub(crate) fn bst_ex_warn_print(
     pool: &StringPool,
     cites: &CiteInfo,
 ) -> Result<(), BibtexError> {
+    ctx.diagnostics.end_message();
     if ctx.mess_with_entries {
+        ctx.diagnostics
+            .set_cite_key(pool.get_str(cites.get_cite(cites.ptr())));
         ctx.write_logs(" for entry ");
         print_a_pool_str(ctx, cites.get_cite(cites.ptr()), pool)?;
     }
@@ -237,6 +561,12 @@ pub(crate) fn bst_ln_num_print(
     ctx: &mut Bibtex<'_, '_>,
     pool: &StringPool,
 ) -> Result<(), BibtexError> {
+    locate_line(
+        &mut ctx.diagnostics,
+        pool,
+        ctx.bst.as_ref().unwrap(),
+        ".bst",
+    );
     ctx.write_logs(&format!(
         "--line {} of file ",
         ctx.bst.as_ref().unwrap().line
@@ -300,414 +630,58 @@ pub fn skip_brace_level_greater_than_one(str: &[ASCIICode], brace_level: &mut i3
     pos
 }
 
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn figure_out_the_formatted_name(
-    ctx: &mut ExecCtx<'_, '_, '_>,
-    buffers: &mut GlobalBuffer,
-    pool: &StringPool,
-    cites: &CiteInfo,
-    s1: StrNumber,
-    first_start: BufPointer,
-    first_end: BufPointer,
-    last_end: BufPointer,
-    von_start: BufPointer,
-    von_end: BufPointer,
-    name_bf_ptr: &mut BufPointer,
-    name_bf_xptr: &mut BufPointer,
-    jr_end: BufPointer,
-    brace_level: &mut i32,
-) -> Result<(), BibtexError> {
-    let mut old_idx;
-    let mut inner_brace_level = 0;
-    let str = pool.get_str(s1);
-    let mut idx = 0;
-
-    buffers.set_offset(BufTy::Ex, 1, 0);
-
-    while idx < str.len() {
-        if str[idx] == b'{' {
-            inner_brace_level += 1;
-            idx += 1;
-            old_idx = idx;
-
-            let mut alpha_found = false;
-            let mut double_letter = false;
-            let mut end_of_group = false;
-            let mut to_be_written = true;
-            let mut cur_token = 0;
-            let mut last_token = 0;
-
-            while !end_of_group && idx < str.len() {
-                if LexClass::of(str[idx]) == LexClass::Alpha {
-                    idx += 1;
-                    if alpha_found {
-                        brace_lvl_one_letters_complaint(ctx, pool, cites, s1)?;
-                        to_be_written = false;
-                    } else {
-                        match str[idx - 1] {
-                            b'f' | b'F' => {
-                                cur_token = first_start;
-                                last_token = first_end;
-                                if cur_token == last_token {
-                                    to_be_written = false;
-                                }
-                                if str[idx] == b'f' || str[idx] == b'F' {
-                                    double_letter = true;
-                                }
-                            }
-                            b'v' | b'V' => {
-                                cur_token = von_start;
-                                last_token = von_end;
-                                if cur_token == last_token {
-                                    to_be_written = false;
-                                }
-                                if str[idx] == b'v' || str[idx] == b'V' {
-                                    double_letter = true;
-                                }
-                            }
-                            b'l' | b'L' => {
-                                cur_token = von_end;
-                                last_token = last_end;
-                                if cur_token == last_token {
-                                    to_be_written = false;
-                                }
-                                if str[idx] == b'l' || str[idx] == b'L' {
-                                    double_letter = true;
-                                }
-                            }
-                            b'j' | b'J' => {
-                                cur_token = last_end;
-                                last_token = jr_end;
-                                if cur_token == last_token {
-                                    to_be_written = false;
-                                }
-                                if str[idx] == b'j' || str[idx] == b'J' {
-                                    double_letter = true;
-                                }
-                            }
-                            _ => {
-                                brace_lvl_one_letters_complaint(ctx, pool, cites, s1)?;
-                                to_be_written = false;
-                                break;
-                            }
-                        }
-                        if double_letter {
-                            idx += 1;
-                        }
-                    }
-                    alpha_found = true;
-                } else if str[idx] == b'}' {
-                    inner_brace_level -= 1;
-                    idx += 1;
-                    end_of_group = true;
-                } else if str[idx] == b'{' {
-                    inner_brace_level += 1;
-                    idx =
-                        skip_brace_level_greater_than_one(&str[idx + 1..], &mut inner_brace_level)
-                            + idx;
-                    idx += 1;
-                } else {
-                    idx += 1;
-                }
-            }
-
-            if end_of_group && to_be_written {
-                let buf_ptr = buffers.offset(BufTy::Ex, 1);
-                idx = old_idx;
-                inner_brace_level = 1;
-                while inner_brace_level > 0 {
-                    if LexClass::of(str[idx]) == LexClass::Alpha && inner_brace_level == 1 {
-                        idx += 1;
-                        if double_letter {
-                            idx += 1;
-                        }
-                        let mut use_default = true;
-                        let mut sp_xptr2 = idx;
-                        if str[idx] == b'{' {
-                            use_default = false;
-                            inner_brace_level += 1;
-                            idx += 1;
-                            old_idx = idx;
-                            idx = skip_brace_level_greater_than_one(
-                                &str[idx..],
-                                &mut inner_brace_level,
-                            ) + idx;
-                            sp_xptr2 = idx - 1;
-                        }
-                        while cur_token < last_token {
-                            *name_bf_ptr = buffers.name_tok(cur_token);
-                            *name_bf_xptr = buffers.name_tok(cur_token + 1);
-                            if double_letter {
-                                if buffers.init(BufTy::Ex) + (*name_bf_xptr - *name_bf_ptr)
-                                    > buffers.len()
-                                {
-                                    buffers.grow_all();
-                                }
-                                let ptr = buffers.offset(BufTy::Ex, 1);
-                                let len = *name_bf_xptr - *name_bf_ptr;
-                                buffers.copy_within(BufTy::Sv, BufTy::Ex, *name_bf_ptr, ptr, len);
-                                buffers.set_offset(BufTy::Ex, 1, ptr + len);
-                                *name_bf_ptr += len;
-                            } else {
-                                while *name_bf_ptr < *name_bf_xptr {
-                                    if LexClass::of(buffers.at(BufTy::Sv, *name_bf_ptr))
-                                        == LexClass::Alpha
-                                    {
-                                        if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                                            buffers.grow_all();
-                                        }
-                                        buffers.set_at(
-                                            BufTy::Ex,
-                                            buffers.offset(BufTy::Ex, 1),
-                                            buffers.at(BufTy::Sv, *name_bf_ptr),
-                                        );
-                                        buffers.set_offset(
-                                            BufTy::Ex,
-                                            1,
-                                            buffers.offset(BufTy::Ex, 1) + 1,
-                                        );
-                                        break;
-                                    } else if *name_bf_ptr + 1 < *name_bf_xptr
-                                        && buffers.at(BufTy::Sv, *name_bf_ptr) == b'{'
-                                        && buffers.at(BufTy::Sv, *name_bf_ptr + 1) == b'\\'
-                                    {
-                                        if buffers.offset(BufTy::Ex, 1) + 2 > buffers.len() {
-                                            buffers.grow_all();
-                                        }
-                                        let offset = buffers.offset(BufTy::Ex, 1);
-                                        buffers.set_at(BufTy::Ex, offset, b'{');
-                                        buffers.set_at(BufTy::Ex, offset + 1, b'\\');
-                                        buffers.set_offset(BufTy::Ex, 1, offset + 2);
-                                        *name_bf_ptr += 2;
-                                        let mut nm_brace_level = 1;
-                                        while *name_bf_ptr < *name_bf_xptr && nm_brace_level > 0 {
-                                            if buffers.at(BufTy::Sv, *name_bf_ptr) == b'}' {
-                                                nm_brace_level -= 1;
-                                            } else if buffers.at(BufTy::Sv, *name_bf_ptr) == b'{' {
-                                                nm_brace_level += 1;
-                                            }
-
-                                            if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                                                buffers.grow_all();
-                                            }
-
-                                            buffers.set_at(
-                                                BufTy::Ex,
-                                                buffers.offset(BufTy::Ex, 1),
-                                                buffers.at(BufTy::Sv, *name_bf_ptr),
-                                            );
-                                            buffers.set_offset(
-                                                BufTy::Ex,
-                                                1,
-                                                buffers.offset(BufTy::Ex, 1) + 1,
-                                            );
-                                            *name_bf_ptr += 1;
-                                        }
-                                        break;
-                                    }
-                                    *name_bf_ptr += 1;
-                                }
-                            }
-
-                            cur_token += 1;
-                            if cur_token < last_token {
-                                if use_default {
-                                    if !double_letter {
-                                        if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                                            buffers.grow_all();
-                                        }
-                                        buffers.set_at(
-                                            BufTy::Ex,
-                                            buffers.offset(BufTy::Ex, 1),
-                                            b'.',
-                                        );
-                                        buffers.set_offset(
-                                            BufTy::Ex,
-                                            1,
-                                            buffers.offset(BufTy::Ex, 1) + 1,
-                                        );
-                                    }
-
-                                    if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                                        buffers.grow_all();
-                                    }
-
-                                    let c = if LexClass::of(buffers.at(BufTy::NameSep, cur_token))
-                                        == LexClass::Sep
-                                    {
-                                        buffers.at(BufTy::NameSep, cur_token)
-                                    } else if cur_token == last_token - 1
-                                        || (!enough_text_chars(buffers, 3, buf_ptr, brace_level))
-                                    {
-                                        b'~'
-                                    } else {
-                                        b' '
-                                    };
-                                    buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), c);
-                                    buffers.set_offset(
-                                        BufTy::Ex,
-                                        1,
-                                        buffers.offset(BufTy::Ex, 1) + 1,
-                                    );
-                                } else {
-                                    if buffers.offset(BufTy::Ex, 1) + (sp_xptr2 - old_idx)
-                                        > buffers.len()
-                                    {
-                                        buffers.grow_all();
-                                    }
-
-                                    let ptr = buffers.offset(BufTy::Ex, 1);
-                                    let tmp_str = &str[old_idx..sp_xptr2];
-                                    buffers.copy_from(BufTy::Ex, ptr, tmp_str);
-                                    buffers.set_offset(BufTy::Ex, 1, ptr + tmp_str.len());
-                                    idx = sp_xptr2;
-                                }
-                            }
-                        }
-                        if !use_default {
-                            idx = sp_xptr2 + 1;
-                        }
-                    } else if str[idx] == b'}' {
-                        inner_brace_level -= 1;
-                        idx += 1;
-                        if inner_brace_level > 0 {
-                            if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                                buffers.grow_all();
-                            }
-                            buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), b'}');
-                            buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                        }
-                    } else if str[idx] == b'{' {
-                        inner_brace_level += 1;
-                        idx += 1;
-                        if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                            buffers.grow_all();
-                        }
-                        buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), b'{');
-                        buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                    } else {
-                        if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                            buffers.grow_all();
-                        }
-                        buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), str[idx]);
-                        buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                        idx += 1;
-                    }
-                }
-                if buffers.offset(BufTy::Ex, 1) > 0
-                    && buffers.at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) - 1) == b'~'
-                {
-                    buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) - 1);
-                    if buffers.at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) - 1) == b'~' {
-                    } else if !enough_text_chars(buffers, 3, buf_ptr, brace_level) {
-                        buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                    } else {
-                        buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), b' ');
-                        buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                    }
-                }
-            }
-        } else if str[idx] == b'}' {
-            braces_unbalanced_complaint(ctx, pool, cites, s1)?;
-            idx += 1;
-        } else {
-            if buffers.offset(BufTy::Ex, 1) == buffers.len() {
-                buffers.grow_all();
-            }
-            buffers.set_at(BufTy::Ex, buffers.offset(BufTy::Ex, 1), str[idx]);
-            buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-            idx += 1;
-        }
-    }
-
-    if inner_brace_level > 0 {
-        braces_unbalanced_complaint(ctx, pool, cites, s1)?;
-    }
-
-    buffers.set_init(BufTy::Ex, buffers.offset(BufTy::Ex, 1));
-
-    Ok(())
-}
-
-pub(crate) fn add_buf_pool(pool: &StringPool, buffers: &mut GlobalBuffer, str: StrNumber) {
-    let str = pool.get_str(str);
-
-    if buffers.init(BufTy::Ex) + str.len() > buffers.len() {
-        buffers.grow_all();
-    }
-
-    let start = buffers.init(BufTy::Ex);
-    buffers.copy_from(BufTy::Ex, start, str);
-    buffers.set_offset(BufTy::Ex, 1, start + str.len());
-    buffers.set_init(BufTy::Ex, start + str.len());
-}
-
 pub(crate) fn add_out_pool(
-    ctx: &mut Bibtex<'_, '_>,
-    buffers: &mut GlobalBuffer,
+    ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &StringPool,
+    hash: &HashData,
     str: StrNumber,
 ) {
     let str = pool.get_str(str);
 
-    while buffers.init(BufTy::Out) + str.len() > buffers.len() {
-        buffers.grow_all();
+    if let Some(idx) = ctx.cur_output {
+        let Bibtex {
+            engine,
+            extra_outputs,
+            ..
+        } = &mut *ctx.glbl_ctx;
+        let output = &mut extra_outputs[idx];
+        let out = engine.get_output(output.file);
+        output.lines.write(str, &mut |line| {
+            out.write_all(line).unwrap();
+        });
+        return;
     }
 
-    let out_offset = buffers.init(BufTy::Out);
-    buffers.copy_from(BufTy::Out, out_offset, str);
-    buffers.set_init(BufTy::Out, out_offset + str.len());
-
-    let mut unbreakable_tail = false;
-    while buffers.init(BufTy::Out) > MAX_PRINT_LINE && !unbreakable_tail {
-        let end_ptr = buffers.init(BufTy::Out);
-        let mut out_offset = MAX_PRINT_LINE;
-        let mut break_pt_found = false;
-
-        while LexClass::of(buffers.at(BufTy::Out, out_offset)) != LexClass::Whitespace
-            && out_offset >= MIN_PRINT_LINE
-        {
-            out_offset -= 1;
-        }
-
-        if out_offset == MIN_PRINT_LINE - 1 {
-            out_offset = MAX_PRINT_LINE + 1;
-            while out_offset < end_ptr {
-                if LexClass::of(buffers.at(BufTy::Out, out_offset)) != LexClass::Whitespace {
-                    out_offset += 1;
-                } else {
-                    break;
-                }
-            }
-
-            if out_offset == end_ptr {
-                unbreakable_tail = true;
-            } else {
-                break_pt_found = true;
-                while out_offset + 1 < end_ptr {
-                    if LexClass::of(buffers.at(BufTy::Out, out_offset + 1)) == LexClass::Whitespace
-                    {
-                        out_offset += 1;
-                    } else {
-                        break;
-                    }
-                }
-            }
-        } else {
-            break_pt_found = true;
-        }
+    if let Some(recorder) = &mut ctx.glbl_ctx.bbl_recorder {
+        let function = ctx
+            .wiz_fns
+            .last()
+            .map(|&loc| String::from_utf8_lossy(pool.get_str(hash.text(loc))).into_owned());
+        recorder.write(str, function);
+    }
 
-        if break_pt_found {
-            buffers.set_init(BufTy::Out, out_offset);
-            let break_ptr = buffers.init(BufTy::Out) + 1;
-            output_bbl_line(ctx, buffers);
-            buffers.set_at(BufTy::Out, 0, b' ');
-            buffers.set_at(BufTy::Out, 1, b' ');
-            let len = end_ptr - break_ptr;
-            buffers.copy_within(BufTy::Out, BufTy::Out, break_ptr, 2, len);
-            buffers.set_init(BufTy::Out, len + 2);
-        }
+    // When reordering, the `.bbl` file is written from the recording at the
+    // end of the run.
+    if ctx.glbl_ctx.config.reordering() {
+        return;
     }
+
+    let Bibtex {
+        engine,
+        bbl_file,
+        bbl_line_num,
+        bbl_lines,
+        source_map,
+        ..
+    } = &mut *ctx.glbl_ctx;
+    let out = engine.get_output(bbl_file.unwrap());
+    bbl_lines.write(str, &mut |line| {
+        out.write_all(line).unwrap();
+        if let Some(map) = source_map {
+            map.line(*bbl_line_num);
+        }
+        *bbl_line_num += 1;
+    });
 }
 
 pub(crate) fn check_command_execution(
@@ -723,7 +697,7 @@ pub(crate) fn check_command_execution(
         ctx.write_logs("---the literal stack isn't empty");
         bst_ex_warn_print(ctx, pool, cites)?;
     }
-    if !pool.is_at(ctx.checkpoint) {
+    if pool.has_scratch() {
         ctx.write_logs("Nonempty empty string stack");
         print_confusion(ctx);
         return Err(BibtexError::Fatal);
@@ -731,18 +705,6 @@ pub(crate) fn check_command_execution(
     Ok(())
 }
 
-fn add_pool_buf_and_push(
-    ctx: &mut ExecCtx<'_, '_, '_>,
-    buffers: &mut GlobalBuffer,
-    pool: &mut StringPool,
-) -> Result<(), BibtexError> {
-    buffers.set_offset(BufTy::Ex, 1, buffers.init(BufTy::Ex));
-    let str = &buffers.buffer(BufTy::Ex)[0..buffers.init(BufTy::Ex)];
-    let val = ExecVal::String(pool.add_string(str));
-    ctx.push_stack(val);
-    Ok(())
-}
-
 fn interp_eq(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
@@ -757,10 +719,7 @@ fn interp_eq(
             ctx.push_stack(ExecVal::Integer((i1 == i2) as i64));
         }
         (ExecVal::String(s1), ExecVal::String(s2)) => {
-            // TODO: Can we just compare str numbers here?
-            ctx.push_stack(ExecVal::Integer(
-                (pool.get_str(s1) == pool.get_str(s2)) as i64,
-            ));
+            ctx.push_stack(ExecVal::Integer(pool.str_eq(s1, s2) as i64));
         }
         _ if pop1.ty() != pop2.ty() => {
             if pop1.ty() != StkType::Illegal && pop2.ty() != StkType::Illegal {
@@ -907,72 +866,15 @@ fn interp_concat(
         }
     };
 
-    // A string pointer being >= bib_str_ptr means it's a 'scratch string' not yet saved permanently
-    // TODO: Add pool API for scratch strings, instead of doing it manually through dangerous manual
-    //       implementation of strings
-
-    if ctx.checkpoint.is_before(s2) && ctx.checkpoint.is_before(s1) {
-        // Both strings are 'scratch', they must be next to each-other due to external invariants,
-        // se we just make one new string covering both
-        let new_len = pool.get_str(s1).len() + pool.get_str(s2).len();
-        let new = pool.write_str(|cursor| cursor.extend(new_len));
-        ctx.push_stack(ExecVal::String(new));
-    } else if ctx.checkpoint.is_before(s2) {
-        if pool.get_str(s2).is_empty() {
-            ctx.push_stack(pop1);
-        } else {
-            // s2 is scratch, we add s1 to its end and return the new scratch string
-            let s2_len = pool.get_str(s2).len();
-            let new = pool.write_str(|cursor| {
-                cursor.extend(s2_len);
-                cursor.append_str(s1);
-            });
-            ctx.push_stack(ExecVal::String(new));
-        }
-    } else if ctx.checkpoint.is_before(s1) {
-        let str1 = pool.get_str(s1);
-        let str2 = pool.get_str(s2);
-
-        if str2.is_empty() {
-            // s1 is scratch and s2 is empty - just save s1 and return it
-            let s1_len = str1.len();
-            let new = pool.write_str(|cursor| cursor.extend(s1_len));
-            ctx.push_stack(ExecVal::String(new));
-        } else if str1.is_empty() {
-            // s1 is empty - just return s2
-            ctx.push_stack(pop2);
-        } else {
-            let s1_len = str1.len();
-            let s2_len = str2.len();
-
-            // s1 is scratch and s2 is not - we want to copy s1 forward by the length of s2,
-            // then write s2 in where it was, returning the new scratch string
-            let new = pool.write_str(|cursor| {
-                cursor.extend(s1_len + s2_len);
-                let raw = cursor.bytes();
-                raw.copy_within(0..s1_len, s2_len);
-                cursor.insert_str(s2, 0);
-            });
-            let val = ExecVal::String(new);
-            ctx.push_stack(val);
-        }
+    // An empty permanent string can stand for the result, rather than making
+    // a new one. Scratch strings are discarded when popped, so they can't.
+    if pool.get_str(s1).is_empty() && !pool.is_scratch(s2) {
+        ctx.push_stack(pop2);
+    } else if pool.get_str(s2).is_empty() && !pool.is_scratch(s1) {
+        ctx.push_stack(pop1);
     } else {
-        let str1 = pool.get_str(s1);
-        let str2 = pool.get_str(s2);
-
-        if str1.is_empty() {
-            ctx.push_stack(pop2);
-        } else if str2.is_empty() {
-            ctx.push_stack(pop1);
-        } else {
-            // Neither is scratch or empty - make a new scratch string from the concat of both
-            let new = pool.write_str(|cursor| {
-                cursor.append_str(s2);
-                cursor.append_str(s1);
-            });
-            let val = ExecVal::String(new);
-            ctx.push_stack(val);
-        }
+        let new = pool.concat(s2, s1);
+        ctx.push_stack(ExecVal::String(new));
     }
     Ok(())
 }
@@ -1010,7 +912,16 @@ fn interp_gets(
         HashExtra::BstFn(BstFn::StrEntry(entry)) => {
             if let ExecVal::String(s2) = pop2 {
                 let mut s = pool.get_str(s2);
-                if s.len() > ENT_STR_SIZE {
+
+                // Transliterate sort keys before they're truncated, if they
+                // are, since that may make them longer.
+                let translit;
+                if *entry == entries.sort_key_num() {
+                    translit = ctx.config.transliteration.apply(s);
+                    s = &translit;
+                }
+
+                if ctx.config.truncate_strings && s.len() > ENT_STR_SIZE {
                     bst_1print_string_size_exceeded(ctx);
                     ctx.write_logs(&format!("{ENT_STR_SIZE}, the entry"));
                     bst_2print_string_size_exceeded(ctx, pool, cites)?;
@@ -1030,12 +941,12 @@ fn interp_gets(
         }
         HashExtra::BstFn(BstFn::StrGlbl(str_ptr)) => {
             if let ExecVal::String(s2) = pop2 {
-                if !ctx.checkpoint.is_before(s2) {
+                if !pool.is_scratch(s2) {
                     globals.set_str_ptr(*str_ptr, s2);
                 } else {
                     globals.set_str_ptr(*str_ptr, StrNumber::invalid());
                     let mut s = pool.get_str(s2);
-                    if s.len() > GLOB_STR_SIZE {
+                    if ctx.config.truncate_strings && s.len() > GLOB_STR_SIZE {
                         bst_1print_string_size_exceeded(ctx);
                         ctx.write_logs(&format!("{GLOB_STR_SIZE}, the global"));
                         bst_2print_string_size_exceeded(ctx, pool, cites)?;
@@ -1085,24 +996,12 @@ fn interp_add_period(
 
     match str[pos] {
         b'.' | b'?' | b'!' => {
-            // If scratch, save
-            if ctx.checkpoint.is_before(s1) {
-                let s1_len = pool.get_str(s1).len();
-                let new = pool.write_str(|cursor| cursor.extend(s1_len));
-                ctx.push_stack(ExecVal::String(new));
-            } else {
-                ctx.push_stack(pop1);
-            }
+            let s1 = pool.restore_scratch(s1);
+            ctx.push_stack(ExecVal::String(s1));
         }
         _ => {
-            let is_bst_str = ctx.checkpoint.is_before(s1);
-            let s1_len = pool.get_str(s1).len();
             let new = pool.write_str(|cursor| {
-                if is_bst_str {
-                    cursor.extend(s1_len);
-                } else {
-                    cursor.append_str(s1);
-                }
+                cursor.append_str(s1);
                 cursor.append(b'.');
             });
             let val = ExecVal::String(new);
@@ -1151,6 +1050,19 @@ fn interp_change_case(
                 bst_ex_warn_print(ctx, pool, cites)?;
             }
 
+            let memo_key = (conv_ty != ConvTy::Bad && !pool.is_scratch(s2)).then(|| str1[0]);
+
+            if let Some(conv) = memo_key {
+                if let Some(result) = ctx.memo.as_mut().and_then(|m| m.case_changed(conv, s2)) {
+                    let val = ExecVal::String(pool.add_string(result));
+                    ctx.push_stack(val);
+                    return Ok(());
+                }
+            }
+
+            let history = ctx.history;
+            let unicode = ctx.config.unicode_case;
+            let chars = Rc::clone(&ctx.char_info);
             let mut scratch = Vec::from(pool.get_str(s2));
 
             let mut brace_level = 0;
@@ -1164,7 +1076,7 @@ fn interp_change_case(
                         || (conv_ty == ConvTy::TitleLower
                             && (idx == 0
                                 || (prev_colon
-                                    && LexClass::of(scratch[idx - 1]) == LexClass::Whitespace))))
+                                    && chars.lex_class(scratch[idx - 1]) == LexClass::Whitespace))))
                     {
                         idx += 1;
 
@@ -1172,7 +1084,7 @@ fn interp_change_case(
                             idx += 1;
                             let old_idx = idx;
                             while idx < scratch.len()
-                                && LexClass::of(scratch[idx]) == LexClass::Alpha
+                                && chars.lex_class(scratch[idx]) == LexClass::Alpha
                             {
                                 idx += 1;
                             }
@@ -1182,35 +1094,26 @@ fn interp_change_case(
 
                             if res.exists {
                                 let HashExtra::ControlSeq(seq) = hash.node(res.loc).extra else {
-                                    panic!("ControlSeq lookup didn't have ControlSeq extra");
+                                    return Err(internal_error(
+                                        ctx,
+                                        "ControlSeq lookup didn't have ControlSeq extra",
+                                    ));
                                 };
                                 match conv_ty {
-                                    ConvTy::TitleLower | ConvTy::AllLower => match seq {
-                                        ControlSeq::UpperOE
-                                        | ControlSeq::UpperAE
-                                        | ControlSeq::UpperAA
-                                        | ControlSeq::UpperO
-                                        | ControlSeq::UpperL => {
-                                            scratch[old_idx..idx].make_ascii_lowercase()
+                                    ConvTy::TitleLower | ConvTy::AllLower => {
+                                        if !seq.is_lower() {
+                                            chars.make_lowercase(&mut scratch[old_idx..idx])
                                         }
-                                        _ => (),
-                                    },
+                                    }
                                     ConvTy::AllUpper => match seq {
-                                        ControlSeq::LowerOE
-                                        | ControlSeq::LowerAE
-                                        | ControlSeq::LowerAA
-                                        | ControlSeq::LowerO
-                                        | ControlSeq::LowerL => {
-                                            scratch[old_idx..idx].make_ascii_uppercase()
-                                        }
                                         ControlSeq::LowerI
                                         | ControlSeq::LowerJ
                                         | ControlSeq::LowerSS => {
-                                            scratch[old_idx..idx].make_ascii_uppercase();
+                                            chars.make_uppercase(&mut scratch[old_idx..idx]);
                                             scratch.copy_within(old_idx..idx, old_idx - 1);
                                             let old_idx = idx - 1;
                                             while idx < scratch.len()
-                                                && LexClass::of(scratch[idx])
+                                                && chars.lex_class(scratch[idx])
                                                     == LexClass::Whitespace
                                             {
                                                 idx += 1;
@@ -1219,6 +1122,9 @@ fn interp_change_case(
                                             scratch.truncate(scratch.len() - idx + old_idx);
                                             idx = old_idx;
                                         }
+                                        _ if seq.is_lower() => {
+                                            chars.make_uppercase(&mut scratch[old_idx..idx])
+                                        }
                                         _ => (),
                                     },
                                     ConvTy::Bad => (),
@@ -1237,9 +1143,23 @@ fn interp_change_case(
 
                             match conv_ty {
                                 ConvTy::TitleLower | ConvTy::AllLower => {
-                                    scratch[old_idx..idx].make_ascii_lowercase()
+                                    idx = change_range_case(
+                                        &mut scratch,
+                                        old_idx..idx,
+                                        false,
+                                        unicode,
+                                        &chars,
+                                    )
+                                }
+                                ConvTy::AllUpper => {
+                                    idx = change_range_case(
+                                        &mut scratch,
+                                        old_idx..idx,
+                                        true,
+                                        unicode,
+                                        &chars,
+                                    )
                                 }
-                                ConvTy::AllUpper => scratch[old_idx..idx].make_ascii_uppercase(),
                                 ConvTy::Bad => (),
                             }
                         }
@@ -1255,25 +1175,36 @@ fn interp_change_case(
                         ConvTy::TitleLower => {
                             if idx != 0
                                 && !(prev_colon
-                                    && LexClass::of(scratch[idx - 1]) == LexClass::Whitespace)
+                                    && chars.lex_class(scratch[idx - 1]) == LexClass::Whitespace)
                             {
-                                scratch[idx].make_ascii_lowercase()
+                                idx = change_char_case(&mut scratch, idx, false, unicode, &chars);
                             }
 
                             if scratch[idx] == b':' {
                                 prev_colon = true;
-                            } else if LexClass::of(scratch[idx]) != LexClass::Whitespace {
+                            } else if chars.lex_class(scratch[idx]) != LexClass::Whitespace {
                                 prev_colon = false;
                             }
                         }
-                        ConvTy::AllLower => scratch[idx].make_ascii_lowercase(),
-                        ConvTy::AllUpper => scratch[idx].make_ascii_uppercase(),
+                        ConvTy::AllLower => {
+                            idx = change_char_case(&mut scratch, idx, false, unicode, &chars)
+                        }
+                        ConvTy::AllUpper => {
+                            idx = change_char_case(&mut scratch, idx, true, unicode, &chars)
+                        }
                         ConvTy::Bad => (),
                     }
                 }
                 idx += 1;
             }
             check_brace_level(ctx, pool, cites, s2, brace_level)?;
+
+            if let Some(conv) = memo_key.filter(|_| ctx.history == history) {
+                if let Some(memo) = &mut ctx.memo {
+                    memo.save_case_changed(conv, s2, &scratch);
+                }
+            }
+
             let val = ExecVal::String(pool.add_string(&scratch));
             ctx.push_stack(val);
         }
@@ -1289,6 +1220,70 @@ fn interp_change_case(
     Ok(())
 }
 
+/// Change the case of the characters in *range* of *scratch*, returning the
+/// new end of the range.
+///
+/// Classic BibTeX only maps ASCII letters, leaving the bytes of other
+/// characters alone, unless the case mappings of *chars* have been
+/// customized. With *unicode*, UTF-8 characters are mapped too, which may
+/// change their lengths, as when `ß` is uppercased to `SS`. Bytes that
+/// aren't valid UTF-8 are left alone.
+fn change_range_case(
+    scratch: &mut Vec<u8>,
+    range: Range<usize>,
+    upper: bool,
+    unicode: bool,
+    chars: &CharInfo,
+) -> usize {
+    if !unicode {
+        if upper {
+            chars.make_uppercase(&mut scratch[range.clone()]);
+        } else {
+            chars.make_lowercase(&mut scratch[range.clone()]);
+        }
+        return range.end;
+    }
+
+    let mut changed = Vec::with_capacity(range.len());
+    for chunk in scratch[range.clone()].utf8_chunks() {
+        let text = if upper {
+            chunk.valid().to_uppercase()
+        } else {
+            chunk.valid().to_lowercase()
+        };
+        changed.extend_from_slice(text.as_bytes());
+        changed.extend_from_slice(chunk.invalid());
+    }
+
+    let end = range.start + changed.len();
+    scratch.splice(range, changed);
+    end
+}
+
+/// Change the case of the character starting at *idx* of *scratch*,
+/// returning the index of the last byte of its replacement.
+///
+/// See [`change_range_case`]. Continuation bytes are left alone, so that the
+/// rest of a character whose first byte was skipped is skipped too.
+fn change_char_case(
+    scratch: &mut Vec<u8>,
+    idx: usize,
+    upper: bool,
+    unicode: bool,
+    chars: &CharInfo,
+) -> usize {
+    let len = match scratch[idx] {
+        _ if !unicode => 1,
+        0x00..=0x7F => 1,
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => return idx,
+    };
+    let end = (idx + len).min(scratch.len());
+    change_range_case(scratch, idx..end, upper, unicode, chars) - 1
+}
+
 fn interp_chr_to_int(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
@@ -1338,19 +1333,15 @@ fn interp_dup(
     let pop1 = ctx.pop_stack(pool, cites)?;
     match pop1 {
         ExecVal::String(s1) => {
-            ctx.push_stack(pop1);
-            if !ctx.checkpoint.is_before(s1) {
-                ctx.push_stack(pop1);
+            // Each scratch string on the stack is discarded when popped, so
+            // the copy has to be a new one.
+            let s1 = pool.restore_scratch(s1);
+            ctx.push_stack(ExecVal::String(s1));
+            if pool.is_scratch(s1) {
+                let new = pool.write_str(|cursor| cursor.append_str(s1));
+                ctx.push_stack(ExecVal::String(new));
             } else {
-                let str_len = pool.get_str(s1).len();
-                let _ = pool.write_str(|cursor| {
-                    cursor.extend(str_len);
-                });
-                let new = pool.write_str(|cursor| {
-                    cursor.append_str(s1);
-                });
-                let val = ExecVal::String(new);
-                ctx.push_stack(val);
+                ctx.push_stack(pop1);
             }
         }
         _ => {
@@ -1393,7 +1384,6 @@ fn interp_empty(
 fn interp_format_name(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
-    buffers: &mut GlobalBuffer,
     cites: &CiteInfo,
     hash: &HashData,
 ) -> Result<(), BibtexError> {
@@ -1420,252 +1410,61 @@ fn interp_format_name(
         }
     };
 
-    let mut brace_level = 0;
-    let mut xptr = 0;
-    buffers.set_init(BufTy::Ex, 0);
-    add_buf_pool(pool, buffers, s3);
-    buffers.set_offset(BufTy::Ex, 1, 0);
-
-    let mut num_names = 0;
-    while num_names < i2 && buffers.offset(BufTy::Ex, 1) < buffers.init(BufTy::Ex) {
-        num_names += 1;
-        xptr = buffers.offset(BufTy::Ex, 1);
-        name_scan_for_and(ctx, pool, buffers, cites, s3, &mut brace_level)?;
-    }
-
-    if buffers.offset(BufTy::Ex, 1) < buffers.init(BufTy::Ex) {
-        buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) - 4);
-    }
+    let mut problems = Vec::new();
+    let out = format_nth_name(pool.get_str(s3), i2, pool.get_str(s1), &mut problems);
+    report_name_problems(ctx, pool, cites, &problems, s3, i2, s1)?;
 
-    if num_names < i2 {
-        if i2 == 1 {
-            ctx.write_logs("There is no name in \"");
-        } else {
-            ctx.write_logs(&format!("There aren't {i2} names in \""));
-        }
-        print_a_pool_str(ctx, s3, pool)?;
-        ctx.write_logs("\"");
-        bst_ex_warn_print(ctx, pool, cites)?;
-    }
+    let s = pool.add_string(&out);
+    ctx.push_stack(ExecVal::String(s));
+    Ok(())
+}
 
-    while buffers.offset(BufTy::Ex, 1) > xptr {
-        match LexClass::of(buffers.at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) - 1)) {
-            LexClass::Whitespace | LexClass::Sep => {
-                buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) - 1);
-            }
-            _ => {
-                if buffers.at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) - 1) == b',' {
-                    ctx.write_logs(&format!("Name {i2} in \""));
-                    print_a_pool_str(ctx, s3, pool)?;
-                    ctx.write_logs("\" has a comma at the end");
-                    bst_ex_warn_print(ctx, pool, cites)?;
-                    buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) - 1);
+/// Warn about the problems found while formatting the *n*th name of *names*
+/// according to *spec*, or counting the names, as BibTeX does.
+fn report_name_problems(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    pool: &StringPool,
+    cites: &CiteInfo,
+    problems: &[NameProblem],
+    names: StrNumber,
+    n: i64,
+    spec: StrNumber,
+) -> Result<(), BibtexError> {
+    for problem in problems {
+        match problem {
+            NameProblem::MissingName => {
+                if n == 1 {
+                    ctx.write_logs("There is no name in \"");
                 } else {
-                    break;
+                    ctx.write_logs(&format!("There aren't {n} names in \""));
                 }
+                print_a_pool_str(ctx, names, pool)?;
+                ctx.write_logs("\"");
+                bst_ex_warn_print(ctx, pool, cites)?;
             }
-        }
-    }
-
-    enum Commas {
-        None,
-        One(BufPointer),
-        Two(BufPointer, BufPointer),
-    }
-
-    let mut num_tokens = 0;
-    let mut commas = Commas::None;
-    let mut name_ptr = 0;
-    let mut token_starting = true;
-
-    while xptr < buffers.offset(BufTy::Ex, 1) {
-        match buffers.at(BufTy::Ex, xptr) {
-            b',' => {
-                match commas {
-                    Commas::None => {
-                        commas = Commas::One(num_tokens);
-                        buffers.set_at(BufTy::NameSep, num_tokens, b',');
-                    }
-                    Commas::One(first) => {
-                        commas = Commas::Two(first, num_tokens);
-                        buffers.set_at(BufTy::NameSep, num_tokens, b',');
-                    }
-                    Commas::Two(_, _) => {
-                        ctx.write_logs(&format!("Too many commas in name {i2} of \""));
-                        print_a_pool_str(ctx, s3, pool)?;
-                        ctx.write_logs("\"");
-                        bst_ex_warn_print(ctx, pool, cites)?;
-                    }
-                }
-                xptr += 1;
-                token_starting = true;
+            NameProblem::CommaAtEnd => {
+                ctx.write_logs(&format!("Name {n} in \""));
+                print_a_pool_str(ctx, names, pool)?;
+                ctx.write_logs("\" has a comma at the end");
+                bst_ex_warn_print(ctx, pool, cites)?;
             }
-            b'{' => {
-                brace_level += 1;
-                if token_starting {
-                    buffers.set_name_tok(num_tokens, name_ptr);
-                    num_tokens += 1;
-                }
-                buffers.set_at(BufTy::Sv, name_ptr, buffers.at(BufTy::Ex, xptr));
-                name_ptr += 1;
-                xptr += 1;
-                while brace_level > 0 && xptr < buffers.offset(BufTy::Ex, 1) {
-                    match buffers.at(BufTy::Ex, xptr) {
-                        b'{' => brace_level += 1,
-                        b'}' => brace_level -= 1,
-                        _ => (),
-                    }
-                    buffers.set_at(BufTy::Sv, name_ptr, buffers.at(BufTy::Ex, xptr));
-                    name_ptr += 1;
-                    xptr += 1;
-                }
-                token_starting = false;
+            NameProblem::TooManyCommas => {
+                ctx.write_logs(&format!("Too many commas in name {n} of \""));
+                print_a_pool_str(ctx, names, pool)?;
+                ctx.write_logs("\"");
+                bst_ex_warn_print(ctx, pool, cites)?;
             }
-            b'}' => {
-                if token_starting {
-                    buffers.set_name_tok(num_tokens, name_ptr);
-                    num_tokens += 1;
-                }
-
-                ctx.write_logs(&format!("Name {i2} of \""));
-                print_a_pool_str(ctx, s3, pool)?;
+            NameProblem::UnbalancedName => {
+                ctx.write_logs(&format!("Name {n} of \""));
+                print_a_pool_str(ctx, names, pool)?;
                 ctx.write_logs("\" isn't brace balanced");
                 bst_ex_warn_print(ctx, pool, cites)?;
-                xptr += 1;
-                token_starting = false;
-            }
-            _ => match LexClass::of(buffers.at(BufTy::Ex, xptr)) {
-                LexClass::Whitespace => {
-                    if !token_starting {
-                        buffers.set_at(BufTy::NameSep, num_tokens, b' ');
-                    }
-                    xptr += 1;
-                    token_starting = true;
-                }
-                LexClass::Sep => {
-                    if !token_starting {
-                        buffers.set_at(BufTy::NameSep, num_tokens, buffers.at(BufTy::Ex, xptr));
-                    }
-                    xptr += 1;
-                    token_starting = true;
-                }
-                _ => {
-                    if token_starting {
-                        buffers.set_name_tok(num_tokens, name_ptr);
-                        num_tokens += 1;
-                    }
-                    buffers.set_at(BufTy::Sv, name_ptr, buffers.at(BufTy::Ex, xptr));
-                    name_ptr += 1;
-                    xptr += 1;
-                    token_starting = false;
-                }
-            },
-        }
-    }
-
-    buffers.set_name_tok(num_tokens, name_ptr);
-
-    let mut first_start = 0;
-    let first_end;
-    let last_end;
-    let mut von_start = 0;
-    let mut von_end = 0;
-    let jr_end;
-    let mut name_ptr2 = 0;
-
-    match commas {
-        Commas::None => {
-            last_end = num_tokens;
-            jr_end = last_end;
-
-            let mut second_loop = true;
-            while von_start < last_end - 1 {
-                name_ptr = buffers.name_tok(von_start);
-                name_ptr2 = buffers.name_tok(von_start + 1);
-                if von_token_found(buffers, hash, pool, &mut name_ptr, name_ptr2) {
-                    von_name_ends_and_last_name_starts_stuff(
-                        buffers,
-                        hash,
-                        pool,
-                        last_end,
-                        von_start,
-                        &mut von_end,
-                        &mut name_ptr,
-                        &mut name_ptr2,
-                    );
-                    second_loop = false;
-                    break;
-                }
-                von_start += 1;
-            }
-
-            if second_loop {
-                while von_start > 0 {
-                    if LexClass::of(buffers.at(BufTy::NameSep, von_start)) != LexClass::Sep
-                        || buffers.at(BufTy::NameSep, von_start) == b'~'
-                    {
-                        break;
-                    }
-                    von_start -= 1;
-                }
-                von_end = von_start;
             }
-            first_end = von_start;
-        }
-        Commas::One(comma) => {
-            last_end = comma;
-            jr_end = last_end;
-            first_start = jr_end;
-            first_end = num_tokens;
-            von_name_ends_and_last_name_starts_stuff(
-                buffers,
-                hash,
-                pool,
-                last_end,
-                von_start,
-                &mut von_end,
-                &mut name_ptr,
-                &mut name_ptr2,
-            );
-        }
-        Commas::Two(comma1, comma2) => {
-            last_end = comma1;
-            jr_end = comma2;
-            first_start = jr_end;
-            first_end = num_tokens;
-            von_name_ends_and_last_name_starts_stuff(
-                buffers,
-                hash,
-                pool,
-                last_end,
-                von_start,
-                &mut von_end,
-                &mut name_ptr,
-                &mut name_ptr2,
-            );
+            NameProblem::UnbalancedNames => braces_unbalanced_complaint(ctx, pool, cites, names)?,
+            NameProblem::IllegalLetter => brace_lvl_one_letters_complaint(ctx, pool, cites, spec)?,
+            NameProblem::UnbalancedSpec => braces_unbalanced_complaint(ctx, pool, cites, spec)?,
         }
     }
-
-    buffers.set_init(BufTy::Ex, 0);
-    add_buf_pool(pool, buffers, s1);
-    figure_out_the_formatted_name(
-        ctx,
-        buffers,
-        pool,
-        cites,
-        s1,
-        first_start,
-        first_end,
-        last_end,
-        von_start,
-        von_end,
-        &mut name_ptr,
-        &mut name_ptr2,
-        jr_end,
-        &mut brace_level,
-    )?;
-    add_pool_buf_and_push(ctx, buffers, pool)?;
-
     Ok(())
 }
 
@@ -1752,21 +1551,17 @@ fn interp_missing(
 fn interp_num_names(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
-    buffers: &mut GlobalBuffer,
     hash: &HashData,
     cites: &CiteInfo,
 ) -> Result<(), BibtexError> {
     let pop1 = ctx.pop_stack(pool, cites)?;
     match pop1 {
         ExecVal::String(s1) => {
-            buffers.set_init(BufTy::Ex, 0);
-            add_buf_pool(pool, buffers, s1);
-            buffers.set_offset(BufTy::Ex, 1, 0);
-            let mut num_names = 0;
-            while buffers.offset(BufTy::Ex, 1) < buffers.init(BufTy::Ex) {
-                let mut brace_level = 0;
-                name_scan_for_and(ctx, pool, buffers, cites, s1, &mut brace_level)?;
-                num_names += 1;
+            let mut problems = Vec::new();
+            let num_names = num_names(pool.get_str(s1), &mut problems);
+            // Counting names can only find unbalanced braces.
+            for _ in problems {
+                braces_unbalanced_complaint(ctx, pool, cites, s1)?;
             }
             ctx.push_stack(ExecVal::Integer(num_names))
         }
@@ -1808,13 +1603,24 @@ fn interp_purify(
         }
     };
 
+    let permanent = !pool.is_scratch(s1);
+
+    if permanent {
+        if let Some(result) = ctx.memo.as_mut().and_then(|m| m.purified(s1)) {
+            let out = pool.add_string(result);
+            ctx.push_stack(ExecVal::String(out));
+            return Ok(());
+        }
+    }
+
+    let chars = Rc::clone(&ctx.char_info);
     let mut scratch = Vec::from(pool.get_str(s1));
     let mut idx = 0;
     let mut brace_level: i32 = 0;
     let mut write_idx = 0;
 
     while idx < scratch.len() {
-        match LexClass::of(scratch[idx]) {
+        match chars.lex_class(scratch[idx]) {
             LexClass::Whitespace | LexClass::Sep => {
                 scratch[write_idx] = b' ';
                 write_idx += 1;
@@ -1832,7 +1638,7 @@ fn interp_purify(
                             idx += 1;
                             let old_idx = idx;
                             while idx < scratch.len()
-                                && LexClass::of(scratch[idx]) == LexClass::Alpha
+                                && chars.lex_class(scratch[idx]) == LexClass::Alpha
                             {
                                 idx += 1;
                             }
@@ -1841,24 +1647,20 @@ fn interp_purify(
                                 hash.lookup_str(pool, &scratch[old_idx..idx], StrIlk::ControlSeq);
                             if res.exists {
                                 let HashExtra::ControlSeq(seq) = hash.node(res.loc).extra else {
-                                    panic!("ControlSeq lookup didn't have ControlSeq extra");
+                                    return Err(internal_error(
+                                        ctx,
+                                        "ControlSeq lookup didn't have ControlSeq extra",
+                                    ));
                                 };
                                 scratch[write_idx] = scratch[old_idx];
                                 write_idx += 1;
-                                match seq {
-                                    ControlSeq::LowerOE
-                                    | ControlSeq::UpperOE
-                                    | ControlSeq::LowerAE
-                                    | ControlSeq::UpperAE
-                                    | ControlSeq::LowerSS => {
-                                        scratch[write_idx] = scratch[old_idx + 1];
-                                        write_idx += 1;
-                                    }
-                                    _ => (),
+                                if seq.purifies_to_two_letters() {
+                                    scratch[write_idx] = scratch[old_idx + 1];
+                                    write_idx += 1;
                                 }
                             }
                             while idx < scratch.len() && brace_level > 0 && scratch[idx] != b'\\' {
-                                match LexClass::of(scratch[idx]) {
+                                match chars.lex_class(scratch[idx]) {
                                     LexClass::Alpha | LexClass::Numeric => {
                                         scratch[write_idx] = scratch[idx];
                                         write_idx += 1;
@@ -1885,6 +1687,13 @@ fn interp_purify(
     }
 
     scratch.truncate(write_idx);
+
+    if permanent {
+        if let Some(memo) = &mut ctx.memo {
+            memo.save_purified(s1, &scratch);
+        }
+    }
+
     let out = pool.add_string(&scratch);
     ctx.push_stack(ExecVal::String(out));
 
@@ -1903,26 +1712,33 @@ struct SLRange {
     len: usize,
 }
 
-impl<T> Index<SLRange> for [T] {
-    type Output = [T];
-
-    fn index(&self, index: SLRange) -> &Self::Output {
-        let len = usize::min(self.len() + 1 - index.start.unsigned_abs(), index.len);
+impl SLRange {
+    /// The range of a string of *str_len* bytes that this selects.
+    fn range(self, str_len: usize) -> Range<usize> {
+        let len = usize::min(str_len + 1 - self.start.unsigned_abs(), self.len);
 
-        match index.start {
+        match self.start {
             ..=-1 => {
-                let start = index.start.unsigned_abs() - 1;
-                &self[self.len() - start - len..self.len() - start]
+                let start = self.start.unsigned_abs() - 1;
+                str_len - start - len..str_len - start
             }
             1.. => {
-                let start = index.start as usize - 1;
-                &self[start..start + len]
+                let start = self.start as usize - 1;
+                start..start + len
             }
-            _ => &[],
+            _ => 0..0,
         }
     }
 }
 
+impl<T> Index<SLRange> for [T] {
+    type Output = [T];
+
+    fn index(&self, index: SLRange) -> &Self::Output {
+        &self[index.range(self.len())]
+    }
+}
+
 fn interp_substr(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
@@ -1963,26 +1779,13 @@ fn interp_substr(
     let start = start as isize;
 
     if len >= str.len() && (start == 1 || start == -1) {
-        if ctx.checkpoint.is_before(s3) {
-            let str_len = pool.get_str(s3).len();
-            let _ = pool.write_str(|cursor| cursor.extend(str_len));
-        }
-        ctx.push_stack(pop3);
+        let s3 = pool.restore_scratch(s3);
+        ctx.push_stack(ExecVal::String(s3));
         return Ok(());
     }
 
-    if start == 1 && ctx.checkpoint.is_before(s3) {
-        let new = pool.write_str(|cursor| {
-            cursor.extend(len);
-        });
-        ctx.push_stack(ExecVal::String(new));
-        return Ok(());
-    }
-
-    // TODO: Remove this intermediate allocation, currently can't pass a `&str` from a StringPool
-    //       to that StringPool.
-    let new_str = Vec::from(&str[SLRange { start, len }]);
-    let out = pool.add_string(&new_str);
+    let range = SLRange { start, len }.range(str.len());
+    let out = pool.write_str(|cursor| cursor.append_substr(s3, range));
     ctx.push_stack(ExecVal::String(out));
 
     Ok(())
@@ -1998,8 +1801,10 @@ fn interp_swap(
 
     match (pop1, pop2) {
         (ExecVal::String(s1), ExecVal::String(s2))
-            if ctx.checkpoint.is_before(s1) && ctx.checkpoint.is_before(s2) =>
+            if pool.is_scratch(s1) && pool.is_scratch(s2) =>
         {
+            // Each takes the other's place in the pool, so the one that's
+            // written over first is copied out.
             let tmp = Vec::from(pool.get_str(s2));
             let new = pool.write_str(|cursor| {
                 cursor.append_str(s1);
@@ -2010,11 +1815,9 @@ fn interp_swap(
             ctx.push_stack(val);
             return Ok(());
         }
-        (ExecVal::String(s), _) | (_, ExecVal::String(s)) if ctx.checkpoint.is_before(s) => {
-            let str_len = pool.get_str(s).len();
-            let _ = pool.write_str(|cursor| {
-                cursor.extend(str_len);
-            });
+        // At most one is a scratch string, which is put back where it was.
+        (ExecVal::String(s), _) | (_, ExecVal::String(s)) if pool.is_scratch(s) => {
+            pool.restore_scratch(s);
         }
         (_, _) => (),
     }
@@ -2101,11 +1904,28 @@ fn interp_text_prefix(
         return Ok(());
     }
 
+    let (idx, brace_level) = text_prefix(pool.get_str(s2), i1);
+
+    let new = pool.write_str(|cursor| {
+        cursor.append_substr(s2, 0..idx);
+        for _ in 0..brace_level {
+            cursor.append(b'}');
+        }
+    });
+
+    let val = ExecVal::String(new);
+    ctx.push_stack(val);
+    Ok(())
+}
+
+/// Find the end of the prefix of *str* that `text.prefix$` keeps, which has
+/// *num* text characters, counting a special character as one, and the number
+/// of braces left open at the end of it.
+fn text_prefix(str: &[u8], num: i64) -> (usize, usize) {
     let mut brace_level: usize = 0;
-    let str = pool.get_str(s2);
     let mut num_chars = 0;
     let mut idx = 0;
-    while idx < str.len() && num_chars < i1 {
+    while idx < str.len() && num_chars < num {
         idx += 1;
         match str[idx - 1] {
             b'{' => {
@@ -2118,8 +1938,9 @@ fn interp_text_prefix(
                             b'}' => brace_level -= 1,
                             _ => (),
                         }
-                        num_chars += 1;
+                        idx += 1;
                     }
+                    num_chars += 1;
                 }
             }
             b'}' => {
@@ -2129,21 +1950,7 @@ fn interp_text_prefix(
         }
     }
 
-    let is_before = ctx.checkpoint.is_before(s2);
-    let new = pool.write_str(|cursor| {
-        if is_before {
-            cursor.extend(idx)
-        } else {
-            cursor.append_substr(s2, 0..idx)
-        }
-        for _ in 0..brace_level {
-            cursor.append(b'}');
-        }
-    });
-
-    let val = ExecVal::String(new);
-    ctx.push_stack(val);
-    Ok(())
+    (idx, brace_level)
 }
 
 fn interp_ty(
@@ -2167,6 +1974,27 @@ fn interp_ty(
     Ok(())
 }
 
+fn interp_output_file(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    pool: &mut StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+) -> Result<(), BibtexError> {
+    let pop1 = ctx.pop_stack(pool, cites)?;
+    match pop1 {
+        ExecVal::String(s1) => {
+            if !ctx.select_output(pool.get_str(s1)) {
+                ctx.write_logs("I couldn't open output file `");
+                print_a_pool_str(ctx, s1, pool)?;
+                ctx.write_logs("`");
+                bst_ex_warn_print(ctx, pool, cites)?;
+            }
+        }
+        _ => print_wrong_stk_lit(ctx, pool, hash, cites, pop1, StkType::String)?,
+    }
+    Ok(())
+}
+
 fn interp_warning(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
@@ -2176,6 +2004,14 @@ fn interp_warning(
     let pop1 = ctx.pop_stack(pool, cites)?;
     match pop1 {
         ExecVal::String(_) => {
+            if ctx.mess_with_entries {
+                match entry_location(pool, cites, cites.ptr()) {
+                    Some(location) => ctx.diagnostics.locate_entry(location),
+                    None => ctx
+                        .diagnostics
+                        .set_cite_key(pool.get_str(cites.get_cite(cites.ptr()))),
+                }
+            }
             ctx.write_logs("Warning--");
             print_lit(ctx, pool, hash, pop1)?;
             ctx.mark_warning();
@@ -2227,16 +2063,13 @@ fn interp_width(
                             let res = hash.lookup_str(pool, &str[old_idx..idx], StrIlk::ControlSeq);
                             if res.exists {
                                 let HashExtra::ControlSeq(seq) = hash.node(res.loc).extra else {
-                                    panic!("ControlSeq lookup didn't have ControlSeq extra");
+                                    return Err(internal_error(
+                                        ctx,
+                                        "ControlSeq lookup didn't have ControlSeq extra",
+                                    ));
                                 };
-                                match seq {
-                                    ControlSeq::LowerSS => string_width += 500,
-                                    ControlSeq::LowerAE => string_width += 722,
-                                    ControlSeq::LowerOE => string_width += 778,
-                                    ControlSeq::UpperAE => string_width += 903,
-                                    ControlSeq::UpperOE => string_width += 1014,
-                                    _ => string_width += CHAR_WIDTH[str[old_idx] as usize],
-                                }
+                                string_width +=
+                                    ctx.char_widths.control_seq(seq, &str[old_idx..idx]);
                             }
                         }
 
@@ -2248,7 +2081,11 @@ fn interp_width(
                             match str[idx] {
                                 b'{' => brace_level += 1,
                                 b'}' => brace_level -= 1,
-                                c => string_width += CHAR_WIDTH[c as usize],
+                                _ => {
+                                    let (width, len) = ctx.char_widths.next(&str[idx..]);
+                                    string_width += width;
+                                    idx += len - 1;
+                                }
                             }
                             idx += 1;
                         }
@@ -2256,14 +2093,18 @@ fn interp_width(
 
                     idx -= 1;
                 } else {
-                    string_width += CHAR_WIDTH[b'{' as usize];
+                    string_width += ctx.char_widths.next(b"{").0;
                 }
             }
             b'}' => {
                 decr_brace_level(ctx, pool, cites, s1, &mut brace_level)?;
-                string_width += CHAR_WIDTH[b'}' as usize];
+                string_width += ctx.char_widths.next(b"}").0;
+            }
+            _ => {
+                let (width, len) = ctx.char_widths.next(&str[idx..]);
+                string_width += width;
+                idx += len - 1;
             }
-            _ => string_width += CHAR_WIDTH[str[idx] as usize],
         }
 
         idx += 1;
@@ -2279,13 +2120,12 @@ fn interp_write(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &mut StringPool,
     hash: &HashData,
-    buffers: &mut GlobalBuffer,
     cites: &CiteInfo,
 ) -> Result<(), BibtexError> {
     let pop1 = ctx.pop_stack(pool, cites)?;
     match pop1 {
         ExecVal::String(s1) => {
-            add_out_pool(ctx, buffers, pool, s1);
+            add_out_pool(ctx, pool, hash, s1);
         }
         _ => {
             print_wrong_stk_lit(ctx, pool, hash, cites, pop1, StkType::String)?;
@@ -2294,10 +2134,77 @@ fn interp_write(
     Ok(())
 }
 
+/// An instruction of the code that the body of a wizard function is lowered
+/// to, so that running it doesn't have to look up each literal in the hash
+/// table. The code is kept alongside the body, an instruction for each of its
+/// entries.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Instr {
+    /// Push an integer literal.
+    Int(i64),
+    /// Push a string literal.
+    Str(StrNumber),
+    /// Push the function at the location, quoted with `'` or written as a
+    /// `{...}` block. The next instruction, which stands for the function
+    /// itself, is skipped.
+    Quote(HashPointer),
+    /// Call the function at the location.
+    Call(HashPointer),
+    /// Return from the function.
+    Return,
+}
+
+/// Lower the body of a wizard function, as the locations of the functions
+/// that it calls, to code.
+pub(crate) fn lower_fn(hash: &HashData, body: &[HashPointer]) -> Vec<Instr> {
+    body.iter()
+        .enumerate()
+        .map(|(idx, &loc)| {
+            if loc == QUOTE_NEXT_FN {
+                Instr::Quote(body[idx + 1])
+            } else if loc == HashData::end_of_def() {
+                Instr::Return
+            } else {
+                match hash.node(loc).extra {
+                    HashExtra::Integer(i) => Instr::Int(i),
+                    HashExtra::Text => Instr::Str(hash.text(loc)),
+                    _ => Instr::Call(loc),
+                }
+            }
+        })
+        .collect()
+}
+
 pub(crate) fn execute_fn(
     ctx: &mut ExecCtx<'_, '_, '_>,
     globals: &mut GlobalItems<'_>,
     ex_fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
+    match &mut ctx.steps_left {
+        Some(0) => return step_limit_exceeded(ctx, globals.pool, globals.hash, globals.cites),
+        Some(left) => *left -= 1,
+        None => (),
+    }
+
+    if ctx.trace.is_some() {
+        trace_call(ctx, globals.pool, globals.hash, globals.cites, ex_fn_loc)?;
+    }
+
+    if ctx.profiler.is_some() && matches!(globals.hash.node(ex_fn_loc).extra, HashExtra::BstFn(_)) {
+        ctx.profiler.as_mut().unwrap().enter(ex_fn_loc);
+        let res = call_fn(ctx, globals, ex_fn_loc);
+        ctx.profiler.as_mut().unwrap().exit();
+        return res;
+    }
+
+    call_fn(ctx, globals, ex_fn_loc)
+}
+
+/// Push the literal at *ex_fn_loc*, or run the function there.
+fn call_fn(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    ex_fn_loc: HashPointer,
 ) -> Result<(), BibtexError> {
     match &globals.hash.node(ex_fn_loc).extra {
         HashExtra::Text => {
@@ -2348,13 +2255,9 @@ pub(crate) fn execute_fn(
             BstBuiltin::Cite => interp_cite(ctx, globals.pool, globals.cites),
             BstBuiltin::Duplicate => interp_dup(ctx, globals.pool, globals.cites),
             BstBuiltin::Empty => interp_empty(ctx, globals.pool, globals.hash, globals.cites),
-            BstBuiltin::FormatName => interp_format_name(
-                ctx,
-                globals.pool,
-                globals.buffers,
-                globals.cites,
-                globals.hash,
-            ),
+            BstBuiltin::FormatName => {
+                interp_format_name(ctx, globals.pool, globals.cites, globals.hash)
+            }
             BstBuiltin::If => {
                 let pop1 = ctx.pop_stack(globals.pool, globals.cites)?;
                 let pop2 = ctx.pop_stack(globals.pool, globals.cites)?;
@@ -2402,16 +2305,15 @@ pub(crate) fn execute_fn(
             }
             BstBuiltin::Missing => interp_missing(ctx, globals.pool, globals.hash, globals.cites),
             BstBuiltin::Newline => {
-                output_bbl_line(ctx, globals.buffers);
+                output_bbl_line(ctx);
                 Ok(())
             }
-            BstBuiltin::NumNames => interp_num_names(
-                ctx,
-                globals.pool,
-                globals.buffers,
-                globals.hash,
-                globals.cites,
-            ),
+            BstBuiltin::NumNames => {
+                interp_num_names(ctx, globals.pool, globals.hash, globals.cites)
+            }
+            BstBuiltin::OutputFile => {
+                interp_output_file(ctx, globals.pool, globals.hash, globals.cites)
+            }
             BstBuiltin::Pop => ctx.pop_stack(globals.pool, globals.cites).map(|_| ()),
             BstBuiltin::Preamble => interp_preamble(ctx, globals.pool, globals.bibs),
             BstBuiltin::Purify => interp_purify(ctx, globals.pool, globals.hash, globals.cites),
@@ -2477,26 +2379,40 @@ pub(crate) fn execute_fn(
                 }
             }
             BstBuiltin::Width => interp_width(ctx, globals.pool, globals.hash, globals.cites),
-            BstBuiltin::Write => interp_write(
-                ctx,
-                globals.pool,
-                globals.hash,
-                globals.buffers,
-                globals.cites,
-            ),
+            BstBuiltin::Write => interp_write(ctx, globals.pool, globals.hash, globals.cites),
         },
-        HashExtra::BstFn(BstFn::Wizard(mut wiz_ptr)) => {
-            let mut cur_fn = globals.other.wiz_function(wiz_ptr);
-            while cur_fn != HashData::end_of_def() {
-                if cur_fn != QUOTE_NEXT_FN {
-                    execute_fn(ctx, globals, cur_fn)?;
-                } else {
-                    wiz_ptr += 1;
-                    cur_fn = globals.other.wiz_function(wiz_ptr);
-                    ctx.push_stack(ExecVal::Function(cur_fn))
+        HashExtra::BstFn(BstFn::Custom(index)) => {
+            execute_custom(ctx, globals.pool, globals.hash, globals.cites, *index)
+        }
+        HashExtra::BstFn(BstFn::Wizard(wiz_ptr)) => {
+            ctx.wiz_fns.push(ex_fn_loc);
+            let caller_line = ctx.trace.as_ref().map(|trace| trace.line);
+            // Literals are pushed straight from the code unless each step is
+            // counted or traced.
+            let inline_literals = ctx.steps_left.is_none() && ctx.trace.is_none();
+            let mut pc = *wiz_ptr;
+            loop {
+                match globals.other.wiz_instr(pc) {
+                    Instr::Return => break,
+                    Instr::Quote(quoted) => {
+                        ctx.push_stack(ExecVal::Function(quoted));
+                        pc += 1;
+                    }
+                    Instr::Int(i) if inline_literals => ctx.push_stack(ExecVal::Integer(i)),
+                    Instr::Str(s) if inline_literals => ctx.push_stack(ExecVal::String(s)),
+                    Instr::Int(_) | Instr::Str(_) | Instr::Call(_) => {
+                        if let Some(trace) = &mut ctx.trace {
+                            trace.line = globals.other.wiz_line(pc);
+                        }
+                        execute_fn(ctx, globals, globals.other.wiz_function(pc))?;
+                    }
                 }
-                wiz_ptr += 1;
-                cur_fn = globals.other.wiz_function(wiz_ptr);
+                pc += 1;
+            }
+            ctx.wiz_fns.pop();
+            // Builtins such as `while$` call functions more than once.
+            if let (Some(trace), Some(line)) = (&mut ctx.trace, caller_line) {
+                trace.line = line;
             }
             Ok(())
         }
@@ -2538,7 +2454,7 @@ pub(crate) fn execute_fn(
             } else {
                 let str_ent_ptr = globals.cites.ptr() * globals.entries.num_ent_strs() + *entry;
                 let str = globals.entries.strs(str_ent_ptr);
-                let val = ExecVal::String(globals.pool.add_string(str));
+                let val = ExecVal::String(globals.pool.copy_string(str));
                 ctx.push_stack(val);
                 Ok(())
             }
@@ -2553,29 +2469,60 @@ pub(crate) fn execute_fn(
                 ctx.push_stack(ExecVal::String(str_ptr));
             } else {
                 let str = globals.globals.str(*glb_ptr);
-                let val = ExecVal::String(globals.pool.add_string(str));
+                let val = ExecVal::String(globals.pool.copy_string(str));
                 ctx.push_stack(val);
             }
             Ok(())
         }
-        _ => panic!("Invalid node passed as ex_fn_loc"),
+        _ => Err(internal_error(ctx, "Invalid node passed as ex_fn_loc")),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{test_utils::with_cbs, BibtexConfig};
+
+    #[test]
+    fn test_lower_fn() {
+        with_cbs(|cbs| {
+            let mut ctx = Bibtex::new(cbs, BibtexConfig::default());
+            let mut hash = HashData::new();
+            let mut pool = StringPool::new();
+            let mut insert = |str: &[u8], extra| {
+                hash.lookup_str_insert(&mut ctx, &mut pool, str, extra)
+                    .unwrap()
+                    .loc
+            };
+            let int = insert(b"1", HashExtra::Integer(1));
+            let text = insert(b"text", HashExtra::Text);
+            let func = insert(b"func", HashExtra::BstFn(BstFn::Wizard(0)));
+
+            let body = [int, text, QUOTE_NEXT_FN, func, func, HashData::end_of_def()];
+            assert_eq!(
+                lower_fn(&hash, &body),
+                [
+                    Instr::Int(1),
+                    Instr::Str(hash.text(text)),
+                    Instr::Quote(func),
+                    Instr::Call(func),
+                    Instr::Call(func),
+                    Instr::Return,
+                ]
+            );
+        });
+    }
 
     #[test]
     fn test_empty_sl_range() {
         let slice = b"0123456789";
 
         let r1 = SLRange { start: 0, len: 0 };
-        assert_eq!(&slice[r1], &[]);
+        assert_eq!(&slice[r1], &[0u8; 0]);
         let r2 = SLRange { start: 5, len: 0 };
-        assert_eq!(&slice[r2], &[]);
+        assert_eq!(&slice[r2], &[0u8; 0]);
         let r3 = SLRange { start: -5, len: 0 };
-        assert_eq!(&slice[r3], &[]);
+        assert_eq!(&slice[r3], &[0u8; 0]);
     }
 
     #[test]
@@ -2603,4 +2550,62 @@ mod tests {
         };
         assert_eq!(&slice[r1], b"0123456789");
     }
+
+    #[test]
+    fn test_change_case() {
+        let mut s = Vec::from("Études sur l'Œuvre".as_bytes());
+        let len = s.len();
+        let end = change_range_case(&mut s, 0..len, false, false, &CharInfo::default());
+        assert_eq!(end, len);
+        assert_eq!(s, "Études sur l'Œuvre".to_ascii_lowercase().as_bytes());
+
+        let end = change_range_case(&mut s, 0..len, false, true, &CharInfo::default());
+        assert_eq!(end, len);
+        assert_eq!(s, "études sur l'œuvre".as_bytes());
+
+        let mut s = Vec::from("Straße".as_bytes());
+        let end = change_range_case(&mut s, 4..6, true, true, &CharInfo::default());
+        assert_eq!(end, 6);
+        assert_eq!(s, b"StraSSe");
+
+        let mut s = vec![b'a', 0xFF, b'b'];
+        change_range_case(&mut s, 0..3, true, true, &CharInfo::default());
+        assert_eq!(s, [b'A', 0xFF, b'B']);
+    }
+
+    #[test]
+    fn test_change_char_case() {
+        let mut s = Vec::from("xÉß".as_bytes());
+        assert_eq!(
+            change_char_case(&mut s, 1, false, true, &CharInfo::default()),
+            2
+        );
+        assert_eq!(s, "xéß".as_bytes());
+        assert_eq!(
+            change_char_case(&mut s, 2, true, true, &CharInfo::default()),
+            2
+        );
+        assert_eq!(
+            change_char_case(&mut s, 3, true, true, &CharInfo::default()),
+            4
+        );
+        assert_eq!(s, "xéSS".as_bytes());
+
+        let mut s = Vec::from("É".as_bytes());
+        assert_eq!(
+            change_char_case(&mut s, 0, false, false, &CharInfo::default()),
+            0
+        );
+        assert_eq!(s, "É".as_bytes());
+    }
+
+    #[test]
+    fn test_text_prefix() {
+        let name = b"M{\\\"u}ller";
+        assert_eq!(text_prefix(name, 1), (1, 0));
+        assert_eq!(text_prefix(name, 2), (6, 0));
+        assert_eq!(text_prefix(name, 3), (7, 0));
+        assert_eq!(text_prefix(b"{Ab}c", 1), (2, 1));
+        assert_eq!(text_prefix(b"{\\oe", 2), (4, 1));
+    }
 }
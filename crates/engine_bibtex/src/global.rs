@@ -1,12 +1,15 @@
 use crate::{pool::StrNumber, ASCIICode};
 
 const MAX_GLOB_STRS: usize = 10;
+/// The longest global string that classic BibTeX can hold. Longer strings are
+/// only truncated to this if [`BibtexEngine::truncate_strings()`] is enabled.
+///
+/// [`BibtexEngine::truncate_strings()`]: crate::BibtexEngine::truncate_strings
 pub(crate) const GLOB_STR_SIZE: usize = 20000;
 
 pub(crate) struct GlobalData {
     glb_bib_str_ptr: Vec<StrNumber>,
-    global_strs: Vec<ASCIICode>,
-    glb_str_end: Vec<usize>,
+    global_strs: Vec<Vec<ASCIICode>>,
     num_glb_strs: usize,
 }
 
@@ -14,8 +17,7 @@ impl GlobalData {
     pub fn new() -> GlobalData {
         GlobalData {
             glb_bib_str_ptr: vec![StrNumber::invalid(); MAX_GLOB_STRS + 1],
-            global_strs: vec![0; (GLOB_STR_SIZE + 1) * MAX_GLOB_STRS + 1],
-            glb_str_end: vec![0; MAX_GLOB_STRS + 1],
+            global_strs: vec![Vec::new(); MAX_GLOB_STRS + 1],
             num_glb_strs: 0,
         }
     }
@@ -25,17 +27,12 @@ impl GlobalData {
             self.glb_bib_str_ptr.len() + MAX_GLOB_STRS,
             StrNumber::invalid(),
         );
-        self.global_strs.resize(
-            self.global_strs.len() + (GLOB_STR_SIZE + 1) * MAX_GLOB_STRS,
-            0,
-        );
-        self.glb_str_end
-            .resize(self.glb_str_end.len() + MAX_GLOB_STRS, 0);
+        self.global_strs
+            .resize(self.global_strs.len() + MAX_GLOB_STRS, Vec::new());
     }
 
     pub fn str(&self, pos: usize) -> &[ASCIICode] {
-        let spos = pos * (GLOB_STR_SIZE + 1);
-        &self.global_strs[spos..spos + self.glb_str_end[pos]]
+        &self.global_strs[pos]
     }
 
     pub fn str_ptr(&self, pos: usize) -> StrNumber {
@@ -47,9 +44,9 @@ impl GlobalData {
     }
 
     pub fn set_str(&mut self, pos: usize, val: &[ASCIICode]) {
-        let spos = pos * (GLOB_STR_SIZE + 1);
-        self.global_strs[spos..spos + val.len()].copy_from_slice(val);
-        self.glb_str_end[pos] = val.len();
+        let str = &mut self.global_strs[pos];
+        str.clear();
+        str.extend_from_slice(val);
     }
 
     pub fn num_glb_strs(&self) -> usize {
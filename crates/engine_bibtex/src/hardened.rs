@@ -0,0 +1,111 @@
+//! Running the engine on files that can't be trusted.
+//!
+//! The engine checks that the files it reads are well-formed, and reports
+//! what's wrong with them as errors in the style or the database, but it
+//! assumes that its own tables are consistent, and panics if they're not. The
+//! core bridge turns a panic into an error rather than letting it take the
+//! process down, but the run stops then and there: the `.blg` file doesn't say
+//! why, and what the engine had gathered, such as its diagnostics, is lost.
+//! Services that run BibTeX on the `.bst` and `.bib` files of their users need
+//! to know what happened. In hardened mode, the places that check the
+//! engine's tables stop the run with a fatal error instead, which is logged
+//! like any other, and any other panic in the engine is caught and handled the
+//! same way, so that the host gets an [`InternalError`].
+
+use crate::{Bibtex, BibtexError};
+use std::{any::Any, error, fmt};
+
+/// The error returned when the engine finds that its own state is
+/// inconsistent, in hardened mode.
+///
+/// This is always a bug in the engine rather than in the files that it read,
+/// but in hardened mode it ends the run like any other fatal error.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct InternalError {
+    /// What went wrong.
+    pub message: String,
+}
+
+impl fmt::Display for InternalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "internal error in the bibtex engine: {}", self.message)
+    }
+}
+
+impl error::Error for InternalError {}
+
+/// Report that the engine's state is inconsistent.
+///
+/// Outside of hardened mode, this panics with *message*, as the engine always
+/// did. In hardened mode, the error is written to the logs, and the returned
+/// error stops the run.
+#[cold]
+pub(crate) fn internal_error(ctx: &mut Bibtex<'_, '_>, message: &str) -> BibtexError {
+    if !ctx.config.hardened {
+        panic!("{message}");
+    }
+
+    let err = InternalError {
+        message: message.to_owned(),
+    };
+    ctx.write_logs(&format!("Sorry---{err}\n"));
+    ctx.mark_fatal();
+    ctx.internal_error = Some(err);
+    BibtexError::Fatal
+}
+
+/// Get the message of a panic caught by [`std::panic::catch_unwind`].
+pub(crate) fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "the engine panicked"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{test_utils::with_cbs, BibtexConfig, History};
+    use std::panic;
+
+    #[test]
+    fn test_internal_error() {
+        with_cbs(|cbs| {
+            let config = BibtexConfig {
+                hardened: true,
+                ..BibtexConfig::default()
+            };
+            let mut ctx = Bibtex::new(cbs, config);
+            assert!(matches!(
+                internal_error(&mut ctx, "no extra"),
+                BibtexError::Fatal
+            ));
+            assert_eq!(ctx.history, History::FatalError);
+            assert_eq!(ctx.internal_error.take().unwrap().message, "no extra");
+        });
+    }
+
+    #[test]
+    #[should_panic(expected = "no extra")]
+    fn test_internal_error_unhardened() {
+        with_cbs(|cbs| {
+            let mut ctx = Bibtex::new(cbs, BibtexConfig::default());
+            internal_error(&mut ctx, "no extra");
+        });
+    }
+
+    #[test]
+    fn test_panic_message() {
+        let payload = panic::catch_unwind(|| panic!("index {} out of bounds", 3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "index 3 out of bounds");
+
+        let payload = panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(&*payload), "static");
+
+        let payload = panic::catch_unwind(|| panic::panic_any(3)).unwrap_err();
+        assert_eq!(panic_message(&*payload), "the engine panicked");
+    }
+}
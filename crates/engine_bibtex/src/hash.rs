@@ -3,69 +3,29 @@ use crate::{
     bibs::BibCommand,
     bst::BstCommand,
     exec::ControlSeq,
-    log::print_overflow,
     pool,
     pool::{StrNumber, StringPool},
     ASCIICode, Bibtex, BibtexError, CiteNumber, FnDefLoc, HashPointer, LookupRes, StrIlk,
 };
 
+/// The location of the first entry of the table. Location 0 is never used, so
+/// that it can stand for no entry.
 pub(crate) const HASH_BASE: usize = 1;
+/// The size of the hash table of classic BibTeX, which is still reported in
+/// the log so that it can be compared with those of other implementations.
+/// The table here starts small and grows as needed.
 pub(crate) const HASH_SIZE: usize = if pool::MAX_STRINGS > 5000 {
     pool::MAX_STRINGS
 } else {
     5000
 };
-const HASH_MAX: usize = HASH_SIZE + HASH_BASE - 1;
-pub(crate) const HASH_PRIME: usize = compute_hash_prime();
+/// The prime that classic BibTeX would use for a table of [`HASH_SIZE`]
+/// entries, also only reported in the log.
+pub(crate) const HASH_PRIME: usize = 30011;
 
-/// Calculate a prime number for use in hashing that's at least 17/20 of `HASH_SIZE`
-const fn compute_hash_prime() -> usize {
-    const HASH_WANT: usize = HASH_SIZE / 20 * 17;
-
-    let mut primes = [0; HASH_SIZE];
-    let mut sieve = [0; HASH_SIZE];
-
-    let mut j = 1;
-    let mut k = 1;
-    let mut hash_prime = 2;
-    primes[k] = hash_prime;
-    let mut o = 2;
-    let mut square = 9;
-
-    while hash_prime < HASH_WANT {
-        loop {
-            j += 2;
-            if j == square {
-                sieve[o] = j;
-                j += 2;
-                o += 1;
-                square = primes[o] * primes[o];
-            }
-
-            let mut n = 2;
-            let mut j_prime = true;
-            while n < o && j_prime {
-                while sieve[n] < j {
-                    sieve[n] += 2 * primes[n];
-                }
-                if sieve[n] == j {
-                    j_prime = false;
-                }
-                n += 1;
-            }
-
-            if j_prime {
-                break;
-            }
-        }
-
-        k += 1;
-        hash_prime = j;
-        primes[k] = hash_prime;
-    }
-
-    hash_prime
-}
+/// The number of slots that a new table has, which is enough for the strings
+/// that are always defined and those of a small style.
+const MIN_SLOTS: usize = 1024;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub(crate) enum BstBuiltin {
@@ -90,6 +50,7 @@ pub(crate) enum BstBuiltin {
     Missing,
     Newline,
     NumNames,
+    OutputFile,
     Pop,
     Preamble,
     Purify,
@@ -111,6 +72,7 @@ pub(crate) enum BstBuiltin {
 #[derive(Copy, Clone, Debug)]
 pub(crate) enum BstFn {
     Builtin(BstBuiltin),
+    Custom(usize),
     Wizard(FnDefLoc),
     Field(usize),
     IntEntry(usize),
@@ -161,7 +123,6 @@ impl HashExtra {
 
 #[derive(Clone, Default, Debug)]
 pub struct HashNode {
-    next: HashPointer,
     text: StrNumber,
     pub(crate) extra: HashExtra,
 }
@@ -172,26 +133,94 @@ impl HashNode {
     }
 }
 
+/// A slot of the table's index, which refers to an entry by its location.
+#[derive(Clone, Copy, Default, Debug)]
+struct Slot {
+    /// The location of the entry, or 0 if the slot is empty.
+    loc: HashPointer,
+    /// The hash of the entry's text.
+    hash: u32,
+}
+
+/// Statistics about the hash table, for judging how well it performed.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct HashStats {
+    /// The number of entries in the table.
+    pub entries: usize,
+
+    /// The number of slots in the table's index.
+    pub slots: usize,
+
+    /// How many times the index had to grow.
+    pub grows: usize,
+
+    /// The largest distance of an entry from the slot where its hash would
+    /// place it.
+    pub max_probe: usize,
+
+    /// The sum of those distances over all entries.
+    pub total_probe: usize,
+}
+
+impl HashStats {
+    /// The fraction of the index's slots that are in use.
+    pub fn load_factor(&self) -> f64 {
+        self.entries as f64 / self.slots as f64
+    }
+
+    /// The average number of slots that a successful lookup passes over
+    /// before finding its entry.
+    pub fn mean_probe(&self) -> f64 {
+        if self.entries == 0 {
+            0.0
+        } else {
+            self.total_probe as f64 / self.entries as f64
+        }
+    }
+}
+
+/// The table of the strings that BibTeX knows about, each along with what
+/// it stands for.
+///
+/// The same text may have several entries, one for each kind of thing that
+/// it names; they share one string in the pool. Entries are identified by
+/// their locations, which never change, while an open-addressing index with
+/// Robin Hood probing maps texts to them and grows to keep lookups short.
 // TODO: Split string-pool stuff into string pool, executor stuff into execution context
 pub(crate) struct HashData {
+    /// The entries, in the order they were added, after an unused entry at
+    /// location 0.
     hash_data: Vec<HashNode>,
-    len: usize,
+    /// The index, whose length is always a power of two.
+    slots: Vec<Slot>,
+    grows: usize,
 }
 
 impl HashData {
     pub fn new() -> HashData {
         HashData {
-            hash_data: vec![HashNode::default(); HASH_MAX + 1],
-            len: HASH_MAX + 1,
+            hash_data: vec![HashNode::default(); HASH_BASE],
+            slots: vec![Slot::default(); MIN_SLOTS],
+            grows: 0,
+        }
+    }
+
+    /// Make room for at least *entries* entries without growing the index.
+    pub fn reserve(&mut self, entries: usize) {
+        self.hash_data.reserve(entries);
+        let mut slots = self.slots.len();
+        while Self::overloaded(entries, slots) {
+            slots *= 2;
         }
+        self.resize(slots);
     }
 
     pub fn undefined() -> usize {
-        HASH_MAX + 1
+        usize::MAX
     }
 
     pub fn end_of_def() -> usize {
-        HASH_MAX + 1
+        usize::MAX
     }
 
     pub fn node(&self, pos: usize) -> &HashNode {
@@ -206,134 +235,167 @@ impl HashData {
         self.hash_data[pos].text
     }
 
-    fn set_text(&mut self, pos: usize, val: StrNumber) {
-        self.hash_data[pos].text = val;
+    fn len(&self) -> usize {
+        self.hash_data.len() - HASH_BASE
     }
 
-    fn next(&self, pos: usize) -> HashPointer {
-        self.hash_data[pos].next
-    }
+    pub fn stats(&self) -> HashStats {
+        let mut stats = HashStats {
+            entries: self.len(),
+            slots: self.slots.len(),
+            grows: self.grows,
+            ..HashStats::default()
+        };
 
-    fn set_next(&mut self, pos: usize, val: HashPointer) {
-        self.hash_data[pos].next = val
+        for (idx, slot) in self.slots.iter().enumerate() {
+            if slot.loc != 0 {
+                let probe = self.probe_len(idx, slot.hash);
+                stats.max_probe = stats.max_probe.max(probe);
+                stats.total_probe += probe;
+            }
+        }
+
+        stats
     }
 
-    fn len(&self) -> usize {
-        self.len
+    /// Whether an index of *slots* slots is too full to hold *entries*
+    /// entries. Robin Hood probing keeps lookups short up to a load factor of
+    /// about 7/8.
+    fn overloaded(entries: usize, slots: usize) -> bool {
+        entries * 8 > slots * 7
     }
 
-    fn set_len(&mut self, val: usize) {
-        self.len = val;
+    fn hash_str(str: &[ASCIICode]) -> u32 {
+        pool::hash_text(str)
     }
 
-    fn prime(&self) -> usize {
-        HASH_PRIME
+    /// The slot where an entry with hash *hash* would ideally go.
+    fn home(&self, hash: u32) -> usize {
+        hash as usize & (self.slots.len() - 1)
     }
 
-    fn hash_str(&self, str: &[ASCIICode]) -> usize {
-        let prime = self.prime();
-        str.iter()
-            .fold(0, |acc, &c| ((2 * acc) + c as usize) % prime)
+    /// How far slot *idx* is from the home slot of an entry with hash *hash*.
+    fn probe_len(&self, idx: usize, hash: u32) -> usize {
+        idx.wrapping_sub(self.home(hash)) & (self.slots.len() - 1)
     }
 
-    pub fn lookup_str(&self, pool: &StringPool, str: &[ASCIICode], ilk: StrIlk) -> LookupRes {
-        let h = self.hash_str(str);
-        let mut p = h as HashPointer + HASH_BASE as HashPointer;
+    /// Find the entry for *str* of kind *ilk*. If there's none, this also
+    /// returns the text of an entry of another kind with the same string, if
+    /// there's one, so that it can be shared.
+    fn find(
+        &self,
+        pool: &StringPool,
+        str: &[ASCIICode],
+        hash: u32,
+        ilk: StrIlk,
+    ) -> Result<HashPointer, StrNumber> {
+        let mask = self.slots.len() - 1;
+        let mut idx = self.home(hash);
+        let mut text = StrNumber::invalid();
+
+        // An entry is never further from its home than one that comes after
+        // it, so the search can stop at the first entry closer to its home
+        // than the one sought would be.
+        for probe in 0.. {
+            let slot = self.slots[idx];
+            if slot.loc == 0 || self.probe_len(idx, slot.hash) < probe {
+                break;
+            }
+
+            if slot.hash == hash {
+                let node = &self.hash_data[slot.loc];
+                if pool.try_get_str(node.text) == Some(str) {
+                    if node.kind() == ilk {
+                        return Ok(slot.loc);
+                    }
+                    text = node.text;
+                }
+            }
 
-        let exists = loop {
-            let existing = self.text(p);
+            idx = (idx + 1) & mask;
+        }
 
-            if !existing.is_invalid() && pool.get_str(existing) == str && self.node(p).kind() == ilk
-            {
-                break true;
+        Err(text)
+    }
+
+    /// Add *slot* to the index, which must have room for it.
+    fn place(&mut self, mut slot: Slot) {
+        let mask = self.slots.len() - 1;
+        let mut idx = self.home(slot.hash);
+        let mut probe = 0;
+
+        loop {
+            let cur = self.slots[idx];
+            if cur.loc == 0 {
+                self.slots[idx] = slot;
+                return;
             }
 
-            if self.next(p) == 0 {
-                break false;
+            // Take the slot from an entry that's closer to its home, and
+            // move that one on instead.
+            let cur_probe = self.probe_len(idx, cur.hash);
+            if cur_probe < probe {
+                self.slots[idx] = slot;
+                slot = cur;
+                probe = cur_probe;
             }
 
-            p = self.next(p);
-        };
+            idx = (idx + 1) & mask;
+            probe += 1;
+        }
+    }
+
+    fn resize(&mut self, slots: usize) {
+        if slots == self.slots.len() {
+            return;
+        }
 
-        LookupRes { loc: p, exists }
+        let old = std::mem::replace(&mut self.slots, vec![Slot::default(); slots]);
+        for slot in old {
+            if slot.loc != 0 {
+                self.place(slot);
+            }
+        }
+    }
+
+    pub fn lookup_str(&self, pool: &StringPool, str: &[ASCIICode], ilk: StrIlk) -> LookupRes {
+        match self.find(pool, str, Self::hash_str(str), ilk) {
+            Ok(loc) => LookupRes { loc, exists: true },
+            Err(_) => LookupRes {
+                loc: 0,
+                exists: false,
+            },
+        }
     }
 
     /// Lookup a string, inserting it if it isn't found. Note that this returns `Ok` whether the
     /// string is found or not, only returning `Err` if a called function fails.
     pub fn lookup_str_insert(
         &mut self,
-        ctx: &mut Bibtex<'_, '_>,
+        _ctx: &mut Bibtex<'_, '_>,
         pool: &mut StringPool,
         str: &[ASCIICode],
         ilk: HashExtra,
     ) -> Result<LookupRes, BibtexError> {
-        // Hash string using simple hash function. This hash is capped to HASH_PRIME
-        let h = self.hash_str(str);
-        let mut str_num = StrNumber::default();
-        // Get position by adding HASH_BASE
-        let mut p = (h + HASH_BASE) as HashPointer;
-
-        // Look for an existing match, or the last slot
-        let existing = loop {
-            // Get the current text at the position
-            let existing = self.text(p);
-            // If the text exists and is the same as the text we're adding
-            if pool.try_get_str(existing) == Some(str) {
-                // If an existing hash entry exists for this type, return it
-                if self.node(p).kind() == ilk.kind() {
-                    return Ok(LookupRes {
-                        loc: p,
-                        exists: true,
-                    });
-                } else {
-                    str_num = existing;
-                }
-            }
+        let hash = Self::hash_str(str);
 
-            if self.next(p) == 0 {
-                break existing;
-            }
-
-            p = self.next(p);
+        let text = match self.find(pool, str, hash, ilk.kind()) {
+            Ok(loc) => return Ok(LookupRes { loc, exists: true }),
+            // Share the string with an entry of another kind if possible
+            Err(text) if !text.is_invalid() => text,
+            Err(_) => pool.add_string(str),
         };
 
-        // If we hit the end and the slot is already in use
-        if !existing.is_invalid() {
-            // Walk backwards from our current len to our first empty slot.
-            // If all slots are full, error
-            loop {
-                if self.len() == HASH_BASE {
-                    print_overflow(ctx);
-                    ctx.write_logs(&format!("hash size {HASH_SIZE}\n"));
-                    return Err(BibtexError::Fatal);
-                }
-                self.set_len(self.len() - 1);
-
-                if self.text(self.len()).is_invalid() {
-                    break;
-                }
-            }
-            // Set the next item to our new lowest open slot
-            self.set_next(p, self.len());
-            // Operate on the new empty slot
-            p = self.len();
+        if Self::overloaded(self.len() + 1, self.slots.len()) {
+            self.resize(self.slots.len() * 2);
+            self.grows += 1;
         }
 
-        // We found the string in the string pool while hunting for a slot
-        if !str_num.is_invalid() {
-            self.set_text(p, str_num);
-        // The string isn't in the string pool - add it
-        } else {
-            self.set_text(p, pool.add_string(str));
-        }
-
-        // Set the type of this slot
-        self.node_mut(p).extra = ilk;
+        let loc = self.hash_data.len();
+        self.hash_data.push(HashNode { text, extra: ilk });
+        self.place(Slot { loc, hash });
 
-        Ok(LookupRes {
-            loc: p,
-            exists: false,
-        })
+        Ok(LookupRes { loc, exists: false })
     }
 }
 
@@ -378,4 +440,47 @@ mod tests {
             assert_eq!(pool.try_get_str(hash.text(res4.loc)), None,);
         })
     }
+    #[test]
+    fn grows_past_classic_size() {
+        with_cbs(|cbs| {
+            let mut ctx = Bibtex::new(cbs, BibtexConfig::default());
+            let mut hash = HashData::new();
+            let mut pool = StringPool::new();
+            let n = HASH_SIZE + 1000;
+
+            let locs: Vec<_> = (0..n)
+                .map(|i| {
+                    let key = format!("key:{i}");
+                    hash.lookup_str_insert(&mut ctx, &mut pool, key.as_bytes(), HashExtra::Cite(i))
+                        .unwrap()
+                        .loc
+                })
+                .collect();
+
+            for (i, &loc) in locs.iter().enumerate() {
+                let res = hash.lookup_str(&pool, format!("key:{i}").as_bytes(), StrIlk::Cite);
+                assert!(res.exists);
+                assert_eq!(res.loc, loc);
+            }
+
+            // Entries of different kinds share their text
+            let res = hash
+                .lookup_str_insert(
+                    &mut ctx,
+                    &mut pool,
+                    b"key:7",
+                    HashExtra::Macro(StrNumber::invalid()),
+                )
+                .unwrap();
+            assert!(!res.exists);
+            assert_eq!(hash.text(res.loc), hash.text(locs[7]));
+            assert!(!hash.lookup_str(&pool, b"key:7", StrIlk::Text).exists);
+
+            let stats = hash.stats();
+            assert_eq!(stats.entries, n + 1);
+            assert!(stats.grows > 0);
+            assert!(stats.load_factor() <= 0.875);
+            assert!(stats.mean_probe() < 4.0);
+        })
+    }
 }
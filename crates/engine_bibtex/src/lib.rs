@@ -22,47 +22,95 @@ use crate::{
         get_aux_command_and_process, last_check_for_aux_errors, pop_the_aux_stack, AuxCommand,
         AuxData,
     },
+    bbl::{BblRecorder, LineBreaker},
+    biblatex::write_biblatex_bbl,
     bibs::{BibCommand, BibData},
     bst::{get_bst_command_and_process, BstCommand},
     buffer::{BufTy, GlobalBuffer},
+    char_info::CharInfo,
     cite::CiteInfo,
+    custom::CustomBuiltin,
+    diag::DiagnosticRecorder,
+    effects::Effect,
     entries::{EntryData, ENT_STR_SIZE},
     exec::{ControlSeq, ExecCtx},
     global::{GlobalData, GLOB_STR_SIZE},
+    hardened::{internal_error, panic_message},
     hash::{BstBuiltin, BstFn, HashData, HashExtra},
     log::{
         bib_close_log, log_pr_aux_name, print_aux_name, print_confusion, sam_wrong_file_name_print,
         AsBytes,
     },
+    memo::BuiltinMemo,
+    metrics::{MetricsSink, Phase, RunMetrics},
     other::OtherData,
     peekable::{input_ln, PeekableInput},
     pool::{StrNumber, StringPool},
+    profile::Profiler,
+    rng::{IdSource, Rng},
     scan::eat_bst_white_space,
+    schema::RecordedEntry,
+    session::{drive_style, StyleDriver},
+    srcmap::SourceMapRecorder,
+    suggest::KeyFilter,
+    trace::BreakpointHook,
+    width::CharWidths,
 };
 use std::{
+    collections::HashMap,
     ffi::{CStr, CString},
     io::Write,
+    panic::{self, AssertUnwindSafe},
+    rc::Rc,
+    time::{Instant, SystemTime},
 };
 use tectonic_bridge_core::{CoreBridgeLauncher, CoreBridgeState, FileFormat, OutputId};
 use tectonic_errors::prelude::*;
 
 pub(crate) mod auxi;
+pub(crate) mod bbl;
+pub mod bib;
+pub(crate) mod biblatex;
 pub(crate) mod bibs;
 pub(crate) mod bst;
 pub(crate) mod buffer;
 pub(crate) mod char_info;
 pub(crate) mod cite;
+pub(crate) mod codecache;
+pub(crate) mod collate;
+pub(crate) mod custom;
+pub(crate) mod diag;
+pub(crate) mod dups;
+pub(crate) mod effects;
 pub(crate) mod entries;
 pub(crate) mod exec;
 pub(crate) mod global;
+pub(crate) mod hardened;
 pub(crate) mod hash;
+pub(crate) mod limits;
 pub(crate) mod log;
+pub(crate) mod memo;
+pub(crate) mod metrics;
+pub mod names;
 pub(crate) mod other;
 pub(crate) mod peekable;
 pub(crate) mod pool;
+pub(crate) mod profile;
+pub(crate) mod rng;
 pub(crate) mod scan;
+pub(crate) mod schema;
+pub(crate) mod session;
+pub(crate) mod sorting;
+pub(crate) mod srcmap;
+pub(crate) mod structured;
+pub(crate) mod suggest;
 #[cfg(test)]
 pub(crate) mod test_utils;
+pub(crate) mod trace;
+pub(crate) mod translit;
+pub(crate) mod validate;
+pub(crate) mod width;
+pub(crate) mod xdata;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
 pub(crate) enum History {
@@ -72,6 +120,29 @@ pub(crate) enum History {
     FatalError,
 }
 
+pub use bbl::{Bbl, BblBlock, BblChunk, BblCommand, EntryOrder};
+pub use cite::{Citation, CitationReport, EntryLocation, RepeatedEntry};
+pub use codecache::{BstCodeCache, CodeCacheStats};
+pub use collate::Locale;
+pub use custom::BuiltinStack;
+pub use diag::{Diagnostic, Severity};
+pub use dups::DuplicateEntry;
+pub use effects::{StyleLint, StyleProblem, ValueType};
+pub use hardened::InternalError;
+pub use hash::HashStats;
+pub use limits::{FieldLimit, LongFieldAction, StepLimitExceeded};
+#[cfg(feature = "memoize-builtins")]
+pub use memo::MemoStats;
+pub use metrics::{BibtexMetrics, MetricLabels};
+pub use profile::{BstProfile, FunctionProfile};
+pub use schema::{EntryLint, EntrySchema, LintProblem};
+pub use session::{StyleCommand, StyleSession};
+pub use sorting::{SortEntry, SortKeyFn, SortPart, SortScheme};
+pub use suggest::MissingEntry;
+pub use trace::{BreakAction, BstCall, StackValue};
+pub use translit::{Script, Transliteration};
+pub use validate::{FieldCheck, FieldWarning};
+
 #[doc(hidden)]
 #[derive(Debug)]
 pub(crate) enum BibtexError {
@@ -80,13 +151,14 @@ pub(crate) enum BibtexError {
     NoBst,
 }
 
-/// A possible outcome from a BibTeX engine invocation.
+/// The overall result of a BibTeX engine invocation, as summarized by its
+/// "history".
 ///
 /// The classic TeX implementation provides a fourth outcome: "fatal error". In
 /// Tectonic, this outcome is represented as an `Err` result rather than a
-/// [`BibtexOutcome`].
+/// [`BibtexHistory`].
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
-pub enum BibtexOutcome {
+pub enum BibtexHistory {
     /// Nothing bad happened.
     Spotless = 0,
 
@@ -99,6 +171,50 @@ pub enum BibtexOutcome {
     Errors = 2,
 }
 
+/// The outcome of a BibTeX engine invocation that didn't end in a fatal
+/// error.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct BibtexOutcome {
+    /// The overall result of the run.
+    pub history: BibtexHistory,
+
+    /// The number of warnings that were issued.
+    pub n_warnings: u32,
+
+    /// The number of errors that were issued. Unlike the classic
+    /// implementation, this doesn't stop counting warnings once there's an
+    /// error, so both counts are complete.
+    pub n_errors: u32,
+
+    /// Whether the `.bbl` file was written. It isn't if the run stopped
+    /// before the style was read, such as when the `.aux` file names no style.
+    pub wrote_bbl: bool,
+}
+
+/// Hints about how large a run of the BibTeX engine will be, given with
+/// [`BibtexEngine::capacity_hints`].
+///
+/// A value of zero means that nothing is known, and all of the values may be
+/// underestimates or overestimates: the engine's tables grow as needed
+/// regardless.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct CapacityHints {
+    /// The number of entries that will be cited, or read from the databases
+    /// if everything is cited with `\nocite{*}`.
+    pub entries: usize,
+
+    /// The number of fields that the style declares, including the standard
+    /// `crossref` field.
+    pub fields: usize,
+
+    /// The number of distinct strings, such as cite keys, field values, and
+    /// names of macros and functions, that the engine will handle.
+    pub strings: usize,
+
+    /// The total length of those strings, in bytes.
+    pub string_bytes: usize,
+}
+
 /// A struct for invoking the BibTeX engine.
 ///
 /// This struct has a fairly straightforward "builder" interface: you create it,
@@ -114,21 +230,711 @@ pub enum BibtexOutcome {
 #[derive(Debug, Default)]
 pub struct BibtexEngine {
     config: BibtexConfig,
+    bbl: Option<Bbl>,
+    field_warnings: Vec<FieldWarning>,
+    entry_lints: Vec<EntryLint>,
+    style_lints: Vec<StyleLint>,
+    duplicate_entries: Vec<DuplicateEntry>,
+    missing_entries: Vec<MissingEntry>,
+    citation_report: Option<CitationReport>,
+    preambles: Vec<String>,
+    diagnostics: Vec<Diagnostic>,
+    #[cfg(feature = "memoize-builtins")]
+    memo_stats: Option<MemoStats>,
+    hash_stats: Option<HashStats>,
+    bst_profile: Option<BstProfile>,
+    breakpoint_hook: Option<BreakpointHook>,
+    custom_builtins: Vec<CustomBuiltin>,
+    char_widths: CharWidths,
+    id_source: Option<IdSource>,
+    metrics: Option<MetricsSink>,
+    code_cache: Option<BstCodeCache>,
 }
 
 impl BibtexEngine {
-    /// Set the BibTeX `min_crossrefs` parameter.
-    ///
-    /// The default value is 2.
+    /// Set the BibTeX `min_crossrefs` parameter, like the `-min-crossrefs`
+    /// option of classic BibTeX.
     ///
-    /// This needs verifying, but I believe that this setting affects how many
-    /// times an item needs to be referenced in directly-referenced BibTeX
-    /// entries before it gets its own standalone entry.
+    /// The default value is 2. An entry that isn't cited itself, but is named
+    /// in the `crossref` field of cited entries, is added to the bibliography
+    /// in its own right if at least this many of them refer to it. Otherwise,
+    /// the entries that refer to it inherit its fields, but their `crossref`
+    /// fields are cleared, so that styles don't refer to an entry that isn't
+    /// in the bibliography.
     pub fn min_crossrefs(&mut self, value: u32) -> &mut Self {
         self.config.min_crossrefs = value;
         self
     }
 
+    /// Only report warnings and errors on the terminal, like the `-terse`
+    /// option of classic BibTeX.
+    ///
+    /// The default is true. The banner and the names of the files that the
+    /// engine reads are always written to the `.blg` log, and if this is
+    /// false, they're also written to the terminal, as classic BibTeX does
+    /// by default.
+    pub fn terse(&mut self, terse: bool) -> &mut Self {
+        self.config.verbose = !terse;
+        self
+    }
+
+    /// Give hints about how large a run will be, so that the engine's tables
+    /// can be allocated up front.
+    ///
+    /// By default, the tables start small and grow as needed, so hints are
+    /// never required, but they avoid the cost of growing the tables
+    /// repeatedly when processing very large databases. They play the part of
+    /// the capacity settings of classic BibTeX implementations, which
+    /// otherwise fail once their fixed-size tables fill up.
+    pub fn capacity_hints(&mut self, hints: CapacityHints) -> &mut Self {
+        self.config.capacity = hints;
+        self
+    }
+
+    /// Set how many levels of `crossref` fields are followed when an entry
+    /// inherits fields from the entries that it refers to.
+    ///
+    /// The default value is 1, which matches classic BibTeX: an entry inherits
+    /// the fields of the entry named in its `crossref` field, but entries that
+    /// entry refers to in turn are only partially taken into account, and a
+    /// warning is issued. With larger values, chains such as an
+    /// `inproceedings` entry referring to its `proceedings`, which in turn
+    /// refers to a series, are resolved up to the given depth, with nearer
+    /// entries taking precedence. A value of zero is treated as one.
+    ///
+    /// Cross reference cycles are reported as errors regardless of this
+    /// setting.
+    pub fn max_crossref_depth(&mut self, value: u32) -> &mut Self {
+        self.config.max_crossref_depth = value;
+        self
+    }
+
+    /// Sets the date and time seen by the BibTeX engine.
+    ///
+    /// The engine uses this instead of the system clock wherever it needs the
+    /// time, such as for the [`generated`](CitationReport::generated) time of
+    /// the citation report, so that runs aiming for reproducible output give
+    /// the same results whenever they're made. If unset (the default), the
+    /// system clock is used.
+    pub fn build_date(&mut self, date: SystemTime) -> &mut Self {
+        self.config.build_date = Some(date);
+        self
+    }
+
+    /// Seed the pseudo-random numbers used by the engine.
+    ///
+    /// They make the identifiers of runs given in the citation report, unless
+    /// an [`id_source()`](Self::id_source) is set. By default, the generator
+    /// is seeded differently for each run; with a fixed seed, runs with the
+    /// same inputs give the same results.
+    pub fn rng_seed(&mut self, seed: u64) -> &mut Self {
+        self.config.rng_seed = Some(seed);
+        self
+    }
+
+    /// Set a function that gives the identifier of each run, which is given
+    /// in the citation report as [`CitationReport::run_id`].
+    ///
+    /// By default, each run gets a random identifier, from the generator
+    /// seeded with [`rng_seed()`](Self::rng_seed). Embedding code can use
+    /// this to tie the reports to its own records of its runs.
+    pub fn id_source<F>(&mut self, source: F) -> &mut Self
+    where
+        F: FnMut() -> String + Send + 'static,
+    {
+        self.id_source = Some(IdSource(Box::new(source)));
+        self
+    }
+
+    /// Record a structured representation of the `.bbl` output.
+    ///
+    /// The default is false. If enabled, after each run the output is
+    /// available from [`take_bbl()`](Self::take_bbl) as a [`Bbl`], which
+    /// describes what the style wrote for each entry, rather than only as the
+    /// text of the `.bbl` file. This is useful for consumers other than LaTeX,
+    /// such as HTML bibliographies. Each entry's output also records the sort
+    /// key and label that the style computed for it, which helps to find out
+    /// why an entry was put where it was, or labeled as it was.
+    pub fn record_bbl(&mut self, record: bool) -> &mut Self {
+        self.config.record_bbl = record;
+        self
+    }
+
+    /// Allow the style to write to output files other than the `.bbl` file.
+    ///
+    /// The default is false. If enabled, styles may use the `output.file$`
+    /// builtin, which pops a file name from the stack and routes the output
+    /// of subsequent `write$` and `newline$` calls to that file, opening it
+    /// the first time that it is named. An empty name routes output back to
+    /// the `.bbl` file. This makes it possible to split a bibliography across
+    /// several files, such as one per chapter. When disabled, the builtin
+    /// isn't defined at all, so that styles behave exactly as they do in
+    /// classic BibTeX.
+    pub fn output_files(&mut self, enabled: bool) -> &mut Self {
+        self.config.output_files = enabled;
+        self
+    }
+
+    /// Write a source map alongside the `.bbl` file.
+    ///
+    /// The default is false. If enabled, the engine records which entry of
+    /// which `.bib` file produced each line of the `.bbl` file, and writes
+    /// this information to a sidecar file named by appending `.map` to the
+    /// name of the `.bbl` file. Editor tooling can use it to jump from a
+    /// formatted bibliography item to its source. After a header comment,
+    /// the file contains one tab-separated record for each run of lines
+    /// produced by the same entry, giving the first and last line numbers
+    /// in the `.bbl` file, the cite key, the name of the `.bib` file, and
+    /// the line where the entry starts.
+    pub fn source_map(&mut self, enabled: bool) -> &mut Self {
+        self.config.source_map = enabled;
+        self
+    }
+
+    /// Set the order of the entries in the `.bbl` file.
+    ///
+    /// The default is [`EntryOrder::Style`], which keeps the order chosen by
+    /// the style. Any other order is applied after the style has run, by
+    /// moving the output that the style wrote for each entry, so that it can
+    /// be used with styles that don't sort the bibliography as needed. In this
+    /// case the `.bbl` file is only written once the run has finished, and
+    /// each entry's output is expected to end with a `newline$`, as it does in
+    /// all common styles.
+    pub fn entry_order(&mut self, order: EntryOrder) -> &mut Self {
+        self.config.entry_order = order;
+        self
+    }
+
+    /// Have `SORT` order the entries by keys that the engine computes with
+    /// a [`SortScheme`], rather than by the `sort.key$` values that the
+    /// style computes.
+    ///
+    /// By default, the style's keys are used. Unlike
+    /// [`entry_order()`](Self::entry_order), this changes the order in which
+    /// the style processes the entries, so that labels that depend on it,
+    /// such as the `a` and `b` that `alpha`-like styles add to labels that
+    /// would otherwise be the same, follow the new order.
+    pub fn sort_scheme(&mut self, scheme: SortScheme) -> &mut Self {
+        self.config.sort_scheme = Some(scheme);
+        self
+    }
+
+    /// Check the format of the values of a field.
+    ///
+    /// By default, no fields are checked. Each time this is called, the named
+    /// field, which is matched case-insensitively, is checked in all entries
+    /// once the database files have been read. Each problem is reported as a
+    /// warning in the log, and is also available from
+    /// [`take_field_warnings()`](Self::take_field_warnings). To check the
+    /// usual fields, call this with each item of [`FieldCheck::COMMON`].
+    ///
+    /// Only fields that the style declares in its `ENTRY` command can be
+    /// checked; others are silently skipped.
+    pub fn check_field(&mut self, field: &str, check: FieldCheck) -> &mut Self {
+        self.config
+            .field_checks
+            .push((field.to_ascii_lowercase(), check));
+        self
+    }
+
+    /// Lint the entries against the schemas of their types.
+    ///
+    /// The default is false. If enabled, once the database files have been
+    /// read, and before the style runs any of its code, each entry is checked
+    /// for missing required fields and for fields that its type doesn't
+    /// allow, using the standard schemas of [`EntrySchema::standard()`] and
+    /// any given with [`entry_schema()`](Self::entry_schema). Unlike the
+    /// field checks, this takes all of the fields in the `.bib` files into
+    /// account, whether or not the style declares them. Each problem is
+    /// reported as a warning in the log, and is also available from
+    /// [`take_entry_lints()`](Self::take_entry_lints).
+    pub fn lint_entries(&mut self, enabled: bool) -> &mut Self {
+        self.config.lint_entries = enabled;
+        self
+    }
+
+    /// Set the schema used to lint entries of a type.
+    ///
+    /// This replaces the standard schema of the type, if it has one, or adds a
+    /// schema for a type that doesn't, such as `online`. The type is matched
+    /// case-insensitively. Schemas only have an effect if linting is enabled
+    /// with [`lint_entries()`](Self::lint_entries).
+    pub fn entry_schema(&mut self, entry_type: &str, schema: EntrySchema) -> &mut Self {
+        self.config
+            .entry_schemas
+            .push((entry_type.to_ascii_lowercase(), schema));
+        self
+    }
+
+    /// Look for entries that seem to duplicate each other.
+    ///
+    /// The default is false. If enabled, once the database files have been
+    /// read, the entries are compared with each other, and those that have
+    /// the same title and the same last name for their first author as an
+    /// earlier entry are reported, since they probably describe the same
+    /// work. The comparison ignores case, accents, whether written in UTF-8
+    /// or as TeX accent commands, braces, and punctuation. Each duplicate is
+    /// reported as a warning in the log, and is also available from
+    /// [`take_duplicate_entries()`](Self::take_duplicate_entries).
+    ///
+    /// Only entries whose `title` and `author` fields the style declares in
+    /// its `ENTRY` command, as the standard styles do, can be compared.
+    pub fn find_duplicates(&mut self, enabled: bool) -> &mut Self {
+        self.config.find_duplicates = enabled;
+        self
+    }
+
+    /// Transliterate characters in sort keys.
+    ///
+    /// By default, sort keys are left as the style computes them. With a
+    /// non-empty table, the replacements are applied to each value that the
+    /// style assigns to `sort.key$`, and to the field values used by
+    /// [`entry_order()`](Self::entry_order), so that entries written in
+    /// different scripts sort predictably. The printed text of the entries is
+    /// not affected.
+    pub fn transliteration(&mut self, table: Transliteration) -> &mut Self {
+        self.config.transliteration = table;
+        self
+    }
+
+    /// Read bibliography databases in CSL-JSON and YAML formats.
+    ///
+    /// The default is false. If enabled, databases named in `\bibdata` with
+    /// a `.json` extension are read as CSL-JSON, and those with a `.yaml` or
+    /// `.yml` extension as [Hayagriva] YAML. Their items are converted into
+    /// BibTeX entries, with the types and fields that the standard styles
+    /// use, before the style reads them, so that they can be formatted with
+    /// classic `.bst` styles alongside entries from `.bib` files. Items that
+    /// can't be converted are skipped with a warning. When disabled, such
+    /// databases are read as `.bib` files, as in classic BibTeX.
+    ///
+    /// [Hayagriva]: https://github.com/typst/hayagriva/blob/main/docs/file-format.md
+    pub fn structured_databases(&mut self, enabled: bool) -> &mut Self {
+        self.config.structured_databases = enabled;
+        self
+    }
+
+    /// Change the case of non-ASCII characters in `change.case$`.
+    ///
+    /// The default is false, in which case only ASCII letters are mapped, as
+    /// in classic BibTeX, and accented letters written directly in UTF-8 keep
+    /// their case. If enabled, UTF-8 characters are mapped with the Unicode
+    /// case mappings, so that a title such as `Études sur l'Œuvre` is
+    /// lowercased to `Études sur l'œuvre` by the `"t"` conversion.
+    /// Characters protected by braces are left alone either way.
+    pub fn unicode_case(&mut self, enabled: bool) -> &mut Self {
+        self.config.unicode_case = enabled;
+        self
+    }
+
+    /// Truncate long strings stored in entry and global variables.
+    ///
+    /// Classic BibTeX stores the values of `ENTRY` string variables in
+    /// buffers of 250 bytes, and those of `STRINGS` variables in buffers of
+    /// 20,000 bytes, and `:=` truncates longer strings with a warning. The
+    /// default is false, in which case strings of any length are stored
+    /// whole, so that long URLs and abstracts make it into the output. If
+    /// enabled, strings are truncated as in classic BibTeX, which may be
+    /// needed to reproduce its output exactly. Either way, `entry.max$` and
+    /// `global.max$` give the classic limits.
+    pub fn truncate_strings(&mut self, enabled: bool) -> &mut Self {
+        self.config.truncate_strings = enabled;
+        self
+    }
+
+    /// Limit the length of the values of a field in the database files.
+    ///
+    /// By default, field values of any length are read whole. With a limit,
+    /// the values of the named field, which is matched case-insensitively,
+    /// that are longer than its number of bytes are truncated or skipped as
+    /// they're read, as its [`LongFieldAction`] says, and each is reported as
+    /// a warning. This overrides the [default
+    /// limit](Self::default_field_limit) for the field. The `crossref` field
+    /// isn't limited.
+    pub fn field_limit(&mut self, field: &str, limit: FieldLimit) -> &mut Self {
+        self.config
+            .field_limits
+            .push((field.to_ascii_lowercase(), limit));
+        self
+    }
+
+    /// Limit the length of the values of all fields in the database files.
+    ///
+    /// The default is no limit. This works like
+    /// [`field_limit()`](Self::field_limit), for the fields that don't have
+    /// a limit of their own, so that, for instance, a limit that truncates
+    /// long values can be combined with one for `url` that
+    /// [keeps](LongFieldAction::Keep) them.
+    pub fn default_field_limit(&mut self, limit: FieldLimit) -> &mut Self {
+        self.config.default_field_limit = Some(limit);
+        self
+    }
+
+    /// Limit the number of steps that the style may take.
+    ///
+    /// By default, the style's code runs for as long as it takes, so that a
+    /// `while$` loop whose condition never becomes false hangs the engine.
+    /// With a limit, each function call made by the style's code, builtins
+    /// included, is a step, counted over the whole run, and once there have
+    /// been more than *steps* of them, the run stops with a fatal error,
+    /// which [`process()`](Self::process) returns as a [`StepLimitExceeded`].
+    /// Standard styles take several hundred steps per entry, so a limit of
+    /// some millions stops runaway styles without getting in the way of
+    /// large bibliographies.
+    pub fn step_limit(&mut self, steps: u64) -> &mut Self {
+        self.config.step_limit = Some(steps);
+        self
+    }
+
+    /// Turn problems in the engine itself into fatal errors.
+    ///
+    /// The engine reports problems in the files that it reads as errors, but
+    /// panics if it finds that its own tables are inconsistent, as it would
+    /// after a bug had let it read something that it shouldn't have. The
+    /// default is false, which keeps those panics, with their backtraces, and
+    /// [`process()`](Self::process) returns the error that the core bridge
+    /// makes of them. If enabled, they stop the run with a fatal error
+    /// instead, which is written to the logs, and so does any other panic in
+    /// the engine, and `process()` returns an [`InternalError`]. Caught panics
+    /// are still reported by the panic hook. Services that run BibTeX on files
+    /// their users provide should enable this, along with a [step
+    /// limit](Self::step_limit).
+    pub fn hardened(&mut self, enabled: bool) -> &mut Self {
+        self.config.hardened = enabled;
+        self
+    }
+
+    /// Sort entries in the order of a language.
+    ///
+    /// By default, `SORT` orders entries by the bytes of their `sort.key$`
+    /// values, as classic BibTeX does, which puts accented letters written
+    /// in UTF-8 after `z`. With a locale, the values are compared as a
+    /// reader of its language expects: see [`Locale`] for the details. The
+    /// same goes for the field values used by
+    /// [`entry_order()`](Self::entry_order). The `sort.key$` values
+    /// themselves are left as the style computes them.
+    pub fn collation(&mut self, locale: Locale) -> &mut Self {
+        self.config.collation = Some(locale);
+        self
+    }
+
+    /// Read a bibtex8-style codepage and sort definition file.
+    ///
+    /// By default, only the ASCII letters change case, and `SORT` orders
+    /// entries by the bytes of their `sort.key$` values. A `.csf` file, which
+    /// is found like a style file, can give the case mappings of the letters
+    /// of an 8-bit codepage, which `change.case$` uses unless
+    /// [`unicode_case()`](Self::unicode_case) is enabled, and the order in
+    /// which `SORT` compares characters, which a
+    /// [`collation()`](Self::collation) overrides. See the `.csf` files of
+    /// bibtex8 for examples. If the file can't be read, the run fails.
+    pub fn csf_file<S: ToString>(&mut self, name: S) -> &mut Self {
+        self.config.csf_file = Some(name.to_string());
+        self
+    }
+
+    /// Write data for biblatex instead of running the style.
+    ///
+    /// The default is false. If enabled, the `.bbl` file is written in the
+    /// format that biblatex reads from biber, listing the cited entries with
+    /// their names, lists, and fields, so that documents using biblatex can
+    /// be processed without running biber. The entries are read from the
+    /// databases named by `\bibdata` and cited by `\citation` in the `.aux`
+    /// file, as biblatex writes them with its `backend=bibtex` option, and
+    /// sorted as its `\abx@aux@refcontext` asks. The style named by
+    /// `\bibstyle` isn't read, and the options that affect the output of the
+    /// style, such as [`entry_order()`](Self::entry_order), have no effect,
+    /// except for [`collation()`](Self::collation). See the
+    /// [`biblatex`](crate::biblatex) module for what is supported.
+    pub fn biblatex_output(&mut self, enabled: bool) -> &mut Self {
+        self.config.biblatex_output = enabled;
+        self
+    }
+
+    /// Only store the entries that are needed when writing data for biblatex.
+    ///
+    /// The default is false, in which case every entry of every database is
+    /// read and stored when [`biblatex_output()`](Self::biblatex_output) is
+    /// enabled, even if only a handful of them are cited. If enabled, the
+    /// databases are read twice instead: first to index the key and location
+    /// of each entry, along with the entries that it names in its `crossref`
+    /// and `xdata` fields, and then to read the text of only the cited
+    /// entries and those that they inherit from. This takes much less memory
+    /// with large shared databases, at the cost of reading them twice. The
+    /// output is the same, except that warnings about the fields of entries
+    /// that aren't needed aren't given.
+    ///
+    /// When the style is run, only the fields of the cited entries are stored
+    /// anyway, and the databases are read once, a line at a time. The keys of
+    /// the other entries are still remembered, to suggest alternatives to
+    /// cited keys that are missing, which adds up with databases of hundreds
+    /// of thousands of entries. If this option is enabled, a key is only
+    /// remembered if it's near enough to a key that has been cited to be
+    /// suggested for it, so that the memory used doesn't grow with the size
+    /// of the databases, at the cost of comparing each key with the cited
+    /// ones as it's read.
+    pub fn low_memory(&mut self, enabled: bool) -> &mut Self {
+        self.config.low_memory = enabled;
+        self
+    }
+
+    /// Trace the execution of the style.
+    ///
+    /// The default is false. If enabled, every call of a function made by the
+    /// style, including the pushing of literals, is written to the log file,
+    /// on a line starting with `[trace]`, along with the line of the style
+    /// that made it and the contents of the literal stack. See [`BstCall`].
+    /// This makes the log very long, but shows exactly what a misbehaving
+    /// style does.
+    pub fn trace_bst(&mut self, enabled: bool) -> &mut Self {
+        self.config.trace_bst = enabled;
+        self
+    }
+
+    /// Profile the functions of the style.
+    ///
+    /// The default is false. If enabled, the calls that the style makes of
+    /// each builtin and of each of its own functions are counted and timed,
+    /// and the figures are written to the end of the log file, and can be
+    /// taken with [`take_bst_profile()`](Self::take_bst_profile) after the
+    /// run. See [`BstProfile`]. This shows where the time goes in a style
+    /// that is slow, at the cost of slowing it down further.
+    pub fn profile_bst(&mut self, enabled: bool) -> &mut Self {
+        self.config.profile_bst = enabled;
+        self
+    }
+
+    /// Set a breakpoint on the style's function with the given name, which
+    /// may be a builtin such as `format.name$`.
+    ///
+    /// Whenever the style is about to call the function, the hook given to
+    /// [`on_bst_breakpoint()`](Self::on_bst_breakpoint) is called, which can
+    /// inspect the call and decide whether the style keeps running.
+    /// Breakpoints have no effect if there's no hook.
+    pub fn bst_breakpoint(&mut self, function: &str) -> &mut Self {
+        self.config.bst_breakpoints.push(function.to_owned());
+        self
+    }
+
+    /// Set the function that's called when a breakpoint set with
+    /// [`bst_breakpoint()`](Self::bst_breakpoint) is hit.
+    ///
+    /// If the hook returns [`BreakAction::Stop`], the engine stops running
+    /// the style, reports an error, and closes its outputs as they are.
+    pub fn on_bst_breakpoint<F>(&mut self, hook: F) -> &mut Self
+    where
+        F: FnMut(&BstCall) -> BreakAction + Send + 'static,
+    {
+        self.breakpoint_hook = Some(BreakpointHook(Box::new(hook)));
+        self
+    }
+
+    /// Report metrics of each run to *sink*.
+    ///
+    /// At the end of each run, the engine reports counters and histograms,
+    /// such as the time spent in each phase of the run and the number of
+    /// warnings, to the sink, which can pass them on to the metrics system of
+    /// the embedding application. See [`BibtexMetrics`] for the details.
+    pub fn metrics<M: BibtexMetrics + 'static>(&mut self, sink: M) -> &mut Self {
+        self.metrics = Some(MetricsSink(Box::new(sink)));
+        self
+    }
+
+    /// Reuse the code of the functions that styles define from *cache*, and
+    /// add the code of those that have to be scanned to it.
+    ///
+    /// The default is not to use a cache. The code is keyed by the digest of
+    /// the style file, so the cache can hold the code of several styles, and
+    /// it can be kept between runs with [`BstCodeCache::save`] and
+    /// [`BstCodeCache::load`]. The output is the same whether or not the
+    /// cache is used. Get the cache back after the run with
+    /// [`take_code_cache()`](Self::take_code_cache).
+    pub fn code_cache(&mut self, cache: Option<BstCodeCache>) -> &mut Self {
+        self.code_cache = cache;
+        self
+    }
+
+    /// Take the cache given with [`code_cache()`](Self::code_cache), along
+    /// with the code added to it by the runs since then.
+    pub fn take_code_cache(&mut self) -> Option<BstCodeCache> {
+        self.code_cache.take()
+    }
+
+    /// Lint the style for functions that can't balance the literal stack.
+    ///
+    /// The default is false. If enabled, each function is analyzed when the
+    /// style defines it, before any of the style's code runs, following the
+    /// types of the values on the stack through its body. Values of the
+    /// wrong type given to builtins or to the style's own functions, such as
+    /// a string given to `if$` in place of a function, are reported, as are
+    /// `while$` loops that change the depth of the stack, and functions run
+    /// by `EXECUTE`, `ITERATE`, or `REVERSE` that pop more values than they
+    /// push or leave values behind. Only problems that don't depend on the
+    /// data are reported. Each is a warning in the log, and is also available
+    /// from [`take_style_lints()`](Self::take_style_lints).
+    pub fn lint_style(&mut self, enabled: bool) -> &mut Self {
+        self.config.lint_style = enabled;
+        self
+    }
+
+    /// Add a builtin function with the given name, such as `url.cleanup$`,
+    /// which styles can call like the standard ones.
+    ///
+    /// When the style calls it, *func* is given a [`BuiltinStack`], through
+    /// which it pops its arguments from the literal stack and pushes its
+    /// results. If it returns an error, the error is reported as a problem
+    /// with the style, which carries on running. Names aren't case-sensitive,
+    /// and a builtin with the name of a standard one is ignored, as is one
+    /// registered after another with the same name. Builtins aren't
+    /// memoized, even if the `memoize-builtins` feature is enabled.
+    ///
+    /// Styles that call builtins that are only registered by some programs
+    /// can't be used by others, so this is best suited to trying out
+    /// extensions.
+    pub fn register_builtin<F>(&mut self, name: &str, func: F) -> &mut Self
+    where
+        F: FnMut(&mut BuiltinStack<'_>) -> Result<()> + Send + 'static,
+    {
+        self.custom_builtins
+            .push(CustomBuiltin::new(name, Box::new(func)));
+        self
+    }
+
+    /// Set the widths of characters that `width$` adds up, by byte.
+    ///
+    /// By default, `width$` measures text in the widths of the characters of
+    /// cmr10, in thousandths of an em, as classic BibTeX does. Styles use it
+    /// to find the widest label, so that the entries of the bibliography are
+    /// indented to fit, and if the document is set in another font, a table
+    /// of that font's widths gives a better fit. The widths are in whatever
+    /// units the style expects, which for the standard styles don't matter,
+    /// since they only compare widths. Control sequences that stand for
+    /// characters, such as `\ss`, are still given their widths in cmr10.
+    /// This replaces any function given to
+    /// [`char_width_metric()`](Self::char_width_metric).
+    pub fn char_width_table(&mut self, widths: [i64; 256]) -> &mut Self {
+        self.char_widths = CharWidths::Table(Box::new(widths));
+        self
+    }
+
+    /// Set a function that gives the widths of characters that `width$` adds
+    /// up, such as one that looks them up in the metrics of the document's
+    /// font.
+    ///
+    /// The text is decoded as UTF-8, and *metric* is called with each of its
+    /// characters, with any byte that isn't part of a character given as
+    /// U+FFFD. The characters that BibTeX recognizes as control sequences,
+    /// such as `\ss` and `\AE`, are given as the characters that they stand
+    /// for. As with [`char_width_table()`](Self::char_width_table), the
+    /// widths are in whatever units the style expects, which by default are
+    /// thousandths of an em. This replaces any table given to that method.
+    pub fn char_width_metric<F>(&mut self, metric: F) -> &mut Self
+    where
+        F: FnMut(char) -> i64 + Send + 'static,
+    {
+        self.char_widths = CharWidths::Metric(Box::new(metric));
+        self
+    }
+
+    /// Take the problems found by field checks during the most recent run.
+    ///
+    /// See [`check_field()`](Self::check_field).
+    pub fn take_field_warnings(&mut self) -> Vec<FieldWarning> {
+        std::mem::take(&mut self.field_warnings)
+    }
+
+    /// Take the problems found by linting entries during the most recent run.
+    ///
+    /// See [`lint_entries()`](Self::lint_entries).
+    pub fn take_entry_lints(&mut self) -> Vec<EntryLint> {
+        std::mem::take(&mut self.entry_lints)
+    }
+
+    /// Take the problems found by linting the style during the most recent
+    /// run.
+    ///
+    /// See [`lint_style()`](Self::lint_style).
+    pub fn take_style_lints(&mut self) -> Vec<StyleLint> {
+        std::mem::take(&mut self.style_lints)
+    }
+
+    /// Take the entries that seemed to duplicate others during the most
+    /// recent run.
+    ///
+    /// See [`find_duplicates()`](Self::find_duplicates).
+    pub fn take_duplicate_entries(&mut self) -> Vec<DuplicateEntry> {
+        std::mem::take(&mut self.duplicate_entries)
+    }
+
+    /// Take the cite keys that weren't found in any database during the most
+    /// recent run, along with suggestions of similar keys that were.
+    pub fn take_missing_entries(&mut self) -> Vec<MissingEntry> {
+        std::mem::take(&mut self.missing_entries)
+    }
+
+    /// Take the report of the citations of the most recent run: which `.bib`
+    /// file satisfied each cited key, which keys no database had, and which
+    /// entries were ignored because their keys were repeated.
+    ///
+    /// This returns `None` if the run stopped before the databases were
+    /// read, or if the report has already been taken.
+    pub fn take_citation_report(&mut self) -> Option<CitationReport> {
+        self.citation_report.take()
+    }
+
+    /// Take the texts of the `@preamble` commands read from the databases
+    /// during the most recent run, in the order that they were read.
+    ///
+    /// These are the values after macros were expanded and the parts were
+    /// concatenated, which is what the `preamble$` builtin gives the style.
+    pub fn take_preambles(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.preambles)
+    }
+
+    /// Take the warnings and errors reported during the most recent run, in
+    /// the order that they were reported, along with where they were found.
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
+    /// Get the statistics of the memoization of the `purify$` and
+    /// `change.case$` builtins during the most recent run, or `None` if no
+    /// run has completed.
+    ///
+    /// These are also written to the log file.
+    #[cfg(feature = "memoize-builtins")]
+    pub fn memo_stats(&self) -> Option<MemoStats> {
+        self.memo_stats
+    }
+
+    /// Get the statistics of the engine's hash table, which holds the cite
+    /// keys, macros, functions, and other names that it knows about, at the
+    /// end of the most recent run, or `None` if no run has completed.
+    ///
+    /// These can help to choose [`capacity_hints()`](Self::capacity_hints)
+    /// for very large databases.
+    pub fn hash_stats(&self) -> Option<HashStats> {
+        self.hash_stats
+    }
+
+    /// Take the profile of the style's functions made during the most recent
+    /// run, if [`profile_bst()`](Self::profile_bst) was enabled.
+    ///
+    /// This returns `None` if profiling wasn't enabled, or if the profile has
+    /// already been taken.
+    pub fn take_bst_profile(&mut self) -> Option<BstProfile> {
+        self.bst_profile.take()
+    }
+
+    /// Take the structured `.bbl` output of the most recent run.
+    ///
+    /// This returns `None` unless recording was enabled with
+    /// [`record_bbl()`](Self::record_bbl) and a run has completed since the
+    /// last call.
+    pub fn take_bbl(&mut self) -> Option<Bbl> {
+        self.bbl.take()
+    }
+
     /// Run BibTeX.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -140,30 +946,147 @@ impl BibtexEngine {
         &mut self,
         launcher: &mut CoreBridgeLauncher<'_>,
         aux: &str,
+    ) -> Result<BibtexOutcome> {
+        self.process_inner(launcher, aux, None)
+    }
+
+    /// Run BibTeX, letting embedding code decide which of the style's
+    /// commands run.
+    ///
+    /// This works like [`process()`](Self::process), except that the commands
+    /// of the style file that run its code -- `READ`, `EXECUTE`, `ITERATE`,
+    /// `REVERSE`, and `SORT` -- aren't run as the style file is read. Instead,
+    /// once the whole style has been read, *driver* is called with a
+    /// [`StyleSession`] that lists those commands, can run them or others in
+    /// any order, and can inspect the entries in between. For instance, a
+    /// driver could run just the commands that compute the entries' labels,
+    /// and then collect those instead of writing the `.bbl` file. Whatever
+    /// the style writes is still written to the `.bbl` file.
+    ///
+    /// If the driver returns an error, the engine finishes up as usual and
+    /// then this returns the driver's error.
+    pub fn process_with_driver<F>(
+        &mut self,
+        launcher: &mut CoreBridgeLauncher<'_>,
+        aux: &str,
+        driver: F,
+    ) -> Result<BibtexOutcome>
+    where
+        F: FnOnce(&mut StyleSession<'_>) -> Result<()>,
+    {
+        let mut driver = Some(driver);
+        let mut driver_result = Ok(());
+
+        let outcome = self.process_inner(
+            launcher,
+            aux,
+            Some(&mut |session: &mut StyleSession<'_>| {
+                if let Some(driver) = driver.take() {
+                    driver_result = driver(session);
+                }
+            }),
+        );
+
+        driver_result?;
+        outcome
+    }
+
+    fn process_inner(
+        &mut self,
+        launcher: &mut CoreBridgeLauncher<'_>,
+        aux: &str,
+        driver: Option<&mut StyleDriver<'_>>,
     ) -> Result<BibtexOutcome> {
         let caux = CString::new(aux)?;
 
+        self.bbl = None;
+        self.field_warnings.clear();
+        self.entry_lints.clear();
+        self.style_lints.clear();
+        self.duplicate_entries.clear();
+        self.missing_entries.clear();
+        self.citation_report = None;
+        self.preambles.clear();
+        self.diagnostics.clear();
+
         launcher.with_global_lock(|state| {
             let mut ctx = Bibtex::new(state, self.config.clone());
-            let hist = bibtex_main(&mut ctx, &caux);
-
-            match hist {
-                History::Spotless => Ok(BibtexOutcome::Spotless),
-                History::WarningIssued(_) => Ok(BibtexOutcome::Warnings),
-                History::ErrorIssued(_) => Ok(BibtexOutcome::Errors),
-                History::FatalError => Err(anyhow!("unspecified fatal bibtex error")),
+            ctx.breakpoint_hook = self.breakpoint_hook.take();
+            ctx.custom_builtins = std::mem::take(&mut self.custom_builtins);
+            ctx.char_widths = std::mem::take(&mut self.char_widths);
+            ctx.id_source = self.id_source.take();
+            ctx.code_cache = self.code_cache.take();
+            let started = Instant::now();
+            let hist = if ctx.config.hardened {
+                panic::catch_unwind(AssertUnwindSafe(|| bibtex_main(&mut ctx, &caux, driver)))
+                    .unwrap_or_else(|payload| {
+                        internal_error(&mut ctx, panic_message(&*payload));
+                        History::FatalError
+                    })
+            } else {
+                bibtex_main(&mut ctx, &caux, driver)
+            };
+            self.breakpoint_hook = ctx.breakpoint_hook.take();
+            self.custom_builtins = std::mem::take(&mut ctx.custom_builtins);
+            self.char_widths = std::mem::take(&mut ctx.char_widths);
+            self.id_source = ctx.id_source.take();
+            self.code_cache = ctx.code_cache.take();
+            self.field_warnings = std::mem::take(&mut ctx.field_warnings);
+            self.entry_lints = std::mem::take(&mut ctx.entry_lints);
+            self.style_lints = std::mem::take(&mut ctx.style_lints);
+            self.duplicate_entries = std::mem::take(&mut ctx.duplicate_entries);
+            self.missing_entries = std::mem::take(&mut ctx.missing_entries);
+            self.citation_report = ctx.citation_report.take();
+            self.preambles = std::mem::take(&mut ctx.preambles);
+            self.diagnostics = ctx.diagnostics.take();
+            #[cfg(feature = "memoize-builtins")]
+            {
+                self.memo_stats = ctx.memo.as_ref().map(BuiltinMemo::stats);
+            }
+            self.hash_stats = ctx.hash_stats;
+            self.bst_profile = ctx.bst_profile.take();
+            self.bbl = ctx
+                .bbl_recorder
+                .take()
+                .filter(|_| self.config.record_bbl)
+                .map(BblRecorder::finish);
+
+            let history = match hist {
+                History::Spotless => Some(BibtexHistory::Spotless),
+                History::WarningIssued(_) => Some(BibtexHistory::Warnings),
+                History::ErrorIssued(_) => Some(BibtexHistory::Errors),
+                History::FatalError => None,
+            };
+
+            if let Some(MetricsSink(sink)) = &mut self.metrics {
+                ctx.run_metrics
+                    .report(&mut **sink, history, started.elapsed(), &self.diagnostics);
             }
+
+            let Some(history) = history else {
+                return Err(if let Some(err) = ctx.step_limit_exceeded.take() {
+                    err.into()
+                } else if let Some(err) = ctx.internal_error.take() {
+                    err.into()
+                } else {
+                    anyhow!("unspecified fatal bibtex error")
+                });
+            };
+
+            Ok(BibtexOutcome {
+                history,
+                n_warnings: ctx.n_warnings,
+                n_errors: ctx.n_errors,
+                wrote_bbl: ctx.wrote_bbl,
+            })
         })
     }
 }
 
 // These used to be 'bad' checks at the start of a program, now we can ensure them at comptime
-const _: () = assert!(hash::HASH_PRIME >= 128);
 const _: () = assert!(pool::MAX_PRINT_LINE > pool::MIN_PRINT_LINE);
 const _: () = assert!(pool::MIN_PRINT_LINE >= 3);
 const _: () = assert!(pool::MAX_PRINT_LINE < buffer::BUF_SIZE + 1);
-const _: () = assert!(hash::HASH_PRIME <= hash::HASH_SIZE);
-const _: () = assert!(pool::MAX_STRINGS <= hash::HASH_SIZE);
 const _: () = assert!(cite::MAX_CITES <= pool::MAX_STRINGS);
 
 pub(crate) struct File {
@@ -187,18 +1110,92 @@ pub(crate) struct GlobalItems<'a> {
 #[derive(Clone, Debug)]
 pub(crate) struct BibtexConfig {
     pub min_crossrefs: u32,
+    pub max_crossref_depth: u32,
     pub verbose: bool,
+    pub build_date: Option<SystemTime>,
+    pub rng_seed: Option<u64>,
+    pub record_bbl: bool,
+    pub output_files: bool,
+    pub source_map: bool,
+    pub entry_order: EntryOrder,
+    pub sort_scheme: Option<SortScheme>,
+    pub field_checks: Vec<(String, FieldCheck)>,
+    pub lint_entries: bool,
+    pub entry_schemas: Vec<(String, EntrySchema)>,
+    pub find_duplicates: bool,
+    pub transliteration: Transliteration,
+    pub structured_databases: bool,
+    pub unicode_case: bool,
+    pub truncate_strings: bool,
+    pub field_limits: Vec<(String, FieldLimit)>,
+    pub default_field_limit: Option<FieldLimit>,
+    pub step_limit: Option<u64>,
+    pub hardened: bool,
+    pub collation: Option<Locale>,
+    pub csf_file: Option<String>,
+    pub biblatex_output: bool,
+    pub low_memory: bool,
+    pub capacity: CapacityHints,
+    pub trace_bst: bool,
+    pub profile_bst: bool,
+    pub bst_breakpoints: Vec<String>,
+    pub lint_style: bool,
+}
+
+impl BibtexConfig {
+    /// Whether the entries of the `.bbl` output are reordered after the style
+    /// has run.
+    pub(crate) fn reordering(&self) -> bool {
+        self.entry_order != EntryOrder::Style
+    }
 }
 
 impl Default for BibtexConfig {
     fn default() -> Self {
         BibtexConfig {
             min_crossrefs: 2,
+            max_crossref_depth: 1,
             verbose: false,
+            build_date: None,
+            rng_seed: None,
+            record_bbl: false,
+            output_files: false,
+            source_map: false,
+            entry_order: EntryOrder::Style,
+            sort_scheme: None,
+            field_checks: Vec::new(),
+            lint_entries: false,
+            entry_schemas: Vec::new(),
+            find_duplicates: false,
+            transliteration: Transliteration::default(),
+            structured_databases: false,
+            unicode_case: false,
+            truncate_strings: false,
+            field_limits: Vec::new(),
+            default_field_limit: None,
+            step_limit: None,
+            hardened: false,
+            collation: None,
+            csf_file: None,
+            biblatex_output: false,
+            low_memory: false,
+            capacity: CapacityHints::default(),
+            trace_bst: false,
+            profile_bst: false,
+            bst_breakpoints: Vec::new(),
+            lint_style: false,
         }
     }
 }
 
+/// An output file other than the `.bbl`, opened by the style with
+/// `output.file$`.
+pub(crate) struct ExtraOutput {
+    pub name: Vec<u8>,
+    pub file: OutputId,
+    pub lines: LineBreaker,
+}
+
 #[derive(Default)]
 pub(crate) struct Logs {
     stdout: Option<OutputId>,
@@ -209,16 +1206,99 @@ pub(crate) struct Bibtex<'a, 'cbs> {
     pub engine: &'a mut CoreBridgeState<'cbs>,
     pub config: BibtexConfig,
     pub history: History,
+    /// The numbers of warnings and errors issued so far. Unlike `history`,
+    /// these keep counting warnings after an error.
+    pub n_warnings: u32,
+    pub n_errors: u32,
     pub logs: Logs,
 
     pub bst: Option<File>,
 
     pub bbl_file: Option<OutputId>,
     pub bbl_line_num: usize,
+    pub bbl_lines: LineBreaker,
+    pub bbl_recorder: Option<BblRecorder>,
+    /// Whether the `.bbl` file has been written and closed.
+    pub wrote_bbl: bool,
+    pub source_map: Option<SourceMapRecorder>,
+    /// Additional outputs, and which of them `write$` currently goes to, if
+    /// not the `.bbl`.
+    pub extra_outputs: Vec<ExtraOutput>,
+    pub cur_output: Option<usize>,
+    /// Problems found by field checks.
+    pub field_warnings: Vec<FieldWarning>,
+    /// The types and fields of the entries as they were read, keyed by
+    /// lowercase cite key, if linting is enabled.
+    pub recorded_entries: HashMap<String, RecordedEntry>,
+    /// Problems found by linting entries.
+    pub entry_lints: Vec<EntryLint>,
+    /// The stack effects of the style's functions, if linting is enabled.
+    pub stack_effects: HashMap<HashPointer, Option<Effect>>,
+    /// Problems found by linting the style.
+    pub style_lints: Vec<StyleLint>,
+    /// Entries that seem to duplicate earlier ones.
+    pub duplicate_entries: Vec<DuplicateEntry>,
+    /// The keys of all of the entries read from the databases, for suggesting
+    /// alternatives to missing keys. In low-memory mode, only those that
+    /// `key_filter` admits are kept.
+    pub database_keys: Vec<String>,
+    /// The cited keys that database keys are compared with in low-memory
+    /// mode.
+    pub key_filter: KeyFilter,
+    /// Citations of keys that weren't in any database.
+    pub missing_entries: Vec<MissingEntry>,
+    /// Entries ignored because their keys were repeated, so far.
+    pub repeated_entries: Vec<RepeatedEntry>,
+    /// The report of the citations, once the databases have been read.
+    pub citation_report: Option<CitationReport>,
+    /// The texts of the `@preamble`s, once the run is over.
+    pub preambles: Vec<String>,
+    /// The warnings and errors reported so far.
+    pub diagnostics: DiagnosticRecorder,
+    /// Remembered results of builtins, if the `memoize-builtins` feature is
+    /// enabled.
+    pub memo: Option<BuiltinMemo>,
+    /// The statistics of the hash table, once the run is over.
+    pub hash_stats: Option<HashStats>,
+    /// The state of profiling, if the functions of the style are profiled.
+    pub profiler: Option<Profiler>,
+    /// The profile of the style's functions, once the run is over.
+    pub bst_profile: Option<BstProfile>,
+    /// The error to return if the style ran out of steps.
+    pub step_limit_exceeded: Option<StepLimitExceeded>,
+    /// The error to return if the engine found its state inconsistent, in
+    /// hardened mode.
+    pub internal_error: Option<InternalError>,
+    /// The figures from which the metrics of the run are made.
+    pub run_metrics: RunMetrics,
+    /// The function called when the style hits a breakpoint.
+    pub breakpoint_hook: Option<BreakpointHook>,
+    /// The builtins supplied by embedding code.
+    pub custom_builtins: Vec<CustomBuiltin>,
+    /// The widths of characters used by `width$`.
+    pub char_widths: CharWidths,
+    /// The function giving the identifier of the run, if one was supplied.
+    pub id_source: Option<IdSource>,
+    /// The pseudo-random numbers used by the run.
+    pub rng: Rng,
+    /// The character tables, which a `.csf` file may customize.
+    pub char_info: Rc<CharInfo>,
+    /// The cache of the code of the style's functions, if there is one.
+    pub code_cache: Option<BstCodeCache>,
+    /// The digest of the style file, if there's a code cache.
+    pub bst_digest: Option<String>,
 
     pub impl_fn_num: usize,
     pub cite_xptr: usize,
 
+    /// When the style is run by a custom driver, the commands that run the
+    /// style's code, which are captured as the style is read rather than run.
+    pub captured_commands: Option<Vec<StyleCommand>>,
+
+    /// The reference context named by biblatex in the `.aux` file, if data
+    /// is being written for biblatex.
+    pub biblatex_refcontext: Option<String>,
+
     pub bib_seen: bool,
     pub bst_seen: bool,
     pub citation_seen: bool,
@@ -239,16 +1319,59 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
         engine: &'a mut CoreBridgeState<'cbs>,
         config: BibtexConfig,
     ) -> Bibtex<'a, 'cbs> {
+        let bbl_recorder = (config.record_bbl || config.reordering()).then(BblRecorder::default);
+        let source_map = config.source_map.then(SourceMapRecorder::default);
+        let profiler = config.profile_bst.then(Profiler::default);
+        let rng = Rng::new(config.rng_seed);
+
         Bibtex {
             engine,
             config,
             history: History::Spotless,
+            n_warnings: 0,
+            n_errors: 0,
             logs: Logs::default(),
             bst: None,
             bbl_file: None,
             bbl_line_num: 1,
+            bbl_lines: LineBreaker::default(),
+            bbl_recorder,
+            wrote_bbl: false,
+            source_map,
+            extra_outputs: Vec::new(),
+            cur_output: None,
+            field_warnings: Vec::new(),
+            recorded_entries: HashMap::new(),
+            entry_lints: Vec::new(),
+            stack_effects: HashMap::new(),
+            style_lints: Vec::new(),
+            duplicate_entries: Vec::new(),
+            database_keys: Vec::new(),
+            key_filter: KeyFilter::default(),
+            missing_entries: Vec::new(),
+            repeated_entries: Vec::new(),
+            citation_report: None,
+            preambles: Vec::new(),
+            diagnostics: DiagnosticRecorder::default(),
+            memo: cfg!(feature = "memoize-builtins").then(BuiltinMemo::default),
+            hash_stats: None,
+            profiler,
+            bst_profile: None,
+            step_limit_exceeded: None,
+            internal_error: None,
+            run_metrics: RunMetrics::default(),
+            breakpoint_hook: None,
+            custom_builtins: Vec::new(),
+            char_widths: CharWidths::default(),
+            id_source: None,
+            rng,
+            char_info: Rc::default(),
+            code_cache: None,
+            bst_digest: None,
             impl_fn_num: 0,
             cite_xptr: 0,
+            captured_commands: None,
+            biblatex_refcontext: None,
             bib_seen: false,
             bst_seen: false,
             citation_seen: false,
@@ -265,6 +1388,8 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
     }
 
     pub(crate) fn mark_warning(&mut self) {
+        self.diagnostics.finish(Severity::Warning);
+        self.n_warnings += 1;
         match self.history {
             History::WarningIssued(cur) => self.history = History::WarningIssued(cur + 1),
             History::Spotless => self.history = History::WarningIssued(1),
@@ -273,6 +1398,8 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
     }
 
     pub(crate) fn mark_error(&mut self) {
+        self.diagnostics.finish(Severity::Error);
+        self.n_errors += 1;
         match self.history {
             History::Spotless | History::WarningIssued(_) => self.history = History::ErrorIssued(1),
             History::ErrorIssued(cur) => self.history = History::ErrorIssued(cur + 1),
@@ -280,19 +1407,34 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
         }
     }
 
+    /// Get the time, from the [build date](BibtexEngine::build_date) if one
+    /// was set, and otherwise from the system clock.
+    pub(crate) fn now(&self) -> SystemTime {
+        self.config.build_date.unwrap_or_else(SystemTime::now)
+    }
+
+    /// Make an identifier for the run, from the [id
+    /// source](BibtexEngine::id_source) if one was set, and otherwise from
+    /// the pseudo-random numbers.
+    pub(crate) fn run_id(&mut self) -> String {
+        match &mut self.id_source {
+            Some(source) => (source.0)(),
+            None => format!("{:016x}{:016x}", self.rng.next_u64(), self.rng.next_u64()),
+        }
+    }
+
     pub(crate) fn mark_fatal(&mut self) {
+        self.diagnostics.finish(Severity::Fatal);
         self.history = History::FatalError;
     }
 
     pub(crate) fn write_logs<B: ?Sized + AsBytes>(&mut self, str: &B) {
-        let _ = self
-            .engine
-            .get_output(self.logs.file.unwrap())
-            .write_all(str.as_bytes());
-        let _ = self
-            .engine
-            .get_output(self.logs.stdout.unwrap())
-            .write_all(str.as_bytes());
+        self.diagnostics.write(str.as_bytes());
+        // The logs aren't open yet if the run stopped before it got going, as
+        // an internal error in hardened mode can.
+        for out in [self.logs.file, self.logs.stdout].into_iter().flatten() {
+            let _ = self.engine.get_output(out).write_all(str.as_bytes());
+        }
     }
 
     pub(crate) fn write_stdout<B: ?Sized + AsBytes>(&mut self, str: &B) {
@@ -326,6 +1468,108 @@ impl<'a, 'cbs> Bibtex<'a, 'cbs> {
             true
         }
     }
+
+    /// Route the output of `write$` to the extra output file with the given
+    /// name, opening it if needed. An empty name selects the `.bbl` file.
+    /// Returns false if the file couldn't be opened.
+    pub(crate) fn select_output(&mut self, name: &[u8]) -> bool {
+        if name.is_empty() {
+            self.cur_output = None;
+            return true;
+        }
+
+        if let Some(idx) = self.extra_outputs.iter().position(|o| o.name == name) {
+            self.cur_output = Some(idx);
+            return true;
+        }
+
+        let Ok(name_str) = std::str::from_utf8(name) else {
+            return false;
+        };
+
+        // Never clobber the main output
+        if self
+            .bbl_file
+            .is_some_and(|bbl| self.engine.get_output(bbl).name() == name_str)
+        {
+            return false;
+        }
+
+        let Some(file) = self.engine.output_open(name_str, false) else {
+            return false;
+        };
+
+        self.extra_outputs.push(ExtraOutput {
+            name: name.to_owned(),
+            file,
+            lines: LineBreaker::default(),
+        });
+        self.cur_output = Some(self.extra_outputs.len() - 1);
+        true
+    }
+
+    /// Write the `.bbl` file from the recorded output, with its entries
+    /// reordered. Nothing is written to it while the style runs in this case.
+    fn write_reordered_bbl(&mut self, bbl: OutputId) {
+        let Some(recorder) = &mut self.bbl_recorder else {
+            return;
+        };
+
+        recorder.reorder();
+
+        let out = self.engine.get_output(bbl);
+        let mut lines = LineBreaker::default();
+        let mut line_num = 1;
+
+        for (block, location) in recorder.blocks() {
+            if let Some(map) = &mut self.source_map {
+                map.set_entry(location.cloned());
+            }
+
+            let mut sink = |line: &[u8]| {
+                out.write_all(line).unwrap();
+                if let Some(map) = &mut self.source_map {
+                    map.line(line_num);
+                }
+                line_num += 1;
+            };
+
+            for chunk in &block.chunks {
+                match chunk {
+                    BblChunk::Text { text, .. } => lines.write(text, &mut sink),
+                    BblChunk::Newline => lines.newline(&mut sink),
+                }
+            }
+        }
+    }
+
+    pub(crate) fn close_outputs(&mut self) {
+        if let Some(bbl) = self.bbl_file {
+            if self.config.reordering() {
+                self.write_reordered_bbl(bbl);
+            }
+
+            if let Some(map) = self.source_map.take() {
+                let name = format!("{}.map", self.engine.get_output(bbl).name());
+
+                if let Some(out) = self.engine.output_open(&name, false) {
+                    let _ = self
+                        .engine
+                        .get_output(out)
+                        .write_all(map.render().as_bytes());
+                    self.engine.output_close(out);
+                }
+            }
+
+            self.engine.output_close(bbl);
+            self.wrote_bbl = true;
+        }
+
+        for output in self.extra_outputs.drain(..) {
+            self.engine.output_close(output.file);
+        }
+        self.cur_output = None;
+    }
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -372,7 +1616,11 @@ type BibNumber = usize;
 type FieldLoc = usize;
 type FnDefLoc = usize;
 
-pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> History {
+pub(crate) fn bibtex_main(
+    ctx: &mut Bibtex<'_, '_>,
+    aux_file_name: &CStr,
+    driver: Option<&mut StyleDriver<'_>>,
+) -> History {
     let mut buffers = GlobalBuffer::new();
     let mut pool = StringPool::new();
     let mut hash = HashData::new();
@@ -383,6 +1631,12 @@ pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> His
     let mut cites = CiteInfo::new();
     let mut other = OtherData::new();
 
+    let hints = ctx.config.capacity;
+    pool.reserve(hints.strings, hints.string_bytes);
+    hash.reserve(hints.strings);
+    cites.reserve(hints.entries);
+    other.check_field_overflow(hints.entries * hints.fields);
+
     let mut globals = GlobalItems {
         buffers: &mut buffers,
         pool: &mut pool,
@@ -395,18 +1649,27 @@ pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> His
         other: &mut other,
     };
 
-    let res = inner_bibtex_main(ctx, &mut globals, aux_file_name);
+    let res = inner_bibtex_main(ctx, &mut globals, aux_file_name, driver);
+    ctx.hash_stats = Some(globals.hash.stats());
+    ctx.bst_profile = ctx
+        .profiler
+        .take()
+        .map(|profiler| profiler.finish(globals.pool, globals.hash));
+    ctx.run_metrics.entries = globals.cites.num_cites();
+    ctx.run_metrics.pool_bytes = globals.pool.bytes();
+    ctx.preambles = globals
+        .bibs
+        .preamble()
+        .iter()
+        .map(|&s| String::from_utf8_lossy(globals.pool.get_str(s)).into_owned())
+        .collect();
     match res {
         Err(BibtexError::Recover) | Ok(History::Spotless) => {
             ctx.bst.take().map(|file| file.file.close(ctx));
-            if let Some(bbl) = ctx.bbl_file {
-                ctx.engine.output_close(bbl);
-            }
+            ctx.close_outputs();
         }
         Err(BibtexError::NoBst) => {
-            if let Some(bbl) = ctx.bbl_file {
-                ctx.engine.output_close(bbl);
-            }
+            ctx.close_outputs();
         }
         Err(BibtexError::Fatal) => (),
         Ok(hist) => return hist,
@@ -433,6 +1696,18 @@ pub(crate) fn bibtex_main(ctx: &mut Bibtex<'_, '_>, aux_file_name: &CStr) -> His
         }
     }
 
+    if let Some(stats) = ctx.memo.as_ref().map(BuiltinMemo::stats) {
+        ctx.write_log_file(&format!(
+            "Memoized purify$ and change.case$: {} hits, {} misses\n",
+            stats.hits, stats.misses
+        ));
+    }
+
+    if let Some(profile) = &ctx.bst_profile {
+        let report = format!("Profile of the style's functions:\n{profile}");
+        ctx.write_log_file(&report);
+    }
+
     bib_close_log(ctx);
     ctx.history
 }
@@ -475,6 +1750,15 @@ pub(crate) fn pre_def_certain_strings(
         HashExtra::AuxCommand(AuxCommand::Input),
     )?;
 
+    if ctx.config.biblatex_output {
+        hash.lookup_str_insert(
+            ctx,
+            pool,
+            b"\\abx@aux@refcontext",
+            HashExtra::AuxCommand(AuxCommand::RefContext),
+        )?;
+    }
+
     hash.lookup_str_insert(
         ctx,
         pool,
@@ -545,6 +1829,7 @@ pub(crate) fn pre_def_certain_strings(
         HashExtra::BibCommand(BibCommand::String),
     )?;
 
+    let output_files = ctx.config.output_files;
     let mut build_in = |pds: &[ASCIICode], builtin| {
         let res =
             hash.lookup_str_insert(ctx, pool, pds, HashExtra::BstFn(BstFn::Builtin(builtin)))?;
@@ -572,6 +1857,9 @@ pub(crate) fn pre_def_certain_strings(
     build_in(b"missing$", BstBuiltin::Missing)?;
     build_in(b"newline$", BstBuiltin::Newline)?;
     build_in(b"num.names$", BstBuiltin::NumNames)?;
+    if output_files {
+        build_in(b"output.file$", BstBuiltin::OutputFile)?;
+    }
     build_in(b"pop$", BstBuiltin::Pop)?;
     build_in(b"preamble$", BstBuiltin::Preamble)?;
     build_in(b"purify$", BstBuiltin::Purify)?;
@@ -589,25 +1877,25 @@ pub(crate) fn pre_def_certain_strings(
     build_in(b"width$", BstBuiltin::Width)?;
     build_in(b"write$", BstBuiltin::Write)?;
 
+    for index in 0..ctx.custom_builtins.len() {
+        let name = ctx.custom_builtins[index].name.clone();
+        hash.lookup_str_insert(
+            ctx,
+            pool,
+            name.as_bytes(),
+            HashExtra::BstFn(BstFn::Custom(index)),
+        )?;
+    }
+
     let res = hash.lookup_str_insert(ctx, pool, b"", HashExtra::Text)?;
     ctx.s_null = hash.text(res.loc);
     let res = hash.lookup_str_insert(ctx, pool, b"default.type", HashExtra::Text)?;
     ctx.s_default = hash.text(res.loc);
     ctx.b_default = skip_loc;
 
-    hash.lookup_str_insert(ctx, pool, b"i", HashExtra::ControlSeq(ControlSeq::LowerI))?;
-    hash.lookup_str_insert(ctx, pool, b"j", HashExtra::ControlSeq(ControlSeq::LowerJ))?;
-    hash.lookup_str_insert(ctx, pool, b"oe", HashExtra::ControlSeq(ControlSeq::LowerOE))?;
-    hash.lookup_str_insert(ctx, pool, b"OE", HashExtra::ControlSeq(ControlSeq::UpperOE))?;
-    hash.lookup_str_insert(ctx, pool, b"ae", HashExtra::ControlSeq(ControlSeq::LowerAE))?;
-    hash.lookup_str_insert(ctx, pool, b"AE", HashExtra::ControlSeq(ControlSeq::UpperAE))?;
-    hash.lookup_str_insert(ctx, pool, b"aa", HashExtra::ControlSeq(ControlSeq::LowerAA))?;
-    hash.lookup_str_insert(ctx, pool, b"AA", HashExtra::ControlSeq(ControlSeq::UpperAA))?;
-    hash.lookup_str_insert(ctx, pool, b"o", HashExtra::ControlSeq(ControlSeq::LowerO))?;
-    hash.lookup_str_insert(ctx, pool, b"O", HashExtra::ControlSeq(ControlSeq::UpperO))?;
-    hash.lookup_str_insert(ctx, pool, b"l", HashExtra::ControlSeq(ControlSeq::LowerL))?;
-    hash.lookup_str_insert(ctx, pool, b"L", HashExtra::ControlSeq(ControlSeq::UpperL))?;
-    hash.lookup_str_insert(ctx, pool, b"ss", HashExtra::ControlSeq(ControlSeq::LowerSS))?;
+    for (name, seq) in ControlSeq::ALL {
+        hash.lookup_str_insert(ctx, pool, name, HashExtra::ControlSeq(seq))?;
+    }
 
     let num_fields = other.num_fields();
     hash.lookup_str_insert(
@@ -617,8 +1905,15 @@ pub(crate) fn pre_def_certain_strings(
         HashExtra::BstFn(BstFn::Field(num_fields)),
     )?;
     other.set_crossref_num(num_fields);
-    other.set_num_fields(num_fields + 1);
-    other.set_pre_defined_fields(num_fields + 1);
+    hash.lookup_str_insert(
+        ctx,
+        pool,
+        b"xdata",
+        HashExtra::BstFn(BstFn::Field(num_fields + 1)),
+    )?;
+    other.set_xdata_num(num_fields + 1);
+    other.set_num_fields(num_fields + 2);
+    other.set_pre_defined_fields(num_fields + 2);
 
     let num_ent_strs = entries.num_ent_strs();
     hash.lookup_str_insert(
@@ -647,10 +1942,36 @@ pub(crate) fn pre_def_certain_strings(
     Ok(())
 }
 
+/// Read the `.csf` file named by the configuration into the character
+/// tables of the run.
+fn read_csf_file(ctx: &mut Bibtex<'_, '_>, name: &str) -> Result<(), BibtexError> {
+    let fname = CString::new(name).map_err(|_| BibtexError::Fatal)?;
+
+    let Ok(data) = PeekableInput::read_all(ctx, &fname, FileFormat::Bst) else {
+        ctx.write_logs(&format!("I couldn't open character set file `{name}`\n"));
+        ctx.mark_fatal();
+        return Err(BibtexError::Fatal);
+    };
+
+    match CharInfo::from_csf(&data) {
+        Ok(info) => {
+            ctx.char_info = Rc::new(info);
+            ctx.write_log_file(&format!("The character set file: {name}\n"));
+            Ok(())
+        }
+        Err(e) => {
+            ctx.write_logs(&format!("Bad character set file `{name}`, {e}\n"));
+            ctx.mark_fatal();
+            Err(BibtexError::Fatal)
+        }
+    }
+}
+
 pub(crate) fn inner_bibtex_main(
     ctx: &mut Bibtex<'_, '_>,
     globals: &mut GlobalItems<'_>,
     aux_file_name: &CStr,
+    driver: Option<&mut StyleDriver<'_>>,
 ) -> Result<History, BibtexError> {
     if !ctx.init_stdout() {
         return Ok(History::FatalError);
@@ -677,11 +1998,17 @@ pub(crate) fn inner_bibtex_main(
     if ctx.config.verbose {
         ctx.write_logs("The top-level auxiliary file: ");
         print_aux_name(ctx, globals.pool, globals.aux.top_file().name)?;
+        ctx.diagnostics.clear_text();
     } else {
         ctx.write_log_file("The top-level auxiliary file: ");
         log_pr_aux_name(ctx, globals.aux, globals.pool)?;
     }
 
+    if let Some(name) = ctx.config.csf_file.clone() {
+        read_csf_file(ctx, &name)?;
+    }
+
+    let aux_started = Instant::now();
     let last_aux = loop {
         globals.aux.top_file_mut().line += 1;
 
@@ -699,6 +2026,12 @@ pub(crate) fn inner_bibtex_main(
     };
 
     last_check_for_aux_errors(ctx, globals.pool, globals.cites, globals.bibs, last_aux)?;
+    ctx.run_metrics.add_time(Phase::Aux, aux_started.elapsed());
+
+    if ctx.config.biblatex_output {
+        write_biblatex_bbl(ctx, globals)?;
+        return Ok(History::Spotless);
+    }
 
     if ctx.bst.is_none() {
         return Err(BibtexError::NoBst);
@@ -708,6 +2041,10 @@ pub(crate) fn inner_bibtex_main(
         .buffers
         .set_offset(BufTy::Base, 2, globals.buffers.init(BufTy::Base));
 
+    if driver.is_some() {
+        ctx.captured_commands = Some(Vec::new());
+    }
+
     let mut exec = ExecCtx::new(ctx);
     loop {
         if !eat_bst_white_space(&mut exec, globals.buffers) {
@@ -716,6 +2053,10 @@ pub(crate) fn inner_bibtex_main(
         get_bst_command_and_process(&mut exec, globals)?;
     }
 
+    if let Some(driver) = driver {
+        drive_style(&mut exec, globals, driver)?;
+    }
+
     Ok(History::Spotless)
 }
 
@@ -786,3 +2127,17 @@ pub(crate) fn get_the_top_level_aux_file_name(
 /// Does our resulting executable link correctly?
 #[test]
 fn linkage() {}
+
+/// Warnings are still counted after an error, although the history stops
+/// tracking them.
+#[test]
+fn counts_outlast_history() {
+    test_utils::with_cbs(|cbs| {
+        let mut ctx = Bibtex::new(cbs, BibtexConfig::default());
+        ctx.mark_warning();
+        ctx.mark_error();
+        ctx.mark_warning();
+        assert_eq!(ctx.history, History::ErrorIssued(1));
+        assert_eq!((ctx.n_warnings, ctx.n_errors), (2, 1));
+    });
+}
@@ -0,0 +1,309 @@
+//! Limits on the lengths of field values and on the running time of styles.
+//!
+//! Classic BibTeX stops with a buffer overflow on a field value longer than
+//! its buffers, but Tectonic's grow to hold it, so a database with a
+//! multi-megabyte `abstract` copied from a PDF makes every step that handles
+//! the field slow, and its whole text ends up in the output. Limits let such
+//! values be cut short or left out as they're read, before the style sees
+//! them. Each value that's over its limit is reported as a warning, at the
+//! line of the `.bib` file where it ends.
+//!
+//! Similarly, a style whose `while$` loop never ends would run forever, which
+//! services that run BibTeX on styles they didn't write can't afford. A limit
+//! on the number of steps that the style takes turns that into a fatal error.
+
+use crate::{
+    bibs::BibData,
+    cite::CiteInfo,
+    exec::{bst_ln_num_print, ExecCtx},
+    hash::HashData,
+    log::{bib_warn_print, print_a_pool_str},
+    pool::StringPool,
+    Bibtex, BibtexError, HashPointer,
+};
+use std::{borrow::Cow, error, fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+
+/// The text that ends a truncated value: an ellipsis, written as a BibTeX
+/// special character so that `change.case$` and `purify$` leave it alone.
+const TRUNCATION_MARKER: &[u8] = b"{\\ldots}";
+
+/// What to do with a field value longer than its limit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LongFieldAction {
+    /// Cut the value short, at a character boundary outside of any control
+    /// sequence, close its open braces, and end it with an ellipsis.
+    Truncate,
+
+    /// Leave the field out of the entry, as if it weren't there.
+    Skip,
+
+    /// Keep the value whole, without a warning. This exempts a field from
+    /// the default limit.
+    Keep,
+}
+
+impl FromStr for LongFieldAction {
+    type Err = Error;
+
+    /// Parse an action from one of `truncate`, `skip`, or `keep`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "truncate" => Ok(LongFieldAction::Truncate),
+            "skip" => Ok(LongFieldAction::Skip),
+            "keep" => Ok(LongFieldAction::Keep),
+            _ => bail!("unrecognized action `{s}`; expected `truncate`, `skip`, or `keep`"),
+        }
+    }
+}
+
+impl fmt::Display for LongFieldAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            LongFieldAction::Truncate => "truncate",
+            LongFieldAction::Skip => "skip",
+            LongFieldAction::Keep => "keep",
+        })
+    }
+}
+
+/// A limit on the length of the values of a field.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FieldLimit {
+    /// The most bytes that a value may have.
+    pub max_len: usize,
+
+    /// What to do with longer values.
+    pub action: LongFieldAction,
+}
+
+impl FieldLimit {
+    /// A limit of *max_len* bytes, past which values are truncated.
+    pub fn new(max_len: usize) -> Self {
+        FieldLimit {
+            max_len,
+            action: LongFieldAction::Truncate,
+        }
+    }
+}
+
+impl FromStr for FieldLimit {
+    type Err = Error;
+
+    /// Parse a limit from a number of bytes, optionally followed by a colon
+    /// and an action, such as `10000` or `10000:skip`. The default action is
+    /// to truncate.
+    fn from_str(s: &str) -> Result<Self> {
+        let (len, action) = match s.split_once(':') {
+            Some((len, action)) => (len, action.parse()?),
+            None => (s, LongFieldAction::Truncate),
+        };
+
+        let Ok(max_len) = len.trim().parse() else {
+            bail!("expected a number of bytes, not `{len}`");
+        };
+
+        Ok(FieldLimit { max_len, action })
+    }
+}
+
+impl fmt::Display for FieldLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.action {
+            LongFieldAction::Truncate => write!(f, "{}", self.max_len),
+            action => write!(f, "{}:{action}", self.max_len),
+        }
+    }
+}
+
+/// Apply the configured limit to *value*, the value of the field at
+/// *field_loc* in the entry being read. Returns `None` if the field is to be
+/// skipped.
+pub(crate) fn limit_field<'a>(
+    ctx: &mut Bibtex<'_, '_>,
+    pool: &StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+    bibs: &BibData,
+    field_loc: HashPointer,
+    value: &'a [u8],
+) -> Result<Option<Cow<'a, [u8]>>, BibtexError> {
+    let field = pool.get_str(hash.text(field_loc));
+    let limit = ctx
+        .config
+        .field_limits
+        .iter()
+        .rev()
+        .find(|(name, _)| name.as_bytes() == field)
+        .map(|(_, limit)| *limit)
+        .or(ctx.config.default_field_limit);
+
+    let Some(limit) = limit.filter(|limit| value.len() > limit.max_len) else {
+        return Ok(Some(Cow::Borrowed(value)));
+    };
+
+    let result = match limit.action {
+        LongFieldAction::Keep => return Ok(Some(Cow::Borrowed(value))),
+        LongFieldAction::Truncate => {
+            ctx.write_logs("Warning--I'm truncating ");
+            Some(Cow::Owned(truncate(value, limit.max_len)))
+        }
+        LongFieldAction::Skip => {
+            ctx.write_logs("Warning--I'm ignoring ");
+            None
+        }
+    };
+
+    print_a_pool_str(ctx, cites.get_cite(cites.entry_ptr()), pool)?;
+    ctx.write_logs("'s \"");
+    print_a_pool_str(ctx, hash.text(field_loc), pool)?;
+    ctx.write_logs(&format!(
+        "\" field, which is {} bytes long, over the limit of {}\n",
+        value.len(),
+        limit.max_len
+    ));
+    bib_warn_print(ctx, pool, bibs)?;
+
+    Ok(result)
+}
+
+/// Cut *value* down to at most *max_len* bytes, not counting the braces that
+/// are added to balance it and the [`TRUNCATION_MARKER`].
+fn truncate(value: &[u8], max_len: usize) -> Vec<u8> {
+    let mut end = max_len.min(value.len());
+
+    // Don't split a UTF-8 character...
+    while end > 0 && end < value.len() && value[end] & 0xc0 == 0x80 {
+        end -= 1;
+    }
+
+    // ...or a control sequence, which would then name a different one.
+    let mut word = end;
+    while word > 0 && value[word - 1].is_ascii_alphabetic() {
+        word -= 1;
+    }
+    if word > 0 && value[word - 1] == b'\\' {
+        end = word - 1;
+    }
+
+    let kept = &value[..end];
+    let depth = kept.iter().fold(0usize, |depth, &c| match c {
+        b'{' => depth + 1,
+        b'}' => depth.saturating_sub(1),
+        _ => depth,
+    });
+
+    let mut out = Vec::with_capacity(end + depth + TRUNCATION_MARKER.len());
+    out.extend_from_slice(kept);
+    out.resize(end + depth, b'}');
+    out.extend_from_slice(TRUNCATION_MARKER);
+    out
+}
+
+/// The error returned when a style takes more steps than its
+/// [limit](crate::BibtexEngine::step_limit).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StepLimitExceeded {
+    /// The limit on the number of steps.
+    pub limit: u64,
+
+    /// The innermost function defined by the style that was running, if
+    /// any.
+    pub function: Option<String>,
+
+    /// The cite key of the entry being processed, if the style was
+    /// processing one.
+    pub cite_key: Option<String>,
+}
+
+impl fmt::Display for StepLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the bibtex style took more than {} steps", self.limit)?;
+        if let Some(function) = &self.function {
+            write!(f, " in function `{function}`")?;
+        }
+        if let Some(cite_key) = &self.cite_key {
+            write!(f, " for entry `{cite_key}`")?;
+        }
+        f.write_str(", and may be looping forever")
+    }
+}
+
+impl error::Error for StepLimitExceeded {}
+
+/// Stop the style with a fatal error, once it has used up its steps.
+#[cold]
+pub(crate) fn step_limit_exceeded(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    pool: &StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+) -> Result<(), BibtexError> {
+    let limit = ctx.config.step_limit.unwrap_or_default();
+    let lossy = |s| String::from_utf8_lossy(pool.get_str(s)).into_owned();
+    let err = StepLimitExceeded {
+        limit,
+        function: ctx.wiz_fns.last().map(|&loc| lossy(hash.text(loc))),
+        cite_key: ctx
+            .mess_with_entries
+            .then(|| lossy(cites.get_cite(cites.ptr()))),
+    };
+
+    ctx.write_logs(&format!("Sorry---{err}\nwhile executing-"));
+    bst_ln_num_print(ctx, pool)?;
+    ctx.mark_fatal();
+    ctx.step_limit_exceeded = Some(err);
+    Err(BibtexError::Fatal)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        for s in ["100", "100:skip", "0:keep"] {
+            assert_eq!(s.parse::<FieldLimit>().unwrap().to_string(), s);
+        }
+        assert_eq!(
+            "100:truncate".parse::<FieldLimit>().unwrap(),
+            FieldLimit::new(100)
+        );
+
+        assert!("".parse::<FieldLimit>().is_err());
+        assert!("big".parse::<FieldLimit>().is_err());
+        assert!("100:drop".parse::<FieldLimit>().is_err());
+    }
+
+    #[test]
+    fn test_truncate() {
+        let t = |value: &str, max_len| String::from_utf8(truncate(value.as_bytes(), max_len));
+
+        assert_eq!(t("abcdef", 3).unwrap(), "abc{\\ldots}");
+        assert_eq!(t("ab{cd{ef}}", 6).unwrap(), "ab{cd{}}{\\ldots}");
+        assert_eq!(t("ab {\\emph xyz}", 7).unwrap(), "ab {}{\\ldots}");
+        assert_eq!(t("ab\\&cd", 3).unwrap(), "ab{\\ldots}");
+        assert_eq!(t("naïve", 3).unwrap(), "na{\\ldots}");
+    }
+
+    #[test]
+    fn test_step_limit_message() {
+        let mut err = StepLimitExceeded {
+            limit: 1000,
+            function: None,
+            cite_key: None,
+        };
+        assert_eq!(
+            err.to_string(),
+            "the bibtex style took more than 1000 steps, and may be looping forever"
+        );
+
+        err.function = Some("format.names".to_owned());
+        err.cite_key = Some("knuth84".to_owned());
+        assert_eq!(
+            err.to_string(),
+            "the bibtex style took more than 1000 steps in function `format.names` for entry \
+             `knuth84`, and may be looping forever"
+        );
+    }
+}
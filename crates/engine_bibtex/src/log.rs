@@ -4,15 +4,19 @@ use crate::{
     buffer::{BufTy, GlobalBuffer},
     char_info::LexClass,
     cite::CiteInfo,
+    diag::DiagnosticRecorder,
     exec::{bst_ex_warn_print, bst_ln_num_print, ExecCtx},
     hash::{BstFn, HashData, HashExtra},
     other::OtherData,
     peekable::input_ln,
     pool::{StrNumber, StringPool},
     scan::{Scan, ScanRes},
-    ASCIICode, Bibtex, BibtexError, CiteNumber, FieldLoc, HashPointer,
+    structured::DatabaseFormat,
+    suggest::{self, MissingEntry},
+    ASCIICode, Bibtex, BibtexError, CiteNumber, FieldLoc, File, HashPointer,
 };
 use std::{ffi::CStr, io::Write, slice};
+use tectonic_bridge_core::FileFormat;
 use tectonic_io_base::OutputHandle;
 
 pub trait AsBytes {
@@ -43,8 +47,8 @@ pub(crate) fn bib_close_log(ctx: &mut Bibtex<'_, '_>) {
     }
 }
 
-pub fn print_overflow(ctx: &mut Bibtex<'_, '_>) {
-    ctx.write_logs("Sorry---you've exceeded BibTeX's ");
+pub fn print_overflow(ctx: &mut Bibtex<'_, '_>, what: &str) {
+    ctx.write_logs(&format!("Sorry---you've exceeded BibTeX's {what}\n"));
     ctx.mark_fatal();
 }
 
@@ -63,12 +67,31 @@ pub(crate) fn out_token(handle: &mut OutputHandle, buffers: &GlobalBuffer) {
 pub(crate) fn print_a_token(ctx: &mut Bibtex<'_, '_>, buffers: &GlobalBuffer) {
     out_token(ctx.engine.get_output(ctx.logs.stdout.unwrap()), buffers);
     out_token(ctx.engine.get_output(ctx.logs.file.unwrap()), buffers);
+    ctx.diagnostics.write(
+        &buffers.buffer(BufTy::Base)
+            [buffers.offset(BufTy::Base, 1)..buffers.offset(BufTy::Base, 2)],
+    );
+}
+
+/// Tell the diagnostic recorder that the problem being reported is on the
+/// current line of *file*, whose name is completed by *ext*, and that the
+/// description of that location follows.
+pub(crate) fn locate_line(
+    diagnostics: &mut DiagnosticRecorder,
+    pool: &StringPool,
+    file: &File,
+    ext: &str,
+) {
+    let name = format!("{}{ext}", String::from_utf8_lossy(pool.get_str(file.name)));
+    diagnostics.end_message();
+    diagnostics.locate(name, Some(file.line), file.file.line_span());
 }
 
 pub(crate) fn print_bad_input_line(ctx: &mut Bibtex<'_, '_>, buffers: &GlobalBuffer) {
     ctx.write_logs(" : ");
 
     let offset2 = buffers.offset(BufTy::Base, 2);
+    ctx.diagnostics.point_at(offset2);
 
     let slice = &buffers.buffer(BufTy::Base)[0..offset2];
 
@@ -103,8 +126,6 @@ pub(crate) fn print_bad_input_line(ctx: &mut Bibtex<'_, '_>, buffers: &GlobalBuf
     {
         ctx.write_logs("(Error may have been on previous line)\n");
     }
-
-    ctx.mark_error();
 }
 
 pub(crate) fn print_skipping_whatever_remains(ctx: &mut Bibtex<'_, '_>) {
@@ -176,11 +197,13 @@ pub(crate) fn aux_err_print(
     aux: &AuxData,
     pool: &StringPool,
 ) -> Result<(), BibtexError> {
+    locate_line(&mut ctx.diagnostics, pool, aux.top_file(), "");
     ctx.write_logs(&format!("---line {} of file ", aux.top_file().line));
     print_aux_name(ctx, pool, aux.top_file().name)?;
     print_bad_input_line(ctx, buffers);
     print_skipping_whatever_remains(ctx);
     ctx.write_logs("command\n");
+    ctx.mark_error();
     Ok(())
 }
 
@@ -223,12 +246,22 @@ pub(crate) fn aux_end2_err_print(
     pool: &StringPool,
     name: StrNumber,
 ) -> Result<(), BibtexError> {
+    let file = String::from_utf8_lossy(pool.get_str(name)).into_owned();
+    ctx.diagnostics.end_message();
+    ctx.diagnostics.locate(file, None, None);
     ctx.write_logs("---while reading file ");
     print_aux_name(ctx, pool, name)?;
     ctx.mark_error();
     Ok(())
 }
 
+/// Whether the name of a database file already has an extension, so that
+/// `.bib` shouldn't be added when it's printed.
+pub(crate) fn has_database_extension(ctx: &Bibtex<'_, '_>, name: &[u8]) -> bool {
+    name.ends_with(b".bib")
+        || (ctx.config.structured_databases && DatabaseFormat::of_name(name).is_some())
+}
+
 pub(crate) fn print_bib_name(
     ctx: &mut Bibtex<'_, '_>,
     pool: &StringPool,
@@ -238,7 +271,7 @@ pub(crate) fn print_bib_name(
     let res = pool
         .try_get_str(name)
         .ok_or(BibtexError::Fatal)
-        .map(|str| str.ends_with(b".bib"))?;
+        .map(|str| has_database_extension(ctx, str))?;
     if !res {
         ctx.write_logs(".bib");
     }
@@ -246,6 +279,47 @@ pub(crate) fn print_bib_name(
     Ok(())
 }
 
+/// After a `.bib` or `.bst` file couldn't be opened, explain on the terminal
+/// where it was looked for, and suggest files with similar names. This isn't
+/// written to the log file, which stays as classic BibTeX would write it.
+pub(crate) fn print_search_trace(ctx: &mut Bibtex<'_, '_>, name: &CStr, format: FileFormat) {
+    let trace = ctx
+        .engine
+        .trace_input_search(&name.to_string_lossy(), format);
+
+    if trace.layers.is_empty() {
+        return;
+    }
+
+    let quoted: Vec<String> = trace.names.iter().map(|n| format!("\"{n}\"")).collect();
+    ctx.write_stdout(&format!("I looked for {} in:\n", quoted.join(" and ")));
+
+    for layer in &trace.layers {
+        ctx.write_stdout(&format!("    {}\n", layer.description));
+    }
+
+    // The name with the extension is the one that the candidates resemble.
+    let ext = match format {
+        FileFormat::Bib => ".bib",
+        _ => ".bst",
+    };
+
+    let Some(wanted) = trace.names.iter().find(|n| n.ends_with(ext)) else {
+        return;
+    };
+
+    let candidates: Vec<String> = trace
+        .layers
+        .into_iter()
+        .flat_map(|layer| layer.names)
+        .filter(|n| n.ends_with(ext))
+        .collect();
+
+    if let Some(s) = suggest::did_you_mean(&suggest::suggestions(wanted, &candidates)) {
+        ctx.write_stdout(&format!("--{s}\n"));
+    }
+}
+
 pub(crate) fn log_pr_bib_name(
     ctx: &mut Bibtex<'_, '_>,
     pool: &StringPool,
@@ -254,7 +328,7 @@ pub(crate) fn log_pr_bib_name(
     out_pool_str(ctx, pool, name)?;
     let res = pool
         .try_get_str(name)
-        .map(|str| str.ends_with(b".bib"))
+        .map(|str| has_database_extension(ctx, str))
         .ok_or(BibtexError::Fatal)?;
     if !res {
         ctx.write_log_file(".bib");
@@ -333,6 +407,12 @@ pub(crate) fn bib_ln_num_print(
     pool: &StringPool,
     bibs: &BibData,
 ) -> Result<(), BibtexError> {
+    let ext = if has_database_extension(ctx, pool.get_str(bibs.top_file().name)) {
+        ""
+    } else {
+        ".bib"
+    };
+    locate_line(&mut ctx.diagnostics, pool, bibs.top_file(), ext);
     ctx.write_logs(&format!("--line {} of file ", bibs.top_file().line));
     print_bib_name(ctx, pool, bibs.top_file().name)
 }
@@ -353,6 +433,7 @@ pub(crate) fn bib_err_print(
     } else {
         ctx.write_logs("entry\n");
     }
+    ctx.mark_error();
     Ok(())
 }
 
@@ -455,6 +536,8 @@ pub(crate) fn bad_cross_reference_print(
     cite_ptr: CiteNumber,
     s: StrNumber,
 ) -> Result<(), BibtexError> {
+    ctx.diagnostics
+        .set_cite_key(pool.get_str(cites.get_cite(cite_ptr)));
     ctx.write_logs("--entry \"");
     print_a_pool_str(ctx, cites.get_cite(cite_ptr), pool)?;
     ctx.write_logs("\"\nrefers to entry \"");
@@ -468,24 +551,39 @@ pub(crate) fn print_missing_entry(
     pool: &StringPool,
     s: StrNumber,
 ) -> Result<(), BibtexError> {
+    ctx.diagnostics.set_cite_key(pool.get_str(s));
     ctx.write_logs("Warning--I didn't find a database entry for \"");
     print_a_pool_str(ctx, s, pool)?;
     ctx.write_logs("\"\n");
+
+    let key = String::from_utf8_lossy(pool.get_str(s)).into_owned();
+    let missing = MissingEntry::new(key, &ctx.database_keys);
+
+    if let Some(s) = missing.did_you_mean() {
+        ctx.write_logs(&format!("--{s}\n"));
+    }
+
+    ctx.missing_entries.push(missing);
     ctx.mark_warning();
     Ok(())
 }
 
+/// Print where a warning issued while executing the style arose. The caller
+/// counts the warning.
 pub(crate) fn bst_mild_ex_warn_print(
     ctx: &mut ExecCtx<'_, '_, '_>,
     pool: &StringPool,
     cites: &CiteInfo,
 ) -> Result<(), BibtexError> {
+    ctx.diagnostics.end_message();
     if ctx.mess_with_entries {
+        ctx.diagnostics
+            .set_cite_key(pool.get_str(cites.get_cite(cites.ptr())));
         ctx.write_logs(" for entry ");
         print_a_pool_str(ctx, cites.get_cite(cites.ptr()), pool)?;
     }
     ctx.write_logs("\nwhile executing");
-    bst_warn_print(ctx, pool)
+    bst_ln_num_print(ctx, pool)
 }
 
 pub(crate) fn bst_cant_mess_with_entries_print(
@@ -509,6 +607,7 @@ pub(crate) fn bst_2print_string_size_exceeded(
     ctx.write_logs("-string-size,");
     bst_mild_ex_warn_print(ctx, pool, cites)?;
     ctx.write_logs("*Please notify the bibstyle designer*\n");
+    ctx.mark_warning();
     Ok(())
 }
 
@@ -521,12 +620,14 @@ pub(crate) fn braces_unbalanced_complaint(
     ctx.write_logs("Warning--\"");
     print_a_pool_str(ctx, pop_lit_var, pool)?;
     ctx.write_logs("\" isn't a brace-balanced string");
-    bst_mild_ex_warn_print(ctx, pool, cites)
+    bst_mild_ex_warn_print(ctx, pool, cites)?;
+    ctx.mark_warning();
+    Ok(())
 }
 
 pub(crate) fn print_fn_class(ctx: &mut Bibtex<'_, '_>, hash: &HashData, fn_loc: HashPointer) {
     match hash.node(fn_loc).extra {
-        HashExtra::BstFn(BstFn::Builtin(_)) => ctx.write_logs("built-in"),
+        HashExtra::BstFn(BstFn::Builtin(_) | BstFn::Custom(_)) => ctx.write_logs("built-in"),
         HashExtra::BstFn(BstFn::Wizard(_)) => ctx.write_logs("wizard-defined"),
         HashExtra::Integer(_) => ctx.write_logs("integer-literal"),
         HashExtra::Text => ctx.write_logs("string-literal"),
@@ -547,6 +648,7 @@ pub(crate) fn bst_err_print_and_look_for_blank_line(
     ctx.write_logs("-");
     bst_ln_num_print(ctx, pool)?;
     print_bad_input_line(ctx, buffers);
+    ctx.mark_error();
     while buffers.init(BufTy::Base) != 0 {
         if !input_ln(ctx.engine, &mut ctx.bst.as_mut().unwrap().file, buffers) {
             return Err(BibtexError::Recover);
@@ -587,29 +689,119 @@ pub(crate) fn nonexistent_cross_reference_error(
     Ok(())
 }
 
-pub(crate) fn output_bbl_line(ctx: &mut Bibtex<'_, '_>, buffers: &mut GlobalBuffer) {
-    if buffers.init(BufTy::Out) != 0 {
-        let mut init = buffers.init(BufTy::Out);
-        while init > 0 {
-            if LexClass::of(buffers.at(BufTy::Out, init - 1)) == LexClass::Whitespace {
-                init -= 1;
-            } else {
-                break;
-            }
-        }
-        buffers.set_init(BufTy::Out, init);
-        if init == 0 {
-            return;
-        }
-        let slice = &buffers.buffer(BufTy::Out)[..init];
-        ctx.engine
-            .get_output(ctx.bbl_file.unwrap())
-            .write_all(slice)
-            .unwrap();
+/// Report a cycle of cross references from the entry *cite_ptr* through the
+/// entries of *chain* and back, where an empty chain means that the entry
+/// refers to itself.
+pub(crate) fn crossref_cycle_error(
+    ctx: &mut Bibtex<'_, '_>,
+    pool: &StringPool,
+    cites: &CiteInfo,
+    cite_ptr: CiteNumber,
+    chain: &[CiteNumber],
+) -> Result<(), BibtexError> {
+    ctx.write_logs("A cross reference cycle-");
+    let Some((&first, rest)) = chain.split_first() else {
+        ctx.diagnostics
+            .set_cite_key(pool.get_str(cites.get_cite(cite_ptr)));
+        ctx.write_logs("--entry \"");
+        print_a_pool_str(ctx, cites.get_cite(cite_ptr), pool)?;
+        ctx.write_logs("\"\nrefers to itself\n");
+        ctx.mark_error();
+        return Ok(());
+    };
+    bad_cross_reference_print(ctx, pool, cites, cite_ptr, cites.get_cite(first))?;
+    for &cite in rest {
+        ctx.write_logs(",\nwhich refers to entry \"");
+        print_a_pool_str(ctx, cites.get_cite(cite), pool)?;
+        ctx.write_logs("\"");
+    }
+    ctx.write_logs(",\nwhich refers back to entry \"");
+    print_a_pool_str(ctx, cites.get_cite(cite_ptr), pool)?;
+    ctx.write_logs("\"\n");
+    ctx.mark_error();
+    Ok(())
+}
+
+pub(crate) fn bad_xdata_reference_warning(
+    ctx: &mut Bibtex<'_, '_>,
+    entry: &[u8],
+    key: &[u8],
+    problem: &str,
+) {
+    ctx.diagnostics.set_cite_key(entry);
+    ctx.write_logs("Warning--A bad xdata reference--entry \"");
+    ctx.write_logs(entry);
+    ctx.write_logs("\"\nrefers to entry \"");
+    ctx.write_logs(key);
+    ctx.write_logs(&format!("\", {problem}\n"));
+    ctx.mark_warning();
+}
+
+/// Warn about a cycle of `xdata` references, given the keys of the entries
+/// in it, each of which refers to the next, and the last back to the first.
+pub(crate) fn xdata_cycle_warning(ctx: &mut Bibtex<'_, '_>, cycle: &[&[u8]]) {
+    ctx.diagnostics.set_cite_key(cycle[0]);
+    ctx.write_logs("Warning--An xdata cycle--entry \"");
+    ctx.write_logs(cycle[0]);
+    ctx.write_logs("\"");
+    for (i, key) in cycle[1..].iter().enumerate() {
+        ctx.write_logs(if i == 0 { "\n" } else { ",\nwhich " });
+        ctx.write_logs("refers to entry \"");
+        ctx.write_logs(*key);
+        ctx.write_logs("\"");
     }
-    writeln!(ctx.engine.get_output(ctx.bbl_file.unwrap())).unwrap();
-    ctx.bbl_line_num += 1;
-    buffers.set_init(BufTy::Out, 0);
+    if cycle.len() == 1 {
+        ctx.write_logs("\nrefers to itself\n");
+    } else {
+        ctx.write_logs(",\nwhich refers back to entry \"");
+        ctx.write_logs(cycle[0]);
+        ctx.write_logs("\"\n");
+    }
+    ctx.mark_warning();
+}
+
+pub(crate) fn cited_xdata_warning(ctx: &mut Bibtex<'_, '_>, key: &[u8]) {
+    ctx.diagnostics.set_cite_key(key);
+    ctx.write_logs("Warning--\"");
+    ctx.write_logs(key);
+    ctx.write_logs("\" is an @xdata entry, which can't be cited\n");
+    ctx.mark_warning();
+}
+
+pub(crate) fn output_bbl_line(ctx: &mut Bibtex<'_, '_>) {
+    if let Some(idx) = ctx.cur_output {
+        let output = &mut ctx.extra_outputs[idx];
+        let out = ctx.engine.get_output(output.file);
+        output.lines.newline(&mut |line| {
+            out.write_all(line).unwrap();
+        });
+        return;
+    }
+
+    if let Some(recorder) = &mut ctx.bbl_recorder {
+        recorder.newline();
+    }
+
+    if ctx.config.reordering() {
+        return;
+    }
+
+    let Bibtex {
+        engine,
+        bbl_file,
+        bbl_line_num,
+        bbl_lines,
+        source_map,
+        ..
+    } = ctx;
+    let out = engine.get_output(bbl_file.unwrap());
+    bbl_lines.newline(&mut |line| {
+        out.write_all(line).unwrap();
+        if let Some(map) = source_map {
+            map.line(*bbl_line_num);
+        }
+        *bbl_line_num += 1;
+    });
 }
 
 pub(crate) fn skip_token_print(
@@ -0,0 +1,102 @@
+//! Memoization of the `purify$` and `change.case$` builtins.
+//!
+//! Styles call these builtins on the same field values over and over: the
+//! classic author-year styles purify and case-change every name and title
+//! once while computing labels and again while computing sort keys. With the
+//! `memoize-builtins` Cargo feature, their results are remembered for the
+//! duration of a run, keyed by the number of the input string.
+//!
+//! That's only sound for permanent strings -- those that aren't scratch
+//! strings of the running function, such as field values -- since they're
+//! never removed from the pool and so their numbers always refer to the same
+//! text. Scratch strings are discarded and their numbers reused, so results
+//! for them aren't remembered. Nor are results whose computation issued warnings, so
+//! that the warnings are repeated each time, as in classic BibTeX.
+
+use crate::pool::StrNumber;
+use std::collections::HashMap;
+
+/// Counts of how well memoization worked out during a run.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MemoStats {
+    /// The number of calls answered from remembered results.
+    pub hits: u64,
+
+    /// The number of calls on permanent strings whose results had to be
+    /// computed.
+    pub misses: u64,
+}
+
+/// Remembered results of builtins.
+#[derive(Debug, Default)]
+pub(crate) struct BuiltinMemo {
+    purified: HashMap<StrNumber, Vec<u8>>,
+
+    /// Keyed by the lowercased conversion type and the input string.
+    case_changed: HashMap<(u8, StrNumber), Vec<u8>>,
+
+    stats: MemoStats,
+}
+
+impl BuiltinMemo {
+    /// Look up the remembered result of `purify$` on *s*.
+    pub fn purified(&mut self, s: StrNumber) -> Option<&[u8]> {
+        Self::count(&mut self.stats, self.purified.get(&s))
+    }
+
+    pub fn save_purified(&mut self, s: StrNumber, result: &[u8]) {
+        self.purified.insert(s, result.to_vec());
+    }
+
+    /// Look up the remembered result of `change.case$` on *s* with the
+    /// conversion type *conv*.
+    pub fn case_changed(&mut self, conv: u8, s: StrNumber) -> Option<&[u8]> {
+        let key = (conv.to_ascii_lowercase(), s);
+        Self::count(&mut self.stats, self.case_changed.get(&key))
+    }
+
+    pub fn save_case_changed(&mut self, conv: u8, s: StrNumber, result: &[u8]) {
+        self.case_changed
+            .insert((conv.to_ascii_lowercase(), s), result.to_vec());
+    }
+
+    pub fn stats(&self) -> MemoStats {
+        self.stats
+    }
+
+    fn count<'a>(stats: &mut MemoStats, found: Option<&'a Vec<u8>>) -> Option<&'a [u8]> {
+        match found {
+            Some(r) => {
+                stats.hits += 1;
+                Some(r)
+            }
+
+            None => {
+                stats.misses += 1;
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn remembering() {
+        let mut memo = BuiltinMemo::default();
+        let s = StrNumber::from_raw_dangerous(7);
+
+        assert_eq!(memo.purified(s), None);
+        memo.save_purified(s, b"Knuth");
+        assert_eq!(memo.purified(s), Some(&b"Knuth"[..]));
+
+        assert_eq!(memo.case_changed(b't', s), None);
+        memo.save_case_changed(b'T', s, b"The art");
+        assert_eq!(memo.case_changed(b't', s), Some(&b"The art"[..]));
+        assert_eq!(memo.case_changed(b'u', s), None);
+
+        assert_eq!(memo.stats(), MemoStats { hits: 2, misses: 3 });
+    }
+}
@@ -0,0 +1,244 @@
+//! Metrics for monitoring the engine in services.
+//!
+//! Services that compile documents for their users need to see how much work
+//! the engine does for them. Embedding code can give the engine a
+//! [`BibtexMetrics`] sink, which is fed at the end of each run.
+
+use crate::{
+    diag::{Diagnostic, Severity},
+    BibtexHistory,
+};
+use std::{
+    fmt::{Debug, Error as FmtError, Formatter},
+    time::Duration,
+};
+
+/// The labels of a metric, as pairs of names and values.
+pub type MetricLabels<'a> = &'a [(&'static str, &'static str)];
+
+/// Where the engine reports its metrics.
+///
+/// At the end of each run, the engine reports counters and histogram
+/// observations named in the style of Prometheus, so that the sink only has
+/// to forward them to whatever metrics library the application uses. They
+/// are:
+///
+/// - `bibtex_runs_total`, a counter labelled with the `outcome` of the run:
+///   `spotless`, `warnings`, `errors`, or `fatal`.
+/// - `bibtex_run_seconds`, a histogram of the time that runs take.
+/// - `bibtex_phase_seconds`, a histogram labelled with the `phase` of a run:
+///   `aux` for reading the `.aux` files, `style` for reading the commands of
+///   the style that define things, `read` for its `READ` command, `execute`
+///   for its `EXECUTE`, `ITERATE`, and `REVERSE` commands, and `sort` for
+///   `SORT`. Each run observes the total time of each phase that it went
+///   through, including the commands run by a [`StyleSession`] driver.
+/// - `bibtex_entries_total`, a counter of the entries processed, which are
+///   those cited and those added for their cross-references.
+/// - `bibtex_pool_bytes`, a histogram of the bytes of text in the engine's
+///   string pool at the end of runs, which is most of its memory.
+/// - `bibtex_diagnostics_total`, a counter of the problems reported, labelled
+///   with their `severity`, which is `warning`, `error`, or `fatal`, and their
+///   `source`: `aux`, `style`, `database`, or `other` for those not found in
+///   a file.
+///
+/// Counters are only reported with values greater than zero.
+///
+/// [`StyleSession`]: crate::StyleSession
+pub trait BibtexMetrics: Send {
+    /// Add *value* to the counter *name* with the given labels.
+    fn counter(&mut self, name: &'static str, labels: MetricLabels<'_>, value: u64);
+
+    /// Record an observation of *value* in the histogram *name* with the
+    /// given labels.
+    fn histogram(&mut self, name: &'static str, labels: MetricLabels<'_>, value: f64);
+}
+
+/// The sink given to the engine.
+pub(crate) struct MetricsSink(pub Box<dyn BibtexMetrics>);
+
+impl Debug for MetricsSink {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.write_str("MetricsSink")
+    }
+}
+
+/// A phase of a run, whose time is measured.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum Phase {
+    Aux,
+    Style,
+    Read,
+    Execute,
+    Sort,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Aux,
+        Phase::Style,
+        Phase::Read,
+        Phase::Execute,
+        Phase::Sort,
+    ];
+
+    fn label(self) -> &'static str {
+        match self {
+            Phase::Aux => "aux",
+            Phase::Style => "style",
+            Phase::Read => "read",
+            Phase::Execute => "execute",
+            Phase::Sort => "sort",
+        }
+    }
+}
+
+/// The figures of a run that its metrics are made from, other than its
+/// diagnostics.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct RunMetrics {
+    /// The time spent in each phase, or `None` for the phases that the run
+    /// didn't go through.
+    phase_times: [Option<Duration>; 5],
+    /// The number of entries processed.
+    pub entries: usize,
+    /// The bytes of text in the string pool at the end of the run.
+    pub pool_bytes: usize,
+}
+
+impl RunMetrics {
+    /// Add to the time spent in a phase.
+    pub fn add_time(&mut self, phase: Phase, time: Duration) {
+        *self.phase_times[phase as usize].get_or_insert(Duration::ZERO) += time;
+    }
+
+    /// Report the metrics of a run that took *run_time* and ended with
+    /// *history*, or `None` if it stopped with a fatal error.
+    pub fn report(
+        &self,
+        sink: &mut dyn BibtexMetrics,
+        history: Option<BibtexHistory>,
+        run_time: Duration,
+        diagnostics: &[Diagnostic],
+    ) {
+        let outcome = match history {
+            Some(BibtexHistory::Spotless) => "spotless",
+            Some(BibtexHistory::Warnings) => "warnings",
+            Some(BibtexHistory::Errors) => "errors",
+            None => "fatal",
+        };
+        sink.counter("bibtex_runs_total", &[("outcome", outcome)], 1);
+        sink.histogram("bibtex_run_seconds", &[], run_time.as_secs_f64());
+
+        for phase in Phase::ALL {
+            if let Some(time) = self.phase_times[phase as usize] {
+                sink.histogram(
+                    "bibtex_phase_seconds",
+                    &[("phase", phase.label())],
+                    time.as_secs_f64(),
+                );
+            }
+        }
+
+        if self.entries > 0 {
+            sink.counter("bibtex_entries_total", &[], self.entries as u64);
+        }
+        sink.histogram("bibtex_pool_bytes", &[], self.pool_bytes as f64);
+
+        let mut counts: Vec<((&str, &str), u64)> = Vec::new();
+        for diag in diagnostics {
+            let key = (severity_label(diag.severity), source_label(diag));
+            match counts.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((key, 1)),
+            }
+        }
+        for ((severity, source), n) in counts {
+            sink.counter(
+                "bibtex_diagnostics_total",
+                &[("severity", severity), ("source", source)],
+                n,
+            );
+        }
+    }
+}
+
+fn severity_label(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Warning => "warning",
+        Severity::Error => "error",
+        Severity::Fatal => "fatal",
+    }
+}
+
+/// The kind of file that a diagnostic was found in.
+fn source_label(diag: &Diagnostic) -> &'static str {
+    match diag.file.as_deref() {
+        Some(file) if file.ends_with(".aux") => "aux",
+        Some(file) if file.ends_with(".bst") => "style",
+        Some(_) => "database",
+        None => "other",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl BibtexMetrics for Recorder {
+        fn counter(&mut self, name: &'static str, labels: MetricLabels<'_>, value: u64) {
+            self.0.push(format!("{name}{labels:?} += {value}"));
+        }
+
+        fn histogram(&mut self, name: &'static str, labels: MetricLabels<'_>, value: f64) {
+            self.0.push(format!("{name}{labels:?} <- {value}"));
+        }
+    }
+
+    #[test]
+    fn test_report() {
+        let mut metrics = RunMetrics {
+            entries: 3,
+            pool_bytes: 100,
+            ..RunMetrics::default()
+        };
+        metrics.add_time(Phase::Execute, Duration::from_millis(250));
+        metrics.add_time(Phase::Execute, Duration::from_millis(250));
+
+        let diag = |severity, file: Option<&str>| Diagnostic {
+            severity,
+            message: String::new(),
+            file: file.map(str::to_owned),
+            line: None,
+            span: None,
+            cite_key: None,
+        };
+        let diagnostics = [
+            diag(Severity::Warning, Some("refs.bib")),
+            diag(Severity::Error, Some("plain.bst")),
+            diag(Severity::Warning, Some("refs.bib")),
+        ];
+
+        let mut sink = Recorder::default();
+        metrics.report(
+            &mut sink,
+            Some(BibtexHistory::Errors),
+            Duration::from_secs(1),
+            &diagnostics,
+        );
+        assert_eq!(
+            sink.0,
+            [
+                r#"bibtex_runs_total[("outcome", "errors")] += 1"#,
+                "bibtex_run_seconds[] <- 1",
+                r#"bibtex_phase_seconds[("phase", "execute")] <- 0.5"#,
+                "bibtex_entries_total[] += 3",
+                "bibtex_pool_bytes[] <- 100",
+                r#"bibtex_diagnostics_total[("severity", "warning"), ("source", "database")] += 2"#,
+                r#"bibtex_diagnostics_total[("severity", "error"), ("source", "style")] += 1"#,
+            ]
+        );
+    }
+}
@@ -0,0 +1,802 @@
+//! BibTeX's parsing and formatting of names.
+//!
+//! The `format.name$` builtin splits a name into its First, von, Last, and Jr
+//! parts, and formats them according to a specification such as
+//! `{ff~}{vv~}{ll}{, jj}`, in which each group of braces holding a part's
+//! letter is output if that part isn't empty. A doubled letter stands for the
+//! whole words of the part, and a single one for their initials. The rules
+//! for splitting are those of BibTeX: see Oren Patashnik's *Designing BibTeX
+//! Styles*, and the notes by Nicolas Markey in *Tame the BeaST*.
+//!
+//! The algorithm works on bytes, as BibTeX does. Other crates can use it with
+//! [`format_name()`], without running the engine.
+
+use crate::{
+    char_info::LexClass,
+    exec::{skip_brace_level_greater_than_one, ControlSeq},
+};
+use tectonic_errors::prelude::*;
+
+/// Format a name as the `format.name$` builtin does.
+///
+/// *name* is split into its First, von, Last, and Jr parts as BibTeX would,
+/// and then formatted according to *spec*. If *name* is a list of names
+/// joined by `and`, as in an `author` field, only the first one is formatted.
+/// For example, formatting `Ludwig van Beethoven` with `{vv~}{ll}{, f.}`
+/// gives `van Beethoven, L.`.
+///
+/// The problems that the builtin would warn about, such as a name that isn't
+/// brace-balanced, or a letter other than `f`, `v`, `l`, or `j` in the
+/// specification, are returned as errors.
+///
+/// As in BibTeX, the initial of a word is its first letter, or its first group
+/// if it starts with a special character such as `{\"o}`. So the initial of a
+/// word starting with a non-ASCII letter written in UTF-8 is only the first
+/// byte of the letter, which is replaced with U+FFFD.
+pub fn format_name(name: &str, spec: &str) -> Result<String> {
+    let mut problems = Vec::new();
+    let out = format_nth_name(name.as_bytes(), 1, spec.as_bytes(), &mut problems);
+
+    if let Some(problem) = problems.first() {
+        bail!(
+            "{}",
+            match problem {
+                NameProblem::MissingName => format!("there is no name in \"{name}\""),
+                NameProblem::CommaAtEnd => format!("the name \"{name}\" has a comma at the end"),
+                NameProblem::TooManyCommas => format!("the name \"{name}\" has too many commas"),
+                NameProblem::UnbalancedName | NameProblem::UnbalancedNames => {
+                    format!("the name \"{name}\" isn't brace-balanced")
+                }
+                NameProblem::IllegalLetter => {
+                    format!("the format \"{spec}\" has an illegal brace-level-1 letter")
+                }
+                NameProblem::UnbalancedSpec => {
+                    format!("the format \"{spec}\" isn't brace-balanced")
+                }
+            }
+        );
+    }
+
+    Ok(String::from_utf8_lossy(&out).into_owned())
+}
+
+/// A problem found while formatting a name, which `format.name$` reports as a
+/// warning in the style.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum NameProblem {
+    /// The list doesn't have as many names as asked for.
+    MissingName,
+    /// The name ends with a comma.
+    CommaAtEnd,
+    /// The name has more than two commas.
+    TooManyCommas,
+    /// The name has a closing brace without an opening one.
+    UnbalancedName,
+    /// The list of names isn't brace-balanced.
+    UnbalancedNames,
+    /// The specification has a letter other than `f`, `v`, `l`, or `j` in
+    /// one of its groups, or more than one of them.
+    IllegalLetter,
+    /// The specification isn't brace-balanced.
+    UnbalancedSpec,
+}
+
+/// The number of names in a list of names joined by `and`, as counted by the
+/// `num.names$` builtin.
+pub(crate) fn num_names(names: &[u8], problems: &mut Vec<NameProblem>) -> i64 {
+    let mut pos = 0;
+    let mut num_names = 0;
+
+    while pos < names.len() {
+        let mut brace_level = 0;
+        scan_for_and(names, &mut pos, &mut brace_level, problems);
+        num_names += 1;
+    }
+
+    num_names
+}
+
+/// Format the *n*th name, counting from 1, of a list of names joined by
+/// `and`, as the `format.name$` builtin does. Problems are added to
+/// *problems* in the order in which BibTeX reports them.
+pub(crate) fn format_nth_name(
+    names: &[u8],
+    n: i64,
+    spec: &[u8],
+    problems: &mut Vec<NameProblem>,
+) -> Vec<u8> {
+    // BibTeX keeps track of the brace level from finding the name, through
+    // splitting it up, to formatting it, so we do too.
+    let mut brace_level = 0;
+    let mut start = 0;
+    let mut pos = 0;
+    let mut num_names = 0;
+
+    while num_names < n && pos < names.len() {
+        num_names += 1;
+        start = pos;
+        scan_for_and(names, &mut pos, &mut brace_level, problems);
+    }
+
+    // If there's another name, back up to before the ` and` that ended this
+    // one.
+    let mut end = if pos < names.len() {
+        pos.saturating_sub(4)
+    } else {
+        pos
+    };
+
+    if num_names < n {
+        problems.push(NameProblem::MissingName);
+    }
+
+    while end > start {
+        match LexClass::of(names[end - 1]) {
+            LexClass::Whitespace | LexClass::Sep => end -= 1,
+            _ if names[end - 1] == b',' => {
+                problems.push(NameProblem::CommaAtEnd);
+                end -= 1;
+            }
+            _ => break,
+        }
+    }
+
+    let name = split_name(&names[start..end], &mut brace_level, problems);
+    format_split_name(&name, spec, &mut brace_level, problems)
+}
+
+/// Advance *pos* past the next name of a list, and the `and` that ends it if
+/// there is one.
+fn scan_for_and(
+    names: &[u8],
+    pos: &mut usize,
+    brace_level: &mut i32,
+    problems: &mut Vec<NameProblem>,
+) {
+    let mut preceding_white = false;
+
+    while *pos < names.len() {
+        match names[*pos] {
+            b'A' | b'a' => {
+                *pos += 1;
+
+                if preceding_white
+                    && *pos + 3 <= names.len()
+                    && names[*pos].eq_ignore_ascii_case(&b'n')
+                    && names[*pos + 1].eq_ignore_ascii_case(&b'd')
+                    && LexClass::of(names[*pos + 2]) == LexClass::Whitespace
+                {
+                    *pos += 2;
+                    break;
+                }
+
+                preceding_white = false;
+            }
+            b'{' => {
+                *brace_level += 1;
+                *pos += 1;
+
+                while *brace_level > 0 && *pos < names.len() {
+                    match names[*pos] {
+                        b'{' => *brace_level += 1,
+                        b'}' => *brace_level -= 1,
+                        _ => (),
+                    }
+                    *pos += 1;
+                }
+
+                preceding_white = false;
+            }
+            b'}' => {
+                if *brace_level == 0 {
+                    problems.push(NameProblem::UnbalancedNames);
+                } else {
+                    *brace_level -= 1;
+                }
+
+                *pos += 1;
+                preceding_white = false;
+            }
+            c => {
+                preceding_white = LexClass::of(c) == LexClass::Whitespace;
+                *pos += 1;
+            }
+        }
+    }
+
+    if *brace_level > 0 {
+        problems.push(NameProblem::UnbalancedNames);
+    }
+}
+
+/// A name split into tokens, and those into its parts.
+struct SplitName {
+    /// The text of the tokens, without the separators between them.
+    text: Vec<u8>,
+    /// Where each token starts in `text`, followed by the end of the last.
+    starts: Vec<usize>,
+    /// The separator before each token after the first.
+    seps: Vec<u8>,
+    /// The tokens of the First, von, Last, and Jr parts. The von part ends
+    /// where the Last part starts, and the Last part where the Jr part
+    /// starts.
+    first: (usize, usize),
+    von: (usize, usize),
+    last_end: usize,
+    jr_end: usize,
+}
+
+impl SplitName {
+    fn token(&self, token: usize) -> &[u8] {
+        &self.text[self.starts[token]..self.starts[token + 1]]
+    }
+}
+
+/// Split a name into tokens at whitespace, hyphens, ties, and commas outside
+/// of braces, and then into its parts.
+fn split_name(name: &[u8], brace_level: &mut i32, problems: &mut Vec<NameProblem>) -> SplitName {
+    enum Commas {
+        None,
+        One(usize),
+        Two(usize, usize),
+    }
+
+    let mut text = Vec::with_capacity(name.len());
+    let mut starts = Vec::new();
+    let mut seps = vec![0; name.len() + 1];
+    let mut commas = Commas::None;
+    let mut token_starting = true;
+    let mut pos = 0;
+
+    while pos < name.len() {
+        match name[pos] {
+            b',' => {
+                match commas {
+                    Commas::None => {
+                        commas = Commas::One(starts.len());
+                        seps[starts.len()] = b',';
+                    }
+                    Commas::One(first) => {
+                        commas = Commas::Two(first, starts.len());
+                        seps[starts.len()] = b',';
+                    }
+                    Commas::Two(_, _) => problems.push(NameProblem::TooManyCommas),
+                }
+
+                pos += 1;
+                token_starting = true;
+            }
+            b'{' => {
+                *brace_level += 1;
+
+                if token_starting {
+                    starts.push(text.len());
+                }
+
+                text.push(name[pos]);
+                pos += 1;
+
+                while *brace_level > 0 && pos < name.len() {
+                    match name[pos] {
+                        b'{' => *brace_level += 1,
+                        b'}' => *brace_level -= 1,
+                        _ => (),
+                    }
+                    text.push(name[pos]);
+                    pos += 1;
+                }
+
+                token_starting = false;
+            }
+            b'}' => {
+                if token_starting {
+                    starts.push(text.len());
+                }
+
+                problems.push(NameProblem::UnbalancedName);
+                pos += 1;
+                token_starting = false;
+            }
+            c => match LexClass::of(c) {
+                LexClass::Whitespace => {
+                    if !token_starting {
+                        seps[starts.len()] = b' ';
+                    }
+                    pos += 1;
+                    token_starting = true;
+                }
+                LexClass::Sep => {
+                    if !token_starting {
+                        seps[starts.len()] = c;
+                    }
+                    pos += 1;
+                    token_starting = true;
+                }
+                _ => {
+                    if token_starting {
+                        starts.push(text.len());
+                    }
+                    text.push(c);
+                    pos += 1;
+                    token_starting = false;
+                }
+            },
+        }
+    }
+
+    let num_tokens = starts.len();
+    starts.push(text.len());
+
+    let mut name = SplitName {
+        text,
+        starts,
+        seps,
+        first: (0, 0),
+        von: (0, 0),
+        last_end: 0,
+        jr_end: 0,
+    };
+
+    match commas {
+        // First von Last: the von part starts with the first lowercase token
+        // before the last, and ends with the last lowercase one before the
+        // last.
+        Commas::None => {
+            name.last_end = num_tokens;
+            name.jr_end = num_tokens;
+
+            let mut von_start = 0;
+            let mut von_end = None;
+
+            while von_start + 1 < num_tokens {
+                if is_von_token(name.token(von_start)) {
+                    von_end = Some(von_end_from(&name, von_start, num_tokens));
+                    break;
+                }
+                von_start += 1;
+            }
+
+            // Without a von part, the Last part is the last token and any
+            // tokens joined to it by hyphens.
+            let von_end = von_end.unwrap_or_else(|| {
+                while von_start > 0 {
+                    let sep = name.seps[von_start];
+
+                    if LexClass::of(sep) != LexClass::Sep || sep == b'~' {
+                        break;
+                    }
+                    von_start -= 1;
+                }
+                von_start
+            });
+
+            name.first = (0, von_start);
+            name.von = (von_start, von_end);
+        }
+        // von Last, First
+        Commas::One(comma) => {
+            name.last_end = comma;
+            name.jr_end = comma;
+            name.first = (comma, num_tokens);
+            name.von = (0, von_end_from(&name, 0, comma));
+        }
+        // von Last, Jr, First
+        Commas::Two(comma1, comma2) => {
+            name.last_end = comma1;
+            name.jr_end = comma2;
+            name.first = (comma2, num_tokens);
+            name.von = (0, von_end_from(&name, 0, comma1));
+        }
+    }
+
+    name
+}
+
+/// Find the end of a von part that starts at *von_start*, given the end of
+/// the Last part that follows it, which has at least one token.
+fn von_end_from(name: &SplitName, von_start: usize, last_end: usize) -> usize {
+    let mut von_end = last_end.saturating_sub(1);
+
+    while von_end > von_start {
+        if is_von_token(name.token(von_end - 1)) {
+            break;
+        }
+        von_end -= 1;
+    }
+
+    von_end
+}
+
+/// Whether a token belongs in the von part, which it does if its first
+/// letter at brace level 0 is lowercase. Special characters, such as `{\"o}`
+/// or `{\oe}`, count as letters at brace level 0.
+fn is_von_token(token: &[u8]) -> bool {
+    let mut pos = 0;
+
+    while pos < token.len() {
+        match token[pos] {
+            b'A'..=b'Z' => return false,
+            b'a'..=b'z' => return true,
+            b'{' => {
+                let mut brace_level = 1;
+                pos += 1;
+
+                if pos + 2 < token.len() && token[pos] == b'\\' {
+                    pos += 1;
+                    let name_start = pos;
+
+                    while pos < token.len() && LexClass::of(token[pos]) == LexClass::Alpha {
+                        pos += 1;
+                    }
+
+                    if let Some(lower) = special_char_is_lower(&token[name_start..pos]) {
+                        return lower;
+                    }
+
+                    while pos < token.len() && brace_level > 0 {
+                        match token[pos] {
+                            b'A'..=b'Z' => return false,
+                            b'a'..=b'z' => return true,
+                            b'}' => brace_level -= 1,
+                            b'{' => brace_level += 1,
+                            _ => (),
+                        }
+                        pos += 1;
+                    }
+
+                    return false;
+                }
+
+                while brace_level > 0 && pos < token.len() {
+                    match token[pos] {
+                        b'{' => brace_level += 1,
+                        b'}' => brace_level -= 1,
+                        _ => (),
+                    }
+                    pos += 1;
+                }
+            }
+            _ => pos += 1,
+        }
+    }
+
+    false
+}
+
+/// Whether the special character written as a control sequence, such as
+/// `\oe`, is lowercase, if it's one of the letters that BibTeX knows.
+fn special_char_is_lower(name: &[u8]) -> Option<bool> {
+    ControlSeq::of_name(name).map(ControlSeq::is_lower)
+}
+
+/// Format a split name according to a specification.
+fn format_split_name(
+    name: &SplitName,
+    spec: &[u8],
+    brace_level: &mut i32,
+    problems: &mut Vec<NameProblem>,
+) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut spec_level = 0;
+    let mut idx = 0;
+
+    while idx < spec.len() {
+        match spec[idx] {
+            b'{' => {
+                spec_level += 1;
+                idx += 1;
+                let group_start = idx;
+
+                let mut alpha_found = false;
+                let mut double_letter = false;
+                let mut end_of_group = false;
+                let mut to_be_written = true;
+                let mut tokens = (0, 0);
+
+                // Find the part that the group is for.
+                while !end_of_group && idx < spec.len() {
+                    if LexClass::of(spec[idx]) == LexClass::Alpha {
+                        idx += 1;
+
+                        if alpha_found {
+                            problems.push(NameProblem::IllegalLetter);
+                            to_be_written = false;
+                        } else {
+                            let letter = spec[idx - 1];
+
+                            tokens = match letter.to_ascii_lowercase() {
+                                b'f' => name.first,
+                                b'v' => name.von,
+                                b'l' => (name.von.1, name.last_end),
+                                b'j' => (name.last_end, name.jr_end),
+                                _ => {
+                                    problems.push(NameProblem::IllegalLetter);
+                                    to_be_written = false;
+                                    break;
+                                }
+                            };
+
+                            if tokens.0 == tokens.1 {
+                                to_be_written = false;
+                            }
+
+                            if spec
+                                .get(idx)
+                                .is_some_and(|c| c.eq_ignore_ascii_case(&letter))
+                            {
+                                double_letter = true;
+                                idx += 1;
+                            }
+                        }
+
+                        alpha_found = true;
+                    } else if spec[idx] == b'}' {
+                        spec_level -= 1;
+                        idx += 1;
+                        end_of_group = true;
+                    } else if spec[idx] == b'{' {
+                        spec_level += 1;
+                        idx += skip_brace_level_greater_than_one(&spec[idx + 1..], &mut spec_level);
+                        idx += 1;
+                    } else {
+                        idx += 1;
+                    }
+                }
+
+                if end_of_group && to_be_written {
+                    idx = group_start;
+                    format_group(
+                        name,
+                        spec,
+                        &mut idx,
+                        tokens,
+                        double_letter,
+                        brace_level,
+                        &mut out,
+                    );
+                }
+            }
+            b'}' => {
+                problems.push(NameProblem::UnbalancedSpec);
+                idx += 1;
+            }
+            c => {
+                out.push(c);
+                idx += 1;
+            }
+        }
+    }
+
+    if spec_level > 0 {
+        problems.push(NameProblem::UnbalancedSpec);
+    }
+
+    out
+}
+
+/// Output a group of a specification, starting just after its opening brace,
+/// for the given tokens of the name, and advance *idx* past the group.
+fn format_group(
+    name: &SplitName,
+    spec: &[u8],
+    idx: &mut usize,
+    (mut cur_token, last_token): (usize, usize),
+    double_letter: bool,
+    brace_level: &mut i32,
+    out: &mut Vec<u8>,
+) {
+    let group_out_start = out.len();
+    let mut spec_level = 1;
+
+    while spec_level > 0 {
+        if LexClass::of(spec[*idx]) == LexClass::Alpha && spec_level == 1 {
+            *idx += 1;
+            if double_letter {
+                *idx += 1;
+            }
+
+            // The text between tokens may be given in braces after the
+            // letter.
+            let mut between = None;
+
+            if spec[*idx] == b'{' {
+                spec_level += 1;
+                *idx += 1;
+                let start = *idx;
+                *idx += skip_brace_level_greater_than_one(&spec[*idx..], &mut spec_level);
+                between = Some(&spec[start..*idx - 1]);
+            }
+
+            while cur_token < last_token {
+                let token = name.token(cur_token);
+
+                if double_letter {
+                    out.extend_from_slice(token);
+                } else {
+                    push_initial(token, out);
+                }
+
+                cur_token += 1;
+
+                if cur_token < last_token {
+                    if let Some(between) = between {
+                        out.extend_from_slice(between);
+                    } else {
+                        if !double_letter {
+                            out.push(b'.');
+                        }
+
+                        let sep = name.seps[cur_token];
+
+                        out.push(if LexClass::of(sep) == LexClass::Sep {
+                            sep
+                        } else if cur_token == last_token - 1
+                            || !enough_text_chars(out, group_out_start, brace_level)
+                        {
+                            b'~'
+                        } else {
+                            b' '
+                        });
+                    }
+                }
+            }
+        } else if spec[*idx] == b'}' {
+            spec_level -= 1;
+            *idx += 1;
+            if spec_level > 0 {
+                out.push(b'}');
+            }
+        } else if spec[*idx] == b'{' {
+            spec_level += 1;
+            *idx += 1;
+            out.push(b'{');
+        } else {
+            out.push(spec[*idx]);
+            *idx += 1;
+        }
+    }
+
+    // A tie at the end of the group becomes a space if the group is long
+    // enough, unless it's doubled.
+    if out.last() == Some(&b'~') {
+        out.pop();
+
+        if out.last() == Some(&b'~') {
+        } else if !enough_text_chars(out, group_out_start, brace_level) {
+            out.push(b'~');
+        } else {
+            out.push(b' ');
+        }
+    }
+}
+
+/// Output the initial of a token: its first letter, or its first special
+/// character.
+fn push_initial(token: &[u8], out: &mut Vec<u8>) {
+    let mut pos = 0;
+
+    while pos < token.len() {
+        if LexClass::of(token[pos]) == LexClass::Alpha {
+            out.push(token[pos]);
+            return;
+        }
+
+        if pos + 1 < token.len() && token[pos] == b'{' && token[pos + 1] == b'\\' {
+            out.extend_from_slice(b"{\\");
+            pos += 2;
+            let mut level = 1;
+
+            while pos < token.len() && level > 0 {
+                match token[pos] {
+                    b'}' => level -= 1,
+                    b'{' => level += 1,
+                    _ => (),
+                }
+                out.push(token[pos]);
+                pos += 1;
+            }
+
+            return;
+        }
+
+        pos += 1;
+    }
+}
+
+/// Whether the output since *start* has at least three text characters,
+/// counting a special character as one.
+fn enough_text_chars(out: &[u8], start: usize, brace_level: &mut i32) -> bool {
+    const ENOUGH: usize = 3;
+    let mut num_text_chars = 0;
+    let mut cur = start;
+
+    while cur < out.len() && num_text_chars < ENOUGH {
+        cur += 1;
+
+        if out[cur - 1] == b'{' {
+            *brace_level += 1;
+
+            if *brace_level == 1 && cur < out.len() && out[cur] == b'\\' {
+                cur += 1;
+
+                while cur < out.len() && *brace_level > 0 {
+                    match out[cur] {
+                        b'}' => *brace_level -= 1,
+                        b'{' => *brace_level += 1,
+                        _ => (),
+                    }
+                    cur += 1;
+                }
+            }
+        } else if out[cur - 1] == b'}' {
+            *brace_level -= 1;
+        }
+
+        num_text_chars += 1;
+    }
+
+    num_text_chars >= ENOUGH
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        let cases = [
+            ("Donald E. Knuth", "{ff~}{vv~}{ll}{, jj}", "Donald~E. Knuth"),
+            ("Knuth, Donald E.", "{f.~}{vv~}{ll}", "D.~E. Knuth"),
+            (
+                "Ludwig van Beethoven",
+                "{vv~}{ll}{, f.}",
+                "van Beethoven, L.",
+            ),
+            (
+                "de la Vall{\\'e}e Poussin, Charles Louis Xavier Joseph",
+                "{vv~}{ll}{, jj}{, f.}",
+                "de~la Vall{\\'e}e~Poussin, C. L. X.~J.",
+            ),
+            (
+                "Ford, Jr., Henry",
+                "{ff~}{vv~}{ll}{, jj}",
+                "Henry Ford, Jr.",
+            ),
+            ("Jean-Paul Sartre", "{f.~}{ll}", "J.-P. Sartre"),
+            ("{\\OE}rsted, Hans and others", "{ll}", "{\\OE}rsted"),
+            ("Jon {\\th}e Ulf", "{vv}", "{\\th}e"),
+            ("{Barnes and Noble}", "{ll}", "{Barnes and Noble}"),
+            ("Brinch Hansen, Per", "{l{}}", "BH"),
+        ];
+
+        for (name, spec, expected) in cases {
+            assert_eq!(format_name(name, spec).unwrap(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn problems() {
+        let mut problems = Vec::new();
+        assert_eq!(
+            format_nth_name(b"A. Smith and B. Jones", 2, b"{ll}", &mut problems),
+            b"Jones"
+        );
+        assert_eq!(
+            format_nth_name(b"A. Smith and B. Jones", 3, b"{ll}", &mut problems),
+            b"Jones"
+        );
+        assert_eq!(problems, [NameProblem::MissingName]);
+
+        assert_eq!(
+            num_names(b"A. Smith and {B. and C.} AND D. Jones", &mut problems),
+            3
+        );
+
+        assert!(format_name("", "{ll}").is_err());
+        assert!(format_name("a, b, c, d", "{ll}").is_err());
+        assert!(format_name("Smith, John,", "{ll}").is_err());
+        assert!(format_name("Smith}", "{ll}").is_err());
+        assert!(format_name("Smith", "{lf}").is_err());
+        assert!(format_name("Smith", "{ll").is_err());
+    }
+}
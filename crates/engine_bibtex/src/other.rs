@@ -1,23 +1,32 @@
-use crate::{pool::StrNumber, FieldLoc, HashPointer};
+use crate::{exec::Instr, pool::StrNumber, FieldLoc, HashPointer};
 
 const MAX_FIELDS: usize = 17250;
 
 pub(crate) struct OtherData {
     wiz_functions: Vec<HashPointer>,
+    /// The line of the `.bst` file on which each entry of `wiz_functions`
+    /// was written.
+    wiz_lines: Vec<u32>,
+    /// The code that each entry of `wiz_functions` is lowered to.
+    wiz_code: Vec<Instr>,
     field_info: Vec<StrNumber>,
     num_fields: FieldLoc,
     num_pre_defined_fields: FieldLoc,
     crossref_num: FieldLoc,
+    xdata_num: FieldLoc,
 }
 
 impl OtherData {
     pub fn new() -> OtherData {
         OtherData {
             wiz_functions: Vec::new(),
+            wiz_lines: Vec::new(),
+            wiz_code: Vec::new(),
             field_info: vec![StrNumber::invalid(); MAX_FIELDS + 1],
             num_fields: 0,
             num_pre_defined_fields: 0,
             crossref_num: 0,
+            xdata_num: 0,
         }
     }
 
@@ -64,12 +73,30 @@ impl OtherData {
         self.crossref_num = val;
     }
 
+    pub fn xdata_num(&self) -> FieldLoc {
+        self.xdata_num
+    }
+
+    pub fn set_xdata_num(&mut self, val: FieldLoc) {
+        self.xdata_num = val;
+    }
+
     pub fn wiz_function(&self, pos: usize) -> HashPointer {
         self.wiz_functions[pos]
     }
 
-    pub fn push_wiz_func(&mut self, val: HashPointer) {
-        self.wiz_functions.push(val)
+    pub fn wiz_line(&self, pos: usize) -> u32 {
+        self.wiz_lines[pos]
+    }
+
+    pub fn wiz_instr(&self, pos: usize) -> Instr {
+        self.wiz_code[pos]
+    }
+
+    pub fn push_wiz_func(&mut self, val: HashPointer, line: u32, instr: Instr) {
+        self.wiz_functions.push(val);
+        self.wiz_lines.push(line);
+        self.wiz_code.push(instr);
     }
 
     pub fn wiz_func_len(&self) -> usize {
@@ -4,16 +4,35 @@ use crate::{
     ASCIICode, Bibtex, BibtexError, BufPointer,
 };
 use libc::EOF;
-use std::{ffi::CStr, io};
+use std::{
+    ffi::CStr,
+    io::{self, Read, Seek},
+    ops::Range,
+};
 use tectonic_bridge_core::{CoreBridgeState, FileFormat, InputId};
 
 /* Sigh, I'm worried about ungetc() and EOF semantics in Bibtex's I/O, so
  * here's a tiny wrapper that lets us fake it. */
 
 pub(crate) struct PeekableInput {
-    id: InputId,
+    source: Source,
     peek_char: libc::c_int,
     saw_eof: bool,
+    /// The number of bytes read so far.
+    pos: usize,
+    /// The bytes of the line most recently read by [`input_ln`], without the
+    /// line ending and trailing whitespace.
+    line: Range<usize>,
+}
+
+/// Where the characters of a [`PeekableInput`] come from.
+enum Source {
+    /// A file opened through the engine.
+    Engine(InputId),
+
+    /// Text generated by the engine itself, such as a database converted to
+    /// `.bib` syntax.
+    Memory(io::Cursor<Vec<u8>>),
 }
 
 impl PeekableInput {
@@ -26,33 +45,204 @@ impl PeekableInput {
 
         if let Some(id) = id {
             Ok(PeekableInput {
-                id,
+                source: Source::Engine(id),
                 peek_char: EOF,
                 saw_eof: false,
+                pos: 0,
+                line: 0..0,
             })
         } else {
             Err(BibtexError::Fatal)
         }
     }
 
+    /// Create an input that reads *text* instead of a file.
+    pub(crate) fn from_memory(text: Vec<u8>) -> PeekableInput {
+        PeekableInput {
+            source: Source::Memory(io::Cursor::new(text)),
+            peek_char: EOF,
+            saw_eof: false,
+            pos: 0,
+            line: 0..0,
+        }
+    }
+
+    /// Open a file and read the whole of it.
+    pub(crate) fn read_all(
+        ctx: &mut Bibtex<'_, '_>,
+        path: &CStr,
+        format: FileFormat,
+    ) -> Result<Vec<u8>, BibtexError> {
+        let id = ctx
+            .engine
+            .input_open(path.to_str().unwrap(), format, false)
+            .ok_or(BibtexError::Fatal)?;
+
+        let mut data = Vec::new();
+        let read = ctx.engine.get_input(id).read_to_end(&mut data);
+        let failed_close = ctx.engine.input_close(id);
+
+        if read.is_err() || failed_close {
+            return Err(BibtexError::Fatal);
+        }
+
+        Ok(data)
+    }
+
+    /// Read the rest of the input, and close it.
+    pub(crate) fn read_to_end(mut self, ctx: &mut Bibtex<'_, '_>) -> Result<Vec<u8>, BibtexError> {
+        let read = self.read_rest(ctx);
+        self.close(ctx)?;
+        read
+    }
+
+    /// Read the rest of the input, leaving it open.
+    pub(crate) fn read_rest(&mut self, ctx: &mut Bibtex<'_, '_>) -> Result<Vec<u8>, BibtexError> {
+        let mut data = Vec::new();
+
+        if self.peek_char != EOF {
+            data.push(self.peek_char as u8);
+            self.peek_char = EOF;
+        }
+
+        let read = match &mut self.source {
+            Source::Engine(id) => ctx.engine.get_input(*id).read_to_end(&mut data),
+            Source::Memory(cursor) => cursor.read_to_end(&mut data),
+        };
+
+        read.map_err(|_| BibtexError::Fatal)?;
+        self.pos += data.len();
+        self.saw_eof = true;
+        Ok(data)
+    }
+
+    /// Go back to the start of the input, so that it can be read again.
+    pub(crate) fn rewind(&mut self, ctx: &mut Bibtex<'_, '_>) -> Result<(), BibtexError> {
+        match &mut self.source {
+            Source::Engine(id) => ctx.engine.get_input(*id).rewind(),
+            Source::Memory(cursor) => cursor.rewind(),
+        }
+        .map_err(|_| BibtexError::Fatal)?;
+
+        self.peek_char = EOF;
+        self.saw_eof = false;
+        self.pos = 0;
+        self.line = 0..0;
+        Ok(())
+    }
+
+    /// Read the bytes in *range* of the input, which mustn't start before
+    /// any byte that has already been read. The bytes before it are read
+    /// and thrown away, rather than skipped over by seeking, so that the
+    /// digest of the input that's used to detect changes stays valid.
+    pub(crate) fn read_range(
+        &mut self,
+        ctx: &mut Bibtex<'_, '_>,
+        range: Range<usize>,
+    ) -> Result<Vec<u8>, BibtexError> {
+        assert!(range.start >= self.pos && self.peek_char == EOF);
+
+        let reader: &mut dyn Read = match &mut self.source {
+            Source::Engine(id) => ctx.engine.get_input(*id),
+            Source::Memory(cursor) => cursor,
+        };
+
+        let skip = (range.start - self.pos) as u64;
+        let skipped = io::copy(&mut Read::take(&mut *reader, skip), &mut io::sink());
+        let mut data = vec![0; range.len()];
+        let read = reader.read_exact(&mut data);
+
+        if skipped.ok() != Some(skip) || read.is_err() {
+            return Err(BibtexError::Fatal);
+        }
+
+        self.pos = range.end;
+        Ok(data)
+    }
+
+    /// Read and throw away the input up to the byte at *pos*, which mustn't
+    /// be before the next byte to be read.
+    pub(crate) fn skip_to(
+        &mut self,
+        ctx: &mut Bibtex<'_, '_>,
+        pos: usize,
+    ) -> Result<(), BibtexError> {
+        assert!(pos >= self.pos);
+
+        if pos > self.pos && self.peek_char != EOF {
+            self.peek_char = EOF;
+            self.pos += 1;
+        }
+
+        let reader: &mut dyn Read = match &mut self.source {
+            Source::Engine(id) => ctx.engine.get_input(*id),
+            Source::Memory(cursor) => cursor,
+        };
+
+        let skip = (pos - self.pos) as u64;
+        let skipped = io::copy(&mut Read::take(reader, skip), &mut io::sink());
+
+        if skipped.ok() != Some(skip) {
+            return Err(BibtexError::Fatal);
+        }
+
+        self.pos = pos;
+        Ok(())
+    }
+
     pub(crate) fn close(self, ctx: &mut Bibtex<'_, '_>) -> Result<(), BibtexError> {
-        if !ctx.engine.input_close(self.id) {
+        let Source::Engine(id) = self.source else {
+            return Ok(());
+        };
+
+        if !ctx.engine.input_close(id) {
             Ok(())
         } else {
             Err(BibtexError::Fatal)
         }
     }
 
+    /// The bytes of the file that make up the line most recently read, or
+    /// `None` if the input isn't read from a file.
+    pub(crate) fn line_span(&self) -> Option<Range<usize>> {
+        match self.source {
+            Source::Engine(_) => Some(self.line.clone()),
+            Source::Memory(_) => None,
+        }
+    }
+
     fn getc(&mut self, engine: &mut CoreBridgeState<'_>) -> libc::c_int {
+        let c = self.getc_inner(engine);
+        if c != EOF {
+            self.pos += 1;
+        }
+        c
+    }
+
+    fn getc_inner(&mut self, engine: &mut CoreBridgeState<'_>) -> libc::c_int {
         if self.peek_char != EOF {
             let rv = self.peek_char;
             self.peek_char = EOF;
             return rv;
         }
 
+        let id = match self.source {
+            Source::Engine(id) => id,
+            Source::Memory(ref mut cursor) => {
+                let mut c = [0];
+                return match cursor.read(&mut c) {
+                    Ok(1) => libc::c_int::from(c[0]),
+                    _ => {
+                        self.saw_eof = true;
+                        EOF
+                    }
+                };
+            }
+        };
+
         // SAFETY: Internal handle guaranteed valid, unique access to this input is unique access
         //         to the handle
-        let handle = engine.get_input(self.id);
+        let handle = engine.get_input(id);
         let rv = match handle.getc() {
             Ok(c) => libc::c_int::from(c),
             Err(e) => {
@@ -73,6 +263,7 @@ impl PeekableInput {
     fn ungetc(&mut self, c: libc::c_int) {
         assert_ne!(c, EOF);
         self.peek_char = c;
+        self.pos -= 1;
     }
 
     pub fn eof(&mut self, engine: &mut CoreBridgeState<'_>) -> bool {
@@ -109,6 +300,7 @@ pub(crate) fn input_ln(
     if peekable.eof(engine) {
         return false;
     }
+    let start = peekable.pos;
 
     // Read up to end-of-line
     while !peekable.eoln(engine) {
@@ -139,6 +331,7 @@ pub(crate) fn input_ln(
     }
 
     buffers.set_init(BufTy::Base, last);
+    peekable.line = start..start + last;
 
     true
 }
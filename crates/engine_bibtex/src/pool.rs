@@ -1,4 +1,10 @@
-use std::{fmt, fmt::Formatter, ops::Range};
+use std::{
+    collections::HashMap,
+    fmt,
+    fmt::Formatter,
+    hash::{BuildHasherDefault, Hasher},
+    ops::Range,
+};
 
 const POOL_SIZE: usize = 65536;
 const STRINGS_SIZE: usize = 2048;
@@ -6,7 +12,7 @@ pub(crate) const MAX_PRINT_LINE: usize = 79;
 pub(crate) const MIN_PRINT_LINE: usize = 3;
 pub(crate) const MAX_STRINGS: usize = 35307;
 
-#[derive(Default, Debug, PartialEq, PartialOrd, Copy, Clone)]
+#[derive(Default, Debug, PartialEq, Eq, Hash, PartialOrd, Copy, Clone)]
 pub(crate) struct StrNumber(usize);
 
 impl fmt::Display for StrNumber {
@@ -33,15 +39,6 @@ impl StrNumber {
     }
 }
 
-#[derive(Default, Debug, PartialEq, Copy, Clone)]
-pub(crate) struct Checkpoint(usize);
-
-impl Checkpoint {
-    pub fn is_before(self, num: StrNumber) -> bool {
-        num.0 >= self.0
-    }
-}
-
 pub(crate) struct PoolCursor<'a> {
     pool: &'a mut StringPool,
     start: usize,
@@ -49,39 +46,64 @@ pub(crate) struct PoolCursor<'a> {
 }
 
 impl PoolCursor<'_> {
-    pub fn extend(&mut self, len: usize) {
-        self.end += len;
-    }
-
     pub fn append(&mut self, c: u8) {
+        self.pool.reserve_to(self.end + 1);
         self.pool.strings[self.end] = c;
         self.end += 1;
     }
 
+    /// Append the text of *str*. The text of a scratch string that was just
+    /// discarded is still in place at the top of the pool, and is reused
+    /// rather than copied if it's where the cursor would write it.
     pub fn append_str(&mut self, str: StrNumber) {
-        self.pool.copy_raw(str, self.end);
-        self.end += self.pool.get_str(str).len();
+        let len = self.pool.get_str(str).len();
+        self.append_substr(str, 0..len);
     }
 
+    /// Append part of the text of *str*, reusing it in place like
+    /// [`append_str`](Self::append_str).
     pub fn append_substr(&mut self, str: StrNumber, range: Range<usize>) {
         let start = self.pool.str_start(str);
-        self.pool
-            .copy_range_raw(start + range.start..start + range.end, self.end);
-    }
+        let len = range.end - range.start;
 
-    pub fn insert_str(&mut self, str: StrNumber, offset: usize) {
-        self.pool.copy_raw(str, self.start + offset);
-        let len = self.pool.get_str(str).len();
-        if self.start + offset + len > self.end {
-            self.end = self.start + offset + len;
+        if start + range.start != self.end {
+            self.pool
+                .copy_range_raw(start + range.start..start + range.end, self.end);
+        } else {
+            self.pool.reserve_to(self.end + len);
         }
+
+        self.end += len;
     }
 
-    pub fn bytes(&mut self) -> &mut [u8] {
-        &mut self.pool.strings[self.start..self.end]
+    /// Insert the text of *str* before what has been written so far.
+    pub fn prepend_str(&mut self, str: StrNumber) {
+        let len = self.pool.get_str(str).len();
+        self.pool
+            .copy_range_raw(self.start..self.end, self.start + len);
+        self.pool.copy_raw(str, self.start);
+        self.end += len;
     }
 }
 
+/// The strings of a run.
+///
+/// While a style's function runs, the strings that it makes are *scratch
+/// strings*. They're made at the top of the pool, and since the literal stack
+/// pops strings in the reverse of the order in which they were made, each is
+/// discarded from the top again as it's popped, so that the pool doesn't grow
+/// with every builtin that's called. The text of a discarded scratch string
+/// stays in place until the next string is made, so that a builtin can pop
+/// its arguments and then make its result from their text without copying
+/// it. The strings made before the function started, such as field values,
+/// are permanent.
+///
+/// Permanent strings are interned: adding a string whose text is that of a
+/// permanent string gives back the permanent string instead, so that equal
+/// strings usually have equal numbers. Scratch strings aren't, since they'd
+/// then appear on the stack more than once and couldn't be discarded in
+/// order, and the scratch strings promoted together may duplicate each
+/// other, so numbers are only compared with [`str_eq`](Self::str_eq).
 pub(crate) struct StringPool {
     strings: Vec<u8>,
     // Stores string starting locations in the string pool
@@ -89,6 +111,12 @@ pub(crate) struct StringPool {
     offsets: Vec<usize>,
     pool_ptr: usize,
     cur_strs: usize,
+    // The first scratch string
+    scratch_start: usize,
+    // The permanent strings that aren't duplicates, by the hash of their text
+    interned: HashMap<u32, StrNumber, BuildHasherDefault<TextHasher>>,
+    // Whether each permanent string is in `interned`
+    canonical: Vec<bool>,
 }
 
 impl StringPool {
@@ -101,9 +129,24 @@ impl StringPool {
             offsets,
             pool_ptr: 0,
             cur_strs: 1,
+            scratch_start: 1,
+            interned: HashMap::default(),
+            // The invalid string 0 is never interned
+            canonical: vec![false],
         }
     }
 
+    /// The number of bytes of text in the pool.
+    pub fn bytes(&self) -> usize {
+        self.pool_ptr
+    }
+
+    /// Make room for about *strings* more strings, of *bytes* bytes in total.
+    pub fn reserve(&mut self, strings: usize, bytes: usize) {
+        self.offsets.reserve(strings);
+        self.strings.reserve(bytes);
+    }
+
     fn str_start(&self, str: StrNumber) -> usize {
         self.offsets[str.0 - 1]
     }
@@ -128,10 +171,22 @@ impl StringPool {
             .unwrap_or_else(|| panic!("String number {s} doesn't exist"))
     }
 
+    /// Make a string with the text *str*, or return the permanent string
+    /// that already has it.
     pub fn add_string(&mut self, str: &[u8]) -> StrNumber {
-        while self.pool_ptr + str.len() > self.strings.len() {
-            self.grow();
+        if let Some(&interned) = self.interned.get(&hash_text(str)) {
+            if self.get_str(interned) == str {
+                return interned;
+            }
         }
+        self.copy_string(str)
+    }
+
+    /// Make a string with the text *str* without looking for a permanent one,
+    /// for text that's seldom that of one, such as the value of a variable
+    /// being pushed, so that it isn't hashed each time.
+    pub fn copy_string(&mut self, str: &[u8]) -> StrNumber {
+        self.reserve_to(self.pool_ptr + str.len());
         self.strings[self.pool_ptr..self.pool_ptr + str.len()].copy_from_slice(str);
         self.pool_ptr += str.len();
         self.make_string()
@@ -148,10 +203,54 @@ impl StringPool {
         self.make_string()
     }
 
-    /// Check if the provided string is the last. If it is, remove it from the pool and return true.
-    /// Otherwise, return false.
-    pub fn remove_last_str(&mut self, str: StrNumber) -> bool {
-        if str.0 != self.cur_strs - 1 {
+    /// Make all strings permanent, so that only those made from now on are
+    /// scratch strings. This is done as each function of a style is started.
+    pub fn promote_scratch(&mut self) {
+        for idx in self.scratch_start..self.cur_strs {
+            let str = StrNumber(idx);
+            let hash = hash_text(self.get_str(str));
+            let canonical = !self.interned.contains_key(&hash);
+            if canonical {
+                self.interned.insert(hash, str);
+            }
+            self.canonical.push(canonical);
+        }
+        self.scratch_start = self.cur_strs;
+    }
+
+    /// Whether the text of *s1* and *s2* is the same. Two interned strings
+    /// are compared by number alone.
+    pub fn str_eq(&self, s1: StrNumber, s2: StrNumber) -> bool {
+        if s1 == s2 {
+            true
+        } else if self.is_canonical(s1) && self.is_canonical(s2) {
+            false
+        } else {
+            self.get_str(s1) == self.get_str(s2)
+        }
+    }
+
+    fn is_canonical(&self, str: StrNumber) -> bool {
+        self.canonical.get(str.0).copied().unwrap_or(false)
+    }
+
+    /// Whether *str* is a scratch string.
+    pub fn is_scratch(&self, str: StrNumber) -> bool {
+        str.0 >= self.scratch_start
+    }
+
+    /// Whether any scratch strings haven't been discarded.
+    pub fn has_scratch(&self) -> bool {
+        self.cur_strs > self.scratch_start
+    }
+
+    /// Discard *str* if it's a scratch string, which must then be the newest
+    /// string. Returns false if it isn't, which means that scratch strings
+    /// have been popped out of order. Permanent strings are left alone.
+    pub fn discard_scratch(&mut self, str: StrNumber) -> bool {
+        if !self.is_scratch(str) {
+            true
+        } else if str.0 != self.cur_strs - 1 {
             false
         } else {
             self.cur_strs -= 1;
@@ -160,12 +259,40 @@ impl StringPool {
         }
     }
 
-    pub fn checkpoint(&self) -> Checkpoint {
-        Checkpoint(self.cur_strs)
+    /// Take back the discarding of *str*, if it's a scratch string, for a
+    /// builtin that pushes an argument back after popping it. This must be
+    /// done before any other string is made.
+    pub fn restore_scratch(&mut self, str: StrNumber) -> StrNumber {
+        if self.is_scratch(str) {
+            self.write_str(|cursor| cursor.append_str(str))
+        } else {
+            str
+        }
     }
 
-    pub fn is_at(&self, check: Checkpoint) -> bool {
-        self.cur_strs == check.0
+    /// Make a string of the text of *first* followed by that of *second*.
+    /// Either may be a scratch string that was just discarded, whose text is
+    /// reused where it is.
+    pub fn concat(&mut self, first: StrNumber, second: StrNumber) -> StrNumber {
+        // If only the second string's text is in place, the first has to be
+        // moved in front of it rather than written over it.
+        if self.str_start(second) == self.pool_ptr && self.str_start(first) != self.pool_ptr {
+            self.write_str(|cursor| {
+                cursor.append_str(second);
+                cursor.prepend_str(first);
+            })
+        } else {
+            self.write_str(|cursor| {
+                cursor.append_str(first);
+                cursor.append_str(second);
+            })
+        }
+    }
+
+    fn reserve_to(&mut self, len: usize) {
+        while len > self.strings.len() {
+            self.grow();
+        }
     }
 
     fn grow(&mut self) {
@@ -188,25 +315,61 @@ impl StringPool {
         let start = self.str_start(str);
         let end = self.str_end(str);
 
-        while pos + (end - start) > self.strings.len() {
-            self.grow();
-        }
-
+        self.reserve_to(pos + (end - start));
         self.strings.copy_within(start..end, pos);
     }
 
     fn copy_range_raw(&mut self, range: Range<usize>, pos: usize) {
-        while pos + (range.end - range.start) > self.strings.len() {
-            self.grow();
-        }
+        self.reserve_to(pos + (range.end - range.start));
         self.strings.copy_within(range, pos)
     }
 }
 
+/// Since the keys of `StringPool::interned` are already hashes, they're only
+/// spread over 64 bits, since the table uses the top bits too.
+#[derive(Default)]
+struct TextHasher(u64);
+
+impl Hasher for TextHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // Only `u32` keys are hashed in practice, but fall back to 64-bit
+        // FNV-1a rather than panicking should anything else be.
+        self.0 = bytes.iter().fold(self.0 ^ 0xcbf29ce484222325, |acc, &b| {
+            (acc ^ b as u64).wrapping_mul(0x100000001b3)
+        });
+    }
+
+    fn write_u32(&mut self, hash: u32) {
+        self.0 = (hash as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    }
+}
+
+/// The 32-bit FNV-1a hash of *str*.
+pub(crate) fn hash_text(str: &[u8]) -> u32 {
+    str.iter().fold(0x811c9dc5, |acc: u32, &c| {
+        (acc ^ c as u32).wrapping_mul(0x01000193)
+    })
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_text_hasher_bytes() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = TextHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+        assert_eq!(hash(b"abc"), hash(b"abc"));
+        assert_ne!(hash(b"abc"), hash(b"abd"));
+    }
+
     #[test]
     fn test_invalid_str() {
         let pool = StringPool::new();
@@ -237,14 +400,117 @@ mod tests {
         assert_ne!(str, new_str);
         assert_eq!(pool.get_str(str), pool.get_str(new_str));
 
-        assert!(pool.remove_last_str(new_str));
-        // Ensure we can get length of removed string
-        let str_len = pool.get_str(new_str).len();
-        // Ensure extending by that length restores the string
-        let new_str_2 = pool.write_str(|cursor| {
-            cursor.extend(str_len);
+        let sub = pool.write_str(|cursor| {
+            cursor.append_substr(str, 6..11);
+            cursor.append(b'!');
         });
-        assert_eq!(new_str, new_str_2);
-        assert_eq!(pool.get_str(str), pool.get_str(new_str_2));
+        assert_eq!(pool.get_str(sub), b"World!");
+    }
+
+    #[test]
+    fn test_scratch_strings() {
+        let mut pool = StringPool::new();
+
+        let permanent = pool.add_string(b"field");
+        pool.promote_scratch();
+        assert!(!pool.is_scratch(permanent));
+        assert!(!pool.has_scratch());
+
+        let s1 = pool.add_string(b"one");
+        let s2 = pool.add_string(b"two");
+        assert!(pool.is_scratch(s1));
+        assert!(pool.has_scratch());
+
+        // Scratch strings must be discarded newest first.
+        assert!(!pool.discard_scratch(s1));
+        assert!(pool.discard_scratch(permanent));
+        assert!(pool.discard_scratch(s2));
+
+        // A discarded string can be restored, and keeps its number.
+        assert_eq!(pool.restore_scratch(s2), s2);
+        assert_eq!(pool.get_str(s2), b"two");
+        assert_eq!(pool.restore_scratch(permanent), permanent);
+
+        assert!(pool.discard_scratch(s2));
+        assert!(pool.discard_scratch(s1));
+        assert!(!pool.has_scratch());
+        assert_eq!(pool.get_str(permanent), b"field");
+    }
+
+    #[test]
+    fn test_concat() {
+        let mut pool = StringPool::new();
+        let a = pool.add_string(b"ab");
+        let b = pool.add_string(b"cd");
+        pool.promote_scratch();
+
+        // Neither is scratch.
+        let new = pool.concat(a, b);
+        assert_eq!(pool.get_str(new), b"abcd");
+        assert!(pool.discard_scratch(new));
+
+        // Both are scratch, and next to each other.
+        let s1 = pool.add_string(b"ef");
+        let s2 = pool.add_string(b"gh");
+        assert!(pool.discard_scratch(s2));
+        assert!(pool.discard_scratch(s1));
+        let new = pool.concat(s1, s2);
+        assert_eq!(pool.get_str(new), b"efgh");
+        assert!(pool.discard_scratch(new));
+
+        // Only the first is scratch.
+        let s = pool.add_string(b"ij");
+        assert!(pool.discard_scratch(s));
+        let new = pool.concat(s, a);
+        assert_eq!(pool.get_str(new), b"ijab");
+        assert!(pool.discard_scratch(new));
+
+        // Only the second is scratch, so the first goes in front of it.
+        let s = pool.add_string(b"kl");
+        assert!(pool.discard_scratch(s));
+        let new = pool.concat(a, s);
+        assert_eq!(pool.get_str(new), b"abkl");
+        assert!(pool.discard_scratch(new));
+        assert!(!pool.has_scratch());
+    }
+
+    #[test]
+    fn test_interning() {
+        let mut pool = StringPool::new();
+        let a = pool.add_string(b"same");
+        let b = pool.add_string(b"same");
+        let c = pool.add_string(b"other");
+        pool.promote_scratch();
+
+        // Scratch strings promoted together aren't merged, but are still equal.
+        assert_ne!(a, b);
+        assert!(pool.str_eq(a, b));
+        assert!(!pool.str_eq(a, c));
+
+        // Added strings with the text of a permanent one are that one.
+        assert_eq!(pool.add_string(b"same"), a);
+        assert!(!pool.has_scratch());
+
+        // Others are scratch strings as usual.
+        let s = pool.add_string(b"sam");
+        let t = pool.add_string(b"sam");
+        assert!(pool.is_scratch(s));
+        assert_ne!(s, t);
+        assert!(pool.str_eq(s, t));
+        assert!(!pool.str_eq(s, a));
+
+        let u = pool.write_str(|cursor| {
+            cursor.append_str(s);
+            cursor.append(b'e');
+        });
+        let v = pool.copy_string(b"same");
+        assert!(pool.is_scratch(u) && pool.is_scratch(v));
+        assert!(pool.str_eq(u, a));
+        assert!(pool.str_eq(v, b));
+
+        for str in [v, u, t, s] {
+            assert!(pool.discard_scratch(str));
+        }
+        assert!(!pool.has_scratch());
     }
 }
@@ -0,0 +1,192 @@
+//! Profiling the functions of styles.
+//!
+//! Complex styles such as `IEEEtran.bst` define hundreds of functions, and
+//! when one of them is slow on a large database it's hard to tell which of
+//! its functions the time goes to. If profiling is enabled, every call of a
+//! builtin or of a function defined by the style is counted and timed, and
+//! once the run is over the figures are given as a [`BstProfile`], and
+//! written to the log file. Pushing literals isn't profiled.
+
+use crate::{
+    hash::{BstFn, HashData, HashExtra},
+    pool::StringPool,
+    HashPointer,
+};
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    time::{Duration, Instant},
+};
+
+/// The time spent by a style in each of the functions that it called.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BstProfile {
+    /// The functions that were called, those that took the most time of
+    /// their own first.
+    pub functions: Vec<FunctionProfile>,
+}
+
+/// The calls of one function, as seen by profiling.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FunctionProfile {
+    /// The name of the function. As in a [`BstCall`](crate::BstCall),
+    /// `{...}` blocks are named by a `'` followed by a number.
+    pub name: String,
+
+    /// Whether the function is a builtin, rather than one defined by the
+    /// style.
+    pub builtin: bool,
+
+    /// The number of times that the function was called.
+    pub calls: u64,
+
+    /// The time spent in the function, including the functions that it
+    /// called. The time of a call made while another call of the same
+    /// function was running is only counted once.
+    pub total_time: Duration,
+
+    /// The time spent in the function itself, not including the functions
+    /// that it called.
+    pub self_time: Duration,
+}
+
+impl Display for BstProfile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        writeln!(
+            f,
+            "{:>10} {:>10} {:>10}  function",
+            "calls", "total ms", "self ms"
+        )?;
+        for func in &self.functions {
+            writeln!(
+                f,
+                "{:>10} {:>10.3} {:>10.3}  {}",
+                func.calls,
+                func.total_time.as_secs_f64() * 1000.0,
+                func.self_time.as_secs_f64() * 1000.0,
+                func.name,
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Counts {
+    calls: u64,
+    total_time: Duration,
+    self_time: Duration,
+    /// The number of calls of the function that are running.
+    active: u32,
+}
+
+/// A call that is running.
+#[derive(Debug)]
+struct Call {
+    fn_loc: HashPointer,
+    started: Instant,
+    /// The time spent in the calls made by this one so far.
+    callee_time: Duration,
+}
+
+/// The state of profiling while a style runs.
+#[derive(Debug, Default)]
+pub(crate) struct Profiler {
+    /// The counts of each function, indexed by its location in the hash
+    /// table.
+    counts: Vec<Counts>,
+    /// The calls that are running, innermost last.
+    calls: Vec<Call>,
+}
+
+impl Profiler {
+    /// Start timing a call of the function at *fn_loc*.
+    pub fn enter(&mut self, fn_loc: HashPointer) {
+        if fn_loc >= self.counts.len() {
+            self.counts.resize(fn_loc + 1, Counts::default());
+        }
+        let counts = &mut self.counts[fn_loc];
+        counts.calls += 1;
+        counts.active += 1;
+        self.calls.push(Call {
+            fn_loc,
+            started: Instant::now(),
+            callee_time: Duration::ZERO,
+        });
+    }
+
+    /// Stop timing the innermost call.
+    pub fn exit(&mut self) {
+        let Some(call) = self.calls.pop() else {
+            return;
+        };
+        let elapsed = call.started.elapsed();
+
+        let counts = &mut self.counts[call.fn_loc];
+        counts.self_time += elapsed.saturating_sub(call.callee_time);
+        counts.active -= 1;
+        if counts.active == 0 {
+            counts.total_time += elapsed;
+        }
+
+        if let Some(caller) = self.calls.last_mut() {
+            caller.callee_time += elapsed;
+        }
+    }
+
+    /// Make the report of the run, naming the functions from *hash*.
+    pub fn finish(self, pool: &StringPool, hash: &HashData) -> BstProfile {
+        let mut functions: Vec<_> = self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, counts)| counts.calls > 0)
+            .map(|(fn_loc, counts)| FunctionProfile {
+                name: String::from_utf8_lossy(pool.get_str(hash.text(fn_loc))).into_owned(),
+                builtin: matches!(
+                    hash.node(fn_loc).extra,
+                    HashExtra::BstFn(BstFn::Builtin(_) | BstFn::Custom(_))
+                ),
+                calls: counts.calls,
+                total_time: counts.total_time,
+                self_time: counts.self_time,
+            })
+            .collect();
+
+        functions.sort_by(|a, b| {
+            b.self_time
+                .cmp(&a.self_time)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+        BstProfile { functions }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn test_profiler() {
+        let mut profiler = Profiler::default();
+
+        // A function that calls itself, then another.
+        profiler.enter(3);
+        profiler.enter(3);
+        sleep(Duration::from_millis(10));
+        profiler.exit();
+        profiler.enter(1);
+        sleep(Duration::from_millis(10));
+        profiler.exit();
+        profiler.exit();
+
+        let outer = &profiler.counts[3];
+        let inner = &profiler.counts[1];
+        assert_eq!((outer.calls, outer.active), (2, 0));
+        assert_eq!((inner.calls, inner.active), (1, 0));
+        assert!(inner.self_time >= Duration::from_millis(10));
+        assert!(outer.self_time >= Duration::from_millis(10));
+        assert_eq!(outer.total_time, outer.self_time + inner.total_time);
+        assert_eq!(profiler.counts[2].calls, 0);
+    }
+}
@@ -0,0 +1,65 @@
+//! The sources of the values, other than the time, that would otherwise
+//! differ between runs with the same inputs: the identifiers of runs, and
+//! pseudo-random numbers.
+//!
+//! Embedding code can fix both, with
+//! [`BibtexEngine::id_source()`](crate::BibtexEngine::id_source) and
+//! [`BibtexEngine::rng_seed()`](crate::BibtexEngine::rng_seed), so that the
+//! results of a run can be reproduced, as with the
+//! [build date](crate::BibtexEngine::build_date) for the time.
+
+use std::{
+    collections::hash_map::RandomState,
+    fmt::{Debug, Formatter, Result as FmtResult},
+    hash::{BuildHasher, Hasher},
+};
+
+/// A function giving the identifier of each run.
+pub(crate) struct IdSource(pub Box<dyn FnMut() -> String + Send>);
+
+impl Debug for IdSource {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        f.write_str("IdSource")
+    }
+}
+
+/// A small pseudo-random number generator, SplitMix64. It's not meant to be
+/// unpredictable, only to give different numbers unless it's seeded the same.
+#[derive(Clone, Debug)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// Create a generator with the given seed, or, without one, a seed that
+    /// differs from run to run.
+    pub(crate) fn new(seed: Option<u64>) -> Rng {
+        // The standard library seeds the keys of hash maps randomly, which
+        // saves depending on a crate for the entropy.
+        let state = seed.unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        Rng { state }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn seeded() {
+        let mut a = Rng::new(Some(1));
+        let mut b = Rng::new(Some(1));
+        let first = a.next_u64();
+        assert_eq!(first, b.next_u64());
+        assert_ne!(first, a.next_u64());
+        assert_ne!(first, Rng::new(Some(2)).next_u64());
+    }
+}
@@ -1,11 +1,13 @@
 use crate::{
+    bib::identifier_len,
     bibs::{compress_bib_white, eat_bib_white_space, BibCommand, BibData},
     buffer::{BufTy, GlobalBuffer},
-    char_info::{IdClass, LexClass},
+    char_info::LexClass,
     cite::{add_database_cite, CiteInfo},
-    exec::{ControlSeq, ExecCtx},
+    exec::{lower_fn, ExecCtx},
     hash,
     hash::{BstFn, HashData, HashExtra},
+    limits::limit_field,
     log::{
         bib_err_print, bib_id_print, bib_unbalanced_braces_print, bib_warn_print,
         braces_unbalanced_complaint, bst_err_print_and_look_for_blank_line, eat_bib_print,
@@ -102,14 +104,8 @@ pub(crate) fn scan_identifier(
     let start = buffers.offset(BufTy::Base, 2);
     buffers.set_offset(BufTy::Base, 1, start);
 
-    let mut idx = start;
-    let char = buffers.at(BufTy::Base, idx);
-    if LexClass::of(char) != LexClass::Numeric {
-        while idx < last && IdClass::of(buffers.at(BufTy::Base, idx)) == IdClass::LegalIdChar {
-            idx += 1;
-        }
-        buffers.set_offset(BufTy::Base, 2, idx);
-    }
+    let idx = start + identifier_len(&buffers.buffer(BufTy::Base)[start..last]);
+    buffers.set_offset(BufTy::Base, 2, idx);
 
     let char = buffers.at(BufTy::Base, idx);
     if idx - start == 0 {
@@ -175,10 +171,12 @@ pub(crate) fn eat_bst_white_space(ctx: &mut Bibtex<'_, '_>, buffers: &mut Global
 fn handle_char(
     ctx: &mut Bibtex<'_, '_>,
     globals: &mut GlobalItems<'_>,
-    single_function: &mut Vec<FnDefLoc>,
+    single_function: &mut Vec<(FnDefLoc, u32)>,
     wiz_loc: HashPointer,
     char: ASCIICode,
 ) -> Result<(), BibtexError> {
+    let line = ctx.bst.as_ref().unwrap().line;
+
     match char {
         b'#' => {
             let mut token_value = 0;
@@ -210,7 +208,7 @@ fn handle_char(
                 return skip_illegal_stuff_after_token_print(ctx, globals.buffers, globals.pool);
             }
 
-            single_function.push(res.loc);
+            single_function.push((res.loc, line));
         }
         b'"' => {
             globals
@@ -243,7 +241,7 @@ fn handle_char(
                 return skip_illegal_stuff_after_token_print(ctx, globals.buffers, globals.pool);
             }
 
-            single_function.push(res.loc);
+            single_function.push((res.loc, line));
         }
         b'\'' => {
             globals
@@ -269,8 +267,8 @@ fn handle_char(
                 return print_recursion_illegal(ctx, globals.buffers, globals.pool);
             }
 
-            single_function.push(QUOTE_NEXT_FN);
-            single_function.push(res.loc);
+            single_function.push((QUOTE_NEXT_FN, line));
+            single_function.push((res.loc, line));
         }
         b'{' => {
             globals
@@ -293,8 +291,8 @@ fn handle_char(
             }
             ctx.impl_fn_num += 1;
 
-            single_function.push(QUOTE_NEXT_FN);
-            single_function.push(res.loc);
+            single_function.push((QUOTE_NEXT_FN, line));
+            single_function.push((res.loc, line));
 
             scan_fn_def(ctx, globals, res.loc, wiz_loc)?;
         }
@@ -318,7 +316,7 @@ fn handle_char(
                 return print_recursion_illegal(ctx, globals.buffers, globals.pool);
             }
 
-            single_function.push(res.loc);
+            single_function.push((res.loc, line));
         }
     }
     Ok(())
@@ -352,14 +350,8 @@ pub(crate) fn scan_fn_def(
         char = globals.buffers.at_offset(BufTy::Base, 2);
     }
 
-    single_function.push(HashData::end_of_def());
-
-    globals.hash.node_mut(fn_hash_loc).extra =
-        HashExtra::BstFn(BstFn::Wizard(globals.other.wiz_func_len()));
-
-    for ptr in single_function {
-        globals.other.push_wiz_func(ptr);
-    }
+    single_function.push((HashData::end_of_def(), ctx.bst.as_ref().unwrap().line));
+    define_wiz_fn(globals, fn_hash_loc, single_function);
 
     globals
         .buffers
@@ -368,6 +360,24 @@ pub(crate) fn scan_fn_def(
     Ok(())
 }
 
+/// Define the wizard function at *fn_hash_loc* with the given body, as the
+/// locations of the functions that it calls and the lines on which they were
+/// written.
+pub(crate) fn define_wiz_fn(
+    globals: &mut GlobalItems<'_>,
+    fn_hash_loc: HashPointer,
+    single_function: Vec<(FnDefLoc, u32)>,
+) {
+    globals.hash.node_mut(fn_hash_loc).extra =
+        HashExtra::BstFn(BstFn::Wizard(globals.other.wiz_func_len()));
+
+    let body: Vec<_> = single_function.iter().map(|&(ptr, _)| ptr).collect();
+    let code = lower_fn(globals.hash, &body);
+    for ((ptr, line), instr) in single_function.into_iter().zip(code) {
+        globals.other.push_wiz_func(ptr, line, instr);
+    }
+}
+
 fn scan_balanced_braces(
     ctx: &mut Bibtex<'_, '_>,
     buffers: &mut GlobalBuffer,
@@ -794,8 +804,34 @@ pub(crate) fn scan_and_store_the_field_value_and_eat_white(
             0
         };
 
-        let str =
+        let mut str =
             &globals.buffers.buffer(BufTy::Ex)[ex_buf_xptr..globals.buffers.offset(BufTy::Ex, 1)];
+
+        // Fields are limited as they're stored, apart from `crossref`, whose
+        // value is also looked up as a cite key below.
+        let limited;
+        if command.is_none()
+            && !matches!(
+                globals.hash.node(field_name_loc).extra,
+                HashExtra::BstFn(BstFn::Field(field)) if field == globals.other.crossref_num()
+            )
+        {
+            let Some(value) = limit_field(
+                ctx,
+                globals.pool,
+                globals.hash,
+                globals.cites,
+                globals.bibs,
+                field_name_loc,
+                str,
+            )?
+            else {
+                return Ok(true);
+            };
+            limited = value;
+            str = &limited;
+        }
+
         let res = globals
             .hash
             .lookup_str_insert(ctx, globals.pool, str, HashExtra::Text)?;
@@ -939,204 +975,3 @@ pub(crate) fn check_brace_level(
     }
     Ok(())
 }
-
-pub(crate) fn name_scan_for_and(
-    ctx: &mut ExecCtx<'_, '_, '_>,
-    pool: &StringPool,
-    buffers: &mut GlobalBuffer,
-    cites: &CiteInfo,
-    pop_lit_var: StrNumber,
-    brace_level: &mut i32,
-) -> Result<(), BibtexError> {
-    let mut preceding_white = false;
-    let mut and_found = false;
-
-    while !and_found && buffers.offset(BufTy::Ex, 1) < buffers.init(BufTy::Ex) {
-        match buffers.at_offset(BufTy::Ex, 1) {
-            b'A' | b'a' => {
-                buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                if preceding_white
-                    && buffers.offset(BufTy::Ex, 1) <= buffers.init(BufTy::Ex).saturating_sub(3)
-                    && buffers.at_offset(BufTy::Ex, 1).eq_ignore_ascii_case(&b'n')
-                    && buffers
-                        .at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) + 1)
-                        .eq_ignore_ascii_case(&b'd')
-                    && LexClass::of(buffers.at(BufTy::Ex, buffers.offset(BufTy::Ex, 1) + 2))
-                        == LexClass::Whitespace
-                {
-                    buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 2);
-                    and_found = true;
-                }
-                preceding_white = false;
-            }
-            b'{' => {
-                *brace_level += 1;
-                buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                while *brace_level > 0 && buffers.offset(BufTy::Ex, 1) < buffers.init(BufTy::Ex) {
-                    match buffers.at_offset(BufTy::Ex, 1) {
-                        b'{' => *brace_level += 1,
-                        b'}' => *brace_level -= 1,
-                        _ => (),
-                    }
-                    buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                }
-                preceding_white = false;
-            }
-            b'}' => {
-                decr_brace_level(ctx, pool, cites, pop_lit_var, brace_level)?;
-                buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-                preceding_white = false;
-            }
-            _ => {
-                preceding_white =
-                    LexClass::of(buffers.at_offset(BufTy::Ex, 1)) == LexClass::Whitespace;
-                buffers.set_offset(BufTy::Ex, 1, buffers.offset(BufTy::Ex, 1) + 1);
-            }
-        }
-    }
-
-    check_brace_level(ctx, pool, cites, pop_lit_var, *brace_level)
-}
-
-pub(crate) fn von_token_found(
-    buffers: &GlobalBuffer,
-    hash: &HashData,
-    pool: &StringPool,
-    name_bf_ptr: &mut BufPointer,
-    name_bf_xptr: BufPointer,
-) -> bool {
-    while *name_bf_ptr < name_bf_xptr {
-        let char = buffers.at(BufTy::Sv, *name_bf_ptr);
-        match char {
-            b'A'..=b'Z' => return false,
-            b'a'..=b'z' => return true,
-            b'{' => {
-                let mut nm_brace_level = 1;
-                *name_bf_ptr += 1;
-                if *name_bf_ptr + 2 < name_bf_xptr && buffers.at(BufTy::Sv, *name_bf_ptr) == b'\\' {
-                    *name_bf_ptr += 1;
-                    let name_bf_yptr = *name_bf_ptr;
-                    while *name_bf_ptr < name_bf_xptr
-                        && LexClass::of(buffers.at(BufTy::Sv, *name_bf_ptr)) == LexClass::Alpha
-                    {
-                        *name_bf_ptr += 1;
-                    }
-                    let str = &buffers.buffer(BufTy::Sv)[name_bf_yptr..*name_bf_ptr];
-                    let res = hash.lookup_str(pool, str, StrIlk::ControlSeq);
-                    let ilk = res
-                        .exists
-                        .then(|| {
-                            if let HashExtra::ControlSeq(seq) = hash.node(res.loc).extra {
-                                Some(seq)
-                            } else {
-                                None
-                            }
-                        })
-                        .flatten();
-                    if let Some(seq) = ilk {
-                        return match seq {
-                            ControlSeq::UpperOE
-                            | ControlSeq::UpperAE
-                            | ControlSeq::UpperAA
-                            | ControlSeq::UpperO
-                            | ControlSeq::UpperL => false,
-                            ControlSeq::LowerI
-                            | ControlSeq::LowerJ
-                            | ControlSeq::LowerOE
-                            | ControlSeq::LowerAE
-                            | ControlSeq::LowerAA
-                            | ControlSeq::LowerO
-                            | ControlSeq::LowerL
-                            | ControlSeq::LowerSS => true,
-                        };
-                    }
-
-                    while *name_bf_ptr < name_bf_xptr && nm_brace_level > 0 {
-                        let char = buffers.at(BufTy::Sv, *name_bf_ptr);
-                        match char {
-                            b'A'..=b'Z' => return false,
-                            b'a'..=b'z' => return true,
-                            b'}' => nm_brace_level -= 1,
-                            b'{' => nm_brace_level += 1,
-                            _ => (),
-                        }
-                        *name_bf_ptr += 1;
-                    }
-                    return false;
-                } else {
-                    while nm_brace_level > 0 && *name_bf_ptr < name_bf_xptr {
-                        let char = buffers.at(BufTy::Sv, *name_bf_ptr);
-                        match char {
-                            b'{' => nm_brace_level += 1,
-                            b'}' => nm_brace_level -= 1,
-                            _ => (),
-                        }
-                        *name_bf_ptr += 1;
-                    }
-                }
-            }
-            _ => {
-                *name_bf_ptr += 1;
-            }
-        }
-    }
-    false
-}
-
-#[allow(clippy::too_many_arguments)]
-pub(crate) fn von_name_ends_and_last_name_starts_stuff(
-    buffers: &GlobalBuffer,
-    hash: &HashData,
-    pool: &StringPool,
-    last_end: BufPointer,
-    von_start: BufPointer,
-    von_end: &mut BufPointer,
-    name_bf_ptr: &mut BufPointer,
-    name_bf_xptr: &mut BufPointer,
-) {
-    *von_end = last_end - 1;
-    while *von_end > von_start {
-        *name_bf_ptr = buffers.name_tok(*von_end - 1);
-        *name_bf_xptr = buffers.name_tok(*von_end);
-        if von_token_found(buffers, hash, pool, name_bf_ptr, *name_bf_xptr) {
-            return;
-        }
-        *von_end -= 1;
-    }
-}
-
-pub(crate) fn enough_text_chars(
-    buffers: &GlobalBuffer,
-    enough_chars: BufPointer,
-    buf_start: BufPointer,
-    brace_level: &mut i32,
-) -> bool {
-    let mut num_text_chars = 0;
-    let mut buf_cur = buf_start;
-
-    while buf_cur < buffers.offset(BufTy::Ex, 1) && num_text_chars < enough_chars {
-        buf_cur += 1;
-        if buffers.at(BufTy::Ex, buf_cur - 1) == b'{' {
-            *brace_level += 1;
-            if *brace_level == 1
-                && buf_cur < buffers.offset(BufTy::Ex, 1)
-                && buffers.at(BufTy::Ex, buf_cur) == b'\\'
-            {
-                buf_cur += 1;
-                while buf_cur < buffers.offset(BufTy::Ex, 1) && *brace_level > 0 {
-                    match buffers.at(BufTy::Ex, buf_cur) {
-                        b'}' => *brace_level -= 1,
-                        b'{' => *brace_level += 1,
-                        _ => (),
-                    }
-                    buf_cur += 1;
-                }
-            }
-        } else if buffers.at(BufTy::Ex, buf_cur - 1) == b'}' {
-            *brace_level -= 1;
-        }
-        num_text_chars += 1;
-    }
-
-    num_text_chars >= enough_chars
-}
@@ -0,0 +1,438 @@
+//! Schemas of the fields that each entry type requires and allows.
+//!
+//! Classic BibTeX leaves it to the style to complain about missing fields,
+//! and most styles only check some of them, while fields that the style
+//! doesn't declare are silently dropped. When linting is enabled, each entry
+//! is checked against the schema of its type once the database files have
+//! been read, before the style runs any of its code: every group of required
+//! fields must have at least one member present, either in the entry or in
+//! the entries that it cross-references, and every field of the entry must be
+//! required, optional, or one of the [`EntrySchema::UNIVERSAL`] fields. Each
+//! problem is written to the log as a warning and recorded as an
+//! [`EntryLint`].
+//!
+//! The standard schemas follow the descriptions of the entry types in the
+//! BibTeX documentation. Entries of types without a schema aren't checked.
+
+use crate::{
+    exec::{entry_location, field_value},
+    srcmap::EntryLocation,
+    Bibtex, BibtexError, GlobalItems,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    str::FromStr,
+};
+use tectonic_errors::prelude::*;
+
+/// The fields that an entry type requires and allows.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct EntrySchema {
+    required: Vec<Vec<String>>,
+    optional: Vec<String>,
+}
+
+/// The standard schemas, as `(type, required, optional)` in the syntax
+/// accepted by [`EntrySchema::from_str`].
+const STANDARD: &[(&str, &str, &str)] = &[
+    (
+        "article",
+        "author,title,journal,year",
+        "volume,number,pages,month,note",
+    ),
+    (
+        "book",
+        "author/editor,title,publisher,year",
+        "volume,number,series,address,edition,month,note",
+    ),
+    (
+        "booklet",
+        "title",
+        "author,howpublished,address,month,year,note",
+    ),
+    (
+        "conference",
+        "author,title,booktitle,year",
+        "editor,volume,number,series,pages,address,month,organization,publisher,note",
+    ),
+    (
+        "inbook",
+        "author/editor,title,chapter/pages,publisher,year",
+        "volume,number,series,type,address,edition,month,note",
+    ),
+    (
+        "incollection",
+        "author,title,booktitle,publisher,year",
+        "editor,volume,number,series,type,chapter,pages,address,edition,month,note",
+    ),
+    (
+        "inproceedings",
+        "author,title,booktitle,year",
+        "editor,volume,number,series,pages,address,month,organization,publisher,note",
+    ),
+    (
+        "manual",
+        "title",
+        "author,organization,address,edition,month,year,note",
+    ),
+    (
+        "mastersthesis",
+        "author,title,school,year",
+        "type,address,month,note",
+    ),
+    ("misc", "", "author,title,howpublished,month,year,note"),
+    (
+        "phdthesis",
+        "author,title,school,year",
+        "type,address,month,note",
+    ),
+    (
+        "proceedings",
+        "title,year",
+        "editor,volume,number,series,address,month,organization,publisher,note",
+    ),
+    (
+        "techreport",
+        "author,title,institution,year",
+        "type,number,address,month,note",
+    ),
+    ("unpublished", "author,title,note", "month,year"),
+];
+
+impl EntrySchema {
+    /// Fields that are allowed in entries of any type. Besides the `key`,
+    /// `crossref`, and `annote` fields described in the BibTeX documentation,
+    /// these are fields that are widely used by modern styles.
+    pub const UNIVERSAL: &'static [&'static str] = &[
+        "abstract", "annote", "crossref", "doi", "eprint", "isbn", "issn", "key", "keywords",
+        "language", "url",
+    ];
+
+    /// Create a schema from its required and optional fields.
+    ///
+    /// Each item of *required* is a group of alternatives, of which at least
+    /// one must be present, such as `["author", "editor"]`. Field names are
+    /// matched case-insensitively.
+    pub fn new<R, O>(required: R, optional: O) -> Self
+    where
+        R: IntoIterator,
+        R::Item: IntoIterator,
+        <R::Item as IntoIterator>::Item: AsRef<str>,
+        O: IntoIterator,
+        O::Item: AsRef<str>,
+    {
+        EntrySchema {
+            required: required
+                .into_iter()
+                .map(|group| {
+                    group
+                        .into_iter()
+                        .map(|f| f.as_ref().to_ascii_lowercase())
+                        .collect()
+                })
+                .filter(|group: &Vec<String>| !group.is_empty())
+                .collect(),
+            optional: optional
+                .into_iter()
+                .map(|f| f.as_ref().to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// Get the standard schema of an entry type, if there is one.
+    pub fn standard(entry_type: &str) -> Option<EntrySchema> {
+        let entry_type = entry_type.to_ascii_lowercase();
+
+        STANDARD
+            .iter()
+            .find(|(name, _, _)| *name == entry_type)
+            .map(|(_, required, optional)| {
+                format!("{required}:{optional}")
+                    .parse()
+                    .expect("standard schemas should parse")
+            })
+    }
+
+    /// The names of the entry types that have standard schemas.
+    pub fn standard_types() -> impl Iterator<Item = &'static str> {
+        STANDARD.iter().map(|(name, _, _)| *name)
+    }
+
+    /// The groups of required fields.
+    pub fn required(&self) -> &[Vec<String>] {
+        &self.required
+    }
+
+    /// The optional fields.
+    pub fn optional(&self) -> &[String] {
+        &self.optional
+    }
+
+    /// Whether a field, given in lowercase, is allowed by this schema.
+    pub fn allows(&self, field: &str) -> bool {
+        EntrySchema::UNIVERSAL.contains(&field)
+            || self.optional.iter().any(|f| f == field)
+            || self.required.iter().flatten().any(|f| f == field)
+    }
+}
+
+impl FromStr for EntrySchema {
+    type Err = Error;
+
+    /// Parse a schema such as `author/editor,title,year:volume,note`, giving
+    /// the comma-separated required fields, with alternatives separated by
+    /// slashes, and then after a colon the optional fields. Either list may
+    /// be empty, and the colon may be omitted if there are no optional
+    /// fields.
+    fn from_str(s: &str) -> Result<Self> {
+        let (required, optional) = s.split_once(':').unwrap_or((s, ""));
+
+        let names = |list: &str| -> Result<Vec<String>> {
+            list.split([',', '/'])
+                .map(str::trim)
+                .filter(|f| !f.is_empty())
+                .map(|f| {
+                    ensure!(
+                        f.bytes()
+                            .all(|b| b.is_ascii_graphic() && !b"\"#%'(),={}:/".contains(&b)),
+                        "`{f}` is not a valid field name"
+                    );
+                    Ok(f.to_ascii_lowercase())
+                })
+                .collect()
+        };
+
+        ensure!(
+            !optional.contains(':'),
+            "entry schema `{s}` has more than one `:`"
+        );
+
+        let mut groups = Vec::new();
+
+        for group in required.split(',').filter(|g| !g.trim().is_empty()) {
+            groups.push(names(group)?);
+        }
+
+        Ok(EntrySchema::new(groups, names(optional)?))
+    }
+}
+
+impl fmt::Display for EntrySchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let required: Vec<String> = self.required.iter().map(|g| g.join("/")).collect();
+        write!(f, "{}:{}", required.join(","), self.optional.join(","))
+    }
+}
+
+/// The kind of problem found by linting an entry.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum LintProblem {
+    /// None of a group of required fields is present. The group has more
+    /// than one member if any of them would do.
+    MissingField(Vec<String>),
+
+    /// A field that the schema doesn't allow is present.
+    UnexpectedField(String),
+}
+
+impl fmt::Display for LintProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LintProblem::MissingField(group) => {
+                write!(f, "missing required field {}", group.join(" or "))
+            }
+            LintProblem::UnexpectedField(field) => write!(f, "unexpected field {field}"),
+        }
+    }
+}
+
+/// A problem found by linting an entry against the schema of its type.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EntryLint {
+    /// The cite key of the entry.
+    pub cite_key: String,
+
+    /// The name of the `.bib` file containing the entry.
+    pub file: String,
+
+    /// The line of the `.bib` file where the entry starts.
+    pub line: u32,
+
+    /// The type of the entry, in lowercase.
+    pub entry_type: String,
+
+    /// The problem.
+    pub problem: LintProblem,
+}
+
+impl EntryLint {
+    fn new(location: EntryLocation, entry_type: &str, problem: LintProblem) -> Self {
+        EntryLint {
+            cite_key: location.cite_key,
+            file: location.file,
+            line: location.line,
+            entry_type: entry_type.to_owned(),
+            problem,
+        }
+    }
+}
+
+impl fmt::Display for EntryLint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: {} in {} entry {}",
+            self.file, self.line, self.problem, self.entry_type, self.cite_key
+        )
+    }
+}
+
+/// The type and fields of an entry as it appeared in a `.bib` file,
+/// including fields that the style doesn't declare.
+#[derive(Debug, Default)]
+pub(crate) struct RecordedEntry {
+    pub entry_type: String,
+    pub fields: Vec<String>,
+}
+
+/// Lint all of the entries that have been read against the configured
+/// schemas.
+pub(crate) fn lint_entries(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    let entries = std::mem::take(&mut ctx.recorded_entries);
+    let mut schemas: HashMap<String, Option<EntrySchema>> = HashMap::new();
+    let cite_numbers: HashMap<String, usize> = (0..globals.cites.num_cites())
+        .map(|c| {
+            let key = globals.pool.get_str(globals.cites.get_cite(c));
+            (String::from_utf8_lossy(key).to_ascii_lowercase(), c)
+        })
+        .collect();
+
+    for cite in 0..globals.cites.num_cites() {
+        let Some(location) = entry_location(globals.pool, globals.cites, cite) else {
+            continue;
+        };
+
+        let Some(entry) = entries.get(&location.cite_key.to_ascii_lowercase()) else {
+            continue;
+        };
+
+        let schema = schemas
+            .entry(entry.entry_type.clone())
+            .or_insert_with(|| {
+                ctx.config
+                    .entry_schemas
+                    .iter()
+                    .rev()
+                    .find(|(t, _)| *t == entry.entry_type)
+                    .map(|(_, s)| s.clone())
+                    .or_else(|| EntrySchema::standard(&entry.entry_type))
+            })
+            .clone();
+
+        let Some(schema) = schema else {
+            continue;
+        };
+
+        // Required fields may be inherited through cross references, which
+        // BibTeX always records in the predefined `crossref` field.
+        let mut present: HashSet<&str> = entry.fields.iter().map(String::as_str).collect();
+        let mut seen = HashSet::new();
+        let mut cur = cite;
+
+        while let Some(parent) =
+            field_value(globals.pool, globals.hash, globals.other, cur, b"crossref")
+        {
+            let parent = String::from_utf8_lossy(parent).to_ascii_lowercase();
+
+            let Some(parent_entry) = entries.get(&parent) else {
+                break;
+            };
+
+            if !seen.insert(parent.clone()) {
+                break;
+            }
+
+            present.extend(parent_entry.fields.iter().map(String::as_str));
+
+            let Some(&next) = cite_numbers.get(&parent) else {
+                break;
+            };
+
+            cur = next;
+        }
+
+        let mut problems = Vec::new();
+
+        for group in schema.required() {
+            if !group.iter().any(|f| present.contains(f.as_str())) {
+                problems.push(LintProblem::MissingField(group.clone()));
+            }
+        }
+
+        for field in &entry.fields {
+            if !schema.allows(field) {
+                problems.push(LintProblem::UnexpectedField(field.clone()));
+            }
+        }
+
+        for problem in problems {
+            let lint = EntryLint::new(location.clone(), &entry.entry_type, problem);
+            ctx.diagnostics.locate_entry(location.clone());
+            ctx.write_logs(&format!("Warning--{} in {}\n", lint.problem, lint.cite_key));
+            ctx.diagnostics.end_message();
+            ctx.write_logs(&format!("--line {} of file {}\n", lint.line, lint.file));
+            ctx.mark_warning();
+            ctx.entry_lints.push(lint);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schemas() {
+        for entry_type in EntrySchema::standard_types() {
+            assert!(EntrySchema::standard(entry_type).is_some(), "{entry_type}");
+        }
+
+        let book = EntrySchema::standard("Book").unwrap();
+        assert_eq!(book.required()[0], ["author", "editor"]);
+        assert!(book.allows("series"));
+        assert!(book.allows("doi"));
+        assert!(!book.allows("journal"));
+        assert!(EntrySchema::standard("online").is_none());
+
+        let misc = EntrySchema::standard("misc").unwrap();
+        assert!(misc.required().is_empty());
+
+        let custom: EntrySchema = "Author/editor, title,url:urldate".parse().unwrap();
+        assert_eq!(custom.to_string(), "author/editor,title,url:urldate");
+        assert_eq!(
+            custom,
+            EntrySchema::new(
+                [vec!["author", "editor"], vec!["title"], vec!["url"]],
+                ["urldate"]
+            )
+        );
+        assert_eq!(
+            "title".parse::<EntrySchema>().unwrap().to_string(),
+            "title:"
+        );
+
+        assert!("title:note:year".parse::<EntrySchema>().is_err());
+        assert!("ti{tle".parse::<EntrySchema>().is_err());
+
+        assert_eq!(
+            LintProblem::MissingField(vec!["author".into(), "editor".into()]).to_string(),
+            "missing required field author or editor"
+        );
+    }
+}
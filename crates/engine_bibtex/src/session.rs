@@ -0,0 +1,401 @@
+//! Running the commands of a style under the control of embedding code.
+//!
+//! Normally, the commands of a `.bst` file run as soon as they're read. When a
+//! custom driver is used, the declarative commands (`ENTRY`, `FUNCTION`,
+//! `MACRO`, `INTEGERS`, and `STRINGS`) still take effect as they're read, but
+//! the ones that run the style's code (`READ`, `EXECUTE`, `ITERATE`, `REVERSE`,
+//! and `SORT`) are captured instead. Once the whole style has been read, the
+//! driver gets a [`StyleSession`] through which it can run those commands, or
+//! any others, in whatever order it likes, and inspect the entries and global
+//! variables in between.
+
+use crate::{
+    bst::{run_execute, run_iterate, run_read, run_reverse, run_sort},
+    exec::ExecCtx,
+    hash::{BstFn, HashData, HashExtra},
+    metrics::Phase,
+    pool::StringPool,
+    BibtexError, CiteNumber, GlobalItems, HashPointer, StrIlk,
+};
+use std::{
+    fmt::{Display, Error as FmtError, Formatter},
+    time::Instant,
+};
+use tectonic_errors::prelude::*;
+
+/// A style command that runs the style's code.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StyleCommand {
+    /// `READ`: read the database files.
+    Read,
+
+    /// `EXECUTE {function}`: run the function once.
+    Execute(String),
+
+    /// `ITERATE {function}`: run the function for each entry, in the current
+    /// order.
+    Iterate(String),
+
+    /// `REVERSE {function}`: run the function for each entry, in reverse
+    /// order.
+    Reverse(String),
+
+    /// `SORT`: sort the entries by their `sort.key$`.
+    Sort,
+}
+
+impl Display for StyleCommand {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            StyleCommand::Read => write!(f, "READ"),
+            StyleCommand::Execute(func) => write!(f, "EXECUTE {{{func}}}"),
+            StyleCommand::Iterate(func) => write!(f, "ITERATE {{{func}}}"),
+            StyleCommand::Reverse(func) => write!(f, "REVERSE {{{func}}}"),
+            StyleCommand::Sort => write!(f, "SORT"),
+        }
+    }
+}
+
+/// The operations behind a [`StyleSession`]. This lets the session hide the
+/// lifetimes of the engine state that it borrows.
+trait SessionOps {
+    fn run(&mut self, command: &StyleCommand) -> Result<()>;
+    fn cite_keys(&self) -> Vec<String>;
+    fn entry_type(&self, cite_key: &str) -> Option<String>;
+    fn field(&self, cite_key: &str, name: &str) -> Option<String>;
+    fn entry_str(&self, cite_key: &str, name: &str) -> Option<String>;
+    fn entry_int(&self, cite_key: &str, name: &str) -> Option<i64>;
+    fn global_str(&self, name: &str) -> Option<String>;
+    fn global_int(&self, name: &str) -> Option<i64>;
+}
+
+/// Access to a style whose commands are run by embedding code.
+///
+/// This is passed to the driver given to
+/// [`BibtexEngine::process_with_driver()`](crate::BibtexEngine::process_with_driver).
+/// The entries can only be inspected once the database has been read, and
+/// are listed in the order that `ITERATE` would visit them, which changes
+/// when they're sorted. Entry variables are those declared by the style's
+/// `ENTRY` command, and keep the values that the style's code last gave
+/// them.
+pub struct StyleSession<'s> {
+    commands: Vec<StyleCommand>,
+    ops: &'s mut (dyn SessionOps + 's),
+}
+
+impl StyleSession<'_> {
+    /// Get the commands that the style file would have run, in order.
+    pub fn commands(&self) -> &[StyleCommand] {
+        &self.commands
+    }
+
+    /// Run a command.
+    ///
+    /// The database must be read before any other command can run, and can
+    /// only be read once. Functions are named as in the style file, and may be
+    /// builtins or functions defined by the style. Errors in the style's code
+    /// are reported in the log as usual, and don't cause this to fail; only
+    /// misuse of this API, or fatal errors that stop the engine, do so. After
+    /// a fatal error, no further commands can run.
+    pub fn run(&mut self, command: &StyleCommand) -> Result<()> {
+        self.ops.run(command)
+    }
+
+    /// Run all of the commands of the style file, in order, which is what
+    /// happens when no driver is used.
+    pub fn run_style(&mut self) -> Result<()> {
+        for command in &self.commands {
+            self.ops.run(command)?;
+        }
+
+        Ok(())
+    }
+
+    /// Read the database files. Equivalent to running [`StyleCommand::Read`].
+    pub fn read(&mut self) -> Result<()> {
+        self.run(&StyleCommand::Read)
+    }
+
+    /// Run a function once. Equivalent to running [`StyleCommand::Execute`].
+    pub fn execute(&mut self, function: &str) -> Result<()> {
+        self.run(&StyleCommand::Execute(function.to_owned()))
+    }
+
+    /// Run a function for each entry. Equivalent to running
+    /// [`StyleCommand::Iterate`].
+    pub fn iterate(&mut self, function: &str) -> Result<()> {
+        self.run(&StyleCommand::Iterate(function.to_owned()))
+    }
+
+    /// Run a function for each entry, in reverse order. Equivalent to running
+    /// [`StyleCommand::Reverse`].
+    pub fn reverse(&mut self, function: &str) -> Result<()> {
+        self.run(&StyleCommand::Reverse(function.to_owned()))
+    }
+
+    /// Sort the entries. Equivalent to running [`StyleCommand::Sort`].
+    pub fn sort(&mut self) -> Result<()> {
+        self.run(&StyleCommand::Sort)
+    }
+
+    /// Get the cite keys of the entries, in their current order. This is empty
+    /// until the database has been read.
+    pub fn cite_keys(&self) -> Vec<String> {
+        self.ops.cite_keys()
+    }
+
+    /// Get the type of an entry, such as `article`. This is the empty string
+    /// if the entry was cited but not found in the database.
+    pub fn entry_type(&self, cite_key: &str) -> Option<String> {
+        self.ops.entry_type(cite_key)
+    }
+
+    /// Get the value of a field of an entry. The field must be declared by the
+    /// style's `ENTRY` command.
+    pub fn field(&self, cite_key: &str, name: &str) -> Option<String> {
+        self.ops.field(cite_key, name)
+    }
+
+    /// Get the value of a string entry variable, such as `sort.label`.
+    pub fn entry_str(&self, cite_key: &str, name: &str) -> Option<String> {
+        self.ops.entry_str(cite_key, name)
+    }
+
+    /// Get the value of an integer entry variable.
+    pub fn entry_int(&self, cite_key: &str, name: &str) -> Option<i64> {
+        self.ops.entry_int(cite_key, name)
+    }
+
+    /// Get the value of a global string variable, declared by the style's
+    /// `STRINGS` command.
+    pub fn global_str(&self, name: &str) -> Option<String> {
+        self.ops.global_str(name)
+    }
+
+    /// Get the value of a global integer variable, declared by the style's
+    /// `INTEGERS` command, or one of the builtins such as `entry.max$`.
+    pub fn global_int(&self, name: &str) -> Option<i64> {
+        self.ops.global_int(name)
+    }
+}
+
+/// The driver of a style, as passed to the engine. The public API keeps track
+/// of the driver's own result.
+pub(crate) type StyleDriver<'d> = dyn FnMut(&mut StyleSession<'_>) + 'd;
+
+struct Session<'r, 'a, 'bib, 'cbs, 'g> {
+    ctx: &'r mut ExecCtx<'a, 'bib, 'cbs>,
+    globals: &'r mut GlobalItems<'g>,
+    /// Whether the database has been read successfully, so that the entries
+    /// can be inspected.
+    read: bool,
+    /// The error that stopped the engine, if any.
+    stopped: Option<BibtexError>,
+}
+
+impl Session<'_, '_, '_, '_, '_> {
+    /// Look up a function that a command can run.
+    fn function(&self, name: &str) -> Result<HashPointer> {
+        let res = self.globals.hash.lookup_str(
+            self.globals.pool,
+            name.to_ascii_lowercase().as_bytes(),
+            StrIlk::BstFn,
+        );
+
+        match self.globals.hash.node(res.loc).extra {
+            HashExtra::BstFn(BstFn::Builtin(_) | BstFn::Custom(_) | BstFn::Wizard(_))
+                if res.exists =>
+            {
+                Ok(res.loc)
+            }
+            _ => bail!("`{name}` is not a function of the style"),
+        }
+    }
+
+    /// Find the cite number of an entry, if the database has been read.
+    fn find_cite(&self, cite_key: &str) -> Option<CiteNumber> {
+        if !self.read {
+            return None;
+        }
+
+        let pool = &*self.globals.pool;
+        let cites = &*self.globals.cites;
+        (0..cites.num_cites()).find(|&i| pool.get_str(cites.get_cite(i)) == cite_key.as_bytes())
+    }
+
+    /// Look up an entry and a variable or field of it.
+    fn lookup(&self, cite_key: &str, name: &str) -> Option<(CiteNumber, BstFn)> {
+        let cite = self.find_cite(cite_key)?;
+        let f = lookup_fn(self.globals.pool, self.globals.hash, name)?;
+        Some((cite, f))
+    }
+}
+
+impl SessionOps for Session<'_, '_, '_, '_, '_> {
+    fn run(&mut self, command: &StyleCommand) -> Result<()> {
+        if self.stopped.is_some() {
+            bail!("can't run `{command}`: the engine stopped after a fatal error");
+        }
+
+        match command {
+            StyleCommand::Read if self.ctx.read_performed => {
+                bail!("the database has already been read")
+            }
+            StyleCommand::Read => {}
+            _ if !self.ctx.read_performed => {
+                bail!("can't run `{command}` before the database is read")
+            }
+            _ => {}
+        }
+
+        let started = Instant::now();
+        let (phase, result) = match command {
+            StyleCommand::Read => (Phase::Read, run_read(self.ctx, self.globals)),
+            StyleCommand::Execute(func) => {
+                let fn_loc = self.function(func)?;
+                (Phase::Execute, run_execute(self.ctx, self.globals, fn_loc))
+            }
+            StyleCommand::Iterate(func) => {
+                let fn_loc = self.function(func)?;
+                (Phase::Execute, run_iterate(self.ctx, self.globals, fn_loc))
+            }
+            StyleCommand::Reverse(func) => {
+                let fn_loc = self.function(func)?;
+                (Phase::Execute, run_reverse(self.ctx, self.globals, fn_loc))
+            }
+            StyleCommand::Sort => {
+                run_sort(&self.ctx.config, &self.ctx.char_info, self.globals);
+                (Phase::Sort, Ok(()))
+            }
+        };
+        self.ctx.run_metrics.add_time(phase, started.elapsed());
+
+        if let Err(e) = result {
+            self.stopped = Some(e);
+            bail!("the engine stopped while running `{command}`");
+        }
+
+        if *command == StyleCommand::Read {
+            self.read = true;
+        }
+
+        Ok(())
+    }
+
+    fn cite_keys(&self) -> Vec<String> {
+        if !self.read {
+            return Vec::new();
+        }
+
+        let cites = &*self.globals.cites;
+
+        (0..cites.num_cites())
+            .map(|i| cites.get_cite(cites.info(i).to_raw_dangerous()))
+            .map(|key| String::from_utf8_lossy(self.globals.pool.get_str(key)).into_owned())
+            .collect()
+    }
+
+    fn entry_type(&self, cite_key: &str) -> Option<String> {
+        let cite = self.find_cite(cite_key)?;
+        let ty = self.globals.cites.get_type(cite);
+
+        if ty == HashData::undefined() || ty == 0 {
+            Some(String::new())
+        } else {
+            let name = self.globals.pool.get_str(self.globals.hash.text(ty));
+            Some(String::from_utf8_lossy(name).into_owned())
+        }
+    }
+
+    fn field(&self, cite_key: &str, name: &str) -> Option<String> {
+        let (cite, BstFn::Field(field)) = self.lookup(cite_key, name)? else {
+            return None;
+        };
+
+        let other = &*self.globals.other;
+        let value = other.field(cite * other.num_fields() + field);
+        (!value.is_invalid())
+            .then(|| String::from_utf8_lossy(self.globals.pool.get_str(value)).into_owned())
+    }
+
+    fn entry_str(&self, cite_key: &str, name: &str) -> Option<String> {
+        let (cite, BstFn::StrEntry(entry)) = self.lookup(cite_key, name)? else {
+            return None;
+        };
+
+        let entries = &*self.globals.entries;
+        let value = entries.strs(cite * entries.num_ent_strs() + entry);
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+
+    fn entry_int(&self, cite_key: &str, name: &str) -> Option<i64> {
+        let (cite, BstFn::IntEntry(entry)) = self.lookup(cite_key, name)? else {
+            return None;
+        };
+
+        let entries = &*self.globals.entries;
+        Some(entries.ints(cite * entries.num_ent_ints() + entry))
+    }
+
+    fn global_str(&self, name: &str) -> Option<String> {
+        let BstFn::StrGlbl(glb_ptr) = lookup_fn(self.globals.pool, self.globals.hash, name)? else {
+            return None;
+        };
+
+        let globals = &*self.globals.globals;
+        let str_ptr = globals.str_ptr(glb_ptr);
+        let value = if str_ptr.is_invalid() {
+            globals.str(glb_ptr)
+        } else {
+            self.globals.pool.get_str(str_ptr)
+        };
+
+        Some(String::from_utf8_lossy(value).into_owned())
+    }
+
+    fn global_int(&self, name: &str) -> Option<i64> {
+        match lookup_fn(self.globals.pool, self.globals.hash, name)? {
+            BstFn::IntGlbl(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+fn lookup_fn(pool: &StringPool, hash: &HashData, name: &str) -> Option<BstFn> {
+    let res = hash.lookup_str(pool, name.to_ascii_lowercase().as_bytes(), StrIlk::BstFn);
+
+    match &hash.node(res.loc).extra {
+        HashExtra::BstFn(f) if res.exists => Some(*f),
+        _ => None,
+    }
+}
+
+/// Hand the captured commands of the style to the driver. If the engine
+/// stopped while the driver was running commands, this returns the error that
+/// stopped it.
+pub(crate) fn drive_style(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    globals: &mut GlobalItems<'_>,
+    driver: &mut StyleDriver<'_>,
+) -> Result<(), BibtexError> {
+    let commands = ctx.captured_commands.take().unwrap_or_default();
+
+    let mut ops = Session {
+        ctx,
+        globals,
+        read: false,
+        stopped: None,
+    };
+
+    let mut session = StyleSession {
+        commands,
+        ops: &mut ops,
+    };
+
+    driver(&mut session);
+
+    match ops.stopped {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
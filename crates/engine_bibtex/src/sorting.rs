@@ -0,0 +1,308 @@
+//! Sort schemes computed by the engine rather than by the style.
+//!
+//! Classic styles decide the order of the bibliography themselves: a
+//! `presort` function stores a `sort.key$` for each entry, and `SORT` orders
+//! the entries by it. Changing the order means editing the style. A
+//! [`SortScheme`] instead has the engine compute each entry's key from its
+//! fields, in the manner of biblatex's sorting templates, and `SORT` then
+//! orders the entries by that key, ignoring `sort.key$`. Everything else that
+//! the style does is unchanged, so labels and other computed values stay as
+//! the style makes them.
+//!
+//! The parts of a scheme are compared in turn, and entries lacking a part
+//! come after those that have it. Entries with equal keys keep their order.
+
+use crate::{
+    bbl::{sort_text, SortKey},
+    biblatex::parse_date,
+    char_info::CharInfo,
+    cite::CiteInfo,
+    collate::Locale,
+    exec::field_value,
+    hash::HashData,
+    names::{format_nth_name, num_names},
+    other::OtherData,
+    pool::StringPool,
+    translit::Transliteration,
+    CiteNumber,
+};
+use std::{
+    fmt::{Debug, Error as FmtError, Formatter},
+    str::FromStr,
+    sync::Arc,
+};
+use tectonic_errors::prelude::*;
+
+/// How names are formatted for sorting, as in the `sort.format.names`
+/// function of the standard styles.
+const NAME_SPEC: &[u8] = b"{vv{ } }{ll{ }}{  ff{ }}{  jj{ }}";
+
+/// Words dropped from the start of titles for sorting.
+const TITLE_ARTICLES: &[&[u8]] = &[b"a ", b"an ", b"the "];
+
+/// The type of a function that computes part of a sort key.
+type KeyFn = Arc<dyn Fn(&SortEntry<'_>) -> Option<String> + Send + Sync>;
+
+/// The function behind a [`SortPart::Custom`].
+#[derive(Clone)]
+pub struct SortKeyFn(KeyFn);
+
+impl SortKeyFn {
+    /// Wrap a function that computes part of the sort key of an entry, or
+    /// returns `None` if the entry lacks it.
+    pub fn new<F>(f: F) -> Self
+    where
+        F: Fn(&SortEntry<'_>) -> Option<String> + Send + Sync + 'static,
+    {
+        SortKeyFn(Arc::new(f))
+    }
+}
+
+impl Debug for SortKeyFn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.write_str("SortKeyFn")
+    }
+}
+
+/// One part of a [`SortScheme`].
+#[derive(Clone, Debug)]
+pub enum SortPart {
+    /// The names of the authors, or if there are none, of the editors, each
+    /// written as von, Last, First, and Jr parts, as the standard styles sort
+    /// them. Entries with neither use their `key` field.
+    Name,
+
+    /// The year, from the `year` field, or if there is none, from an ISO 8601
+    /// `date` field such as `2024-05-01`.
+    Year,
+
+    /// The title, without a leading "A", "An", or "The".
+    Title,
+
+    /// The value of the named field, which is matched case-insensitively.
+    Field(String),
+
+    /// A key computed by embedding code.
+    Custom(SortKeyFn),
+}
+
+/// A scheme by which `SORT` orders the entries, in place of their
+/// `sort.key$` values.
+///
+/// Only fields that the style declares in its `ENTRY` command are available,
+/// as with [`EntryOrder`](crate::EntryOrder). Text is normalized like the
+/// `purify$` builtin does, lowercased, transliterated if
+/// [transliteration](crate::BibtexEngine::transliteration) is enabled, and
+/// compared in the order of the [collation](crate::BibtexEngine::collation)
+/// if one is set.
+#[derive(Clone, Debug)]
+pub struct SortScheme {
+    parts: Vec<SortPart>,
+}
+
+impl SortScheme {
+    /// Create a scheme that compares the given parts in turn.
+    pub fn new(parts: Vec<SortPart>) -> Self {
+        SortScheme { parts }
+    }
+
+    /// By name, then year, then title, much as the standard styles sort.
+    /// This is biblatex's `nyt` scheme.
+    pub fn author_year() -> Self {
+        SortScheme::new(vec![SortPart::Name, SortPart::Year, SortPart::Title])
+    }
+
+    /// By name, then title, then year. This is biblatex's `nty` scheme.
+    pub fn nty() -> Self {
+        SortScheme::new(vec![SortPart::Name, SortPart::Title, SortPart::Year])
+    }
+
+    /// By year, then name, then title. This is biblatex's `ynt` scheme.
+    pub fn ynt() -> Self {
+        SortScheme::new(vec![SortPart::Year, SortPart::Name, SortPart::Title])
+    }
+
+    /// The parts of the scheme.
+    pub fn parts(&self) -> &[SortPart] {
+        &self.parts
+    }
+
+    /// Compute the key of an entry.
+    fn key(&self, entry: &SortEntry<'_>, collation: Option<Locale>, chars: &CharInfo) -> SortKey {
+        let collate = |text: Vec<u8>| match collation {
+            Some(locale) => locale.sort_key(&text),
+            None => chars.sort_key(&text),
+        };
+
+        SortKey::values(self.parts.iter().map(|part| {
+            let text = match part {
+                SortPart::Name => entry.names(),
+                SortPart::Year => entry.year(),
+                SortPart::Title => entry.raw_field(b"title").map(|title| {
+                    let title = sort_text(&title);
+                    let article = TITLE_ARTICLES.iter().find(|a| title.starts_with(a));
+                    title[article.map_or(0, |a| a.len())..].to_vec()
+                }),
+                SortPart::Field(name) => entry
+                    .raw_field(name.to_ascii_lowercase().as_bytes())
+                    .map(|v| sort_text(&v)),
+                SortPart::Custom(f) => (f.0)(entry).map(|v| sort_text(v.as_bytes())),
+            };
+
+            text.map(collate)
+        }))
+    }
+}
+
+impl FromStr for SortScheme {
+    type Err = Error;
+
+    /// Parse a scheme from one of the presets `author-year` (or `nyt`),
+    /// `nty`, and `ynt`, or from a comma-separated list of the parts `name`,
+    /// `year`, `title`, and `field:<name>`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "author-year" | "nyt" => return Ok(SortScheme::author_year()),
+            "nty" => return Ok(SortScheme::nty()),
+            "ynt" => return Ok(SortScheme::ynt()),
+            _ => {}
+        }
+
+        let parts = s
+            .split(',')
+            .map(|part| match part.trim() {
+                "name" => Ok(SortPart::Name),
+                "year" => Ok(SortPart::Year),
+                "title" => Ok(SortPart::Title),
+                p => match p.strip_prefix("field:") {
+                    Some(name) if !name.is_empty() => Ok(SortPart::Field(name.to_owned())),
+                    _ => bail!(
+                        "unrecognized sort scheme `{s}`; expected `author-year`, `nty`, `ynt`, \
+                         or a list of `name`, `year`, `title`, and `field:<name>`"
+                    ),
+                },
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(SortScheme::new(parts))
+    }
+}
+
+/// An entry whose sort key is being computed, as seen by a
+/// [`SortPart::Custom`].
+pub struct SortEntry<'a> {
+    pool: &'a StringPool,
+    hash: &'a HashData,
+    other: &'a OtherData,
+    cites: &'a CiteInfo,
+    translit: &'a Transliteration,
+    cite: CiteNumber,
+}
+
+impl SortEntry<'_> {
+    /// The cite key of the entry, as written in its database.
+    pub fn cite_key(&self) -> String {
+        String::from_utf8_lossy(self.pool.get_str(self.cites.get_cite(self.cite))).into_owned()
+    }
+
+    /// The value of a field of the entry, if the style declares the field and
+    /// the entry has it. The name is matched case-insensitively. The value
+    /// is transliterated if transliteration is enabled.
+    pub fn field(&self, name: &str) -> Option<String> {
+        self.raw_field(name.to_ascii_lowercase().as_bytes())
+            .map(|v| String::from_utf8_lossy(&v).into_owned())
+    }
+
+    fn raw_field(&self, name: &[u8]) -> Option<Vec<u8>> {
+        field_value(self.pool, self.hash, self.other, self.cite, name)
+            .map(|v| self.translit.apply(v).into_owned())
+    }
+
+    /// The normalized names of the authors or editors, separated by two
+    /// spaces, which can't occur within a normalized name.
+    fn names(&self) -> Option<Vec<u8>> {
+        let Some(names) = self
+            .raw_field(b"author")
+            .or_else(|| self.raw_field(b"editor"))
+        else {
+            return self.raw_field(b"key").map(|key| sort_text(&key));
+        };
+
+        // Problems with the names are for the style to report.
+        let mut problems = Vec::new();
+        let formatted: Vec<Vec<u8>> = (1..=num_names(&names, &mut problems))
+            .map(|n| sort_text(&format_nth_name(&names, n, NAME_SPEC, &mut problems)))
+            .collect();
+        Some(formatted.join(&b"  "[..]))
+    }
+
+    fn year(&self) -> Option<Vec<u8>> {
+        if let Some(year) = self.raw_field(b"year") {
+            return Some(sort_text(&year));
+        }
+
+        let date = self.raw_field(b"date")?;
+        let start = String::from_utf8_lossy(&date);
+        let (year, _, _) = parse_date(start.split('/').next()?)?;
+        Some(format!("{year:04}").into_bytes())
+    }
+}
+
+/// Sort the entries by the keys of *scheme*, as `SORT` does when a scheme is
+/// set.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn sort_by_scheme(
+    scheme: &SortScheme,
+    translit: &Transliteration,
+    collation: Option<Locale>,
+    chars: &CharInfo,
+    pool: &StringPool,
+    hash: &HashData,
+    other: &OtherData,
+    cites: &mut CiteInfo,
+) {
+    let keys: Vec<SortKey> = (0..cites.num_cites())
+        .map(|cite| {
+            let entry = SortEntry {
+                pool,
+                hash,
+                other,
+                cites,
+                translit,
+                cite,
+            };
+            scheme.key(&entry, collation, chars)
+        })
+        .collect();
+
+    cites.sort_info_by_key(|cite| &keys[cite]);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schemes() {
+        let parts = |s: &str| -> Vec<String> {
+            s.parse::<SortScheme>()
+                .unwrap()
+                .parts()
+                .iter()
+                .map(|p| format!("{p:?}"))
+                .collect()
+        };
+
+        assert_eq!(parts("author-year"), ["Name", "Year", "Title"]);
+        assert_eq!(parts("nyt"), parts("author-year"));
+        assert_eq!(parts("nty"), ["Name", "Title", "Year"]);
+        assert_eq!(parts("ynt"), ["Year", "Name", "Title"]);
+        assert_eq!(
+            parts("year, field:Volume,title"),
+            ["Year", "Field(\"Volume\")", "Title"]
+        );
+        assert!("".parse::<SortScheme>().is_err());
+        assert!("name,field:".parse::<SortScheme>().is_err());
+        assert!("tny".parse::<SortScheme>().is_err());
+    }
+}
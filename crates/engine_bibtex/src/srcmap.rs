@@ -0,0 +1,108 @@
+//! Source maps from the lines of the `.bbl` output back to the `.bib` entries
+//! that produced them.
+//!
+//! When enabled, the engine writes a sidecar file next to the `.bbl` file,
+//! named by appending `.map` to its name. After a comment line describing the
+//! columns, it contains one record for each run of consecutive `.bbl` lines
+//! that were written while the style processed a single entry, with the
+//! columns separated by tabs:
+//!
+//! ```text
+//! % first-line  last-line  cite-key  bib-file  bib-line
+//! 4  7  knuth84  refs.bib  12
+//! ```
+//!
+//! Line numbers are 1-based, and the `.bib` line is the one where the `@` that
+//! starts the entry appears. Lines written outside of `ITERATE` and `REVERSE`
+//! commands, such as the preamble and the closing of the bibliography, aren't
+//! listed. This lets editor tooling jump from a formatted bibliography item, or
+//! from a LaTeX error reported inside the `.bbl` file, to the source entry.
+
+use std::fmt::Write;
+
+/// The location of the `.bib` entry that the style is currently processing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub(crate) struct EntryLocation {
+    pub cite_key: String,
+    pub file: String,
+    pub line: u32,
+}
+
+/// Accumulates a source map while the engine runs.
+#[derive(Default)]
+pub(crate) struct SourceMapRecorder {
+    current: Option<EntryLocation>,
+    /// Runs of `.bbl` lines, as `(first, last, entry)`.
+    runs: Vec<(usize, usize, EntryLocation)>,
+}
+
+impl SourceMapRecorder {
+    /// Set the entry that subsequent output comes from, if any.
+    pub(crate) fn set_entry(&mut self, entry: Option<EntryLocation>) {
+        self.current = entry;
+    }
+
+    /// Note that line number *bbl_line* of the `.bbl` file was just written.
+    pub(crate) fn line(&mut self, bbl_line: usize) {
+        let Some(entry) = &self.current else {
+            return;
+        };
+
+        if let Some((_, last, prev)) = self.runs.last_mut() {
+            if *last + 1 == bbl_line && prev == entry {
+                *last = bbl_line;
+                return;
+            }
+        }
+
+        self.runs.push((bbl_line, bbl_line, entry.clone()));
+    }
+
+    /// Render the text of the sidecar file.
+    pub(crate) fn render(&self) -> String {
+        let mut out = String::from("% first-line\tlast-line\tcite-key\tbib-file\tbib-line\n");
+
+        for (first, last, entry) in &self.runs {
+            let _ = writeln!(
+                out,
+                "{first}\t{last}\t{}\t{}\t{}",
+                entry.cite_key, entry.file, entry.line
+            );
+        }
+
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(key: &str, line: u32) -> EntryLocation {
+        EntryLocation {
+            cite_key: key.to_owned(),
+            file: "refs.bib".to_owned(),
+            line,
+        }
+    }
+
+    #[test]
+    fn test_runs() {
+        let mut rec = SourceMapRecorder::default();
+        rec.line(1);
+        rec.set_entry(Some(entry("a", 3)));
+        rec.line(2);
+        rec.line(3);
+        rec.set_entry(Some(entry("b", 10)));
+        rec.line(5);
+        rec.set_entry(None);
+        rec.line(6);
+
+        assert_eq!(
+            rec.render(),
+            "% first-line\tlast-line\tcite-key\tbib-file\tbib-line\n\
+             2\t3\ta\trefs.bib\t3\n\
+             5\t5\tb\trefs.bib\t10\n"
+        );
+    }
+}
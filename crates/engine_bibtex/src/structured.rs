@@ -0,0 +1,1269 @@
+//! Reading bibliography databases that aren't written in `.bib` syntax.
+//!
+//! If enabled with
+//! [`BibtexEngine::structured_databases()`](crate::BibtexEngine::structured_databases),
+//! a database named in `\bibdata` with a `.json` extension is read as a
+//! [CSL-JSON] array of items, and one with a `.yaml` or `.yml` extension is
+//! read as a [Hayagriva] YAML file. Each is converted into the text of an
+//! equivalent `.bib` file, which the engine then reads like any other, so
+//! that the entries can be formatted with classic `.bst` styles.
+//!
+//! The conversion maps the types and fields of the source format onto those
+//! of the standard BibTeX styles. Fields that have no BibTeX counterpart are
+//! dropped, and items that can't be converted, such as those whose keys
+//! BibTeX couldn't parse, are skipped with a warning.
+//!
+//! Only the parts of YAML that bibliography files use are supported: block
+//! and flow mappings and sequences, plain, quoted, and block scalars, and
+//! comments. Anchors, aliases, tags, and multiple documents are not.
+//!
+//! [CSL-JSON]: https://citeproc-js.readthedocs.io/en/latest/csl-json/markup.html
+//! [Hayagriva]: https://github.com/typst/hayagriva/blob/main/docs/file-format.md
+
+use serde_json::Value;
+use std::fmt::Write;
+
+/// The formats of databases that can be converted.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum DatabaseFormat {
+    CslJson,
+    Hayagriva,
+}
+
+impl DatabaseFormat {
+    /// Get the format of a database from the extension of its name, if it
+    /// isn't a `.bib` file.
+    pub(crate) fn of_name(name: &[u8]) -> Option<DatabaseFormat> {
+        let lower = name.to_ascii_lowercase();
+
+        if lower.ends_with(b".json") {
+            Some(DatabaseFormat::CslJson)
+        } else if lower.ends_with(b".yaml") || lower.ends_with(b".yml") {
+            Some(DatabaseFormat::Hayagriva)
+        } else {
+            None
+        }
+    }
+}
+
+/// The result of converting a database.
+#[derive(Debug, Default)]
+pub(crate) struct Converted {
+    /// The database in `.bib` syntax.
+    pub text: Vec<u8>,
+
+    /// Problems with individual items, which were skipped.
+    pub warnings: Vec<String>,
+}
+
+/// Convert the database *data* into `.bib` syntax.
+pub(crate) fn convert(format: DatabaseFormat, data: &[u8]) -> Result<Converted, String> {
+    let records = match format {
+        DatabaseFormat::CslJson => {
+            let value: Value = serde_json::from_slice(data).map_err(|e| e.to_string())?;
+            csl_records(&value)?
+        }
+
+        DatabaseFormat::Hayagriva => {
+            let text = std::str::from_utf8(data).map_err(|_| "the file isn't valid UTF-8")?;
+            hayagriva_records(&parse_yaml(text)?)?
+        }
+    };
+
+    let mut converted = Converted::default();
+    let mut text = String::new();
+
+    for record in records {
+        match record {
+            Ok(record) => record.write(&mut text),
+            Err(warning) => converted.warnings.push(warning),
+        }
+    }
+
+    converted.text = text.into_bytes();
+    Ok(converted)
+}
+
+/// An entry to be written in `.bib` syntax.
+#[derive(Debug, Default)]
+struct Record {
+    entry_type: &'static str,
+    key: String,
+    fields: Vec<(&'static str, FieldValue)>,
+}
+
+#[derive(Debug)]
+enum FieldValue {
+    /// Text, which is escaped and braced.
+    Text(String),
+
+    /// Names, which are escaped and joined with `and`.
+    Names(Vec<String>),
+
+    /// A macro name, such as that of a month.
+    Macro(&'static str),
+}
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+
+impl Record {
+    fn new(key: String) -> Result<Record, String> {
+        let bad = |c: char| c.is_whitespace() || "\",#%'(){}=".contains(c);
+
+        if key.is_empty() || key.contains(bad) {
+            return Err(format!(
+                "the key \"{key}\" can't be used in BibTeX, so its item was skipped"
+            ));
+        }
+
+        Ok(Record {
+            entry_type: "misc",
+            key,
+            fields: Vec::new(),
+        })
+    }
+
+    fn has(&self, field: &str) -> bool {
+        self.fields.iter().any(|(f, _)| *f == field)
+    }
+
+    /// Set a text field, unless it's already set or *value* is empty.
+    fn text(&mut self, field: &'static str, value: impl Into<String>) {
+        let value = value.into();
+
+        if !value.trim().is_empty() && !self.has(field) {
+            self.fields.push((field, FieldValue::Text(value)));
+        }
+    }
+
+    fn names(&mut self, field: &'static str, names: Vec<String>) {
+        if !names.is_empty() && !self.has(field) {
+            self.fields.push((field, FieldValue::Names(names)));
+        }
+    }
+
+    /// Set the `year` and `month` fields from a date such as `2020-05-01`.
+    fn date(&mut self, date: &str) {
+        let date = date.trim();
+        let digits = |s: &str| s.chars().take_while(|c| c.is_ascii_digit()).count();
+        let n = digits(date.trim_start_matches('-'));
+
+        if n == 0 {
+            self.text("year", date);
+            return;
+        }
+
+        let (year, rest) = date.split_at(n + usize::from(date.starts_with('-')));
+        self.text("year", year);
+
+        if let Some(rest) = rest.strip_prefix('-') {
+            self.month(&rest[..digits(rest)]);
+        }
+    }
+
+    fn month(&mut self, month: &str) {
+        if let Ok(m @ 1..=12) = month.parse::<usize>() {
+            if !self.has("month") {
+                self.fields
+                    .push(("month", FieldValue::Macro(MONTHS[m - 1])));
+            }
+        }
+    }
+
+    /// Set the `pages` field, using an en dash between page numbers.
+    fn pages(&mut self, pages: &str) {
+        let mut value = String::new();
+        let mut chars = pages.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            value.push(c);
+
+            if (c == '-' || c == '\u{2013}') && chars.peek() != Some(&'-') {
+                value.pop();
+                value.push_str("--");
+            }
+        }
+
+        self.text("pages", value);
+    }
+
+    fn write(&self, out: &mut String) {
+        writeln!(out, "@{}{{{},", self.entry_type, self.key).unwrap();
+
+        for (field, value) in &self.fields {
+            match value {
+                FieldValue::Text(t) => writeln!(out, "  {field} = {{{}}},", escape(t)),
+                FieldValue::Names(n) => {
+                    let names: Vec<_> = n.iter().map(|n| escape(n)).collect();
+                    writeln!(out, "  {field} = {{{}}},", names.join(" and "))
+                }
+                FieldValue::Macro(m) => writeln!(out, "  {field} = {m},"),
+            }
+            .unwrap();
+        }
+
+        out.push_str("}\n\n");
+    }
+}
+
+/// Make text safe to use as a braced `.bib` field value. The characters that
+/// are special in TeX but common in plain text are escaped, unless they
+/// already are, and braces that don't balance are dropped.
+fn escape(text: &str) -> String {
+    let chars: Vec<char> = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .collect();
+    let mut keep = vec![true; chars.len()];
+    let mut open = Vec::new();
+
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 && chars[i - 1] == '\\' {
+            continue;
+        }
+
+        if *c == '{' {
+            open.push(i);
+        } else if *c == '}' && open.pop().is_none() {
+            keep[i] = false;
+        }
+    }
+
+    for i in open {
+        keep[i] = false;
+    }
+
+    let mut out = String::with_capacity(text.len());
+
+    for (i, c) in chars.iter().enumerate() {
+        if !keep[i] {
+            continue;
+        }
+
+        if "&%$#_".contains(*c) && (i == 0 || chars[i - 1] != '\\') {
+            out.push('\\');
+        }
+
+        out.push(*c);
+    }
+
+    out
+}
+
+/// Get the text of a JSON string or number.
+fn json_text(value: &Value) -> Option<String> {
+    match value {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        Value::Array(items) => {
+            let items: Vec<_> = items.iter().filter_map(json_text).collect();
+            (!items.is_empty()).then(|| items.join(", "))
+        }
+        _ => None,
+    }
+}
+
+fn csl_records(value: &Value) -> Result<Vec<Result<Record, String>>, String> {
+    let Value::Array(items) = value else {
+        return Err("expected an array of CSL-JSON items".to_owned());
+    };
+
+    Ok(items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| csl_record(i, item))
+        .collect())
+}
+
+fn csl_record(index: usize, item: &Value) -> Result<Record, String> {
+    let Some(key) = item.get("id").and_then(json_text) else {
+        return Err(format!("item #{} has no id, so it was skipped", index + 1));
+    };
+
+    let mut rec = Record::new(key)?;
+    let get = |field: &str| item.get(field).and_then(json_text);
+    let csl_type = get("type").unwrap_or_default();
+    let genre = get("genre").unwrap_or_default().to_lowercase();
+
+    rec.entry_type = match csl_type.as_str() {
+        "article" | "article-journal" | "article-magazine" | "article-newspaper" => "article",
+        "book" => "book",
+        "chapter" => "incollection",
+        "paper-conference" => "inproceedings",
+        "report" => "techreport",
+        "thesis" if genre.contains("master") => "mastersthesis",
+        "thesis" => "phdthesis",
+        "manuscript" => "unpublished",
+        "pamphlet" => "booklet",
+        _ => "misc",
+    };
+
+    rec.names("author", csl_names(item.get("author")));
+    rec.names("editor", csl_names(item.get("editor")));
+
+    if let Some(title) = get("title") {
+        rec.text("title", title);
+    }
+
+    if let Some(container) = get("container-title") {
+        match rec.entry_type {
+            "article" => rec.text("journal", container),
+            "incollection" | "inproceedings" => rec.text("booktitle", container),
+            _ => rec.text("howpublished", container),
+        }
+    }
+
+    if let Some(publisher) = get("publisher") {
+        match rec.entry_type {
+            "techreport" => rec.text("institution", publisher),
+            "phdthesis" | "mastersthesis" => rec.text("school", publisher),
+            _ => rec.text("publisher", publisher),
+        }
+    }
+
+    if let Some(issued) = item.get("issued") {
+        csl_date(&mut rec, issued);
+    }
+
+    if let Some(pages) = get("page") {
+        rec.pages(&pages);
+    }
+
+    for (csl, bib) in [
+        ("collection-title", "series"),
+        ("publisher-place", "address"),
+        ("volume", "volume"),
+        ("issue", "number"),
+        ("number", "number"),
+        ("edition", "edition"),
+        ("DOI", "doi"),
+        ("URL", "url"),
+        ("ISBN", "isbn"),
+        ("ISSN", "issn"),
+        ("abstract", "abstract"),
+        ("keyword", "keywords"),
+        ("language", "language"),
+        ("note", "note"),
+    ] {
+        if let Some(value) = get(csl) {
+            rec.text(bib, value);
+        }
+    }
+
+    Ok(rec)
+}
+
+/// Get the names in a CSL-JSON name variable, in BibTeX's "von Last, Jr,
+/// First" form.
+fn csl_names(value: Option<&Value>) -> Vec<String> {
+    let Some(Value::Array(names)) = value else {
+        return Vec::new();
+    };
+
+    names
+        .iter()
+        .filter_map(|name| {
+            let part = |p: &str| name.get(p).and_then(json_text).unwrap_or_default();
+
+            if let Some(literal) = name.get("literal").and_then(json_text) {
+                return Some(format!("{{{literal}}}"));
+            }
+
+            bibtex_name(
+                &[part("non-dropping-particle"), part("family")].join(" "),
+                &part("suffix"),
+                &[part("given"), part("dropping-particle")].join(" "),
+            )
+        })
+        .collect()
+}
+
+/// Assemble a name from its parts, in the form that BibTeX parses most
+/// reliably.
+fn bibtex_name(last: &str, jr: &str, first: &str) -> Option<String> {
+    let (last, jr, first) = (last.trim(), jr.trim(), first.trim());
+
+    match (last.is_empty(), jr.is_empty(), first.is_empty()) {
+        (true, _, true) => None,
+        (true, _, false) => Some(format!("{{{first}}}")),
+        (false, true, true) => Some(format!("{last},")),
+        (false, true, false) => Some(format!("{last}, {first}")),
+        (false, false, _) => Some(format!("{last}, {jr}, {first}")),
+    }
+}
+
+fn csl_date(rec: &mut Record, date: &Value) {
+    if let Some(Value::Array(parts)) = date.get("date-parts") {
+        if let Some(Value::Array(first)) = parts.first() {
+            let mut parts = first.iter().filter_map(json_text);
+
+            if let Some(year) = parts.next() {
+                rec.text("year", year);
+            }
+
+            if let Some(month) = parts.next() {
+                rec.month(&month);
+            }
+
+            return;
+        }
+    }
+
+    if let Some(raw) = date.get("raw").and_then(json_text) {
+        rec.date(&raw);
+    } else if let Some(literal) = date.get("literal").and_then(json_text) {
+        rec.text("year", literal);
+    }
+}
+
+/// A node of a YAML document.
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Yaml {
+    Scalar(String),
+    Seq(Vec<Yaml>),
+    Map(Vec<(String, Yaml)>),
+}
+
+impl Yaml {
+    fn get(&self, key: &str) -> Option<&Yaml> {
+        match self {
+            Yaml::Map(items) => items
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(key))
+                .map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Get the text of a scalar, or of the `value` of a mapping, as
+    /// Hayagriva allows for titles and URLs.
+    fn text(&self) -> Option<String> {
+        match self {
+            Yaml::Scalar(s) => Some(s.clone()),
+            Yaml::Map(_) => self.get("value").and_then(Yaml::text),
+            Yaml::Seq(items) => {
+                let items: Vec<_> = items.iter().filter_map(Yaml::text).collect();
+                (!items.is_empty()).then(|| items.join(", "))
+            }
+        }
+    }
+
+    /// Get the items of a sequence, or this node as the only item.
+    fn items(&self) -> &[Yaml] {
+        match self {
+            Yaml::Seq(items) => items,
+            other => std::slice::from_ref(other),
+        }
+    }
+}
+
+fn hayagriva_records(doc: &Yaml) -> Result<Vec<Result<Record, String>>, String> {
+    let Yaml::Map(entries) = doc else {
+        return Err("expected a mapping from keys to entries".to_owned());
+    };
+
+    Ok(entries
+        .iter()
+        .map(|(key, entry)| hayagriva_record(key, entry))
+        .collect())
+}
+
+fn hayagriva_record(key: &str, entry: &Yaml) -> Result<Record, String> {
+    let mut rec = Record::new(key.to_owned())?;
+
+    if !matches!(entry, Yaml::Map(_)) {
+        return Err(format!(
+            "the entry \"{key}\" isn't a mapping, so it was skipped"
+        ));
+    }
+
+    let kind = |e: &Yaml| {
+        e.get("type")
+            .and_then(Yaml::text)
+            .unwrap_or_default()
+            .to_lowercase()
+    };
+    let parent = entry.get("parent").and_then(|p| p.items().first());
+    let parent_kind = parent.map(kind).unwrap_or_default();
+    let genre = entry
+        .get("genre")
+        .and_then(Yaml::text)
+        .unwrap_or_default()
+        .to_lowercase();
+
+    rec.entry_type = match (kind(entry).as_str(), parent_kind.as_str()) {
+        ("article", "proceedings" | "conference") => "inproceedings",
+        ("article", _) => "article",
+        ("book", _) => "book",
+        ("chapter", "proceedings" | "conference") => "inproceedings",
+        ("chapter", _) => "incollection",
+        ("proceedings" | "conference", _) => "proceedings",
+        ("report", _) => "techreport",
+        ("thesis", _) if genre.contains("master") => "mastersthesis",
+        ("thesis", _) => "phdthesis",
+        ("manuscript", _) => "unpublished",
+        _ => "misc",
+    };
+
+    // The fields of the entry itself take precedence over those of its
+    // parent, which describes the journal or book that it appeared in.
+
+    if let Some(title) = entry.get("title").and_then(Yaml::text) {
+        rec.text("title", title);
+    }
+
+    if let Some(parent_title) = parent.and_then(|p| p.get("title")).and_then(Yaml::text) {
+        match rec.entry_type {
+            "article" => rec.text("journal", parent_title),
+            "incollection" | "inproceedings" => rec.text("booktitle", parent_title),
+            _ => rec.text("howpublished", parent_title),
+        }
+    }
+
+    for source in std::iter::once(entry).chain(parent) {
+        hayagriva_fields(&mut rec, source);
+    }
+
+    Ok(rec)
+}
+
+/// Set the fields of *rec* that an entry and its parent have in common.
+fn hayagriva_fields(rec: &mut Record, entry: &Yaml) {
+    let get = |field: &str| entry.get(field).and_then(Yaml::text);
+
+    rec.names("author", hayagriva_names(entry.get("author")));
+    rec.names("editor", hayagriva_names(entry.get("editor")));
+
+    if let Some(date) = get("date") {
+        rec.date(&date);
+    }
+
+    if let Some(pages) = get("page-range") {
+        rec.pages(&pages);
+    }
+
+    if let Some(publisher) = entry.get("publisher") {
+        let field = match rec.entry_type {
+            "techreport" => "institution",
+            "phdthesis" | "mastersthesis" => "school",
+            _ => "publisher",
+        };
+
+        match publisher {
+            Yaml::Map(_) => {
+                if let Some(name) = publisher.get("name").and_then(Yaml::text) {
+                    rec.text(field, name);
+                }
+
+                if let Some(location) = publisher.get("location").and_then(Yaml::text) {
+                    rec.text("address", location);
+                }
+            }
+
+            other => {
+                if let Some(name) = other.text() {
+                    rec.text(field, name);
+                }
+            }
+        }
+    }
+
+    match entry.get("serial-number") {
+        Some(numbers @ Yaml::Map(_)) => {
+            for (haya, bib) in [("doi", "doi"), ("isbn", "isbn"), ("issn", "issn")] {
+                if let Some(value) = numbers.get(haya).and_then(Yaml::text) {
+                    rec.text(bib, value);
+                }
+            }
+        }
+
+        Some(other) => {
+            if let Some(value) = other.text() {
+                rec.text("number", value);
+            }
+        }
+
+        None => {}
+    }
+
+    for (haya, bib) in [
+        ("location", "address"),
+        ("organization", "organization"),
+        ("volume", "volume"),
+        ("issue", "number"),
+        ("edition", "edition"),
+        ("doi", "doi"),
+        ("url", "url"),
+        ("isbn", "isbn"),
+        ("issn", "issn"),
+        ("abstract", "abstract"),
+        ("language", "language"),
+        ("note", "note"),
+    ] {
+        if let Some(value) = get(haya) {
+            rec.text(bib, value);
+        }
+    }
+}
+
+/// Get the names in a Hayagriva person list. Each is either a string in the
+/// usual "Last, First" form, which BibTeX understands as it is, or a mapping
+/// of the parts of the name.
+fn hayagriva_names(value: Option<&Yaml>) -> Vec<String> {
+    let Some(value) = value else {
+        return Vec::new();
+    };
+
+    value
+        .items()
+        .iter()
+        .filter_map(|name| match name {
+            Yaml::Scalar(s) => Some(s.trim().to_owned()).filter(|s| !s.is_empty()),
+            Yaml::Map(_) => {
+                let part = |p: &str| name.get(p).and_then(Yaml::text).unwrap_or_default();
+                bibtex_name(
+                    &[part("prefix"), part("name")].join(" "),
+                    &part("suffix"),
+                    &part("given-name"),
+                )
+            }
+            Yaml::Seq(_) => None,
+        })
+        .collect()
+}
+
+/// A line of a YAML document, without its indentation.
+#[derive(Clone, Copy, Debug)]
+struct Line<'a> {
+    number: usize,
+    indent: usize,
+    text: &'a str,
+}
+
+/// Parse the subset of YAML described in the module documentation.
+fn parse_yaml(text: &str) -> Result<Yaml, String> {
+    let mut lines = Vec::new();
+
+    for (i, raw) in text.lines().enumerate() {
+        let raw = raw.trim_end();
+        let content = raw.trim_start_matches(' ');
+
+        if content.starts_with('\t') {
+            return Err(format!(
+                "line {}: tabs can't be used for indentation",
+                i + 1
+            ));
+        }
+
+        if i == 0 && content.starts_with('\u{feff}') {
+            lines.push(Line {
+                number: 1,
+                indent: 0,
+                text: content.trim_start_matches('\u{feff}'),
+            });
+            continue;
+        }
+
+        lines.push(Line {
+            number: i + 1,
+            indent: raw.len() - content.len(),
+            text: content,
+        });
+    }
+
+    let mut parser = YamlParser { lines, pos: 0 };
+    parser.skip_blank();
+
+    if parser.pos == parser.lines.len() {
+        return Ok(Yaml::Map(Vec::new()));
+    }
+
+    let indent = parser.lines[parser.pos].indent;
+    let doc = parser.block(indent)?;
+    parser.skip_blank();
+
+    if let Some(line) = parser.lines.get(parser.pos) {
+        return Err(format!("line {}: unexpected indentation", line.number));
+    }
+
+    Ok(doc)
+}
+
+struct YamlParser<'a> {
+    lines: Vec<Line<'a>>,
+    pos: usize,
+}
+
+impl<'a> YamlParser<'a> {
+    /// Skip blank lines, comments, and document markers.
+    fn skip_blank(&mut self) {
+        while let Some(line) = self.lines.get(self.pos) {
+            let t = line.text;
+
+            if t.is_empty() || t.starts_with('#') || t == "---" || t == "..." {
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+    }
+
+    fn peek(&mut self) -> Option<Line<'a>> {
+        self.skip_blank();
+        self.lines.get(self.pos).copied()
+    }
+
+    /// Parse the block node whose first line is the next one, which must be
+    /// indented by *indent*.
+    fn block(&mut self, indent: usize) -> Result<Yaml, String> {
+        let line = self.peek().ok_or("unexpected end of file")?;
+
+        if is_seq_item(line.text) {
+            self.seq(indent)
+        } else if split_key(line.text).is_some() {
+            self.map(indent)
+        } else {
+            self.pos += 1;
+            self.scalar(line, line.text, indent)
+        }
+    }
+
+    fn seq(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut items = Vec::new();
+
+        while let Some(line) = self.peek() {
+            if line.indent != indent || !is_seq_item(line.text) {
+                break;
+            }
+
+            let rest = line.text[1..].trim_start_matches(' ');
+
+            if rest.is_empty() || rest.starts_with('#') {
+                self.pos += 1;
+                items.push(self.child(indent)?);
+            } else if split_key(rest).is_some() || is_seq_item(rest) {
+                // A compact mapping or sequence that starts on the line of
+                // the dash: treat its text as if it were on a line of its own.
+                let offset = line.text.len() - rest.len();
+                self.lines[self.pos] = Line {
+                    number: line.number,
+                    indent: indent + offset,
+                    text: rest,
+                };
+                items.push(self.block(indent + offset)?);
+            } else {
+                self.pos += 1;
+                items.push(self.scalar(line, rest, indent)?);
+            }
+        }
+
+        Ok(Yaml::Seq(items))
+    }
+
+    fn map(&mut self, indent: usize) -> Result<Yaml, String> {
+        let mut items: Vec<(String, Yaml)> = Vec::new();
+
+        while let Some(line) = self.peek() {
+            if line.indent != indent || is_seq_item(line.text) {
+                if line.indent > indent {
+                    return Err(format!("line {}: unexpected indentation", line.number));
+                }
+
+                break;
+            }
+
+            let Some((key, value)) = split_key(line.text) else {
+                return Err(format!("line {}: expected `key: value`", line.number));
+            };
+
+            let key = parse_flow_scalar(key, line.number)?;
+            self.pos += 1;
+
+            let value = value.trim();
+            let node = if value.is_empty() || value.starts_with('#') {
+                // A sequence may be indented as much as its key.
+                match self.peek() {
+                    Some(next) if next.indent == indent && is_seq_item(next.text) => {
+                        self.seq(indent)?
+                    }
+                    _ => self.child(indent)?,
+                }
+            } else if value.starts_with('|') || value.starts_with('>') {
+                self.block_scalar(value, indent)
+            } else {
+                self.scalar(line, value, indent)?
+            };
+
+            if !items.iter().any(|(k, _)| *k == key) {
+                items.push((key, node));
+            }
+        }
+
+        Ok(Yaml::Map(items))
+    }
+
+    /// Parse the node nested under the line just read, or an empty scalar if
+    /// there isn't one.
+    fn child(&mut self, indent: usize) -> Result<Yaml, String> {
+        match self.peek() {
+            Some(next) if next.indent > indent => self.block(next.indent),
+            _ => Ok(Yaml::Scalar(String::new())),
+        }
+    }
+
+    /// Parse an inline scalar or flow collection, *text*, which may continue
+    /// on following lines that are indented more than *indent*.
+    fn scalar(&mut self, line: Line<'_>, text: &str, indent: usize) -> Result<Yaml, String> {
+        let mut text = text.to_owned();
+
+        while let Some(next) = self.lines.get(self.pos) {
+            if next.indent <= indent || next.text.is_empty() || next.text.starts_with('#') {
+                break;
+            }
+
+            // Only quoted scalars and flow collections can contain keys or
+            // sequence items on their continuation lines.
+            if !text.starts_with(['"', '\'', '[', '{'])
+                && (split_key(next.text).is_some() || is_seq_item(next.text))
+            {
+                return Err(format!("line {}: unexpected indentation", next.number));
+            }
+
+            text.push(' ');
+            text.push_str(next.text);
+            self.pos += 1;
+        }
+
+        let mut chars = FlowChars {
+            text: &text,
+            pos: 0,
+            line: line.number,
+        };
+        let node = chars.node(false)?;
+        chars.skip_space();
+
+        if !chars.rest().is_empty() && !chars.rest().starts_with('#') {
+            return Err(format!(
+                "line {}: unexpected text after value: `{}`",
+                line.number,
+                chars.rest()
+            ));
+        }
+
+        Ok(node)
+    }
+
+    /// Parse a literal (`|`) or folded (`>`) block scalar.
+    fn block_scalar(&mut self, header: &str, indent: usize) -> Yaml {
+        let folded = header.starts_with('>');
+        let keep = header.contains('+');
+        let strip = header.contains('-');
+        let mut body: Vec<String> = Vec::new();
+        let mut body_indent = None;
+
+        while let Some(line) = self.lines.get(self.pos) {
+            if line.text.is_empty() {
+                body.push(String::new());
+            } else if line.indent <= indent {
+                break;
+            } else {
+                // Indentation beyond that of the first line is kept.
+                let base = *body_indent.get_or_insert(line.indent);
+                body.push(" ".repeat(line.indent.saturating_sub(base)) + line.text);
+            }
+
+            self.pos += 1;
+        }
+
+        let trailing = body.iter().rev().take_while(|l| l.is_empty()).count();
+        body.truncate(body.len() - trailing);
+
+        let mut text = if folded {
+            let mut text = String::new();
+
+            for (i, line) in body.iter().enumerate() {
+                if i > 0 {
+                    text.push(if line.is_empty() || body[i - 1].is_empty() {
+                        '\n'
+                    } else {
+                        ' '
+                    });
+                }
+
+                text.push_str(line);
+            }
+
+            text
+        } else {
+            body.join("\n")
+        };
+
+        if !strip && !body.is_empty() {
+            text.push('\n');
+
+            if keep {
+                text.push_str(&"\n".repeat(trailing));
+            }
+        }
+
+        Yaml::Scalar(text)
+    }
+}
+
+fn is_seq_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// Split the text of a line into a mapping key and the rest of the line, if
+/// it has a key.
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with(['[', '{']) {
+        return None;
+    }
+
+    let mut quote = None;
+
+    for (i, c) in text.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') if i == 0 => quote = Some(c),
+            (Some(q), c) if c == q => quote = None,
+            (None, '#') if i == 0 || text[..i].ends_with(' ') => return None,
+            (None, ':') => {
+                let rest = &text[i + 1..];
+
+                if rest.is_empty() || rest.starts_with(' ') {
+                    return Some((text[..i].trim_end(), rest));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    None
+}
+
+fn parse_flow_scalar(text: &str, line: usize) -> Result<String, String> {
+    let mut chars = FlowChars { text, pos: 0, line };
+
+    match chars.node(true)? {
+        Yaml::Scalar(s) => Ok(s),
+        _ => Err(format!("line {line}: keys must be scalars")),
+    }
+}
+
+/// A cursor over the text of an inline value.
+struct FlowChars<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl FlowChars<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+
+    fn skip_space(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn error<T>(&self, message: &str) -> Result<T, String> {
+        Err(format!("line {}: {}", self.line, message))
+    }
+
+    /// Parse a node. Inside flow collections, *in_flow* is true, and plain
+    /// scalars end at the punctuation of the collection.
+    fn node(&mut self, in_flow: bool) -> Result<Yaml, String> {
+        self.skip_space();
+
+        match self.rest().chars().next() {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+
+                loop {
+                    self.skip_space();
+
+                    if self.rest().starts_with(']') {
+                        self.pos += 1;
+                        return Ok(Yaml::Seq(items));
+                    }
+
+                    items.push(self.node(true)?);
+                    self.skip_space();
+
+                    if self.rest().starts_with(',') {
+                        self.pos += 1;
+                    } else if !self.rest().starts_with(']') {
+                        return self.error("expected `,` or `]` in a flow sequence");
+                    }
+                }
+            }
+
+            Some('{') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+
+                loop {
+                    self.skip_space();
+
+                    if self.rest().starts_with('}') {
+                        self.pos += 1;
+                        return Ok(Yaml::Map(items));
+                    }
+
+                    let Yaml::Scalar(key) = self.node(true)? else {
+                        return self.error("keys must be scalars");
+                    };
+
+                    self.skip_space();
+
+                    let value = if self.rest().starts_with(':') {
+                        self.pos += 1;
+                        self.node(true)?
+                    } else {
+                        Yaml::Scalar(String::new())
+                    };
+
+                    items.push((key, value));
+                    self.skip_space();
+
+                    if self.rest().starts_with(',') {
+                        self.pos += 1;
+                    } else if !self.rest().starts_with('}') {
+                        return self.error("expected `,` or `}` in a flow mapping");
+                    }
+                }
+            }
+
+            Some('"') => {
+                self.pos += 1;
+                let mut out = String::new();
+                let mut chars = self.rest().char_indices();
+
+                while let Some((i, c)) = chars.next() {
+                    match c {
+                        '"' => {
+                            self.pos += i + 1;
+                            return Ok(Yaml::Scalar(out));
+                        }
+
+                        '\\' => match chars.next().map(|(_, c)| c) {
+                            Some('n') => out.push('\n'),
+                            Some('t') => out.push('\t'),
+                            Some('u') => {
+                                let hex: String = chars.by_ref().take(4).map(|(_, c)| c).collect();
+                                match u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32) {
+                                    Some(c) => out.push(c),
+                                    None => return self.error("bad `\\u` escape"),
+                                }
+                            }
+                            Some(c) => out.push(c),
+                            None => break,
+                        },
+
+                        c => out.push(c),
+                    }
+                }
+
+                self.error("unterminated double-quoted string")
+            }
+
+            Some('\'') => {
+                self.pos += 1;
+                let mut out = String::new();
+                let mut chars = self.rest().char_indices().peekable();
+
+                while let Some((i, c)) = chars.next() {
+                    if c != '\'' {
+                        out.push(c);
+                    } else if chars.peek().map(|&(_, c)| c) == Some('\'') {
+                        out.push('\'');
+                        chars.next();
+                    } else {
+                        self.pos += i + 1;
+                        return Ok(Yaml::Scalar(out));
+                    }
+                }
+
+                self.error("unterminated single-quoted string")
+            }
+
+            Some('&' | '*' | '!') => self.error("anchors, aliases, and tags aren't supported"),
+
+            _ => {
+                let rest = &self.text[self.pos..];
+                let mut end = rest.len();
+
+                for (i, c) in rest.char_indices() {
+                    let next = rest[i + c.len_utf8()..].chars().next();
+
+                    if (c == '#' && (i == 0 || rest[..i].ends_with(' ')))
+                        || (in_flow
+                            && (",[]{}".contains(c)
+                                || (c == ':' && matches!(next, None | Some(' ')))))
+                    {
+                        end = i;
+                        break;
+                    }
+                }
+
+                self.pos += end;
+                Ok(Yaml::Scalar(rest[..end].trim().to_owned()))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bib(format: DatabaseFormat, data: &str) -> (String, Vec<String>) {
+        let converted = convert(format, data.as_bytes()).unwrap();
+        (
+            String::from_utf8(converted.text).unwrap(),
+            converted.warnings,
+        )
+    }
+
+    #[test]
+    fn csl_json() {
+        let (text, warnings) = bib(
+            DatabaseFormat::CslJson,
+            r#"[
+                {
+                    "id": "knuth84",
+                    "type": "article-journal",
+                    "title": "Literate Programming & You",
+                    "container-title": "The Computer Journal",
+                    "author": [
+                        {"family": "Knuth", "given": "Donald E."},
+                        {"family": "Gogh", "given": "Vincent", "non-dropping-particle": "van"},
+                        {"literal": "The Tectonic Project"}
+                    ],
+                    "issued": {"date-parts": [[1984, 5]]},
+                    "page": "97-111",
+                    "volume": 27
+                },
+                {"id": "bad key", "type": "book"},
+                {"id": "thesis", "type": "thesis", "genre": "Master's thesis",
+                 "publisher": "MIT", "issued": {"raw": "2001-13"}}
+            ]"#,
+        );
+
+        assert_eq!(
+            text,
+            "@article{knuth84,\n  \
+             author = {Knuth, Donald E. and van Gogh, Vincent and {The Tectonic Project}},\n  \
+             title = {Literate Programming \\& You},\n  \
+             journal = {The Computer Journal},\n  \
+             year = {1984},\n  \
+             month = may,\n  \
+             pages = {97--111},\n  \
+             volume = {27},\n}\n\n\
+             @mastersthesis{thesis,\n  \
+             school = {MIT},\n  \
+             year = {2001},\n}\n\n"
+        );
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("bad key"));
+
+        assert!(convert(DatabaseFormat::CslJson, b"{}").is_err());
+        assert!(convert(DatabaseFormat::CslJson, b"[").is_err());
+    }
+
+    #[test]
+    fn hayagriva() {
+        let (text, warnings) = bib(
+            DatabaseFormat::Hayagriva,
+            r#"
+# A comment.
+harry:
+  type: Article
+  title: "Harry: a {Study"
+  author: ["Potter, Harry", "Granger, Hermione"]
+  date: 1997-06-26
+  page-range: 1-20
+  serial-number:
+    doi: 10.1000/xyz
+  parent:
+    type: Periodical
+    title: Journal of Wizardry
+    volume: 4
+    publisher:
+      name: Bloomsbury
+      location: London
+
+proc:
+  type: article
+  title: >-
+    A folded
+    title
+  author:
+    - name: Lovelace
+      given-name: Ada
+  parent:
+    - type: proceedings
+      title: 'Proceedings of the ''Analytical'' Conference'
+"#,
+        );
+
+        assert_eq!(
+            text,
+            "@article{harry,\n  \
+             title = {Harry: a Study},\n  \
+             journal = {Journal of Wizardry},\n  \
+             author = {Potter, Harry and Granger, Hermione},\n  \
+             year = {1997},\n  \
+             month = jun,\n  \
+             pages = {1--20},\n  \
+             doi = {10.1000/xyz},\n  \
+             publisher = {Bloomsbury},\n  \
+             address = {London},\n  \
+             volume = {4},\n}\n\n\
+             @inproceedings{proc,\n  \
+             title = {A folded title},\n  \
+             booktitle = {Proceedings of the 'Analytical' Conference},\n  \
+             author = {Lovelace, Ada},\n}\n\n"
+        );
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    fn yaml_subset() {
+        let doc = parse_yaml(
+            "a:\n- 1\n- [x, 'y, z']\n- k: v\n  l: {m: n}\nb: |\n  line one\n  line two\nc: \"\\u00e9\" # comment\n",
+        )
+        .unwrap();
+
+        let s = |s: &str| Yaml::Scalar(s.to_owned());
+        assert_eq!(
+            doc,
+            Yaml::Map(vec![
+                (
+                    "a".to_owned(),
+                    Yaml::Seq(vec![
+                        s("1"),
+                        Yaml::Seq(vec![s("x"), s("y, z")]),
+                        Yaml::Map(vec![
+                            ("k".to_owned(), s("v")),
+                            ("l".to_owned(), Yaml::Map(vec![("m".to_owned(), s("n"))])),
+                        ]),
+                    ])
+                ),
+                ("b".to_owned(), s("line one\nline two\n")),
+                ("c".to_owned(), s("é")),
+            ])
+        );
+
+        assert!(parse_yaml("a: 1\n  b: 2\n").is_err());
+        assert!(parse_yaml("a: \"open\n").is_err());
+        assert!(parse_yaml("a: *alias\n").is_err());
+    }
+}
@@ -0,0 +1,305 @@
+//! Suggestions for cite keys that aren't in any database.
+//!
+//! When a `\citation` names a key that none of the `.bib` files define, the
+//! usual cause is a typo, such as `knuth1948` for `knuth1984`. The keys of
+//! all of the entries in the databases are collected as they are read, and
+//! the ones nearest to the missing key, by edit distance, are suggested in
+//! the warning and recorded in a [`MissingEntry`]. The same search suggests
+//! alternatives to the names of `.bib` and `.bst` files that can't be found.
+//!
+//! Large projects can have tens of thousands of entries and many missing
+//! keys, so the work done per missing key is bounded: only keys of a similar
+//! length are compared, each comparison gives up once the distance is too
+//! large, and the search stops once its budget is spent.
+//!
+//! In [low-memory mode](crate::BibtexEngine::low_memory), a [`KeyFilter`]
+//! keeps the list of keys from growing with the size of the databases: a key
+//! is only kept if it's near enough to one of the cited keys that it could be
+//! suggested for it, should that key turn out to be missing.
+
+use std::fmt;
+
+/// The most suggestions made for any one key.
+const MAX_SUGGESTIONS: usize = 3;
+
+/// Keys shorter than this are too short for suggestions to be meaningful.
+const MIN_KEY_LENGTH: usize = 3;
+
+/// The number of steps of the edit-distance computation allowed per missing
+/// key, summed over all of the candidates.
+const WORK_BUDGET: usize = 4_000_000;
+
+/// A citation of a key that wasn't found in any database.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingEntry {
+    /// The cite key, as it was cited.
+    pub cite_key: String,
+
+    /// Up to three keys of database entries that are close to the cite key,
+    /// nearest first.
+    pub suggestions: Vec<String>,
+}
+
+impl MissingEntry {
+    pub(crate) fn new(cite_key: String, database_keys: &[String]) -> Self {
+        let suggestions = suggestions(&cite_key, database_keys);
+
+        MissingEntry {
+            cite_key,
+            suggestions,
+        }
+    }
+
+    /// Describe the suggestions as an English phrase, such as
+    /// `did you mean "knuth1984"?`, or return `None` if there are none.
+    pub fn did_you_mean(&self) -> Option<String> {
+        did_you_mean(&self.suggestions)
+    }
+}
+
+/// Describe some suggestions as an English phrase, or return `None` if there
+/// are none.
+pub(crate) fn did_you_mean(suggestions: &[String]) -> Option<String> {
+    let quoted: Vec<String> = suggestions.iter().map(|s| format!("\"{s}\"")).collect();
+
+    let list = match quoted.as_slice() {
+        [] => return None,
+        [a] => a.clone(),
+        [a, b] => format!("{a} or {b}"),
+        [init @ .., last] => format!("{}, or {}", init.join(", "), last),
+    };
+
+    Some(format!("did you mean {list}?"))
+}
+
+impl fmt::Display for MissingEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "no database entry for \"{}\"", self.cite_key)?;
+
+        if let Some(s) = self.did_you_mean() {
+            write!(f, "; {s}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The cited keys that database keys are compared with in low-memory mode.
+#[derive(Debug, Default)]
+pub(crate) struct KeyFilter {
+    /// The lowercase characters of each cited key that's long enough to get
+    /// suggestions, along with its [`signature`].
+    cited: Vec<(Vec<char>, u64)>,
+    /// The number of citations that have been added.
+    n_added: usize,
+}
+
+impl KeyFilter {
+    /// The number of citations that have been added to the filter, so that
+    /// the caller can add new ones as they come.
+    pub(crate) fn n_added(&self) -> usize {
+        self.n_added
+    }
+
+    /// Add a cited key.
+    pub(crate) fn add(&mut self, key: &str) {
+        self.n_added += 1;
+        let key: Vec<char> = key.to_lowercase().chars().collect();
+
+        if key.len() >= MIN_KEY_LENGTH {
+            let sig = signature(&key);
+            self.cited.push((key, sig));
+        }
+    }
+
+    /// Whether *candidate* could be suggested for any of the cited keys.
+    pub(crate) fn admits(&self, candidate: &str) -> bool {
+        let lc: Vec<char> = candidate.to_lowercase().chars().collect();
+        let sig = signature(&lc);
+
+        self.cited.iter().any(|(key, key_sig)| {
+            let max_distance = (key.len() / 3).min(3);
+
+            lc.len().abs_diff(key.len()) <= max_distance
+                && (sig ^ key_sig).count_ones() as usize <= 2 * max_distance
+                && *key != lc
+                && bounded_distance(key, &lc, max_distance).is_some()
+        })
+    }
+}
+
+/// The set of the characters of a key, hashed into the bits of a word.
+///
+/// Each edit adds or removes at most one character, or both, so the
+/// signatures of keys at an edit distance of *d* differ in at most *2d* bits.
+/// This rules out most pairs of keys without computing their distance.
+fn signature(key: &[char]) -> u64 {
+    key.iter().fold(0, |sig, c| sig | 1 << (*c as u32 % 64))
+}
+
+/// Find the candidates nearest to *key*, ignoring case as BibTeX does.
+pub(crate) fn suggestions(key: &str, database_keys: &[String]) -> Vec<String> {
+    let key: Vec<char> = key.to_lowercase().chars().collect();
+
+    if key.len() < MIN_KEY_LENGTH {
+        return Vec::new();
+    }
+
+    // Allow about one edit for every three characters, so that short keys
+    // don't match everything.
+    let max_distance = (key.len() / 3).min(3);
+    let mut budget = WORK_BUDGET;
+    let mut found: Vec<(usize, String)> = Vec::new();
+
+    for candidate in database_keys {
+        let lc: Vec<char> = candidate.to_lowercase().chars().collect();
+
+        if lc.len().abs_diff(key.len()) > max_distance || lc == key {
+            continue;
+        }
+
+        let cost = key.len() * lc.len();
+
+        if cost > budget {
+            break;
+        }
+
+        budget -= cost;
+
+        if let Some(d) = bounded_distance(&key, &lc, max_distance) {
+            if !found.iter().any(|(_, k)| k.eq_ignore_ascii_case(candidate)) {
+                found.push((d, candidate.clone()));
+            }
+        }
+    }
+
+    found.sort();
+    found.truncate(MAX_SUGGESTIONS);
+    found.into_iter().map(|(_, k)| k).collect()
+}
+
+/// The edit distance between two strings, counting insertions, deletions,
+/// substitutions, and transpositions of adjacent characters, or `None` if it
+/// exceeds *max*.
+fn bounded_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    let mut prev2: Vec<usize> = vec![0; b.len() + 1];
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for i in 1..=a.len() {
+        cur[0] = i;
+        let mut row_min = cur[0];
+
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            let mut d = (prev[j] + 1).min(cur[j - 1] + 1).min(prev[j - 1] + cost);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d = d.min(prev2[j - 2] + 1);
+            }
+
+            cur[j] = d;
+            row_min = row_min.min(d);
+        }
+
+        if row_min > max {
+            return None;
+        }
+
+        std::mem::swap(&mut prev2, &mut prev);
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    Some(prev[b.len()]).filter(|d| *d <= max)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(k: &[&str]) -> Vec<String> {
+        k.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn distance() {
+        let d = |a: &str, b: &str, max| {
+            let a: Vec<char> = a.chars().collect();
+            let b: Vec<char> = b.chars().collect();
+            bounded_distance(&a, &b, max)
+        };
+
+        assert_eq!(d("knuth1948", "knuth1984", 3), Some(1));
+        assert_eq!(d("lamport", "lampert", 3), Some(1));
+        assert_eq!(d("smith", "smyth2", 3), Some(2));
+        assert_eq!(d("abc", "xyz", 2), None);
+    }
+
+    #[test]
+    fn suggesting() {
+        let db = keys(&[
+            "Knuth1984",
+            "knuth1986",
+            "lamport1994",
+            "knuth1974",
+            "knuth1984a",
+            "smith",
+        ]);
+
+        let m = MissingEntry::new("knuth1948".to_owned(), &db);
+        assert_eq!(m.suggestions, ["Knuth1984", "knuth1974", "knuth1984a"]);
+        assert_eq!(
+            m.did_you_mean().unwrap(),
+            "did you mean \"Knuth1984\", \"knuth1974\", or \"knuth1984a\"?"
+        );
+
+        let m = MissingEntry::new("lamport".to_owned(), &db);
+        assert!(m.suggestions.is_empty());
+        assert_eq!(m.to_string(), "no database entry for \"lamport\"");
+
+        let m = MissingEntry::new("smiht".to_owned(), &db);
+        assert_eq!(
+            m.to_string(),
+            "no database entry for \"smiht\"; did you mean \"smith\"?"
+        );
+
+        assert!(MissingEntry::new("sm".to_owned(), &db)
+            .suggestions
+            .is_empty());
+        assert!(MissingEntry::new(String::new(), &db).suggestions.is_empty());
+    }
+
+    #[test]
+    fn filtering() {
+        let mut filter = KeyFilter::default();
+        filter.add("Knuth1948");
+        filter.add("sm");
+        assert_eq!(filter.n_added(), 2);
+
+        assert!(filter.admits("knuth1984"));
+        assert!(filter.admits("KNUTH1948a"));
+        assert!(!filter.admits("knuth1948"));
+        assert!(!filter.admits("lamport1994"));
+        assert!(!filter.admits("sn"));
+
+        // Whatever the filter lets through is what would be suggested.
+        let db = keys(&["knuth1984", "knuth1948", "lamport1994", "smith"]);
+        let kept: Vec<String> = db.iter().filter(|k| filter.admits(k)).cloned().collect();
+        assert_eq!(
+            suggestions("Knuth1948", &kept),
+            suggestions("Knuth1948", &db)
+        );
+    }
+
+    #[test]
+    fn file_names() {
+        let files = keys(&["refs.bib", "Missing2.bib", "other.bib", "mising.bib"]);
+        let found = suggestions("missing.bib", &files);
+        assert_eq!(found, ["Missing2.bib", "mising.bib"]);
+        assert_eq!(
+            did_you_mean(&found).unwrap(),
+            "did you mean \"Missing2.bib\" or \"mising.bib\"?"
+        );
+        assert_eq!(did_you_mean(&[]), None);
+    }
+}
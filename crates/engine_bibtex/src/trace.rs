@@ -0,0 +1,248 @@
+//! Tracing the execution of styles.
+//!
+//! Debugging a `.bst` file is hard when all that the engine reports is the
+//! output and the odd warning. If tracing is enabled, every function call
+//! that the style makes, including the pushing of literals, is written to the
+//! log file along with the line of the style that it was made from and the
+//! contents of the literal stack. Embedding code can also set breakpoints on
+//! functions by name, and be called back with the same information whenever
+//! one of them is about to run.
+
+use crate::{
+    exec::{ExecCtx, ExecVal},
+    hash::{HashData, HashExtra},
+    pool::StringPool,
+    BibtexError, CiteInfo, HashPointer,
+};
+use std::fmt::{Debug, Display, Error as FmtError, Formatter};
+
+/// A value on the literal stack of the style interpreter.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum StackValue {
+    /// An integer.
+    Integer(i64),
+
+    /// A string.
+    String(String),
+
+    /// A function, pushed by quoting it or with a `{...}` block.
+    Function(String),
+
+    /// The value of a field that the current entry doesn't have. This holds
+    /// the name of the field.
+    Missing(String),
+}
+
+impl Display for StackValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        match self {
+            StackValue::Integer(i) => write!(f, "{i}"),
+            StackValue::String(s) => write!(f, "\"{s}\""),
+            StackValue::Function(name) => write!(f, "`{name}`"),
+            StackValue::Missing(field) => write!(f, "`{field}` (missing)"),
+        }
+    }
+}
+
+/// A call of a function by the style, as seen by tracing.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BstCall {
+    /// The name of the function. Literals are given as they're written in
+    /// the style, with strings in double quotes and integers prefixed with
+    /// `#`, and `{...}` blocks are named by a `'` followed by a number.
+    pub function: String,
+
+    /// The line of the `.bst` file that the call was made from. For calls
+    /// made by the body of another function, this is the line of the token
+    /// that made the call, and for the function run by an `EXECUTE`,
+    /// `ITERATE`, or `REVERSE` command, the line of the command.
+    pub line: u32,
+
+    /// How many of the style's own functions were running when the call was
+    /// made.
+    pub depth: usize,
+
+    /// The literal stack before the call, from the bottom up.
+    pub stack: Vec<StackValue>,
+
+    /// The cite key of the entry being processed, if the call was made on
+    /// behalf of an `ITERATE` or `REVERSE` command.
+    pub cite_key: Option<String>,
+}
+
+/// What to do after a breakpoint has been hit.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum BreakAction {
+    /// Make the call and carry on.
+    Continue,
+
+    /// Stop running the style. This is reported as an error.
+    Stop,
+}
+
+/// The function called when a breakpoint is hit.
+pub(crate) struct BreakpointHook(pub Box<dyn FnMut(&BstCall) -> BreakAction + Send>);
+
+impl Debug for BreakpointHook {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), FmtError> {
+        f.write_str("BreakpointHook")
+    }
+}
+
+/// The state of tracing while a style runs.
+pub(crate) struct Tracer {
+    log: bool,
+    breakpoints: Vec<String>,
+    /// The line of the `.bst` file from which the next call is made.
+    pub line: u32,
+}
+
+impl Tracer {
+    pub fn new(log: bool, breakpoints: &[String]) -> Option<Tracer> {
+        (log || !breakpoints.is_empty()).then(|| Tracer {
+            log,
+            breakpoints: breakpoints.iter().map(|b| b.to_ascii_lowercase()).collect(),
+            line: 0,
+        })
+    }
+}
+
+/// Log the call of the function at *fn_loc*, or stop at it, as configured.
+pub(crate) fn trace_call(
+    ctx: &mut ExecCtx<'_, '_, '_>,
+    pool: &StringPool,
+    hash: &HashData,
+    cites: &CiteInfo,
+    fn_loc: HashPointer,
+) -> Result<(), BibtexError> {
+    let Some(tracer) = &ctx.trace else {
+        return Ok(());
+    };
+
+    let function = function_name(pool, hash, fn_loc);
+    let hit = ctx.breakpoint_hook.is_some() && tracer.breakpoints.contains(&function);
+    let (log, line) = (tracer.log, tracer.line);
+
+    if !log && !hit {
+        return Ok(());
+    }
+
+    let call = BstCall {
+        function,
+        line,
+        depth: ctx.wiz_fns.len(),
+        stack: ctx
+            .lit_stack
+            .iter()
+            .filter_map(|val| stack_value(pool, hash, val))
+            .collect(),
+        cite_key: ctx.mess_with_entries.then(|| {
+            String::from_utf8_lossy(pool.get_str(cites.get_cite(cites.ptr()))).into_owned()
+        }),
+    };
+
+    if log {
+        ctx.write_log_file(&format_call(&call));
+    }
+
+    if hit {
+        let hook = ctx.breakpoint_hook.as_mut().unwrap();
+
+        if (hook.0)(&call) == BreakAction::Stop {
+            let bst_name = ctx.bst.as_ref().map_or(Default::default(), |bst| {
+                String::from_utf8_lossy(pool.get_str(bst.name)).into_owned()
+            });
+
+            ctx.write_logs(&format!("Stopped at a breakpoint on `{}`", call.function));
+            ctx.diagnostics.end_message();
+            ctx.diagnostics
+                .locate(format!("{bst_name}.bst"), Some(call.line), None);
+            if let Some(key) = &call.cite_key {
+                ctx.diagnostics.set_cite_key(key.as_bytes());
+            }
+            ctx.write_logs(&format!("--line {} of file {bst_name}.bst\n", call.line));
+            ctx.mark_error();
+            return Err(BibtexError::Recover);
+        }
+    }
+
+    Ok(())
+}
+
+fn function_name(pool: &StringPool, hash: &HashData, fn_loc: HashPointer) -> String {
+    let text = String::from_utf8_lossy(pool.get_str(hash.text(fn_loc)));
+
+    match hash.node(fn_loc).extra {
+        HashExtra::Text => format!("\"{text}\""),
+        HashExtra::Integer(_) => format!("#{text}"),
+        _ => text.into_owned(),
+    }
+}
+
+pub(crate) fn stack_value(pool: &StringPool, hash: &HashData, val: &ExecVal) -> Option<StackValue> {
+    let text = |s| String::from_utf8_lossy(pool.get_str(s)).into_owned();
+
+    Some(match *val {
+        ExecVal::Integer(i) => StackValue::Integer(i),
+        ExecVal::String(s) => StackValue::String(text(s)),
+        ExecVal::Function(f) => StackValue::Function(text(hash.text(f))),
+        ExecVal::Missing(s) => StackValue::Missing(text(s)),
+        ExecVal::Illegal => return None,
+    })
+}
+
+/// Format a call as a line of the log.
+fn format_call(call: &BstCall) -> String {
+    let mut line = format!(
+        "[trace] line {}, depth {}: {}",
+        call.line, call.depth, call.function
+    );
+
+    if call.depth == 0 {
+        if let Some(key) = &call.cite_key {
+            line.push_str(&format!(" for entry {key}"));
+        }
+    }
+
+    line.push_str(" |");
+
+    for val in &call.stack {
+        line.push_str(&format!(" {val}"));
+    }
+
+    line.push('\n');
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn log_lines() {
+        let mut call = BstCall {
+            function: "format.names".to_owned(),
+            line: 215,
+            depth: 3,
+            stack: vec![
+                StackValue::String("Knuth, Donald E.".to_owned()),
+                StackValue::Integer(1),
+                StackValue::Function("'12".to_owned()),
+                StackValue::Missing("editor".to_owned()),
+            ],
+            cite_key: Some("knuth84".to_owned()),
+        };
+
+        assert_eq!(
+            format_call(&call),
+            "[trace] line 215, depth 3: format.names | \"Knuth, Donald E.\" 1 `'12` `editor` (missing)\n"
+        );
+
+        call.depth = 0;
+        call.stack.clear();
+        assert_eq!(
+            format_call(&call),
+            "[trace] line 215, depth 0: format.names for entry knuth84 |\n"
+        );
+    }
+}
@@ -0,0 +1,270 @@
+//! Transliteration of sort keys.
+//!
+//! The classic styles build sort keys with `purify$` and `change.case$`,
+//! which only know about ASCII, and leave the UTF-8 bytes of other scripts
+//! alone. Entries whose names or titles are written in Cyrillic or Greek
+//! therefore all sort after the Latin ones, in the order of their encodings.
+//! A [`Transliteration`] rewrites such characters in Latin letters, so that
+//! they sort among the Latin entries. It is only applied to sort keys: the
+//! formatted text of the bibliography is left as it is.
+
+use std::{borrow::Cow, collections::BTreeMap, fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+
+/// A script with a built-in transliteration table.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Script {
+    /// The Cyrillic alphabets of Russian, Ukrainian, Belarusian, Bulgarian,
+    /// Serbian, and Macedonian, transliterated roughly as in the BGN/PCGN
+    /// systems, e.g. `Чехов` as `chekhov`.
+    Cyrillic,
+
+    /// The Greek alphabet, including the letters with accents and diaereses
+    /// of the monotonic orthography, transliterated roughly as in the ALA-LC
+    /// system, e.g. `Φιλοσοφία` as `philosophia`.
+    Greek,
+}
+
+impl Script {
+    /// All of the scripts with built-in tables.
+    pub const ALL: &'static [Script] = &[Script::Cyrillic, Script::Greek];
+
+    fn table(&self) -> &'static [(char, &'static str)] {
+        match self {
+            Script::Cyrillic => CYRILLIC,
+            Script::Greek => GREEK,
+        }
+    }
+}
+
+impl FromStr for Script {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "cyrillic" => Ok(Script::Cyrillic),
+            "greek" => Ok(Script::Greek),
+            _ => bail!("unknown script `{}`; expected `cyrillic` or `greek`", s),
+        }
+    }
+}
+
+impl fmt::Display for Script {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Script::Cyrillic => "cyrillic",
+            Script::Greek => "greek",
+        })
+    }
+}
+
+// The lowercase letters of each script. Uppercase letters are transliterated
+// in the same way, since the classic styles lowercase their sort keys.
+
+const CYRILLIC: &[(char, &str)] = &[
+    ('а', "a"),
+    ('б', "b"),
+    ('в', "v"),
+    ('г', "g"),
+    ('ґ', "g"),
+    ('д', "d"),
+    ('ђ', "dj"),
+    ('ѓ', "gj"),
+    ('е', "e"),
+    ('ё', "e"),
+    ('є', "ye"),
+    ('ж', "zh"),
+    ('з', "z"),
+    ('ѕ', "dz"),
+    ('и', "i"),
+    ('і', "i"),
+    ('ї', "yi"),
+    ('й', "y"),
+    ('ј', "j"),
+    ('к', "k"),
+    ('л', "l"),
+    ('љ', "lj"),
+    ('м', "m"),
+    ('н', "n"),
+    ('њ', "nj"),
+    ('о', "o"),
+    ('п', "p"),
+    ('р', "r"),
+    ('с', "s"),
+    ('т', "t"),
+    ('ћ', "c"),
+    ('ќ', "kj"),
+    ('у', "u"),
+    ('ў', "u"),
+    ('ф', "f"),
+    ('х', "kh"),
+    ('ц', "ts"),
+    ('ч', "ch"),
+    ('џ', "dz"),
+    ('ш', "sh"),
+    ('щ', "shch"),
+    ('ъ', ""),
+    ('ы', "y"),
+    ('ь', ""),
+    ('э', "e"),
+    ('ю', "yu"),
+    ('я', "ya"),
+];
+
+const GREEK: &[(char, &str)] = &[
+    ('α', "a"),
+    ('ά', "a"),
+    ('β', "b"),
+    ('γ', "g"),
+    ('δ', "d"),
+    ('ε', "e"),
+    ('έ', "e"),
+    ('ζ', "z"),
+    ('η', "e"),
+    ('ή', "e"),
+    ('θ', "th"),
+    ('ι', "i"),
+    ('ί', "i"),
+    ('ϊ', "i"),
+    ('ΐ', "i"),
+    ('κ', "k"),
+    ('λ', "l"),
+    ('μ', "m"),
+    ('ν', "n"),
+    ('ξ', "x"),
+    ('ο', "o"),
+    ('ό', "o"),
+    ('π', "p"),
+    ('ρ', "r"),
+    ('σ', "s"),
+    ('ς', "s"),
+    ('τ', "t"),
+    ('υ', "y"),
+    ('ύ', "y"),
+    ('ϋ', "y"),
+    ('ΰ', "y"),
+    ('φ', "ph"),
+    ('χ', "ch"),
+    ('ψ', "ps"),
+    ('ω', "o"),
+    ('ώ', "o"),
+];
+
+/// A table of replacements for characters in sort keys.
+///
+/// The table starts out empty. Tables for whole scripts can be added with
+/// [`add_script()`](Self::add_script), and replacements for individual
+/// characters with [`add()`](Self::add); later additions override earlier
+/// ones.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Transliteration {
+    map: BTreeMap<char, String>,
+}
+
+impl Transliteration {
+    /// Create an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the built-in table for a script, covering both its lowercase and
+    /// uppercase letters. The replacements are lowercase.
+    pub fn add_script(&mut self, script: Script) -> &mut Self {
+        for (c, to) in script.table() {
+            self.map.insert(*c, (*to).to_owned());
+
+            let mut upper = c.to_uppercase();
+
+            if let (Some(u), None) = (upper.next(), upper.next()) {
+                if u != *c {
+                    self.map.insert(u, (*to).to_owned());
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Replace a single character with some text, which may be empty to drop
+    /// the character.
+    pub fn add(&mut self, from: char, to: &str) -> &mut Self {
+        self.map.insert(from, to.to_owned());
+        self
+    }
+
+    /// Whether the table has no replacements at all.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Apply the table to some text. Bytes that aren't valid UTF-8 are kept
+    /// as they are.
+    pub(crate) fn apply<'a>(&self, text: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.map.is_empty() || text.is_ascii() {
+            return Cow::Borrowed(text);
+        }
+
+        let mut out = Vec::with_capacity(text.len());
+
+        for chunk in text.utf8_chunks() {
+            for c in chunk.valid().chars() {
+                match self.map.get(&c) {
+                    Some(to) => out.extend_from_slice(to.as_bytes()),
+                    None => {
+                        let mut buf = [0; 4];
+                        out.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+                    }
+                }
+            }
+
+            out.extend_from_slice(chunk.invalid());
+        }
+
+        Cow::Owned(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(t: &Transliteration, s: &str) -> String {
+        String::from_utf8(t.apply(s.as_bytes()).into_owned()).unwrap()
+    }
+
+    #[test]
+    fn scripts() {
+        let mut t = Transliteration::new();
+        assert_eq!(apply(&t, "Чехов"), "Чехов");
+
+        t.add_script(Script::Cyrillic);
+        assert_eq!(apply(&t, "чехов антон"), "chekhov anton");
+        assert_eq!(apply(&t, "Щедрин, Ёлкин"), "shchedrin, elkin");
+        assert_eq!(apply(&t, "Αριστοτέλης"), "Αριστοτέλης");
+
+        t.add_script(Script::Greek);
+        assert_eq!(apply(&t, "Φιλοσοφία"), "philosophia");
+        assert_eq!(apply(&t, "ΑΡΙΣΤΟΤΈΛΗΣ"), "aristoteles");
+        assert_eq!(apply(&t, "smith & Чехов"), "smith & chekhov");
+    }
+
+    #[test]
+    fn custom() {
+        let mut t = Transliteration::new();
+        t.add_script(Script::Cyrillic).add('ж', "j").add('ь', "'");
+        assert_eq!(apply(&t, "жизнь"), "jizn'");
+
+        // Invalid UTF-8 is passed through.
+        assert_eq!(
+            t.apply(b"\xd0\xb0\xff\xd0").into_owned(),
+            b"a\xff\xd0".to_vec()
+        );
+    }
+
+    #[test]
+    fn parse_script() {
+        assert_eq!("greek".parse::<Script>().unwrap(), Script::Greek);
+        assert_eq!(Script::Cyrillic.to_string(), "cyrillic");
+        assert!("latin".parse::<Script>().is_err());
+    }
+}
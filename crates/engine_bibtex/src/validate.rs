@@ -0,0 +1,469 @@
+//! Checks of the formats of common fields.
+//!
+//! Classic BibTeX accepts any text in any field, so a mistyped DOI or ISBN
+//! only shows up once a reader tries to follow it. When checks are enabled,
+//! the values of the chosen fields are checked once the database files have
+//! been read, before any cross references are resolved, so that each problem
+//! is reported once, at the entry where it appears. Each problem is written to
+//! the log as a warning and recorded as a [`FieldWarning`].
+//!
+//! As with everything else that BibTeX does with fields, only fields that the
+//! style declares in its `ENTRY` command can be checked.
+
+use crate::{
+    exec::{entry_location, field_value},
+    srcmap::EntryLocation,
+    Bibtex, BibtexError, GlobalItems,
+};
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+
+/// A check of the format of a field's value.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FieldCheck {
+    /// A DOI such as `10.1000/182`, optionally given as a `doi:` or
+    /// `https://doi.org/` link.
+    Doi,
+
+    /// An ISBN-10 or ISBN-13, with a valid check digit. Hyphens and spaces are
+    /// ignored.
+    Isbn,
+
+    /// An ISSN such as `0378-5955`, with a valid check digit.
+    Issn,
+
+    /// An absolute URL, with a scheme and no spaces. `http` and `https` URLs
+    /// must name a host.
+    Url,
+
+    /// A page number, or range or list of them, where ranges of plain numbers
+    /// don't run backwards.
+    Pages,
+}
+
+impl FieldCheck {
+    /// The fields that are checked by default, each with the check of the
+    /// same name.
+    pub const COMMON: &'static [(&'static str, FieldCheck)] = &[
+        ("doi", FieldCheck::Doi),
+        ("isbn", FieldCheck::Isbn),
+        ("issn", FieldCheck::Issn),
+        ("pages", FieldCheck::Pages),
+        ("url", FieldCheck::Url),
+    ];
+
+    /// Check a field value, returning a description of the problem if there
+    /// is one.
+    ///
+    /// Braces are ignored, as are backslashes that escape punctuation, so
+    /// that values like `10.1000/a\_b` are checked as they'll be printed.
+    pub fn check(&self, value: &str) -> Option<String> {
+        let value = unescape(value);
+        let value = value.trim();
+
+        let result = match self {
+            FieldCheck::Doi => check_doi(value),
+            FieldCheck::Isbn => check_isbn(value),
+            FieldCheck::Issn => check_issn(value),
+            FieldCheck::Url => check_url(value),
+            FieldCheck::Pages => check_pages(value),
+        };
+
+        result.err()
+    }
+}
+
+impl FromStr for FieldCheck {
+    type Err = Error;
+
+    /// Parse a check from one of `doi`, `isbn`, `issn`, `url`, or `pages`.
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "doi" => Ok(FieldCheck::Doi),
+            "isbn" => Ok(FieldCheck::Isbn),
+            "issn" => Ok(FieldCheck::Issn),
+            "url" => Ok(FieldCheck::Url),
+            "pages" => Ok(FieldCheck::Pages),
+            _ => bail!(
+                "unrecognized field check `{s}`; expected `doi`, `isbn`, `issn`, `url`, \
+                 or `pages`"
+            ),
+        }
+    }
+}
+
+impl fmt::Display for FieldCheck {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            FieldCheck::Doi => "doi",
+            FieldCheck::Isbn => "isbn",
+            FieldCheck::Issn => "issn",
+            FieldCheck::Url => "url",
+            FieldCheck::Pages => "pages",
+        })
+    }
+}
+
+/// A problem found by a field check.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FieldWarning {
+    /// The cite key of the entry.
+    pub cite_key: String,
+
+    /// The name of the `.bib` file containing the entry.
+    pub file: String,
+
+    /// The line of the `.bib` file where the entry starts.
+    pub line: u32,
+
+    /// The name of the field, in lowercase.
+    pub field: String,
+
+    /// The value of the field.
+    pub value: String,
+
+    /// A description of the problem.
+    pub message: String,
+}
+
+impl FieldWarning {
+    pub(crate) fn new(location: EntryLocation, field: &str, value: &str, message: String) -> Self {
+        FieldWarning {
+            cite_key: location.cite_key,
+            file: location.file,
+            line: location.line,
+            field: field.to_owned(),
+            value: value.to_owned(),
+            message,
+        }
+    }
+}
+
+/// Check the fields of all of the entries that have been read, as configured.
+pub(crate) fn check_fields(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    let checks = ctx.config.field_checks.clone();
+
+    for cite in 0..globals.cites.num_cites() {
+        let Some(location) = entry_location(globals.pool, globals.cites, cite) else {
+            continue;
+        };
+
+        for (field, check) in &checks {
+            let Some(value) = field_value(
+                globals.pool,
+                globals.hash,
+                globals.other,
+                cite,
+                field.as_bytes(),
+            ) else {
+                continue;
+            };
+
+            let value = String::from_utf8_lossy(value);
+
+            if let Some(message) = check.check(&value) {
+                let warning = FieldWarning::new(location.clone(), field, &value, message);
+                ctx.diagnostics.locate_entry(location.clone());
+                ctx.write_logs(&format!(
+                    "Warning--the {} field of {}: {}\n",
+                    warning.field, warning.cite_key, warning.message
+                ));
+                ctx.diagnostics.end_message();
+                ctx.write_logs(&format!(
+                    "--line {} of file {}\n",
+                    warning.line, warning.file
+                ));
+                ctx.mark_warning();
+                ctx.field_warnings.push(warning);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+impl fmt::Display for FieldWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}:{}: the {} field of {}: {}",
+            self.file, self.line, self.field, self.cite_key, self.message
+        )
+    }
+}
+
+fn unescape(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => {}
+            '\\' if chars.peek().is_some_and(|n| n.is_ascii_punctuation()) => {}
+            _ => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn check_doi(value: &str) -> Result<(), String> {
+    let lower = value.to_ascii_lowercase();
+    let mut doi = value;
+
+    for prefix in [
+        "https://doi.org/",
+        "http://doi.org/",
+        "https://dx.doi.org/",
+        "http://dx.doi.org/",
+        "doi:",
+    ] {
+        if lower.starts_with(prefix) {
+            doi = value[prefix.len()..].trim_start();
+            break;
+        }
+    }
+
+    let Some((prefix, suffix)) = doi.split_once('/') else {
+        return Err(format!("`{value}` is not a DOI: it has no `/`"));
+    };
+
+    let registrant = prefix.strip_prefix("10.").unwrap_or("");
+
+    if registrant.is_empty()
+        || !registrant
+            .split('.')
+            .all(|part| !part.is_empty() && part.bytes().all(|b| b.is_ascii_digit()))
+    {
+        return Err(format!(
+            "`{value}` is not a DOI: it should start with `10.` and a registrant number"
+        ));
+    }
+
+    if suffix.is_empty() || suffix.chars().any(char::is_whitespace) {
+        return Err(format!(
+            "`{value}` is not a DOI: its suffix is empty or contains spaces"
+        ));
+    }
+
+    Ok(())
+}
+
+/// The digits of an identifier, ignoring hyphens and spaces, and mapping an
+/// `X` to 10.
+fn id_digits(value: &str) -> Option<Vec<u32>> {
+    value
+        .chars()
+        .filter(|c| *c != '-' && !c.is_whitespace())
+        .map(|c| match c {
+            'X' | 'x' => Some(10),
+            _ => c.to_digit(10),
+        })
+        .collect()
+}
+
+fn check_isbn(value: &str) -> Result<(), String> {
+    let Some(digits) = id_digits(value) else {
+        return Err(format!(
+            "`{value}` is not an ISBN: it contains other characters"
+        ));
+    };
+
+    // Only the last digit of an ISBN-10 can be an X.
+    if digits.len() == 13 && digits.contains(&10)
+        || digits[..digits.len().saturating_sub(1)].contains(&10)
+    {
+        return Err(format!(
+            "`{value}` is not an ISBN: only its last digit can be an X"
+        ));
+    }
+
+    match digits.len() {
+        10 => {
+            let sum: u32 = digits.iter().zip((1..=10).rev()).map(|(d, w)| d * w).sum();
+
+            if !sum.is_multiple_of(11) {
+                return Err(format!(
+                    "`{value}` is not an ISBN: its check digit is wrong"
+                ));
+            }
+        }
+
+        13 => {
+            if digits[..3] != [9, 7, 8] && digits[..3] != [9, 7, 9] {
+                return Err(format!(
+                    "`{value}` is not an ISBN: 13-digit ISBNs start with 978 or 979"
+                ));
+            }
+
+            let sum: u32 = digits
+                .iter()
+                .zip([1, 3].iter().cycle())
+                .map(|(d, w)| d * w)
+                .sum();
+
+            if !sum.is_multiple_of(10) {
+                return Err(format!(
+                    "`{value}` is not an ISBN: its check digit is wrong"
+                ));
+            }
+        }
+
+        _ => {
+            return Err(format!(
+                "`{value}` is not an ISBN: it should have 10 or 13 digits"
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+fn check_issn(value: &str) -> Result<(), String> {
+    let well_formed = value.len() == 9
+        && value.as_bytes()[4] == b'-'
+        && value[..4].bytes().all(|b| b.is_ascii_digit())
+        && value[5..8].bytes().all(|b| b.is_ascii_digit());
+
+    let digits = id_digits(value).filter(|_| well_formed);
+
+    let Some(digits) = digits else {
+        return Err(format!(
+            "`{value}` is not an ISSN: it should look like `1234-567X`"
+        ));
+    };
+
+    let sum: u32 = digits.iter().zip((1..=8).rev()).map(|(d, w)| d * w).sum();
+
+    if !sum.is_multiple_of(11) {
+        return Err(format!(
+            "`{value}` is not an ISSN: its check digit is wrong"
+        ));
+    }
+
+    Ok(())
+}
+
+fn check_url(value: &str) -> Result<(), String> {
+    if value.chars().any(char::is_whitespace) {
+        return Err(format!("`{value}` is not a URL: it contains spaces"));
+    }
+
+    let Some((scheme, rest)) = value.split_once(':').filter(|(s, _)| {
+        s.starts_with(|c: char| c.is_ascii_alphabetic())
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    }) else {
+        return Err(format!(
+            "`{value}` is not a URL: it should start with a scheme like `https:`"
+        ));
+    };
+
+    if rest.is_empty() {
+        return Err(format!("`{value}` is not a URL: it is only a scheme"));
+    }
+
+    if scheme.eq_ignore_ascii_case("http") || scheme.eq_ignore_ascii_case("https") {
+        let host = rest
+            .strip_prefix("//")
+            .map(|r| r.split(['/', '?', '#']).next().unwrap_or(""))
+            .unwrap_or("");
+
+        if host.is_empty() {
+            return Err(format!("`{value}` is not a URL: it has no host name"));
+        }
+    }
+
+    Ok(())
+}
+
+fn check_pages(value: &str) -> Result<(), String> {
+    if value.is_empty() {
+        return Err("the page range is empty".to_owned());
+    }
+
+    for item in value.split(',').map(str::trim) {
+        // Runs of hyphens and dashes separate the ends of a range.
+        let ends: Vec<&str> = item
+            .split(['-', '\u{2013}', '\u{2014}'])
+            .filter(|s| !s.is_empty())
+            .collect();
+        let has_dash = item.contains(['-', '\u{2013}', '\u{2014}']);
+
+        match ends[..] {
+            [_] if !has_dash => {}
+
+            [first, last] if has_dash && !item.starts_with('-') && !item.ends_with('-') => {
+                if let (Ok(first), Ok(last)) =
+                    (first.trim().parse::<u64>(), last.trim().parse::<u64>())
+                {
+                    if first > last {
+                        return Err(format!("the page range `{item}` runs backwards"));
+                    }
+                }
+            }
+
+            _ => return Err(format!("`{item}` is not a page or page range")),
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ok(check: FieldCheck, value: &str) {
+        assert_eq!(check.check(value), None, "{value}");
+    }
+
+    fn bad(check: FieldCheck, value: &str) {
+        assert!(check.check(value).is_some(), "{value}");
+    }
+
+    #[test]
+    fn test_checks() {
+        ok(FieldCheck::Doi, "10.1000/182");
+        ok(
+            FieldCheck::Doi,
+            "https://doi.org/10.1103/PhysRevLett.116.061102",
+        );
+        ok(FieldCheck::Doi, "doi: 10.1000/a\\_b{C}");
+        bad(FieldCheck::Doi, "10.1000");
+        bad(FieldCheck::Doi, "11.1000/182");
+        bad(FieldCheck::Doi, "10.abc/182");
+
+        ok(FieldCheck::Isbn, "0-306-40615-2");
+        ok(FieldCheck::Isbn, "978-0-306-40615-7");
+        ok(FieldCheck::Isbn, "0-8044-2957-X");
+        bad(FieldCheck::Isbn, "0-306-40615-3");
+        bad(FieldCheck::Isbn, "978-0-306-40615-6");
+        bad(FieldCheck::Isbn, "X-306-40615-2");
+        bad(FieldCheck::Isbn, "12345");
+
+        ok(FieldCheck::Issn, "0378-5955");
+        ok(FieldCheck::Issn, "2049-3630");
+        bad(FieldCheck::Issn, "0378-5954");
+        bad(FieldCheck::Issn, "03785955");
+
+        ok(FieldCheck::Url, "https://tectonic-typesetting.github.io/");
+        ok(FieldCheck::Url, "mailto:someone@example.com");
+        bad(FieldCheck::Url, "www.example.com");
+        bad(FieldCheck::Url, "https:/example.com");
+        bad(FieldCheck::Url, "https://example.com/a b");
+
+        ok(FieldCheck::Pages, "12");
+        ok(FieldCheck::Pages, "12--34");
+        ok(FieldCheck::Pages, "e1234");
+        ok(FieldCheck::Pages, "iv--x, 3-7");
+        ok(FieldCheck::Pages, "12+");
+        ok(FieldCheck::Pages, "12\u{2013}34");
+        bad(FieldCheck::Pages, "34--12");
+        bad(FieldCheck::Pages, "12--");
+        bad(FieldCheck::Pages, "1-2-3");
+    }
+}
@@ -0,0 +1,95 @@
+//! The widths of characters, as measured by `width$`.
+//!
+//! Styles use `width$` to find the widest label of a bibliography, which is
+//! given to the `thebibliography` environment to set the indentation of the
+//! entries. Classic BibTeX measures text in the widths of the characters of
+//! cmr10, in thousandths of an em, which can be far off if the document uses
+//! another font. The engine can be given a table of widths to use instead,
+//! with [`BibtexEngine::char_width_table()`](crate::BibtexEngine::char_width_table),
+//! or a function that looks up the metrics of the document's font, with
+//! [`BibtexEngine::char_width_metric()`](crate::BibtexEngine::char_width_metric).
+
+use crate::{char_info::CHAR_WIDTH, exec::ControlSeq};
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+/// How the widths of characters are found.
+pub(crate) enum CharWidths {
+    /// The width of each byte.
+    Table(Box<[i64; 256]>),
+    /// A function giving the width of each character.
+    Metric(Box<dyn FnMut(char) -> i64 + Send>),
+}
+
+impl Default for CharWidths {
+    fn default() -> Self {
+        CharWidths::Table(Box::new(CHAR_WIDTH))
+    }
+}
+
+impl Debug for CharWidths {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            CharWidths::Table(_) => f.write_str("CharWidths::Table"),
+            CharWidths::Metric(_) => f.write_str("CharWidths::Metric"),
+        }
+    }
+}
+
+impl CharWidths {
+    /// Get the width of the character at the start of *text*, along with the
+    /// number of bytes that it takes up. With a table, that's always one
+    /// byte; with a function, it's a UTF-8 character, or a byte that isn't
+    /// part of one, which is measured as U+FFFD.
+    pub(crate) fn next(&mut self, text: &[u8]) -> (i64, usize) {
+        match self {
+            CharWidths::Table(table) => (table[text[0] as usize], 1),
+            CharWidths::Metric(metric) => {
+                let len = match text[0] {
+                    0xc0..=0xdf => 2,
+                    0xe0..=0xef => 3,
+                    0xf0..=0xf7 => 4,
+                    _ => 1,
+                };
+
+                match text.get(..len).and_then(|c| std::str::from_utf8(c).ok()) {
+                    Some(c) => (metric(c.chars().next().unwrap()), len),
+                    None => (metric(char::REPLACEMENT_CHARACTER), 1),
+                }
+            }
+        }
+    }
+
+    /// Get the width of a character that's written as the control sequence
+    /// *seq*, named *name*, such as `\ss` for `ß`. Tables only give the
+    /// widths of bytes, so the characters that have widths of their own in
+    /// cmr10 keep them, and the others are as wide as the first letter of
+    /// their names.
+    pub(crate) fn control_seq(&mut self, seq: ControlSeq, name: &[u8]) -> i64 {
+        match self {
+            CharWidths::Table(table) => {
+                seq.cmr10_width().unwrap_or_else(|| table[name[0] as usize])
+            }
+            CharWidths::Metric(metric) => metric(seq.char()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn widths() {
+        let mut table = CharWidths::default();
+        assert_eq!(table.next(b"ab"), (500, 1));
+        assert_eq!(table.next("é".as_bytes()), (0, 1));
+        assert_eq!(table.control_seq(ControlSeq::LowerSS, b"ss"), 500);
+        assert_eq!(table.control_seq(ControlSeq::UpperTH, b"TH"), 722);
+
+        let mut metric = CharWidths::Metric(Box::new(|c| c as i64));
+        assert_eq!(metric.next(b"ab"), ('a' as i64, 1));
+        assert_eq!(metric.next("é!".as_bytes()), ('é' as i64, 2));
+        assert_eq!(metric.next(b"\xe9!"), (0xfffd, 1));
+        assert_eq!(metric.control_seq(ControlSeq::LowerSS, b"ss"), 'ß' as i64);
+    }
+}
@@ -0,0 +1,182 @@
+//! Inheriting fields from biblatex `@xdata` entries.
+//!
+//! biblatex lets fields that several entries share, such as the details of a
+//! conference, be given once in an `@xdata` entry. Entries name the `@xdata`
+//! entries that they take fields from in their `xdata` field, as a
+//! comma-separated list of keys, and `@xdata` entries can do the same in
+//! turn. Once the database files have been read, the fields are copied into
+//! the entries that name them, before cross references are resolved, so that
+//! styles see them as if they had been given in the entries themselves. An
+//! entry's own fields take precedence over those that it inherits, and the
+//! fields of earlier `@xdata` entries in the list over those of later ones.
+//!
+//! `@xdata` entries are read whether or not they're cited, wherever they are
+//! in the databases, but they're never output. References to entries that
+//! don't exist or aren't `@xdata` entries, and references that form cycles,
+//! are reported as warnings and otherwise ignored.
+//!
+//! As with everything else that BibTeX does with fields, only fields that the
+//! style declares in its `ENTRY` command are inherited.
+
+use crate::{
+    hash::HashExtra,
+    log::{bad_xdata_reference_warning, xdata_cycle_warning},
+    Bibtex, BibtexError, CiteNumber, GlobalItems, StrIlk,
+};
+
+#[derive(Clone, Copy, Eq, PartialEq)]
+enum State {
+    Unresolved,
+    Resolving,
+    Resolved,
+}
+
+/// Copy the fields of the `@xdata` entries named by each entry into it.
+pub(crate) fn resolve_xdata(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+) -> Result<(), BibtexError> {
+    let mut states = vec![State::Unresolved; globals.cites.num_cites()];
+    let mut path = Vec::new();
+
+    for cite in 0..states.len() {
+        resolve(ctx, globals, cite, &mut states, &mut path)?;
+    }
+
+    Ok(())
+}
+
+/// Resolve the `xdata` field of *cite*, after resolving those of the entries
+/// that it names. *path* holds the entries whose resolution is under way.
+fn resolve(
+    ctx: &mut Bibtex<'_, '_>,
+    globals: &mut GlobalItems<'_>,
+    cite: CiteNumber,
+    states: &mut [State],
+    path: &mut Vec<CiteNumber>,
+) -> Result<(), BibtexError> {
+    if states[cite] != State::Unresolved {
+        return Ok(());
+    }
+
+    let num_fields = globals.other.num_fields();
+    let value = globals
+        .other
+        .field(cite * num_fields + globals.other.xdata_num());
+
+    if value.is_invalid() {
+        states[cite] = State::Resolved;
+        return Ok(());
+    }
+
+    states[cite] = State::Resolving;
+    path.push(cite);
+
+    let keys: Vec<Vec<u8>> = split_keys(globals.pool.get_str(value))
+        .map(<[u8]>::to_vec)
+        .collect();
+
+    for key in keys {
+        let entry_key = globals.pool.get_str(globals.cites.get_cite(cite));
+
+        let Some(parent) = find_entry(globals, &key) else {
+            bad_xdata_reference_warning(ctx, entry_key, &key, "which doesn't exist");
+            continue;
+        };
+
+        if !globals.cites.is_xdata(parent) {
+            bad_xdata_reference_warning(ctx, entry_key, &key, "which isn't an @xdata entry");
+            continue;
+        }
+
+        if states[parent] == State::Resolving {
+            let start = path.iter().position(|&c| c == parent).unwrap_or(0);
+            let cycle: Vec<&[u8]> = path[start..]
+                .iter()
+                .map(|&c| globals.pool.get_str(globals.cites.get_cite(c)))
+                .collect();
+            xdata_cycle_warning(ctx, &cycle);
+            continue;
+        }
+
+        resolve(ctx, globals, parent, states, path)?;
+
+        let child_start = cite * num_fields;
+        let parent_start = parent * num_fields;
+
+        for idx in globals.other.pre_defined_fields()..num_fields {
+            if globals.other.field(child_start + idx).is_invalid() {
+                globals
+                    .other
+                    .set_field(child_start + idx, globals.other.field(parent_start + idx));
+            }
+        }
+
+        // Linting looks at the fields as they were written, so that it can
+        // see those that the style doesn't declare.
+        if ctx.config.lint_entries {
+            let lc_key = |c: CiteNumber| {
+                String::from_utf8_lossy(globals.pool.get_str(globals.cites.get_cite(c)))
+                    .to_ascii_lowercase()
+            };
+
+            if let Some(inherited) = ctx
+                .recorded_entries
+                .get(&lc_key(parent))
+                .map(|e| e.fields.clone())
+            {
+                if let Some(entry) = ctx.recorded_entries.get_mut(&lc_key(cite)) {
+                    entry.fields.extend(inherited);
+                }
+            }
+        }
+    }
+
+    path.pop();
+    states[cite] = State::Resolved;
+    Ok(())
+}
+
+/// Find the entry with the given key, if it was read from a database.
+fn find_entry(globals: &GlobalItems<'_>, key: &[u8]) -> Option<CiteNumber> {
+    let lc_res = globals
+        .hash
+        .lookup_str(globals.pool, &key.to_ascii_lowercase(), StrIlk::LcCite);
+
+    if !lc_res.exists {
+        return None;
+    }
+
+    let HashExtra::LcCite(cite_loc) = globals.hash.node(lc_res.loc).extra else {
+        return None;
+    };
+
+    match globals.hash.node(cite_loc).extra {
+        HashExtra::Cite(cite)
+            if cite < globals.cites.num_cites() && globals.cites.get_type(cite) != 0 =>
+        {
+            Some(cite)
+        }
+        _ => None,
+    }
+}
+
+/// Split the value of an `xdata` field into the keys that it lists.
+fn split_keys(value: &[u8]) -> impl Iterator<Item = &[u8]> {
+    value
+        .split(|&c| c == b',')
+        .map(<[u8]>::trim_ascii)
+        .filter(|key| !key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys() {
+        let keys: Vec<_> = split_keys(b" proc:2020, ,publisher ").collect();
+        assert_eq!(keys, [&b"proc:2020"[..], b"publisher"]);
+        assert_eq!(split_keys(b"").count(), 0);
+    }
+}
@@ -0,0 +1,235 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Auditing the HTML outputs for accessibility gaps.
+//!
+//! Much of the structure of the final HTML comes from the templates rather
+//! than the TeX content, so most of these checks are run on each output file
+//! after it has been rendered. For complete documents, we check that the root
+//! `<html>` element declares a language, that there is exactly one `main`
+//! landmark, and that heading levels never skip downwards (e.g., from `<h2>`
+//! straight to `<h4>`). Landmarks can come either from the template or from
+//! the content, using `tdux:mfs main`, `tdux:mfs nav`, and so on.
+//!
+//! The one thing that can't be seen in the rendered output is whether an
+//! image had real alternative text: images are always emitted with an `alt`
+//! attribute, but it's empty unless a `tdux:alt` special provided the text.
+//! The emitter reports such images here as it encounters them.
+
+use std::fmt::{Display, Error, Formatter, Write};
+
+/// A gap found in one of the HTML outputs.
+#[derive(Debug)]
+pub(crate) enum Gap {
+    /// An image was included without alternative text.
+    MissingAltText(String),
+
+    /// The document does not declare its language.
+    NoLanguage,
+
+    /// The document has no `main` landmark.
+    NoMainLandmark,
+
+    /// The document has this many `main` landmarks, which is more than one.
+    MultipleMainLandmarks(usize),
+
+    /// The heading level jumped from the first level to the second.
+    SkippedHeadingLevel(u8, u8),
+}
+
+impl Display for Gap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        match self {
+            Gap::MissingAltText(src) => write!(f, "image `{src}` has no alternative text"),
+            Gap::NoLanguage => write!(f, "the `<html>` element does not declare a language"),
+            Gap::NoMainLandmark => write!(f, "there is no `main` landmark"),
+            Gap::MultipleMainLandmarks(n) => write!(f, "there are {n} `main` landmarks"),
+            Gap::SkippedHeadingLevel(from, to) => {
+                write!(f, "heading level skips from `<h{from}>` to `<h{to}>`")
+            }
+        }
+    }
+}
+
+/// Accumulates the gaps found while processing.
+#[derive(Debug, Default)]
+pub(crate) struct AccessibilityAudit {
+    /// Gaps found in the content that hasn't been emitted yet.
+    pending: Vec<Gap>,
+
+    /// Gaps found in each output file, in the order that they were emitted.
+    files: Vec<(String, Vec<Gap>)>,
+}
+
+impl AccessibilityAudit {
+    /// Note that an image was emitted into the current content without
+    /// alternative text.
+    pub(crate) fn image_without_alt(&mut self, src_tex_path: &str) {
+        self.pending
+            .push(Gap::MissingAltText(src_tex_path.to_owned()));
+    }
+
+    /// Check a rendered output file.
+    pub(crate) fn check_output(&mut self, output_path: &str, html: &str) {
+        let mut gaps = std::mem::take(&mut self.pending);
+        let mut is_document = false;
+        let mut has_lang = false;
+        let mut n_main = 0;
+        let mut last_heading = None;
+
+        for (name, attrs) in StartTags(html) {
+            let name = name.to_ascii_lowercase();
+
+            if name == "html" {
+                is_document = true;
+                has_lang = attr_value(attrs, "lang").is_some_and(|v| !v.is_empty());
+            }
+
+            if name == "main" || attr_value(attrs, "role") == Some("main") {
+                n_main += 1;
+            }
+
+            if let Some(level) = heading_level(&name) {
+                if let Some(last) = last_heading {
+                    if level > last + 1 {
+                        gaps.push(Gap::SkippedHeadingLevel(last, level));
+                    }
+                }
+
+                last_heading = Some(level);
+            }
+        }
+
+        if is_document {
+            if !has_lang {
+                gaps.push(Gap::NoLanguage);
+            }
+
+            match n_main {
+                0 => gaps.push(Gap::NoMainLandmark),
+                1 => {}
+                n => gaps.push(Gap::MultipleMainLandmarks(n)),
+            }
+        }
+
+        if !gaps.is_empty() {
+            self.files.push((output_path.to_owned(), gaps));
+        }
+    }
+
+    /// Get the total number of gaps that have been found.
+    pub(crate) fn n_gaps(&self) -> usize {
+        self.files.iter().map(|(_, gaps)| gaps.len()).sum()
+    }
+
+    /// Render the report. Each gap is listed on its own line, prefixed with
+    /// the path of the output file in which it was found.
+    pub(crate) fn render(&self) -> String {
+        let mut report = String::new();
+
+        for (path, gaps) in &self.files {
+            for gap in gaps {
+                writeln!(report, "{path}: {gap}").unwrap();
+            }
+        }
+
+        report
+    }
+}
+
+/// Check whether a language tag is plausible. We don't validate it against the
+/// BCP 47 registry, but it should look like `en` or `pt-BR`.
+pub(crate) fn is_plausible_language_tag(tag: &str) -> bool {
+    !tag.is_empty()
+        && tag.split('-').all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+/// The level of a heading element with the given name, if it is one.
+pub(crate) fn heading_level(name: &str) -> Option<u8> {
+    match name.as_bytes() {
+        [b'h', d @ b'1'..=b'6'] => Some(d - b'0'),
+        _ => None,
+    }
+}
+
+/// Look up the value of an attribute in the text of a start tag following its
+/// name. Attributes without values are treated as having empty ones.
+fn attr_value<'a>(mut attrs: &'a str, name: &str) -> Option<&'a str> {
+    loop {
+        attrs = attrs.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == '/');
+
+        if attrs.is_empty() {
+            return None;
+        }
+
+        let name_end = attrs
+            .find(|c: char| c.is_ascii_whitespace() || c == '=' || c == '/')
+            .unwrap_or(attrs.len());
+        let this_name = &attrs[..name_end];
+        attrs = attrs[name_end..].trim_start();
+
+        let value = if let Some(rest) = attrs.strip_prefix('=') {
+            let rest = rest.trim_start();
+
+            let (value, remainder) = match rest.chars().next() {
+                Some(q @ ('"' | '\'')) => {
+                    let end = rest[1..].find(q).map(|i| i + 1).unwrap_or(rest.len());
+                    (&rest[1..end], rest.get(end + 1..).unwrap_or_default())
+                }
+                _ => {
+                    let end = rest
+                        .find(|c: char| c.is_ascii_whitespace())
+                        .unwrap_or(rest.len());
+                    (&rest[..end], &rest[end..])
+                }
+            };
+
+            attrs = remainder;
+            value
+        } else {
+            ""
+        };
+
+        if this_name.eq_ignore_ascii_case(name) {
+            return Some(value);
+        }
+    }
+}
+
+/// An iterator over the start tags of an HTML document, yielding each tag's
+/// name and the text of its attributes. This is nowhere near a real HTML
+/// parser, but it doesn't need to be: it only has to understand the output of
+/// our own templates.
+struct StartTags<'a>(&'a str);
+
+impl<'a> Iterator for StartTags<'a> {
+    type Item = (&'a str, &'a str);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let start = self.0.find('<')?;
+            let rest = &self.0[start + 1..];
+
+            // Skip comments wholesale, since they might contain markup.
+            if let Some(comment) = rest.strip_prefix("!--") {
+                self.0 = comment.find("-->").map(|i| &comment[i + 3..]).unwrap_or("");
+                continue;
+            }
+
+            let end = rest.find('>').unwrap_or(rest.len());
+            let tag = &rest[..end];
+            self.0 = rest.get(end + 1..).unwrap_or_default();
+
+            if !tag.starts_with(|c: char| c.is_ascii_alphabetic()) {
+                continue;
+            }
+
+            let name_end = tag
+                .find(|c: char| !c.is_ascii_alphanumeric())
+                .unwrap_or(tag.len());
+            return Some((&tag[..name_end], &tag[name_end..]));
+        }
+    }
+}
@@ -0,0 +1,451 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Stable IDs for anchors.
+//!
+//! Published HTML gets linked to from elsewhere, so the IDs of the elements
+//! that people link to, such as sections, shouldn't change from one build to
+//! the next just because something was added before them. The TeX side can
+//! describe what an element is with `I` lines in its flexible start tag:
+//!
+//! - `Ilabel <name>` gives the element's cross-referencing label,
+//! - `Inumber <number>` gives its number, such as `2.1`, and
+//! - `Ititle <text>` gives its title.
+//!
+//! Unless the tag also sets an explicit `id` attribute, the element gets an ID
+//! derived from these: the label if there is one, so that `sec:intro` becomes
+//! `sec-intro`, and otherwise the number and a short hash of the title, such as
+//! `sec-2-1-5d41a2`. If the same ID comes up twice, the later ones get
+//! suffixes like `-2`.
+//!
+//! Such IDs still change when a label is renamed or an unlabeled section is
+//! renumbered or retitled. So that old links keep working, when output files
+//! are written, the anchors of each build are recorded in [`MAP_NAME`] in the
+//! output directory. The next build compares its anchors with the recorded
+//! ones, matching them up by label, then by title, then by number, and lists
+//! the old IDs that have gone away or moved to another file in the
+//! `redirects` table of the same file, each mapped to the output file and ID
+//! where the element now lives. Redirects are carried over from build to
+//! build, so links that are several builds old keep working. Templates can
+//! include a small script that consults the map when the fragment of a URL
+//! isn't found on the page.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs,
+    path::Path,
+};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::digest::{self, Digest};
+use tectonic_status_base::{tt_warning, StatusBackend};
+
+use crate::output::OutputWriter;
+
+/// The name of the file, relative to the output directory, in which anchors
+/// and redirects are recorded.
+pub(crate) const MAP_NAME: &str = "tdux-anchors.json";
+
+/// The number of hex digits of the title hash used in IDs.
+const TITLE_HASH_LEN: usize = 6;
+
+/// What the TeX code has told us about an element that may be linked to.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AnchorInfo {
+    pub label: Option<String>,
+    pub number: Option<String>,
+    pub title: Option<String>,
+}
+
+impl AnchorInfo {
+    /// Whether nothing is known about the element, in which case it doesn't
+    /// get an anchor.
+    pub fn is_empty(&self) -> bool {
+        self.label.is_none() && self.number.is_none() && self.title.is_none()
+    }
+
+    /// The ID derived from this information, before any deduplication.
+    fn base_id(&self, title_hash: Option<&str>) -> String {
+        if let Some(label) = self.label.as_deref() {
+            return slug(label);
+        }
+
+        let mut id = String::from("sec");
+
+        if let Some(number) = self.number.as_deref() {
+            id.push('-');
+            id.push_str(&slug(number));
+        }
+
+        if let Some(hash) = title_hash {
+            id.push('-');
+            id.push_str(hash);
+        }
+
+        id
+    }
+}
+
+/// An anchor as recorded in the map file.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct Anchor {
+    id: String,
+
+    /// The output file containing the anchor, relative to the output
+    /// directory.
+    path: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    label: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    number: Option<String>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title_hash: Option<String>,
+}
+
+/// Where an old ID now lives.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+struct Target {
+    path: String,
+    id: String,
+}
+
+/// The contents of the map file.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct AnchorMap {
+    anchors: Vec<Anchor>,
+    redirects: BTreeMap<String, Target>,
+}
+
+/// Assigns IDs to anchors and keeps track of them across output files.
+#[derive(Debug, Default)]
+pub(crate) struct AnchorRegistry {
+    /// The anchors in output files that have been emitted.
+    anchors: Vec<Anchor>,
+
+    /// The anchors in the content of the current output file, whose path
+    /// isn't known until it's emitted.
+    pending: Vec<Anchor>,
+
+    /// All of the IDs used so far.
+    ids: HashSet<String>,
+}
+
+impl AnchorRegistry {
+    /// Note an explicit ID given by the TeX code, so that no generated ID
+    /// duplicates it.
+    pub fn note_explicit_id(&mut self, id: &str) {
+        self.ids.insert(id.to_owned());
+    }
+
+    /// Register an element described by *info*, returning its ID. If the TeX
+    /// code gave it an explicit ID, that's used as-is.
+    pub fn register(&mut self, info: &AnchorInfo, explicit_id: Option<&str>) -> String {
+        let title_hash = info.title.as_deref().map(title_hash);
+
+        let id = match explicit_id {
+            Some(id) => {
+                self.note_explicit_id(id);
+                id.to_owned()
+            }
+
+            None => {
+                let base = info.base_id(title_hash.as_deref());
+                let mut id = base.clone();
+                let mut n = 1;
+
+                while !self.ids.insert(id.clone()) {
+                    n += 1;
+                    id = format!("{base}-{n}");
+                }
+
+                id
+            }
+        };
+
+        self.pending.push(Anchor {
+            id: id.clone(),
+            path: String::new(),
+            label: info.label.clone(),
+            number: info.number.clone(),
+            title_hash,
+        });
+
+        id
+    }
+
+    /// Note that the current content has been emitted to the output file
+    /// *path*.
+    pub fn finish_file(&mut self, path: &str) {
+        for mut anchor in self.pending.drain(..) {
+            anchor.path = path.to_owned();
+            self.anchors.push(anchor);
+        }
+    }
+
+    /// Whether no anchors have been emitted.
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    /// Update the map file in the output directory, returning the number of
+    /// redirects that it lists.
+    pub fn write_map(
+        self,
+        out_base: &Path,
+        outputs: &mut OutputWriter,
+        status: &mut dyn StatusBackend,
+    ) -> Result<usize> {
+        let path = out_base.join(MAP_NAME);
+
+        let previous = match fs::read(&path) {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(map) => map,
+                Err(e) => {
+                    tt_warning!(
+                        status,
+                        "ignoring unreadable anchor map `{}`; old links may not be redirected",
+                        path.display();
+                        e.into()
+                    );
+                    AnchorMap::default()
+                }
+            },
+            Err(_) => AnchorMap::default(),
+        };
+
+        let map = AnchorMap {
+            redirects: redirects(&previous, &self.anchors),
+            anchors: self.anchors,
+        };
+
+        let mut data = serde_json::to_vec_pretty(&map)?;
+        data.push(b'\n');
+        outputs.write(&path, &data, status)?;
+        Ok(map.redirects.len())
+    }
+}
+
+/// Work out where the anchors of a previous build have gone in this one.
+fn redirects(previous: &AnchorMap, current: &[Anchor]) -> BTreeMap<String, Target> {
+    let by_id: HashMap<&str, &Anchor> = current.iter().map(|a| (a.id.as_str(), a)).collect();
+    let mut redirects = BTreeMap::new();
+
+    for old in &previous.anchors {
+        if by_id
+            .get(old.id.as_str())
+            .is_some_and(|a| a.path == old.path)
+        {
+            continue;
+        }
+
+        if let Some(new) = successor(old, current) {
+            redirects.insert(
+                old.id.clone(),
+                Target {
+                    path: new.path.clone(),
+                    id: new.id.clone(),
+                },
+            );
+        }
+    }
+
+    // Carry over older redirects, updating them if their targets have moved
+    // again and dropping them if their IDs have come back into use.
+    for (from, target) in &previous.redirects {
+        if by_id.contains_key(from.as_str()) || redirects.contains_key(from) {
+            continue;
+        }
+
+        let updated = match by_id.get(target.id.as_str()) {
+            Some(a) => Target {
+                path: a.path.clone(),
+                id: a.id.clone(),
+            },
+            None => match redirects.get(&target.id) {
+                Some(t) => t.clone(),
+                None => continue,
+            },
+        };
+
+        redirects.insert(from.clone(), updated);
+    }
+
+    redirects
+}
+
+/// Find the anchor of this build that corresponds to an anchor of a previous
+/// build.
+fn successor<'a>(old: &Anchor, current: &'a [Anchor]) -> Option<&'a Anchor> {
+    if old.label.is_some() {
+        if let Some(a) = current.iter().find(|a| a.label == old.label) {
+            return Some(a);
+        }
+    }
+
+    // Titles and numbers aren't unique, so only trust them if they are here.
+    let unique = |matches: &dyn Fn(&Anchor) -> bool| {
+        let mut found = current.iter().filter(|a| matches(a));
+        let first = found.next()?;
+        found.next().is_none().then_some(first)
+    };
+
+    if old.title_hash.is_some() {
+        if let Some(a) = unique(&|a| a.title_hash == old.title_hash) {
+            return Some(a);
+        }
+    }
+
+    if old.number.is_some() {
+        if let Some(a) = unique(&|a| a.number == old.number) {
+            return Some(a);
+        }
+    }
+
+    None
+}
+
+/// Turn arbitrary text into something tidy for use in an ID: runs of
+/// characters other than ASCII letters and digits become single hyphens.
+fn slug(text: &str) -> String {
+    let mut s = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        if c.is_ascii_alphanumeric() {
+            s.push(c.to_ascii_lowercase());
+        } else if !s.is_empty() && !s.ends_with('-') {
+            s.push('-');
+        }
+    }
+
+    while s.ends_with('-') {
+        s.pop();
+    }
+
+    if s.is_empty() {
+        s.push_str("anchor");
+    }
+
+    s
+}
+
+/// A short hash of a title, ignoring differences in whitespace.
+fn title_hash(title: &str) -> String {
+    let normalized = title.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut hex = digest::bytes_to_hex(&digest::DigestComputer::digest(normalized.as_bytes()));
+    hex.truncate(TITLE_HASH_LEN);
+    hex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(label: Option<&str>, number: Option<&str>, title: Option<&str>) -> AnchorInfo {
+        AnchorInfo {
+            label: label.map(|s| s.to_owned()),
+            number: number.map(|s| s.to_owned()),
+            title: title.map(|s| s.to_owned()),
+        }
+    }
+
+    fn build(infos: &[AnchorInfo]) -> Vec<Anchor> {
+        let mut reg = AnchorRegistry::default();
+
+        for i in infos {
+            reg.register(i, None);
+        }
+
+        reg.finish_file("index.html");
+        reg.anchors
+    }
+
+    #[test]
+    fn ids() {
+        let mut reg = AnchorRegistry::default();
+        let hash = title_hash("Introduction");
+        assert_eq!(hash.len(), TITLE_HASH_LEN);
+        assert_eq!(title_hash("  Introduction\n"), hash);
+
+        assert_eq!(
+            reg.register(&info(Some("sec:Intro"), Some("1"), None), None),
+            "sec-intro"
+        );
+        assert_eq!(
+            reg.register(&info(None, Some("2.1"), Some("Introduction")), None),
+            format!("sec-2-1-{hash}")
+        );
+        assert_eq!(
+            reg.register(&info(None, None, Some("Introduction")), None),
+            format!("sec-{hash}")
+        );
+        assert_eq!(
+            reg.register(&info(Some("sec:intro"), None, None), None),
+            "sec-intro-2"
+        );
+        assert_eq!(
+            reg.register(&info(Some("x"), None, None), Some("custom")),
+            "custom"
+        );
+        assert_eq!(slug("::"), "anchor");
+
+        assert!(reg.is_empty());
+        reg.finish_file("ch1.html");
+        assert_eq!(reg.anchors.len(), 5);
+        assert_eq!(reg.anchors[4].path, "ch1.html");
+    }
+
+    #[test]
+    fn redirecting() {
+        let old = AnchorMap {
+            anchors: build(&[
+                info(Some("sec:intro"), Some("1"), Some("Intro")),
+                info(None, Some("2"), Some("Methods")),
+                info(None, Some("3"), Some("Results")),
+                info(None, Some("4"), Some("Gone")),
+            ]),
+            redirects: [
+                ("sec-ancient".to_owned(), target("index.html", "sec-2-old")),
+                ("sec-lost".to_owned(), target("index.html", "sec-nowhere")),
+            ]
+            .into_iter()
+            .collect(),
+        };
+
+        // A section is inserted before "Methods", and "Results" is retitled.
+        let mut new = build(&[
+            info(Some("sec:intro"), Some("1"), Some("Introduction")),
+            info(None, Some("2"), Some("Background")),
+            info(None, Some("3"), Some("Methods")),
+            info(None, Some("3"), Some("Findings")),
+        ]);
+        new[0].path = "other.html".to_owned();
+
+        let r = redirects(&old, &new);
+        assert_eq!(r["sec-intro"], target("other.html", "sec-intro"));
+        assert_eq!(r[&old.anchors[1].id], target("index.html", &new[2].id));
+        assert!(!r.contains_key(&old.anchors[2].id));
+        assert!(!r.contains_key("sec-lost"));
+        assert_eq!(r.len(), 2);
+
+        // Older redirects follow their targets.
+        let old = AnchorMap {
+            anchors: new.clone(),
+            redirects: [("sec-ancient".to_owned(), target("index.html", &new[2].id))]
+                .into_iter()
+                .collect(),
+        };
+        new.remove(1);
+        let r = redirects(&old, &new);
+        assert_eq!(r["sec-ancient"], target("index.html", &new[1].id));
+    }
+
+    fn target(path: &str, id: &str) -> Target {
+        Target {
+            path: path.to_owned(),
+            id: id.to_owned(),
+        }
+    }
+}
@@ -7,11 +7,12 @@ use serde::Serialize;
 use std::{
     borrow::Cow,
     collections::{hash_map::Iter, HashMap},
-    fs::File,
     io::{Read, Write},
     path::{Path, PathBuf},
+    process::{Command, Stdio},
 };
 use tectonic_errors::{anyhow::Context, prelude::*};
+use tectonic_io_base::digest::{self, Digest, DigestData};
 use tectonic_status_base::tt_warning;
 
 use crate::{fonts::FontEnsemble, specials::Special, Common};
@@ -31,6 +32,9 @@ enum AssetOrigin {
     /// Emit a CSS file containing information about the ensemble of fonts
     /// that have been used.
     FontCss,
+
+    /// Convert the first page of a PDF figure in the source stack to SVG.
+    PdfFigure(String),
 }
 
 impl Assets {
@@ -77,6 +81,52 @@ impl Assets {
         }
     }
 
+    /// Register an image referenced by a `tdux:image` special, returning the
+    /// output path that the HTML should reference. Raster and SVG images are
+    /// copied directly, while PDF figures are converted to SVG, so the returned
+    /// path may differ from the requested one. None is returned if the image
+    /// format isn't supported.
+    pub(crate) fn provide_image(
+        &mut self,
+        src_tex_path: &str,
+        dest_path: &str,
+        common: &mut Common,
+    ) -> Option<String> {
+        let ext = src_tex_path
+            .rsplit_once('.')
+            .map(|t| t.1.to_ascii_lowercase())
+            .unwrap_or_default();
+
+        match ext.as_ref() {
+            "gif" | "jpeg" | "jpg" | "png" | "svg" | "webp" => {
+                self.copy_file(src_tex_path, dest_path);
+                Some(dest_path.to_owned())
+            }
+
+            "pdf" => {
+                let dest_path = match dest_path.rsplit_once('.') {
+                    Some((stem, ext)) if ext.eq_ignore_ascii_case("pdf") => format!("{stem}.svg"),
+                    _ => format!("{dest_path}.svg"),
+                };
+
+                self.paths.insert(
+                    dest_path.clone(),
+                    AssetOrigin::PdfFigure(src_tex_path.to_owned()),
+                );
+                Some(dest_path)
+            }
+
+            _ => {
+                tt_warning!(
+                    common.status,
+                    "ignoring image `{}`: only PNG, JPEG, GIF, WebP, SVG, and PDF images are supported",
+                    src_tex_path
+                );
+                None
+            }
+        }
+    }
+
     fn copy_file<S1: ToString, S2: ToString>(&mut self, src_path: S1, dest_path: S2) {
         self.paths.insert(
             dest_path.to_string(),
@@ -89,27 +139,40 @@ impl Assets {
             .insert(dest_path.to_string(), AssetOrigin::FontCss);
     }
 
-    /// This functional must only be called if `common.out_path` is not None.
+    /// This function must only be called if `common.out_path` is not None.
     pub(crate) fn emit(mut self, mut fonts: FontEnsemble, common: &mut Common) -> Result<()> {
-        let faces = fonts.emit(common.out_base)?;
+        let faces = fonts.emit(
+            common.out_base,
+            &mut common.outputs,
+            common.subset_fonts,
+            common.status,
+        )?;
 
         for (dest_path, origin) in self.paths.drain() {
             match origin {
                 AssetOrigin::Copy(ref src_path) => emit_copied_file(src_path, &dest_path, common),
                 AssetOrigin::FontCss => emit_font_css(&dest_path, &faces, common),
+                AssetOrigin::PdfFigure(ref src_path) => {
+                    emit_pdf_figure(src_path, &dest_path, common)
+                }
             }?;
         }
 
         Ok(())
     }
 
-    pub(crate) fn into_serialize(mut self, fonts: FontEnsemble) -> impl Serialize {
-        let (mut assets, css_data) = fonts.into_serialize();
+    pub(crate) fn into_serialize(
+        mut self,
+        fonts: FontEnsemble,
+        subset_fonts: bool,
+    ) -> impl Serialize {
+        let (mut assets, css_data) = fonts.into_serialize(subset_fonts);
 
         for (dest_path, origin) in self.paths.drain() {
             let info = match origin {
                 AssetOrigin::Copy(src_path) => syntax::AssetOrigin::Copy(src_path),
                 AssetOrigin::FontCss => syntax::AssetOrigin::FontCss(css_data.clone()),
+                AssetOrigin::PdfFigure(src_path) => syntax::AssetOrigin::PdfFigure(src_path),
             };
             assets.0.insert(dest_path, info);
         }
@@ -118,21 +181,21 @@ impl Assets {
     }
 }
 
-/// This functional must only be called if `common.out_path` is not None.
+/// This function must only be called if `common.out_path` is not None.
 fn emit_copied_file(src_tex_path: &str, dest_path: &str, common: &mut Common) -> Result<()> {
     let mut ih = atry!(
         common.hooks.io().input_open_name(src_tex_path, common.status).must_exist();
         ["unable to open provideFile source `{}`", &src_tex_path]
     );
 
-    {
-        let (mut out_file, out_path) = create_asset_file(dest_path, common)?;
+    let mut data = Vec::new();
+    atry!(
+        ih.read_to_end(&mut data);
+        ["unable to read provideFile source `{}`", &src_tex_path]
+    );
 
-        atry!(
-            std::io::copy(&mut ih, &mut out_file);
-            ["cannot copy to output file `{}`", out_path.display()]
-        );
-    }
+    let out_path = asset_output_path(dest_path, common)?;
+    common.outputs.write(&out_path, &data, common.status)?;
 
     let (name, digest_opt) = ih.into_name_digest();
     common
@@ -141,28 +204,129 @@ fn emit_copied_file(src_tex_path: &str, dest_path: &str, common: &mut Common) ->
     Ok(())
 }
 
-/// This functional must only be called if `common.out_path` is not None.
+/// This function must only be called if `common.out_path` is not None.
 fn emit_font_css(dest_path: &str, faces: &str, common: &mut Common) -> Result<()> {
-    let (mut out_file, out_path) = create_asset_file(dest_path, common)?;
+    let out_path = asset_output_path(dest_path, common)?;
+    common
+        .outputs
+        .write(&out_path, faces.as_bytes(), common.status)
+}
+
+/// This function must only be called if `common.out_path` is not None.
+///
+/// If an image cache directory has been configured, converted figures are
+/// stored there keyed by the digest of the input PDF, so that subsequent builds
+/// don't need to convert unchanged figures again. Conversion failures are
+/// reported as warnings, since a missing figure is a better outcome than a
+/// failed build.
+fn emit_pdf_figure(src_tex_path: &str, dest_path: &str, common: &mut Common) -> Result<()> {
+    let mut ih = atry!(
+        common.hooks.io().input_open_name(src_tex_path, common.status).must_exist();
+        ["unable to open PDF figure source `{}`", &src_tex_path]
+    );
 
+    let mut pdf_data = Vec::new();
     atry!(
-        write!(&mut out_file, "{faces}");
-        ["cannot write output file `{}`", out_path.display()]
+        ih.read_to_end(&mut pdf_data);
+        ["unable to read PDF figure source `{}`", &src_tex_path]
     );
 
-    Ok(())
+    let (name, digest_opt) = ih.into_name_digest();
+    common
+        .hooks
+        .event_input_closed(name, digest_opt, common.status);
+
+    let cache_path = match common.image_cache_dir {
+        Some(cache_dir) => {
+            let mut dc = digest::create();
+            dc.update(&pdf_data);
+            let p = atry!(
+                DigestData::from(dc).create_two_part_path(cache_dir);
+                ["cannot create image cache directory under `{}`", cache_dir.display()]
+            );
+            Some(p.with_extension("svg"))
+        }
+
+        None => None,
+    };
+
+    let svg_data = match cache_path.as_ref().and_then(|p| std::fs::read(p).ok()) {
+        Some(d) => d,
+
+        None => {
+            let svg_data = match convert_pdf_to_svg(&pdf_data) {
+                Ok(d) => d,
+                Err(e) => {
+                    tt_warning!(
+                        common.status,
+                        "unable to convert PDF figure `{}` to SVG; it will be missing from the HTML output",
+                        src_tex_path;
+                        e
+                    );
+                    return Ok(());
+                }
+            };
+
+            // Failing to populate the cache isn't worth aborting over. Write
+            // to a temporary file first so that concurrent builds never see
+            // partial outputs.
+            if let Some(p) = cache_path.as_ref() {
+                if let Some(dir) = p.parent() {
+                    if let Ok(mut tmp) = tempfile::NamedTempFile::new_in(dir) {
+                        if tmp.write_all(&svg_data).is_ok() {
+                            let _ = tmp.persist(p);
+                        }
+                    }
+                }
+            }
+
+            svg_data
+        }
+    };
+
+    let out_path = asset_output_path(dest_path, common)?;
+    common.outputs.write(&out_path, &svg_data, common.status)
 }
 
-/// This functional must only be called if `common.out_path` is not None.
-fn create_asset_file(dest_path: &str, common: &mut Common) -> Result<(File, PathBuf)> {
-    let out_path = create_output_path(dest_path, common)?.0.unwrap();
+/// Convert the first page of a PDF document to SVG.
+///
+/// This uses the `pdftocairo` program from Poppler, which must be available on
+/// `$PATH`.
+fn convert_pdf_to_svg(pdf_data: &[u8]) -> Result<Vec<u8>> {
+    let work_dir = atry!(
+        tempfile::Builder::new().prefix("tectonic_spx2html_figure").tempdir();
+        ["cannot create temporary directory for figure conversion"]
+    );
 
-    let out_file = atry!(
-        File::create(&out_path);
-        ["cannot open output file `{}`", out_path.display()]
+    let pdf_path = work_dir.path().join("figure.pdf");
+    let svg_path = work_dir.path().join("figure.svg");
+
+    atry!(
+        std::fs::write(&pdf_path, pdf_data);
+        ["cannot write temporary file `{}`", pdf_path.display()]
     );
 
-    Ok((out_file, out_path))
+    let status = atry!(
+        Command::new("pdftocairo")
+            .args(["-svg", "-f", "1", "-l", "1"])
+            .arg(&pdf_path)
+            .arg(&svg_path)
+            .stdin(Stdio::null())
+            .status();
+        ["cannot run `pdftocairo`; is Poppler installed?"]
+    );
+
+    ensure!(status.success(), "`pdftocairo` failed: {}", status);
+
+    Ok(atry!(
+        std::fs::read(&svg_path);
+        ["cannot read converted figure `{}`", svg_path.display()]
+    ))
+}
+
+/// This function must only be called if `common.out_path` is not None.
+fn asset_output_path(dest_path: &str, common: &mut Common) -> Result<PathBuf> {
+    Ok(create_output_path(dest_path, common)?.0.unwrap())
 }
 
 /// Process a TeX output path into one for the actual filesystem.
@@ -261,6 +425,17 @@ impl AssetSpecification {
                         }
                     }
 
+                    (AO::PdfFigure(new_src), AO::PdfFigure(cur_src)) => {
+                        if cur_src != new_src {
+                            bail!(
+                                "disagreeing sources `{}` and `{}` for converted figure asset `{}`",
+                                cur_src,
+                                new_src,
+                                path
+                            );
+                        }
+                    }
+
                     (AO::FontFile(new_ff), AO::FontFile(cur_ff)) => {
                         if new_ff.source != cur_ff.source {
                             bail!(
@@ -281,9 +456,11 @@ impl AssetSpecification {
                         }
 
                         // We have two font assets with the same source. We need
-                        // to merge the vglyph information, but otherwise we're
-                        // good!
+                        // to merge the vglyph and glyph-usage information, but
+                        // otherwise we're good!
                         syntax::merge_vglyphs(&mut cur_ff.vglyphs, &new_ff.vglyphs);
+                        syntax::merge_sorted(&mut cur_ff.glyphs, &new_ff.glyphs);
+                        syntax::merge_sorted(&mut cur_ff.chunks, &new_ff.chunks);
                     }
 
                     (AO::FontCss(new_fe), AO::FontCss(cur_fe)) => {
@@ -325,8 +502,7 @@ impl AssetSpecification {
     pub fn output_paths(&self) -> impl Iterator<Item = Cow<'_, str>> {
         AssetOutputsIterator {
             iter: self.0 .0.iter(),
-            cur_vg_path: None,
-            next_vg_index: 0,
+            pending: Vec::new(),
         }
     }
 
@@ -370,6 +546,20 @@ impl AssetSpecification {
 
                     (AssetOrigin::FontCss, syntax::AssetOrigin::FontCss(_)) => {}
 
+                    (
+                        AssetOrigin::PdfFigure(run_path),
+                        syntax::AssetOrigin::PdfFigure(pre_path),
+                    ) => {
+                        ensure!(
+                            run_path == pre_path,
+                            "asset `{}` should \
+                            convert figure `{}`, but in this session the source is `{}`",
+                            path,
+                            pre_path,
+                            run_path
+                        );
+                    }
+
                     _ => {
                         bail!(
                             "this session and the precomputed assets disagree on `{}`",
@@ -391,6 +581,9 @@ impl AssetSpecification {
             let mapped = match pre_origin {
                 syntax::AssetOrigin::Copy(pre_path) => AssetOrigin::Copy(pre_path.to_owned()),
                 syntax::AssetOrigin::FontCss(_) => AssetOrigin::FontCss,
+                syntax::AssetOrigin::PdfFigure(pre_path) => {
+                    AssetOrigin::PdfFigure(pre_path.to_owned())
+                }
                 syntax::AssetOrigin::FontFile(_) => continue,
             };
 
@@ -403,40 +596,40 @@ impl AssetSpecification {
 
 struct AssetOutputsIterator<'a> {
     iter: Iter<'a, String, syntax::AssetOrigin>,
-    cur_vg_path: Option<String>,
-    next_vg_index: usize,
+
+    /// Additional outputs of the most recent font file, in reverse order.
+    pending: Vec<String>,
 }
 
 impl<'a> Iterator for AssetOutputsIterator<'a> {
     type Item = Cow<'a, str>;
 
     fn next(&mut self) -> Option<Cow<'a, str>> {
-        if let Some(p) = self.cur_vg_path.as_ref() {
-            let rv = Cow::Owned(format!("vg{}{}", self.next_vg_index, p));
-
-            if self.next_vg_index == 0 {
-                self.cur_vg_path = None;
-            } else {
-                self.next_vg_index -= 1;
-            }
-
-            return Some(rv);
+        if let Some(p) = self.pending.pop() {
+            return Some(Cow::Owned(p));
         }
 
         self.iter.next().map(|(path, origin)| {
             if let syntax::AssetOrigin::FontFile(ref ffi) = origin {
-                if !ffi.vglyphs.is_empty() {
-                    // If we have moved on to a font file with variant glyphs,
-                    // we first (now) yield the unmodified filename, then set up
-                    // to iterate through the `vg` versions.
-                    let mut highest_vg_index = 0;
-
-                    for mapping in ffi.vglyphs.values() {
-                        highest_vg_index = std::cmp::max(highest_vg_index, mapping.index);
-                    }
+                // If we have moved on to a font file with variant glyphs, we
+                // first (now) yield the unmodified filename, then set up to
+                // iterate through the `vg` versions. If the font is split into
+                // chunks, those replace the unmodified file.
+                let mut highest_vg_index = None;
+
+                for mapping in ffi.vglyphs.values() {
+                    highest_vg_index = std::cmp::max(highest_vg_index, Some(mapping.index));
+                }
 
-                    self.cur_vg_path = Some(path.to_owned());
-                    self.next_vg_index = highest_vg_index;
+                if let Some(highest) = highest_vg_index {
+                    self.pending
+                        .extend((0..=highest).map(|i| format!("vg{i}{path}")));
+                }
+
+                if let Some((first, rest)) = ffi.chunks.split_first() {
+                    self.pending
+                        .extend(rest.iter().rev().map(|start| format!("u{start:04x}{path}")));
+                    return Cow::Owned(format!("u{first:04x}{path}"));
                 }
             }
 
@@ -483,6 +676,9 @@ pub(crate) mod syntax {
         /// An OpenType/TrueType font file and variants with customized CMAP tables
         /// allowing access to unusual glyphs.
         FontFile(FontFileAssetData),
+
+        /// Convert the first page of a PDF figure in the source stack to SVG.
+        PdfFigure(String),
     }
 
     impl std::fmt::Display for AssetOrigin {
@@ -512,6 +708,8 @@ pub(crate) mod syntax {
                 AssetOrigin::FontFile(ff) => {
                     write!(f, "font face #{} from `{}`", ff.face_index, ff.source)
                 }
+
+                AssetOrigin::PdfFigure(src) => write!(f, "SVG conversion of `{src}`"),
             }
         }
     }
@@ -532,6 +730,23 @@ pub(crate) mod syntax {
         /// GlyphIds.
         #[serde(serialize_with = "ordered_map")]
         pub vglyphs: HashMap<String, GlyphVariantMapping>,
+
+        /// The glyphs that the document uses, sorted, so that the font can be
+        /// subsetted.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub glyphs: Vec<u16>,
+
+        /// If the font is subsetted and split into chunks by Unicode range, the
+        /// first codepoints of the chunks, sorted.
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        pub chunks: Vec<u32>,
+    }
+
+    /// Merge one sorted list of values into another.
+    pub(crate) fn merge_sorted<T: Copy + Ord>(cur: &mut Vec<T>, new: &[T]) {
+        cur.extend_from_slice(new);
+        cur.sort_unstable();
+        cur.dedup();
     }
 
     /// Merge one table of variant glyph USV mappings into another.
@@ -646,3 +861,80 @@ pub(crate) mod syntax {
         BoldItalic,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+    use tectonic_bridge_core::MinimalDriver;
+    use tectonic_io_base::filesystem::FilesystemIo;
+    use tectonic_status_base::NoopStatusBackend;
+
+    use crate::{
+        accessibility::AccessibilityAudit, anchors::AnchorRegistry, citations::CitationOptions,
+        headings::HeadingOptions, output::OutputWriter,
+    };
+
+    #[test]
+    fn pdf_figure() {
+        let src_dir = tempfile::tempdir().unwrap();
+        let cache_dir = tempfile::tempdir().unwrap();
+        let out_dir = tempfile::tempdir().unwrap();
+
+        // Seed the image cache with the conversion of the figure, so that
+        // this doesn't need `pdftocairo`.
+        let pdf_data = b"%PDF-1.4 not really a figure";
+        std::fs::write(src_dir.path().join("figure.pdf"), pdf_data).unwrap();
+        let mut dc = digest::create();
+        dc.update(pdf_data);
+        let cache_path = DigestData::from(dc)
+            .create_two_part_path(cache_dir.path())
+            .unwrap()
+            .with_extension("svg");
+        std::fs::write(cache_path, b"<svg/>").unwrap();
+
+        let io = FilesystemIo::new(src_dir.path(), false, false, HashSet::new());
+        let mut hooks = MinimalDriver::new(io);
+        let mut status = NoopStatusBackend::default();
+        let mut common = Common {
+            hooks: &mut hooks,
+            status: &mut status,
+            out_base: Some(out_dir.path()),
+            precomputed_assets: None,
+            image_cache_dir: Some(cache_dir.path()),
+            subset_fonts: false,
+            audit: AccessibilityAudit::default(),
+            anchors: AnchorRegistry::default(),
+            outputs: OutputWriter::new(false, None),
+            citations: CitationOptions::default(),
+            social: None,
+            math_rendering: Default::default(),
+            headings: HeadingOptions::default(),
+        };
+
+        let mut assets = Assets::default();
+        assert_eq!(
+            assets.provide_image("figure.pdf", "img/figure.PDF", &mut common),
+            Some("img/figure.svg".to_owned())
+        );
+        assert_eq!(
+            assets.provide_image("photo.PNG", "img/photo.png", &mut common),
+            Some("img/photo.png".to_owned())
+        );
+        assert_eq!(
+            assets.provide_image("plot.eps", "img/plot.eps", &mut common),
+            None
+        );
+        assert!(matches!(
+            assets.paths.get("img/figure.svg"),
+            Some(AssetOrigin::PdfFigure(src)) if src == "figure.pdf"
+        ));
+
+        emit_pdf_figure("figure.pdf", "img/figure.svg", &mut common).unwrap();
+        assert_eq!(
+            std::fs::read(out_dir.path().join("img").join("figure.svg")).unwrap(),
+            b"<svg/>"
+        );
+    }
+}
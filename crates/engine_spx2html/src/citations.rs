@@ -0,0 +1,199 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Citation popovers and bibliography backlinks.
+//!
+//! The TeX side marks up citations and bibliography entries with four
+//! specials:
+//!
+//! - `tdux:citeStart <key>` and `tdux:citeEnd` delimit an in-text citation of
+//!   the entry with the given cite key. The citation becomes a link to the
+//!   entry, `<a class="tdux-cite" href="#tdux-bib-<key>">`, with a unique
+//!   `id` of the form `tdux-cite-<key>-<n>` for the *n*th citation of that
+//!   key, so that the IDs don't change when citations of other keys are
+//!   added or removed.
+//! - `tdux:bibStart <key>` and `tdux:bibEnd` delimit the entry itself in the
+//!   bibliography. The start inserts the anchor that the citations link to.
+//!
+//! If popovers are enabled and the formatted text of the entry is known from
+//! the [`References`] given to the engine, each citation link also gets a
+//! `<span class="tdux-cite-popover" role="tooltip">` holding that text, which
+//! the templates' stylesheets can show on hover or focus. If backlinks are
+//! enabled, the end of each bibliography entry gets a
+//! `<span class="tdux-bib-backlinks">` with a link back to each site that
+//! cited it.
+//!
+//! Links are relative to the output file that they appear in, so backlinks
+//! only cover the citations that precede the entry in the same output file,
+//! and citations only link to entries in the same file.
+
+use std::{collections::HashMap, fmt::Write};
+
+/// The formatted texts of bibliography entries, keyed by cite key.
+///
+/// These are used to fill in the popovers of in-text citations. Drivers can
+/// obtain them from the structured `.bbl` output of the BibTeX engine.
+#[derive(Clone, Debug, Default)]
+pub struct References {
+    texts: HashMap<String, String>,
+}
+
+impl References {
+    /// Create an empty set of references.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Set the plain-text rendering of the entry with the given cite key,
+    /// replacing any previous text for it.
+    pub fn insert(&mut self, key: impl Into<String>, text: impl Into<String>) -> &mut Self {
+        self.texts.insert(key.into(), text.into());
+        self
+    }
+
+    /// Whether there are no references at all.
+    pub fn is_empty(&self) -> bool {
+        self.texts.is_empty()
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&str> {
+        self.texts.get(key).map(|s| s.as_str())
+    }
+}
+
+/// How citations and bibliography entries are rendered.
+#[derive(Debug, Default)]
+pub(crate) struct CitationOptions<'a> {
+    pub references: Option<&'a References>,
+    pub popovers: bool,
+    pub backlinks: bool,
+}
+
+/// Tracks the citation sites in the content of the current output file.
+#[derive(Debug, Default)]
+pub(crate) struct CitationTracker {
+    /// The number of citations of each key so far in the whole document.
+    n_cites: HashMap<String, usize>,
+
+    /// The IDs of the sites citing each key in the current output file.
+    sites: HashMap<String, Vec<String>>,
+
+    /// The IDs and keys of the citations that are currently open, innermost
+    /// last.
+    open_cites: Vec<(String, String)>,
+
+    /// The key of the bibliography entry that is currently open.
+    open_entry: Option<String>,
+}
+
+impl CitationTracker {
+    /// Note the start of a citation of *key*, returning the ID of its site.
+    pub fn start_cite(&mut self, key: &str) -> String {
+        let n = self.n_cites.entry(key.to_owned()).or_default();
+        *n += 1;
+        let id = format!("tdux-cite-{key}-{n}");
+
+        self.sites
+            .entry(key.to_owned())
+            .or_default()
+            .push(id.clone());
+        self.open_cites.push((id.clone(), key.to_owned()));
+        id
+    }
+
+    /// Note the end of a citation, returning the ID of its site and the key
+    /// that it cited, or `None` if no citation is open.
+    pub fn end_cite(&mut self) -> Option<(String, String)> {
+        self.open_cites.pop()
+    }
+
+    /// Note the start of the bibliography entry for *key*.
+    pub fn start_entry(&mut self, key: &str) {
+        self.open_entry = Some(key.to_owned());
+    }
+
+    /// Note the end of a bibliography entry, returning its key and the IDs of
+    /// the sites that cited it, or `None` if no entry is open.
+    pub fn end_entry(&mut self) -> Option<(String, &[String])> {
+        let key = self.open_entry.take()?;
+        let sites = self.sites.get(&key).map_or(&[][..], |s| &s[..]);
+        Some((key, sites))
+    }
+
+    /// Forget the sites of the current output file, since links to them
+    /// won't work from the next one.
+    pub fn finish_file(&mut self) {
+        self.sites.clear();
+    }
+}
+
+/// The ID of the anchor of the bibliography entry for *key*.
+pub(crate) fn entry_id(key: &str) -> String {
+    format!("tdux-bib-{key}")
+}
+
+/// Render the backlinks from a bibliography entry to the sites citing it.
+pub(crate) fn render_backlinks(sites: &[String]) -> String {
+    let mut html = String::from("<span class=\"tdux-bib-backlinks\">");
+
+    for (i, site) in sites.iter().enumerate() {
+        if i > 0 {
+            html.push(' ');
+        }
+
+        html.push_str("<a href=\"#");
+        html_escape::encode_double_quoted_attribute_to_string(site, &mut html);
+        write!(html, "\" aria-label=\"Back to citation {}\">↩</a>", i + 1).unwrap();
+    }
+
+    html.push_str("</span>");
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tracking() {
+        let mut t = CitationTracker::default();
+        assert_eq!(t.start_cite("knuth"), "tdux-cite-knuth-1");
+        assert_eq!(
+            t.end_cite(),
+            Some(("tdux-cite-knuth-1".into(), "knuth".into()))
+        );
+        assert_eq!(t.start_cite("lamport"), "tdux-cite-lamport-1");
+        assert_eq!(t.start_cite("knuth"), "tdux-cite-knuth-2");
+        assert_eq!(
+            t.end_cite(),
+            Some(("tdux-cite-knuth-2".into(), "knuth".into()))
+        );
+        assert_eq!(
+            t.end_cite(),
+            Some(("tdux-cite-lamport-1".into(), "lamport".into()))
+        );
+        assert_eq!(t.end_cite(), None);
+
+        assert!(t.end_entry().is_none());
+        t.start_entry("knuth");
+        let (key, sites) = t.end_entry().unwrap();
+        assert_eq!(key, "knuth");
+        assert_eq!(sites, ["tdux-cite-knuth-1", "tdux-cite-knuth-2"]);
+
+        t.finish_file();
+        t.start_entry("knuth");
+        assert!(t.end_entry().unwrap().1.is_empty());
+        assert_eq!(t.start_cite("knuth"), "tdux-cite-knuth-3");
+    }
+
+    #[test]
+    fn backlinks() {
+        assert_eq!(
+            render_backlinks(&["tdux-cite-1".into(), "tdux-cite-3".into()]),
+            "<span class=\"tdux-bib-backlinks\">\
+             <a href=\"#tdux-cite-1\" aria-label=\"Back to citation 1\">↩</a> \
+             <a href=\"#tdux-cite-3\" aria-label=\"Back to citation 2\">↩</a>\
+             </span>"
+        );
+    }
+}
@@ -12,10 +12,15 @@ use tectonic_errors::prelude::*;
 use tectonic_status_base::tt_warning;
 
 use crate::{
+    accessibility::heading_level,
+    anchors::AnchorInfo,
     assets::Assets,
+    citations::{self, CitationTracker},
     finalization::FinalizingState,
     fonts::{FamilyRelativeFontId, FontEnsemble, FontFamilyAnalysis, PathToNewFont},
+    headings::{self, HeadingNumbers, HeadingTracker},
     html::Element,
+    math,
     specials::Special,
     templating::Templating,
     Common, FixedPoint, TexFontNum,
@@ -31,6 +36,21 @@ pub(crate) struct EmittingState {
     rems_per_tex: f32,
     elem_stack: Vec<ElementState>,
     current_canvas: Option<CanvasState>,
+    directions: Vec<DirectionState>,
+
+    /// Alternative text from a `tdux:alt` special, waiting for the image or
+    /// canvas that it describes.
+    pending_alt: Option<String>,
+
+    /// TeX source from a `tdux:mathSource` special given outside of a canvas,
+    /// waiting for the formula that it describes.
+    pending_math_source: Option<String>,
+
+    /// The citations and bibliography entries in the current content.
+    citations: CitationTracker,
+
+    /// The headings in the current content.
+    headings: HeadingTracker,
 }
 
 #[derive(Debug, Default)]
@@ -93,11 +113,11 @@ impl ContentState {
             return false;
         }
 
-        // TODO: RTL ASSUMPTION!!!!!
-        //
-        // If the "next" x is smaller than the last one, assume that we've
-        // started a new line. We ignore Y values since those are going to
-        // get hairy with subscripts, etc.
+        // Runs of text always arrive in visual order, even inside
+        // right-to-left segments; those are put back into logical order when
+        // the segment ends. So if the "next" x is smaller than the last one,
+        // assume that we've started a new line. We ignore Y values since those
+        // are going to get hairy with subscripts, etc.
 
         if x0 < self.last_content_x {
             return true;
@@ -213,6 +233,27 @@ pub(crate) enum ElementOrigin {
     /// The element was automatically inserted by us to
     /// activate the desired font.
     FontAuto,
+
+    /// The element was inserted to mark a change in the text direction.
+    Direction,
+}
+
+/// A text direction context, opened by a `tdux:dirStart` special.
+///
+/// XeTeX reverses right-to-left segments when it ships them out, so their runs
+/// of text reach us in visual order. To emit them in logical order, we record
+/// where each run starts in the content buffer, and reorder the runs when the
+/// segment ends.
+#[derive(Debug)]
+struct DirectionState {
+    rtl: bool,
+
+    /// The offset in the content buffer at which the segment's content starts.
+    start: usize,
+
+    /// The offsets at which the runs in this segment start, and whether each
+    /// one is visually preceded by a space.
+    runs: Vec<(usize, bool)>,
 }
 
 #[derive(Debug)]
@@ -223,10 +264,13 @@ struct CanvasState {
     y0: i32,
     glyphs: Vec<GlyphInfo>,
     rules: Vec<RuleInfo>,
+
+    /// The TeX source of the canvas, if it's a formula whose source is known.
+    source: Option<String>,
 }
 
 impl CanvasState {
-    fn new(kind: &str, x0: i32, y0: i32) -> Self {
+    fn new(kind: &str, x0: i32, y0: i32, source: Option<String>) -> Self {
         CanvasState {
             kind: kind.to_owned(),
             depth: 1,
@@ -234,6 +278,7 @@ impl CanvasState {
             y0,
             glyphs: Vec::new(),
             rules: Vec::new(),
+            source,
         }
     }
 }
@@ -282,6 +327,11 @@ impl EmittingState {
                 active_font: FamilyRelativeFontId::Regular,
             }],
             current_canvas: None,
+            directions: Vec::new(),
+            pending_alt: None,
+            pending_math_source: None,
+            citations: CitationTracker::default(),
+            headings: HeadingTracker::default(),
         })
     }
 
@@ -295,6 +345,33 @@ impl EmittingState {
             .push_space_if_needed(x0, cur_space_width, self.cur_elstate().do_auto_spaces);
     }
 
+    /// Note the start of a run of content at *x0*.
+    ///
+    /// Inside a right-to-left segment, the runs need to be self-contained so
+    /// that they can be reordered. Rather than emitting the space between this
+    /// run and the previous one, we record it so that it can be inserted in
+    /// the right place later. Callers must call [`Self::end_run`] afterwards.
+    fn begin_run(&mut self, x0: i32, fnum: Option<TexFontNum>) {
+        let do_auto_spaces = self.cur_elstate().do_auto_spaces;
+
+        if let Some(dir) = self.directions.last_mut().filter(|d| d.rtl) {
+            let cur_space_width = self.fonts.maybe_get_font_space_width(fnum);
+            let space = self
+                .content
+                .is_space_needed(x0, cur_space_width, do_auto_spaces);
+            dir.runs.push((self.content.current_content.len(), space));
+            self.content.update_content_pos(x0, cur_space_width);
+        }
+    }
+
+    /// Note the end of a run of content. Inside a right-to-left segment, this
+    /// closes any elements opened to select fonts.
+    fn end_run(&mut self) {
+        if self.directions.last().is_some_and(|d| d.rtl) {
+            self.close_automatics();
+        }
+    }
+
     fn create_elem(&self, name: &str, is_start: bool, common: &mut Common) -> Element {
         // Parsing can never fail since we offer an `Other` element type
         let el: Element = name.parse().unwrap();
@@ -339,12 +416,26 @@ impl EmittingState {
     fn close_one(&mut self) {
         // Refuse the close the root element
         if self.elem_stack.len() > 1 {
-            let cur = self.elem_stack.pop().unwrap();
+            self.pop_one();
+        }
+    }
 
-            if let Some(e) = cur.elem.as_ref() {
-                self.content.push_close_tag(e.name());
-            }
+    /// Pop the topmost element off the stack and emit its closing tag. If the
+    /// element delimited a direction context, the context is finished too.
+    fn pop_one(&mut self) -> ElementState {
+        self.headings
+            .end_element(self.elem_stack.len(), &self.content.current_content);
+        let cur = self.elem_stack.pop().unwrap();
+
+        if cur.origin == ElementOrigin::Direction {
+            self.finish_direction();
+        }
+
+        if let Some(e) = cur.elem.as_ref() {
+            self.content.push_close_tag(e.name());
         }
+
+        cur
     }
 
     /// Close an auto-close elements that are currently at the top of the stack.
@@ -392,10 +483,15 @@ impl EmittingState {
         let mut n_closed = 0;
 
         while self.elem_stack.len() > 1 {
-            let cur = self.elem_stack.pop().unwrap();
+            let cur = self.pop_one();
+
+            // Direction contexts that were left open, such as when TeX closes
+            // them at the end of a paragraph, don't count as imbalanced.
+            if cur.origin == ElementOrigin::Direction {
+                continue;
+            }
 
             if let Some(e) = cur.elem.as_ref() {
-                self.content.push_close_tag(e.name());
                 n_closed += 1;
 
                 if e.name() == name {
@@ -455,7 +551,8 @@ impl EmittingState {
                     canvas.depth += 1;
                 } else {
                     self.close_automatics();
-                    self.current_canvas = Some(CanvasState::new(kind, x, y));
+                    let source = self.pending_math_source.take();
+                    self.current_canvas = Some(CanvasState::new(kind, x, y, source));
                 }
                 Ok(())
             }
@@ -486,11 +583,60 @@ impl EmittingState {
                 Ok(())
             }
 
+            Special::DirectionStart(dir) => {
+                if self.current_canvas.is_none() {
+                    self.handle_direction_start(x, dir, common);
+                }
+                Ok(())
+            }
+
+            Special::DirectionEnd => {
+                if self.current_canvas.is_none() {
+                    self.handle_direction_end(common);
+                }
+                Ok(())
+            }
+
             Special::DirectText(text) => {
                 self.content.push_with_html_escaping(text);
                 Ok(())
             }
 
+            Special::CiteStart(key) => {
+                self.handle_cite_start(x, key, common);
+                Ok(())
+            }
+
+            Special::CiteEnd => {
+                self.handle_cite_end(common);
+                Ok(())
+            }
+
+            Special::BibStart(key) => {
+                self.push_space_if_needed(x, None);
+                self.content
+                    .push_str("<span class=\"tdux-bib-anchor\" id=\"");
+                self.content
+                    .push_with_html_double_quoted_attribute_escaping(citations::entry_id(key));
+                self.content.push_str("\"></span>");
+                self.citations.start_entry(key);
+                Ok(())
+            }
+
+            Special::BibEnd => {
+                match self.citations.end_entry() {
+                    Some((_, sites)) => {
+                        if common.citations.backlinks && !sites.is_empty() {
+                            let html = citations::render_backlinks(sites);
+                            self.content.push_char(' ');
+                            self.content.push_str(&html);
+                        }
+                    }
+                    None => tt_warning!(common.status, "ignoring unpaired tdux:bibEnd special"),
+                }
+                Ok(())
+            }
+
             Special::Emit => self.finish_file(common),
 
             Special::SetTemplate(path) => {
@@ -512,6 +658,36 @@ impl EmittingState {
                 Ok(())
             }
 
+            Special::Image(spec) => {
+                self.handle_image(x, spec, common);
+                Ok(())
+            }
+
+            Special::AltText(text) => {
+                if self.pending_alt.is_some() {
+                    tt_warning!(
+                        common.status,
+                        "tdux:alt special was not followed by an image or canvas"
+                    );
+                }
+
+                self.pending_alt = Some(text.to_owned());
+                Ok(())
+            }
+
+            Special::MathSource(source) => {
+                // A formula nested in another one, say in the text of a
+                // `\text{}`, is part of the source of the outer one.
+                if let Some(canvas) = self.current_canvas.as_mut() {
+                    canvas.source.get_or_insert_with(|| source.to_owned());
+                } else {
+                    self.pending_math_source = Some(source.to_owned());
+                }
+                Ok(())
+            }
+
+            Special::SetLanguage(tag) => self.templating.handle_set_language(tag, common),
+
             other => {
                 tt_warning!(common.status, "ignoring unrecognized special: {}", other);
                 Ok(())
@@ -519,6 +695,226 @@ impl EmittingState {
         }
     }
 
+    /// Handle the start of an in-text citation of the bibliography entry with
+    /// the given key.
+    ///
+    /// The citation becomes a link to the entry. If popovers are enabled and
+    /// we know the text of the entry, the link is described by the popover
+    /// that gets inserted when the citation ends.
+    fn handle_cite_start(&mut self, x: i32, key: &str, common: &mut Common) {
+        let el = self.create_elem("a", true, common);
+        let id = self.citations.start_cite(key);
+        let has_popover = popover_text(key, common).is_some();
+
+        self.close_automatics();
+        self.push_space_if_needed(x, None);
+        self.content.push_str("<a class=\"tdux-cite\" id=\"");
+        self.content.push_str(&id);
+        self.content.push_str("\" href=\"#");
+        self.content
+            .push_with_html_double_quoted_attribute_escaping(citations::entry_id(key));
+        self.content.push_char('"');
+
+        if has_popover {
+            write!(self.content, " aria-describedby=\"{id}-ref\"").unwrap();
+        }
+
+        self.content.push_char('>');
+        self.push_elem(el, ElementOrigin::Manual);
+    }
+
+    /// Handle the end of an in-text citation.
+    fn handle_cite_end(&mut self, common: &mut Common) {
+        let Some((id, key)) = self.citations.end_cite() else {
+            tt_warning!(common.status, "ignoring unpaired tdux:citeEnd special");
+            return;
+        };
+
+        self.close_automatics();
+
+        if let Some(text) = popover_text(&key, common) {
+            write!(
+                self.content,
+                "<span class=\"tdux-cite-popover\" id=\"{id}-ref\" role=\"tooltip\">"
+            )
+            .unwrap();
+            self.content.push_with_html_escaping(text);
+            self.content.push_str("</span>");
+        }
+
+        self.pop_elem("a", common);
+    }
+
+    /// Handle the start of a segment of text with an explicit direction.
+    ///
+    /// The special has the form `tdux:dirStart <dir>`, where `dir` is either
+    /// `ltr` or `rtl`. XeTeX emits these around the segments delimited by its
+    /// TeX--XeT primitives, such as `\beginR` and `\endR`.
+    fn handle_direction_start(&mut self, x: i32, dir: &str, common: &mut Common) {
+        let rtl = match dir {
+            "ltr" => false,
+            "rtl" => true,
+            _ => {
+                tt_warning!(
+                    common.status,
+                    "ignoring tdux:dirStart special with unrecognized direction `{}`",
+                    dir
+                );
+                return;
+            }
+        };
+
+        // In an enclosing right-to-left segment, this whole segment is a
+        // single run as far as reordering is concerned.
+        self.close_automatics();
+        self.begin_run(x, None);
+        self.push_space_if_needed(x, None);
+        write!(self.content, "<span dir=\"{dir}\">").unwrap();
+        self.elem_stack.push(ElementState {
+            elem: Some(Element::Span),
+            origin: ElementOrigin::Direction,
+            ..*self.cur_elstate()
+        });
+
+        self.directions.push(DirectionState {
+            rtl,
+            start: self.content.current_content.len(),
+            runs: Vec::new(),
+        });
+    }
+
+    /// Handle the end of a segment of text with an explicit direction.
+    fn handle_direction_end(&mut self, common: &mut Common) {
+        if !self
+            .elem_stack
+            .iter()
+            .any(|e| e.origin == ElementOrigin::Direction)
+        {
+            tt_warning!(common.status, "ignoring unpaired tdux:dirEnd special");
+            return;
+        }
+
+        self.close_automatics();
+
+        while self.elem_stack.len() > 1 {
+            if self.pop_one().origin == ElementOrigin::Direction {
+                break;
+            }
+        }
+
+        self.end_run();
+    }
+
+    /// Finish the innermost direction context. If it was right-to-left, its
+    /// runs are put into logical order.
+    fn finish_direction(&mut self) {
+        let dir = match self.directions.pop() {
+            Some(d) => d,
+            None => return,
+        };
+
+        if !dir.rtl || dir.runs.is_empty() {
+            return;
+        }
+
+        let inner = self.content.current_content.split_off(dir.start);
+        let mut ends: Vec<usize> = dir.runs.iter().skip(1).map(|r| r.0).collect();
+        ends.push(dir.start + inner.len());
+
+        // Anything before the first run, like a manually-inserted tag, stays
+        // at the front.
+        self.content.push_str(&inner[..dir.runs[0].0 - dir.start]);
+
+        for (i, (&(start, _), end)) in dir.runs.iter().zip(ends).enumerate().rev() {
+            self.content
+                .push_str(&inner[start - dir.start..end - dir.start]);
+
+            if i > 0 && dir.runs[i].1 {
+                self.content.push_char(' ');
+            }
+        }
+    }
+
+    /// Handle an included image.
+    ///
+    /// The special has the form `tdux:image <width> <height> <dest> <src>`,
+    /// where the dimensions are the size of the image box in TeX units, `dest`
+    /// is the output path of the image asset, and `src` is its path in the
+    /// source stack. The source path comes last so that it may contain spaces.
+    /// A dimension of zero means that it wasn't specified, in which case the
+    /// browser will use the natural size of the image (scaled to preserve its
+    /// aspect ratio if the other dimension is given).
+    ///
+    /// The image's alternative text is given by a preceding special of the
+    /// form `tdux:alt <text>`. An empty text marks the image as decorative. If
+    /// there is no such special, the `alt` attribute is left empty, but the
+    /// image is listed as a gap in the accessibility report.
+    fn handle_image(&mut self, x: i32, spec: &str, common: &mut Common) {
+        let mut pieces = spec.splitn(4, ' ');
+
+        let parsed = (|| {
+            let width = pieces.next()?.parse::<FixedPoint>().ok()?;
+            let height = pieces.next()?.parse::<FixedPoint>().ok()?;
+            let dest_path = pieces.next()?;
+            let src_tex_path = pieces.next()?;
+            Some((width, height, dest_path, src_tex_path))
+        })();
+
+        let (width, height, dest_path, src_tex_path) = match parsed {
+            Some(t) => t,
+            None => {
+                tt_warning!(
+                    common.status,
+                    "ignoring malformatted tdux:image special `{}`",
+                    spec
+                );
+                return;
+            }
+        };
+
+        let alt = self.pending_alt.take();
+
+        let url = match self.assets.provide_image(src_tex_path, dest_path, common) {
+            Some(u) => u,
+            None => return,
+        };
+
+        if alt.is_none() {
+            common.audit.image_without_alt(src_tex_path);
+        }
+
+        self.push_space_if_needed(x, None);
+        self.content.push_str("<img class=\"tdux-image\" src=\"");
+        self.content
+            .push_with_html_double_quoted_attribute_escaping(url);
+        self.content.push_str("\" alt=\"");
+        self.content
+            .push_with_html_double_quoted_attribute_escaping(alt.unwrap_or_default());
+        self.content.push_char('"');
+
+        let mut style = String::new();
+
+        if width > 0 {
+            write!(style, "width: {}rem;", width as f32 * self.rems_per_tex).unwrap();
+        }
+
+        if height > 0 {
+            if !style.is_empty() {
+                style.push(' ');
+            }
+
+            write!(style, "height: {}rem;", height as f32 * self.rems_per_tex).unwrap();
+        }
+
+        if !style.is_empty() {
+            self.content.push_str(" style=\"");
+            self.content.push_str(&style);
+            self.content.push_char('"');
+        }
+
+        self.content.push_char('>');
+    }
+
     /// Handle a "flexible" start tag.
     ///
     /// These start tags are built with a line-oriented structure that aims to
@@ -531,11 +927,18 @@ impl EmittingState {
     /// Sname value % Add a CSS setting in the style attr
     /// Uname value % Add an unquoted attribute
     /// Dname value % Add a double-quoted attribute
+    /// Ilabel name % Give the element's cross-referencing label
+    /// Inumber number % Give the element's number, e.g. a section number
+    /// Ititle text % Give the element's title
     /// NAS % Turn off automatic space insertion while processing this tag
     /// NAT % Turn off automatic tag insertion while processing this tag
     /// }
     /// ```
     ///
+    /// If any `I` lines are given, the element is an anchor that gets a stable
+    /// ID derived from them, unless an `id` attribute is given explicitly. See
+    /// the [`crate::anchors`] module.
+    ///
     /// More ...
     fn handle_flexible_start_tag(
         &mut self,
@@ -590,6 +993,7 @@ impl EmittingState {
         let mut styles = Vec::new();
         let mut unquoted_attrs = Vec::new();
         let mut double_quoted_attrs = Vec::new();
+        let mut anchor = AnchorInfo::default();
 
         for line in lines {
             if let Some(cls) = line.strip_prefix('C') {
@@ -675,6 +1079,22 @@ impl EmittingState {
                     }
                 };
                 double_quoted_attrs.push((name.to_owned(), bits.next().map(|v| v.to_owned())));
+            } else if let Some(rest) = line.strip_prefix('I') {
+                let (field, value) = rest.split_once(' ').unwrap_or((rest, ""));
+                let value = Some(value.to_owned());
+
+                match field {
+                    "label" => anchor.label = value,
+                    "number" => anchor.number = value,
+                    "title" => anchor.title = value,
+                    _ => {
+                        tt_warning!(
+                            common.status,
+                            "ignoring TDUX flexible start tag anchor info -- unknown field: {:?}",
+                            rest
+                        );
+                    }
+                }
             } else if line == "NAS" {
                 elstate.do_auto_spaces = false;
             } else if line == "NAT" {
@@ -688,6 +1108,36 @@ impl EmittingState {
             }
         }
 
+        let explicit_id = unquoted_attrs
+            .iter()
+            .chain(double_quoted_attrs.iter())
+            .find(|(name, _)| name == "id")
+            .map(|(_, value)| value.clone().unwrap_or_default());
+
+        let anchor_id = if !anchor.is_empty() {
+            let id = common.anchors.register(&anchor, explicit_id.as_deref());
+
+            if explicit_id.is_none() {
+                double_quoted_attrs.push(("id".to_owned(), Some(id.clone())));
+            }
+
+            Some(id)
+        } else {
+            if let Some(id) = explicit_id.as_deref() {
+                common.anchors.note_explicit_id(id);
+            }
+
+            None
+        };
+
+        // Anchored headings go in the table of contents, and their numbers
+        // may be replaced.
+        let heading = heading_level(tagname).zip(anchor_id);
+
+        if heading.is_some() && anchor.number.is_some() {
+            classes.extend(common.headings.heading_class().map(|c| c.to_owned()));
+        }
+
         self.push_space_if_needed(x, None);
         self.content.push_char('<');
         self.content.push_with_html_escaping(tagname);
@@ -756,6 +1206,19 @@ impl EmittingState {
 
         self.content.push_char('>');
         self.elem_stack.push(elstate);
+
+        if let Some((level, id)) = heading {
+            self.headings.start(
+                level,
+                id,
+                anchor.number,
+                anchor.title.as_deref(),
+                self.elem_stack.len(),
+                self.content.current_content.len(),
+                &common.headings,
+            );
+        }
+
         Ok(())
     }
 
@@ -778,14 +1241,21 @@ impl EmittingState {
                 });
             }
         } else if !glyphs.is_empty() {
+            self.begin_run(xs[0], Some(font_num));
             self.set_up_for_font(xs[0], font_num, common);
-            self.push_space_if_needed(xs[0], Some(font_num));
-            self.content.push_with_html_escaping(text);
+            let shown = self.headings.strip_number(text);
+
+            if !shown.is_empty() {
+                self.push_space_if_needed(xs[0], Some(font_num));
+                self.content.push_with_html_escaping(shown);
+            }
+
+            self.fonts.note_text_used(font_num, text, glyphs);
 
             // To figure out when we need spaces, we need to care about the last
-            // glyph's actual width (well, its advance).
-            //
-            // TODO: RTL correctness!!!!
+            // glyph's actual width (well, its advance). The glyphs are in
+            // visual order, so the last one is the rightmost one, even in
+            // right-to-left text.
 
             let idx = glyphs.len() - 1;
             let gm = atry!(
@@ -800,6 +1270,7 @@ impl EmittingState {
             let cur_space_width = self.fonts.maybe_get_font_space_width(Some(font_num));
             self.content
                 .update_content_pos(xs[idx] + advance, cur_space_width);
+            self.end_run();
         }
 
         Ok(())
@@ -834,6 +1305,7 @@ impl EmittingState {
             // translate them to Unicode, hoping for the best that the naive
             // inversion suffices.
 
+            self.begin_run(xs[0], Some(font_num));
             self.set_up_for_font(xs[0], font_num, common);
 
             let fonts = &mut self.fonts;
@@ -863,6 +1335,8 @@ impl EmittingState {
                 self.content
                     .update_content_pos(xs[idx] + advance, cur_space_width);
             }
+
+            self.end_run();
         }
 
         Ok(())
@@ -1070,6 +1544,22 @@ impl EmittingState {
             }
         }
 
+        // If the formula is to be rendered by the browser, we only need the
+        // bounds to know where the content ends.
+
+        if let Some(source) = canvas.source.as_deref() {
+            let alt = self.pending_alt.as_deref();
+
+            if let Some(html) = math::render(common.math_rendering, source, inline, alt) {
+                self.pending_alt = None;
+                self.content.push_str(&html);
+                let cur_space_width = self.fonts.maybe_get_font_space_width(None);
+                self.content
+                    .update_content_pos(x_max_tex + canvas.x0, cur_space_width);
+                return Ok(());
+            }
+        }
+
         // Now that we have that information, we can lay out the individual
         // glyphs.
         //
@@ -1180,7 +1670,7 @@ impl EmittingState {
 
         write!(
             self.content,
-            "<{} class=\"canvas {}\" style=\"width: {}rem; height: {}rem; padding-left: {}rem{}\">",
+            "<{} class=\"canvas {}\" style=\"width: {}rem; height: {}rem; padding-left: {}rem{}\"",
             element.name(),
             layout_class,
             (x_max_tex - x_min_tex) as f32 * self.rems_per_tex,
@@ -1189,6 +1679,17 @@ impl EmittingState {
             valign,
         )
         .unwrap();
+
+        // If the canvas has alternative text, expose it to assistive
+        // technologies in place of the individually positioned glyphs.
+        if let Some(alt) = self.pending_alt.take() {
+            self.content.push_str(" role=\"img\" aria-label=\"");
+            self.content
+                .push_with_html_double_quoted_attribute_escaping(alt);
+            self.content.push_char('"');
+        }
+
+        self.content.push_char('>');
         self.content.push_str(&inner_content);
         write!(self.content, "</{}>", element.name()).unwrap();
         let cur_space_width = self.fonts.maybe_get_font_space_width(None);
@@ -1198,9 +1699,26 @@ impl EmittingState {
     }
 
     fn finish_file(&mut self, common: &mut Common) -> Result<()> {
-        self.templating
-            .set_variable("tduxContent", self.content.take());
+        // Any open direction contexts refer to the content that we're about to
+        // hand off, so they can't be reordered anymore.
+        for dir in &mut self.directions {
+            dir.start = 0;
+            dir.runs.clear();
+        }
+
+        let path = self.templating.next_output_path().to_owned();
+        let headings = self.headings.finish_file();
+        let mut content = self.content.take();
+
+        if common.headings.numbers == HeadingNumbers::CssCounters {
+            content.insert_str(0, &headings::counter_style(&headings));
+        }
+
+        self.templating.set_variable("tduxContent", content);
+        self.templating.set_headings(&headings, &common.headings);
         self.templating.emit(common)?;
+        self.citations.finish_file();
+        common.anchors.finish_file(&path);
 
         let cur_space_width = self.fonts.maybe_get_font_space_width(None);
         self.content.update_content_pos(0, cur_space_width);
@@ -1222,3 +1740,13 @@ impl EmittingState {
         FinalizingState::new(self.fonts, self.templating, self.assets)
     }
 }
+
+/// The text of the popover for a citation of *key*, if popovers are enabled
+/// and the text of the entry is known.
+fn popover_text<'a>(key: &str, common: &Common<'a>) -> Option<&'a str> {
+    if !common.citations.popovers {
+        return None;
+    }
+
+    common.citations.references.and_then(|r| r.get(key))
+}
@@ -57,6 +57,8 @@ impl FinalizingState {
                 self.templating.handle_set_template_variable(spec, common)
             }
 
+            Special::SetLanguage(tag) => self.templating.handle_set_language(tag, common),
+
             Special::ProvideFile(_) | Special::ProvideSpecial(_) => {
                 self.assets.try_handle_special(special, common);
                 Ok(())
@@ -86,6 +88,7 @@ impl FinalizingState {
 
     fn finish_file(&mut self, common: &mut Common) -> Result<()> {
         self.templating.set_variable("tduxContent", "");
+        self.templating.set_headings(&[], &common.headings);
         self.templating.emit(common)?;
         Ok(())
     }
@@ -9,6 +9,10 @@
 //! possible we try to get "ActualText" info out of the engine so that we don't
 //! have to do this, but for math and potentially other situations this is
 //! sometimes necessary.
+//!
+//! When fonts are emitted, they may also be subsetted to the glyphs that the
+//! document actually uses, with large fonts split into chunks by Unicode range.
+//! See [`crate::subset`] for the details of the subsetting itself.
 
 use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
 use percent_encoding::{utf8_percent_encode, CONTROLS};
@@ -18,10 +22,16 @@ use pinot::{
     types::{FWord, Tag, UfWord},
     FontDataRef, TableProvider,
 };
-use std::{collections::HashMap, num::Wrapping, path::Path};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    num::Wrapping,
+    path::Path,
+};
 use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_warning, StatusBackend};
 
-use crate::FixedPoint;
+use crate::{output::OutputWriter, subset, FixedPoint};
 
 /// A numerical identifier of a glyph in a font.
 pub type GlyphId = u16;
@@ -34,6 +44,16 @@ pub type Usv = u32;
 
 const SSTY: Tag = Tag(0x73_73_74_79);
 
+/// When subsetting, fonts with at least this many glyphs are split into chunks
+/// by Unicode range, so that browsers only need to download the chunks that a
+/// page uses. In practice this singles out CJK fonts.
+const CHUNKED_FONT_MIN_GLYPHS: usize = 8192;
+
+/// The number of codepoints covered by each chunk of a split font. The chunks
+/// are fixed ranges, so that a change to the text of a document only changes
+/// the chunks containing the affected characters.
+const CHUNK_SPAN: u32 = 256;
+
 /// A type for retrieving data about the glyphs used in a particular font.
 #[derive(Debug)]
 pub struct FontFileData {
@@ -42,6 +62,18 @@ pub struct FontFileData {
     /// Currently, this must be an OpenType font.
     buffer: Vec<u8>,
 
+    /// The index of the face that we're using, if the font data are a
+    /// collection.
+    face_index: u32,
+
+    /// The direct character-to-glyph mappings of the font, sorted by character.
+    /// We need these to build the character maps of subsetted fonts.
+    cmap: Vec<(char, GlyphId)>,
+
+    /// The glyphs used by the document outside of the variant fonts, either
+    /// directly or to render characters of its text.
+    used_glyphs: BTreeSet<GlyphId>,
+
     /// Information about how glyphs can be reverse-mapped to Unicode input
     gmap: HashMap<GlyphId, MapEntry>,
 
@@ -147,6 +179,44 @@ pub struct GlyphVariantMapping {
     pub variant_map_index: usize,
 }
 
+/// Information about a font file created by [`FontFileData::emit`].
+#[derive(Clone, Debug)]
+pub struct EmittedFace {
+    /// If this file is a variant font, the variant-map index.
+    pub variant_map_index: Option<usize>,
+
+    /// The path of the file, relative to the output root.
+    pub rel_path: String,
+
+    /// The CSS `src` descriptor for the file.
+    pub css_src: String,
+
+    /// If this file is one chunk of a font split by Unicode range, the CSS
+    /// `unicode-range` descriptor covering its characters.
+    pub unicode_range: Option<String>,
+
+    /// The size of the file in bytes, or zero if it wasn't written.
+    pub size: usize,
+}
+
+impl EmittedFace {
+    fn new(
+        variant_map_index: Option<usize>,
+        rel_path: String,
+        unicode_range: Option<String>,
+    ) -> Self {
+        let rel_url = utf8_percent_encode(&rel_path, CONTROLS).to_string();
+
+        EmittedFace {
+            variant_map_index,
+            css_src: format!(r#"url("{rel_url}") format("opentype")"#),
+            rel_path,
+            unicode_range,
+            size: 0,
+        }
+    }
+}
+
 #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
 pub struct GlyphMetrics {
     /// Advance width in TeX units
@@ -203,6 +273,7 @@ impl FontFileData {
         );
 
         let mut gmap = HashMap::new();
+        let mut cmap_entries = Vec::new();
         let mut space_glyph = 0;
 
         for usv in valid_usvs() {
@@ -220,6 +291,7 @@ impl FontFileData {
             }
 
             gmap.insert(gidx, MapEntry::Direct(c));
+            cmap_entries.push((c, gidx));
         }
 
         // Check for additional substitution-based mappings.
@@ -298,6 +370,9 @@ impl FontFileData {
 
         Ok(FontFileData {
             buffer,
+            face_index,
+            cmap: cmap_entries,
+            used_glyphs: BTreeSet::new(),
             gmap,
             space_glyph,
             units_per_em,
@@ -318,6 +393,57 @@ impl FontFileData {
         self.gmap.get(&glyph).copied()
     }
 
+    /// Note that the document uses a glyph, so that it's kept if the font is
+    /// subsetted.
+    pub fn note_glyph_used(&mut self, glyph: GlyphId) {
+        self.used_glyphs.insert(glyph);
+    }
+
+    /// Note that the document renders a character in this font. Browsers will
+    /// map it to a glyph themselves, so that glyph needs to be kept if the
+    /// font is subsetted.
+    pub fn note_char_used(&mut self, ch: char) {
+        if let Ok(i) = self.cmap.binary_search_by_key(&ch, |e| e.0) {
+            self.used_glyphs.insert(self.cmap[i].1);
+        }
+    }
+
+    /// Get the glyphs that the document uses outside of the variant fonts.
+    pub fn used_glyphs(&self) -> Vec<GlyphId> {
+        self.used_glyphs.iter().copied().collect()
+    }
+
+    /// Test whether this font is split into chunks by Unicode range when it is
+    /// subsetted.
+    fn is_chunked(&self) -> bool {
+        self.hmetrics.len() >= CHUNKED_FONT_MIN_GLYPHS
+    }
+
+    /// Group the used glyphs that are mapped from characters into chunks by
+    /// Unicode range. The keys of the returned map are the first codepoints of
+    /// the chunks.
+    fn chunks(&self) -> BTreeMap<u32, BTreeSet<GlyphId>> {
+        let mut chunks: BTreeMap<_, BTreeSet<_>> = BTreeMap::new();
+
+        for (ch, glyph) in &self.cmap {
+            if self.used_glyphs.contains(glyph) {
+                chunks.entry(chunk_start(*ch)).or_default().insert(*glyph);
+            }
+        }
+
+        chunks
+    }
+
+    /// Get the first codepoints of the chunks that this font is split into when
+    /// it is subsetted. If it isn't split, the list is empty.
+    pub fn chunk_starts(&self) -> Vec<u32> {
+        if self.is_chunked() {
+            self.chunks().into_keys().collect()
+        } else {
+            Vec::new()
+        }
+    }
+
     /// Get the position of the baseline within the standard glyph cell.
     ///
     /// This value gives the position of the baseline in the glyph cell as a
@@ -414,61 +540,70 @@ impl FontFileData {
     /// `out_base` is the output directory, or None if we shouldn't be writing
     /// anything to disk.
     ///
-    /// Return value is a vec of (variant-map-index, CSS-src-field).
+    /// If `subset` is true, the fonts are subsetted to the glyphs that the
+    /// document uses, and large fonts are split into chunks by Unicode range.
+    /// If subsetting fails, a warning is issued and the whole font is emitted.
     pub fn emit(
         self,
         out_base: Option<&Path>,
+        outputs: &mut OutputWriter,
         rel_path: &str,
-    ) -> Result<Vec<(Option<usize>, String)>> {
-        // Write the main font file ... maybe.
-
-        let mut out_path = out_base.map(|p| p.to_owned());
-
-        if let Some(out_path) = out_path.as_mut() {
-            out_path.push(rel_path);
-            let display_path = out_path.clone();
-            atry!(
-                std::fs::write(out_path, &self.buffer);
-                ["cannot write output file `{}`", display_path.display()]
-            );
-        }
-
-        // CSS info for the main font.
-
-        let rel_url = utf8_percent_encode(rel_path, CONTROLS).to_string();
-        let mut rv = vec![(None, format!(r#"url("{rel_url}") format("opentype")"#))];
+        subset: bool,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<EmittedFace>> {
+        let build = out_base.is_some();
+
+        let files = if subset {
+            match self.subsetted_files(rel_path, build) {
+                Ok(f) => f,
+                Err(e) => {
+                    tt_warning!(
+                        status,
+                        "unable to subset font `{}`; it will be emitted whole",
+                        rel_path;
+                        e
+                    );
+                    self.whole_files(rel_path, build)
+                }
+            }
+        } else {
+            self.whole_files(rel_path, build)
+        };
 
-        // Variants until we're done
+        let mut faces = Vec::with_capacity(files.len());
 
-        let mut buffer = self.buffer;
-        let orig_len = buffer.len();
+        for (mut face, data) in files {
+            if let Some(out_base) = out_base {
+                face.size = data.len();
+                outputs.write(&out_base.join(&face.rel_path), &data, status)?;
+            }
 
-        for cur_map_index in 0.. {
-            let mut mappings = Vec::new();
+            faces.push(face);
+        }
 
-            for (glyph, altmap) in &self.variant_map_allocations {
-                if altmap.variant_map_index == cur_map_index {
-                    mappings.push((altmap.usv, *glyph));
-                }
-            }
+        Ok(faces)
+    }
 
-            if mappings.is_empty() {
-                break;
-            }
+    /// Get the file data for emitting the font without subsetting: the
+    /// original font, followed by the variants. If `build` is false, the data
+    /// are left empty.
+    fn whole_files(&self, rel_path: &str, build: bool) -> Vec<(EmittedFace, Cow<'_, [u8]>)> {
+        let mut files = vec![(
+            EmittedFace::new(None, rel_path.to_owned(), None),
+            Cow::Borrowed(&self.buffer[..]),
+        )];
 
+        for (cur_map_index, mappings) in self.variant_mappings().into_iter().enumerate() {
             // We have some variants to emit! If we're not actually writing
             // files, we might not have much work to actually do though.
 
-            let varname = format!("vg{cur_map_index}{rel_path}");
+            let mut buffer = Vec::new();
 
-            if let Some(out_path) = out_path.as_mut() {
+            if build {
                 // Step 1: create new CMAP, appending to buffer.
-                //
-                // Might be nice to sort mappings as we construct it, rather than
-                // after the fact?
 
-                buffer.truncate(orig_len);
-                mappings.sort_unstable();
+                buffer.extend_from_slice(&self.buffer);
+                let orig_len = buffer.len();
                 append_simple_cmap(&mut buffer, &mappings[..]);
                 let cmap_size = buffer.len() - orig_len;
 
@@ -486,30 +621,133 @@ impl FontFileData {
                 let chkadj = Wrapping(0xB1B0AFBA) - Wrapping(cs);
                 let ofs = self.fontdata_head_offset as usize + 8;
                 BigEndian::write_u32(&mut buffer[ofs..ofs + 4], chkadj.0);
+            }
 
-                // step 4: write new file
+            files.push((
+                EmittedFace::new(
+                    Some(cur_map_index),
+                    format!("vg{cur_map_index}{rel_path}"),
+                    None,
+                ),
+                Cow::Owned(buffer),
+            ));
+        }
 
-                out_path.pop();
-                out_path.push(&varname);
-                let display_path = out_path.clone();
-                atry!(
-                    std::fs::write(out_path, &buffer);
-                    ["cannot write output file `{}`", display_path.display()]
-                );
-            }
+        files
+    }
 
-            // step 5: update CSS
+    /// Get the file data for emitting the font with subsetting: the subsetted
+    /// font, or its chunks, followed by the subsetted variants. If `build` is
+    /// false, the data are left empty.
+    fn subsetted_files(
+        &self,
+        rel_path: &str,
+        build: bool,
+    ) -> Result<Vec<(EmittedFace, Cow<'_, [u8]>)>> {
+        let font = a_ok_or!(
+            FontDataRef::new(&self.buffer).and_then(|fd| fd.get(self.face_index));
+            ["unable to load face #{} in the OpenType font", self.face_index]
+        );
 
-            let rel_url = utf8_percent_encode(&varname, CONTROLS).to_string();
-            rv.push((
-                Some(cur_map_index),
-                format!(r#"url("{rel_url}") format("opentype")"#),
+        let groups = if self.is_chunked() {
+            // Glyphs that aren't mapped from characters, like ligatures, could
+            // be needed by any chunk.
+            let mapped: BTreeSet<_> = self.cmap.iter().map(|e| e.1).collect();
+            let unmapped: Vec<_> = self.used_glyphs.difference(&mapped).copied().collect();
+
+            self.chunks()
+                .into_iter()
+                .map(|(start, mut glyphs)| {
+                    glyphs.extend(&unmapped);
+                    (Some(start), glyphs)
+                })
+                .collect()
+        } else {
+            vec![(None, self.used_glyphs.clone())]
+        };
+
+        let mut files = Vec::with_capacity(groups.len());
+
+        for (chunk, mut glyphs) in groups {
+            subset::close_over_substitutions(&font, &mut glyphs);
+
+            let mappings: Vec<_> = self
+                .cmap
+                .iter()
+                .filter(|(ch, g)| glyphs.contains(g) && chunk.is_none_or(|s| chunk_start(*ch) == s))
+                .copied()
+                .collect();
+
+            let (path, range) = match chunk {
+                Some(start) => (
+                    format!("u{start:04x}{rel_path}"),
+                    Some(unicode_range(&mappings)),
+                ),
+                None => (rel_path.to_owned(), None),
+            };
+
+            files.push((
+                EmittedFace::new(None, path, range),
+                Cow::Owned(self.subsetted_data(&glyphs, &mappings, build)?),
             ));
         }
 
-        // All done!
+        // The variant glyphs are reached through their custom character maps,
+        // so browsers won't substitute them with anything else.
+
+        for (cur_map_index, mappings) in self.variant_mappings().into_iter().enumerate() {
+            let glyphs = mappings.iter().map(|e| e.1).chain([0]).collect();
+
+            files.push((
+                EmittedFace::new(
+                    Some(cur_map_index),
+                    format!("vg{cur_map_index}{rel_path}"),
+                    None,
+                ),
+                Cow::Owned(self.subsetted_data(&glyphs, &mappings, build)?),
+            ));
+        }
+
+        Ok(files)
+    }
+
+    fn subsetted_data(
+        &self,
+        glyphs: &BTreeSet<GlyphId>,
+        mappings: &[(char, GlyphId)],
+        build: bool,
+    ) -> Result<Vec<u8>> {
+        if !build {
+            return Ok(Vec::new());
+        }
+
+        let mut cmap = Vec::new();
+        append_simple_cmap(&mut cmap, mappings);
+        subset::subset(&self.buffer, self.face_index, glyphs, &cmap)
+    }
+
+    /// Get the character mappings of the variant fonts, sorted by character.
+    fn variant_mappings(&self) -> Vec<Vec<(char, GlyphId)>> {
+        let mut variants = Vec::new();
+
+        for cur_map_index in 0.. {
+            let mut mappings = Vec::new();
+
+            for (glyph, altmap) in &self.variant_map_allocations {
+                if altmap.variant_map_index == cur_map_index {
+                    mappings.push((altmap.usv, *glyph));
+                }
+            }
+
+            if mappings.is_empty() {
+                break;
+            }
+
+            mappings.sort_unstable();
+            variants.push(mappings);
+        }
 
-        Ok(rv)
+        variants
     }
 
     /// Emit customized fonts to the filesystem and return information so that
@@ -527,8 +765,8 @@ impl FontFileData {
     }
 
     /// Update this "runtime" information to match the precomputed asset
-    /// information. At the moment the only thing we need to change is the table
-    /// of variant glyphs.
+    /// information. We need to adopt its table of variant glyphs, and since
+    /// this session might emit the assets, its record of the used glyphs.
     pub(crate) fn match_to_precomputed(&mut self, ffad: &crate::assets::syntax::FontFileAssetData) {
         self.variant_map_counts.clear();
         self.variant_map_allocations.clear();
@@ -542,6 +780,7 @@ impl FontFileData {
             *c = std::cmp::max(mapping.index + 1, *c);
         }
 
+        self.used_glyphs.extend(ffad.glyphs.iter().copied());
         self.no_new_variants = true;
     }
 }
@@ -618,11 +857,45 @@ fn load_math_variants(
     Ok(())
 }
 
+/// Get the first codepoint of the chunk of a split font containing a
+/// character.
+fn chunk_start(ch: char) -> u32 {
+    ch as u32 / CHUNK_SPAN * CHUNK_SPAN
+}
+
+/// Build a CSS `unicode-range` descriptor covering the characters of a sorted
+/// character map.
+fn unicode_range(map: &[(char, GlyphId)]) -> String {
+    let mut ranges: Vec<(u32, u32)> = Vec::new();
+
+    for (ch, _) in map {
+        let c = *ch as u32;
+
+        match ranges.last_mut() {
+            Some(r) if r.1 + 1 == c => r.1 = c,
+            _ => ranges.push((c, c)),
+        }
+    }
+
+    let ranges: Vec<_> = ranges
+        .into_iter()
+        .map(|(start, end)| {
+            if start == end {
+                format!("U+{start:X}")
+            } else {
+                format!("U+{start:X}-{end:X}")
+            }
+        })
+        .collect();
+
+    ranges.join(", ")
+}
+
 fn valid_usvs() -> impl Iterator<Item = Usv> {
     (0..0xD800).chain(0xE000..0x11_0000)
 }
 
-fn opentype_checksum(data: &[u8]) -> u32 {
+pub(crate) fn opentype_checksum(data: &[u8]) -> u32 {
     let mut iter = data.chunks_exact(4);
     let cs: Wrapping<u32> = iter
         .by_ref()
@@ -638,7 +911,7 @@ fn opentype_checksum(data: &[u8]) -> u32 {
 /// Append a dumb OpenType CMAP table to a buffer.
 ///
 /// The input *map* must be sorted by USV value.
-fn append_simple_cmap(buf: &mut Vec<u8>, map: &[(char, GlyphId)]) {
+pub(crate) fn append_simple_cmap(buf: &mut Vec<u8>, map: &[(char, GlyphId)]) {
     buf.write_u16::<BigEndian>(0).unwrap(); // version
     buf.write_u16::<BigEndian>(1).unwrap(); // numTables
 
@@ -673,3 +946,98 @@ impl From<crate::assets::syntax::GlyphVariantMapping> for GlyphVariantMapping {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tectonic_status_base::NoopStatusBackend;
+
+    fn load(name: &str) -> FontFileData {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/assets")
+            .join(name);
+        FontFileData::from_opentype(std::fs::read(path).unwrap(), 0).unwrap()
+    }
+
+    #[test]
+    fn emit_subsetted() {
+        let mut ffd = load("lmroman12-regular.otf");
+        let orig_len = ffd.buffer.len();
+
+        for ch in "Hello".chars() {
+            ffd.note_char_used(ch);
+        }
+
+        let x = ffd.cmap[ffd.cmap.binary_search_by_key(&'x', |e| e.0).unwrap()].1;
+        assert_eq!(ffd.request_variant(x, 'H').unwrap().variant_map_index, 0);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut outputs = OutputWriter::default();
+        let mut status = NoopStatusBackend::default();
+        let faces = ffd
+            .emit(Some(dir.path()), &mut outputs, "lm.otf", true, &mut status)
+            .unwrap();
+
+        assert_eq!(faces.len(), 2);
+        assert_eq!(faces[0].rel_path, "lm.otf");
+        assert_eq!(faces[0].variant_map_index, None);
+        assert_eq!(faces[1].rel_path, "vg0lm.otf");
+        assert_eq!(faces[1].variant_map_index, Some(0));
+
+        for face in &faces {
+            let data = std::fs::read(dir.path().join(&face.rel_path)).unwrap();
+            assert_eq!(data.len(), face.size);
+            assert!(face.size < orig_len);
+            assert_eq!(face.unicode_range, None);
+        }
+
+        // The variant font maps `H` to the glyph for `x`, and nothing else.
+        let data = std::fs::read(dir.path().join("vg0lm.otf")).unwrap();
+        let font = FontDataRef::new(&data).unwrap().get(0).unwrap();
+        let cmap = font.cmap().unwrap();
+        assert_eq!(cmap.map('H' as u32), Some(x));
+        assert_eq!(cmap.map('e' as u32), None);
+    }
+
+    #[test]
+    fn emit_chunked() {
+        let mut ffd = load("lmroman12-regular.otf");
+
+        // Pretend that this is a big font.
+        let hm = ffd.hmetrics[0];
+        ffd.hmetrics.resize(CHUNKED_FONT_MIN_GLYPHS, hm);
+
+        for ch in "Hi\u{2013}".chars() {
+            ffd.note_char_used(ch);
+        }
+
+        assert_eq!(ffd.chunk_starts(), [0, 0x2000]);
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut outputs = OutputWriter::default();
+        let mut status = NoopStatusBackend::default();
+        let faces = ffd
+            .emit(Some(dir.path()), &mut outputs, "lm.otf", true, &mut status)
+            .unwrap();
+
+        let summary: Vec<_> = faces
+            .iter()
+            .map(|f| (f.rel_path.as_str(), f.unicode_range.as_deref()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("u0000lm.otf", Some("U+48, U+69")),
+                ("u2000lm.otf", Some("U+2013")),
+            ]
+        );
+        assert!(!dir.path().join("lm.otf").exists());
+    }
+
+    #[test]
+    fn unicode_ranges() {
+        let map: Vec<_> = "ABCEabc\u{4e00}".chars().map(|c| (c, 1)).collect();
+        assert_eq!(unicode_range(&map), "U+41-43, U+45, U+61-63, U+4E00");
+        assert_eq!(chunk_start('\u{4e3f}'), 0x4e00);
+    }
+}
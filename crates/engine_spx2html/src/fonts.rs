@@ -6,6 +6,7 @@
 //! Here a "font family" is interpreted in the HTML sense, meaning a set of
 //! related fonts. In typography you might call this a typeface.
 
+use serde::Serialize;
 use std::{collections::HashMap, fmt::Write, io::Read, path::Path};
 use tectonic_errors::prelude::*;
 use tectonic_io_base::InputHandle;
@@ -14,9 +15,14 @@ use tectonic_status_base::{tt_warning, StatusBackend};
 use crate::{
     assets::syntax,
     fontfile::{FontFileData, GlyphId, GlyphMetrics, MapEntry},
+    output::OutputWriter,
     Common, FixedPoint, TexFontNum,
 };
 
+/// The name of the manifest of the generated font files, at the top of the
+/// output tree. It's only written if fonts are subsetted.
+pub const FONT_MANIFEST_NAME: &str = "tdux-fonts.json";
+
 /// An identifier for a "font file" (which may be one face in a collection).
 type FontId = usize;
 
@@ -71,7 +77,8 @@ impl FontEnsemble {
     /// particular, different sizes of the same font get different font-nums.
     ///
     /// The styling options like *color_rgba* and *slant* are currently stored
-    /// but unused.
+    /// but unused. Fonts marked as *vertical* get a vertical CSS writing mode
+    /// when they're selected.
     #[allow(clippy::too_many_arguments)]
     pub(crate) fn register_tex_font(
         &mut self,
@@ -82,6 +89,7 @@ impl FontEnsemble {
         extend: Option<u32>,
         slant: Option<u32>,
         embolden: Option<u32>,
+        vertical: bool,
         texpath: String,
         ih: InputHandle,
         common: &mut Common,
@@ -95,6 +103,7 @@ impl FontEnsemble {
             extend,
             slant,
             embolden,
+            vertical,
         };
 
         self.tex_fonts.insert(font_num, info);
@@ -337,6 +346,12 @@ impl FontEnsemble {
         cur_af: FamilyRelativeFontId,
     ) -> FontFamilyAnalysis {
         if let Ok(tf) = self.lookup_tex(fnum) {
+            // Vertical fonts need an explicit styling span to set up the
+            // writing mode, so they never participate in families.
+            if tf.vertical && cur_af != FamilyRelativeFontId::Other(tf.fid) {
+                return FontFamilyAnalysis::NoMatch(tf.fid);
+            }
+
             if let Ok(tc) = self.lookup_tex(cur_ffid) {
                 if let Some(cur_fam) = self.font_families.get(&tc.fid) {
                     // Already set up for the right font? If so, great!
@@ -362,7 +377,8 @@ impl FontEnsemble {
     /// Write HTML code for an open `<span>` element that activates a font.
     ///
     /// The font size is specified in CSS "rem" units, which need to be
-    /// calculated with the *rems_per_tex* parameter.
+    /// calculated with the *rems_per_tex* parameter. Vertical fonts also set
+    /// the CSS writing mode, so that browsers lay the text out top-to-bottom.
     pub fn write_styling_span_html<W: Write>(
         &self,
         fnum: TexFontNum,
@@ -371,42 +387,92 @@ impl FontEnsemble {
     ) -> Result<()> {
         let tfi = self.lookup_tex(fnum)?;
         let rel_size = tfi.size as f32 * rems_per_tex;
+        let writing_mode = if tfi.vertical {
+            "writing-mode: vertical-rl; "
+        } else {
+            ""
+        };
 
         write!(
             dest,
-            "<span style=\"font-size: {}rem; {}\">",
+            "<span style=\"font-size: {}rem; {}{}\">",
             rel_size,
+            writing_mode,
             self.font_files[tfi.fid].selection_style_text(None)
         )
         .map_err(|e| e.into())
     }
 
+    /// Note that some text, typeset with the given glyphs, is rendered in the
+    /// font identified by the given SPX font number.
+    pub fn note_text_used(&mut self, fnum: TexFontNum, text: &str, glyphs: &[GlyphId]) {
+        if let Some(tfi) = self.tex_fonts.get(&fnum) {
+            let details = &mut self.font_files[tfi.fid].details;
+
+            for ch in text.chars() {
+                details.note_char_used(ch);
+            }
+
+            for glyph in glyphs {
+                details.note_glyph_used(*glyph);
+            }
+        }
+    }
+
     /// Emit the font files and return CSS code setting up the files.
     ///
+    /// If `subset` is true, the fonts are subsetted, and a manifest of the
+    /// font files is written to [`FONT_MANIFEST_NAME`].
+    ///
     /// This function clears this object's internal data structures, making it
     /// effectively unusable for subsequent operations.
-    pub fn emit(&mut self, out_base: Option<&Path>) -> Result<String> {
+    pub fn emit(
+        &mut self,
+        out_base: Option<&Path>,
+        outputs: &mut OutputWriter,
+        subset: bool,
+        status: &mut dyn StatusBackend,
+    ) -> Result<String> {
         let mut faces = String::default();
+        let mut manifest = FontManifest::default();
 
         for font in self.font_files.drain(..) {
-            font.emit(out_base, &mut faces)?;
+            font.emit(out_base, outputs, subset, status, &mut faces, &mut manifest)?;
+        }
+
+        if let (true, Some(out_base)) = (subset, out_base) {
+            manifest.fonts.sort_by(|a, b| a.path.cmp(&b.path));
+            let mut data = serde_json::to_vec_pretty(&manifest)?;
+            data.push(b'\n');
+            outputs.write(&out_base.join(FONT_MANIFEST_NAME), &data, status)?;
         }
 
         Ok(faces)
     }
 
-    pub(crate) fn into_serialize(mut self) -> (syntax::Assets, syntax::FontEnsembleAssetData) {
+    pub(crate) fn into_serialize(
+        mut self,
+        subset: bool,
+    ) -> (syntax::Assets, syntax::FontEnsembleAssetData) {
         let mut assets: syntax::Assets = Default::default();
         let mut css_data: syntax::FontEnsembleAssetData = Default::default();
         let mut fid_to_filename = Vec::new();
 
         for font in self.font_files.drain(..) {
+            let glyphs = font.details.used_glyphs();
+            let chunks = if subset {
+                font.details.chunk_starts()
+            } else {
+                Vec::new()
+            };
             let vglyphs = font.details.into_vglyphs();
 
             let ffad = syntax::FontFileAssetData {
                 source: font.src_tex_path,
                 face_index: font.face_index,
                 vglyphs,
+                glyphs,
+                chunks,
             };
 
             let filename = ffad.source.clone();
@@ -639,6 +705,10 @@ fn get_text_info(
             None
         };
 
+        if var_index.is_none() {
+            font.details.note_char_used(ch);
+        }
+
         // For later: might help to allow some context about the active font so
         // that we can maybe use a simpler selection string here.
         let font_sel = font.selection_style_text(var_index);
@@ -659,6 +729,35 @@ fn get_text_info(
     text_info
 }
 
+/// The manifest of the font files generated when emitting assets.
+#[derive(Debug, Default, Serialize)]
+struct FontManifest {
+    fonts: Vec<FontManifestEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct FontManifestEntry {
+    /// The path of the file, relative to the output root.
+    path: String,
+
+    /// The TeX path of the font that the file was generated from.
+    source: String,
+
+    /// The index of the face in the source font.
+    face_index: u32,
+
+    /// The CSS `font-family` name declared for the file.
+    family: String,
+
+    /// If the file is one chunk of a font split by Unicode range, the
+    /// characters that it covers, as a CSS `unicode-range` descriptor.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unicode_range: Option<String>,
+
+    /// The size of the file in bytes.
+    size: usize,
+}
+
 /// The return type for [`FontEnsemble::analyze_font_for_family`].
 #[derive(Debug)]
 pub enum FontFamilyAnalysis {
@@ -701,6 +800,9 @@ struct TexFontInfo {
 
     /// Unused TeX/SPX setting.
     embolden: Option<u32>,
+
+    /// Whether this font was loaded for vertical typesetting.
+    vertical: bool,
 }
 
 #[derive(Debug)]
@@ -749,17 +851,31 @@ impl Font {
         }
     }
 
-    fn emit<W: Write>(self, out_base: Option<&Path>, mut dest: W) -> Result<()> {
-        for (var_index, css_src) in self.details.emit(out_base, &self.out_rel_path)? {
+    fn emit<W: Write>(
+        self,
+        out_base: Option<&Path>,
+        outputs: &mut OutputWriter,
+        subset: bool,
+        status: &mut dyn StatusBackend,
+        mut dest: W,
+        manifest: &mut FontManifest,
+    ) -> Result<()> {
+        for face in self
+            .details
+            .emit(out_base, outputs, &self.out_rel_path, subset, status)?
+        {
             // This is almost identical to `selection_style_text`. A major
             // factor is that we're consuming `self`, with `self.details`
             // already consumed by the `emit()` call, so we can't borrow &self.
             // Also, here we have double quotes around the font-family
             // specifier, which we want to have in the CSS but shouldn't have
             // (maybe???) in the HTML `style` attribute.
-            let var_text = var_index.map(|i| format!("vg{i}")).unwrap_or_default();
+            let var_text = face
+                .variant_map_index
+                .map(|i| format!("vg{i}"))
+                .unwrap_or_default();
 
-            let extra = match self.family_relation {
+            let mut extra = match self.family_relation {
                 FamilyRelativeFontId::Regular => "",
                 FamilyRelativeFontId::Bold => "\n    font-weight: bold;",
                 FamilyRelativeFontId::Italic => "\n    font-style: italic;",
@@ -767,7 +883,12 @@ impl Font {
                     "\n    font-weight: bold;\n    font-style: italic;"
                 }
                 FamilyRelativeFontId::Other(_) => unreachable!(),
-            };
+            }
+            .to_owned();
+
+            if let Some(range) = face.unicode_range.as_ref() {
+                write!(extra, "\n    unicode-range: {range};")?;
+            }
 
             writeln!(
                 dest,
@@ -775,8 +896,17 @@ impl Font {
     font-family: "{}{}";{}
     src: {};
 }}"#,
-                self.family_name, var_text, extra, css_src,
+                self.family_name, var_text, extra, face.css_src,
             )?;
+
+            manifest.fonts.push(FontManifestEntry {
+                path: face.rel_path,
+                source: self.src_tex_path.clone(),
+                face_index: self.face_index,
+                family: format!("{}{}", self.family_name, var_text),
+                unicode_range: face.unicode_range,
+                size: face.size,
+            });
         }
 
         Ok(())
@@ -0,0 +1,456 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Heading numbers and tables of contents.
+//!
+//! Headings are the `<h1>` through `<h6>` elements that the TeX side opens
+//! with flexible start tags carrying anchor information (see
+//! [`crate::anchors`]). Their numbers, given by `Inumber` lines, are also
+//! typeset by TeX at the start of the heading text. With
+//! [`HeadingNumbers::Strip`], that text is left out of the output, and with
+//! [`HeadingNumbers::CssCounters`], it's left out too but stylesheet rules
+//! are added that number the headings with CSS counters, so that the numbers
+//! can be styled separately from the titles. The rules go at the start of the
+//! content of each output file, so templates don't need to change. The
+//! counters of each file start from the number of its first numbered heading,
+//! so split documents stay numbered consistently as long as the numbers are
+//! dotted integers like `2.1`; appendix letters and the like are better kept
+//! as typeset.
+//!
+//! Each output file also gets a table of contents of its own headings, with
+//! links to them. It's rendered into the `tduxToc` template variable as a
+//! `<nav class="tdux-toc">` holding nested `<ul>` lists, and the same
+//! headings are available to templates that want to render it differently in
+//! `tduxHeadings`, a list of objects with `level`, `id`, `number` (or null),
+//! and `title` members. The titles are HTML text, so they should be used with
+//! the `safe` filter.
+
+use serde::Serialize;
+use std::fmt::Write;
+
+/// The names of the counters, one per heading level.
+const COUNTER_PREFIX: &str = "tdux-h";
+
+/// The class marking headings numbered with CSS counters.
+const COUNTED_CLASS: &str = "tdux-counted";
+
+/// How the section numbers typeset by TeX in headings are handled in HTML
+/// outputs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum HeadingNumbers {
+    /// Keep the numbers as typeset.
+    #[default]
+    Keep,
+
+    /// Leave the numbers out.
+    Strip,
+
+    /// Leave the numbers out and number the headings with CSS counters.
+    CssCounters,
+}
+
+/// How headings are rendered.
+#[derive(Debug, Default)]
+pub(crate) struct HeadingOptions {
+    pub numbers: HeadingNumbers,
+    pub toc_depth: Option<u8>,
+}
+
+impl HeadingOptions {
+    /// The class to add to a heading with a number, if any.
+    pub fn heading_class(&self) -> Option<&'static str> {
+        match self.numbers {
+            HeadingNumbers::CssCounters => Some(COUNTED_CLASS),
+            _ => None,
+        }
+    }
+
+    /// Whether the number typeset at the start of a heading is left out.
+    pub fn strips_numbers(&self) -> bool {
+        self.numbers != HeadingNumbers::Keep
+    }
+}
+
+/// A heading in the current output file.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub(crate) struct Heading {
+    pub level: u8,
+    pub id: String,
+    pub number: Option<String>,
+
+    /// The title, as HTML text.
+    pub title: String,
+}
+
+/// A heading whose element is still open.
+#[derive(Debug)]
+struct OpenHeading {
+    /// The index of the heading in the list.
+    index: usize,
+
+    /// The depth of the element stack with the heading's element on top.
+    depth: usize,
+
+    /// Where the heading's content starts in the current content.
+    start: usize,
+
+    /// Whether the title was given by the TeX code, rather than to be taken
+    /// from the content.
+    has_title: bool,
+}
+
+/// Tracks the headings in the content of the current output file.
+#[derive(Debug, Default)]
+pub(crate) struct HeadingTracker {
+    headings: Vec<Heading>,
+    open: Option<OpenHeading>,
+
+    /// The part of the current heading's number that hasn't yet been seen at
+    /// the start of its text, if it's to be left out.
+    pending_number: Option<String>,
+}
+
+impl HeadingTracker {
+    /// Note the start of a heading, whose element is at *depth* in the
+    /// element stack and whose content starts at *start*.
+    #[allow(clippy::too_many_arguments)]
+    pub fn start(
+        &mut self,
+        level: u8,
+        id: String,
+        number: Option<String>,
+        title: Option<&str>,
+        depth: usize,
+        start: usize,
+        options: &HeadingOptions,
+    ) {
+        if options.strips_numbers() {
+            self.pending_number = number.clone().filter(|n| !n.is_empty());
+        }
+
+        let mut escaped = String::new();
+
+        if let Some(title) = title {
+            html_escape::encode_safe_to_string(title, &mut escaped);
+        }
+
+        self.open = Some(OpenHeading {
+            index: self.headings.len(),
+            depth,
+            start,
+            has_title: title.is_some(),
+        });
+
+        self.headings.push(Heading {
+            level,
+            id,
+            number,
+            title: escaped,
+        });
+    }
+
+    /// Note that the element at *depth* in the element stack has been closed.
+    /// If it's the current heading, its title is taken from *content* unless
+    /// it was given, without the number if that was kept.
+    pub fn end_element(&mut self, depth: usize, content: &str) {
+        let Some(open) = self.open.take_if(|o| o.depth == depth) else {
+            return;
+        };
+
+        self.pending_number = None;
+
+        if open.has_title {
+            return;
+        }
+
+        if let Some(inner) = content.get(open.start..) {
+            let heading = &mut self.headings[open.index];
+            let text = strip_tags(inner);
+            let mut title = text.trim();
+
+            if let Some(number) = heading.number.as_deref().filter(|n| !n.is_empty()) {
+                if let Some(rest) = title
+                    .strip_prefix(number)
+                    .filter(|rest| !rest.starts_with(char::is_alphanumeric))
+                {
+                    title = rest.trim_start();
+                }
+            }
+
+            heading.title = title.to_owned();
+        }
+    }
+
+    /// Remove what's left of the current heading's number from the start of
+    /// a run of its *text*, returning the rest.
+    pub fn strip_number<'t>(&mut self, text: &'t str) -> &'t str {
+        let Some(pending) = self.pending_number.as_mut() else {
+            return text;
+        };
+
+        let trimmed = text.trim_start();
+
+        let rest = trimmed
+            .strip_prefix(pending.as_str())
+            .filter(|rest| !rest.starts_with(char::is_alphanumeric));
+
+        if let Some(rest) = rest {
+            self.pending_number = None;
+            rest.trim_start()
+        } else if !trimmed.is_empty() && pending.starts_with(trimmed) {
+            pending.drain(..trimmed.len());
+            ""
+        } else if trimmed.is_empty() {
+            text
+        } else {
+            // The text doesn't start with the number after all, so leave it
+            // alone.
+            self.pending_number = None;
+            text
+        }
+    }
+
+    /// Finish the current output file, returning its headings.
+    pub fn finish_file(&mut self) -> Vec<Heading> {
+        self.open = None;
+        self.pending_number = None;
+        std::mem::take(&mut self.headings)
+    }
+}
+
+/// Remove the tags from a fragment of HTML, leaving its text.
+fn strip_tags(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+
+    text
+}
+
+/// The headings of an output file that go in its table of contents.
+pub(crate) fn toc_entries<'h>(
+    headings: &'h [Heading],
+    options: &HeadingOptions,
+) -> Vec<&'h Heading> {
+    headings
+        .iter()
+        .filter(|h| options.toc_depth.is_none_or(|d| h.level <= d))
+        .collect()
+}
+
+/// Render the table of contents of an output file, or nothing if it has no
+/// headings.
+pub(crate) fn render_toc(entries: &[&Heading], options: &HeadingOptions) -> String {
+    if entries.is_empty() {
+        return String::new();
+    }
+
+    let mut html = String::from("<nav class=\"tdux-toc\">");
+
+    // The levels of the lists that are open, each of which has an open item.
+    let mut open: Vec<u8> = Vec::new();
+
+    for h in entries {
+        while open.last().is_some_and(|&l| l > h.level) {
+            html.push_str("</li></ul>");
+            open.pop();
+        }
+
+        if open.last() == Some(&h.level) {
+            html.push_str("</li>");
+        } else {
+            html.push_str("<ul>");
+            open.push(h.level);
+        }
+
+        html.push_str("<li><a href=\"#");
+        html_escape::encode_double_quoted_attribute_to_string(&h.id, &mut html);
+        html.push_str("\">");
+
+        if let Some(number) = h
+            .number
+            .as_deref()
+            .filter(|_| options.numbers != HeadingNumbers::Strip)
+        {
+            html.push_str("<span class=\"tdux-toc-number\">");
+            html_escape::encode_text_to_string(number, &mut html);
+            html.push_str("</span> ");
+        }
+
+        html.push_str(&h.title);
+        html.push_str("</a>");
+    }
+
+    for _ in open {
+        html.push_str("</li></ul>");
+    }
+
+    html.push_str("</nav>");
+    html
+}
+
+/// Render the stylesheet that numbers the headings of an output file with
+/// CSS counters, or nothing if none of them are numbered.
+///
+/// The counters are set up so that the first numbered heading gets its
+/// number as typeset: for a `<h3>` numbered `2.1`, numbering starts from the
+/// `<h2>` level, and the counters start at `2` and `0`.
+pub(crate) fn counter_style(headings: &[Heading]) -> String {
+    let Some((first, number)) = headings
+        .iter()
+        .find_map(|h| h.number.as_deref().map(|n| (h, n)))
+    else {
+        return String::new();
+    };
+
+    let parts: Vec<&str> = number.split('.').collect();
+    let top = (first.level as usize + 1)
+        .saturating_sub(parts.len())
+        .max(1) as u8;
+    let parts = &parts[parts.len() - (first.level - top + 1) as usize..];
+
+    let mut css = String::from("<style>\nbody { counter-reset:");
+
+    for level in top..=6 {
+        let seed = match parts.get((level - top) as usize) {
+            Some(part) => {
+                let n = part.trim().parse::<i32>().unwrap_or(0);
+                if level == first.level {
+                    n - 1
+                } else {
+                    n
+                }
+            }
+            None => 0,
+        };
+
+        write!(css, " {COUNTER_PREFIX}{level} {seed}").unwrap();
+    }
+
+    css.push_str("; }\n");
+
+    for level in top..=6 {
+        write!(
+            css,
+            "h{level}.{COUNTED_CLASS} {{ counter-increment: {COUNTER_PREFIX}{level};"
+        )
+        .unwrap();
+
+        if level < 6 {
+            css.push_str(" counter-set:");
+
+            for deeper in level + 1..=6 {
+                write!(css, " {COUNTER_PREFIX}{deeper} 0").unwrap();
+            }
+
+            css.push(';');
+        }
+
+        css.push_str(" }\n");
+
+        let counters: Vec<String> = (top..=level)
+            .map(|l| format!("counter({COUNTER_PREFIX}{l})"))
+            .collect();
+        writeln!(
+            css,
+            "h{level}.{COUNTED_CLASS}::before {{ content: {} \"\\2003\"; }}",
+            counters.join(" \".\" ")
+        )
+        .unwrap();
+    }
+
+    css.push_str("</style>\n");
+    css
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn heading(level: u8, id: &str, number: Option<&str>, title: &str) -> Heading {
+        Heading {
+            level,
+            id: id.to_owned(),
+            number: number.map(|n| n.to_owned()),
+            title: title.to_owned(),
+        }
+    }
+
+    #[test]
+    fn strip_numbers() {
+        let options = HeadingOptions {
+            numbers: HeadingNumbers::Strip,
+            toc_depth: None,
+        };
+        let mut t = HeadingTracker::default();
+
+        t.start(2, "a".into(), Some("2.1".into()), None, 2, 0, &options);
+        assert_eq!(t.strip_number("2."), "");
+        assert_eq!(t.strip_number("1 Methods"), "Methods");
+        assert_eq!(t.strip_number("2.1"), "2.1");
+
+        t.start(2, "b".into(), Some("3".into()), None, 2, 0, &options);
+        assert_eq!(t.strip_number("30 Results"), "30 Results");
+        assert_eq!(t.strip_number("3"), "3");
+
+        t.start(2, "c".into(), Some("4".into()), None, 2, 0, &options);
+        t.end_element(2, "<b>4</b> Discussion");
+        assert_eq!(t.strip_number("4"), "4");
+
+        let headings = t.finish_file();
+        assert_eq!(headings[2].title, "Discussion");
+    }
+
+    #[test]
+    fn toc() {
+        let headings = [
+            heading(2, "a", Some("1"), "One &amp; two"),
+            heading(3, "b", Some("1.1"), "Sub"),
+            heading(4, "c", Some("1.1.1"), "Subsub"),
+            heading(2, "d", None, "Two"),
+        ];
+
+        let options = HeadingOptions {
+            numbers: HeadingNumbers::Keep,
+            toc_depth: Some(3),
+        };
+        assert_eq!(
+            render_toc(&toc_entries(&headings, &options), &options),
+            "<nav class=\"tdux-toc\"><ul>\
+             <li><a href=\"#a\"><span class=\"tdux-toc-number\">1</span> One &amp; two</a>\
+             <ul><li><a href=\"#b\"><span class=\"tdux-toc-number\">1.1</span> Sub</a></li></ul>\
+             </li><li><a href=\"#d\">Two</a></li></ul></nav>"
+        );
+
+        let options = HeadingOptions {
+            numbers: HeadingNumbers::Strip,
+            toc_depth: Some(1),
+        };
+        assert_eq!(render_toc(&toc_entries(&headings, &options), &options), "");
+    }
+
+    #[test]
+    fn counters() {
+        assert_eq!(counter_style(&[heading(2, "a", None, "Intro")]), "");
+
+        let css = counter_style(&[
+            heading(1, "t", None, "Title"),
+            heading(3, "a", Some("2.1"), "Sub"),
+        ]);
+        assert!(css.contains(
+            "body { counter-reset: tdux-h2 2 tdux-h3 0 tdux-h4 0 tdux-h5 0 tdux-h6 0; }"
+        ));
+        assert!(css.contains(
+            "h3.tdux-counted::before { content: counter(tdux-h2) \".\" counter(tdux-h3) \"\\2003\"; }"
+        ));
+        assert!(!css.contains("h1.tdux-counted"));
+    }
+}
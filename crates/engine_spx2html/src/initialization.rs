@@ -9,8 +9,8 @@ use tectonic_io_base::OpenResult;
 use tectonic_status_base::tt_warning;
 
 use crate::{
-    fonts::FontEnsemble, html::Element, specials::Special, templating::Templating, Common,
-    EmittingState, FixedPoint, TexFontNum,
+    accessibility::is_plausible_language_tag, fonts::FontEnsemble, html::Element,
+    specials::Special, templating::Templating, Common, EmittingState, FixedPoint, TexFontNum,
 };
 
 #[derive(Debug)]
@@ -75,6 +75,7 @@ impl InitializationState {
         extend: Option<u32>,
         slant: Option<u32>,
         embolden: Option<u32>,
+        vertical: bool,
         common: &mut Common,
     ) -> Result<()> {
         if self.fonts.contains(font_num) {
@@ -112,7 +113,8 @@ impl InitializationState {
         // Now that we have that, we can pass off to the font manager.
 
         self.fonts.register_tex_font(
-            font_num, size, face_index, color_rgba, extend, slant, embolden, texpath, ih, common,
+            font_num, size, face_index, color_rgba, extend, slant, embolden, vertical, texpath, ih,
+            common,
         )
     }
 
@@ -126,6 +128,7 @@ impl InitializationState {
             Special::SetTemplate(t) => self.handle_set_template(t, common),
             Special::SetOutputPath(t) => self.handle_set_output_path(t, common),
             Special::SetTemplateVariable(t) => self.handle_set_template_variable(t, common),
+            Special::SetLanguage(t) => self.handle_set_language(t, common),
             Special::StartDefineFontFamily => self.handle_start_define_font_family(),
             Special::EndDefineFontFamily => self.handle_end_define_font_family(common),
             Special::StartFontFamilyTagAssociations => {
@@ -190,6 +193,20 @@ impl InitializationState {
         Ok(())
     }
 
+    fn handle_set_language(&mut self, tag: &str, common: &mut Common) -> Result<()> {
+        if is_plausible_language_tag(tag) {
+            self.variables.insert("tduxLang".to_owned(), tag.to_owned());
+        } else {
+            tt_warning!(
+                common.status,
+                "ignoring tdux:lang special with invalid language tag `{}`",
+                tag
+            );
+        }
+
+        Ok(())
+    }
+
     // "Font family" definitions, allowing us to synthesize bold/italic tags
     // based on tracking font changes, and also to know what the main body font
     // is.
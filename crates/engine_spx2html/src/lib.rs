@@ -6,25 +6,39 @@
 //! SPX is essentially the same thing as XDV, but we identify it differently to
 //! mark that the semantics of the content wil be set up for HTML output.
 
-use std::path::{Path, PathBuf};
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
 use tectonic_bridge_core::DriverHooks;
 use tectonic_errors::prelude::*;
-use tectonic_status_base::StatusBackend;
+use tectonic_status_base::{tt_note, StatusBackend};
 use tectonic_xdv::{FileType, XdvEvents, XdvParser};
 
+mod accessibility;
+mod anchors;
 mod assets;
+mod citations;
 mod emission;
 mod finalization;
 mod fontfile;
 mod fonts;
+mod headings;
 mod html;
 mod initialization;
+mod math;
+mod output;
+mod site;
+mod social;
 mod specials;
+mod subset;
 mod templating;
 
 use self::{
-    assets::Assets, emission::EmittingState, finalization::FinalizingState, fonts::FontEnsemble,
-    initialization::InitializationState, specials::Special,
+    accessibility::AccessibilityAudit, anchors::AnchorRegistry, assets::Assets,
+    citations::CitationOptions, emission::EmittingState, finalization::FinalizingState,
+    fonts::FontEnsemble, headings::HeadingOptions, initialization::InitializationState,
+    output::OutputWriter, specials::Special,
 };
 
 /// An engine that converts SPX to HTML.
@@ -33,7 +47,20 @@ pub struct Spx2HtmlEngine {
     output: OutputState,
     precomputed_assets: Option<AssetSpecification>,
     assets_spec_path: Option<String>,
+    accessibility_report_path: Option<String>,
     do_not_emit_assets: bool,
+    image_cache_dir: Option<PathBuf>,
+    subset_fonts: bool,
+    incremental: bool,
+    references: Option<References>,
+    citation_popovers: bool,
+    bibliography_backlinks: bool,
+    output_filter: Option<Box<dyn OutputFilter>>,
+    social_metadata: Option<SocialMetadata>,
+    preview_image: Option<PreviewImage>,
+    math_rendering: MathRendering,
+    heading_numbers: HeadingNumbers,
+    toc_depth: Option<u8>,
 }
 
 #[derive(Debug, Default)]
@@ -66,6 +93,20 @@ impl Spx2HtmlEngine {
         self
     }
 
+    /// Emit a report of accessibility gaps in the HTML outputs.
+    ///
+    /// If this method is called, each templated output file is audited after
+    /// it's rendered, and the gaps found are listed in a report written to the
+    /// given path in Tectonic's virtual I/O backend. The checks cover images
+    /// without alternative text, documents that don't declare their language
+    /// or that lack a single `main` landmark, and headings that skip levels.
+    /// The report is a UTF8-encoded text file with one gap per line, each
+    /// prefixed with the path of the output file in which it was found.
+    pub fn accessibility_report_path<S: ToString>(&mut self, path: S) -> &mut Self {
+        self.accessibility_report_path = Some(path.to_string());
+        self
+    }
+
     /// Specify that this session should use a precomputed asset specification.
     ///
     /// If this function is used, subsequent runs will generate HTML outputs
@@ -111,6 +152,151 @@ impl Spx2HtmlEngine {
         self
     }
 
+    /// Specify a directory in which to cache converted figures.
+    ///
+    /// PDF figures included in the document are converted to SVG when assets
+    /// are emitted, which requires the `pdftocairo` program from Poppler. If a
+    /// cache directory is specified, converted figures are saved there, keyed
+    /// by the digest of the source PDF, and reused in subsequent builds. If it
+    /// is not specified, every build converts every figure.
+    pub fn image_cache_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.image_cache_dir = Some(dir.into());
+        self
+    }
+
+    /// Subset the fonts that are emitted as assets.
+    ///
+    /// The default is false, in which case each font is emitted whole. If
+    /// enabled, each font only contains the glyphs that the document uses,
+    /// along with those that browsers might need to shape its text, and only
+    /// maps the characters of those glyphs, so that browsers fall back to
+    /// other fonts for anything else, such as text in templates that doesn't
+    /// occur in the document. Fonts with many glyphs, in practice CJK ones, are
+    /// further split into chunks by Unicode range, declared with CSS
+    /// `unicode-range` descriptors so that browsers only download the chunks
+    /// that a page needs. The output is deterministic, so unchanged fonts stay
+    /// unchanged between builds. A manifest of the generated font files is
+    /// written to [`FONT_MANIFEST_NAME`] at the top of the output tree.
+    pub fn subset_fonts(&mut self, enabled: bool) -> &mut Self {
+        self.subset_fonts = enabled;
+        self
+    }
+
+    /// Only rewrite output files whose contents have changed.
+    ///
+    /// The default is false. If enabled, each output file is compared with the
+    /// file already at its path, and is only written if they differ. Every
+    /// HTML output is still rendered, so a chunk of a multi-file document is
+    /// rewritten if its own content changed or if its navigation did, but the
+    /// others keep their modification times, as do unchanged assets. This
+    /// plays nicely with tools that deploy static sites based on those times.
+    /// A note reports how many files were rewritten.
+    pub fn incremental(&mut self, enabled: bool) -> &mut Self {
+        self.incremental = enabled;
+        self
+    }
+
+    /// Specify the formatted texts of the bibliography entries.
+    ///
+    /// These fill in the popovers of in-text citations if
+    /// [`Self::citation_popovers`] is enabled. Citations of keys that aren't
+    /// listed here don't get popovers.
+    pub fn references(&mut self, references: References) -> &mut Self {
+        self.references = Some(references);
+        self
+    }
+
+    /// Give in-text citations popovers showing the entries that they cite.
+    ///
+    /// The default is false. Citations are marked up by the TeX code with the
+    /// `tdux:citeStart` and `tdux:citeEnd` specials, and are always turned
+    /// into links to their bibliography entries. If this is enabled, each
+    /// link also gets a `tdux-cite-popover` element holding the text of the
+    /// entry, as given to [`Self::references`], that stylesheets can show on
+    /// hover.
+    pub fn citation_popovers(&mut self, enabled: bool) -> &mut Self {
+        self.citation_popovers = enabled;
+        self
+    }
+
+    /// Give bibliography entries links back to the places that cite them.
+    ///
+    /// The default is false. Bibliography entries are marked up by the TeX
+    /// code with the `tdux:bibStart` and `tdux:bibEnd` specials. If this is
+    /// enabled, each entry ends with a `tdux-bib-backlinks` element linking to
+    /// each citation of it earlier in the same output file.
+    pub fn bibliography_backlinks(&mut self, enabled: bool) -> &mut Self {
+        self.bibliography_backlinks = enabled;
+        self
+    }
+
+    /// Pass the contents of each output file through a filter before it's
+    /// written.
+    ///
+    /// In incremental mode, the filtered contents are what's compared with
+    /// the existing file.
+    pub fn output_filter(&mut self, filter: Box<dyn OutputFilter>) -> &mut Self {
+        self.output_filter = Some(filter);
+        self
+    }
+
+    /// Describe the document for previews on social media.
+    ///
+    /// If this is called, the OpenGraph and Twitter `<meta>` tags describing
+    /// each output file are rendered into the `tduxSocialMeta` template
+    /// variable, which templates should include in the `<head>` of each page
+    /// as `{{ tduxSocialMeta | safe }}`. Otherwise, the variable is empty.
+    pub fn social_metadata(&mut self, metadata: SocialMetadata) -> &mut Self {
+        self.social_metadata = Some(metadata);
+        self
+    }
+
+    /// Render a preview image for social media.
+    ///
+    /// When assets are emitted, the image is rendered to
+    /// [`PREVIEW_IMAGE_NAME`] at the top of the output tree. If the metadata
+    /// given to [`Self::social_metadata`] don't name an image, the tags point
+    /// to this one.
+    pub fn preview_image(&mut self, preview: PreviewImage) -> &mut Self {
+        self.preview_image = Some(preview);
+        self
+    }
+
+    /// Choose how math is rendered.
+    ///
+    /// The default is [`MathRendering::Glyphs`], in which case formulas are
+    /// drawn as positioned glyphs. The other modes emit the TeX source of
+    /// each formula, as given by the `tdux:mathSource` special, in markup
+    /// that KaTeX or MathJax can render in the browser; the templates need to
+    /// load the renderer. Formulas whose source isn't given are still drawn
+    /// as glyphs.
+    pub fn math_rendering(&mut self, mode: MathRendering) -> &mut Self {
+        self.math_rendering = mode;
+        self
+    }
+
+    /// Choose what happens to the section numbers in headings.
+    ///
+    /// The default is [`HeadingNumbers::Keep`], in which case headings keep
+    /// the numbers typeset by TeX. Otherwise, the numbers are left out of
+    /// headings whose elements give them with `Inumber` lines, and with
+    /// [`HeadingNumbers::CssCounters`], stylesheet rules are emitted that
+    /// number those headings with CSS counters instead.
+    pub fn heading_numbers(&mut self, mode: HeadingNumbers) -> &mut Self {
+        self.heading_numbers = mode;
+        self
+    }
+
+    /// Limit the table of contents to headings of the given level or above.
+    ///
+    /// Each output file gets a table of contents of its headings, in the
+    /// `tduxToc` template variable. By default, it lists all of them. If this
+    /// is called with a depth of 2, it only lists `<h1>` and `<h2>` headings.
+    pub fn toc_depth(&mut self, depth: u8) -> &mut Self {
+        self.toc_depth = Some(depth);
+        self
+    }
+
     /// Specify the root path for output files.
     ///
     /// Because this driver will, in the generic case, produce a tree of HTML
@@ -142,19 +328,98 @@ impl Spx2HtmlEngine {
             OutputState::Undefined => panic!("spx2html output mode not specified"),
         };
 
+        let social = self.social_metadata.clone().map(|mut meta| {
+            if meta.image.is_none() && self.preview_image.is_some() {
+                meta.image = Some(PREVIEW_IMAGE_NAME.to_owned());
+            }
+
+            meta
+        });
+
         {
-            let state = EngineState::new(hooks, status, out_base, self.precomputed_assets.as_ref());
+            let state = EngineState::new(
+                hooks,
+                status,
+                out_base,
+                self.precomputed_assets.as_ref(),
+                self.image_cache_dir.as_deref(),
+                self.subset_fonts,
+                OutputWriter::new(
+                    self.incremental,
+                    self.output_filter.as_deref_mut().map(|f| f as _),
+                ),
+                CitationOptions {
+                    references: self.references.as_ref(),
+                    popovers: self.citation_popovers,
+                    backlinks: self.bibliography_backlinks,
+                },
+                social.as_ref(),
+                self.math_rendering,
+                HeadingOptions {
+                    numbers: self.heading_numbers,
+                    toc_depth: self.toc_depth,
+                },
+            );
             let state = XdvParser::process_with_seeks(&mut input, state)?;
             let (fonts, assets, mut common) = state.finished()?;
 
+            if let Some(arp) = self.accessibility_report_path.as_ref() {
+                let n_gaps = common.audit.n_gaps();
+
+                if n_gaps > 0 {
+                    tt_note!(
+                        common.status,
+                        "found {} accessibility gap(s) in the HTML output; see `{}`",
+                        n_gaps,
+                        arp
+                    );
+                }
+
+                let mut output = common.hooks.io().output_open_name(arp).must_exist()?;
+                output.write_all(common.audit.render().as_bytes())?;
+                let (name, digest) = output.into_name_digest();
+                common.hooks.event_output_closed(name, digest);
+            }
+
             if let Some(asp) = self.assets_spec_path.as_ref() {
-                let ser = assets.into_serialize(fonts);
-                let mut output = hooks.io().output_open_name(asp).must_exist()?;
+                let ser = assets.into_serialize(fonts, common.subset_fonts);
+                let mut output = common.hooks.io().output_open_name(asp).must_exist()?;
                 serde_json::to_writer_pretty(&mut output, &ser)?;
                 let (name, digest) = output.into_name_digest();
-                hooks.event_output_closed(name, digest);
+                common.hooks.event_output_closed(name, digest);
             } else if !self.do_not_emit_assets {
                 assets.emit(fonts, &mut common)?;
+
+                if let (Some(preview), Some(_)) = (self.preview_image.as_ref(), out_base) {
+                    preview.emit(&mut common)?;
+                }
+            }
+
+            if let Some(out_base) = out_base {
+                let anchors = std::mem::take(&mut common.anchors);
+
+                if !anchors.is_empty() {
+                    let n_redirects =
+                        anchors.write_map(out_base, &mut common.outputs, common.status)?;
+
+                    if n_redirects > 0 {
+                        tt_note!(
+                            common.status,
+                            "{} old anchor ID(s) are redirected to their new locations; see `{}`",
+                            n_redirects,
+                            anchors::MAP_NAME
+                        );
+                    }
+                }
+            }
+
+            if self.incremental && out_base.is_some() {
+                tt_note!(
+                    common.status,
+                    "rewrote {} changed HTML output file(s); {} were unchanged",
+                    common.outputs.n_written(),
+                    common.outputs.n_unchanged()
+                );
             }
         }
 
@@ -165,6 +430,13 @@ impl Spx2HtmlEngine {
 }
 
 pub use assets::AssetSpecification;
+pub use citations::References;
+pub use fonts::FONT_MANIFEST_NAME;
+pub use headings::HeadingNumbers;
+pub use math::MathRendering;
+pub use output::OutputFilter;
+pub use site::{Site, SitePage, FEED_NAME, SITEMAP_NAME, SITE_INDEX_NAME};
+pub use social::{PreviewImage, PreviewRegion, SocialMetadata, PREVIEW_IMAGE_NAME};
 
 struct EngineState<'a> {
     common: Common<'a>,
@@ -176,14 +448,31 @@ struct Common<'a> {
     status: &'a mut dyn StatusBackend,
     out_base: Option<&'a Path>,
     precomputed_assets: Option<&'a AssetSpecification>,
+    image_cache_dir: Option<&'a Path>,
+    subset_fonts: bool,
+    audit: AccessibilityAudit,
+    anchors: AnchorRegistry,
+    outputs: OutputWriter<'a>,
+    citations: CitationOptions<'a>,
+    social: Option<&'a SocialMetadata>,
+    math_rendering: MathRendering,
+    headings: HeadingOptions,
 }
 
 impl<'a> EngineState<'a> {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         hooks: &'a mut dyn DriverHooks,
         status: &'a mut dyn StatusBackend,
         out_base: Option<&'a Path>,
         precomputed_assets: Option<&'a AssetSpecification>,
+        image_cache_dir: Option<&'a Path>,
+        subset_fonts: bool,
+        outputs: OutputWriter<'a>,
+        citations: CitationOptions<'a>,
+        social: Option<&'a SocialMetadata>,
+        math_rendering: MathRendering,
+        headings: HeadingOptions,
     ) -> Self {
         Self {
             common: Common {
@@ -191,6 +480,15 @@ impl<'a> EngineState<'a> {
                 status,
                 out_base,
                 precomputed_assets,
+                image_cache_dir,
+                subset_fonts,
+                audit: AccessibilityAudit::default(),
+                anchors: AnchorRegistry::default(),
+                outputs,
+                citations,
+                social,
+                math_rendering,
+                headings,
             },
             state: State::Initializing(InitializationState::default()),
         }
@@ -317,6 +615,7 @@ impl XdvEvents for EngineState<'_> {
         extend: Option<u32>,
         slant: Option<u32>,
         embolden: Option<u32>,
+        vertical: bool,
     ) -> Result<(), Self::Error> {
         match &mut self.state {
             State::Invalid => panic!("invalid spx2html state leaked"),
@@ -329,6 +628,7 @@ impl XdvEvents for EngineState<'_> {
                 extend,
                 slant,
                 embolden,
+                vertical,
                 &mut self.common,
             ),
             _ => Ok(()),
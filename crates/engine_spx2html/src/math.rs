@@ -0,0 +1,120 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Rendering math as TeX markup for client-side renderers.
+//!
+//! By default, math is drawn like any other canvas, as individually
+//! positioned glyphs. Documents can instead have math rendered in the browser
+//! by KaTeX or MathJax, in which case the TeX code has to tell us the source
+//! of each formula: the `tdux:mathSource <tex>` special, given inside the
+//! formula's canvas or just before it starts, supplies it. Formulas whose
+//! source isn't known are still drawn as glyphs.
+//!
+//! For KaTeX, formulas are emitted as `<span class="tdux-math">` or
+//! `<div class="tdux-math">` elements holding the source between `\(`/`\)` or
+//! `\[`/`\]` delimiters, which is what its auto-render extension looks for by
+//! default (MathJax's too). For MathJax, they're emitted as
+//! `<script type="math/tex">` elements, with `; mode=display` for displayed
+//! formulas, which MathJax 2 processes natively. Either way, the page
+//! templates need to load the renderer.
+
+/// How math is rendered in HTML outputs.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum MathRendering {
+    /// Draw math as positioned glyphs, like other canvases.
+    #[default]
+    Glyphs,
+
+    /// Emit the TeX source of math in delimited `<span>` and `<div>`
+    /// elements, for KaTeX.
+    Katex,
+
+    /// Emit the TeX source of math in `<script type="math/tex">` elements,
+    /// for MathJax.
+    MathJax,
+}
+
+/// Render a formula with the given TeX source in the given mode, returning
+/// `None` for [`MathRendering::Glyphs`]. *alt* is the formula's alternative
+/// text, if it has any.
+pub(crate) fn render(
+    mode: MathRendering,
+    source: &str,
+    inline: bool,
+    alt: Option<&str>,
+) -> Option<String> {
+    let source = source.trim();
+
+    match mode {
+        MathRendering::Glyphs => None,
+
+        MathRendering::Katex => {
+            let (element, class, open, close) = if inline {
+                ("span", "tdux-math tdux-math-inline", "\\(", "\\)")
+            } else {
+                ("div", "tdux-math tdux-math-display", "\\[", "\\]")
+            };
+
+            let mut html = format!("<{element} class=\"{class}\"");
+
+            if let Some(alt) = alt {
+                html.push_str(" aria-label=\"");
+                html_escape::encode_double_quoted_attribute_to_string(alt, &mut html);
+                html.push('"');
+            }
+
+            html.push('>');
+            html.push_str(open);
+            html_escape::encode_text_to_string(source, &mut html);
+            html.push_str(close);
+            html.push_str(&format!("</{element}>"));
+            Some(html)
+        }
+
+        MathRendering::MathJax => {
+            let kind = if inline {
+                "math/tex"
+            } else {
+                "math/tex; mode=display"
+            };
+
+            // The contents of a script can't be escaped, but they can't
+            // contain its end tag either. Math mode ignores spaces, so
+            // splitting up `</` doesn't change the meaning.
+            Some(format!(
+                "<script type=\"{kind}\">{}</script>",
+                source.replace("</", "< /")
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn katex() {
+        assert_eq!(
+            render(MathRendering::Katex, " a<b ", true, None).unwrap(),
+            "<span class=\"tdux-math tdux-math-inline\">\\(a&lt;b\\)</span>"
+        );
+        assert_eq!(
+            render(MathRendering::Katex, "x^2", false, Some("x \"squared\"")).unwrap(),
+            "<div class=\"tdux-math tdux-math-display\" aria-label=\"x &quot;squared&quot;\">\\[x^2\\]</div>"
+        );
+    }
+
+    #[test]
+    fn mathjax() {
+        assert_eq!(
+            render(MathRendering::MathJax, "a</script>", true, None).unwrap(),
+            "<script type=\"math/tex\">a< /script></script>"
+        );
+        assert_eq!(
+            render(MathRendering::MathJax, "\\sum_i x_i", false, None).unwrap(),
+            "<script type=\"math/tex; mode=display\">\\sum_i x_i</script>"
+        );
+        assert_eq!(render(MathRendering::Glyphs, "x", true, None), None);
+    }
+}
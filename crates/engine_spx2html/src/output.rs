@@ -0,0 +1,160 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Writing output files to disk.
+//!
+//! In incremental mode, an output file is only rewritten if its contents
+//! differ from those of the file already at its path. A multi-file document
+//! renders every chunk on every build, since the navigation of one chunk can
+//! depend on the others, but chunks whose rendering is unchanged keep their
+//! modification times, as do unchanged assets. This matters to static-site
+//! deployment tools that decide what to upload based on those times.
+//!
+//! If an [`OutputFilter`] is given, it sees the contents of each file before
+//! they're compared or written.
+
+use std::{fmt, fs, path::Path};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::StatusBackend;
+
+/// A transformation of the contents of output files before they're written.
+///
+/// This lets drivers post-process the outputs, for instance to minify them,
+/// without defeating [incremental
+/// mode](crate::Spx2HtmlEngine::incremental).
+pub trait OutputFilter: fmt::Debug {
+    /// Transform the contents of the output file that will be written to
+    /// *path*.
+    fn filter(
+        &mut self,
+        path: &Path,
+        data: Vec<u8>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>>;
+}
+
+#[derive(Default)]
+pub(crate) struct OutputWriter<'a> {
+    incremental: bool,
+    filter: Option<&'a mut dyn OutputFilter>,
+    n_written: usize,
+    n_unchanged: usize,
+}
+
+impl<'a> OutputWriter<'a> {
+    pub(crate) fn new(incremental: bool, filter: Option<&'a mut dyn OutputFilter>) -> Self {
+        OutputWriter {
+            incremental,
+            filter,
+            ..Default::default()
+        }
+    }
+
+    /// Write an output file, unless we're in incremental mode and the file
+    /// already has this content.
+    pub(crate) fn write(
+        &mut self,
+        path: &Path,
+        data: &[u8],
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        let filtered;
+
+        let data = match self.filter.as_mut() {
+            Some(f) => {
+                filtered = atry!(
+                    f.filter(path, data.to_vec(), status);
+                    ["cannot post-process output file `{}`", path.display()]
+                );
+                &filtered[..]
+            }
+            None => data,
+        };
+
+        if self.incremental && is_unchanged(path, data) {
+            self.n_unchanged += 1;
+            return Ok(());
+        }
+
+        atry!(
+            fs::write(path, data);
+            ["cannot write output file `{}`", path.display()]
+        );
+
+        self.n_written += 1;
+        Ok(())
+    }
+
+    pub(crate) fn n_written(&self) -> usize {
+        self.n_written
+    }
+
+    pub(crate) fn n_unchanged(&self) -> usize {
+        self.n_unchanged
+    }
+}
+
+fn is_unchanged(path: &Path, data: &[u8]) -> bool {
+    // Check the size first, to avoid reading files that have obviously
+    // changed.
+    if !fs::metadata(path).is_ok_and(|md| md.len() == data.len() as u64) {
+        return false;
+    }
+
+    fs::read(path).is_ok_and(|existing| existing == data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[test]
+    fn skip_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        let mut status = NoopStatusBackend::default();
+
+        let mut out = OutputWriter::new(true, None);
+        out.write(&path, b"one", &mut status).unwrap();
+        out.write(&path, b"one", &mut status).unwrap();
+        out.write(&path, b"two", &mut status).unwrap();
+        out.write(&path, b"six", &mut status).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"six");
+        assert_eq!((out.n_written(), out.n_unchanged()), (3, 1));
+
+        let mut out = OutputWriter::new(false, None);
+        out.write(&path, b"six", &mut status).unwrap();
+        assert_eq!((out.n_written(), out.n_unchanged()), (1, 0));
+    }
+
+    #[derive(Debug)]
+    struct Upcase;
+
+    impl OutputFilter for Upcase {
+        fn filter(
+            &mut self,
+            _path: &Path,
+            data: Vec<u8>,
+            _status: &mut dyn StatusBackend,
+        ) -> Result<Vec<u8>> {
+            Ok(data.to_ascii_uppercase())
+        }
+    }
+
+    #[test]
+    fn filtering() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("index.html");
+        let mut status = NoopStatusBackend::default();
+        let mut filter = Upcase;
+
+        // The filtered contents are what's compared in incremental mode.
+        let mut out = OutputWriter::new(true, Some(&mut filter));
+        out.write(&path, b"one", &mut status).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"ONE");
+        out.write(&path, b"one", &mut status).unwrap();
+        assert_eq!((out.n_written(), out.n_unchanged()), (1, 1));
+    }
+}
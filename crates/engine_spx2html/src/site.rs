@@ -0,0 +1,526 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Site-level outputs for several HTML documents published together.
+//!
+//! When a workspace builds several documents to HTML, they can be published
+//! as one site. A [`Site`] describes the pages of such a site, one for each
+//! HTML output, and emits the files that tie them together: a [sitemap] for
+//! search engines, an [Atom] feed of the most recently changed documents, and
+//! a landing page linking to all of them, rendered from a Tera template.
+//!
+//! [sitemap]: https://www.sitemaps.org/protocol.html
+//! [Atom]: https://www.rfc-editor.org/rfc/rfc4287
+
+use html_escape::encode_text;
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use serde::Serialize;
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::date::{civil_from_days, unix_seconds};
+use tectonic_status_base::StatusBackend;
+
+use crate::output::OutputWriter;
+
+/// The name of the sitemap, in the site's output directory.
+pub const SITEMAP_NAME: &str = "sitemap.xml";
+
+/// The name of the Atom feed, in the site's output directory.
+pub const FEED_NAME: &str = "feed.xml";
+
+/// The name of the landing page, in the site's output directory.
+pub const SITE_INDEX_NAME: &str = "index.html";
+
+/// The characters that are percent-encoded in the paths of pages, so that
+/// the paths can go into URLs and HTML attributes as they are.
+const PATH_ENCODE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// The number of documents listed in the feed.
+const FEED_LENGTH: usize = 20;
+
+/// The template of the landing page if none is given.
+const DEFAULT_INDEX_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>{{ tduxSiteTitle }}</title>
+</head>
+<body>
+<main>
+<h1>{{ tduxSiteTitle }}</h1>
+<ul>
+{% for page in tduxPages %}<li><a href="{{ page.href | safe }}">{{ page.title }}</a>{% if page.description %}: {{ page.description }}{% endif %}</li>
+{% endfor %}</ul>
+</main>
+</body>
+</html>
+"#;
+
+/// One page of a site, which is the main file of an HTML output.
+#[derive(Clone, Debug)]
+pub struct SitePage {
+    /// The title of the page.
+    pub title: String,
+
+    /// A short description of the page, if there is one.
+    pub description: Option<String>,
+
+    /// The path of the page relative to the root directory of the site,
+    /// with `/` as the separator.
+    pub path: String,
+
+    /// When the page was last changed.
+    pub updated: SystemTime,
+}
+
+/// A site of several HTML documents.
+///
+/// The pages are located relative to a root directory, which is published at
+/// the URL given to [`Self::url`]. The site-level files are written to a
+/// subdirectory of it, given to [`Self::output_dir`].
+#[derive(Clone, Debug)]
+pub struct Site {
+    root: PathBuf,
+    output_dir: String,
+    title: String,
+    url: Option<String>,
+    sitemap: bool,
+    feed: bool,
+    index: bool,
+    index_template: Option<String>,
+    pages: Vec<SitePage>,
+}
+
+/// A page as the landing page template sees it.
+#[derive(Serialize)]
+struct TemplatePage<'a> {
+    title: &'a str,
+    description: Option<&'a str>,
+    href: String,
+    url: Option<String>,
+    updated: String,
+}
+
+impl Site {
+    /// Describe a site whose root directory is *root*.
+    ///
+    /// By default, the site is titled "Documents", its files are written to
+    /// the root directory itself, and all of them are emitted.
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Site {
+            root: root.into(),
+            output_dir: String::new(),
+            title: "Documents".to_owned(),
+            url: None,
+            sitemap: true,
+            feed: true,
+            index: true,
+            index_template: None,
+            pages: Vec::new(),
+        }
+    }
+
+    /// Write the site-level files to *dir*, a path relative to the root
+    /// directory with `/` as the separator.
+    pub fn output_dir(&mut self, dir: impl Into<String>) -> &mut Self {
+        self.output_dir = dir.into().trim_matches('/').to_owned();
+        self
+    }
+
+    /// Set the title of the site.
+    pub fn title(&mut self, title: impl Into<String>) -> &mut Self {
+        self.title = title.into();
+        self
+    }
+
+    /// Set the URL at which the root directory is published. The sitemap and
+    /// feed can only be emitted if this is set, since they use absolute
+    /// URLs.
+    pub fn url(&mut self, url: impl Into<String>) -> &mut Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Set whether to emit the sitemap.
+    pub fn sitemap(&mut self, enabled: bool) -> &mut Self {
+        self.sitemap = enabled;
+        self
+    }
+
+    /// Set whether to emit the feed.
+    pub fn feed(&mut self, enabled: bool) -> &mut Self {
+        self.feed = enabled;
+        self
+    }
+
+    /// Set whether to emit the landing page.
+    pub fn index(&mut self, enabled: bool) -> &mut Self {
+        self.index = enabled;
+        self
+    }
+
+    /// Render the landing page from this Tera template instead of the
+    /// default one, which is a plain list of links.
+    ///
+    /// The template gets the variables `tduxSiteTitle`, `tduxSiteUrl` (which
+    /// is empty if no URL is set), `tduxRelTop`, the path from the landing
+    /// page to the root directory, and `tduxPages`, the list of pages. Each
+    /// page has the attributes `title`, `description`, which may be null,
+    /// `href`, its path relative to the landing page, `url`, its absolute
+    /// URL, which may be null, and `updated`, when it last changed, in RFC
+    /// 3339 format. The special characters in `href` and `url` are
+    /// percent-encoded, so that they can be used unescaped with Tera's
+    /// `safe` filter.
+    pub fn index_template(&mut self, template: impl Into<String>) -> &mut Self {
+        self.index_template = Some(template.into());
+        self
+    }
+
+    /// Add a page to the site. The landing page lists them in the order in
+    /// which they're added.
+    pub fn page(&mut self, page: SitePage) -> &mut Self {
+        self.pages.push(page);
+        self
+    }
+
+    /// Write the site-level files, returning how many were rewritten.
+    ///
+    /// Files whose contents haven't changed aren't rewritten, so that they
+    /// keep their modification times.
+    pub fn emit(&self, status: &mut dyn StatusBackend) -> Result<usize> {
+        let mut out_dir = self.root.clone();
+
+        for piece in self.output_dir.split('/').filter(|p| !p.is_empty()) {
+            out_dir.push(piece);
+        }
+
+        atry!(
+            std::fs::create_dir_all(&out_dir);
+            ["cannot create site output directory `{}`", out_dir.display()]
+        );
+
+        let mut outputs = OutputWriter::new(true, None);
+        let mut write = |name: &str, text: String, status: &mut dyn StatusBackend| {
+            outputs.write(&out_dir.join(name), text.as_bytes(), status)
+        };
+
+        if self.sitemap {
+            write(SITEMAP_NAME, self.render_sitemap()?, status)?;
+        }
+
+        if self.feed {
+            write(FEED_NAME, self.render_feed()?, status)?;
+        }
+
+        if self.index {
+            let template = self
+                .index_template
+                .as_deref()
+                .unwrap_or(DEFAULT_INDEX_TEMPLATE);
+            write(SITE_INDEX_NAME, self.render_index(template)?, status)?;
+        }
+
+        Ok(outputs.n_written())
+    }
+
+    /// Get the absolute URL of the file at *path* relative to the root
+    /// directory.
+    fn absolute_url(&self, path: &str) -> Option<String> {
+        self.url
+            .as_ref()
+            .map(|url| format!("{}/{}", url.trim_end_matches('/'), encode_path(path)))
+    }
+
+    fn require_url(&self, what: &str) -> Result<()> {
+        ensure!(
+            self.url.is_some(),
+            "the {} of a site can only be emitted if its URL is known",
+            what
+        );
+        Ok(())
+    }
+
+    fn render_sitemap(&self) -> Result<String> {
+        self.require_url("sitemap")?;
+
+        let mut xml = String::from(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <urlset xmlns=\"http://www.sitemaps.org/schemas/sitemap/0.9\">\n",
+        );
+
+        for page in &self.pages {
+            writeln!(
+                xml,
+                "  <url>\n    <loc>{}</loc>\n    <lastmod>{}</lastmod>\n  </url>",
+                encode_text(&self.absolute_url(&page.path).unwrap()),
+                rfc3339(page.updated)
+            )
+            .unwrap();
+        }
+
+        xml.push_str("</urlset>\n");
+        Ok(xml)
+    }
+
+    fn render_feed(&self) -> Result<String> {
+        self.require_url("feed")?;
+
+        let mut recent: Vec<&SitePage> = self.pages.iter().collect();
+        recent.sort_by(|a, b| b.updated.cmp(&a.updated).then(a.path.cmp(&b.path)));
+        recent.truncate(FEED_LENGTH);
+
+        let feed_path = self.output_path(FEED_NAME);
+        let feed_url = self.absolute_url(&feed_path).unwrap();
+        let updated = recent
+            .first()
+            .map(|p| p.updated)
+            .unwrap_or(SystemTime::UNIX_EPOCH);
+
+        let mut xml = String::new();
+        writeln!(
+            xml,
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+             <feed xmlns=\"http://www.w3.org/2005/Atom\">\n  \
+             <id>{url}</id>\n  \
+             <title>{}</title>\n  \
+             <updated>{}</updated>\n  \
+             <link rel=\"self\" href=\"{url}\"/>",
+            encode_text(&self.title),
+            rfc3339(updated),
+            url = encode_text(&feed_url),
+        )
+        .unwrap();
+
+        for page in recent {
+            let url = self.absolute_url(&page.path).unwrap();
+            writeln!(
+                xml,
+                "  <entry>\n    \
+                 <id>{url}</id>\n    \
+                 <title>{}</title>\n    \
+                 <updated>{}</updated>\n    \
+                 <link href=\"{url}\"/>",
+                encode_text(&page.title),
+                rfc3339(page.updated),
+                url = encode_text(&url),
+            )
+            .unwrap();
+
+            if let Some(description) = page.description.as_ref() {
+                writeln!(xml, "    <summary>{}</summary>", encode_text(description)).unwrap();
+            }
+
+            xml.push_str("  </entry>\n");
+        }
+
+        xml.push_str("</feed>\n");
+        Ok(xml)
+    }
+
+    fn render_index(&self, template: &str) -> Result<String> {
+        let rel_top = "../".repeat(self.output_dir.split('/').filter(|p| !p.is_empty()).count());
+
+        let pages: Vec<TemplatePage> = self
+            .pages
+            .iter()
+            .map(|page| TemplatePage {
+                title: &page.title,
+                description: page.description.as_deref(),
+                href: format!("{rel_top}{}", encode_path(&page.path)),
+                url: self.absolute_url(&page.path),
+                updated: rfc3339(page.updated),
+            })
+            .collect();
+
+        let mut context = tera::Context::new();
+        context.insert("tduxSiteTitle", &self.title);
+        context.insert("tduxSiteUrl", self.url.as_deref().unwrap_or_default());
+        context.insert("tduxRelTop", &rel_top);
+        context.insert("tduxPages", &pages);
+
+        Ok(atry!(
+            tera::Tera::one_off(template, &context, true);
+            ["failed to render the landing page of the site"]
+        ))
+    }
+
+    /// Get the path of a site-level file relative to the root directory.
+    fn output_path(&self, name: &str) -> String {
+        if self.output_dir.is_empty() {
+            name.to_owned()
+        } else {
+            format!("{}/{}", self.output_dir, name)
+        }
+    }
+}
+
+impl SitePage {
+    /// Describe the page at *path*, relative to *root*, taking the time at
+    /// which it was last changed from its modification time.
+    pub fn from_file(
+        title: impl Into<String>,
+        description: Option<String>,
+        root: &Path,
+        path: &Path,
+    ) -> Result<Self> {
+        let rel = a_ok_or!(
+            path.strip_prefix(root).ok();
+            ["the page `{}` isn't inside the site directory `{}`", path.display(), root.display()]
+        );
+
+        let mut pieces = Vec::new();
+
+        for piece in rel.components() {
+            pieces.push(a_ok_or!(
+                piece.as_os_str().to_str();
+                ["the path of the page `{}` isn't Unicode", path.display()]
+            ));
+        }
+
+        let updated = atry!(
+            std::fs::metadata(path).and_then(|md| md.modified());
+            ["cannot get the modification time of `{}`", path.display()]
+        );
+
+        Ok(SitePage {
+            title: title.into(),
+            description,
+            path: pieces.join("/"),
+            updated,
+        })
+    }
+}
+
+/// Percent-encode the path of a page.
+fn encode_path(path: &str) -> String {
+    utf8_percent_encode(path, PATH_ENCODE).to_string()
+}
+
+/// Format a time in UTC as in RFC 3339, which the sitemap and feed formats
+/// both accept, to the second.
+fn rfc3339(t: SystemTime) -> String {
+    let secs = unix_seconds(t);
+    let (year, month, day) = civil_from_days(secs.div_euclid(86400));
+    let tod = secs.rem_euclid(86400);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        tod / 3600,
+        tod / 60 % 60,
+        tod % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn site() -> Site {
+        let day = |n: u64| SystemTime::UNIX_EPOCH + Duration::from_secs(n * 86400 + 3723);
+        let mut site = Site::new("/ws");
+        site.title("Papers & Slides")
+            .url("https://example.com/ws/")
+            .output_dir("build/")
+            .page(SitePage {
+                title: "Paper".to_owned(),
+                description: Some("On <things>".to_owned()),
+                path: "paper/build/html/index.html".to_owned(),
+                updated: day(20742),
+            })
+            .page(SitePage {
+                title: "Slides".to_owned(),
+                description: None,
+                path: "slides & such/build/html/index.html".to_owned(),
+                updated: day(20743),
+            });
+        site
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(rfc3339(SystemTime::UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        assert_eq!(
+            rfc3339(SystemTime::UNIX_EPOCH + Duration::from_secs(951782400 + 86399)),
+            "2000-02-29T23:59:59Z"
+        );
+    }
+
+    #[test]
+    fn sitemap() {
+        let xml = site().render_sitemap().unwrap();
+        assert!(xml.contains(
+            "  <url>\n    <loc>https://example.com/ws/paper/build/html/index.html</loc>\n    \
+             <lastmod>2026-10-16T01:02:03Z</lastmod>\n  </url>\n"
+        ));
+        assert_eq!(xml.matches("<url>").count(), 2);
+
+        let mut no_url = Site::new("/ws");
+        no_url.page(site().pages[0].clone());
+        assert!(no_url.render_sitemap().is_err());
+    }
+
+    #[test]
+    fn feed() {
+        let xml = site().render_feed().unwrap();
+        assert!(xml.contains("<id>https://example.com/ws/build/feed.xml</id>"));
+        assert!(xml.contains("<title>Papers &amp; Slides</title>"));
+        assert!(xml.contains("<updated>2026-10-17T01:02:03Z</updated>\n  <link rel=\"self\""));
+        assert!(xml.contains("<summary>On &lt;things&gt;</summary>"));
+
+        // The most recently changed documents come first.
+        let slides = xml.find("<title>Slides</title>").unwrap();
+        let paper = xml.find("<title>Paper</title>").unwrap();
+        assert!(slides < paper);
+    }
+
+    #[test]
+    fn index() {
+        let html = site().render_index(DEFAULT_INDEX_TEMPLATE).unwrap();
+        assert!(html.contains("<title>Papers &amp; Slides</title>"));
+        assert!(html.contains(
+            "<li><a href=\"../paper/build/html/index.html\">Paper</a>: On &lt;things&gt;</li>"
+        ));
+        assert!(html.contains(
+            "<li><a href=\"../slides%20%26%20such/build/html/index.html\">Slides</a></li>"
+        ));
+
+        let html = site()
+            .render_index(
+                "{{ tduxRelTop | safe }} {{ tduxPages.1.url | safe }} {{ tduxPages.1.description }}",
+            )
+            .unwrap();
+        assert_eq!(
+            html,
+            "../ https://example.com/ws/slides%20%26%20such/build/html/index.html "
+        );
+    }
+
+    #[test]
+    fn page_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let html = dir.path().join("doc").join("index.html");
+        std::fs::create_dir(html.parent().unwrap()).unwrap();
+        std::fs::write(&html, "").unwrap();
+
+        let page = SitePage::from_file("Doc", None, dir.path(), &html).unwrap();
+        assert_eq!(page.path, "doc/index.html");
+        assert!(SitePage::from_file("Doc", None, Path::new("/elsewhere"), &html).is_err());
+    }
+}
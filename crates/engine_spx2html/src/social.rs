@@ -0,0 +1,390 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Metadata for previews of the HTML outputs on social media.
+//!
+//! Sites that "unfurl" links into cards look for [OpenGraph] `<meta>` tags,
+//! and their Twitter-specific variants, in the `<head>` of the linked page.
+//! If [`SocialMetadata`] is given to the engine, the tags for each output
+//! file are rendered into the `tduxSocialMeta` template variable, which
+//! templates should include unescaped, as in `{{ tduxSocialMeta | safe }}`.
+//! The variable is always defined, and is empty if no metadata were given.
+//!
+//! The engine can also render a page of a PDF version of the document, or a
+//! region of one, into a PNG image that the tags point to, as described in
+//! [`PreviewImage`].
+//!
+//! [OpenGraph]: https://ogp.me/
+
+use html_escape::encode_double_quoted_attribute;
+use std::{
+    fmt::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+use tectonic_errors::prelude::*;
+
+use crate::Common;
+
+/// The name of the rendered preview image, at the top of the output tree.
+pub const PREVIEW_IMAGE_NAME: &str = "social-preview.png";
+
+/// The metadata that describe the document in social media previews.
+///
+/// All of the fields are optional, and tags are only emitted for those that
+/// are set.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SocialMetadata {
+    /// The title of the document.
+    pub title: Option<String>,
+
+    /// A short description of the document.
+    pub description: Option<String>,
+
+    /// The URL at which the top of the output tree will be published. The
+    /// URL of each output file is formed by appending its path to this.
+    pub url: Option<String>,
+
+    /// The name of the site that the document is part of.
+    pub site_name: Option<String>,
+
+    /// The Twitter handle of the site, such as `@tectonic`.
+    pub twitter_site: Option<String>,
+
+    /// The image to show in previews. This may be an absolute URL, or a path
+    /// relative to the top of the output tree. If it's unset and a
+    /// [`PreviewImage`] is rendered, the rendered image is used.
+    pub image: Option<String>,
+
+    /// A description of the image, for readers who can't see it.
+    pub image_alt: Option<String>,
+}
+
+impl SocialMetadata {
+    /// Render the tags for the output file at *output_path*, whose path to
+    /// the top of the output tree is *rel_top*.
+    pub(crate) fn render(&self, output_path: &str, rel_top: &str) -> String {
+        let mut tags = String::new();
+        let mut tag = |attr: &str, name: &str, value: &str| {
+            writeln!(
+                tags,
+                "<meta {attr}=\"{name}\" content=\"{}\">",
+                encode_double_quoted_attribute(value)
+            )
+            .unwrap();
+        };
+
+        let image = self.image.as_ref().map(|image| {
+            if image.contains("://") {
+                image.clone()
+            } else if let Some(url) = self.url.as_ref() {
+                format!("{}/{}", url.trim_end_matches('/'), image)
+            } else {
+                format!("{rel_top}{image}")
+            }
+        });
+
+        tag("property", "og:type", "website");
+
+        if let Some(title) = self.title.as_ref() {
+            tag("property", "og:title", title);
+        }
+
+        if let Some(description) = self.description.as_ref() {
+            tag("property", "og:description", description);
+        }
+
+        if let Some(url) = self.url.as_ref() {
+            tag(
+                "property",
+                "og:url",
+                &format!("{}/{}", url.trim_end_matches('/'), output_path),
+            );
+        }
+
+        if let Some(site_name) = self.site_name.as_ref() {
+            tag("property", "og:site_name", site_name);
+        }
+
+        if let Some(image) = image.as_ref() {
+            tag("property", "og:image", image);
+
+            if let Some(alt) = self.image_alt.as_ref() {
+                tag("property", "og:image:alt", alt);
+            }
+        }
+
+        tag(
+            "name",
+            "twitter:card",
+            if image.is_some() {
+                "summary_large_image"
+            } else {
+                "summary"
+            },
+        );
+
+        if let Some(site) = self.twitter_site.as_ref() {
+            tag("name", "twitter:site", site);
+        }
+
+        if let Some(title) = self.title.as_ref() {
+            tag("name", "twitter:title", title);
+        }
+
+        if let Some(description) = self.description.as_ref() {
+            tag("name", "twitter:description", description);
+        }
+
+        if let Some(image) = image.as_ref() {
+            tag("name", "twitter:image", image);
+
+            if let Some(alt) = self.image_alt.as_ref() {
+                tag("name", "twitter:image:alt", alt);
+            }
+        }
+
+        tags
+    }
+}
+
+/// A region of a page, in PostScript points measured from the top left
+/// corner of the page.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PreviewRegion {
+    /// The distance of the left edge of the region from that of the page.
+    pub x: f64,
+
+    /// The distance of the top edge of the region from that of the page.
+    pub y: f64,
+
+    /// The width of the region.
+    pub width: f64,
+
+    /// The height of the region.
+    pub height: f64,
+}
+
+/// How to render the preview image for social media.
+///
+/// The HTML output itself can't be rendered without a web browser, so the
+/// image is rendered from a PDF version of the same document, using the
+/// `pdftocairo` program from Poppler, which must be available on `$PATH`. It
+/// is written to the top of the output tree as [`PREVIEW_IMAGE_NAME`]
+/// whenever assets are emitted.
+#[derive(Clone, Debug)]
+pub struct PreviewImage {
+    pdf: PathBuf,
+    page: u32,
+    region: Option<PreviewRegion>,
+    width: u32,
+}
+
+impl PreviewImage {
+    /// Render the preview from the PDF file at *pdf*.
+    ///
+    /// By default, the whole of the first page is rendered, 1200 pixels
+    /// wide, which suits the common social media sites.
+    pub fn new(pdf: impl Into<PathBuf>) -> Self {
+        PreviewImage {
+            pdf: pdf.into(),
+            page: 1,
+            region: None,
+            width: 1200,
+        }
+    }
+
+    /// Set the page to render, counting from 1.
+    pub fn page(&mut self, page: u32) -> &mut Self {
+        self.page = page.max(1);
+        self
+    }
+
+    /// Only render a region of the page.
+    pub fn region(&mut self, region: PreviewRegion) -> &mut Self {
+        self.region = Some(region);
+        self
+    }
+
+    /// Set the width of the image in pixels. The height follows from the
+    /// proportions of the page or region.
+    pub fn width(&mut self, width: u32) -> &mut Self {
+        self.width = width.max(1);
+        self
+    }
+
+    /// The arguments to `pdftocairo` that select and scale the page or
+    /// region.
+    fn pdftocairo_args(&self) -> Result<Vec<String>> {
+        let page = self.page.to_string();
+        let mut args = vec![
+            "-png".to_owned(),
+            "-singlefile".to_owned(),
+            "-f".to_owned(),
+            page.clone(),
+            "-l".to_owned(),
+            page,
+        ];
+
+        match self.region {
+            None => {
+                args.extend([
+                    "-scale-to-x".to_owned(),
+                    self.width.to_string(),
+                    "-scale-to-y".to_owned(),
+                    "-1".to_owned(),
+                ]);
+            }
+
+            Some(r) => {
+                ensure!(
+                    r.x >= 0. && r.y >= 0. && r.width > 0. && r.height > 0.,
+                    "the region of the social preview image must be on the page and not empty"
+                );
+
+                // Crop coordinates are in pixels at the rendering resolution.
+                let dpi = f64::from(self.width) * 72. / r.width;
+                let px = |pt: f64| ((pt * dpi / 72.).round() as u64).to_string();
+
+                args.extend([
+                    "-r".to_owned(),
+                    format!("{dpi:.3}"),
+                    "-x".to_owned(),
+                    px(r.x),
+                    "-y".to_owned(),
+                    px(r.y),
+                    "-W".to_owned(),
+                    self.width.to_string(),
+                    "-H".to_owned(),
+                    px(r.height),
+                ]);
+            }
+        }
+
+        Ok(args)
+    }
+
+    /// Render the image into the output tree.
+    ///
+    /// This must only be called if `common.out_base` is not None.
+    pub(crate) fn emit(&self, common: &mut Common) -> Result<()> {
+        let work_dir = atry!(
+            tempfile::Builder::new().prefix("tectonic_spx2html_preview").tempdir();
+            ["cannot create temporary directory for the social preview image"]
+        );
+
+        // `pdftocairo` adds the extension itself.
+        let stem = work_dir.path().join("preview");
+
+        let status = atry!(
+            Command::new("pdftocairo")
+                .args(self.pdftocairo_args()?)
+                .arg(&self.pdf)
+                .arg(&stem)
+                .stdin(Stdio::null())
+                .status();
+            ["cannot run `pdftocairo`; is Poppler installed?"]
+        );
+
+        ensure!(
+            status.success(),
+            "`pdftocairo` failed to render page {} of `{}` for the social preview image: {}",
+            self.page,
+            self.pdf.display(),
+            status
+        );
+
+        let png_path = stem.with_extension("png");
+        let data = atry!(
+            std::fs::read(&png_path);
+            ["cannot read the rendered social preview image `{}`", png_path.display()]
+        );
+
+        let out_path = crate::assets::create_output_path(PREVIEW_IMAGE_NAME, common)?
+            .0
+            .unwrap();
+        common.outputs.write(&out_path, &data, common.status)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_tags() {
+        let mut meta = SocialMetadata {
+            title: Some("On \"Quotes\" & Things".to_owned()),
+            url: Some("https://example.com/doc/".to_owned()),
+            image: Some(PREVIEW_IMAGE_NAME.to_owned()),
+            ..Default::default()
+        };
+
+        let tags = meta.render("ch1/index.html", "../");
+        assert!(tags.contains(
+            "<meta property=\"og:title\" content=\"On &quot;Quotes&quot; &amp; Things\">"
+        ));
+        assert!(tags.contains(
+            "<meta property=\"og:url\" content=\"https://example.com/doc/ch1/index.html\">"
+        ));
+        assert!(tags.contains(
+            "<meta property=\"og:image\" content=\"https://example.com/doc/social-preview.png\">"
+        ));
+        assert!(tags.contains("<meta name=\"twitter:card\" content=\"summary_large_image\">"));
+        assert!(!tags.contains("og:description"));
+
+        meta.url = None;
+        let tags = meta.render("ch1/index.html", "../");
+        assert!(tags.contains("<meta property=\"og:image\" content=\"../social-preview.png\">"));
+        assert!(!tags.contains("og:url"));
+
+        meta.image = None;
+        let tags = meta.render("index.html", "");
+        assert!(tags.contains("<meta name=\"twitter:card\" content=\"summary\">"));
+    }
+
+    #[test]
+    fn preview_args() {
+        let mut preview = PreviewImage::new("doc.pdf");
+        assert_eq!(
+            preview.pdftocairo_args().unwrap()[6..],
+            ["-scale-to-x", "1200", "-scale-to-y", "-1"]
+        );
+
+        preview.page(2).width(600).region(PreviewRegion {
+            x: 72.,
+            y: 36.,
+            width: 300.,
+            height: 150.,
+        });
+        assert_eq!(
+            preview.pdftocairo_args().unwrap(),
+            [
+                "-png",
+                "-singlefile",
+                "-f",
+                "2",
+                "-l",
+                "2",
+                "-r",
+                "144.000",
+                "-x",
+                "144",
+                "-y",
+                "72",
+                "-W",
+                "600",
+                "-H",
+                "300"
+            ]
+        );
+
+        preview.region(PreviewRegion {
+            x: 0.,
+            y: 0.,
+            width: 0.,
+            height: 10.,
+        });
+        assert!(preview.pdftocairo_args().is_err());
+    }
+}
@@ -10,19 +10,29 @@ use tectonic_status_base::{tt_warning, StatusBackend};
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 pub(crate) enum Special<'a> {
     AddTemplate(&'a str),
+    AltText(&'a str),
     AutoStartParagraph,
     AutoEndParagraph,
+    BibEnd,
+    BibStart(&'a str),
     CanvasEnd(&'a str),
     CanvasStart(&'a str),
+    CiteEnd,
+    CiteStart(&'a str),
     ContentFinished,
+    DirectionEnd,
+    DirectionStart(&'a str),
     DirectText(&'a str),
     EndDefineFontFamily,
     EndFontFamilyTagAssociations,
     Emit,
+    Image(&'a str),
     ManualEnd(&'a str),
     ManualFlexibleStart(&'a str),
+    MathSource(&'a str),
     ProvideFile(&'a str),
     ProvideSpecial(&'a str),
+    SetLanguage(&'a str),
     SetOutputPath(&'a str),
     SetTemplate(&'a str),
     SetTemplateVariable(&'a str),
@@ -55,7 +65,17 @@ impl<'a> Special<'a> {
             "mfs" => Special::ManualFlexibleStart(remainder),
             "me" => Special::ManualEnd(remainder),
             "dt" => Special::DirectText(remainder),
+            "dirStart" => Special::DirectionStart(remainder),
+            "dirEnd" => Special::DirectionEnd,
+            "citeStart" => Special::CiteStart(remainder),
+            "citeEnd" => Special::CiteEnd,
+            "bibStart" => Special::BibStart(remainder),
+            "bibEnd" => Special::BibEnd,
             "emit" => Special::Emit,
+            "image" => Special::Image(remainder),
+            "alt" => Special::AltText(remainder),
+            "mathSource" => Special::MathSource(remainder),
+            "lang" => Special::SetLanguage(remainder),
             "addTemplate" => Special::AddTemplate(remainder),
             "setTemplate" => Special::SetTemplate(remainder),
             "setOutputPath" => Special::SetOutputPath(remainder),
@@ -92,6 +112,15 @@ impl<'a> Special<'a> {
                 | Special::ManualFlexibleStart(_)
                 | Special::ManualEnd(_)
                 | Special::DirectText(_)
+                | Special::DirectionStart(_)
+                | Special::DirectionEnd
+                | Special::CiteStart(_)
+                | Special::CiteEnd
+                | Special::BibStart(_)
+                | Special::BibEnd
+                | Special::Image(_)
+                | Special::AltText(_)
+                | Special::MathSource(_)
         )
     }
 }
@@ -100,19 +129,29 @@ impl Display for Special<'_> {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
         let (cmd, rest) = match self {
             Special::AddTemplate(t) => ("addTemplate", Some(t)),
+            Special::AltText(t) => ("alt", Some(t)),
             Special::AutoStartParagraph => ("asp", None),
             Special::AutoEndParagraph => ("aep", None),
+            Special::BibEnd => ("bibEnd", None),
+            Special::BibStart(t) => ("bibStart", Some(t)),
             Special::CanvasEnd(t) => ("ce", Some(t)),
             Special::CanvasStart(t) => ("cs", Some(t)),
+            Special::CiteEnd => ("citeEnd", None),
+            Special::CiteStart(t) => ("citeStart", Some(t)),
             Special::ContentFinished => ("contentFinished", None),
+            Special::DirectionEnd => ("dirEnd", None),
+            Special::DirectionStart(t) => ("dirStart", Some(t)),
             Special::DirectText(t) => ("dt", Some(t)),
             Special::EndDefineFontFamily => ("endDefineFontFamily", None),
             Special::EndFontFamilyTagAssociations => ("endFontFamilyTagAssociations", None),
             Special::Emit => ("emit", None),
+            Special::Image(t) => ("image", Some(t)),
             Special::ManualEnd(t) => ("me", Some(t)),
             Special::ManualFlexibleStart(t) => ("mfs", Some(t)),
+            Special::MathSource(t) => ("mathSource", Some(t)),
             Special::ProvideFile(t) => ("provideFile", Some(t)),
             Special::ProvideSpecial(t) => ("provideSpecial", Some(t)),
+            Special::SetLanguage(t) => ("lang", Some(t)),
             Special::SetOutputPath(t) => ("setOutputPath", Some(t)),
             Special::SetTemplate(t) => ("setTemplate", Some(t)),
             Special::SetTemplateVariable(t) => ("setTemplateVariable", Some(t)),
@@ -0,0 +1,1036 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Subsetting OpenType fonts to the glyphs that a document uses.
+//!
+//! Our subsetting preserves glyph IDs: the outlines of unused glyphs are
+//! emptied, but every glyph keeps its number. This way the tables that refer to
+//! glyphs — metrics, layout rules, the custom character maps of the variant
+//! fonts — stay valid without being rewritten, and the output is determined
+//! entirely by the input font and the set of glyphs. Outlines are the bulk of
+//! most fonts, so little is lost by not compacting everything else.
+//!
+//! TrueType (`glyf`) and CFF outlines are subsetted. For other kinds of fonts,
+//! such as CFF2 ones, only the character map is replaced.
+
+use byteorder::{BigEndian, ByteOrder, WriteBytesExt};
+use pinot::{otl::SubtableKind, types::Tag, FontRef, TableProvider};
+use std::{collections::BTreeSet, num::Wrapping};
+use tectonic_errors::prelude::*;
+
+use crate::fontfile::{opentype_checksum, GlyphId};
+
+const CFF: Tag = Tag::new(b"CFF ");
+const CMAP: Tag = Tag::new(b"cmap");
+const DSIG: Tag = Tag::new(b"DSIG");
+const GSUB: Tag = Tag::new(b"GSUB");
+const GLYF: Tag = Tag::new(b"glyf");
+const HEAD: Tag = Tag::new(b"head");
+const LOCA: Tag = Tag::new(b"loca");
+
+const GRAPHITE_TABLES: &[Tag] = &[
+    Tag::new(b"Feat"),
+    Tag::new(b"Glat"),
+    Tag::new(b"Gloc"),
+    Tag::new(b"Silf"),
+    Tag::new(b"Sill"),
+];
+
+/// Extend a set of glyphs with those that the font's glyph substitutions could
+/// produce from them.
+///
+/// Browsers shape the text that we emit themselves, so they may need glyphs,
+/// such as ligatures, that the SPX file never mentioned. We don't try to work
+/// out which substitutions would actually be activated: all of them are
+/// followed. Glyph zero (`.notdef`) is always included.
+pub fn close_over_substitutions(font: &FontRef, glyphs: &mut BTreeSet<GlyphId>) {
+    glyphs.insert(0);
+
+    let gsub = match font.gsub() {
+        Some(g) => g,
+        None => return,
+    };
+
+    loop {
+        let mut added = Vec::new();
+
+        for lookup in gsub.lookups() {
+            for st in lookup.subtables() {
+                match st.kind() {
+                    SubtableKind::SingleSubst1(t) => {
+                        t.substs_with(|g, s| {
+                            if glyphs.contains(&g) {
+                                added.push(s);
+                            }
+                            true
+                        });
+                    }
+
+                    SubtableKind::SingleSubst2(t) => {
+                        t.substs_with(|g, s| {
+                            if glyphs.contains(&g) {
+                                added.push(s);
+                            }
+                            true
+                        });
+                    }
+
+                    SubtableKind::MultipleSubst1(t) => {
+                        t.substs_with(|g, seq| {
+                            if glyphs.contains(&g) {
+                                added.extend(seq.iter());
+                            }
+                            true
+                        });
+                    }
+
+                    SubtableKind::AlternateSubst1(t) => {
+                        t.alternates_with(|g, alts| {
+                            if glyphs.contains(&g) {
+                                added.extend(alts.iter());
+                            }
+                            true
+                        });
+                    }
+
+                    SubtableKind::LigatureSubst1(t) => {
+                        t.ligatures_with(|lig| {
+                            if glyphs.contains(&lig.first_component)
+                                && lig.trailing_components.iter().all(|c| glyphs.contains(&c))
+                            {
+                                added.push(lig.ligature);
+                            }
+                            true
+                        });
+                    }
+
+                    _ => {}
+                }
+            }
+        }
+
+        let n_before = glyphs.len();
+        glyphs.extend(added);
+
+        if glyphs.len() == n_before {
+            break;
+        }
+    }
+}
+
+/// Create a standalone font containing only the specified glyphs.
+///
+/// The *cmap* argument is the complete data of the character map table of the
+/// new font. It should only map characters to glyphs that are kept, so that
+/// browsers fall back to other fonts for the rest. Glyphs that are parts of
+/// kept composite TrueType glyphs are kept as well.
+///
+/// If *data* is a font collection, the new font contains just the face
+/// *face_index*.
+pub fn subset(
+    data: &[u8],
+    face_index: u32,
+    glyphs: &BTreeSet<GlyphId>,
+    cmap: &[u8],
+) -> Result<Vec<u8>> {
+    let font = a_ok_or!(
+        pinot::FontDataRef::new(data).and_then(|fd| fd.get(face_index));
+        ["unable to load face #{} in the OpenType font", face_index]
+    );
+
+    let head = table_data(data, &font, HEAD)?.to_vec();
+    ensure!(head.len() >= 54, "font `head` table is truncated");
+
+    let mut replacements = Vec::new();
+
+    if font.find_record(GLYF).is_some() {
+        let long_loca = BigEndian::read_i16(&head[50..52]) != 0;
+        let (glyf, loca) = subset_glyf(
+            table_data(data, &font, GLYF)?,
+            table_data(data, &font, LOCA)?,
+            long_loca,
+            glyphs,
+        )?;
+        replacements.push((GLYF, glyf));
+        replacements.push((LOCA, loca));
+    } else if font.find_record(CFF).is_some() {
+        replacements.push((CFF, subset_cff(table_data(data, &font, CFF)?, glyphs)?));
+    }
+
+    replacements.push((CMAP, cmap.to_vec()));
+
+    let mut tables = Vec::new();
+
+    for rec in font.records().iter() {
+        if rec.tag == DSIG {
+            // Any digital signature would be invalidated.
+            continue;
+        }
+
+        if GRAPHITE_TABLES.contains(&rec.tag) && font.find_record(GSUB).is_some() {
+            // Only Firefox uses Graphite, and its data can be huge. Browsers
+            // will use the OpenType layout rules instead.
+            continue;
+        }
+
+        if let Some(i) = replacements.iter().position(|(tag, _)| *tag == rec.tag) {
+            tables.push(replacements.swap_remove(i));
+        } else {
+            tables.push((rec.tag, table_data(data, &font, rec.tag)?.to_vec()));
+        }
+    }
+
+    let version = BigEndian::read_u32(&data[font.offset as usize..]);
+    Ok(assemble_sfnt(version, tables))
+}
+
+fn table_data<'a>(data: &'a [u8], font: &FontRef, tag: Tag) -> Result<&'a [u8]> {
+    let rec = a_ok_or!(
+        font.find_record(tag);
+        ["font has no `{}` table", tag]
+    );
+    let start = rec.offset as usize;
+    Ok(a_ok_or!(
+        data.get(start..start + rec.len as usize);
+        ["font table `{}` extends past the end of the file", tag]
+    ))
+}
+
+/// Subset TrueType outlines, returning new `glyf` and `loca` tables. The
+/// `loca` table keeps its format.
+fn subset_glyf(
+    glyf: &[u8],
+    loca: &[u8],
+    long_loca: bool,
+    glyphs: &BTreeSet<GlyphId>,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    let offsets: Vec<usize> = if long_loca {
+        loca.chunks_exact(4)
+            .map(|c| BigEndian::read_u32(c) as usize)
+            .collect()
+    } else {
+        loca.chunks_exact(2)
+            .map(|c| BigEndian::read_u16(c) as usize * 2)
+            .collect()
+    };
+
+    ensure!(!offsets.is_empty(), "font `loca` table is empty");
+    let num_glyphs = offsets.len() - 1;
+
+    let glyph_data = |gid: usize| -> Result<&[u8]> {
+        let (start, end) = (offsets[gid], offsets[gid + 1]);
+        Ok(a_ok_or!(
+            glyf.get(start..end);
+            ["invalid `loca` entry for glyph {}", gid]
+        ))
+    };
+
+    // Keep the components of composite glyphs.
+
+    let mut keep: BTreeSet<usize> = glyphs
+        .iter()
+        .map(|g| *g as usize)
+        .filter(|g| *g < num_glyphs)
+        .collect();
+    let mut todo: Vec<usize> = keep.iter().copied().collect();
+
+    while let Some(gid) = todo.pop() {
+        for component in composite_components(glyph_data(gid)?) {
+            if component < num_glyphs && keep.insert(component) {
+                todo.push(component);
+            }
+        }
+    }
+
+    // Now build the new tables. The short `loca` format stores offsets divided
+    // by two, so glyphs need to be padded to even lengths at least.
+
+    let align = if long_loca { 4 } else { 2 };
+    let mut new_glyf = Vec::new();
+    let mut new_loca = Vec::new();
+
+    for gid in 0..=num_glyphs {
+        if long_loca {
+            new_loca.write_u32::<BigEndian>(new_glyf.len() as u32)?;
+        } else {
+            new_loca.write_u16::<BigEndian>((new_glyf.len() / 2) as u16)?;
+        }
+
+        if gid < num_glyphs && keep.contains(&gid) {
+            new_glyf.extend_from_slice(glyph_data(gid)?);
+
+            while new_glyf.len() % align != 0 {
+                new_glyf.push(0);
+            }
+        }
+    }
+
+    Ok((new_glyf, new_loca))
+}
+
+/// Get the glyph IDs of the components of a composite TrueType glyph. For a
+/// simple glyph, the list is empty.
+fn composite_components(glyph: &[u8]) -> Vec<usize> {
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    let mut components = Vec::new();
+
+    if glyph.len() < 10 || BigEndian::read_i16(glyph) >= 0 {
+        return components;
+    }
+
+    let mut pos = 10;
+
+    while pos + 4 <= glyph.len() {
+        let flags = BigEndian::read_u16(&glyph[pos..]);
+        components.push(BigEndian::read_u16(&glyph[pos + 2..]) as usize);
+        pos += 4;
+
+        pos += if flags & ARG_1_AND_2_ARE_WORDS != 0 {
+            4
+        } else {
+            2
+        };
+
+        if flags & WE_HAVE_A_SCALE != 0 {
+            pos += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            pos += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            pos += 8;
+        }
+
+        if flags & MORE_COMPONENTS == 0 {
+            break;
+        }
+    }
+
+    components
+}
+
+/// Top DICT operators whose operands are offsets into the CFF data.
+const OP_CHARSET: u16 = 15;
+const OP_ENCODING: u16 = 16;
+const OP_CHARSTRINGS: u16 = 17;
+const OP_PRIVATE: u16 = 18;
+const OP_SUBRS: u16 = 19;
+const OP_FDARRAY: u16 = 0x0c24;
+const OP_FDSELECT: u16 = 0x0c25;
+
+/// The Type 2 charstring for an empty glyph: just `endchar`.
+const EMPTY_CHARSTRING: &[u8] = &[14];
+
+/// Subset CFF outlines, returning a new `CFF ` table.
+///
+/// The charstrings of unused glyphs are replaced with empty ones. Everything
+/// else is copied, but since that changes where things are, the new table has
+/// to be laid out from scratch. Subroutines are kept whole.
+fn subset_cff(cff: &[u8], glyphs: &BTreeSet<GlyphId>) -> Result<Vec<u8>> {
+    ensure!(cff.len() >= 4, "CFF data are truncated");
+    let hdr_size = cff[2] as usize;
+
+    let name_index = read_index(cff, hdr_size)?;
+    let top_index = read_index(cff, name_index.end)?;
+    let string_index = read_index(cff, top_index.end)?;
+    let gsubr_index = read_index(cff, string_index.end)?;
+
+    ensure!(
+        top_index.items.len() == 1,
+        "CFF data with {} fonts are not supported",
+        top_index.items.len()
+    );
+
+    let mut top = Dict::parse(top_index.items[0])?;
+
+    let charstrings_ofs = a_ok_or!(
+        top.get_int(OP_CHARSTRINGS, 0);
+        ["CFF data have no charstrings"]
+    );
+    let charstrings = read_index(cff, charstrings_ofs as usize)?;
+    let num_glyphs = charstrings.items.len();
+
+    // Gather up the pieces that are referenced by offset.
+
+    let charset = match top.get_int(OP_CHARSET, 0) {
+        Some(ofs) if ofs > 2 => Some(cff_slice(cff, ofs, charset_len(cff, ofs, num_glyphs)?)?),
+        _ => None,
+    };
+
+    let encoding = match top.get_int(OP_ENCODING, 0) {
+        Some(ofs) if ofs > 1 => Some(cff_slice(cff, ofs, encoding_len(cff, ofs)?)?),
+        _ => None,
+    };
+
+    let fdselect = match top.get_int(OP_FDSELECT, 0) {
+        Some(ofs) => Some(cff_slice(cff, ofs, fdselect_len(cff, ofs, num_glyphs)?)?),
+        None => None,
+    };
+
+    let private = match top.get_private() {
+        Some((size, ofs)) => Some(PrivateData::read(cff, size, ofs)?),
+        None => None,
+    };
+
+    let mut font_dicts = Vec::new();
+
+    if let Some(ofs) = top.get_int(OP_FDARRAY, 0) {
+        for item in read_index(cff, ofs as usize)?.items {
+            let fd = Dict::parse(item)?;
+            let private = match fd.get_private() {
+                Some((size, ofs)) => Some(PrivateData::read(cff, size, ofs)?),
+                None => None,
+            };
+            font_dicts.push((fd, private));
+        }
+    }
+
+    let mut new_charstrings = Vec::with_capacity(num_glyphs);
+
+    for (gid, cs) in charstrings.items.iter().enumerate() {
+        if gid == 0 || glyphs.contains(&(gid as GlyphId)) {
+            new_charstrings.push(*cs);
+        } else {
+            new_charstrings.push(EMPTY_CHARSTRING);
+        }
+    }
+
+    let charstrings_data = write_index(&new_charstrings);
+
+    // Lay out the new table. All offsets in the DICTs are encoded at a fixed
+    // width, so the sizes of the DICTs don't depend on the values of the
+    // offsets, and we can work out where everything goes in one pass.
+
+    let top_index_len = write_index(&[top.encode()]).len();
+    let mut pos = hdr_size
+        + name_index.data.len()
+        + top_index_len
+        + string_index.data.len()
+        + gsubr_index.data.len();
+
+    let mut place = |len: usize| {
+        let here = pos;
+        pos += len;
+        here as i32
+    };
+
+    if let Some(d) = charset {
+        top.set(OP_CHARSET, &[place(d.len())]);
+    }
+
+    if let Some(d) = encoding {
+        top.set(OP_ENCODING, &[place(d.len())]);
+    }
+
+    if let Some(d) = fdselect {
+        top.set(OP_FDSELECT, &[place(d.len())]);
+    }
+
+    top.set(OP_CHARSTRINGS, &[place(charstrings_data.len())]);
+
+    let private_data = private.map(|p| p.encode());
+
+    if let Some(d) = private_data.as_ref() {
+        top.set(OP_PRIVATE, &[d.dict_len as i32, place(d.data.len())]);
+    }
+
+    let fd_private_data: Vec<_> = font_dicts
+        .iter()
+        .map(|(_, p)| p.as_ref().map(|p| p.encode()))
+        .collect();
+
+    let fdarray_data = if font_dicts.is_empty() {
+        None
+    } else {
+        let fdarray_len = write_index(
+            &font_dicts
+                .iter()
+                .map(|(fd, _)| fd.encode())
+                .collect::<Vec<_>>(),
+        )
+        .len();
+        top.set(OP_FDARRAY, &[place(fdarray_len)]);
+
+        let mut encoded = Vec::new();
+
+        for ((fd, _), pd) in font_dicts.iter_mut().zip(&fd_private_data) {
+            if let Some(pd) = pd {
+                fd.set(OP_PRIVATE, &[pd.dict_len as i32, place(pd.data.len())]);
+            }
+
+            encoded.push(fd.encode());
+        }
+
+        Some(write_index(&encoded))
+    };
+
+    // And write it all out.
+
+    let mut out = Vec::with_capacity(pos);
+    out.extend_from_slice(&cff[..hdr_size]);
+    out.extend_from_slice(name_index.data);
+    out.extend(write_index(&[top.encode()]));
+    out.extend_from_slice(string_index.data);
+    out.extend_from_slice(gsubr_index.data);
+
+    for d in [charset, encoding, fdselect].into_iter().flatten() {
+        out.extend_from_slice(d);
+    }
+
+    out.extend(charstrings_data);
+
+    if let Some(d) = private_data {
+        out.extend(d.data);
+    }
+
+    if let Some(d) = fdarray_data {
+        out.extend(d);
+    }
+
+    for d in fd_private_data.into_iter().flatten() {
+        out.extend(d.data);
+    }
+
+    Ok(out)
+}
+
+fn cff_slice(cff: &[u8], ofs: i32, len: usize) -> Result<&[u8]> {
+    let start = usize::try_from(ofs)?;
+    Ok(a_ok_or!(
+        cff.get(start..start + len);
+        ["CFF data are truncated"]
+    ))
+}
+
+fn cff_byte(cff: &[u8], pos: usize) -> Result<usize> {
+    Ok(*a_ok_or!(cff.get(pos); ["CFF data are truncated"]) as usize)
+}
+
+fn cff_u16(cff: &[u8], pos: usize) -> Result<usize> {
+    Ok((cff_byte(cff, pos)? << 8) | cff_byte(cff, pos + 1)?)
+}
+
+fn charset_len(cff: &[u8], ofs: i32, num_glyphs: usize) -> Result<usize> {
+    let ofs = usize::try_from(ofs)?;
+    let n_covered = num_glyphs.saturating_sub(1);
+
+    match cff_byte(cff, ofs)? {
+        0 => Ok(1 + 2 * n_covered),
+
+        fmt @ (1 | 2) => {
+            let range_len = if fmt == 1 { 3 } else { 4 };
+            let mut pos = ofs + 1;
+            let mut covered = 0;
+
+            while covered < n_covered {
+                let n_left = if fmt == 1 {
+                    cff_byte(cff, pos + 2)?
+                } else {
+                    cff_u16(cff, pos + 2)?
+                };
+                covered += n_left + 1;
+                pos += range_len;
+            }
+
+            Ok(pos - ofs)
+        }
+
+        fmt => bail!("unsupported CFF charset format {}", fmt),
+    }
+}
+
+fn encoding_len(cff: &[u8], ofs: i32) -> Result<usize> {
+    let ofs = usize::try_from(ofs)?;
+    let fmt = cff_byte(cff, ofs)?;
+
+    let mut len = match fmt & 0x7f {
+        0 => 2 + cff_byte(cff, ofs + 1)?,
+        1 => 2 + 2 * cff_byte(cff, ofs + 1)?,
+        f => bail!("unsupported CFF encoding format {}", f),
+    };
+
+    if fmt & 0x80 != 0 {
+        len += 1 + 3 * cff_byte(cff, ofs + len)?;
+    }
+
+    Ok(len)
+}
+
+fn fdselect_len(cff: &[u8], ofs: i32, num_glyphs: usize) -> Result<usize> {
+    let ofs = usize::try_from(ofs)?;
+
+    match cff_byte(cff, ofs)? {
+        0 => Ok(1 + num_glyphs),
+        3 => Ok(5 + 3 * cff_u16(cff, ofs + 1)?),
+        fmt => bail!("unsupported CFF FDSelect format {}", fmt),
+    }
+}
+
+/// A CFF INDEX structure.
+struct Index<'a> {
+    /// All of the bytes of the INDEX.
+    data: &'a [u8],
+
+    items: Vec<&'a [u8]>,
+
+    /// The offset of the end of the INDEX in the CFF data.
+    end: usize,
+}
+
+fn read_index(cff: &[u8], start: usize) -> Result<Index<'_>> {
+    let count = cff_u16(cff, start)?;
+
+    if count == 0 {
+        return Ok(Index {
+            data: &cff[start..start + 2],
+            items: Vec::new(),
+            end: start + 2,
+        });
+    }
+
+    let off_size = cff_byte(cff, start + 2)?;
+    ensure!(
+        (1..=4).contains(&off_size),
+        "invalid CFF INDEX offset size {}",
+        off_size
+    );
+
+    let mut offsets = Vec::with_capacity(count + 1);
+
+    for i in 0..=count {
+        let pos = start + 3 + i * off_size;
+        let mut value = 0;
+
+        for j in 0..off_size {
+            value = (value << 8) | cff_byte(cff, pos + j)?;
+        }
+
+        offsets.push(value);
+    }
+
+    // Offsets are relative to the byte before the item data.
+    let base = start + 2 + (count + 1) * off_size;
+    let end = base + offsets[count];
+    ensure!(end <= cff.len(), "CFF data are truncated");
+
+    let mut items = Vec::with_capacity(count);
+
+    for w in offsets.windows(2) {
+        items.push(a_ok_or!(
+            cff.get(base + w[0]..base + w[1]);
+            ["invalid CFF INDEX offsets"]
+        ));
+    }
+
+    Ok(Index {
+        data: &cff[start..end],
+        items,
+        end,
+    })
+}
+
+fn write_index<T: AsRef<[u8]>>(items: &[T]) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.write_u16::<BigEndian>(items.len() as u16).unwrap();
+
+    if items.is_empty() {
+        return out;
+    }
+
+    let last = 1 + items.iter().map(|i| i.as_ref().len()).sum::<usize>();
+    let off_size = match last {
+        0..=0xFF => 1,
+        0x100..=0xFFFF => 2,
+        0x1_0000..=0xFF_FFFF => 3,
+        _ => 4,
+    };
+
+    out.push(off_size as u8);
+    let mut offset = 1;
+
+    for i in 0..=items.len() {
+        out.write_uint::<BigEndian>(offset as u64, off_size)
+            .unwrap();
+
+        if let Some(item) = items.get(i) {
+            offset += item.as_ref().len();
+        }
+    }
+
+    for item in items {
+        out.extend_from_slice(item.as_ref());
+    }
+
+    out
+}
+
+/// An operand in a CFF DICT.
+#[derive(Clone, Debug)]
+enum Operand {
+    Int(i32),
+
+    /// A real number, kept in its encoded form.
+    Real(Vec<u8>),
+}
+
+/// A CFF DICT, as a sequence of operators with their operands. Two-byte
+/// operators are stored as `0x0cXX`.
+#[derive(Clone, Debug, Default)]
+struct Dict(Vec<(u16, Vec<Operand>)>);
+
+impl Dict {
+    fn parse(data: &[u8]) -> Result<Self> {
+        let mut entries = Vec::new();
+        let mut operands = Vec::new();
+        let mut pos = 0;
+        let byte = |i: usize| -> Result<i32> { Ok(cff_byte(data, i)? as i32) };
+
+        while pos < data.len() {
+            let b0 = data[pos] as i32;
+
+            match b0 {
+                0..=21 => {
+                    let op = if b0 == 12 {
+                        pos += 1;
+                        0x0c00 | byte(pos)? as u16
+                    } else {
+                        b0 as u16
+                    };
+                    entries.push((op, std::mem::take(&mut operands)));
+                    pos += 1;
+                }
+
+                28 => {
+                    operands.push(Operand::Int(
+                        ((byte(pos + 1)? << 8) | byte(pos + 2)?) as i16 as i32,
+                    ));
+                    pos += 3;
+                }
+
+                29 => {
+                    let v = (byte(pos + 1)? << 24)
+                        | (byte(pos + 2)? << 16)
+                        | (byte(pos + 3)? << 8)
+                        | byte(pos + 4)?;
+                    operands.push(Operand::Int(v));
+                    pos += 5;
+                }
+
+                30 => {
+                    let start = pos;
+                    pos += 1;
+
+                    loop {
+                        let b = byte(pos)?;
+                        pos += 1;
+
+                        if b & 0x0f == 0x0f || b & 0xf0 == 0xf0 {
+                            break;
+                        }
+                    }
+
+                    operands.push(Operand::Real(data[start..pos].to_vec()));
+                }
+
+                32..=246 => {
+                    operands.push(Operand::Int(b0 - 139));
+                    pos += 1;
+                }
+
+                247..=250 => {
+                    operands.push(Operand::Int((b0 - 247) * 256 + byte(pos + 1)? + 108));
+                    pos += 2;
+                }
+
+                251..=254 => {
+                    operands.push(Operand::Int(-(b0 - 251) * 256 - byte(pos + 1)? - 108));
+                    pos += 2;
+                }
+
+                _ => bail!("invalid byte {} in CFF DICT", b0),
+            }
+        }
+
+        Ok(Dict(entries))
+    }
+
+    fn get_int(&self, op: u16, index: usize) -> Option<i32> {
+        self.0
+            .iter()
+            .find(|(o, _)| *o == op)
+            .and_then(|(_, operands)| match operands.get(index) {
+                Some(Operand::Int(v)) => Some(*v),
+                _ => None,
+            })
+    }
+
+    /// Get the size and offset of the Private DICT.
+    fn get_private(&self) -> Option<(i32, i32)> {
+        Some((self.get_int(OP_PRIVATE, 0)?, self.get_int(OP_PRIVATE, 1)?))
+    }
+
+    fn set(&mut self, op: u16, values: &[i32]) {
+        let operands = values.iter().map(|v| Operand::Int(*v)).collect();
+
+        match self.0.iter_mut().find(|(o, _)| *o == op) {
+            Some(entry) => entry.1 = operands,
+            None => self.0.push((op, operands)),
+        }
+    }
+
+    /// Encode the DICT. Integers are always written in the five-byte form.
+    fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+
+        for (op, operands) in &self.0 {
+            for operand in operands {
+                match operand {
+                    Operand::Int(v) => {
+                        out.push(29);
+                        out.write_i32::<BigEndian>(*v).unwrap();
+                    }
+
+                    Operand::Real(r) => out.extend_from_slice(r),
+                }
+            }
+
+            if *op > 0xff {
+                out.push(12);
+            }
+
+            out.push(*op as u8);
+        }
+
+        out
+    }
+}
+
+/// A Private DICT and its local subroutines.
+struct PrivateData<'a> {
+    dict: Dict,
+    subrs: Option<&'a [u8]>,
+}
+
+/// An encoded Private DICT, followed by its local subroutines.
+struct EncodedPrivateData {
+    dict_len: usize,
+    data: Vec<u8>,
+}
+
+impl<'a> PrivateData<'a> {
+    fn read(cff: &'a [u8], size: i32, ofs: i32) -> Result<Self> {
+        let dict = Dict::parse(cff_slice(cff, ofs, usize::try_from(size)?)?)?;
+
+        let subrs = match dict.get_int(OP_SUBRS, 0) {
+            Some(rel) => Some(read_index(cff, usize::try_from(ofs + rel)?)?.data),
+            None => None,
+        };
+
+        Ok(PrivateData { dict, subrs })
+    }
+
+    /// Encode the DICT, with the subroutines placed right after it.
+    fn encode(&self) -> EncodedPrivateData {
+        let mut dict = self.dict.clone();
+
+        if self.subrs.is_some() {
+            // The DICT size is independent of the offset value.
+            let dict_len = dict.encode().len();
+            dict.set(OP_SUBRS, &[dict_len as i32]);
+        }
+
+        let mut data = dict.encode();
+        let dict_len = data.len();
+
+        if let Some(subrs) = self.subrs {
+            data.extend_from_slice(subrs);
+        }
+
+        EncodedPrivateData { dict_len, data }
+    }
+}
+
+/// Assemble a font file from its tables. The tables are sorted by tag and the
+/// checksums are filled in.
+fn assemble_sfnt(version: u32, mut tables: Vec<(Tag, Vec<u8>)>) -> Vec<u8> {
+    tables.sort_by_key(|(tag, _)| tag.0);
+
+    let n = tables.len() as u16;
+    let entry_selector = 15 - n.max(1).leading_zeros() as u16;
+    let search_range = (1 << entry_selector) * 16;
+
+    let mut out = Vec::new();
+    out.write_u32::<BigEndian>(version).unwrap();
+    out.write_u16::<BigEndian>(n).unwrap();
+    out.write_u16::<BigEndian>(search_range).unwrap();
+    out.write_u16::<BigEndian>(entry_selector).unwrap();
+    out.write_u16::<BigEndian>(n * 16 - search_range).unwrap();
+
+    let mut offset = 12 + 16 * tables.len();
+    let mut head_offset = None;
+
+    for (tag, data) in &mut tables {
+        if *tag == HEAD {
+            // The checksum adjustment is zeroed while checksums are computed.
+            BigEndian::write_u32(&mut data[8..12], 0);
+            head_offset = Some(offset);
+        }
+
+        out.write_u32::<BigEndian>(tag.0).unwrap();
+        out.write_u32::<BigEndian>(opentype_checksum(data)).unwrap();
+        out.write_u32::<BigEndian>(offset as u32).unwrap();
+        out.write_u32::<BigEndian>(data.len() as u32).unwrap();
+        offset += (data.len() + 3) & !3;
+    }
+
+    for (_, data) in &tables {
+        out.extend_from_slice(data);
+        out.resize((out.len() + 3) & !3, 0);
+    }
+
+    if let Some(ofs) = head_offset {
+        let adj = Wrapping(0xB1B0AFBA) - Wrapping(opentype_checksum(&out));
+        BigEndian::write_u32(&mut out[ofs + 8..ofs + 12], adj.0);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pinot::FontDataRef;
+    use std::path::Path;
+
+    fn load(name: &str) -> Vec<u8> {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../tests/assets")
+            .join(name);
+        std::fs::read(path).unwrap()
+    }
+
+    fn cmap_for(data: &[u8], text: &str) -> (BTreeSet<GlyphId>, Vec<u8>) {
+        let font = FontDataRef::new(data).unwrap().get(0).unwrap();
+        let cmap = font.cmap().unwrap();
+        let mut map: Vec<_> = text
+            .chars()
+            .map(|c| (c, cmap.map(c as u32).unwrap()))
+            .collect();
+        map.sort_unstable();
+        map.dedup();
+
+        let mut glyphs = map.iter().map(|(_, g)| *g).collect();
+        close_over_substitutions(&font, &mut glyphs);
+
+        let mut cmap_data = Vec::new();
+        crate::fontfile::append_simple_cmap(&mut cmap_data, &map);
+        (glyphs, cmap_data)
+    }
+
+    /// Check a subsetted font: the table checksums have to be right, the kept
+    /// glyphs have to be there, and the font should be a lot smaller.
+    fn check(orig: &[u8], sub: &[u8], glyphs: &BTreeSet<GlyphId>, text: &str) {
+        let font = FontDataRef::new(sub).unwrap().get(0).unwrap();
+
+        for rec in font.records().iter() {
+            let data = &sub[rec.offset as usize..(rec.offset + rec.len) as usize];
+
+            if rec.tag != HEAD {
+                assert_eq!(opentype_checksum(data), rec.checksum, "{}", rec.tag);
+            }
+        }
+
+        assert_eq!(opentype_checksum(sub), 0xB1B0AFBA);
+
+        let cmap = font.cmap().unwrap();
+
+        for c in text.chars() {
+            assert!(glyphs.contains(&cmap.map(c as u32).unwrap()));
+        }
+
+        assert_eq!(cmap.map('Z' as u32), None);
+        assert_eq!(
+            font.hmtx().unwrap().hmetrics().len(),
+            FontDataRef::new(orig)
+                .unwrap()
+                .get(0)
+                .unwrap()
+                .hmtx()
+                .unwrap()
+                .hmetrics()
+                .len()
+        );
+    }
+
+    #[test]
+    fn truetype() {
+        let orig = load("LinLibertine_R_G.ttf");
+        let (glyphs, cmap) = cmap_for(&orig, "fish");
+
+        // The "fi" ligature can be formed from the text, so it's kept.
+        assert!(glyphs.len() > 5);
+
+        let sub = subset(&orig, 0, &glyphs, &cmap).unwrap();
+        check(&orig, &sub, &glyphs, "fish");
+
+        let font = FontDataRef::new(&sub[..]).unwrap().get(0).unwrap();
+        let glyf = font.find_record(GLYF).unwrap();
+        let loca = font.find_record(LOCA).unwrap();
+        let orig_font = FontDataRef::new(&orig[..]).unwrap().get(0).unwrap();
+        let orig_loca = orig_font.find_record(LOCA).unwrap();
+        assert_eq!(loca.len, orig_loca.len);
+        assert!(glyf.len < 20_000);
+        assert!(sub.len() * 10 < orig.len());
+
+        // Subsetting is deterministic.
+        assert_eq!(sub, subset(&orig, 0, &glyphs, &cmap).unwrap());
+    }
+
+    #[test]
+    fn cff() {
+        let orig = load("lmroman12-regular.otf");
+        let (glyphs, cmap) = cmap_for(&orig, "Hello");
+        let sub = subset(&orig, 0, &glyphs, &cmap).unwrap();
+        check(&orig, &sub, &glyphs, "Hello");
+
+        let font = FontDataRef::new(&sub[..]).unwrap().get(0).unwrap();
+        let rec = font.find_record(CFF).unwrap();
+        let cff = &sub[rec.offset as usize..(rec.offset + rec.len) as usize];
+        let orig_font = FontDataRef::new(&orig[..]).unwrap().get(0).unwrap();
+        let orig_rec = orig_font.find_record(CFF).unwrap();
+        let orig_cff = &orig[orig_rec.offset as usize..(orig_rec.offset + orig_rec.len) as usize];
+        assert!(cff.len() * 2 < orig_cff.len());
+
+        // The glyph numbering is preserved, and the kept charstrings are the
+        // same as in the original.
+        fn index(data: &[u8]) -> Vec<&[u8]> {
+            let hdr = data[2] as usize;
+            let top = read_index(data, read_index(data, hdr).unwrap().end).unwrap();
+            let dict = Dict::parse(top.items[0]).unwrap();
+            let ofs = dict.get_int(OP_CHARSTRINGS, 0).unwrap();
+            read_index(data, ofs as usize).unwrap().items
+        }
+
+        let new_cs = index(cff);
+        let orig_cs = index(orig_cff);
+        assert_eq!(new_cs.len(), orig_cs.len());
+
+        for (gid, (n, o)) in new_cs.iter().zip(&orig_cs).enumerate() {
+            if gid == 0 || glyphs.contains(&(gid as GlyphId)) {
+                assert_eq!(n, o);
+            } else {
+                assert_eq!(*n, EMPTY_CHARSTRING);
+            }
+        }
+
+        // The Private DICT is still found, with its subroutines.
+        let hdr = cff[2] as usize;
+        let top = read_index(cff, read_index(cff, hdr).unwrap().end).unwrap();
+        let (size, ofs) = Dict::parse(top.items[0]).unwrap().get_private().unwrap();
+        let private = PrivateData::read(cff, size, ofs).unwrap();
+        assert_eq!(private.dict.get_int(OP_SUBRS, 0), Some(size));
+    }
+}
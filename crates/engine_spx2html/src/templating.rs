@@ -3,14 +3,15 @@
 
 //! State relating to handling the Tera templating and file emission.
 
-use std::{
-    fs::File,
-    io::{Read, Write},
-};
+use std::io::Read;
 use tectonic_errors::prelude::*;
 use tectonic_status_base::tt_warning;
 
-use crate::Common;
+use crate::{
+    accessibility::is_plausible_language_tag,
+    headings::{self, Heading, HeadingOptions},
+    Common,
+};
 
 #[derive(Debug)]
 pub(crate) struct Templating {
@@ -61,12 +62,43 @@ impl Templating {
         Ok(())
     }
 
+    /// Handle a `tdux:lang` special, which declares the language of the
+    /// document. Templates can use it through the `tduxLang` variable, which
+    /// should be used to set the `lang` attribute of the `<html>` element.
+    pub(crate) fn handle_set_language(&mut self, tag: &str, common: &mut Common) -> Result<()> {
+        if is_plausible_language_tag(tag) {
+            self.set_variable("tduxLang", tag);
+        } else {
+            tt_warning!(
+                common.status,
+                "ignoring tdux:lang special with invalid language tag `{}`",
+                tag
+            );
+        }
+
+        Ok(())
+    }
+
     pub(crate) fn set_variable<S: AsRef<str>>(&mut self, name: &str, value: S) {
         // Unfortunately tera doesn't seem to give us a way to move an owned
         // value directly into the context object.
         self.context.insert(name, value.as_ref());
     }
 
+    /// Set the variables describing the headings of the current output file:
+    /// `tduxToc`, its rendered table of contents, and `tduxHeadings`, the
+    /// headings that it lists. See the [`crate::headings`] module.
+    pub(crate) fn set_headings(&mut self, headings: &[Heading], options: &HeadingOptions) {
+        let entries = headings::toc_entries(headings, options);
+        self.context
+            .insert("tduxToc", &headings::render_toc(&entries, options));
+        self.context.insert("tduxHeadings", &entries);
+    }
+
+    pub(crate) fn next_output_path(&self) -> &str {
+        &self.next_output_path
+    }
+
     pub(crate) fn ready_to_output(&self) -> bool {
         !self.next_template_path.is_empty() && !self.next_output_path.is_empty()
     }
@@ -83,18 +115,20 @@ impl Templating {
         let (out_path, n_levels) =
             crate::assets::create_output_path(&self.next_output_path, common)?;
 
-        if n_levels < 2 {
-            self.context.insert("tduxRelTop", "");
-        } else {
-            let mut rel_top = String::default();
-
-            for _ in 0..(n_levels - 1) {
-                rel_top.push_str("../");
-            }
+        let mut rel_top = String::default();
 
-            self.context.insert("tduxRelTop", &rel_top);
+        for _ in 1..n_levels {
+            rel_top.push_str("../");
         }
 
+        self.context.insert("tduxRelTop", &rel_top);
+
+        let social_meta = common
+            .social
+            .map(|meta| meta.render(&self.next_output_path, &rel_top))
+            .unwrap_or_default();
+        self.context.insert("tduxSocialMeta", &social_meta);
+
         // Read in the template. Let's not cache it, in case someone wants to do
         // something fancy with rewriting it. If that setting is empty, probably
         // the user is compiling the document in HTML mode without all of the
@@ -123,18 +157,14 @@ impl Templating {
             ["failed to render HTML template `{}` while creating `{}`", &self.next_template_path, &self.next_output_path]
         );
 
+        common.audit.check_output(&self.next_output_path, &rendered);
+
         // Save it. Unless we shouldn't, actually.
 
         if let Some(out_path) = out_path {
-            let mut out_file = atry!(
-                File::create(&out_path);
-                ["cannot open output file `{}`", out_path.display()]
-            );
-
-            atry!(
-                out_file.write_all(rendered.as_bytes());
-                ["cannot write output file `{}`", out_path.display()]
-            );
+            common
+                .outputs
+                .write(&out_path, rendered.as_bytes(), common.status)?;
         }
 
         // Clear the output path, because we don't want people to be accidentally
@@ -36,8 +36,10 @@ use tectonic_errors::prelude::*;
 /// at the same time.
 pub struct XdvipdfmxEngine {
     paper_spec: String,
+    page_spec: Option<String>,
     enable_compression: bool,
     deterministic_tags: bool,
+    draft: bool,
     build_date: SystemTime,
 }
 
@@ -45,8 +47,10 @@ impl Default for XdvipdfmxEngine {
     fn default() -> Self {
         XdvipdfmxEngine {
             paper_spec: "letter".to_owned(),
+            page_spec: None,
             enable_compression: true,
             deterministic_tags: false,
+            draft: false,
             build_date: SystemTime::UNIX_EPOCH,
         }
     }
@@ -75,6 +79,18 @@ impl XdvipdfmxEngine {
         self
     }
 
+    /// Set whether the engine will run in draft mode.
+    ///
+    /// The default is false. In draft mode, raster images are not decoded:
+    /// each one is replaced with a blank placeholder of the same size, and
+    /// compression is disabled. This makes the engine much faster on documents
+    /// with many or large figures, at the cost of an output that is only good
+    /// for previewing the layout.
+    pub fn draft(&mut self, draft: bool) -> &mut Self {
+        self.draft = draft;
+        self
+    }
+
     /// Sets the build date embedded in the output artifacts
     ///
     /// The default value is the Unix epoch, which is almost certainly not what
@@ -93,6 +109,24 @@ impl XdvipdfmxEngine {
         self
     }
 
+    /// Only convert some of the pages of the input.
+    ///
+    /// The default is to convert all of them. The specification is a
+    /// comma-separated list of pages, such as `3`, and ranges of pages, such as
+    /// `12-20`. Pages are numbered from 1 in the order in which they were
+    /// shipped out, regardless of their printed numbers. A range with no start
+    /// begins at the first page, and one with no end runs through the last
+    /// page. Use [`validate_page_spec`] to check a specification before running
+    /// the engine.
+    ///
+    /// The pages that aren't selected are skipped entirely, so this is much
+    /// faster than converting the whole document when previewing part of a long
+    /// one. Links to skipped pages won't work in the output.
+    pub fn page_spec(&mut self, page_spec: Option<String>) -> &mut Self {
+        self.page_spec = page_spec;
+        self
+    }
+
     /// Run xdvipdfmx.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -114,10 +148,20 @@ impl XdvipdfmxEngine {
             ["paper_spec may not contain internal NULs"]
         );
 
+        if let Some(spec) = self.page_spec.as_deref() {
+            validate_page_spec(spec)?;
+        }
+
+        let pagespec_str = self.page_spec.as_deref().map(CString::new).transpose()?;
+
         let config = c_api::XdvipdfmxConfig {
             paperspec: paperspec_str.as_c_str().as_ptr(),
-            enable_compression: u8::from(self.enable_compression),
+            pagespec: pagespec_str
+                .as_ref()
+                .map_or(std::ptr::null(), |s| s.as_c_str().as_ptr()),
+            enable_compression: u8::from(self.enable_compression && !self.draft),
             deterministic_tags: u8::from(self.deterministic_tags),
+            draft: u8::from(self.draft),
             build_date: self
                 .build_date
                 .duration_since(SystemTime::UNIX_EPOCH)
@@ -145,6 +189,48 @@ impl XdvipdfmxEngine {
     }
 }
 
+/// Check that a specification of the pages to convert, as given to
+/// [`XdvipdfmxEngine::page_spec`], is well-formed.
+///
+/// The underlying C code aborts on malformed specifications with an unhelpful
+/// message, so they're checked before they get that far.
+pub fn validate_page_spec(spec: &str) -> Result<()> {
+    fn page_number(text: &str, spec: &str) -> Result<()> {
+        let text = text.trim();
+
+        if text.is_empty() {
+            return Ok(());
+        }
+
+        match text.parse::<u32>() {
+            Ok(0) => bail!("pages are numbered from 1, in page specification `{spec}`"),
+            Ok(_) => Ok(()),
+            Err(_) => bail!("bad page number `{text}` in page specification `{spec}`"),
+        }
+    }
+
+    ensure!(!spec.trim().is_empty(), "the page specification is empty");
+
+    for item in spec.split(',') {
+        ensure!(
+            !item.trim().is_empty(),
+            "empty item in page specification `{}`",
+            spec
+        );
+
+        match item.split_once('-') {
+            Some((first, last)) => {
+                page_number(first, spec)?;
+                page_number(last, spec)?;
+            }
+
+            None => page_number(item, spec)?,
+        }
+    }
+
+    Ok(())
+}
+
 #[doc(hidden)]
 pub mod c_api {
     // If you change the interfaces here, rerun cbindgen as described in the README!
@@ -155,8 +241,10 @@ pub mod c_api {
     #[repr(C)]
     pub struct XdvipdfmxConfig {
         pub paperspec: *const libc::c_char,
+        pub pagespec: *const libc::c_char,
         pub enable_compression: libc::c_uchar,
         pub deterministic_tags: libc::c_uchar,
+        pub draft: libc::c_uchar,
         pub build_date: u64,
     }
 
@@ -181,3 +269,14 @@ mod linkage {
 /// Does our resulting executable link correctly?
 #[test]
 fn linkage() {}
+
+#[test]
+fn page_specs() {
+    for good in ["3", "12-20", "1,4-6, 9", "5-", "-5", "2 - 3"] {
+        assert!(validate_page_spec(good).is_ok(), "{good}");
+    }
+
+    for bad in ["", "0", "1,,2", "a-b", "3-x", "1-2-3", "-1.5"] {
+        assert!(validate_page_spec(bad).is_err(), "{bad}");
+    }
+}
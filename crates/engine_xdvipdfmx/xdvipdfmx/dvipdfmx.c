@@ -511,10 +511,12 @@ tt_engine_xdvipdfmx_main(
   /* See ttstub_input_get_mtime() in tectonic_bridge_core about bridging time_t
    * over FFI. */
 
+  pdf_ximage_set_draft((bool) config->draft);
+
   rv = dvipdfmx_main(
     pdfname,
     dviname,
-    NULL, /* pagespec */
+    config->pagespec,
     0, /* opt_flags */
     false, /* translate */
     (bool) config->enable_compression,
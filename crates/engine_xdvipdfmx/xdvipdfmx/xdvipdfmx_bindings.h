@@ -8,8 +8,10 @@
 
 typedef struct {
   const char *paperspec;
+  const char *pagespec;
   unsigned char enable_compression;
   unsigned char deterministic_tags;
+  unsigned char draft;
   uint64_t build_date;
 } XdvipdfmxConfig;
 
@@ -169,6 +169,7 @@ const C_FLAGS: &[&str] = &[
 ];
 
 const C_FILES: &[&str] = &[
+    "xetex/xetex-checkpoint.c",
     "xetex/xetex-engine-interface.c",
     "xetex/xetex-errors.c",
     "xetex/xetex-ext.c",
@@ -0,0 +1,91 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Checkpoints of the engine's state in the middle of a document.
+//!
+//! The state itself is serialized by the C code, in `xetex-checkpoint.c`,
+//! which explains what's saved and how. It's opaque to the Rust side, which
+//! just keeps it around until the next run wants it.
+
+use std::fmt;
+
+use crate::c_api;
+
+/// A snapshot of the engine's state, taken partway through a document.
+///
+/// **Important:** this is an experimental feature.
+///
+/// A run asked to with [`TexEngine::checkpoint_after_page`] takes a
+/// checkpoint once it has shipped out the given number of pages, and it can
+/// then be retrieved with [`TexEngine::take_checkpoint`]. A later run given
+/// it with [`TexEngine::resume_from`] starts from where the checkpoint was
+/// taken instead of from the top of the document, if none of the input that
+/// the engine had read by then has changed; otherwise, it starts from the
+/// top as usual. This makes it possible to re-typeset just the tail of a
+/// document after it's been edited, as an editor's live preview might.
+///
+/// Checkpoints are only good for the build of the engine, and the format
+/// file, that they were taken with, so they're only kept in memory.
+///
+/// [`TexEngine::checkpoint_after_page`]: crate::TexEngine::checkpoint_after_page
+/// [`TexEngine::take_checkpoint`]: crate::TexEngine::take_checkpoint
+/// [`TexEngine::resume_from`]: crate::TexEngine::resume_from
+pub struct Checkpoint {
+    pub(crate) data: Vec<u8>,
+    pages: u32,
+}
+
+impl Checkpoint {
+    /// The number of pages that had been shipped out when the checkpoint was
+    /// taken.
+    pub fn pages(&self) -> u32 {
+        self.pages
+    }
+}
+
+impl fmt::Debug for Checkpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Checkpoint")
+            .field("pages", &self.pages)
+            .field("len", &self.data.len())
+            .finish()
+    }
+}
+
+/// Take the checkpoint made by the last engine run, if it made one. This
+/// must be called while holding the global engine lock.
+pub(crate) fn take() -> Option<Checkpoint> {
+    let mut checkpoint = None;
+
+    // SAFETY: the engine only calls `collect_checkpoint` during this call,
+    // with the context pointer that we give it.
+    unsafe {
+        c_api::tt_xetex_take_checkpoint(
+            collect_checkpoint,
+            &mut checkpoint as *mut Option<Checkpoint> as *mut _,
+        );
+    }
+
+    checkpoint
+}
+
+extern "C" fn collect_checkpoint(
+    context: *mut libc::c_void,
+    data: *const u8,
+    len: libc::size_t,
+    pages: i32,
+) {
+    // SAFETY: the context is the option passed in by `take`, and the engine
+    // gives us a pointer that's valid for the stated length during the call.
+    let (checkpoint, data) = unsafe {
+        (
+            &mut *(context as *mut Option<Checkpoint>),
+            std::slice::from_raw_parts(data, len),
+        )
+    };
+
+    *checkpoint = Some(Checkpoint {
+        data: data.to_vec(),
+        pages: pages.max(0) as u32,
+    });
+}
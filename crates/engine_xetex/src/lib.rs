@@ -18,10 +18,18 @@
 // TODO: the internal interface we're using here is pretty janky. The bibtex
 // engine has a nicer approach that we should probably start using.
 
-use std::{ffi::CString, time::SystemTime};
+use std::{ffi::CString, fmt, str::FromStr, time::SystemTime};
 use tectonic_bridge_core::{CoreBridgeLauncher, EngineAbortedError};
 use tectonic_errors::prelude::*;
 
+mod checkpoint;
+mod missing;
+mod shaping;
+
+pub use checkpoint::Checkpoint;
+pub use missing::{MissingCharacter, MissingCharacters};
+pub use shaping::{load_shaping_cache, save_shaping_cache};
+
 /// A serial number describing the detailed binary layout of the TeX "format
 /// files" used by this crate. This number will occasionally increment,
 /// indicating that the format file structure has changed. There is no provision
@@ -33,7 +41,11 @@ use tectonic_errors::prelude::*;
 //
 // DEVELOPER NOTE: if you change this, rerun cbindgen! This value is exported
 // into the C/C++ code as a #define.
-pub const FORMAT_SERIAL: u32 = 33;
+pub const FORMAT_SERIAL: u32 = 34;
+
+/// The default limit on the memory taken by the shaping cache, which matches
+/// the one that the C++ code starts with.
+const DEFAULT_SHAPING_CACHE_LIMIT: usize = 64 * 1024 * 1024;
 
 /// A possible outcome from a (Xe)TeX engine invocation.
 ///
@@ -61,6 +73,249 @@ pub enum TexOutcome {
     Errors,
 }
 
+/// Overrides for parameters read from OpenType math fonts.
+///
+/// Some OpenType math fonts ship with parameters that produce poor results,
+/// such as script sizes that are too small or a math axis that doesn't line up
+/// with the font's operators. These settings let a document work around such
+/// problems without patching the font files. They apply to every OpenType math
+/// font loaded by the engine.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct MathFontOverrides {
+    /// If set, replaces the font's `ScriptPercentScaleDown` constant: the
+    /// size of first-level scripts, as a percentage of the base size.
+    pub script_percent_scale_down: Option<u8>,
+
+    /// If set, replaces the font's `ScriptScriptPercentScaleDown` constant:
+    /// the size of second-level scripts, as a percentage of the base size.
+    pub script_script_percent_scale_down: Option<u8>,
+
+    /// An adjustment added to the font's `AxisHeight` constant, in thousandths
+    /// of an em. Positive values raise the math axis.
+    pub axis_height_adjust: i32,
+}
+
+/// How the engine memoizes the shaping of native-font text.
+///
+/// **Important:** this is an experimental feature.
+///
+/// Shaping text with OpenType and Graphite fonts is one of the more expensive
+/// things that the engine does. In a multi-pass build, most of the words
+/// shaped in the second and later passes are the same as in the first pass.
+/// When the cache is enabled, shaping results are remembered, keyed by the
+/// font, a digest of its file, its size, and the text, and reused when the
+/// same text is shaped again.
+/// The cache is global to the process, so that it can outlive a single engine
+/// run, and it is shared by all fonts. It has its own lock, so it can be
+/// saved with [`save_shaping_cache`] and loaded with [`load_shaping_cache`]
+/// from any thread, which lets a cache be carried over from one process to
+/// the next. Once its entries take up more memory than the limit set with
+/// [`TexEngine::shaping_cache_limit`], the least recently used ones are
+/// evicted.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum ShapingCache {
+    /// Don't memoize shaping, and discard any results cached by previous runs.
+    /// This is the default.
+    #[default]
+    Disabled,
+
+    /// Memoize shaping, starting from an empty cache.
+    Fresh,
+
+    /// Memoize shaping, reusing any results cached by previous runs in this
+    /// process. Only use this for runs with the same format and fonts as the
+    /// previous ones, such as the later passes of a build.
+    Reuse,
+}
+
+/// Statistics about use of the shaping cache during an engine run.
+///
+/// See [`ShapingCache`].
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct ShapingCacheStats {
+    /// The number of times that a shaping result was found in the cache.
+    pub hits: u64,
+
+    /// The number of times that text had to be shaped and was then added to
+    /// the cache.
+    pub misses: u64,
+
+    /// The number of entries that were evicted from the cache to keep it
+    /// within its limit.
+    pub evictions: u64,
+}
+
+/// A TeX tracing parameter that can be enabled by a [`TraceCapture`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum TraceParameter {
+    /// `\tracingcommands`, at level 3.
+    Commands,
+    /// `\tracingmacros`, at level 2, which also shows token lists such as
+    /// `\everypar` as they're inserted.
+    Macros,
+    /// `\tracingparagraphs`.
+    Paragraphs,
+    /// `\tracingpages`.
+    Pages,
+    /// `\tracingoutput`.
+    Output,
+    /// `\tracingrestores`.
+    Restores,
+    /// `\tracingassigns`.
+    Assigns,
+    /// `\tracinggroups`.
+    Groups,
+    /// `\tracingifs`.
+    Ifs,
+    /// `\tracingnesting`, at level 2.
+    Nesting,
+    /// `\tracingscantokens`.
+    ScanTokens,
+    /// `\tracinglostchars`.
+    LostChars,
+}
+
+impl TraceParameter {
+    // The order here defines the bits of the `trace_capture_flags` variable,
+    // and must match the `trace_params` table in `xetex-output.c`.
+    const ALL: &'static [(TraceParameter, &'static str)] = &[
+        (TraceParameter::Commands, "commands"),
+        (TraceParameter::Macros, "macros"),
+        (TraceParameter::Paragraphs, "paragraphs"),
+        (TraceParameter::Pages, "pages"),
+        (TraceParameter::Output, "output"),
+        (TraceParameter::Restores, "restores"),
+        (TraceParameter::Assigns, "assigns"),
+        (TraceParameter::Groups, "groups"),
+        (TraceParameter::Ifs, "ifs"),
+        (TraceParameter::Nesting, "nesting"),
+        (TraceParameter::ScanTokens, "scantokens"),
+        (TraceParameter::LostChars, "lostchars"),
+    ];
+
+    fn bit(&self) -> u32 {
+        let i = Self::ALL.iter().position(|(p, _)| p == self).unwrap();
+        1 << i
+    }
+}
+
+impl FromStr for TraceParameter {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.strip_prefix("tracing").unwrap_or(s);
+
+        match Self::ALL.iter().find(|(_, name)| *name == s) {
+            Some((p, _)) => Ok(*p),
+            None => bail!("unknown tracing parameter `{}`", s),
+        }
+    }
+}
+
+impl fmt::Display for TraceParameter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (_, name) = Self::ALL.iter().find(|(p, _)| p == self).unwrap();
+        f.write_str(name)
+    }
+}
+
+/// Settings for capturing TeX tracing output into a separate file.
+///
+/// Debugging layout problems often calls for the `\tracing...` parameters,
+/// but turning them on for a whole document buries the normal log under
+/// megabytes of output. With a capture, the engine forces the chosen
+/// parameters on only while it's working on the given range of pages, or of
+/// lines of the primary input file, and writes the diagnostics that they
+/// produce to a file named after the job with the extension `.trace` (or
+/// `.trace.gz` if compressed) instead of to the log. The parameters are set
+/// back to the document's values when the range is left.
+///
+/// The textual form, parsed by the [`FromStr`] implementation, is a
+/// comma-separated list of parameter names, such as `macros` or
+/// `paragraphs`, and settings: `page-range=<first>-<last>`,
+/// `line-range=<first>-<last>`, `max-size=<bytes>`, and `compress`. Either end
+/// of a range may be omitted, and sizes may have a `k` or `M` suffix.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct TraceCapture {
+    /// The tracing parameters to turn on.
+    pub parameters: Vec<TraceParameter>,
+
+    /// The first and last pages on which to capture, counting from 1 in the
+    /// order that they're shipped out. `None` leaves that end open.
+    pub pages: (Option<u32>, Option<u32>),
+
+    /// The first and last lines of the primary input file at which to
+    /// capture, including anything read from files that those lines input.
+    /// `None` leaves that end open.
+    pub lines: (Option<u32>, Option<u32>),
+
+    /// Stop capturing once the trace file reaches this many bytes.
+    pub max_bytes: Option<u32>,
+
+    /// Compress the trace file with gzip.
+    pub compress: bool,
+}
+
+impl FromStr for TraceCapture {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        fn range(s: &str) -> Result<(Option<u32>, Option<u32>)> {
+            let Some((first, last)) = s.split_once('-') else {
+                let n = s.parse().map_err(|_| anyhow!("invalid range `{}`", s))?;
+                return Ok((Some(n), Some(n)));
+            };
+
+            let end = |e: &str| -> Result<Option<u32>> {
+                if e.is_empty() {
+                    Ok(None)
+                } else {
+                    Ok(Some(
+                        e.parse().map_err(|_| anyhow!("invalid range `{}`", s))?,
+                    ))
+                }
+            };
+
+            Ok((end(first)?, end(last)?))
+        }
+
+        let mut capture = TraceCapture::default();
+
+        for item in s.split(',') {
+            match item.split_once('=') {
+                Some(("page-range", r)) => capture.pages = range(r)?,
+                Some(("line-range", r)) => capture.lines = range(r)?,
+                Some(("max-size", v)) => {
+                    let (digits, scale) = match v.strip_suffix('k') {
+                        Some(d) => (d, 1 << 10),
+                        None => match v.strip_suffix('M') {
+                            Some(d) => (d, 1 << 20),
+                            None => (v, 1),
+                        },
+                    };
+
+                    let size = digits
+                        .parse::<u32>()
+                        .ok()
+                        .and_then(|n| n.checked_mul(scale))
+                        .ok_or_else(|| anyhow!("invalid trace size `{}`", v))?;
+                    capture.max_bytes = Some(size);
+                }
+                Some((k, _)) => bail!("unknown trace setting `{}`", k),
+                None if item == "compress" => capture.compress = true,
+                None => capture.parameters.push(item.parse()?),
+            }
+        }
+
+        ensure!(
+            !capture.parameters.is_empty(),
+            "no tracing parameters given in `{}`",
+            s
+        );
+        Ok(capture)
+    }
+}
+
 /// A struct for invoking the (Xe)TeX engine.
 ///
 /// This struct has a fairly straightforward "builder" interface: you create it,
@@ -83,6 +338,17 @@ pub struct TexEngine {
     semantic_pagination_enabled: bool,
     shell_escape_enabled: bool,
     build_date: SystemTime,
+    math_font_overrides: MathFontOverrides,
+    shaping_cache: ShapingCache,
+    shaping_cache_limit: usize,
+    shaping_cache_stats: ShapingCacheStats,
+    trace_capture: Option<TraceCapture>,
+    checkpoint_after_page: Option<u32>,
+    resume_from: Option<Checkpoint>,
+    checkpoint: Option<Checkpoint>,
+    resumed: bool,
+    missing_characters: Vec<MissingCharacters>,
+    missing_character_count: usize,
 }
 
 impl Default for TexEngine {
@@ -94,6 +360,17 @@ impl Default for TexEngine {
             semantic_pagination_enabled: false,
             shell_escape_enabled: false,
             build_date: SystemTime::UNIX_EPOCH,
+            math_font_overrides: MathFontOverrides::default(),
+            shaping_cache: ShapingCache::default(),
+            shaping_cache_limit: DEFAULT_SHAPING_CACHE_LIMIT,
+            shaping_cache_stats: ShapingCacheStats::default(),
+            trace_capture: None,
+            checkpoint_after_page: None,
+            resume_from: None,
+            checkpoint: None,
+            resumed: false,
+            missing_characters: Vec::new(),
+            missing_character_count: 0,
         }
     }
 }
@@ -159,6 +436,128 @@ impl TexEngine {
         self
     }
 
+    /// Configure overrides for the parameters of OpenType math fonts.
+    ///
+    /// The default is to use the parameters provided by the fonts unchanged.
+    pub fn math_font_overrides(&mut self, overrides: MathFontOverrides) -> &mut Self {
+        self.math_font_overrides = overrides;
+        self
+    }
+
+    /// Capture tracing output into a separate file. See [`TraceCapture`].
+    ///
+    /// The default is not to capture anything.
+    pub fn trace_capture(&mut self, capture: Option<TraceCapture>) -> &mut Self {
+        self.trace_capture = capture;
+        self
+    }
+
+    /// Configure memoization of text shaping. See [`ShapingCache`].
+    ///
+    /// The default is [`ShapingCache::Disabled`].
+    pub fn shaping_cache(&mut self, mode: ShapingCache) -> &mut Self {
+        self.shaping_cache = mode;
+        self
+    }
+
+    /// Set the limit on the memory taken by the shaping cache, in bytes. See
+    /// [`ShapingCache`].
+    ///
+    /// Since the cache is global to the process, the limit applies to it
+    /// from the start of this run until another one sets it differently.
+    /// The default is 64 MiB.
+    pub fn shaping_cache_limit(&mut self, bytes: usize) -> &mut Self {
+        self.shaping_cache_limit = bytes;
+        self
+    }
+
+    /// Take a [`Checkpoint`] of the engine state once the given number of
+    /// pages have been shipped out, for a later run to resume from.
+    ///
+    /// **Important:** this is an experimental feature.
+    ///
+    /// The checkpoint is taken at the first point after the page has been
+    /// shipped out at which the engine is between commands, and isn't
+    /// reading any files through ICU converters (which are used for legacy
+    /// encodings selected with `\XeTeXinputencoding`). It can't be taken if
+    /// SyncTeX or trace capture are enabled, in "initex" mode, or if a TFM
+    /// font with a TECkit mapping has been loaded, in which case a warning is
+    /// issued. Once the run is over, get it with
+    /// [`take_checkpoint()`](Self::take_checkpoint).
+    ///
+    /// The default is not to take a checkpoint.
+    pub fn checkpoint_after_page(&mut self, page: Option<u32>) -> &mut Self {
+        self.checkpoint_after_page = page;
+        self
+    }
+
+    /// Resume from a [`Checkpoint`] taken by an earlier run, rather than
+    /// starting from the top of the document.
+    ///
+    /// **Important:** this is an experimental feature.
+    ///
+    /// The run is set up as the earlier one was when it took the checkpoint,
+    /// with the files that it was reading reopened at the same places, and
+    /// with its output files rewritten as they were at the time. This is only
+    /// done if the files that the earlier run had read, or had looked for and
+    /// not found, are the same as they were then, as far as it had read them;
+    /// if not, or if the checkpoint doesn't match the engine and format, the
+    /// run starts from the top as usual, with a warning. Use
+    /// [`resumed_from_checkpoint()`](Self::resumed_from_checkpoint) to find
+    /// out which happened.
+    ///
+    /// Only the primary input, the files read by TeX, the TFM files, and the
+    /// images and files whose sizes or dates were asked for are checked:
+    /// changes to native font files aren't detected, nor are any side effects
+    /// of the earlier run, such as those of shell-escape commands. The
+    /// resumed run should use the same settings as the earlier one.
+    ///
+    /// The default is to start from the top.
+    pub fn resume_from(&mut self, checkpoint: Option<Checkpoint>) -> &mut Self {
+        self.resume_from = checkpoint;
+        self
+    }
+
+    /// Take the [`Checkpoint`] made by the most recent call to
+    /// [`process()`](Self::process), if it made one. See
+    /// [`checkpoint_after_page()`](Self::checkpoint_after_page).
+    pub fn take_checkpoint(&mut self) -> Option<Checkpoint> {
+        self.checkpoint.take()
+    }
+
+    /// Whether the most recent call to [`process()`](Self::process) resumed
+    /// from the checkpoint given with [`resume_from()`](Self::resume_from).
+    pub fn resumed_from_checkpoint(&self) -> bool {
+        self.resumed
+    }
+
+    /// Get the characters that couldn't be found in their fonts during the
+    /// most recent call to [`process()`](Self::process), grouped by font in
+    /// the order in which the fonts were first missing one.
+    ///
+    /// Only the first several thousand are recorded; see
+    /// [`missing_character_count()`](Self::missing_character_count) for the
+    /// total. If the run resumed from a checkpoint, characters that were
+    /// missing before the checkpoint was taken aren't included.
+    pub fn missing_characters(&self) -> &[MissingCharacters] {
+        &self.missing_characters
+    }
+
+    /// Get the total number of times that a character couldn't be found in
+    /// its font during the most recent call to [`process()`](Self::process),
+    /// including any that weren't recorded.
+    pub fn missing_character_count(&self) -> usize {
+        self.missing_character_count
+    }
+
+    /// Get statistics about use of the shaping cache during the most recent
+    /// call to [`process()`](Self::process).
+    ///
+    /// If the cache was disabled, the statistics will all be zero.
+    pub fn shaping_cache_stats(&self) -> ShapingCacheStats {
+        self.shaping_cache_stats
+    }
+
     /// Process a document using the current engine configuration.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -203,8 +602,67 @@ impl TexEngine {
                     c"semantic_pagination_enabled".as_ptr(),
                     self.semantic_pagination_enabled.into(),
                 );
+                tt_xetex_set_int_variable(
+                    c"math_script_percent_scale_down_override".as_ptr(),
+                    self.math_font_overrides
+                        .script_percent_scale_down
+                        .unwrap_or(0)
+                        .into(),
+                );
+                tt_xetex_set_int_variable(
+                    c"math_script_script_percent_scale_down_override".as_ptr(),
+                    self.math_font_overrides
+                        .script_script_percent_scale_down
+                        .unwrap_or(0)
+                        .into(),
+                );
+                tt_xetex_set_int_variable(
+                    c"math_axis_height_adjust".as_ptr(),
+                    self.math_font_overrides.axis_height_adjust,
+                );
+                tt_xetex_set_int_variable(
+                    c"shaping_cache_mode".as_ptr(),
+                    match self.shaping_cache {
+                        ShapingCache::Disabled => 0,
+                        ShapingCache::Fresh => 1,
+                        ShapingCache::Reuse => 2,
+                    },
+                );
+                tt_xetex_set_shaping_cache_limit(self.shaping_cache_limit);
+
+                let trace = self.trace_capture.clone().unwrap_or_default();
+                let trace_vars = [
+                    (
+                        c"trace_capture_flags",
+                        trace.parameters.iter().fold(0, |f, p| f | p.bit()),
+                    ),
+                    (c"trace_capture_first_page", trace.pages.0.unwrap_or(0)),
+                    (c"trace_capture_last_page", trace.pages.1.unwrap_or(0)),
+                    (c"trace_capture_first_line", trace.lines.0.unwrap_or(0)),
+                    (c"trace_capture_last_line", trace.lines.1.unwrap_or(0)),
+                    (c"trace_capture_max_bytes", trace.max_bytes.unwrap_or(0)),
+                    (c"trace_capture_compress", trace.compress.into()),
+                ];
 
-                tt_engine_xetex_main(
+                for (name, value) in trace_vars {
+                    tt_xetex_set_int_variable(name.as_ptr(), value.min(i32::MAX as u32) as i32);
+                }
+
+                tt_xetex_set_int_variable(
+                    c"checkpoint_after_page".as_ptr(),
+                    self.checkpoint_after_page
+                        .map_or(0, |p| p.clamp(1, i32::MAX as u32) as i32),
+                );
+
+                match &self.resume_from {
+                    Some(checkpoint) => tt_xetex_set_resume_checkpoint(
+                        checkpoint.data.as_ptr(),
+                        checkpoint.data.len(),
+                    ),
+                    None => tt_xetex_set_resume_checkpoint(std::ptr::null(), 0),
+                }
+
+                let r = tt_engine_xetex_main(
                     state,
                     cformat.as_ptr(),
                     cinput.as_ptr(),
@@ -212,7 +670,18 @@ impl TexEngine {
                         .duration_since(SystemTime::UNIX_EPOCH)
                         .expect("invalid build date")
                         .as_secs(),
-                )
+                );
+
+                tt_xetex_get_shaping_cache_stats(
+                    &mut self.shaping_cache_stats.hits,
+                    &mut self.shaping_cache_stats.misses,
+                    &mut self.shaping_cache_stats.evictions,
+                );
+                tt_xetex_set_resume_checkpoint(std::ptr::null(), 0);
+                self.resumed = tt_xetex_resumed_from_checkpoint() != 0;
+                self.checkpoint = checkpoint::take();
+                (self.missing_characters, self.missing_character_count) = missing::take();
+                r
             };
 
             match r {
@@ -232,6 +701,31 @@ pub mod c_api {
 
     use tectonic_bridge_core::CoreBridgeState;
 
+    /// A callback that receives the entries of the shaping cache.
+    pub type ShapedTextVisitor = extern "C" fn(
+        context: *mut libc::c_void,
+        key: *const u16,
+        key_len: libc::size_t,
+        width: i32,
+        glyph_count: libc::c_int,
+        glyph_info: *const libc::c_void,
+        glyph_info_len: libc::size_t,
+    );
+
+    /// A callback that receives an engine checkpoint.
+    pub type CheckpointVisitor =
+        extern "C" fn(context: *mut libc::c_void, data: *const u8, len: libc::size_t, pages: i32);
+
+    /// A callback that receives a character that was missing from a font.
+    pub type MissingCharVisitor = extern "C" fn(
+        context: *mut libc::c_void,
+        font: *const libc::c_char,
+        file: *const libc::c_char,
+        line: i32,
+        code: i32,
+        unicode: libc::c_int,
+    );
+
     #[allow(improper_ctypes)] // for CoreBridgeState
     extern "C" {
         pub fn tt_xetex_set_int_variable(
@@ -239,6 +733,42 @@ pub mod c_api {
             value: libc::c_int,
         ) -> libc::c_int;
 
+        pub fn tt_xetex_get_shaping_cache_stats(
+            hits: *mut u64,
+            misses: *mut u64,
+            evictions: *mut u64,
+        );
+
+        pub fn tt_xetex_set_shaping_cache_limit(limit: libc::size_t);
+
+        pub fn tt_xetex_add_shaped_text(
+            key: *const u16,
+            key_len: libc::size_t,
+            width: i32,
+            glyph_count: libc::c_int,
+            glyph_info: *const libc::c_void,
+            glyph_info_len: libc::size_t,
+        ) -> libc::c_int;
+
+        pub fn tt_xetex_visit_shaped_texts(
+            visitor: ShapedTextVisitor,
+            context: *mut libc::c_void,
+        ) -> libc::size_t;
+
+        pub fn tt_xetex_set_resume_checkpoint(data: *const u8, len: libc::size_t);
+
+        pub fn tt_xetex_take_checkpoint(
+            visitor: CheckpointVisitor,
+            context: *mut libc::c_void,
+        ) -> libc::c_int;
+
+        pub fn tt_xetex_resumed_from_checkpoint() -> libc::c_int;
+
+        pub fn tt_xetex_visit_missing_chars(
+            visitor: MissingCharVisitor,
+            context: *mut libc::c_void,
+        ) -> libc::size_t;
+
         pub fn tt_engine_xetex_main(
             api: &mut CoreBridgeState,
             dump_name: *const libc::c_char,
@@ -261,3 +791,27 @@ mod linkage {
 /// Does our resulting executable link correctly?
 #[test]
 fn linkage() {}
+
+#[test]
+fn parse_trace_capture() {
+    let c: TraceCapture =
+        "macros,tracingparagraphs,page-range=3-5,line-range=-200,max-size=2M,compress"
+            .parse()
+            .unwrap();
+    assert_eq!(
+        c.parameters,
+        [TraceParameter::Macros, TraceParameter::Paragraphs]
+    );
+    assert_eq!(c.pages, (Some(3), Some(5)));
+    assert_eq!(c.lines, (None, Some(200)));
+    assert_eq!(c.max_bytes, Some(2 << 20));
+    assert!(c.compress);
+
+    let c: TraceCapture = "output,page-range=7".parse().unwrap();
+    assert_eq!(c.pages, (Some(7), Some(7)));
+    assert_eq!(TraceParameter::ScanTokens.to_string(), "scantokens");
+
+    assert!("page-range=1-2".parse::<TraceCapture>().is_err());
+    assert!("macros,bogus".parse::<TraceCapture>().is_err());
+    assert!("macros,max-size=lots".parse::<TraceCapture>().is_err());
+}
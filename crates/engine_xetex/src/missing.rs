@@ -0,0 +1,96 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Characters that the engine couldn't find in its fonts.
+//!
+//! Besides logging them, `char_warning()` in the C code records each one along
+//! with where it was in the input. The records are collected here after a
+//! run and grouped by font.
+
+use std::ffi::CStr;
+
+use crate::c_api;
+
+/// A character that the engine couldn't find in a font.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingCharacter {
+    /// The character code. For OpenType and AAT fonts this is a Unicode
+    /// scalar value; for TFM fonts it's a slot in the font.
+    pub code: u32,
+
+    /// Whether [`Self::code`] is a Unicode scalar value.
+    pub unicode: bool,
+
+    /// The name of the input file that was being read when the character was
+    /// typeset, if it was being read from a file.
+    pub file: Option<String>,
+
+    /// The line of [`Self::file`] that was being read, if there was one.
+    pub line: Option<u32>,
+}
+
+/// The characters that were missing from one font during a run, as returned
+/// by [`TexEngine::missing_characters`].
+///
+/// [`TexEngine::missing_characters`]: crate::TexEngine::missing_characters
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingCharacters {
+    /// The name of the font, as TeX knows it.
+    pub font: String,
+
+    /// The missing characters, in the order in which they were typeset. A
+    /// character appears once for every time that it was typeset.
+    pub characters: Vec<MissingCharacter>,
+}
+
+/// Take the missing characters recorded by the last engine run, along with
+/// the total number that were found, which can be more than were recorded.
+/// This must be called while holding the global engine lock.
+pub(crate) fn take() -> (Vec<MissingCharacters>, usize) {
+    let mut fonts = Vec::new();
+
+    // SAFETY: the engine only calls `collect_missing_char` during this call,
+    // with the context pointer that we give it.
+    let total = unsafe {
+        c_api::tt_xetex_visit_missing_chars(
+            collect_missing_char,
+            &mut fonts as *mut Vec<MissingCharacters> as *mut _,
+        )
+    };
+
+    (fonts, total)
+}
+
+extern "C" fn collect_missing_char(
+    context: *mut libc::c_void,
+    font: *const libc::c_char,
+    file: *const libc::c_char,
+    line: i32,
+    code: i32,
+    unicode: libc::c_int,
+) {
+    // SAFETY: the context is the vector passed in by `take`, and the engine
+    // gives us valid C strings, or a null pointer if there's no file.
+    let (fonts, font, file) = unsafe {
+        (
+            &mut *(context as *mut Vec<MissingCharacters>),
+            CStr::from_ptr(font).to_string_lossy(),
+            (!file.is_null()).then(|| CStr::from_ptr(file).to_string_lossy().into_owned()),
+        )
+    };
+
+    let character = MissingCharacter {
+        code: code as u32,
+        unicode: unicode != 0,
+        line: file.as_ref().map(|_| line.max(0) as u32),
+        file,
+    };
+
+    match fonts.iter_mut().find(|m| m.font == font) {
+        Some(m) => m.characters.push(character),
+        None => fonts.push(MissingCharacters {
+            font: font.into_owned(),
+            characters: vec![character],
+        }),
+    }
+}
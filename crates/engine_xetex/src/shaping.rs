@@ -0,0 +1,226 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Saving the shaping cache to a file and loading it back.
+//!
+//! The file format is private to this module, and tied to the machine that
+//! wrote it: the glyph data are stored in the layout that the C code uses in
+//! memory. A byte-order mark and a caller-provided fingerprint guard against
+//! loading a file that doesn't match.
+
+use std::io::{Read, Write};
+use tectonic_errors::prelude::*;
+
+use crate::c_api;
+
+const MAGIC: &[u8; 8] = b"TTSHAPE2";
+const BYTE_ORDER_MARK: u32 = 0x0102_0304;
+
+/// One memoized shaping result, as stored by the layout library.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Entry {
+    key: Vec<u16>,
+    width: i32,
+    glyph_count: i32,
+    glyph_info: Vec<u8>,
+}
+
+/// Save the contents of the shaping cache. See [`crate::ShapingCache`].
+///
+/// The *fingerprint* should identify everything that affects shaping other
+/// than the fonts, sizes, and text that key the cache entries, such as
+/// the bundle and format file in use. [`load_shaping_cache`] ignores files
+/// saved with a different fingerprint. The entries are written in a fixed
+/// order, so that the same cache contents always give the same file.
+///
+/// Returns the number of entries that were saved.
+pub fn save_shaping_cache<W: Write>(fingerprint: &str, mut dest: W) -> Result<usize> {
+    let mut entries: Vec<Entry> = Vec::new();
+
+    // SAFETY: the layout library only calls `collect_entry` during this call,
+    // with the context pointer that we give it.
+    unsafe {
+        c_api::tt_xetex_visit_shaped_texts(collect_entry, &mut entries as *mut _ as *mut _);
+    }
+
+    atry!(
+        dest.write_all(&encode(fingerprint, &entries));
+        ["failed to write the shaping cache"]
+    );
+    Ok(entries.len())
+}
+
+/// Load a shaping cache saved by [`save_shaping_cache`], adding its entries
+/// to the one in memory.
+///
+/// To use the loaded entries, run the engine with
+/// [`crate::ShapingCache::Reuse`]. If the file was saved with a different
+/// *fingerprint*, or on a different kind of machine, it is ignored and `None`
+/// is returned. Otherwise, returns the number of entries that were loaded.
+pub fn load_shaping_cache<R: Read>(fingerprint: &str, mut src: R) -> Result<Option<usize>> {
+    let mut data = Vec::new();
+    atry!(
+        src.read_to_end(&mut data);
+        ["failed to read the shaping cache"]
+    );
+
+    let Some(entries) = decode(fingerprint, &data)? else {
+        return Ok(None);
+    };
+
+    for entry in &entries {
+        // SAFETY: the slices are valid for the lengths that we pass, and the
+        // C code checks the glyph info against the glyph count.
+        let bogus = unsafe {
+            c_api::tt_xetex_add_shaped_text(
+                entry.key.as_ptr(),
+                entry.key.len(),
+                entry.width,
+                entry.glyph_count,
+                entry.glyph_info.as_ptr() as *const _,
+                entry.glyph_info.len(),
+            )
+        };
+
+        ensure!(bogus == 0, "the shaping cache contains a corrupt entry");
+    }
+
+    Ok(Some(entries.len()))
+}
+
+extern "C" fn collect_entry(
+    context: *mut libc::c_void,
+    key: *const u16,
+    key_len: libc::size_t,
+    width: i32,
+    glyph_count: libc::c_int,
+    glyph_info: *const libc::c_void,
+    glyph_info_len: libc::size_t,
+) {
+    // SAFETY: the context is the vector passed in by `save_shaping_cache`,
+    // and the layout library gives us pointers that are valid for the stated
+    // lengths during the call.
+    let (entries, key, glyph_info) = unsafe {
+        (
+            &mut *(context as *mut Vec<Entry>),
+            std::slice::from_raw_parts(key, key_len),
+            if glyph_info_len == 0 {
+                &[][..]
+            } else {
+                std::slice::from_raw_parts(glyph_info as *const u8, glyph_info_len)
+            },
+        )
+    };
+
+    entries.push(Entry {
+        key: key.to_vec(),
+        width,
+        glyph_count,
+        glyph_info: glyph_info.to_vec(),
+    });
+}
+
+fn encode(fingerprint: &str, entries: &[Entry]) -> Vec<u8> {
+    let mut data = Vec::new();
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&BYTE_ORDER_MARK.to_ne_bytes());
+    data.extend_from_slice(&(fingerprint.len() as u32).to_ne_bytes());
+    data.extend_from_slice(fingerprint.as_bytes());
+    data.extend_from_slice(&(entries.len() as u64).to_ne_bytes());
+
+    for entry in entries {
+        data.extend_from_slice(&(entry.key.len() as u32).to_ne_bytes());
+
+        for unit in &entry.key {
+            data.extend_from_slice(&unit.to_ne_bytes());
+        }
+
+        data.extend_from_slice(&entry.width.to_ne_bytes());
+        data.extend_from_slice(&entry.glyph_count.to_ne_bytes());
+        data.extend_from_slice(&(entry.glyph_info.len() as u32).to_ne_bytes());
+        data.extend_from_slice(&entry.glyph_info);
+    }
+
+    data
+}
+
+fn decode(fingerprint: &str, mut data: &[u8]) -> Result<Option<Vec<Entry>>> {
+    fn take<'a>(data: &mut &'a [u8], n: usize) -> Result<&'a [u8]> {
+        ensure!(data.len() >= n, "the shaping cache is truncated");
+        let (head, tail) = data.split_at(n);
+        *data = tail;
+        Ok(head)
+    }
+
+    fn take_u32(data: &mut &[u8]) -> Result<u32> {
+        Ok(u32::from_ne_bytes(take(data, 4)?.try_into().unwrap()))
+    }
+
+    if data.len() < 12
+        || &data[..8] != MAGIC
+        || u32::from_ne_bytes(data[8..12].try_into().unwrap()) != BYTE_ORDER_MARK
+    {
+        return Ok(None);
+    }
+
+    data = &data[12..];
+    let n = take_u32(&mut data)? as usize;
+
+    if take(&mut data, n)? != fingerprint.as_bytes() {
+        return Ok(None);
+    }
+
+    let count = u64::from_ne_bytes(take(&mut data, 8)?.try_into().unwrap());
+    let mut entries = Vec::new();
+
+    for _ in 0..count {
+        let key_len = take_u32(&mut data)? as usize;
+        let key = take(&mut data, 2 * key_len)?
+            .chunks_exact(2)
+            .map(|c| u16::from_ne_bytes([c[0], c[1]]))
+            .collect();
+        let width = take_u32(&mut data)? as i32;
+        let glyph_count = take_u32(&mut data)? as i32;
+        let info_len = take_u32(&mut data)? as usize;
+        let glyph_info = take(&mut data, info_len)?.to_vec();
+
+        entries.push(Entry {
+            key,
+            width,
+            glyph_count,
+            glyph_info,
+        });
+    }
+
+    ensure!(data.is_empty(), "the shaping cache has trailing data");
+    Ok(Some(entries))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let entries = vec![
+            Entry {
+                key: vec![0, 12, 3, 0x61, 0x62, 0x63, 0x4E2D],
+                width: 65536 * 17,
+                glyph_count: 1,
+                glyph_info: vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10],
+            },
+            Entry {
+                key: vec![0, 12, 3, 0x61, 0x62, 0x63],
+                width: 0,
+                glyph_count: 0,
+                glyph_info: vec![],
+            },
+        ];
+
+        let data = encode("bundle:format", &entries);
+        assert_eq!(decode("bundle:format", &data).unwrap(), Some(entries));
+        assert_eq!(decode("other", &data).unwrap(), None);
+        assert_eq!(decode("bundle:format", b"not a cache").unwrap(), None);
+        assert!(decode("bundle:format", &data[..data.len() - 1]).is_err());
+    }
+}
@@ -60,6 +60,25 @@ get_ot_math_constant(int f, int n)
                 rval = D2Fix(ttxl_font_units_to_points(font, rval));
                 break;
         }
+
+        /* Tectonic: apply any overrides configured by the driver, so that
+         * users can work around fonts with problematic parameters without
+         * patching them. The axis adjustment is in thousandths of an em. */
+        switch (constant) {
+            case HB_OT_MATH_CONSTANT_SCRIPT_PERCENT_SCALE_DOWN:
+                if (math_script_percent_scale_down_override > 0)
+                    rval = math_script_percent_scale_down_override;
+                break;
+            case HB_OT_MATH_CONSTANT_SCRIPT_SCRIPT_PERCENT_SCALE_DOWN:
+                if (math_script_script_percent_scale_down_override > 0)
+                    rval = math_script_script_percent_scale_down_override;
+                break;
+            case HB_OT_MATH_CONSTANT_AXIS_HEIGHT:
+                rval += (int64_t) font_size[f] * math_axis_height_adjust / 1000;
+                break;
+            default:
+                break;
+        }
     }
 
     return rval;
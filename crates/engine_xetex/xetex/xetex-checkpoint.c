@@ -0,0 +1,1037 @@
+/* tectonic/xetex-checkpoint.c -- saving and restoring the engine mid-document
+   Copyright 2026 The Tectonic Project
+   Licensed under the MIT License.
+*/
+
+#include "xetex-core.h"
+#include "xetex-xetexd.h"
+#include "xetex_bindings.h" /* FORMAT_SERIAL */
+#include "tectonic_bridge_core.h"
+
+#include <stdio.h>
+#include <string.h>
+
+/* Engine checkpoints. This is experimental support for re-typesetting just
+ * the tail of a document after it has been edited: a run can be asked to
+ * take a checkpoint once it has shipped out a given number of pages, and a
+ * later run can resume from it instead of starting from the top.
+ *
+ * A checkpoint is taken in main_control(), at the top of its loop, where
+ * the engine isn't in the middle of anything that only lives on the C
+ * stack. The state that matters is then all in globals: the memory array,
+ * eqtb, the string pool, the font arrays, the various stacks, and a long
+ * tail of scalars. checkpoint_engine_state() and its counterparts in the
+ * page builder and shipout code transfer these with CHECKPOINT_VAR() and
+ * CHECKPOINT_ARRAY(), which write them out when a checkpoint is being taken
+ * and read them back in when it's being restored, so that the two
+ * directions can't get out of step.
+ *
+ * Some state isn't plain data:
+ *
+ * - Native word nodes point to malloc'd glyph information. The nodes that
+ *   have some are tracked in a bitmap as they're created and freed, and the
+ *   information is saved separately.
+ *
+ * - Native fonts are reloaded by name, since their layout engines can't be
+ *   saved. TECkit mappings for native fonts come back with them, but those
+ *   of TFM fonts can't be recreated, so a document that uses one can't be
+ *   checkpointed.
+ *
+ * - Input files are recorded as they're opened, along with how much of each
+ *   was read. When the checkpoint is taken, each record gets a digest of
+ *   that much of the file, or of all of it if it was read to the end, and
+ *   files that weren't found are noted too. Before resuming, everything is
+ *   checked against the current inputs, and if anything that the engine had
+ *   already read has changed, the run starts from the beginning as usual.
+ *   The files that were open are reopened and skipped forward to where the
+ *   engine had got to. Files read through ICU converters can't be skipped
+ *   forward reliably, so the checkpoint is put off until none are open.
+ *
+ * - Output files, namely the log, the DVI/XDV file, and \openout files, are
+ *   recorded byte for byte from the start of the run. On resuming, they're
+ *   rewritten as they were when the checkpoint was taken, and those that
+ *   were still open are left open for the engine to carry on writing.
+ *
+ * SyncTeX and trace capture keep state that isn't saved, so checkpoints
+ * can't be taken or resumed while they're in use, and neither can they be
+ * while generating a format. A checkpoint is only good for the build of the
+ * engine and the format that it was taken with; it's in native byte order
+ * and holds pointers into the engine's arrays. */
+
+#define MAGIC "TTCKPT01"
+#define HEADER_SIZE 16 /* the magic and the digest */
+#define N_STREAMS 18
+#define READ_CHUNK 16384
+
+#define FNV_OFFSET 0xcbf29ce484222325ULL
+#define FNV_PRIME 0x100000001b3ULL
+
+typedef struct {
+    char *name;
+    int32_t format;
+    bool found;
+    bool whole; /* read to the end at some point */
+    uint64_t prefix; /* how much has been read, at the most */
+} input_record;
+
+typedef struct {
+    char *name;
+    int stream; /* or -1 once closed */
+    char *data;
+    size_t len;
+    size_t alloc;
+} output_record;
+
+typedef struct {
+    UFILE *file;
+    bool read_stream; /* a \read stream, rather than an input level */
+    int32_t slot;
+    uint64_t offset;
+} open_file;
+
+static bool recording;
+static bool restoring;
+static bool resumed;
+
+static const unsigned char *resume_data;
+static size_t resume_len;
+
+static unsigned char *out_buf;
+static size_t out_len;
+static size_t out_alloc;
+
+static const unsigned char *in_data;
+static size_t in_len;
+static size_t in_pos;
+static bool in_short;
+
+static unsigned char *taken;
+static size_t taken_len;
+static int32_t taken_pages;
+
+static input_record *inputs;
+static size_t n_inputs;
+static size_t inputs_alloc;
+
+static output_record *outputs;
+static size_t n_outputs;
+static size_t outputs_alloc;
+
+static int stream_output[N_STREAMS];
+static rust_output_handle_t stream_handle[N_STREAMS];
+
+static unsigned char *native_nodes;
+
+
+static uint64_t
+fnv(uint64_t h, const void *data, size_t len)
+{
+    const unsigned char *p = data;
+
+    while (len-- > 0) {
+        h ^= *p++;
+        h *= FNV_PRIME;
+    }
+
+    return h;
+}
+
+
+/* Serialization. */
+
+static void
+put(const void *data, size_t len)
+{
+    if (out_len + len > out_alloc) {
+        out_alloc = (out_len + len) * 2;
+        out_buf = xrealloc(out_buf, out_alloc);
+    }
+
+    memcpy(out_buf + out_len, data, len);
+    out_len += len;
+}
+
+static void
+put_i32(int32_t v)
+{
+    put(&v, sizeof(v));
+}
+
+static void
+put_u64(uint64_t v)
+{
+    put(&v, sizeof(v));
+}
+
+static void
+put_str(const char *s)
+{
+    put_u64(strlen(s));
+    put(s, strlen(s));
+}
+
+/* Reading past the end of the data yields zeros and marks it as short, which
+ * the callers check once they're done. */
+static const unsigned char *
+get(void *data, size_t len)
+{
+    const unsigned char *p;
+
+    if (in_short || len > in_len - in_pos) {
+        in_short = true;
+        if (data != NULL)
+            memset(data, 0, len);
+        return NULL;
+    }
+
+    p = in_data + in_pos;
+    if (data != NULL)
+        memcpy(data, p, len);
+    in_pos += len;
+    return p;
+}
+
+static int32_t
+get_i32(void)
+{
+    int32_t v;
+
+    get(&v, sizeof(v));
+    return v;
+}
+
+static uint64_t
+get_u64(void)
+{
+    uint64_t v;
+
+    get(&v, sizeof(v));
+    return v;
+}
+
+static char *
+get_str(void)
+{
+    uint64_t len = get_u64();
+    const unsigned char *p = get(NULL, len);
+    char *s = xmalloc(p == NULL ? 1 : len + 1);
+
+    if (p == NULL)
+        len = 0;
+
+    memcpy(s, p == NULL ? (const unsigned char *) "" : p, len);
+    s[len] = '\0';
+    return s;
+}
+
+void
+checkpoint_transfer(void *data, size_t len)
+{
+    if (restoring)
+        get(data, len);
+    else
+        put(data, len);
+}
+
+
+/* Compatibility. The array sizes have to match for the saved state to fit,
+ * and the format has to be the same one for it to mean anything. */
+
+#define N_DIMENSIONS 16
+
+static void
+get_dimensions(int32_t *dims)
+{
+    dims[0] = MEM_TOP;
+    dims[1] = eqtb_top;
+    dims[2] = hash_top;
+    dims[3] = pool_size;
+    dims[4] = max_strings;
+    dims[5] = font_max;
+    dims[6] = font_mem_size;
+    dims[7] = hyph_size;
+    dims[8] = buf_size;
+    dims[9] = nest_size;
+    dims[10] = save_size;
+    dims[11] = stack_size;
+    dims[12] = max_in_open;
+    dims[13] = param_size;
+    dims[14] = semantic_pagination_enabled;
+    dims[15] = sizeof(memory_word);
+}
+
+static uint64_t
+format_digest(void)
+{
+    uint64_t h = fnv(FNV_OFFSET, str_pool, (size_t) init_pool_ptr * sizeof(str_pool[0]));
+
+    h = fnv(h, &init_str_ptr, sizeof(init_str_ptr));
+    return fnv(h, &trie_max, sizeof(trie_max));
+}
+
+static const char *
+unavailable_reason(void)
+{
+    if (in_initex_mode)
+        return "a format is being generated";
+    if (synctex_enabled)
+        return "SyncTeX is enabled";
+    if (trace_capture_flags != 0)
+        return "tracing output is being captured";
+    return NULL;
+}
+
+
+/* Recording inputs and outputs. */
+
+static input_record *
+find_input(const char *name, int32_t format)
+{
+    size_t i;
+
+    for (i = 0; i < n_inputs; i++) {
+        if (inputs[i].format == format && strcmp(inputs[i].name, name) == 0)
+            return &inputs[i];
+    }
+
+    if (n_inputs == inputs_alloc) {
+        inputs_alloc = inputs_alloc == 0 ? 16 : inputs_alloc * 2;
+        inputs = xrealloc(inputs, inputs_alloc * sizeof(inputs[0]));
+    }
+
+    inputs[n_inputs].name = xstrdup(name);
+    inputs[n_inputs].format = format;
+    inputs[n_inputs].found = false;
+    inputs[n_inputs].whole = false;
+    inputs[n_inputs].prefix = 0;
+    return &inputs[n_inputs++];
+}
+
+static output_record *
+add_output(const char *name, int stream)
+{
+    output_record *rec;
+
+    if (n_outputs == outputs_alloc) {
+        outputs_alloc = outputs_alloc == 0 ? 8 : outputs_alloc * 2;
+        outputs = xrealloc(outputs, outputs_alloc * sizeof(outputs[0]));
+    }
+
+    if (stream >= 0) {
+        if (stream_output[stream] >= 0)
+            outputs[stream_output[stream]].stream = -1;
+        stream_output[stream] = n_outputs;
+    }
+
+    rec = &outputs[n_outputs++];
+    rec->name = xstrdup(name);
+    rec->stream = stream;
+    rec->data = NULL;
+    rec->len = 0;
+    rec->alloc = 0;
+    return rec;
+}
+
+static void
+append_output(output_record *rec, const void *data, size_t len)
+{
+    if (rec->len + len > rec->alloc) {
+        rec->alloc = (rec->len + len) * 2;
+        rec->data = xrealloc(rec->data, rec->alloc);
+    }
+
+    memcpy(rec->data + rec->len, data, len);
+    rec->len += len;
+}
+
+static void
+free_recorder(void)
+{
+    size_t i;
+    int k;
+
+    for (i = 0; i < n_inputs; i++)
+        free(inputs[i].name);
+
+    for (i = 0; i < n_outputs; i++) {
+        free(outputs[i].name);
+        free(outputs[i].data);
+    }
+
+    inputs = mfree(inputs);
+    n_inputs = inputs_alloc = 0;
+    outputs = mfree(outputs);
+    n_outputs = outputs_alloc = 0;
+    native_nodes = mfree(native_nodes);
+
+    for (k = 0; k < N_STREAMS; k++)
+        stream_output[k] = -1;
+
+    recording = false;
+}
+
+/* Stop recording for good, if the run can't be checkpointed. */
+static void
+disarm(const char *reason)
+{
+    ttstub_issue_warning("cannot take an engine checkpoint, since %s", reason);
+    checkpoint_after_page = 0;
+    free_recorder();
+}
+
+void
+checkpoint_note_input(const char *name, int32_t format, bool found, bool whole)
+{
+    input_record *rec;
+
+    if (!recording || name == NULL)
+        return;
+
+    rec = find_input(name, format);
+    rec->found |= found;
+    rec->whole |= found && whole;
+}
+
+/* A file that was opened with u_open_in() is being closed, so note how much
+ * of it was read. */
+void
+checkpoint_note_input_closed(UFILE *f)
+{
+    input_record *rec;
+    size_t pos;
+
+    if (!recording || f->name == NULL)
+        return;
+
+    rec = find_input(f->name, f->format);
+    pos = ttstub_input_seek(f->handle, 0, SEEK_CUR);
+
+    if (pos >= ttstub_input_get_size(f->handle))
+        rec->whole = true;
+    else if (pos > rec->prefix)
+        rec->prefix = pos;
+}
+
+void
+checkpoint_note_output_open(int stream, const char *name)
+{
+    if (recording)
+        add_output(name, stream);
+}
+
+void
+checkpoint_note_output(int stream, const void *data, size_t len)
+{
+    if (recording && stream_output[stream] >= 0)
+        append_output(&outputs[stream_output[stream]], data, len);
+}
+
+void
+checkpoint_note_output_close(int stream)
+{
+    if (!recording || stream_output[stream] < 0)
+        return;
+
+    outputs[stream_output[stream]].stream = -1;
+    stream_output[stream] = -1;
+}
+
+rust_output_handle_t
+checkpoint_output_handle(int stream)
+{
+    return stream_handle[stream];
+}
+
+void
+checkpoint_track_native_node(int32_t p)
+{
+    if (native_nodes != NULL)
+        native_nodes[p >> 3] |= 1 << (p & 7);
+}
+
+void
+checkpoint_forget_node(int32_t p)
+{
+    if (native_nodes != NULL)
+        native_nodes[p >> 3] &= ~(1 << (p & 7));
+}
+
+
+/* Setting up and tearing down. */
+
+void
+checkpoint_set_resume_data(const unsigned char *data, size_t len)
+{
+    resume_data = data;
+    resume_len = len;
+}
+
+/* Reset the checkpoint state at the start of a run, once the format has been
+ * loaded. */
+void
+checkpoint_init(void)
+{
+    const char *reason;
+    int k;
+
+    free_recorder();
+    taken = mfree(taken);
+    taken_len = 0;
+    resumed = false;
+    restoring = false;
+
+    for (k = 0; k < N_STREAMS; k++)
+        stream_handle[k] = INVALID_HANDLE;
+
+    if (checkpoint_after_page <= 0)
+        return;
+
+    reason = unavailable_reason();
+    if (reason != NULL) {
+        disarm(reason);
+        return;
+    }
+
+    native_nodes = xcalloc(MEM_TOP / 8 + 1, 1);
+    recording = true;
+}
+
+void
+checkpoint_cleanup(void)
+{
+    free_recorder();
+}
+
+bool
+checkpoint_resumed(void)
+{
+    return resumed;
+}
+
+bool
+checkpoint_restoring(void)
+{
+    return restoring;
+}
+
+/* Hand over the checkpoint taken by the last run, if it took one. */
+int
+checkpoint_visit(CheckpointVisitor visitor, void *context)
+{
+    if (taken == NULL)
+        return 0;
+
+    visitor(context, taken, taken_len, taken_pages);
+    taken = mfree(taken);
+    taken_len = 0;
+    return 1;
+}
+
+
+/* Taking checkpoints. */
+
+/* The files that the engine has open: those of the input levels, which are
+ * told apart from terminal, \read, and pseudo-file levels by their names,
+ * and those of the \read streams. */
+static open_file *
+collect_open_files(size_t *n)
+{
+    open_file *open = xmalloc((max_in_open + 16 + 1) * sizeof(open[0]));
+    int32_t k;
+
+    *n = 0;
+
+    for (k = 0; k <= input_ptr; k++) {
+        input_state_t *level = k == input_ptr ? &cur_input : &input_stack[k];
+
+        if (level->state == TOKEN_LIST || level->name <= 19)
+            continue;
+
+        open[*n].file = input_file[level->index];
+        open[*n].read_stream = false;
+        open[*n].slot = level->index;
+        (*n)++;
+    }
+
+    for (k = 0; k < 16; k++) {
+        if (read_open[k] == CLOSED)
+            continue;
+
+        open[*n].file = read_file[k];
+        open[*n].read_stream = true;
+        open[*n].slot = k;
+        (*n)++;
+    }
+
+    return open;
+}
+
+/* Digest the first *len* bytes of a record's file, or all of it if the
+ * record is for the whole file, in which case *len* is set to its size. The
+ * size is returned, or -1 if the file can't be read. */
+static int64_t
+digest_input(const input_record *rec, uint64_t *len, uint64_t *digest)
+{
+    static char chunk[READ_CHUNK];
+    rust_input_handle_t handle;
+    uint64_t left;
+    int64_t size;
+
+    if (rec->format == TTBC_FILE_FORMAT_TECTONIC_PRIMARY)
+        handle = ttstub_input_open_primary();
+    else
+        handle = ttstub_input_open(rec->name, (ttbc_file_format) rec->format, 0);
+
+    if (handle == INVALID_HANDLE)
+        return -1;
+
+    size = ttstub_input_get_size(handle);
+    if (rec->whole)
+        *len = size;
+
+    *digest = FNV_OFFSET;
+
+    for (left = *len; left > 0; ) {
+        ssize_t got = ttstub_input_read_partial(handle, chunk, left < READ_CHUNK ? left : READ_CHUNK);
+
+        if (got <= 0) {
+            size = -1;
+            break;
+        }
+
+        *digest = fnv(*digest, chunk, got);
+        left -= got;
+    }
+
+    ttstub_input_close(handle);
+    return size;
+}
+
+void
+take_checkpoint(void)
+{
+    int32_t dims[N_DIMENSIONS];
+    open_file *open;
+    size_t n_open, i;
+    int32_t f, p, count;
+    uint64_t digest;
+
+    for (f = FONT_BASE + 1; f <= font_ptr; f++) {
+        if (font_mapping[f] != NULL && font_area[f] != AAT_FONT_FLAG && font_area[f] != OTGR_FONT_FLAG) {
+            disarm("a TFM font with a TECkit mapping is in use");
+            return;
+        }
+    }
+
+    open = collect_open_files(&n_open);
+
+    for (i = 0; i < n_open; i++) {
+        if (open[i].file->name == NULL) {
+            free(open);
+            disarm("an input file can't be reopened");
+            return;
+        }
+
+        if (open[i].file->encodingMode == ICUMAPPING) {
+            /* Try again once it's been closed. */
+            free(open);
+            return;
+        }
+    }
+
+    for (i = 0; i < n_open; i++) {
+        UFILE *u = open[i].file;
+        input_record *rec = find_input(u->name, u->format);
+
+        open[i].offset = ttstub_input_seek(u->handle, 0, SEEK_CUR);
+        rec->found = true;
+        if (open[i].offset > rec->prefix)
+            rec->prefix = open[i].offset;
+    }
+
+    out_len = 0;
+    put(MAGIC, 8);
+    put_u64(0); /* the digest, filled in below */
+    put_i32(FORMAT_SERIAL);
+    get_dimensions(dims);
+    put(dims, sizeof(dims));
+    put_u64(format_digest());
+    put_i32(total_pages);
+
+    put_u64(n_inputs);
+
+    for (i = 0; i < n_inputs; i++) {
+        input_record *rec = &inputs[i];
+        uint64_t len = rec->prefix;
+        int64_t size = -1;
+
+        digest = 0;
+        if (rec->found)
+            size = digest_input(rec, &len, &digest);
+
+        put_str(rec->name);
+        put_i32(rec->format);
+        put(&rec->found, sizeof(rec->found));
+        put(&rec->whole, sizeof(rec->whole));
+        put_u64(len);
+        put_u64(size);
+        put_u64(digest);
+    }
+
+    put_u64(n_open);
+
+    for (i = 0; i < n_open; i++) {
+        UFILE *u = open[i].file;
+
+        put(&open[i].read_stream, sizeof(open[i].read_stream));
+        put_i32(open[i].slot);
+        put_str(u->name);
+        put_i32(u->format);
+        put_u64(open[i].offset);
+        put(&u->encodingMode, sizeof(u->encodingMode));
+        put(&u->savedChar, sizeof(u->savedChar));
+        put(&u->skipNextLF, sizeof(u->skipNextLF));
+    }
+
+    put_u64(n_outputs);
+
+    for (i = 0; i < n_outputs; i++) {
+        put_str(outputs[i].name);
+        put_i32(outputs[i].stream);
+        put_u64(outputs[i].len);
+        put(outputs[i].data, outputs[i].len);
+    }
+
+    checkpoint_engine_state();
+    pagebuilder_checkpoint_state();
+    shipout_checkpoint_state();
+
+    for (p = 0; p <= MEM_TOP; p++) {
+        if (!(native_nodes[p >> 3] & (1 << (p & 7))))
+            continue;
+
+        count = NATIVE_NODE_glyph_info_ptr(p) == NULL ? 0 : NATIVE_NODE_glyph_count(p);
+        put_i32(p);
+        put_i32(count);
+        if (count > 0)
+            put(NATIVE_NODE_glyph_info_ptr(p), (size_t) count * NATIVE_GLYPH_INFO_SIZE);
+    }
+
+    put_i32(-1);
+
+    digest = fnv(FNV_OFFSET, out_buf + HEADER_SIZE, out_len - HEADER_SIZE);
+    memcpy(out_buf + 8, &digest, sizeof(digest));
+
+    taken = out_buf;
+    taken_len = out_len;
+    taken_pages = total_pages;
+    out_buf = NULL;
+    out_len = out_alloc = 0;
+
+    free(open);
+    checkpoint_after_page = 0;
+    free_recorder();
+}
+
+
+/* Resuming from checkpoints. */
+
+/* Check an input record against the file as it is now. */
+static bool
+input_unchanged(const input_record *rec, uint64_t len, int64_t size, uint64_t digest)
+{
+    rust_input_handle_t handle;
+    uint64_t now_len = len;
+    uint64_t now_digest;
+    int64_t now_size;
+
+    if (!rec->found) {
+        handle = ttstub_input_open(rec->name, (ttbc_file_format) rec->format, 0);
+        if (handle == INVALID_HANDLE)
+            return true;
+        ttstub_input_close(handle);
+        return false;
+    }
+
+    now_size = digest_input(rec, &now_len, &now_digest);
+    if (now_size < 0 || size < 0)
+        return false;
+    if (rec->whole && now_size != size)
+        return false;
+    return now_len == len && now_digest == digest;
+}
+
+/* Open a file and skip to where the engine had got to in it. */
+static UFILE *
+reopen_input(const char *name, int32_t format, short mode, uint64_t offset)
+{
+    static char chunk[READ_CHUNK];
+    UFILE *f;
+
+    free(name_of_file);
+    name_of_file = xstrdup(name);
+    name_length = strlen(name);
+
+    if (!u_open_in(&f, format, "rb", mode, 0))
+        return NULL;
+
+    while (offset > 0) {
+        ssize_t got = ttstub_input_read_partial(f->handle, chunk, offset < READ_CHUNK ? offset : READ_CHUNK);
+
+        if (got <= 0) {
+            u_close(f);
+            return NULL;
+        }
+
+        offset -= got;
+    }
+
+    return f;
+}
+
+static void
+reload_native_fonts(void)
+{
+    selector_t saved_selector = selector;
+    int32_t f;
+
+    selector = SELECTOR_NO_PRINT;
+
+    for (f = FONT_BASE + 1; f <= font_ptr; f++) {
+        char *name;
+
+        if (font_area[f] != AAT_FONT_FLAG && font_area[f] != OTGR_FONT_FLAG)
+            continue;
+
+        name = gettexstring(font_name[f]);
+        font_layout_engine[f] = find_native_font(name, font_size[f]);
+        if (font_layout_engine[f] == NULL)
+            _tt_abort("cannot reload the font \"%s\" for the engine checkpoint", name);
+
+        font_mapping[f] = loaded_font_mapping;
+        free(name);
+    }
+
+    selector = saved_selector;
+}
+
+/* Start the run from the checkpoint given by the driver, if there is one and
+ * it's still good. Returns false if the run should start from the beginning
+ * as usual, in which case nothing has been changed. */
+bool
+checkpoint_resume(void)
+{
+    int32_t dims[N_DIMENSIONS], saved_dims[N_DIMENSIONS];
+    open_file *open = NULL;
+    size_t n_open = 0, i, n;
+    const char *reason;
+    uint64_t digest;
+    int32_t p;
+    int k;
+
+    if (resume_data == NULL)
+        return false;
+
+    reason = unavailable_reason();
+    if (reason != NULL) {
+        ttstub_issue_warning("cannot resume from the engine checkpoint, since %s", reason);
+        return false;
+    }
+
+    in_data = resume_data;
+    in_len = resume_len;
+    in_pos = HEADER_SIZE;
+    in_short = false;
+
+    if (in_len < HEADER_SIZE || memcmp(in_data, MAGIC, 8) != 0)
+        goto corrupt;
+
+    memcpy(&digest, in_data + 8, sizeof(digest));
+    if (digest != fnv(FNV_OFFSET, in_data + HEADER_SIZE, in_len - HEADER_SIZE))
+        goto corrupt;
+
+    get_dimensions(dims);
+    k = get_i32();
+    get(saved_dims, sizeof(saved_dims));
+
+    if (k != FORMAT_SERIAL || memcmp(dims, saved_dims, sizeof(dims)) != 0 || get_u64() != format_digest()) {
+        ttstub_issue_warning("the engine checkpoint was taken with a different engine or format; starting from the beginning");
+        return false;
+    }
+
+    get_i32(); /* the page count */
+
+    /* Check that the inputs that had been read are the same. The records
+     * become those of this run, so that a new checkpoint covers them too. */
+
+    n = get_u64();
+
+    for (i = 0; i < n && !in_short; i++) {
+        char *name = get_str();
+        int32_t format = get_i32();
+        input_record *rec = find_input(name, format);
+        uint64_t len, saved_digest;
+        int64_t size;
+
+        free(name);
+        get(&rec->found, sizeof(rec->found));
+        get(&rec->whole, sizeof(rec->whole));
+        len = get_u64();
+        size = get_u64();
+        saved_digest = get_u64();
+        rec->prefix = len;
+
+        if (!in_short && !input_unchanged(rec, len, size, saved_digest))
+            goto stale;
+    }
+
+    /* Reopen the files that were open. */
+
+    n = get_u64();
+    if (in_short || n > (size_t) max_in_open + 16)
+        goto corrupt;
+
+    open = xmalloc((n + 1) * sizeof(open[0]));
+
+    for (n_open = 0; n_open < n; n_open++) {
+        char *name;
+        int32_t format;
+        short mode;
+        UFILE *f;
+
+        get(&open[n_open].read_stream, sizeof(open[n_open].read_stream));
+        open[n_open].slot = get_i32();
+        name = get_str();
+        format = get_i32();
+        open[n_open].offset = get_u64();
+        get(&mode, sizeof(mode));
+
+        f = in_short ? NULL : reopen_input(name, format, mode, open[n_open].offset);
+        free(name);
+        if (f == NULL)
+            goto stale;
+
+        open[n_open].file = f;
+        get(&f->savedChar, sizeof(f->savedChar));
+        get(&f->skipNextLF, sizeof(f->skipNextLF));
+
+        if (open[n_open].slot < 0 || open[n_open].slot > (open[n_open].read_stream ? 15 : max_in_open)) {
+            n_open++;
+            goto corrupt;
+        }
+    }
+
+    /* From here on, there's no going back. Rewrite the outputs. */
+
+    n = get_u64();
+
+    for (i = 0; i < n && !in_short; i++) {
+        char *name = get_str();
+        int stream = get_i32();
+        uint64_t len = get_u64();
+        const unsigned char *data = get(NULL, len);
+        rust_output_handle_t handle;
+
+        if (data == NULL || stream < -1 || stream >= N_STREAMS) {
+            free(name);
+            in_short = true;
+            break;
+        }
+
+        handle = ttstub_output_open(name, 0);
+        if (handle == INVALID_HANDLE)
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "cannot open output file \"%s\"", name);
+
+        ttstub_output_write(handle, (const char *) data, len);
+
+        if (stream >= 0)
+            stream_handle[stream] = handle;
+        else
+            ttstub_output_close(handle);
+
+        if (recording)
+            append_output(add_output(name, stream), data, len);
+
+        free(name);
+    }
+
+    restoring = true;
+    checkpoint_engine_state();
+    pagebuilder_checkpoint_state();
+    shipout_checkpoint_state();
+    restoring = false;
+
+    for (p = get_i32(); p >= 0 && p <= MEM_TOP && !in_short; p = get_i32()) {
+        int32_t count = get_i32();
+        void *info = NULL;
+
+        if (count > 0 && (size_t) count * NATIVE_GLYPH_INFO_SIZE <= in_len - in_pos) {
+            info = xmalloc((size_t) count * NATIVE_GLYPH_INFO_SIZE);
+            get(info, (size_t) count * NATIVE_GLYPH_INFO_SIZE);
+        } else if (count != 0) {
+            in_short = true;
+        }
+
+        NATIVE_NODE_glyph_info_ptr(p) = info;
+        checkpoint_track_native_node(p);
+    }
+
+    if (in_short)
+        _tt_abort("the engine checkpoint is corrupt");
+
+    for (k = 1; k <= max_in_open; k++)
+        input_file[k] = NULL;
+
+    for (i = 0; i < n_open; i++) {
+        if (open[i].read_stream)
+            read_file[open[i].slot] = open[i].file;
+        else
+            input_file[open[i].slot] = open[i].file;
+    }
+
+    free(open);
+
+    if (log_opened) {
+        log_file = stream_handle[CHECKPOINT_LOG_STREAM];
+        if (log_file == INVALID_HANDLE)
+            _tt_abort("the engine checkpoint is corrupt");
+    }
+
+    for (k = 0; k < 16; k++) {
+        if (write_open[k])
+            write_file[k] = stream_handle[k];
+    }
+
+    if (!recording)
+        free_recorder();
+
+    reload_native_fonts();
+    term_offset = 0;
+    resumed = true;
+    return true;
+
+corrupt:
+    ttstub_issue_warning("the engine checkpoint is corrupt; starting from the beginning");
+    goto fail;
+
+stale:
+    ttstub_issue_warning("the inputs have changed since the engine checkpoint was taken; starting from the beginning");
+
+fail:
+    for (i = 0; i < n_open; i++)
+        u_close(open[i].file);
+
+    free(open);
+
+    /* Forget the records made from the checkpoint, but carry on recording
+     * if this run is to take a checkpoint of its own. */
+    if (recording) {
+        free_recorder();
+        native_nodes = xcalloc(MEM_TOP / 8 + 1, 1);
+        recording = true;
+    } else {
+        free_recorder();
+    }
+
+    return false;
+}
@@ -11,6 +11,15 @@
 
 int tt_xetex_set_int_variable (const char *var_name, int value);
 int tt_xetex_set_string_variable (const char *var_name, const char *value);
+void tt_xetex_get_shaping_cache_stats (uint64_t *hits, uint64_t *misses, uint64_t *evictions);
+void tt_xetex_set_shaping_cache_limit (size_t limit);
+int tt_xetex_add_shaped_text (const uint16_t *key, size_t key_len, int32_t width, int glyph_count,
+                              const void *glyph_info, size_t glyph_info_len);
+size_t tt_xetex_visit_shaped_texts (ShapedTextVisitor visitor, void *context);
+void tt_xetex_set_resume_checkpoint (const unsigned char *data, size_t len);
+int tt_xetex_take_checkpoint (CheckpointVisitor visitor, void *context);
+int tt_xetex_resumed_from_checkpoint (void);
+size_t tt_xetex_visit_missing_chars (MissingCharVisitor visitor, void *context);
 int tt_engine_xetex_main(
     ttbc_state_t *api,
     const char *dump_name,
@@ -34,6 +43,30 @@ tt_xetex_set_int_variable (const char *var_name, int value)
         semantic_pagination_enabled = (value != 0);
     else if (streq_ptr(var_name, "shell_escape_enabled"))
         shell_escape_enabled = (value != 0);
+    else if (streq_ptr(var_name, "math_script_percent_scale_down_override"))
+        math_script_percent_scale_down_override = value;
+    else if (streq_ptr(var_name, "math_script_script_percent_scale_down_override"))
+        math_script_script_percent_scale_down_override = value;
+    else if (streq_ptr(var_name, "math_axis_height_adjust"))
+        math_axis_height_adjust = value;
+    else if (streq_ptr(var_name, "shaping_cache_mode"))
+        shaping_cache_mode = value;
+    else if (streq_ptr(var_name, "trace_capture_flags"))
+        trace_capture_flags = value;
+    else if (streq_ptr(var_name, "trace_capture_first_page"))
+        trace_capture_first_page = value;
+    else if (streq_ptr(var_name, "trace_capture_last_page"))
+        trace_capture_last_page = value;
+    else if (streq_ptr(var_name, "trace_capture_first_line"))
+        trace_capture_first_line = value;
+    else if (streq_ptr(var_name, "trace_capture_last_line"))
+        trace_capture_last_line = value;
+    else if (streq_ptr(var_name, "trace_capture_max_bytes"))
+        trace_capture_max_bytes = value;
+    else if (streq_ptr(var_name, "trace_capture_compress"))
+        trace_capture_compress = (value != 0);
+    else if (streq_ptr(var_name, "checkpoint_after_page"))
+        checkpoint_after_page = value;
     else
         return 1; /* Uh oh: unrecognized variable */
 
@@ -48,6 +81,90 @@ tt_xetex_set_string_variable (const char *var_name, const char *value)
     return 1;
 }
 
+
+void
+tt_xetex_get_shaping_cache_stats (uint64_t *hits, uint64_t *misses, uint64_t *evictions)
+{
+    *hits = shaping_cache_hits;
+    *misses = shaping_cache_misses;
+    *evictions = shaping_cache_evictions;
+}
+
+
+/* The limit on the memory taken by the shaping cache, in bytes. The cache is
+ * global, so the limit applies to all later runs; entries beyond it are
+ * evicted right away, before the run's statistics are reset. */
+
+void
+tt_xetex_set_shaping_cache_limit (size_t limit)
+{
+    setShapedTextCacheLimit(limit);
+}
+
+
+/* These two are used to save the shaping cache to a file and to load it
+ * back. They don't need the global engine lock, since the cache has its own.
+ * Entries are checked against the glyph info layout here, since that's where
+ * it's known; returns nonzero if the entry is bogus. */
+
+int
+tt_xetex_add_shaped_text (const uint16_t *key, size_t key_len, int32_t width, int glyph_count,
+                          const void *glyph_info, size_t glyph_info_len)
+{
+    if (key_len < 3 || glyph_count < 0 || glyph_count > 0xFFFF)
+        return 1;
+
+    if (glyph_info_len != (size_t) glyph_count * native_glyph_info_size)
+        return 1;
+
+    cacheShapedText(key, key_len, width, glyph_count, glyph_info, glyph_info_len);
+    return 0;
+}
+
+
+size_t
+tt_xetex_visit_shaped_texts (ShapedTextVisitor visitor, void *context)
+{
+    return visitShapedTextCache(visitor, context);
+}
+
+
+/* Engine checkpoints; see xetex-checkpoint.c. The data to resume from must
+ * stay alive until the engine has run. After a run, the checkpoint that it
+ * took, if any, is handed to the visitor and then freed; returns nonzero if
+ * there was one. */
+
+void
+tt_xetex_set_resume_checkpoint (const unsigned char *data, size_t len)
+{
+    checkpoint_set_resume_data(data, len);
+}
+
+
+int
+tt_xetex_take_checkpoint (CheckpointVisitor visitor, void *context)
+{
+    return checkpoint_visit(visitor, context);
+}
+
+
+int
+tt_xetex_resumed_from_checkpoint (void)
+{
+    return checkpoint_resumed();
+}
+
+
+/* The characters that the last run couldn't find in its fonts. Each recorded
+ * one is handed to the visitor, and then they're all freed; returns how many
+ * there were, which can be more than were recorded. */
+
+size_t
+tt_xetex_visit_missing_chars (MissingCharVisitor visitor, void *context)
+{
+    return missing_chars_visit(visitor, context);
+}
+
 int
 tt_engine_xetex_main(
     ttbc_state_t *api,
@@ -57,6 +174,16 @@ tt_engine_xetex_main(
 ) {
     int rv;
 
+    /* Unless we've been asked to reuse them, drop any shaping results left
+     * over from a previous run, so that they don't hold on to memory. */
+    if (shaping_cache_mode != SHAPING_CACHE_REUSE)
+        clearShapedTextCache();
+
+    shaping_cache_hits = 0;
+    shaping_cache_misses = 0;
+    shaping_cache_evictions = 0;
+    missing_chars_clear();
+
     if (setjmp(*ttbc_global_engine_enter(api))) {
         ttbc_global_engine_exit();
         return HISTORY_FATAL_ERROR;
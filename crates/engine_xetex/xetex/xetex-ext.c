@@ -1544,157 +1544,231 @@ store_justified_native_glyphs(void* pNode)
     }
 }
 
-void
-measure_native_node(void* pNode, int use_glyph_metrics)
+/* Shape the text of a native word node using an OpenType/Graphite font,
+ * setting its width and glyph info. */
+static void
+shape_native_node(memory_word* node, unsigned int f)
 {
-    memory_word* node = (memory_word*)pNode;
     int txtLen = native_length(node);
     uint16_t* txtPtr = (uint16_t*)(node + NATIVE_NODE_SIZE);
 
-    unsigned int f = native_font(node);
+    XeTeXLayoutEngine engine = (XeTeXLayoutEngine)(font_layout_engine[f]);
 
-#ifdef XETEX_MAC
-    if (font_area[f] == AAT_FONT_FLAG) {
-        /* we're using this font in AAT mode, so font_layout_engine[f] is actually a CFDictionaryRef */
-        DoAATLayout(node, 0);
-    } else
-#endif
-    if (font_area[f] == OTGR_FONT_FLAG) {
-        /* using this font in OT Layout mode, so font_layout_engine[f] is actually a XeTeXLayoutEngine */
-
-        XeTeXLayoutEngine engine = (XeTeXLayoutEngine)(font_layout_engine[f]);
-
-        FixedPoint* locations = NULL;
-        uint16_t* glyphIDs;
-        Fixed* glyphAdvances = NULL;
-        int totalGlyphCount = 0;
-
-        /* need to find direction runs within the text, and call layoutChars separately for each */
-
-        UBiDiDirection dir;
-        void* glyph_info = 0;
-        static FloatPoint* positions = 0;
-        static float* advances = 0;
-        static uint32_t* glyphs = 0;
-
-        UBiDi* pBiDi = ubidi_open();
+    FixedPoint* locations = NULL;
+    uint16_t* glyphIDs;
+    Fixed* glyphAdvances = NULL;
+    int totalGlyphCount = 0;
+
+    /* need to find direction runs within the text, and call layoutChars separately for each */
+
+    UBiDiDirection dir;
+    void* glyph_info = 0;
+    static FloatPoint* positions = 0;
+    static float* advances = 0;
+    static uint32_t* glyphs = 0;
+
+    UBiDi* pBiDi = ubidi_open();
+
+    UErrorCode errorCode = U_ZERO_ERROR;
+    ubidi_setPara(pBiDi, (const UChar*) txtPtr, txtLen, getDefaultDirection(engine), NULL, &errorCode);
+
+    dir = ubidi_getDirection(pBiDi);
+    if (dir == UBIDI_MIXED) {
+        /* we actually do the layout twice here, once to count glyphs and then again to get them;
+           which is inefficient, but i figure that MIXED is a relatively rare occurrence, so i can't be
+           bothered to deal with the memory reallocation headache of doing it differently
+        */
+        int nRuns = ubidi_countRuns(pBiDi, &errorCode);
+        double width = 0;
+        int i, runIndex;
+        int32_t logicalStart, length;
+        for (runIndex = 0; runIndex < nRuns; ++runIndex) {
+            dir = ubidi_getVisualRun(pBiDi, runIndex, &logicalStart, &length);
+            totalGlyphCount += layoutChars(engine, txtPtr, logicalStart, length, txtLen, (dir == UBIDI_RTL));
+        }
 
-        UErrorCode errorCode = U_ZERO_ERROR;
-        ubidi_setPara(pBiDi, (const UChar*) txtPtr, txtLen, getDefaultDirection(engine), NULL, &errorCode);
+        if (totalGlyphCount > 0) {
+            double x, y;
+            glyph_info = xcalloc(totalGlyphCount, native_glyph_info_size);
+            locations = (FixedPoint*)glyph_info;
+            glyphIDs = (uint16_t*)(locations + totalGlyphCount);
+            glyphAdvances = xcalloc(totalGlyphCount, sizeof(Fixed));
+            totalGlyphCount = 0;
 
-        dir = ubidi_getDirection(pBiDi);
-        if (dir == UBIDI_MIXED) {
-            /* we actually do the layout twice here, once to count glyphs and then again to get them;
-               which is inefficient, but i figure that MIXED is a relatively rare occurrence, so i can't be
-               bothered to deal with the memory reallocation headache of doing it differently
-            */
-            int nRuns = ubidi_countRuns(pBiDi, &errorCode);
-            double width = 0;
-            int i, runIndex;
-            int32_t logicalStart, length;
+            x = y = 0.0;
             for (runIndex = 0; runIndex < nRuns; ++runIndex) {
+                int nGlyphs;
                 dir = ubidi_getVisualRun(pBiDi, runIndex, &logicalStart, &length);
-                totalGlyphCount += layoutChars(engine, txtPtr, logicalStart, length, txtLen, (dir == UBIDI_RTL));
-            }
-
-            if (totalGlyphCount > 0) {
-                double x, y;
-                glyph_info = xcalloc(totalGlyphCount, native_glyph_info_size);
-                locations = (FixedPoint*)glyph_info;
-                glyphIDs = (uint16_t*)(locations + totalGlyphCount);
-                glyphAdvances = xcalloc(totalGlyphCount, sizeof(Fixed));
-                totalGlyphCount = 0;
-
-                x = y = 0.0;
-                for (runIndex = 0; runIndex < nRuns; ++runIndex) {
-                    int nGlyphs;
-                    dir = ubidi_getVisualRun(pBiDi, runIndex, &logicalStart, &length);
-                    nGlyphs = layoutChars(engine, txtPtr, logicalStart, length, txtLen,
-                                            (dir == UBIDI_RTL));
-
-                    glyphs = xcalloc(nGlyphs, sizeof(uint32_t));
-                    positions = xcalloc(nGlyphs + 1, sizeof(FloatPoint));
-                    advances = xcalloc(nGlyphs, sizeof(float));
-
-                    getGlyphs(engine, glyphs);
-                    getGlyphAdvances(engine, advances);
-                    getGlyphPositions(engine, positions);
-
-                    for (i = 0; i < nGlyphs; ++i) {
-                        glyphIDs[totalGlyphCount] = glyphs[i];
-                        locations[totalGlyphCount].x = D2Fix(positions[i].x + x);
-                        locations[totalGlyphCount].y = D2Fix(positions[i].y + y);
-                        glyphAdvances[totalGlyphCount] = D2Fix(advances[i]);
-                        ++totalGlyphCount;
-                    }
-                    x += positions[nGlyphs].x;
-                    y += positions[nGlyphs].y;
-
-                    free(glyphs);
-                    free(positions);
-                    free(advances);
+                nGlyphs = layoutChars(engine, txtPtr, logicalStart, length, txtLen,
+                                        (dir == UBIDI_RTL));
+
+                glyphs = xcalloc(nGlyphs, sizeof(uint32_t));
+                positions = xcalloc(nGlyphs + 1, sizeof(FloatPoint));
+                advances = xcalloc(nGlyphs, sizeof(float));
+
+                getGlyphs(engine, glyphs);
+                getGlyphAdvances(engine, advances);
+                getGlyphPositions(engine, positions);
+
+                for (i = 0; i < nGlyphs; ++i) {
+                    glyphIDs[totalGlyphCount] = glyphs[i];
+                    locations[totalGlyphCount].x = D2Fix(positions[i].x + x);
+                    locations[totalGlyphCount].y = D2Fix(positions[i].y + y);
+                    glyphAdvances[totalGlyphCount] = D2Fix(advances[i]);
+                    ++totalGlyphCount;
                 }
-                width = x;
-            }
+                x += positions[nGlyphs].x;
+                y += positions[nGlyphs].y;
 
-            node_width(node) = D2Fix(width);
-            native_glyph_count(node) = totalGlyphCount;
-            native_glyph_info_ptr(node) = glyph_info;
-        } else {
-            double width = 0;
-            totalGlyphCount = layoutChars(engine, txtPtr, 0, txtLen, txtLen, (dir == UBIDI_RTL));
-
-            glyphs = xcalloc(totalGlyphCount, sizeof(uint32_t));
-            positions = xcalloc(totalGlyphCount + 1, sizeof(FloatPoint));
-            advances = xcalloc(totalGlyphCount, sizeof(float));
-
-            getGlyphs(engine, glyphs);
-            getGlyphAdvances(engine, advances);
-            getGlyphPositions(engine, positions);
-
-            if (totalGlyphCount > 0) {
-                int i;
-                glyph_info = xcalloc(totalGlyphCount, native_glyph_info_size);
-                locations = (FixedPoint*)glyph_info;
-                glyphIDs = (uint16_t*)(locations + totalGlyphCount);
-                glyphAdvances = xcalloc(totalGlyphCount, sizeof(Fixed));
-                for (i = 0; i < totalGlyphCount; ++i) {
-                    glyphIDs[i] = glyphs[i];
-                    glyphAdvances[i] = D2Fix(advances[i]);
-                    locations[i].x = D2Fix(positions[i].x);
-                    locations[i].y = D2Fix(positions[i].y);
-                }
-                width = positions[totalGlyphCount].x;
+                free(glyphs);
+                free(positions);
+                free(advances);
             }
-
-            node_width(node) = D2Fix(width);
-            native_glyph_count(node) = totalGlyphCount;
-            native_glyph_info_ptr(node) = glyph_info;
-
-            free(glyphs);
-            free(positions);
-            free(advances);
+            width = x;
         }
 
-        ubidi_close(pBiDi);
+        node_width(node) = D2Fix(width);
+        native_glyph_count(node) = totalGlyphCount;
+        native_glyph_info_ptr(node) = glyph_info;
+    } else {
+        double width = 0;
+        totalGlyphCount = layoutChars(engine, txtPtr, 0, txtLen, txtLen, (dir == UBIDI_RTL));
+
+        glyphs = xcalloc(totalGlyphCount, sizeof(uint32_t));
+        positions = xcalloc(totalGlyphCount + 1, sizeof(FloatPoint));
+        advances = xcalloc(totalGlyphCount, sizeof(float));
 
+        getGlyphs(engine, glyphs);
+        getGlyphAdvances(engine, advances);
+        getGlyphPositions(engine, positions);
 
-        if (font_letter_space[f] != 0) {
-            Fixed lsDelta = 0;
-            Fixed lsUnit = font_letter_space[f];
+        if (totalGlyphCount > 0) {
             int i;
+            glyph_info = xcalloc(totalGlyphCount, native_glyph_info_size);
+            locations = (FixedPoint*)glyph_info;
+            glyphIDs = (uint16_t*)(locations + totalGlyphCount);
+            glyphAdvances = xcalloc(totalGlyphCount, sizeof(Fixed));
             for (i = 0; i < totalGlyphCount; ++i) {
-                if (glyphAdvances[i] == 0 && lsDelta != 0)
-                    lsDelta -= lsUnit;
-                locations[i].x += lsDelta;
-                lsDelta += lsUnit;
+                glyphIDs[i] = glyphs[i];
+                glyphAdvances[i] = D2Fix(advances[i]);
+                locations[i].x = D2Fix(positions[i].x);
+                locations[i].y = D2Fix(positions[i].y);
             }
-            if (lsDelta != 0) {
+            width = positions[totalGlyphCount].x;
+        }
+
+        node_width(node) = D2Fix(width);
+        native_glyph_count(node) = totalGlyphCount;
+        native_glyph_info_ptr(node) = glyph_info;
+
+        free(glyphs);
+        free(positions);
+        free(advances);
+    }
+
+    ubidi_close(pBiDi);
+
+
+    if (font_letter_space[f] != 0) {
+        Fixed lsDelta = 0;
+        Fixed lsUnit = font_letter_space[f];
+        int i;
+        for (i = 0; i < totalGlyphCount; ++i) {
+            if (glyphAdvances[i] == 0 && lsDelta != 0)
                 lsDelta -= lsUnit;
-                node_width(node) += lsDelta;
-            }
+            locations[i].x += lsDelta;
+            lsDelta += lsUnit;
+        }
+        if (lsDelta != 0) {
+            lsDelta -= lsUnit;
+            node_width(node) += lsDelta;
         }
-        free(glyphAdvances);
+    }
+    free(glyphAdvances);
+}
+
+
+/* Experimental memoization of shaped text. The cache lives in the layout
+ * library and persists across engine runs, so that in a multi-pass build the
+ * second and later passes can reuse the shaping of unchanged words. Entries
+ * are keyed by the font's name (which includes its features) and size, along
+ * with the text itself; the font number isn't usable since it can differ
+ * between runs. For the same reason, the Rust code can save the cache to a
+ * file and load it into a later process. Since a font found on the system or
+ * in the project can change between processes without its name changing,
+ * the key also includes a digest of the font file. */
+
+static uint16_t* shaping_key = NULL;
+static size_t shaping_key_alloc = 0;
+
+static size_t
+build_shaping_key(memory_word* node, unsigned int f)
+{
+    int txtLen = native_length(node);
+    uint16_t* txtPtr = (uint16_t*)(node + NATIVE_NODE_SIZE);
+    str_number name = font_name[f];
+    int32_t nameLen = length(name);
+    uint64_t digest = getFontDataDigest(getFont((XeTeXLayoutEngine) font_layout_engine[f]));
+    size_t keyLen = 7 + nameLen + txtLen;
+
+    if (keyLen > shaping_key_alloc) {
+        shaping_key_alloc = keyLen + 64;
+        shaping_key = xrealloc(shaping_key, shaping_key_alloc * sizeof(uint16_t));
+    }
+
+    shaping_key[0] = (uint16_t) ((uint32_t) font_size[f] >> 16);
+    shaping_key[1] = (uint16_t) ((uint32_t) font_size[f] & 0xFFFF);
+    shaping_key[2] = (uint16_t) (digest >> 48);
+    shaping_key[3] = (uint16_t) (digest >> 32);
+    shaping_key[4] = (uint16_t) (digest >> 16);
+    shaping_key[5] = (uint16_t) digest;
+    shaping_key[6] = (uint16_t) nameLen;
+    memcpy(shaping_key + 7, str_pool + str_start[name - TOO_BIG_CHAR], nameLen * sizeof(uint16_t));
+    memcpy(shaping_key + 7 + nameLen, txtPtr, txtLen * sizeof(uint16_t));
+    return keyLen;
+}
+
+static void
+shape_native_node_cached(memory_word* node, unsigned int f)
+{
+    size_t keyLen = build_shaping_key(node, f);
+    Fixed width;
+    int glyphCount;
+    void* glyphInfo;
+
+    /* On a hit, the cache hands us our own copy of the glyph info. */
+    if (getCachedShapedText(shaping_key, keyLen, &width, &glyphCount, &glyphInfo)) {
+        node_width(node) = width;
+        native_glyph_count(node) = glyphCount;
+        native_glyph_info_ptr(node) = glyphInfo;
+        shaping_cache_hits++;
+        return;
+    }
+
+    shape_native_node(node, f);
+    shaping_cache_evictions += cacheShapedText(shaping_key, keyLen, node_width(node), native_glyph_count(node),
+                                               native_glyph_info_ptr(node), native_glyph_count(node) * native_glyph_info_size);
+    shaping_cache_misses++;
+}
+
+void
+measure_native_node(void* pNode, int use_glyph_metrics)
+{
+    memory_word* node = (memory_word*)pNode;
+    unsigned int f = native_font(node);
+
+#ifdef XETEX_MAC
+    if (font_area[f] == AAT_FONT_FLAG) {
+        /* we're using this font in AAT mode, so font_layout_engine[f] is actually a CFDictionaryRef */
+        DoAATLayout(node, 0);
+    } else
+#endif
+    if (font_area[f] == OTGR_FONT_FLAG) {
+        /* using this font in OT Layout mode, so font_layout_engine[f] is actually a XeTeXLayoutEngine */
+        if (shaping_cache_mode == SHAPING_CACHE_DISABLED)
+            shape_native_node(node, f);
+        else
+            shape_native_node_cached(node, f);
     } else {
         _tt_abort("bad native font flag in `measure_native_node`");
     }
@@ -47,6 +47,11 @@ typedef void* CFDictionaryRef; /* dummy declaration just so the stubs can compil
 #define AAT_FONT_FLAG   0xFFFFu
 #define OTGR_FONT_FLAG  0xFFFEu
 
+/* values of shaping_cache_mode; keep in sync with the Rust ShapingCache enum */
+#define SHAPING_CACHE_DISABLED 0
+#define SHAPING_CACHE_FRESH    1
+#define SHAPING_CACHE_REUSE    2
+
 /* some typedefs that XeTeX uses - on Mac OS, we get these from Apple headers,
    but otherwise we'll need these substitute definitions */
 
@@ -348,7 +348,21 @@ scaled_t delta;
 int synctex_enabled;
 bool used_tectonic_coda_tokens;
 bool semantic_pagination_enabled;
-bool gave_char_warning_help;
+int32_t math_script_percent_scale_down_override;
+int32_t math_script_script_percent_scale_down_override;
+int32_t math_axis_height_adjust;
+int32_t shaping_cache_mode;
+uint64_t shaping_cache_hits;
+uint64_t shaping_cache_misses;
+uint64_t shaping_cache_evictions;
+int32_t trace_capture_flags;
+int32_t trace_capture_first_page;
+int32_t trace_capture_last_page;
+int32_t trace_capture_first_line;
+int32_t trace_capture_last_line;
+int32_t trace_capture_max_bytes;
+bool trace_capture_compress;
+int32_t checkpoint_after_page;
 
 /* These ought to live in xetex-pagebuilder.c but are shared a lot: */
 int32_t page_tail;
@@ -2440,6 +2454,318 @@ store_fmt_file(void)
 }
 
 
+/* Write a textual description of the parts of the current engine state that
+ * are saved in format files, for comparison against a format with the
+ * tectonic_xetex_format crate. This is driven by \tectonicdumpstate, and is
+ * meant for debugging documents that behave differently depending on which
+ * copy of a format they're run with. The layout must be kept in sync with the
+ * parser in `xetex_format/src/state.rs`. */
+
+static void
+dump_state_cs(rust_output_handle_t out, int32_t p)
+{
+    int32_t t = eqtb[p].b16.s1;
+    int32_t v = eqtb[p].b32.s1;
+    char *name;
+    unsigned char *c;
+
+    if (t == UNDEFINED_CS)
+        return;
+
+    if (t >= CALL) {
+        /* The equivalent of a macro is a pointer into mem, and the head of its
+         * token list is a reference count; neither is meaningful here, so
+         * describe the macro by a hash of the rest of the list instead. */
+        uint64_t h = UINT64_C(0xcbf29ce484222325);
+        int32_t q = LLIST_link(v);
+        int32_t n = 0;
+
+        while (q != TEX_NULL && q >= 0 && q <= mem_end && n++ < 1000000) {
+            uint32_t info = (uint32_t) mem[q].b32.s0;
+
+            for (int i = 0; i < 4; i++) {
+                h ^= (info >> (8 * i)) & 0xFF;
+                h *= UINT64_C(0x100000001b3);
+            }
+
+            q = LLIST_link(q);
+        }
+
+        ttstub_fprintf(out, "cs %d m%016llx ", t, (unsigned long long) h);
+    } else {
+        ttstub_fprintf(out, "cs %d %d ", t, v);
+    }
+
+    name = gettexstring(hash[p].s1);
+
+    for (c = (unsigned char *) name; *c; c++) {
+        if (*c <= ' ' || *c == '%' || *c == 0x7F)
+            ttstub_fprintf(out, "%%%02X", *c);
+        else
+            ttstub_output_write(out, (const char *) c, 1);
+    }
+
+    ttstub_output_write(out, "\n", 1);
+    free(name);
+}
+
+
+void
+tectonic_dump_state(const char *path)
+{
+    rust_output_handle_t out;
+    int32_t k;
+
+    out = ttstub_output_open(path, 0);
+    if (out == INVALID_HANDLE) {
+        ttstub_issue_warning("cannot open engine state output file \"%s\"", path);
+        return;
+    }
+
+    ttstub_fprintf(out, "%% Tectonic engine state\n");
+    ttstub_fprintf(out, "format-version %d\n", FORMAT_SERIAL);
+
+    for (k = 0; k < INT_PARS; k++)
+        ttstub_fprintf(out, "int %d %d\n", k, eqtb[INT_BASE + k].b32.s1);
+
+    for (k = 0; k < DIMEN_PARS; k++)
+        ttstub_fprintf(out, "dimen %d %d\n", k, eqtb[DIMEN_BASE + k].b32.s1);
+
+    for (k = 0; k < NUMBER_REGS; k++)
+        ttstub_fprintf(out, "count %d %d\n", k, eqtb[COUNT_BASE + k].b32.s1);
+
+    for (k = 0; k < NUMBER_REGS; k++)
+        ttstub_fprintf(out, "dimen-register %d %d\n", k, eqtb[SCALED_BASE + k].b32.s1);
+
+    for (k = 0; k < 256; k++) {
+        ttstub_fprintf(out, "catcode %d %d\n", k, eqtb[CAT_CODE_BASE + k].b32.s1);
+        ttstub_fprintf(out, "lccode %d %d\n", k, eqtb[LC_CODE_BASE + k].b32.s1);
+        ttstub_fprintf(out, "uccode %d %d\n", k, eqtb[UC_CODE_BASE + k].b32.s1);
+        ttstub_fprintf(out, "sfcode %d %d\n", k, eqtb[SF_CODE_BASE + k].b32.s1);
+        ttstub_fprintf(out, "mathcode %d %d\n", k, eqtb[MATH_CODE_BASE + k].b32.s1);
+    }
+
+    for (k = HASH_BASE; k < FROZEN_CONTROL_SEQUENCE; k++) {
+        if (hash[k].s1 != 0)
+            dump_state_cs(out, k);
+    }
+
+    for (k = EQTB_SIZE + 1; k <= EQTB_SIZE + hash_high; k++) {
+        if (hash[k].s1 != 0)
+            dump_state_cs(out, k);
+    }
+
+    ttstub_output_close(out);
+}
+
+
+/* Transfer the engine's state into or out of a checkpoint; see
+ * xetex-checkpoint.c. This runs at the top of main_control()'s loop, so the
+ * variables that only hold values in the middle of a command are left out.
+ * Scalars that give the sizes of arrays come before the arrays. */
+void
+checkpoint_engine_state(void)
+{
+    int32_t k;
+
+    CHECKPOINT_VAR(lo_mem_max);
+    CHECKPOINT_VAR(hi_mem_min);
+    CHECKPOINT_VAR(mem_end);
+    CHECKPOINT_VAR(var_used);
+    CHECKPOINT_VAR(dyn_used);
+    CHECKPOINT_VAR(avail);
+    CHECKPOINT_VAR(rover);
+    CHECKPOINT_ARRAY(mem, lo_mem_max + 1);
+    CHECKPOINT_ARRAY(&mem[hi_mem_min], mem_end + 1 - hi_mem_min);
+
+    CHECKPOINT_ARRAY(eqtb, eqtb_top + 1);
+    CHECKPOINT_ARRAY(_xeq_level_array, EQTB_SIZE - INT_BASE + 1);
+    CHECKPOINT_ARRAY(yhash, 1 + hash_top - hash_offset);
+    CHECKPOINT_VAR(hash_used);
+    CHECKPOINT_VAR(hash_high);
+    CHECKPOINT_VAR(cs_count);
+    CHECKPOINT_VAR(no_new_control_sequence);
+
+    CHECKPOINT_VAR(str_ptr);
+    CHECKPOINT_VAR(pool_ptr);
+    CHECKPOINT_ARRAY(str_start, str_ptr - TOO_BIG_CHAR + 1);
+    CHECKPOINT_ARRAY(str_pool, pool_ptr);
+
+    CHECKPOINT_VAR(fmem_ptr);
+    CHECKPOINT_VAR(font_ptr);
+    CHECKPOINT_ARRAY(font_info, fmem_ptr);
+    k = font_ptr + 1;
+    CHECKPOINT_ARRAY(font_check, k);
+    CHECKPOINT_ARRAY(font_size, k);
+    CHECKPOINT_ARRAY(font_dsize, k);
+    CHECKPOINT_ARRAY(font_params, k);
+    CHECKPOINT_ARRAY(font_name, k);
+    CHECKPOINT_ARRAY(font_area, k);
+    CHECKPOINT_ARRAY(font_bc, k);
+    CHECKPOINT_ARRAY(font_ec, k);
+    CHECKPOINT_ARRAY(font_glue, k);
+    CHECKPOINT_ARRAY(font_used, k);
+    CHECKPOINT_ARRAY(hyphen_char, k);
+    CHECKPOINT_ARRAY(skew_char, k);
+    CHECKPOINT_ARRAY(bchar_label, k);
+    CHECKPOINT_ARRAY(font_bchar, k);
+    CHECKPOINT_ARRAY(font_false_bchar, k);
+    CHECKPOINT_ARRAY(font_flags, k);
+    CHECKPOINT_ARRAY(font_letter_space, k);
+    CHECKPOINT_ARRAY(char_base, k);
+    CHECKPOINT_ARRAY(width_base, k);
+    CHECKPOINT_ARRAY(height_base, k);
+    CHECKPOINT_ARRAY(depth_base, k);
+    CHECKPOINT_ARRAY(italic_base, k);
+    CHECKPOINT_ARRAY(lig_kern_base, k);
+    CHECKPOINT_ARRAY(kern_base, k);
+    CHECKPOINT_ARRAY(exten_base, k);
+    CHECKPOINT_ARRAY(param_base, k);
+
+    CHECKPOINT_VAR(hyph_count);
+    CHECKPOINT_VAR(hyph_next);
+    CHECKPOINT_VAR(hyph_start);
+    CHECKPOINT_VAR(hyph_index);
+    CHECKPOINT_ARRAY(hyph_word, hyph_size + 1);
+    CHECKPOINT_ARRAY(hyph_list, hyph_size + 1);
+    CHECKPOINT_ARRAY(hyph_link, hyph_size + 1);
+
+    CHECKPOINT_VAR(nest_ptr);
+    CHECKPOINT_VAR(max_nest_stack);
+    CHECKPOINT_VAR(shown_mode);
+    CHECKPOINT_VAR(cur_list);
+    CHECKPOINT_ARRAY(nest, nest_ptr + 1);
+
+    CHECKPOINT_VAR(save_ptr);
+    CHECKPOINT_VAR(max_save_stack);
+    CHECKPOINT_VAR(cur_level);
+    CHECKPOINT_VAR(cur_group);
+    CHECKPOINT_VAR(cur_boundary);
+    CHECKPOINT_ARRAY(save_stack, save_size + 1);
+
+    CHECKPOINT_VAR(input_ptr);
+    CHECKPOINT_VAR(max_in_stack);
+    CHECKPOINT_VAR(cur_input);
+    CHECKPOINT_ARRAY(input_stack, input_ptr + 1);
+    CHECKPOINT_VAR(in_open);
+    CHECKPOINT_VAR(open_parens);
+    CHECKPOINT_VAR(line);
+    CHECKPOINT_ARRAY(line_stack, max_in_open + 1);
+    CHECKPOINT_ARRAY(eof_seen, max_in_open + 1);
+    CHECKPOINT_ARRAY(grp_stack, max_in_open + 1);
+    CHECKPOINT_ARRAY(if_stack, max_in_open + 1);
+    CHECKPOINT_ARRAY(source_filename_stack, max_in_open + 1);
+    CHECKPOINT_ARRAY(full_source_filename_stack, max_in_open + 1);
+    CHECKPOINT_VAR(param_ptr);
+    CHECKPOINT_VAR(max_param_stack);
+    CHECKPOINT_ARRAY(param_stack, param_ptr + 1);
+    CHECKPOINT_VAR(first);
+    CHECKPOINT_VAR(last);
+    CHECKPOINT_VAR(max_buf_stack);
+    CHECKPOINT_ARRAY(buffer, buf_size + 1);
+
+    CHECKPOINT_VAR(scanner_status);
+    CHECKPOINT_VAR(warning_index);
+    CHECKPOINT_VAR(def_ref);
+    CHECKPOINT_VAR(align_state);
+    CHECKPOINT_VAR(par_loc);
+    CHECKPOINT_VAR(par_token);
+    CHECKPOINT_VAR(force_eof);
+    CHECKPOINT_VAR(expand_depth_count);
+    CHECKPOINT_VAR(is_in_csname);
+    CHECKPOINT_VAR(cur_cmd);
+    CHECKPOINT_VAR(cur_chr);
+    CHECKPOINT_VAR(cur_cs);
+    CHECKPOINT_VAR(cur_tok);
+    CHECKPOINT_VAR(cur_mark);
+    CHECKPOINT_VAR(cond_ptr);
+    CHECKPOINT_VAR(if_limit);
+    CHECKPOINT_VAR(cur_if);
+    CHECKPOINT_VAR(if_line);
+    CHECKPOINT_VAR(skip_line);
+    CHECKPOINT_VAR(read_open);
+    CHECKPOINT_VAR(cur_name);
+    CHECKPOINT_VAR(cur_area);
+    CHECKPOINT_VAR(cur_ext);
+    CHECKPOINT_VAR(name_in_progress);
+    CHECKPOINT_VAR(stop_at_space);
+    CHECKPOINT_VAR(long_help_seen);
+
+    CHECKPOINT_VAR(job_name);
+    CHECKPOINT_VAR(log_opened);
+    CHECKPOINT_VAR(texmf_log_name);
+    CHECKPOINT_VAR(selector);
+    CHECKPOINT_VAR(interaction);
+    CHECKPOINT_VAR(history);
+    CHECKPOINT_VAR(error_count);
+    CHECKPOINT_VAR(deletions_allowed);
+    CHECKPOINT_VAR(set_box_allowed);
+    CHECKPOINT_VAR(use_err_help);
+    CHECKPOINT_VAR(file_offset);
+    CHECKPOINT_VAR(write_open);
+    CHECKPOINT_VAR(write_loc);
+
+    CHECKPOINT_VAR(randoms);
+    CHECKPOINT_VAR(j_random);
+    CHECKPOINT_VAR(random_seed);
+
+    CHECKPOINT_VAR(mag_set);
+    CHECKPOINT_VAR(total_pages);
+    CHECKPOINT_VAR(max_v);
+    CHECKPOINT_VAR(max_h);
+    CHECKPOINT_VAR(max_push);
+    CHECKPOINT_VAR(last_bop);
+    CHECKPOINT_VAR(dead_cycles);
+    CHECKPOINT_VAR(doing_leaders);
+    CHECKPOINT_VAR(cur_page_width);
+    CHECKPOINT_VAR(cur_page_height);
+    CHECKPOINT_VAR(cur_h_offset);
+    CHECKPOINT_VAR(cur_v_offset);
+    CHECKPOINT_VAR(pdf_last_x_pos);
+    CHECKPOINT_VAR(pdf_last_y_pos);
+
+    CHECKPOINT_VAR(last_badness);
+    CHECKPOINT_VAR(adjust_tail);
+    CHECKPOINT_VAR(pre_adjust_tail);
+    CHECKPOINT_VAR(pack_begin_line);
+    CHECKPOINT_VAR(cur_align);
+    CHECKPOINT_VAR(cur_span);
+    CHECKPOINT_VAR(cur_loop);
+    CHECKPOINT_VAR(align_ptr);
+    CHECKPOINT_VAR(cur_head);
+    CHECKPOINT_VAR(cur_tail);
+    CHECKPOINT_VAR(cur_pre_head);
+    CHECKPOINT_VAR(cur_pre_tail);
+
+    CHECKPOINT_VAR(page_tail);
+    CHECKPOINT_VAR(page_contents);
+    CHECKPOINT_VAR(page_so_far);
+    CHECKPOINT_VAR(last_glue);
+    CHECKPOINT_VAR(last_penalty);
+    CHECKPOINT_VAR(last_kern);
+    CHECKPOINT_VAR(last_node_type);
+    CHECKPOINT_VAR(insert_penalties);
+    CHECKPOINT_VAR(output_active);
+    CHECKPOINT_VAR(best_height_plus_depth);
+
+    CHECKPOINT_VAR(disc_ptr);
+    CHECKPOINT_VAR(cur_lang);
+    CHECKPOINT_VAR(space_class);
+    CHECKPOINT_VAR(prev_class);
+    CHECKPOINT_VAR(after_token);
+
+    CHECKPOINT_VAR(LR_ptr);
+    CHECKPOINT_VAR(LR_problems);
+    CHECKPOINT_VAR(cur_dir);
+    CHECKPOINT_VAR(pseudo_files);
+    CHECKPOINT_VAR(sa_root);
+    CHECKPOINT_VAR(sa_chain);
+    CHECKPOINT_VAR(sa_level);
+
+    CHECKPOINT_VAR(used_tectonic_coda_tokens);
+}
+
+
 static void
 pack_buffered_name(small_number n, int32_t a, int32_t b)
 {
@@ -2468,7 +2794,7 @@ load_fmt_file(void)
 
     fmt_in = ttstub_input_open(name_of_file, TTBC_FILE_FORMAT_FORMAT, 0);
     if (fmt_in == INVALID_HANDLE)
-        _tt_abort("cannot open the format file \"%s\"", name_of_file);
+        _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "cannot open the format file \"%s\"", name_of_file);
 
     cur_input.loc = j;
 
@@ -2490,8 +2816,9 @@ load_fmt_file(void)
 
     undump_int(x);
     if (x != FORMAT_SERIAL)
-        _tt_abort("format file \"%s\" is of the wrong version: expected %d, found %d",
-                  name_of_file, FORMAT_SERIAL, x);
+        _tt_abort_with_kind(TTBC_ABORT_BAD_FORMAT,
+                            "format file \"%s\" is of the wrong version: expected %d, found %d",
+                            name_of_file, FORMAT_SERIAL, x);
 
     /* hash table parameters */
 
@@ -2927,7 +3254,7 @@ load_fmt_file(void)
     return true;
 
 bad_fmt:
-    _tt_abort ("fatal format file error");
+    _tt_abort_with_kind(TTBC_ABORT_BAD_FORMAT, "fatal format file error");
 }
 
 
@@ -3040,6 +3367,8 @@ init_io(void)
     stdin_ufile.skipNextLF = 0;
     stdin_ufile.encodingMode = UTF8;
     stdin_ufile.conversionData = 0;
+    stdin_ufile.name = NULL;
+    stdin_ufile.format = 0;
     input_file[0] = &stdin_ufile;
 
     buffer[first] = 0;
@@ -3474,6 +3803,7 @@ tt_cleanup(void) {
     free(TEX_format_default);
     free(font_used);
     deinitialize_shipout_variables();
+    checkpoint_cleanup();
 
     destroy_font_manager();
 
@@ -3711,6 +4041,7 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
     job_name = 0;
     name_in_progress = false;
     log_opened = false;
+    trace_capture_init();
 
     if (semantic_pagination_enabled)
         output_file_extension = ".spx";
@@ -3729,7 +4060,6 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
     param_ptr = 0;
     max_param_stack = 0;
     used_tectonic_coda_tokens = false;
-    gave_char_warning_help = false;
 
     memset(buffer, 0, buf_size * sizeof(buffer[0]));
     first = 0;
@@ -3869,8 +4199,13 @@ tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_d
 
     pdf_files_init();
     synctex_init_command();
-    start_input(input_file_name);
-    history = HISTORY_SPOTLESS;
+    checkpoint_init();
+
+    if (!checkpoint_resume()) {
+        start_input(input_file_name);
+        history = HISTORY_SPOTLESS;
+    }
+
     main_control();
     final_cleanup();
     close_files_and_terminate();
@@ -27,15 +27,20 @@ tt_xetex_open_input (int filefmt)
     if (filefmt == TTBC_FILE_FORMAT_TECTONIC_PRIMARY) {
         handle = ttstub_input_open_primary ();
     } else if (name_of_file[0] == '|') {
-        // Tectonic TODO: issue #859. In mainline XeTeX, a pipe symbol indicates
-        // piped input from an external command via `popen()`. Now that we have
-        // shell-escape support we could also support this, but we don't have a
-        // real implementation yet. For now, issue a warning.
-        print_nl_cstr("Warning: ");
-        diagnostic_begin_capture_warning_here();
-        print_cstr("piped inputs from external commands are not implemented in Tectonic");
-        capture_to_diagnostic(NULL);
-        return INVALID_HANDLE;
+        // Tectonic: issue #859. In mainline XeTeX, a pipe symbol indicates
+        // piped input from an external command via `popen()`. We never run
+        // commands here; instead, the whole name, pipe included, is looked up
+        // like any other, so that the driver can provide the output of a
+        // generator declared ahead of time under it.
+        handle = ttstub_input_open (name_of_file, (ttbc_file_format) filefmt, 0);
+
+        if (handle == INVALID_HANDLE) {
+            print_nl_cstr("Warning: ");
+            diagnostic_begin_capture_warning_here();
+            print_cstr("piped inputs from external commands are only available from declared generators in Tectonic");
+            capture_to_diagnostic(NULL);
+            return INVALID_HANDLE;
+        }
     } else {
         handle = ttstub_input_open (name_of_file, (ttbc_file_format) filefmt, 0);
     }
@@ -131,6 +136,7 @@ u_open_in(UFILE **f, int32_t filefmt, const char *fopen_mode, int32_t mode, int3
     int B1, B2;
 
     handle = tt_xetex_open_input (filefmt);
+    checkpoint_note_input(name_of_file, filefmt, handle != INVALID_HANDLE, false);
     if (handle == INVALID_HANDLE)
         return 0;
 
@@ -140,6 +146,8 @@ u_open_in(UFILE **f, int32_t filefmt, const char *fopen_mode, int32_t mode, int3
     (*f)->savedChar = -1;
     (*f)->skipNextLF = 0;
     (*f)->handle = handle;
+    (*f)->name = xstrdup(name_of_file);
+    (*f)->format = filefmt;
 
     if (mode == AUTO) {
         /* sniff encoding form */
@@ -361,11 +369,13 @@ u_close(UFILE* f)
         /* NULL handle is stdin/terminal file. Shouldn't happen but meh. */
         return;
 
+    checkpoint_note_input_closed (f);
     ttstub_input_close (f->handle);
 
     if (f->encodingMode == ICUMAPPING && f->conversionData != NULL)
         ucnv_close ((UConverter*) f->conversionData);
 
+    free (f->name);
     free (f);
 }
 
@@ -15,6 +15,8 @@ typedef struct {
     short skipNextLF;
     short encodingMode;
     void *conversionData;
+    char *name; /* what the file was opened as, for checkpoints */
+    int32_t format;
 } UFILE;
 
 
@@ -8,6 +8,13 @@
 #include "xetex-synctex.h"
 #include "tectonic_bridge_core.h"
 
+#include <stdio.h>
+
+/* Whether a diagnostic is being diverted to the trace file, and how many
+ * bytes have been written to it; see trace_capture_update() below. */
+static bool trace_diverted;
+static uint64_t trace_bytes;
+
 static ttbc_diagnostic_t *current_diagnostic = 0;
 
 void
@@ -81,6 +88,27 @@ warn_char(int c)
     }
 }
 
+/* Write a byte to the log, keeping a copy for an engine checkpoint if one is
+ * being recorded. */
+static void
+log_putc(int c)
+{
+    char b = c;
+
+    ttstub_output_putc(log_file, c);
+    checkpoint_note_output(CHECKPOINT_LOG_STREAM, &b, 1);
+}
+
+/* The same for the \write file of the current selector. */
+static void
+write_file_putc(int c)
+{
+    char b = c;
+
+    ttstub_output_putc(write_file[selector], c);
+    checkpoint_note_output(selector, &b, 1);
+}
+
 void
 print_ln(void)
 {
@@ -88,14 +116,16 @@ print_ln(void)
     case SELECTOR_TERM_AND_LOG:
         warn_char('\n');
         ttstub_output_putc(rust_stdout, '\n');
-        ttstub_output_putc(log_file, '\n');
+        log_putc('\n');
         term_offset = 0;
         file_offset = 0;
         break;
     case SELECTOR_LOG_ONLY:
         warn_char('\n');
-        ttstub_output_putc(log_file, '\n');
+        log_putc('\n');
         file_offset = 0;
+        if (trace_diverted)
+            trace_bytes++;
         break;
     case SELECTOR_TERM_ONLY:
         warn_char('\n');
@@ -107,7 +137,7 @@ print_ln(void)
     case SELECTOR_NEW_STRING:
         break;
     default:
-        ttstub_output_putc(write_file[selector], '\n');
+        write_file_putc('\n');
         break;
     }
 }
@@ -120,7 +150,7 @@ print_raw_char(UTF16_code s, bool incr_offset)
     case SELECTOR_TERM_AND_LOG:
         warn_char(s);
         ttstub_output_putc(rust_stdout, s);
-        ttstub_output_putc(log_file, s);
+        log_putc(s);
         if (incr_offset) {
             term_offset++;
             file_offset++;
@@ -130,18 +160,22 @@ print_raw_char(UTF16_code s, bool incr_offset)
             term_offset = 0;
         }
         if (file_offset == max_print_line) {
-            ttstub_output_putc(log_file, '\n');
+            log_putc('\n');
             file_offset = 0;
         }
         break;
     case SELECTOR_LOG_ONLY:
         warn_char(s);
-        ttstub_output_putc(log_file, s);
+        log_putc(s);
         if (incr_offset)
             file_offset++;
+        if (trace_diverted)
+            trace_bytes++;
         if (file_offset == max_print_line) {
-            ttstub_output_putc(log_file, '\n');
+            log_putc('\n');
             file_offset = 0;
+            if (trace_diverted)
+                trace_bytes++;
         }
         break;
     case SELECTOR_TERM_ONLY:
@@ -167,7 +201,7 @@ print_raw_char(UTF16_code s, bool incr_offset)
         }
         break;
     default:
-        ttstub_output_putc(write_file[selector], s);
+        write_file_putc(s);
         break;
     }
     tally++;
@@ -766,3 +800,223 @@ print_ucs_code(UnicodeScalar n)
 
     print_the_digs(k);
 }
+
+
+/* Trace capture. The driver can ask for some of the \tracing... parameters
+ * to be switched on while the engine is working on a range of pages, or of
+ * lines of the primary input file, with the diagnostics that they produce
+ * going to a separate `.trace` file instead of the log. While the range is
+ * active, the parameters are forced to the requested levels, and
+ * begin_diagnostic() diverts its output by swapping the trace file in for
+ * the log file. The bits of trace_capture_flags index trace_params[], which
+ * must be kept in sync with `TraceParameter` in the crate's lib.rs. */
+
+static const struct {
+    int32_t par;
+    int32_t level;
+} trace_params[] = {
+    { INT_PAR__tracing_commands, 3 },
+    { INT_PAR__tracing_macros, 2 },
+    { INT_PAR__tracing_paragraphs, 1 },
+    { INT_PAR__tracing_pages, 1 },
+    { INT_PAR__tracing_output, 1 },
+    { INT_PAR__tracing_restores, 1 },
+    { INT_PAR__tracing_assigns, 1 },
+    { INT_PAR__tracing_groups, 1 },
+    { INT_PAR__tracing_ifs, 1 },
+    { INT_PAR__tracing_nesting, 2 },
+    { INT_PAR__tracing_scan_tokens, 1 },
+    { INT_PAR__tracing_lost_chars, 1 },
+};
+
+#define N_TRACE_PARAMS ((int) (sizeof(trace_params) / sizeof(trace_params[0])))
+
+static rust_output_handle_t trace_file;
+static bool trace_active;
+static bool trace_stopped;
+static int32_t trace_file_offset;
+static rust_output_handle_t trace_saved_log_file;
+static int32_t trace_saved_file_offset;
+static int32_t trace_saved_params[N_TRACE_PARAMS];
+
+
+/* Reset the capture state at the start of a run. Handles left over from an
+ * aborted run have already been cleaned up by the bridge. */
+void
+trace_capture_init(void)
+{
+    trace_file = INVALID_HANDLE;
+    trace_active = false;
+    trace_stopped = false;
+    trace_diverted = false;
+    trace_bytes = 0;
+    trace_file_offset = 0;
+}
+
+
+/* The current line of the primary input file, or 0 if it isn't open. While
+ * a nested file is being read, line_stack[2] holds the line of the primary
+ * input from which it was opened. */
+static int32_t
+primary_input_line(void)
+{
+    if (in_open < 1)
+        return 0;
+    if (in_open == 1)
+        return line;
+    return line_stack[2];
+}
+
+
+static bool
+trace_in_range(void)
+{
+    int32_t page = total_pages + 1;
+    int32_t l;
+
+    if (trace_capture_first_page > 0 && page < trace_capture_first_page)
+        return false;
+    if (trace_capture_last_page > 0 && page > trace_capture_last_page)
+        return false;
+
+    if (trace_capture_first_line > 0 || trace_capture_last_line > 0) {
+        l = primary_input_line();
+
+        if (l < 1 || l < trace_capture_first_line)
+            return false;
+        if (trace_capture_last_line > 0 && l > trace_capture_last_line)
+            return false;
+    }
+
+    return true;
+}
+
+
+static bool
+trace_open(void)
+{
+    char *job;
+    char *name;
+
+    if (trace_file != INVALID_HANDLE)
+        return true;
+
+    job = job_name == 0 ? NULL : gettexstring(job_name);
+    name = xmalloc(strlen(job ? job : "texput") + 10);
+    sprintf(name, "%s.trace%s", job ? job : "texput", trace_capture_compress ? ".gz" : "");
+    trace_file = ttstub_output_open(name, trace_capture_compress);
+    free(name);
+    free(job);
+    return trace_file != INVALID_HANDLE;
+}
+
+
+static void
+trace_restore_params(void)
+{
+    int i;
+
+    for (i = 0; i < N_TRACE_PARAMS; i++) {
+        if (trace_capture_flags & (1 << i))
+            eqtb[INT_BASE + trace_params[i].par].b32.s1 = trace_saved_params[i];
+    }
+
+    trace_active = false;
+}
+
+
+/* Start or stop capturing if the engine has entered or left the requested
+ * range. This is called whenever the page number or the line of the primary
+ * input changes. */
+void
+trace_capture_update(void)
+{
+    int i;
+    bool want;
+
+    if (trace_capture_flags == 0 || trace_stopped)
+        return;
+
+    want = trace_in_range();
+
+    if (want == trace_active)
+        return;
+
+    if (!want) {
+        trace_restore_params();
+        return;
+    }
+
+    if (!trace_open()) {
+        trace_stopped = true;
+        return;
+    }
+
+    for (i = 0; i < N_TRACE_PARAMS; i++) {
+        if (trace_capture_flags & (1 << i)) {
+            int32_t *p = &eqtb[INT_BASE + trace_params[i].par].b32.s1;
+            trace_saved_params[i] = *p;
+            *p = trace_params[i].level;
+        }
+    }
+
+    trace_active = true;
+}
+
+
+/* Called by begin_diagnostic(): if capture is active, send the diagnostic to
+ * the trace file. Diagnostics printed into strings are left alone. */
+bool
+trace_capture_divert(void)
+{
+    if (!trace_active || selector < SELECTOR_NO_PRINT || selector > SELECTOR_TERM_AND_LOG)
+        return false;
+
+    trace_saved_log_file = log_file;
+    trace_saved_file_offset = file_offset;
+    log_file = trace_file;
+    file_offset = trace_file_offset;
+    selector = SELECTOR_LOG_ONLY;
+    trace_diverted = true;
+    return true;
+}
+
+
+/* Called by end_diagnostic() once a diagnostic has been printed, to switch
+ * back to the log if it was diverted. Returns true if the trace file has just
+ * reached its size limit, in which case capture stops for the rest of the
+ * run. */
+bool
+trace_capture_undivert(void)
+{
+    static const char note[] = "\n% trace truncated: size limit reached\n";
+
+    if (!trace_diverted)
+        return false;
+
+    trace_file_offset = file_offset;
+    log_file = trace_saved_log_file;
+    file_offset = trace_saved_file_offset;
+    trace_diverted = false;
+
+    if (trace_capture_max_bytes <= 0 || trace_bytes < (uint64_t) trace_capture_max_bytes)
+        return false;
+
+    ttstub_output_write(trace_file, note, sizeof(note) - 1);
+    trace_restore_params();
+    trace_stopped = true;
+    return true;
+}
+
+
+void
+trace_capture_finish(void)
+{
+    if (trace_active)
+        trace_restore_params();
+
+    if (trace_file != INVALID_HANDLE) {
+        ttstub_output_close(trace_file);
+        trace_file = INVALID_HANDLE;
+    }
+}
@@ -29,6 +29,17 @@ initialize_pagebuilder_variables(void)
 }
 
 
+/* Transfer the page builder's state into or out of a checkpoint. */
+void
+pagebuilder_checkpoint_state(void)
+{
+    CHECKPOINT_VAR(best_page_break);
+    CHECKPOINT_VAR(best_size);
+    CHECKPOINT_VAR(least_page_cost);
+    CHECKPOINT_VAR(page_max_depth);
+}
+
+
 static void
 freeze_page_specs(small_number s)
 {
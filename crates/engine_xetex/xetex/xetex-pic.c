@@ -80,6 +80,7 @@ count_pdf_file_pages (void)
     pdf_file *pf;
 
     handle = ttstub_input_open (name_of_file, TTBC_FILE_FORMAT_PICT, 0);
+    checkpoint_note_input(name_of_file, TTBC_FILE_FORMAT_PICT, handle != INVALID_HANDLE, true);
     if (handle == INVALID_HANDLE)
         return 0;
 
@@ -227,6 +228,7 @@ find_pic_file (char **path, real_rect *bounds, int pdfBoxType, int page)
     rust_input_handle_t handle;
 
     handle = ttstub_input_open (name_of_file, TTBC_FILE_FORMAT_PICT, 0);
+    checkpoint_note_input(name_of_file, TTBC_FILE_FORMAT_PICT, handle != INVALID_HANDLE, true);
     bounds->x = bounds->y = bounds->wd = bounds->ht = 0.0;
 
     if (handle == INVALID_HANDLE)
@@ -67,6 +67,31 @@ deinitialize_shipout_variables(void)
 }
 
 
+/* Transfer the shipout state into or out of a checkpoint. */
+void
+shipout_checkpoint_state(void)
+{
+    CHECKPOINT_VAR(output_file_name);
+    CHECKPOINT_VAR(dvi_limit);
+    CHECKPOINT_VAR(g);
+    CHECKPOINT_VAR(lq);
+    CHECKPOINT_VAR(lr);
+    CHECKPOINT_VAR(dvi_ptr);
+    CHECKPOINT_VAR(dvi_offset);
+    CHECKPOINT_VAR(dvi_gone);
+    CHECKPOINT_VAR(down_ptr);
+    CHECKPOINT_VAR(right_ptr);
+    CHECKPOINT_VAR(dvi_h);
+    CHECKPOINT_VAR(dvi_v);
+    CHECKPOINT_VAR(dvi_f);
+    CHECKPOINT_VAR(cur_s);
+    CHECKPOINT_ARRAY(dvi_buf, DVI_BUF_SIZE + 1);
+
+    if (checkpoint_restoring() && output_file_name != 0)
+        dvi_file = checkpoint_output_handle(CHECKPOINT_DVI_STREAM);
+}
+
+
 static inline void
 dvi_out(eight_bits c)
 {
@@ -184,7 +209,8 @@ ship_out(int32_t p)
         pack_job_name(output_file_extension);
         dvi_file = ttstub_output_open(name_of_file, 0);
         if (dvi_file == INVALID_HANDLE)
-            _tt_abort("cannot open output file \"%s\"", name_of_file);
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "cannot open output file \"%s\"", name_of_file);
+        checkpoint_note_output_open(CHECKPOINT_DVI_STREAM, name_of_file);
         output_file_name = make_name_string();
     }
 
@@ -262,6 +288,7 @@ ship_out(int32_t p)
     dvi_out(EOP);
     total_pages++;
     cur_s = -1;
+    trace_capture_update();
 
 done:
     /*1518: "Check for LR anomalies at the end of ship_out" */
@@ -1737,8 +1764,10 @@ out_what(int32_t p)
             break;
         }
 
-        if (write_open[j])
+        if (write_open[j]) {
             ttstub_output_close(write_file[j]);
+            checkpoint_note_output_close(j);
+        }
 
         if (mem[p].b16.s0 == CLOSE_NODE) {
             write_open[j] = false;
@@ -1760,7 +1789,8 @@ out_what(int32_t p)
 
         write_file[j] = ttstub_output_open(name_of_file, 0);
         if (write_file[j] == INVALID_HANDLE)
-            _tt_abort("cannot open output file \"%s\"", name_of_file);
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "cannot open output file \"%s\"", name_of_file);
+        checkpoint_note_output_open(j, name_of_file);
 
         write_open[j] = true;
 
@@ -2388,6 +2418,8 @@ write_to_dvi(int32_t a, int32_t b)
 
     if (ttstub_output_write (dvi_file, (char *) &dvi_buf[a], n) != n)
         _tt_abort ("failed to write data to XDV file");
+
+    checkpoint_note_output(CHECKPOINT_DVI_STREAM, &dvi_buf[a], n);
 }
 
 
@@ -156,6 +156,7 @@ getfilemoddate(str_number s)
 
   name = gettexstring(s);
   handle = ttstub_input_open(name, TTBC_FILE_FORMAT_TEX, 0);
+  checkpoint_note_input(name, TTBC_FILE_FORMAT_TEX, handle != INVALID_HANDLE, true);
   free(name);
 
   if (handle == INVALID_HANDLE)
@@ -191,6 +192,7 @@ getfilesize(str_number s)
 
   name = gettexstring(s);
   handle = ttstub_input_open(name, TTBC_FILE_FORMAT_TEX, 0);
+  checkpoint_note_input(name, TTBC_FILE_FORMAT_TEX, handle != INVALID_HANDLE, true);
   free(name);
 
   if (handle == INVALID_HANDLE)
@@ -240,6 +242,7 @@ void getfiledump(int32_t s, int offset, int length)
 
   name = gettexstring(s);
   handle = ttstub_input_open(name, TTBC_FILE_FORMAT_TEX, 0);
+  checkpoint_note_input(name, TTBC_FILE_FORMAT_TEX, handle != INVALID_HANDLE, true);
   free(name);
 
   if (handle == INVALID_HANDLE) {
@@ -270,16 +273,14 @@ checkpool_pointer (pool_pointer pool_ptr, size_t len)
 }
 
 
-int
-maketexstring(const char *s)
+/* Append a UTF-8 C string to the string pool, as UTF-16. */
+static void
+append_utf8_to_pool(const char *s)
 {
   size_t len;
   UInt32 rval;
   const unsigned char *cp = (const unsigned char *)s;
 
-  if (s == NULL || *s == 0)
-    return EMPTY_STRING;
-
   len = strlen(s);
   checkpool_pointer (pool_ptr, len); /* in the XeTeX case, this may be more than enough */
 
@@ -302,11 +303,54 @@ maketexstring(const char *s)
     else
       str_pool[pool_ptr++] = rval;
   }
+}
+
 
+int
+maketexstring(const char *s)
+{
+  if (s == NULL || *s == 0)
+    return EMPTY_STRING;
+
+  append_utf8_to_pool(s);
   return make_string();
 }
 
 
+/* Given an environment variable name stored in the string pool, insert into
+ * the string pool text giving its value. The variable must be explicitly
+ * allowed by the security settings; if it isn't, or it isn't set, the result
+ * is the empty string. */
+void
+gettectonicenv(str_number s)
+{
+  char *name;
+  char buf[256];
+  char *value = buf;
+  size_t needed;
+
+  name = gettexstring(s);
+  needed = ttstub_get_env(name, buf, sizeof(buf));
+
+  if (needed > sizeof(buf)) {
+    size_t size = needed;
+
+    value = xmalloc(size);
+    needed = ttstub_get_env(name, value, size);
+    if (needed > size)
+      needed = 0;
+  }
+
+  free(name);
+
+  if (needed > 0)
+    append_utf8_to_pool(value);
+
+  if (value != buf)
+    free(value);
+}
+
+
 char *
 gettexstring (str_number s)
 {
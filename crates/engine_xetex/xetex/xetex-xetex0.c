@@ -284,6 +284,7 @@ found:
 void free_node(int32_t p, int32_t s)
 {
     int32_t q;
+    checkpoint_forget_node(p);
     mem[p].b32.s0 = s;
     mem[p].b32.s1 = MAX_HALFWORD;
     q = mem[rover + 1].b32.s0;
@@ -365,6 +366,7 @@ copy_native_glyph_info(int32_t src, int32_t dest)
         NATIVE_NODE_glyph_info_ptr(dest) = xmalloc_array(char, glyph_count * NATIVE_GLYPH_INFO_SIZE);
         memcpy(NATIVE_NODE_glyph_info_ptr(dest), NATIVE_NODE_glyph_info_ptr(src), glyph_count * NATIVE_GLYPH_INFO_SIZE);
         NATIVE_NODE_glyph_count(dest) = glyph_count;
+        checkpoint_track_native_node(dest);
     }
 }
 
@@ -2107,6 +2109,9 @@ void begin_diagnostic(void)
 
     old_setting = selector;
 
+    if (trace_capture_divert())
+        return;
+
     if (INTPAR(tracing_online) <= 0 && selector == SELECTOR_TERM_AND_LOG) {
         selector--;
         if (history == HISTORY_SPOTLESS)
@@ -2116,10 +2121,18 @@ void begin_diagnostic(void)
 
 void end_diagnostic(bool blank_line)
 {
+    bool truncated;
+
     print_nl_cstr("");
     if (blank_line)
         print_ln();
+    truncated = trace_capture_undivert();
     selector = old_setting;
+
+    if (truncated) {
+        print_nl_cstr("Trace capture stopped: the trace file reached its size limit.");
+        print_ln();
+    }
 }
 
 void print_length_param(int32_t n)
@@ -2951,6 +2964,9 @@ print_cmd_chr(uint16_t cmd, int32_t chr_code)
         case XETEX_UCHARCAT_CODE:
             print_esc_cstr("Ucharcat");
             break;
+        case TECTONIC_GETENV_CODE:
+            print_esc_cstr("tectonicgetenv");
+            break;
         default:
             print_esc_cstr("jobname");
             break;
@@ -3627,6 +3643,9 @@ print_cmd_chr(uint16_t cmd, int32_t chr_code)
         case XETEX_DEFAULT_ENCODING_EXTENSION_CODE:
             print_esc_cstr("XeTeXdefaultencoding");
             break;
+        case TECTONIC_DUMP_STATE_EXTENSION_CODE:
+            print_esc_cstr("tectonicdumpstate");
+            break;
         default:
             print_cstr("[unknown extension!]");
             break;
@@ -5499,6 +5518,7 @@ restart:
 
             if (cur_input.name > 17) { /*374:*/
                 line++;
+                trace_capture_update();
                 first = cur_input.start;
 
                 if (!force_eof) {
@@ -9566,6 +9586,42 @@ conv_toks(void)
             str_ptr--;
         return;
 
+    case TECTONIC_GETENV_CODE:
+        save_scanner_status = scanner_status;
+        save_warning_index = warning_index;
+        save_def_ref = def_ref;
+        if (str_start[str_ptr - TOO_BIG_CHAR] < pool_ptr)
+            u = make_string();
+        else
+            u = 0;
+        scan_pdf_ext_toks();
+
+        if (selector == SELECTOR_NEW_STRING)
+            pdf_error("tokens", "tokens_to_string() called while selector = new_string");
+
+        old_setting = selector;
+        selector = SELECTOR_NEW_STRING;
+        show_token_list(mem[def_ref].b32.s1, TEX_NULL, pool_size - pool_ptr);
+        selector = old_setting;
+        s = make_string();
+        delete_token_ref(def_ref);
+        def_ref = save_def_ref;
+        warning_index = save_warning_index;
+        scanner_status = save_scanner_status;
+        b = pool_ptr;
+        gettectonicenv(s); /* <= the difference-maker */
+        mem[GARBAGE].b32.s1 = str_toks(b);
+
+        if (s == str_ptr - 1) {
+            str_ptr--;
+            pool_ptr = str_start[str_ptr - TOO_BIG_CHAR];
+        }
+
+        begin_token_list(mem[TEMP_HEAD].b32.s1, INSERTED);
+        if (u != 0)
+            str_ptr--;
+        return;
+
     case PDF_STRCMP_CODE:
         save_scanner_status = scanner_status;
         save_warning_index = warning_index;
@@ -10879,6 +10935,7 @@ open_log_file(void)
     log_file = ttstub_output_open (name_of_file, 0);
     if (log_file == INVALID_HANDLE)
         _tt_abort ("cannot open log file output \"%s\"", name_of_file);
+    checkpoint_note_output_open(CHECKPOINT_LOG_STREAM, name_of_file);
 
     texmf_log_name = make_name_string();
     selector = SELECTOR_LOG_ONLY;
@@ -11054,6 +11111,7 @@ start_input(const char *primary_input_name)
     synctex_start_input();
 
     line = 1;
+    trace_capture_update();
     input_line(input_file[cur_input.index]);
     cur_input.limit = last;
 
@@ -11078,6 +11136,63 @@ effective_char_info(internal_font_number f, uint16_t c)
 }
 
 
+/* Besides being logged by char_warning(), missing characters are recorded so
+ * that once the run is done the driver can report them grouped by font, along
+ * with where they turned up in the input, and give the hint about choosing
+ * another font just once. A broken document can be missing a character on
+ * every line, so only the first MAX_MISSING_CHARS are kept, although all are
+ * counted. */
+
+#define MAX_MISSING_CHARS 10000
+
+typedef struct {
+    char *font;
+    char *file;
+    int32_t line;
+    int32_t code;
+    bool unicode;
+} missing_char_t;
+
+static missing_char_t *missing_chars = NULL;
+static size_t n_missing_chars = 0;
+static size_t missing_chars_alloc = 0;
+static size_t missing_chars_total = 0;
+
+static void
+record_missing_char(internal_font_number f, int32_t c)
+{
+    missing_char_t *m;
+    int32_t level = in_open;
+
+    missing_chars_total++;
+
+    if (n_missing_chars >= MAX_MISSING_CHARS)
+        return;
+
+    if (n_missing_chars == missing_chars_alloc) {
+        missing_chars_alloc = missing_chars_alloc ? 2 * missing_chars_alloc : 64;
+        missing_chars = xrealloc(missing_chars, missing_chars_alloc * sizeof(missing_char_t));
+    }
+
+    m = &missing_chars[n_missing_chars++];
+    m->font = gettexstring(font_name[f]);
+    m->code = c;
+    m->unicode = (font_area[f] == AAT_FONT_FLAG || font_area[f] == OTGR_FONT_FLAG);
+
+    /* Find the innermost input that's a file, as print_file_line() does. */
+    while (level > 0 && full_source_filename_stack[level] == 0)
+        level--;
+
+    if (level == 0) {
+        m->file = NULL;
+        m->line = 0;
+    } else {
+        m->file = gettexstring(full_source_filename_stack[level]);
+        m->line = (level == in_open) ? line : line_stack[level + 1];
+    }
+}
+
+
 void char_warning(internal_font_number f, int32_t c)
 {
     int32_t old_setting;
@@ -11131,34 +11246,40 @@ void char_warning(internal_font_number f, int32_t c)
         }
     }
 
-    {
-        char *fn = gettexstring(font_name[f]);
-        char *chr = NULL;
-        selector_t prev_selector = selector;
-        int s;
+    record_missing_char(f, c);
+}
 
-        selector = SELECTOR_NEW_STRING;
-        if (c < 0x10000)
-            print(c);
-        else
-            print_char(c);
-        selector = prev_selector;
-        s = make_string();
-        chr = gettexstring(s);
-        str_ptr--; /* this is the "flush_string" macro which discards the most recent string */
-        pool_ptr = str_start[str_ptr - 0x10000];
 
-        ttstub_issue_warning("could not represent character \"%s\" (0x%" PRIx32 ") in font \"%s\"", chr, c, fn);
+void
+missing_chars_clear(void)
+{
+    size_t i;
+
+    for (i = 0; i < n_missing_chars; i++) {
+        free(missing_chars[i].font);
+        free(missing_chars[i].file);
+    }
+
+    free(missing_chars);
+    missing_chars = NULL;
+    n_missing_chars = 0;
+    missing_chars_alloc = 0;
+    missing_chars_total = 0;
+}
 
-        free(fn);
-        free(chr);
 
-        if (!gave_char_warning_help) {
-            ttstub_issue_warning("  you may need to load the `fontspec` package and use (e.g.) \\setmainfont to");
-            ttstub_issue_warning("  choose a different font that covers the unrepresentable character(s)");
-            gave_char_warning_help = true;
-        }
+size_t
+missing_chars_visit(MissingCharVisitor visitor, void *context)
+{
+    size_t i, total = missing_chars_total;
+
+    for (i = 0; i < n_missing_chars; i++) {
+        missing_char_t *m = &missing_chars[i];
+        visitor(context, m->font, m->file, m->line, m->code, m->unicode);
     }
+
+    missing_chars_clear();
+    return total;
 }
 
 
@@ -11182,6 +11303,7 @@ new_native_word_node(internal_font_number f, int32_t n)
     NATIVE_NODE_length(q) = n;
     NATIVE_NODE_glyph_count(q) = 0;
     NATIVE_NODE_glyph_info_ptr(q) = NULL;
+    checkpoint_track_native_node(q);
     return q;
 }
 
@@ -11249,6 +11371,7 @@ new_native_character(internal_font_number f, UnicodeScalar c)
         NATIVE_NODE_glyph_count(p) = 0;
         NATIVE_NODE_glyph_info_ptr(p) = NULL;
         NATIVE_NODE_font(p) = f;
+        checkpoint_track_native_node(p);
 
         if (c > 65535L) {
             NATIVE_NODE_length(p) = 2;
@@ -11611,6 +11734,7 @@ read_font_info(int32_t u, str_number nom, str_number aire, scaled_t s)
     check_for_tfm_font_mapping();
 
     tfm_file = tt_xetex_open_input (TTBC_FILE_FORMAT_TFM);
+    checkpoint_note_input(name_of_file, TTBC_FILE_FORMAT_TFM, tfm_file != INVALID_HANDLE, true);
     if (tfm_file == INVALID_HANDLE) {
         if (!quoted_filename) {
             g = load_native_font(u, nom, aire, s);
@@ -16596,6 +16720,14 @@ void do_extension(void)
         }
         break;
 
+    case TECTONIC_DUMP_STATE_EXTENSION_CODE:
+        {
+            scan_file_name();
+            pack_file_name(cur_name, cur_area, cur_ext);
+            tectonic_dump_state(name_of_file);
+        }
+        break;
+
     default:
         confusion("ext1");
         break;
@@ -16695,6 +16827,27 @@ tt_insert_special(const char *ascii_text)
     begin_token_list(toklist_start, INSERTED);
 }
 
+/* Tectonic: append a \special whatsit to the current list right away */
+void
+tt_append_special(const char *ascii_text)
+{
+    pool_pointer start_pool_ptr = pool_ptr;
+
+    if (pool_ptr + strlen(ascii_text) >= (size_t) pool_size)
+        _tt_abort("string pool overflow");
+
+    while (*ascii_text)
+        str_pool[pool_ptr++] = *ascii_text++;
+
+    new_whatsit(SPECIAL_NODE, WRITE_NODE_SIZE);
+    mem[cur_list.tail + 1].b32.s0 = TEX_NULL;
+    def_ref = get_avail();
+    mem[def_ref].b32.s0 = TEX_NULL;
+    str_toks(start_pool_ptr);
+    mem[def_ref].b32.s1 = mem[TEMP_HEAD].b32.s1;
+    mem[cur_list.tail + 1].b32.s1 = def_ref;
+}
+
 void
 handle_right_brace(void)
 {
@@ -16918,10 +17071,13 @@ main_control(void)
 {
     int32_t t;
 
-    if (LOCAL(every_job) != TEX_NULL)
+    if (LOCAL(every_job) != TEX_NULL && !checkpoint_resumed())
         begin_token_list(LOCAL(every_job), EVERY_JOB_TEXT);
 
 big_switch: /* big_switch */
+    if (checkpoint_after_page > 0 && total_pages >= checkpoint_after_page)
+        take_checkpoint();
+
     get_x_token();
 
 reswitch:
@@ -17251,8 +17407,21 @@ reswitch:
     case HMODE + VALIGN:
         if (cur_chr > 0) {
             if (eTeX_enabled(INTPAR(texxet) > 0, cur_cmd, cur_chr)) {
+                /* Tectonic customization: flag direction changes. The
+                 * specials go outside of the segment so that they aren't
+                 * affected when it's reversed at shipout. */
+                if (semantic_pagination_enabled) {
+                    if (cur_chr == BEGIN_R_CODE)
+                        tt_append_special("tdux:dirStart rtl");
+                    else if (cur_chr == BEGIN_L_CODE)
+                        tt_append_special("tdux:dirStart ltr");
+                }
+
                 mem[cur_list.tail].b32.s1 = new_math(0, cur_chr);
                 cur_list.tail = LLIST_link(cur_list.tail);
+
+                if (semantic_pagination_enabled && (cur_chr == END_R_CODE || cur_chr == END_L_CODE))
+                    tt_append_special("tdux:dirEnd");
             }
         } else /*:1490 */
             init_align();
@@ -18431,6 +18600,7 @@ close_files_and_terminate(void)
 
     finalize_dvi_file();
     synctex_terminate(log_opened);
+    trace_capture_finish();
 
     if (log_opened) {
         ttstub_output_putc (log_file, '\n');
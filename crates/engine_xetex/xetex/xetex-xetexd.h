@@ -370,6 +370,7 @@ void getcreationdate(void);
 void getfilemoddate(int32_t s);
 void getfilesize(int32_t s);
 void getfiledump(int32_t s, int offset, int length);
+void gettectonicenv(int32_t s);
 
 char *gettexstring(str_number);
 bool is_new_source(str_number, int);
@@ -722,7 +723,21 @@ extern scaled_t delta;
 extern int synctex_enabled;
 extern bool used_tectonic_coda_tokens;
 extern bool semantic_pagination_enabled;
-extern bool gave_char_warning_help;
+extern int32_t math_script_percent_scale_down_override;
+extern int32_t math_script_script_percent_scale_down_override;
+extern int32_t math_axis_height_adjust;
+extern int32_t shaping_cache_mode;
+extern uint64_t shaping_cache_hits;
+extern uint64_t shaping_cache_misses;
+extern uint64_t shaping_cache_evictions;
+extern int32_t trace_capture_flags;
+extern int32_t trace_capture_first_page;
+extern int32_t trace_capture_last_page;
+extern int32_t trace_capture_first_line;
+extern int32_t trace_capture_last_line;
+extern int32_t trace_capture_max_bytes;
+extern bool trace_capture_compress;
+extern int32_t checkpoint_after_page;
 
 /*:1683*/
 
@@ -895,6 +910,8 @@ void begin_name(void);
 bool more_name(UTF16_code c);
 void end_name(void);
 void pack_file_name(str_number n, str_number a, str_number e);
+void tectonic_dump_state(const char *path);
+void checkpoint_engine_state(void);
 str_number make_name_string(void);
 void scan_file_name(void);
 void pack_job_name(const char*);
@@ -902,6 +919,12 @@ void open_log_file(void);
 void start_input(const char *primary_input_name);
 b16x4 effective_char_info(internal_font_number f, uint16_t c);
 void char_warning(internal_font_number f, int32_t c);
+
+typedef void (*MissingCharVisitor)(void *context, const char *font, const char *file, int32_t line,
+                                   int32_t code, int unicode);
+
+void missing_chars_clear(void);
+size_t missing_chars_visit(MissingCharVisitor visitor, void *context);
 int32_t new_native_word_node(internal_font_number f, int32_t n);
 int32_t new_native_character(internal_font_number f, UnicodeScalar c);
 void font_feature_warning(const void *featureNameP, int32_t featLen, const void *settingNameP, int32_t setLen);
@@ -1028,6 +1051,36 @@ str_number tokens_to_string(int32_t p);
 void scan_pdf_ext_toks(void);
 void compare_strings(void);
 
+/* xetex-checkpoint */
+
+/* The output streams that are recorded for checkpoints: those of \openout
+ * files are numbered as in write_file[], followed by these. */
+#define CHECKPOINT_LOG_STREAM 16
+#define CHECKPOINT_DVI_STREAM 17
+
+#define CHECKPOINT_VAR(v) checkpoint_transfer(&(v), sizeof(v))
+#define CHECKPOINT_ARRAY(a, n) checkpoint_transfer((a), (size_t) (n) * sizeof((a)[0]))
+
+typedef void (*CheckpointVisitor)(void *context, const unsigned char *data, size_t len, int32_t pages);
+
+void checkpoint_init(void);
+bool checkpoint_resume(void);
+bool checkpoint_resumed(void);
+bool checkpoint_restoring(void);
+void take_checkpoint(void);
+void checkpoint_transfer(void *data, size_t len);
+void checkpoint_track_native_node(int32_t p);
+void checkpoint_forget_node(int32_t p);
+void checkpoint_note_input(const char *name, int32_t format, bool found, bool whole);
+void checkpoint_note_input_closed(UFILE *f);
+void checkpoint_note_output_open(int stream, const char *name);
+void checkpoint_note_output(int stream, const void *data, size_t len);
+void checkpoint_note_output_close(int stream);
+rust_output_handle_t checkpoint_output_handle(int stream);
+void checkpoint_cleanup(void);
+void checkpoint_set_resume_data(const unsigned char *data, size_t len);
+int checkpoint_visit(CheckpointVisitor visitor, void *context);
+
 /* xetex-errors */
 
 void error(void);
@@ -1119,10 +1172,16 @@ void print_roman_int(int32_t n);
 void print_current_string(void);
 void print_scaled(scaled_t s);
 void print_ucs_code(UnicodeScalar n);
+void trace_capture_init(void);
+void trace_capture_update(void);
+bool trace_capture_divert(void);
+bool trace_capture_undivert(void);
+void trace_capture_finish(void);
 
 /* xetex-pagebuilder */
 
 void initialize_pagebuilder_variables(void);
+void pagebuilder_checkpoint_state(void);
 void build_page(void);
 
 /* xetex-scaledmath */
@@ -1140,6 +1199,7 @@ int32_t norm_rand(void);
 
 void initialize_shipout_variables(void);
 void deinitialize_shipout_variables(void);
+void shipout_checkpoint_state(void);
 void ship_out(int32_t p);
 void finalize_dvi_file(void);
 
@@ -1175,6 +1235,7 @@ void tt_cleanup(void);
 tt_history_t tt_run_engine(const char *dump_name, const char *input_file_name, time_t build_date);
 
 void tt_insert_special(const char *ascii_text);
+void tt_append_special(const char *ascii_text);
 
 
 /* formerly xetex.h: */
@@ -1,4 +1,4 @@
-/* tectonic_xetex_format engine header for version 33 */
+/* tectonic_xetex_format engine header for version 34 */
 /* This file is automatically generated by the `xetex_format` `emit` example. Do not modify. */
 
 #ifndef __TECTONIC_XETEX_FORMAT_ENGINE_HEADER__
@@ -8,7 +8,7 @@
 
 /* The format version associated with these symbols. */
 
-#define TECTONIC_FORMAT_VERSION 33 /* = 0x21 */
+#define TECTONIC_FORMAT_VERSION 34 /* = 0x22 */
 
 /* Parameters associated with the multiletter control string hash table. */
 
@@ -518,6 +518,7 @@
 #define XETEX_UCHARCAT_CODE 39 /* = 0x27 */
 #define JOB_NAME_CODE 40 /* = 0x28 */
 #define XETEX_CONVERT_CODES 40 /* = 0x28 */
+#define TECTONIC_GETENV_CODE 41 /* = 0x29 */
 
 /* Subcommand codes for the EXTENSION command. */
 
@@ -531,6 +532,7 @@
 #define XETEX_INPUT_ENCODING_EXTENSION_CODE 44 /* = 0x2c */
 #define XETEX_DEFAULT_ENCODING_EXTENSION_CODE 45 /* = 0x2d */
 #define XETEX_LINEBREAK_LOCALE_EXTENSION_CODE 46 /* = 0x2e */
+#define TECTONIC_DUMP_STATE_EXTENSION_CODE 47 /* = 0x2f */
 
 /* Subcommand codes for the FI_OR_ELSE command. */
 
@@ -856,6 +858,7 @@ typedef struct xetex_format_primitive_def_t {
     { "XeTeXinputencoding", EXTENSION, XETEX_INPUT_ENCODING_EXTENSION_CODE, xf_prim_init_none }, \
     { "XeTeXdefaultencoding", EXTENSION, XETEX_DEFAULT_ENCODING_EXTENSION_CODE, xf_prim_init_none }, \
     { "XeTeXlinebreaklocale", EXTENSION, XETEX_LINEBREAK_LOCALE_EXTENSION_CODE, xf_prim_init_none }, \
+    { "tectonicdumpstate", EXTENSION, TECTONIC_DUMP_STATE_EXTENSION_CODE, xf_prim_init_none }, \
     { "closein", IN_STREAM, 0, xf_prim_init_none }, \
     { "openin", IN_STREAM, 1, xf_prim_init_none }, \
     { "begingroup", BEGIN_GROUP, 0, xf_prim_init_none }, \
@@ -1068,6 +1071,7 @@ typedef struct xetex_format_primitive_def_t {
     { "Uchar", CONVERT, XETEX_UCHAR_CODE, xf_prim_init_none }, \
     { "Ucharcat", CONVERT, XETEX_UCHARCAT_CODE, xf_prim_init_none }, \
     { "jobname", CONVERT, JOB_NAME_CODE, xf_prim_init_none }, \
+    { "tectonicgetenv", CONVERT, TECTONIC_GETENV_CODE, xf_prim_init_none }, \
     { "the", THE, SHOW_CODE, xf_prim_init_none }, \
     { "unexpanded", THE, SHOW_BOX_CODE, xf_prim_init_none }, \
     { "detokenize", THE, SHOW_TOKENS, xf_prim_init_none }, \
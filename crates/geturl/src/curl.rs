@@ -7,7 +7,7 @@ use curl::easy::Easy;
 use std::io::Cursor;
 use tectonic_errors::{anyhow::bail, Result};
 
-use crate::{GetUrlBackend, RangeReader};
+use crate::{check_network_allowed, GetUrlBackend, RangeReader};
 
 const MAX_HTTP_REDIRECTS_ALLOWED: u32 = 10;
 
@@ -16,6 +16,7 @@ fn get_url_generic(
     url: &str,
     range: Option<(u64, usize)>,
 ) -> Result<Cursor<Vec<u8>>> {
+    check_network_allowed(url)?;
     handle.url(url)?;
     handle.follow_location(true)?;
     handle.max_redirections(MAX_HTTP_REDIRECTS_ALLOWED)?;
@@ -19,10 +19,19 @@
 //! crates relying on this one need not use a lot of dyns and impl Traits. It is
 //! intended that the choice of HTTP backend is a build-time one, not a runtime
 //! one.
+//!
+//! Network access can, however, be forbidden at runtime for the whole process
+//! with [`forbid_network`], as is done for hermetic builds.
 
 use cfg_if::cfg_if;
-use std::io::Read;
-use tectonic_errors::Result;
+use std::{
+    io::Read,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+use tectonic_errors::{anyhow::bail, Result};
 
 /// A trait for reading byte ranges from an HTTP resource.
 pub trait RangeReader {
@@ -54,6 +63,42 @@ pub trait GetUrlBackend: Default {
     fn open_range_reader(&self, url: &str) -> Self::RangeReader;
 }
 
+static NETWORK_FORBIDDEN: AtomicBool = AtomicBool::new(false);
+static FORBIDDEN_REQUESTS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// Forbid, or allow again, network access by every backend in this process.
+///
+/// While network access is forbidden, all requests fail with an error, and the
+/// URLs that they were for are recorded so that they can be reported with
+/// [`take_forbidden_requests`]. Starting or stopping a range reader doesn't
+/// count as a request, but reading from one does.
+pub fn forbid_network(forbid: bool) {
+    NETWORK_FORBIDDEN.store(forbid, Ordering::SeqCst);
+}
+
+/// Check whether network access is currently forbidden; see
+/// [`forbid_network`].
+pub fn network_forbidden() -> bool {
+    NETWORK_FORBIDDEN.load(Ordering::SeqCst)
+}
+
+/// Get the URLs of the requests that have failed because network access was
+/// forbidden, in the order in which they were made, and forget them.
+pub fn take_forbidden_requests() -> Vec<String> {
+    std::mem::take(&mut *FORBIDDEN_REQUESTS.lock().unwrap())
+}
+
+/// Check whether a request for the given URL may go ahead, recording it if
+/// not. Backends call this before touching the network.
+fn check_network_allowed(url: &str) -> Result<()> {
+    if network_forbidden() {
+        FORBIDDEN_REQUESTS.lock().unwrap().push(url.to_owned());
+        bail!("network access to `{}` is forbidden", url);
+    }
+
+    Ok(())
+}
+
 pub mod null;
 
 #[cfg(feature = "curl")]
@@ -74,3 +119,28 @@ cfg_if! {
 
 /// The range-reader type exposed by the default URL-get backend (for convenience).
 pub type DefaultRangeReader = <DefaultBackend as GetUrlBackend>::RangeReader;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn forbidden_network() {
+        let mut backend = null::NullBackend::default();
+        let mut reader = backend.open_range_reader("https://example.com/b.tar");
+
+        forbid_network(true);
+        assert!(network_forbidden());
+        let err = backend.get_url("https://example.com/a").unwrap_err();
+        assert!(err.to_string().contains("forbidden"));
+        assert!(reader.read_range(0, 10).is_err());
+        forbid_network(false);
+        assert!(backend.get_url("https://example.com/c").is_err());
+
+        assert_eq!(
+            take_forbidden_requests(),
+            ["https://example.com/a", "https://example.com/b.tar"]
+        );
+        assert!(take_forbidden_requests().is_empty());
+    }
+}
@@ -11,7 +11,7 @@ use std::{
 };
 use tectonic_errors::Result;
 
-use crate::{GetUrlBackend, RangeReader};
+use crate::{check_network_allowed, GetUrlBackend, RangeReader};
 
 /// The error type for the always-failing geturl backend.
 #[derive(Debug)]
@@ -33,27 +33,34 @@ impl GetUrlBackend for NullBackend {
     type Response = Empty;
     type RangeReader = NullRangeReader;
 
-    fn get_url(&mut self, _url: &str) -> Result<Empty> {
+    fn get_url(&mut self, url: &str) -> Result<Empty> {
+        check_network_allowed(url)?;
         Err((NoGetUrlBackendError {}).into())
     }
 
-    fn resolve_url(&mut self, _url: &str) -> Result<String> {
+    fn resolve_url(&mut self, url: &str) -> Result<String> {
+        check_network_allowed(url)?;
         Err((NoGetUrlBackendError {}).into())
     }
 
-    fn open_range_reader(&self, _url: &str) -> Self::RangeReader {
-        NullRangeReader {}
+    fn open_range_reader(&self, url: &str) -> Self::RangeReader {
+        NullRangeReader {
+            url: url.to_owned(),
+        }
     }
 }
 
 /// The "null" URL-get range reader, which always fails.
 #[derive(Debug)]
-pub struct NullRangeReader {}
+pub struct NullRangeReader {
+    url: String,
+}
 
 impl RangeReader for NullRangeReader {
     type Response = Empty;
 
     fn read_range(&mut self, _offset: u64, _length: usize) -> Result<Empty> {
+        check_network_allowed(&self.url)?;
         Err((NoGetUrlBackendError {}).into())
     }
 }
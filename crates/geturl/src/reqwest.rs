@@ -11,7 +11,7 @@ use reqwest::{
 };
 use tectonic_errors::{anyhow::bail, Result};
 
-use crate::{GetUrlBackend, RangeReader};
+use crate::{check_network_allowed, GetUrlBackend, RangeReader};
 
 const MAX_HTTP_REDIRECTS_ALLOWED: usize = 10;
 
@@ -24,6 +24,7 @@ impl GetUrlBackend for ReqwestBackend {
     type RangeReader = ReqwestRangeReader;
 
     fn get_url(&mut self, url: &str) -> Result<Response> {
+        check_network_allowed(url)?;
         let res = Client::new().get(url).send()?;
         if !res.status().is_success() {
             bail!(
@@ -36,6 +37,7 @@ impl GetUrlBackend for ReqwestBackend {
     }
 
     fn resolve_url(&mut self, url: &str) -> Result<String> {
+        check_network_allowed(url)?;
         let parsed = Url::parse(url)?;
         let original_filename = parsed
             .path_segments()
@@ -123,6 +125,7 @@ impl RangeReader for ReqwestRangeReader {
     type Response = Response;
 
     fn read_range(&mut self, offset: u64, length: usize) -> Result<Response> {
+        check_network_allowed(&self.url)?;
         let end_inclusive = offset + length as u64 - 1;
         let header_val = format!("bytes={offset}-{end_inclusive}").parse()?;
 
@@ -0,0 +1,50 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Helpers for turning times, such as build dates, into calendar dates.
+
+use std::time::SystemTime;
+
+/// Get the number of seconds between the Unix epoch and a time, which is
+/// negative if the time is before the epoch.
+pub fn unix_seconds(t: SystemTime) -> i64 {
+    match t.duration_since(SystemTime::UNIX_EPOCH) {
+        Ok(d) => d.as_secs() as i64,
+        Err(e) => -(e.duration().as_secs() as i64),
+    }
+}
+
+/// Convert a number of days since 1970-01-01 into a year, month, and day of
+/// the proleptic Gregorian calendar.
+///
+/// This is Howard Hinnant's `civil_from_days` algorithm.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn dates() {
+        assert_eq!(civil_from_days(0), (1970, 1, 1));
+        assert_eq!(civil_from_days(-1), (1969, 12, 31));
+        assert_eq!(civil_from_days(11016), (2000, 2, 29));
+        assert_eq!(civil_from_days(20742), (2026, 10, 16));
+
+        let t = SystemTime::UNIX_EPOCH;
+        assert_eq!(unix_seconds(t + Duration::from_secs(90)), 90);
+        assert_eq!(unix_seconds(t - Duration::from_secs(90)), -90);
+    }
+}
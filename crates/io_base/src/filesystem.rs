@@ -4,18 +4,18 @@
 //! Tectonic I/O implementations for `std::fs` types.
 
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     env,
-    fs::File,
-    io::{self, BufReader, Seek, SeekFrom},
-    path::{Path, PathBuf},
+    fs::{self, File},
+    io::{self, BufReader, Cursor, Read, Seek, SeekFrom},
+    path::{Component, Path, PathBuf},
 };
 use tectonic_errors::Result;
 use tectonic_status_base::{tt_warning, StatusBackend};
 
 use super::{
-    try_open_file, InputFeatures, InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle,
-    TectonicIoError,
+    stdstreams::SharedByteBuffer, try_open_file, InputFeatures, InputHandle, InputOrigin,
+    IoProvider, OpenResult, OutputHandle, SearchLayer, TectonicIoError,
 };
 
 /// FilesystemPrimaryInputIo is an I/O provider that provides the TeX "primary input"
@@ -78,12 +78,22 @@ impl IoProvider for FilesystemPrimaryInputIo {
 /// NOTE: no effort is made to contain I/O within the specified root!! We have
 /// an option to disallow absolute paths, but we don't do anything about
 /// `../../../....` paths.
+///
+/// Inputs that aren't regular files, such as named pipes or the paths created
+/// by a shell's process substitution, can't be seeked or read more than once.
+/// These are buffered in memory the first time that they're opened.
+///
+/// A provider can be [confined](Self::confine) to its root, in which case
+/// `..` paths that lead out of it are refused too.
 pub struct FilesystemIo {
     root: PathBuf,
     writes_allowed: bool,
     absolute_allowed: bool,
+    confined: bool,
     hidden_input_paths: HashSet<PathBuf>,
     reported_paths: HashSet<PathBuf>,
+    forbidden_paths: Vec<PathBuf>,
+    stream_buffers: HashMap<PathBuf, SharedByteBuffer>,
 }
 
 impl FilesystemIo {
@@ -98,8 +108,11 @@ impl FilesystemIo {
             root: PathBuf::from(root),
             writes_allowed,
             absolute_allowed,
+            confined: false,
             hidden_input_paths,
             reported_paths: HashSet::new(),
+            forbidden_paths: Vec::new(),
+            stream_buffers: HashMap::new(),
         }
     }
 
@@ -108,6 +121,59 @@ impl FilesystemIo {
         &self.root
     }
 
+    /// Confine this provider to its root directory.
+    ///
+    /// A confined provider treats absolute paths, and relative paths that
+    /// climb out of the root with `..`, as if they didn't exist, and records
+    /// them so that they can be reported with
+    /// [`forbidden_paths()`](Self::forbidden_paths). Symbolic links aren't
+    /// resolved, so this guards against accidents rather than acting as a
+    /// sandbox.
+    pub fn confine(&mut self, confined: bool) -> &mut Self {
+        self.confined = confined;
+        self
+    }
+
+    /// Get the paths that this provider has refused to open because it's
+    /// confined, in the order in which they were first requested.
+    pub fn forbidden_paths(&self) -> &[PathBuf] {
+        &self.forbidden_paths
+    }
+
+    /// If this provider is confined, check whether the named path lies outside
+    /// of its root, recording it if so.
+    fn is_forbidden(&mut self, name: &str) -> bool {
+        if !self.confined {
+            return false;
+        }
+
+        let path = Path::new(name);
+        let mut depth = 0;
+
+        let escapes = path.components().any(|c| match c {
+            Component::Prefix(_) | Component::RootDir => true,
+            Component::CurDir => false,
+            Component::Normal(_) => {
+                depth += 1;
+                false
+            }
+            Component::ParentDir => {
+                if depth == 0 {
+                    true
+                } else {
+                    depth -= 1;
+                    false
+                }
+            }
+        });
+
+        if escapes && !self.forbidden_paths.iter().any(|p| p == path) {
+            self.forbidden_paths.push(path.to_owned());
+        }
+
+        escapes
+    }
+
     fn construct_path(&mut self, name: &str) -> Result<PathBuf> {
         let path = Path::new(name);
 
@@ -123,7 +189,7 @@ impl FilesystemIo {
 
 impl IoProvider for FilesystemIo {
     fn output_open_name(&mut self, name: &str) -> OpenResult<OutputHandle> {
-        if !self.writes_allowed {
+        if !self.writes_allowed || self.is_forbidden(name) {
             return OpenResult::NotAvailable;
         }
 
@@ -163,6 +229,10 @@ impl IoProvider for FilesystemIo {
         name: &str,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
+        if self.is_forbidden(name) {
+            return OpenResult::NotAvailable;
+        }
+
         let path = match self.construct_path(name) {
             Ok(p) => p,
             Err(e) => return OpenResult::Err(e),
@@ -180,7 +250,16 @@ impl IoProvider for FilesystemIo {
             return OpenResult::NotAvailable;
         }
 
-        let f = match File::open(&path) {
+        if let Some(buf) = self.stream_buffers.get(&path) {
+            let handle = InputHandle::new(name, Cursor::new(buf.clone()), InputOrigin::Filesystem);
+
+            return match make_abspath(&path) {
+                Ok(abspath) => OpenResult::Ok((handle, Some(abspath))),
+                Err(e) => OpenResult::Err(e),
+            };
+        }
+
+        let mut f = match File::open(&path) {
             Ok(f) => f,
             Err(e) => {
                 return if e.kind() == io::ErrorKind::NotFound {
@@ -227,14 +306,55 @@ impl IoProvider for FilesystemIo {
         }
 
         // SyncTeX requires absolute paths.
-        let path = match make_abspath(path) {
+        let abspath = match make_abspath(&path) {
             Ok(m) => m,
             Err(e) => return OpenResult::Err(e),
         };
 
+        if !md.is_file() {
+            let mut data = Vec::new();
+
+            if let Err(e) = f.read_to_end(&mut data) {
+                return OpenResult::Err(e.into());
+            }
+
+            let buf = SharedByteBuffer::new(data);
+            self.stream_buffers.insert(path, buf.clone());
+            let handle = InputHandle::new(name, Cursor::new(buf), InputOrigin::Filesystem);
+            return OpenResult::Ok((handle, Some(abspath)));
+        }
+
         // Good to go.
         let handle = InputHandle::new(name, BufReader::new(f), InputOrigin::Filesystem);
-        OpenResult::Ok((handle, Some(path)))
+        OpenResult::Ok((handle, Some(abspath)))
+    }
+    fn describe_search(&mut self, _status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        let root = if self.root.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            &self.root
+        };
+
+        // Only the files directly inside the root are listed, since walking
+        // the whole tree could take a long time.
+        let mut names: Vec<String> = fs::read_dir(root)
+            .into_iter()
+            .flatten()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+            .filter(|entry| {
+                !self
+                    .hidden_input_paths
+                    .contains(&self.root.join(entry.file_name()))
+            })
+            .filter_map(|entry| entry.file_name().into_string().ok())
+            .collect();
+        names.sort();
+
+        vec![SearchLayer {
+            description: format!("the directory `{}`", root.display()),
+            names,
+        }]
     }
 }
 
@@ -280,3 +400,38 @@ fn make_abspath<P: AsRef<Path>>(path: P) -> Result<PathBuf> {
     let cwd = env::current_dir()?;
     Ok(cwd.join(path.as_ref()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[test]
+    fn confined() {
+        let root = env::temp_dir();
+        let mut io = FilesystemIo::new(&root, false, true, HashSet::new());
+        io.confine(true);
+        let mut status = NoopStatusBackend::default();
+
+        for name in [
+            "/etc/passwd",
+            "../secret.tex",
+            "a/../../secret.tex",
+            "../secret.tex",
+        ] {
+            assert!(io.input_open_name(name, &mut status).is_not_available());
+        }
+
+        assert!(io
+            .input_open_name("a/./../nonexistent-file.tex", &mut status)
+            .is_not_available());
+        assert_eq!(
+            io.forbidden_paths(),
+            [
+                Path::new("/etc/passwd"),
+                Path::new("../secret.tex"),
+                Path::new("a/../../secret.tex"),
+            ]
+        );
+    }
+}
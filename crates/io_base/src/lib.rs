@@ -27,6 +27,7 @@ use thiserror::Error as ThisError;
 use crate::digest::DigestData;
 
 pub mod app_dirs;
+pub mod date;
 pub mod digest;
 pub mod filesystem;
 pub mod flate2;
@@ -412,6 +413,18 @@ impl<T> OpenResult<T> {
     }
 }
 
+/// A place where an [`IoProvider`] looks for input files, as reported by
+/// [`IoProvider::describe_search`].
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SearchLayer {
+    /// A description of the place, such as `the directory "/home/me/doc"`.
+    pub description: String,
+
+    /// The names of files that are available here, which are used to suggest
+    /// alternatives to names that can't be found. This needn't be complete.
+    pub names: Vec<String>,
+}
+
 /// A hack to allow casting of Bundles to IoProviders.
 ///
 /// The code that sets up the I/O stack is handed a reference to a Bundle
@@ -530,6 +543,16 @@ pub trait IoProvider: AsIoProviderMut {
     ) -> Result<()> {
         bail!("this I/O layer cannot save format files");
     }
+
+    /// Describe the places where this provider looks for input files, in the
+    /// order in which it searches them.
+    ///
+    /// This is used to explain why a file couldn't be found, so it doesn't
+    /// need to be cheap. Providers that delegate to others should combine
+    /// their descriptions. The default implementation describes nothing.
+    fn describe_search(&mut self, _status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        Vec::new()
+    }
 }
 
 impl<P: IoProvider + ?Sized> IoProvider for Box<P> {
@@ -584,6 +607,10 @@ impl<P: IoProvider + ?Sized> IoProvider for Box<P> {
     ) -> Result<()> {
         (**self).write_format(name, data, status)
     }
+
+    fn describe_search(&mut self, status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        (**self).describe_search(status)
+    }
 }
 
 // Some generically helpful InputFeatures impls
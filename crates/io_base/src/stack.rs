@@ -7,7 +7,7 @@
 use std::path::PathBuf;
 use tectonic_status_base::StatusBackend;
 
-use super::{InputHandle, IoProvider, OpenResult, OutputHandle};
+use super::{InputHandle, IoProvider, OpenResult, OutputHandle, SearchLayer};
 
 /// An IoStack is an IoProvider that delegates to an ordered list of
 /// subordinate IoProviders. It also checks the order in which files are read
@@ -131,4 +131,11 @@ impl IoProvider for IoStack<'_> {
 
         OpenResult::NotAvailable
     }
+
+    fn describe_search(&mut self, status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        self.items
+            .iter_mut()
+            .flat_map(|item| item.describe_search(status))
+            .collect()
+    }
 }
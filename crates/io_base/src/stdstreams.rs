@@ -4,18 +4,23 @@
 //! Tectonic I/O trait implementations for the standard I/O streams.
 
 use std::{
-    io::{stdin, stdout, Cursor, Read, Seek, SeekFrom},
+    collections::HashMap,
+    io::{stderr, stdin, stdout, Cursor, Read, Seek, SeekFrom},
     rc::Rc,
 };
 use tectonic_errors::Result;
 use tectonic_status_base::StatusBackend;
 
-use super::{InputFeatures, InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle};
+use super::{
+    InputFeatures, InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle, SearchLayer,
+};
 
 /// GenuineStdoutIo provides a mechanism for the "stdout" output to actually
 /// go to the process's stdout.
 #[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
-pub struct GenuineStdoutIo {}
+pub struct GenuineStdoutIo {
+    to_stderr: bool,
+}
 
 impl GenuineStdoutIo {
     /// Create a new object.
@@ -24,12 +29,25 @@ impl GenuineStdoutIo {
     pub fn new() -> GenuineStdoutIo {
         Default::default()
     }
+
+    /// Create a new object that sends the "stdout" output to the process's
+    /// stderr instead.
+    ///
+    /// This is useful when the process's actual stdout is being used for
+    /// something else, such as streaming the output document.
+    pub fn new_stderr() -> GenuineStdoutIo {
+        GenuineStdoutIo { to_stderr: true }
+    }
 }
 
 impl IoProvider for GenuineStdoutIo {
     fn output_open_stdout(&mut self) -> OpenResult<OutputHandle> {
         // NOTE: keep in sync with io::memory::MemoryIo::stdout_key()
-        OpenResult::Ok(OutputHandle::new("", stdout()))
+        if self.to_stderr {
+            OpenResult::Ok(OutputHandle::new("", stderr()))
+        } else {
+            OpenResult::Ok(OutputHandle::new("", stdout()))
+        }
     }
 }
 
@@ -38,10 +56,10 @@ impl IoProvider for GenuineStdoutIo {
 /// Rc<Vec<u8>> since none of the types or traits come from the Tectonic
 /// crate.
 #[derive(Clone, Debug, Eq, PartialEq)]
-struct SharedByteBuffer(Rc<[u8]>);
+pub(crate) struct SharedByteBuffer(Rc<[u8]>);
 
 impl SharedByteBuffer {
-    fn new(data: Vec<u8>) -> SharedByteBuffer {
+    pub(crate) fn new(data: Vec<u8>) -> SharedByteBuffer {
         SharedByteBuffer(data.into())
     }
 }
@@ -146,3 +164,109 @@ impl IoProvider for BufferedPrimaryIo {
         ))
     }
 }
+
+/// BufferedStreamIo provides named input files whose contents come from
+/// arbitrary streams, such as pipes or in-memory readers.
+///
+/// As with [`BufferedPrimaryIo`], each stream is buffered in memory in its
+/// entirety, since the engines may open a file several times, and need to
+/// seek within it. A stream is only read the first time that its file is
+/// opened.
+#[derive(Default)]
+pub struct BufferedStreamIo {
+    streams: HashMap<String, Box<dyn Read>>,
+    buffers: HashMap<String, SharedByteBuffer>,
+}
+
+impl BufferedStreamIo {
+    /// Create a new object with no streams.
+    pub fn new() -> BufferedStreamIo {
+        Default::default()
+    }
+
+    /// Provide the input file with the given name by reading it from a stream.
+    ///
+    /// If a stream was already provided for this name, it is replaced.
+    pub fn add_stream<R: Read + 'static>(&mut self, name: impl Into<String>, stream: R) {
+        let name = name.into();
+        self.buffers.remove(&name);
+        self.streams.insert(name, Box::new(stream));
+    }
+
+    /// Returns true if no streams have been provided.
+    pub fn is_empty(&self) -> bool {
+        self.streams.is_empty() && self.buffers.is_empty()
+    }
+}
+
+impl IoProvider for BufferedStreamIo {
+    fn input_open_name(
+        &mut self,
+        name: &str,
+        _status: &mut dyn StatusBackend,
+    ) -> OpenResult<InputHandle> {
+        if let Some(mut stream) = self.streams.remove(name) {
+            let mut data = Vec::new();
+
+            if let Err(e) = stream.read_to_end(&mut data) {
+                return OpenResult::Err(e.into());
+            }
+
+            self.buffers
+                .insert(name.to_owned(), SharedByteBuffer::new(data));
+        }
+
+        match self.buffers.get(name) {
+            Some(buf) => OpenResult::Ok(InputHandle::new(
+                name,
+                Cursor::new(buf.clone()),
+                InputOrigin::Other,
+            )),
+            None => OpenResult::NotAvailable,
+        }
+    }
+
+    fn describe_search(&mut self, _status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        if self.is_empty() {
+            return Vec::new();
+        }
+
+        let mut names: Vec<String> = self
+            .streams
+            .keys()
+            .chain(self.buffers.keys())
+            .cloned()
+            .collect();
+        names.sort();
+
+        vec![SearchLayer {
+            description: "the input files provided as streams".to_owned(),
+            names,
+        }]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[test]
+    fn stream_reopened() {
+        let mut io = BufferedStreamIo::new();
+        io.add_stream("piped.tex", &b"Hello\\end"[..]);
+        let mut status = NoopStatusBackend::default();
+
+        for _ in 0..2 {
+            let mut ih = io.input_open_name("piped.tex", &mut status).unwrap();
+            let mut text = String::new();
+            ih.read_to_string(&mut text).unwrap();
+            assert_eq!(text, "Hello\\end");
+            assert_eq!(ih.try_seek(SeekFrom::Start(0)).unwrap(), 0);
+        }
+
+        assert!(io
+            .input_open_name("other.tex", &mut status)
+            .is_not_available());
+    }
+}
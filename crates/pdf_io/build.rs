@@ -143,6 +143,7 @@ fn main() {
         "pdf_io/dpx-truetype.c",
         "pdf_io/dpx-tt_aux.c",
         "pdf_io/dpx-tt_cmap.c",
+        "pdf_io/dpx-tt_colr.c",
         "pdf_io/dpx-tt_glyf.c",
         "pdf_io/dpx-tt_gsub.c",
         "pdf_io/dpx-tt_post.c",
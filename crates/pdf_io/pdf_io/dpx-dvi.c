@@ -60,6 +60,7 @@
 #include "dpx-t1_load.h"
 #include "dpx-tfm.h"
 #include "dpx-tt_aux.h"
+#include "dpx-tt_colr.h"
 #include "dpx-tt_table.h"
 #include "dpx-vf.h"
 
@@ -148,6 +149,7 @@ static struct loaded_font
     struct gm *gm;
     int shift_gid;
     uint16_t num_glyphs;
+    struct tt_colr_table *colr; /* Layers of color glyphs, for native fonts */
     int   layout_dir;
     float extend;
     float slant;
@@ -1260,6 +1262,10 @@ dvi_locate_native_font (const char *filename, uint32_t index,
             loaded_fonts[cur_id].gm[i].descent = descent;
         }
 
+        loaded_fonts[cur_id].colr = tt_read_colr_table(sfont);
+        if (!loaded_fonts[cur_id].colr && sfnt_find_table_pos(sfont, "SVG ") > 0)
+            dpx_warning("SVG color glyphs of font \"%s\" can't be embedded in PDF; drawing their outlines instead.", filename);
+
         free(gm);
         free(hhea);
         free(maxp);
@@ -1938,6 +1944,79 @@ skip_glyphs (void)
     }
 }
 
+/* ExtGState resources for the opacities of the layers of color glyphs,
+ * indexed by alpha. The resource IDs are stored plus one, so that zero
+ * means that there's none yet.
+ */
+static int colr_xgs_ids[256];
+
+/* Draw a color glyph of an OpenType font as the glyphs of its COLR layers,
+ * each filled with its palette color, in place of its own outline.
+ */
+static void
+do_color_glyph (struct loaded_font *font, spt_t xpos, spt_t ypos,
+                const struct tt_colr_layer *layers, int num_layers)
+{
+    unsigned char wbuf[2];
+    int i;
+
+    for (i = 0; i < num_layers; i++) {
+        const struct tt_colr_layer *layer = &layers[i];
+        int translucent = !layer->use_text_color && layer->alpha < 0xff;
+        spt_t advance = 0;
+
+        if (!layer->use_text_color) {
+            pdf_color color;
+
+            if (layer->alpha == 0)
+                continue;
+
+            pdf_color_rgbcolor(&color,
+                               (double) layer->red / 255,
+                               (double) layer->green / 255,
+                               (double) layer->blue / 255);
+            pdf_color_push(&color, &color);
+        }
+
+        if (translucent) {
+            char resname[16];
+            char content[22];
+
+            if (colr_xgs_ids[layer->alpha] == 0) {
+                pdf_obj *xgs_dict = pdf_new_dict();
+
+                pdf_add_dict(xgs_dict, pdf_new_name("Type"), pdf_new_name("ExtGState"));
+                pdf_add_dict(xgs_dict, pdf_new_name("ca"), pdf_new_number(layer->alpha/255.0));
+                pdf_add_dict(xgs_dict, pdf_new_name("CA"), pdf_new_number(layer->alpha/255.0));
+                colr_xgs_ids[layer->alpha] = pdf_defineresource("ExtGState", NULL, xgs_dict, 0) + 1;
+            }
+
+            sprintf(resname, "Xtx_Colr_Gs_%02x", layer->alpha);
+            pdf_doc_add_page_resource("ExtGState", resname,
+                                      pdf_get_resource_reference(colr_xgs_ids[layer->alpha] - 1));
+            graphics_mode();
+            pdf_dev_gsave();
+            sprintf(content, " /%s gs ", resname);
+            pdf_doc_add_page_content(content, strlen(content));
+        }
+
+        if (layer->gid < font->num_glyphs)
+            advance = font->gm[layer->gid].advance;
+
+        wbuf[0] = layer->gid >> 8;
+        wbuf[1] = layer->gid & 0xff;
+        set_string(xpos, ypos, wbuf, 2, advance, font->font_id);
+
+        if (translucent) {
+            graphics_mode();
+            pdf_dev_grestore();
+        }
+
+        if (!layer->use_text_color)
+            pdf_color_pop();
+    }
+}
+
 static void
 do_glyphs (int do_actual_text)
 {
@@ -1946,6 +2025,8 @@ do_glyphs (int do_actual_text)
     unsigned char wbuf[2];
     int32_t i;
     uint16_t glyph_id, slen = 0;
+    const struct tt_colr_layer *layers;
+    int num_layers;
 
     if (current_font < 0)
         _tt_abort("No font selected!");
@@ -2032,6 +2113,13 @@ do_glyphs (int do_actual_text)
             }
         }
 
+        if (font->colr && glyph_id < font->num_glyphs
+            && (num_layers = tt_colr_get_layers(font->colr, glyph_id, &layers)) > 0) {
+            do_color_glyph(font, dvi_state.h + xloc[i], -dvi_state.v - yloc[i],
+                           layers, num_layers);
+            continue;
+        }
+
         wbuf[0] = glyph_id >> 8;
         wbuf[1] = glyph_id & 0xff;
         set_string(dvi_state.h + xloc[i], -dvi_state.v - yloc[i], wbuf, 2,
@@ -2277,7 +2365,7 @@ dvi_init (const char *dvi_filename, double mag)
 
     dvi_handle = ttstub_input_open (dvi_filename, TTBC_FILE_FORMAT_PROGRAM_DATA, 0);
     if (dvi_handle == INVALID_HANDLE)
-        _tt_abort("cannot open \"%s\"", dvi_filename);
+        _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "cannot open \"%s\"", dvi_filename);
 
     /* DVI files are most easily read backwards by searching for post_post and
      * then post opcode.
@@ -2331,8 +2419,12 @@ dvi_close (void)
     {
         free(loaded_fonts[i].gm);
         loaded_fonts[i].gm = NULL;
+        tt_release_colr_table(loaded_fonts[i].colr);
+        loaded_fonts[i].colr = NULL;
     }
 
+    memset(colr_xgs_ids, 0, sizeof(colr_xgs_ids));
+
     loaded_fonts = mfree(loaded_fonts);
     num_loaded_fonts = 0;
 
@@ -476,9 +476,9 @@ pdf_out_init (const char *filename,
     p->output.handle = ttstub_output_open(filename, 0);
     if (!p->output.handle) {
         if (strlen(filename) < 128)
-            _tt_abort("Unable to open \"%s\".", filename);
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Unable to open \"%s\".", filename);
         else
-            _tt_abort("Unable to open file.");
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Unable to open file.");
     }
 
     pdf_out_str(p, "%PDF-", strlen("%PDF-"));
@@ -91,10 +91,11 @@ struct pdf_ximage_
 struct opt_
 {
     char  *cmdtmpl;
+    int    draft;
 };
 
 static struct opt_ _opts = {
-    NULL
+    NULL, 0
 };
 
 struct ic_
@@ -218,6 +219,56 @@ source_image_type (rust_input_handle_t handle)
     return format;
 }
 
+/* Tectonic: in draft mode, raster images are not decoded. Each one is
+ * replaced with a single white sample that is scaled to the size of the
+ * original, so that the layout of the page is unchanged. */
+static int
+draft_include_image (pdf_ximage *I, rust_input_handle_t handle, int format)
+{
+    pdf_obj      *stream;
+    ximage_info   info;
+    unsigned int  width = 0, height = 0;
+    uint32_t      png_width = 0, png_height = 0;
+    double        xdensity = 1.0, ydensity = 1.0;
+    int           r = -1;
+    unsigned char white = 0xff;
+
+    switch (format) {
+    case IMAGE_TYPE_JPEG:
+        r = jpeg_get_bbox(handle, &width, &height, &xdensity, &ydensity);
+        break;
+    case IMAGE_TYPE_PNG:
+        r = png_get_bbox(handle, &png_width, &png_height, &xdensity, &ydensity);
+        width = png_width;
+        height = png_height;
+        break;
+    case IMAGE_TYPE_BMP:
+        r = bmp_get_bbox(handle, &width, &height, &xdensity, &ydensity);
+        break;
+    }
+
+    if (r < 0)
+        return -1;
+
+    pdf_ximage_init_image_info(&info);
+    info.width = 1;
+    info.height = 1;
+    info.bits_per_component = 8;
+    info.num_components = 1;
+
+    stream = pdf_new_stream(0);
+    pdf_add_dict(pdf_stream_dict(stream), pdf_new_name("ColorSpace"), pdf_new_name("DeviceGray"));
+    pdf_add_stream(stream, &white, 1);
+    pdf_ximage_set_image(I, &info, stream);
+
+    /* The placeholder must occupy the same space as the real image. */
+    I->attr.width = width;
+    I->attr.height = height;
+    I->attr.xdensity = xdensity;
+    I->attr.ydensity = ydensity;
+    return 0;
+}
+
 static int
 load_image (const char *ident, const char *filename, const char *fullname,
             int format, rust_input_handle_t handle,
@@ -269,7 +320,8 @@ load_image (const char *ident, const char *filename, const char *fullname,
     case IMAGE_TYPE_JPEG:
         if (dpx_conf.verbose_level > 0)
             dpx_message("[JPEG]");
-        if (jpeg_include_image(I, handle) < 0)
+        if ((_opts.draft ? draft_include_image(I, handle, IMAGE_TYPE_JPEG)
+                         : jpeg_include_image(I, handle)) < 0)
             goto error;
         I->subtype = PDF_XOBJECT_TYPE_IMAGE;
         break;
@@ -284,14 +336,16 @@ load_image (const char *ident, const char *filename, const char *fullname,
     case IMAGE_TYPE_PNG:
         if (dpx_conf.verbose_level > 0)
             dpx_message("[PNG]");
-        if (png_include_image(I, handle) < 0)
+        if ((_opts.draft ? draft_include_image(I, handle, IMAGE_TYPE_PNG)
+                         : png_include_image(I, handle)) < 0)
             goto error;
         I->subtype = PDF_XOBJECT_TYPE_IMAGE;
         break;
     case IMAGE_TYPE_BMP:
         if (dpx_conf.verbose_level > 0)
             dpx_message("[BMP]");
-        if (bmp_include_image(I, handle) < 0)
+        if ((_opts.draft ? draft_include_image(I, handle, IMAGE_TYPE_BMP)
+                         : bmp_include_image(I, handle)) < 0)
             goto error;
         I->subtype = PDF_XOBJECT_TYPE_IMAGE;
         break;
@@ -996,6 +1050,13 @@ char *get_distiller_template (void)
     return _opts.cmdtmpl;
 }
 
+/* Tectonic: draft mode */
+
+void pdf_ximage_set_draft (int draft)
+{
+    _opts.draft = draft;
+}
+
 static int
 check_for_ps (rust_input_handle_t handle)
 {
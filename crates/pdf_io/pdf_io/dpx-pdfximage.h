@@ -84,6 +84,9 @@ int  pdf_ximage_get_page  (pdf_ximage *I);
 void set_distiller_template (char *s);
 char *get_distiller_template (void);
 
+/* Tectonic: in draft mode, raster images are replaced with placeholders */
+void pdf_ximage_set_draft (int draft);
+
 int
 pdf_ximage_scale_image (int            id,
                         pdf_tmatrix    *M, /* ret */
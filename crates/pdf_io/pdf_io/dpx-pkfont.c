@@ -520,7 +520,7 @@ pdf_font_load_pkfont (pdf_font *font)
   dpi  = truedpi(ident, point_size, base_dpi);
   handle = dpx_open_pk_font_at(ident, dpi);
   if (!handle) {
-    _tt_abort("Could not find/open PK font file: %s (at %udpi)", ident, dpi);
+    _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Could not find/open PK font file: %s (at %udpi)", ident, dpi);
   }
 
   memset(charavail, 0, 256);
@@ -878,7 +878,7 @@ pdf_font_load_truetype (pdf_font *font)
     if (handle == INVALID_HANDLE) {
         handle = dpx_open_dfont_file(ident);
         if (handle == INVALID_HANDLE)
-            _tt_abort("Unable to open TrueType/dfont font file: %s", ident); /* Should find *truetype* here */
+            _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Unable to open TrueType/dfont font file: %s", ident); /* Should find *truetype* here */
 
         sfont = dfont_open(handle, index);
     } else {
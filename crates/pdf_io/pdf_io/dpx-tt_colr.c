@@ -0,0 +1,152 @@
+/* This is dvipdfmx, an eXtended version of dvipdfm by Mark A. Wicks.
+
+    Copyright (C) 2026 the Tectonic Project
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program; if not, write to the Free Software
+    Foundation, Inc., 59 Temple Place, Suite 330, Boston, MA 02111-1307 USA.
+*/
+
+/*
+ * COLR and CPAL tables of OpenType color fonts.
+ *
+ * A version 0 COLR table describes a color glyph as a stack of layers, each
+ * an ordinary glyph drawn in a color taken from a CPAL palette. Only the
+ * first palette is used.
+ */
+
+#include "dpx-tt_colr.h"
+
+#include <stdlib.h>
+
+#include "dpx-error.h"
+#include "dpx-mem.h"
+#include "dpx-sfnt.h"
+
+#define TEXT_COLOR_INDEX 0xffff
+
+struct tt_colr_table *
+tt_read_colr_table (sfnt *sfont)
+{
+  struct tt_colr_table *colr;
+  ULONG  colr_pos, cpal_pos, bases_offset, layers_offset, colors_offset;
+  USHORT num_entries, num_palettes, num_colors, first_color;
+  BYTE  *colors;
+  int    i;
+
+  colr_pos = sfnt_find_table_pos(sfont, "COLR");
+  cpal_pos = sfnt_find_table_pos(sfont, "CPAL");
+  if (colr_pos == 0 || cpal_pos == 0)
+    return NULL;
+
+  /* CPAL: the colors of the first palette, as BGRA records. */
+  sfnt_seek_set(sfont, cpal_pos);
+  sfnt_get_ushort(sfont); /* version */
+  num_entries   = sfnt_get_ushort(sfont);
+  num_palettes  = sfnt_get_ushort(sfont);
+  num_colors    = sfnt_get_ushort(sfont);
+  colors_offset = sfnt_get_ulong(sfont);
+  if (num_palettes < 1 || num_entries < 1) {
+    dpx_warning("Empty CPAL table in color font; drawing glyph outlines instead.");
+    return NULL;
+  }
+  first_color = sfnt_get_ushort(sfont);
+  if ((ULONG) first_color + num_entries > num_colors) {
+    dpx_warning("Invalid CPAL table in color font; drawing glyph outlines instead.");
+    return NULL;
+  }
+
+  colors = NEW(4 * num_entries, BYTE);
+  sfnt_seek_set(sfont, cpal_pos + colors_offset + 4 * (ULONG) first_color);
+  sfnt_read(colors, 4 * num_entries, sfont);
+
+  /* COLR: the base glyph and layer records of version 0. Later versions
+   * start with the same fields. */
+  colr = NEW(1, struct tt_colr_table);
+  sfnt_seek_set(sfont, colr_pos);
+  sfnt_get_ushort(sfont); /* version */
+  colr->num_bases  = sfnt_get_ushort(sfont);
+  bases_offset     = sfnt_get_ulong(sfont);
+  layers_offset    = sfnt_get_ulong(sfont);
+  colr->num_layers = sfnt_get_ushort(sfont);
+
+  colr->bases = NEW(colr->num_bases, struct tt_colr_base);
+  sfnt_seek_set(sfont, colr_pos + bases_offset);
+  for (i = 0; i < colr->num_bases; i++) {
+    colr->bases[i].gid         = sfnt_get_ushort(sfont);
+    colr->bases[i].first_layer = sfnt_get_ushort(sfont);
+    colr->bases[i].num_layers  = sfnt_get_ushort(sfont);
+
+    if ((ULONG) colr->bases[i].first_layer + colr->bases[i].num_layers > colr->num_layers)
+      colr->bases[i].num_layers = 0;
+  }
+
+  colr->layers = NEW(colr->num_layers, struct tt_colr_layer);
+  sfnt_seek_set(sfont, colr_pos + layers_offset);
+  for (i = 0; i < colr->num_layers; i++) {
+    struct tt_colr_layer *layer = &colr->layers[i];
+    USHORT index;
+
+    layer->gid = sfnt_get_ushort(sfont);
+    index      = sfnt_get_ushort(sfont);
+
+    if (index == TEXT_COLOR_INDEX || index >= num_entries) {
+      layer->use_text_color = 1;
+      layer->red = layer->green = layer->blue = 0;
+      layer->alpha = 0xff;
+    } else {
+      layer->use_text_color = 0;
+      layer->blue  = colors[4 * index];
+      layer->green = colors[4 * index + 1];
+      layer->red   = colors[4 * index + 2];
+      layer->alpha = colors[4 * index + 3];
+    }
+  }
+
+  free(colors);
+  return colr;
+}
+
+void
+tt_release_colr_table (struct tt_colr_table *colr)
+{
+  if (!colr)
+    return;
+
+  free(colr->bases);
+  free(colr->layers);
+  free(colr);
+}
+
+int
+tt_colr_get_layers (struct tt_colr_table *colr, USHORT gid,
+                    const struct tt_colr_layer **layers)
+{
+  int lo = 0, hi = colr->num_bases - 1;
+
+  while (lo <= hi) {
+    int mid = (lo + hi) / 2;
+    struct tt_colr_base *base = &colr->bases[mid];
+
+    if (base->gid == gid) {
+      *layers = colr->layers + base->first_layer;
+      return base->num_layers;
+    } else if (base->gid < gid) {
+      lo = mid + 1;
+    } else {
+      hi = mid - 1;
+    }
+  }
+
+  return 0;
+}
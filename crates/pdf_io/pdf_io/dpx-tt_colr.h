@@ -0,0 +1,66 @@
+/* This is dvipdfmx, an eXtended version of dvipdfm by Mark A. Wicks.
+
+    Copyright (C) 2026 the Tectonic Project
+
+    This program is free software; you can redistribute it and/or modify
+    it under the terms of the GNU General Public License as published by
+    the Free Software Foundation; either version 2 of the License, or
+    (at your option) any later version.
+
+    This program is distributed in the hope that it will be useful,
+    but WITHOUT ANY WARRANTY; without even the implied warranty of
+    MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE.  See the
+    GNU General Public License for more details.
+
+    You should have received a copy of the GNU General Public License
+    along with this program; if not, write to the Free Software
+    Foundation, Inc., 59 Temple Place, Suite 330, Boston, MA 02111-1307 USA.
+*/
+
+#ifndef _TT_COLR_H_
+#define _TT_COLR_H_
+
+#include "tectonic_bridge_core.h"
+
+#include "dpx-sfnt.h"
+
+/* One layer of a color glyph, with its color resolved from the default
+ * (first) CPAL palette. Layers that use the text color, palette index
+ * 0xFFFF, have use_text_color set and no color of their own.
+ */
+struct tt_colr_layer
+{
+  USHORT gid;
+  BYTE   red, green, blue, alpha;
+  int    use_text_color;
+};
+
+struct tt_colr_base
+{
+  USHORT gid;
+  USHORT first_layer;
+  USHORT num_layers;
+};
+
+/* The version 0 layers of the COLR table. The paint graphs of version 1
+ * aren't supported, so glyphs that only have those are drawn from their
+ * outlines.
+ */
+struct tt_colr_table
+{
+  USHORT num_bases;
+  struct tt_colr_base  *bases;  /* Sorted by gid */
+
+  USHORT num_layers;
+  struct tt_colr_layer *layers;
+};
+
+/* Returns NULL if the font has no usable COLR and CPAL tables. */
+struct tt_colr_table *tt_read_colr_table (sfnt *sfont);
+void   tt_release_colr_table (struct tt_colr_table *colr);
+
+/* Returns the number of layers of the glyph, or 0 if it isn't a color glyph. */
+int    tt_colr_get_layers (struct tt_colr_table *colr, USHORT gid,
+                           const struct tt_colr_layer **layers);
+
+#endif /* _TT_COLR_H_ */
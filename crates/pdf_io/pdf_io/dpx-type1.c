@@ -513,7 +513,7 @@ pdf_font_load_type1 (pdf_font *font)
 
     handle = ttstub_input_open(ident, TTBC_FILE_FORMAT_TYPE1, 0);
     if (handle == INVALID_HANDLE)
-        _tt_abort("Type1: Could not open Type1 font: %s", ident);
+        _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Type1: Could not open Type1 font: %s", ident);
 
     GIDMap     = NULL;
     num_glyphs = 0;
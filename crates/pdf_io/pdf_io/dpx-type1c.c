@@ -284,7 +284,7 @@ pdf_font_load_type1c (pdf_font *font)
 
     handle = dpx_open_opentype_file(ident);
     if (!handle) {
-        _tt_abort("Could not open OpenType font: %s", ident);
+        _tt_abort_with_kind(TTBC_ABORT_FILE_OPEN, "Could not open OpenType font: %s", ident);
     }
 
     sfont = sfnt_open(handle);
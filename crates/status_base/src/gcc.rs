@@ -0,0 +1,180 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A status-reporting backend that prints messages in the style of GCC.
+//!
+//! Messages are printed as `<file>:<line>: <kind>: <message>`, which is the
+//! format that Make, editors, and many other tools know how to parse, so that
+//! they can jump to the source of a TeX error. Messages that don't refer to a
+//! location in a file are printed as `tectonic: <kind>: <message>`. Characters
+//! missing from fonts get a warning for each place where they appeared.
+
+use std::{
+    collections::HashSet,
+    fmt::Arguments,
+    io::{self, Write},
+};
+use tectonic_errors::Error;
+
+use super::{ChatterLevel, MessageKind, MissingCharacter, StatusBackend};
+
+/// A status-reporting backend that prints messages in the style of GCC.
+///
+/// Engine diagnostics that start with a `<file>:<line>:` location, as TeX
+/// errors do, are reformatted so that the location comes first.
+#[derive(Clone, Debug, Default)]
+pub struct GccStatusBackend {
+    chatter: ChatterLevel,
+    always_stderr: bool,
+}
+
+impl GccStatusBackend {
+    /// Create a new backend with the specified chatter level.
+    pub fn new(chatter: ChatterLevel) -> Self {
+        GccStatusBackend {
+            chatter,
+            always_stderr: false,
+        }
+    }
+
+    /// Configure this backend to always print to the standard error stream.
+    ///
+    /// See [`super::plain::PlainStatusBackend::always_stderr`].
+    pub fn always_stderr(&mut self, setting: bool) -> &mut Self {
+        self.always_stderr = setting;
+        self
+    }
+}
+
+impl StatusBackend for GccStatusBackend {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        if self.chatter.suppress_message(kind) {
+            return;
+        }
+
+        let line = format_message(kind, &args.to_string());
+
+        if kind == MessageKind::Note && !self.always_stderr {
+            println!("{line}");
+        } else {
+            eprintln!("{line}");
+        }
+
+        if let Some(e) = err {
+            for item in e.chain() {
+                eprintln!("tectonic: note: caused by: {item}");
+            }
+        }
+    }
+
+    fn report_error(&mut self, err: &Error) {
+        let mut chain = err.chain();
+
+        if let Some(item) = chain.next() {
+            eprintln!("{}", format_message(MessageKind::Error, &item.to_string()));
+        }
+
+        for item in chain {
+            eprintln!("tectonic: note: caused by: {item}");
+        }
+    }
+
+    fn note_highlighted(&mut self, before: &str, highlighted: &str, after: &str) {
+        self.report(
+            MessageKind::Note,
+            format_args!("{before}{highlighted}{after}"),
+            None,
+        );
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        io::stderr()
+            .write_all(output)
+            .expect("write to stderr failed");
+    }
+
+    fn report_missing_characters(&mut self, font: &str, chars: &[MissingCharacter]) {
+        let mut seen = HashSet::new();
+
+        for c in chars {
+            let message = format!(
+                "could not represent character {} in font \"{font}\"",
+                c.describe()
+            );
+            let message = match c.location() {
+                Some(location) => format!("{location}: {message}"),
+                None => message,
+            };
+
+            if seen.insert(message.clone()) {
+                self.report(MessageKind::Warning, format_args!("{message}"), None);
+            }
+        }
+    }
+}
+
+/// Format a message as a single GCC-style line.
+fn format_message(kind: MessageKind, message: &str) -> String {
+    let kind = match kind {
+        MessageKind::Note => "note",
+        MessageKind::Warning => "warning",
+        MessageKind::Error => "error",
+    };
+
+    match split_location(message) {
+        Some((location, rest)) => format!("{location}: {kind}: {rest}"),
+        None => {
+            // TeX marks diagnostics that have no location with a `!`.
+            let message = message.strip_prefix('!').unwrap_or(message).trim_start();
+            format!("tectonic: {kind}: {message}")
+        }
+    }
+}
+
+/// Split a leading `<file>:<line>` location off a message.
+fn split_location(message: &str) -> Option<(&str, &str)> {
+    let (location, rest) = message.split_once(": ")?;
+    let (file, line) = location.rsplit_once(':')?;
+
+    if file.is_empty()
+        || file.contains('\n')
+        || line.is_empty()
+        || !line.bytes().all(|b| b.is_ascii_digit())
+    {
+        return None;
+    }
+
+    Some((location, rest))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formatting() {
+        assert_eq!(
+            format_message(
+                MessageKind::Error,
+                "main.tex:12: Undefined control sequence"
+            ),
+            "main.tex:12: error: Undefined control sequence"
+        );
+        assert_eq!(
+            format_message(MessageKind::Warning, "C:\\doc\\main.tex:3: Overfull \\hbox"),
+            "C:\\doc\\main.tex:3: warning: Overfull \\hbox"
+        );
+        assert_eq!(
+            format_message(MessageKind::Error, "!Emergency stop"),
+            "tectonic: error: Emergency stop"
+        );
+        assert_eq!(
+            format_message(MessageKind::Note, "Running TeX ..."),
+            "tectonic: note: Running TeX ..."
+        );
+        assert_eq!(
+            format_message(MessageKind::Error, "main.tex:x: not a line"),
+            "tectonic: error: main.tex:x: not a line"
+        );
+    }
+}
@@ -0,0 +1,232 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! A status-reporting backend that emits machine-readable JSON.
+//!
+//! Each message or event is printed to the standard error stream as a single
+//! line holding a JSON object, so that programs wrapping Tectonic, such as
+//! graphical frontends, can follow what it's doing without having to parse
+//! human-oriented text. Every object has a `type` field:
+//!
+//! - `note`, `warning`, and `error` objects have a `message` field and a
+//!   `causes` array listing the chain of underlying errors, if any.
+//! - `error-log` objects have a `text` field holding engine log output.
+//! - `download-start` objects have `name`, `total`, and `source` fields.
+//! - `download-progress` objects have `name`, `bytes`, and `total` fields.
+//! - `download-finish` objects have `name` and `bytes` fields.
+//! - `download-fail` objects have a `name` field.
+//! - `missing-characters` objects have a `font` field and a `characters`
+//!   array, each of whose objects has `code`, `unicode`, `file`, and `line`
+//!   fields.
+//!
+//! Sizes are given in bytes, and `total` is `null` if the size of a file isn't
+//! known ahead of time. For a missing character, `code` is a Unicode scalar
+//! value if `unicode` is true and a slot in a TFM font otherwise, and `file`
+//! and `line` are `null` if it wasn't typeset from a file.
+
+use std::fmt::{Arguments, Write};
+use tectonic_errors::Error;
+
+use super::{ChatterLevel, DownloadEvent, MessageKind, MissingCharacter, StatusBackend};
+
+/// A status-reporting backend that prints messages and events as lines of
+/// JSON on the standard error stream.
+#[derive(Clone, Debug, Default)]
+pub struct JsonStatusBackend {
+    chatter: ChatterLevel,
+}
+
+impl JsonStatusBackend {
+    /// Create a new backend with the specified chatter level.
+    ///
+    /// The chatter level only affects messages: download events are always
+    /// emitted.
+    pub fn new(chatter: ChatterLevel) -> Self {
+        JsonStatusBackend { chatter }
+    }
+
+    fn emit_message(&self, kind: &str, message: &str, causes: &[String]) {
+        let mut line = format!(
+            "{{\"type\":{},\"message\":{},\"causes\":[",
+            json_string(kind),
+            json_string(message)
+        );
+
+        for (i, cause) in causes.iter().enumerate() {
+            if i > 0 {
+                line.push(',');
+            }
+
+            line.push_str(&json_string(cause));
+        }
+
+        line.push_str("]}");
+        eprintln!("{line}");
+    }
+}
+
+impl StatusBackend for JsonStatusBackend {
+    fn report(&mut self, kind: MessageKind, args: Arguments, err: Option<&Error>) {
+        if self.chatter.suppress_message(kind) {
+            return;
+        }
+
+        let kind = match kind {
+            MessageKind::Note => "note",
+            MessageKind::Warning => "warning",
+            MessageKind::Error => "error",
+        };
+
+        let causes: Vec<String> = err
+            .map(|e| e.chain().map(|item| item.to_string()).collect())
+            .unwrap_or_default();
+
+        self.emit_message(kind, &args.to_string(), &causes);
+    }
+
+    fn report_error(&mut self, err: &Error) {
+        let mut chain = err.chain().map(|item| item.to_string());
+        let message = chain.next().unwrap_or_default();
+        let causes: Vec<String> = chain.collect();
+        self.emit_message("error", &message, &causes);
+    }
+
+    fn dump_error_logs(&mut self, output: &[u8]) {
+        eprintln!(
+            "{{\"type\":\"error-log\",\"text\":{}}}",
+            json_string(&String::from_utf8_lossy(output))
+        );
+    }
+
+    fn report_download(&mut self, event: DownloadEvent<'_>) {
+        let total = |t: Option<u64>| t.map_or_else(|| "null".to_owned(), |t| t.to_string());
+
+        match event {
+            DownloadEvent::Start {
+                name,
+                total: t,
+                source,
+            } => eprintln!(
+                "{{\"type\":\"download-start\",\"name\":{},\"total\":{},\"source\":{}}}",
+                json_string(name),
+                total(t),
+                json_string(source)
+            ),
+
+            DownloadEvent::Progress {
+                name,
+                bytes,
+                total: t,
+            } => eprintln!(
+                "{{\"type\":\"download-progress\",\"name\":{},\"bytes\":{},\"total\":{}}}",
+                json_string(name),
+                bytes,
+                total(t)
+            ),
+
+            DownloadEvent::Finish { name, bytes } => eprintln!(
+                "{{\"type\":\"download-finish\",\"name\":{},\"bytes\":{}}}",
+                json_string(name),
+                bytes
+            ),
+
+            DownloadEvent::Fail { name } => eprintln!(
+                "{{\"type\":\"download-fail\",\"name\":{}}}",
+                json_string(name)
+            ),
+        }
+    }
+
+    fn report_missing_characters(&mut self, font: &str, chars: &[MissingCharacter]) {
+        eprintln!("{}", missing_characters_json(font, chars));
+    }
+}
+
+/// Format the characters missing from a font as a JSON object.
+fn missing_characters_json(font: &str, chars: &[MissingCharacter]) -> String {
+    let mut line = format!(
+        "{{\"type\":\"missing-characters\",\"font\":{},\"characters\":[",
+        json_string(font)
+    );
+
+    for (i, c) in chars.iter().enumerate() {
+        if i > 0 {
+            line.push(',');
+        }
+
+        write!(
+            line,
+            "{{\"code\":{},\"unicode\":{},\"file\":{},\"line\":{}}}",
+            c.code,
+            c.unicode,
+            c.file
+                .as_deref()
+                .map_or_else(|| "null".to_owned(), json_string),
+            c.line.map_or_else(|| "null".to_owned(), |l| l.to_string())
+        )
+        .unwrap();
+    }
+
+    line.push_str("]}");
+    line
+}
+
+/// Quote a string as a JSON string literal.
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+    result.push('"');
+
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => write!(result, "\\u{:04x}", c as u32).unwrap(),
+            c => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quoting() {
+        assert_eq!(json_string("plain"), "\"plain\"");
+        assert_eq!(
+            json_string("a \"b\"\\c\nd\u{1}é"),
+            "\"a \\\"b\\\"\\\\c\\nd\\u0001é\""
+        );
+    }
+
+    #[test]
+    fn missing_characters() {
+        let chars = [
+            MissingCharacter {
+                code: 0xE9,
+                unicode: true,
+                file: Some("main.tex".to_owned()),
+                line: Some(12),
+            },
+            MissingCharacter {
+                code: 0x41,
+                unicode: false,
+                file: None,
+                line: None,
+            },
+        ];
+
+        assert_eq!(
+            missing_characters_json("cmr10", &chars),
+            "{\"type\":\"missing-characters\",\"font\":\"cmr10\",\"characters\":[\
+             {\"code\":233,\"unicode\":true,\"file\":\"main.tex\",\"line\":12},\
+             {\"code\":65,\"unicode\":false,\"file\":null,\"line\":null}]}"
+        );
+    }
+}
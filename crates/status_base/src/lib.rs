@@ -10,6 +10,8 @@
 use std::{cmp::Ordering, fmt::Arguments, result::Result as StdResult, str::FromStr};
 use tectonic_errors::Error;
 
+pub mod gcc;
+pub mod json;
 pub mod plain;
 
 /// A kind of status message.
@@ -84,6 +86,98 @@ impl Ord for ChatterLevel {
     }
 }
 
+/// A step in the download of a file, as reported through
+/// [`StatusBackend::report_download`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum DownloadEvent<'a> {
+    /// A download is starting.
+    Start {
+        /// The name of the file being downloaded.
+        name: &'a str,
+
+        /// The size of the file in bytes, if known.
+        total: Option<u64>,
+
+        /// Where the file is being downloaded from, such as the URL of a
+        /// bundle.
+        source: &'a str,
+    },
+
+    /// Some of the file has been received.
+    Progress {
+        /// The name of the file being downloaded.
+        name: &'a str,
+
+        /// The number of bytes received so far.
+        bytes: u64,
+
+        /// The size of the file in bytes, if known.
+        total: Option<u64>,
+    },
+
+    /// The download has completed successfully.
+    Finish {
+        /// The name of the file that was downloaded.
+        name: &'a str,
+
+        /// The number of bytes received.
+        bytes: u64,
+    },
+
+    /// The download has been abandoned. The reason is reported separately as
+    /// a regular message.
+    Fail {
+        /// The name of the file that couldn't be downloaded.
+        name: &'a str,
+    },
+}
+
+/// A character that the TeX engine couldn't find in a font, as reported
+/// through [`StatusBackend::report_missing_characters`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MissingCharacter {
+    /// The character code. For OpenType and AAT fonts this is a Unicode
+    /// scalar value; for TFM fonts it's a slot in the font.
+    pub code: u32,
+
+    /// Whether [`Self::code`] is a Unicode scalar value.
+    pub unicode: bool,
+
+    /// The name of the input file in which the character was typeset, if
+    /// known.
+    pub file: Option<String>,
+
+    /// The line of [`Self::file`] on which the character was typeset, if
+    /// known.
+    pub line: Option<u32>,
+}
+
+impl MissingCharacter {
+    /// Describe the character for the user, as in `"é" (U+00E9)`.
+    pub fn describe(&self) -> String {
+        let code = if self.unicode {
+            format!("U+{:04X}", self.code)
+        } else {
+            format!("0x{:02X}", self.code)
+        };
+
+        match char::from_u32(self.code).filter(|c| !c.is_control()) {
+            Some(c) => format!("\"{c}\" ({code})"),
+            None => code,
+        }
+    }
+
+    /// Where the character was typeset, as in `main.tex:12`, if known.
+    pub fn location(&self) -> Option<String> {
+        let file = self.file.as_ref()?;
+
+        Some(match self.line {
+            Some(line) => format!("{file}:{line}"),
+            None => file.clone(),
+        })
+    }
+}
+
 /// A trait for accepting status messages.
 pub trait StatusBackend {
     /// Report a message to the status backend.
@@ -123,6 +217,100 @@ pub trait StatusBackend {
     /// should print the provided output, which may span many lines, with some
     /// clear delineation.
     fn dump_error_logs(&mut self, output: &[u8]);
+
+    /// Report the progress of a download.
+    ///
+    /// Downloads are also announced with regular notes, so the default
+    /// implementation ignores these events. Backends that feed progress
+    /// displays, such as [`json::JsonStatusBackend`], can use them to track
+    /// how far along each download is.
+    fn report_download(&mut self, _event: DownloadEvent<'_>) {}
+
+    /// Report characters that the TeX engine couldn't find in a font.
+    ///
+    /// The characters are given in the order in which they were typeset, and
+    /// one that was typeset several times appears several times. The default
+    /// implementation issues a single warning for the font that lists each
+    /// distinct character along with the first few places where it appeared.
+    fn report_missing_characters(&mut self, font: &str, chars: &[MissingCharacter]) {
+        self.report(
+            MessageKind::Warning,
+            format_args!("{}", summarize_missing_characters(font, chars)),
+            None,
+        )
+    }
+}
+
+/// Summarize the characters missing from a font in one message.
+fn summarize_missing_characters(font: &str, chars: &[MissingCharacter]) -> String {
+    const MAX_CHARS: usize = 10;
+    const MAX_LOCATIONS: usize = 3;
+
+    // The distinct characters, in order of first appearance, each with the
+    // distinct places where it appeared.
+    let mut distinct: Vec<(&MissingCharacter, Vec<String>)> = Vec::new();
+
+    for c in chars {
+        let i = match distinct
+            .iter()
+            .position(|(d, _)| d.code == c.code && d.unicode == c.unicode)
+        {
+            Some(i) => i,
+            None => {
+                distinct.push((c, Vec::new()));
+                distinct.len() - 1
+            }
+        };
+
+        if let Some(location) = c.location() {
+            if !distinct[i].1.contains(&location) {
+                distinct[i].1.push(location);
+            }
+        }
+    }
+
+    let mut message = format!(
+        "could not represent {} character{} in font \"{font}\":",
+        distinct.len(),
+        if distinct.len() == 1 { "" } else { "s" }
+    );
+
+    for (i, (c, locations)) in distinct.iter().take(MAX_CHARS).enumerate() {
+        if i > 0 {
+            message.push(';');
+        }
+
+        message.push(' ');
+        message.push_str(&c.describe());
+
+        if !locations.is_empty() {
+            message.push_str(" at ");
+            message.push_str(
+                &locations
+                    .iter()
+                    .take(MAX_LOCATIONS)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            );
+
+            let more = locations.len().saturating_sub(MAX_LOCATIONS);
+
+            if more > 0 {
+                let s = if more == 1 { "" } else { "s" };
+                message.push_str(&format!(", and {more} more place{s}"));
+            }
+        }
+    }
+
+    let more = distinct.len().saturating_sub(MAX_CHARS);
+
+    if more > 0 {
+        let s = if more == 1 { "" } else { "s" };
+        message.push_str(&format!("; and {more} other character{s}"));
+    }
+
+    message
 }
 
 /// Report a formatted informational message to the user.
@@ -177,3 +365,46 @@ impl StatusBackend for NoopStatusBackend {
     fn report(&mut self, _kind: MessageKind, _args: Arguments, _err: Option<&Error>) {}
     fn dump_error_logs(&mut self, _output: &[u8]) {}
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn missing(code: u32, file: Option<&str>, line: u32) -> MissingCharacter {
+        MissingCharacter {
+            code,
+            unicode: true,
+            file: file.map(|f| f.to_owned()),
+            line: file.map(|_| line),
+        }
+    }
+
+    #[test]
+    fn missing_characters() {
+        assert_eq!(missing(0xE9, None, 0).describe(), "\"é\" (U+00E9)");
+        assert_eq!(missing(0x7, None, 0).describe(), "U+0007");
+
+        let tfm = MissingCharacter {
+            unicode: false,
+            ..missing(0x41, Some("a.tex"), 3)
+        };
+        assert_eq!(tfm.describe(), "\"A\" (0x41)");
+        assert_eq!(tfm.location().unwrap(), "a.tex:3");
+
+        let chars = [
+            missing(0xE9, Some("main.tex"), 12),
+            missing(0xE9, Some("main.tex"), 12),
+            missing(0x1F600, Some("ch1.tex"), 4),
+            missing(0xE9, Some("ch1.tex"), 9),
+            missing(0xE9, Some("ch1.tex"), 10),
+            missing(0xE9, Some("ch1.tex"), 11),
+            missing(0xFC, None, 0),
+        ];
+        assert_eq!(
+            summarize_missing_characters("lmroman10-regular", &chars),
+            "could not represent 3 characters in font \"lmroman10-regular\": \
+             \"é\" (U+00E9) at main.tex:12, ch1.tex:9, ch1.tex:10, and 1 more place; \
+             \"😀\" (U+1F600) at ch1.tex:4; \"ü\" (U+00FC)"
+        );
+    }
+}
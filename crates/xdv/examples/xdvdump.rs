@@ -78,9 +78,10 @@ impl tectonic_xdv::XdvEvents for Stats {
         extend: Option<u32>,
         slant: Option<u32>,
         embolden: Option<u32>,
+        vertical: bool,
     ) -> Result<(), Self::Error> {
         println!(
-            "define native font: `{name}` num={font_num} size={size} faceIndex={face_index} color={color_rgba:?} extend={extend:?} slant={slant:?} embolden={embolden:?}"
+            "define native font: `{name}` num={font_num} size={size} faceIndex={face_index} color={color_rgba:?} extend={extend:?} slant={slant:?} embolden={embolden:?} vertical={vertical}"
         );
         Ok(())
     }
@@ -171,7 +171,8 @@ pub trait XdvEvents {
         Ok(())
     }
 
-    /// Handle the definition of a native font
+    /// Handle the definition of a native font. The *vertical* flag is set if
+    /// the font was loaded for vertical typesetting.
     #[allow(unused)]
     #[allow(clippy::too_many_arguments)]
     fn handle_define_native_font(
@@ -184,6 +185,7 @@ pub trait XdvEvents {
         extend: Option<u32>,
         slant: Option<u32>,
         embolden: Option<u32>,
+        vertical: bool,
     ) -> Result<(), Self::Error> {
         Ok(())
     }
@@ -691,8 +693,10 @@ impl<T: XdvEvents> XdvParser<T> {
             None
         };
 
+        let vertical = flags & NativeFontFlags::Vertical as u16 != 0;
+
         self.events.handle_define_native_font(
-            &name_str, font_num, size, face_index, color_rgba, extend, slant, embolden,
+            &name_str, font_num, size, face_index, color_rgba, extend, slant, embolden, vertical,
         )?;
         Ok(())
     }
@@ -0,0 +1,299 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! An interactive terminal browser for format files.
+//!
+//! This shows the same data as the other subcommands, but in panels that can
+//! be searched incrementally, which is a lot quicker than re-running the
+//! decoder with different `grep`s.
+
+use ratatui::{
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+    layout::{Constraint, Layout},
+    style::{Style, Stylize},
+    text::Line,
+    widgets::{Block, List, ListState, Paragraph, Tabs, Wrap},
+    DefaultTerminal, Frame,
+};
+use std::fmt::Write;
+use tectonic_errors::prelude::*;
+use tectonic_xetex_format::format::{fmt_scaled, Format};
+
+/// Browse a format until the user quits.
+pub fn run(fmt: &Format) -> Result<()> {
+    let mut browser = Browser::new(fmt);
+    let mut terminal = ratatui::init();
+    let result = browser.run(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+/// One item in a panel.
+struct Item {
+    /// The one-line summary shown in the list, which is also what searches
+    /// match against.
+    summary: String,
+
+    /// The full text shown when the item is selected.
+    detail: String,
+}
+
+impl Item {
+    fn new(summary: String) -> Self {
+        Item {
+            detail: summary.clone(),
+            summary,
+        }
+    }
+}
+
+/// A searchable list of items.
+struct Panel {
+    title: &'static str,
+    items: Vec<Item>,
+    query: String,
+    /// The indices of the items that match the query.
+    matches: Vec<usize>,
+    state: ListState,
+}
+
+impl Panel {
+    fn new(title: &'static str, items: Vec<Item>) -> Self {
+        let mut panel = Panel {
+            title,
+            items,
+            query: String::new(),
+            matches: Vec::new(),
+            state: ListState::default(),
+        };
+        panel.filter();
+        panel
+    }
+
+    /// Update the matches after the query has changed. Matching is
+    /// case-insensitive.
+    fn filter(&mut self) {
+        let query = self.query.to_lowercase();
+
+        self.matches = self
+            .items
+            .iter()
+            .enumerate()
+            .filter(|(_, item)| item.summary.to_lowercase().contains(&query))
+            .map(|(i, _)| i)
+            .collect();
+
+        self.state.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    fn move_by(&mut self, delta: isize) {
+        if let Some(n) = self.state.selected() {
+            let last = self.matches.len().saturating_sub(1);
+            self.state
+                .select(Some(n.saturating_add_signed(delta).min(last)));
+        }
+    }
+}
+
+struct Browser {
+    panels: Vec<Panel>,
+    current: usize,
+    /// Whether keystrokes are currently being added to the query.
+    searching: bool,
+    /// The number of items that fit in the list, for paging.
+    page: usize,
+}
+
+impl Browser {
+    fn new(fmt: &Format) -> Self {
+        let cseqs = fmt
+            .control_sequences(true)
+            .into_iter()
+            .map(|cs| {
+                let summary = format!("{} => {}", cs.name, cs.meaning);
+                let detail = match cs.extended {
+                    Some(e) => format!("{summary}\n\n{e}"),
+                    None => summary.clone(),
+                };
+                Item { summary, detail }
+            })
+            .collect();
+
+        let int_pars = fmt
+            .int_parameters()
+            .into_iter()
+            .map(|(name, value)| Item::new(format!("\\{name} = {value}")))
+            .collect();
+
+        let dimen_pars = fmt
+            .dimen_parameters()
+            .into_iter()
+            .map(|(name, value)| Item {
+                summary: format!("\\{name} = {}", fmt_scaled(value)),
+                detail: format!("\\{name} = {} ({value}sp)", fmt_scaled(value)),
+            })
+            .collect();
+
+        let strings = fmt
+            .strings()
+            .map(|(sp, value)| Item::new(format!("{sp} = \"{value}\"")))
+            .collect();
+
+        let languages = fmt
+            .languages()
+            .into_iter()
+            .map(|lang| {
+                let summary = format!(
+                    "language {}: {} exceptions{}",
+                    lang.number,
+                    lang.exceptions.len(),
+                    if lang.has_patterns { ", patterns" } else { "" }
+                );
+
+                let mut detail = format!("{summary}\n\ntrie ops: {}\n", lang.n_trie_ops);
+
+                if let Some(hc) = &lang.hyph_codes {
+                    writeln!(detail, "saved hyphenation codes: {}", hc.len()).unwrap();
+                }
+
+                for word in &lang.exceptions {
+                    writeln!(detail, "    {word}").unwrap();
+                }
+
+                Item { summary, detail }
+            })
+            .collect();
+
+        Browser {
+            panels: vec![
+                Panel::new("Control sequences", cseqs),
+                Panel::new("Integer parameters", int_pars),
+                Panel::new("Dimension parameters", dimen_pars),
+                Panel::new("Strings", strings),
+                Panel::new("Languages", languages),
+            ],
+            current: 0,
+            searching: false,
+            page: 1,
+        }
+    }
+
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press && !self.handle_key(key) {
+                    return Ok(());
+                }
+            }
+        }
+    }
+
+    /// Handle a keystroke, returning false if the browser should exit.
+    fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return false;
+        }
+
+        let page = self.page as isize;
+        let panel = &mut self.panels[self.current];
+
+        if self.searching {
+            match key.code {
+                KeyCode::Char(c) => {
+                    panel.query.push(c);
+                    panel.filter();
+                }
+                KeyCode::Backspace => {
+                    panel.query.pop();
+                    panel.filter();
+                }
+                KeyCode::Esc => {
+                    panel.query.clear();
+                    panel.filter();
+                    self.searching = false;
+                }
+                KeyCode::Enter => self.searching = false,
+                _ => {}
+            }
+
+            return true;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => return false,
+            KeyCode::Esc if panel.query.is_empty() => return false,
+            KeyCode::Esc => {
+                panel.query.clear();
+                panel.filter();
+            }
+            KeyCode::Char('/') => self.searching = true,
+            KeyCode::Tab | KeyCode::Right => {
+                self.current = (self.current + 1) % self.panels.len();
+            }
+            KeyCode::BackTab | KeyCode::Left => {
+                self.current = (self.current + self.panels.len() - 1) % self.panels.len();
+            }
+            KeyCode::Down | KeyCode::Char('j') => panel.move_by(1),
+            KeyCode::Up | KeyCode::Char('k') => panel.move_by(-1),
+            KeyCode::PageDown => panel.move_by(page),
+            KeyCode::PageUp => panel.move_by(-page),
+            KeyCode::Home | KeyCode::Char('g') => panel.move_by(isize::MIN),
+            KeyCode::End | KeyCode::Char('G') => panel.move_by(isize::MAX),
+            _ => {}
+        }
+
+        true
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [tabs_area, body_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(0),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        let [list_area, detail_area] =
+            Layout::horizontal([Constraint::Percentage(50); 2]).areas(body_area);
+
+        let tabs = Tabs::new(self.panels.iter().map(|p| p.title))
+            .select(self.current)
+            .highlight_style(Style::new().reversed());
+        frame.render_widget(tabs, tabs_area);
+
+        self.page = list_area.height.saturating_sub(2).max(1) as usize;
+
+        let Panel {
+            title,
+            items,
+            query,
+            matches,
+            state,
+        } = &mut self.panels[self.current];
+
+        let list = List::new(matches.iter().map(|&i| items[i].summary.as_str()))
+            .block(Block::bordered().title(format!("{title} ({}/{})", matches.len(), items.len())))
+            .highlight_style(Style::new().reversed());
+        frame.render_stateful_widget(list, list_area, state);
+
+        let detail = state
+            .selected()
+            .and_then(|n| matches.get(n))
+            .map(|&i| items[i].detail.as_str())
+            .unwrap_or_default();
+        let detail = Paragraph::new(detail)
+            .wrap(Wrap { trim: false })
+            .block(Block::bordered());
+        frame.render_widget(detail, detail_area);
+
+        let status = if self.searching {
+            format!("/{query}")
+        } else if !query.is_empty() {
+            format!("filter: {query} (/ to change, Esc to clear)")
+        } else {
+            "q: quit  /: search  Tab: next panel  arrows, PgUp, PgDn: move".to_owned()
+        };
+        frame.render_widget(Line::from(status), status_area);
+    }
+}
@@ -0,0 +1,353 @@
+// Copyright 2021 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Decode a format file.
+
+#[cfg(feature = "browse")]
+mod browse;
+
+use clap::Parser;
+use std::{fs::File, io::Read, path::PathBuf, process};
+use tectonic_errors::prelude::*;
+use tectonic_xetex_format::{
+    consistency::MAX_REPORTED,
+    container::{self, Container},
+    format::Format,
+    state::EngineState,
+};
+
+#[derive(Debug, Parser)]
+#[clap(name = "decode", about = "Decode a Tectonic format file")]
+struct Options {
+    #[command(subcommand)]
+    command: Commands,
+}
+
+impl Options {
+    fn execute(self) -> Result<()> {
+        match self.command {
+            Commands::Actives(c) => c.execute_actives(),
+            Commands::Backend(c) => c.execute_backend(),
+            #[cfg(feature = "browse")]
+            Commands::Browse(c) => c.execute_browse(),
+            Commands::Catcodes(c) => c.execute_catcodes(),
+            Commands::Check(c) => c.execute_check(),
+            Commands::CompareState(c) => c.execute(),
+            Commands::ControlSequences(c) => c.execute(),
+            Commands::Export(c) => c.execute(),
+            Commands::Info(c) => c.execute_info(),
+            Commands::Languages(c) => c.execute(),
+            Commands::Recompress(c) => c.execute(),
+            Commands::Registers(c) => c.execute(),
+            Commands::Strings(c) => c.execute_strings(),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+enum Commands {
+    /// Dump the active characters
+    Actives(GenericCommand),
+    /// Dump the saved state that affects the output once pages are shipped out
+    Backend(GenericCommand),
+    #[cfg(feature = "browse")]
+    /// Browse the control sequences, parameters, and strings interactively
+    Browse(GenericCommand),
+    /// Dump the character category codes
+    Catcodes(GenericCommand),
+    /// Check the consistency of the saved equivalents table and sparse arrays
+    Check(GenericCommand),
+    #[command(name = "compare-state")]
+    /// Compare the format with an engine state written by \tectonicdumpstate
+    CompareState(CompareStateCommand),
+    #[command(name = "cseqs")]
+    /// Dump the control sequences
+    ControlSequences(CseqsCommand),
+    /// Export the format's decodable content for external analysis
+    Export(ExportCommand),
+    /// Print the format's build metadata
+    Info(GenericCommand),
+    /// Dump the hyphenation data of each language
+    Languages(LanguagesCommand),
+    /// Write a copy of the format file compressed in a different way
+    Recompress(RecompressCommand),
+    /// Summarize the register allocations and shortcuts
+    Registers(RegistersCommand),
+    /// Dump the strings table
+    Strings(GenericCommand),
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct GenericCommand {
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+}
+
+impl GenericCommand {
+    fn parse(&self) -> Result<Format> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Format::parse(&data[..])
+    }
+
+    fn execute_actives(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_actives(&mut lock)?;
+        Ok(())
+    }
+
+    fn execute_backend(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_backend(&mut lock)?;
+        Ok(())
+    }
+
+    fn execute_catcodes(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_catcodes(&mut lock)?;
+        Ok(())
+    }
+
+    #[cfg(feature = "browse")]
+    fn execute_browse(self) -> Result<()> {
+        let fmt = self.parse()?;
+        browse::run(&fmt)
+    }
+
+    fn execute_check(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let problems = fmt.check_consistency();
+
+        for problem in &problems {
+            println!("{problem}");
+        }
+
+        if problems.is_empty() {
+            println!("no problems found");
+        } else if problems.len() == MAX_REPORTED {
+            println!("(stopped after {MAX_REPORTED} problems)");
+        }
+
+        Ok(())
+    }
+
+    fn execute_info(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_metadata(&mut lock)?;
+        Ok(())
+    }
+
+    fn execute_strings(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_string_table(&mut lock)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct CseqsCommand {
+    /// Whether to dump extended information such as macro contents
+    #[arg(long = "extended", short = 'e')]
+    extended: bool,
+
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+}
+
+impl CseqsCommand {
+    fn parse(&self) -> Result<Format> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Format::parse(&data[..])
+    }
+
+    fn execute(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_cseqs(&mut lock, self.extended)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct CompareStateCommand {
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+
+    /// The engine state filename.
+    #[arg()]
+    state_path: PathBuf,
+}
+
+impl CompareStateCommand {
+    fn execute(self) -> Result<()> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        let fmt = Format::parse(&data[..])?;
+
+        let text = std::fs::read_to_string(&self.state_path)?;
+        let state = EngineState::parse(&text)?;
+
+        let diffs = fmt.compare_state(&state)?;
+
+        for diff in &diffs {
+            println!("{diff}");
+        }
+
+        if diffs.is_empty() {
+            println!("no differences");
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct ExportCommand {
+    /// Export as JSON (currently the only supported output)
+    #[arg(long = "json", required = true)]
+    json: bool,
+
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+}
+
+impl ExportCommand {
+    fn parse(&self) -> Result<Format> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Format::parse(&data[..])
+    }
+
+    fn execute(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_json(&mut lock)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct LanguagesCommand {
+    /// Whether to also dump the saved hyphenation codes and the lowercase and
+    /// uppercase codes of all characters
+    #[arg(long = "codes", short = 'c')]
+    codes: bool,
+
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+}
+
+impl LanguagesCommand {
+    fn parse(&self) -> Result<Format> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Format::parse(&data[..])
+    }
+
+    fn execute(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_languages(&mut lock, self.codes)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct RegistersCommand {
+    /// Whether to also list every shortcut defined with \countdef, \chardef,
+    /// and the like
+    #[arg(long = "shortcuts", short = 's')]
+    shortcuts: bool,
+
+    /// The format filename.
+    #[arg()]
+    path: PathBuf,
+}
+
+impl RegistersCommand {
+    fn parse(&self) -> Result<Format> {
+        let mut file = File::open(&self.path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+        Format::parse(&data[..])
+    }
+
+    fn execute(self) -> Result<()> {
+        let fmt = self.parse()?;
+        let stdout = std::io::stdout();
+        let mut lock = stdout.lock();
+        fmt.dump_registers(&mut lock, self.shortcuts)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct RecompressCommand {
+    /// The framing to write: raw, gzip, zlib, or zstd
+    #[arg(long = "to", short = 't', default_value = "raw")]
+    to: Container,
+
+    /// The input format filename.
+    #[arg()]
+    path: PathBuf,
+
+    /// The output filename.
+    #[arg()]
+    out_path: PathBuf,
+}
+
+impl RecompressCommand {
+    fn execute(self) -> Result<()> {
+        let data = std::fs::read(&self.path)?;
+        let (from, raw) = container::decode(&data)?;
+
+        // Make sure that the result will be usable before writing it.
+        Format::parse(&raw)?;
+
+        let out = self.to.encode(&raw)?;
+        std::fs::write(&self.out_path, &out)?;
+        println!(
+            "{} ({}, {} bytes) -> {} ({}, {} bytes)",
+            self.path.display(),
+            from,
+            data.len(),
+            self.out_path.display(),
+            self.to,
+            out.len()
+        );
+        Ok(())
+    }
+}
+
+fn main() {
+    let options = Options::parse();
+
+    if let Err(e) = options.execute() {
+        eprintln!("error: {e}");
+        process::exit(1);
+    }
+}
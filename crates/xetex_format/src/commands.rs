@@ -364,6 +364,11 @@ impl Commands {
         Ok(Commands { codes })
     }
 
+    /// Whether a command code is defined in this engine version.
+    pub fn is_valid(&self, code: CommandCode) -> bool {
+        self.codes.contains_key(&code)
+    }
+
     pub fn describe(&self, code: CommandCode, arg: CommandArgument) -> String {
         if let Some(cmd) = self.codes.get(&code) {
             cmd.describe(arg)
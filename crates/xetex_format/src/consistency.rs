@@ -0,0 +1,557 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Check the internal consistency of the state saved in a format file.
+//!
+//! When the engine finds something wrong with a format file as it loads it,
+//! all that it can say is that the file is corrupted. This module checks the
+//! invariants that the engine relies on in two of the more intricate
+//! structures in a format, and describes each violation precisely:
+//!
+//! - The equivalents table (`eqtb`). Formats can only be dumped outside of all
+//!   groups, so that the save stack is empty and every entry is at save level
+//!   1, or level 0 if it's undefined. Entries must have known command codes,
+//!   and those that refer to glue, shapes, boxes, or token lists must point
+//!   into `mem`.
+//! - The sparse arrays that e-TeX uses for registers numbered above 255 and
+//!   for the XeTeX inter-character token lists. Each is a tree of index nodes
+//!   four levels deep, stored in the variable-size part of `mem`, whose nodes
+//!   record their parents, their positions, and how many children they have.
+//!
+//! Use [`Format::check_consistency`](crate::format::Format::check_consistency)
+//! to run the checks.
+
+use std::{collections::HashSet, fmt, ops::RangeInclusive};
+
+use crate::{
+    base::{self, SIZEOF_MEMORY_WORD, TEX_NULL},
+    commands::CommandCode,
+    engine::Engine,
+    eqtb::{EqtbEntry, EqtbPointer, EquivalenciesTable},
+    mem::{MemPointer, Memory},
+};
+
+/// The most inconsistencies that a check reports before giving up.
+pub const MAX_REPORTED: usize = 100;
+
+const LEVEL_ZERO: i16 = 0;
+const LEVEL_ONE: i16 = 1;
+
+const INDEX_NODE_SIZE: i32 = 33;
+const WORD_NODE_SIZE: i32 = 3;
+const POINTER_NODE_SIZE: i32 = 2;
+
+/// The depth of the leaves of a sparse array tree.
+const SA_LEAF_DEPTH: u32 = 4;
+
+/// The names of the sparse arrays saved in formats, indexed by their types
+/// (`INT_VAL` through `INTER_CHAR_VAL`).
+const SA_NAMES: &[&str] = &[
+    "\\count",
+    "\\dimen",
+    "\\skip",
+    "\\muskip",
+    "\\box",
+    "\\toks",
+    "\\XeTeXinterchartoks",
+];
+
+const DIMEN_VAL: usize = 1;
+const INTER_CHAR_VAL: usize = 6;
+
+/// The character classes used by `\XeTeXinterchartoks`.
+const CHAR_CLASS_LIMIT: i32 = 4096;
+
+/// A problem found in the state saved in a format.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Inconsistency {
+    /// Where the problem is, such as `eqtb[1234] (\skip register 3)` or
+    /// `mem[5678] (\count300)`.
+    pub location: String,
+
+    /// What the problem is.
+    pub problem: String,
+}
+
+impl fmt::Display for Inconsistency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.location, self.problem)
+    }
+}
+
+/// Accumulates the inconsistencies found in a format.
+pub(crate) struct Checker<'a> {
+    engine: &'a Engine,
+    cs_name: &'a dyn Fn(EqtbPointer) -> Option<String>,
+    found: Vec<Inconsistency>,
+
+    // Looked up once, since they're needed for every entry of eqtb.
+    undefined_cs: CommandCode,
+    pointer_cmds: [CommandCode; 3],
+    macro_cmds: RangeInclusive<CommandCode>,
+}
+
+impl<'a> Checker<'a> {
+    /// Create a checker. *cs_name* gives the name of the control sequence at
+    /// a location in `eqtb`, if it has one.
+    pub fn new(engine: &'a Engine, cs_name: &'a dyn Fn(EqtbPointer) -> Option<String>) -> Self {
+        let cmd = |name| engine.symbols.lookup(name) as CommandCode;
+
+        Checker {
+            engine,
+            cs_name,
+            found: Vec::new(),
+            undefined_cs: cmd("UNDEFINED_CS"),
+            pointer_cmds: [cmd("GLUE_REF"), cmd("SHAPE_REF"), cmd("BOX_REF")],
+            macro_cmds: cmd("CALL")..=cmd("LONG_OUTER_CALL"),
+        }
+    }
+
+    pub fn finish(self) -> Vec<Inconsistency> {
+        self.found
+    }
+
+    fn report(&mut self, location: String, problem: String) {
+        if self.found.len() < MAX_REPORTED {
+            self.found.push(Inconsistency { location, problem });
+        }
+    }
+
+    fn full(&self) -> bool {
+        self.found.len() >= MAX_REPORTED
+    }
+
+    fn symbol(&self, name: &str) -> i32 {
+        self.engine.symbols.lookup(name) as i32
+    }
+
+    /// Check the save levels, command codes, and pointers of the entries of
+    /// `eqtb` that have them.
+    ///
+    /// The integer and dimension regions at the end of the table keep their
+    /// save levels in a separate array that isn't saved, so they're skipped.
+    pub fn check_eqtb(&mut self, eqtb: &EquivalenciesTable, mem: &Memory) {
+        let n_words = (mem.mem.len() / SIZEOF_MEMORY_WORD) as MemPointer;
+        let int_base = self.symbol("INT_BASE");
+        let eqtb_size = self.symbol("EQTB_SIZE");
+        let eqtb_top = self.symbol("EQTB_TOP");
+
+        // 1 is formally ACTIVE_BASE
+        for p in (1..int_base).chain(eqtb_size + 1..=eqtb_top) {
+            if self.full() {
+                return;
+            }
+
+            if let Some(problem) = self.eqtb_entry_problem(eqtb.decode(p), n_words) {
+                let location = self.describe_eqtb(p);
+                self.report(location, problem);
+            }
+        }
+    }
+
+    fn eqtb_entry_problem(&self, entry: EqtbEntry, n_words: MemPointer) -> Option<String> {
+        let describe = || self.engine.commands.describe(entry.ty, entry.value);
+
+        if !self.engine.commands.is_valid(entry.ty) {
+            return Some(format!("has unknown command code {}", entry.ty));
+        }
+
+        if entry.level > LEVEL_ONE {
+            return Some(format!(
+                "has save level {}, but formats are saved outside of all groups at level 1; \
+                 the save stack was not unwound",
+                entry.level
+            ));
+        }
+
+        if entry.level < LEVEL_ZERO {
+            return Some(format!("has invalid save level {}", entry.level));
+        }
+
+        if entry.level == LEVEL_ZERO && entry.ty != self.undefined_cs {
+            return Some(format!(
+                "is {} at save level 0, which only undefined entries may have",
+                describe()
+            ));
+        }
+
+        let is_pointer =
+            self.pointer_cmds.contains(&entry.ty) || self.macro_cmds.contains(&entry.ty);
+
+        if is_pointer && entry.value != TEX_NULL && !(0..n_words).contains(&entry.value) {
+            return Some(format!(
+                "is {} pointing to mem[{}], outside of memory",
+                self.engine.commands.describe(entry.ty, 0),
+                entry.value
+            ));
+        }
+
+        None
+    }
+
+    /// Describe a location in `eqtb`, such as `eqtb[1234] (\skip register 3)`.
+    fn describe_eqtb(&self, p: EqtbPointer) -> String {
+        let mut regions = vec![
+            ("ACTIVE_BASE", "active character"),
+            ("SINGLE_BASE", "control sequence"),
+            ("NULL_CS", "control sequence"),
+            ("HASH_BASE", "control sequence"),
+            ("FROZEN_CONTROL_SEQUENCE", "frozen control sequence"),
+            ("PRIM_EQTB_BASE", "primitive"),
+            ("FROZEN_NULL_FONT", "font identifier"),
+            ("UNDEFINED_CONTROL_SEQUENCE", "undefined control sequence"),
+            ("GLUE_BASE", "glue parameter"),
+            ("SKIP_BASE", "\\skip register"),
+            ("MU_SKIP_BASE", "\\muskip register"),
+            ("LOCAL_BASE", "local parameter"),
+            ("TOKS_BASE", "\\toks register"),
+            ("ETEX_PEN_BASE", "e-TeX penalty parameter"),
+            ("BOX_BASE", "\\box register"),
+            ("CUR_FONT_LOC", "current font"),
+            ("MATH_FONT_BASE", "math font"),
+            ("CAT_CODE_BASE", "\\catcode"),
+            ("LC_CODE_BASE", "\\lccode"),
+            ("UC_CODE_BASE", "\\uccode"),
+            ("SF_CODE_BASE", "\\sfcode"),
+            ("MATH_CODE_BASE", "\\mathcode"),
+        ];
+
+        if self.engine.version < 33 {
+            regions.push(("CHAR_SUB_CODE_BASE", "character substitution code"));
+        }
+
+        let desc = if p > self.symbol("EQTB_SIZE") {
+            match (self.cs_name)(p) {
+                Some(name) => crate::format::fmt_csname(name),
+                None => "extra control sequence".to_owned(),
+            }
+        } else {
+            let (sym, label) = regions
+                .iter()
+                .rev()
+                .find(|(sym, _)| self.symbol(sym) <= p)
+                .copied()
+                .unwrap_or(regions[0]);
+            let offset = p - self.symbol(sym);
+
+            match sym {
+                "ACTIVE_BASE" => format!("{label} {}", crate::format::fmt_usv(offset)),
+                "SINGLE_BASE" | "NULL_CS" | "HASH_BASE" | "FROZEN_CONTROL_SEQUENCE" => {
+                    match (self.cs_name)(p) {
+                        Some(name) => crate::format::fmt_csname(name),
+                        None => format!("{label} {offset}"),
+                    }
+                }
+                "CUR_FONT_LOC" | "UNDEFINED_CONTROL_SEQUENCE" => label.to_owned(),
+                _ => format!("{label} {offset}"),
+            }
+        };
+
+        format!("eqtb[{p}] ({desc})")
+    }
+
+    /// Check the trees of the sparse arrays.
+    pub fn check_sparse_arrays(&mut self, mem: &Memory) {
+        let mut seen = HashSet::new();
+
+        for (t, root) in mem.sa_roots.iter().enumerate() {
+            if *root != TEX_NULL {
+                self.check_sa_node(mem, t, *root, TEX_NULL, 0, 0, &mut seen);
+            }
+        }
+    }
+
+    /// Check a node of a sparse array tree and its descendants. *n* is the
+    /// number formed by the positions of the node and its ancestors within
+    /// their parents, six bits per level, which for a leaf is the number of
+    /// the element that it holds.
+    #[allow(clippy::too_many_arguments)]
+    fn check_sa_node(
+        &mut self,
+        mem: &Memory,
+        t: usize,
+        p: MemPointer,
+        parent: MemPointer,
+        depth: u32,
+        n: i32,
+        seen: &mut HashSet<MemPointer>,
+    ) {
+        if self.full() {
+            return;
+        }
+
+        let name = SA_NAMES[t];
+        let is_leaf = depth == SA_LEAF_DEPTH;
+
+        let location = if !is_leaf {
+            format!("mem[{p}] (index node of the {name} sparse array)")
+        } else if t == INTER_CHAR_VAL {
+            format!(
+                "mem[{p}] ({name} {} {})",
+                n / CHAR_CLASS_LIMIT,
+                n % CHAR_CLASS_LIMIT
+            )
+        } else {
+            format!("mem[{p}] ({name}{n})")
+        };
+
+        let size = if !is_leaf {
+            INDEX_NODE_SIZE
+        } else if t <= DIMEN_VAL {
+            WORD_NODE_SIZE
+        } else {
+            POINTER_NODE_SIZE
+        };
+
+        if p < 0 || p + size - 1 > mem.lo_mem_max {
+            self.report(
+                location,
+                format!(
+                    "lies outside of the variable-size memory, which ends at mem[{}]",
+                    mem.lo_mem_max
+                ),
+            );
+            return;
+        }
+
+        if !seen.insert(p) {
+            self.report(
+                location,
+                "is linked into the sparse arrays more than once".to_owned(),
+            );
+            return;
+        }
+
+        let arr = &mem.mem[..];
+        let index = base::memword_read_b16_s1(arr, p) as i32;
+        let link = base::memword_read_b32_s1(arr, p);
+
+        if link != parent {
+            self.report(
+                location.clone(),
+                format!(
+                    "records {} as its parent, but is linked from {}",
+                    fmt_pointer(link),
+                    fmt_pointer(parent)
+                ),
+            );
+        }
+
+        let expected_index = match depth {
+            0 => t as i32,
+            SA_LEAF_DEPTH => 64 * t as i32 + n % 64,
+            _ => n % 64,
+        };
+
+        if index != expected_index {
+            self.report(
+                location.clone(),
+                format!("records index {index}, but its position calls for {expected_index}"),
+            );
+        }
+
+        if is_leaf {
+            let level = base::memword_read_b16_s0(arr, p);
+
+            if level != LEVEL_ONE {
+                self.report(
+                    location.clone(),
+                    format!(
+                        "has save level {level}, but formats are saved outside of all groups \
+                         at level 1; the save stack was not unwound"
+                    ),
+                );
+            }
+
+            if t < INTER_CHAR_VAL && n < 256 {
+                self.report(
+                    location.clone(),
+                    "should be held in eqtb rather than a sparse array".to_owned(),
+                );
+            }
+
+            let value = base::memword_read_b32_s1(arr, p + 1);
+
+            if t <= DIMEN_VAL {
+                if value != n {
+                    self.report(
+                        location,
+                        format!("records register number {value}, but is stored as number {n}"),
+                    );
+                }
+            } else {
+                let n_words = (mem.mem.len() / SIZEOF_MEMORY_WORD) as MemPointer;
+
+                if value != TEX_NULL && !(0..n_words).contains(&value) {
+                    self.report(
+                        location,
+                        format!("points to mem[{value}], outside of memory"),
+                    );
+                }
+            }
+
+            return;
+        }
+
+        let used = base::memword_read_b16_s0(arr, p) as i32;
+        let mut n_children = 0;
+
+        for i in 0..64 {
+            let child = if i % 2 == 1 {
+                base::memword_read_b32_s1(arr, p + i / 2 + 1)
+            } else {
+                base::memword_read_b32_s0(arr, p + i / 2 + 1)
+            };
+
+            if child != TEX_NULL {
+                n_children += 1;
+                self.check_sa_node(mem, t, child, p, depth + 1, n * 64 + i, seen);
+            }
+        }
+
+        if n_children == 0 {
+            self.report(
+                location,
+                "is empty, but empty index nodes are always freed".to_owned(),
+            );
+        } else if used != n_children {
+            self.report(
+                location,
+                format!("records {used} children in use, but has {n_children}"),
+            );
+        }
+    }
+}
+
+fn fmt_pointer(p: MemPointer) -> String {
+    if p == TEX_NULL {
+        "null".to_owned()
+    } else {
+        format!("mem[{p}]")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build the sparse array for `\count300`: a chain of index nodes at 100,
+    /// 140, 180, and 220, and a word node at 260.
+    fn count300() -> Memory {
+        let mut mem = Memory {
+            mem: vec![0; 400 * SIZEOF_MEMORY_WORD],
+            lo_mem_max: 300,
+            sa_roots: vec![TEX_NULL; 7],
+        };
+        mem.sa_roots[0] = 100;
+
+        let arr = &mut mem.mem[..];
+        let mut parent = TEX_NULL;
+
+        // 300 = 4 * 64 + 44
+        for (p, index, slot) in [(100, 0, 0), (140, 0, 0), (180, 0, 4), (220, 4, 44)] {
+            base::memword_write_b16_s1(arr, p, index);
+            base::memword_write_b16_s0(arr, p, 1);
+            base::memword_write_b32_s1(arr, p, parent);
+
+            for k in 1..INDEX_NODE_SIZE {
+                base::memword_write_b32_s0(arr, p + k, TEX_NULL);
+                base::memword_write_b32_s1(arr, p + k, TEX_NULL);
+            }
+
+            let child = if p == 220 { 260 } else { p + 40 };
+
+            if slot % 2 == 1 {
+                base::memword_write_b32_s1(arr, p + slot / 2 + 1, child);
+            } else {
+                base::memword_write_b32_s0(arr, p + slot / 2 + 1, child);
+            }
+
+            parent = p;
+        }
+
+        base::memword_write_b16_s1(arr, 260, 44);
+        base::memword_write_b16_s0(arr, 260, 1);
+        base::memword_write_b32_s1(arr, 260, 220);
+        base::memword_write_b32_s0(arr, 261, TEX_NULL);
+        base::memword_write_b32_s1(arr, 261, 300);
+        mem
+    }
+
+    fn check(mem: &Memory) -> Vec<String> {
+        let engine = Engine::default();
+        let cs_name = |_| None;
+        let mut checker = Checker::new(&engine, &cs_name);
+        checker.check_sparse_arrays(mem);
+        checker
+            .finish()
+            .into_iter()
+            .map(|i| i.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn sparse_arrays() {
+        let mut mem = count300();
+        assert!(check(&mem).is_empty());
+
+        base::memword_write_b16_s0(&mut mem.mem[..], 260, 2);
+        base::memword_write_b32_s1(&mut mem.mem[..], 261, 301);
+        base::memword_write_b16_s0(&mut mem.mem[..], 180, 3);
+        assert_eq!(
+            check(&mem),
+            [
+                "mem[260] (\\count300): has save level 2, but formats are saved outside of all \
+                 groups at level 1; the save stack was not unwound",
+                "mem[260] (\\count300): records register number 301, but is stored as number 300",
+                "mem[180] (index node of the \\count sparse array): records 3 children in use, \
+                 but has 1",
+            ]
+        );
+
+        let mut mem = count300();
+        base::memword_write_b32_s1(&mut mem.mem[..], 260, 180);
+        base::memword_write_b32_s0(&mut mem.mem[..], 140 + 3, 290);
+        assert_eq!(
+            check(&mem),
+            [
+                "mem[260] (\\count300): records mem[180] as its parent, but is linked from \
+                 mem[220]",
+                "mem[290] (index node of the \\count sparse array): lies outside of the \
+                 variable-size memory, which ends at mem[300]",
+                "mem[140] (index node of the \\count sparse array): records 1 children in use, \
+                 but has 2",
+            ]
+        );
+    }
+
+    #[test]
+    fn eqtb_entries() {
+        let engine = Engine::default();
+        let cs_name = |_| None;
+        let checker = Checker::new(&engine, &cs_name);
+        let sym = |s: &str| engine.symbols.lookup(s) as CommandCode;
+
+        let entry = |level, ty, value| EqtbEntry { level, ty, value };
+        let problem = |e| checker.eqtb_entry_problem(e, 1000);
+
+        assert_eq!(problem(entry(0, sym("UNDEFINED_CS"), TEX_NULL)), None);
+        assert_eq!(problem(entry(1, sym("GLUE_REF"), 0)), None);
+        assert_eq!(problem(entry(1, sym("CALL"), 999)), None);
+        assert_eq!(problem(entry(1, sym("BOX_REF"), TEX_NULL)), None);
+
+        assert!(problem(entry(2, sym("GLUE_REF"), 0))
+            .unwrap()
+            .starts_with("has save level 2"));
+        assert!(problem(entry(0, sym("GLUE_REF"), 0))
+            .unwrap()
+            .ends_with("at save level 0, which only undefined entries may have"));
+        assert!(problem(entry(1, sym("CALL"), 1000))
+            .unwrap()
+            .ends_with("pointing to mem[1000], outside of memory"));
+        assert_eq!(
+            problem(entry(1, 30000, 0)).unwrap(),
+            "has unknown command code 30000"
+        );
+    }
+}
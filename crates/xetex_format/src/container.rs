@@ -0,0 +1,241 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! The compression that a format file is stored with.
+//!
+//! The engine reads and writes format data as-is, but format files found in
+//! the wild are often compressed: web2c-based TeX distributions write them
+//! with gzip, and caches and archives may use zlib or zstd. Each of these
+//! framings starts with a distinctive signature, as does the uncompressed
+//! data, so the framing of a file can be detected reliably with
+//! [`Container::detect`]. [`Format::parse`](crate::format::Format::parse)
+//! does so automatically, and [`Container::encode`] re-compresses data so
+//! that a file can be written back in the framing that it was found in.
+//!
+//! Support for zstd requires the `zstd` Cargo feature.
+
+use flate2::{
+    read::{MultiGzDecoder, ZlibDecoder},
+    write::{GzEncoder, ZlibEncoder},
+    Compression,
+};
+use std::{
+    borrow::Cow,
+    fmt,
+    io::{Read, Write},
+    str::FromStr,
+};
+use tectonic_errors::prelude::*;
+
+const RAW_MAGIC: &[u8] = b"TTNC";
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+
+/// The framing around the data of a format file.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum Container {
+    /// Uncompressed format data, as written by the engine.
+    #[default]
+    Raw,
+
+    /// Compressed with gzip, as TeX Live's format files are.
+    Gzip,
+
+    /// Compressed into a zlib stream, without the gzip header.
+    Zlib,
+
+    /// Compressed with zstd.
+    Zstd,
+}
+
+impl Container {
+    /// All of the framings.
+    pub const ALL: &'static [Container] = &[
+        Container::Raw,
+        Container::Gzip,
+        Container::Zlib,
+        Container::Zstd,
+    ];
+
+    /// Detect the framing of *data*, from its first few bytes.
+    ///
+    /// Returns `None` if the data don't look like any of them, in which case
+    /// they aren't a format file.
+    pub fn detect(data: &[u8]) -> Option<Container> {
+        if data.starts_with(RAW_MAGIC) {
+            Some(Container::Raw)
+        } else if data.starts_with(GZIP_MAGIC) {
+            Some(Container::Gzip)
+        } else if data.starts_with(ZSTD_MAGIC) {
+            Some(Container::Zstd)
+        } else if is_zlib_header(data) {
+            Some(Container::Zlib)
+        } else {
+            None
+        }
+    }
+
+    /// Whether this build of the crate can decode and encode this framing.
+    pub fn is_supported(self) -> bool {
+        self != Container::Zstd || cfg!(feature = "zstd")
+    }
+
+    /// Get the format data out of *data*, which must be in this framing.
+    pub fn decode(self, data: &[u8]) -> Result<Cow<'_, [u8]>> {
+        let mut out = Vec::new();
+
+        match self {
+            Container::Raw => return Ok(Cow::Borrowed(data)),
+
+            Container::Gzip => {
+                atry!(
+                    MultiGzDecoder::new(data).read_to_end(&mut out);
+                    ["failed to decompress gzipped format data"]
+                );
+            }
+
+            Container::Zlib => {
+                atry!(
+                    ZlibDecoder::new(data).read_to_end(&mut out);
+                    ["failed to decompress zlib format data"]
+                );
+            }
+
+            Container::Zstd => {
+                out = zstd_decode(data)?;
+            }
+        }
+
+        Ok(Cow::Owned(out))
+    }
+
+    /// Put the format data *data* into this framing, so that it can be
+    /// written out.
+    pub fn encode(self, data: &[u8]) -> Result<Vec<u8>> {
+        match self {
+            Container::Raw => Ok(data.to_vec()),
+
+            Container::Gzip => {
+                let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+                enc.write_all(data)?;
+                Ok(enc.finish()?)
+            }
+
+            Container::Zlib => {
+                let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+                enc.write_all(data)?;
+                Ok(enc.finish()?)
+            }
+
+            Container::Zstd => zstd_encode(data),
+        }
+    }
+}
+
+impl fmt::Display for Container {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Container::Raw => "raw",
+            Container::Gzip => "gzip",
+            Container::Zlib => "zlib",
+            Container::Zstd => "zstd",
+        })
+    }
+}
+
+impl FromStr for Container {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "raw" | "none" => Container::Raw,
+            "gzip" | "gz" => Container::Gzip,
+            "zlib" => Container::Zlib,
+            "zstd" | "zst" => Container::Zstd,
+            _ => bail!("unknown format file framing `{s}`; expected raw, gzip, zlib, or zstd"),
+        })
+    }
+}
+
+/// Detect the framing of *data* and get the format data out of it.
+pub fn decode(data: &[u8]) -> Result<(Container, Cow<'_, [u8]>)> {
+    let Some(container) = Container::detect(data) else {
+        bail!("not a format file: the data aren't in any known framing");
+    };
+
+    Ok((container, container.decode(data)?))
+}
+
+/// Check for the two-byte zlib header: deflate with a window of at most
+/// 32 KiB, no preset dictionary, and a valid check value.
+fn is_zlib_header(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => {
+            cmf & 0x0F == 8
+                && cmf >> 4 <= 7
+                && flg & 0x20 == 0
+                && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0
+        }
+        _ => false,
+    }
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_decode(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(atry!(
+        zstd::stream::decode_all(data);
+        ["failed to decompress zstd format data"]
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_decode(_data: &[u8]) -> Result<Vec<u8>> {
+    bail!(
+        "the format data are compressed with zstd, but this program was built without zstd support"
+    )
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_encode(data: &[u8]) -> Result<Vec<u8>> {
+    Ok(atry!(
+        zstd::stream::encode_all(data, 0);
+        ["failed to compress format data with zstd"]
+    ))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_encode(_data: &[u8]) -> Result<Vec<u8>> {
+    bail!("cannot compress format data with zstd: this program was built without zstd support")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        let data = b"TTNC\x00\x00\x00\x21 and the rest of the format".repeat(10);
+
+        for &container in Container::ALL {
+            if !container.is_supported() {
+                assert!(container.encode(&data).is_err());
+                continue;
+            }
+
+            let framed = container.encode(&data).unwrap();
+            assert_eq!(Container::detect(&framed), Some(container));
+
+            let (detected, decoded) = decode(&framed).unwrap();
+            assert_eq!(detected, container);
+            assert_eq!(decoded, &data[..]);
+            assert_eq!(
+                container.to_string().parse::<Container>().unwrap(),
+                container
+            );
+        }
+
+        assert_eq!(Container::detect(b"%PDF-1.5"), None);
+        assert!(decode(b"").is_err());
+        assert!(Container::Gzip.decode(b"\x1F\x8B truncated").is_err());
+    }
+}
@@ -213,6 +213,6 @@ impl ControlSeqHash {
         // `if (text(p) >= str_ptr) => "NONEXISTENT."`
 
         let (text_ptr, _next_ptr) = self.decode(p);
-        Some(strings.lookup(text_ptr).to_owned())
+        strings.get(text_ptr).map(|s| s.to_owned())
     }
 }
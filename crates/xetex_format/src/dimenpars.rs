@@ -11,9 +11,85 @@ use crate::{
     FormatVersion,
 };
 
+/// A dimensional parameter defined by the engine.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DimenPar {
+    /// The `\parindent` parameter.
+    ParIndent,
+
+    /// The `\mathsurround` parameter.
+    MathSurround,
+
+    /// The `\lineskiplimit` parameter.
+    LineSkipLimit,
+
+    /// The `\hsize` parameter.
+    Hsize,
+
+    /// The `\vsize` parameter.
+    Vsize,
+
+    /// The `\maxdepth` parameter.
+    MaxDepth,
+
+    /// The `\splitmaxdepth` parameter.
+    SplitMaxDepth,
+
+    /// The `\boxmaxdepth` parameter.
+    BoxMaxDepth,
+
+    /// The `\hfuzz` parameter.
+    Hfuzz,
+
+    /// The `\vfuzz` parameter.
+    Vfuzz,
+
+    /// The `\delimitershortfall` parameter.
+    DelimiterShortfall,
+
+    /// The `\nulldelimiterspace` parameter.
+    NullDelimiterSpace,
+
+    /// The `\scriptspace` parameter.
+    ScriptSpace,
+
+    /// The `\predisplaysize` parameter.
+    PreDisplaySize,
+
+    /// The `\displaywidth` parameter.
+    DisplayWidth,
+
+    /// The `\displayindent` parameter.
+    DisplayIndent,
+
+    /// The `\overfullrule` parameter.
+    OverfullRule,
+
+    /// The `\hangindent` parameter.
+    HangIndent,
+
+    /// The `\hoffset` parameter.
+    HOffset,
+
+    /// The `\voffset` parameter.
+    VOffset,
+
+    /// The `\emergencystretch` parameter.
+    EmergencyStretch,
+
+    /// The `\pdfpagewidth` parameter.
+    PdfPageWidth,
+
+    /// The `\pdfpageheight` parameter.
+    PdfPageHeight,
+}
+
 /// Information about dimensional parameters.
 #[derive(Clone, Copy, Debug)]
-pub struct DimenPar {
+pub struct DimenParInfo {
+    /// The parameter.
+    par: DimenPar,
+
     /// The name of the parameter.
     name: &'static str,
 
@@ -21,96 +97,137 @@ pub struct DimenPar {
     since: FormatVersion,
 }
 
-const DIMEN_PARS: &[DimenPar] = &[
-    DimenPar {
+impl DimenParInfo {
+    /// Get the parameter that this information describes.
+    pub fn par(&self) -> DimenPar {
+        self.par
+    }
+
+    /// Get the name of the parameter.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the name of the primitive that accesses the parameter, without the
+    /// leading backslash.
+    pub fn primitive_name(&self) -> String {
+        self.name.replace('_', "")
+    }
+}
+
+const DIMEN_PARS: &[DimenParInfo] = &[
+    DimenParInfo {
+        par: DimenPar::ParIndent,
         name: "par_indent",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::MathSurround,
         name: "math_surround",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::LineSkipLimit,
         name: "line_skip_limit",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::Hsize,
         name: "hsize",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::Vsize,
         name: "vsize",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::MaxDepth,
         name: "max_depth",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::SplitMaxDepth,
         name: "split_max_depth",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::BoxMaxDepth,
         name: "box_max_depth",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::Hfuzz,
         name: "hfuzz",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::Vfuzz,
         name: "vfuzz",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::DelimiterShortfall,
         name: "delimiter_shortfall",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::NullDelimiterSpace,
         name: "null_delimiter_space",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::ScriptSpace,
         name: "script_space",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::PreDisplaySize,
         name: "pre_display_size",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::DisplayWidth,
         name: "display_width",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::DisplayIndent,
         name: "display_indent",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::OverfullRule,
         name: "overfull_rule",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::HangIndent,
         name: "hang_indent",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::HOffset,
         name: "h_offset",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::VOffset,
         name: "v_offset",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::EmergencyStretch,
         name: "emergency_stretch",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::PdfPageWidth,
         name: "pdf_page_width",
         since: 0,
     },
-    DimenPar {
+    DimenParInfo {
+        par: DimenPar::PdfPageHeight,
         name: "pdf_page_height",
         since: 0,
     },
@@ -121,7 +238,7 @@ const DIMEN_PARS: &[DimenPar] = &[
 pub fn get_dimenpars_for_version(
     version: FormatVersion,
     symbols: &mut SymbolTable,
-) -> Result<Vec<DimenPar>> {
+) -> Result<Vec<DimenParInfo>> {
     let mut r = Vec::new();
     let mut n = 0;
 
@@ -142,12 +259,12 @@ pub fn get_dimenpars_for_version(
 }
 
 /// Emit initializers for dimenpar primitives in the C header.
-pub fn emit_c_header_primitives<W: Write>(pars: &[DimenPar], mut stream: W) -> Result<()> {
+pub fn emit_c_header_primitives<W: Write>(pars: &[DimenParInfo], mut stream: W) -> Result<()> {
     for par in pars {
         writeln!(
             stream,
             "    {{ \"{}\", ASSIGN_DIMEN, DIMEN_BASE + DIMEN_PAR__{}, xf_prim_init_none }}, \\",
-            par.name.replace('_', ""),
+            par.primitive_name(),
             par.name.to_lowercase(),
         )?;
     }
@@ -26,10 +26,10 @@ pub struct Engine {
     pub commands: Commands,
 
     /// The integer parameters defined in this engine implementation.
-    pub int_pars: Vec<intpars::IntPar>,
+    pub int_pars: Vec<intpars::IntParInfo>,
 
     /// The dimension parameters defined in this engine implementation.
-    pub dimen_pars: Vec<dimenpars::DimenPar>,
+    pub dimen_pars: Vec<dimenpars::DimenParInfo>,
 
     /// The glue parameters defined in this engine implementation.
     pub glue_pars: Vec<gluepars::GluePar>,
@@ -466,6 +466,51 @@ mod tests {
         );
     }
 
+    #[test]
+    fn check_version_34() {
+        let eng = Engine::new_for_version(34).unwrap();
+
+        check!(
+            eng [
+                TECTONIC_FORMAT_VERSION = 34,
+                PRIM_SIZE = 2100,
+
+                // eqtb
+                UNDEFINED_CONTROL_SEQUENCE = 2_254_339,
+                EQTB_SIZE = 8_941_458,
+
+                // CONVERT codes
+                XETEX_CONVERT_CODES = 40,
+                JOB_NAME_CODE = 40,
+                TECTONIC_GETENV_CODE = 41,
+
+                // EXTENSION codes
+                XETEX_LINEBREAK_LOCALE_EXTENSION_CODE = 46,
+                TECTONIC_DUMP_STATE_EXTENSION_CODE = 47,
+            ]
+        );
+    }
+
+    // The typed parameter accessors of `Format` locate parameters by their
+    // position in the engine's lists, which must agree with the symbols used by
+    // the C code.
+    #[test]
+    fn par_positions() {
+        for version in 31..=LATEST_VERSION {
+            let eng = Engine::new_for_version(version).unwrap();
+
+            for (i, p) in eng.int_pars.iter().enumerate() {
+                let sym = format!("INT_PAR__{}", p.name().to_lowercase());
+                assert_eq!(eng.symbols.lookup(&sym), i as isize);
+            }
+
+            for (i, p) in eng.dimen_pars.iter().enumerate() {
+                let sym = format!("DIMEN_PAR__{}", p.name().to_lowercase());
+                assert_eq!(eng.symbols.lookup(&sym), i as isize);
+            }
+        }
+    }
+
     /// Check the various "bad" conditions tested in the classical
     /// implementation. Most of them have to do with parameters like
     /// `min_halfword` that we don't change, so there are only a few to check.
@@ -357,6 +357,7 @@ declare! {
         var XetexUCharCat XETEX_UCHARCAT_CODE Ucharcat 0 [Next],
         var JobName JOB_NAME_CODE jobname 0 [Next],
         not var XETEX_CONVERT_CODES _ 0 [Same],
+        var TectonicGetenv TECTONIC_GETENV_CODE tectonicgetenv 34 [Next],
     }
 }
 
@@ -378,6 +379,7 @@ declare! {
         var XetexInputEncoding XETEX_INPUT_ENCODING_EXTENSION_CODE XeTeXinputencoding 0 [Next],
         var XetexDefaultEncoding XETEX_DEFAULT_ENCODING_EXTENSION_CODE XeTeXdefaultencoding 0 [Next],
         var XetexLinebreakLocale XETEX_LINEBREAK_LOCALE_EXTENSION_CODE XeTeXlinebreaklocale 0 [Next],
+        var TectonicDumpState TECTONIC_DUMP_STATE_EXTENSION_CODE tectonicdumpstate 34 [Next],
     }
 }
 
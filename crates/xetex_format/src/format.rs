@@ -10,25 +10,41 @@ use nom::{
     number::complete::{be_i16, be_i32, be_i64, be_u16},
     Err as NomErr, IResult, Parser,
 };
-use std::io::Write;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt,
+    io::Write,
+};
 use tectonic_errors::prelude::*;
 
 use crate::{
-    base::{MAX_HALFWORD, MIN_HALFWORD, TEX_NULL},
+    base::{MAX_HALFWORD, MIN_HALFWORD, NUMBER_REGS, SIZEOF_MEMORY_WORD, TEX_NULL},
     catcodes::CatCode,
     commands::CommandCode,
+    consistency::{Checker, Inconsistency},
+    container::{self, Container},
     cshash,
+    dimenpars::DimenPar,
     engine::Engine,
     eqtb::{self, EqtbPointer},
-    mem, parseutils, stringtable,
+    intpars::IntPar,
+    languages::{HyphenationTables, Language, BIGGEST_LANG},
+    locals::LocalParKind,
+    mem, parseutils,
+    registers::{self, Allocation, AllocationKind, RegisterShortcut, ShortcutKind},
+    state::{self, CharCodeKind, EngineState, StateDifference, StateKey, StateValue},
+    stringtable::{self, StrPointer},
     tokenlist::Token,
     FormatVersion,
 };
 
 /// Saved Tectonic/XeTeX engine state, decoded into memory.
 ///
-/// This public API of this structure isn't yet complete. It parses format files
-/// but does not yet provide proper runtime introspection of the results.
+/// This public API of this structure isn't yet complete. Registers, parameters,
+/// character codes, and hyphenation data can be queried with typed accessors
+/// such as [`Self::count_register`], [`Self::dimen_parameter`],
+/// [`Self::cat_code`], and [`Self::languages`], but other saved state, such as
+/// glue and token lists, isn't yet exposed.
 #[derive(Debug)]
 #[allow(dead_code)] // TEMPORARY!
 pub struct Format {
@@ -37,6 +53,196 @@ pub struct Format {
     mem: mem::Memory,
     eqtb: eqtb::EquivalenciesTable,
     cshash: cshash::ControlSeqHash,
+    hyph: HyphenationTables,
+    container: Container,
+    scalars: RawScalars,
+}
+
+/// The scalars saved outside of the main tables, as they appear in the file.
+#[derive(Clone, Copy, Debug)]
+struct RawScalars {
+    hash_high: i32,
+    hyph_prime: i32,
+    par_loc: EqtbPointer,
+    write_loc: EqtbPointer,
+    font_ptr: i32,
+    max_hyph_char: i32,
+}
+
+/// The miscellaneous scalars saved in a format alongside its main tables, as
+/// returned by [`Format::saved_scalars`].
+///
+/// Unlike Knuth's TeX, Tectonic doesn't save the interaction mode or the
+/// format identification banner in its formats: the engine always starts in
+/// the interaction mode that it's configured with, and the job name comes
+/// from the primary input. The banner of the macro package is available as
+/// [`FormatMetadata::banner`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SavedScalars {
+    /// The number of control sequences stored beyond the main hash table.
+    pub hash_high: i32,
+
+    /// The size of the hash table of hyphenation exceptions.
+    pub hyph_prime: i32,
+
+    /// The control sequence that the engine inserts to end paragraphs,
+    /// formatted with [`fmt_csname`]. This is `\par` unless the primitive
+    /// was renamed while the format was built.
+    pub par_token: String,
+
+    /// The control sequence that the engine uses for `\write`, formatted
+    /// with [`fmt_csname`].
+    pub write_token: String,
+
+    /// The number of fonts loaded, including the null font.
+    pub fonts: usize,
+
+    /// One more than the largest character code in the hyphenation
+    /// patterns.
+    pub max_hyph_char: i32,
+}
+
+/// The state saved in a format that affects the output of the engine once
+/// pages are shipped out, as returned by [`Format::backend_state`].
+///
+/// XeTeX writes XDV files that `xdvipdfmx` turns into PDF, so it has no
+/// equivalents of pdfTeX's `\pdfcompresslevel` or LuaTeX's `\pdfvariable`:
+/// the compression level and similar settings of the PDF come from the
+/// driver's configuration, not from the format. What a format can fix are the
+/// parameters below, and the `\special`s that its macros issue, such as the
+/// `papersize` and `pdf:` specials with which macro packages pass settings to
+/// the driver.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BackendState {
+    /// The value of `\pdfoutput`. XeTeX writes XDV either way, but macro
+    /// packages test this parameter to choose their drivers.
+    pub pdf_output: i32,
+
+    /// The value of `\mag`, by which the driver scales the output, in
+    /// thousandths.
+    pub mag: i32,
+
+    /// The value of `\pdfpagewidth`, in scaled points. If it's zero, the
+    /// page width comes from `papersize` specials or the driver's default.
+    pub page_width: i32,
+
+    /// The value of `\pdfpageheight`, in scaled points. If it's zero, the
+    /// page height comes from `papersize` specials or the driver's default.
+    pub page_height: i32,
+
+    /// The value of `\hoffset`, in scaled points.
+    pub h_offset: i32,
+
+    /// The value of `\voffset`, in scaled points.
+    pub v_offset: i32,
+
+    /// Whether `\XeTeXgenerateactualtext` is on, so that the text of
+    /// shaped words is given to the driver for copying and searching.
+    pub generate_actual_text: bool,
+
+    /// The value of `\synctex`, which turns on the writing of SyncTeX data
+    /// alongside the output.
+    pub synctex: i32,
+
+    /// The `\special`s that macros and token list parameters issue, in the
+    /// order of the control sequences and then of the parameters.
+    pub specials: Vec<SavedSpecial>,
+}
+
+/// A `\special` that a macro or token list parameter saved in a format
+/// issues, as listed in [`BackendState::specials`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SavedSpecial {
+    /// The name of the macro or token list parameter, such as `\output`,
+    /// formatted with [`fmt_csname`].
+    pub location: String,
+
+    /// The text of the special, with control sequences and macro parameters
+    /// as they appear in the definition, or `None` if its argument isn't
+    /// given as a braced group, such as when it comes from expanding another
+    /// macro.
+    pub text: Option<String>,
+}
+
+/// A control sequence defined in a format, as returned by
+/// [`Format::control_sequences`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ControlSequence {
+    /// The name of the control sequence, formatted with [`fmt_csname`].
+    pub name: String,
+
+    /// A description of the meaning of the control sequence.
+    pub meaning: String,
+
+    /// Extended information about the meaning, such as the contents of a
+    /// macro, if it was requested and there is any.
+    pub extended: Option<String>,
+}
+
+/// Information about how a format was built, as returned by
+/// [`Format::metadata`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct FormatMetadata {
+    /// The version of the format file layout. This changes whenever the
+    /// engine's primitives or memory layout change, so it identifies the
+    /// engine revision that wrote the format.
+    pub version: FormatVersion,
+
+    /// The name and version of the macro package in the format, such as
+    /// `LaTeX2e 2022-11-01`, taken from the `\fmtname` and `\fmtversion`
+    /// macros. This is `None` if the format doesn't define `\fmtname`.
+    pub banner: Option<String>,
+
+    /// When the format was dumped, according to the engine.
+    pub dump_time: DumpTime,
+
+    /// Whether the e-TeX mixed-direction typesetting of `\TeXXeTstate` was
+    /// enabled.
+    pub texxet: bool,
+
+    /// Whether XeTeX's inter-character token insertion,
+    /// `\XeTeXinterchartokenstate`, was enabled.
+    pub xetex_inter_char_tokens: bool,
+
+    /// Whether XeTeX's `\XeTeXuseglyphmetrics` was enabled.
+    pub xetex_use_glyph_metrics: bool,
+
+    /// Whether XeTeX's `\XeTeXupwardsmode` was enabled.
+    pub xetex_upwards: bool,
+}
+
+/// The time at which a format was dumped, from the values of the `\year`,
+/// `\month`, `\day`, and `\time` parameters saved in it.
+///
+/// These come from the build date given to the engine, so they're fixed in
+/// deterministic builds.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct DumpTime {
+    /// The year.
+    pub year: i32,
+
+    /// The month, from 1 to 12.
+    pub month: i32,
+
+    /// The day of the month, from 1 to 31.
+    pub day: i32,
+
+    /// The number of minutes since midnight.
+    pub minutes: i32,
+}
+
+impl fmt::Display for DumpTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:04}-{:02}-{:02} {:02}:{:02}",
+            self.year,
+            self.month,
+            self.day,
+            self.minutes / 60,
+            self.minutes % 60
+        )
+    }
 }
 
 // Parsing
@@ -49,13 +255,18 @@ const HYPH_SIZE: usize = 8191;
 
 const TRIE_OP_SIZE: i32 = 35111;
 
-const BIGGEST_LANG: usize = 255;
-
 const MAX_USV: i32 = crate::base::NUMBER_USVS as i32;
 
 impl Format {
+    /// Parse the contents of a format file.
+    ///
+    /// The data may be compressed in any of the framings described in the
+    /// [`container`] module, which is detected automatically and can be
+    /// retrieved with [`Self::container`].
     pub fn parse(input: &[u8]) -> Result<Self> {
-        let (input, serial) = match parse_header(input) {
+        let (container, data) = container::decode(input)?;
+
+        let (input, serial) = match parse_header(&data) {
             Ok(t) => t,
             Err(NomErr::Error(inner)) => bail!("parse error: {}", inner.code.description()),
             Err(NomErr::Failure(inner)) => bail!("parse failure: {}", inner.code.description()),
@@ -65,7 +276,10 @@ impl Format {
         let engine = Engine::new_for_version(serial as FormatVersion)?;
 
         match parse_body(engine, input) {
-            Ok((_remainder, result)) => Ok(result),
+            Ok((_remainder, mut result)) => {
+                result.container = container;
+                Ok(result)
+            }
             Err(NomErr::Error(inner)) => bail!("parse error: {}", inner.code.description()),
             Err(NomErr::Failure(inner)) => bail!("parse failure: {}", inner.code.description()),
             Err(NomErr::Incomplete(_)) => bail!("incomplete input"),
@@ -73,8 +287,7 @@ impl Format {
     }
 
     pub fn dump_string_table<W: Write>(&self, stream: &mut W) -> Result<()> {
-        for sp in self.strings.all_sps() {
-            let value = self.strings.lookup(sp);
+        for (sp, value) in self.strings() {
             writeln!(stream, "{sp} = \"{value}\"")?;
         }
 
@@ -82,24 +295,13 @@ impl Format {
     }
 
     pub fn dump_actives<W: Write>(&self, stream: &mut W) -> Result<()> {
-        let undefined_cs_cmd = self.engine.symbols.lookup("UNDEFINED_CS") as CommandCode;
-
-        for chr in valid_usvs() {
-            let entry = self.eqtb_active(chr);
-
-            if entry.ty == undefined_cs_cmd {
-                continue;
-            }
-
-            let cur_cat = self.eqtb_catcode(chr)?;
-            let cmd_desc = self.engine.commands.describe(entry.ty, entry.value);
-
+        for (chr, meaning) in self.active_characters() {
             writeln!(
                 stream,
                 "{} ({}) => {}",
-                fmt_usv(chr),
-                cur_cat.abbrev(),
-                cmd_desc
+                fmt_usv(chr as i32),
+                self.cat_code(chr)?.abbrev(),
+                meaning
             )?;
         }
 
@@ -148,33 +350,923 @@ impl Format {
     }
 
     pub fn dump_cseqs<W: Write>(&self, stream: &mut W, extended: bool) -> Result<()> {
+        for cs in self.control_sequences(extended) {
+            writeln!(stream, "{} => {}", cs.name, cs.meaning)?;
+
+            if let Some(e) = cs.extended {
+                writeln!(stream, "--------\n{e}\n--------")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn dump_languages<W: Write>(&self, stream: &mut W, codes: bool) -> Result<()> {
+        let current = self.int_parameter(IntPar::Language)?;
+        let pars = [
+            ("language", IntPar::Language),
+            ("lefthyphenmin", IntPar::LeftHyphenMin),
+            ("righthyphenmin", IntPar::RightHyphenMin),
+            ("uchyph", IntPar::UcHyph),
+            ("savinghyphcodes", IntPar::SavingHyphCodes),
+        ];
+
+        for (name, par) in pars {
+            if let Ok(value) = self.int_parameter(par) {
+                writeln!(stream, "\\{name} = {value}")?;
+            }
+        }
+
+        for lang in self.languages() {
+            let note = if lang.number as i32 == current {
+                " (current)"
+            } else {
+                ""
+            };
+
+            writeln!(stream)?;
+            writeln!(stream, "language {}{}:", lang.number, note)?;
+
+            if lang.has_patterns {
+                writeln!(stream, "    patterns: {} trie ops", lang.n_trie_ops)?;
+            } else {
+                writeln!(stream, "    patterns: none")?;
+            }
+
+            match &lang.hyph_codes {
+                Some(hc) => writeln!(stream, "    hyphenation codes: {} saved", hc.len())?,
+                None => writeln!(stream, "    hyphenation codes: current \\lccode values")?,
+            }
+
+            writeln!(stream, "    exceptions ({}):", lang.exceptions.len())?;
+
+            for word in &lang.exceptions {
+                writeln!(stream, "        {word}")?;
+            }
+
+            if let (true, Some(hc)) = (codes, &lang.hyph_codes) {
+                writeln!(stream, "    saved hyphenation codes:")?;
+
+                for (c, code) in hc {
+                    writeln!(
+                        stream,
+                        "        {} => {}",
+                        fmt_usv(*c as i32),
+                        fmt_usv(*code)
+                    )?;
+                }
+            }
+        }
+
+        if codes {
+            writeln!(stream)?;
+            writeln!(stream, "character codes:")?;
+
+            for chr in valid_usvs() {
+                let c = char::from_u32(chr as u32).unwrap();
+                let lc = self.lc_code(c);
+                let uc = self.uc_code(c);
+
+                if lc != 0 || uc != 0 {
+                    writeln!(
+                        stream,
+                        "    {}: lc = {}, uc = {}",
+                        fmt_usv(chr),
+                        fmt_code(lc),
+                        fmt_code(uc)
+                    )?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn dump_registers<W: Write>(&self, stream: &mut W, shortcuts: bool) -> Result<()> {
+        let all_shortcuts = self.register_shortcuts();
+
+        match self.register_allocations() {
+            Some(allocations) => {
+                writeln!(stream, "allocations:")?;
+
+                for a in &allocations {
+                    writeln!(
+                        stream,
+                        "    \\{} (\\count{}): last {}, limit {}, {} remaining",
+                        a.kind.primitive_name(),
+                        a.kind.counter(),
+                        a.last,
+                        a.limit,
+                        a.remaining()
+                    )?;
+                }
+            }
+            None => writeln!(stream, "allocations: not tracked by this format")?,
+        }
+
+        writeln!(stream)?;
+        writeln!(stream, "shortcuts:")?;
+
+        let mut counts: BTreeMap<ShortcutKind, (usize, usize)> = BTreeMap::new();
+
+        for sc in &all_shortcuts {
+            let c = counts.entry(sc.kind).or_default();
+            c.0 += 1;
+
+            if sc.number >= NUMBER_REGS as i32 {
+                c.1 += 1;
+            }
+        }
+
+        for (kind, (n, extended)) in counts {
+            match kind {
+                ShortcutKind::Char | ShortcutKind::MathChar | ShortcutKind::XetexMathChar => {
+                    writeln!(stream, "    \\{}: {}", kind.primitive_name(), n)?
+                }
+                _ => writeln!(
+                    stream,
+                    "    \\{}: {} ({} above {})",
+                    kind.primitive_name(),
+                    n,
+                    extended,
+                    NUMBER_REGS - 1
+                )?,
+            }
+        }
+
+        if shortcuts {
+            writeln!(stream)?;
+
+            for sc in &all_shortcuts {
+                writeln!(stream, "{sc}")?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // Typed accessors. The eqtb is kept in its raw saved form and entries are
+    // decoded on demand; these methods take care of locating the relevant
+    // region for this format's engine version.
+
+    /// Get the value of a `\count` register.
+    pub fn count_register(&self, n: usize) -> Result<i32> {
+        Ok(self.eqtb.decode(self.eqtb_register("COUNT_BASE", n)?).value)
+    }
+
+    /// Get the value of a `\dimen` register, in scaled points.
+    pub fn dimen_register(&self, n: usize) -> Result<i32> {
+        Ok(self
+            .eqtb
+            .decode(self.eqtb_register("SCALED_BASE", n)?)
+            .value)
+    }
+
+    /// Get the value of an integer parameter.
+    ///
+    /// Returns an error if the parameter isn't defined in this format's engine
+    /// version.
+    pub fn int_parameter(&self, par: IntPar) -> Result<i32> {
+        let Some(index) = self.engine.int_pars.iter().position(|p| p.par() == par) else {
+            bail!(
+                "integer parameter {:?} is not defined in format version {}",
+                par,
+                self.engine.version
+            );
+        };
+
+        Ok(self
+            .eqtb
+            .decode(self.eqtb_base("INT_BASE") + index as EqtbPointer)
+            .value)
+    }
+
+    /// Get the value of a dimensional parameter, in scaled points.
+    ///
+    /// Returns an error if the parameter isn't defined in this format's engine
+    /// version.
+    pub fn dimen_parameter(&self, par: DimenPar) -> Result<i32> {
+        let Some(index) = self.engine.dimen_pars.iter().position(|p| p.par() == par) else {
+            bail!(
+                "dimension parameter {:?} is not defined in format version {}",
+                par,
+                self.engine.version
+            );
+        };
+
+        Ok(self
+            .eqtb
+            .decode(self.eqtb_base("DIMEN_BASE") + index as EqtbPointer)
+            .value)
+    }
+
+    /// Get the category code of a character.
+    pub fn cat_code(&self, c: char) -> Result<CatCode> {
+        self.eqtb_catcode(c as i32)
+    }
+
+    /// Get the `\lccode` of a character. Zero means that the character has no
+    /// lowercase form.
+    pub fn lc_code(&self, c: char) -> i32 {
+        self.eqtb_char_code("LC_CODE_BASE", c)
+    }
+
+    /// Get the `\uccode` of a character. Zero means that the character has no
+    /// uppercase form.
+    pub fn uc_code(&self, c: char) -> i32 {
+        self.eqtb_char_code("UC_CODE_BASE", c)
+    }
+
+    /// Get the `\sfcode` of a character.
+    pub fn sf_code(&self, c: char) -> i32 {
+        self.eqtb_char_code("SF_CODE_BASE", c)
+    }
+
+    /// Get the `\mathcode` of a character, in XeTeX's extended encoding.
+    pub fn math_code(&self, c: char) -> i32 {
+        self.eqtb_char_code("MATH_CODE_BASE", c)
+    }
+
+    /// Get the characters that are defined as active control sequences, with
+    /// descriptions of their meanings, ordered by character code.
+    pub fn active_characters(&self) -> Vec<(char, String)> {
         let undefined_cs_cmd = self.engine.symbols.lookup("UNDEFINED_CS") as CommandCode;
 
+        valid_usvs()
+            .filter_map(|chr| {
+                let entry = self.eqtb_active(chr);
+
+                if entry.ty == undefined_cs_cmd {
+                    return None;
+                }
+
+                Some((
+                    char::from_u32(chr as u32)?,
+                    self.engine.commands.describe(entry.ty, entry.value),
+                ))
+            })
+            .collect()
+    }
+
+    /// Write this format's decodable content as JSON. See the [`json`]
+    /// module for a description of the output.
+    ///
+    /// [`json`]: crate::json
+    pub fn dump_json<W: Write>(&self, stream: &mut W) -> Result<()> {
+        crate::json::write_format(self, stream)
+    }
+
+    /// Get the hyphenation data of every language that has patterns,
+    /// exceptions, or saved hyphenation codes, ordered by language number.
+    pub fn languages(&self) -> Vec<Language> {
+        self.hyph.languages(&self.strings, &self.mem)
+    }
+
+    /// Get the control sequences that are shortcuts to registers or character
+    /// codes, as defined by `\countdef`, `\chardef`, and the like, sorted by
+    /// kind and number.
+    pub fn register_shortcuts(&self) -> Vec<RegisterShortcut> {
+        let cmd = |name| self.engine.symbols.lookup(name) as CommandCode;
+        let char_given = cmd("CHAR_GIVEN");
+        let math_given = cmd("MATH_GIVEN");
+        let xetex_math_given = cmd("XETEX_MATH_GIVEN");
+        let assign_int = cmd("ASSIGN_INT");
+        let assign_dimen = cmd("ASSIGN_DIMEN");
+        let assign_glue = cmd("ASSIGN_GLUE");
+        let assign_mu_glue = cmd("ASSIGN_MU_GLUE");
+        let assign_toks = cmd("ASSIGN_TOKS");
+        let register = cmd("REGISTER");
+        let toks_register = cmd("TOKS_REGISTER");
+
+        let mut shortcuts: Vec<_> = self
+            .cseqs()
+            .filter_map(|(name, ptr)| {
+                let entry = self.eqtb.decode(ptr);
+                let value = entry.value;
+
+                let (kind, number) = match entry.ty {
+                    t if t == char_given => (ShortcutKind::Char, value),
+                    t if t == math_given => (ShortcutKind::MathChar, value),
+                    t if t == xetex_math_given => (ShortcutKind::XetexMathChar, value),
+                    t if t == assign_int => (
+                        ShortcutKind::Count,
+                        self.register_number("COUNT_BASE", value)?,
+                    ),
+                    t if t == assign_dimen => (
+                        ShortcutKind::Dimen,
+                        self.register_number("SCALED_BASE", value)?,
+                    ),
+                    t if t == assign_glue => (
+                        ShortcutKind::Skip,
+                        self.register_number("SKIP_BASE", value)?,
+                    ),
+                    t if t == assign_mu_glue => (
+                        ShortcutKind::MuSkip,
+                        self.register_number("MU_SKIP_BASE", value)?,
+                    ),
+                    t if t == assign_toks => (
+                        ShortcutKind::Toks,
+                        self.register_number("TOKS_BASE", value)?,
+                    ),
+                    t if t == register || t == toks_register => {
+                        registers::sparse_register(&self.mem, value)?
+                    }
+                    _ => return None,
+                };
+
+                Some(RegisterShortcut { kind, number, name })
+            })
+            .collect();
+
+        shortcuts.sort();
+        shortcuts.dedup();
+        shortcuts
+    }
+
+    /// Get the state of the allocation counters that plain TeX and LaTeX keep
+    /// in `\count10` through `\count20`.
+    ///
+    /// Returns `None` if the format doesn't use them, which is detected by
+    /// checking that `\insc@unt` is a shortcut to `\count20`.
+    pub fn register_allocations(&self) -> Option<Vec<Allocation>> {
+        let ptr = self.cshash.lookup("insc@unt", &self.strings)?;
+        let entry = self.eqtb.decode(ptr);
+
+        if entry.ty != self.engine.symbols.lookup("ASSIGN_INT") as CommandCode
+            || self.register_number("COUNT_BASE", entry.value)
+                != Some(registers::INSERT_COUNTER as i32)
+        {
+            return None;
+        }
+
+        let counter = |kind: AllocationKind| {
+            self.eqtb
+                .decode(self.eqtb_base("COUNT_BASE") + kind.counter() as EqtbPointer)
+                .value
+        };
+
+        let insertions = counter(AllocationKind::Insert);
+        let max_shared = [
+            AllocationKind::Count,
+            AllocationKind::Dimen,
+            AllocationKind::Skip,
+            AllocationKind::Box,
+        ]
+        .into_iter()
+        .map(counter)
+        .max()
+        .unwrap();
+
+        let allocations = AllocationKind::ALL
+            .iter()
+            .map(|&kind| {
+                let limit = match kind {
+                    AllocationKind::Count
+                    | AllocationKind::Dimen
+                    | AllocationKind::Skip
+                    | AllocationKind::Box => insertions,
+                    AllocationKind::MuSkip | AllocationKind::Toks | AllocationKind::Language => {
+                        NUMBER_REGS as i32
+                    }
+                    AllocationKind::Read | AllocationKind::Write | AllocationKind::Family => 16,
+                    AllocationKind::Insert => max_shared,
+                };
+
+                Allocation {
+                    kind,
+                    last: counter(kind),
+                    limit,
+                }
+            })
+            .collect();
+
+        Some(allocations)
+    }
+
+    /// Get the strings of the string table, along with their pointers.
+    pub fn strings(&self) -> impl Iterator<Item = (StrPointer, &str)> {
+        self.strings
+            .all_sps()
+            .map(|sp| (sp, self.strings.lookup(sp)))
+    }
+
+    /// Get the defined control sequences, with descriptions of their
+    /// meanings. If *extended* is true, extended information such as the
+    /// contents of macros is included too.
+    pub fn control_sequences(&self, extended: bool) -> Vec<ControlSequence> {
+        let undefined_cs_cmd = self.engine.symbols.lookup("UNDEFINED_CS") as CommandCode;
+
+        self.cseqs()
+            .filter_map(|(name, ptr)| {
+                let entry = self.eqtb.decode(ptr);
+
+                if entry.ty == undefined_cs_cmd {
+                    return None;
+                }
+
+                let (meaning, extended) = if extended {
+                    self.engine
+                        .commands
+                        .describe_extended(entry.ty, entry.value, self)
+                } else {
+                    (self.engine.commands.describe(entry.ty, entry.value), None)
+                };
+
+                Some(ControlSequence {
+                    name: fmt_csname(name),
+                    meaning,
+                    extended,
+                })
+            })
+            .collect()
+    }
+
+    /// Get the values of the integer parameters defined in this format's
+    /// engine version, along with their primitive names, or their internal
+    /// names if they have no primitives.
+    pub fn int_parameters(&self) -> Vec<(String, i32)> {
+        let base = self.eqtb_base("INT_BASE");
+
+        self.engine
+            .int_pars
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let name = p.primitive_name().unwrap_or_else(|| p.name().to_owned());
+                (name, self.eqtb.decode(base + i as EqtbPointer).value)
+            })
+            .collect()
+    }
+
+    /// Get the values of the dimensional parameters defined in this format's
+    /// engine version, in scaled points, along with their primitive names.
+    pub fn dimen_parameters(&self) -> Vec<(String, i32)> {
+        let base = self.eqtb_base("DIMEN_BASE");
+
+        self.engine
+            .dimen_pars
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                (
+                    p.primitive_name(),
+                    self.eqtb.decode(base + i as EqtbPointer).value,
+                )
+            })
+            .collect()
+    }
+
+    /// Get the framing that the format data were found in, so that a
+    /// modified copy of the file can be written back in the same way with
+    /// [`Container::encode`].
+    pub fn container(&self) -> Container {
+        self.container
+    }
+
+    /// Get information about how this format was built.
+    pub fn metadata(&self) -> Result<FormatMetadata> {
+        let banner = self.macro_text("fmtname").map(|name| {
+            match self.macro_text("fmtversion").filter(|v| !v.is_empty()) {
+                Some(version) => format!("{name} {version}"),
+                None => name,
+            }
+        });
+
+        let dump_time = DumpTime {
+            year: self.int_parameter(IntPar::Year)?,
+            month: self.int_parameter(IntPar::Month)?,
+            day: self.int_parameter(IntPar::Day)?,
+            minutes: self.int_parameter(IntPar::Time)?,
+        };
+
+        Ok(FormatMetadata {
+            version: self.engine.version,
+            banner,
+            dump_time,
+            texxet: self.int_parameter(IntPar::Texxet)? > 0,
+            xetex_inter_char_tokens: self.int_parameter(IntPar::XetexInterCharTokens)? > 0,
+            xetex_use_glyph_metrics: self.int_parameter(IntPar::XetexUseGlyphMetrics)? > 0,
+            xetex_upwards: self.int_parameter(IntPar::XetexUpwards)? > 0,
+        })
+    }
+
+    /// Get the miscellaneous scalars saved in this format.
+    pub fn saved_scalars(&self) -> SavedScalars {
+        let raw = self.scalars;
+
+        SavedScalars {
+            hash_high: raw.hash_high,
+            hyph_prime: raw.hyph_prime,
+            par_token: self.fmt_cs_pointer(raw.par_loc),
+            write_token: self.fmt_cs_pointer(raw.write_loc),
+            fonts: raw.font_ptr as usize + 1,
+            max_hyph_char: raw.max_hyph_char,
+        }
+    }
+
+    /// Get the state saved in this format that affects the output of the
+    /// engine once pages are shipped out.
+    pub fn backend_state(&self) -> Result<BackendState> {
+        let call_cmd = self.engine.symbols.lookup("CALL") as CommandCode;
+        let long_outer_call_cmd = self.engine.symbols.lookup("LONG_OUTER_CALL") as CommandCode;
+        let mut specials = Vec::new();
+
         for (name, ptr) in self.cseqs() {
             let entry = self.eqtb.decode(ptr);
 
-            if entry.ty == undefined_cs_cmd {
+            if (call_cmd..=long_outer_call_cmd).contains(&entry.ty) {
+                self.find_specials(&fmt_csname(name), entry.value, &mut specials);
+            }
+        }
+
+        let local_base = self.eqtb_base("LOCAL_BASE");
+
+        for (i, par) in self.engine.local_pars.iter().enumerate() {
+            let p = self.eqtb.decode(local_base + i as EqtbPointer).value;
+
+            if par.kind() == LocalParKind::TokenList && p != TEX_NULL {
+                self.find_specials(&fmt_csname(par.primitive_name()), p, &mut specials);
+            }
+        }
+
+        Ok(BackendState {
+            pdf_output: self.int_parameter(IntPar::Pdfoutput)?,
+            mag: self.int_parameter(IntPar::Mag)?,
+            page_width: self.dimen_parameter(DimenPar::PdfPageWidth)?,
+            page_height: self.dimen_parameter(DimenPar::PdfPageHeight)?,
+            h_offset: self.dimen_parameter(DimenPar::HOffset)?,
+            v_offset: self.dimen_parameter(DimenPar::VOffset)?,
+            generate_actual_text: self.int_parameter(IntPar::XetexGenerateActualText)? > 0,
+            synctex: self.int_parameter(IntPar::Synctex)?,
+            specials,
+        })
+    }
+
+    /// Write a description of the state saved in this format that affects
+    /// the output of the engine once pages are shipped out.
+    pub fn dump_backend<W: Write>(&self, stream: &mut W) -> Result<()> {
+        let state = self.backend_state()?;
+        let dimen = |s: i32| {
+            if s == 0 {
+                "[unset]".to_owned()
+            } else {
+                fmt_scaled(s)
+            }
+        };
+
+        writeln!(stream, "\\pdfoutput: {}", state.pdf_output)?;
+        writeln!(stream, "\\mag: {}", state.mag)?;
+        writeln!(stream, "\\pdfpagewidth: {}", dimen(state.page_width))?;
+        writeln!(stream, "\\pdfpageheight: {}", dimen(state.page_height))?;
+        writeln!(stream, "\\hoffset: {}", fmt_scaled(state.h_offset))?;
+        writeln!(stream, "\\voffset: {}", fmt_scaled(state.v_offset))?;
+        writeln!(
+            stream,
+            "\\XeTeXgenerateactualtext: {}",
+            if state.generate_actual_text {
+                "on"
+            } else {
+                "off"
+            }
+        )?;
+        writeln!(stream, "\\synctex: {}", state.synctex)?;
+
+        if state.specials.is_empty() {
+            writeln!(stream, "specials: [none]")?;
+        } else {
+            writeln!(stream, "specials:")?;
+        }
+
+        for special in &state.specials {
+            match &special.text {
+                Some(text) => writeln!(stream, "    {}: {{{}}}", special.location, text)?,
+                None => writeln!(stream, "    {}: [computed]", special.location)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Write a description of how this format was built.
+    pub fn dump_metadata<W: Write>(&self, stream: &mut W) -> Result<()> {
+        let meta = self.metadata()?;
+        let flag = |b: bool| if b { "on" } else { "off" };
+
+        writeln!(stream, "format version: {}", meta.version)?;
+        writeln!(stream, "stored as: {}", self.container)?;
+        writeln!(
+            stream,
+            "banner: {}",
+            meta.banner.as_deref().unwrap_or("[none]")
+        )?;
+        writeln!(stream, "dumped: {}", meta.dump_time)?;
+        writeln!(stream, "TeXXeT: {}", flag(meta.texxet))?;
+        writeln!(
+            stream,
+            "XeTeX inter-character tokens: {}",
+            flag(meta.xetex_inter_char_tokens)
+        )?;
+        writeln!(
+            stream,
+            "XeTeX glyph metrics: {}",
+            flag(meta.xetex_use_glyph_metrics)
+        )?;
+        writeln!(stream, "XeTeX upwards mode: {}", flag(meta.xetex_upwards))?;
+
+        let scalars = self.saved_scalars();
+        writeln!(stream, "extra control sequences: {}", scalars.hash_high)?;
+        writeln!(
+            stream,
+            "hyphenation exception table size: {}",
+            scalars.hyph_prime
+        )?;
+        writeln!(stream, "paragraph token: {}", scalars.par_token)?;
+        writeln!(stream, "write token: {}", scalars.write_token)?;
+        writeln!(stream, "fonts: {}", scalars.fonts)?;
+        writeln!(stream, "max hyphenation char: {}", scalars.max_hyph_char)?;
+        Ok(())
+    }
+
+    /// Compare the state saved in this format with a snapshot of the state of
+    /// a running engine, as written by the `\tectonicdumpstate` primitive,
+    /// and list the items that differ.
+    ///
+    /// The `\time`, `\day`, `\month`, and `\year` parameters are set every
+    /// time that the engine starts, so they're left out of the comparison.
+    pub fn compare_state(&self, engine_state: &EngineState) -> Result<Vec<StateDifference>> {
+        ensure!(
+            engine_state.version() == self.engine.version,
+            "the engine state is from format version {}, but the format is version {}",
+            engine_state.version(),
+            self.engine.version
+        );
+
+        let ours = self.state_items();
+        let keys: BTreeSet<&StateKey> = ours.keys().chain(engine_state.items.keys()).collect();
+        let mut diffs = Vec::new();
+
+        for key in keys {
+            if let StateKey::Int(i) = key {
+                if self.engine.int_pars.get(*i).is_some_and(|p| {
+                    matches!(
+                        p.par(),
+                        IntPar::Time | IntPar::Day | IntPar::Month | IntPar::Year
+                    )
+                }) {
+                    continue;
+                }
+            }
+
+            let format = ours.get(key);
+            let engine = engine_state.items.get(key);
+
+            if format == engine {
+                continue;
+            }
+
+            diffs.push(StateDifference {
+                item: self.fmt_state_key(key),
+                format: format.map(|v| self.fmt_state_value(v)),
+                engine: engine.map(|v| self.fmt_state_value(v)),
+            });
+        }
+
+        Ok(diffs)
+    }
+
+    /// Check the internal consistency of the equivalents table and of the
+    /// e-TeX sparse arrays, and describe each problem found.
+    ///
+    /// A format that the engine rejects as corrupted will often parse, since
+    /// the parser only checks the overall layout of the file; this explains
+    /// what's wrong with its contents. At most
+    /// [`crate::consistency::MAX_REPORTED`] problems are returned.
+    pub fn check_consistency(&self) -> Vec<Inconsistency> {
+        let cs_name = |p| self.cshash.stringify(p, &self.strings);
+        let mut checker = Checker::new(&self.engine, &cs_name);
+        checker.check_eqtb(&self.eqtb, &self.mem);
+        checker.check_sparse_arrays(&self.mem);
+        checker.finish()
+    }
+
+    /// Collect the same items of state that `\tectonicdumpstate` writes out.
+    fn state_items(&self) -> BTreeMap<StateKey, StateValue> {
+        let mut items = BTreeMap::new();
+
+        let int_base = self.eqtb_base("INT_BASE");
+        for i in 0..self.engine.int_pars.len() {
+            let value = self.eqtb.decode(int_base + i as EqtbPointer).value;
+            items.insert(StateKey::Int(i), StateValue::Int(value));
+        }
+
+        let dimen_base = self.eqtb_base("DIMEN_BASE");
+        for i in 0..self.engine.dimen_pars.len() {
+            let value = self.eqtb.decode(dimen_base + i as EqtbPointer).value;
+            items.insert(StateKey::Dimen(i), StateValue::Scaled(value));
+        }
+
+        let count_base = self.eqtb_base("COUNT_BASE");
+        let scaled_base = self.eqtb_base("SCALED_BASE");
+        for n in 0..NUMBER_REGS {
+            let value = self.eqtb.decode(count_base + n as EqtbPointer).value;
+            items.insert(StateKey::Count(n), StateValue::Int(value));
+            let value = self.eqtb.decode(scaled_base + n as EqtbPointer).value;
+            items.insert(StateKey::DimenRegister(n), StateValue::Scaled(value));
+        }
+
+        for kind in [
+            CharCodeKind::Cat,
+            CharCodeKind::Lc,
+            CharCodeKind::Uc,
+            CharCodeKind::Sf,
+            CharCodeKind::Math,
+        ] {
+            let base = self.eqtb_base(kind.eqtb_region());
+
+            for c in 0..256 {
+                let value = self.eqtb.decode(base + c).value;
+                items.insert(StateKey::CharCode(kind, c as u32), StateValue::Int(value));
+            }
+        }
+
+        let hash_base = self.eqtb_base("HASH_BASE");
+        let undefined_cs_cmd = self.engine.symbols.lookup("UNDEFINED_CS") as CommandCode;
+        let call_cmd = self.engine.symbols.lookup("CALL") as CommandCode;
+
+        for (name, ptr) in self.cseqs() {
+            if ptr < hash_base {
                 continue;
             }
 
-            let cs_desc = fmt_csname(name);
+            let entry = self.eqtb.decode(ptr);
 
-            let (cmd_desc, extended) = if extended {
-                self.engine
-                    .commands
-                    .describe_extended(entry.ty, entry.value, self)
+            let value = if entry.ty == undefined_cs_cmd {
+                continue;
+            } else if entry.ty >= call_cmd {
+                StateValue::Macro {
+                    ty: entry.ty,
+                    hash: self.hash_macro_body(entry.value),
+                }
             } else {
-                (self.engine.commands.describe(entry.ty, entry.value), None)
+                StateValue::Equiv {
+                    ty: entry.ty,
+                    value: entry.value,
+                }
             };
 
-            writeln!(stream, "{cs_desc} => {cmd_desc}")?;
+            items.insert(StateKey::ControlSeq(name), value);
+        }
+
+        items
+    }
 
-            if let Some(e) = extended {
-                writeln!(stream, "--------\n{e}\n--------")?;
+    /// Get the replacement text of a parameterless macro as plain text, or
+    /// `None` if the control sequence isn't a macro.
+    fn macro_text(&self, name: &str) -> Option<String> {
+        let ptr = self.cshash.lookup(name, &self.strings)?;
+        let entry = self.eqtb.decode(ptr);
+        let call_cmd = self.engine.symbols.lookup("CALL") as CommandCode;
+        let long_outer_call_cmd = self.engine.symbols.lookup("LONG_OUTER_CALL") as CommandCode;
+
+        if !(call_cmd..=long_outer_call_cmd).contains(&entry.ty) {
+            return None;
+        }
+
+        let n_words = (self.mem.mem.len() / SIZEOF_MEMORY_WORD) as mem::MemPointer;
+        let mut text = String::new();
+        let mut in_body = false;
+
+        // Skip the reference count
+        let mut p = self.mem.decode_toklist(entry.value).1;
+
+        while p != TEX_NULL && (0..n_words).contains(&p) {
+            let (value, next) = self.mem.decode_toklist(p);
+
+            match Token::from(value) {
+                Token::Char { cmd: 14, .. } => in_body = true, // END_MATCH
+                Token::Char { chr, .. } if in_body => text.extend(char::from_u32(chr as u32)),
+                Token::ControlSeq { ptr } if in_body => text.push_str(&self.fmt_cs_pointer(ptr)),
+                _ => {}
             }
+
+            p = next;
         }
 
-        Ok(())
+        Some(text)
+    }
+
+    /// Add the `\special`s issued by the token list at *p*, which starts with
+    /// a reference count, to *specials*.
+    fn find_specials(&self, location: &str, p: mem::MemPointer, specials: &mut Vec<SavedSpecial>) {
+        let extension_cmd = self.engine.symbols.lookup("EXTENSION") as CommandCode;
+        let special_code = self.engine.symbols.lookup("SPECIAL_NODE") as i32;
+        let n_words = (self.mem.mem.len() / SIZEOF_MEMORY_WORD) as mem::MemPointer;
+        let mut tokens = Vec::new();
+
+        // Skip the reference count
+        let mut p = self.mem.decode_toklist(p).1;
+
+        while p != TEX_NULL && (0..n_words).contains(&p) {
+            let (value, next) = self.mem.decode_toklist(p);
+            tokens.push(Token::from(value));
+            p = next;
+        }
+
+        for (i, tok) in tokens.iter().enumerate() {
+            let Token::ControlSeq { ptr } = *tok else {
+                continue;
+            };
+
+            // This also catches control sequences `\let` to `\special`.
+            let entry = self.eqtb.decode(ptr);
+
+            if entry.ty == extension_cmd && entry.value == special_code {
+                specials.push(SavedSpecial {
+                    location: location.to_owned(),
+                    text: self.fmt_braced_group(&tokens[i + 1..]),
+                });
+            }
+        }
+    }
+
+    /// Format the group that *tokens* start with, without its braces, or
+    /// return `None` if they don't start with a complete group.
+    fn fmt_braced_group(&self, tokens: &[Token]) -> Option<String> {
+        let (Token::Char { cmd: 1, .. }, rest) = tokens.split_first()? else {
+            return None; // LEFT_BRACE
+        };
+
+        let mut text = String::new();
+        let mut depth = 0;
+
+        for tok in rest {
+            match *tok {
+                Token::Char { cmd: 2, .. } if depth == 0 => return Some(text), // RIGHT_BRACE
+                Token::Char { cmd: 5, chr } => text.push_str(&format!("#{chr}")), // OUT_PARAM
+                Token::Char { cmd, chr } => {
+                    match cmd {
+                        1 => depth += 1,
+                        2 => depth -= 1,
+                        _ => {}
+                    }
+
+                    text.extend(char::from_u32(chr as u32));
+                }
+                Token::ControlSeq { ptr } => text.push_str(&self.fmt_cs_pointer(ptr)),
+            }
+        }
+
+        None
+    }
+
+    /// Hash the token list of a macro in the same way as the engine does when
+    /// it writes a snapshot of its state.
+    fn hash_macro_body(&self, p: mem::MemPointer) -> u64 {
+        let n_words = (self.mem.mem.len() / SIZEOF_MEMORY_WORD) as mem::MemPointer;
+        let mut hash = state::BODY_HASH_INIT;
+
+        // Skip the reference count
+        let mut p = self.mem.decode_toklist(p).1;
+
+        while p != TEX_NULL && (0..n_words).contains(&p) {
+            let (value, next) = self.mem.decode_toklist(p);
+            hash = state::hash_body_word(hash, value);
+            p = next;
+        }
+
+        hash
+    }
+
+    fn fmt_state_key(&self, key: &StateKey) -> String {
+        state::fmt_key(
+            key,
+            |i| match self.engine.int_pars.get(i) {
+                Some(p) => match p.primitive_name() {
+                    Some(name) => format!("\\{name}"),
+                    None => p.name().to_owned(),
+                },
+                None => format!("[integer parameter {i}]"),
+            },
+            |i| match self.engine.dimen_pars.get(i) {
+                Some(p) => format!("\\{}", p.primitive_name()),
+                None => format!("[dimension parameter {i}]"),
+            },
+        )
+    }
+
+    fn fmt_state_value(&self, value: &StateValue) -> String {
+        match value {
+            StateValue::Int(v) => v.to_string(),
+            StateValue::Scaled(v) => fmt_scaled(*v),
+            StateValue::Equiv { ty, value } => self.engine.commands.describe(*ty, *value),
+            StateValue::Macro { ty, hash } => {
+                format!(
+                    "{} with body hash {:016x}",
+                    self.engine.commands.describe(*ty, 0),
+                    hash
+                )
+            }
+        }
     }
 
     fn cseqs(&self) -> impl Iterator<Item = (String, EqtbPointer)> {
@@ -283,6 +1375,33 @@ impl Format {
     // type, except it doesn't actually hold onto all of the magic offsets needed
     // to index into it properly.
 
+    fn eqtb_base(&self, region: &str) -> EqtbPointer {
+        self.engine.symbols.lookup(region) as EqtbPointer
+    }
+
+    fn eqtb_register(&self, region: &str, n: usize) -> Result<EqtbPointer> {
+        ensure!(
+            n < NUMBER_REGS,
+            "register number {} out of range (must be less than {})",
+            n,
+            NUMBER_REGS
+        );
+        Ok(self.eqtb_base(region) + n as EqtbPointer)
+    }
+
+    /// Get the number of the register at eqtb location *p*, if it's in the
+    /// given region of registers.
+    fn register_number(&self, region: &str, p: i32) -> Option<i32> {
+        let n = p - self.eqtb_base(region);
+        (0..NUMBER_REGS as i32).contains(&n).then_some(n)
+    }
+
+    fn eqtb_char_code(&self, region: &str, c: char) -> i32 {
+        self.eqtb
+            .decode(self.eqtb_base(region) + c as EqtbPointer)
+            .value
+    }
+
     fn eqtb_active(&self, c: i32) -> eqtb::EqtbEntry {
         assert!((0..MAX_USV).contains(&c));
         self.eqtb
@@ -317,7 +1436,7 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
     let (input, _mem_top) = parseutils::satisfy_be_i32(mem_top)(input)?;
     let (input, _eqtb_size) = parseutils::satisfy_be_i32(eqtb_size)(input)?;
     let (input, _hash_prime) = parseutils::satisfy_be_i32(hash_prime)(input)?;
-    let (input, _hyph_prime) = be_i32(input)?;
+    let (input, hyph_prime) = be_i32(input)?;
 
     // string table
 
@@ -332,9 +1451,9 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
     let (input, eqtb) = eqtb::EquivalenciesTable::parse(input, &engine, hash_high)?;
 
     // nominally hash_top, but hash_top = eqtb_top since hash_extra is nonzero
-    let (input, _par_loc) = parseutils::ranged_be_i32(hash_base, eqtb_top)(input)?;
+    let (input, par_loc) = parseutils::ranged_be_i32(hash_base, eqtb_top)(input)?;
 
-    let (input, _write_loc) = parseutils::ranged_be_i32(hash_base, eqtb_top)(input)?;
+    let (input, write_loc) = parseutils::ranged_be_i32(hash_base, eqtb_top)(input)?;
 
     // Primitives. TODO: figure out best type for `prims`.
 
@@ -397,8 +1516,7 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
 
     let mut hyph_next;
     let mut hyph_link = vec![0u16; HYPH_SIZE];
-    let mut hyph_word = vec![0i32; HYPH_SIZE];
-    let mut hyph_list = vec![0i32; HYPH_SIZE];
+    let mut exceptions = Vec::new();
     let mut input = input;
     let max_word = strings.len() as i32 + TOO_BIG_CHAR - 1;
 
@@ -415,10 +1533,8 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
         hyph_link[j as usize] = hyph_next as u16;
 
         let (ii, w) = parseutils::ranged_be_i32(0, max_word)(ii)?;
-        hyph_word[j as usize] = w;
-
         let (ii, l) = parseutils::ranged_be_i32(MIN_HALFWORD, MAX_HALFWORD)(ii)?;
-        hyph_list[j as usize] = l;
+        exceptions.push((w, l));
 
         input = ii;
     }
@@ -427,14 +1543,14 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
 
     let (input, trie_max) = be_i32(input)?;
 
-    let (input, _hyph_start) = parseutils::ranged_be_i32(0, trie_max)(input)?;
+    let (input, hyph_start) = parseutils::ranged_be_i32(0, trie_max)(input)?;
 
     let n_trie = trie_max as usize + 1;
-    let (input, _trie_trl) = count(be_i32, n_trie).parse(input)?;
-    let (input, _trie_tro) = count(be_i32, n_trie).parse(input)?;
-    let (input, _trie_trc) = count(be_u16, n_trie).parse(input)?;
+    let (input, trie_trl) = count(be_i32, n_trie).parse(input)?;
+    let (input, trie_tro) = count(be_i32, n_trie).parse(input)?;
+    let (input, trie_trc) = count(be_u16, n_trie).parse(input)?;
 
-    let (input, _max_hyph_char) = be_i32(input)?;
+    let (input, max_hyph_char) = be_i32(input)?;
 
     let (input, trie_op_ptr) = parseutils::ranged_be_i32(0, TRIE_OP_SIZE)(input)?;
 
@@ -464,12 +1580,31 @@ fn parse_body(engine: Engine, input: &[u8]) -> IResult<&[u8], Format> {
 
     let (input, _) = parseutils::satisfy_be_i32(FOOTER_MAGIC)(input)?;
 
+    let hyph = HyphenationTables {
+        exceptions,
+        trie_trl,
+        trie_tro,
+        trie_trc,
+        hyph_start,
+        trie_used,
+    };
+
     let fmt = Format {
         engine,
         strings,
         mem,
         eqtb,
         cshash,
+        hyph,
+        container: Container::Raw,
+        scalars: RawScalars {
+            hash_high,
+            hyph_prime,
+            par_loc,
+            write_loc,
+            font_ptr,
+            max_hyph_char,
+        },
     };
     Ok((input, fmt))
 }
@@ -502,6 +1637,51 @@ pub fn fmt_usv(c: i32) -> String {
     }
 }
 
+fn fmt_code(c: i32) -> String {
+    if c == 0 {
+        "none".to_owned()
+    } else {
+        fmt_usv(c)
+    }
+}
+
+/// Format a dimension given in scaled points as TeX would, e.g. `12.0pt`.
+pub fn fmt_scaled(s: i32) -> String {
+    const UNITY: i64 = 0x10000;
+
+    let mut s = s as i64;
+    let mut result = String::new();
+
+    if s < 0 {
+        result.push('-');
+        s = -s;
+    }
+
+    result.push_str(&format!("{}.", s / UNITY));
+
+    // This is TeX's `print_scaled`, which prints the shortest decimal
+    // fraction that rounds to the same value.
+    let mut s = 10 * (s % UNITY) + 5;
+    let mut delta = 10;
+
+    loop {
+        if delta > UNITY {
+            s += 0x8000 - 50000;
+        }
+
+        result.push(char::from(b'0' + (s / UNITY) as u8));
+        s = 10 * (s % UNITY);
+        delta *= 10;
+
+        if s <= delta {
+            break;
+        }
+    }
+
+    result.push_str("pt");
+    result
+}
+
 pub fn fmt_csname<S: AsRef<str>>(name: S) -> String {
     let name = name.as_ref();
     let has_ws = name.contains(char::is_whitespace);
@@ -513,3 +1693,17 @@ pub fn fmt_csname<S: AsRef<str>>(name: S) -> String {
         (_, true) => format!("\"\\{name}\""),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scaled() {
+        assert_eq!(fmt_scaled(0), "0.0pt");
+        assert_eq!(fmt_scaled(12 * 0x10000), "12.0pt");
+        assert_eq!(fmt_scaled(-0x8000), "-0.5pt");
+        assert_eq!(fmt_scaled(1), "0.00002pt");
+        assert_eq!(fmt_scaled(0x4000_0000 - 1), "16383.99998pt");
+    }
+}
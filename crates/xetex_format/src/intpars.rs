@@ -24,9 +24,274 @@ pub enum IntParPrimitiveKind {
     CustomName(&'static str),
 }
 
+/// An integer parameter defined by the engine.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IntPar {
+    /// The `\pretolerance` parameter.
+    Pretolerance,
+
+    /// The `\tolerance` parameter.
+    Tolerance,
+
+    /// The `\linepenalty` parameter.
+    LinePenalty,
+
+    /// The `\hyphenpenalty` parameter.
+    HyphenPenalty,
+
+    /// The `\exhyphenpenalty` parameter.
+    ExHyphenPenalty,
+
+    /// The `\clubpenalty` parameter.
+    ClubPenalty,
+
+    /// The `\widowpenalty` parameter.
+    WidowPenalty,
+
+    /// The `\displaywidowpenalty` parameter.
+    DisplayWidowPenalty,
+
+    /// The `\brokenpenalty` parameter.
+    BrokenPenalty,
+
+    /// The `\binoppenalty` parameter.
+    BinOpPenalty,
+
+    /// The `\relpenalty` parameter.
+    RelPenalty,
+
+    /// The `\predisplaypenalty` parameter.
+    PreDisplayPenalty,
+
+    /// The `\postdisplaypenalty` parameter.
+    PostDisplayPenalty,
+
+    /// The `\interlinepenalty` parameter.
+    InterLinePenalty,
+
+    /// The `\doublehyphendemerits` parameter.
+    DoubleHyphenDemerits,
+
+    /// The `\finalhyphendemerits` parameter.
+    FinalHyphenDemerits,
+
+    /// The `\adjdemerits` parameter.
+    AdjDemerits,
+
+    /// The `\mag` parameter.
+    Mag,
+
+    /// The `\delimiterfactor` parameter.
+    DelimiterFactor,
+
+    /// The `\looseness` parameter.
+    Looseness,
+
+    /// The `\time` parameter.
+    Time,
+
+    /// The `\day` parameter.
+    Day,
+
+    /// The `\month` parameter.
+    Month,
+
+    /// The `\year` parameter.
+    Year,
+
+    /// The `\showboxbreadth` parameter.
+    ShowBoxBreadth,
+
+    /// The `\showboxdepth` parameter.
+    ShowBoxDepth,
+
+    /// The `\hbadness` parameter.
+    Hbadness,
+
+    /// The `\vbadness` parameter.
+    Vbadness,
+
+    /// The `\pausing` parameter.
+    Pausing,
+
+    /// The `\tracingonline` parameter.
+    TracingOnline,
+
+    /// The `\tracingmacros` parameter.
+    TracingMacros,
+
+    /// The `\tracingstats` parameter.
+    TracingStats,
+
+    /// The `\tracingparagraphs` parameter.
+    TracingParagraphs,
+
+    /// The `\tracingpages` parameter.
+    TracingPages,
+
+    /// The `\tracingoutput` parameter.
+    TracingOutput,
+
+    /// The `\tracinglostchars` parameter.
+    TracingLostChars,
+
+    /// The `\tracingcommands` parameter.
+    TracingCommands,
+
+    /// The `\tracingrestores` parameter.
+    TracingRestores,
+
+    /// The `\uchyph` parameter.
+    UcHyph,
+
+    /// The `\outputpenalty` parameter.
+    OutputPenalty,
+
+    /// The `\maxdeadcycles` parameter.
+    MaxDeadCycles,
+
+    /// The `\hangafter` parameter.
+    HangAfter,
+
+    /// The `\floatingpenalty` parameter.
+    FloatingPenalty,
+
+    /// The `\globaldefs` parameter.
+    GlobalDefs,
+
+    /// The `\fam` parameter.
+    CurFam,
+
+    /// The `\escapechar` parameter.
+    EscapeChar,
+
+    /// The `\defaulthyphenchar` parameter.
+    DefaultHyphenChar,
+
+    /// The `\defaultskewchar` parameter.
+    DefaultSkewChar,
+
+    /// The `\endlinechar` parameter.
+    EndLineChar,
+
+    /// The `\newlinechar` parameter.
+    NewLineChar,
+
+    /// The `\language` parameter.
+    Language,
+
+    /// The `\lefthyphenmin` parameter.
+    LeftHyphenMin,
+
+    /// The `\righthyphenmin` parameter.
+    RightHyphenMin,
+
+    /// The `\holdinginserts` parameter.
+    HoldingInserts,
+
+    /// The `\errorcontextlines` parameter.
+    ErrorContextLines,
+
+    /// The internal `char_sub_def_min` parameter, which has no primitive.
+    CharSubDefMin,
+
+    /// The internal `char_sub_def_max` parameter, which has no primitive.
+    CharSubDefMax,
+
+    /// The internal `tracing_char_sub_def` parameter, which has no primitive.
+    TracingCharSubDef,
+
+    /// The `\tracingstacklevels` parameter.
+    TracingStackLevels,
+
+    /// The `\tracingassigns` parameter.
+    TracingAssigns,
+
+    /// The `\tracinggroups` parameter.
+    TracingGroups,
+
+    /// The `\tracingifs` parameter.
+    TracingIfs,
+
+    /// The `\tracingscantokens` parameter.
+    TracingScanTokens,
+
+    /// The `\tracingnesting` parameter.
+    TracingNesting,
+
+    /// The `\predisplaydirection` parameter.
+    PreDisplayDirection,
+
+    /// The `\lastlinefit` parameter.
+    LastLineFit,
+
+    /// The `\savingvdiscards` parameter.
+    SavingVdiscards,
+
+    /// The `\savinghyphcodes` parameter.
+    SavingHyphCodes,
+
+    /// The `\suppressfontnotfounderror` parameter.
+    SuppressFontnotfoundError,
+
+    /// The internal `xetex_linebreak_locale` parameter, which has no primitive.
+    XetexLinebreakLocale,
+
+    /// The `\XeTeXlinebreakpenalty` parameter.
+    XetexLinebreakPenalty,
+
+    /// The `\XeTeXprotrudechars` parameter.
+    XetexProtrudeChars,
+
+    /// The `\TeXXeTstate` parameter.
+    Texxet,
+
+    /// The `\XeTeXdashbreakstate` parameter.
+    XetexDashBreak,
+
+    /// The `\XeTeXupwardsmode` parameter.
+    XetexUpwards,
+
+    /// The `\XeTeXuseglyphmetrics` parameter.
+    XetexUseGlyphMetrics,
+
+    /// The `\XeTeXinterchartokenstate` parameter.
+    XetexInterCharTokens,
+
+    /// The `\XeTeXinputnormalization` parameter.
+    XetexInputNormalization,
+
+    /// The internal `XeTeX_default_input_mode` parameter, which has no primitive.
+    XetexDefaultInputMode,
+
+    /// The internal `XeTeX_default_input_encoding` parameter, which has no primitive.
+    XetexDefaultInputEncoding,
+
+    /// The `\XeTeXtracingfonts` parameter.
+    XetexTracingFonts,
+
+    /// The `\XeTeXinterwordspaceshaping` parameter.
+    XetexInterwordSpaceShaping,
+
+    /// The `\XeTeXgenerateactualtext` parameter.
+    XetexGenerateActualText,
+
+    /// The `\XeTeXhyphenatablelength` parameter.
+    XetexHyphenatableLength,
+
+    /// The `\synctex` parameter.
+    Synctex,
+
+    /// The `\pdfoutput` parameter.
+    Pdfoutput,
+}
+
 /// Information about integer parameters.
 #[derive(Clone, Copy, Debug)]
-pub struct IntPar {
+pub struct IntParInfo {
+    /// The parameter.
+    par: IntPar,
+
     /// The name of the parameter.
     name: &'static str,
 
@@ -40,518 +305,626 @@ pub struct IntPar {
     until: FormatVersion,
 }
 
-const INT_PARS: &[IntPar] = &[
-    IntPar {
+impl IntParInfo {
+    /// Get the parameter that this information describes.
+    pub fn par(&self) -> IntPar {
+        self.par
+    }
+
+    /// Get the name of the parameter.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the name of the primitive that accesses the parameter, without the
+    /// leading backslash, if there is one.
+    pub fn primitive_name(&self) -> Option<String> {
+        match self.primitive_kind {
+            IntParPrimitiveKind::None => None,
+            IntParPrimitiveKind::Standard => Some(self.name.replace('_', "")),
+            IntParPrimitiveKind::CustomName(s) => Some(s.replace('_', "")),
+        }
+    }
+}
+
+const INT_PARS: &[IntParInfo] = &[
+    IntParInfo {
+        par: IntPar::Pretolerance,
         name: "pretolerance",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Tolerance,
         name: "tolerance",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::LinePenalty,
         name: "line_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::HyphenPenalty,
         name: "hyphen_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::ExHyphenPenalty,
         name: "ex_hyphen_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::ClubPenalty,
         name: "club_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::WidowPenalty,
         name: "widow_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::DisplayWidowPenalty,
         name: "display_widow_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::BrokenPenalty,
         name: "broken_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::BinOpPenalty,
         name: "bin_op_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::RelPenalty,
         name: "rel_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::PreDisplayPenalty,
         name: "pre_display_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::PostDisplayPenalty,
         name: "post_display_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::InterLinePenalty,
         name: "inter_line_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::DoubleHyphenDemerits,
         name: "double_hyphen_demerits",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::FinalHyphenDemerits,
         name: "final_hyphen_demerits",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::AdjDemerits,
         name: "adj_demerits",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Mag,
         name: "mag",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::DelimiterFactor,
         name: "delimiter_factor",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Looseness,
         name: "looseness",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Time,
         name: "time",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Day,
         name: "day",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Month,
         name: "month",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Year,
         name: "year",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::ShowBoxBreadth,
         name: "show_box_breadth",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::ShowBoxDepth,
         name: "show_box_depth",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Hbadness,
         name: "hbadness",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Vbadness,
         name: "vbadness",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Pausing,
         name: "pausing",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingOnline,
         name: "tracing_online",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingMacros,
         name: "tracing_macros",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingStats,
         name: "tracing_stats",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingParagraphs,
         name: "tracing_paragraphs",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingPages,
         name: "tracing_pages",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingOutput,
         name: "tracing_output",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingLostChars,
         name: "tracing_lost_chars",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingCommands,
         name: "tracing_commands",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingRestores,
         name: "tracing_restores",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::UcHyph,
         name: "uc_hyph",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::OutputPenalty,
         name: "output_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::MaxDeadCycles,
         name: "max_dead_cycles",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::HangAfter,
         name: "hang_after",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::FloatingPenalty,
         name: "floating_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::GlobalDefs,
         name: "global_defs",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::CurFam,
         name: "cur_fam",
         primitive_kind: IntParPrimitiveKind::CustomName("fam"),
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::EscapeChar,
         name: "escape_char",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::DefaultHyphenChar,
         name: "default_hyphen_char",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::DefaultSkewChar,
         name: "default_skew_char",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::EndLineChar,
         name: "end_line_char",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::NewLineChar,
         name: "new_line_char",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Language,
         name: "language",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::LeftHyphenMin,
         name: "left_hyphen_min",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::RightHyphenMin,
         name: "right_hyphen_min",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::HoldingInserts,
         name: "holding_inserts",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::ErrorContextLines,
         name: "error_context_lines",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::CharSubDefMin,
         name: "char_sub_def_min",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: 32,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::CharSubDefMax,
         name: "char_sub_def_max",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: 32,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingCharSubDef,
         name: "tracing_char_sub_def",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: 32,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingStackLevels,
         name: "tracing_stack_levels",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 32,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingAssigns,
         name: "tracing_assigns",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingGroups,
         name: "tracing_groups",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingIfs,
         name: "tracing_ifs",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingScanTokens,
         name: "tracing_scan_tokens",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::TracingNesting,
         name: "tracing_nesting",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::PreDisplayDirection,
         name: "pre_display_direction",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::LastLineFit,
         name: "last_line_fit",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::SavingVdiscards,
         name: "saving_vdiscards",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::SavingHyphCodes,
         name: "saving_hyph_codes",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::SuppressFontnotfoundError,
         name: "suppress_fontnotfound_error",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexLinebreakLocale,
         name: "xetex_linebreak_locale",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexLinebreakPenalty,
         name: "XeTeX_linebreak_penalty",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexProtrudeChars,
         name: "XeTeX_protrude_chars",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Texxet,
         name: "texxet",
         primitive_kind: IntParPrimitiveKind::CustomName("TeXXeTstate"),
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexDashBreak,
         name: "xetex_dash_break",
         primitive_kind: IntParPrimitiveKind::CustomName("XeTeXdashbreakstate"),
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexUpwards,
         name: "XeTeX_upwards",
         primitive_kind: IntParPrimitiveKind::CustomName("XeTeXupwardsmode"),
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexUseGlyphMetrics,
         name: "XeTeX_use_glyph_metrics",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexInterCharTokens,
         name: "XeTeX_inter_char_tokens",
         primitive_kind: IntParPrimitiveKind::CustomName("XeTeXinterchartokenstate"),
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexInputNormalization,
         name: "XeTeX_input_normalization",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexDefaultInputMode,
         name: "XeTeX_default_input_mode",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexDefaultInputEncoding,
         name: "XeTeX_default_input_encoding",
         primitive_kind: IntParPrimitiveKind::None,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexTracingFonts,
         name: "XeTeX_tracing_fonts",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexInterwordSpaceShaping,
         name: "XeTeX_interword_space_shaping",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexGenerateActualText,
         name: "XeTeX_generate_actual_text",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::XetexHyphenatableLength,
         name: "XeTeX_hyphenatable_length",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Synctex,
         name: "synctex",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
         until: FormatVersion::MAX,
     },
-    IntPar {
+    IntParInfo {
+        par: IntPar::Pdfoutput,
         name: "pdfoutput",
         primitive_kind: IntParPrimitiveKind::Standard,
         since: 0,
@@ -564,7 +937,7 @@ const INT_PARS: &[IntPar] = &[
 pub fn get_intpars_for_version(
     version: FormatVersion,
     symbols: &mut SymbolTable,
-) -> Result<Vec<IntPar>> {
+) -> Result<Vec<IntParInfo>> {
     let mut r = Vec::new();
     let mut n = 0;
 
@@ -585,19 +958,13 @@ pub fn get_intpars_for_version(
 }
 
 /// Emit initializers for intpar primitives in the C header.
-pub fn emit_c_header_primitives<W: Write>(pars: &[IntPar], mut stream: W) -> Result<()> {
+pub fn emit_c_header_primitives<W: Write>(pars: &[IntParInfo], mut stream: W) -> Result<()> {
     for par in pars {
-        let (has_prim, prim_name) = match par.primitive_kind {
-            IntParPrimitiveKind::None => (false, ""),
-            IntParPrimitiveKind::Standard => (true, par.name),
-            IntParPrimitiveKind::CustomName(s) => (true, s),
-        };
-
-        if has_prim {
+        if let Some(prim_name) = par.primitive_name() {
             writeln!(
                 stream,
                 "    {{ \"{}\", ASSIGN_INT, INT_BASE + INT_PAR__{}, xf_prim_init_none }}, \\",
-                prim_name.replace('_', ""),
+                prim_name,
                 par.name.to_lowercase(),
             )?;
         }
@@ -0,0 +1,253 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Export the decodable content of a format as JSON.
+//!
+//! This makes the state saved in a format available to tools written in other
+//! languages. The output is a single object whose `schema_version` member is
+//! [`SCHEMA_VERSION`]. That number is incremented whenever members are removed
+//! or their meaning changes, so that consumers can reject output that they
+//! don't understand; new members may be added without changing it.
+//!
+//! The other members are:
+//!
+//! - `format_version`: the version of the format file layout, as in
+//!   [`FormatMetadata::version`](crate::format::FormatMetadata::version).
+//! - `container`: the framing that the format data were found in, such as
+//!   `"gzip"`.
+//! - `metadata`: an object with the `banner` of the macro package (or
+//!   `null`), the `dump_time` as an object with `year`, `month`, `day`, and
+//!   `minutes` members, and the booleans `texxet`,
+//!   `xetex_inter_char_tokens`, `xetex_use_glyph_metrics`, and
+//!   `xetex_upwards`.
+//! - `scalars`: an object with the members of
+//!   [`SavedScalars`](crate::format::SavedScalars).
+//! - `backend`: an object with the members of
+//!   [`BackendState`](crate::format::BackendState), where `specials` is an
+//!   array of objects with `location` and `text` members.
+//! - `int_parameters` and `dimen_parameters`: arrays of objects with `name`
+//!   and `value` members, in the engine's order. Dimensions are in scaled
+//!   points.
+//! - `cat_codes`, `lc_codes`, `uc_codes`, `sf_codes`, and `math_codes`: the
+//!   character code tables, as arrays of objects with `first`, `last`, and
+//!   `value` members. Each describes an inclusive range of characters that
+//!   share a value. The ranges are sorted and cover every Unicode scalar
+//!   value, except that a range may span the surrogate code points, which
+//!   have no entries.
+//! - `active_characters`: an array of objects with `char` and `meaning`
+//!   members.
+//! - `control_sequences`: an array of objects with `name`, `meaning`, and
+//!   `extended` members, where `extended` holds details such as the contents
+//!   of macros, or `null`. Names are formatted as by
+//!   [`fmt_csname`](crate::format::fmt_csname).
+//! - `register_shortcuts`: an array of objects with `name`, `kind`, and
+//!   `number` members, where `kind` is the name of the primitive that the
+//!   shortcut stands in for, such as `"count"`.
+//! - `register_allocations`: an array of objects with `kind`, `last`,
+//!   `limit`, and `remaining` members, or `null` if the format doesn't use
+//!   the plain TeX allocation counters.
+//! - `languages`: an array of objects with `number`, `has_patterns`,
+//!   `n_trie_ops`, `exceptions`, and `hyph_codes` members, where
+//!   `hyph_codes` is an array of `[char, code]` pairs, or `null`.
+//! - `strings`: an array of objects with `pointer` and `text` members.
+//!
+//! Characters are given as integer code points throughout.
+//!
+//! Use [`Format::dump_json`](crate::format::Format::dump_json) to write the
+//! export.
+
+use serde_json::{json, Value};
+use std::io::Write;
+use tectonic_errors::prelude::*;
+
+use crate::format::Format;
+
+/// The version of the structure of the JSON export.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Build the JSON representation of a format.
+pub fn format_to_value(fmt: &Format) -> Result<Value> {
+    let meta = fmt.metadata()?;
+    let scalars = fmt.saved_scalars();
+    let backend = fmt.backend_state()?;
+
+    let named = |pars: Vec<(String, i32)>| -> Value {
+        pars.into_iter()
+            .map(|(name, value)| json!({ "name": name, "value": value }))
+            .collect()
+    };
+
+    let mut cat_codes = Vec::new();
+
+    for c in all_chars() {
+        cat_codes.push(fmt.cat_code(c)? as i32);
+    }
+
+    Ok(json!({
+        "schema_version": SCHEMA_VERSION,
+        "format_version": meta.version,
+        "container": fmt.container().to_string(),
+        "metadata": {
+            "banner": meta.banner,
+            "dump_time": {
+                "year": meta.dump_time.year,
+                "month": meta.dump_time.month,
+                "day": meta.dump_time.day,
+                "minutes": meta.dump_time.minutes,
+            },
+            "texxet": meta.texxet,
+            "xetex_inter_char_tokens": meta.xetex_inter_char_tokens,
+            "xetex_use_glyph_metrics": meta.xetex_use_glyph_metrics,
+            "xetex_upwards": meta.xetex_upwards,
+        },
+        "scalars": {
+            "hash_high": scalars.hash_high,
+            "hyph_prime": scalars.hyph_prime,
+            "par_token": scalars.par_token,
+            "write_token": scalars.write_token,
+            "fonts": scalars.fonts,
+            "max_hyph_char": scalars.max_hyph_char,
+        },
+        "backend": {
+            "pdf_output": backend.pdf_output,
+            "mag": backend.mag,
+            "page_width": backend.page_width,
+            "page_height": backend.page_height,
+            "h_offset": backend.h_offset,
+            "v_offset": backend.v_offset,
+            "generate_actual_text": backend.generate_actual_text,
+            "synctex": backend.synctex,
+            "specials": backend
+                .specials
+                .iter()
+                .map(|s| json!({ "location": s.location, "text": s.text }))
+                .collect::<Value>(),
+        },
+        "int_parameters": named(fmt.int_parameters()),
+        "dimen_parameters": named(fmt.dimen_parameters()),
+        "cat_codes": runs(all_chars().zip(cat_codes)),
+        "lc_codes": runs(all_chars().map(|c| (c, fmt.lc_code(c)))),
+        "uc_codes": runs(all_chars().map(|c| (c, fmt.uc_code(c)))),
+        "sf_codes": runs(all_chars().map(|c| (c, fmt.sf_code(c)))),
+        "math_codes": runs(all_chars().map(|c| (c, fmt.math_code(c)))),
+        "active_characters": fmt
+            .active_characters()
+            .into_iter()
+            .map(|(c, meaning)| json!({ "char": c as u32, "meaning": meaning }))
+            .collect::<Value>(),
+        "control_sequences": fmt
+            .control_sequences(true)
+            .into_iter()
+            .map(|cs| json!({
+                "name": cs.name,
+                "meaning": cs.meaning,
+                "extended": cs.extended,
+            }))
+            .collect::<Value>(),
+        "register_shortcuts": fmt
+            .register_shortcuts()
+            .into_iter()
+            .map(|s| json!({
+                "name": s.name,
+                "kind": s.kind.primitive_name(),
+                "number": s.number,
+            }))
+            .collect::<Value>(),
+        "register_allocations": fmt.register_allocations().map(|allocs| {
+            allocs
+                .iter()
+                .map(|a| json!({
+                    "kind": a.kind.primitive_name(),
+                    "last": a.last,
+                    "limit": a.limit,
+                    "remaining": a.remaining(),
+                }))
+                .collect::<Value>()
+        }),
+        "languages": fmt
+            .languages()
+            .into_iter()
+            .map(|lang| json!({
+                "number": lang.number,
+                "has_patterns": lang.has_patterns,
+                "n_trie_ops": lang.n_trie_ops,
+                "exceptions": lang.exceptions,
+                "hyph_codes": lang.hyph_codes.map(|codes| {
+                    codes
+                        .into_iter()
+                        .map(|(c, code)| json!([c as u32, code]))
+                        .collect::<Value>()
+                }),
+            }))
+            .collect::<Value>(),
+        "strings": fmt
+            .strings()
+            .map(|(sp, text)| json!({ "pointer": sp, "text": text }))
+            .collect::<Value>(),
+    }))
+}
+
+/// Write the JSON representation of a format, pretty-printed.
+pub fn write_format<W: Write>(fmt: &Format, stream: &mut W) -> Result<()> {
+    let value = format_to_value(fmt)?;
+    serde_json::to_writer_pretty(&mut *stream, &value)?;
+    writeln!(stream)?;
+    Ok(())
+}
+
+fn all_chars() -> impl Iterator<Item = char> {
+    '\0'..=char::MAX
+}
+
+/// Collapse a table of per-character values, given in order, into ranges of
+/// consecutive characters that share a value.
+fn runs<I: IntoIterator<Item = (char, i32)>>(values: I) -> Value {
+    let mut result = Vec::new();
+    let mut cur: Option<(char, char, i32)> = None;
+
+    for (c, value) in values {
+        cur = match cur {
+            Some((first, _, v)) if v == value => Some((first, c, v)),
+            Some(prev) => {
+                result.push(prev);
+                Some((c, c, value))
+            }
+            None => Some((c, c, value)),
+        };
+    }
+
+    result.extend(cur);
+    result
+        .into_iter()
+        .map(|(first, last, value)| {
+            json!({ "first": first as u32, "last": last as u32, "value": value })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn collapse_runs() {
+        let values = [
+            ('a', 1),
+            ('b', 1),
+            ('c', 2),
+            ('\u{D7FF}', 2),
+            ('\u{E000}', 2),
+            ('z', 1),
+        ];
+
+        assert_eq!(
+            runs(values),
+            json!([
+                { "first": 0x61, "last": 0x62, "value": 1 },
+                { "first": 0x63, "last": 0xE000, "value": 2 },
+                { "first": 0x7A, "last": 0x7A, "value": 1 },
+            ])
+        );
+        assert_eq!(runs([]), json!([]));
+    }
+}
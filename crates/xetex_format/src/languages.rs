@@ -0,0 +1,224 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+#![allow(missing_docs)]
+
+//! Per-language hyphenation data.
+//!
+//! TeX keeps the hyphenation state of each language in a few different places.
+//! The patterns of all languages are packed into a single trie, with the
+//! first level of the trie indexed by language number. Hyphenation exceptions
+//! are kept in a hash table of strings, where the last character of each
+//! string is the number of the language that it belongs to. Finally, if
+//! `\savinghyphcodes` was positive when a language's patterns were loaded, a
+//! copy of the `\lccode`s in effect at that time is stored in a second part of
+//! the trie, and those codes are used instead of the current `\lccode`s when
+//! words in that language are hyphenated.
+
+use crate::{base::TEX_NULL, mem::Memory, stringtable::StringTable};
+
+/// The largest language number.
+pub const BIGGEST_LANG: usize = 255;
+
+/// The hyphenation data saved in a format file, in raw form.
+#[derive(Debug, Default)]
+pub struct HyphenationTables {
+    /// The `(hyph_word, hyph_list)` pairs of the hyphenation exceptions.
+    pub(crate) exceptions: Vec<(i32, i32)>,
+    pub(crate) trie_trl: Vec<i32>,
+    pub(crate) trie_tro: Vec<i32>,
+    pub(crate) trie_trc: Vec<u16>,
+    pub(crate) hyph_start: i32,
+    /// The number of trie operations used by each language.
+    pub(crate) trie_used: Vec<i32>,
+}
+
+/// The hyphenation data for one language.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Language {
+    /// The language number, as used by `\language`.
+    pub number: usize,
+
+    /// Whether any hyphenation patterns were loaded for this language.
+    pub has_patterns: bool,
+
+    /// The number of trie operations that this language's patterns use.
+    pub n_trie_ops: i32,
+
+    /// The hyphenation exceptions of this language, with their allowed
+    /// hyphenation points marked with hyphens, sorted alphabetically.
+    pub exceptions: Vec<String>,
+
+    /// The hyphenation codes saved when the patterns of this language were
+    /// loaded, as `(character, code)` pairs, if `\savinghyphcodes` was
+    /// positive at that time. Only characters with nonzero codes are listed.
+    /// When these are absent, the current `\lccode`s are used instead.
+    pub hyph_codes: Option<Vec<(char, i32)>>,
+}
+
+impl HyphenationTables {
+    fn trie_char(&self, p: i32) -> Option<u16> {
+        self.trie_trc.get(usize::try_from(p).ok()?).copied()
+    }
+
+    fn has_patterns(&self, lang: usize) -> bool {
+        let root = lang as i32 + 1;
+        self.trie_char(root) == Some(lang as u16) && self.trie_trl[root as usize] != 0
+    }
+
+    fn hyph_codes(&self, lang: usize) -> Option<Vec<(char, i32)>> {
+        if self.hyph_start == 0 {
+            return None;
+        }
+
+        let root = self.hyph_start + lang as i32;
+
+        if self.trie_char(root) != Some(lang as u16) {
+            return None;
+        }
+
+        let index = self.trie_trl[root as usize];
+
+        if index == 0 {
+            return None;
+        }
+
+        let codes = (0..=255u16)
+            .filter_map(|c| {
+                let p = index + c as i32;
+
+                if self.trie_char(p) != Some(c) {
+                    return None;
+                }
+
+                let code = self.trie_tro[p as usize];
+                (code != 0).then(|| (char::from(c as u8), code))
+            })
+            .collect();
+
+        Some(codes)
+    }
+
+    /// Decode the exceptions into `(language, word)` pairs.
+    fn decode_exceptions(&self, strings: &StringTable, mem: &Memory) -> Vec<(usize, String)> {
+        self.exceptions
+            .iter()
+            .filter_map(|&(word, list)| {
+                let mut chars: Vec<char> = strings.lookup(word).chars().collect();
+                let lang = chars.pop()? as usize;
+
+                // The list gives the number of letters preceding each
+                // hyphenation point.
+                let mut positions = Vec::new();
+                let mut p = list;
+
+                while p != TEX_NULL {
+                    let (n, next) = mem.decode_toklist(p);
+                    positions.push(n as usize);
+                    p = next;
+                }
+
+                let mut text = String::new();
+
+                for (i, c) in chars.into_iter().enumerate() {
+                    if positions.contains(&i) && i > 0 {
+                        text.push('-');
+                    }
+
+                    text.push(c);
+                }
+
+                Some((lang, text))
+            })
+            .collect()
+    }
+
+    /// Gather the data of every language that has patterns, exceptions, or
+    /// saved hyphenation codes.
+    pub(crate) fn languages(&self, strings: &StringTable, mem: &Memory) -> Vec<Language> {
+        let mut exceptions = vec![Vec::new(); BIGGEST_LANG + 1];
+
+        for (lang, word) in self.decode_exceptions(strings, mem) {
+            if let Some(e) = exceptions.get_mut(lang) {
+                e.push(word);
+            }
+        }
+
+        exceptions
+            .into_iter()
+            .enumerate()
+            .filter_map(|(number, mut exceptions)| {
+                let has_patterns = self.has_patterns(number);
+                let hyph_codes = self.hyph_codes(number);
+
+                if !has_patterns && exceptions.is_empty() && hyph_codes.is_none() {
+                    return None;
+                }
+
+                exceptions.sort();
+
+                Some(Language {
+                    number,
+                    has_patterns,
+                    n_trie_ops: self.trie_used.get(number).copied().unwrap_or(0),
+                    exceptions,
+                    hyph_codes,
+                })
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::{memword_write_b32_s0, memword_write_b32_s1, SIZEOF_MEMORY_WORD};
+
+    #[test]
+    fn decode_languages() {
+        let strings = StringTable {
+            strings: vec!["table\u{0}".to_owned(), "xyz\u{1}".to_owned()],
+        };
+
+        let mut mem = vec![0; 8 * SIZEOF_MEMORY_WORD];
+        memword_write_b32_s0(&mut mem, 5, 2);
+        memword_write_b32_s1(&mut mem, 5, TEX_NULL);
+        let mem = Memory {
+            mem,
+            lo_mem_max: 0,
+            sa_roots: Vec::new(),
+        };
+
+        // Patterns for language 0, and saved codes for language 1.
+        let mut tables = HyphenationTables {
+            exceptions: vec![(0x10000, 5), (0x10001, TEX_NULL)],
+            trie_trl: vec![0; 600],
+            trie_tro: vec![0; 600],
+            trie_trc: vec![0; 600],
+            hyph_start: 300,
+            trie_used: vec![0; BIGGEST_LANG + 1],
+        };
+        tables.trie_trl[1] = 10;
+        tables.trie_used[0] = 3;
+        tables.trie_trc[301] = 1;
+        tables.trie_trl[301] = 400;
+        tables.trie_trc[400 + 66] = 66;
+        tables.trie_tro[400 + 66] = 98;
+        tables.trie_trc[400 + 97] = 97;
+        tables.trie_tro[400 + 97] = 97;
+
+        let langs = tables.languages(&strings, &mem);
+        assert_eq!(langs.len(), 2);
+
+        assert_eq!(langs[0].number, 0);
+        assert!(langs[0].has_patterns);
+        assert_eq!(langs[0].n_trie_ops, 3);
+        assert_eq!(langs[0].exceptions, ["ta-ble"]);
+        assert_eq!(langs[0].hyph_codes, None);
+
+        assert_eq!(langs[1].number, 1);
+        assert!(!langs[1].has_patterns);
+        assert_eq!(langs[1].exceptions, ["xyz"]);
+        assert_eq!(langs[1].hyph_codes, Some(vec![('B', 98), ('a', 97)]));
+    }
+}
@@ -44,13 +44,15 @@
 pub type FormatVersion = usize;
 
 /// The latest format version number supported by this version of the crate.
-pub const LATEST_VERSION: FormatVersion = 33;
+pub const LATEST_VERSION: FormatVersion = 34;
 
 mod parseutils;
 
 pub mod base;
 pub mod catcodes;
 pub mod commands;
+pub mod consistency;
+pub mod container;
 pub mod cshash;
 pub mod dimenpars;
 pub mod engine;
@@ -60,8 +62,12 @@ pub mod etexpenalties;
 pub mod format;
 pub mod gluepars;
 pub mod intpars;
+pub mod json;
+pub mod languages;
 pub mod locals;
 pub mod mem;
+pub mod registers;
+pub mod state;
 pub mod stringtable;
 pub mod symbols;
 pub mod tokenlist;
@@ -123,6 +123,26 @@ const LOCAL_PARS: &[LocalPar] = &[
     },
 ];
 
+impl LocalPar {
+    /// Get the name of the parameter.
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Get the kind of the parameter.
+    pub fn kind(&self) -> LocalParKind {
+        self.kind
+    }
+
+    /// Get the name of the primitive that accesses the parameter, without the
+    /// leading backslash.
+    pub fn primitive_name(&self) -> String {
+        self.custom_primitive_name
+            .unwrap_or(self.name)
+            .replace('_', "")
+    }
+}
+
 /// Get information about the local parameters used in a specific
 /// engine format version.
 pub fn get_local_pars_for_version(
@@ -156,15 +176,10 @@ pub fn emit_c_header_primitives<W: Write>(pars: &[LocalPar], mut stream: W) -> R
             LocalParKind::TokenList => "ASSIGN_TOKS",
         };
 
-        let prim_name = match par.custom_primitive_name {
-            Some(s) => s,
-            None => par.name,
-        };
-
         writeln!(
             stream,
             "    {{ \"{}\", {}, LOCAL_BASE + LOCAL__{}, xf_prim_init_none }}, \\",
-            prim_name.replace('_', ""),
+            par.primitive_name(),
             cmd,
             par.name.to_lowercase(),
         )?;
@@ -25,6 +25,10 @@ pub struct Memory {
 
     /// This is needed by the format-file parser for a bounds check.
     pub lo_mem_max: MemPointer,
+
+    /// The roots of the e-TeX sparse arrays, from `INT_VAL` to
+    /// `INTER_CHAR_VAL`.
+    pub sa_roots: Vec<MemPointer>,
 }
 
 const HI_MEM_STAT_USAGE: i32 = 15;
@@ -41,7 +45,7 @@ impl Memory {
         // lower limit hardcoded
         let (input, rover) = parseutils::ranged_be_i32(20, lo_mem_max)(input)?;
 
-        let (input, _sa_roots) = count(
+        let (input, sa_roots) = count(
             parseutils::ranged_be_i32(MIN_HALFWORD, lo_mem_max),
             N_SERIALIZED_SA_ROOTS,
         )
@@ -93,7 +97,14 @@ impl Memory {
 
         let (input, _var_used) = be_i32(input)?;
         let (input, _dyn_used) = be_i32(input)?;
-        Ok((input, Memory { mem, lo_mem_max }))
+        Ok((
+            input,
+            Memory {
+                mem,
+                lo_mem_max,
+                sa_roots,
+            },
+        ))
     }
 
     pub fn decode_toklist(&self, index: MemPointer) -> (i32, MemPointer) {
@@ -0,0 +1,340 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Register shortcuts and allocations.
+//!
+//! Macro packages seldom refer to registers by number. Allocation macros such
+//! as `\newcount` pick the next free register and name it with `\countdef`
+//! and its relatives, making the name a *shortcut* that acts like `\count`
+//! followed by the number. TeX has no `\boxdef`, so boxes, as well as input
+//! and output streams, math families, and languages, are named with
+//! `\chardef` instead.
+//!
+//! Plain TeX and LaTeX record the last number that they've allocated of each
+//! kind in `\count10` through `\count19`, and the last insertion, which are
+//! allocated downwards from 254, in `\count20`. When the numbers of a kind run
+//! out, allocation fails with errors like "No room for a new \count". Recent
+//! LaTeX kernels then move on to the e-TeX registers above 255, which aren't
+//! tracked by these counters but show up as shortcuts.
+//!
+//! Use [`Format::register_shortcuts`](crate::format::Format::register_shortcuts)
+//! and [`Format::register_allocations`](crate::format::Format::register_allocations)
+//! to examine them.
+
+use std::fmt;
+
+use crate::{
+    base,
+    format::fmt_csname,
+    mem::{MemPointer, Memory},
+};
+
+/// The number of the `\count` register that plain TeX and LaTeX use to track
+/// insertions, also known as `\insc@unt`.
+pub const INSERT_COUNTER: usize = 20;
+
+/// The largest value of the pointer that a `\count`, `\dimen`, `\skip`,
+/// `\muskip`, or `\toks` primitive holds in place of a sparse array element.
+const MAX_PRIMITIVE_POINTER: MemPointer = 3;
+
+/// The kind of a register shortcut, which is named after the primitive that
+/// it stands in for.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum ShortcutKind {
+    /// Defined with `\countdef`.
+    Count,
+
+    /// Defined with `\dimendef`.
+    Dimen,
+
+    /// Defined with `\skipdef`.
+    Skip,
+
+    /// Defined with `\muskipdef`.
+    MuSkip,
+
+    /// Defined with `\toksdef`.
+    Toks,
+
+    /// Defined with `\chardef`.
+    Char,
+
+    /// Defined with `\mathchardef`.
+    MathChar,
+
+    /// Defined with `\Umathchardef` or `\Umathcharnumdef`, whose values use
+    /// XeTeX's extended math code encoding.
+    XetexMathChar,
+}
+
+impl ShortcutKind {
+    /// Get the name of the primitive that shortcuts of this kind stand in for.
+    pub fn primitive_name(self) -> &'static str {
+        match self {
+            ShortcutKind::Count => "count",
+            ShortcutKind::Dimen => "dimen",
+            ShortcutKind::Skip => "skip",
+            ShortcutKind::MuSkip => "muskip",
+            ShortcutKind::Toks => "toks",
+            ShortcutKind::Char => "char",
+            ShortcutKind::MathChar => "mathchar",
+            ShortcutKind::XetexMathChar => "Umathchar",
+        }
+    }
+
+    /// Get the kind of a register held in the e-TeX sparse array of type *t*,
+    /// if shortcuts can be defined to registers of that type.
+    fn from_sparse_type(t: i32) -> Option<Self> {
+        match t {
+            0 => Some(ShortcutKind::Count),
+            1 => Some(ShortcutKind::Dimen),
+            2 => Some(ShortcutKind::Skip),
+            3 => Some(ShortcutKind::MuSkip),
+            5 => Some(ShortcutKind::Toks),
+            _ => None,
+        }
+    }
+}
+
+/// A control sequence defined as a shortcut to a register or a character
+/// code, as returned by
+/// [`Format::register_shortcuts`](crate::format::Format::register_shortcuts).
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct RegisterShortcut {
+    /// The kind of the shortcut.
+    pub kind: ShortcutKind,
+
+    /// The number of the register, or the character code, that the shortcut
+    /// stands for.
+    pub number: i32,
+
+    /// The name of the control sequence, without an escape character.
+    pub name: String,
+}
+
+impl fmt::Display for RegisterShortcut {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} => \\{}{}",
+            fmt_csname(&self.name),
+            self.kind.primitive_name(),
+            self.number
+        )
+    }
+}
+
+/// A kind of numbered resource allocated by plain TeX and LaTeX.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum AllocationKind {
+    /// `\count` registers, allocated by `\newcount`.
+    Count,
+
+    /// `\dimen` registers, allocated by `\newdimen`.
+    Dimen,
+
+    /// `\skip` registers, allocated by `\newskip`.
+    Skip,
+
+    /// `\muskip` registers, allocated by `\newmuskip`.
+    MuSkip,
+
+    /// `\box` registers, allocated by `\newbox`.
+    Box,
+
+    /// `\toks` registers, allocated by `\newtoks`.
+    Toks,
+
+    /// Input streams, allocated by `\newread`.
+    Read,
+
+    /// Output streams, allocated by `\newwrite`.
+    Write,
+
+    /// Math families, allocated by `\newfam`.
+    Family,
+
+    /// Languages, allocated by `\newlanguage`.
+    Language,
+
+    /// Insertions, allocated by `\newinsert`. Each one uses the `\box`,
+    /// `\count`, `\dimen`, and `\skip` registers of its number.
+    Insert,
+}
+
+impl AllocationKind {
+    /// All of the kinds, in the order of their counters.
+    pub const ALL: &'static [AllocationKind] = &[
+        AllocationKind::Count,
+        AllocationKind::Dimen,
+        AllocationKind::Skip,
+        AllocationKind::MuSkip,
+        AllocationKind::Box,
+        AllocationKind::Toks,
+        AllocationKind::Read,
+        AllocationKind::Write,
+        AllocationKind::Family,
+        AllocationKind::Language,
+        AllocationKind::Insert,
+    ];
+
+    /// Get the number of the `\count` register that tracks allocations of
+    /// this kind.
+    pub fn counter(self) -> usize {
+        match self {
+            AllocationKind::Insert => INSERT_COUNTER,
+            k => 10 + k as usize,
+        }
+    }
+
+    /// Get the name of the primitive that uses resources of this kind.
+    pub fn primitive_name(self) -> &'static str {
+        match self {
+            AllocationKind::Count => "count",
+            AllocationKind::Dimen => "dimen",
+            AllocationKind::Skip => "skip",
+            AllocationKind::MuSkip => "muskip",
+            AllocationKind::Box => "box",
+            AllocationKind::Toks => "toks",
+            AllocationKind::Read => "read",
+            AllocationKind::Write => "write",
+            AllocationKind::Family => "fam",
+            AllocationKind::Language => "language",
+            AllocationKind::Insert => "insert",
+        }
+    }
+}
+
+/// The state of the allocation of one kind of resource, as returned by
+/// [`Format::register_allocations`](crate::format::Format::register_allocations).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Allocation {
+    /// The kind of resource.
+    pub kind: AllocationKind,
+
+    /// The value of the kind's counter: the last number that was allocated,
+    /// or the one before the first number to be allocated if none have been.
+    pub last: i32,
+
+    /// The bound that allocations must stay below, or above for insertions.
+    /// For `\count`, `\dimen`, `\skip`, and `\box` registers, this is the
+    /// last insertion number, and for insertions, it's the largest of those
+    /// registers' counters.
+    pub limit: i32,
+}
+
+impl Allocation {
+    /// Get how many more resources of this kind can be allocated before the
+    /// allocation macros report that there's no room for another.
+    pub fn remaining(&self) -> i32 {
+        let n = match self.kind {
+            AllocationKind::Insert => self.last - self.limit - 1,
+            _ => self.limit - self.last - 1,
+        };
+
+        n.max(0)
+    }
+}
+
+/// Work out which register an element of an e-TeX sparse array is.
+///
+/// The element is at *p*, a pointer that a register shortcut holds in place
+/// of an eqtb location when the register is numbered above 255. Its index
+/// gives its type and the low six bits of its number, and the index nodes
+/// between it and the root of its tree give the rest.
+pub(crate) fn sparse_register(mem: &Memory, p: MemPointer) -> Option<(ShortcutKind, i32)> {
+    let in_range = |q: MemPointer| (0..=mem.lo_mem_max).contains(&q);
+
+    if p <= MAX_PRIMITIVE_POINTER || !in_range(p) {
+        return None;
+    }
+
+    let arr = &mem.mem[..];
+    let index = base::memword_read_b16_s1(arr, p) as i32;
+    let kind = ShortcutKind::from_sparse_type(index / 64)?;
+    let mut number = index % 64;
+    let mut q = p;
+
+    for shift in [6, 12, 18] {
+        q = base::memword_read_b32_s1(arr, q);
+
+        if !in_range(q) {
+            return None;
+        }
+
+        number += (base::memword_read_b16_s1(arr, q) as i32 % 64) << shift;
+    }
+
+    Some((kind, number))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::base::{SIZEOF_MEMORY_WORD, TEX_NULL};
+
+    /// Build the chain of nodes leading to the sparse array element of
+    /// `\dimen` 4141 = 1 * 4096 + 0 * 64 + 45: index nodes at 100, 140, 180,
+    /// and 220, and a word node at 260.
+    fn dimen4141() -> Memory {
+        let mut mem = Memory {
+            mem: vec![0; 400 * SIZEOF_MEMORY_WORD],
+            lo_mem_max: 300,
+            sa_roots: vec![TEX_NULL; 7],
+        };
+        mem.sa_roots[1] = 100;
+
+        let arr = &mut mem.mem[..];
+        let mut parent = TEX_NULL;
+
+        for (p, index) in [(100, 1), (140, 0), (180, 1), (220, 0), (260, 64 + 45)] {
+            base::memword_write_b16_s1(arr, p, index);
+            base::memword_write_b32_s1(arr, p, parent);
+            parent = p;
+        }
+
+        mem
+    }
+
+    #[test]
+    fn sparse_registers() {
+        let mut mem = dimen4141();
+        assert_eq!(
+            sparse_register(&mem, 260),
+            Some((ShortcutKind::Dimen, 4141))
+        );
+
+        // Primitives hold small pointers rather than sparse array elements.
+        assert_eq!(sparse_register(&mem, 1), None);
+
+        // Boxes can't have shortcuts.
+        base::memword_write_b16_s1(&mut mem.mem[..], 260, 4 * 64 + 45);
+        assert_eq!(sparse_register(&mem, 260), None);
+
+        // Broken links are rejected rather than followed.
+        base::memword_write_b16_s1(&mut mem.mem[..], 260, 5 * 64 + 45);
+        base::memword_write_b32_s1(&mut mem.mem[..], 180, 999);
+        assert_eq!(sparse_register(&mem, 260), None);
+    }
+
+    #[test]
+    fn allocations() {
+        let count = Allocation {
+            kind: AllocationKind::Count,
+            last: 22,
+            limit: 255,
+        };
+        assert_eq!(count.remaining(), 232);
+
+        let insert = Allocation {
+            kind: AllocationKind::Insert,
+            last: 253,
+            limit: 252,
+        };
+        assert_eq!(insert.remaining(), 0);
+
+        assert_eq!(AllocationKind::Count.counter(), 10);
+        assert_eq!(AllocationKind::Language.counter(), 19);
+        assert_eq!(AllocationKind::Insert.counter(), INSERT_COUNTER);
+    }
+}
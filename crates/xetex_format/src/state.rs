@@ -0,0 +1,343 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Compare a format file against the state of a running engine.
+//!
+//! The `\tectonicdumpstate{<file>}` primitive writes a textual snapshot of the
+//! parts of the engine state that are saved in format files: the integer and
+//! dimension parameters, the `\count` and `\dimen` registers, the character
+//! codes of the first 256 characters, and the meanings of the multi-letter
+//! control sequences. Macros are described by a hash of their token lists,
+//! since their bodies live at addresses that don't mean anything outside of
+//! the engine.
+//!
+//! [`EngineState::parse`] reads such a snapshot, and
+//! [`Format::compare_state`](crate::format::Format::compare_state) lists the
+//! places where it differs from a format. Running the snapshot primitive at
+//! the very start of a document, before anything has been redefined, should
+//! therefore show no differences with the format that the engine loaded.
+//!
+//! The snapshot is written by `tectonic_dump_state()` in the engine's
+//! `xetex-ini.c`, and the layout of the two must be kept in sync.
+
+use std::{collections::BTreeMap, fmt};
+use tectonic_errors::prelude::*;
+
+use crate::{commands::CommandCode, FormatVersion};
+
+/// The 64-bit FNV-1a offset basis, which starts the hash of a macro body.
+pub(crate) const BODY_HASH_INIT: u64 = 0xcbf2_9ce4_8422_2325;
+
+const BODY_HASH_PRIME: u64 = 0x0100_0000_01b3;
+
+/// Add a word of a macro's token list to its hash.
+pub(crate) fn hash_body_word(h: u64, word: i32) -> u64 {
+    word.to_le_bytes()
+        .iter()
+        .fold(h, |h, b| (h ^ *b as u64).wrapping_mul(BODY_HASH_PRIME))
+}
+
+/// The kinds of per-character codes in an engine state snapshot.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum CharCodeKind {
+    Cat,
+    Lc,
+    Uc,
+    Sf,
+    Math,
+}
+
+impl CharCodeKind {
+    fn keyword(&self) -> &'static str {
+        match self {
+            CharCodeKind::Cat => "catcode",
+            CharCodeKind::Lc => "lccode",
+            CharCodeKind::Uc => "uccode",
+            CharCodeKind::Sf => "sfcode",
+            CharCodeKind::Math => "mathcode",
+        }
+    }
+
+    fn from_keyword(s: &str) -> Option<Self> {
+        Some(match s {
+            "catcode" => CharCodeKind::Cat,
+            "lccode" => CharCodeKind::Lc,
+            "uccode" => CharCodeKind::Uc,
+            "sfcode" => CharCodeKind::Sf,
+            "mathcode" => CharCodeKind::Math,
+            _ => return None,
+        })
+    }
+
+    /// The name of the eqtb region holding this kind of code.
+    pub(crate) fn eqtb_region(&self) -> &'static str {
+        match self {
+            CharCodeKind::Cat => "CAT_CODE_BASE",
+            CharCodeKind::Lc => "LC_CODE_BASE",
+            CharCodeKind::Uc => "UC_CODE_BASE",
+            CharCodeKind::Sf => "SF_CODE_BASE",
+            CharCodeKind::Math => "MATH_CODE_BASE",
+        }
+    }
+}
+
+/// An item of engine state that can be compared.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub(crate) enum StateKey {
+    Int(usize),
+    Dimen(usize),
+    Count(usize),
+    DimenRegister(usize),
+    CharCode(CharCodeKind, u32),
+    ControlSeq(String),
+}
+
+/// The value of an item of engine state.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum StateValue {
+    Int(i32),
+    Scaled(i32),
+    Equiv { ty: CommandCode, value: i32 },
+    Macro { ty: CommandCode, hash: u64 },
+}
+
+/// A snapshot of the state of a running engine, as written by
+/// `\tectonicdumpstate`.
+#[derive(Clone, Debug)]
+pub struct EngineState {
+    version: FormatVersion,
+    pub(crate) items: BTreeMap<StateKey, StateValue>,
+}
+
+impl EngineState {
+    /// Parse the text of a snapshot.
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut version = None;
+        let mut items = BTreeMap::new();
+
+        for (lineno, line) in text.lines().enumerate() {
+            let line = line.trim_end();
+
+            if line.is_empty() || line.starts_with('%') {
+                continue;
+            }
+
+            let (key, value) = match parse_line(line) {
+                Ok(Line::Version(v)) => {
+                    version = Some(v);
+                    continue;
+                }
+                Ok(Line::Item(k, v)) => (k, v),
+                Err(e) => bail!("line {}: {}", lineno + 1, e),
+            };
+
+            items.insert(key, value);
+        }
+
+        let Some(version) = version else {
+            bail!("not an engine state snapshot: no `format-version` line");
+        };
+
+        Ok(EngineState { version, items })
+    }
+
+    /// The format version of the engine that wrote the snapshot.
+    pub fn version(&self) -> FormatVersion {
+        self.version
+    }
+}
+
+enum Line {
+    Version(FormatVersion),
+    Item(StateKey, StateValue),
+}
+
+fn parse_line(line: &str) -> Result<Line> {
+    let mut fields = line.splitn(4, ' ');
+    let keyword = fields.next().unwrap(); // splitn always yields an item
+    let mut next = || {
+        fields
+            .next()
+            .ok_or_else(|| anyhow!("missing field in `{}`", line))
+    };
+
+    let index = |s: &str| -> Result<usize> {
+        s.parse()
+            .map_err(|_| anyhow!("expected an index, got `{}`", s))
+    };
+
+    let int = |s: &str| -> Result<i32> {
+        s.parse()
+            .map_err(|_| anyhow!("expected an integer, got `{}`", s))
+    };
+
+    Ok(match keyword {
+        "format-version" => Line::Version(index(next()?)?),
+        "int" => Line::Item(
+            StateKey::Int(index(next()?)?),
+            StateValue::Int(int(next()?)?),
+        ),
+        "dimen" => Line::Item(
+            StateKey::Dimen(index(next()?)?),
+            StateValue::Scaled(int(next()?)?),
+        ),
+        "count" => Line::Item(
+            StateKey::Count(index(next()?)?),
+            StateValue::Int(int(next()?)?),
+        ),
+        "dimen-register" => Line::Item(
+            StateKey::DimenRegister(index(next()?)?),
+            StateValue::Scaled(int(next()?)?),
+        ),
+        "cs" => {
+            let ty: CommandCode = next()?
+                .parse()
+                .map_err(|_| anyhow!("expected a command code in `{}`", line))?;
+            let value = next()?;
+
+            let value = if let Some(hash) = value.strip_prefix('m') {
+                let hash = u64::from_str_radix(hash, 16)
+                    .map_err(|_| anyhow!("expected a macro body hash, got `{}`", value))?;
+                StateValue::Macro { ty, hash }
+            } else {
+                StateValue::Equiv {
+                    ty,
+                    value: int(value)?,
+                }
+            };
+
+            Line::Item(StateKey::ControlSeq(unescape_name(next()?)?), value)
+        }
+        other => {
+            let Some(kind) = CharCodeKind::from_keyword(other) else {
+                bail!("unrecognized item `{}`", other);
+            };
+
+            let c = index(next()?)? as u32;
+            let value = int(next()?)?;
+            Line::Item(StateKey::CharCode(kind, c), StateValue::Int(value))
+        }
+    })
+}
+
+/// Undo the `%XX` escaping applied to control sequence names, which keeps
+/// whitespace out of them.
+fn unescape_name(s: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+
+    while let Some((&b, tail)) = rest.split_first() {
+        if b == b'%' {
+            let hex = tail
+                .get(..2)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+                .ok_or_else(|| anyhow!("bad escape in control sequence name `{}`", s))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(b);
+            rest = tail;
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| anyhow!("control sequence name `{}` is not UTF-8", s))
+}
+
+/// A difference between a format and a snapshot of engine state, as returned
+/// by [`Format::compare_state`](crate::format::Format::compare_state).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StateDifference {
+    /// The name of the item that differs, such as `\tolerance`, `\count12`,
+    /// or `\catcode64`.
+    pub item: String,
+
+    /// A description of the item's value in the format, or `None` if the
+    /// format doesn't define it.
+    pub format: Option<String>,
+
+    /// A description of the item's value in the running engine, or `None` if
+    /// the engine doesn't define it.
+    pub engine: Option<String>,
+}
+
+impl fmt::Display for StateDifference {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let show = |v: &Option<String>| v.clone().unwrap_or_else(|| "(undefined)".to_owned());
+
+        write!(
+            f,
+            "{}: {} in the format, {} in the engine",
+            self.item,
+            show(&self.format),
+            show(&self.engine)
+        )
+    }
+}
+
+pub(crate) fn fmt_key(
+    key: &StateKey,
+    int_name: impl Fn(usize) -> String,
+    dimen_name: impl Fn(usize) -> String,
+) -> String {
+    match key {
+        StateKey::Int(i) => int_name(*i),
+        StateKey::Dimen(i) => dimen_name(*i),
+        StateKey::Count(n) => format!("\\count{n}"),
+        StateKey::DimenRegister(n) => format!("\\dimen{n}"),
+        StateKey::CharCode(kind, c) => format!("\\{}{}", kind.keyword(), c),
+        StateKey::ControlSeq(name) => crate::format::fmt_csname(name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let state = EngineState::parse(
+            "% Tectonic engine state\n\
+             format-version 33\n\
+             int 3 1000\n\
+             dimen 2 65536\n\
+             count 12 -4\n\
+             dimen-register 0 0\n\
+             catcode 64 11\n\
+             cs 113 m00000000000000ff my%20macro\n\
+             cs 68 42 chardefd\n",
+        )
+        .unwrap();
+
+        assert_eq!(state.version(), 33);
+        assert_eq!(state.items.len(), 7);
+        assert_eq!(state.items[&StateKey::Int(3)], StateValue::Int(1000));
+        assert_eq!(state.items[&StateKey::Dimen(2)], StateValue::Scaled(65536));
+        assert_eq!(state.items[&StateKey::Count(12)], StateValue::Int(-4));
+        assert_eq!(
+            state.items[&StateKey::CharCode(CharCodeKind::Cat, 64)],
+            StateValue::Int(11)
+        );
+        assert_eq!(
+            state.items[&StateKey::ControlSeq("my macro".to_owned())],
+            StateValue::Macro {
+                ty: 113,
+                hash: 0xff
+            }
+        );
+        assert_eq!(
+            state.items[&StateKey::ControlSeq("chardefd".to_owned())],
+            StateValue::Equiv { ty: 68, value: 42 }
+        );
+
+        assert!(EngineState::parse("int 3 1000\n").is_err());
+        assert!(EngineState::parse("format-version 33\nbogus 1 2\n").is_err());
+        assert!(EngineState::parse("format-version 33\ncs 113 m12 bad%2\n").is_err());
+    }
+
+    #[test]
+    fn body_hash() {
+        // The FNV-1a hash of the bytes 01 00 00 00.
+        assert_eq!(hash_body_word(BODY_HASH_INIT, 1), 0xad2a_ca77_4798_5764);
+    }
+}
@@ -47,6 +47,13 @@ impl StringTable {
         &self.strings[sp as usize - 0x10000]
     }
 
+    /// Look up a string, returning `None` if the pointer doesn't refer to an
+    /// entry in the table.
+    pub fn get(&self, sp: StrPointer) -> Option<&str> {
+        let index = usize::try_from(sp).ok()?.checked_sub(0x10000)?;
+        self.strings.get(index).map(|s| s.as_str())
+    }
+
     pub fn utf16_length(&self, sp: StrPointer) -> usize {
         if sp > 0xFFFF {
             len_utf16(self.lookup(sp))
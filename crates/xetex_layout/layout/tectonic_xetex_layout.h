@@ -90,6 +90,14 @@ int get_cp_code(int fontNum, unsigned int code, int side);
 int getCachedGlyphBBox(uint16_t fontID, uint16_t glyphID, GlyphBBox* bbox);
 void cacheGlyphBBox(uint16_t fontID, uint16_t glyphID, const GlyphBBox* bbox);
 
+typedef void (*ShapedTextVisitor)(void* context, const uint16_t* key, size_t keyLen, Fixed width, int glyphCount, const void* glyphInfo, size_t glyphInfoLen);
+
+int getCachedShapedText(const uint16_t* key, size_t keyLen, Fixed* width, int* glyphCount, void** glyphInfo);
+size_t cacheShapedText(const uint16_t* key, size_t keyLen, Fixed width, int glyphCount, const void* glyphInfo, size_t glyphInfoLen);
+void clearShapedTextCache(void);
+size_t setShapedTextCacheLimit(size_t limit);
+size_t visitShapedTextCache(ShapedTextVisitor visitor, void* context);
+
 void terminate_font_manager(void);
 void destroy_font_manager(void);
 
@@ -132,6 +140,7 @@ XeTeXLayoutEngine createLayoutEngine(PlatformFontRef fontRef, XeTeXFont font, hb
 void deleteLayoutEngine(XeTeXLayoutEngine engine);
 
 XeTeXFont getFont(XeTeXLayoutEngine engine);
+uint64_t getFontDataDigest(XeTeXFont font);
 PlatformFontRef getFontRef(XeTeXLayoutEngine engine);
 
 float getExtendFactor(XeTeXLayoutEngine engine);
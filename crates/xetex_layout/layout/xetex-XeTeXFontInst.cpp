@@ -81,6 +81,7 @@ XeTeXFontInst::XeTeXFontInst(const char* pathname, int index, float pointSize, i
     , m_ftFace(0)
     , m_backingData(NULL)
     , m_backingData2(NULL)
+    , m_dataDigest(0)
     , m_hbFont(NULL)
 {
     if (pathname != NULL)
@@ -299,6 +300,17 @@ _get_table(hb_face_t *, hb_tag_t tag, void *user_data)
     return blob;
 }
 
+// FNV-1a, which is plenty to tell versions of a font file apart.
+static uint64_t
+digestBytes(uint64_t digest, const FT_Byte* data, size_t len)
+{
+    for (size_t i = 0; i < len; i++) {
+        digest ^= data[i];
+        digest *= UINT64_C(0x100000001b3);
+    }
+    return digest;
+}
+
 void
 XeTeXFontInst::initialize(const char* pathname, int index, int &status)
 {
@@ -330,6 +342,7 @@ XeTeXFontInst::initialize(const char* pathname, int index, int &status)
     if (r < 0 || (size_t) r != sz)
         _tt_abort("failed to read font file");
     ttstub_input_close(handle);
+    m_dataDigest = digestBytes(UINT64_C(0xcbf29ce484222325), m_backingData, sz);
 
     error = FT_New_Memory_Face(gFreeTypeLibrary, m_backingData, sz, index, &m_ftFace);
 
@@ -360,6 +373,7 @@ XeTeXFontInst::initialize(const char* pathname, int index, int &status)
             if (r < 0 || (size_t) r != sz)
                 _tt_abort("failed to read AFM file");
             ttstub_input_close(afm_handle);
+            m_dataDigest = digestBytes(m_dataDigest, m_backingData2, sz);
 
             FT_Open_Args open_args;
             open_args.flags = FT_OPEN_MEMORY;
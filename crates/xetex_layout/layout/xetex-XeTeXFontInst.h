@@ -85,6 +85,7 @@ protected:
 
     FT_Face m_ftFace;
     FT_Byte *m_backingData, *m_backingData2;
+    uint64_t m_dataDigest; // digest of the font file and any AFM file attached to it
     hb_font_t* m_hbFont;
 
 public:
@@ -104,6 +105,7 @@ public:
         return m_filename;
     }
     hb_font_t *getHbFont() const { return m_hbFont; }
+    uint64_t getDataDigest() const { return m_dataDigest; }
     void setLayoutDirVertical(bool vertical);
     bool getLayoutDirVertical() const { return m_vertical; }
 
@@ -99,6 +99,154 @@ cacheGlyphBBox(uint16_t fontID, uint16_t glyphID, const GlyphBBox* bbox)
     sGlyphBoxes[key] = *bbox;
 }
 
+/*******************************************************************/
+/* Shaped text cache, to avoid reshaping unchanged text across     */
+/* engine passes                                                   */
+/*******************************************************************/
+#include <algorithm>
+#include <list>
+#include <mutex>
+#include <string>
+#include <unordered_map>
+#include <vector>
+
+// key is an opaque sequence of UTF-16 code units built by the caller,
+// identifying the font and the text that was shaped. The cache may be
+// loaded or saved from another thread while the engine runs, so all
+// accesses go through the lock, and lookups hand out copies of the glyph
+// info rather than pointers into the map.
+//
+// The entries are kept in a list, most recently used first, and once they
+// take up more than the limit the least recently used ones are evicted, so
+// that a large document can't make the cache grow without bound.
+struct ShapedText {
+    std::u16string key;
+    Fixed       width;
+    int         glyphCount;
+    std::string glyphInfo;
+
+    // A rough measure of the memory taken by the entry, counting its key
+    // twice since the map holds a copy of it.
+    size_t size() const {
+        return sizeof(ShapedText) + 2 * key.size() * sizeof(char16_t) + glyphInfo.size();
+    }
+};
+
+typedef std::list<ShapedText> ShapedTextList;
+
+static ShapedTextList sShapedTexts;
+static std::unordered_map<std::u16string,ShapedTextList::iterator> sShapedTextIndex;
+static size_t sShapedTextsSize = 0;
+static size_t sShapedTextsLimit = 64 * 1024 * 1024;
+static std::mutex sShapedTextsLock;
+
+// Must be called with the lock held. Returns the number of entries evicted.
+static size_t
+evictShapedTexts(void)
+{
+    size_t evicted = 0;
+
+    while (sShapedTextsSize > sShapedTextsLimit && !sShapedTexts.empty()) {
+        const ShapedText& victim = sShapedTexts.back();
+        sShapedTextsSize -= victim.size();
+        sShapedTextIndex.erase(victim.key);
+        sShapedTexts.pop_back();
+        evicted++;
+    }
+
+    return evicted;
+}
+
+int
+getCachedShapedText(const uint16_t* key, size_t keyLen, Fixed* width, int* glyphCount, void** glyphInfo)
+{
+    std::u16string k((const char16_t*)key, keyLen);
+    std::lock_guard<std::mutex> guard(sShapedTextsLock);
+    std::unordered_map<std::u16string,ShapedTextList::iterator>::const_iterator i = sShapedTextIndex.find(k);
+    if (i == sShapedTextIndex.end()) {
+        return 0;
+    }
+    sShapedTexts.splice(sShapedTexts.begin(), sShapedTexts, i->second);
+    const ShapedText& entry = *i->second;
+    *width = entry.width;
+    *glyphCount = entry.glyphCount;
+    *glyphInfo = NULL;
+    if (!entry.glyphInfo.empty()) {
+        *glyphInfo = xmalloc(entry.glyphInfo.size());
+        memcpy(*glyphInfo, entry.glyphInfo.data(), entry.glyphInfo.size());
+    }
+    return 1;
+}
+
+size_t
+cacheShapedText(const uint16_t* key, size_t keyLen, Fixed width, int glyphCount, const void* glyphInfo, size_t glyphInfoLen)
+{
+    std::u16string k((const char16_t*)key, keyLen);
+    std::lock_guard<std::mutex> guard(sShapedTextsLock);
+    std::unordered_map<std::u16string,ShapedTextList::iterator>::iterator i = sShapedTextIndex.find(k);
+    if (i == sShapedTextIndex.end()) {
+        sShapedTexts.emplace_front();
+        sShapedTexts.front().key = k;
+        sShapedTextIndex.emplace(k, sShapedTexts.begin());
+    } else {
+        sShapedTextsSize -= i->second->size();
+        sShapedTexts.splice(sShapedTexts.begin(), sShapedTexts, i->second);
+    }
+    ShapedText& entry = sShapedTexts.front();
+    entry.width = width;
+    entry.glyphCount = glyphCount;
+    if (glyphInfoLen > 0)
+        entry.glyphInfo.assign((const char*)glyphInfo, glyphInfoLen);
+    else
+        entry.glyphInfo.clear();
+    sShapedTextsSize += entry.size();
+    return evictShapedTexts();
+}
+
+void
+clearShapedTextCache(void)
+{
+    std::lock_guard<std::mutex> guard(sShapedTextsLock);
+    sShapedTexts.clear();
+    sShapedTextIndex.clear();
+    sShapedTextsSize = 0;
+}
+
+size_t
+setShapedTextCacheLimit(size_t limit)
+{
+    std::lock_guard<std::mutex> guard(sShapedTextsLock);
+    sShapedTextsLimit = limit;
+    return evictShapedTexts();
+}
+
+// Entries are visited in order of their keys, so that anything built from
+// them (such as a file saved by the Rust code) doesn't depend on the order
+// in which the text happened to be shaped.
+size_t
+visitShapedTextCache(ShapedTextVisitor visitor, void* context)
+{
+    typedef ShapedTextList::const_iterator Iter;
+
+    std::lock_guard<std::mutex> guard(sShapedTextsLock);
+    std::vector<Iter> entries;
+    entries.reserve(sShapedTexts.size());
+    for (Iter i = sShapedTexts.begin(); i != sShapedTexts.end(); ++i)
+        entries.push_back(i);
+
+    std::sort(entries.begin(), entries.end(), [](const Iter& a, const Iter& b) {
+        return a->key < b->key;
+    });
+
+    for (size_t n = 0; n < entries.size(); ++n) {
+        const ShapedText& entry = *entries[n];
+        visitor(context, (const uint16_t*)entry.key.data(), entry.key.size(), entry.width, entry.glyphCount,
+                entry.glyphInfo.data(), entry.glyphInfo.size());
+    }
+
+    return entries.size();
+}
+
 /* The following code used to be in a file called "hz.cpp" and there's no
  * particular reason for it to be here, but it was a tiny file with a weird
  * name so I wanted to get rid of it. The functions are invoked from the C
@@ -671,6 +819,12 @@ getFont(XeTeXLayoutEngine engine)
     return (XeTeXFont)(engine->font);
 }
 
+uint64_t
+getFontDataDigest(XeTeXFont font)
+{
+    return ((XeTeXFontInst*)font)->getDataDigest();
+}
+
 float
 getExtendFactor(XeTeXLayoutEngine engine)
 {
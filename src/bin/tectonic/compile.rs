@@ -13,7 +13,7 @@ use tectonic::{
     config::{maybe_return_test_bundle, PersistentConfig},
     driver::{OutputFormat, PassSetting, ProcessingSession, ProcessingSessionBuilder},
     errmsg,
-    errors::{ErrorKind, Result},
+    errors::{ChainErrCompatExt, ErrorKind, Result},
     status::StatusBackend,
     tt_error, tt_note,
     unstable_opts::{UnstableArg, UnstableOptions},
@@ -39,6 +39,10 @@ pub struct CompileOptions {
     #[arg(short = 'C', long)]
     only_cached: bool,
 
+    /// Fail if the build uses the network or files outside of the input's directory, the bundle, and the caches
+    #[arg(long)]
+    hermetic: bool,
+
     /// The kind of output to generate
     #[arg(long, name = "format", default_value = "pdf")]
     outfmt: OutputFormat,
@@ -75,14 +79,22 @@ pub struct CompileOptions {
     #[arg(long = "print", short)]
     print_stdout: bool,
 
-    /// The directory in which to place output files [default: the directory containing <input>]
+    /// The directory in which to place output files, or "-" to write the output to the standard output stream [default: the directory containing <input>]
     #[arg(name = "outdir", short, long)]
     outdir: Option<PathBuf>,
 
+    /// Write the output to the standard output stream instead of to disk, like `-o -`
+    #[arg(long)]
+    print_pdf: bool,
+
     /// Input is untrusted -- disable all known-insecure features
     #[arg(long)]
     untrusted: bool,
 
+    /// Let the document read the environment variable <name> with `\tectonicgetenv`
+    #[arg(long, name = "name")]
+    allow_env: Vec<String>,
+
     /// Unstable options. Pass -Zhelp to show a list
     #[arg(name = "option", short = 'Z')]
     unstable: Vec<UnstableArg>,
@@ -92,8 +104,22 @@ pub struct CompileOptions {
 
 //impl TectonicCommand for CompileOptions {
 impl CompileOptions {
+    /// Whether the output is to be written to stdout, in which case status
+    /// messages must all go to stderr.
+    pub fn streams_to_stdout(&self) -> bool {
+        self.print_pdf || self.outdir.as_deref() == Some(Path::new("-"))
+    }
+
     pub fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let stream_output = self.streams_to_stdout();
         let unstable = UnstableOptions::from_unstable_args(self.unstable.into_iter());
+        let only_cached = self.only_cached || self.hermetic;
+
+        // Forbid the network before anything else, since even setting up the
+        // bundle could touch it.
+        if self.hermetic {
+            tectonic_geturl::forbid_network(true);
+        }
 
         // Default to allowing insecure since it would be super duper annoying
         // to have to pass `--trusted` every time to build a personal document
@@ -105,8 +131,12 @@ impl CompileOptions {
             SecurityStance::MaybeAllowInsecures
         };
 
-        let mut sess_builder =
-            ProcessingSessionBuilder::new_with_security(SecuritySettings::new(stance));
+        let mut security = SecuritySettings::new(stance);
+        for name in self.allow_env {
+            security.with_allowed_env_var(name);
+        }
+
+        let mut sess_builder = ProcessingSessionBuilder::new_with_security(security);
         let format_path = self.format;
         let deterministic_mode = unstable.deterministic_mode;
         sess_builder
@@ -117,7 +147,8 @@ impl CompileOptions {
             .format_cache_path(config.format_cache_path()?)
             .synctex(self.synctex)
             .output_format(self.outfmt)
-            .pass(self.pass);
+            .pass(self.pass)
+            .hermetic(self.hermetic);
 
         if let Some(s) = self.reruns {
             sess_builder.reruns(s);
@@ -138,6 +169,16 @@ impl CompileOptions {
                 status,
                 "reading from standard input; outputs will appear under the base name \"texput\""
             );
+        } else if std::fs::metadata(&input_path).is_ok_and(|md| !md.is_file() && !md.is_dir()) {
+            // A named pipe, such as one created by the shell's process
+            // substitution. Its name is meaningless, so treat it like stdin.
+            sess_builder.primary_input_path(&input_path);
+            sess_builder.tex_input_name("texput.tex");
+            sess_builder.output_dir(Path::new(""));
+            tt_note!(
+                status,
+                "reading from a pipe; outputs will appear under the base name \"texput\""
+            );
         } else {
             let input_path = Path::new(&input_path);
             sess_builder.primary_input_path(input_path);
@@ -161,7 +202,9 @@ impl CompileOptions {
             }
         }
 
-        if let Some(output_dir) = self.outdir {
+        if stream_output {
+            sess_builder.output_to_stdout();
+        } else if let Some(output_dir) = self.outdir {
             if !output_dir.is_dir() {
                 return Err(errmsg!(
                     "output directory \"{}\" does not exist",
@@ -181,7 +224,7 @@ impl CompileOptions {
             }
         }
 
-        if self.only_cached {
+        if only_cached {
             tt_note!(status, "using only cached resource files");
         }
 
@@ -191,7 +234,9 @@ impl CompileOptions {
             // don't need special cases for tests our source.
             if let Ok(bundle) = maybe_return_test_bundle(Some(bundle.clone())) {
                 sess_builder.bundle(bundle);
-            } else if let Some(bundle) = detect_bundle(bundle.clone(), self.only_cached, None)? {
+            } else if let Some(bundle) = detect_bundle(bundle.clone(), only_cached, None)
+                .chain_err(|| ErrorKind::BundleError)?
+            {
                 sess_builder.bundle(bundle);
             } else {
                 return Err(errmsg!("`{bundle}` doesn't specify a valid bundle."));
@@ -200,7 +245,7 @@ impl CompileOptions {
             // TODO: this is ugly too.
             sess_builder.bundle(bundle);
         } else {
-            sess_builder.bundle(config.default_bundle(self.only_cached)?);
+            sess_builder.bundle(config.default_bundle(only_cached)?);
         }
         sess_builder.build_date_from_env(deterministic_mode);
 
@@ -6,11 +6,13 @@
 
 use clap::{Parser, ValueEnum};
 use std::{env, io::IsTerminal, process};
-use tectonic_status_base::plain::PlainStatusBackend;
+use tectonic_status_base::{
+    gcc::GccStatusBackend, json::JsonStatusBackend, plain::PlainStatusBackend,
+};
 
 use tectonic::{
     config::PersistentConfig,
-    errors::SyncError,
+    errors::{FailureClass, SyncError},
     status::{
         termcolor::TermcolorStatusBackend,
         {ChatterLevel, StatusBackend},
@@ -52,6 +54,14 @@ struct CliOptions {
     #[arg(long = "color", default_value = "auto")]
     cli_color: CliColor,
 
+    /// How to format status messages
+    #[arg(long = "status-format", default_value = "text")]
+    status_format: StatusFormat,
+
+    /// How to format error and warning messages
+    #[arg(long = "error-format", default_value = "human")]
+    error_format: ErrorFormat,
+
     #[command(flatten)]
     compile: compile::CompileOptions,
 }
@@ -78,6 +88,30 @@ impl CliColor {
     }
 }
 
+#[derive(ValueEnum, Clone, Debug, Eq, PartialEq)]
+enum StatusFormat {
+    /// Messages for people to read
+    #[value(name = "text")]
+    Text,
+
+    /// One JSON object per line on standard error, including the progress of
+    /// downloads, for programs that wrap Tectonic
+    #[value(name = "json")]
+    Json,
+}
+
+#[derive(ValueEnum, Clone, Debug, Eq, PartialEq)]
+enum ErrorFormat {
+    /// Messages for people to read
+    #[value(name = "human")]
+    Human,
+
+    /// `file:line: error: message`, as printed by GCC, for build tools and
+    /// editors
+    #[value(name = "gcc")]
+    Gcc,
+}
+
 #[derive(Parser)]
 struct PeekUnstableOptions {
     #[arg(name = "option", short = 'Z')]
@@ -161,7 +195,7 @@ fn main() {
             // in trouble, so it seems safest to keep things simple anyway and
             // just use bare stderr without colorization.
             e.dump_uncolorized();
-            process::exit(1);
+            process::exit(FailureClass::Configuration.exit_code());
         }
     };
 
@@ -169,19 +203,36 @@ fn main() {
     // imagine wanting to be able to configure the colorization (which is
     // something I'd be relatively OK with since it'd only affect the progam
     // UI, not the processing results).
-
-    let mut status = if args.cli_color.should_enable() {
-        Box::new(TermcolorStatusBackend::new(args.chatter_level)) as Box<dyn StatusBackend>
+    //
+    // If the output is being streamed to stdout, all status messages have to
+    // go to stderr.
+
+    let always_stderr = args.compile.streams_to_stdout();
+
+    let mut status = if args.status_format == StatusFormat::Json {
+        Box::new(JsonStatusBackend::new(args.chatter_level)) as Box<dyn StatusBackend>
+    } else if args.error_format == ErrorFormat::Gcc {
+        let mut sb = GccStatusBackend::new(args.chatter_level);
+        sb.always_stderr(always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
+    } else if args.cli_color.should_enable() {
+        let mut sb = TermcolorStatusBackend::new(args.chatter_level);
+        sb.always_stderr(always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
     } else {
-        Box::new(PlainStatusBackend::new(args.chatter_level)) as Box<dyn StatusBackend>
+        let mut sb = PlainStatusBackend::new(args.chatter_level);
+        sb.always_stderr(always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
     };
 
     // Now that we've got colorized output, pass off to the inner function ...
     // all so that we can print out the word "error:" in red. This code
-    // parallels various bits of the `error_chain` crate.
+    // parallels various bits of the `error_chain` crate. The exit code tells
+    // build tools what kind of failure happened.
 
     if let Err(e) = args.compile.execute(config, &mut *status) {
+        let code = e.failure_class().exit_code();
         status.report_error(&SyncError::new(e).into());
-        process::exit(1)
+        process::exit(code)
     }
 }
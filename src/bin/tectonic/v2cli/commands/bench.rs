@@ -0,0 +1,459 @@
+use clap::Parser;
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+use tectonic::{
+    config::PersistentConfig,
+    ctry,
+    driver::{EngineRun, PassSetting, ProcessingSessionBuilder},
+    errmsg,
+    errors::Result,
+    tt_note,
+    unstable_opts::{UnstableArg, UnstableOptions},
+};
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// The name of the variant that uses no extra options, which the others are
+/// compared against.
+const BASELINE: &str = "default";
+
+/// `bench`: Time the builds of a document under different options
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct BenchCommand {
+    /// The file to build
+    #[arg(value_name = "PATH")]
+    input: PathBuf,
+
+    /// How many timed builds to make with each set of options
+    #[arg(long, short = 'n', value_name = "N", default_value_t = 5)]
+    runs: usize,
+
+    /// How many untimed builds to make with each set of options first
+    #[arg(long, value_name = "N", default_value_t = 1)]
+    warmup: usize,
+
+    /// Also time builds with a space-separated list of unstable options, such as `cached=shaping-cache`; can be given multiple times
+    #[arg(long, value_name = "NAME=OPTIONS")]
+    variant: Vec<Variant>,
+
+    /// The name of the format to use
+    #[arg(long, value_name = "FORMAT", default_value = "latex")]
+    format: String,
+
+    /// Use only resource files cached locally
+    #[arg(short = 'C', long)]
+    only_cached: bool,
+
+    /// Document is untrusted -- disable all known-insecure features
+    #[arg(long)]
+    untrusted: bool,
+
+    /// Also write the comparison table to this file, as Markdown
+    #[arg(long, value_name = "PATH")]
+    report: Option<PathBuf>,
+}
+
+/// A named set of unstable options to time builds with.
+#[derive(Clone, Debug, Eq, PartialEq)]
+struct Variant {
+    name: String,
+    options: Vec<String>,
+}
+
+impl Variant {
+    fn baseline() -> Self {
+        Variant {
+            name: BASELINE.to_owned(),
+            options: Vec::new(),
+        }
+    }
+
+    fn unstables(&self) -> UnstableOptions {
+        // The options were checked when the variant was parsed.
+        UnstableOptions::from_unstable_args(
+            self.options
+                .iter()
+                .map(|o| UnstableArg::from_str(o).expect("unstable option was validated")),
+        )
+    }
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let Some((name, options)) = s.split_once('=') else {
+            return Err(format!(
+                "expected a variant like `cached=shaping-cache`, not `{s}`"
+            ));
+        };
+
+        let name = name.trim();
+
+        if name.is_empty() || name == BASELINE {
+            return Err(format!(
+                "variants need a name other than `{BASELINE}`, which is used for the build \
+                 without extra options"
+            ));
+        }
+
+        let options: Vec<String> = options.split_whitespace().map(str::to_owned).collect();
+
+        for option in &options {
+            match UnstableArg::from_str(option) {
+                Ok(UnstableArg::Help) => {
+                    return Err("`help` can't be part of a variant".to_owned());
+                }
+                Ok(_) => {}
+                Err(e) => return Err(format!("bad option `{option}`: {e}")),
+            }
+        }
+
+        Ok(Variant {
+            name: name.to_owned(),
+            options,
+        })
+    }
+}
+
+/// The timings of one pass of the builds of a variant, such as its second
+/// TeX run, or of the whole builds.
+#[derive(Debug)]
+struct Pass {
+    label: String,
+    samples: Vec<Duration>,
+}
+
+/// Summary statistics of the timings of a pass, in seconds.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Stats {
+    n: usize,
+    mean: f64,
+    stddev: f64,
+    min: f64,
+    median: f64,
+}
+
+impl Stats {
+    fn of(samples: &[Duration]) -> Self {
+        let mut secs: Vec<f64> = samples.iter().map(Duration::as_secs_f64).collect();
+        secs.sort_by(f64::total_cmp);
+
+        let n = secs.len();
+        let mean = secs.iter().sum::<f64>() / n as f64;
+
+        // The sample standard deviation; a single build gives no spread.
+        let stddev = if n > 1 {
+            (secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / (n - 1) as f64).sqrt()
+        } else {
+            0.0
+        };
+
+        let median = if n % 2 == 0 {
+            (secs[n / 2 - 1] + secs[n / 2]) / 2.0
+        } else {
+            secs[n / 2]
+        };
+
+        Stats {
+            n,
+            mean,
+            stddev,
+            min: secs[0],
+            median,
+        }
+    }
+}
+
+impl TectonicCommand for BenchCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.runs == 0 {
+            return Err(errmsg!("`--runs` must be at least 1"));
+        }
+
+        let mut variants = vec![Variant::baseline()];
+
+        for variant in &self.variant {
+            if variants.iter().any(|v| v.name == variant.name) {
+                return Err(errmsg!("there are two variants named `{}`", variant.name));
+            }
+
+            variants.push(variant.clone());
+        }
+
+        // Each variant gets its own output directory, which is kept between
+        // its builds, so that caches kept there are reused as in a series of
+        // rebuilds.
+        let out_root = ctry!(
+            tempfile::Builder::new().prefix("tectonic_bench").tempdir();
+            "couldn't create a temporary directory for the builds"
+        );
+
+        let mut results = Vec::new();
+
+        for (i, variant) in variants.iter().enumerate() {
+            let out_dir = out_root.path().join(i.to_string());
+            ctry!(fs::create_dir_all(&out_dir); "couldn't create directory `{}`", out_dir.display());
+
+            let mut passes: Vec<Pass> = Vec::new();
+
+            for run in 0..self.warmup + self.runs {
+                let timed = run >= self.warmup;
+
+                if timed {
+                    tt_note!(
+                        status,
+                        "timing `{}`: build {} of {}",
+                        variant.name,
+                        run - self.warmup + 1,
+                        self.runs
+                    );
+                } else {
+                    tt_note!(status, "warming up `{}`", variant.name);
+                }
+
+                let (elapsed, runs) = self.build(&config, variant, &out_dir, status)?;
+
+                if timed {
+                    record(&mut passes, "total", elapsed);
+
+                    for (engine, duration) in label_engine_runs(&runs) {
+                        record(&mut passes, &engine, duration);
+                    }
+                }
+            }
+
+            results.push((variant, passes));
+        }
+
+        // Put the totals last, after the passes.
+        for (_, passes) in &mut results {
+            passes.rotate_left(1);
+        }
+
+        let mut rows = Vec::new();
+
+        for (variant, passes) in &results {
+            for pass in passes {
+                let stats = Stats::of(&pass.samples);
+
+                let baseline = results[0]
+                    .1
+                    .iter()
+                    .find(|p| p.label == pass.label)
+                    .map(|p| Stats::of(&p.samples).mean);
+
+                let change = match baseline {
+                    Some(base) if variant.name != BASELINE && base > 0.0 => {
+                        format!("{:+.1}%", (stats.mean - base) / base * 100.0)
+                    }
+                    _ => String::new(),
+                };
+
+                rows.push([
+                    variant.name.clone(),
+                    pass.label.clone(),
+                    stats.n.to_string(),
+                    format!("{:.3} s", stats.mean),
+                    format!("{:.3} s", stats.stddev),
+                    format!("{:.3} s", stats.min),
+                    format!("{:.3} s", stats.median),
+                    change,
+                ]);
+            }
+        }
+
+        print!("{}", text_table(&rows));
+
+        if let Some(path) = self.report.as_ref() {
+            let report = self.markdown_report(&variants, &rows);
+            ctry!(fs::write(path, report); "couldn't write `{}`", path.display());
+            tt_note!(status, "wrote the comparison to `{}`", path.display());
+        }
+
+        Ok(0)
+    }
+}
+
+impl BenchCommand {
+    /// Build the document once with the options of *variant*, and return how
+    /// long the build took and the engines that it ran.
+    fn build(
+        &self,
+        config: &PersistentConfig,
+        variant: &Variant,
+        out_dir: &Path,
+        status: &mut dyn StatusBackend,
+    ) -> Result<(Duration, Vec<EngineRun>)> {
+        let stance = if self.untrusted {
+            SecurityStance::DisableInsecures
+        } else {
+            SecurityStance::MaybeAllowInsecures
+        };
+
+        let Some(file_name) = self.input.file_name() else {
+            return Err(errmsg!(
+                "can't figure out a basename for input path `{}`",
+                self.input.display()
+            ));
+        };
+
+        let mut sess_builder =
+            ProcessingSessionBuilder::new_with_security(SecuritySettings::new(stance));
+
+        sess_builder
+            .unstables(variant.unstables())
+            .primary_input_path(&self.input)
+            .tex_input_name(&file_name.to_string_lossy())
+            .output_dir(out_dir)
+            .format_name(&self.format)
+            .format_cache_path(config.format_cache_path()?)
+            .pass(PassSetting::Default)
+            .bundle(config.default_bundle(self.only_cached)?);
+
+        // Failures are reported as they happen, after the note that says
+        // which variant is being built.
+        let start = Instant::now();
+        let sess = crate::compile::run_and_report(sess_builder, status)?;
+
+        Ok((start.elapsed(), sess.engine_runs().to_vec()))
+    }
+
+    /// Write the comparison as a Markdown document, with what's needed to
+    /// reproduce it, for pasting into a bug report.
+    fn markdown_report(&self, variants: &[Variant], rows: &[[String; 8]]) -> String {
+        let mut report = String::new();
+
+        let _ = writeln!(report, "# Tectonic benchmark\n");
+        let _ = writeln!(
+            report,
+            "- Tectonic {} on {}-{}",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        );
+        let _ = writeln!(report, "- Input: `{}`", self.input.display());
+        let _ = writeln!(
+            report,
+            "- {} timed builds per variant, after {} warmup builds",
+            self.runs, self.warmup
+        );
+
+        for variant in variants {
+            let options = if variant.options.is_empty() {
+                "no extra options".to_owned()
+            } else {
+                variant
+                    .options
+                    .iter()
+                    .map(|o| format!("`-Z {o}`"))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            };
+
+            let _ = writeln!(report, "- Variant `{}`: {}", variant.name, options);
+        }
+
+        let _ = writeln!(report);
+        let _ = writeln!(report, "| {} |", HEADINGS.join(" | "));
+        let _ = writeln!(
+            report,
+            "|{}",
+            HEADINGS
+                .iter()
+                .enumerate()
+                .map(|(i, _)| if i < 2 { " --- |" } else { " ---: |" })
+                .collect::<String>()
+        );
+
+        for row in rows {
+            let _ = writeln!(report, "| {} |", row.join(" | "));
+        }
+
+        report
+    }
+}
+
+const HEADINGS: [&str; 8] = [
+    "variant", "pass", "n", "mean", "stddev", "min", "median", "change",
+];
+
+/// Add a sample to the timings of the pass with the given label.
+fn record(passes: &mut Vec<Pass>, label: &str, duration: Duration) {
+    match passes.iter_mut().find(|p| p.label == label) {
+        Some(pass) => pass.samples.push(duration),
+        None => passes.push(Pass {
+            label: label.to_owned(),
+            samples: vec![duration],
+        }),
+    }
+}
+
+/// Label the engine runs of a build by engine and number, such as `TeX 2`
+/// for the second run of TeX, so that the same pass can be compared across
+/// builds.
+fn label_engine_runs(runs: &[EngineRun]) -> Vec<(String, Duration)> {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+
+    runs.iter()
+        .map(|run| {
+            let n = match counts.iter_mut().find(|(e, _)| *e == run.engine) {
+                Some((_, n)) => {
+                    *n += 1;
+                    *n
+                }
+                None => {
+                    counts.push((run.engine, 1));
+                    1
+                }
+            };
+
+            (format!("{} {}", run.engine, n), run.duration)
+        })
+        .collect()
+}
+
+/// Format the comparison as a table with aligned columns. The first two
+/// columns are aligned left, and the numbers right.
+fn text_table(rows: &[[String; 8]]) -> String {
+    let mut widths = HEADINGS.map(str::len);
+
+    for row in rows {
+        for (w, cell) in widths.iter_mut().zip(row) {
+            *w = (*w).max(cell.len());
+        }
+    }
+
+    let mut table = String::new();
+    let headings = HEADINGS.map(str::to_owned);
+
+    for row in std::iter::once(&headings).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, w))| {
+                if i < 2 {
+                    format!("{cell:<w$}")
+                } else {
+                    format!("{cell:>w$}")
+                }
+            })
+            .collect();
+
+        let _ = writeln!(table, "{}", cells.join("  ").trim_end());
+    }
+
+    table
+}
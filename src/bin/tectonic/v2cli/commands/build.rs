@@ -1,13 +1,24 @@
 use clap::Args;
+use std::{
+    env,
+    ffi::OsString,
+    io::{self, Read},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
 use tectonic::{
     config::is_config_test_mode_activated,
     config::PersistentConfig,
-    docmodel::{DocumentExt, DocumentSetupOptions},
-    errors::Result,
-    tt_error, tt_note,
+    docmodel::{DocumentExt, DocumentSetupOptions, WorkspaceExt},
+    errmsg,
+    errors::{ChainErrCompatExt, ErrorKind, Result},
+    golden::GoldenDigests,
+    schedule::{BuildOutcome, BuildOutput, BuildScheduler, ScheduledDocument},
+    scratch::ScratchLocation,
+    tt_error, tt_note, tt_warning,
 };
 use tectonic_bridge_core::{SecuritySettings, SecurityStance};
-use tectonic_docmodel::workspace::Workspace;
+use tectonic_docmodel::{document::BuildTargetType, workspace::Workspace};
 use tectonic_status_base::StatusBackend;
 
 use crate::v2cli::{CommandCustomizations, TectonicCommand};
@@ -19,10 +30,18 @@ pub struct BuildCommand {
     #[arg(long)]
     untrusted: bool,
 
+    /// Let the document read the environment variable <NAME> with `\tectonicgetenv`
+    #[arg(long, value_name = "NAME")]
+    allow_env: Vec<String>,
+
     /// Use only resource files cached locally
     #[arg(short = 'C', long)]
     only_cached: bool,
 
+    /// Fail if the build uses the network or files outside of the project, bundle, and caches
+    #[arg(long)]
+    hermetic: bool,
+
     /// Keep the intermediate files generated during processing
     #[arg(short, long)]
     keep_intermediates: bool,
@@ -39,6 +58,26 @@ pub struct BuildCommand {
     #[arg(long)]
     open: bool,
 
+    /// Build quickly, skipping expensive steps; the output is for previewing only
+    #[arg(long, conflicts_with_all = ["record", "verify"])]
+    draft: bool,
+
+    /// Only put these pages into the PDF, e.g. `12-20` or `1,5-`, for a quick preview
+    #[arg(long, value_name = "SPEC", conflicts_with_all = ["record", "verify"])]
+    pages: Option<String>,
+
+    /// Cache the shaping of text in the build directory and reuse it in later builds (experimental)
+    #[arg(long)]
+    shaping_cache: bool,
+
+    /// Create scratch files in <WHERE>: `system`, `project`, `memory`, or a directory [default: system]
+    #[arg(long, value_name = "WHERE")]
+    scratch_dir: Option<ScratchLocation>,
+
+    /// Fail the build if its scratch files take up more than <BYTES>
+    #[arg(long, value_name = "BYTES")]
+    scratch_limit: Option<u64>,
+
     /// Specify a target to be used by the build
     #[arg(long, help = "Specify the target of the build.")]
     target: Option<String>,
@@ -46,6 +85,26 @@ pub struct BuildCommand {
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// Record the digests of the build outputs in this file
+    #[arg(long, value_name = "PATH", conflicts_with = "verify")]
+    record: Option<PathBuf>,
+
+    /// Check the build outputs against the digests recorded in this file
+    #[arg(long, value_name = "PATH")]
+    verify: Option<PathBuf>,
+
+    /// Normalize dates and IDs in the outputs before recording their digests
+    #[arg(long, requires = "record")]
+    normalize: bool,
+
+    /// Only build the document named <NAME>, in a workspace of several documents
+    #[arg(long, value_name = "NAME")]
+    document: Option<String>,
+
+    /// Build up to <N> documents of a workspace at once [default: the number of CPUs]
+    #[arg(long, short, value_name = "N")]
+    jobs: Option<usize>,
 }
 
 impl TectonicCommand for BuildCommand {
@@ -58,8 +117,21 @@ impl TectonicCommand for BuildCommand {
             tt_note!(status, "--bundle {} ignored", url);
             tt_note!(status, "using workspace bundle configuration");
         }
-        let ws = Workspace::open_from_environment()?;
-        let doc = ws.first_document();
+        // Forbid the network before anything else, since even setting up the
+        // bundle could touch it.
+        if self.hermetic {
+            tectonic_geturl::forbid_network(true);
+        }
+
+        let ws = Workspace::open_from_environment().chain_err(|| ErrorKind::ConfigError)?;
+
+        let doc = match self.document.as_ref() {
+            Some(name) => ws
+                .document(name)
+                .ok_or_else(|| errmsg!("the workspace has no document named `{}`", name))?,
+            None if ws.documents().len() == 1 => ws.first_document(),
+            None => return self.build_workspace(&ws, status),
+        };
 
         // Default to allowing insecure since it would be super duper annoying
         // to have to pass `--trusted` every time to build a personal document
@@ -71,11 +143,45 @@ impl TectonicCommand for BuildCommand {
             SecurityStance::MaybeAllowInsecures
         };
 
-        let mut setup_options =
-            DocumentSetupOptions::new_with_security(SecuritySettings::new(stance));
-        setup_options.only_cached(self.only_cached);
+        let mut security = SecuritySettings::new(stance);
+        for name in &self.allow_env {
+            security.with_allowed_env_var(name.as_str());
+        }
+
+        let mut setup_options = DocumentSetupOptions::new_with_security(security);
+        setup_options
+            .only_cached(self.only_cached)
+            .hermetic(self.hermetic)
+            .shaping_cache(self.shaping_cache)
+            .scratch(
+                self.scratch_dir.clone().unwrap_or_default(),
+                self.scratch_limit,
+            );
+
+        // Load the golden digests up front, so that a bad path doesn't cost
+        // the user a full build.
+        let golden = match self.verify.as_ref() {
+            Some(path) => Some(GoldenDigests::load(path)?),
+            None => None,
+        };
 
-        for output_name in doc.output_names() {
+        let normalize = golden
+            .as_ref()
+            .map(|g| g.is_normalized())
+            .unwrap_or(self.normalize);
+        let mut outputs = Vec::new();
+
+        // Build the PDF outputs first, since the HTML outputs may render their
+        // social preview images from them.
+        let mut output_names: Vec<&str> = doc.output_names().collect();
+        output_names.sort_by_key(|name| {
+            (
+                doc.outputs[*name].target_type == BuildTargetType::Html,
+                *name,
+            )
+        });
+
+        for output_name in output_names {
             if let Some(out) = self.target.as_ref() {
                 if out != output_name {
                     continue;
@@ -88,9 +194,20 @@ impl TectonicCommand for BuildCommand {
                 .format_cache_path(config.format_cache_path()?)
                 .keep_intermediates(self.keep_intermediates)
                 .keep_logs(self.keep_logs)
-                .print_stdout(self.print_stdout);
+                .print_stdout(self.print_stdout)
+                .draft(self.draft);
+
+            if let Some(spec) = self.pages.as_ref() {
+                builder.pages(spec.as_str());
+            }
 
-            crate::compile::run_and_report(builder, status)?;
+            let sess = crate::compile::run_and_report(builder, status)?;
+
+            if self.record.is_some() || golden.is_some() {
+                for (name, data) in sess.written_files() {
+                    outputs.push((format!("{output_name}/{name}"), data));
+                }
+            }
 
             if self.open {
                 let out_file = doc.output_main_file(output_name);
@@ -111,6 +228,232 @@ impl TectonicCommand for BuildCommand {
             }
         }
 
+        let actual = GoldenDigests::compute(
+            outputs.iter().map(|(n, d)| (n.as_str(), d.as_slice())),
+            normalize,
+        );
+
+        if let Some(path) = self.record.as_ref() {
+            actual.save(path)?;
+            tt_note!(
+                status,
+                "recorded digests of {} output files in `{}`",
+                actual.len(),
+                path.display()
+            );
+        }
+
+        if let Some(mut golden) = golden {
+            // If only one output was built, the others can't be checked.
+            if let Some(target) = self.target.as_ref() {
+                let prefix = format!("{target}/");
+                golden.retain(|name| name.starts_with(&prefix));
+            }
+
+            let diff = golden.compare(&actual);
+
+            if !diff.is_empty() {
+                return Err(errmsg!(
+                    "build outputs do not match the digests recorded in `{}`:\n{}",
+                    self.verify.as_ref().unwrap().display(),
+                    diff
+                ));
+            }
+
+            tt_note!(
+                status,
+                "all {} output files match the recorded digests",
+                golden.len()
+            );
+        }
+
+        Ok(0)
+    }
+}
+
+impl BuildCommand {
+    /// Build all of the documents of a workspace, each in a child process.
+    fn build_workspace(&self, ws: &Workspace, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.record.is_some() || self.verify.is_some() {
+            return Err(errmsg!(
+                "`--record` and `--verify` can only be used when building one document; \
+                 choose it with `--document`"
+            ));
+        }
+
+        let docs = ws
+            .documents()
+            .iter()
+            .map(|d| ScheduledDocument {
+                name: d.name.clone(),
+                depends_on: d.depends_on.clone(),
+            })
+            .collect();
+
+        let mut scheduler = BuildScheduler::new(docs)?;
+
+        if let Some(jobs) = self.jobs {
+            scheduler.jobs(jobs);
+        }
+
+        let exe = env::current_exe()?;
+        let n_docs = ws.documents().len();
+        tt_note!(status, "building {} documents", n_docs);
+
+        let reports = scheduler.run(
+            |name| build_in_child(&exe, &self.child_args(&exe, name)),
+            |report| {
+                match report.outcome {
+                    BuildOutcome::Succeeded => tt_note!(status, "{}", report),
+                    BuildOutcome::Failed => tt_error!(status, "{}", report),
+                    BuildOutcome::Skipped(_) => tt_warning!(status, "{}", report),
+                }
+
+                if !report.diagnostics.is_empty() {
+                    status.dump_error_logs(report.diagnostics.as_bytes());
+                }
+            },
+        );
+
+        let n_failed = reports
+            .iter()
+            .filter(|r| r.outcome != BuildOutcome::Succeeded)
+            .count();
+
+        if n_failed > 0 {
+            return Err(errmsg!(
+                "{} of {} documents could not be built",
+                n_failed,
+                n_docs
+            ));
+        }
+
+        tt_note!(status, "all {} documents were built", n_docs);
+
+        if ws.site().is_some() {
+            let n_written = ws.emit_site(status)?;
+            tt_note!(
+                status,
+                "rewrote {} changed site file(s) in `{}`",
+                n_written,
+                ws.root_dir().join("build").display()
+            );
+        }
+
         Ok(0)
     }
+
+    /// Get the arguments with which to run this program to build the named
+    /// document. The settings of this command are passed along, and only
+    /// warnings and errors are printed, since they're shown to the user as a
+    /// block once the build is done.
+    fn child_args(&self, exe: &Path, name: &str) -> Vec<OsString> {
+        let mut args: Vec<OsString> = Vec::new();
+
+        let v2cli_default = exe
+            .file_name()
+            .and_then(|n| n.to_str())
+            .map(|n| n.contains("nextonic"))
+            .unwrap_or(false);
+
+        if !v2cli_default {
+            args.push("-X".into());
+        }
+
+        args.extend(
+            [
+                "--chatter",
+                "minimal",
+                "--color",
+                "never",
+                "build",
+                "--document",
+            ]
+            .map(OsString::from),
+        );
+        args.push(name.into());
+
+        let flags = [
+            (self.untrusted, "--untrusted"),
+            (self.only_cached, "--only-cached"),
+            (self.hermetic, "--hermetic"),
+            (self.keep_intermediates, "--keep-intermediates"),
+            (self.keep_logs, "--keep-logs"),
+            (self.print_stdout, "--print"),
+            (self.open, "--open"),
+            (self.draft, "--draft"),
+            (self.shaping_cache, "--shaping-cache"),
+        ];
+
+        for (set, flag) in flags {
+            if set {
+                args.push(flag.into());
+            }
+        }
+
+        for name in &self.allow_env {
+            args.push("--allow-env".into());
+            args.push(name.into());
+        }
+
+        if let Some(spec) = self.pages.as_ref() {
+            args.push("--pages".into());
+            args.push(spec.into());
+        }
+
+        if let Some(target) = self.target.as_ref() {
+            args.push("--target".into());
+            args.push(target.into());
+        }
+
+        if let Some(location) = self.scratch_dir.as_ref() {
+            args.push("--scratch-dir".into());
+            args.push(location.to_string().into());
+        }
+
+        if let Some(limit) = self.scratch_limit {
+            args.push("--scratch-limit".into());
+            args.push(limit.to_string().into());
+        }
+
+        args
+    }
+}
+
+/// Run a child process to build a document, capturing everything that it
+/// prints.
+fn build_in_child(exe: &Path, args: &[OsString]) -> BuildOutput {
+    match run_capturing_output(exe, args) {
+        Ok((succeeded, output)) => BuildOutput {
+            succeeded,
+            diagnostics: String::from_utf8_lossy(&output).into_owned(),
+        },
+        Err(e) => BuildOutput {
+            succeeded: false,
+            diagnostics: format!("error: couldn't run `{}`: {}\n", exe.display(), e),
+        },
+    }
+}
+
+/// Run a program and wait for it to finish, returning whether it succeeded
+/// and what it printed. Its standard output and error go to the same pipe, so
+/// that their messages stay in order.
+fn run_capturing_output(exe: &Path, args: &[OsString]) -> io::Result<(bool, Vec<u8>)> {
+    let (mut reader, writer) = io::pipe()?;
+
+    let mut cmd = Command::new(exe);
+    cmd.args(args)
+        .stdin(Stdio::null())
+        .stdout(writer.try_clone()?)
+        .stderr(writer);
+    let mut child = cmd.spawn()?;
+
+    // This closes our copies of the write end of the pipe, so that reading
+    // stops when the child exits.
+    drop(cmd);
+
+    let mut output = Vec::new();
+    reader.read_to_end(&mut output)?;
+    let exit = child.wait()?;
+    Ok((exit.success(), output))
 }
@@ -14,10 +14,10 @@ use crate::v2cli::{CommandCustomizations, TectonicCommand};
 
 mod actions;
 mod create;
-mod pack;
+pub(crate) mod pack;
 mod select;
 
-fn get_a_bundle(
+pub(crate) fn get_a_bundle(
     _config: PersistentConfig,
     only_cached: bool,
     status: &mut dyn StatusBackend,
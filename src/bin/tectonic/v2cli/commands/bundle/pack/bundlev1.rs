@@ -50,10 +50,22 @@ impl Display for FileListEntry {
     }
 }
 
+/// A file to be packed into a bundle.
+#[derive(Debug)]
+pub struct PackFile {
+    /// The SHA256 hash of this file, or `nohash`.
+    pub hash: String,
+
+    /// The path of this file inside the bundle.
+    pub path: String,
+
+    /// Where this file's content is on disk.
+    pub source: PathBuf,
+}
+
 pub struct BundleV1 {
     filelist: Vec<FileListEntry>,
     target: Box<dyn WriteSeek>,
-    content_dir: PathBuf,
 
     index_start: u64,
     index_real_len: u32,
@@ -61,69 +73,98 @@ pub struct BundleV1 {
 }
 
 impl BundleV1 {
+    /// Pack the `content` directory of a bundle build directory, as
+    /// produced by the `select` job.
     pub fn make(target: Box<dyn WriteSeek>, build_dir: PathBuf) -> Result<()> {
-        let mut bundle = BundleV1::new(target, build_dir)?;
+        let content_dir = build_dir.join("content");
+
+        let filelist_file = File::open(content_dir.join("FILELIST"))?;
+        let reader = BufReader::new(filelist_file);
+        let mut files = Vec::new();
 
-        bundle.add_files()?;
-        bundle.write_index()?;
-        bundle.write_header()?;
+        for line in reader.lines() {
+            let line = line?;
+            let mut bits = line.split_whitespace();
 
-        Ok(())
+            if let Some(hash) = bits.next() {
+                let path = bits.collect::<Vec<&str>>().join(" ");
+
+                files.push(PackFile {
+                    hash: hash.to_owned(),
+                    source: content_dir.join(&path),
+                    path,
+                });
+            } else {
+                bail!("malformed filelist line");
+            }
+        }
+
+        let search = fs::read_to_string(content_dir.join("SEARCH"))?
+            .lines()
+            .map(|l| l.to_owned())
+            .collect();
+
+        // Parse bundle hash
+        let mut hash_file = File::open(content_dir.join("SHA256SUM")).unwrap();
+        let mut hash_text = String::new();
+        hash_file.read_to_string(&mut hash_text)?;
+
+        BundleV1::make_from_files(target, files, search, hash_text.trim())
     }
 
-    fn new(target: Box<dyn WriteSeek>, build_dir: PathBuf) -> Result<BundleV1> {
-        Ok(BundleV1 {
+    /// Pack an explicit list of files, with the given `SEARCH` rules and
+    /// bundle digest (as hex).
+    pub fn make_from_files(
+        target: Box<dyn WriteSeek>,
+        files: Vec<PackFile>,
+        search: Vec<String>,
+        digest: &str,
+    ) -> Result<()> {
+        let mut bundle = BundleV1 {
             filelist: Vec::new(),
             target,
-            content_dir: build_dir.join("content"),
             index_start: 0,
             index_gzip_len: 0,
             index_real_len: 0,
-        })
+        };
+
+        bundle.add_files(files)?;
+        bundle.write_index(&search)?;
+        bundle.write_header(&decode_hex(digest)?)?;
+
+        Ok(())
     }
 
-    fn add_files(&mut self) -> Result<u64> {
+    fn add_files(&mut self, files: Vec<PackFile>) -> Result<u64> {
         let mut byte_count = HEADER_SIZE; // Start after header
         let mut real_len_sum = 0; // Compute average compression ratio
 
         self.target.seek(std::io::SeekFrom::Start(byte_count))?;
 
-        let filelist_file = File::open(self.content_dir.join("FILELIST"))?;
-        let reader = BufReader::new(filelist_file);
-
         info!("Building ttbv1 bundle...");
 
-        for line in reader.lines() {
+        for pf in files {
             stdout().flush()?;
 
-            let line = line?;
-            let mut bits = line.split_whitespace();
-
-            if let Some(hash) = bits.next() {
-                let path = bits.collect::<Vec<&str>>().join(" ");
-
-                let mut file = fs::File::open(self.content_dir.join(&path))?;
-
-                // Compress and write bytes
-                let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-                let real_len = std::io::copy(&mut file, &mut encoder)?;
-                let gzip_len = self.target.write(&encoder.finish()?)?;
-                assert!(real_len < u32::MAX as u64);
-                assert!(gzip_len < u32::MAX as usize);
-
-                // Add to index
-                self.filelist.push(FileListEntry {
-                    start: byte_count,
-                    gzip_len: gzip_len as u32,
-                    real_len: real_len as u32,
-                    path: PathBuf::from(path),
-                    hash: hash.to_owned(),
-                });
-                byte_count += gzip_len as u64;
-                real_len_sum += real_len;
-            } else {
-                bail!("malformed filelist line");
-            }
+            let mut file = fs::File::open(&pf.source)?;
+
+            // Compress and write bytes
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            let real_len = std::io::copy(&mut file, &mut encoder)?;
+            let gzip_len = self.target.write(&encoder.finish()?)?;
+            assert!(real_len < u32::MAX as u64);
+            assert!(gzip_len < u32::MAX as usize);
+
+            // Add to index
+            self.filelist.push(FileListEntry {
+                start: byte_count,
+                gzip_len: gzip_len as u32,
+                real_len: real_len as u32,
+                path: PathBuf::from(pf.path),
+                hash: pf.hash,
+            });
+            byte_count += gzip_len as u64;
+            real_len_sum += real_len;
         }
 
         info!(
@@ -134,7 +175,7 @@ impl BundleV1 {
         Ok(byte_count)
     }
 
-    fn write_index(&mut self) -> Result<()> {
+    fn write_index(&mut self, search: &[String]) -> Result<()> {
         // Generate a ttbv1 index and write it to the bundle.
         //
         // This index is a replacement for FILELIST and SEARCH, containing everything in those files
@@ -154,7 +195,7 @@ impl BundleV1 {
         real_len += encoder.write("MAIN\n".as_bytes())?;
 
         real_len += encoder.write("[SEARCH:MAIN]\n".as_bytes())?;
-        for l in fs::read_to_string(self.content_dir.join("SEARCH"))?.lines() {
+        for l in search {
             real_len += encoder.write(l.as_bytes())?;
             real_len += encoder.write(b"\n")?;
         }
@@ -178,17 +219,11 @@ impl BundleV1 {
         Ok(())
     }
 
-    fn write_header(&mut self) -> Result<u64> {
+    fn write_header(&mut self, digest: &[u8]) -> Result<u64> {
         self.target.seek(std::io::SeekFrom::Start(0))?;
 
         info!("Writing header");
 
-        // Parse bundle hash
-        let mut hash_file = File::open(self.content_dir.join("SHA256SUM")).unwrap();
-        let mut hash_text = String::new();
-        hash_file.read_to_string(&mut hash_text)?;
-        let digest = decode_hex(hash_text.trim())?;
-
         let mut byte_count = 0u64;
 
         // 14 bytes: signature
@@ -208,7 +243,7 @@ impl BundleV1 {
 
         // 32 bytes: bundle hash
         // We include this in the header so we don't need to load the index to get the hash.
-        byte_count += self.target.write(&digest)? as u64;
+        byte_count += self.target.write(digest)? as u64;
 
         // Make sure we wrote the expected number of bytes
         assert!(byte_count == HEADER_SIZE);
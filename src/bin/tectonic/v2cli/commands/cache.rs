@@ -0,0 +1,251 @@
+use clap::{Parser, Subcommand};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+use tectonic::{config::PersistentConfig, ctry, errmsg, errors::Result, tt_note, tt_warning};
+use tectonic_io_base::digest;
+use tectonic_status_base::StatusBackend;
+
+use super::bundle::{
+    get_a_bundle,
+    pack::bundlev1::{BundleV1, PackFile},
+};
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `cache`: Commands relating to the local bundle cache
+#[derive(Debug, Parser)]
+pub struct CacheCommand {
+    #[command(subcommand)]
+    command: CacheCommands,
+}
+
+#[derive(Debug, Subcommand)]
+enum CacheCommands {
+    #[command(name = "export-bundle")]
+    /// Turn the locally cached files of the bundle into a standalone bundle
+    ExportBundle(CacheExportBundleCommand),
+}
+
+impl TectonicCommand for CacheCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        match &self.command {
+            CacheCommands::ExportBundle(c) => c.customize(cc),
+        }
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.command {
+            CacheCommands::ExportBundle(c) => c.execute(config, status),
+        }
+    }
+}
+
+#[derive(Debug, Parser)]
+struct CacheExportBundleCommand {
+    /// Where to write the bundle: a directory, or a file ending in `.ttb`
+    dest: PathBuf,
+}
+
+impl CacheExportBundleCommand {
+    fn customize(&self, _cc: &mut CommandCustomizations) {}
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if self.dest.exists() {
+            return Err(errmsg!(
+                "refusing to overwrite `{}`; remove it first",
+                self.dest.display()
+            ));
+        }
+
+        // Only look at the cache: we must never download anything here, since
+        // the point is to capture what's already on this machine.
+        let mut bundle = get_a_bundle(config, true, status)?;
+        let digest = bundle.get_digest()?;
+        let files = bundle.cached_files()?;
+
+        if files.is_empty() {
+            return Err(errmsg!("no files of this bundle are cached locally"));
+        }
+
+        if self.dest.extension().is_some_and(|e| e == "ttb") {
+            export_ttb(&self.dest, files, &digest.to_string())?;
+        } else {
+            export_dir(&self.dest, files, &digest.to_string(), status)?;
+        }
+
+        tt_note!(
+            status,
+            "exported bundle `{}` to `{}`",
+            digest,
+            self.dest.display()
+        );
+        tt_note!(
+            status,
+            "use it on another machine with `--bundle {}`",
+            self.dest.display()
+        );
+        Ok(0)
+    }
+}
+
+/// Write the files into a flat directory, the layout that `DirBundle` reads.
+fn export_dir(
+    dest: &Path,
+    files: Vec<(String, PathBuf)>,
+    digest: &str,
+    status: &mut dyn StatusBackend,
+) -> Result<()> {
+    // Directory bundles only look up files by name, so if the same name
+    // appears in several places, keep the first path in alphabetical order,
+    // which is what a TTB bundle's search would pick.
+    let mut by_name = BTreeMap::new();
+
+    for (path, local) in files {
+        let name = path
+            .rsplit_once('/')
+            .map_or(&path[..], |(_, n)| n)
+            .to_owned();
+
+        if name == digest::DIGEST_NAME {
+            continue;
+        }
+
+        if let Some((kept, _)) = by_name.get(&name) {
+            tt_warning!(
+                status,
+                "skipping `{}`: its name clashes with `{}`",
+                path,
+                kept
+            );
+            continue;
+        }
+
+        by_name.insert(name, (path, local));
+    }
+
+    ctry!(fs::create_dir_all(dest); "cannot create directory `{}`", dest.display());
+
+    for (name, (_, local)) in &by_name {
+        ctry!(
+            fs::copy(local, dest.join(name));
+            "cannot copy `{}` into the exported bundle", local.display()
+        );
+    }
+
+    ctry!(
+        fs::write(dest.join(digest::DIGEST_NAME), format!("{digest}\n"));
+        "cannot write `{}`", digest::DIGEST_NAME
+    );
+    Ok(())
+}
+
+/// Pack the files into a TTB bundle file.
+fn export_ttb(dest: &Path, files: Vec<(String, PathBuf)>, digest: &str) -> Result<()> {
+    // Search every directory that holds a file, in alphabetical order.
+    let search: BTreeSet<String> = files
+        .iter()
+        .map(|(path, _)| match path.rsplit_once('/') {
+            Some((dir, _)) => format!("/{dir}/"),
+            None => "/".to_owned(),
+        })
+        .collect();
+
+    let mut pack_files = Vec::with_capacity(files.len());
+
+    for (path, local) in files {
+        let mut hasher = Sha256::new();
+        ctry!(
+            std::io::copy(&mut File::open(&local)?, &mut hasher);
+            "cannot read `{}`", local.display()
+        );
+        let hash = digest::bytes_to_hex(&hasher.finalize());
+
+        pack_files.push(PackFile {
+            hash,
+            path,
+            source: local,
+        });
+    }
+
+    let target = ctry!(File::create(dest); "cannot create `{}`", dest.display());
+    BundleV1::make_from_files(
+        Box::new(target),
+        pack_files,
+        search.into_iter().collect(),
+        digest,
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use tectonic_bundles::{dir::DirBundle, ttb_fs::TTBFsBundle, Bundle};
+    use tectonic_io_base::OpenResult;
+    use tectonic_status_base::NoopStatusBackend;
+
+    const DIGEST: &str = "0a1b2c3d4e5f60718293a4b5c6d7e8f90a1b2c3d4e5f60718293a4b5c6d7e8f9";
+
+    /// Lay out some files the way the cache stores them, returning them as
+    /// `Bundle::cached_files` would.
+    fn make_cache(root: &Path) -> Vec<(String, PathBuf)> {
+        let files = [
+            ("tex/a/clash.tex", "first\n"),
+            ("tex/a/plain.tex", "\\bye\n"),
+            ("tex/b/clash.tex", "second\n"),
+            ("tex/b/hyphen.tex", "% hyphens\n"),
+        ];
+
+        files
+            .iter()
+            .map(|(path, text)| {
+                let local = root.join(path);
+                fs::create_dir_all(local.parent().unwrap()).unwrap();
+                fs::write(&local, text).unwrap();
+                (path.to_string(), local)
+            })
+            .collect()
+    }
+
+    fn read_back(bundle: &mut dyn Bundle, name: &str) -> String {
+        let mut text = String::new();
+
+        match bundle.input_open_name(name, &mut NoopStatusBackend::default()) {
+            OpenResult::Ok(mut h) => h.read_to_string(&mut text).unwrap(),
+            _ => panic!("`{name}` is missing from the exported bundle"),
+        };
+
+        text
+    }
+
+    fn check_bundle(bundle: &mut dyn Bundle) {
+        assert_eq!(bundle.get_digest().unwrap().to_string(), DIGEST);
+        assert_eq!(read_back(bundle, "plain.tex"), "\\bye\n");
+        assert_eq!(read_back(bundle, "hyphen.tex"), "% hyphens\n");
+        assert_eq!(read_back(bundle, "clash.tex"), "first\n");
+    }
+
+    #[test]
+    fn export_dir_reads_back() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let files = make_cache(&tempdir.path().join("cache"));
+        let dest = tempdir.path().join("exported");
+
+        export_dir(&dest, files, DIGEST, &mut NoopStatusBackend::default()).unwrap();
+        check_bundle(&mut DirBundle::new(&dest));
+    }
+
+    #[test]
+    fn export_ttb_reads_back() {
+        let tempdir = tempfile::tempdir().unwrap();
+        let files = make_cache(&tempdir.path().join("cache"));
+        let dest = tempdir.path().join("exported.ttb");
+
+        export_ttb(&dest, files, DIGEST).unwrap();
+        check_bundle(&mut TTBFsBundle::open(&dest).unwrap());
+    }
+}
@@ -0,0 +1,69 @@
+use clap::Parser;
+use std::path::PathBuf;
+use tectonic::{
+    config::PersistentConfig, doctor::EnvironmentReport, errors::Result, tt_note, tt_warning,
+};
+use tectonic_docmodel::workspace::Workspace;
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// `doctor`: Describe the environment that Tectonic runs in
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct DoctorCommand {
+    /// Save the report to the specified JSON file instead of printing it
+    #[arg(long, short, value_name = "PATH", conflicts_with = "diff")]
+    output: Option<PathBuf>,
+
+    /// Compare two saved reports instead of describing this environment
+    #[arg(long, num_args = 2, value_names = ["FIRST", "SECOND"])]
+    diff: Option<Vec<PathBuf>>,
+}
+
+impl TectonicCommand for DoctorCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        if let Some(paths) = self.diff.as_ref() {
+            let first = EnvironmentReport::load(&paths[0])?;
+            let second = EnvironmentReport::load(&paths[1])?;
+            let diff = first.compare(&second);
+
+            if diff.is_empty() {
+                tt_note!(status, "the environment reports are the same");
+                return Ok(0);
+            }
+
+            print!("{diff}");
+            return Ok(1);
+        }
+
+        let mut report = EnvironmentReport::capture(&config);
+
+        // If we're in a workspace, its document may use a different bundle
+        // than the default.
+        if let Ok(ws) = Workspace::open_from_environment() {
+            report.insert("bundle.document", ws.first_document().bundle_loc.as_str());
+        }
+
+        if report.get("fontconfig.available") == Some("no") {
+            tt_warning!(
+                status,
+                "fontconfig's `fc-list` program was not found, so system fonts are not described"
+            );
+        }
+
+        match self.output.as_ref() {
+            Some(path) => {
+                report.save(path)?;
+                tt_note!(status, "wrote environment report to `{}`", path.display());
+            }
+
+            None => print!("{report}"),
+        }
+
+        Ok(0)
+    }
+}
@@ -6,7 +6,7 @@ use tectonic::{
     ctry,
     docmodel::{DocumentExt, DocumentSetupOptions},
     driver::PassSetting,
-    errors::Result,
+    errors::{ChainErrCompatExt, ErrorKind, Result},
     tt_error,
 };
 use tectonic_bridge_core::{SecuritySettings, SecurityStance};
@@ -46,7 +46,7 @@ impl TectonicCommand for DumpCommand {
     }
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
-        let ws = Workspace::open_from_environment()?;
+        let ws = Workspace::open_from_environment().chain_err(|| ErrorKind::ConfigError)?;
         let doc = ws.first_document();
 
         // Default to allowing insecure since it would be super duper annoying
@@ -1,6 +1,10 @@
+pub mod bench;
 pub mod build;
 pub mod bundle;
+pub mod cache;
+pub mod doctor;
 pub mod dump;
 pub mod new;
+pub mod selftest;
 pub mod show;
 pub mod watch;
@@ -4,7 +4,7 @@ use std::path::PathBuf;
 use tectonic::{
     config::PersistentConfig, ctry, docmodel::WorkspaceCreatorExt, errors::Result, tt_note,
 };
-use tectonic_docmodel::workspace::WorkspaceCreator;
+use tectonic_docmodel::{ci::CiService, workspace::WorkspaceCreator};
 use tectonic_status_base::StatusBackend;
 
 use crate::v2cli::{CommandCustomizations, TectonicCommand};
@@ -15,6 +15,10 @@ pub struct InitCommand {
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// Also generate configuration for building the documents on this CI service (github, gitlab)
+    #[arg(long, value_name = "SERVICE")]
+    ci: Vec<CiService>,
 }
 
 impl TectonicCommand for InitCommand {
@@ -28,7 +32,17 @@ impl TectonicCommand for InitCommand {
             path.display()
         );
 
-        let wc = WorkspaceCreator::new(path);
+        let mut wc = WorkspaceCreator::new(path);
+
+        for service in self.ci {
+            tt_note!(
+                status,
+                "generating {service} CI configuration in `{}`",
+                service.config_path()
+            );
+            wc.ci_service(service);
+        }
+
         ctry!(
             wc.create_defaulted(&config, self.bundle);
             "failed to create the new Tectonic workspace"
@@ -47,6 +61,10 @@ pub struct NewCommand {
     /// Use this URL to find resource files instead of the default
     #[arg(long, short)]
     bundle: Option<String>,
+
+    /// Also generate configuration for building the documents on this CI service (github, gitlab)
+    #[arg(long, value_name = "SERVICE")]
+    ci: Vec<CiService>,
 }
 
 impl TectonicCommand for NewCommand {
@@ -59,7 +77,17 @@ impl TectonicCommand for NewCommand {
             self.path.display()
         );
 
-        let wc = WorkspaceCreator::new(self.path);
+        let mut wc = WorkspaceCreator::new(self.path);
+
+        for service in self.ci {
+            tt_note!(
+                status,
+                "generating {service} CI configuration in `{}`",
+                service.config_path()
+            );
+            wc.ci_service(service);
+        }
+
         ctry!(
             wc.create_defaulted(&config, self.bundle);
             "failed to create the new Tectonic workspace"
@@ -0,0 +1,344 @@
+use clap::Parser;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+use tectonic::{
+    config::PersistentConfig,
+    ctry,
+    driver::{EngineRun, OutputFormat, PassSetting, ProcessingSessionBuilder},
+    errors::{ChainErrCompatExt, ErrorKind, Result},
+    tt_error, tt_note,
+};
+use tectonic_bridge_core::{SecuritySettings, SecurityStance};
+use tectonic_bundles::Bundle;
+use tectonic_status_base::StatusBackend;
+
+use crate::v2cli::{CommandCustomizations, TectonicCommand};
+
+/// The document built to check the PDF pipeline. It's small, but it loads
+/// OpenType fonts, typesets some math, and has a bibliography, so that every
+/// engine gets some real work.
+const SELFTEST_TEX: &str = r#"\documentclass{article}
+\usepackage{fontspec}
+\begin{document}
+\section{Tectonic self-test}
+If you can read this, the \TeX{} engine, its fonts, and the bundle are
+working: na\"ive caf\'e, Ærøskøbing, $e^{i\pi} + 1 = 0$. The bibliography
+exercises Bib\TeX~\cite{tectonic}.
+\bibliographystyle{plain}
+\bibliography{selftest}
+\end{document}
+"#;
+
+const SELFTEST_BIB: &str = r#"@misc{tectonic,
+  author = {{The Tectonic Project}},
+  title = {Tectonic: a modernized, complete, self-contained {\TeX}/{\LaTeX} engine},
+  howpublished = {https://tectonic-typesetting.github.io/},
+}
+"#;
+
+/// The document built to check the HTML pipeline. HTML output needs the
+/// document to say how to template its output, which is done here with the
+/// raw specials.
+const SELFTEST_HTML_TEX: &str = r#"\documentclass{article}
+\usepackage{fontspec}
+\begin{document}
+\special{tdux:addTemplate selftest-template.html}
+\special{tdux:setTemplate selftest-template.html}
+\special{tdux:setOutputPath index.html}
+If you can read this, HTML output is working.
+\special{tdux:emit}
+\end{document}
+"#;
+
+const SELFTEST_HTML_TEMPLATE: &str = r#"<!DOCTYPE html>
+<html>
+<head><meta charset="utf-8"><title>Tectonic self-test</title></head>
+<body>{{ tduxContent | safe }}</body>
+</html>
+"#;
+
+/// `selftest`: Check that this installation can build documents
+#[derive(Debug, Eq, PartialEq, Parser)]
+pub struct SelftestCommand {
+    /// Use only resource files cached locally
+    #[arg(short = 'C', long)]
+    only_cached: bool,
+
+    /// Also check the HTML output pipeline
+    #[arg(long)]
+    html: bool,
+
+    /// Build the test documents in this directory and keep the results,
+    /// instead of using a temporary directory
+    #[arg(long, value_name = "PATH")]
+    outdir: Option<PathBuf>,
+}
+
+/// How a stage of the test turned out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Outcome {
+    Pass,
+    Fail,
+    NotRun,
+}
+
+/// The results of one stage of the test, such as running BibTeX.
+#[derive(Debug)]
+struct Stage {
+    name: &'static str,
+    outcome: Outcome,
+    runs: usize,
+    duration: Duration,
+}
+
+impl Stage {
+    fn not_run(name: &'static str) -> Self {
+        Stage {
+            name,
+            outcome: Outcome::NotRun,
+            runs: 0,
+            duration: Duration::ZERO,
+        }
+    }
+
+    /// Summarize the runs of the engine with the given name.
+    fn from_runs(name: &'static str, runs: &[EngineRun]) -> Self {
+        let mut stage = Stage::not_run(name);
+
+        for run in runs.iter().filter(|r| r.engine == name) {
+            stage.runs += 1;
+            stage.duration += run.duration;
+            stage.outcome = match (stage.outcome, run.succeeded) {
+                (Outcome::Fail, _) | (_, false) => Outcome::Fail,
+                _ => Outcome::Pass,
+            };
+        }
+
+        stage
+    }
+}
+
+impl TectonicCommand for SelftestCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
+        let temp_dir;
+        let dir = match self.outdir {
+            Some(ref d) => {
+                ctry!(fs::create_dir_all(d); "couldn't create directory `{}`", d.display());
+                d.clone()
+            }
+
+            None => {
+                temp_dir = ctry!(
+                    tempfile::Builder::new().prefix("tectonic_selftest").tempdir();
+                    "couldn't create a temporary directory for the self-test"
+                );
+                temp_dir.path().to_owned()
+            }
+        };
+
+        let mut inputs = vec![
+            ("selftest.tex", SELFTEST_TEX),
+            ("selftest.bib", SELFTEST_BIB),
+        ];
+
+        if self.html {
+            inputs.push(("selftest-html.tex", SELFTEST_HTML_TEX));
+            inputs.push(("selftest-template.html", SELFTEST_HTML_TEMPLATE));
+        }
+
+        for (name, text) in inputs {
+            let path = dir.join(name);
+            ctry!(fs::write(&path, text); "couldn't write `{}`", path.display());
+        }
+
+        let mut stages = Vec::new();
+
+        // Opening the bundle is where a broken installation or network
+        // usually shows up first, so it gets a stage of its own.
+
+        let start = Instant::now();
+        let bundle_ok = match config
+            .default_bundle(self.only_cached)
+            .and_then(|mut b| b.get_digest().chain_err(|| ErrorKind::BundleError))
+        {
+            Ok(_) => true,
+            Err(e) => {
+                tt_error!(status, "couldn't open the bundle"; e);
+                false
+            }
+        };
+
+        stages.push(Stage {
+            name: "bundle",
+            outcome: if bundle_ok {
+                Outcome::Pass
+            } else {
+                Outcome::Fail
+            },
+            runs: 1,
+            duration: start.elapsed(),
+        });
+
+        let pdf_engines = ["TeX", "BibTeX", "xdvipdfmx"];
+
+        if bundle_ok {
+            let runs = self.build(
+                &config,
+                &dir,
+                &dir,
+                "selftest.tex",
+                OutputFormat::Pdf,
+                status,
+            );
+            stages.extend(pdf_engines.map(|e| Stage::from_runs(e, &runs)));
+
+            // Make sure that the PDF really was written.
+            if let Some(stage) = stages.last_mut() {
+                if stage.outcome == Outcome::Pass && !is_pdf(&dir.join("selftest.pdf")) {
+                    tt_error!(status, "xdvipdfmx ran, but didn't produce a valid PDF file");
+                    stage.outcome = Outcome::Fail;
+                }
+            }
+        } else {
+            stages.extend(pdf_engines.map(Stage::not_run));
+        }
+
+        if self.html {
+            let html_dir = dir.join("html");
+            ctry!(fs::create_dir_all(&html_dir); "couldn't create directory `{}`", html_dir.display());
+
+            let mut stage = if bundle_ok {
+                let runs = self.build(
+                    &config,
+                    &dir,
+                    &html_dir,
+                    "selftest-html.tex",
+                    OutputFormat::Html,
+                    status,
+                );
+                Stage::from_runs("spx2html", &runs)
+            } else {
+                Stage::not_run("spx2html")
+            };
+
+            if stage.outcome == Outcome::Pass && !html_dir.join("index.html").is_file() {
+                tt_error!(status, "spx2html ran, but didn't produce `index.html`");
+                stage.outcome = Outcome::Fail;
+            }
+
+            stages.push(stage);
+        }
+
+        println!("{:<10}  {:<7}  {:>9}", "stage", "result", "time");
+
+        for stage in &stages {
+            let result = match stage.outcome {
+                Outcome::Pass => "pass",
+                Outcome::Fail => "FAIL",
+                Outcome::NotRun => "not run",
+            };
+
+            let time = if stage.outcome == Outcome::NotRun {
+                String::new()
+            } else {
+                format!("{:.2} s", stage.duration.as_secs_f64())
+            };
+
+            let runs = if stage.runs > 1 {
+                format!("  ({} runs)", stage.runs)
+            } else {
+                String::new()
+            };
+
+            println!("{:<10}  {:<7}  {:>9}{}", stage.name, result, time, runs);
+        }
+
+        if stages.iter().all(|s| s.outcome == Outcome::Pass) {
+            tt_note!(status, "all stages of the self-test passed");
+            Ok(0)
+        } else {
+            if self.outdir.is_none() {
+                tt_note!(
+                    status,
+                    "use `--outdir` to keep the files of the self-test for inspection"
+                );
+            }
+
+            Ok(1)
+        }
+    }
+}
+
+impl SelftestCommand {
+    /// Build one of the test documents in *dir*, writing the outputs into
+    /// *out_dir*, and return the engines that were run.
+    /// Errors are reported here, and show up as failed or missing runs.
+    fn build(
+        &self,
+        config: &PersistentConfig,
+        dir: &Path,
+        out_dir: &Path,
+        input: &str,
+        format: OutputFormat,
+        status: &mut dyn StatusBackend,
+    ) -> Vec<EngineRun> {
+        match self.build_inner(config, dir, out_dir, input, format, status) {
+            Ok(runs) => runs,
+            Err(e) => {
+                tt_error!(status, "couldn't set up the build of `{}`", input; e);
+                Vec::new()
+            }
+        }
+    }
+
+    fn build_inner(
+        &self,
+        config: &PersistentConfig,
+        dir: &Path,
+        out_dir: &Path,
+        input: &str,
+        format: OutputFormat,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<EngineRun>> {
+        let mut sess_builder = ProcessingSessionBuilder::new_with_security(SecuritySettings::new(
+            SecurityStance::DisableInsecures,
+        ));
+
+        sess_builder
+            .primary_input_path(dir.join(input))
+            .tex_input_name(input)
+            .filesystem_root(dir)
+            .output_dir(out_dir)
+            .format_name("latex")
+            .format_cache_path(config.format_cache_path()?)
+            .output_format(format)
+            .pass(PassSetting::Default)
+            .build_date(SystemTime::UNIX_EPOCH)
+            .bundle(config.default_bundle(self.only_cached)?);
+
+        let mut sess = sess_builder.create(status)?;
+
+        if let Err(e) = sess.run(status) {
+            tt_error!(status, "the build of `{}` failed", input; e);
+
+            let output = sess.get_stdout_content();
+            if !output.is_empty() {
+                status.dump_error_logs(&output);
+            }
+        }
+
+        Ok(sess.engine_runs().to_vec())
+    }
+}
+
+/// Check that the file at *path* looks like a PDF file.
+fn is_pdf(path: &Path) -> bool {
+    fs::read(path).is_ok_and(|d| d.starts_with(b"%PDF-"))
+}
@@ -1,5 +1,11 @@
 use clap::{CommandFactory, Parser};
-use tectonic::{config::PersistentConfig, errors::Result};
+use tectonic::{
+    config::PersistentConfig,
+    docmodel::{DocumentExt, DocumentSetupOptions},
+    errors::Result,
+    io::format_cache::cached_formats,
+};
+use tectonic_docmodel::workspace::Workspace;
 use tectonic_io_base::app_dirs;
 use tectonic_status_base::StatusBackend;
 
@@ -14,6 +20,19 @@ pub struct ShowCommand {
 
 #[derive(Debug, Eq, PartialEq, Parser)]
 enum ShowCommands {
+    #[command(name = "bundle")]
+    /// Print the location and digest of the bundle in use
+    Bundle(ShowBundleCommand),
+
+    #[command(name = "formats")]
+    /// List the cached format files
+    Formats(ShowFormatsCommand),
+
+    #[command(name = "paths")]
+    /// Print the locations of the configuration file, the caches, and the
+    /// directories searched for input files
+    Paths(ShowPathsCommand),
+
     #[command(name = "user-cache-dir")]
     /// Print the location of the default per-user cache directory
     UserCacheDir(ShowUserCacheDirCommand),
@@ -26,6 +45,9 @@ enum ShowCommands {
 impl TectonicCommand for ShowCommand {
     fn customize(&self, cc: &mut CommandCustomizations) {
         match &self.command {
+            ShowCommands::Bundle(c) => c.customize(cc),
+            ShowCommands::Formats(c) => c.customize(cc),
+            ShowCommands::Paths(c) => c.customize(cc),
             ShowCommands::UserCacheDir(c) => c.customize(cc),
             ShowCommands::ShellCompletions(c) => c.customize(cc),
         }
@@ -33,12 +55,114 @@ impl TectonicCommand for ShowCommand {
 
     fn execute(self, config: PersistentConfig, status: &mut dyn StatusBackend) -> Result<i32> {
         match self.command {
+            ShowCommands::Bundle(c) => c.execute(config, status),
+            ShowCommands::Formats(c) => c.execute(config, status),
+            ShowCommands::Paths(c) => c.execute(config, status),
             ShowCommands::UserCacheDir(c) => c.execute(config, status),
             ShowCommands::ShellCompletions(c) => c.execute(config, status),
         }
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct ShowBundleCommand {}
+
+impl ShowBundleCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        // Only look at the cache, so that nothing is downloaded: if the
+        // bundle isn't cached, its digest is reported as unavailable.
+        let (location, bundle) = match Workspace::open_from_environment() {
+            Ok(ws) => {
+                let doc = ws.first_document();
+                let mut options = DocumentSetupOptions::default();
+                options.only_cached(true);
+                (doc.bundle_loc.clone(), doc.bundle(&options))
+            }
+            Err(_) => (
+                config.default_bundle_loc().to_owned(),
+                config.default_bundle(true),
+            ),
+        };
+
+        println!("location: {location}");
+
+        match bundle {
+            Ok(mut bundle) => {
+                match bundle.get_digest() {
+                    Ok(digest) => println!("digest: {digest}"),
+                    Err(e) => println!("digest: unavailable ({e})"),
+                }
+
+                match bundle.cache_dir() {
+                    Some(dir) => println!("cache: {}", dir.display()),
+                    None => println!("cache: none"),
+                }
+            }
+            Err(e) => println!("digest: unavailable ({e})"),
+        }
+
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct ShowFormatsCommand {}
+
+impl ShowFormatsCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        for format in cached_formats(&config.format_cache_path()?)? {
+            println!(
+                "{}\t{}\t{}\t{}\t{}",
+                format.name,
+                format.version,
+                format.bundle_digest,
+                format.size,
+                format.path.display()
+            );
+        }
+
+        Ok(0)
+    }
+}
+
+#[derive(Debug, Eq, PartialEq, Parser)]
+struct ShowPathsCommand {}
+
+impl ShowPathsCommand {
+    fn customize(&self, cc: &mut CommandCustomizations) {
+        cc.always_stderr = true;
+    }
+
+    fn execute(self, config: PersistentConfig, _status: &mut dyn StatusBackend) -> Result<i32> {
+        println!(
+            "config file: {}",
+            PersistentConfig::config_file_path()?.display()
+        );
+        println!("bundle cache: {}", config.bundle_cache_path()?.display());
+        println!("format cache: {}", config.format_cache_path()?.display());
+
+        if let Ok(ws) = Workspace::open_from_environment() {
+            let doc = ws.first_document();
+
+            for path in doc.search_paths() {
+                println!("search path: {}", path.display());
+            }
+
+            println!("build directory: {}", doc.build_dir().display());
+        }
+
+        Ok(0)
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Parser)]
 struct ShowUserCacheDirCommand {}
 
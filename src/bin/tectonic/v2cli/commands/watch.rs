@@ -1,13 +1,18 @@
 use clap::Parser;
 use std::time::Duration;
-use std::{env, path::PathBuf, sync::Arc};
+use std::{
+    collections::BTreeSet,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use tectonic::{config::PersistentConfig, errors::Result, tt_error};
 use tectonic_status_base::StatusBackend;
 use tokio::runtime;
 use watchexec::command::Program;
 use watchexec::{
     command::{Command, Shell},
-    Id, Watchexec,
+    Config, Id, WatchedPath, Watchexec,
 };
 use watchexec_filterer_globset::GlobsetFilterer;
 use watchexec_signals::Signal;
@@ -32,6 +37,42 @@ fn get_trimmed_exe_name() -> PathBuf {
     exe_name
 }
 
+/// Get the paths to watch: the current directory, and the files outside of
+/// it that the most recent builds depended on, such as graphics kept in a
+/// shared directory. Builds list their dependencies in the `.d` files that
+/// they write in the build directory.
+fn watched_paths(current_dir: &Path) -> Vec<WatchedPath> {
+    let mut external = BTreeSet::new();
+
+    if let Ok(entries) = fs::read_dir(current_dir.join("build")) {
+        for path in entries.flatten().map(|e| e.path()) {
+            if path.extension().is_some_and(|e| e == "d") {
+                if let Ok(rules) = fs::read_to_string(&path) {
+                    external.extend(
+                        dependencies(&rules).filter(|p| !p.starts_with(current_dir) && p.exists()),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut paths = vec![WatchedPath::recursive(current_dir)];
+    paths.extend(external.into_iter().map(WatchedPath::non_recursive));
+    paths
+}
+
+/// Get the dependencies listed in the Makefile rules written by a build.
+/// The first line names the targets, and each line after it names one
+/// dependency.
+fn dependencies(rules: &str) -> impl Iterator<Item = PathBuf> + '_ {
+    rules
+        .lines()
+        .skip(1)
+        .map(|l| l.trim_end_matches('\\').trim())
+        .filter(|l| !l.is_empty())
+        .map(PathBuf::from)
+}
+
 /// `watch`: Watch input files and execute commands on change
 #[derive(Debug, Eq, PartialEq, Parser)]
 pub struct WatchCommand {
@@ -109,7 +150,7 @@ impl WatchCommand {
         .await
         .unwrap();
 
-        async fn end_task(end: Ticket, job: Job) {
+        async fn end_task(end: Ticket, job: Job, config: Arc<Config>, current_dir: PathBuf) {
             end.await;
             job.run(|ctx| match ctx.current {
                 CommandState::Finished {
@@ -127,11 +168,32 @@ impl WatchCommand {
                 _ => (),
             })
             .await;
+
+            // The build may have picked up new dependencies.
+            config.pathset(watched_paths(&current_dir));
         }
 
+        let exec_handler = match Watchexec::with_config(Config::default()) {
+            Ok(h) => h,
+            Err(e) => {
+                tt_error!(
+                    status,
+                    "failed to build arguments for watch ExecHandler";
+                    e.into()
+                );
+                return Ok(1);
+            }
+        };
+
         let cmds = Arc::new(cmds);
-        let exec_handler = Watchexec::new_async(move |mut action| {
+        let config = Arc::clone(&exec_handler.config);
+        let watch_dir = current_dir.clone();
+
+        exec_handler.config.on_action_async(move |mut action| {
             let cmds = Arc::clone(&cmds);
+            let config = Arc::clone(&config);
+            let watch_dir = watch_dir.clone();
+
             Box::new(async move {
                 // When we spawn a job it doesn't immediately become available. So we chain it
                 // with existing jobs.
@@ -169,7 +231,12 @@ impl WatchCommand {
                         for (_, job) in action.list_jobs().chain(new_job) {
                             job.start().await;
                             let end = job.to_wait();
-                            tokio::spawn(end_task(end, job));
+                            tokio::spawn(end_task(
+                                end,
+                                job,
+                                Arc::clone(&config),
+                                watch_dir.clone(),
+                            ));
                         }
                         return action;
                     }
@@ -178,24 +245,12 @@ impl WatchCommand {
             })
         });
 
-        match exec_handler {
-            Err(e) => {
-                tt_error!(
-                    status,
-                    "failed to build arguments for watch ExecHandler";
-                    e.into()
-                );
-                Ok(1)
-            }
-            Ok(exec_handler) => {
-                exec_handler
-                    .config
-                    .pathset([current_dir])
-                    .filterer(Arc::new(filter));
-                exec_handler.main().await.unwrap().unwrap();
-                Ok(0)
-            }
-        }
+        exec_handler
+            .config
+            .pathset(watched_paths(&current_dir))
+            .filterer(Arc::new(filter));
+        exec_handler.main().await.unwrap().unwrap();
+        Ok(0)
     }
 }
 
@@ -8,19 +8,25 @@ use clap::{Parser, Subcommand};
 use std::{env, ffi::OsString, fs, path::Path, path::PathBuf, process};
 use tectonic::{
     config::PersistentConfig,
-    errors::{Result, SyncError},
+    errors::{FailureClass, Result, SyncError},
     status::{termcolor::TermcolorStatusBackend, ChatterLevel, StatusBackend},
     tt_note,
 };
 use tectonic_errors::prelude::anyhow;
-use tectonic_status_base::plain::PlainStatusBackend;
+use tectonic_status_base::{
+    gcc::GccStatusBackend, json::JsonStatusBackend, plain::PlainStatusBackend,
+};
 use tracing::level_filters::LevelFilter;
 
 use self::commands::{
+    bench::BenchCommand,
     build::BuildCommand,
     bundle::BundleCommand,
+    cache::CacheCommand,
+    doctor::DoctorCommand,
     dump::DumpCommand,
     new::{InitCommand, NewCommand},
+    selftest::SelftestCommand,
     show::ShowCommand,
     watch::WatchCommand,
 };
@@ -43,6 +49,14 @@ struct V2CliOptions {
     #[arg(long = "color", default_value = "auto")]
     cli_color: crate::CliColor,
 
+    /// How to format status messages
+    #[arg(long = "status-format", default_value = "text")]
+    status_format: crate::StatusFormat,
+
+    /// How to format error and warning messages
+    #[arg(long = "error-format", default_value = "human")]
+    error_format: crate::ErrorFormat,
+
     /// The command to run
     #[command(subcommand)]
     command: Commands,
@@ -71,7 +85,7 @@ pub fn v2_main(effective_args: &[OsString]) {
         Ok(c) => c,
         Err(ref e) => {
             e.dump_uncolorized();
-            process::exit(1);
+            process::exit(FailureClass::Configuration.exit_code());
         }
     };
 
@@ -92,12 +106,16 @@ pub fn v2_main(effective_args: &[OsString]) {
 
     let mut customizations = CommandCustomizations::default();
     match &args.command {
+        Commands::Bench(o) => o.customize(&mut customizations),
         Commands::Build(o) => o.customize(&mut customizations),
         Commands::Bundle(o) => o.customize(&mut customizations),
-        Commands::Compile(_) => {} // avoid namespacing/etc issues
+        Commands::Cache(o) => o.customize(&mut customizations),
+        Commands::Compile(o) => customizations.always_stderr = o.streams_to_stdout(),
+        Commands::Doctor(o) => o.customize(&mut customizations),
         Commands::Dump(o) => o.customize(&mut customizations),
         Commands::New(o) => o.customize(&mut customizations),
         Commands::Init(o) => o.customize(&mut customizations),
+        Commands::Selftest(o) => o.customize(&mut customizations),
         Commands::Show(o) => o.customize(&mut customizations),
         Commands::Watch(o) => o.customize(&mut customizations),
         Commands::External(_) => {}
@@ -110,7 +128,13 @@ pub fn v2_main(effective_args: &[OsString]) {
         args.chatter_level
     };
 
-    let mut status = if args.cli_color.should_enable() {
+    let mut status = if args.status_format == crate::StatusFormat::Json {
+        Box::new(JsonStatusBackend::new(chatter_level)) as Box<dyn StatusBackend>
+    } else if args.error_format == crate::ErrorFormat::Gcc {
+        let mut sb = GccStatusBackend::new(chatter_level);
+        sb.always_stderr(customizations.always_stderr);
+        Box::new(sb) as Box<dyn StatusBackend>
+    } else if args.cli_color.should_enable() {
         let mut sb = TermcolorStatusBackend::new(chatter_level);
         sb.always_stderr(customizations.always_stderr);
         Box::new(sb) as Box<dyn StatusBackend>
@@ -130,12 +154,16 @@ pub fn v2_main(effective_args: &[OsString]) {
     // Now that we've got colorized output, pass off to the inner function.
 
     let r = match args.command {
+        Commands::Bench(o) => o.execute(config, &mut *status),
         Commands::Build(o) => o.execute(config, &mut *status),
         Commands::Bundle(o) => o.execute(config, &mut *status),
+        Commands::Cache(o) => o.execute(config, &mut *status),
         Commands::Compile(o) => o.execute(config, &mut *status),
+        Commands::Doctor(o) => o.execute(config, &mut *status),
         Commands::Dump(o) => o.execute(config, &mut *status),
         Commands::New(o) => o.execute(config, &mut *status),
         Commands::Init(o) => o.execute(config, &mut *status),
+        Commands::Selftest(o) => o.execute(config, &mut *status),
         Commands::Show(o) => o.execute(config, &mut *status),
         Commands::Watch(o) => o.execute(config, &mut *status),
         Commands::External(all_args) => do_external(all_args),
@@ -144,8 +172,9 @@ pub fn v2_main(effective_args: &[OsString]) {
     process::exit(match r {
         Ok(c) => c,
         Err(e) => {
+            let code = e.failure_class().exit_code();
             status.report_error(&SyncError::new(e).into());
-            1
+            code
         }
     })
 }
@@ -158,6 +187,10 @@ trait TectonicCommand {
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug, Subcommand)]
 enum Commands {
+    #[command(name = "bench")]
+    /// Time the builds of a document under different options
+    Bench(BenchCommand),
+
     #[command(name = "build")]
     /// Build a document
     Build(BuildCommand),
@@ -166,10 +199,18 @@ enum Commands {
     /// Commands relating to this document’s TeX file bundle
     Bundle(BundleCommand),
 
+    #[command(name = "cache")]
+    /// Commands relating to the local cache of bundle files
+    Cache(CacheCommand),
+
     #[command(name = "compile")]
     /// Run a standalone (La)TeX compilation
     Compile(crate::compile::CompileOptions),
 
+    #[command(name = "doctor")]
+    /// Describe this environment, or compare two descriptions
+    Doctor(DoctorCommand),
+
     #[command(name = "dump")]
     /// Run a partial compilation and output an intermediate file
     Dump(DumpCommand),
@@ -182,6 +223,10 @@ enum Commands {
     /// Initializes a new document in the current directory
     Init(InitCommand),
 
+    #[command(name = "selftest")]
+    /// Check that this installation can build documents
+    Selftest(SelftestCommand),
+
     #[command(name = "show")]
     /// Display various useful pieces of information
     Show(ShowCommand),
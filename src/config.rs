@@ -12,13 +12,15 @@
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 use std::{
+    collections::BTreeMap,
     path::PathBuf,
     sync::atomic::{AtomicBool, Ordering},
 };
 use tectonic_bundles::{detect_bundle, Bundle};
+use tectonic_errors::prelude::ensure;
 use tectonic_io_base::app_dirs;
 
-use crate::errors::{ErrorKind, Result};
+use crate::errors::{ChainErrCompatExt, ErrorKind, Result};
 
 /// Awesome hack time!!!
 ///
@@ -151,11 +153,26 @@ impl PersistentConfig {
             .into());
         }
 
-        Ok(
-            detect_bundle(self.default_bundles[0].url.to_owned(), only_cached, None)
-                .unwrap()
-                .unwrap(),
-        )
+        let url = &self.default_bundles[0].url;
+
+        match detect_bundle(url.to_owned(), only_cached, None)
+            .chain_err(|| ErrorKind::BundleError)?
+        {
+            Some(b) => Ok(b),
+            None => Err(ErrorKind::Msg(format!("`{url}` doesn't specify a valid bundle")).into()),
+        }
+    }
+
+    /// Get the path of the per-user configuration file, which may not exist.
+    pub fn config_file_path() -> Result<PathBuf> {
+        let mut path = app_dirs::get_user_config()?;
+        path.push("config.toml");
+        Ok(path)
+    }
+
+    /// Get the cache directory to use for the files of network bundles
+    pub fn bundle_cache_path(&self) -> Result<PathBuf> {
+        Ok(app_dirs::get_user_cache_dir("bundles")?)
     }
 
     /// Get the cache directory to use for format files
@@ -168,6 +185,23 @@ impl PersistentConfig {
     }
 }
 
+/// Make sure that a table of settings, such as those of a post-processing step
+/// or of a generated input, only has the given keys.
+pub(crate) fn check_settings(
+    settings: &BTreeMap<String, String>,
+    allowed: &[&str],
+) -> tectonic_errors::Result<()> {
+    for key in settings.keys() {
+        ensure!(
+            allowed.contains(&key.as_str()),
+            "unrecognized setting `{}`",
+            key
+        );
+    }
+
+    Ok(())
+}
+
 impl Default for PersistentConfig {
     fn default() -> Self {
         let url = tectonic_bundles::get_fallback_bundle_url(tectonic_engine_xetex::FORMAT_SERIAL);
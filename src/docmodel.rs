@@ -7,22 +7,35 @@
 //! `tectonic_docmodel` crate with the actual document-processing capabilities
 //! provided by the processing engines.
 
-use std::{fmt::Write as FmtWrite, fs, io, path::PathBuf};
+use std::{fmt::Write as FmtWrite, fs, io, path::PathBuf, rc::Rc};
 use tectonic_bridge_core::SecuritySettings;
 use tectonic_bundles::{detect_bundle, Bundle};
 use tectonic_docmodel::{
-    document::{BuildTargetType, Document, InputFile},
+    document::{
+        BuildTargetType, Document, Generator, HeadingNumbers, InputFile, MathRendering,
+        PostProcessor,
+    },
     workspace::{Workspace, WorkspaceCreator},
 };
+use tectonic_engine_spx2html::{
+    HeadingNumbers as HtmlHeadingNumbers, MathRendering as HtmlMathRendering, PreviewImage,
+    PreviewRegion, Site, SitePage, SocialMetadata,
+};
 use tectonic_geturl::{DefaultBackend, GetUrlBackend};
 
 use crate::{
     config, ctry,
     driver::{OutputFormat, PassSetting, ProcessingSessionBuilder},
-    errors::{ErrorKind, Result},
+    errmsg,
+    errors::{ChainErrCompatExt, ErrorKind, Result},
+    generate::{self, ExternalGenerator, GeneratedInput},
+    io::encoding::{InputEncodingPolicy, LegacyEncoding, Utf16Policy},
+    postprocess::{self, ExternalPostProcessor, PostProcessStep},
+    scratch::ScratchLocation,
     status::StatusBackend,
-    test_util, tt_note,
+    test_util, tt_note, tt_warning,
     unstable_opts::UnstableOptions,
+    MathFontOverrides,
 };
 
 /// Options for setting up [`Document`] instances with the driver
@@ -37,6 +50,18 @@ pub struct DocumentSetupOptions {
 
     /// Ensure a deterministic build environment.
     deterministic_mode: bool,
+
+    /// Keep a shaping cache in the build directory.
+    shaping_cache: bool,
+
+    /// Where the scratch space of each build is created.
+    scratch_location: ScratchLocation,
+
+    /// The most space that the scratch space of a build may use, if limited.
+    scratch_limit: Option<u64>,
+
+    /// Make builds hermetic.
+    hermetic: bool,
 }
 
 impl DocumentSetupOptions {
@@ -46,6 +71,10 @@ impl DocumentSetupOptions {
         DocumentSetupOptions {
             only_cached: false,
             deterministic_mode: false,
+            shaping_cache: false,
+            scratch_location: ScratchLocation::default(),
+            scratch_limit: None,
+            hermetic: false,
             security,
         }
     }
@@ -65,6 +94,35 @@ impl DocumentSetupOptions {
         self.deterministic_mode = s;
         self
     }
+
+    /// Specify whether to memoize the shaping of text in native fonts.
+    ///
+    /// This is experimental. The cache of each output profile is saved in the
+    /// document’s build directory, so that later builds can reuse it.
+    pub fn shaping_cache(&mut self, s: bool) -> &mut Self {
+        self.shaping_cache = s;
+        self
+    }
+
+    /// Specify where the scratch space of each build is created, and the
+    /// most space, in bytes, that it may use.
+    ///
+    /// See [`ProcessingSessionBuilder::scratch_location`] and
+    /// [`ProcessingSessionBuilder::scratch_limit`].
+    pub fn scratch(&mut self, location: ScratchLocation, limit: Option<u64>) -> &mut Self {
+        self.scratch_location = location;
+        self.scratch_limit = limit;
+        self
+    }
+
+    /// Specify whether builds are hermetic.
+    ///
+    /// See [`ProcessingSessionBuilder::hermetic`]. A hermetic build only uses
+    /// cached bundle files, regardless of [`Self::only_cached`].
+    pub fn hermetic(&mut self, s: bool) -> &mut Self {
+        self.hermetic = s;
+        self
+    }
 }
 
 /// Extension methods for [`Document`].
@@ -75,6 +133,12 @@ pub trait DocumentExt {
     /// bundle backend.
     fn bundle(&self, setup_options: &DocumentSetupOptions) -> Result<Box<dyn Bundle>>;
 
+    /// Get the local directories searched for the document's input files, in
+    /// the order in which they're searched: its `src` directory, and then its
+    /// [extra search paths](Document::extra_paths). Files that aren't found
+    /// in any of them come from the bundle.
+    fn search_paths(&self) -> Vec<PathBuf>;
+
     /// Set up a [`ProcessingSessionBuilder`] for one of the outputs.
     ///
     /// The *output_profile* argument gives the name of the document’s output profile to
@@ -95,7 +159,9 @@ impl DocumentExt for Document {
             return Ok(Box::new(bundle));
         }
 
-        let d = detect_bundle(self.bundle_loc.clone(), setup_options.only_cached, None)?;
+        let only_cached = setup_options.only_cached || setup_options.hermetic;
+        let d = detect_bundle(self.bundle_loc.clone(), only_cached, None)
+            .chain_err(|| ErrorKind::BundleError)?;
 
         match d {
             Some(b) => Ok(b),
@@ -103,6 +169,13 @@ impl DocumentExt for Document {
         }
     }
 
+    fn search_paths(&self) -> Vec<PathBuf> {
+        // Extra paths are relative to the document's directory.
+        let mut paths = vec![self.src_dir().join("src")];
+        paths.extend(self.extra_paths.iter().map(|x| self.src_dir().join(x)));
+        paths
+    }
+
     fn setup_session(
         &self,
         output_profile: &str,
@@ -136,12 +209,10 @@ impl DocumentExt for Document {
         let mut sess_builder =
             ProcessingSessionBuilder::new_with_security(setup_options.security.clone());
 
-        // Interpret all extra paths as relative to our working dir
-        let extra_paths: Vec<PathBuf> = self
-            .extra_paths
-            .iter()
-            .map(|x| self.src_dir().join(x))
-            .collect();
+        // The first search path is the root of the filesystem I/O, and the
+        // rest are searched after it.
+        let mut search_paths = self.search_paths();
+        let tex_dir = search_paths.remove(0);
 
         sess_builder
             .output_format(output_format)
@@ -149,13 +220,109 @@ impl DocumentExt for Document {
             .build_date_from_env(setup_options.deterministic_mode)
             .unstables(UnstableOptions {
                 deterministic_mode: setup_options.deterministic_mode,
-                extra_search_paths: extra_paths,
+                extra_search_paths: search_paths,
+                shaping_cache: setup_options.shaping_cache,
+                shaping_cache_file: setup_options.shaping_cache.then(|| {
+                    self.build_dir()
+                        .join(format!("{output_profile}.shaping-cache"))
+                }),
+                scratch_location: setup_options.scratch_location.clone(),
+                scratch_limit: setup_options.scratch_limit,
                 ..Default::default()
             })
             .pass(PassSetting::Default)
             .primary_input_buffer(input_buffer.as_bytes())
             .tex_input_name(output_profile)
-            .synctex(profile.synctex);
+            .synctex(profile.synctex)
+            .math_font_overrides(MathFontOverrides {
+                script_percent_scale_down: profile.math_font.script_percent_scale_down,
+                script_script_percent_scale_down: profile
+                    .math_font
+                    .script_script_percent_scale_down,
+                axis_height_adjust: profile.math_font.axis_height_adjust,
+            });
+
+        sess_builder.input_encoding(InputEncodingPolicy {
+            utf16: if profile.transcode_utf16 {
+                Utf16Policy::Transcode
+            } else {
+                Utf16Policy::Reject
+            },
+            legacy_encoding: match profile.legacy_input_encoding.as_ref() {
+                Some(e) => Some(ctry!(
+                    e.parse::<LegacyEncoding>();
+                    "invalid `legacy_input_encoding` for output `{}`", profile.name
+                )),
+                None => None,
+            },
+        });
+
+        if profile.accessibility_report {
+            sess_builder.html_accessibility_report_path(format!("{output_profile}.a11y.txt"));
+        }
+
+        if profile.target_type == BuildTargetType::Html {
+            sess_builder.html_math_rendering(match profile.math_rendering {
+                MathRendering::Glyphs => HtmlMathRendering::Glyphs,
+                MathRendering::Katex => HtmlMathRendering::Katex,
+                MathRendering::MathJax => HtmlMathRendering::MathJax,
+            });
+
+            sess_builder.html_heading_numbers(match profile.heading_numbers {
+                HeadingNumbers::Keep => HtmlHeadingNumbers::Keep,
+                HeadingNumbers::Strip => HtmlHeadingNumbers::Strip,
+                HeadingNumbers::CssCounters => HtmlHeadingNumbers::CssCounters,
+            });
+
+            if let Some(depth) = profile.toc_depth {
+                sess_builder.html_toc_depth(depth);
+            }
+
+            if profile.social_metadata {
+                sess_builder.html_social_metadata(social_metadata(self));
+            }
+
+            if let Some(preview) = profile.social_preview.as_ref() {
+                if !self
+                    .outputs
+                    .get(&preview.from)
+                    .is_some_and(|p| p.target_type == BuildTargetType::Pdf)
+                {
+                    return Err(errmsg!(
+                        "the social preview image of output `{}` should be rendered from a PDF \
+                         output, but `{}` isn't one",
+                        profile.name,
+                        preview.from
+                    ));
+                }
+
+                let pdf = self.output_main_file(&preview.from);
+
+                if pdf.exists() {
+                    let mut image = PreviewImage::new(pdf);
+                    image.page(preview.page).width(preview.width);
+
+                    if let Some([x, y, width, height]) = preview.region {
+                        image.region(PreviewRegion {
+                            x,
+                            y,
+                            width,
+                            height,
+                        });
+                    }
+
+                    sess_builder.html_preview_image(image);
+                } else {
+                    tt_warning!(
+                        status,
+                        "not rendering the social preview image of output `{}` because `{}` \
+                         hasn't been built yet",
+                        profile.name,
+                        pdf.display()
+                    );
+                }
+            }
+        }
 
         if profile.shell_escape {
             // For now, this is the only option we allow.
@@ -166,13 +333,81 @@ impl DocumentExt for Document {
             }
         }
 
-        if setup_options.only_cached {
+        for step in &profile.postprocess {
+            let processor: Rc<dyn postprocess::PostProcessor> = match &step.processor {
+                PostProcessor::Builtin(name) => match postprocess::builtin(name) {
+                    Some(p) => p,
+                    None => {
+                        return Err(errmsg!(
+                            "unknown built-in post-processor `{}` in output `{}` (known ones are: {})",
+                            name,
+                            profile.name,
+                            postprocess::BUILTIN_NAMES.join(", ")
+                        ));
+                    }
+                },
+
+                PostProcessor::Command(argv) => {
+                    // Commands are run from the document's root directory.
+                    let mut ext = ExternalPostProcessor::new(argv);
+                    ext.current_dir(self.src_dir());
+                    Rc::new(ext)
+                }
+            };
+
+            let mut pp_step = PostProcessStep::new(processor);
+            pp_step
+                .extensions(&step.extensions)
+                .config(step.config.clone());
+            sess_builder.post_process(pp_step);
+        }
+
+        if let Some(bytes) = profile.max_size {
+            sess_builder.max_output_size(bytes);
+        }
+
+        for gen_input in &self.generators {
+            let generator: Rc<dyn generate::Generator> = match &gen_input.generator {
+                Generator::Builtin(name) => match generate::builtin(name) {
+                    Some(g) => g,
+                    None => {
+                        return Err(errmsg!(
+                            "unknown built-in generator `{}` for generated input `{}` (known ones are: {})",
+                            name,
+                            gen_input.name,
+                            generate::BUILTIN_NAMES.join(", ")
+                        ));
+                    }
+                },
+
+                Generator::Command(argv) => Rc::new(ExternalGenerator::new(argv)),
+            };
+
+            // Generators are run in, and their inputs are relative to, the
+            // document's root directory, like post-processors.
+            let mut input = GeneratedInput::new(&gen_input.name, generator);
+            input
+                .dir(self.src_dir())
+                .inputs(&gen_input.inputs)
+                .config(gen_input.config.clone());
+            sess_builder.generated_input(input);
+        }
+
+        if !self.generators.is_empty() {
+            sess_builder
+                .generator_cache_dir(self.build_dir().join("generated"))
+                .generated_inputs_record_path(
+                    self.build_dir()
+                        .join(format!("{output_profile}.generated.tsv")),
+                );
+        }
+
+        if setup_options.only_cached || setup_options.hermetic {
             tt_note!(status, "using only cached resource files");
         }
         sess_builder.bundle(self.bundle(setup_options)?);
+        sess_builder.hermetic(setup_options.hermetic);
 
-        let mut tex_dir = self.src_dir().to_owned();
-        tex_dir.push("src");
         sess_builder.filesystem_root(&tex_dir);
 
         let mut output_dir = self.build_dir().to_owned();
@@ -183,10 +418,122 @@ impl DocumentExt for Document {
         );
         sess_builder.output_dir(&output_dir);
 
+        // Record what the build depended on, including graphics found outside
+        // of the source tree, so that `tectonic -X watch` can watch them too.
+        sess_builder.makefile_output_path(self.build_dir().join(format!("{output_profile}.d")));
+
         Ok(sess_builder)
     }
 }
 
+/// Get a string from the metadata of a document.
+fn metadata_str(doc: &Document, key: &str) -> Option<String> {
+    doc.metadata
+        .as_ref()
+        .and_then(|m| m.get(key))
+        .and_then(|v| v.as_str())
+        .map(|s| s.to_owned())
+}
+
+/// Get the description of a document for previews on social media from its
+/// metadata.
+fn social_metadata(doc: &Document) -> SocialMetadata {
+    let get = |key: &str| metadata_str(doc, key);
+
+    SocialMetadata {
+        title: get("title"),
+        description: get("description"),
+        url: get("url"),
+        site_name: get("site_name"),
+        twitter_site: get("twitter_site"),
+        image: get("image"),
+        image_alt: get("image_alt"),
+    }
+}
+
+/// Extension methods for [`Workspace`].
+pub trait WorkspaceExt {
+    /// Write the files of the site made of the documents' HTML outputs, if
+    /// the workspace defines one, returning how many were rewritten.
+    ///
+    /// This should be done after the documents are built, since their pages
+    /// are dated by the modification times of their main files. HTML outputs
+    /// that haven't been built are left out of the site.
+    fn emit_site(&self, status: &mut dyn StatusBackend) -> Result<usize>;
+}
+
+impl WorkspaceExt for Workspace {
+    fn emit_site(&self, status: &mut dyn StatusBackend) -> Result<usize> {
+        let Some(config) = self.site() else {
+            return Ok(0);
+        };
+
+        let mut site = Site::new(self.root_dir());
+        site.output_dir("build")
+            .sitemap(config.sitemap)
+            .feed(config.feed)
+            .index(config.index);
+
+        if let Some(title) = config.title.as_ref() {
+            site.title(title.as_str());
+        }
+
+        if let Some(url) = config.url.as_ref() {
+            site.url(url.as_str());
+        }
+
+        if let Some(path) = config.index_template.as_ref() {
+            let path = self.root_dir().join(path);
+            site.index_template(ctry!(
+                fs::read_to_string(&path);
+                "couldn\'t read the site template `{}`", path.display()
+            ));
+        }
+
+        for doc in self.documents() {
+            let title = metadata_str(doc, "title").unwrap_or_else(|| doc.name.clone());
+            let description = metadata_str(doc, "description");
+
+            let mut html_outputs: Vec<&str> = doc
+                .outputs
+                .values()
+                .filter(|p| p.target_type == BuildTargetType::Html)
+                .map(|p| p.name.as_str())
+                .collect();
+            html_outputs.sort();
+
+            for name in &html_outputs {
+                let path = doc.output_main_file(name);
+
+                if !path.exists() {
+                    tt_warning!(
+                        status,
+                        "leaving `{}` out of the site, since it hasn\'t been built",
+                        path.display()
+                    );
+                    continue;
+                }
+
+                // Documents with several HTML outputs get a page for each.
+                let title = if html_outputs.len() > 1 {
+                    format!("{title} ({name})")
+                } else {
+                    title.clone()
+                };
+
+                site.page(SitePage::from_file(
+                    title,
+                    description.clone(),
+                    self.root_dir(),
+                    &path,
+                )?);
+            }
+        }
+
+        Ok(site.emit(status)?)
+    }
+}
+
 /// Extension methods for [`WorkspaceCreator`].
 pub trait WorkspaceCreatorExt {
     /// Create the new workspace with a good default for the bundle location.
@@ -0,0 +1,357 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Capturing and comparing descriptions of the environments that Tectonic
+//! runs in.
+//!
+//! When the same document builds differently on two machines, the cause is
+//! usually something about the machines rather than the document: a
+//! different version of Tectonic or of the bundle, a different set of system
+//! fonts, or an environment variable such as `SOURCE_DATE_EPOCH`. An
+//! [`EnvironmentReport`] records these things as a flat set of named values,
+//! so that reports from two machines can be attached to a bug report and
+//! compared with [`EnvironmentReport::compare`].
+//!
+//! Reports only include environment variables that are known to affect
+//! Tectonic, so that they're safe to share.
+
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, env, fmt, fs, path::Path, process::Command};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::app_dirs;
+
+use crate::{
+    config::PersistentConfig,
+    digest::{self, Digest, DigestData},
+};
+
+/// The version of the report file format that we write.
+const FORMAT_VERSION: u32 = 1;
+
+/// Environment variables that are recorded if they're set, in addition to
+/// all of those whose names start with `TECTONIC_`.
+const RECORDED_VARS: &[&str] = &[
+    "FONTCONFIG_FILE",
+    "FONTCONFIG_PATH",
+    "FONTCONFIG_SYSROOT",
+    "LANG",
+    "LC_ALL",
+    "LC_CTYPE",
+    "SOURCE_DATE_EPOCH",
+    "TZ",
+];
+
+/// A description of the environment that Tectonic runs in.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EnvironmentReport {
+    /// The version of the file format.
+    version: u32,
+
+    /// The recorded values, keyed by dotted names such as `os.arch`.
+    entries: BTreeMap<String, String>,
+}
+
+impl Default for EnvironmentReport {
+    fn default() -> Self {
+        EnvironmentReport {
+            version: FORMAT_VERSION,
+            entries: BTreeMap::new(),
+        }
+    }
+}
+
+impl EnvironmentReport {
+    /// Describe the current environment.
+    ///
+    /// This records the version of Tectonic, the operating system, the
+    /// locations of the caches, the default bundle and its digest if it's
+    /// cached, the state of fontconfig, and the relevant environment
+    /// variables. Nothing is downloaded: if the default bundle isn't cached,
+    /// its digest is recorded as unavailable.
+    pub fn capture(config: &PersistentConfig) -> Self {
+        let mut report = EnvironmentReport::default();
+
+        report
+            .insert("tectonic.version", env!("CARGO_PKG_VERSION"))
+            .insert("os.family", env::consts::FAMILY)
+            .insert("os.name", env::consts::OS)
+            .insert("os.arch", env::consts::ARCH);
+
+        if let Some(release) = os_release() {
+            report.insert("os.release", release);
+        }
+
+        report.insert(
+            "paths.config",
+            describe(app_dirs::get_user_config().map(|p| p.display().to_string())),
+        );
+        report.insert(
+            "paths.bundle_cache",
+            describe(app_dirs::get_user_cache_dir("bundles").map(|p| p.display().to_string())),
+        );
+        report.insert(
+            "paths.format_cache",
+            describe(
+                config
+                    .format_cache_path()
+                    .map(|p| p.display().to_string())
+                    .map_err(|e| anyhow!("{}", e)),
+            ),
+        );
+
+        report.insert("bundle.default", config.default_bundle_loc());
+        let digest = match config.default_bundle(true) {
+            Ok(mut bundle) => describe(bundle.get_digest().map(|d| d.to_string())),
+            Err(e) => format!("unavailable ({e})"),
+        };
+        report.insert("bundle.default_digest", digest);
+
+        report.capture_fontconfig();
+
+        for (name, value) in env::vars() {
+            if name.starts_with("TECTONIC_") || RECORDED_VARS.contains(&name.as_str()) {
+                report.insert(&format!("env.{name}"), value);
+            }
+        }
+
+        report
+    }
+
+    /// Record the version of fontconfig and a summary of the fonts that it
+    /// knows about, if its `fc-list` program is available.
+    fn capture_fontconfig(&mut self) {
+        let Ok(fc_list) = which::which("fc-list") else {
+            self.insert("fontconfig.available", "no");
+            return;
+        };
+
+        if let Ok(output) = Command::new(&fc_list).arg("--version").output() {
+            // The version goes to stderr in some releases and stdout in others.
+            let text = String::from_utf8_lossy(if output.stdout.is_empty() {
+                &output.stderr
+            } else {
+                &output.stdout
+            })
+            .trim()
+            .to_owned();
+            self.insert("fontconfig.version", text);
+        }
+
+        match Command::new(&fc_list)
+            .args(["--format", "%{file}\n"])
+            .output()
+        {
+            Ok(output) if output.status.success() => {
+                let text = String::from_utf8_lossy(&output.stdout);
+                let mut files: Vec<&str> = text.lines().filter(|l| !l.is_empty()).collect();
+                files.sort_unstable();
+                files.dedup();
+
+                let mut dc = digest::create();
+                for f in &files {
+                    dc.update(f.as_bytes());
+                    dc.update(b"\n");
+                }
+
+                self.insert("fontconfig.font_files", files.len().to_string());
+                self.insert(
+                    "fontconfig.font_files_digest",
+                    DigestData::from(dc).to_string(),
+                );
+            }
+
+            _ => {
+                self.insert("fontconfig.font_files", "unavailable");
+            }
+        }
+    }
+
+    /// Record a value, replacing any previous value with the same key.
+    pub fn insert(&mut self, key: &str, value: impl Into<String>) -> &mut Self {
+        self.entries.insert(key.to_owned(), value.into());
+        self
+    }
+
+    /// Get a recorded value.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|s| s.as_str())
+    }
+
+    /// Load a report from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = atry!(
+            fs::read_to_string(path);
+            ["failed to read environment report from `{}`", path.display()]
+        );
+        let report: EnvironmentReport = atry!(
+            serde_json::from_str(&text);
+            ["failed to parse environment report in `{}`", path.display()]
+        );
+
+        ensure!(
+            report.version == FORMAT_VERSION,
+            "environment report in `{}` has unsupported format version {}",
+            path.display(),
+            report.version
+        );
+
+        Ok(report)
+    }
+
+    /// Save this report to a JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        atry!(
+            fs::write(path, text);
+            ["failed to write environment report to `{}`", path.display()]
+        );
+        Ok(())
+    }
+
+    /// Compare this report with another.
+    pub fn compare(&self, other: &EnvironmentReport) -> ReportDiff {
+        let mut diff = ReportDiff::default();
+
+        for (key, value) in &self.entries {
+            match other.entries.get(key) {
+                Some(v) if v == value => {}
+                Some(v) => diff.changed.push((key.clone(), value.clone(), v.clone())),
+                None => diff.only_first.push((key.clone(), value.clone())),
+            }
+        }
+
+        for (key, value) in &other.entries {
+            if !self.entries.contains_key(key) {
+                diff.only_second.push((key.clone(), value.clone()));
+            }
+        }
+
+        diff
+    }
+}
+
+impl fmt::Display for EnvironmentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, value) in &self.entries {
+            writeln!(f, "{key} = {value}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The differences between two environment reports.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct ReportDiff {
+    /// Keys with different values, with the values in the first and second
+    /// reports.
+    pub changed: Vec<(String, String, String)>,
+
+    /// Keys that only appear in the first report, with their values.
+    pub only_first: Vec<(String, String)>,
+
+    /// Keys that only appear in the second report, with their values.
+    pub only_second: Vec<(String, String)>,
+}
+
+impl ReportDiff {
+    /// Whether the reports are the same.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.only_first.is_empty() && self.only_second.is_empty()
+    }
+}
+
+impl fmt::Display for ReportDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (key, first, second) in &self.changed {
+            writeln!(f, "  changed: {key}: `{first}` -> `{second}`")?;
+        }
+
+        for (key, value) in &self.only_first {
+            writeln!(f, "  only in first: {key} = `{value}`")?;
+        }
+
+        for (key, value) in &self.only_second {
+            writeln!(f, "  only in second: {key} = `{value}`")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Describe a value that couldn't necessarily be determined.
+fn describe(value: Result<String>) -> String {
+    value.unwrap_or_else(|e| format!("unavailable ({e})"))
+}
+
+/// Get the name of the operating system release, on systems that describe
+/// it in `/etc/os-release`.
+fn os_release() -> Option<String> {
+    let text = fs::read_to_string("/etc/os-release").ok()?;
+
+    text.lines().find_map(|line| {
+        let value = line.strip_prefix("PRETTY_NAME=")?;
+        Some(value.trim_matches('"').to_owned())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_reports() {
+        let mut a = EnvironmentReport::default();
+        a.insert("os.name", "linux")
+            .insert("bundle.default_digest", "abc")
+            .insert("env.TZ", "UTC");
+
+        let mut b = EnvironmentReport::default();
+        b.insert("os.name", "linux")
+            .insert("bundle.default_digest", "def")
+            .insert("env.LANG", "C");
+
+        assert!(a.compare(&a).is_empty());
+
+        let diff = a.compare(&b);
+        assert_eq!(
+            diff.changed,
+            [(
+                "bundle.default_digest".to_owned(),
+                "abc".to_owned(),
+                "def".to_owned()
+            )]
+        );
+        assert_eq!(diff.only_first, [("env.TZ".to_owned(), "UTC".to_owned())]);
+        assert_eq!(diff.only_second, [("env.LANG".to_owned(), "C".to_owned())]);
+        assert_eq!(
+            diff.to_string(),
+            "  changed: bundle.default_digest: `abc` -> `def`\n\
+             \x20 only in first: env.TZ = `UTC`\n\
+             \x20 only in second: env.LANG = `C`\n"
+        );
+
+        assert_eq!(
+            a.to_string().lines().next(),
+            Some("bundle.default_digest = abc")
+        );
+        assert_eq!(a.get("env.TZ"), Some("UTC"));
+    }
+
+    #[test]
+    fn save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("env.json");
+
+        let mut report = EnvironmentReport::default();
+        report.insert("tectonic.version", "1.0.0");
+        report.save(&path).unwrap();
+        assert_eq!(EnvironmentReport::load(&path).unwrap(), report);
+
+        fs::write(&path, "{\"version\": 99, \"entries\": {}}").unwrap();
+        assert!(EnvironmentReport::load(&path).is_err());
+    }
+}
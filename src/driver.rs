@@ -16,39 +16,54 @@
 use byte_unit::{Byte, UnitType};
 use quick_xml::{events::Event, NsReader};
 use std::{
-    collections::{HashMap, HashSet},
-    fs::File,
-    io::{Cursor, Read, Write},
+    cell::RefCell,
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{self, File},
+    io::{self, Cursor, Read, Write},
     path::{Path, PathBuf},
     process::Command,
     rc::Rc,
     result::Result as StdResult,
     str::FromStr,
-    time::{Duration, SystemTime},
+    time::{Duration, Instant, SystemTime},
+};
+use tectonic_bridge_core::{
+    CoreBridgeLauncher, DriverHooks, EngineAbortKind, EnvReadOutcome, FileFormat, InputSearch,
+    SecuritySettings, SystemRequestError,
 };
-use tectonic_bridge_core::{CoreBridgeLauncher, DriverHooks, SecuritySettings, SystemRequestError};
 use tectonic_bundles::Bundle;
-use tectonic_engine_spx2html::AssetSpecification;
+use tectonic_engine_spx2html::{
+    AssetSpecification, HeadingNumbers, MathRendering, PreviewImage, References, SocialMetadata,
+};
 use tectonic_io_base::{
-    digest::DigestData,
+    app_dirs,
+    digest::{self, Digest, DigestData},
     filesystem::{FilesystemIo, FilesystemPrimaryInputIo},
-    stdstreams::{BufferedPrimaryIo, GenuineStdoutIo},
+    stdstreams::{BufferedPrimaryIo, BufferedStreamIo, GenuineStdoutIo},
     InputHandle, IoProvider, OpenResult, OutputHandle,
 };
 use which::which;
 
 use crate::{
-    ctry, errmsg,
+    ctry,
+    engines::tex::{load_shaping_cache, save_shaping_cache, MissingCharacters},
+    errmsg,
     errors::{ChainErrCompatExt, ErrorKind, Result},
+    generate::{self, GeneratedInput, GeneratedInputRecord},
+    inspect::{self, Contributor, ContributorKind, SizeBreakdown},
     io::{
+        encoding::InputEncodingPolicy,
         format_cache::FormatCache,
         memory::{MemoryFileCollection, MemoryIo},
-        InputOrigin,
+        InputOrigin, SearchLayer,
     },
-    status::StatusBackend,
+    postprocess::{self, Artifact, HtmlOutputFilter, PostProcessStep},
+    scratch::{ScratchLocation, ScratchSpace},
+    status::{MissingCharacter, StatusBackend},
     tt_error, tt_note, tt_warning,
     unstable_opts::UnstableOptions,
-    BibtexEngine, Spx2HtmlEngine, TexEngine, TexOutcome, XdvipdfmxEngine,
+    BibtexEngine, BstCodeCache, MathFontOverrides, ShapingCache, ShapingCacheStats, Spx2HtmlEngine,
+    TexEngine, TexOutcome, XdvipdfmxEngine,
 };
 
 /// Different patterns with which files may have been accessed by the
@@ -99,6 +114,9 @@ struct FileSummary {
     /// written.
     pub write_digest: Option<DigestData>,
 
+    /// If this file was read from the filesystem, this is where it was found.
+    pub path: Option<PathBuf>,
+
     got_written_to_disk: bool,
 }
 
@@ -109,6 +127,7 @@ impl FileSummary {
             input_origin,
             read_digest: None,
             write_digest: None,
+            path: None,
             got_written_to_disk: false,
         }
     }
@@ -172,7 +191,7 @@ impl FromStr for PassSetting {
 }
 
 /// Different places from which the "primary input" might originate.
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+#[derive(Default)]
 enum PrimaryInputMode {
     /// This process's standard input.
     #[default]
@@ -183,6 +202,16 @@ enum PrimaryInputMode {
 
     /// An in-memory buffer.
     Buffer(Vec<u8>),
+
+    /// An arbitrary stream.
+    Stream(Box<dyn Read>),
+}
+
+/// Returns true if the path refers to something that exists, but isn't a
+/// regular file or a directory, such as a named pipe. Such inputs can't be
+/// seeked or read more than once.
+fn is_stream_path(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|md| !md.is_file() && !md.is_dir())
 }
 
 /// Different places where the output files might land.
@@ -200,6 +229,11 @@ enum OutputDestination {
     /// Files will not be written to disk. The code running the engine should
     /// examine the memory layer of the I/O stack to obtain the output files.
     Nowhere,
+
+    /// Files will not be written to disk, except that the final output file
+    /// is written to standard output. For HTML output, which consists of many
+    /// files, a tarball of them is written.
+    Stdout,
 }
 
 /// The subset of the driver state that is captured when running a C/C++ engine.
@@ -229,6 +263,9 @@ struct BridgeState {
     /// The main filesystem backing for input files in the project.
     filesystem: FilesystemIo,
 
+    /// Input files provided by the caller as streams.
+    streams: BufferedStreamIo,
+
     /// Extra paths we search through for files.
     extra_search_paths: Vec<FilesystemIo>,
 
@@ -253,6 +290,21 @@ struct BridgeState {
 
     /// The I/O events that occurred while processing.
     events: HashMap<String, FileSummary>,
+
+    /// The environment variables that the engines asked to read, and what
+    /// came of it.
+    env_reads: BTreeMap<String, EnvReadOutcome>,
+
+    /// The policy for normalizing the encodings of text inputs.
+    input_encoding: InputEncodingPolicy,
+
+    /// The graphics files that the engines searched for.
+    graphics_inputs: Vec<GraphicsInput>,
+
+    /// The scratch space of the current run, which holds the files that
+    /// must be on disk, such as those of shell-escape commands. This is only
+    /// set while a run is in progress.
+    scratch: Option<ScratchSpace>,
 }
 
 impl BridgeState {
@@ -270,6 +322,55 @@ impl BridgeState {
         self.format_primary = None;
     }
 
+    /// Get the scratch space of the current run.
+    fn scratch(&self) -> &ScratchSpace {
+        self.scratch
+            .as_ref()
+            .expect("the scratch space is only used during runs")
+    }
+
+    /// Apply the input encoding policy to a newly opened text input from the
+    /// filesystem. Inputs from other sources, such as the bundle, are left
+    /// alone. The primary input is always text, but its handle is unnamed.
+    fn normalize_input_encoding<T>(
+        &self,
+        r: OpenResult<(InputHandle, T)>,
+        status: &mut dyn StatusBackend,
+    ) -> OpenResult<(InputHandle, T)> {
+        let (mut ih, extra) = match r {
+            OpenResult::Ok((ih, extra))
+                if ih.origin() == InputOrigin::Filesystem
+                    && (ih.name().is_empty() || self.input_encoding.applies_to(ih.name())) =>
+            {
+                (ih, extra)
+            }
+            r => return r,
+        };
+
+        let mut data = Vec::new();
+
+        if let Err(e) = ih.read_to_end(&mut data) {
+            return OpenResult::Err(e.into());
+        }
+
+        let display_name = if ih.name().is_empty() {
+            "<primary input>"
+        } else {
+            ih.name()
+        };
+
+        let data = match self.input_encoding.normalize(display_name, &data, status) {
+            Ok(Some(normalized)) => normalized,
+            Ok(None) => data,
+            Err(e) => return OpenResult::Err(e),
+        };
+
+        OpenResult::Ok((
+            InputHandle::new(ih.name().to_owned(), Cursor::new(data), ih.origin()),
+            extra,
+        ))
+    }
+
     /// Invoke an external tool as a pass in the processing pipeline.
     fn external_tool_pass(
         &mut self,
@@ -301,7 +402,7 @@ impl BridgeState {
         // can actually use them.
 
         let tempdir = ctry!(
-            tempfile::Builder::new().tempdir();
+            self.scratch().tempdir("tool");
             "can't create temporary directory for external tool"
         );
 
@@ -356,6 +457,7 @@ impl BridgeState {
         // Now we can actually run the command.
 
         let output = cmd.current_dir(tempdir.path()).output()?;
+        self.scratch().check_usage()?;
 
         if let Some(0) = output.status.code() {
         } else {
@@ -457,6 +559,7 @@ macro_rules! bridgestate_ioprovider_cascade {
         bridgestate_ioprovider_try!($self.mem, $($inner)+);
 
         if use_fs {
+            bridgestate_ioprovider_try!($self.streams, $($inner)+);
             bridgestate_ioprovider_try!($self.filesystem, $($inner)+);
 
             // With this ordering, we are preventing files created by
@@ -549,19 +652,23 @@ impl IoProvider for BridgeState {
         let r = (|| {
             bridgestate_ioprovider_cascade!(self, input_open_name_with_abspath(name, status));
         })();
+        let r = self.normalize_input_encoding(r, status);
 
         match r {
-            OpenResult::Ok((ref ih, ref _path)) => {
+            OpenResult::Ok((ref ih, ref path)) => {
                 if let Some(summ) = self.events.get_mut(name) {
                     summ.access_pattern = match summ.access_pattern {
                         AccessPattern::Written => AccessPattern::WrittenThenRead,
                         c => c, // identity mapping makes sense for remaining options
                     };
+
+                    if summ.path.is_none() {
+                        summ.path.clone_from(path);
+                    }
                 } else {
-                    self.events.insert(
-                        name.to_owned(),
-                        FileSummary::new(AccessPattern::Read, ih.origin()),
-                    );
+                    let mut fs = FileSummary::new(AccessPattern::Read, ih.origin());
+                    fs.path.clone_from(path);
+                    self.events.insert(name.to_owned(), fs);
                 }
             }
 
@@ -605,7 +712,10 @@ impl IoProvider for BridgeState {
         &mut self,
         status: &mut dyn StatusBackend,
     ) -> OpenResult<(InputHandle, Option<PathBuf>)> {
-        bridgestate_ioprovider_cascade!(self, input_open_primary_with_abspath(status));
+        let r = (|| {
+            bridgestate_ioprovider_cascade!(self, input_open_primary_with_abspath(status));
+        })();
+        self.normalize_input_encoding(r, status)
     }
 
     fn input_open_format(
@@ -633,6 +743,39 @@ impl IoProvider for BridgeState {
 
         r
     }
+
+    fn describe_search(&mut self, status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        // This follows the order of `bridgestate_ioprovider_cascade`.
+        let mut layers = self.mem.describe_search(status);
+
+        if self.format_primary.is_none() {
+            layers.extend(self.streams.describe_search(status));
+            layers.extend(self.filesystem.describe_search(status));
+
+            if let Some(ref mut p) = self.shell_escape_work {
+                for mut layer in p.describe_search(status) {
+                    layer
+                        .description
+                        .push_str(" (the shell-escape working directory)");
+                    layers.push(layer);
+                }
+            }
+
+            for fsio in self.extra_search_paths.iter_mut() {
+                for mut layer in fsio.describe_search(status) {
+                    layer.description.push_str(" (an extra search path)");
+                    layers.push(layer);
+                }
+            }
+        }
+
+        layers.push(SearchLayer {
+            description: "the support bundle".to_owned(),
+            names: self.bundle.all_files(),
+        });
+
+        layers
+    }
 }
 
 impl DriverHooks for BridgeState {
@@ -667,6 +810,37 @@ impl DriverHooks for BridgeState {
         }
     }
 
+    fn event_env_read(&mut self, name: &str, outcome: EnvReadOutcome) {
+        self.env_reads.insert(name.to_owned(), outcome);
+    }
+
+    fn event_input_searched(&mut self, search: &InputSearch<'_>) {
+        if !matches!(search.format, FileFormat::Pict) {
+            return;
+        }
+
+        // The same graphic is usually searched for several times: by TeX to
+        // get its size, and again by xdvipdfmx to embed it. Keep the first
+        // successful search.
+
+        let input = GraphicsInput {
+            requested: search.requested.to_owned(),
+            tried: search.tried.to_vec(),
+            resolved: search.resolved.map(|s| s.to_owned()),
+            path: search.path.map(|p| p.to_owned()),
+        };
+
+        match self
+            .graphics_inputs
+            .iter_mut()
+            .find(|g| g.requested == input.requested)
+        {
+            Some(g) if g.resolved.is_none() => *g = input,
+            Some(_) => {}
+            None => self.graphics_inputs.push(input),
+        }
+    }
+
     fn sysrq_shell_escape(
         &mut self,
         command: &str,
@@ -757,6 +931,51 @@ impl DriverHooks for BridgeState {
     }
 }
 
+/// A graphics file that an engine searched for during a processing session,
+/// as with `\includegraphics`.
+///
+/// See [`ProcessingSession::graphics_inputs`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GraphicsInput {
+    /// The name that the document asked for.
+    pub requested: String,
+
+    /// The names that were tried, in order: the requested name itself, and
+    /// then the name with each of the graphics extensions added.
+    pub tried: Vec<String>,
+
+    /// The name under which the file was found, if it was.
+    pub resolved: Option<String>,
+
+    /// Where the file was found on the filesystem, if it was found there
+    /// rather than in the bundle or in memory.
+    pub path: Option<PathBuf>,
+}
+
+/// A record of one run of an engine during a processing session.
+///
+/// See [`ProcessingSession::engine_runs`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EngineRun {
+    /// The name of the engine, such as `"TeX"` or `"BibTeX"`.
+    pub engine: &'static str,
+
+    /// How long the run took.
+    pub duration: Duration,
+
+    /// Whether the run succeeded. Runs that issued warnings, or errors that
+    /// were ignored, count as successes.
+    pub succeeded: bool,
+
+    /// The number of warnings that the run issued, if the engine counts
+    /// them. Only BibTeX does.
+    pub warnings: Option<u32>,
+
+    /// The number of errors that the run issued and that were ignored, if
+    /// the engine counts them. Only BibTeX does.
+    pub errors: Option<u32>,
+}
+
 /// Possible modes for handling shell-escape functionality
 #[derive(Clone, Debug, Default, Eq, PartialEq)]
 enum ShellEscapeMode {
@@ -799,6 +1018,7 @@ struct ExternalToolPass {
 pub struct ProcessingSessionBuilder {
     security: SecuritySettings,
     primary_input: PrimaryInputMode,
+    input_streams: BufferedStreamIo,
     tex_input_name: Option<String>,
     output_dest: OutputDestination,
     filesystem_root: Option<PathBuf>,
@@ -809,18 +1029,36 @@ pub struct ProcessingSessionBuilder {
     hidden_input_paths: HashSet<PathBuf>,
     pass: PassSetting,
     reruns: Option<usize>,
+    draft: bool,
+    page_spec: Option<String>,
     print_stdout: bool,
     bundle: Option<Box<dyn Bundle>>,
     keep_intermediates: bool,
     keep_logs: bool,
     synctex: bool,
+    math_font_overrides: MathFontOverrides,
+    input_encoding: InputEncodingPolicy,
     build_date: Option<SystemTime>,
     unstables: UnstableOptions,
     shell_escape_mode: ShellEscapeMode,
     html_assets_spec_path: Option<String>,
+    html_accessibility_report_path: Option<String>,
+    html_social_metadata: Option<SocialMetadata>,
+    html_preview_image: Option<PreviewImage>,
+    html_math_rendering: MathRendering,
+    html_heading_numbers: HeadingNumbers,
+    html_toc_depth: Option<u8>,
     html_precomputed_assets: Option<AssetSpecification>,
     html_do_not_emit_files: bool,
     html_do_not_emit_assets: bool,
+    post_processing: Vec<PostProcessStep>,
+    generated_inputs: Vec<GeneratedInput>,
+    generator_cache_dir: Option<PathBuf>,
+    generated_inputs_record_path: Option<PathBuf>,
+    max_output_size: Option<u64>,
+    scratch_location: Option<ScratchLocation>,
+    scratch_limit: Option<u64>,
+    hermetic: bool,
 }
 
 impl ProcessingSessionBuilder {
@@ -849,6 +1087,28 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Sets the primary input to be read from a caller-specified stream.
+    ///
+    /// The stream need not be seekable. Since the engines will generally need
+    /// to make multiple passes over the input, it is read into memory in its
+    /// entirety when the session is created.
+    pub fn primary_input_stream<R: Read + 'static>(&mut self, stream: R) -> &mut Self {
+        self.primary_input = PrimaryInputMode::Stream(Box::new(stream));
+        self
+    }
+
+    /// Provides the input file with the given name by reading it from a
+    /// caller-specified stream.
+    ///
+    /// The stream need not be seekable. It is read into memory in its entirety
+    /// the first time that the file is opened. Files provided this way take
+    /// precedence over files on the filesystem, but not over files created
+    /// during processing.
+    pub fn input_stream<R: Read + 'static>(&mut self, name: &str, stream: R) -> &mut Self {
+        self.input_streams.add_stream(name, stream);
+        self
+    }
+
     /// Sets the name of the main input file.
     ///
     /// This value will be used to infer the names of the output files; for example, if
@@ -890,6 +1150,19 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Indicate that the final output should be streamed to standard output.
+    ///
+    /// No output files will be written to disk. Instead, once processing
+    /// succeeds, the main output file -- the PDF, for instance -- is written
+    /// to standard output. For HTML output, a tarball of all of the output
+    /// files is written. In this mode, any engine output requested with
+    /// [`Self::print_stdout`] is sent to standard error. Streaming `.fmt`
+    /// output is not supported.
+    pub fn output_to_stdout(&mut self) -> &mut Self {
+        self.output_dest = OutputDestination::Stdout;
+        self
+    }
+
     /// The name of the `.fmt` file used to initialize the TeX engine.
     ///
     /// This file does not necessarily have to exist already; it will be created
@@ -939,6 +1212,33 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// If set to `true`, run a "draft" build that trades output fidelity for
+    /// speed.
+    ///
+    /// Unless [`Self::reruns`] is also set, the TeX engine is re-run only
+    /// once. The `xdvipdfmx` engine runs in draft mode, in which images are
+    /// not decoded and the PDF output is not compressed. Finally, the `.bbl`
+    /// files produced by BibTeX are saved in the output directory along with
+    /// a digest of their inputs, and later draft builds reuse them instead of
+    /// running BibTeX if the citations, databases, and styles are unchanged.
+    pub fn draft(&mut self, d: bool) -> &mut Self {
+        self.draft = d;
+        self
+    }
+
+    /// Only put some of the pages of the document into the PDF output.
+    ///
+    /// The document is still typeset in full, but the `xdvipdfmx` engine skips
+    /// the pages that aren't selected, which makes for much faster previews of
+    /// parts of long documents. The specification is a comma-separated list of
+    /// pages and ranges of pages, such as `12-20` or `1,5-`, numbered from 1 in
+    /// order of output. See [`XdvipdfmxEngine::page_spec`] for details. This
+    /// has no effect unless the output format is PDF.
+    pub fn pages(&mut self, spec: impl Into<String>) -> &mut Self {
+        self.page_spec = Some(spec.into());
+        self
+    }
+
     /// If set to `true`, stdout from the TeX engine will be forwarded to actual stdout. (By
     /// default, it will be suppressed.)
     pub fn print_stdout(&mut self, p: bool) -> &mut Self {
@@ -978,6 +1278,24 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Sets overrides for the parameters of OpenType math fonts.
+    /// See `TexEngine::math_font_overrides` for more information.
+    pub fn math_font_overrides(&mut self, overrides: MathFontOverrides) -> &mut Self {
+        self.math_font_overrides = overrides;
+        self
+    }
+
+    /// Sets the policy for normalizing the encodings of text inputs.
+    ///
+    /// Text files read from the filesystem are sniffed as they're opened, so
+    /// that files in encodings other than UTF-8 are transcoded or rejected
+    /// before the engines see them. See [`InputEncodingPolicy`] for the
+    /// defaults.
+    pub fn input_encoding(&mut self, policy: InputEncodingPolicy) -> &mut Self {
+        self.input_encoding = policy;
+        self
+    }
+
     /// Sets the date and time of the processing session.
     /// See `TexEngine::build_date` for mor information.
     pub fn build_date(&mut self, date: SystemTime) -> &mut Self {
@@ -1064,6 +1382,72 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// In HTML mode, write a report of accessibility gaps in the outputs.
+    ///
+    /// Each HTML output file is audited after it's created, and the gaps found,
+    /// such as images without alternative text or headings that skip levels,
+    /// are listed in a text report written to the given output path. If no
+    /// gaps are found, the report is empty and so isn't saved.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_accessibility_report_path<S: ToString>(&mut self, path: S) -> &mut Self {
+        self.html_accessibility_report_path = Some(path.to_string());
+        self
+    }
+
+    /// In HTML mode, describe the document for previews on social media.
+    ///
+    /// The OpenGraph and Twitter `<meta>` tags for each output file are made
+    /// available to the HTML templates. See
+    /// [`Spx2HtmlEngine::social_metadata`] for details.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_social_metadata(&mut self, metadata: SocialMetadata) -> &mut Self {
+        self.html_social_metadata = Some(metadata);
+        self
+    }
+
+    /// In HTML mode, render a preview image for social media.
+    ///
+    /// See [`Spx2HtmlEngine::preview_image`] for details.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_preview_image(&mut self, preview: PreviewImage) -> &mut Self {
+        self.html_preview_image = Some(preview);
+        self
+    }
+
+    /// In HTML mode, choose how math is rendered.
+    ///
+    /// See [`Spx2HtmlEngine::math_rendering`] for details.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_math_rendering(&mut self, mode: MathRendering) -> &mut Self {
+        self.html_math_rendering = mode;
+        self
+    }
+
+    /// In HTML mode, choose what happens to the section numbers in headings.
+    ///
+    /// See [`Spx2HtmlEngine::heading_numbers`] for details.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_heading_numbers(&mut self, mode: HeadingNumbers) -> &mut Self {
+        self.html_heading_numbers = mode;
+        self
+    }
+
+    /// In HTML mode, limit the generated tables of contents to headings of
+    /// the given level or above.
+    ///
+    /// See [`Spx2HtmlEngine::toc_depth`] for details.
+    ///
+    /// If the build does not use HTML mode, this setting has no effect.
+    pub fn html_toc_depth(&mut self, depth: u8) -> &mut Self {
+        self.html_toc_depth = Some(depth);
+        self
+    }
+
     /// In HTML mode, use a precomputed asset specification.
     ///
     /// "Assets" are files like fonts and images that accompany the HTML output
@@ -1107,8 +1491,120 @@ impl ProcessingSessionBuilder {
         self
     }
 
+    /// Add a step of post-processing to be applied to the outputs.
+    ///
+    /// Steps are run in the order that they're added, after the engines have
+    /// finished and before the outputs are written. Steps that run external
+    /// programs are ignored if the security settings disallow them.
+    pub fn post_process(&mut self, step: PostProcessStep) -> &mut Self {
+        self.post_processing.push(step);
+        self
+    }
+
+    /// Add an input file whose contents are produced by a generator.
+    ///
+    /// The generators are run at the start of each run of the session, and
+    /// their outputs can be read by the engines under the names of the inputs,
+    /// or under those names prefixed with `|`, as with piped input. Like
+    /// input streams, they take precedence over files on the filesystem.
+    /// Inputs with external generators are ignored if the security settings
+    /// disallow them.
+    pub fn generated_input(&mut self, input: GeneratedInput) -> &mut Self {
+        self.generated_inputs.push(input);
+        self
+    }
+
+    /// Set the directory in which the outputs of generators are cached.
+    ///
+    /// If unset, as it is by default, generators are run on every build.
+    pub fn generator_cache_dir<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.generator_cache_dir = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// If set, a record of where the contents of each generated input came
+    /// from will be written out at the given path.
+    ///
+    /// See [`ProcessingSession::generated_inputs`].
+    pub fn generated_inputs_record_path<P: AsRef<Path>>(&mut self, p: P) -> &mut Self {
+        self.generated_inputs_record_path = Some(p.as_ref().to_owned());
+        self
+    }
+
+    /// Set the largest size, in bytes, that the main output may have.
+    ///
+    /// For HTML outputs, this is the largest total size of the files that the
+    /// engine emits. If the output is larger, the run fails with a breakdown
+    /// of what takes up the space, as computed by [`crate::inspect`]. Other
+    /// outputs are checked after post-processing, and aren't written if
+    /// they're over budget, but HTML outputs are written by the engine as it
+    /// goes, so they're left in place.
+    ///
+    /// If unset, as it is by default, outputs may be of any size.
+    pub fn max_output_size(&mut self, bytes: u64) -> &mut Self {
+        self.max_output_size = Some(bytes);
+        self
+    }
+
+    /// Set where the scratch space of each run is created.
+    ///
+    /// Files that have to be on disk while the session runs, such as the
+    /// working directories of external tools and of shell-escape commands
+    /// (unless one is given with
+    /// [`Self::shell_escape_with_work_dir`]), are kept in a temporary
+    /// directory created here, which is deleted when the run ends, even if it
+    /// fails. This overrides the [`UnstableOptions`]; the default is the
+    /// system's temporary directory.
+    pub fn scratch_location(&mut self, location: ScratchLocation) -> &mut Self {
+        self.scratch_location = Some(location);
+        self
+    }
+
+    /// Set the most space, in bytes, that the files in the scratch space may
+    /// take up.
+    ///
+    /// The space used is checked after each engine pass and external tool
+    /// that may have written to it, and the run fails if it's over the limit.
+    /// This overrides the [`UnstableOptions`]; by default, there's no limit.
+    pub fn scratch_limit(&mut self, bytes: u64) -> &mut Self {
+        self.scratch_limit = Some(bytes);
+        self
+    }
+
+    /// Make the session hermetic, as is wanted for release builds whose
+    /// inputs must all be accounted for.
+    ///
+    /// A hermetic session doesn't touch the network, and its filesystem root
+    /// and extra search paths are [confined](FilesystemIo::confine), so that
+    /// the only files that it can read are the ones under them, in the
+    /// bundle, and in the format cache. Anything else that the engines try to
+    /// access is treated as missing and recorded, and the run fails once it's
+    /// done with a report of the attempts. External programs could do
+    /// anything, so creating the session fails if shell-escape, external
+    /// generators, or external post-processors are enabled, and running it
+    /// fails if biber is needed.
+    ///
+    /// The bundle is set up before the session is, so it's up to the caller
+    /// to make sure that it only uses cached files.
+    pub fn hermetic(&mut self, hermetic: bool) -> &mut Self {
+        self.hermetic = hermetic;
+        self
+    }
+
     /// Creates a `ProcessingSession`.
     pub fn create(self, status: &mut dyn StatusBackend) -> Result<ProcessingSession> {
+        if let Some(spec) = self.page_spec.as_deref() {
+            crate::engines::xdvipdfmx::validate_page_spec(spec)?;
+
+            if self.output_format != OutputFormat::Pdf {
+                tt_warning!(
+                    status,
+                    "page selection `{}` ignored since the output format isn't PDF",
+                    spec
+                );
+            }
+        }
+
         // First, work on the "bridge state", which gathers the subset of our
         // state that has to be held in a mutable reference while running the
         // C/C++ engines:
@@ -1118,6 +1614,21 @@ impl ProcessingSessionBuilder {
         let mut filesystem_root = self.filesystem_root.unwrap_or_default();
 
         let (pio, primary_input_path, default_output_path) = match self.primary_input {
+            PrimaryInputMode::Path(p) if is_stream_path(&p) => {
+                // Named pipes, including the paths created by a shell's
+                // process substitution, can only be read once. Buffer them
+                // like stdin. Their parent directories are meaningless, so the
+                // filesystem root and output location default the same way,
+                // too.
+                let mut f = ctry!(File::open(&p); "error opening input \"{}\"", p.display());
+                let pio = ctry!(
+                    BufferedPrimaryIo::from_stream(&mut f);
+                    "error reading input \"{}\"", p.display()
+                );
+                let pio: Box<dyn IoProvider> = Box::new(pio);
+                (pio, None, "".into())
+            }
+
             PrimaryInputMode::Path(p) => {
                 // Set the filesystem root (that's the directory we'll search
                 // for files in) to be the same directory as the main input
@@ -1155,17 +1666,36 @@ impl ProcessingSessionBuilder {
                 let pio: Box<dyn IoProvider> = Box::new(BufferedPrimaryIo::from_buffer(buf));
                 (pio, None, "".into())
             }
+
+            PrimaryInputMode::Stream(mut stream) => {
+                // Same behavior as with stdin.
+                let pio = ctry!(
+                    BufferedPrimaryIo::from_stream(&mut stream);
+                    "error reading the primary input stream"
+                );
+                let pio: Box<dyn IoProvider> = Box::new(pio);
+                (pio, None, "".into())
+            }
         };
 
         let format_cache_path = self
             .format_cache_path
             .unwrap_or_else(|| filesystem_root.clone());
-        let format_cache = FormatCache::new(bundle.get_digest()?, format_cache_path);
+        let format_cache = FormatCache::new(
+            bundle.get_digest().chain_err(|| ErrorKind::BundleError)?,
+            format_cache_path,
+        );
 
-        let genuine_stdout = if self.print_stdout {
-            Some(GenuineStdoutIo::new())
-        } else {
-            None
+        let stream_output = self.output_dest == OutputDestination::Stdout;
+
+        if stream_output && self.output_format == OutputFormat::Format {
+            return Err(errmsg!("format files can't be written to standard output"));
+        }
+
+        let genuine_stdout = match (self.print_stdout, stream_output) {
+            (false, _) => None,
+            (true, false) => Some(GenuineStdoutIo::new()),
+            (true, true) => Some(GenuineStdoutIo::new_stderr()),
         };
 
         // move this out of self to get around borrow checker issues
@@ -1175,7 +1705,11 @@ impl ProcessingSessionBuilder {
             self.unstables
                 .extra_search_paths
                 .iter()
-                .map(|p| FilesystemIo::new(p, false, false, hidden_input_paths.clone()))
+                .map(|p| {
+                    let mut fsio = FilesystemIo::new(p, false, false, hidden_input_paths.clone());
+                    fsio.confine(self.hermetic);
+                    fsio
+                })
                 .collect()
         } else {
             if !self.unstables.extra_search_paths.is_empty() {
@@ -1184,7 +1718,72 @@ impl ProcessingSessionBuilder {
             Vec::new()
         };
 
-        let filesystem = FilesystemIo::new(&filesystem_root, false, true, hidden_input_paths);
+        let mut filesystem = FilesystemIo::new(&filesystem_root, false, true, hidden_input_paths);
+        filesystem.confine(self.hermetic);
+
+        if self.hermetic {
+            if let Some(step) = self
+                .post_processing
+                .iter()
+                .find(|step| step.processor().is_external())
+            {
+                return Err(errmsg!(
+                    "post-processor `{}` can't be used in a hermetic build",
+                    step.processor().name()
+                ));
+            }
+
+            if let Some(input) = self
+                .generated_inputs
+                .iter()
+                .find(|input| input.generator().is_external())
+            {
+                return Err(errmsg!(
+                    "generator `{}` of input `{}` can't be used in a hermetic build",
+                    input.generator().name(),
+                    input.name()
+                ));
+            }
+        }
+
+        let post_processing = if self.security.allow_external_post_processors() {
+            self.post_processing
+        } else {
+            let (allowed, external): (Vec<_>, Vec<_>) = self
+                .post_processing
+                .into_iter()
+                .partition(|step| !step.processor().is_external());
+
+            for step in &external {
+                tt_warning!(
+                    status,
+                    "post-processor `{}` ignored due to security",
+                    step.processor().name()
+                );
+            }
+
+            allowed
+        };
+
+        let generated_inputs = if self.security.allow_external_generators() {
+            self.generated_inputs
+        } else {
+            let (allowed, external): (Vec<_>, Vec<_>) = self
+                .generated_inputs
+                .into_iter()
+                .partition(|input| !input.generator().is_external());
+
+            for input in &external {
+                tt_warning!(
+                    status,
+                    "generator `{}` of input `{}` ignored due to security",
+                    input.generator().name(),
+                    input.name()
+                );
+            }
+
+            allowed
+        };
 
         let mem = MemoryIo::new(true);
 
@@ -1192,6 +1791,7 @@ impl ProcessingSessionBuilder {
             primary_input: pio,
             mem,
             filesystem,
+            streams: self.input_streams,
             extra_search_paths,
             shell_escape_work: None,
             format_cache,
@@ -1199,6 +1799,10 @@ impl ProcessingSessionBuilder {
             genuine_stdout,
             format_primary: None,
             events: HashMap::new(),
+            input_encoding: self.input_encoding,
+            env_reads: BTreeMap::new(),
+            graphics_inputs: Vec::new(),
+            scratch: None,
         };
 
         // Now we can do the rest.
@@ -1206,7 +1810,7 @@ impl ProcessingSessionBuilder {
         let output_path = match self.output_dest {
             OutputDestination::Default => Some(default_output_path),
             OutputDestination::Path(p) => Some(p),
-            OutputDestination::Nowhere => None,
+            OutputDestination::Nowhere | OutputDestination::Stdout => None,
         };
 
         let tex_input_name = self
@@ -1241,6 +1845,15 @@ impl ProcessingSessionBuilder {
             }
         };
 
+        if self.hermetic && shell_escape_mode != ShellEscapeMode::Disabled {
+            return Err(errmsg!("shell-escape can't be used in a hermetic build"));
+        }
+
+        let scratch_location = self
+            .scratch_location
+            .unwrap_or_else(|| self.unstables.scratch_location.clone());
+        let scratch_limit = self.scratch_limit.or(self.unstables.scratch_limit);
+
         Ok(ProcessingSession {
             security: self.security,
             bs,
@@ -1254,17 +1867,43 @@ impl ProcessingSessionBuilder {
             output_format: self.output_format,
             makefile_output_path: self.makefile_output_path,
             output_path,
-            tex_rerun_specification: self.reruns,
+            stream_output,
+            tex_rerun_specification: self.reruns.or(self.draft.then_some(DRAFT_TEX_RERUNS)),
+            draft: self.draft,
+            page_spec: self.page_spec,
             keep_intermediates: self.keep_intermediates,
             keep_logs: self.keep_logs,
             synctex_enabled: self.synctex,
+            math_font_overrides: self.math_font_overrides,
             build_date: self.build_date.unwrap_or(SystemTime::UNIX_EPOCH),
             unstables: self.unstables,
             shell_escape_mode,
             html_assets_spec_path: self.html_assets_spec_path,
+            html_accessibility_report_path: self.html_accessibility_report_path,
+            html_social_metadata: self.html_social_metadata,
+            html_preview_image: self.html_preview_image,
+            html_math_rendering: self.html_math_rendering,
+            html_heading_numbers: self.html_heading_numbers,
+            html_toc_depth: self.html_toc_depth,
             html_precomputed_assets: self.html_precomputed_assets,
             html_emit_files: !self.html_do_not_emit_files,
             html_emit_assets: !self.html_do_not_emit_assets,
+            html_references: References::new(),
+            shaping_cache_primed: false,
+            shaping_cache_stats: ShapingCacheStats::default(),
+            missing_characters: Vec::new(),
+            missing_character_count: 0,
+            engine_runs: Vec::new(),
+            post_processing,
+            generated_inputs,
+            generator_cache_dir: self.generator_cache_dir,
+            generated_inputs_record_path: self.generated_inputs_record_path,
+            generated: Vec::new(),
+            max_output_size: self.max_output_size,
+            html_output_sizes: Rc::new(RefCell::new(Vec::new())),
+            scratch_location,
+            scratch_limit,
+            hermetic: self.hermetic,
         })
     }
 }
@@ -1316,12 +1955,19 @@ pub struct ProcessingSession {
     /// to the output files.
     output_path: Option<PathBuf>,
 
+    /// If true, the final output is written to standard output once
+    /// processing succeeds. `output_path` is None in this case.
+    stream_output: bool,
+
     pass: PassSetting,
     output_format: OutputFormat,
     tex_rerun_specification: Option<usize>,
+    draft: bool,
+    page_spec: Option<String>,
     keep_intermediates: bool,
     keep_logs: bool,
     synctex_enabled: bool,
+    math_font_overrides: MathFontOverrides,
 
     /// See `TexEngine::with_date` and `XdvipdfmxEngine::with_date`.
     build_date: SystemTime,
@@ -1333,12 +1979,79 @@ pub struct ProcessingSession {
     shell_escape_mode: ShellEscapeMode,
 
     html_assets_spec_path: Option<String>,
+    html_accessibility_report_path: Option<String>,
+    html_social_metadata: Option<SocialMetadata>,
+    html_preview_image: Option<PreviewImage>,
+    html_math_rendering: MathRendering,
+    html_heading_numbers: HeadingNumbers,
+    html_toc_depth: Option<u8>,
     html_precomputed_assets: Option<AssetSpecification>,
     html_emit_files: bool,
     html_emit_assets: bool,
+
+    /// The formatted bibliography entries produced by the BibTeX passes, used
+    /// for HTML citation popovers.
+    html_references: References,
+
+    /// Whether a TeX pass of the current run has already filled the shaping
+    /// cache, so that later passes can reuse it.
+    shaping_cache_primed: bool,
+
+    /// Shaping cache counters, summed over the TeX passes of the current run.
+    shaping_cache_stats: ShapingCacheStats,
+
+    /// The characters that the most recent TeX pass couldn't find in its
+    /// fonts, grouped by font. Earlier passes will generally have been
+    /// missing the same ones, so only the last pass is reported.
+    missing_characters: Vec<MissingCharacters>,
+
+    /// How many times the most recent TeX pass was missing a character,
+    /// including any that weren't recorded.
+    missing_character_count: usize,
+
+    /// The engines run so far during the current run, in order.
+    engine_runs: Vec<EngineRun>,
+
+    /// The steps of post-processing applied to the outputs.
+    post_processing: Vec<PostProcessStep>,
+
+    /// The input files produced by generators.
+    generated_inputs: Vec<GeneratedInput>,
+
+    /// Where the outputs of generators are cached, if anywhere.
+    generator_cache_dir: Option<PathBuf>,
+
+    /// If set, where to write the record of the generated inputs.
+    generated_inputs_record_path: Option<PathBuf>,
+
+    /// Where the contents of each generated input came from during the
+    /// current run.
+    generated: Vec<GeneratedInputRecord>,
+
+    /// The largest size that the main output may have, if limited.
+    max_output_size: Option<u64>,
+
+    /// The names and sizes of the files emitted by the HTML engine during
+    /// the current run, if its outputs are being measured.
+    html_output_sizes: Rc<RefCell<Vec<(String, u64)>>>,
+
+    /// Where the scratch space of each run is created.
+    scratch_location: ScratchLocation,
+
+    /// The most space that the scratch space of a run may use, if limited.
+    scratch_limit: Option<u64>,
+
+    /// Whether runs are hermetic; see [`ProcessingSessionBuilder::hermetic`].
+    hermetic: bool,
 }
 
 const DEFAULT_MAX_TEX_PASSES: usize = 6;
+const DRAFT_TEX_RERUNS: usize = 1;
+
+/// In draft mode, the `.bbl` file produced from each `.aux` file is saved
+/// alongside a file with this extension, which holds the digest of the inputs
+/// that produced it.
+const DRAFT_BBL_STAMP_EXTENSION: &str = "bbl.inputs";
 const ALWAYS_INTERMEDIATE_EXTENSIONS: &[&str] = &[
     ".snm", ".toc", // generated by Beamer
 ];
@@ -1412,97 +2125,215 @@ impl ProcessingSession {
     /// - repeat the last two steps as often as needed
     /// - write the output files to disk, including a Makefile if it was requested.
     pub fn run(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
-        // Pre-invocation setup that requires cleanup even if the processing errors out.
-
-        let (shell_escape_work, clean_up_shell_escape) = match self.shell_escape_mode {
-            ShellEscapeMode::Disabled => (None, false),
-
-            ShellEscapeMode::ExternallyManagedDir(ref p) => (
-                Some(FilesystemIo::new(p, false, false, HashSet::new())),
-                false,
-            ),
+        // Pre-invocation setup that requires cleanup even if the processing
+        // errors out. The scratch space is deleted when it's dropped, so it
+        // doesn't outlive the run even if an engine panics.
+
+        let project_dir = self
+            .output_path
+            .clone()
+            .unwrap_or_else(|| self.bs.filesystem.root().to_owned());
+        let scratch =
+            ScratchSpace::create(&self.scratch_location, &project_dir, self.scratch_limit)?;
+
+        let shell_escape_work = match self.shell_escape_mode {
+            ShellEscapeMode::Disabled => None,
+
+            ShellEscapeMode::ExternallyManagedDir(ref p) => {
+                Some(FilesystemIo::new(p, false, false, HashSet::new()))
+            }
 
             ShellEscapeMode::TempDir => {
-                let tempdir = ctry!(tempfile::Builder::new().tempdir(); "can't create temporary directory for shell-escape work");
-                (
-                    Some(FilesystemIo::new(
-                        &tempdir.keep(),
-                        false,
-                        false,
-                        HashSet::new(),
-                    )),
-                    true,
-                )
+                // This is deleted along with the rest of the scratch space.
+                let tempdir = ctry!(scratch.tempdir("shell-escape"); "can't create temporary directory for shell-escape work");
+                Some(FilesystemIo::new(
+                    &tempdir.keep(),
+                    false,
+                    false,
+                    HashSet::new(),
+                ))
             }
 
             ShellEscapeMode::Defaulted => unreachable!(),
         };
 
         self.bs.shell_escape_work = shell_escape_work;
+        self.bs.scratch = Some(scratch);
 
         // Go-time!
+        let network_was_forbidden = tectonic_geturl::network_forbidden();
+        tectonic_geturl::forbid_network(network_was_forbidden || self.hermetic);
         let result = self.run_inner(status);
+        tectonic_geturl::forbid_network(network_was_forbidden);
 
         // Do that cleanup.
 
-        if clean_up_shell_escape {
-            let shell_escape_work = self.bs.shell_escape_work.take().unwrap();
-            let shell_escape_err = std::fs::remove_dir_all(shell_escape_work.root());
+        self.bs.shell_escape_work = None;
 
-            if let Err(e) = shell_escape_err {
-                tt_warning!(status, "an error occurred while cleaning up the \
-                    shell-escape temporary directory `{}`", shell_escape_work.root().display(); e.into());
+        if let Some(scratch) = self.bs.scratch.take() {
+            if let Err(e) = scratch.close() {
+                tt_warning!(status, "an error occurred while cleaning up the scratch space"; e);
             }
         }
 
+        // A forbidden access can make the run fail in some roundabout way, so
+        // the attempts are reported regardless, but the original error takes
+        // precedence.
+        if self.hermetic {
+            let check = self.check_hermeticity(status);
+            return result.and(check);
+        }
+
         // Propagate the actual result.
         result
     }
 
+    /// Report everything that a hermetic run tried to access outside of its
+    /// bounds, failing if there was anything.
+    fn check_hermeticity(&self, status: &mut dyn StatusBackend) -> Result<()> {
+        let mut attempts: Vec<String> = tectonic_geturl::take_forbidden_requests()
+            .into_iter()
+            .map(|url| format!("the network resource `{url}`"))
+            .collect();
+
+        let providers = std::iter::once(&self.bs.filesystem).chain(&self.bs.extra_search_paths);
+
+        for path in providers.flat_map(|fsio| fsio.forbidden_paths()) {
+            let attempt = format!("the file `{}`", path.display());
+
+            if !attempts.contains(&attempt) {
+                attempts.push(attempt);
+            }
+        }
+
+        for attempt in &attempts {
+            tt_error!(status, "the hermetic build tried to access {}", attempt);
+        }
+
+        if attempts.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrorKind::HermeticityViolation(attempts.len()).into())
+        }
+    }
+
     /// The bulk of the `run` implementation. We need to wrap it to manage the
     /// lifecycle of resources like the shell-escape temporary directory, if
     /// needed.
     fn run_inner(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
-        // Do we need to generate the format file?
+        self.shaping_cache_primed = false;
+        self.shaping_cache_stats = ShapingCacheStats::default();
+        self.missing_characters.clear();
+        self.missing_character_count = 0;
+        self.engine_runs.clear();
+        self.bs.env_reads.clear();
+        self.bs.graphics_inputs.clear();
+        self.run_generators(status)?;
+        self.ensure_format(status)?;
 
-        let generate_format = if self.output_format == OutputFormat::Format {
-            false
-        } else {
-            match self.bs.input_open_format(&self.format_name, status) {
-                OpenResult::Ok(_) => false,
-                OpenResult::NotAvailable => true,
-                OpenResult::Err(e) => {
-                    return Err(e)
-                        .chain_err(|| format!("could not open format file {}", self.format_name));
-                }
-            }
-        };
+        // This has to come after the format pass, which empties the shaping
+        // cache.
 
-        if generate_format {
-            tt_note!(status, "generating format \"{}\"", self.format_name);
-            self.make_format_pass(status)?;
-        }
+        let mut shaping_cache_loaded = self.load_shaping_cache(status);
 
         // Do the meat of the work.
 
-        let result = match self.pass {
-            PassSetting::Tex => match self.tex_pass(None, status) {
-                Ok(Some(warnings)) => {
-                    tt_warning!(status, "{}", warnings);
-                    Ok(0)
-                }
-                Ok(None) => Ok(0),
-                Err(e) => Err(e),
-            },
-            PassSetting::Default => self.default_pass(false, status),
-            PassSetting::BibtexFirst => self.default_pass(true, status),
+        let mut result = self.main_pass(status);
+
+        // A cached format can pass the checks made when it's opened and
+        // still fail to load. Regenerate it and try again, once.
+        let bad_format = match result {
+            Err(ref e) => e.engine_abort_kind() == Some(EngineAbortKind::BadFormat),
+            Ok(_) => false,
         };
 
+        if bad_format
+            && self.output_format != OutputFormat::Format
+            && self.bs.format_cache.invalidate_format(&self.format_name)?
+        {
+            tt_warning!(
+                status,
+                "the engine couldn't load the cached format \"{}\"; removed it and trying again",
+                self.format_name
+            );
+            self.ensure_format(status)?;
+            shaping_cache_loaded = self.load_shaping_cache(status);
+            result = self.main_pass(status);
+        }
+
+        let shaping_cache_saved = self.save_shaping_cache(status);
+
+        if self.unstables.shaping_cache {
+            let stats = self.shaping_cache_stats;
+            let total = stats.hits + stats.misses;
+
+            if total > 0 {
+                tt_note!(
+                    status,
+                    "shaping cache: {} hits, {} misses ({:.1}% hit rate)",
+                    stats.hits,
+                    stats.misses,
+                    100. * stats.hits as f64 / total as f64
+                );
+            }
+
+            if stats.evictions > 0 {
+                tt_note!(status, "shaping cache: {} entries evicted", stats.evictions);
+            }
+
+            if let Some(n) = shaping_cache_loaded {
+                tt_note!(status, "shaping cache: {} entries loaded", n);
+            }
+
+            if let Some(n) = shaping_cache_saved {
+                tt_note!(status, "shaping cache: {} entries saved", n);
+            }
+        }
+
+        if !self.bs.env_reads.is_empty() {
+            let reads: Vec<_> = self
+                .bs
+                .env_reads
+                .iter()
+                .map(|(name, outcome)| match outcome {
+                    EnvReadOutcome::Read => name.clone(),
+                    EnvReadOutcome::NotSet => format!("{name} (not set)"),
+                    EnvReadOutcome::Forbidden => format!("{name} (forbidden)"),
+                })
+                .collect();
+
+            tt_note!(
+                status,
+                "the document read environment variable(s): {}",
+                reads.join(", ")
+            );
+        }
+
+        self.report_missing_characters(status);
+
+        for g in &self.bs.graphics_inputs {
+            if g.resolved.is_none() {
+                tt_warning!(
+                    status,
+                    "couldn't find the graphics file \"{}\" (tried {})",
+                    g.requested,
+                    g.tried.join(", ")
+                );
+            }
+        }
+
         if let Err(e) = result {
             self.write_files(None, status, true)?;
             return Err(e);
         };
 
+        self.post_process_outputs(status)?;
+
+        if let Err(e) = self.check_output_size(status) {
+            self.write_files(None, status, true)?;
+            return Err(e);
+        }
+
         // Write output files and the first line of our Makefile output.
 
         let mut mf_dest_maybe = match self.makefile_output_path {
@@ -1523,6 +2354,10 @@ impl ProcessingSession {
 
         let n_skipped_intermediates = self.write_files(mf_dest_maybe.as_mut(), status, false)?;
 
+        if self.stream_output {
+            self.write_output_to_stdout()?;
+        }
+
         if n_skipped_intermediates > 0 {
             status.note_highlighted(
                 "Skipped writing ",
@@ -1563,7 +2398,14 @@ impl ProcessingSession {
                     continue;
                 }
 
-                ctry!(write!(mf_dest, " \\\n  {}", root.join(name).display()); "couldn't write to Makefile-rules file");
+                let path = info.path.clone().unwrap_or_else(|| root.join(name));
+                ctry!(write!(mf_dest, " \\\n  {}", path.display()); "couldn't write to Makefile-rules file");
+            }
+
+            // The files that generated inputs were computed from aren't read
+            // by the engines, but the build depends on them all the same.
+            for path in self.generated_inputs.iter().flat_map(|g| g.input_paths()) {
+                ctry!(write!(mf_dest, " \\\n  {}", path.display()); "couldn't write to Makefile-rules file");
             }
 
             ctry!(writeln!(mf_dest, ""); "couldn't write to Makefile-rules file");
@@ -1574,6 +2416,229 @@ impl ProcessingSession {
         Ok(())
     }
 
+    /// Make sure that the format file is available, generating it if needed.
+    fn ensure_format(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let generate_format = if self.output_format == OutputFormat::Format {
+            false
+        } else {
+            match self.bs.input_open_format(&self.format_name, status) {
+                OpenResult::Ok(_) => false,
+                OpenResult::NotAvailable => true,
+                OpenResult::Err(e) => {
+                    return Err(e)
+                        .chain_err(|| format!("could not open format file {}", self.format_name));
+                }
+            }
+        };
+
+        if generate_format {
+            // Other builds may be using the same format cache at the same
+            // time. Only one of them should generate the format, and the
+            // others should use what it writes once it's done.
+            let _lock = ctry!(
+                self.bs.format_cache.lock_format(&self.format_name, status);
+                "cannot lock the cache of format file {}", self.format_name
+            );
+
+            let still_missing = match self.bs.input_open_format(&self.format_name, status) {
+                OpenResult::Ok(_) => false,
+                OpenResult::NotAvailable => true,
+                OpenResult::Err(e) => {
+                    return Err(e)
+                        .chain_err(|| format!("could not open format file {}", self.format_name));
+                }
+            };
+
+            if still_missing {
+                tt_note!(status, "generating format \"{}\"", self.format_name);
+                self.make_format_pass(status)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Run the passes selected by the pass setting.
+    fn main_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
+        match self.pass {
+            PassSetting::Tex => match self.tex_pass(None, status) {
+                Ok(Some(warnings)) => {
+                    tt_warning!(status, "{}", warnings);
+                    Ok(0)
+                }
+                Ok(None) => Ok(0),
+                Err(e) => Err(e),
+            },
+            PassSetting::Default => self.default_pass(false, status),
+            PassSetting::BibtexFirst => self.default_pass(true, status),
+        }
+    }
+
+    /// Run the generators of the generated inputs, and make their outputs
+    /// available to the engines.
+    fn run_generators(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        self.generated.clear();
+
+        for input in &self.generated_inputs {
+            let (data, record) = generate::run(
+                input,
+                self.build_date,
+                self.generator_cache_dir.as_deref(),
+                status,
+            )?;
+
+            self.bs
+                .streams
+                .add_stream(format!("|{}", input.name()), Cursor::new(data.clone()));
+            self.bs.streams.add_stream(input.name(), Cursor::new(data));
+            self.generated.push(record);
+        }
+
+        if let Some(path) = self.generated_inputs_record_path.as_ref() {
+            let mut dest = ctry!(
+                File::create(path);
+                "couldn't create `{}`", path.display()
+            );
+            ctry!(
+                generate::write_records(&self.generated, &mut dest);
+                "couldn't write to `{}`", path.display()
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Run the post-processing steps over the final outputs, which are still
+    /// held in memory at this point. HTML outputs are written directly by the
+    /// engine, so they're handled in `spx2html_pass` instead.
+    fn post_process_outputs(&mut self, status: &mut dyn StatusBackend) -> Result<()> {
+        let output_extension = match self.output_format {
+            OutputFormat::Aux => "aux",
+            OutputFormat::Html => "html",
+            OutputFormat::Xdv => "xdv",
+            OutputFormat::Pdf => "pdf",
+            OutputFormat::Format => return Ok(()),
+        };
+
+        if self.post_processing.is_empty() {
+            return Ok(());
+        }
+
+        let mut files = self.bs.mem.files.borrow_mut();
+
+        // Go in a predictable order, in case the steps have side effects.
+        let mut names: Vec<String> = files
+            .keys()
+            .filter(|name| {
+                *name != self.bs.mem.stdout_key()
+                    && self
+                        .bs
+                        .events
+                        .get(*name)
+                        .is_some_and(|s| s.access_pattern == AccessPattern::Written)
+            })
+            .cloned()
+            .collect();
+        names.sort();
+
+        for name in names {
+            let file = files.get_mut(&name).unwrap();
+            let mut artifact = Artifact {
+                name,
+                data: std::mem::take(&mut file.data),
+            };
+
+            let result = postprocess::run_steps(
+                &self.post_processing,
+                &mut artifact,
+                output_extension,
+                status,
+            );
+            file.data = artifact.data;
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the size of the main output against its budget, if it has one,
+    /// failing with a breakdown of the largest contributors to its size if
+    /// it's over.
+    fn check_output_size(&self, status: &mut dyn StatusBackend) -> Result<()> {
+        const MAX_CONTRIBUTORS: usize = 10;
+
+        let Some(budget) = self.max_output_size else {
+            return Ok(());
+        };
+
+        let (name, breakdown) = match self.output_format {
+            OutputFormat::Html => (
+                "the HTML output".to_owned(),
+                inspect::inspect_html(self.html_output_sizes.borrow().iter().cloned()),
+            ),
+
+            OutputFormat::Format => return Ok(()),
+
+            _ => {
+                let name = self.stdout_output_name();
+                let files = self.bs.mem.files.borrow();
+
+                let Some(file) = files.get(&name) else {
+                    return Ok(());
+                };
+
+                let whole = || SizeBreakdown {
+                    total: file.data.len() as u64,
+                    contributors: vec![Contributor {
+                        kind: ContributorKind::Other,
+                        name: "everything".to_owned(),
+                        size: file.data.len() as u64,
+                    }],
+                };
+
+                let breakdown = if self.output_format == OutputFormat::Pdf {
+                    match inspect::inspect_pdf(&file.data) {
+                        Ok(b) => b,
+                        Err(e) => {
+                            tt_warning!(status, "couldn't break down the size of `{}`", name; e);
+                            whole()
+                        }
+                    }
+                } else {
+                    whole()
+                };
+
+                (format!("`{name}`"), breakdown)
+            }
+        };
+
+        if breakdown.total <= budget {
+            return Ok(());
+        }
+
+        let size = |n: u64| Byte::from_u64(n).get_appropriate_unit(UnitType::Binary);
+
+        let mut message = format!(
+            "{} is {}, which is over its budget of {}; its largest contributors are:",
+            name,
+            size(breakdown.total),
+            size(budget)
+        );
+
+        for c in breakdown.contributors.iter().take(MAX_CONTRIBUTORS) {
+            message.push_str(&format!("\n    {} ({}): {}", c.name, c.kind, size(c.size)));
+        }
+
+        if breakdown.contributors.len() > MAX_CONTRIBUTORS {
+            message.push_str(&format!(
+                "\n    ... and {} more",
+                breakdown.contributors.len() - MAX_CONTRIBUTORS
+            ));
+        }
+
+        Err(ErrorKind::Msg(message).into())
+    }
+
     fn write_files(
         &mut self,
         mut mf_dest_maybe: Option<&mut File>,
@@ -1678,6 +2743,7 @@ impl ProcessingSession {
 
         let mut warnings = None;
         let mut rerun_result = if bibtex_first {
+            // TeX hasn't run yet, so it always needs to run now.
             self.bibtex_pass(status)?;
             Some(RerunReason::Bibtex)
         } else {
@@ -1685,10 +2751,15 @@ impl ProcessingSession {
             let maybe_biber = self.check_biber_requirement(status)?;
 
             if let Some(biber) = maybe_biber {
+                if self.hermetic {
+                    return Err(errmsg!(
+                        "the document needs biber, but external tools can't be run in a hermetic build"
+                    ));
+                }
+
                 self.bs.external_tool_pass(&biber, status)?;
                 Some(RerunReason::Biber)
-            } else if self.is_bibtex_needed() {
-                self.bibtex_pass(status)?;
+            } else if self.is_bibtex_needed() && self.bibtex_pass(status)? {
                 Some(RerunReason::Bibtex)
             } else {
                 self.is_rerun_needed(status)
@@ -1787,6 +2858,7 @@ impl ProcessingSession {
         });
         let stem = r?;
 
+        let start = Instant::now();
         let result = {
             self.bs
                 .enter_format_mode(&format!("tectonic-format-{stem}.tex"));
@@ -1801,6 +2873,8 @@ impl ProcessingSession {
             r
         };
 
+        self.record_engine_run("TeX", start, result.is_ok());
+
         match result {
             Ok(TexOutcome::Spotless) => {}
             Ok(TexOutcome::Warnings) => {
@@ -1846,6 +2920,17 @@ impl ProcessingSession {
         rerun_explanation: Option<&str>,
         status: &mut dyn StatusBackend,
     ) -> Result<Option<&'static str>> {
+        let shaping_cache = if !self.unstables.shaping_cache {
+            ShapingCache::Disabled
+        } else if self.shaping_cache_primed {
+            ShapingCache::Reuse
+        } else {
+            ShapingCache::Fresh
+        };
+
+        let mut engine = TexEngine::default();
+        let start = Instant::now();
+
         let result = {
             if let Some(s) = rerun_explanation {
                 status.note_highlighted("Rerunning ", "TeX", &format!(" because {s} ..."));
@@ -1868,13 +2953,16 @@ impl ProcessingSession {
                 ));
             }
 
-            TexEngine::default()
+            engine
                 .halt_on_error_mode(!self.unstables.continue_on_errors)
                 .initex_mode(self.output_format == OutputFormat::Format)
                 .synctex(self.synctex_enabled)
+                .math_font_overrides(self.math_font_overrides)
                 .semantic_pagination(self.output_format == OutputFormat::Html)
                 .shell_escape(self.shell_escape_mode != ShellEscapeMode::Disabled)
                 .build_date(self.build_date)
+                .shaping_cache(shaping_cache)
+                .trace_capture(self.unstables.trace.clone())
                 .process(
                     &mut launcher,
                     &self.format_name,
@@ -1882,6 +2970,19 @@ impl ProcessingSession {
                 )
         };
 
+        self.record_engine_run("TeX", start, result.is_ok());
+
+        if shaping_cache != ShapingCache::Disabled {
+            let stats = engine.shaping_cache_stats();
+            self.shaping_cache_stats.hits += stats.hits;
+            self.shaping_cache_stats.misses += stats.misses;
+            self.shaping_cache_stats.evictions += stats.evictions;
+            self.shaping_cache_primed = true;
+        }
+
+        self.missing_characters = engine.missing_characters().to_vec();
+        self.missing_character_count = engine.missing_character_count();
+
         let warnings = match result {
             Ok(TexOutcome::Spotless) => None,
             Ok(TexOutcome::Warnings) =>
@@ -1893,6 +2994,9 @@ impl ProcessingSession {
                 return Err(e.into()),
         };
 
+        // Shell-escape commands may have written to the scratch space.
+        self.bs.scratch().check_usage()?;
+
         if !self.bs.mem.files.borrow().contains_key(&self.tex_xdv_path) {
             // TeX did not produce the expected output file
             tt_warning!(
@@ -1905,44 +3009,149 @@ impl ProcessingSession {
         Ok(warnings)
     }
 
-    // Run Bibtex process for one .aux file.
+    /// Report the characters that the last TeX pass couldn't find in its
+    /// fonts, with where they were in the input.
+    fn report_missing_characters(&self, status: &mut dyn StatusBackend) {
+        let mut n_recorded = 0;
+
+        for missing in &self.missing_characters {
+            let chars: Vec<_> = missing
+                .characters
+                .iter()
+                .map(|c| MissingCharacter {
+                    code: c.code,
+                    unicode: c.unicode,
+                    file: c.file.clone(),
+                    line: c.line,
+                })
+                .collect();
+
+            n_recorded += chars.len();
+            status.report_missing_characters(&missing.font, &chars);
+        }
+
+        if self.missing_character_count > n_recorded {
+            tt_warning!(
+                status,
+                "{} more missing characters weren't recorded",
+                self.missing_character_count - n_recorded
+            );
+        }
+
+        // The engine used to give this hint along with its first warning
+        // about a missing character; it's given once here, after all of them.
+        if self.missing_character_count > 0 {
+            tt_note!(
+                status,
+                "you may need to load the `fontspec` package and use (e.g.) \\setmainfont to \
+                 choose a different font that covers the unrepresentable character(s)"
+            );
+        }
+    }
+
+    // Run Bibtex process for one .aux file, returning whether it wrote a .bbl
+    // file.
     fn bibtex_pass_for_one_aux_file(
         &mut self,
         status: &mut dyn StatusBackend,
         aux_file: &String,
-    ) -> Result<i32> {
+    ) -> Result<bool> {
+        let start = Instant::now();
         let result = {
             status.note_highlighted("Running ", "BibTeX", &format!(" on {aux_file} ..."));
+            let mut engine = BibtexEngine::new();
+            engine.code_cache(self.load_bibtex_code_cache(status));
+
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
-            let mut engine = BibtexEngine::new();
-            engine.process(&mut launcher, aux_file, &self.unstables)
-        };
 
-        match result {
-            Ok(TexOutcome::Spotless) => {}
-            Ok(TexOutcome::Warnings) => {
-                tt_note!(
-                    status,
-                    "warnings were issued by BibTeX; use --print and/or --keep-logs for details."
-                );
+            // The HTML citation popovers show the formatted entries, which we
+            // get from the structured output of the run.
+            if self.unstables.html_citations && self.output_format == OutputFormat::Html {
+                engine.record_bbl(true);
             }
-            Ok(TexOutcome::Errors) => {
-                tt_warning!(
-                    status,
-                    "errors were issued by BibTeX, but were ignored; \
-                     use --print and/or --keep-logs for details."
-                );
+
+            // As with the TeX pass, deterministic mode stubs out the parts of
+            // the environment that would otherwise leak into the outputs.
+            if self.unstables.deterministic_mode {
+                launcher.with_expose_absolute_paths(false);
+                engine.build_date(self.build_date).rng_seed(0);
+            }
+
+            let result = engine
+                .process(&mut launcher, aux_file, &self.unstables)
+                .map(|_| engine.outcome().unwrap());
+
+            if let Some(cache) = engine.take_code_cache() {
+                self.save_bibtex_code_cache(status, &cache);
+            }
+
+            for warning in engine.field_warnings() {
+                tt_warning!(status, "{}", warning);
+            }
+
+            for lint in engine.entry_lints() {
+                tt_warning!(status, "{}", lint);
+            }
+
+            for dup in engine.duplicate_entries() {
+                tt_warning!(status, "{}", dup);
+            }
+
+            for missing in engine.missing_entries() {
+                tt_warning!(status, "{}", missing);
+            }
+
+            if let Some(bbl) = engine.take_bbl() {
+                for block in bbl.blocks() {
+                    if let Some(key) = block.cite_key.as_ref() {
+                        self.html_references.insert(key, block.plain_text());
+                    }
+                }
             }
+
+            result
+        };
+
+        self.record_engine_run("BibTeX", start, result.is_ok());
+
+        let outcome = match result {
+            Ok(outcome) => outcome,
             Err(e) => {
                 return Err(e.chain_err(|| ErrorKind::EngineError("BibTeX")));
             }
+        };
+
+        if let Some(run) = self.engine_runs.last_mut() {
+            run.warnings = Some(outcome.n_warnings);
+            run.errors = Some(outcome.n_errors);
         }
 
-        Ok(0)
+        let plural = |n: u32| if n == 1 { "" } else { "s" };
+
+        if outcome.n_errors > 0 {
+            tt_warning!(
+                status,
+                "BibTeX issued {} error{}, but carried on; \
+                 use --print and/or --keep-logs for details.",
+                outcome.n_errors,
+                plural(outcome.n_errors)
+            );
+        } else if outcome.n_warnings > 0 {
+            tt_note!(
+                status,
+                "BibTeX issued {} warning{}; use --print and/or --keep-logs for details.",
+                outcome.n_warnings,
+                plural(outcome.n_warnings)
+            );
+        }
+
+        Ok(outcome.wrote_bbl)
     }
 
-    fn bibtex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
+    /// Run BibTeX on the main `.aux` file and any others that TeX wrote,
+    /// returning whether any `.bbl` file was written or restored.
+    fn bibtex_pass(&mut self, status: &mut dyn StatusBackend) -> Result<bool> {
         let mut aux_files = vec![self.tex_aux_path.clone()];
 
         // find other .aux files generated by tex_pass
@@ -1952,39 +3161,201 @@ impl ProcessingSession {
             }
         }
 
+        let mut wrote_bbl = false;
+
         for f in aux_files {
-            let _r = self.bibtex_pass_for_one_aux_file(status, &f)?;
+            // The HTML citation popovers need the structured output of an
+            // actual BibTeX run, so don't reuse `.bbl` files in that case.
+            let draft_digest = if self.draft && !self.unstables.html_citations {
+                self.bibtex_inputs_digest(&f, status)
+            } else {
+                None
+            };
+
+            if let Some(ref d) = draft_digest {
+                if self.restore_draft_bbl(&f, d) {
+                    status.note_highlighted(
+                        "Skipping ",
+                        "BibTeX",
+                        &format!(" on {f}: its inputs are unchanged"),
+                    );
+                    wrote_bbl = true;
+                    continue;
+                }
+            }
+
+            wrote_bbl |= self.bibtex_pass_for_one_aux_file(status, &f)?;
+
+            if let Some(ref d) = draft_digest {
+                self.save_draft_bbl(&f, d)?;
+            }
         }
 
-        Ok(0)
+        Ok(wrote_bbl)
+    }
+
+    /// Compute a digest of everything that determines the `.bbl` file that
+    /// BibTeX will produce from an `.aux` file: the citation-related commands
+    /// in the `.aux` file, and the contents of the databases and style that
+    /// they name. Returns `None` if the digest can't be computed.
+    fn bibtex_inputs_digest(
+        &mut self,
+        aux_file: &str,
+        status: &mut dyn StatusBackend,
+    ) -> Option<DigestData> {
+        let aux = self.bs.mem.files.borrow().get(aux_file)?.data.clone();
+        let mut dc = digest::create();
+        let mut names = Vec::new();
+
+        for line in String::from_utf8_lossy(&aux).lines() {
+            let (args, ext) = if let Some(rest) = line.strip_prefix("\\bibdata{") {
+                (rest, ".bib")
+            } else if let Some(rest) = line.strip_prefix("\\bibstyle{") {
+                (rest, ".bst")
+            } else if line.starts_with("\\citation{") || line.starts_with("\\@input{") {
+                ("", "")
+            } else {
+                continue;
+            };
+
+            dc.update(line.as_bytes());
+            dc.update(b"\n");
+
+            let args = args.split('}').next().unwrap_or_default();
+
+            for name in args.split(',').filter(|n| !n.is_empty()) {
+                if name.ends_with(ext) {
+                    names.push(name.to_owned());
+                } else {
+                    names.push(format!("{name}{ext}"));
+                }
+            }
+        }
+
+        for name in names {
+            dc.update(name.as_bytes());
+            dc.update(b"\0");
+
+            match self.bs.input_open_name(&name, status) {
+                OpenResult::Ok(mut ih) => {
+                    let mut data = Vec::new();
+                    ih.read_to_end(&mut data).ok()?;
+                    dc.update(&data);
+                }
+                OpenResult::NotAvailable => {}
+                OpenResult::Err(_) => return None,
+            }
+        }
+
+        Some(DigestData::from(dc))
+    }
+
+    /// In draft mode, try to reuse the `.bbl` file saved by a previous build
+    /// instead of running BibTeX. This succeeds if the saved file was produced
+    /// from inputs with the given digest.
+    fn restore_draft_bbl(&mut self, aux_file: &str, inputs: &DigestData) -> bool {
+        let root = match self.output_path {
+            Some(ref p) => p,
+            None => return false,
+        };
+
+        let stem = aux_file.strip_suffix(".aux").unwrap_or(aux_file);
+        let bbl = format!("{stem}.bbl");
+
+        match fs::read_to_string(root.join(format!("{stem}.{DRAFT_BBL_STAMP_EXTENSION}"))) {
+            Ok(stamp) if stamp.trim() == inputs.to_string() => {}
+            _ => return false,
+        }
+
+        let data = match fs::read(root.join(&bbl)) {
+            Ok(d) => d,
+            Err(_) => return false,
+        };
+
+        self.bs.mem.create_entry(&bbl, data);
+        self.bs.events.insert(
+            bbl,
+            FileSummary::new(AccessPattern::Written, InputOrigin::NotInput),
+        );
+        true
+    }
+
+    /// In draft mode, save the `.bbl` file that BibTeX just produced, along
+    /// with the digest of its inputs, so that later draft builds can reuse it.
+    fn save_draft_bbl(&self, aux_file: &str, inputs: &DigestData) -> Result<()> {
+        let root = match self.output_path {
+            Some(ref p) => p,
+            None => return Ok(()),
+        };
+
+        let stem = aux_file.strip_suffix(".aux").unwrap_or(aux_file);
+        let bbl = format!("{stem}.bbl");
+
+        if let Some(file) = self.bs.mem.files.borrow().get(&bbl) {
+            let bbl_path = root.join(&bbl);
+            let stamp_path = root.join(format!("{stem}.{DRAFT_BBL_STAMP_EXTENSION}"));
+
+            ctry!(
+                fs::write(&bbl_path, &file.data);
+                "failed to write file `{}`", bbl_path.display()
+            );
+            ctry!(
+                fs::write(&stamp_path, format!("{inputs}\n"));
+                "failed to write file `{}`", stamp_path.display()
+            );
+        }
+
+        Ok(())
     }
 
     fn xdvipdfmx_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
-        {
+        let start = Instant::now();
+        let result = {
             status.note_highlighted("Running ", "xdvipdfmx", " ...");
 
             let mut launcher =
                 CoreBridgeLauncher::new_with_security(&mut self.bs, status, self.security.clone());
             let mut engine = XdvipdfmxEngine::default();
 
-            engine.build_date(self.build_date);
+            engine
+                .build_date(self.build_date)
+                .draft(self.draft)
+                .page_spec(self.page_spec.clone());
 
             if let Some(ref ps) = self.unstables.paper_size {
                 engine.paper_spec(ps.clone());
             }
 
-            engine.process(&mut launcher, &self.tex_xdv_path, &self.tex_pdf_path)?;
-        }
+            engine.process(&mut launcher, &self.tex_xdv_path, &self.tex_pdf_path)
+        };
+
+        self.record_engine_run("xdvipdfmx", start, result.is_ok());
+        result?;
 
         self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
         Ok(0)
     }
 
     fn spx2html_pass(&mut self, status: &mut dyn StatusBackend) -> Result<i32> {
+        // When streaming to stdout, the engine emits its files into a
+        // temporary directory, which we then bundle up into a tarball.
+        let stream_dir = if self.html_emit_files && self.stream_output {
+            Some(ctry!(
+                self.bs.scratch().tempdir("tectonic_html");
+                "can't create temporary directory for HTML output"
+            ))
+        } else {
+            None
+        };
+
         {
             let mut engine = Spx2HtmlEngine::default();
+            let out_base = match stream_dir {
+                Some(ref d) => Some(d.path()),
+                None => self.output_path.as_deref(),
+            };
 
-            match (self.html_emit_files, self.output_path.as_ref()) {
+            match (self.html_emit_files, out_base) {
                 (true, Some(p)) => engine.output_base(p),
                 (false, _) => engine.do_not_emit_files(),
                 (true, None) => return Err(errmsg!("HTML output must be saved directly to disk")),
@@ -2000,14 +3371,343 @@ impl ProcessingSession {
                 engine.precomputed_assets(a.clone());
             }
 
+            if let Some(p) = self.html_accessibility_report_path.as_ref() {
+                engine.accessibility_report_path(p);
+            }
+
+            if let Some(m) = self.html_social_metadata.as_ref() {
+                engine.social_metadata(m.clone());
+            }
+
+            if let Some(p) = self.html_preview_image.as_ref() {
+                engine.preview_image(p.clone());
+            }
+
+            engine.math_rendering(self.html_math_rendering);
+            engine.heading_numbers(self.html_heading_numbers);
+
+            if let Some(depth) = self.html_toc_depth {
+                engine.toc_depth(depth);
+            }
+
+            if self.unstables.html_incremental {
+                engine.incremental(true);
+            }
+
+            if self.unstables.html_subset_fonts {
+                engine.subset_fonts(true);
+            }
+
+            // The filter also measures the outputs, if they have a budget.
+            self.html_output_sizes.borrow_mut().clear();
+
+            if let Some(p) = out_base {
+                if !self.post_processing.is_empty() || self.max_output_size.is_some() {
+                    engine.output_filter(Box::new(HtmlOutputFilter {
+                        steps: self.post_processing.clone(),
+                        out_base: p.to_owned(),
+                        sizes: self.html_output_sizes.clone(),
+                    }));
+                }
+            }
+
+            if self.unstables.html_citations {
+                engine
+                    .references(std::mem::take(&mut self.html_references))
+                    .citation_popovers(true)
+                    .bibliography_backlinks(true);
+            }
+
+            // Converting PDF figures is slow, so keep the results around
+            // between builds if we can.
+            if let Ok(p) = app_dirs::get_user_cache_dir("html-figures") {
+                engine.image_cache_dir(p);
+            }
+
             status.note_highlighted("Running ", "spx2html", " ...");
-            engine.process_to_filesystem(&mut self.bs, status, &self.tex_xdv_path)?;
+            let start = Instant::now();
+            let result = engine.process_to_filesystem(&mut self.bs, status, &self.tex_xdv_path);
+            self.record_engine_run("spx2html", start, result.is_ok());
+            result?;
         }
 
+        self.bs.scratch().check_usage()?;
+
         self.bs.mem.files.borrow_mut().remove(&self.tex_xdv_path);
+
+        if let Some(d) = stream_dir {
+            let mut tarball = tar::Builder::new(Vec::new());
+            ctry!(tarball.append_dir_all(".", d.path()); "failed to create tarball of HTML outputs");
+            let data = ctry!(tarball.into_inner(); "failed to create tarball of HTML outputs");
+            let name = self.stdout_output_name();
+            self.bs.mem.create_entry(&name, data);
+            self.bs.events.insert(
+                name,
+                FileSummary::new(AccessPattern::Written, InputOrigin::NotInput),
+            );
+        }
+
         Ok(0)
     }
 
+    /// The name of the file in the memory layer that is written to stdout
+    /// when streaming the output. For HTML, this is the tarball of the
+    /// outputs assembled by `spx2html_pass`.
+    fn stdout_output_name(&self) -> String {
+        match self.output_format {
+            OutputFormat::Aux => self.tex_aux_path.clone(),
+            OutputFormat::Xdv => self.tex_xdv_path.clone(),
+            OutputFormat::Pdf => self.tex_pdf_path.clone(),
+            OutputFormat::Html => {
+                let mut p = PathBuf::from(&self.tex_aux_path);
+                p.set_extension("tar");
+                p.display().to_string()
+            }
+            // This is rejected when the session is created.
+            OutputFormat::Format => unreachable!(),
+        }
+    }
+
+    /// Write the final output file to stdout, after a successful run in
+    /// streaming mode.
+    fn write_output_to_stdout(&self) -> Result<()> {
+        let name = self.stdout_output_name();
+        let files = self.bs.mem.files.borrow();
+
+        let Some(file) = files.get(&name) else {
+            return Err(errmsg!("no output file `{}` was produced to stream", name));
+        };
+
+        let mut stdout = std::io::stdout().lock();
+        ctry!(stdout.write_all(&file.data); "failed to write output to standard output");
+        ctry!(stdout.flush(); "failed to write output to standard output");
+        Ok(())
+    }
+
+    /// The fingerprint that a saved shaping cache must match to be loaded.
+    /// Its entries are keyed by the text and by the fonts, including a digest
+    /// of each font file, so that fonts from the project or the system that
+    /// change between builds don't reuse stale entries. Shaping also depends
+    /// on the settings made by the format.
+    fn shaping_cache_fingerprint(&self) -> String {
+        format!(
+            "{}:{}:{}",
+            crate::FORMAT_SERIAL,
+            self.bs.format_cache.bundle_digest(),
+            self.format_name
+        )
+    }
+
+    /// Load the shaping cache from the file named by the `shaping-cache-file`
+    /// unstable option, if it exists, so that the first TeX pass can reuse
+    /// it. Returns the number of entries loaded.
+    ///
+    /// Problems are only warned about, since the cache can always be rebuilt.
+    fn load_shaping_cache(&mut self, status: &mut dyn StatusBackend) -> Option<usize> {
+        if !self.unstables.shaping_cache {
+            return None;
+        }
+
+        let path = self.unstables.shaping_cache_file.as_ref()?;
+
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+            Err(e) => {
+                tt_warning!(status, "couldn't open the shaping cache file `{}`", path.display(); e.into());
+                return None;
+            }
+        };
+
+        match load_shaping_cache(&self.shaping_cache_fingerprint(), file) {
+            Ok(Some(n)) => {
+                self.shaping_cache_primed = true;
+                Some(n)
+            }
+
+            Ok(None) => {
+                tt_note!(
+                    status,
+                    "ignoring the shaping cache file `{}`, which was saved with a different bundle or format",
+                    path.display()
+                );
+                None
+            }
+
+            Err(e) => {
+                tt_warning!(status, "couldn't load the shaping cache file `{}`", path.display(); e.into());
+                None
+            }
+        }
+    }
+
+    /// Save the shaping cache to the file named by the `shaping-cache-file`
+    /// unstable option, if a TeX pass has filled it. Returns the number of
+    /// entries saved.
+    ///
+    /// The file is replaced atomically, so that an interrupted build can't
+    /// leave a truncated cache behind.
+    fn save_shaping_cache(&self, status: &mut dyn StatusBackend) -> Option<usize> {
+        if !self.shaping_cache_primed {
+            return None;
+        }
+
+        let path = self.unstables.shaping_cache_file.as_ref()?;
+
+        match self.save_shaping_cache_inner(path) {
+            Ok(n) => Some(n),
+            Err(e) => {
+                tt_warning!(status, "couldn't save the shaping cache file `{}`", path.display(); e);
+                None
+            }
+        }
+    }
+
+    fn save_shaping_cache_inner(&self, path: &Path) -> Result<usize> {
+        let dir = match path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+
+        let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+        let n = save_shaping_cache(&self.shaping_cache_fingerprint(), temp.as_file_mut())?;
+        temp.persist(path)?;
+        Ok(n)
+    }
+
+    /// Load the bibtex code cache from the file named by the
+    /// `bibtex-code-cache` unstable option, if it's set. A missing or
+    /// unreadable file gives an empty cache.
+    fn load_bibtex_code_cache(&self, status: &mut dyn StatusBackend) -> Option<BstCodeCache> {
+        let path = self.unstables.bibtex_code_cache.as_ref()?;
+
+        let file = match File::open(path) {
+            Ok(f) => f,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Some(BstCodeCache::new()),
+            Err(e) => {
+                tt_warning!(status, "couldn't open the bibtex code cache file `{}`", path.display(); e.into());
+                return Some(BstCodeCache::new());
+            }
+        };
+
+        match BstCodeCache::load(file) {
+            Ok(Some(cache)) => Some(cache),
+
+            Ok(None) => {
+                tt_note!(
+                    status,
+                    "ignoring the bibtex code cache file `{}`, which was saved by a different version",
+                    path.display()
+                );
+                Some(BstCodeCache::new())
+            }
+
+            Err(e) => {
+                tt_warning!(status, "couldn't load the bibtex code cache file `{}`", path.display(); e);
+                Some(BstCodeCache::new())
+            }
+        }
+    }
+
+    /// Save the bibtex code cache to the file named by the
+    /// `bibtex-code-cache` unstable option, replacing it atomically as with
+    /// the shaping cache.
+    fn save_bibtex_code_cache(&self, status: &mut dyn StatusBackend, cache: &BstCodeCache) {
+        let Some(path) = self.unstables.bibtex_code_cache.as_ref() else {
+            return;
+        };
+
+        let save = || -> Result<()> {
+            let dir = match path.parent() {
+                Some(p) if !p.as_os_str().is_empty() => p,
+                _ => Path::new("."),
+            };
+
+            let mut temp = tempfile::NamedTempFile::new_in(dir)?;
+            cache.save(temp.as_file_mut())?;
+            temp.persist(path)?;
+            Ok(())
+        };
+
+        if let Err(e) = save() {
+            tt_warning!(status, "couldn't save the bibtex code cache file `{}`", path.display(); e.into());
+        }
+    }
+
+    fn record_engine_run(&mut self, engine: &'static str, start: Instant, succeeded: bool) {
+        self.engine_runs.push(EngineRun {
+            engine,
+            duration: start.elapsed(),
+            succeeded,
+            warnings: None,
+            errors: None,
+        });
+    }
+
+    /// Get the engines run during the most recent run, in order, along with
+    /// how long each one took.
+    ///
+    /// If the run failed, the last engine listed is usually the one that
+    /// failed. Engines that were never reached aren't listed.
+    pub fn engine_runs(&self) -> &[EngineRun] {
+        &self.engine_runs
+    }
+
+    /// Get the shaping cache counters, summed over the TeX passes of the most
+    /// recent run.
+    ///
+    /// These are only nonzero if the `shaping-cache` unstable option is set.
+    pub fn shaping_cache_stats(&self) -> ShapingCacheStats {
+        self.shaping_cache_stats
+    }
+
+    /// Get the environment variables that the document tried to read during
+    /// the most recent run, and what came of each attempt.
+    pub fn env_reads(&self) -> &BTreeMap<String, EnvReadOutcome> {
+        &self.bs.env_reads
+    }
+
+    /// Get the graphics files that the document searched for during the
+    /// most recent run, in the order that they were first searched for.
+    ///
+    /// Graphics found on the filesystem are also listed in the Makefile
+    /// rules, if those are requested, but this also records the names that
+    /// were tried, and the graphics that weren't found at all.
+    pub fn graphics_inputs(&self) -> &[GraphicsInput] {
+        &self.bs.graphics_inputs
+    }
+
+    /// Get where the contents of each generated input came from during the
+    /// most recent run, in the order that the inputs were added.
+    ///
+    /// See [`ProcessingSessionBuilder::generated_input`].
+    pub fn generated_inputs(&self) -> &[GeneratedInputRecord] {
+        &self.generated
+    }
+
+    /// Get the files that the most recent run wrote to disk, along with their
+    /// contents. The names are relative to the output directory, and the list
+    /// is sorted by name.
+    pub fn written_files(&self) -> Vec<(String, Vec<u8>)> {
+        let mut files: Vec<_> = self
+            .bs
+            .mem
+            .files
+            .borrow()
+            .iter()
+            .filter(|(name, _)| {
+                self.bs
+                    .events
+                    .get(*name)
+                    .is_some_and(|summ| summ.got_written_to_disk)
+            })
+            .map(|(name, file)| (name.clone(), file.data.clone()))
+            .collect();
+
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        files
+    }
+
     /// Get what was printed to standard output, if anything.
     pub fn get_stdout_content(&self) -> Vec<u8> {
         self.bs
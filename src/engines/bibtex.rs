@@ -3,8 +3,14 @@
 
 //! Engine for invoking `bibtex`.
 
+use std::time::SystemTime;
 use tectonic_bridge_core::CoreBridgeLauncher;
-use tectonic_engine_bibtex::{BibtexEngine as RealBibtexEngine, BibtexOutcome};
+use tectonic_engine_bibtex::{
+    Bbl, BibtexEngine as RealBibtexEngine, CitationReport, DuplicateEntry, EntryLint, FieldWarning,
+    MissingEntry, Transliteration,
+};
+
+pub use tectonic_engine_bibtex::{BibtexHistory, BibtexOutcome, BstCodeCache};
 
 use super::tex::TexOutcome;
 use crate::{errors::Result, unstable_opts::UnstableOptions};
@@ -15,7 +21,19 @@ use crate::{errors::Result, unstable_opts::UnstableOptions};
 /// apply any settings that you wish, and eventually run the
 /// [`process()`](Self::process) method.
 #[derive(Default)]
-pub struct BibtexEngine {}
+pub struct BibtexEngine {
+    build_date: Option<SystemTime>,
+    rng_seed: Option<u64>,
+    record_bbl: bool,
+    field_warnings: Vec<FieldWarning>,
+    entry_lints: Vec<EntryLint>,
+    duplicate_entries: Vec<DuplicateEntry>,
+    missing_entries: Vec<MissingEntry>,
+    bbl: Option<Bbl>,
+    citation_report: Option<CitationReport>,
+    code_cache: Option<BstCodeCache>,
+    outcome: Option<BibtexOutcome>,
+}
 
 impl BibtexEngine {
     /// Create a new, default engine for running `bibtex`.
@@ -23,6 +41,96 @@ impl BibtexEngine {
         Default::default()
     }
 
+    /// Sets the date and time seen by the `bibtex` engine.
+    ///
+    /// See [`tectonic_engine_bibtex::BibtexEngine::build_date`] for details.
+    pub fn build_date(&mut self, date: SystemTime) -> &mut Self {
+        self.build_date = Some(date);
+        self
+    }
+
+    /// Seeds the pseudo-random numbers used by the `bibtex` engine.
+    ///
+    /// See [`tectonic_engine_bibtex::BibtexEngine::rng_seed`] for details.
+    pub fn rng_seed(&mut self, seed: u64) -> &mut Self {
+        self.rng_seed = Some(seed);
+        self
+    }
+
+    /// Record a structured representation of the `.bbl` output.
+    ///
+    /// See [`tectonic_engine_bibtex::BibtexEngine::record_bbl`] for details.
+    pub fn record_bbl(&mut self, record: bool) -> &mut Self {
+        self.record_bbl = record;
+        self
+    }
+
+    /// Reuse and add to the code of the style's functions in *cache*.
+    ///
+    /// See [`tectonic_engine_bibtex::BibtexEngine::code_cache`] for details.
+    pub fn code_cache(&mut self, cache: Option<BstCodeCache>) -> &mut Self {
+        self.code_cache = cache;
+        self
+    }
+
+    /// Take back the code cache given to [`Self::code_cache`], with the
+    /// functions of the most recent run added to it.
+    pub fn take_code_cache(&mut self) -> Option<BstCodeCache> {
+        self.code_cache.take()
+    }
+
+    /// Take the structured `.bbl` output of the most recent run, if it was
+    /// recorded.
+    pub fn take_bbl(&mut self) -> Option<Bbl> {
+        self.bbl.take()
+    }
+
+    /// The problems found by field checks during the most recent run.
+    ///
+    /// Fields are only checked if requested with the `bibtex_validate`
+    /// unstable option.
+    pub fn field_warnings(&self) -> &[FieldWarning] {
+        &self.field_warnings
+    }
+
+    /// The problems found by linting entries during the most recent run.
+    ///
+    /// Entries are only linted if requested with the `bibtex_lint` unstable
+    /// option.
+    pub fn entry_lints(&self) -> &[EntryLint] {
+        &self.entry_lints
+    }
+
+    /// The entries that seemed to duplicate others during the most recent
+    /// run.
+    ///
+    /// Duplicates are only looked for if requested with the
+    /// `bibtex_duplicates` unstable option.
+    pub fn duplicate_entries(&self) -> &[DuplicateEntry] {
+        &self.duplicate_entries
+    }
+
+    /// The cited keys that weren't found in any database during the most
+    /// recent run, with suggestions of similar keys that were.
+    pub fn missing_entries(&self) -> &[MissingEntry] {
+        &self.missing_entries
+    }
+
+    /// The report of the citations of the most recent run, if it got as far
+    /// as reading the databases.
+    ///
+    /// See [`tectonic_engine_bibtex::BibtexEngine::take_citation_report`] for
+    /// details.
+    pub fn citation_report(&self) -> Option<&CitationReport> {
+        self.citation_report.as_ref()
+    }
+
+    /// The detailed outcome of the most recent run, including the numbers
+    /// of warnings and errors, or `None` if it ended in a fatal error.
+    pub fn outcome(&self) -> Option<BibtexOutcome> {
+        self.outcome
+    }
+
     /// Process a document using the current engine configuration.
     ///
     /// The *launcher* parameter gives overarching environmental context in
@@ -45,12 +153,121 @@ impl BibtexEngine {
             real_engine.min_crossrefs(x);
         }
 
-        let real_outcome = real_engine.process(launcher, aux)?;
+        if let Some(x) = unstables.max_crossref_depth {
+            real_engine.max_crossref_depth(x);
+        }
+
+        if unstables.bibtex_biblatex {
+            real_engine.biblatex_output(true);
+        }
+
+        if unstables.bibtex_low_memory {
+            real_engine.low_memory(true);
+        }
+
+        if unstables.bibtex_output_files {
+            real_engine.output_files(true);
+        }
+
+        if unstables.bibtex_source_map {
+            real_engine.source_map(true);
+        }
+
+        if unstables.bibtex_structured_data {
+            real_engine.structured_databases(true);
+        }
+
+        if unstables.bibtex_truncate_strings {
+            real_engine.truncate_strings(true);
+        }
+
+        if unstables.bibtex_unicode_case {
+            real_engine.unicode_case(true);
+        }
+
+        if unstables.bibtex_hardened {
+            real_engine.hardened(true);
+        }
+
+        if let Some(locale) = unstables.bibtex_collation {
+            real_engine.collation(locale);
+        }
+
+        if let Some(name) = &unstables.bibtex_csf {
+            real_engine.csf_file(name);
+        }
+
+        if let Some(order) = &unstables.bibtex_sort {
+            real_engine.entry_order(order.clone());
+        }
+
+        if let Some(scheme) = &unstables.bibtex_sort_scheme {
+            real_engine.sort_scheme(scheme.clone());
+        }
+
+        if let Some(steps) = unstables.bibtex_step_limit {
+            real_engine.step_limit(steps);
+        }
+
+        for (field, limit) in &unstables.bibtex_field_limits {
+            match field {
+                Some(field) => real_engine.field_limit(field, *limit),
+                None => real_engine.default_field_limit(*limit),
+            };
+        }
+
+        for (field, check) in &unstables.bibtex_validate {
+            real_engine.check_field(field, *check);
+        }
+
+        if unstables.bibtex_lint {
+            real_engine.lint_entries(true);
+        }
+
+        for (entry_type, schema) in &unstables.bibtex_schemas {
+            real_engine.entry_schema(entry_type, schema.clone());
+        }
+
+        if unstables.bibtex_duplicates {
+            real_engine.find_duplicates(true);
+        }
+
+        if !unstables.bibtex_transliterate.is_empty() {
+            let mut table = Transliteration::new();
+
+            for script in &unstables.bibtex_transliterate {
+                table.add_script(*script);
+            }
+
+            real_engine.transliteration(table);
+        }
+
+        if let Some(date) = self.build_date {
+            real_engine.build_date(date);
+        }
+
+        if let Some(seed) = self.rng_seed {
+            real_engine.rng_seed(seed);
+        }
+
+        real_engine.record_bbl(self.record_bbl);
+        real_engine.code_cache(self.code_cache.take());
+
+        let real_outcome = real_engine.process(launcher, aux);
+        self.code_cache = real_engine.take_code_cache();
+        self.field_warnings = real_engine.take_field_warnings();
+        self.entry_lints = real_engine.take_entry_lints();
+        self.duplicate_entries = real_engine.take_duplicate_entries();
+        self.missing_entries = real_engine.take_missing_entries();
+        self.citation_report = real_engine.take_citation_report();
+        self.bbl = real_engine.take_bbl();
+        self.outcome = real_outcome.as_ref().ok().copied();
+        let real_outcome = real_outcome?;
 
-        match real_outcome {
-            BibtexOutcome::Spotless => Ok(TexOutcome::Spotless),
-            BibtexOutcome::Warnings => Ok(TexOutcome::Warnings),
-            BibtexOutcome::Errors => Ok(TexOutcome::Errors),
+        match real_outcome.history {
+            BibtexHistory::Spotless => Ok(TexOutcome::Spotless),
+            BibtexHistory::Warnings => Ok(TexOutcome::Warnings),
+            BibtexHistory::Errors => Ok(TexOutcome::Errors),
         }
     }
 }
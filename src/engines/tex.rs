@@ -6,7 +6,10 @@
 
 use crate::errors::DefinitelySame;
 
-pub use tectonic_engine_xetex::{TexEngine, TexOutcome};
+pub use tectonic_engine_xetex::{
+    load_shaping_cache, save_shaping_cache, Checkpoint, MathFontOverrides, MissingCharacters,
+    ShapingCache, ShapingCacheStats, TexEngine, TexOutcome, TraceCapture, TraceParameter,
+};
 
 // Sigh, have to do this manually because of the Result/PartialEq conflict in errors.rs
 impl DefinitelySame for TexOutcome {
@@ -4,4 +4,4 @@
 
 //! Engine for invoking `xdvipdfmx`.
 
-pub use tectonic_engine_xdvipdfmx::XdvipdfmxEngine;
+pub use tectonic_engine_xdvipdfmx::{validate_page_spec, XdvipdfmxEngine};
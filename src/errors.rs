@@ -20,6 +20,7 @@ use std::{
     str,
     sync::{Arc, Mutex, Weak},
 };
+use tectonic_bridge_core::{EngineAbortKind, EngineAbortedError};
 use tectonic_errors::Error as NewError;
 use zip::result::ZipError;
 
@@ -100,6 +101,20 @@ error_chain! {
             description("some engine had an unrecoverable error")
             display("the {} engine had an unrecoverable error", engine)
         }
+
+        ConfigError {
+            description("the configuration is invalid")
+            display("the configuration is invalid")
+        }
+
+        BundleError {
+            description("the support bundle could not be used")
+            display("the support bundle could not be used")
+        }
+        HermeticityViolation(count: usize) {
+            description("a hermetic build tried to access something outside of its bounds")
+            display("the hermetic build tried to make {} forbidden access(es)", count)
+        }
     }
 }
 
@@ -177,6 +192,136 @@ impl Error {
     }
 }
 
+/// The broad class of a failure.
+///
+/// The command-line program exits with a different code for each class, so
+/// that build tools and editors can react appropriately to different kinds of
+/// failures; see [`FailureClass::exit_code`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FailureClass {
+    /// A failure that doesn't fall into any of the other classes.
+    Other,
+
+    /// An error in the document, reported by the TeX engine or another engine
+    /// processing it.
+    Document,
+
+    /// An error reported by BibTeX.
+    Bibliography,
+
+    /// A problem with the configuration, such as an unreadable `Tectonic.toml`
+    /// file.
+    Configuration,
+
+    /// A failure to load the support bundle, such as a network error.
+    Bundle,
+
+    /// A hermetic build that tried to use the network or to read files from
+    /// outside of its bounds.
+    Hermeticity,
+
+    /// An internal error, such as a panic or running out of memory.
+    Internal,
+}
+
+impl FailureClass {
+    /// The exit code of the command-line program for this class of failure.
+    ///
+    /// The code 2 is left for errors in the command-line arguments, which are
+    /// reported by the argument parser, and the code for internal errors
+    /// matches the one that Rust uses when the program panics.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            FailureClass::Other => 1,
+            FailureClass::Document => 3,
+            FailureClass::Bibliography => 4,
+            FailureClass::Configuration => 5,
+            FailureClass::Bundle => 6,
+            FailureClass::Hermeticity => 7,
+            FailureClass::Internal => 101,
+        }
+    }
+
+    fn of_new_style(err: &NewError) -> Option<Self> {
+        for item in err.chain() {
+            if let Some(e) = item.downcast_ref::<EngineAbortedError>() {
+                return Some(match e.kind() {
+                    EngineAbortKind::General | EngineAbortKind::FileOpen => FailureClass::Document,
+                    EngineAbortKind::OutOfMemory | EngineAbortKind::Internal => {
+                        FailureClass::Internal
+                    }
+                    EngineAbortKind::BadFormat => FailureClass::Bundle,
+                });
+            }
+        }
+
+        None
+    }
+}
+
+impl Error {
+    /// Determine the broad class of this failure.
+    ///
+    /// The outermost classifiable error in the chain wins, except that an
+    /// internal error anywhere in the chain makes the whole failure internal.
+    pub fn failure_class(&self) -> FailureClass {
+        let mut class = None;
+        let mut next = Some(self);
+
+        while let Some(err) = next {
+            let this = match err.kind() {
+                ErrorKind::EngineError("BibTeX") => Some(FailureClass::Bibliography),
+                ErrorKind::EngineError(_) => Some(FailureClass::Document),
+                ErrorKind::ConfigError | ErrorKind::ConfigRead(_) | ErrorKind::ConfigWrite(_) => {
+                    Some(FailureClass::Configuration)
+                }
+                ErrorKind::BundleError => Some(FailureClass::Bundle),
+                ErrorKind::HermeticityViolation(_) => Some(FailureClass::Hermeticity),
+                ErrorKind::NewStyle(e) => FailureClass::of_new_style(e),
+                _ => None,
+            };
+
+            if this == Some(FailureClass::Internal) {
+                return FailureClass::Internal;
+            }
+
+            class = class.or(this);
+            next = err
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|e| e.downcast_ref::<Error>());
+        }
+
+        class.unwrap_or(FailureClass::Other)
+    }
+
+    /// Get the kind of the engine abort that caused this failure, if it was
+    /// caused by one.
+    pub fn engine_abort_kind(&self) -> Option<EngineAbortKind> {
+        let mut next = Some(self);
+
+        while let Some(err) = next {
+            if let ErrorKind::NewStyle(e) = err.kind() {
+                if let Some(abort) = e
+                    .chain()
+                    .find_map(|item| item.downcast_ref::<EngineAbortedError>())
+                {
+                    return Some(abort.kind());
+                }
+            }
+
+            next = err
+                .1
+                .next_error
+                .as_ref()
+                .and_then(|e| e.downcast_ref::<Error>());
+        }
+
+        None
+    }
+}
+
 /// The DefinitelySame trait is a helper trait implemented because Errors do
 /// not generically implement PartialEq. This is a bit of a drag for testing
 /// since it's nice to be able to check if an error matches the one that's
@@ -0,0 +1,281 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! The built-in generators.
+//!
+//! These produce TeX files that define macros, so that documents can use
+//! their values after reading them with `\input`. The names of the macros
+//! start with a prefix that can be changed with the `prefix` setting, which
+//! must consist of ASCII letters.
+
+use std::{
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+};
+use tectonic_errors::prelude::*;
+use tectonic_io_base::date::{civil_from_days, unix_seconds};
+use tectonic_status_base::StatusBackend;
+
+use super::{GenerateContext, Generator, GeneratorConfig};
+use crate::config::check_settings;
+
+/// The `date` generator.
+///
+/// This defines the macros `\builddate`, as the date of the build in the
+/// form `YYYY-MM-DD`, and `\buildyear`, `\buildmonth`, and `\buildday`, as
+/// its parts. The date is the build date of the session, in UTC, so it
+/// respects `SOURCE_DATE_EPOCH` and deterministic mode.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Date;
+
+impl Generator for Date {
+    fn name(&self) -> &str {
+        "date"
+    }
+
+    fn generate(
+        &self,
+        ctx: &GenerateContext<'_>,
+        config: &GeneratorConfig,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>> {
+        check_settings(config, &["prefix"])?;
+        let prefix = macro_prefix(config, "build")?;
+
+        let days = unix_seconds(ctx.build_date).div_euclid(86400);
+        let (year, month, day) = civil_from_days(days);
+
+        let mut text = String::new();
+        define(
+            &mut text,
+            prefix,
+            "date",
+            &format!("{year:04}-{month:02}-{day:02}"),
+        );
+        define(&mut text, prefix, "year", &format!("{year:04}"));
+        define(&mut text, prefix, "month", &format!("{month:02}"));
+        define(&mut text, prefix, "day", &format!("{day:02}"));
+        Ok(text.into_bytes())
+    }
+}
+
+/// The `git-info` generator.
+///
+/// This defines the macros `\gitcommit`, as the full hash of the commit
+/// checked out in the Git repository containing the directory of the
+/// generated input, `\gitshortcommit`, as its first seven digits, and
+/// `\gitbranch`, as the name of the branch checked out, which is empty if
+/// the `HEAD` is detached. The branch name is detokenized, so that names
+/// containing characters such as `_` can be typeset.
+///
+/// The repository is read directly, without running `git`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct GitInfo;
+
+impl Generator for GitInfo {
+    fn name(&self) -> &str {
+        "git-info"
+    }
+
+    fn generate(
+        &self,
+        ctx: &GenerateContext<'_>,
+        config: &GeneratorConfig,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>> {
+        check_settings(config, &["prefix"])?;
+        let prefix = macro_prefix(config, "git")?;
+
+        let git_dir = a_ok_or!(
+            find_git_dir(ctx.dir);
+            ["no Git repository contains `{}`", ctx.dir.display()]
+        );
+        let (commit, branch) = read_head(&git_dir)?;
+
+        let mut text = String::new();
+        define(&mut text, prefix, "commit", &commit);
+        define(&mut text, prefix, "shortcommit", &commit[..7]);
+        writeln!(
+            text,
+            "\\edef\\{prefix}branch{{\\detokenize{{{}}}}}%",
+            branch.unwrap_or_default()
+        )
+        .unwrap();
+        Ok(text.into_bytes())
+    }
+}
+
+/// Get the prefix of the names of the macros defined by a generator.
+fn macro_prefix<'a>(config: &'a GeneratorConfig, default: &'a str) -> Result<&'a str> {
+    let prefix = config.get("prefix").map_or(default, |s| s.as_str());
+    ensure!(
+        !prefix.is_empty() && prefix.bytes().all(|b| b.is_ascii_alphabetic()),
+        "setting `prefix` must consist of ASCII letters, not `{}`",
+        prefix
+    );
+    Ok(prefix)
+}
+
+fn define(text: &mut String, prefix: &str, name: &str, value: &str) {
+    writeln!(text, "\\def\\{prefix}{name}{{{value}}}%").unwrap();
+}
+
+/// Find the Git directory of the repository containing *dir*.
+fn find_git_dir(dir: &Path) -> Option<PathBuf> {
+    let dir = fs::canonicalize(dir).ok()?;
+
+    for d in dir.ancestors() {
+        let dot_git = d.join(".git");
+
+        if dot_git.is_dir() {
+            return Some(dot_git);
+        }
+
+        // In worktrees and submodules, `.git` is a file pointing to the Git
+        // directory.
+        if let Ok(text) = fs::read_to_string(&dot_git) {
+            if let Some(path) = text.trim().strip_prefix("gitdir:") {
+                return Some(d.join(path.trim()));
+            }
+        }
+    }
+
+    None
+}
+
+/// Read the commit and branch name of the `HEAD` of a repository.
+fn read_head(git_dir: &Path) -> Result<(String, Option<String>)> {
+    let head = atry!(
+        fs::read_to_string(git_dir.join("HEAD"));
+        ["couldn't read the HEAD of the Git repository `{}`", git_dir.display()]
+    );
+    let head = head.trim();
+
+    // Worktrees share the refs of the main repository.
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Ok(text) => git_dir.join(text.trim()),
+        Err(_) => git_dir.to_owned(),
+    };
+
+    let (commit, branch) = match head.strip_prefix("ref:") {
+        Some(name) => {
+            let name = name.trim();
+            let commit = a_ok_or!(
+                resolve_ref(git_dir, &common_dir, name);
+                ["the Git branch `{}` has no commits", name]
+            );
+            let branch = name.strip_prefix("refs/heads/").unwrap_or(name);
+            (commit, Some(branch.to_owned()))
+        }
+
+        None => (head.to_owned(), None),
+    };
+
+    ensure!(
+        commit.len() >= 40 && commit.bytes().all(|b| b.is_ascii_hexdigit()),
+        "unexpected Git commit hash `{}`",
+        commit
+    );
+    Ok((commit, branch))
+}
+
+/// Resolve a Git ref, such as `refs/heads/main`, into a commit hash.
+fn resolve_ref(git_dir: &Path, common_dir: &Path, name: &str) -> Option<String> {
+    for dir in [git_dir, common_dir] {
+        if let Ok(text) = fs::read_to_string(dir.join(name)) {
+            return Some(text.trim().to_owned());
+        }
+    }
+
+    let packed = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+
+    packed
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+        .filter_map(|line| line.split_once(' '))
+        .find(|(_, r)| *r == name)
+        .map(|(commit, _)| commit.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{Duration, SystemTime};
+    use tectonic_status_base::NoopStatusBackend;
+
+    fn generate(g: &dyn Generator, dir: &Path, date: SystemTime, prefix: Option<&str>) -> String {
+        let mut config = GeneratorConfig::new();
+
+        if let Some(p) = prefix {
+            config.insert("prefix".to_owned(), p.to_owned());
+        }
+
+        let ctx = GenerateContext {
+            build_date: date,
+            dir,
+        };
+        let data = g
+            .generate(&ctx, &config, &mut NoopStatusBackend::default())
+            .unwrap();
+        String::from_utf8(data).unwrap()
+    }
+
+    #[test]
+    fn dates() {
+        let date = SystemTime::UNIX_EPOCH + Duration::from_secs(20742 * 86400 + 3600);
+        assert_eq!(
+            generate(&Date, Path::new("."), date, Some("doc")),
+            "\\def\\docdate{2026-10-16}%\n\\def\\docyear{2026}%\n\
+             \\def\\docmonth{10}%\n\\def\\docday{16}%\n"
+        );
+    }
+
+    #[test]
+    fn bad_prefix() {
+        let mut config = GeneratorConfig::new();
+        config.insert("prefix".to_owned(), "my_".to_owned());
+        let ctx = GenerateContext {
+            build_date: SystemTime::UNIX_EPOCH,
+            dir: Path::new("."),
+        };
+        assert!(Date
+            .generate(&ctx, &config, &mut NoopStatusBackend::default())
+            .is_err());
+    }
+
+    #[test]
+    fn git_info() {
+        let repo = tempfile::tempdir().unwrap();
+        let git_dir = repo.path().join(".git");
+        let commit = "0123456789abcdef0123456789abcdef01234567";
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/my_branch\n").unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled\n{commit} refs/heads/my_branch\n"),
+        )
+        .unwrap();
+
+        let sub = repo.path().join("doc");
+        fs::create_dir(&sub).unwrap();
+        let text = generate(&GitInfo, &sub, SystemTime::UNIX_EPOCH, None);
+        assert_eq!(
+            text,
+            format!(
+                "\\def\\gitcommit{{{commit}}}%\n\\def\\gitshortcommit{{0123456}}%\n\
+                 \\edef\\gitbranch{{\\detokenize{{my_branch}}}}%\n"
+            )
+        );
+
+        // Loose refs take precedence over packed ones.
+        let newer = "fedcba9876543210fedcba9876543210fedcba98";
+        fs::write(git_dir.join("refs/heads/my_branch"), format!("{newer}\n")).unwrap();
+        let text = generate(&GitInfo, &sub, SystemTime::UNIX_EPOCH, None);
+        assert!(text.contains(newer));
+
+        fs::write(git_dir.join("HEAD"), format!("{commit}\n")).unwrap();
+        let text = generate(&GitInfo, &sub, SystemTime::UNIX_EPOCH, Some("doc"));
+        assert!(text.contains("\\edef\\docbranch{\\detokenize{}}%"));
+    }
+}
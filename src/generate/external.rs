@@ -0,0 +1,114 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Generating inputs with external programs.
+
+use std::{
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_error, StatusBackend};
+
+use super::{GenerateContext, Generator, GeneratorConfig};
+
+/// A generator that runs an external program.
+///
+/// The program is run in the directory of the generated input, with its
+/// standard input empty, and whatever it prints to its standard output
+/// becomes the contents of the input. Each setting `<key>` of the generated
+/// input is given in an environment variable `TECTONIC_GENERATOR_<KEY>`,
+/// upper-cased.
+///
+/// If the program exits unsuccessfully, the build fails.
+#[derive(Clone, Debug)]
+pub struct ExternalGenerator {
+    argv: Vec<String>,
+}
+
+impl ExternalGenerator {
+    /// Create a generator that runs a program, given as its name or path
+    /// followed by its arguments.
+    ///
+    /// A relative path with more than one component is relative to the
+    /// directory of the generated input, but bare names are looked up in
+    /// `$PATH`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if *argv* is empty.
+    pub fn new<I, S>(argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let argv: Vec<String> = argv.into_iter().map(|a| a.into()).collect();
+        assert!(!argv.is_empty(), "external generators need a program");
+        ExternalGenerator { argv }
+    }
+}
+
+impl Generator for ExternalGenerator {
+    fn name(&self) -> &str {
+        &self.argv[0]
+    }
+
+    fn is_external(&self) -> bool {
+        true
+    }
+
+    fn cache_identity(&self) -> Option<String> {
+        Some(self.argv.join("\0"))
+    }
+
+    fn generate(
+        &self,
+        ctx: &GenerateContext<'_>,
+        config: &GeneratorConfig,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>> {
+        let mut program = PathBuf::from(&self.argv[0]);
+
+        if program.is_relative() && program.components().count() > 1 {
+            program = ctx.dir.join(program);
+        }
+
+        let mut cmd = Command::new(&program);
+        cmd.args(&self.argv[1..])
+            .current_dir(ctx.dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        for (key, value) in config {
+            let var: String = key
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_uppercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            cmd.env(format!("TECTONIC_GENERATOR_{var}"), value);
+        }
+
+        let output = atry!(
+            cmd.output();
+            ["failed to run `{}`", program.display()]
+        );
+
+        if !output.status.success() {
+            tt_error!(status, "`{}` failed; its stderr was:\n", program.display());
+            status.dump_error_logs(&output.stderr[..]);
+
+            match output.status.code() {
+                Some(n) => bail!("`{}` exited with error code {}", program.display(), n),
+                None => bail!("`{}` was terminated by a signal", program.display()),
+            }
+        }
+
+        Ok(output.stdout)
+    }
+}
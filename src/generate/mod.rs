@@ -0,0 +1,407 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Input files generated before a build.
+//!
+//! Documents sometimes need text that doesn't live in their source tree, such
+//! as the date of the build, the commit that it was built from, or a table
+//! computed from a data file. With mainline TeX engines, this is usually done
+//! with piped input, as in `\input{|"git describe"}`, which runs arbitrary
+//! commands in the middle of the build and requires shell-escape. Tectonic
+//! instead lets the caller declare named [`GeneratedInput`]s up front. Before
+//! the engines run, the [`Generator`] of each one is run, and its output is
+//! provided to the engines as a virtual input file, which documents can read
+//! either as `\input{<name>}` or, for compatibility with piped input, as
+//! `\input{|<name>}`.
+//!
+//! Tectonic has built-in generators for some common needs, which can be
+//! looked up by name with [`builtin`]. Other programs can be run as
+//! generators with [`ExternalGenerator`]. The output of a generated input
+//! that lists the files that it's computed from is cached, keyed by their
+//! contents, and every build records where each of its generated inputs came
+//! from; see [`GeneratedInputRecord`].
+
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::SystemTime,
+};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_warning, StatusBackend};
+
+use crate::digest::{self, Digest, DigestData};
+
+mod builtin;
+mod external;
+
+pub use builtin::{Date, GitInfo};
+pub use external::ExternalGenerator;
+
+/// The settings of a generated input.
+///
+/// The meanings of the keys depend on the generator.
+pub type GeneratorConfig = BTreeMap<String, String>;
+
+/// The circumstances in which a generator is run.
+#[derive(Clone, Copy, Debug)]
+pub struct GenerateContext<'a> {
+    /// The date and time of the build, which should be used instead of the
+    /// current time so that deterministic builds stay deterministic.
+    pub build_date: SystemTime,
+
+    /// The directory of the generated input, in which external programs are
+    /// run.
+    pub dir: &'a Path,
+}
+
+/// A source of generated input files.
+pub trait Generator {
+    /// The name of this generator, for use in messages.
+    fn name(&self) -> &str;
+
+    /// Whether this generator runs programs outside of Tectonic.
+    ///
+    /// Such generators aren't run if known-insecure features have been
+    /// disabled.
+    fn is_external(&self) -> bool {
+        false
+    }
+
+    /// A description of what this generator does, for use in cache keys.
+    ///
+    /// If this is `Some`, the output of the generator must only depend on
+    /// this description, the settings, and the contents of the files that
+    /// the generated input lists, so that it can be cached. The default is
+    /// `None`, in which case the generator is run on every build.
+    fn cache_identity(&self) -> Option<String> {
+        None
+    }
+
+    /// Produce the contents of a generated input.
+    ///
+    /// The *config* holds the settings of the generated input. Generators
+    /// should reject settings that they don't understand.
+    fn generate(
+        &self,
+        ctx: &GenerateContext<'_>,
+        config: &GeneratorConfig,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>>;
+}
+
+/// The names of the built-in generators.
+pub const BUILTIN_NAMES: &[&str] = &["date", "git-info"];
+
+/// Look up a built-in generator by name.
+///
+/// See [`BUILTIN_NAMES`] for the names that are recognized.
+pub fn builtin(name: &str) -> Option<Rc<dyn Generator>> {
+    match name {
+        "date" => Some(Rc::new(Date)),
+        "git-info" => Some(Rc::new(GitInfo)),
+        _ => None,
+    }
+}
+
+/// An input file whose contents are produced by a generator.
+#[derive(Clone)]
+pub struct GeneratedInput {
+    name: String,
+    generator: Rc<dyn Generator>,
+    dir: PathBuf,
+    inputs: Vec<String>,
+    config: GeneratorConfig,
+}
+
+impl fmt::Debug for GeneratedInput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GeneratedInput")
+            .field("name", &self.name)
+            .field("generator", &self.generator.name())
+            .field("dir", &self.dir)
+            .field("inputs", &self.inputs)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl GeneratedInput {
+    /// Create an input file, with the given name, whose contents are produced
+    /// by the given generator with no settings.
+    pub fn new(name: impl Into<String>, generator: Rc<dyn Generator>) -> Self {
+        GeneratedInput {
+            name: name.into(),
+            generator,
+            dir: PathBuf::from("."),
+            inputs: Vec::new(),
+            config: GeneratorConfig::new(),
+        }
+    }
+
+    /// Set the directory in which external generators are run, and relative
+    /// to which the paths of the input's own inputs are resolved.
+    ///
+    /// By default, this is the current directory.
+    pub fn dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.dir = dir.into();
+        self
+    }
+
+    /// Set the paths of the files that the contents of this input are
+    /// computed from.
+    ///
+    /// If any are given and the generator supports it, the output of the
+    /// generator is cached until the contents of these files change. The
+    /// files are also dependencies of the build, so that `tectonic -X watch`
+    /// rebuilds the document when they change.
+    pub fn inputs<I, S>(&mut self, inputs: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.inputs = inputs.into_iter().map(|i| i.into()).collect();
+        self
+    }
+
+    /// Set the settings passed to the generator.
+    pub fn config(&mut self, config: GeneratorConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the name of this input file.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the generator that produces this input.
+    pub fn generator(&self) -> &dyn Generator {
+        &*self.generator
+    }
+
+    /// The full paths of the files that the contents of this input are
+    /// computed from.
+    pub(crate) fn input_paths(&self) -> impl Iterator<Item = PathBuf> + '_ {
+        self.inputs.iter().map(|i| self.dir.join(i))
+    }
+
+    /// Compute the key under which the output of the generator is cached, if
+    /// it can be.
+    fn cache_key(&self) -> Result<Option<String>> {
+        if self.inputs.is_empty() {
+            return Ok(None);
+        }
+
+        let Some(identity) = self.generator.cache_identity() else {
+            return Ok(None);
+        };
+
+        let mut dc = digest::create();
+        dc.update(b"tectonic-generated-input-1\0");
+        dc.update(identity.as_bytes());
+        dc.update(b"\0");
+
+        for (key, value) in &self.config {
+            dc.update(format!("{key}={value}\0").as_bytes());
+        }
+
+        for (name, path) in self.inputs.iter().zip(self.input_paths()) {
+            let data = atry!(
+                fs::read(&path);
+                ["couldn't read `{}`, an input of generated file `{}`", path.display(), self.name]
+            );
+            dc.update(format!("{name}\0{}\0", data.len()).as_bytes());
+            dc.update(&data);
+        }
+
+        Ok(Some(DigestData::from(dc).to_string()))
+    }
+}
+
+/// Where the contents of a generated input came from during a build.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GeneratedInputRecord {
+    /// The name of the input file.
+    pub name: String,
+
+    /// The name of the generator that produced it.
+    pub generator: String,
+
+    /// The paths of the files that it was computed from, as given.
+    pub inputs: Vec<String>,
+
+    /// The key under which its contents are cached, if they can be.
+    pub cache_key: Option<String>,
+
+    /// The SHA-256 digest of its contents, in hexadecimal.
+    pub digest: String,
+
+    /// Whether its contents were taken from the cache rather than generated.
+    pub cached: bool,
+}
+
+/// Produce the contents of a generated input, using the cache in
+/// *cache_dir* if one is given.
+pub(crate) fn run(
+    input: &GeneratedInput,
+    build_date: SystemTime,
+    cache_dir: Option<&Path>,
+    status: &mut dyn StatusBackend,
+) -> Result<(Vec<u8>, GeneratedInputRecord)> {
+    let cache_key = input.cache_key()?;
+    let cache_path = cache_dir.zip(cache_key.as_ref()).map(|(d, k)| d.join(k));
+    let cached_data = cache_path.as_ref().and_then(|p| fs::read(p).ok());
+    let cached = cached_data.is_some();
+
+    let data = match cached_data {
+        Some(data) => data,
+
+        None => {
+            status.note_highlighted(
+                "Running generator ",
+                input.generator.name(),
+                &format!(" for `{}` ...", input.name),
+            );
+
+            let ctx = GenerateContext {
+                build_date,
+                dir: &input.dir,
+            };
+
+            let data = atry!(
+                input.generator.generate(&ctx, &input.config, status);
+                ["generator `{}` failed for `{}`", input.generator.name(), input.name]
+            );
+
+            if let Some(path) = cache_path.as_ref() {
+                // The cache is only an optimization, so failing to fill it
+                // isn't fatal.
+                let saved = path
+                    .parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(path, &data));
+
+                if let Err(e) = saved {
+                    tt_warning!(status, "couldn't cache generated file `{}`", input.name; e.into());
+                }
+            }
+
+            data
+        }
+    };
+
+    let record = GeneratedInputRecord {
+        name: input.name.clone(),
+        generator: input.generator.name().to_owned(),
+        inputs: input.inputs.clone(),
+        cache_key,
+        digest: DigestData::from(digest::create().chain_update(&data)).to_string(),
+        cached,
+    };
+
+    Ok((data, record))
+}
+
+/// Write out records of generated inputs, one per line, with tab-separated
+/// fields.
+pub(crate) fn write_records<W: Write>(
+    records: &[GeneratedInputRecord],
+    dest: &mut W,
+) -> std::io::Result<()> {
+    writeln!(
+        dest,
+        "# name\tgenerator\tinputs\tcache key\toutput digest\tsource"
+    )?;
+
+    for r in records {
+        let inputs = if r.inputs.is_empty() {
+            "-".to_owned()
+        } else {
+            r.inputs.join(",")
+        };
+
+        writeln!(
+            dest,
+            "{}\t{}\t{}\t{}\t{}\t{}",
+            r.name,
+            r.generator,
+            inputs,
+            r.cache_key.as_deref().unwrap_or("-"),
+            r.digest,
+            if r.cached { "cache" } else { "generated" }
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tectonic_status_base::NoopStatusBackend;
+
+    #[cfg(unix)]
+    #[test]
+    fn cached_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = dir.path().join("cache");
+        fs::write(dir.path().join("data.csv"), "a,b\n").unwrap();
+
+        let mut input = GeneratedInput::new("table.tex", Rc::new(ExternalGenerator::new(["cat"])));
+        input.dir(dir.path()).inputs(["data.csv"]);
+
+        let mut status = NoopStatusBackend::default();
+        let date = SystemTime::UNIX_EPOCH;
+
+        // `cat` with no arguments echoes its empty standard input.
+        let (data, record) = run(&input, date, Some(&cache), &mut status).unwrap();
+        assert!(data.is_empty());
+        assert!(!record.cached);
+        let key = record.cache_key.unwrap();
+
+        // Doctor the cached copy, so that we can tell that it's used.
+        fs::write(cache.join(&key), "cached").unwrap();
+        let (data, record) = run(&input, date, Some(&cache), &mut status).unwrap();
+        assert_eq!(data, b"cached");
+        assert!(record.cached);
+
+        // Changing an input invalidates the cache.
+        fs::write(dir.path().join("data.csv"), "a,b\n1,2\n").unwrap();
+        let (data, record) = run(&input, date, Some(&cache), &mut status).unwrap();
+        assert!(data.is_empty());
+        assert!(!record.cached);
+        assert_ne!(record.cache_key.unwrap(), key);
+
+        // Inputs without listed files, or with built-in generators, aren't
+        // cached.
+        let later = date + Duration::from_secs(86400);
+        let input = GeneratedInput::new("date.tex", builtin("date").unwrap());
+        let (data, record) = run(&input, later, Some(&cache), &mut status).unwrap();
+        assert!(String::from_utf8(data).unwrap().contains("{1970-01-02}"));
+        assert_eq!(record.cache_key, None);
+    }
+
+    #[test]
+    fn records() {
+        let records = [GeneratedInputRecord {
+            name: "table.tex".to_owned(),
+            generator: "python3".to_owned(),
+            inputs: vec!["a.csv".to_owned(), "b.csv".to_owned()],
+            cache_key: None,
+            digest: "00ff".to_owned(),
+            cached: false,
+        }];
+
+        let mut out = Vec::new();
+        write_records(&records, &mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert_eq!(
+            text.lines().nth(1),
+            Some("table.tex\tpython3\ta.csv,b.csv\t-\t00ff\tgenerated")
+        );
+    }
+}
@@ -0,0 +1,250 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Checking build outputs against recorded "golden" digests.
+//!
+//! Upgrading Tectonic or switching to a new bundle can change the output of a
+//! document in subtle ways. This module lets users record the SHA256 digests
+//! of the files produced by a known-good build, and later check new builds
+//! against them. This gives a cheap regression test for their own documents.
+//!
+//! Outputs often contain values that change on every build, such as the
+//! creation date and the file identifier of a PDF. When digests are recorded
+//! in normalized mode, these values are replaced with fixed placeholders
+//! before hashing, so that only meaningful changes are detected.
+
+use regex::bytes::Regex;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, collections::BTreeMap, fmt, fs, path::Path};
+use tectonic_errors::prelude::*;
+
+use crate::digest::{self, Digest, DigestData};
+
+/// The version of the golden-digest file format that we write.
+const FORMAT_VERSION: u32 = 1;
+
+/// A set of digests of build outputs.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct GoldenDigests {
+    /// The version of the file format.
+    version: u32,
+
+    /// Whether volatile values were normalized before the outputs were
+    /// hashed.
+    normalized: bool,
+
+    /// Hex-encoded SHA256 digests of the outputs, keyed by their paths.
+    files: BTreeMap<String, String>,
+}
+
+impl GoldenDigests {
+    /// Compute the digests of a set of output files.
+    ///
+    /// If *normalized* is true, volatile values in the files are normalized
+    /// before they're hashed; see [`normalize`].
+    pub fn compute<'a, I>(files: I, normalized: bool) -> Self
+    where
+        I: IntoIterator<Item = (&'a str, &'a [u8])>,
+    {
+        let files = files
+            .into_iter()
+            .map(|(name, data)| {
+                let data = if normalized {
+                    normalize(name, data)
+                } else {
+                    Cow::Borrowed(data)
+                };
+
+                let mut dc = digest::create();
+                dc.update(&data);
+                (name.to_owned(), DigestData::from(dc).to_string())
+            })
+            .collect();
+
+        GoldenDigests {
+            version: FORMAT_VERSION,
+            normalized,
+            files,
+        }
+    }
+
+    /// Load digests from a JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let path = path.as_ref();
+        let text = atry!(
+            fs::read_to_string(path);
+            ["failed to read golden digests from `{}`", path.display()]
+        );
+        let golden: GoldenDigests = atry!(
+            serde_json::from_str(&text);
+            ["failed to parse golden digests in `{}`", path.display()]
+        );
+
+        ensure!(
+            golden.version == FORMAT_VERSION,
+            "golden digests in `{}` have unsupported format version {}",
+            path.display(),
+            golden.version
+        );
+
+        Ok(golden)
+    }
+
+    /// Save these digests to a JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        let mut text = serde_json::to_string_pretty(self)?;
+        text.push('\n');
+        atry!(
+            fs::write(path, text);
+            ["failed to write golden digests to `{}`", path.display()]
+        );
+        Ok(())
+    }
+
+    /// Whether volatile values were normalized when these digests were
+    /// computed.
+    pub fn is_normalized(&self) -> bool {
+        self.normalized
+    }
+
+    /// Get the number of files that have digests.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether there are no digests at all.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Only keep the digests of files whose names satisfy a predicate.
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut f: F) {
+        self.files.retain(|name, _| f(name));
+    }
+
+    /// Compare a set of expected digests, `self`, with the digests of the
+    /// outputs of a new build.
+    pub fn compare(&self, actual: &GoldenDigests) -> GoldenDiff {
+        let mut diff = GoldenDiff::default();
+
+        for (name, expected) in &self.files {
+            match actual.files.get(name) {
+                Some(d) if d == expected => {}
+                Some(_) => diff.changed.push(name.clone()),
+                None => diff.missing.push(name.clone()),
+            }
+        }
+
+        for name in actual.files.keys() {
+            if !self.files.contains_key(name) {
+                diff.added.push(name.clone());
+            }
+        }
+
+        diff
+    }
+}
+
+/// The differences between recorded digests and the outputs of a build.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GoldenDiff {
+    /// Files whose contents differ from what was recorded.
+    pub changed: Vec<String>,
+
+    /// Files that were recorded, but not produced by the build.
+    pub missing: Vec<String>,
+
+    /// Files that were produced by the build, but not recorded.
+    pub added: Vec<String>,
+}
+
+impl GoldenDiff {
+    /// Whether the build matched the recorded digests exactly.
+    pub fn is_empty(&self) -> bool {
+        self.changed.is_empty() && self.missing.is_empty() && self.added.is_empty()
+    }
+}
+
+impl fmt::Display for GoldenDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sections = [
+            ("changed", &self.changed),
+            ("missing", &self.missing),
+            ("added", &self.added),
+        ];
+
+        let mut first = true;
+
+        for (label, names) in sections {
+            for name in names {
+                if !first {
+                    writeln!(f)?;
+                }
+
+                write!(f, "  {label}: {name}")?;
+                first = false;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Normalize the volatile values in an output file.
+///
+/// In PDF files, the creation and modification dates, and the file
+/// identifier, are replaced with fixed values. In XDV and SPX files, the date
+/// in the preamble comment is replaced. Other files are returned unchanged.
+pub fn normalize<'a>(name: &str, data: &'a [u8]) -> Cow<'a, [u8]> {
+    if name.ends_with(".pdf") {
+        let dates = Regex::new(r"(/(?:CreationDate|ModDate)\s*)\(D:[^)]*\)").unwrap();
+        let id = Regex::new(r"/ID\s*\[\s*<[0-9A-Fa-f]*>\s*<[0-9A-Fa-f]*>\s*\]").unwrap();
+
+        match dates.replace_all(data, &b"${1}(D:19700101000000Z)"[..]) {
+            Cow::Borrowed(d) => id.replace_all(d, &b"/ID [<> <>]"[..]),
+            Cow::Owned(d) => Cow::Owned(id.replace_all(&d, &b"/ID [<> <>]"[..]).into_owned()),
+        }
+    } else if name.ends_with(".xdv") || name.ends_with(".spx") {
+        let date = Regex::new(r" XeTeX output \d{4}\.\d{2}\.\d{2}:\d{4}").unwrap();
+        date.replace(data, &b" XeTeX output 1970.01.01:0000"[..])
+    } else {
+        Cow::Borrowed(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_pdf() {
+        let a = b"<< /CreationDate (D:20250101120000+01'00') >> /ID [<0A1B> <0A1B>]";
+        let b = b"<< /CreationDate (D:20240606060606Z) >> /ID [ <FFFF><EEEE> ]";
+        assert_eq!(normalize("doc.pdf", a), normalize("doc.pdf", b));
+        assert_eq!(normalize("doc.html", a), Cow::Borrowed(&a[..]));
+    }
+
+    #[test]
+    fn compare_digests() {
+        let golden = GoldenDigests::compute(
+            [("a/doc.pdf", &b"one"[..]), ("a/doc.log", &b"two"[..])],
+            false,
+        );
+        let actual = GoldenDigests::compute(
+            [("a/doc.pdf", &b"uno"[..]), ("a/extra.txt", &b"three"[..])],
+            false,
+        );
+
+        assert!(golden.compare(&golden).is_empty());
+
+        let diff = golden.compare(&actual);
+        assert_eq!(diff.changed, ["a/doc.pdf"]);
+        assert_eq!(diff.missing, ["a/doc.log"]);
+        assert_eq!(diff.added, ["a/extra.txt"]);
+        assert_eq!(
+            diff.to_string(),
+            "  changed: a/doc.pdf\n  missing: a/doc.log\n  added: a/extra.txt"
+        );
+    }
+}
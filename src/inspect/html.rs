@@ -0,0 +1,76 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Inspecting HTML outputs.
+
+use std::path::Path;
+
+use super::{Contributor, ContributorKind, SizeBreakdown};
+
+/// Break down the size of an HTML output, given the names and sizes of its
+/// files.
+///
+/// Each file is a contributor of its own. Font and image files are
+/// recognized by their extensions, and everything else, including the HTML
+/// files themselves, is [`ContributorKind::Other`].
+pub fn inspect_html<I, S>(files: I) -> SizeBreakdown
+where
+    I: IntoIterator<Item = (S, u64)>,
+    S: Into<String>,
+{
+    let mut breakdown = SizeBreakdown::default();
+
+    for (name, size) in files {
+        let name = name.into();
+        let ext = Path::new(&name)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        let kind = match ext.as_str() {
+            "otf" | "ttf" | "woff" | "woff2" => ContributorKind::Font,
+            "avif" | "gif" | "jpeg" | "jpg" | "png" | "svg" | "webp" => ContributorKind::Image,
+            _ => ContributorKind::Other,
+        };
+
+        breakdown.total += size;
+        breakdown
+            .contributors
+            .push(Contributor { kind, name, size });
+    }
+
+    breakdown.sort();
+    breakdown
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn kinds() {
+        let breakdown = inspect_html([
+            ("index.html", 300),
+            ("fonts/lmroman10-regular.OTF", 1000),
+            ("figure.png", 2000),
+            ("tdux.css", 300),
+        ]);
+
+        assert_eq!(breakdown.total, 3600);
+        let summary: Vec<_> = breakdown
+            .contributors
+            .iter()
+            .map(|c| (c.kind, c.name.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (ContributorKind::Image, "figure.png"),
+                (ContributorKind::Font, "fonts/lmroman10-regular.OTF"),
+                (ContributorKind::Other, "index.html"),
+                (ContributorKind::Other, "tdux.css"),
+            ]
+        );
+    }
+}
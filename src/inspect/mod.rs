@@ -0,0 +1,80 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Inspecting what takes up space in the outputs of a build.
+//!
+//! Submission systems often limit the sizes of the files that they accept,
+//! and when a document is over the limit, the question is what to shrink.
+//! The functions here break the size of an output down into its largest
+//! contributors: [`inspect_pdf`] finds the embedded fonts, images, and
+//! attachments of a PDF file, and [`inspect_html`] sorts the files of an
+//! HTML output into fonts, images, and the rest.
+
+use std::fmt;
+
+mod html;
+mod pdf;
+
+pub use html::inspect_html;
+pub use pdf::inspect_pdf;
+
+/// The kinds of things that take up space in an output.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum ContributorKind {
+    /// An embedded font, or a font file.
+    Font,
+
+    /// An image.
+    Image,
+
+    /// An embedded file.
+    Attachment,
+
+    /// Anything else, such as page contents, or the structure of the file.
+    Other,
+}
+
+impl fmt::Display for ContributorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            ContributorKind::Font => "font",
+            ContributorKind::Image => "image",
+            ContributorKind::Attachment => "attachment",
+            ContributorKind::Other => "other",
+        })
+    }
+}
+
+/// Something that takes up space in an output.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Contributor {
+    /// What kind of thing this is.
+    pub kind: ContributorKind,
+
+    /// A description of the thing, such as the name of a font, for use in
+    /// messages.
+    pub name: String,
+
+    /// The number of bytes that it takes up.
+    pub size: u64,
+}
+
+/// The breakdown of the size of an output.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct SizeBreakdown {
+    /// The total size of the output, in bytes.
+    pub total: u64,
+
+    /// The things that take up space in the output, largest first. Their
+    /// sizes add up to the total.
+    pub contributors: Vec<Contributor>,
+}
+
+impl SizeBreakdown {
+    /// Sort the contributors, largest first, and then by name, so that the
+    /// order is predictable.
+    fn sort(&mut self) {
+        self.contributors
+            .sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.name.cmp(&b.name)));
+    }
+}
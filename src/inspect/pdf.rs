@@ -0,0 +1,466 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Inspecting PDF outputs.
+//!
+//! This uses the bits of PDF parsing that the `pdf-compress` post-processor
+//! needs, which are enough to find the objects of a file, measure them, and
+//! look at their dictionaries.
+
+use flate2::read::ZlibDecoder;
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Read,
+};
+use tectonic_errors::prelude::*;
+
+use super::{Contributor, ContributorKind, SizeBreakdown};
+use crate::postprocess::pdf::{
+    find_startxref, parse_u64, parse_xref, skip_ws, stream_end, token, Dict, XrefEntry,
+};
+
+/// Break down the size of a PDF file.
+///
+/// The font programs embedded in the file are found through their font
+/// descriptors, and grouped by font name. Each image and embedded file is a
+/// contributor of its own, and everything else, such as the contents of the
+/// pages and the structure of the file, is lumped together as
+/// [`ContributorKind::Other`]. Objects are measured as they're stored in
+/// the file, so compressed streams count at their compressed sizes.
+pub fn inspect_pdf(data: &[u8]) -> Result<SizeBreakdown> {
+    ensure!(data.starts_with(b"%PDF-"), "missing PDF header");
+
+    // Gather the cross-reference data, following the chain of sections of a
+    // file that has been updated. Later sections take precedence.
+
+    let mut entries = BTreeMap::new();
+    let mut boundaries = vec![data.len() as u64];
+    let mut seen = HashSet::new();
+    let mut next = Some(find_startxref(data)?);
+
+    while let Some(ofs) = next {
+        ensure!(seen.insert(ofs), "the cross-reference sections form a loop");
+        let xref = parse_xref(data, ofs)?;
+        boundaries.push(ofs);
+
+        for (num, e) in xref.entries {
+            entries.entry(num).or_insert(e);
+        }
+
+        next = xref.trailer.get("Prev").and_then(|r| parse_u64(&data[r]));
+    }
+
+    // Each object runs until the start of the next one, or of the
+    // cross-reference data.
+
+    let mut objects: Vec<(u64, u64)> = entries
+        .iter()
+        .filter_map(|(num, e)| match e {
+            XrefEntry::InFile(ofs, _) if (*ofs as usize) < data.len() => Some((*ofs, *num)),
+            _ => None,
+        })
+        .collect();
+    objects.sort_unstable();
+    boundaries.extend(objects.iter().map(|o| o.0));
+    boundaries.sort_unstable();
+    boundaries.dedup();
+
+    let mut infos = Vec::new();
+    let mut refs = References::default();
+
+    for &(ofs, num) in &objects {
+        let end = boundaries[boundaries.partition_point(|b| *b <= ofs)];
+        let span = &data[ofs as usize..end as usize];
+
+        // Objects that we can't make sense of just count as "other".
+        let kind = look_at_object(span, &mut refs).unwrap_or(ObjectKind::Other);
+
+        infos.push((num, span.len() as u64, kind));
+    }
+
+    // Now that we know what refers to what, sort the objects out.
+
+    let mut breakdown = SizeBreakdown {
+        total: data.len() as u64,
+        ..SizeBreakdown::default()
+    };
+    let mut fonts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut n_classified = 0;
+
+    for (num, size, kind) in infos {
+        if let Some(name) = refs.font_programs.get(&num) {
+            *fonts.entry(name.clone()).or_default() += size;
+            n_classified += size;
+            continue;
+        }
+
+        let (kind, name) = match kind {
+            ObjectKind::Image { width, height } => (
+                ContributorKind::Image,
+                format!("image {num} ({width}x{height} pixels)"),
+            ),
+
+            ObjectKind::EmbeddedFile => (
+                ContributorKind::Attachment,
+                refs.attachments
+                    .get(&num)
+                    .cloned()
+                    .unwrap_or_else(|| format!("embedded file {num}")),
+            ),
+
+            ObjectKind::FontProgram => (ContributorKind::Font, format!("font program {num}")),
+
+            ObjectKind::Other => continue,
+        };
+
+        breakdown
+            .contributors
+            .push(Contributor { kind, name, size });
+        n_classified += size;
+    }
+
+    for (name, size) in fonts {
+        breakdown.contributors.push(Contributor {
+            kind: ContributorKind::Font,
+            name,
+            size,
+        });
+    }
+
+    let rest = breakdown.total.saturating_sub(n_classified);
+
+    if rest > 0 {
+        breakdown.contributors.push(Contributor {
+            kind: ContributorKind::Other,
+            name: "everything else".to_owned(),
+            size: rest,
+        });
+    }
+
+    breakdown.sort();
+    Ok(breakdown)
+}
+
+/// What an object is, as far as we can tell from the object itself.
+#[derive(Clone, Copy, Debug)]
+enum ObjectKind {
+    Image { width: u64, height: u64 },
+    EmbeddedFile,
+    FontProgram,
+    Other,
+}
+
+/// What the dictionaries of the file say about other objects.
+#[derive(Debug, Default)]
+struct References {
+    /// The names of the fonts of font programs, by object number.
+    font_programs: HashMap<u64, String>,
+
+    /// The names of embedded files, by object number.
+    attachments: HashMap<u64, String>,
+}
+
+impl References {
+    fn note_dict(&mut self, buf: &[u8], dict: &Dict) {
+        if has_name(buf, dict, "Type", b"/FontDescriptor") {
+            let name = match dict.get("FontName") {
+                Some(r) => {
+                    let v = &buf[r];
+                    String::from_utf8_lossy(v.strip_prefix(b"/").unwrap_or(v)).into_owned()
+                }
+                None => "unnamed font".to_owned(),
+            };
+
+            for key in ["FontFile", "FontFile2", "FontFile3"] {
+                if let Some(num) = dict.get(key).and_then(|r| parse_ref(&buf[r])) {
+                    self.font_programs.insert(num, name.clone());
+                }
+            }
+        }
+
+        // File specifications point to the files that they embed.
+        let Some(ef) = dict.get("EF") else {
+            return;
+        };
+
+        if !buf[ef.clone()].starts_with(b"<<") {
+            return;
+        }
+
+        let Ok(ef) = Dict::parse(buf, ef.start) else {
+            return;
+        };
+
+        let Some(name) = dict.get("UF").or_else(|| dict.get("F")) else {
+            return;
+        };
+        let name = decode_string(&buf[name]);
+
+        for key in ["UF", "F"] {
+            if let Some(num) = ef.get(key).and_then(|r| parse_ref(&buf[r])) {
+                self.attachments.insert(num, name.clone());
+            }
+        }
+    }
+}
+
+/// Look at the object in *span*, noting what its dictionary says about other
+/// objects, and figure out what kind of object it is.
+fn look_at_object(span: &[u8], refs: &mut References) -> Result<ObjectKind> {
+    let mut pos = skip_ws(span, 0);
+
+    for _ in 0..2 {
+        let (tok, end) = token(span, pos);
+        ensure!(parse_u64(tok).is_some(), "malformed object header");
+        pos = skip_ws(span, end);
+    }
+
+    let (tok, end) = token(span, pos);
+    ensure!(tok == b"obj", "malformed object header");
+    pos = skip_ws(span, end);
+
+    if !span[pos..].starts_with(b"<<") {
+        return Ok(ObjectKind::Other);
+    }
+
+    let dict = Dict::parse(span, pos)?;
+    refs.note_dict(span, &dict);
+
+    let (tok, mut data_start) = token(span, skip_ws(span, dict.end));
+
+    if tok != b"stream" {
+        return Ok(ObjectKind::Other);
+    }
+
+    if has_name(span, &dict, "Subtype", b"/Image") {
+        let dim = |key| dict.get(key).and_then(|r| parse_u64(&span[r])).unwrap_or(0);
+
+        return Ok(ObjectKind::Image {
+            width: dim("Width"),
+            height: dim("Height"),
+        });
+    }
+
+    if has_name(span, &dict, "Type", b"/EmbeddedFile") {
+        return Ok(ObjectKind::EmbeddedFile);
+    }
+
+    // Font programs that we don't find through a font descriptor can still
+    // be recognized by the keys that the different formats require.
+    if ["Length1", "Length2", "Length3"]
+        .iter()
+        .any(|k| dict.get(k).is_some())
+        || [&b"/Type1C"[..], b"/CIDFontType0C", b"/OpenType"]
+            .iter()
+            .any(|n| has_name(span, &dict, "Subtype", n))
+    {
+        return Ok(ObjectKind::FontProgram);
+    }
+
+    // The dictionaries of other objects may be packed into object streams.
+
+    if has_name(span, &dict, "Type", b"/ObjStm") {
+        if span[data_start..].starts_with(b"\r\n") {
+            data_start += 2;
+        } else if span[data_start..].starts_with(b"\n") {
+            data_start += 1;
+        }
+
+        let data_end = stream_end(span, &dict, data_start)?;
+        let raw = &span[data_start..data_end];
+
+        let decoded = match dict.get("Filter").map(|r| &span[r]) {
+            _ if dict.get("DecodeParms").is_some() => return Ok(ObjectKind::Other),
+            None => raw.to_vec(),
+            Some(b"/FlateDecode") | Some(b"[/FlateDecode]") => {
+                let mut buf = Vec::new();
+                ZlibDecoder::new(raw).read_to_end(&mut buf)?;
+                buf
+            }
+            Some(_) => return Ok(ObjectKind::Other),
+        };
+
+        let n = a_ok_or!(
+            dict.get("N").and_then(|r| parse_u64(&span[r]));
+            ["object stream has no count"]
+        );
+        let first = a_ok_or!(
+            dict.get("First").and_then(|r| parse_u64(&span[r]));
+            ["object stream has no offset"]
+        ) as usize;
+
+        let mut p = 0;
+
+        for _ in 0..n {
+            // Each object is preceded by its number and offset.
+            let (tok, end) = token(&decoded, skip_ws(&decoded, p));
+            ensure!(parse_u64(tok).is_some(), "malformed object stream");
+            let (tok, end) = token(&decoded, skip_ws(&decoded, end));
+            let ofs = a_ok_or!(parse_u64(tok); ["malformed object stream"]) as usize;
+            p = end;
+
+            let start = skip_ws(&decoded, first.saturating_add(ofs));
+
+            if decoded[start..].starts_with(b"<<") {
+                refs.note_dict(&decoded, &Dict::parse(&decoded, start)?);
+            }
+        }
+    }
+
+    Ok(ObjectKind::Other)
+}
+
+/// Check whether the value of a key of a dictionary is the given name.
+fn has_name(buf: &[u8], dict: &Dict, key: &str, name: &[u8]) -> bool {
+    dict.get(key).is_some_and(|r| &buf[r] == name)
+}
+
+/// Get the object number of an indirect reference, `<num> <gen> R`.
+fn parse_ref(value: &[u8]) -> Option<u64> {
+    let (num, end) = token(value, 0);
+    let num = parse_u64(num)?;
+    let (gen, end) = token(value, skip_ws(value, end));
+    parse_u64(gen)?;
+    let (r, _) = token(value, skip_ws(value, end));
+    (r == b"R").then_some(num)
+}
+
+/// Decode a string, such as the name of an embedded file, for use in
+/// messages.
+fn decode_string(value: &[u8]) -> String {
+    let mut bytes = Vec::new();
+
+    if let Some(hex) = value.strip_prefix(b"<").and_then(|v| v.strip_suffix(b">")) {
+        let digits: Vec<u8> = hex
+            .iter()
+            .filter_map(|b| (*b as char).to_digit(16).map(|d| d as u8))
+            .collect();
+
+        for pair in digits.chunks(2) {
+            bytes.push((pair[0] << 4) | pair.get(1).copied().unwrap_or(0));
+        }
+    } else if let Some(text) = value.strip_prefix(b"(").and_then(|v| v.strip_suffix(b")")) {
+        let mut iter = text.iter().copied().peekable();
+
+        while let Some(b) = iter.next() {
+            if b != b'\\' {
+                bytes.push(b);
+                continue;
+            }
+
+            match iter.next() {
+                Some(b'n') => bytes.push(b'\n'),
+                Some(b'r') => bytes.push(b'\r'),
+                Some(b't') => bytes.push(b'\t'),
+                Some(b'b') => bytes.push(b'\x08'),
+                Some(b'f') => bytes.push(b'\x0c'),
+                Some(d @ b'0'..=b'7') => {
+                    let mut n = u32::from(d - b'0');
+
+                    for _ in 0..2 {
+                        match iter.peek() {
+                            Some(d @ b'0'..=b'7') => {
+                                n = n * 8 + u32::from(d - b'0');
+                                iter.next();
+                            }
+                            _ => break,
+                        }
+                    }
+
+                    bytes.push(n as u8);
+                }
+                Some(b'\r') | Some(b'\n') | None => {}
+                Some(other) => bytes.push(other),
+            }
+        }
+    } else {
+        return String::from_utf8_lossy(value).into_owned();
+    }
+
+    // Text strings are either UTF-16 with a byte order mark, or in
+    // PDFDocEncoding, which is close enough to Latin-1 for our purposes.
+    if let Some(utf16) = bytes.strip_prefix(b"\xfe\xff") {
+        let units: Vec<u16> = utf16
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        String::from_utf16_lossy(&units)
+    } else {
+        bytes.iter().map(|b| *b as char).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pdf_with_table;
+
+    fn stream(dict: &str, len: usize) -> Vec<u8> {
+        let mut body = format!("<<{dict} /Length {len}>>\nstream\n").into_bytes();
+        body.resize(body.len() + len, b'x');
+        body.extend_from_slice(b"\nendstream");
+        body
+    }
+
+    #[test]
+    fn breakdown() {
+        // The descriptor of the second font is packed into an object stream,
+        // as xdvipdfmx does.
+        let packed = b"10 0 <</Type /FontDescriptor /FontName /Other /FontFile2 7 0 R>>";
+        let mut objstm = format!(
+            "<</Type /ObjStm /N 1 /First 5 /Length {}>>\nstream\n",
+            packed.len()
+        )
+        .into_bytes();
+        objstm.extend_from_slice(packed);
+        objstm.extend_from_slice(b"\nendstream");
+
+        let objects = [
+            b"<</Type /Catalog /Names <</EmbeddedFiles <</Names [(data) 5 0 R]>>>>>>".to_vec(),
+            b"<</Type /FontDescriptor /FontName /ABCDEF+LMRoman10-Regular /FontFile3 3 0 R>>"
+                .to_vec(),
+            stream("/Subtype /Type1C", 5000),
+            stream("/Type /XObject /Subtype /Image /Width 10 /Height 20", 8000),
+            b"<</Type /Filespec /F (data.csv) /UF <feff00e9002e006300730076> /EF <</F 6 0 R>>>>"
+                .to_vec(),
+            stream("/Type /EmbeddedFile", 3000),
+            stream("/Length1 2000", 2000),
+            objstm,
+            stream("/Length1 500", 500),
+        ];
+        let data = pdf_with_table(&objects.iter().map(Vec::as_slice).collect::<Vec<_>>());
+
+        let breakdown = inspect_pdf(&data).unwrap();
+        assert_eq!(breakdown.total, data.len() as u64);
+        assert_eq!(
+            breakdown.contributors.iter().map(|c| c.size).sum::<u64>(),
+            breakdown.total
+        );
+
+        let summary: Vec<_> = breakdown
+            .contributors
+            .iter()
+            .map(|c| (c.kind, c.name.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (ContributorKind::Image, "image 4 (10x20 pixels)"),
+                (ContributorKind::Font, "ABCDEF+LMRoman10-Regular"),
+                (ContributorKind::Attachment, "é.csv"),
+                (ContributorKind::Font, "Other"),
+                (ContributorKind::Other, "everything else"),
+                (ContributorKind::Font, "font program 9"),
+            ]
+        );
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(decode_string(b"(a\\(b\\)\\101\\\nc)"), "a(b)Ac");
+        assert_eq!(decode_string(b"<4142 43>"), "ABC");
+        assert_eq!(decode_string(b"(\xfe\xff\x00\xe9)"), "é");
+        assert_eq!(parse_ref(b"12 0 R"), Some(12));
+        assert_eq!(parse_ref(b"12"), None);
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright 2025 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Normalizing the text encodings of input files.
+//!
+//! The engines expect their text inputs to be UTF-8. Files in other encodings
+//! don't cause errors when they're opened; instead they produce garbled tokens
+//! somewhere deep inside the run, which can be very confusing. This module
+//! implements a policy layer that sniffs the encoding of each text input as it
+//! is opened, strips byte-order marks, and either transcodes the file to UTF-8
+//! or rejects it with a clear diagnostic.
+
+use std::{fmt, str::FromStr};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_warning, StatusBackend};
+
+/// File extensions of the inputs that the encoding policy applies to.
+///
+/// Other files, such as images and fonts, are binary and must never be
+/// transcoded.
+const TEXT_EXTENSIONS: &[&str] = &[
+    "bbx", "bib", "bst", "cbx", "cfg", "clo", "cls", "def", "dtx", "fd", "ins", "lbx", "ltx",
+    "sty", "tex",
+];
+
+/// A legacy 8-bit text encoding that can be transcoded into UTF-8.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LegacyEncoding {
+    /// ISO 8859-1, also known as Latin-1.
+    Latin1,
+
+    /// Windows code page 1252, a superset of Latin-1 that assigns printable
+    /// characters to most of the C1 control range.
+    Windows1252,
+}
+
+impl FromStr for LegacyEncoding {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_ref() {
+            "latin1" | "latin-1" | "iso-8859-1" | "iso8859-1" => Ok(LegacyEncoding::Latin1),
+            "cp1252" | "windows-1252" => Ok(LegacyEncoding::Windows1252),
+            _ => bail!(
+                "unsupported input encoding `{}`; supported encodings are `latin1` and `windows-1252`",
+                s
+            ),
+        }
+    }
+}
+
+impl fmt::Display for LegacyEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LegacyEncoding::Latin1 => write!(f, "Latin-1"),
+            LegacyEncoding::Windows1252 => write!(f, "Windows-1252"),
+        }
+    }
+}
+
+/// Code points for the bytes 0x80 through 0x9F in Windows-1252. The five
+/// bytes that the code page leaves undefined map to the corresponding C1
+/// controls, as in the WHATWG Encoding Standard.
+const WINDOWS_1252_HIGH: [char; 32] = [
+    '\u{20AC}', '\u{0081}', '\u{201A}', '\u{0192}', '\u{201E}', '\u{2026}', '\u{2020}', '\u{2021}',
+    '\u{02C6}', '\u{2030}', '\u{0160}', '\u{2039}', '\u{0152}', '\u{008D}', '\u{017D}', '\u{008F}',
+    '\u{0090}', '\u{2018}', '\u{2019}', '\u{201C}', '\u{201D}', '\u{2022}', '\u{2013}', '\u{2014}',
+    '\u{02DC}', '\u{2122}', '\u{0161}', '\u{203A}', '\u{0153}', '\u{009D}', '\u{017E}', '\u{0178}',
+];
+
+impl LegacyEncoding {
+    fn decode(&self, data: &[u8]) -> String {
+        data.iter()
+            .map(|&b| match (self, b) {
+                (LegacyEncoding::Windows1252, 0x80..=0x9F) => {
+                    WINDOWS_1252_HIGH[(b - 0x80) as usize]
+                }
+                _ => b as char,
+            })
+            .collect()
+    }
+}
+
+/// How UTF-16 inputs are handled.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum Utf16Policy {
+    /// Refuse to process UTF-16 inputs. This is the default.
+    #[default]
+    Reject,
+
+    /// Transcode UTF-16 inputs into UTF-8.
+    Transcode,
+}
+
+/// A policy for normalizing the encodings of text inputs.
+///
+/// With the default settings, UTF-8 byte-order marks are stripped, UTF-16
+/// inputs are rejected, and inputs that aren't valid UTF-8 are passed through
+/// unchanged with a warning.
+#[derive(Clone, Debug, Default)]
+pub struct InputEncodingPolicy {
+    /// How to handle UTF-16 inputs.
+    pub utf16: Utf16Policy,
+
+    /// If set, text inputs that aren't valid UTF-8 are assumed to be in this
+    /// encoding, and are transcoded into UTF-8.
+    pub legacy_encoding: Option<LegacyEncoding>,
+}
+
+/// The encoding forms that we can identify by sniffing a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Sniffed {
+    Utf8 { bom: bool },
+    Utf16 { big_endian: bool, bom: bool },
+    Other,
+}
+
+fn sniff(data: &[u8]) -> Sniffed {
+    match data {
+        [0xEF, 0xBB, 0xBF, ..] => Sniffed::Utf8 { bom: true },
+        [0xFE, 0xFF, ..] => Sniffed::Utf16 {
+            big_endian: true,
+            bom: true,
+        },
+        [0xFF, 0xFE, ..] => Sniffed::Utf16 {
+            big_endian: false,
+            bom: true,
+        },
+        // The same BOM-less heuristic that XeTeX uses: ASCII text in UTF-16
+        // will start with a zero byte on one side or the other.
+        [0, b, ..] if *b != 0 => Sniffed::Utf16 {
+            big_endian: true,
+            bom: false,
+        },
+        [b, 0, ..] if *b != 0 => Sniffed::Utf16 {
+            big_endian: false,
+            bom: false,
+        },
+        _ if std::str::from_utf8(data).is_ok() => Sniffed::Utf8 { bom: false },
+        _ => Sniffed::Other,
+    }
+}
+
+impl InputEncodingPolicy {
+    /// Returns true if the policy should be applied to the input file with
+    /// the given name.
+    pub fn applies_to(&self, name: &str) -> bool {
+        match name.rsplit_once('.') {
+            Some((_, ext)) => TEXT_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_ref()),
+            None => false,
+        }
+    }
+
+    /// Normalize the contents of an input file to UTF-8.
+    ///
+    /// Returns `Ok(None)` if the contents should be used unchanged.
+    pub fn normalize(
+        &self,
+        name: &str,
+        data: &[u8],
+        status: &mut dyn StatusBackend,
+    ) -> Result<Option<Vec<u8>>> {
+        match sniff(data) {
+            Sniffed::Utf8 { bom: false } => Ok(None),
+
+            Sniffed::Utf8 { bom: true } => Ok(Some(data[3..].to_owned())),
+
+            Sniffed::Utf16 { big_endian, bom } => {
+                let order = if big_endian { "big" } else { "little" };
+
+                if self.utf16 == Utf16Policy::Reject {
+                    bail!(
+                        "input file `{}` appears to be encoded as UTF-16 ({}-endian); \
+                         re-save it as UTF-8, or enable transcoding of UTF-16 inputs",
+                        name,
+                        order
+                    );
+                }
+
+                let body = if bom { &data[2..] } else { data };
+
+                ensure!(
+                    body.len() % 2 == 0,
+                    "input file `{}` appears to be encoded as UTF-16 ({}-endian), \
+                     but has an odd number of bytes",
+                    name,
+                    order
+                );
+
+                let units = body.chunks_exact(2).map(|c| {
+                    if big_endian {
+                        u16::from_be_bytes([c[0], c[1]])
+                    } else {
+                        u16::from_le_bytes([c[0], c[1]])
+                    }
+                });
+
+                let text = atry!(
+                    char::decode_utf16(units).collect::<Result<String, _>>();
+                    ["input file `{}` appears to be encoded as UTF-16 ({}-endian), but is malformed", name, order]
+                );
+
+                Ok(Some(text.into_bytes()))
+            }
+
+            Sniffed::Other => {
+                if let Some(enc) = self.legacy_encoding {
+                    Ok(Some(enc.decode(data).into_bytes()))
+                } else {
+                    tt_warning!(
+                        status,
+                        "input file `{}` is not valid UTF-8; if it uses a legacy encoding, \
+                         declare it so that it can be transcoded",
+                        name
+                    );
+                    Ok(None)
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::NoopStatusBackend;
+
+    fn normalize(policy: &InputEncodingPolicy, data: &[u8]) -> Result<Option<Vec<u8>>> {
+        policy.normalize("test.tex", data, &mut NoopStatusBackend::default())
+    }
+
+    #[test]
+    fn utf8_passthrough() {
+        let policy = InputEncodingPolicy::default();
+        assert_eq!(normalize(&policy, "héllo".as_bytes()).unwrap(), None);
+    }
+
+    #[test]
+    fn utf8_bom_stripped() {
+        let policy = InputEncodingPolicy::default();
+        assert_eq!(
+            normalize(&policy, b"\xEF\xBB\xBFhello").unwrap(),
+            Some(b"hello".to_vec())
+        );
+    }
+
+    #[test]
+    fn utf16_rejected_by_default() {
+        let policy = InputEncodingPolicy::default();
+        assert!(normalize(&policy, b"\xFF\xFEh\0i\0").is_err());
+    }
+
+    #[test]
+    fn utf16_transcoded() {
+        let policy = InputEncodingPolicy {
+            utf16: Utf16Policy::Transcode,
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize(&policy, b"\xFF\xFEh\0\xE9\0").unwrap(),
+            Some("hé".as_bytes().to_vec())
+        );
+        assert_eq!(normalize(&policy, b"\0h\0i").unwrap(), Some(b"hi".to_vec()));
+    }
+
+    #[test]
+    fn legacy_transcoded() {
+        let latin1 = InputEncodingPolicy {
+            legacy_encoding: Some(LegacyEncoding::Latin1),
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize(&latin1, b"caf\xE9").unwrap(),
+            Some("café".as_bytes().to_vec())
+        );
+
+        let cp1252 = InputEncodingPolicy {
+            legacy_encoding: Some(LegacyEncoding::Windows1252),
+            ..Default::default()
+        };
+        assert_eq!(
+            normalize(&cp1252, b"\x93hi\x94").unwrap(),
+            Some("\u{201C}hi\u{201D}".as_bytes().to_vec())
+        );
+    }
+
+    #[test]
+    fn only_text_files() {
+        let policy = InputEncodingPolicy::default();
+        assert!(policy.applies_to("chapter.tex"));
+        assert!(policy.applies_to("refs.BIB"));
+        assert!(!policy.applies_to("figure.png"));
+        assert!(!policy.applies_to("README"));
+    }
+}
@@ -4,10 +4,17 @@
 //! Code for locally caching compiled format files.
 
 use std::{
-    io::{BufReader, Write},
-    path::PathBuf,
+    collections::HashSet,
+    fs::{self, File, TryLockError},
+    io::{Cursor, Read, Write},
+    path::{Path, PathBuf},
 };
 use tectonic_errors::{anyhow::bail, Result};
+use tectonic_status_base::{tt_note, tt_warning};
+use tectonic_xetex_format::{
+    container::{self, Container},
+    format::Format,
+};
 
 use super::{InputHandle, InputOrigin, IoProvider, OpenResult};
 use crate::{digest::DigestData, status::StatusBackend};
@@ -18,9 +25,18 @@ use crate::{digest::DigestData, status::StatusBackend};
 /// same root cache directory as the `LocalCache` item, but is implemented
 /// separately so that there is a way to save the format files associated with
 /// backends that may not have their own LocalCache.
+///
+/// Before a cached format is used, its metadata are checked to make sure that
+/// it was written by this version of the engine. Formats that fail the check
+/// are treated as missing, so that they get regenerated.
+///
+/// Several processes may use the same cache at once. Formats are written to
+/// it atomically, and [`FormatCache::lock_format`] lets a process that's about
+/// to generate a format keep others from doing the same work.
 pub struct FormatCache {
     bundle_digest: DigestData,
     formats_base: PathBuf,
+    validated: HashSet<PathBuf>,
 }
 
 impl FormatCache {
@@ -33,9 +49,15 @@ impl FormatCache {
         FormatCache {
             bundle_digest,
             formats_base,
+            validated: HashSet::new(),
         }
     }
 
+    /// Get the digest of the bundle whose formats are cached.
+    pub fn bundle_digest(&self) -> &DigestData {
+        &self.bundle_digest
+    }
+
     /// Get an on-disk path name for a given format file. This function simply
     /// produces a path that may or may not exist.
     #[allow(clippy::manual_split_once)] // requires Rust 1.52 (note that we don't actually define our MSRV)
@@ -59,28 +81,219 @@ impl FormatCache {
         ));
         Ok(p)
     }
+
+    /// Wait until no other process is generating the named format, and keep
+    /// them from starting to until the returned lock is dropped.
+    ///
+    /// The lock is held on a file next to the cached format. Since another
+    /// process may have generated the format while this one was waiting,
+    /// callers should check whether it's still missing once they have the
+    /// lock.
+    pub fn lock_format(
+        &mut self,
+        name: &str,
+        status: &mut dyn StatusBackend,
+    ) -> Result<FormatLock> {
+        let mut path = self.path_for_format(name)?;
+        path.set_extension("lock");
+
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                tt_note!(
+                    status,
+                    "waiting for another build to generate format \"{}\"",
+                    name
+                );
+                file.lock()?;
+            }
+            Err(TryLockError::Error(e)) => return Err(e.into()),
+        }
+
+        Ok(FormatLock { _file: file })
+    }
+
+    /// Remove the cached copy of the named format, so that it's generated
+    /// again the next time that it's needed.
+    ///
+    /// This is for formats that passed the checks made when they were opened
+    /// but that the engine still couldn't load. Returns whether there was a
+    /// cached copy to remove.
+    pub fn invalidate_format(&mut self, name: &str) -> Result<bool> {
+        let path = self.path_for_format(name)?;
+        self.validated.remove(&path);
+
+        match fs::remove_file(&path) {
+            Ok(()) => Ok(true),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Check that the format data can be loaded by this version of the
+    /// engine, returning a description of the problem if not.
+    ///
+    /// Besides the version, this checks the internal consistency of the saved
+    /// state, so that a damaged format is explained and regenerated rather
+    /// than making the engine abort with a bare "format file corrupted".
+    fn validate(data: &[u8]) -> Option<String> {
+        let (meta, fmt) = match Format::parse(data).and_then(|fmt| Ok((fmt.metadata()?, fmt))) {
+            Ok(t) => t,
+            Err(e) => return Some(format!("it could not be parsed: {e}")),
+        };
+
+        if meta.version != crate::FORMAT_SERIAL as usize {
+            return Some(format!(
+                "it has version {} but this engine needs version {}",
+                meta.version,
+                crate::FORMAT_SERIAL
+            ));
+        }
+
+        let problems = fmt.check_consistency();
+
+        if let Some(first) = problems.first() {
+            return Some(match problems.len() {
+                1 => format!("its saved state is inconsistent: {first}"),
+                n => format!(
+                    "its saved state is inconsistent: {first} (and {} more problems)",
+                    n - 1
+                ),
+            });
+        }
+
+        None
+    }
+}
+
+/// A format file found in a format cache directory, as returned by
+/// [`cached_formats`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CachedFormat {
+    /// The name of the format, such as `latex`.
+    pub name: String,
+
+    /// The digest of the bundle that the format was generated from.
+    pub bundle_digest: DigestData,
+
+    /// The format version of the engine that generated the format.
+    pub version: u32,
+
+    /// The path of the format file.
+    pub path: PathBuf,
+
+    /// The size of the format file, in bytes.
+    pub size: u64,
+}
+
+/// List the formats cached in the given directory, for any bundle and engine
+/// version, sorted by name and then by path.
+///
+/// Files that don't have the names that [`FormatCache`] gives formats, such
+/// as lock files, are skipped. The formats aren't checked for validity.
+pub fn cached_formats(formats_base: &Path) -> Result<Vec<CachedFormat>> {
+    let mut formats = Vec::new();
+
+    for entry in fs::read_dir(formats_base)? {
+        let entry = entry?;
+        let file_name = entry.file_name();
+
+        let Some(stem) = file_name.to_str().and_then(|n| n.strip_suffix(".fmt")) else {
+            continue;
+        };
+
+        // The names have the form `<digest>-<format name>-<version>.fmt`.
+        let parsed: Option<(DigestData, &str, u32)> =
+            stem.split_once('-').and_then(|(digest, rest)| {
+                let (name, version) = rest.rsplit_once('-')?;
+                Some((digest.parse().ok()?, name, version.parse().ok()?))
+            });
+
+        let Some((bundle_digest, name, version)) = parsed else {
+            continue;
+        };
+
+        formats.push(CachedFormat {
+            name: name.to_owned(),
+            bundle_digest,
+            version,
+            path: entry.path(),
+            size: entry.metadata()?.len(),
+        });
+    }
+
+    formats.sort_by(|a, b| (&a.name, &a.path).cmp(&(&b.name, &b.path)));
+    Ok(formats)
+}
+
+/// A lock on the generation of a cached format, as returned by
+/// [`FormatCache::lock_format`]. The lock is released when this is dropped.
+#[derive(Debug)]
+pub struct FormatLock {
+    _file: File,
 }
 
 impl IoProvider for FormatCache {
     fn input_open_format(
         &mut self,
         name: &str,
-        _status: &mut dyn StatusBackend,
+        status: &mut dyn StatusBackend,
     ) -> OpenResult<InputHandle> {
         let path = match self.path_for_format(name) {
             Ok(p) => p,
             Err(e) => return OpenResult::Err(e),
         };
 
-        let f = match super::try_open_file(path) {
+        let mut f = match super::try_open_file(&path) {
             OpenResult::Ok(f) => f,
             OpenResult::NotAvailable => return OpenResult::NotAvailable,
             OpenResult::Err(e) => return OpenResult::Err(e),
         };
 
+        let mut data = Vec::new();
+
+        if let Err(e) = f.read_to_end(&mut data) {
+            return OpenResult::Err(e.into());
+        }
+
+        // Formats are cached uncompressed, but the engine can't read them
+        // otherwise, so accept ones that have been compressed after the fact.
+        let decoded = match container::decode(&data) {
+            Ok((Container::Raw, _)) => None,
+            Ok((_, raw)) => Some(raw.into_owned()),
+            Err(e) => {
+                tt_warning!(status, "ignoring cached format file `{}`", path.display(); e);
+                return OpenResult::NotAvailable;
+            }
+        };
+        let data = decoded.unwrap_or(data);
+
+        // The format is opened once to decide whether it needs to be
+        // generated and again when the engine loads it, so remember the
+        // formats that have already passed.
+        if !self.validated.contains(&path) {
+            if let Some(problem) = Self::validate(&data) {
+                tt_warning!(
+                    status,
+                    "ignoring cached format file `{}` because {}",
+                    path.display(),
+                    problem
+                );
+                return OpenResult::NotAvailable;
+            }
+
+            self.validated.insert(path);
+        }
+
         OpenResult::Ok(InputHandle::new_read_only(
             name,
-            BufReader::new(f),
+            Cursor::new(data),
             InputOrigin::Other,
         ))
     }
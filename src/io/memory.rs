@@ -16,7 +16,7 @@ use tectonic_status_base::StatusBackend;
 
 use super::{
     normalize_tex_path, InputFeatures, InputHandle, InputOrigin, IoProvider, OpenResult,
-    OutputHandle,
+    OutputHandle, SearchLayer,
 };
 
 /// Information about a file created or used inside the memory-backed I/O
@@ -238,6 +238,22 @@ impl IoProvider for MemoryIo {
             OpenResult::NotAvailable
         }
     }
+
+    fn describe_search(&mut self, _status: &mut dyn StatusBackend) -> Vec<SearchLayer> {
+        let mut names: Vec<String> = self
+            .files
+            .borrow()
+            .keys()
+            .filter(|name| *name != self.stdout_key())
+            .cloned()
+            .collect();
+        names.sort();
+
+        vec![SearchLayer {
+            description: "the files created during this build".to_owned(),
+            names,
+        }]
+    }
 }
 
 #[cfg(test)]
@@ -5,6 +5,7 @@
 
 use tectonic_status_base::StatusBackend;
 
+pub mod encoding;
 pub mod format_cache;
 pub mod memory;
 
@@ -17,6 +18,7 @@ pub use tectonic_io_base::{
     stack::IoStack,
     stdstreams::GenuineStdoutIo,
     try_open_file, InputFeatures, InputHandle, InputOrigin, IoProvider, OpenResult, OutputHandle,
+    SearchLayer,
 };
 
 // Internal Reexports
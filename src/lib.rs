@@ -81,10 +81,19 @@ pub mod config;
 pub mod digest;
 #[cfg(feature = "serialization")]
 pub mod docmodel;
+#[cfg(feature = "serialization")]
+pub mod doctor;
 pub mod driver;
 pub mod engines;
 pub mod errors;
+pub mod generate;
+#[cfg(feature = "serialization")]
+pub mod golden;
+pub mod inspect;
 pub mod io;
+pub mod postprocess;
+pub mod schedule;
+pub mod scratch;
 pub mod status;
 pub mod unstable_opts;
 
@@ -93,9 +102,12 @@ pub mod unstable_opts;
 #[doc(hidden)]
 pub mod test_util;
 
-pub use crate::engines::bibtex::BibtexEngine;
+pub use crate::engines::bibtex::{BibtexEngine, BibtexOutcome, BstCodeCache};
 pub use crate::engines::spx2html::Spx2HtmlEngine;
-pub use crate::engines::tex::{TexEngine, TexOutcome};
+pub use crate::engines::tex::{
+    MathFontOverrides, ShapingCache, ShapingCacheStats, TexEngine, TexOutcome, TraceCapture,
+    TraceParameter,
+};
 pub use crate::engines::xdvipdfmx::XdvipdfmxEngine;
 pub use crate::errors::{Error, ErrorKind, Result};
 
@@ -0,0 +1,151 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Post-processing with external programs.
+
+use std::{
+    io::Write,
+    path::PathBuf,
+    process::{Command, Stdio},
+};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_error, StatusBackend};
+
+use super::{Artifact, PostProcessConfig, PostProcessor};
+
+/// A post-processor that runs an external program.
+///
+/// The program is given the contents of the file on its standard input, and
+/// whatever it prints to its standard output replaces them. If it prints
+/// nothing, the file is left unchanged, so that programs that only check or
+/// publish outputs don't need to echo them back. The name of the file,
+/// relative to the output directory, is given in the environment variable
+/// `TECTONIC_POSTPROCESS_FILE`, and each setting `<key>` of the step is given
+/// in a variable `TECTONIC_POSTPROCESS_<KEY>`, upper-cased.
+///
+/// If the program exits unsuccessfully, the build fails.
+#[derive(Clone, Debug)]
+pub struct ExternalPostProcessor {
+    argv: Vec<String>,
+    current_dir: Option<PathBuf>,
+}
+
+impl ExternalPostProcessor {
+    /// Create a post-processor that runs a program, given as its name or path
+    /// followed by its arguments.
+    ///
+    /// # Panics
+    ///
+    /// Panics if *argv* is empty.
+    pub fn new<I, S>(argv: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let argv: Vec<String> = argv.into_iter().map(|a| a.into()).collect();
+        assert!(!argv.is_empty(), "external post-processors need a program");
+
+        ExternalPostProcessor {
+            argv,
+            current_dir: None,
+        }
+    }
+
+    /// Set the directory in which the program is run, and relative to which
+    /// its path is resolved.
+    ///
+    /// By default, it's run in the current directory.
+    pub fn current_dir(&mut self, dir: impl Into<PathBuf>) -> &mut Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+}
+
+impl PostProcessor for ExternalPostProcessor {
+    fn name(&self) -> &str {
+        &self.argv[0]
+    }
+
+    fn is_external(&self) -> bool {
+        true
+    }
+
+    fn process(
+        &self,
+        artifact: &mut Artifact,
+        config: &PostProcessConfig,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        let mut program = PathBuf::from(&self.argv[0]);
+
+        if let Some(dir) = self.current_dir.as_ref() {
+            // A relative path with more than one component is relative to
+            // the working directory, but bare names are looked up in $PATH.
+            if program.is_relative() && program.components().count() > 1 {
+                program = dir.join(program);
+            }
+        }
+
+        let mut cmd = Command::new(&program);
+        cmd.args(&self.argv[1..])
+            .env("TECTONIC_POSTPROCESS_FILE", &artifact.name)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        for (key, value) in config {
+            let var: String = key
+                .chars()
+                .map(|c| {
+                    if c.is_ascii_alphanumeric() {
+                        c.to_ascii_uppercase()
+                    } else {
+                        '_'
+                    }
+                })
+                .collect();
+            cmd.env(format!("TECTONIC_POSTPROCESS_{var}"), value);
+        }
+
+        if let Some(dir) = self.current_dir.as_ref() {
+            cmd.current_dir(dir);
+        }
+
+        let mut child = atry!(
+            cmd.spawn();
+            ["failed to run `{}`", program.display()]
+        );
+
+        // Feed the input from another thread, so that a program that writes
+        // output before it has read all of its input doesn't deadlock.
+        let mut stdin = child.stdin.take().unwrap();
+        let output = std::thread::scope(|s| {
+            let data = &artifact.data;
+            let writer = s.spawn(move || {
+                // If the program exits without reading everything, that's
+                // its business.
+                let _ = stdin.write_all(data);
+            });
+
+            let output = child.wait_with_output();
+            writer.join().unwrap();
+            output
+        })?;
+
+        if !output.status.success() {
+            tt_error!(status, "`{}` failed; its stderr was:\n", program.display());
+            status.dump_error_logs(&output.stderr[..]);
+
+            match output.status.code() {
+                Some(n) => bail!("`{}` exited with error code {}", program.display(), n),
+                None => bail!("`{}` was terminated by a signal", program.display()),
+            }
+        }
+
+        if !output.stdout.is_empty() {
+            artifact.data = output.stdout;
+        }
+
+        Ok(())
+    }
+}
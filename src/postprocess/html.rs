@@ -0,0 +1,237 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! The `html-minify` post-processor.
+//!
+//! This makes HTML files smaller without changing how they render. Runs of
+//! whitespace in text and inside tags are collapsed to a single character,
+//! which is a newline if the run contained one and a space otherwise, and
+//! comments are removed. The contents of `<pre>`, `<textarea>`, `<script>`,
+//! and `<style>` elements, and the values of attributes, are left alone.
+//! Whitespace isn't removed entirely, even between tags, since it can be
+//! significant between inline elements.
+//!
+//! If the `keep_comments` setting is `true`, comments are kept.
+
+use tectonic_errors::prelude::*;
+use tectonic_status_base::StatusBackend;
+
+use super::{parse_bool_setting, Artifact, PostProcessConfig, PostProcessor};
+use crate::config::check_settings;
+
+/// The `html-minify` post-processor. See [the module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct HtmlMinify;
+
+impl PostProcessor for HtmlMinify {
+    fn name(&self) -> &str {
+        "html-minify"
+    }
+
+    fn default_extensions(&self) -> &[&str] {
+        &["html", "htm"]
+    }
+
+    fn process(
+        &self,
+        artifact: &mut Artifact,
+        config: &PostProcessConfig,
+        _status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        check_settings(config, &["keep_comments"])?;
+        let keep_comments = parse_bool_setting(config, "keep_comments", false)?;
+        artifact.data = minify(&artifact.data, keep_comments);
+        Ok(())
+    }
+}
+
+/// Elements whose contents are copied verbatim.
+const RAW_ELEMENTS: &[&[u8]] = &[b"pre", b"textarea", b"script", b"style"];
+
+fn minify(html: &[u8], keep_comments: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(html.len());
+    let mut pos = 0;
+
+    while pos < html.len() {
+        let b = html[pos];
+
+        if b == b'<' && html[pos..].starts_with(b"<!--") {
+            let end = find(html, pos + 4, b"-->").map_or(html.len(), |i| i + 3);
+
+            if keep_comments {
+                out.extend_from_slice(&html[pos..end]);
+            }
+
+            pos = end;
+        } else if b == b'<'
+            && html
+                .get(pos + 1)
+                .is_some_and(|c| c.is_ascii_alphabetic() || *c == b'/' || *c == b'!')
+        {
+            let start = pos;
+            pos = copy_tag(html, pos, &mut out);
+
+            if let Some(name) = raw_element(&html[start..pos]) {
+                // Copy everything up to the closing tag as-is.
+                let end = find_closing_tag(html, pos, name).unwrap_or(html.len());
+                out.extend_from_slice(&html[pos..end]);
+                pos = end;
+            }
+        } else if b.is_ascii_whitespace() {
+            let end = skip_whitespace(html, pos);
+            out.push(collapsed(&html[pos..end]));
+            pos = end;
+        } else {
+            out.push(b);
+            pos += 1;
+        }
+    }
+
+    out
+}
+
+/// Copy the tag starting at *pos*, collapsing whitespace outside of quoted
+/// attribute values, and return the position after it.
+fn copy_tag(html: &[u8], mut pos: usize, out: &mut Vec<u8>) -> usize {
+    let mut quote = None;
+
+    while pos < html.len() {
+        let b = html[pos];
+
+        match quote {
+            Some(q) => {
+                out.push(b);
+                pos += 1;
+
+                if b == q {
+                    quote = None;
+                }
+            }
+
+            None if b == b'"' || b == b'\'' => {
+                quote = Some(b);
+                out.push(b);
+                pos += 1;
+            }
+
+            None if b.is_ascii_whitespace() => {
+                let end = skip_whitespace(html, pos);
+
+                // Whitespace before the end of the tag can go entirely.
+                if !matches!(html.get(end), Some(b'>') | None) {
+                    out.push(b' ');
+                }
+
+                pos = end;
+            }
+
+            None => {
+                out.push(b);
+                pos += 1;
+
+                if b == b'>' {
+                    break;
+                }
+            }
+        }
+    }
+
+    pos
+}
+
+/// If the tag opens an element whose contents must be kept verbatim, return
+/// the element's name.
+fn raw_element(tag: &[u8]) -> Option<&'static [u8]> {
+    let tag = &tag[1..];
+
+    RAW_ELEMENTS.iter().copied().find(|name| {
+        tag.len() > name.len()
+            && tag[..name.len()].eq_ignore_ascii_case(name)
+            && !tag[name.len()].is_ascii_alphanumeric()
+            && !tag.ends_with(b"/>")
+    })
+}
+
+fn find_closing_tag(html: &[u8], from: usize, name: &[u8]) -> Option<usize> {
+    let mut pos = from;
+
+    while let Some(i) = find(html, pos, b"</") {
+        let rest = &html[i + 2..];
+
+        if rest.len() >= name.len()
+            && rest[..name.len()].eq_ignore_ascii_case(name)
+            && !rest
+                .get(name.len())
+                .is_some_and(|b| b.is_ascii_alphanumeric())
+        {
+            return Some(i);
+        }
+
+        pos = i + 2;
+    }
+
+    None
+}
+
+fn skip_whitespace(html: &[u8], mut pos: usize) -> usize {
+    while pos < html.len() && html[pos].is_ascii_whitespace() {
+        pos += 1;
+    }
+
+    pos
+}
+
+/// The character that a run of whitespace collapses to.
+fn collapsed(run: &[u8]) -> u8 {
+    if run.contains(&b'\n') {
+        b'\n'
+    } else {
+        b' '
+    }
+}
+
+fn find(haystack: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    haystack[from.min(haystack.len())..]
+        .windows(needle.len())
+        .position(|w| w == needle)
+        .map(|i| i + from)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn min(html: &str) -> String {
+        String::from_utf8(minify(html.as_bytes(), false)).unwrap()
+    }
+
+    #[test]
+    fn minifying() {
+        assert_eq!(
+            min("<p>  Some   <em>text</em>\n\n  here. </p>"),
+            "<p> Some <em>text</em>\nhere. </p>"
+        );
+        assert_eq!(
+            min("<a   href=\"a  b.html\"\n   class='x'  >link</a >"),
+            "<a href=\"a  b.html\" class='x'>link</a>"
+        );
+        assert_eq!(min("<div><!-- a comment --> x</div>"), "<div> x</div>");
+        assert_eq!(
+            min("<pre>  keep\n\n  this</pre>  <p>x</p>"),
+            "<pre>  keep\n\n  this</pre> <p>x</p>"
+        );
+        assert_eq!(
+            min("<script>if (a  <  b) {}</script><style>p {  }</style>"),
+            "<script>if (a  <  b) {}</script><style>p {  }</style>"
+        );
+        assert_eq!(min("<!DOCTYPE   html>\n<html>"), "<!DOCTYPE html>\n<html>");
+        assert_eq!(min("a < b  and  c"), "a < b and c");
+        assert_eq!(min("<preview>  x</preview>"), "<preview> x</preview>");
+
+        assert_eq!(
+            String::from_utf8(minify(b"<!-- keep -->  x", true)).unwrap(),
+            "<!-- keep --> x"
+        );
+    }
+}
@@ -0,0 +1,261 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Post-processing of the outputs of a build.
+//!
+//! After the engines have finished, the output files can be passed through a
+//! sequence of post-processing steps before they're written out. Each step
+//! runs a [`PostProcessor`] on the outputs whose extensions it handles, with
+//! a table of settings, and may rewrite their contents. This covers the kinds
+//! of things that would otherwise require wrapping Tectonic in a shell
+//! script, such as shrinking or signing the final PDF.
+//!
+//! Tectonic has built-in post-processors for some common needs, which can be
+//! looked up by name with [`builtin`]. Other programs can be run as
+//! post-processors with [`ExternalPostProcessor`].
+
+use std::{cell::RefCell, collections::BTreeMap, fmt, path::Path, rc::Rc};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::StatusBackend;
+
+mod external;
+mod html;
+pub(crate) mod pdf;
+
+pub use external::ExternalPostProcessor;
+pub use html::HtmlMinify;
+pub use pdf::PdfCompress;
+
+/// The settings of a post-processing step.
+///
+/// The meanings of the keys depend on the post-processor.
+pub type PostProcessConfig = BTreeMap<String, String>;
+
+/// An output file being post-processed.
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    /// The name of the file, relative to the output directory.
+    pub name: String,
+
+    /// The contents of the file. Post-processors replace these to change the
+    /// file.
+    pub data: Vec<u8>,
+}
+
+/// A transformation applied to the outputs of a build.
+pub trait PostProcessor {
+    /// The name of this post-processor, for use in messages.
+    fn name(&self) -> &str;
+
+    /// The extensions of the files that this post-processor handles if a step
+    /// doesn't specify any, such as `pdf`.
+    ///
+    /// If this is empty, as it is by default, the post-processor handles
+    /// files of the session's output format.
+    fn default_extensions(&self) -> &[&str] {
+        &[]
+    }
+
+    /// Whether this post-processor runs programs outside of Tectonic.
+    ///
+    /// Such post-processors aren't run if known-insecure features have been
+    /// disabled.
+    fn is_external(&self) -> bool {
+        false
+    }
+
+    /// Process an output file.
+    ///
+    /// The *config* holds the settings of the step. Post-processors should
+    /// reject settings that they don't understand.
+    fn process(
+        &self,
+        artifact: &mut Artifact,
+        config: &PostProcessConfig,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()>;
+}
+
+/// The names of the built-in post-processors.
+pub const BUILTIN_NAMES: &[&str] = &["html-minify", "pdf-compress"];
+
+/// Look up a built-in post-processor by name.
+///
+/// See [`BUILTIN_NAMES`] for the names that are recognized.
+pub fn builtin(name: &str) -> Option<Rc<dyn PostProcessor>> {
+    match name {
+        "html-minify" => Some(Rc::new(HtmlMinify)),
+        "pdf-compress" => Some(Rc::new(PdfCompress)),
+        _ => None,
+    }
+}
+
+/// One step of post-processing: a post-processor, the files that it applies
+/// to, and its settings.
+#[derive(Clone)]
+pub struct PostProcessStep {
+    processor: Rc<dyn PostProcessor>,
+    extensions: Vec<String>,
+    config: PostProcessConfig,
+}
+
+impl fmt::Debug for PostProcessStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PostProcessStep")
+            .field("processor", &self.processor.name())
+            .field("extensions", &self.extensions)
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl PostProcessStep {
+    /// Create a step that runs the given post-processor with its default
+    /// extensions and no settings.
+    pub fn new(processor: Rc<dyn PostProcessor>) -> Self {
+        PostProcessStep {
+            processor,
+            extensions: Vec::new(),
+            config: PostProcessConfig::new(),
+        }
+    }
+
+    /// Set the extensions of the files that this step applies to, such as
+    /// `pdf` or `html`.
+    ///
+    /// If none are given, the post-processor's defaults are used.
+    pub fn extensions<I, S>(&mut self, extensions: I) -> &mut Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.extensions = extensions.into_iter().map(|e| e.into()).collect();
+        self
+    }
+
+    /// Set the settings passed to the post-processor.
+    pub fn config(&mut self, config: PostProcessConfig) -> &mut Self {
+        self.config = config;
+        self
+    }
+
+    /// Get the post-processor run by this step.
+    pub fn processor(&self) -> &dyn PostProcessor {
+        &*self.processor
+    }
+
+    /// Whether this step applies to the file *name*, given the extension of
+    /// the files of the session's output format.
+    fn applies_to(&self, name: &str, output_extension: &str) -> bool {
+        let Some(ext) = Path::new(name).extension().and_then(|e| e.to_str()) else {
+            return false;
+        };
+
+        if !self.extensions.is_empty() {
+            self.extensions.iter().any(|e| e.eq_ignore_ascii_case(ext))
+        } else if !self.processor.default_extensions().is_empty() {
+            self.processor
+                .default_extensions()
+                .iter()
+                .any(|e| e.eq_ignore_ascii_case(ext))
+        } else {
+            output_extension.eq_ignore_ascii_case(ext)
+        }
+    }
+}
+
+/// Run the steps that apply to an artifact, in order, returning the number
+/// of steps that were run.
+pub(crate) fn run_steps(
+    steps: &[PostProcessStep],
+    artifact: &mut Artifact,
+    output_extension: &str,
+    status: &mut dyn StatusBackend,
+) -> Result<usize> {
+    let mut n_run = 0;
+
+    for step in steps {
+        if !step.applies_to(&artifact.name, output_extension) {
+            continue;
+        }
+
+        status.note_highlighted(
+            "Running post-processor ",
+            step.processor.name(),
+            &format!(" on `{}` ...", artifact.name),
+        );
+
+        atry!(
+            step.processor.process(artifact, &step.config, status);
+            ["post-processor `{}` failed on `{}`", step.processor.name(), artifact.name]
+        );
+
+        n_run += 1;
+    }
+
+    Ok(n_run)
+}
+
+/// Adapts a sequence of post-processing steps to filter the files written by
+/// the HTML engine, recording the names and final sizes of the files as it
+/// goes.
+#[derive(Debug)]
+pub(crate) struct HtmlOutputFilter {
+    pub steps: Vec<PostProcessStep>,
+    pub out_base: std::path::PathBuf,
+    pub sizes: Rc<RefCell<Vec<(String, u64)>>>,
+}
+
+impl tectonic_engine_spx2html::OutputFilter for HtmlOutputFilter {
+    fn filter(
+        &mut self,
+        path: &Path,
+        data: Vec<u8>,
+        status: &mut dyn StatusBackend,
+    ) -> Result<Vec<u8>> {
+        let name = path.strip_prefix(&self.out_base).unwrap_or(path);
+
+        let mut artifact = Artifact {
+            name: name.display().to_string(),
+            data,
+        };
+
+        run_steps(&self.steps, &mut artifact, "html", status)?;
+        self.sizes
+            .borrow_mut()
+            .push((artifact.name, artifact.data.len() as u64));
+        Ok(artifact.data)
+    }
+}
+
+/// Parse a boolean setting, which may be `true` or `false`.
+fn parse_bool_setting(config: &PostProcessConfig, key: &str, default: bool) -> Result<bool> {
+    match config.get(key).map(|s| s.as_str()) {
+        None => Ok(default),
+        Some("true") => Ok(true),
+        Some("false") => Ok(false),
+        Some(other) => bail!("setting `{key}` must be `true` or `false`, not `{other}`"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_extensions() {
+        let step = PostProcessStep::new(builtin("pdf-compress").unwrap());
+        assert!(step.applies_to("doc.pdf", "html"));
+        assert!(step.applies_to("doc.PDF", "pdf"));
+        assert!(!step.applies_to("doc.log", "pdf"));
+
+        let mut step = PostProcessStep::new(Rc::new(ExternalPostProcessor::new(["true"])));
+        assert!(step.applies_to("doc.xdv", "xdv"));
+        assert!(!step.applies_to("doc.pdf", "xdv"));
+        assert!(!step.applies_to("README", "xdv"));
+
+        step.extensions(["css", "html"]);
+        assert!(step.applies_to("style.css", "pdf"));
+        assert!(!step.applies_to("doc.pdf", "pdf"));
+    }
+}
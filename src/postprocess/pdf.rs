@@ -0,0 +1,1100 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! The `pdf-compress` post-processor.
+//!
+//! This compresses the streams of a PDF file that aren't compressed already,
+//! much like `qpdf --compress-streams=y`, and rewrites the cross-reference
+//! data to match. The PDFs made by `xdvipdfmx` are normally compressed
+//! already, but not in draft mode, and the streams that it copies from
+//! included PDF figures are kept as they were.
+//!
+//! This isn't a general PDF rewriter: it handles files with a single
+//! cross-reference section, which may be a table or a stream, as `xdvipdfmx`
+//! writes them. Files that have been updated incrementally, linearized, or
+//! encrypted are left unchanged with a warning, as are files that can't be
+//! parsed. XMP metadata streams aren't compressed, so that tools that search
+//! for them in the raw file can still find them.
+//!
+//! The `level` setting is the zlib compression level, from 0 to 9. The
+//! default is 9.
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::{
+    collections::BTreeMap,
+    io::{Read, Write},
+    ops::Range,
+};
+use tectonic_errors::prelude::*;
+use tectonic_status_base::{tt_note, tt_warning, StatusBackend};
+
+use super::{Artifact, PostProcessConfig, PostProcessor};
+use crate::config::check_settings;
+
+/// The `pdf-compress` post-processor. See [the module
+/// documentation](self).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PdfCompress;
+
+impl PostProcessor for PdfCompress {
+    fn name(&self) -> &str {
+        "pdf-compress"
+    }
+
+    fn default_extensions(&self) -> &[&str] {
+        &["pdf"]
+    }
+
+    fn process(
+        &self,
+        artifact: &mut Artifact,
+        config: &PostProcessConfig,
+        status: &mut dyn StatusBackend,
+    ) -> Result<()> {
+        check_settings(config, &["level"])?;
+
+        let level = match config.get("level") {
+            Some(s) => match s.parse::<u32>() {
+                Ok(n) if n <= 9 => n,
+                _ => bail!("setting `level` must be a number from 0 to 9, not `{s}`"),
+            },
+            None => 9,
+        };
+
+        match compress(&artifact.data, Compression::new(level)) {
+            Ok(Outcome::Compressed { data, n_streams }) => {
+                tt_note!(
+                    status,
+                    "compressed {} stream(s) of `{}`, from {} to {} bytes",
+                    n_streams,
+                    artifact.name,
+                    artifact.data.len(),
+                    data.len()
+                );
+                artifact.data = data;
+            }
+
+            Ok(Outcome::Unchanged) => {}
+
+            Ok(Outcome::Unsupported(why)) => {
+                tt_warning!(status, "not compressing `{}`: {}", artifact.name, why);
+            }
+
+            Err(e) => {
+                tt_warning!(status, "not compressing `{}`: it could not be parsed", artifact.name; e);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Compressed { data: Vec<u8>, n_streams: usize },
+    Unchanged,
+    Unsupported(&'static str),
+}
+
+/// An entry in the cross-reference data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub(crate) enum XrefEntry {
+    /// A free object, with the next free object number and generation.
+    Free(u64, u64),
+
+    /// An object at a byte offset in the file, with its generation.
+    InFile(u64, u64),
+
+    /// An object inside an object stream, with the stream's object number
+    /// and the object's index in it.
+    InStream(u64, u64),
+}
+
+/// The cross-reference section of a file.
+#[derive(Debug)]
+pub(crate) struct Xref {
+    pub(crate) entries: BTreeMap<u64, XrefEntry>,
+
+    /// The trailer dictionary, or the dictionary of the cross-reference
+    /// stream.
+    pub(crate) trailer: Dict,
+
+    /// The object number and generation of the cross-reference stream, if
+    /// the file uses one.
+    stream_id: Option<(u64, u64)>,
+}
+
+fn compress(data: &[u8], level: Compression) -> Result<Outcome> {
+    ensure!(data.starts_with(b"%PDF-"), "missing PDF header");
+
+    let xref_offset = find_startxref(data)?;
+    let xref = parse_xref(data, xref_offset)?;
+
+    if xref.trailer.get("Prev").is_some() || xref.trailer.get("XRefStm").is_some() {
+        return Ok(Outcome::Unsupported(
+            "it has multiple cross-reference sections",
+        ));
+    }
+
+    if xref.trailer.get("Encrypt").is_some() {
+        return Ok(Outcome::Unsupported("it is encrypted"));
+    }
+
+    // Get the objects in the file in the order in which they appear. Each
+    // one runs until the start of the next, or of the cross-reference data.
+
+    let mut objects: Vec<(u64, u64)> = xref
+        .entries
+        .iter()
+        .filter_map(|(num, e)| match e {
+            XrefEntry::InFile(ofs, _) if *ofs != xref_offset => Some((*ofs, *num)),
+            _ => None,
+        })
+        .collect();
+    objects.sort_unstable();
+
+    let Some(&(first_offset, _)) = objects.first() else {
+        bail!("no objects found");
+    };
+
+    ensure!(
+        objects.last().unwrap().0 < xref_offset,
+        "objects found after the cross-reference data"
+    );
+
+    // Compress what we can.
+
+    let mut out = data[..first_offset as usize].to_vec();
+    let mut new_offsets = BTreeMap::new();
+    let mut n_streams = 0;
+
+    for (i, &(ofs, num)) in objects.iter().enumerate() {
+        let end = objects.get(i + 1).map_or(xref_offset, |o| o.0);
+        let span = &data[ofs as usize..end as usize];
+        new_offsets.insert(num, out.len() as u64);
+
+        match compress_object(span, level)? {
+            Some(new) => {
+                out.extend_from_slice(&new);
+                n_streams += 1;
+            }
+            None => out.extend_from_slice(span),
+        }
+    }
+
+    if n_streams == 0 {
+        return Ok(Outcome::Unchanged);
+    }
+
+    let mut entries = xref.entries.clone();
+
+    for (num, e) in entries.iter_mut() {
+        if let (XrefEntry::InFile(ofs, _), Some(new)) = (e, new_offsets.get(num)) {
+            *ofs = *new;
+        }
+    }
+
+    match xref.stream_id {
+        None => write_xref_table(&mut out, data, &entries, &xref.trailer)?,
+        Some(id) => write_xref_stream(&mut out, data, &mut entries, &xref.trailer, id, level)?,
+    }
+
+    if out.len() >= data.len() {
+        return Ok(Outcome::Unchanged);
+    }
+
+    Ok(Outcome::Compressed {
+        data: out,
+        n_streams,
+    })
+}
+
+/// Compress the stream of an object, if it has an uncompressed one. The
+/// *span* runs from the start of the object to the start of the next one.
+fn compress_object(span: &[u8], level: Compression) -> Result<Option<Vec<u8>>> {
+    let mut pos = skip_ws(span, 0);
+
+    // The object header.
+    for _ in 0..2 {
+        let (tok, end) = token(span, pos);
+        ensure!(parse_u64(tok).is_some(), "malformed object header");
+        pos = skip_ws(span, end);
+    }
+
+    let (tok, end) = token(span, pos);
+    ensure!(tok == b"obj", "malformed object header");
+    pos = skip_ws(span, end);
+
+    if !span[pos..].starts_with(b"<<") {
+        return Ok(None);
+    }
+
+    let dict = Dict::parse(span, pos)?;
+    pos = skip_ws(span, dict.end);
+    let (tok, mut data_start) = token(span, pos);
+
+    if tok != b"stream" {
+        return Ok(None);
+    }
+
+    if dict.get("Filter").is_some() {
+        return Ok(None);
+    }
+
+    if let Some(ty) = dict.get("Type") {
+        let ty = &span[ty];
+
+        if ty == b"/Metadata" || ty == b"/XRef" {
+            return Ok(None);
+        }
+    }
+
+    if span[data_start..].starts_with(b"\r\n") {
+        data_start += 2;
+    } else if span[data_start..].starts_with(b"\n") {
+        data_start += 1;
+    }
+
+    let data_end = stream_end(span, &dict, data_start)?;
+    let stream = &span[data_start..data_end];
+
+    let mut enc = ZlibEncoder::new(Vec::new(), level);
+    enc.write_all(stream)?;
+    let compressed = enc.finish()?;
+
+    if compressed.len() >= stream.len() {
+        return Ok(None);
+    }
+
+    // Write the dictionary with a new length and the filter, then the data,
+    // then everything after it.
+
+    let length = dict.get("Length").unwrap();
+    let mut out = span[..length.start].to_vec();
+    write!(out, "{}", compressed.len())?;
+    out.extend_from_slice(&span[length.end..dict.end - 2]);
+    out.extend_from_slice(b"/Filter /FlateDecode>>");
+    out.extend_from_slice(&span[dict.end..data_start]);
+    out.extend_from_slice(&compressed);
+    out.extend_from_slice(&span[data_end..]);
+    Ok(Some(out))
+}
+
+/// Find the end of the data of a stream.
+pub(crate) fn stream_end(span: &[u8], dict: &Dict, data_start: usize) -> Result<usize> {
+    // If the length is given directly and checks out, use it.
+
+    if let Some(n) = dict.get("Length").and_then(|r| parse_u64(&span[r])) {
+        let end = data_start.saturating_add(n as usize);
+
+        if end <= span.len() && span[skip_ws(span, end)..].starts_with(b"endstream") {
+            return Ok(end);
+        }
+    }
+
+    // Otherwise, it's an indirect reference, or wrong. The data run up to the
+    // last `endstream` in the object, less the end-of-line marker before it.
+
+    let kw = a_ok_or!(
+        rfind(span, b"endstream");
+        ["missing `endstream`"]
+    );
+    ensure!(kw >= data_start, "missing `endstream`");
+
+    let mut end = kw;
+
+    if end > data_start && span[end - 1] == b'\n' {
+        end -= 1;
+    }
+
+    if end > data_start && span[end - 1] == b'\r' {
+        end -= 1;
+    }
+
+    Ok(end)
+}
+
+pub(crate) fn find_startxref(data: &[u8]) -> Result<u64> {
+    let tail_start = data.len().saturating_sub(1024);
+    let kw = a_ok_or!(
+        rfind(&data[tail_start..], b"startxref");
+        ["missing `startxref`"]
+    );
+    let (tok, _) = token(data, skip_ws(data, tail_start + kw + 9));
+    let ofs = a_ok_or!(parse_u64(tok); ["malformed `startxref`"]);
+    ensure!((ofs as usize) < data.len(), "`startxref` is out of range");
+    Ok(ofs)
+}
+
+pub(crate) fn parse_xref(data: &[u8], offset: u64) -> Result<Xref> {
+    let pos = offset as usize;
+
+    if data[pos..].starts_with(b"xref") {
+        parse_xref_table(data, pos + 4)
+    } else {
+        parse_xref_stream(data, pos)
+    }
+}
+
+fn parse_xref_table(data: &[u8], mut pos: usize) -> Result<Xref> {
+    let mut entries = BTreeMap::new();
+
+    loop {
+        pos = skip_ws(data, pos);
+        let (tok, end) = token(data, pos);
+
+        if tok == b"trailer" {
+            pos = skip_ws(data, end);
+            break;
+        }
+
+        let first = a_ok_or!(parse_u64(tok); ["malformed cross-reference table"]);
+        let (tok, end) = token(data, skip_ws(data, end));
+        let count = a_ok_or!(parse_u64(tok); ["malformed cross-reference table"]);
+        pos = end;
+
+        for num in first..first + count {
+            let mut fields = [&b""[..]; 3];
+
+            for f in &mut fields {
+                let (tok, end) = token(data, skip_ws(data, pos));
+                *f = tok;
+                pos = end;
+            }
+
+            let (Some(a), Some(b)) = (parse_u64(fields[0]), parse_u64(fields[1])) else {
+                bail!("malformed cross-reference table entry");
+            };
+
+            let entry = match fields[2] {
+                b"n" => XrefEntry::InFile(a, b),
+                b"f" => XrefEntry::Free(a, b),
+                _ => bail!("malformed cross-reference table entry"),
+            };
+
+            entries.insert(num, entry);
+        }
+    }
+
+    ensure!(data[pos..].starts_with(b"<<"), "missing trailer dictionary");
+
+    Ok(Xref {
+        entries,
+        trailer: Dict::parse(data, pos)?,
+        stream_id: None,
+    })
+}
+
+fn parse_xref_stream(data: &[u8], offset: usize) -> Result<Xref> {
+    let mut pos = offset;
+    let mut id = [0; 2];
+
+    for n in &mut id {
+        let (tok, end) = token(data, skip_ws(data, pos));
+        *n = a_ok_or!(parse_u64(tok); ["malformed cross-reference stream"]);
+        pos = end;
+    }
+
+    let (tok, end) = token(data, skip_ws(data, pos));
+    ensure!(tok == b"obj", "malformed cross-reference stream");
+    pos = skip_ws(data, end);
+    let dict = Dict::parse(data, pos)?;
+
+    ensure!(
+        dict.get("Type").map(|r| &data[r]) == Some(&b"/XRef"[..]),
+        "`startxref` doesn't point to cross-reference data"
+    );
+
+    let (tok, mut data_start) = token(data, skip_ws(data, dict.end));
+    ensure!(tok == b"stream", "malformed cross-reference stream");
+
+    if data[data_start..].starts_with(b"\r\n") {
+        data_start += 2;
+    } else if data[data_start..].starts_with(b"\n") {
+        data_start += 1;
+    }
+
+    let length = a_ok_or!(
+        dict.get("Length").and_then(|r| parse_u64(&data[r]));
+        ["cross-reference stream has no direct length"]
+    ) as usize;
+    ensure!(
+        data_start + length <= data.len(),
+        "cross-reference stream is truncated"
+    );
+    let raw = &data[data_start..data_start + length];
+
+    let decoded = match dict.get("Filter").map(|r| &data[r]) {
+        None => raw.to_vec(),
+        Some(b"/FlateDecode") | Some(b"[/FlateDecode]") => {
+            let mut buf = Vec::new();
+            ZlibDecoder::new(raw).read_to_end(&mut buf)?;
+            buf
+        }
+        Some(_) => bail!("unsupported cross-reference stream filter"),
+    };
+
+    let widths = dict.get_int_array(data, "W")?;
+    ensure!(
+        widths.len() == 3 && widths.iter().all(|w| *w <= 8),
+        "malformed cross-reference stream widths"
+    );
+    let row_len = widths.iter().sum::<u64>() as usize;
+    ensure!(row_len > 0, "malformed cross-reference stream widths");
+
+    let decoded = match dict.get("DecodeParms") {
+        None => decoded,
+        Some(r) => {
+            let parms = Dict::parse(data, r.start)?;
+            let predictor = parms
+                .get("Predictor")
+                .and_then(|r| parse_u64(&data[r]))
+                .unwrap_or(1);
+
+            if predictor >= 10 {
+                let columns = parms
+                    .get("Columns")
+                    .and_then(|r| parse_u64(&data[r]))
+                    .unwrap_or(1) as usize;
+                ensure!(
+                    columns == row_len,
+                    "unsupported cross-reference stream predictor"
+                );
+                unpredict_png(&decoded, columns)?
+            } else {
+                ensure!(
+                    predictor == 1,
+                    "unsupported cross-reference stream predictor"
+                );
+                decoded
+            }
+        }
+    };
+
+    let size = a_ok_or!(
+        dict.get("Size").and_then(|r| parse_u64(&data[r]));
+        ["cross-reference stream has no size"]
+    );
+
+    let index = if dict.get("Index").is_some() {
+        dict.get_int_array(data, "Index")?
+    } else {
+        vec![0, size]
+    };
+    ensure!(
+        index.len() % 2 == 0,
+        "malformed cross-reference stream index"
+    );
+
+    let mut entries = BTreeMap::new();
+    let mut rows = decoded.chunks_exact(row_len);
+
+    for section in index.chunks_exact(2) {
+        for num in section[0]..section[0] + section[1] {
+            let row = a_ok_or!(rows.next(); ["cross-reference stream is truncated"]);
+            let mut fields = [0u64; 3];
+            let mut i = 0;
+
+            for (f, w) in fields.iter_mut().zip(&widths) {
+                for b in &row[i..i + *w as usize] {
+                    *f = (*f << 8) | *b as u64;
+                }
+
+                i += *w as usize;
+            }
+
+            // A zero-width type field means that the type is 1.
+            let ty = if widths[0] == 0 { 1 } else { fields[0] };
+
+            let entry = match ty {
+                0 => XrefEntry::Free(fields[1], fields[2]),
+                1 => XrefEntry::InFile(fields[1], fields[2]),
+                2 => XrefEntry::InStream(fields[1], fields[2]),
+                _ => continue,
+            };
+
+            entries.insert(num, entry);
+        }
+    }
+
+    Ok(Xref {
+        entries,
+        trailer: dict,
+        stream_id: Some((id[0], id[1])),
+    })
+}
+
+/// Undo the PNG predictors applied to the rows of a stream with one byte
+/// per pixel.
+fn unpredict_png(data: &[u8], columns: usize) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(data.len());
+    let mut prev = vec![0u8; columns];
+
+    for row in data.chunks(columns + 1) {
+        ensure!(row.len() == columns + 1, "truncated predicted row");
+        let mut cur = vec![0u8; columns];
+
+        for i in 0..columns {
+            let left = if i > 0 { cur[i - 1] } else { 0 };
+            let up = prev[i];
+            let up_left = if i > 0 { prev[i - 1] } else { 0 };
+            let x = row[i + 1];
+
+            cur[i] = match row[0] {
+                0 => x,
+                1 => x.wrapping_add(left),
+                2 => x.wrapping_add(up),
+                3 => x.wrapping_add(((left as u16 + up as u16) / 2) as u8),
+                4 => x.wrapping_add(paeth(left, up, up_left)),
+                _ => bail!("unknown PNG predictor {}", row[0]),
+            };
+        }
+
+        out.extend_from_slice(&cur);
+        prev = cur;
+    }
+
+    Ok(out)
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+fn write_xref_table(
+    out: &mut Vec<u8>,
+    data: &[u8],
+    entries: &BTreeMap<u64, XrefEntry>,
+    trailer: &Dict,
+) -> Result<()> {
+    let xref_offset = out.len();
+    out.extend_from_slice(b"xref\n");
+
+    for section in sections(entries) {
+        writeln!(out, "{} {}", section.start, section.end - section.start)?;
+
+        for num in section {
+            match entries[&num] {
+                XrefEntry::InFile(ofs, gen) => write!(out, "{ofs:010} {gen:05} n\r\n")?,
+                XrefEntry::Free(next, gen) => write!(out, "{next:010} {gen:05} f\r\n")?,
+                XrefEntry::InStream(..) => bail!("object stream entry in a cross-reference table"),
+            }
+        }
+    }
+
+    out.extend_from_slice(b"trailer\n");
+    out.extend_from_slice(&data[trailer.start..trailer.end]);
+    write!(out, "\nstartxref\n{xref_offset}\n%%EOF\n")?;
+    Ok(())
+}
+
+fn write_xref_stream(
+    out: &mut Vec<u8>,
+    data: &[u8],
+    entries: &mut BTreeMap<u64, XrefEntry>,
+    trailer: &Dict,
+    id: (u64, u64),
+    level: Compression,
+) -> Result<()> {
+    let xref_offset = out.len() as u64;
+    entries.insert(id.0, XrefEntry::InFile(xref_offset, id.1));
+
+    let (w1, w2) = entries.values().fold((1, 1), |(w1, w2), e| {
+        let (a, b) = match *e {
+            XrefEntry::Free(a, b) | XrefEntry::InFile(a, b) | XrefEntry::InStream(a, b) => (a, b),
+        };
+        (w1.max(byte_width(a)), w2.max(byte_width(b)))
+    });
+
+    let mut rows = Vec::new();
+    let mut index = String::new();
+
+    for section in sections(entries) {
+        if !index.is_empty() {
+            index.push(' ');
+        }
+
+        index.push_str(&format!(
+            "{} {}",
+            section.start,
+            section.end - section.start
+        ));
+
+        for num in section {
+            let (ty, a, b) = match entries[&num] {
+                XrefEntry::Free(a, b) => (0, a, b),
+                XrefEntry::InFile(a, b) => (1, a, b),
+                XrefEntry::InStream(a, b) => (2, a, b),
+            };
+
+            rows.push(ty);
+            rows.extend_from_slice(&a.to_be_bytes()[8 - w1..]);
+            rows.extend_from_slice(&b.to_be_bytes()[8 - w2..]);
+        }
+    }
+
+    let mut enc = ZlibEncoder::new(Vec::new(), level);
+    enc.write_all(&rows)?;
+    let rows = enc.finish()?;
+
+    // Keep the entries of the old dictionary that describe the document,
+    // such as `/Root` and `/ID`, and replace the ones that describe the
+    // stream.
+
+    write!(out, "{} {} obj\n<<", id.0, id.1)?;
+
+    for (key, value) in &trailer.entries {
+        if matches!(
+            key.as_str(),
+            "Length" | "Filter" | "DecodeParms" | "W" | "Index" | "Size"
+        ) {
+            continue;
+        }
+
+        write!(out, "/{key} ")?;
+        out.extend_from_slice(&data[value.clone()]);
+    }
+
+    let size = entries.keys().next_back().map_or(0, |n| n + 1);
+    write!(
+        out,
+        "/Size {size}/Index [{index}]/W [1 {w1} {w2}]/Filter /FlateDecode/Length {}>>\nstream\n",
+        rows.len()
+    )?;
+    out.extend_from_slice(&rows);
+    write!(
+        out,
+        "\nendstream\nendobj\nstartxref\n{xref_offset}\n%%EOF\n"
+    )?;
+    Ok(())
+}
+
+/// The number of bytes needed to store a number, at least one.
+fn byte_width(n: u64) -> usize {
+    ((64 - n.leading_zeros() as usize).div_ceil(8)).max(1)
+}
+
+/// Group the object numbers of the entries into runs of consecutive
+/// numbers.
+fn sections(entries: &BTreeMap<u64, XrefEntry>) -> Vec<Range<u64>> {
+    let mut sections: Vec<Range<u64>> = Vec::new();
+
+    for &num in entries.keys() {
+        match sections.last_mut() {
+            Some(r) if r.end == num => r.end += 1,
+            _ => sections.push(num..num + 1),
+        }
+    }
+
+    sections
+}
+
+// Lexing. This is just enough to pick apart the dictionaries that we need
+// to look at.
+
+/// A parsed dictionary: the ranges of its values by key.
+#[derive(Debug)]
+pub(crate) struct Dict {
+    start: usize,
+    pub(crate) end: usize,
+    entries: Vec<(String, Range<usize>)>,
+}
+
+impl Dict {
+    /// Parse the dictionary starting at *pos*, which must point to `<<`.
+    pub(crate) fn parse(data: &[u8], pos: usize) -> Result<Dict> {
+        ensure!(data[pos..].starts_with(b"<<"), "expected a dictionary");
+        let mut entries = Vec::new();
+        let mut p = pos + 2;
+
+        loop {
+            p = skip_ws(data, p);
+            ensure!(p < data.len(), "unterminated dictionary");
+
+            if data[p..].starts_with(b">>") {
+                return Ok(Dict {
+                    start: pos,
+                    end: p + 2,
+                    entries,
+                });
+            }
+
+            ensure!(data[p] == b'/', "expected a dictionary key");
+            let (key, end) = token(data, p + 1);
+            let key = String::from_utf8_lossy(key).into_owned();
+            let vstart = skip_ws(data, end);
+            let vend = skip_value(data, vstart)?;
+            entries.push((key, vstart..vend));
+            p = vend;
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<Range<usize>> {
+        self.entries
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, r)| r.clone())
+    }
+
+    fn get_int_array(&self, data: &[u8], key: &str) -> Result<Vec<u64>> {
+        let r = a_ok_or!(self.get(key); ["missing `/{}`", key]);
+        let text = &data[r];
+        ensure!(
+            text.starts_with(b"[") && text.ends_with(b"]"),
+            "`/{}` isn't an array",
+            key
+        );
+
+        let inner = &text[1..text.len() - 1];
+        let mut values = Vec::new();
+        let mut p = skip_ws(inner, 0);
+
+        while p < inner.len() {
+            let (tok, end) = token(inner, p);
+            values.push(a_ok_or!(parse_u64(tok); ["malformed `/{}`", key]));
+            p = skip_ws(inner, end);
+        }
+
+        Ok(values)
+    }
+}
+
+fn is_ws(b: u8) -> bool {
+    matches!(b, b'\0' | b'\t' | b'\n' | b'\x0c' | b'\r' | b' ')
+}
+
+fn is_delim(b: u8) -> bool {
+    matches!(
+        b,
+        b'(' | b')' | b'<' | b'>' | b'[' | b']' | b'{' | b'}' | b'/' | b'%'
+    )
+}
+
+/// Skip whitespace and comments.
+pub(crate) fn skip_ws(data: &[u8], mut pos: usize) -> usize {
+    while pos < data.len() {
+        if is_ws(data[pos]) {
+            pos += 1;
+        } else if data[pos] == b'%' {
+            while pos < data.len() && data[pos] != b'\n' && data[pos] != b'\r' {
+                pos += 1;
+            }
+        } else {
+            break;
+        }
+    }
+
+    pos
+}
+
+/// Get the run of regular characters starting at *pos*.
+pub(crate) fn token(data: &[u8], pos: usize) -> (&[u8], usize) {
+    let mut end = pos;
+
+    while end < data.len() && !is_ws(data[end]) && !is_delim(data[end]) {
+        end += 1;
+    }
+
+    (&data[pos..end], end)
+}
+
+/// Find the end of the value starting at *pos*.
+fn skip_value(data: &[u8], pos: usize) -> Result<usize> {
+    ensure!(pos < data.len(), "unexpected end of data");
+
+    match data[pos] {
+        b'<' if data[pos..].starts_with(b"<<") => Ok(Dict::parse(data, pos)?.end),
+
+        b'<' => {
+            let close = a_ok_or!(
+                data[pos..].iter().position(|b| *b == b'>');
+                ["unterminated hex string"]
+            );
+            Ok(pos + close + 1)
+        }
+
+        b'[' => {
+            let mut p = pos + 1;
+
+            loop {
+                p = skip_ws(data, p);
+                ensure!(p < data.len(), "unterminated array");
+
+                if data[p] == b']' {
+                    return Ok(p + 1);
+                }
+
+                p = skip_value(data, p)?;
+            }
+        }
+
+        b'(' => {
+            let mut depth = 0;
+            let mut p = pos;
+
+            while p < data.len() {
+                match data[p] {
+                    b'\\' => p += 1,
+                    b'(' => depth += 1,
+                    b')' => {
+                        depth -= 1;
+
+                        if depth == 0 {
+                            return Ok(p + 1);
+                        }
+                    }
+                    _ => {}
+                }
+
+                p += 1;
+            }
+
+            bail!("unterminated string")
+        }
+
+        b'/' => Ok(token(data, pos + 1).1),
+
+        _ => {
+            let (tok, end) = token(data, pos);
+            ensure!(!tok.is_empty(), "unexpected delimiter");
+
+            // An indirect reference, `<num> <gen> R`, is one value.
+            if parse_u64(tok).is_some() {
+                let (gen, gen_end) = token(data, skip_ws(data, end));
+
+                if parse_u64(gen).is_some() {
+                    let (r, r_end) = token(data, skip_ws(data, gen_end));
+
+                    if r == b"R" {
+                        return Ok(r_end);
+                    }
+                }
+            }
+
+            Ok(end)
+        }
+    }
+}
+
+pub(crate) fn parse_u64(tok: &[u8]) -> Option<u64> {
+    if tok.is_empty() || !tok.iter().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    std::str::from_utf8(tok).ok()?.parse().ok()
+}
+
+fn rfind(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).rposition(|w| w == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::pdf_with_table;
+
+    /// Check that every in-file entry of the cross-reference data points at
+    /// the right object, and return the contents of each stream.
+    fn check_and_read_streams(data: &[u8]) -> BTreeMap<u64, Vec<u8>> {
+        let xref = parse_xref(data, find_startxref(data).unwrap()).unwrap();
+        let mut streams = BTreeMap::new();
+
+        for (num, e) in &xref.entries {
+            let XrefEntry::InFile(ofs, _) = *e else {
+                continue;
+            };
+
+            let ofs = ofs as usize;
+            let (tok, _) = token(data, ofs);
+            assert_eq!(parse_u64(tok), Some(*num), "bad offset for object {num}");
+
+            if Some(*num) == xref.stream_id.map(|i| i.0) {
+                continue;
+            }
+
+            let body_start = data[ofs..].windows(3).position(|w| w == b"obj").unwrap() + ofs + 3;
+            let body_start = skip_ws(data, body_start);
+
+            if !data[body_start..].starts_with(b"<<") {
+                continue;
+            }
+
+            let dict = Dict::parse(data, body_start).unwrap();
+            let (tok, mut start) = token(data, skip_ws(data, dict.end));
+
+            if tok != b"stream" {
+                continue;
+            }
+
+            start += 1;
+            let len = parse_u64(&data[dict.get("Length").unwrap()]).unwrap() as usize;
+            let raw = &data[start..start + len];
+            assert!(data[start + len..].starts_with(b"\nendstream"));
+
+            let content = if dict.get("Filter").is_some() {
+                let mut buf = Vec::new();
+                ZlibDecoder::new(raw).read_to_end(&mut buf).unwrap();
+                buf
+            } else {
+                raw.to_vec()
+            };
+
+            streams.insert(*num, content);
+        }
+
+        streams
+    }
+
+    fn run(data: &[u8]) -> Outcome {
+        compress(data, Compression::best()).unwrap()
+    }
+
+    #[test]
+    fn xref_table() {
+        let content = "BT /F1 12 Tf 72 712 Td (Hello, world!) Tj ET\n".repeat(50);
+        let stream = format!(
+            "<</Length {}>>\nstream\n{}\nendstream",
+            content.len(),
+            content
+        );
+        let data = pdf_with_table(&[
+            b"<</Type /Catalog /Pages 2 0 R>>",
+            b"<</Type /Pages /Kids [3 0 R] /Count 1>>",
+            b"<</Type /Page /Parent 2 0 R /Contents 4 0 R /Resources << >> >>",
+            stream.as_bytes(),
+        ]);
+
+        let Outcome::Compressed {
+            data: out,
+            n_streams,
+        } = run(&data)
+        else {
+            panic!("not compressed");
+        };
+
+        assert_eq!(n_streams, 1);
+        assert!(out.len() < data.len());
+        assert_eq!(check_and_read_streams(&out)[&4], content.as_bytes());
+
+        // A second pass has nothing to do.
+        assert!(matches!(run(&out), Outcome::Unchanged));
+    }
+
+    #[test]
+    fn indirect_length() {
+        let content = "0 0 m 100 100 l S\n".repeat(40);
+        let stream = format!("<</Length 3 0 R>>\nstream\n{content}\nendstream");
+        let length = content.len().to_string();
+        let data = pdf_with_table(&[b"<</Type /Catalog>>", stream.as_bytes(), length.as_bytes()]);
+
+        let Outcome::Compressed { data: out, .. } = run(&data) else {
+            panic!("not compressed");
+        };
+
+        assert_eq!(check_and_read_streams(&out)[&2], content.as_bytes());
+    }
+
+    #[test]
+    fn xref_stream() {
+        let content = "q 1 0 0 1 0 0 cm /Im1 Do Q\n".repeat(60);
+        let mut data = b"%PDF-1.5\n".to_vec();
+        let mut offsets = Vec::new();
+
+        offsets.push(data.len());
+        data.extend_from_slice(b"1 0 obj\n<</Type /Catalog /Pages 2 0 R>>\nendobj\n");
+        offsets.push(data.len());
+        write!(
+            data,
+            "2 0 obj\n<</Length {}>>\nstream\n{}\nendstream\nendobj\n",
+            content.len(),
+            content
+        )
+        .unwrap();
+
+        // The cross-reference stream, object 3, with PNG "up" predictors
+        // as xdvipdfmx writes it.
+        let xref_offset = data.len();
+        offsets.push(xref_offset);
+        let mut rows = vec![0u8, 0, 0xff];
+        for ofs in &offsets {
+            rows.extend_from_slice(&[1, (ofs >> 8) as u8, *ofs as u8]);
+        }
+
+        let mut predicted = Vec::new();
+        let mut prev = [0u8; 3];
+        for row in rows.chunks(3) {
+            predicted.push(2);
+            for i in 0..3 {
+                predicted.push(row[i].wrapping_sub(prev[i]));
+            }
+            prev.copy_from_slice(row);
+        }
+
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(&predicted).unwrap();
+        let stream = enc.finish().unwrap();
+
+        write!(
+            data,
+            "3 0 obj\n<</Type /XRef /Root 1 0 R /Size 4 /W [1 2 0] \
+             /Filter /FlateDecode /DecodeParms <</Columns 3 /Predictor 12>> \
+             /Length {}>>\nstream\n",
+            stream.len()
+        )
+        .unwrap();
+        data.extend_from_slice(&stream);
+        write!(
+            data,
+            "\nendstream\nendobj\nstartxref\n{xref_offset}\n%%EOF\n"
+        )
+        .unwrap();
+
+        let Outcome::Compressed { data: out, .. } = run(&data) else {
+            panic!("not compressed");
+        };
+
+        let xref = parse_xref(&out, find_startxref(&out).unwrap()).unwrap();
+        assert_eq!(xref.stream_id, Some((3, 0)));
+        assert!(xref.trailer.get("Root").is_some());
+        assert_eq!(check_and_read_streams(&out)[&2], content.as_bytes());
+    }
+
+    #[test]
+    fn unsupported() {
+        let mut data = pdf_with_table(&[b"<</Type /Catalog>>"]);
+        let root = rfind(&data, b"/Root 1 0 R").unwrap() + 11;
+        data.splice(root..root, b" /Prev 9".iter().copied());
+        assert!(matches!(run(&data), Outcome::Unsupported(_)));
+
+        let metadata = format!(
+            "<</Type /Metadata /Subtype /XML /Length 400>>\nstream\n{}\nendstream",
+            " ".repeat(400)
+        );
+        let data = pdf_with_table(&[b"<</Type /Catalog>>", metadata.as_bytes()]);
+        assert!(matches!(run(&data), Outcome::Unchanged));
+
+        assert!(compress(b"%PDF-1.4\ngarbage", Compression::best()).is_err());
+    }
+
+    #[test]
+    fn predictors() {
+        // Rows of [1, 2, 3] and [4, 6, 8], with the "sub" and "up"
+        // predictors.
+        let data = [1, 1, 1, 1, 2, 3, 4, 5];
+        assert_eq!(unpredict_png(&data, 3).unwrap(), [1, 2, 3, 4, 6, 8]);
+        assert_eq!(byte_width(0), 1);
+        assert_eq!(byte_width(255), 1);
+        assert_eq!(byte_width(256), 2);
+    }
+}
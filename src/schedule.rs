@@ -0,0 +1,430 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Building several documents concurrently.
+//!
+//! A workspace can contain many documents, some of which depend on others,
+//! such as a set of slides that includes figures from a paper. This module
+//! schedules their builds across a pool of workers: each document starts as
+//! soon as the documents that it depends on have been built and a worker is
+//! free, and documents whose dependencies fail aren't built at all.
+//!
+//! The scheduler doesn't know how to build a document itself. It's given a
+//! function that does so and reports the diagnostics that the build produced,
+//! which are kept separate for each document. Since the TeX engines can only
+//! run one at a time within a process, the function will usually run each
+//! build in a child process. Concurrent builds share the bundle and format
+//! caches: files are written to them atomically, and a format is only
+//! generated by one build at a time, with the others waiting to reuse it.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+use tectonic_errors::prelude::*;
+
+/// A document to be built by a [`BuildScheduler`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ScheduledDocument {
+    /// The name of the document.
+    pub name: String,
+
+    /// The names of the documents that must be built before this one.
+    pub depends_on: Vec<String>,
+}
+
+/// What a build function reports about the build of one document.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BuildOutput {
+    /// Whether the build succeeded.
+    pub succeeded: bool,
+
+    /// The messages printed during the build.
+    pub diagnostics: String,
+}
+
+/// How the build of a document turned out.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum BuildOutcome {
+    /// The document was built.
+    Succeeded,
+
+    /// The build failed.
+    Failed,
+
+    /// The document wasn't built, because the named document that it depends
+    /// on, directly or indirectly, failed.
+    Skipped(String),
+}
+
+/// The result of building one document, as given by [`BuildScheduler::run`].
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DocumentReport {
+    /// The name of the document.
+    pub name: String,
+
+    /// How the build turned out.
+    pub outcome: BuildOutcome,
+
+    /// The messages printed during the build. This is empty if the document
+    /// was skipped.
+    pub diagnostics: String,
+
+    /// How long the build took.
+    pub elapsed: Duration,
+}
+
+impl fmt::Display for DocumentReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.outcome {
+            BuildOutcome::Succeeded => write!(
+                f,
+                "`{}` built in {:.1}s",
+                self.name,
+                self.elapsed.as_secs_f64()
+            ),
+            BuildOutcome::Failed => write!(
+                f,
+                "`{}` failed after {:.1}s",
+                self.name,
+                self.elapsed.as_secs_f64()
+            ),
+            BuildOutcome::Skipped(dep) => {
+                write!(f, "`{}` skipped because `{}` failed", self.name, dep)
+            }
+        }
+    }
+}
+
+/// A scheduler for building the documents of a workspace concurrently, in an
+/// order that respects the dependencies between them.
+#[derive(Debug)]
+pub struct BuildScheduler {
+    docs: Vec<ScheduledDocument>,
+
+    /// The indices of the documents that each document depends on.
+    deps: Vec<Vec<usize>>,
+
+    jobs: usize,
+}
+
+impl BuildScheduler {
+    /// Create a scheduler for the given documents.
+    ///
+    /// Returns an error if a document depends on one that isn't among them,
+    /// or if their dependencies form a cycle. By default, as many documents
+    /// are built at once as there are CPUs.
+    pub fn new(docs: Vec<ScheduledDocument>) -> Result<Self> {
+        let index: HashMap<&str, usize> = docs
+            .iter()
+            .enumerate()
+            .map(|(i, d)| (d.name.as_str(), i))
+            .collect();
+
+        ensure!(
+            index.len() == docs.len(),
+            "documents to be built must have distinct names"
+        );
+
+        let mut deps = Vec::with_capacity(docs.len());
+
+        for doc in &docs {
+            let mut these = Vec::new();
+
+            for dep in &doc.depends_on {
+                let Some(&i) = index.get(dep.as_str()) else {
+                    bail!(
+                        "document `{}` depends on `{}`, which isn't being built",
+                        doc.name,
+                        dep
+                    );
+                };
+
+                if !these.contains(&i) {
+                    these.push(i);
+                }
+            }
+
+            deps.push(these);
+        }
+
+        let jobs = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+
+        let sched = BuildScheduler { docs, deps, jobs };
+        let order = sched.order();
+
+        if order.len() < sched.docs.len() {
+            let cycle: Vec<String> = (0..sched.docs.len())
+                .filter(|i| !order.contains(i))
+                .map(|i| format!("`{}`", sched.docs[i].name))
+                .collect();
+            bail!(
+                "documents {} can't be built, because their dependencies form a cycle",
+                cycle.join(", ")
+            );
+        }
+
+        Ok(sched)
+    }
+
+    /// Set the largest number of documents to build at once. The default is
+    /// the number of CPUs.
+    pub fn jobs(&mut self, jobs: usize) -> &mut Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Get the names of the documents in an order in which they could be
+    /// built one at a time.
+    pub fn build_order(&self) -> Vec<&str> {
+        self.order()
+            .into_iter()
+            .map(|i| self.docs[i].name.as_str())
+            .collect()
+    }
+
+    /// Get the indices of the documents in an order that respects their
+    /// dependencies, preferring the order in which they were given. If the
+    /// dependencies form a cycle, the documents in it are left out.
+    fn order(&self) -> Vec<usize> {
+        let mut done = vec![false; self.docs.len()];
+        let mut order = Vec::with_capacity(self.docs.len());
+
+        while let Some(i) =
+            (0..self.docs.len()).find(|&i| !done[i] && self.deps[i].iter().all(|&d| done[d]))
+        {
+            done[i] = true;
+            order.push(i);
+        }
+
+        order
+    }
+
+    /// Build the documents.
+    ///
+    /// The *build* function is called with the name of each document to be
+    /// built, on a worker thread, and up to the configured number of calls
+    /// run at once. Each time a document is finished with, whether it was
+    /// built or skipped, *finished* is called with its report, on the calling
+    /// thread. The reports are also returned, in the order that the documents
+    /// were given.
+    pub fn run<B, F>(&self, build: B, mut finished: F) -> Vec<DocumentReport>
+    where
+        B: Fn(&str) -> BuildOutput + Sync,
+        F: FnMut(&DocumentReport),
+    {
+        let n = self.docs.len();
+        let mut dependents = vec![Vec::new(); n];
+        let mut waiting_on: Vec<usize> = self.deps.iter().map(Vec::len).collect();
+
+        for (i, deps) in self.deps.iter().enumerate() {
+            for &d in deps {
+                dependents[d].push(i);
+            }
+        }
+
+        let mut reports: Vec<Option<DocumentReport>> = vec![None; n];
+        let mut ready: Vec<usize> = (0..n).filter(|&i| waiting_on[i] == 0).collect();
+        let mut running = 0;
+        let build = &build;
+
+        thread::scope(|scope| {
+            let (tx, rx) = mpsc::channel();
+
+            loop {
+                // Start documents in the order they were given.
+                ready.sort_unstable_by(|a, b| b.cmp(a));
+
+                while running < self.jobs {
+                    let Some(i) = ready.pop() else {
+                        break;
+                    };
+
+                    let tx = tx.clone();
+                    let name = self.docs[i].name.as_str();
+                    running += 1;
+
+                    scope.spawn(move || {
+                        let start = Instant::now();
+                        let output = build(name);
+                        // The receiver outlives all of the workers.
+                        let _ = tx.send((i, output, start.elapsed()));
+                    });
+                }
+
+                if running == 0 {
+                    break;
+                }
+
+                let (i, output, elapsed) = rx.recv().expect("build workers never hang up");
+                running -= 1;
+
+                let report = DocumentReport {
+                    name: self.docs[i].name.clone(),
+                    outcome: if output.succeeded {
+                        BuildOutcome::Succeeded
+                    } else {
+                        BuildOutcome::Failed
+                    },
+                    diagnostics: output.diagnostics,
+                    elapsed,
+                };
+                finished(&report);
+                reports[i] = Some(report);
+
+                if output.succeeded {
+                    for &j in &dependents[i] {
+                        waiting_on[j] -= 1;
+
+                        if waiting_on[j] == 0 && reports[j].is_none() {
+                            ready.push(j);
+                        }
+                    }
+                } else {
+                    self.skip_dependents(i, &dependents, &mut reports, &mut finished);
+                }
+            }
+        });
+
+        reports
+            .into_iter()
+            .map(|r| r.expect("every document is built or skipped"))
+            .collect()
+    }
+
+    /// Skip the documents that depend on document *failed*, directly or
+    /// indirectly, and haven't been skipped already.
+    fn skip_dependents<F: FnMut(&DocumentReport)>(
+        &self,
+        failed: usize,
+        dependents: &[Vec<usize>],
+        reports: &mut [Option<DocumentReport>],
+        finished: &mut F,
+    ) {
+        let mut stack = dependents[failed].clone();
+
+        while let Some(j) = stack.pop() {
+            if reports[j].is_some() {
+                continue;
+            }
+
+            let report = DocumentReport {
+                name: self.docs[j].name.clone(),
+                outcome: BuildOutcome::Skipped(self.docs[failed].name.clone()),
+                diagnostics: String::new(),
+                elapsed: Duration::ZERO,
+            };
+            finished(&report);
+            reports[j] = Some(report);
+            stack.extend_from_slice(&dependents[j]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn docs(spec: &[(&str, &[&str])]) -> Vec<ScheduledDocument> {
+        spec.iter()
+            .map(|(name, deps)| ScheduledDocument {
+                name: name.to_string(),
+                depends_on: deps.iter().map(|d| d.to_string()).collect(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn ordering() {
+        let sched = BuildScheduler::new(docs(&[
+            ("slides", &["paper"]),
+            ("paper", &[]),
+            ("notes", &[]),
+        ]))
+        .unwrap();
+        assert_eq!(sched.build_order(), ["paper", "slides", "notes"]);
+
+        let err =
+            BuildScheduler::new(docs(&[("a", &["b"]), ("b", &["a"]), ("c", &[])])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "documents `a`, `b` can't be built, because their dependencies form a cycle"
+        );
+
+        let err = BuildScheduler::new(docs(&[("a", &["z"])])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "document `a` depends on `z`, which isn't being built"
+        );
+    }
+
+    #[test]
+    fn dependencies_are_built_first() {
+        let started = Mutex::new(Vec::new());
+        let mut finished = Vec::new();
+
+        let reports = BuildScheduler::new(docs(&[
+            ("c", &["a", "b"]),
+            ("a", &[]),
+            ("b", &["a"]),
+            ("d", &[]),
+        ]))
+        .unwrap()
+        .jobs(3)
+        .run(
+            |name| {
+                started.lock().unwrap().push(name.to_owned());
+                BuildOutput {
+                    succeeded: true,
+                    diagnostics: format!("built {name}"),
+                }
+            },
+            |r| finished.push(r.name.clone()),
+        );
+
+        let started = started.into_inner().unwrap();
+        let pos = |name| started.iter().position(|n| n == name).unwrap();
+        assert!(pos("a") < pos("b") && pos("b") < pos("c"));
+        assert_eq!(finished.len(), 4);
+        assert_eq!(reports[0].name, "c");
+        assert_eq!(reports[0].outcome, BuildOutcome::Succeeded);
+        assert_eq!(reports[0].diagnostics, "built c");
+    }
+
+    #[test]
+    fn failures_skip_dependents() {
+        let reports = BuildScheduler::new(docs(&[
+            ("a", &[]),
+            ("b", &["a"]),
+            ("c", &["b"]),
+            ("d", &[]),
+        ]))
+        .unwrap()
+        .jobs(1)
+        .run(
+            |name| BuildOutput {
+                succeeded: name != "a",
+                diagnostics: String::new(),
+            },
+            |_| {},
+        );
+
+        let outcomes: Vec<_> = reports.iter().map(|r| r.outcome.clone()).collect();
+        assert_eq!(
+            outcomes,
+            [
+                BuildOutcome::Failed,
+                BuildOutcome::Skipped("a".to_owned()),
+                BuildOutcome::Skipped("a".to_owned()),
+                BuildOutcome::Succeeded,
+            ]
+        );
+        assert_eq!(reports[2].to_string(), "`c` skipped because `a` failed");
+    }
+}
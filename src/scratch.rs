@@ -0,0 +1,215 @@
+// Copyright 2026 the Tectonic Project
+// Licensed under the MIT License.
+
+//! Scratch space for processing sessions.
+//!
+//! Some parts of a build need real files on disk: external tools, the
+//! shell-escape work directory, and HTML output that's streamed as a tarball.
+//! Each run of a processing session puts all of them in one temporary
+//! directory, its scratch space, which is deleted when the run ends, whether
+//! it succeeds, fails, or panics. Where that directory lives is configurable,
+//! since the system's temporary directory isn't always the best place: build
+//! servers may want scratch files on a RAM-backed filesystem, or next to the
+//! project so that they're cleaned up along with it. The space used can also
+//! be capped, so that a runaway shell-escape command or external tool fails
+//! the build instead of filling up the disk.
+
+use std::{
+    fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tectonic_errors::prelude::*;
+use tempfile::TempDir;
+
+/// Where the scratch space of a processing session is created.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub enum ScratchLocation {
+    /// The system's temporary directory, as given by
+    /// [`std::env::temp_dir()`].
+    #[default]
+    System,
+
+    /// The project: the directory where the outputs are written, or the
+    /// directory of the primary input if they aren't written to disk.
+    Project,
+
+    /// A RAM-backed filesystem, `/dev/shm`, if there is one. Otherwise, the
+    /// system's temporary directory is used.
+    Memory,
+
+    /// The given directory, which must exist.
+    Dir(PathBuf),
+}
+
+impl FromStr for ScratchLocation {
+    type Err = Error;
+
+    /// Parse `system`, `project`, or `memory`. Anything else is taken to be
+    /// the path of a directory.
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "system" => ScratchLocation::System,
+            "project" => ScratchLocation::Project,
+            "memory" => ScratchLocation::Memory,
+            "" => bail!("expected `system`, `project`, `memory`, or a path"),
+            path => ScratchLocation::Dir(path.into()),
+        })
+    }
+}
+
+impl fmt::Display for ScratchLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScratchLocation::System => f.write_str("system"),
+            ScratchLocation::Project => f.write_str("project"),
+            ScratchLocation::Memory => f.write_str("memory"),
+            ScratchLocation::Dir(p) => write!(f, "{}", p.display()),
+        }
+    }
+}
+
+/// The directory that holds a RAM-backed filesystem on most Linux systems.
+const MEMORY_DIR: &str = "/dev/shm";
+
+/// The scratch space of one run of a processing session.
+///
+/// The directory is deleted when this is dropped, which happens even if the
+/// run panics. [`ScratchSpace::close()`] deletes it and reports any problem.
+#[derive(Debug)]
+pub(crate) struct ScratchSpace {
+    dir: TempDir,
+    limit: Option<u64>,
+}
+
+impl ScratchSpace {
+    /// Create the scratch space in the given location. *project_dir* is the
+    /// directory used for [`ScratchLocation::Project`].
+    pub fn create(
+        location: &ScratchLocation,
+        project_dir: &Path,
+        limit: Option<u64>,
+    ) -> Result<Self> {
+        let parent = match location {
+            ScratchLocation::System => std::env::temp_dir(),
+            ScratchLocation::Project => project_dir.to_owned(),
+            ScratchLocation::Memory if Path::new(MEMORY_DIR).is_dir() => MEMORY_DIR.into(),
+            ScratchLocation::Memory => std::env::temp_dir(),
+            ScratchLocation::Dir(p) => p.clone(),
+        };
+
+        let dir = atry!(
+            tempfile::Builder::new().prefix(".tectonic-scratch").tempdir_in(&parent);
+            ["can't create scratch directory in `{}`", parent.display()]
+        );
+
+        Ok(ScratchSpace { dir, limit })
+    }
+
+    /// The path of the scratch directory.
+    pub fn path(&self) -> &Path {
+        self.dir.path()
+    }
+
+    /// Create a new directory within the scratch space, whose name starts
+    /// with the given prefix. It's deleted along with the scratch space, if
+    /// not before.
+    pub fn tempdir(&self, prefix: &str) -> Result<TempDir> {
+        Ok(atry!(
+            tempfile::Builder::new().prefix(prefix).tempdir_in(self.path());
+            ["can't create directory in scratch space `{}`", self.path().display()]
+        ))
+    }
+
+    /// Check that the files in the scratch space don't take up more than its
+    /// limit, if it has one.
+    pub fn check_usage(&self) -> Result<()> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+
+        let used = atry!(
+            dir_size(self.path());
+            ["can't measure the scratch space `{}`", self.path().display()]
+        );
+
+        ensure!(
+            used <= limit,
+            "the scratch space holds {} bytes of files, more than its limit of {} bytes",
+            used,
+            limit
+        );
+        Ok(())
+    }
+
+    /// Delete the scratch space.
+    pub fn close(self) -> Result<()> {
+        let path = self.path().to_owned();
+        atry!(
+            self.dir.close();
+            ["can't delete the scratch space `{}`", path.display()]
+        );
+        Ok(())
+    }
+}
+
+/// The total size of the files in a directory and its subdirectories.
+/// Symbolic links aren't followed.
+fn dir_size(dir: &Path) -> std::io::Result<u64> {
+    let mut size = 0;
+
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+
+        if file_type.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_locations() {
+        assert_eq!(
+            "system".parse::<ScratchLocation>().unwrap(),
+            ScratchLocation::System
+        );
+        assert_eq!(
+            "memory".parse::<ScratchLocation>().unwrap(),
+            ScratchLocation::Memory
+        );
+        assert_eq!(
+            "/mnt/scratch".parse::<ScratchLocation>().unwrap(),
+            ScratchLocation::Dir("/mnt/scratch".into())
+        );
+        assert!("".parse::<ScratchLocation>().is_err());
+    }
+
+    #[test]
+    fn limit_and_cleanup() {
+        let project = tempfile::tempdir().unwrap();
+        let scratch =
+            ScratchSpace::create(&ScratchLocation::Project, project.path(), Some(10)).unwrap();
+        let path = scratch.path().to_owned();
+        assert!(path.starts_with(project.path()));
+
+        let sub = scratch.tempdir("tool").unwrap();
+        fs::write(sub.path().join("a"), b"12345").unwrap();
+        scratch.check_usage().unwrap();
+        fs::write(sub.path().join("b"), b"123456").unwrap();
+        assert!(scratch.check_usage().is_err());
+
+        // Subdirectories that are kept are still deleted with the space.
+        let _ = sub.keep();
+        drop(scratch);
+        assert!(!path.exists());
+    }
+}
@@ -6,5 +6,6 @@
 pub mod termcolor;
 
 pub use tectonic_status_base::{
-    plain, ChatterLevel, MessageKind, NoopStatusBackend, StatusBackend,
+    json, plain, ChatterLevel, DownloadEvent, MessageKind, MissingCharacter, NoopStatusBackend,
+    StatusBackend,
 };
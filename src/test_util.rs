@@ -134,3 +134,35 @@ impl Bundle for TestBundle {
         self.0.all_files()
     }
 }
+
+/// Assemble a PDF with a cross-reference table from the bodies of its
+/// objects, numbered from 1, for the unit tests of the PDF code.
+#[cfg(test)]
+pub(crate) fn pdf_with_table(objects: &[&[u8]]) -> Vec<u8> {
+    use std::io::Write;
+
+    let mut out = b"%PDF-1.4\n%\xe2\xe3\xcf\xd3\n".to_vec();
+    let mut offsets = Vec::new();
+
+    for (i, body) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        writeln!(out, "{} 0 obj", i + 1).unwrap();
+        out.extend_from_slice(body);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref = out.len();
+    write!(out, "xref\n0 {}\n0000000000 65535 f\r\n", objects.len() + 1).unwrap();
+
+    for ofs in offsets {
+        write!(out, "{ofs:010} 00000 n\r\n").unwrap();
+    }
+
+    write!(
+        out,
+        "trailer\n<</Size {} /Root 1 0 R>>\nstartxref\n{xref}\n%%EOF\n",
+        objects.len() + 1
+    )
+    .unwrap();
+    out
+}
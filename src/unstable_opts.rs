@@ -11,14 +11,89 @@
 use std::default::Default;
 use std::path::PathBuf;
 use std::str::FromStr;
+use tectonic_engine_bibtex::{
+    EntryOrder, EntrySchema, FieldCheck, FieldLimit, Locale, Script, SortScheme,
+};
+use tectonic_engine_xetex::TraceCapture;
+
+use crate::scratch::ScratchLocation;
 
 const HELPMSG: &str = r#"Available unstable options:
 
     -Z help                     List all unstable options
+    -Z bibtex-biblatex          Have bibtex write the .bbl data of the biblatex package, as biber
+                                    would, instead of running a style
+    -Z bibtex-code-cache=<path> Load the code of bibtex style functions from <path>, keyed by the
+                                    digest of the style, and save it there afterwards, so that
+                                    later runs don't have to scan the functions again
+    -Z bibtex-collation=<locale>
+                                Sort bibtex entries in the order of a language, such as `de`,
+                                    `de-phonebook`, `sv`, `da`, `es`, `cs`, `pl`, or `root`,
+                                    rather than by the bytes of their sort keys
+    -Z bibtex-csf=<file>        Read bibtex's case mappings and sort order from a bibtex8-style
+                                    .csf character set file
+    -Z bibtex-duplicates        Report bibtex entries that seem to duplicate others, having the
+                                    same title and first author's last name
+    -Z bibtex-field-limit=[<field>=]<bytes>[:<action>]
+                                Limit the length of bibtex field values, of all fields or of one,
+                                    truncating longer ones with an ellipsis, or with an action of
+                                    `skip` or `keep`. Can be specified multiple times
+    -Z bibtex-hardened          Have bibtex stop with an error rather than panic if it finds its
+                                    own state inconsistent, as when running untrusted styles
+    -Z bibtex-lint              Check bibtex entries for missing required fields and unexpected
+                                    fields, using the standard schemas of their types
+    -Z bibtex-low-memory        Keep bibtex's memory use from growing with the size of its
+                                    databases, reading them twice with -Z bibtex-biblatex
+    -Z bibtex-output-files      Let bibtex styles write to files other than the .bbl file with the
+                                    output.file$ builtin
+    -Z bibtex-schema=<type>=<required>:<optional>
+                                Lint bibtex entries of <type> against comma-separated lists of
+                                    required and optional fields, with alternatives separated by
+                                    `/`. Can be specified multiple times. Implies -Z bibtex-lint
+    -Z bibtex-source-map        Write a map from the lines of each .bbl file back to the .bib
+                                    entries that produced them, in a .bbl.map file
+    -Z bibtex-sort=<order>      Reorder the entries of each .bbl file after the style has run: by
+                                    `citation` order, by `author-year`, or by `field:<name>`
+                                    [default: style]
+    -Z bibtex-sort-scheme=<scheme>
+                                Have bibtex's SORT command order entries by keys computed from
+                                    their fields rather than by the style's sort.key$: by
+                                    `author-year`, `nty`, `ynt`, or a comma-separated list of
+                                    `name`, `year`, `title`, and `field:<name>`
+    -Z bibtex-step-limit=<steps>
+                                Stop bibtex with an error once its style has called more than
+                                    <steps> functions, as when a while$ loop never ends
+    -Z bibtex-structured-data   Let bibtex read .json databases as CSL-JSON, and .yaml and .yml
+                                    databases as Hayagriva YAML
+    -Z bibtex-transliterate[=<scripts>]
+                                Transliterate a comma-separated list of scripts, `cyrillic` and
+                                    `greek`, into Latin letters in bibtex sort keys [default: all]
+    -Z bibtex-truncate-strings  Let bibtex truncate long strings stored in entry and global
+                                    variables, as classic BibTeX does
+    -Z bibtex-unicode-case      Let bibtex change the case of non-ASCII UTF-8 characters in
+                                    change.case$, not just ASCII letters
+    -Z bibtex-validate[=<fields>]
+                                Check the formats of the doi, isbn, issn, pages, and url fields of
+                                    bibtex entries, or of a comma-separated list of fields, each
+                                    given as <name> or <name>:<check>
     -Z continue-on-errors       Keep compiling even when severe errors occur
+    -Z html-citations           Give HTML citations popovers with their bibliography entries, and
+                                    give the entries links back to their citations
+    -Z html-incremental         Only rewrite HTML output files whose contents have changed, so that
+                                    unchanged files keep their modification times
+    -Z html-subset-fonts        Subset the fonts of HTML output to the glyphs that the document uses,
+                                    splitting large fonts into chunks by Unicode range
+    -Z max-crossref-depth=<num> Follow chains of bibtex crossrefs up to <num> levels deep
+                                    [default: 1]
     -Z min-crossrefs=<num>      Equivalent to bibtex's -min-crossrefs flag - "include after <num>
                                     crossrefs" [default: 2]
     -Z paper-size=<spec>        Change the initial paper size [default: letter]
+    -Z scratch-dir=<where>      Create the scratch files of each run, such as those of shell-escape
+                                    commands, in the `system` temporary directory, the `project`
+                                    output directory, `memory` (/dev/shm), or the directory at a
+                                    path [default: system]
+    -Z scratch-limit=<bytes>    Fail the build if the scratch files of a run take up more than
+                                    <bytes>
     -Z search-path=<path>       Also look in <path> for files (unless --untrusted has been specified),
                                     like TEXINPUTS. Can be specified multiple times.
     -Z shell-escape             Enable \write18 (unless --untrusted has been specified)
@@ -30,20 +105,60 @@ const HELPMSG: &str = r#"Available unstable options:
                                     and this option makes some extra functionality trade-offs.
                                     Specifically, deterministic mode breaks SyncTeX's auxiliary files
                                     as they include and rely on absolute file paths
+    -Z shaping-cache            Experimental: cache the shaping of text in native fonts and reuse it
+                                    in later TeX passes, and report how effective this was
+    -Z shaping-cache-file=<path>
+                                Load the shaping cache from <path> before the first TeX pass, if
+                                    it exists, and save it there afterwards, so that later builds
+                                    can reuse it. Implies -Z shaping-cache
+    -Z trace=<spec>             Turn on the TeX tracing parameters in a comma-separated list, such
+                                    as `macros,paragraphs`, and write their output to a .trace
+                                    file instead of the log. The list may also include
+                                    `page-range=<first>-<last>`, `line-range=<first>-<last>`,
+                                    `max-size=<bytes>`, and `compress`
 "#;
 
 // Each entry of this should correspond to a field of UnstableOptions.
 #[doc(hidden)]
 #[derive(Debug, Clone)]
 pub enum UnstableArg {
+    BibtexBiblatex,
+    BibtexCodeCache(PathBuf),
+    BibtexCollation(Locale),
+    BibtexCsf(String),
+    BibtexDuplicates,
+    BibtexFieldLimit(Option<String>, FieldLimit),
+    BibtexHardened,
+    BibtexLint,
+    BibtexLowMemory,
+    BibtexOutputFiles,
+    BibtexSchema(String, EntrySchema),
+    BibtexSourceMap,
+    BibtexSort(EntryOrder),
+    BibtexSortScheme(SortScheme),
+    BibtexStepLimit(u64),
+    BibtexStructuredData,
+    BibtexTransliterate(Vec<Script>),
+    BibtexTruncateStrings,
+    BibtexUnicodeCase,
+    BibtexValidate(Vec<(String, FieldCheck)>),
     ContinueOnErrors,
     Help,
+    HtmlCitations,
+    HtmlIncremental,
+    HtmlSubsetFonts,
     MinCrossrefs(u32),
+    MaxCrossrefDepth(u32),
     PaperSize(String),
+    ScratchDir(ScratchLocation),
+    ScratchLimit(u64),
     SearchPath(PathBuf),
     ShellEscapeEnabled,
     ShellEscapeCwd(String),
     DeterministicModeEnabled,
+    ShapingCacheEnabled,
+    ShapingCacheFile(PathBuf),
+    Trace(TraceCapture),
 }
 
 impl FromStr for UnstableArg {
@@ -78,16 +193,139 @@ impl FromStr for UnstableArg {
         match arg {
             "help" => Ok(UnstableArg::Help),
 
+            "bibtex-biblatex" => require_no_value(value, UnstableArg::BibtexBiblatex),
+
+            "bibtex-code-cache" => {
+                require_value("path").map(|s| UnstableArg::BibtexCodeCache(s.into()))
+            }
+
+            "bibtex-collation" => require_value("locale")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z bibtex-collation: {e}").into())
+                })
+                .map(UnstableArg::BibtexCollation),
+
+            "bibtex-csf" => require_value("file").map(|s| UnstableArg::BibtexCsf(s.to_string())),
+
+            "bibtex-duplicates" => require_no_value(value, UnstableArg::BibtexDuplicates),
+
+            "bibtex-field-limit" => require_value("limit").and_then(|s| {
+                let (field, limit) = match s.split_once('=') {
+                    Some((field, limit)) => (Some(field.trim().to_owned()), limit),
+                    None => (None, s),
+                };
+
+                limit
+                    .parse::<FieldLimit>()
+                    .map(|limit| UnstableArg::BibtexFieldLimit(field, limit))
+                    .map_err(|e| format!("-Z bibtex-field-limit: {e}").into())
+            }),
+
+            "bibtex-hardened" => require_no_value(value, UnstableArg::BibtexHardened),
+
+            "bibtex-lint" => require_no_value(value, UnstableArg::BibtexLint),
+
+            "bibtex-low-memory" => require_no_value(value, UnstableArg::BibtexLowMemory),
+
+            "bibtex-output-files" => require_no_value(value, UnstableArg::BibtexOutputFiles),
+
+            "bibtex-schema" => require_value("type=schema").and_then(|s| {
+                let Some((entry_type, schema)) = s.split_once('=') else {
+                    return Err(
+                        "-Z bibtex-schema: expected a value like `online=title,url:urldate`".into(),
+                    );
+                };
+
+                schema
+                    .parse::<EntrySchema>()
+                    .map(|schema| UnstableArg::BibtexSchema(entry_type.trim().to_owned(), schema))
+                    .map_err(|e| format!("-Z bibtex-schema: {e}").into())
+            }),
+
+            "bibtex-source-map" => require_no_value(value, UnstableArg::BibtexSourceMap),
+
+            "bibtex-sort" => require_value("order")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z bibtex-sort: {e}").into())
+                })
+                .map(UnstableArg::BibtexSort),
+
+            "bibtex-sort-scheme" => require_value("scheme")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z bibtex-sort-scheme: {e}").into())
+                })
+                .map(UnstableArg::BibtexSortScheme),
+
+            "bibtex-step-limit" => require_value("steps")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z bibtex-step-limit: {e}").into())
+                })
+                .map(UnstableArg::BibtexStepLimit),
+
+            "bibtex-structured-data" => require_no_value(value, UnstableArg::BibtexStructuredData),
+
+            "bibtex-transliterate" => match value {
+                None => Ok(UnstableArg::BibtexTransliterate(Script::ALL.to_vec())),
+                Some(s) => s
+                    .split(',')
+                    .map(|item| item.trim().parse())
+                    .collect::<tectonic_errors::Result<_>>()
+                    .map_err(|e| format!("-Z bibtex-transliterate: {e}").into())
+                    .map(UnstableArg::BibtexTransliterate),
+            },
+
+            "bibtex-truncate-strings" => {
+                require_no_value(value, UnstableArg::BibtexTruncateStrings)
+            }
+
+            "bibtex-unicode-case" => require_no_value(value, UnstableArg::BibtexUnicodeCase),
+
+            "bibtex-validate" => match value {
+                None => Ok(UnstableArg::BibtexValidate(
+                    FieldCheck::COMMON
+                        .iter()
+                        .map(|(name, check)| (name.to_string(), *check))
+                        .collect(),
+                )),
+                Some(s) => parse_field_checks(s)
+                    .map_err(|e| format!("-Z bibtex-validate: {e}").into())
+                    .map(UnstableArg::BibtexValidate),
+            },
+
             "continue-on-errors" => Ok(UnstableArg::ContinueOnErrors),
 
+            "html-citations" => require_no_value(value, UnstableArg::HtmlCitations),
+
+            "html-incremental" => require_no_value(value, UnstableArg::HtmlIncremental),
+
+            "html-subset-fonts" => require_no_value(value, UnstableArg::HtmlSubsetFonts),
+
             "min-crossrefs" => require_value("num")
                 .and_then(|s| {
                     FromStr::from_str(s).map_err(|e| format!("-Z min-crossrefs: {e}").into())
                 })
                 .map(UnstableArg::MinCrossrefs),
 
+            "max-crossref-depth" => require_value("num")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z max-crossref-depth: {e}").into())
+                })
+                .map(UnstableArg::MaxCrossrefDepth),
+
             "paper-size" => require_value("spec").map(|s| UnstableArg::PaperSize(s.to_string())),
 
+            "scratch-dir" => require_value("where")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z scratch-dir: {e}").into())
+                })
+                .map(UnstableArg::ScratchDir),
+
+            "scratch-limit" => require_value("bytes")
+                .and_then(|s| {
+                    FromStr::from_str(s).map_err(|e| format!("-Z scratch-limit: {e}").into())
+                })
+                .map(UnstableArg::ScratchLimit),
+
             "search-path" => require_value("path").map(|s| UnstableArg::SearchPath(s.into())),
 
             "shell-escape" => require_no_value(value, UnstableArg::ShellEscapeEnabled),
@@ -98,6 +336,16 @@ impl FromStr for UnstableArg {
 
             "deterministic-mode" => require_no_value(value, UnstableArg::DeterministicModeEnabled),
 
+            "shaping-cache" => require_no_value(value, UnstableArg::ShapingCacheEnabled),
+
+            "shaping-cache-file" => {
+                require_value("path").map(|s| UnstableArg::ShapingCacheFile(s.into()))
+            }
+
+            "trace" => require_value("spec")
+                .and_then(|s| FromStr::from_str(s).map_err(|e| format!("-Z trace: {e}").into()))
+                .map(UnstableArg::Trace),
+
             _ => Err(format!("Unknown unstable option '{arg}'").into()),
         }
     }
@@ -121,12 +369,105 @@ pub struct UnstableOptions {
     /// [`Self::shell_escape_cwd`] will take precedence over this flag.
     pub shell_escape: bool,
 
+    /// Have `bibtex` write the `.bbl` data read by the `biblatex` package,
+    /// rather than running a style.
+    pub bibtex_biblatex: bool,
+
+    /// A file from which `bibtex` loads the code of style functions that it
+    /// has scanned before, and to which it saves the code afterwards.
+    pub bibtex_code_cache: Option<PathBuf>,
+
+    /// The language in whose order `bibtex` sorts entries, rather than by
+    /// the bytes of their sort keys.
+    pub bibtex_collation: Option<Locale>,
+
+    /// A bibtex8-style `.csf` file giving `bibtex`'s case mappings and sort
+    /// order.
+    pub bibtex_csf: Option<String>,
+
+    /// Have `bibtex` report entries that seem to duplicate others, with the
+    /// same title and first author.
+    pub bibtex_duplicates: bool,
+
+    /// Limits on the lengths of the field values that `bibtex` reads, for
+    /// the named fields or, without a name, for all of them.
+    pub bibtex_field_limits: Vec<(Option<String>, FieldLimit)>,
+
+    /// Have `bibtex` stop with an error, rather than panic, if it finds its
+    /// own state inconsistent.
+    pub bibtex_hardened: bool,
+
+    /// Have `bibtex` lint its entries against the schemas of their types
+    /// before the style runs.
+    pub bibtex_lint: bool,
+
+    /// When writing data for `biblatex`, have `bibtex` only store the cited
+    /// entries and those that they inherit from, reading the databases twice.
+    pub bibtex_low_memory: bool,
+
+    /// Schemas for `bibtex` to use when linting entries of the given types,
+    /// replacing the standard ones.
+    pub bibtex_schemas: Vec<(String, EntrySchema)>,
+
+    /// Allow `bibtex` styles to route their output to files other than the
+    /// `.bbl` file, using the `output.file$` builtin.
+    pub bibtex_output_files: bool,
+
+    /// Have `bibtex` write a map from the lines of each `.bbl` file back to
+    /// the `.bib` entries that produced them.
+    pub bibtex_source_map: bool,
+
+    /// Reorder the entries of each `.bbl` file after the `bibtex` style has
+    /// run, overriding the order that the style chose.
+    pub bibtex_sort: Option<EntryOrder>,
+
+    /// Have the `SORT` command of `bibtex` styles order the entries by keys
+    /// computed from their fields, rather than by their `sort.key$`.
+    pub bibtex_sort_scheme: Option<SortScheme>,
+
+    /// Stop `bibtex` with an error once its style has taken this many
+    /// steps, so that styles that loop forever can't hang the build.
+    pub bibtex_step_limit: Option<u64>,
+
+    /// Have `bibtex` read databases with `.json`, `.yaml`, and `.yml`
+    /// extensions as CSL-JSON and Hayagriva YAML, converting their items into
+    /// BibTeX entries.
+    pub bibtex_structured_data: bool,
+
+    /// Scripts that `bibtex` should transliterate into Latin letters in sort
+    /// keys, so that entries in different scripts sort predictably.
+    pub bibtex_transliterate: Vec<Script>,
+
+    /// Have `bibtex` truncate strings stored in entry and global variables
+    /// at the lengths that classic BibTeX allows, rather than keeping them
+    /// whole.
+    pub bibtex_truncate_strings: bool,
+
+    /// Have `bibtex` change the case of non-ASCII UTF-8 characters in
+    /// `change.case$`, rather than only ASCII letters.
+    pub bibtex_unicode_case: bool,
+
+    /// Fields whose values `bibtex` should check once it has read the
+    /// databases, with the check to apply to each.
+    pub bibtex_validate: Vec<(String, FieldCheck)>,
+
     /// Minimum number of cross-references in `bibtex` before an item gets its own standalone entry.
     pub min_crossrefs: Option<u32>,
 
+    /// How many levels of `crossref` fields `bibtex` follows when entries
+    /// inherit fields from the entries that they refer to.
+    pub max_crossref_depth: Option<u32>,
+
     /// Extra directories to search for input files during a processing session.
     pub extra_search_paths: Vec<PathBuf>,
 
+    /// Where the scratch space of each run of a processing session is
+    /// created.
+    pub scratch_location: ScratchLocation,
+
+    /// The most space that the scratch files of a run may take up, in bytes.
+    pub scratch_limit: Option<u64>,
+
     /// The working directory to use for shell escapes. The directory will be preserved after
     /// compilation is complete. This overrides [`Self::shell_escape`].
     pub shell_escape_cwd: Option<String>,
@@ -142,6 +483,35 @@ pub struct UnstableOptions {
     /// `/dev/urandom`), but anything else (especially behaviour in TeXLive
     /// packages) is considered a bug.
     pub deterministic_mode: bool,
+
+    /// Memoize the shaping of text in native fonts across the TeX passes of a
+    /// processing session. This is an experiment, and the session reports the
+    /// cache hit and miss counts so that its effectiveness can be evaluated.
+    pub shaping_cache: bool,
+
+    /// A file from which to load the shaping cache before the first TeX pass
+    /// of a processing session, and to which to save it afterwards, so that
+    /// it carries over between sessions. This has no effect unless
+    /// [`Self::shaping_cache`] is set.
+    pub shaping_cache_file: Option<PathBuf>,
+
+    /// When generating HTML, only rewrite output files whose contents have
+    /// changed, so that unchanged files keep their modification times.
+    pub html_incremental: bool,
+
+    /// When generating HTML, subset the emitted fonts to the glyphs that the
+    /// document uses, splitting large fonts into chunks by Unicode range.
+    pub html_subset_fonts: bool,
+
+    /// When generating HTML, give in-text citations popovers showing the
+    /// bibliography entries that they cite, and give the entries links back
+    /// to their citations. The text of the popovers comes from the BibTeX
+    /// runs of the session.
+    pub html_citations: bool,
+
+    /// Turn on some of the TeX tracing parameters for part of the document,
+    /// and capture their output in a separate `.trace` file.
+    pub trace: Option<TraceCapture>,
 }
 
 impl UnstableOptions {
@@ -156,16 +526,51 @@ impl UnstableOptions {
             use UnstableArg::*;
             match u {
                 Help => print_unstable_help_and_exit(),
+                BibtexBiblatex => opts.bibtex_biblatex = true,
+                BibtexCodeCache(p) => opts.bibtex_code_cache = Some(p),
+                BibtexCollation(locale) => opts.bibtex_collation = Some(locale),
+                BibtexCsf(name) => opts.bibtex_csf = Some(name),
+                BibtexDuplicates => opts.bibtex_duplicates = true,
+                BibtexFieldLimit(field, limit) => opts.bibtex_field_limits.push((field, limit)),
+                BibtexHardened => opts.bibtex_hardened = true,
+                BibtexLint => opts.bibtex_lint = true,
+                BibtexLowMemory => opts.bibtex_low_memory = true,
+                BibtexOutputFiles => opts.bibtex_output_files = true,
+                BibtexSchema(entry_type, schema) => {
+                    opts.bibtex_lint = true;
+                    opts.bibtex_schemas.push((entry_type, schema));
+                }
+                BibtexSourceMap => opts.bibtex_source_map = true,
+                BibtexSort(order) => opts.bibtex_sort = Some(order),
+                BibtexSortScheme(scheme) => opts.bibtex_sort_scheme = Some(scheme),
+                BibtexStepLimit(steps) => opts.bibtex_step_limit = Some(steps),
+                BibtexStructuredData => opts.bibtex_structured_data = true,
+                BibtexTransliterate(scripts) => opts.bibtex_transliterate.extend(scripts),
+                BibtexTruncateStrings => opts.bibtex_truncate_strings = true,
+                BibtexUnicodeCase => opts.bibtex_unicode_case = true,
+                BibtexValidate(checks) => opts.bibtex_validate.extend(checks),
                 ContinueOnErrors => opts.continue_on_errors = true,
+                HtmlCitations => opts.html_citations = true,
+                HtmlIncremental => opts.html_incremental = true,
+                HtmlSubsetFonts => opts.html_subset_fonts = true,
                 MinCrossrefs(num) => opts.min_crossrefs = Some(num),
+                MaxCrossrefDepth(num) => opts.max_crossref_depth = Some(num),
                 PaperSize(size) => opts.paper_size = Some(size),
                 ShellEscapeEnabled => opts.shell_escape = true,
+                ScratchDir(location) => opts.scratch_location = location,
+                ScratchLimit(bytes) => opts.scratch_limit = Some(bytes),
                 SearchPath(p) => opts.extra_search_paths.push(p),
                 ShellEscapeCwd(p) => {
                     opts.shell_escape_cwd = Some(p);
                     opts.shell_escape = true;
                 }
                 DeterministicModeEnabled => opts.deterministic_mode = true,
+                ShapingCacheEnabled => opts.shaping_cache = true,
+                ShapingCacheFile(p) => {
+                    opts.shaping_cache_file = Some(p);
+                    opts.shaping_cache = true;
+                }
+                Trace(capture) => opts.trace = Some(capture),
             }
         }
 
@@ -173,6 +578,19 @@ impl UnstableOptions {
     }
 }
 
+/// Parse a comma-separated list of fields to check, each of which is either
+/// the name of a check, applied to the field of the same name, or
+/// `<field>:<check>`.
+fn parse_field_checks(s: &str) -> tectonic_errors::Result<Vec<(String, FieldCheck)>> {
+    s.split(',')
+        .map(|item| {
+            let item = item.trim();
+            let (field, check) = item.split_once(':').unwrap_or((item, item));
+            Ok((field.to_owned(), check.parse()?))
+        })
+        .collect()
+}
+
 #[doc(hidden)]
 pub fn print_unstable_help_and_exit() {
     print!("{HELPMSG}");
@@ -5,10 +5,17 @@
 
 use std::collections::HashSet;
 use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
 
 use tectonic::io::{FilesystemIo, IoProvider, IoStack, MemoryIo};
-use tectonic::{errors::Result, BibtexEngine};
+use tectonic::{errors::Result, unstable_opts::UnstableOptions, BibtexEngine};
 use tectonic_bridge_core::{CoreBridgeLauncher, MinimalDriver};
+use tectonic_engine_bibtex::{
+    BibtexEngine as RawBibtexEngine, BibtexHistory, BibtexOutcome, BreakAction, BstCall,
+    BstCodeCache, EntryLocation, EntryOrder, FieldCheck, RepeatedEntry, Script, StackValue,
+    StyleCommand, StyleLint, StyleProblem, ValueType,
+};
 use tectonic_engine_xetex::TexOutcome;
 use tectonic_status_base::NoopStatusBackend;
 
@@ -20,6 +27,16 @@ struct TestCase {
     parts: &'static [&'static str],
     test_bbl: bool,
     expected_result: Result<TexOutcome>,
+    max_crossref_depth: Option<u32>,
+    extra_outputs: &'static [&'static str],
+    test_source_map: bool,
+    sort: Option<EntryOrder>,
+    sort_scheme: Option<&'static str>,
+    validate: bool,
+    lint: Option<&'static [(&'static str, &'static str)]>,
+    duplicates: bool,
+    transliterate: bool,
+    low_memory: bool,
 }
 
 impl TestCase {
@@ -29,6 +46,16 @@ impl TestCase {
             parts,
             test_bbl: true,
             expected_result: Ok(TexOutcome::Spotless),
+            max_crossref_depth: None,
+            extra_outputs: &[],
+            test_source_map: false,
+            sort: None,
+            sort_scheme: None,
+            validate: false,
+            lint: None,
+            duplicates: false,
+            transliterate: false,
+            low_memory: false,
         }
     }
 
@@ -42,6 +69,65 @@ impl TestCase {
         self
     }
 
+    fn max_crossref_depth(mut self, depth: u32) -> Self {
+        self.max_crossref_depth = Some(depth);
+        self
+    }
+
+    /// Enable the `output.file$` builtin, and expect the style to write these
+    /// files in addition to the `.bbl` file.
+    fn extra_outputs(mut self, names: &'static [&'static str]) -> Self {
+        self.extra_outputs = names;
+        self
+    }
+
+    /// Enable the source map, and check it against the expected `.bbl.map`
+    /// file.
+    fn test_source_map(mut self) -> Self {
+        self.test_source_map = true;
+        self
+    }
+
+    fn sort(mut self, order: EntryOrder) -> Self {
+        self.sort = Some(order);
+        self
+    }
+
+    fn sort_scheme(mut self, scheme: &'static str) -> Self {
+        self.sort_scheme = Some(scheme);
+        self
+    }
+
+    /// Check the formats of the common fields.
+    fn validate(mut self) -> Self {
+        self.validate = true;
+        self
+    }
+
+    /// Lint the entries, with these schemas in addition to the standard ones.
+    fn lint(mut self, schemas: &'static [(&'static str, &'static str)]) -> Self {
+        self.lint = Some(schemas);
+        self
+    }
+
+    /// Look for entries that duplicate each other.
+    fn duplicates(mut self) -> Self {
+        self.duplicates = true;
+        self
+    }
+
+    /// Transliterate all supported scripts in sort keys.
+    fn transliterate(mut self) -> Self {
+        self.transliterate = true;
+        self
+    }
+
+    /// Run in low-memory mode, or not.
+    fn low_memory(mut self, enabled: bool) -> Self {
+        self.low_memory = enabled;
+        self
+    }
+
     fn test_dir(&self) -> PathBuf {
         let mut p = test_path(&["bibtex"]);
         for sub in &self.parts[..self.parts.len() - 1] {
@@ -69,7 +155,37 @@ impl TestCase {
         let mut status = NoopStatusBackend::default();
         let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
 
-        let res = BibtexEngine::new().process(&mut launcher, &auxname, &Default::default());
+        let unstables = UnstableOptions {
+            max_crossref_depth: self.max_crossref_depth,
+            bibtex_output_files: !self.extra_outputs.is_empty(),
+            bibtex_source_map: self.test_source_map,
+            bibtex_sort: self.sort.clone(),
+            bibtex_sort_scheme: self.sort_scheme.map(|s| s.parse().unwrap()),
+            bibtex_validate: if self.validate {
+                FieldCheck::COMMON
+                    .iter()
+                    .map(|(name, check)| (name.to_string(), *check))
+                    .collect()
+            } else {
+                Vec::new()
+            },
+            bibtex_lint: self.lint.is_some(),
+            bibtex_schemas: self
+                .lint
+                .unwrap_or_default()
+                .iter()
+                .map(|(entry_type, schema)| (entry_type.to_string(), schema.parse().unwrap()))
+                .collect(),
+            bibtex_duplicates: self.duplicates,
+            bibtex_transliterate: if self.transliterate {
+                Script::ALL.to_vec()
+            } else {
+                Vec::new()
+            },
+            bibtex_low_memory: self.low_memory,
+            ..Default::default()
+        };
+        let res = BibtexEngine::new().process(&mut launcher, &auxname, &unstables);
 
         // Check that outputs match expectations.
 
@@ -84,6 +200,17 @@ impl TestCase {
                 expect.file(ExpectedFile::read_with_extension(&mut p, "bbl").collection(&files));
         }
 
+        if self.test_source_map {
+            let name = format!("{}.bbl.map", self.parts[self.parts.len() - 1]);
+            let path = self.test_dir().join(name);
+            expect = expect.file(ExpectedFile::read(path).collection(&files));
+        }
+
+        for name in self.extra_outputs {
+            let path = self.test_dir().join(name);
+            expect = expect.file(ExpectedFile::read(path).collection(&files));
+        }
+
         expect
             .file(ExpectedFile::read_with_extension(&mut p, "blg").collection(&files))
             .finish();
@@ -135,6 +262,718 @@ fn test_multi_bib() {
     TestCase::new(&["cites", "multi_file"]).go();
 }
 
+#[test]
+fn test_source_map() {
+    TestCase::new(&["cites", "many"])
+        .expect(Ok(TexOutcome::Warnings))
+        .test_source_map()
+        .go();
+}
+
+#[test]
+fn test_sort_by_citation() {
+    TestCase::new(&["entry_order", "citation"])
+        .sort(EntryOrder::Citation)
+        .go();
+}
+
+#[test]
+fn test_sort_scheme_by_year() {
+    TestCase::new(&["sort_scheme", "year"])
+        .sort_scheme("ynt")
+        .go();
+}
+
+#[test]
+fn test_transliterate_mixed_scripts() {
+    TestCase::new(&["translit", "mixed"]).transliterate().go();
+}
+
+#[test]
+fn test_validate_fields() {
+    TestCase::new(&["validate", "fields"])
+        .expect(Ok(TexOutcome::Warnings))
+        .validate()
+        .go();
+}
+
+#[test]
+fn test_lint_entries() {
+    TestCase::new(&["lint", "entries"])
+        .expect(Ok(TexOutcome::Warnings))
+        .lint(&[("online", "title,url:urldate")])
+        .go();
+}
+
+#[test]
+fn test_duplicate_entries() {
+    TestCase::new(&["duplicates", "entries"])
+        .expect(Ok(TexOutcome::Warnings))
+        .duplicates()
+        .go();
+}
+
+/// Run only the commands of the style that sort the entries, and check the
+/// sort keys that the style computed.
+#[test]
+fn test_style_driver() {
+    util::set_test_root();
+
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(
+        &test_path(&["bibtex", "entry_order"]),
+        false,
+        false,
+        HashSet::new(),
+    );
+    let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+    let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let mut keys = Vec::new();
+
+    RawBibtexEngine::default()
+        .process_with_driver(&mut launcher, "citation.aux", |session| {
+            assert_eq!(
+                session.commands()[..3],
+                [
+                    StyleCommand::Read,
+                    StyleCommand::Iterate("presort".to_owned()),
+                    StyleCommand::Sort,
+                ]
+            );
+            assert!(session.iterate("presort").is_err());
+
+            session.read()?;
+            session.iterate("presort")?;
+            session.sort()?;
+
+            for key in session.cite_keys() {
+                let sort_key = session.entry_str(&key, "sort.key$").unwrap();
+                keys.push((key, sort_key));
+            }
+
+            Ok(())
+        })
+        .unwrap();
+
+    assert_eq!(
+        keys,
+        [
+            ("andersson", "andersson  bo    2001    first things"),
+            ("knuth84", "knuth  donald e    1984    book"),
+            ("zed", "zed  alice    1999    last things"),
+        ]
+        .map(|(k, s)| (k.to_owned(), s.to_owned()))
+    );
+}
+
+/// Stop the style at the first call of one of its functions, and check what
+/// the breakpoint hook was told about it.
+#[test]
+fn test_bst_breakpoint() {
+    util::set_test_root();
+
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(
+        &test_path(&["bibtex", "entry_order"]),
+        false,
+        false,
+        HashSet::new(),
+    );
+    let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+    let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let calls = Arc::new(Mutex::new(Vec::new()));
+    let hook_calls = calls.clone();
+
+    let outcome = RawBibtexEngine::default()
+        .bst_breakpoint("SORTIFY")
+        .on_bst_breakpoint(move |call| {
+            hook_calls.lock().unwrap().push(call.clone());
+            BreakAction::Stop
+        })
+        .process(&mut launcher, "citation.aux")
+        .unwrap();
+
+    assert_eq!(outcome.history, BibtexHistory::Errors);
+    assert_eq!(
+        *calls.lock().unwrap(),
+        [BstCall {
+            function: "sortify".to_owned(),
+            line: 938,
+            depth: 6,
+            stack: vec![
+                StackValue::String(String::new()),
+                StackValue::String("Zed  Alice".to_owned()),
+            ],
+            cite_key: Some("zed".to_owned()),
+        }]
+    );
+}
+
+/// Run a style that calls a builtin registered by the embedding code.
+#[test]
+fn test_custom_builtin() {
+    util::set_test_root();
+
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(
+        &test_path(&["bibtex", "custom_builtins"]),
+        false,
+        false,
+        HashSet::new(),
+    );
+    let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+    let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let outcome = RawBibtexEngine::default()
+        .register_builtin("URL.cleanup$", |stack| {
+            let url = stack.pop_str().unwrap_or_default();
+            let url: String = url
+                .chars()
+                .filter(|c| !c.is_whitespace() && *c != '<' && *c != '>')
+                .collect();
+            stack.push_str(&url);
+            Ok(())
+        })
+        .process(&mut launcher, "url.aux")
+        .unwrap();
+
+    assert_eq!(
+        outcome,
+        BibtexOutcome {
+            history: BibtexHistory::Spotless,
+            n_warnings: 0,
+            n_errors: 0,
+            wrote_bbl: true,
+        }
+    );
+
+    let files = mem.files.borrow();
+    assert_eq!(
+        String::from_utf8_lossy(&files.get("url.bbl").unwrap().data),
+        "\\bibitem{tectonic}\nhttps://tectonic-typesetting.github.io/en/latest/\n\\bibitem{nourl}\n(none)\n"
+    );
+}
+
+#[test]
+fn test_char_widths() {
+    util::set_test_root();
+
+    let run = |engine: &mut RawBibtexEngine| {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "width"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let outcome = engine.process(&mut launcher, "width.aux").unwrap();
+        assert_eq!(outcome.history, BibtexHistory::Spotless);
+
+        let files = mem.files.borrow();
+        String::from_utf8_lossy(&files.get("width.bbl").unwrap().data).into_owned()
+    };
+
+    // The style prints the widths of the titles `Knuth`, `Stra{\ss}e Über`,
+    // and `{\ae\oe}`, in which BibTeX only recognizes `\oe` as a character.
+    assert_eq!(
+        run(&mut RawBibtexEngine::default()),
+        "\\bibitem{plain}\n2835\n\\bibitem{accents}\n4739\n\\bibitem{ligatures}\n1722\n"
+    );
+
+    // A table measures bytes, and leaves `\oe` as wide as in cmr10.
+    assert_eq!(
+        run(RawBibtexEngine::default().char_width_table([1; 256])),
+        "\\bibitem{plain}\n5\n\\bibitem{accents}\n13\n\\bibitem{ligatures}\n780\n"
+    );
+
+    assert_eq!(
+        run(RawBibtexEngine::default().char_width_metric(|c| if c.is_ascii() { 10 } else { 20 })),
+        "\\bibitem{plain}\n50\n\\bibitem{accents}\n130\n\\bibitem{ligatures}\n40\n"
+    );
+}
+
+#[test]
+fn test_long_strings() {
+    util::set_test_root();
+
+    let run = |truncate| {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "long_strings"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let outcome = RawBibtexEngine::default()
+            .truncate_strings(truncate)
+            .process(&mut launcher, "long.aux")
+            .unwrap();
+
+        let files = mem.files.borrow();
+        let bbl = String::from_utf8_lossy(&files.get("long.bbl").unwrap().data).into_owned();
+        (outcome.history, bbl)
+    };
+
+    // The style prints the lengths of an entry string and a global string.
+    assert_eq!(
+        run(false),
+        (
+            BibtexHistory::Spotless,
+            "\\bibitem{long}\n497\n20480\n".to_owned()
+        )
+    );
+    assert_eq!(
+        run(true),
+        (
+            BibtexHistory::Warnings,
+            "\\bibitem{long}\n250\n20000\n".to_owned()
+        )
+    );
+}
+
+/// Run a style plainly, with a step limit, and traced. The last two push
+/// each literal through the interpreter rather than straight from the code
+/// that the style's functions are lowered to, and the output must be the same.
+#[test]
+fn test_lowered_functions() {
+    util::set_test_root();
+
+    let dir = test_path(&["bibtex", "cites"]);
+    let expected = std::fs::read(dir.join("control_seq_alpha.bbl")).unwrap();
+
+    for (step_limit, trace) in [(None, false), (Some(u64::MAX), false), (None, true)] {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(&dir, false, false, HashSet::new());
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        if let Some(steps) = step_limit {
+            engine.step_limit(steps);
+        }
+        let outcome = engine
+            .trace_bst(trace)
+            .process(&mut launcher, "control_seq_alpha.aux")
+            .unwrap();
+        assert_eq!(outcome.history, BibtexHistory::Warnings);
+
+        let files = mem.files.borrow();
+        let bbl = &files.get("control_seq_alpha.bbl").unwrap().data;
+        assert_eq!(
+            String::from_utf8_lossy(bbl),
+            String::from_utf8_lossy(&expected)
+        );
+    }
+}
+
+/// Run a style with an empty code cache, save it and load it back, and run
+/// the style again with the loaded cache. Its functions are scanned the
+/// first time and reused the second, and the output must be the same.
+#[test]
+fn test_code_cache() {
+    util::set_test_root();
+
+    let dir = test_path(&["bibtex", "cites"]);
+    let expected = std::fs::read(dir.join("control_seq_alpha.bbl")).unwrap();
+    let mut cache = Some(BstCodeCache::new());
+
+    for reused in [false, true] {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(&dir, false, false, HashSet::new());
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        let outcome = engine
+            .code_cache(cache.take())
+            .process(&mut launcher, "control_seq_alpha.aux")
+            .unwrap();
+        assert_eq!(outcome.history, BibtexHistory::Warnings);
+
+        let files = mem.files.borrow();
+        let bbl = &files.get("control_seq_alpha.bbl").unwrap().data;
+        assert_eq!(
+            String::from_utf8_lossy(bbl),
+            String::from_utf8_lossy(&expected)
+        );
+
+        let used = engine.take_code_cache().unwrap();
+        let stats = used.stats();
+        let defs = used.len() as u64;
+        assert!(defs > 0);
+
+        if reused {
+            assert_eq!((stats.hits, stats.misses), (defs, 0));
+        } else {
+            assert_eq!((stats.hits, stats.misses), (0, defs));
+        }
+
+        let mut data = Vec::new();
+        used.save(&mut data).unwrap();
+        cache = BstCodeCache::load(&data[..]).unwrap();
+        assert!(cache.is_some());
+    }
+}
+
+#[test]
+fn test_low_memory() {
+    util::set_test_root();
+
+    let dir = test_path(&["bibtex", "low_memory"]);
+    let expected = std::fs::read(dir.join("shared.bbl")).unwrap();
+
+    for low_memory in [false, true] {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(&dir, false, false, HashSet::new());
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        // The second database repeats a cited key, and one key is missing.
+        let outcome = RawBibtexEngine::default()
+            .biblatex_output(true)
+            .low_memory(low_memory)
+            .process(&mut launcher, "shared.aux")
+            .unwrap();
+        assert_eq!(outcome.history, BibtexHistory::Errors);
+
+        let files = mem.files.borrow();
+        let bbl = &files.get("shared.bbl").unwrap().data;
+        assert_eq!(
+            String::from_utf8_lossy(bbl),
+            String::from_utf8_lossy(&expected)
+        );
+    }
+}
+
+#[test]
+fn test_low_memory_classic() {
+    // With a classic style, the outputs are the same in low-memory mode,
+    // including the suggestion for a misspelled key.
+    for low_memory in [false, true] {
+        TestCase::new(&["low_memory", "classic"])
+            .low_memory(low_memory)
+            .expect(Ok(TexOutcome::Errors))
+            .go();
+    }
+}
+
+#[test]
+fn test_citation_report() {
+    util::set_test_root();
+
+    let location = |file: &str, line| EntryLocation {
+        file: file.to_owned(),
+        line,
+    };
+
+    for low_memory in [false, true] {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "low_memory"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        engine
+            .biblatex_output(true)
+            .low_memory(low_memory)
+            .process(&mut launcher, "shared.aux")
+            .unwrap();
+        let report = engine.take_citation_report().unwrap();
+
+        let resolved: Vec<_> = report
+            .citations
+            .iter()
+            .map(|c| (c.cite_key.as_str(), c.entry.clone()))
+            .collect();
+        assert_eq!(
+            resolved,
+            [
+                ("knuth84", Some(location("first.bib", 3))),
+                ("Lamport94", Some(location("second.bib", 3))),
+                ("inproc", Some(location("first.bib", 18))),
+                ("missing", None),
+            ]
+        );
+        assert_eq!(report.unresolved().count(), 1);
+        assert!(!report.all_entries);
+        assert_eq!(
+            report.repeated_entries,
+            [RepeatedEntry {
+                cite_key: "knuth84".to_owned(),
+                location: location("second.bib", 16),
+                first: Some(location("first.bib", 3)),
+            }]
+        );
+    }
+}
+
+/// Run twice with the same build date, and check that the outputs and the
+/// citation reports are the same, down to the time that the report was made.
+#[test]
+fn test_build_date() {
+    util::set_test_root();
+
+    let date = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+    let run = || {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "cites"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        engine
+            .build_date(date)
+            .process(&mut launcher, "many.aux")
+            .unwrap();
+
+        let files = mem.files.borrow();
+        let output = |name: &str| files.get(name).unwrap().data.clone();
+        (
+            output("many.bbl"),
+            output("many.blg"),
+            engine.take_citation_report().unwrap(),
+        )
+    };
+
+    let first = run();
+    assert_eq!(first.2.generated, date);
+    assert_eq!(run(), first);
+}
+
+#[test]
+fn test_id_and_rng_sources() {
+    util::set_test_root();
+
+    let run = |setup: &dyn Fn(&mut RawBibtexEngine)| {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "cites"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        setup(&mut engine);
+        engine.process(&mut launcher, "many.aux").unwrap();
+        engine.take_citation_report().unwrap().run_id
+    };
+
+    // With the same seed, the random identifiers are the same.
+    let seeded = run(&|engine| {
+        engine.rng_seed(42);
+    });
+    assert_eq!(seeded.len(), 32);
+    assert_eq!(
+        run(&|engine| {
+            engine.rng_seed(42);
+        }),
+        seeded
+    );
+    assert_ne!(
+        run(&|engine| {
+            engine.rng_seed(43);
+        }),
+        seeded
+    );
+
+    // An id source takes precedence over the generator.
+    let counter = Arc::new(Mutex::new(0));
+    let ids = |engine: &mut RawBibtexEngine| {
+        let counter = counter.clone();
+        engine.rng_seed(42).id_source(move || {
+            let mut n = counter.lock().unwrap();
+            *n += 1;
+            format!("run-{n}")
+        });
+    };
+    assert_eq!(run(&ids), "run-1");
+    assert_eq!(run(&ids), "run-2");
+}
+
+#[test]
+fn test_take_preambles() {
+    util::set_test_root();
+
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(
+        &test_path(&["bibtex", "preamble"]),
+        false,
+        false,
+        HashSet::new(),
+    );
+    let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+    let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let mut engine = RawBibtexEngine::default();
+    engine.process(&mut launcher, "many.aux").unwrap();
+
+    assert_eq!(
+        engine.take_preambles(),
+        [
+            "This should appear before the bibliography. ",
+            "As well as this. ",
+            "And a third. ",
+        ]
+    );
+    assert!(engine.take_preambles().is_empty());
+}
+
+#[test]
+fn test_lint_style() {
+    util::set_test_root();
+
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(
+        &test_path(&["bibtex", "lint"]),
+        false,
+        false,
+        HashSet::new(),
+    );
+    let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets];
+    let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+    let mut status = NoopStatusBackend::default();
+    let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+    let mut engine = RawBibtexEngine::default();
+    engine.lint_style(true);
+    engine.process(&mut launcher, "style.aux").unwrap();
+
+    let lint = |function: &str, line, problem| StyleLint {
+        function: function.to_owned(),
+        file: "style.bst".to_owned(),
+        line,
+        problem,
+    };
+    let wrong_type = |callee: &str, expected, found| StyleProblem::WrongType {
+        callee: callee.to_owned(),
+        expected,
+        found,
+    };
+
+    assert_eq!(
+        engine.take_style_lints(),
+        [
+            lint(
+                "wrong.types",
+                14,
+                wrong_type("if$", ValueType::Integer, ValueType::String)
+            ),
+            lint(
+                "wrong.types",
+                15,
+                wrong_type("not", ValueType::Integer, ValueType::String)
+            ),
+            lint(
+                "wrong.types",
+                16,
+                wrong_type(":=", ValueType::String, ValueType::Integer)
+            ),
+            lint("loop", 32, StyleProblem::UnbalancedWhileBody),
+            lint(
+                "leftover",
+                41,
+                StyleProblem::LeftOnStack {
+                    command: StyleCommand::Iterate("leftover".to_owned()),
+                    values: 1,
+                }
+            ),
+            lint(
+                "underflow",
+                42,
+                StyleProblem::Underflow {
+                    command: StyleCommand::Execute("underflow".to_owned()),
+                    values: 1,
+                }
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_low_memory_suggestions() {
+    util::set_test_root();
+
+    let run = |low_memory| {
+        let mut mem = MemoryIo::new(true);
+        let mut assets = FilesystemIo::new(
+            &test_path(&["bibtex", "low_memory"]),
+            false,
+            false,
+            HashSet::new(),
+        );
+        let mut styles = FilesystemIo::new(&test_path(&["bibtex"]), false, false, HashSet::new());
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut assets, &mut styles];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = RawBibtexEngine::default();
+        engine
+            .low_memory(low_memory)
+            .process(&mut launcher, "typos.aux")
+            .unwrap();
+        engine.take_missing_entries()
+    };
+
+    // Only the keys near the misspelled ones are remembered in low-memory
+    // mode, which are the ones that are suggested anyway.
+    let missing = run(false);
+    assert_eq!(
+        missing.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+        [
+            "no database entry for \"knuth48\"; did you mean \"knuth84\"?",
+            "no database entry for \"procs\"; did you mean \"proc\"?",
+        ]
+    );
+    assert_eq!(run(true), missing);
+}
+
 #[test]
 fn test_empty_files() {
     TestCase::new(&["empty"])
@@ -205,6 +1044,55 @@ fn test_min_crossref() {
         .go();
 }
 
+#[test]
+fn test_nested_crossref() {
+    TestCase::new(&["crossref", "nested"])
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
+#[test]
+fn test_crossref_chain() {
+    TestCase::new(&["crossref", "chain"])
+        .max_crossref_depth(2)
+        .go();
+}
+
+#[test]
+fn test_crossref_cycle() {
+    TestCase::new(&["crossref", "cycle"])
+        .max_crossref_depth(3)
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_crossref_self() {
+    TestCase::new(&["crossref", "self"])
+        .max_crossref_depth(3)
+        .expect(Ok(TexOutcome::Errors))
+        .go();
+}
+
+#[test]
+fn test_xdata() {
+    TestCase::new(&["xdata", "xdata"])
+        .expect(Ok(TexOutcome::Warnings))
+        .go();
+}
+
+#[test]
+fn test_special_letters() {
+    TestCase::new(&["special_letters", "letters"]).go();
+}
+
+#[test]
+fn test_output_files() {
+    TestCase::new(&["output_files", "split"])
+        .extra_outputs(&["split-1.bbl", "split-2.bbl"])
+        .go();
+}
+
 #[test]
 fn test_single_preamble() {
     TestCase::new(&["preamble", "single"])
@@ -17,6 +17,42 @@ mod util;
 
 // Keep these alphabetized.
 
+#[test]
+fn streamed_inputs() {
+    util::set_test_root();
+
+    let _config = PersistentConfig::default();
+    let mut status = TermcolorStatusBackend::new(ChatterLevel::Minimal);
+    let bundle = TestBundle::default();
+
+    let tempdir = tempfile::Builder::new()
+        .prefix("tectonic_driver_test")
+        .tempdir()
+        .unwrap();
+
+    // Neither stream is seekable, and each can only be read once, even though
+    // the engine opens the included file more than once.
+    let mut pbuilder = ProcessingSessionBuilder::default();
+    pbuilder
+        .primary_input_stream(&b"\\input chapter \\input chapter \\bye\n"[..])
+        .input_stream("chapter.tex", &b"A\n"[..])
+        .tex_input_name("streamed.tex")
+        .format_name("plain")
+        .format_cache_path(util::test_path(&[]))
+        .output_dir(tempdir.path())
+        .bundle(Box::new(bundle));
+
+    let mut session = pbuilder
+        .create(&mut status)
+        .expect("couldn't create processing session");
+
+    session
+        .run(&mut status)
+        .expect("failed to execute processing session");
+
+    assert!(tempdir.path().join("streamed.pdf").exists());
+}
+
 #[test]
 fn the_letter_a() {
     util::set_test_root();
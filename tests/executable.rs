@@ -479,6 +479,48 @@ fn keep_logs_on_error() {
     assert!(log.contains(r"job aborted, no legal \end found"));
 }
 
+/// Test that graphics read from the filesystem are listed in the Makefile
+/// rules, along with the TeX inputs.
+#[test]
+fn makefile_rules_graphics() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&[]);
+    let temppath = tempdir.path();
+
+    fs::create_dir(temppath.join("figs")).unwrap();
+    fs::copy(
+        TEST_ROOT.join("assets/redbox.png"),
+        temppath.join("figs/pixel.png"),
+    )
+    .unwrap();
+    fs::write(
+        temppath.join("graphics.tex"),
+        "Hello {\\XeTeXpicfile figs/pixel.png } here is some text.\n\\bye\n",
+    )
+    .unwrap();
+
+    let output = run_tectonic(
+        temppath,
+        &[&fmt_arg, "graphics.tex", "--makefile-rules=graphics.d"],
+    );
+    success_or_panic(&output);
+
+    let rules = fs::read_to_string(temppath.join("graphics.d")).unwrap();
+    println!("rules:\n{rules}");
+    let deps: Vec<_> = rules
+        .split_once(": ")
+        .expect("the rules have no dependencies")
+        .1
+        .split(" \\\n")
+        .map(str::trim)
+        .collect();
+
+    assert!(deps.iter().any(|d| d.ends_with("graphics.tex")));
+    assert!(deps
+        .iter()
+        .any(|d| Path::new(d).ends_with("figs/pixel.png")));
+}
+
 #[test]
 fn no_color_option() {
     // No input files here, but output files are created.
@@ -522,6 +564,32 @@ fn outdir_option() {
     check_file(&tempdir, "subdirectory/1.pdf");
 }
 
+#[test]
+fn outdir_option_stdout() {
+    let fmt_arg = get_plain_format_arg();
+
+    for stream_args in [&["-o", "-"][..], &["--print-pdf"]] {
+        let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+
+        let mut args = vec![fmt_arg.as_str(), "subdirectory/content/1.tex"];
+        args.extend_from_slice(stream_args);
+        let output = run_tectonic(tempdir.path(), &args);
+        success_or_panic(&output);
+
+        // The PDF is all that goes to stdout, with the status messages going
+        // to stderr instead, and it isn't written to disk.
+        let pdf = &output.stdout;
+        assert!(pdf.starts_with(b"%PDF-"), "stdout isn't a PDF");
+        assert!(
+            pdf.trim_ascii_end().ends_with(b"%%EOF"),
+            "stdout has more than the PDF"
+        );
+        assert!(!output.stderr.is_empty());
+        assert!(!tempdir.path().join("subdirectory/content/1.pdf").exists());
+        assert!(!tempdir.path().join("1.pdf").exists());
+    }
+}
+
 #[test]
 #[should_panic]
 // panic unwinding broken: https://github.com/rust-embedded/cross/issues/343
@@ -660,6 +728,47 @@ fn bundle_overrides() {
     }
 }
 
+/// Smoke-test `-X bench` with a single timed build of two variants.
+#[test]
+fn v2_bench() {
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+    let temppath = tempdir.path();
+
+    let output = run_tectonic(
+        temppath,
+        &[
+            "-X",
+            "bench",
+            "--format=plain",
+            "--runs=1",
+            "--warmup=0",
+            "--variant=cached=shaping-cache",
+            "--report=bench.md",
+            "subdirectory/content/1.tex",
+        ],
+    );
+    success_or_panic(&output);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for variant in ["default", "cached"] {
+        for pass in ["TeX 1", "xdvipdfmx 1", "total"] {
+            assert!(
+                stdout
+                    .lines()
+                    .any(|l| l.starts_with(variant) && l.contains(pass)),
+                "no `{pass}` row for `{variant}`"
+            );
+        }
+    }
+
+    let report = fs::read_to_string(temppath.join("bench.md")).unwrap();
+    assert!(report.contains("- Variant `cached`: `-Z shaping-cache`"));
+    assert!(report.contains("| variant | pass |"));
+
+    // The builds' outputs go to temporary directories, not next to the input.
+    assert!(!temppath.join("subdirectory/content/1.pdf").exists());
+}
+
 /// Test various web bundle overrides for the v2 CLI
 #[cfg(feature = "serialization")]
 #[test]
@@ -713,6 +822,46 @@ fn v2_build_open() {
     success_or_panic(&output);
 }
 
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_build_draft() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "build", "--draft"]);
+    success_or_panic(&output);
+
+    let output = run_tectonic(
+        &temppath,
+        &["-X", "build", "--draft", "--record", "golden.json"],
+    );
+    error_or_panic(&output);
+}
+
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_build_golden_digests() {
+    let (_tempdir, temppath) = setup_v2();
+
+    let output = run_tectonic(
+        &temppath,
+        &["-X", "build", "--record", "golden.json", "--normalize"],
+    );
+    success_or_panic(&output);
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--verify", "golden.json"]);
+    success_or_panic(&output);
+
+    {
+        let mut path = temppath.clone();
+        path.push("src");
+        path.push("index.tex");
+        let mut file = File::create(&path).unwrap();
+        writeln!(file, "Goodbye, world!").unwrap();
+    }
+
+    let output = run_tectonic(&temppath, &["-X", "build", "--verify", "golden.json"]);
+    error_or_panic(&output);
+}
+
 #[cfg(feature = "serialization")]
 #[test]
 fn v2_build_multiple_outputs() {
@@ -863,6 +1012,36 @@ fn v2_dump_suffix() {
     assert!(saw_first && saw_second);
 }
 
+/// Checks that `-X selftest` runs and reports its failures. The test bundle's
+/// "latex" format is a stub that can't build the self-test document, so the
+/// TeX stage always fails here, and the later stages aren't reached.
+#[test]
+fn v2_selftest_reports_failure() {
+    let tempdir = setup_and_copy_files(&[]);
+    let temppath = tempdir.path();
+
+    let output = run_tectonic(temppath, &["-X", "selftest", "--outdir=selftest"]);
+    error_or_panic(&output);
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let result = |stage: &str| {
+        stdout
+            .lines()
+            .find_map(|l| l.strip_prefix(stage)?.strip_prefix(' '))
+            .unwrap_or_else(|| panic!("no `{stage}` row in the report"))
+            .trim_start()
+            .to_owned()
+    };
+    assert!(result("bundle").starts_with("pass"));
+    assert!(result("TeX").starts_with("FAIL"));
+    assert!(result("BibTeX").starts_with("not run"));
+    assert!(result("xdvipdfmx").starts_with("not run"));
+
+    // With `--outdir`, the test document is kept for inspection.
+    check_file(&tempdir, "selftest/selftest.tex");
+}
+
 /// Checks that shell completions are correctly generated
 #[cfg(feature = "serialization")]
 #[test]
@@ -876,6 +1055,24 @@ fn v2_show_shell_completions() {
     }
 }
 
+/// Checks that the directories of a document are shown
+#[cfg(feature = "serialization")]
+#[test]
+fn v2_show_paths() {
+    let (_tempdir, temppath) = setup_v2();
+    let output = run_tectonic(&temppath, &["-X", "show", "paths"]);
+    success_or_panic(&output);
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let search_paths: Vec<_> = stdout
+        .lines()
+        .filter_map(|l| l.strip_prefix("search path: "))
+        .collect();
+    assert_eq!(search_paths.len(), 1);
+    assert!(Path::new(search_paths[0]).ends_with("doc/src"));
+    assert!(stdout.lines().any(|l| l.starts_with("format cache: ")));
+}
+
 const SHELL_ESCAPE_TEST_DOC: &str = r"\immediate\write18{mkdir shellwork}
 \immediate\write18{echo 123 >shellwork/persist}
 \ifnum123=\input{shellwork/persist}
@@ -996,6 +1193,41 @@ fn extra_search_paths() {
     error_or_panic(&output);
 }
 
+/// Test that hermetic builds can't read files outside of the document's directory
+#[test]
+fn hermetic_mode() {
+    let fmt_arg = get_plain_format_arg();
+    let tempdir = setup_and_copy_files(&["subdirectory/content/1.tex"]);
+
+    // Absolute paths are forbidden, so the format has to come from inside the
+    // document's directory too.
+    let fmt_path = fmt_arg.trim_start_matches("--format=");
+    fs::copy(fmt_path, tempdir.path().join("plain.fmt")).unwrap();
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &["--format=plain.fmt", "-", "--hermetic"],
+        "\\input subdirectory/content/1.tex\n\\bye",
+    );
+    success_or_panic(&output);
+
+    // Merely probing for a file outside of the directory is a violation.
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &["--format=plain.fmt", "-", "--hermetic"],
+        "\\openin1=../hermetic-probe.tex \\closein1\n\\bye",
+    );
+    error_or_panic(&output);
+    assert_eq!(output.status.code(), Some(7));
+
+    let output = run_tectonic_with_stdin(
+        tempdir.path(),
+        &["--format=plain.fmt", "-"],
+        "\\openin1=../hermetic-probe.tex \\closein1\n\\bye",
+    );
+    success_or_panic(&output);
+}
+
 /// Ensures that watch command succeeds, and when a file is changed while running it rebuilds
 /// periodically
 #[cfg(all(feature = "serialization", not(target_arch = "mips")))]
@@ -25,13 +25,20 @@ use tectonic::{
     io::{IoStack, MemoryIo},
     TexEngine,
 };
-use tectonic_bridge_core::{CoreBridgeLauncher, DriverHooks};
+use tectonic_bridge_core::{CoreBridgeLauncher, DriverHooks, MinimalDriver};
 use tectonic_errors::Result;
 use tectonic_io_base::{
     filesystem::{FilesystemIo, FilesystemPrimaryInputIo},
     InputHandle, IoProvider, OpenResult, OutputHandle,
 };
 use tectonic_status_base::{NoopStatusBackend, StatusBackend};
+use tectonic_xetex_format::{
+    catcodes::CatCode,
+    dimenpars::DimenPar,
+    format::Format,
+    intpars::IntPar,
+    registers::{AllocationKind, ShortcutKind},
+};
 
 mod util;
 use crate::util::test_path;
@@ -180,6 +187,32 @@ fn test_format_generation(texname: &str, fmtname: &str, sha256: &str) {
     }
 }
 
+/// Generate a format and decode it, so that its contents can be examined.
+fn generate_and_decode_format(texname: &str, fmtname: &str) -> Format {
+    util::set_test_root();
+
+    let mut p = test_path(&["assets"]);
+    let mut fs_support = FilesystemIo::new(&p, false, false, HashSet::new());
+    p.push(texname);
+    let mut fs_primary = FilesystemPrimaryInputIo::new(&p);
+    let mut mem = MemoryIo::new(true);
+
+    {
+        let io = IoStack::new(vec![&mut mem, &mut fs_primary, &mut fs_support]);
+        let mut hooks = MinimalDriver::new(io);
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        TexEngine::default()
+            .initex_mode(true)
+            .process(&mut launcher, "unused.fmt", texname)
+            .unwrap();
+    }
+
+    let files = mem.files.borrow();
+    Format::parse(&files[fmtname].data[..]).unwrap()
+}
+
 // Keep these alphabetized.
 
 #[test]
@@ -190,3 +223,78 @@ fn plain_format() {
         "2b52cf6d73940ac2eadf88a437d6dc760ba7ad49fa01336d93fb2ed0b9b305db",
     )
 }
+
+#[test]
+fn plain_format_contents() {
+    let fmt = generate_and_decode_format("plain.tex", "plain.fmt");
+
+    assert_eq!(fmt.cat_code('\\').unwrap(), CatCode::Escape);
+    assert_eq!(fmt.cat_code('{').unwrap(), CatCode::BeginGroup);
+    assert_eq!(fmt.cat_code('a').unwrap(), CatCode::Letter);
+    assert_eq!(fmt.cat_code('~').unwrap(), CatCode::Active);
+    assert_eq!(fmt.lc_code('A'), 'a' as i32);
+    assert_eq!(fmt.uc_code('a'), 'A' as i32);
+
+    assert_eq!(fmt.int_parameter(IntPar::Tolerance).unwrap(), 200);
+    assert_eq!(fmt.int_parameter(IntPar::Mag).unwrap(), 1000);
+    assert_eq!(fmt.dimen_parameter(DimenPar::Hsize).unwrap(), 30_785_863); // 6.5in
+    assert_eq!(fmt.dimen_parameter(DimenPar::ParIndent).unwrap(), 20 << 16);
+
+    // `\count11` tracks `\dimen` allocations. Plain allocates six, starting
+    // with `\maxdimen` in `\dimen10`.
+    assert_eq!(fmt.count_register(11).unwrap(), 15);
+    assert_eq!(fmt.dimen_register(10).unwrap(), 0x3FFF_FFFF);
+    assert!(fmt.count_register(256).is_err());
+
+    // Plain loads the US English patterns and exceptions as language 0.
+    let langs = fmt.languages();
+    assert_eq!(langs.len(), 1);
+    assert_eq!(langs[0].number, 0);
+    assert!(langs[0].has_patterns);
+    assert!(langs[0].exceptions.iter().any(|w| w == "ta-ble"));
+    assert_eq!(langs[0].hyph_codes, None);
+    assert_eq!(fmt.int_parameter(IntPar::LeftHyphenMin).unwrap(), 2);
+    assert_eq!(fmt.int_parameter(IntPar::RightHyphenMin).unwrap(), 3);
+
+    // Plain allocates `\footins` and `\topins` downwards from 254, and the
+    // shared registers of those insertions bound the others.
+    let allocs = fmt.register_allocations().unwrap();
+    let dimens = allocs
+        .iter()
+        .find(|a| a.kind == AllocationKind::Dimen)
+        .unwrap();
+    assert_eq!((dimens.last, dimens.limit), (15, 253));
+    let inserts = allocs
+        .iter()
+        .find(|a| a.kind == AllocationKind::Insert)
+        .unwrap();
+    assert_eq!(inserts.last, 253);
+
+    let shortcuts = fmt.register_shortcuts();
+    let find = |name: &str| {
+        shortcuts
+            .iter()
+            .find(|s| s.name == name)
+            .map(|s| (s.kind, s.number))
+    };
+    assert_eq!(find("pageno"), Some((ShortcutKind::Count, 0)));
+    assert_eq!(find("maxdimen"), Some((ShortcutKind::Dimen, 10)));
+    assert_eq!(find("footins"), Some((ShortcutKind::Char, 254)));
+
+    let meta = fmt.metadata().unwrap();
+    assert_eq!(meta.version, tectonic::FORMAT_SERIAL as usize);
+    assert_eq!(meta.banner.as_deref(), Some("plain 3.141592653"));
+    assert!(!meta.texxet);
+    assert!(!meta.xetex_upwards);
+
+    let scalars = fmt.saved_scalars();
+    assert_eq!(scalars.par_token, "\\par");
+    assert_eq!(scalars.write_token, "\\write");
+    assert!(scalars.fonts > 1);
+
+    // Plain leaves the page size to the driver.
+    let backend = fmt.backend_state().unwrap();
+    assert_eq!(backend.mag, 1000);
+    assert_eq!(backend.page_width, 0);
+    assert_eq!(backend.page_height, 0);
+}
@@ -4,17 +4,22 @@
 //! Test suite for the TeX engine
 
 use std::collections::HashSet;
+use std::fs;
 use std::path::Path;
 use std::time;
 
-use tectonic::engines::tex::TexOutcome;
+use tectonic::engines::tex::{Checkpoint, ShapingCache, ShapingCacheStats, TexOutcome};
 use tectonic::errors::DefinitelySame;
 use tectonic::io::testing::SingleInputFileIo;
 use tectonic::io::{FilesystemIo, FilesystemPrimaryInputIo, IoProvider, IoStack, MemoryIo};
 use tectonic::unstable_opts::UnstableOptions;
-use tectonic::{TexEngine, XdvipdfmxEngine};
-use tectonic_bridge_core::{CoreBridgeLauncher, MinimalDriver};
+use tectonic::{Spx2HtmlEngine, TexEngine, XdvipdfmxEngine};
+use tectonic_bridge_core::{
+    CoreBridgeLauncher, DriverHooks, EnvReadOutcome, MinimalDriver, SecuritySettings,
+    SecurityStance,
+};
 use tectonic_errors::{anyhow::anyhow, Result};
+use tectonic_io_base::stdstreams::BufferedPrimaryIo;
 use tectonic_status_base::NoopStatusBackend;
 
 #[path = "util/mod.rs"]
@@ -28,6 +33,7 @@ struct TestCase {
     check_pdf: bool,
     extra_io: Vec<Box<dyn IoProvider>>,
     unstables: UnstableOptions,
+    shaping_cache_limit: Option<usize>,
 }
 
 impl TestCase {
@@ -39,6 +45,7 @@ impl TestCase {
             check_pdf: false,
             extra_io: Vec::new(),
             unstables: UnstableOptions::default(),
+            shaping_cache_limit: None,
         }
     }
 
@@ -67,6 +74,13 @@ impl TestCase {
         self
     }
 
+    /// Shape text through a fresh shaping cache, limited to the given number
+    /// of bytes. The outputs are expected to be the same as without it.
+    fn with_shaping_cache(mut self, limit: usize) -> Self {
+        self.shaping_cache_limit = Some(limit);
+        self
+    }
+
     fn expect(mut self, result: Result<TexOutcome>) -> Self {
         self.expected_result = result;
         self
@@ -76,7 +90,12 @@ impl TestCase {
         self.expect(Err(anyhow!("{}", msg)))
     }
 
-    fn go(mut self) {
+    fn go(self) {
+        self.run();
+    }
+
+    /// Run the test, returning the statistics of the shaping cache.
+    fn run(mut self) -> ShapingCacheStats {
         util::set_test_root();
 
         let expect_xdv = self.expected_result.is_ok();
@@ -112,7 +131,7 @@ impl TestCase {
         let expected_log = ExpectedFile::read_with_extension(&mut p, "log");
 
         // Run the engine(s)!
-        let res = {
+        let (res, shaping_stats) = {
             let mut io_list: Vec<&mut dyn IoProvider> =
                 vec![&mut mem, &mut tex, &mut fmt, &mut assets];
             for io in &mut self.extra_io {
@@ -123,9 +142,17 @@ impl TestCase {
             let mut status = NoopStatusBackend::default();
             let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
 
-            let tex_res = TexEngine::default()
-                .shell_escape(self.unstables.shell_escape)
-                .process(&mut launcher, "plain.fmt", &texname);
+            let mut engine = TexEngine::default();
+            engine.shell_escape(self.unstables.shell_escape);
+
+            if let Some(limit) = self.shaping_cache_limit {
+                engine
+                    .shaping_cache(ShapingCache::Fresh)
+                    .shaping_cache_limit(limit);
+            }
+
+            let tex_res = engine.process(&mut launcher, "plain.fmt", &texname);
+            let shaping_stats = engine.shaping_cache_stats();
 
             if self.check_pdf && tex_res.definitely_same(&self.expected_result) {
                 let mut engine = XdvipdfmxEngine::default();
@@ -146,7 +173,7 @@ impl TestCase {
                 engine.process(&mut launcher, &xdvname, &pdfname).unwrap();
             }
 
-            tex_res
+            (tex_res, shaping_stats)
         };
 
         // Check that outputs match expectations.
@@ -173,7 +200,218 @@ impl TestCase {
         }
 
         expect.finish();
+        shaping_stats
+    }
+}
+
+/// The outputs of a run of the engine on the `checkpoint` test document.
+struct CheckpointRun {
+    xdv: Vec<u8>,
+    log: Vec<u8>,
+    checkpoint: Option<Checkpoint>,
+    resumed: bool,
+}
+
+/// Run the engine on the given text as the `checkpoint` test document,
+/// optionally taking a checkpoint or resuming from one. Unlike a
+/// [`TestCase`], this doesn't compare the outputs with any expected ones, so
+/// that runs can be compared with each other.
+fn run_checkpoint_doc(
+    text: &str,
+    checkpoint_after_page: Option<u32>,
+    resume_from: Option<Checkpoint>,
+) -> CheckpointRun {
+    util::set_test_root();
+
+    let mut fmt =
+        SingleInputFileIo::new(&ensure_plain_format().expect("couldn't write format file"));
+    let mut tex = BufferedPrimaryIo::from_text(text);
+    let mut mem = MemoryIo::new(true);
+
+    let (checkpoint, resumed) = {
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut tex, &mut fmt];
+        let io = IoStack::new(io_list);
+        let mut hooks = MinimalDriver::new(io);
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        let mut engine = TexEngine::default();
+        engine
+            .checkpoint_after_page(checkpoint_after_page)
+            .resume_from(resume_from);
+
+        let outcome = engine
+            .process(&mut launcher, "plain.fmt", "checkpoint.tex")
+            .expect("the engine failed");
+        assert_eq!(outcome, TexOutcome::Spotless);
+
+        (engine.take_checkpoint(), engine.resumed_from_checkpoint())
+    };
+
+    let files = mem.files.borrow();
+    let contents = |name: &str| {
+        files
+            .get(name)
+            .unwrap_or_else(|| panic!("the engine didn't write {name}"))
+            .data
+            .clone()
+    };
+
+    CheckpointRun {
+        xdv: contents("checkpoint.xdv"),
+        log: contents("checkpoint.log"),
+        checkpoint,
+        resumed,
+    }
+}
+
+fn checkpoint_doc_text() -> String {
+    util::set_test_root();
+    std::fs::read_to_string(test_path(&["tex-outputs", "checkpoint.tex"]))
+        .expect("couldn't read the checkpoint test document")
+}
+
+/// A driver that records the engine's requests to read environment
+/// variables.
+struct EnvReadDriver<'a> {
+    io: IoStack<'a>,
+    reads: Vec<(String, EnvReadOutcome)>,
+}
+
+impl DriverHooks for EnvReadDriver<'_> {
+    fn io(&mut self) -> &mut dyn IoProvider {
+        &mut self.io
     }
+
+    fn event_env_read(&mut self, name: &str, outcome: EnvReadOutcome) {
+        self.reads.push((name.to_owned(), outcome));
+    }
+}
+
+/// Run the engine on a document that shows the values of the environment
+/// variables `TT_GETENV_A` and `TT_GETENV_B` in its log, returning the log
+/// and the requests to read them.
+fn run_getenv_doc(security: SecuritySettings) -> (String, Vec<(String, EnvReadOutcome)>) {
+    util::set_test_root();
+
+    // These names are only used by the getenv tests, which all set the same
+    // values, so it doesn't matter that they may run at the same time.
+    std::env::set_var("TT_GETENV_A", "alpha");
+    std::env::set_var("TT_GETENV_B", "beta");
+
+    let text = "\\message{A=[\\tectonicgetenv{TT_GETENV_A}]}\n\
+                \\message{B=[\\tectonicgetenv{TT_GETENV_B}]}\n\
+                \\message{C=[\\tectonicgetenv{TT_GETENV_C}]}\n\
+                Hello.\n\\bye\n";
+
+    let mut fmt =
+        SingleInputFileIo::new(&ensure_plain_format().expect("couldn't write format file"));
+    let mut tex = BufferedPrimaryIo::from_text(text);
+    let mut mem = MemoryIo::new(true);
+
+    let reads = {
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut tex, &mut fmt];
+        let mut hooks = EnvReadDriver {
+            io: IoStack::new(io_list),
+            reads: Vec::new(),
+        };
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new_with_security(&mut hooks, &mut status, security);
+
+        let outcome = TexEngine::default()
+            .process(&mut launcher, "plain.fmt", "getenv.tex")
+            .expect("the engine failed");
+        assert_eq!(outcome, TexOutcome::Spotless);
+        hooks.reads
+    };
+
+    let files = mem.files.borrow();
+    let log = files
+        .get("getenv.log")
+        .expect("the engine didn't write a log");
+    (String::from_utf8_lossy(&log.data).into_owned(), reads)
+}
+
+/// Run the engine in HTML mode on the given test document, and then
+/// spx2html on its output, returning the HTML that was emitted. Its templates
+/// are found next to the document.
+fn run_html_doc(stem: &str) -> String {
+    util::set_test_root();
+
+    let outdir = tempfile::tempdir().unwrap();
+    let texname = format!("{stem}.tex");
+    let spxname = format!("{stem}.spx");
+
+    let mut fmt =
+        SingleInputFileIo::new(&ensure_plain_format().expect("couldn't write format file"));
+    let mut tex = FilesystemPrimaryInputIo::new(test_path(&["tex-outputs", &texname]));
+    let mut mem = MemoryIo::new(true);
+    let mut inputs = FilesystemIo::new(&test_path(&["tex-outputs"]), false, false, HashSet::new());
+    let mut assets = FilesystemIo::new(&test_path(&["assets"]), false, false, HashSet::new());
+
+    {
+        let io_list: Vec<&mut dyn IoProvider> =
+            vec![&mut mem, &mut tex, &mut fmt, &mut inputs, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+
+        {
+            let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+            let outcome = TexEngine::default()
+                .semantic_pagination(true)
+                .process(&mut launcher, "plain.fmt", &texname)
+                .expect("the engine failed");
+            assert_eq!(outcome, TexOutcome::Spotless);
+        }
+
+        Spx2HtmlEngine::default()
+            .output_base(outdir.path())
+            .do_not_emit_assets()
+            .process_to_filesystem(&mut hooks, &mut status, &spxname)
+            .expect("spx2html failed");
+    }
+
+    fs::read_to_string(outdir.path().join(format!("{stem}.html")))
+        .expect("spx2html didn't write the HTML")
+}
+
+/// Run the engine and xdvipdfmx on the given test document, returning the
+/// PDF, which is uncompressed so that its drawing operators can be checked.
+fn run_pdf_doc(stem: &str) -> Vec<u8> {
+    util::set_test_root();
+
+    let texname = format!("{stem}.tex");
+    let xdvname = format!("{stem}.xdv");
+    let pdfname = format!("{stem}.pdf");
+
+    let mut fmt =
+        SingleInputFileIo::new(&ensure_plain_format().expect("couldn't write format file"));
+    let mut tex = FilesystemPrimaryInputIo::new(test_path(&["tex-outputs", &texname]));
+    let mut mem = MemoryIo::new(true);
+    let mut assets = FilesystemIo::new(&test_path(&["assets"]), false, false, HashSet::new());
+
+    {
+        let io_list: Vec<&mut dyn IoProvider> = vec![&mut mem, &mut tex, &mut fmt, &mut assets];
+        let mut hooks = MinimalDriver::new(IoStack::new(io_list));
+        let mut status = NoopStatusBackend::default();
+        let mut launcher = CoreBridgeLauncher::new(&mut hooks, &mut status);
+
+        TexEngine::default()
+            .process(&mut launcher, "plain.fmt", &texname)
+            .expect("the engine failed");
+        XdvipdfmxEngine::default()
+            .enable_compression(false)
+            .enable_deterministic_tags(true)
+            .process(&mut launcher, &xdvname, &pdfname)
+            .expect("xdvipdfmx failed");
+    }
+
+    let files = mem.files.borrow();
+    files
+        .get(&pdfname)
+        .expect("xdvipdfmx didn't write the PDF")
+        .data
+        .clone()
 }
 
 // Keep these alphabetized.
@@ -190,6 +428,82 @@ fn a4paper() {
         .go()
 }
 
+#[test]
+fn checkpoint_resume() {
+    let text = checkpoint_doc_text();
+    let full = run_checkpoint_doc(&text, None, None);
+
+    // Taking a checkpoint doesn't change the outputs.
+    let first = run_checkpoint_doc(&text, Some(1), None);
+    assert_eq!(first.xdv, full.xdv);
+    assert_eq!(first.log, full.log);
+
+    let checkpoint = first.checkpoint.expect("no checkpoint was taken");
+    assert_eq!(checkpoint.pages(), 1);
+
+    let resumed = run_checkpoint_doc(&text, None, Some(checkpoint));
+    assert!(resumed.resumed);
+    assert_eq!(resumed.xdv, full.xdv);
+    assert_eq!(resumed.log, full.log);
+}
+
+#[test]
+fn checkpoint_resume_edited_tail() {
+    // An edit after the point where the checkpoint was taken doesn't stop
+    // the run from resuming.
+    let text = checkpoint_doc_text();
+    let edited = text.replace("again", "once more");
+    assert_ne!(edited, text);
+    let full = run_checkpoint_doc(&edited, None, None);
+
+    let checkpoint = run_checkpoint_doc(&text, Some(1), None)
+        .checkpoint
+        .expect("no checkpoint was taken");
+
+    let resumed = run_checkpoint_doc(&edited, None, Some(checkpoint));
+    assert!(resumed.resumed);
+    assert_eq!(resumed.xdv, full.xdv);
+    assert_eq!(resumed.log, full.log);
+}
+
+#[test]
+fn checkpoint_changed_input() {
+    // An edit before the point where the checkpoint was taken means that the
+    // run has to start from the top.
+    let text = checkpoint_doc_text();
+    let edited = text.replace(
+        "Some text on the first page",
+        "Other text on the first page",
+    );
+    assert_ne!(edited, text);
+    let full = run_checkpoint_doc(&edited, None, None);
+
+    let checkpoint = run_checkpoint_doc(&text, Some(1), None)
+        .checkpoint
+        .expect("no checkpoint was taken");
+
+    let rerun = run_checkpoint_doc(&edited, None, Some(checkpoint));
+    assert!(!rerun.resumed);
+    assert_eq!(rerun.xdv, full.xdv);
+    assert_eq!(rerun.log, full.log);
+}
+
+#[test]
+fn colr_glyph() {
+    let pdf = run_pdf_doc("colr_glyph");
+    let pdf = String::from_utf8_lossy(&pdf);
+    let pos = |text: &str| {
+        pdf.find(text)
+            .unwrap_or_else(|| panic!("`{text}` is missing from the PDF"))
+    };
+
+    // The red layer is drawn first, and then the blue one, with a graphics
+    // state that makes it half-transparent.
+    assert!(pos("/DeviceRGB cs 1 0 0 sc") < pos("/DeviceRGB cs 0 0 1 sc"));
+    assert!(pos("/Xtx_Colr_Gs_80 gs") > pos("/DeviceRGB cs 1 0 0 sc"));
+    assert!(pdf.contains("/ExtGState"));
+}
+
 #[test]
 fn file_encoding() {
     // Need to do this here since we call test_path unusually early.
@@ -211,6 +525,24 @@ fn utf8_chars() {
         .go();
 }
 
+#[test]
+fn html_rtl() {
+    let html = run_html_doc("html_rtl");
+    println!("html:\n{html}");
+
+    // The segment is marked, and its words are in logical order, even though
+    // TeX shipped them out from right to left.
+    let pos = |text: &str| {
+        html.find(text)
+            .unwrap_or_else(|| panic!("`{text}` is missing from the HTML"))
+    };
+    assert!(pos("Hello") < pos("<span dir=\"rtl\">"));
+    assert!(pos("<span dir=\"rtl\">") < pos("one"));
+    assert!(pos("one") < pos("two"));
+    assert!(pos("two") < pos("three"));
+    assert!(pos("three") < pos("world"));
+}
+
 /// An issue triggered by a bug in how the I/O subsystem reported file offsets
 /// after an ungetc() call.
 #[test]
@@ -237,6 +569,27 @@ fn otf_basic() {
         .go()
 }
 
+#[test]
+fn otf_basic_shaping_cache() {
+    // Words such as "typing" repeat, so some shaping must be reused.
+    let stats = TestCase::new("otf_basic")
+        .with_shaping_cache(usize::MAX)
+        .expect(Ok(TexOutcome::Warnings))
+        .run();
+    assert!(stats.hits > 0);
+    assert_eq!(stats.evictions, 0);
+}
+
+#[test]
+fn otf_basic_shaping_cache_evicting() {
+    // A limit this small only holds a few entries at a time.
+    let stats = TestCase::new("otf_basic")
+        .with_shaping_cache(4096)
+        .expect(Ok(TexOutcome::Warnings))
+        .run();
+    assert!(stats.evictions > 0);
+}
+
 #[test]
 fn graphite_basic() {
     TestCase::new("graphite_basic").go()
@@ -358,6 +711,45 @@ fn tectoniccodatokens_ok() {
     TestCase::new("tectoniccodatokens_ok").go()
 }
 
+#[test]
+fn tectonicgetenv_allowed() {
+    let mut security = SecuritySettings::new(SecurityStance::MaybeAllowInsecures);
+    security
+        .with_allowed_env_var("TT_GETENV_A")
+        .with_allowed_env_var("TT_GETENV_C");
+    let (log, reads) = run_getenv_doc(security);
+
+    // Only the allowed variable that's set gives a value.
+    assert!(log.contains("A=[alpha]"), "log:\n{log}");
+    assert!(log.contains("B=[]"), "log:\n{log}");
+    assert!(log.contains("C=[]"), "log:\n{log}");
+    assert!(!log.contains("beta"));
+    assert_eq!(
+        reads,
+        [
+            ("TT_GETENV_A".to_owned(), EnvReadOutcome::Read),
+            ("TT_GETENV_B".to_owned(), EnvReadOutcome::Forbidden),
+            ("TT_GETENV_C".to_owned(), EnvReadOutcome::NotSet),
+        ]
+    );
+}
+
+#[test]
+fn tectonicgetenv_forbidden() {
+    // Disabling insecure features overrides the allowlist.
+    let mut security = SecuritySettings::new(SecurityStance::DisableInsecures);
+    security.with_allowed_env_var("TT_GETENV_A");
+    let (log, reads) = run_getenv_doc(security);
+
+    assert!(log.contains("A=[]"), "log:\n{log}");
+    assert!(log.contains("B=[]"), "log:\n{log}");
+    assert!(!log.contains("alpha") && !log.contains("beta"));
+    assert!(reads
+        .iter()
+        .all(|(_, outcome)| *outcome == EnvReadOutcome::Forbidden));
+    assert_eq!(reads.len(), 3);
+}
+
 #[test]
 fn the_letter_a() {
     TestCase::new("the_letter_a").check_pdf(true).go()